@@ -0,0 +1,123 @@
+//! Capabilities
+//!
+//! Lets clients find out at runtime which parts of the API this server
+//! implements. The router registers a capability for each route group as it
+//! mounts it, so a group left out by a feature flag is missing from both the
+//! routes and `GET /api/v3/capabilities`.
+//!
+//! A capability that is only partly implemented carries metadata saying
+//! which parts are missing, e.g. `"baselineTimestamps": false`.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+use serde_json::Value;
+
+use crate::version::OP_RS_VERSION;
+
+/// A feature served by the API, such as `work_packages.crud`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capability {
+    pub name: &'static str,
+    pub metadata: BTreeMap<&'static str, Value>,
+}
+
+impl Capability {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Describe how much of the capability is implemented
+    pub fn with_metadata(mut self, key: &'static str, value: impl Into<Value>) -> Self {
+        self.metadata.insert(key, value.into());
+        self
+    }
+}
+
+/// The capabilities of the mounted routes
+#[derive(Debug, Clone, Default)]
+pub struct CapabilityRegistry {
+    capabilities: BTreeMap<&'static str, Capability>,
+}
+
+impl CapabilityRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a capability, merging the metadata of an earlier registration
+    pub fn register(&mut self, capability: Capability) {
+        self.capabilities
+            .entry(capability.name)
+            .or_insert_with(|| Capability::new(capability.name))
+            .metadata
+            .extend(capability.metadata);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Capability> {
+        self.capabilities.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.capabilities.contains_key(name)
+    }
+
+    /// Capability names in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.capabilities.keys().copied()
+    }
+
+    /// Map of capability name to its metadata
+    pub fn to_json(&self) -> Value {
+        self.capabilities
+            .values()
+            .map(|capability| {
+                let metadata = capability
+                    .metadata
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone()))
+                    .collect();
+                (capability.name.to_string(), Value::Object(metadata))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+}
+
+/// GET /api/v3/capabilities
+pub async fn list_capabilities(Extension(registry): Extension<Arc<CapabilityRegistry>>) -> Json<Value> {
+    Json(serde_json::json!({
+        "_type": "Capabilities",
+        "version": OP_RS_VERSION,
+        "capabilities": registry.to_json(),
+        "_links": {
+            "self": { "href": "/api/v3/capabilities" }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_merges_metadata() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(Capability::new("queries.crud").with_metadata("baselineTimestamps", false));
+        registry.register(Capability::new("queries.crud").with_metadata("export", "document"));
+        registry.register(Capability::new("notifications.in_app"));
+
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["notifications.in_app", "queries.crud"]);
+        assert_eq!(
+            registry.to_json(),
+            serde_json::json!({
+                "notifications.in_app": {},
+                "queries.crud": { "baselineTimestamps": false, "export": "document" }
+            })
+        );
+    }
+}
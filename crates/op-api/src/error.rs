@@ -1,14 +1,21 @@
 //! API error handling
 //!
-//! Provides HTTP error types with HAL+JSON responses.
+//! Provides HTTP error types with HAL+JSON responses. Validation messages
+//! render in the locale of the request, see [`crate::locale`].
 
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use op_core::error::ValidationErrors;
-use serde::Serialize;
+use op_core::error::{PropertyError, ValidationErrors};
+use op_core::i18n::{self, I18n, FALLBACK_LOCALE};
+use op_core::representations::error_identifier::*;
+use op_core::representations::{ErrorDetails, ErrorEmbedded, ErrorResponse};
+
+/// Message of a query canceled by the statement timeout
+pub const QUERY_TIMEOUT_MESSAGE: &str =
+    "The query took too long to run. Narrow it down, e.g. with more selective filters.";
 
 /// API error types
 #[derive(Debug)]
@@ -17,9 +24,14 @@ pub enum ApiError {
     Validation(ValidationErrors),
     Unauthorized(String),
     Forbidden(String),
+    /// The scopes of the API key do not allow the request
+    InsufficientScope(String),
     BadRequest(String),
     Conflict(String),
     Internal(String),
+    /// The instance does not accept the request for now, e.g. a write
+    /// during maintenance; clients may retry after the given seconds
+    ServiceUnavailable { message: String, retry_after: u64 },
 }
 
 impl ApiError {
@@ -27,6 +39,31 @@ impl ApiError {
         ApiError::NotFound { resource, id: id.to_string() }
     }
 
+    /// 422 for a request property that failed to parse or validate
+    pub fn invalid_property(property: &str, message: impl Into<String>) -> Self {
+        let mut errors = ValidationErrors::new();
+        errors.add(property, message);
+        ApiError::Validation(errors)
+    }
+
+    /// 422 listing every property error of a failed validation
+    pub fn validation(errors: impl Into<ValidationErrors>) -> Self {
+        ApiError::Validation(errors.into())
+    }
+
+    /// Error of a failed work package query: a 422 asking to narrow the
+    /// query down when it ran into the statement timeout or detailing
+    /// each filter that cannot be executed, a 500 otherwise
+    pub fn query(error: op_db::RepositoryError) -> Self {
+        match error {
+            op_db::RepositoryError::Timeout(_) => ApiError::validation(vec![PropertyError::from(QUERY_TIMEOUT_MESSAGE)]),
+            op_db::RepositoryError::InvalidQuery(e) => {
+                ApiError::validation(e.errors.into_iter().map(PropertyError::from).collect::<Vec<_>>())
+            }
+            e => ApiError::internal(format!("Database error: {}", e)),
+        }
+    }
+
     pub fn unauthorized(msg: impl Into<String>) -> Self {
         ApiError::Unauthorized(msg.into())
     }
@@ -35,6 +72,12 @@ impl ApiError {
         ApiError::Forbidden(msg.into())
     }
 
+    /// 403 for a request the API key's scopes do not allow, even if the
+    /// user may send it
+    pub fn insufficient_scope(msg: impl Into<String>) -> Self {
+        ApiError::InsufficientScope(msg.into())
+    }
+
     pub fn bad_request(msg: impl Into<String>) -> Self {
         ApiError::BadRequest(msg.into())
     }
@@ -47,71 +90,249 @@ impl ApiError {
         ApiError::Internal(msg.into())
     }
 
+    /// 503 with a `Retry-After` of `retry_after` seconds
+    pub fn service_unavailable(msg: impl Into<String>, retry_after: u64) -> Self {
+        ApiError::ServiceUnavailable { message: msg.into(), retry_after }
+    }
+
     pub fn status_code(&self) -> StatusCode {
         match self {
             ApiError::NotFound { .. } => StatusCode::NOT_FOUND,
             ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
             ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
-            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Forbidden(_) | ApiError::InsufficientScope(_) => StatusCode::FORBIDDEN,
             ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
             ApiError::Conflict(_) => StatusCode::CONFLICT,
             ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 }
 
-#[derive(Serialize)]
-struct HalError {
-    #[serde(rename = "_type")]
-    type_name: String,
-    #[serde(rename = "errorIdentifier")]
-    error_identifier: String,
-    message: String,
+/// One `PropertyConstraintViolation` per error, wrapped in a
+/// `MultipleErrors` when there is more than one
+fn validation_response(errors: &ValidationErrors) -> ErrorResponse {
+    let mut violations: Vec<ErrorResponse> = errors
+        .property_errors()
+        .iter()
+        .map(property_constraint_violation)
+        .collect();
+
+    match violations.len() {
+        0 => ErrorResponse::new(PROPERTY_CONSTRAINT_VIOLATION, String::new()),
+        1 => violations.remove(0),
+        _ => ErrorResponse {
+            embedded: Some(ErrorEmbedded { details: None, errors: violations }),
+            ..ErrorResponse::new(MULTIPLE_ERRORS, "Multiple field constraints have been violated.".into())
+        },
+    }
+}
+
+fn property_constraint_violation(error: &PropertyError) -> ErrorResponse {
+    let attribute = camel_case(&error.property);
+    let message = match i18n::current_locale().filter(|locale| i18n::language(locale) != FALLBACK_LOCALE) {
+        Some(locale) => localized_message(error, &locale),
+        None if error.is_base() => error.message.clone(),
+        None => format!("{} {}", attribute, error.message),
+    };
+
+    ErrorResponse {
+        embedded: Some(ErrorEmbedded {
+            details: Some(ErrorDetails { attribute }),
+            errors: Vec::new(),
+        }),
+        ..ErrorResponse::new(PROPERTY_CONSTRAINT_VIOLATION, message)
+    }
+}
+
+/// Message of a property error translated into the locale, naming the
+/// property by its human-readable attribute name
+fn localized_message(error: &PropertyError, locale: &str) -> String {
+    let i18n = I18n::shared();
+    let message = i18n.message(locale, &error.message);
+    if error.is_base() {
+        return message;
+    }
+    let attribute = i18n.attribute(locale, &error.property);
+    i18n.t(locale, "errors.format", &[("attribute", &attribute), ("message", &message)])
+}
+
+/// API v3 attribute of a model property, e.g. `effective_date` -> `effectiveDate`
+fn camel_case(property: &str) -> String {
+    let mut words = property.split('_');
+    let mut attribute = words.next().unwrap_or_default().to_string();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            attribute.extend(first.to_uppercase());
+            attribute.push_str(chars.as_str());
+        }
+    }
+    attribute
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let status = self.status_code();
-        let error = match &self {
-            ApiError::NotFound { resource, id } => HalError {
-                type_name: "Error".into(),
-                error_identifier: "urn:openproject-org:api:v3:errors:NotFound".into(),
-                message: format!("{} with id {} not found", resource, id),
-            },
-            ApiError::Validation(errors) => HalError {
-                type_name: "Error".into(),
-                error_identifier: "urn:openproject-org:api:v3:errors:PropertyConstraintViolation".into(),
-                message: errors.full_messages().join(", "),
-            },
-            ApiError::Unauthorized(msg) => HalError {
-                type_name: "Error".into(),
-                error_identifier: "urn:openproject-org:api:v3:errors:Unauthenticated".into(),
-                message: msg.clone(),
-            },
-            ApiError::Forbidden(msg) => HalError {
-                type_name: "Error".into(),
-                error_identifier: "urn:openproject-org:api:v3:errors:MissingPermission".into(),
-                message: msg.clone(),
-            },
-            ApiError::BadRequest(msg) => HalError {
-                type_name: "Error".into(),
-                error_identifier: "urn:openproject-org:api:v3:errors:InvalidRequestBody".into(),
-                message: msg.clone(),
-            },
-            ApiError::Conflict(msg) => HalError {
-                type_name: "Error".into(),
-                error_identifier: "urn:openproject-org:api:v3:errors:UpdateConflict".into(),
-                message: msg.clone(),
-            },
-            ApiError::Internal(msg) => HalError {
-                type_name: "Error".into(),
-                error_identifier: "urn:openproject-org:api:v3:errors:InternalError".into(),
-                message: msg.clone(),
-            },
+        let mut error = match &self {
+            ApiError::NotFound { resource, id } => {
+                ErrorResponse::new(NOT_FOUND, format!("{} with id {} not found", resource, id))
+            }
+            ApiError::Validation(errors) => validation_response(errors),
+            ApiError::Unauthorized(msg) => ErrorResponse::new(UNAUTHENTICATED, msg.clone()),
+            ApiError::Forbidden(msg) => ErrorResponse::new(MISSING_PERMISSION, msg.clone()),
+            ApiError::InsufficientScope(msg) => ErrorResponse::new(INSUFFICIENT_SCOPE, msg.clone()),
+            ApiError::BadRequest(msg) => ErrorResponse::new(INVALID_REQUEST_BODY, msg.clone()),
+            ApiError::Conflict(msg) => ErrorResponse::new(UPDATE_CONFLICT, msg.clone()),
+            ApiError::Internal(msg) => ErrorResponse::new(INTERNAL_ERROR, msg.clone()),
+            ApiError::ServiceUnavailable { message, .. } => {
+                ErrorResponse::new(SERVICE_UNAVAILABLE, message.clone())
+            }
         };
 
-        (status, Json(error)).into_response()
+        error.request_id = op_core::request_id::current();
+
+        let mut response = (status, Json(error)).into_response();
+        if let ApiError::ServiceUnavailable { retry_after, .. } = self {
+            response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+        }
+        response
     }
 }
 
 pub type ApiResult<T> = Result<T, ApiError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn render(error: ApiError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_single_property_error_carries_attribute() {
+        let error = ApiError::validation(vec![PropertyError::new(
+            "effective_date",
+            "greater_than_or_equal_to_start_date",
+            "must be greater than or equal to start date",
+        )]);
+
+        let (status, body) = render(error).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errorIdentifier"], PROPERTY_CONSTRAINT_VIOLATION);
+        assert_eq!(
+            body["message"],
+            "effectiveDate must be greater than or equal to start date"
+        );
+        assert_eq!(body["_embedded"]["details"]["attribute"], "effectiveDate");
+    }
+
+    #[tokio::test]
+    async fn test_version_errors_are_reported_in_one_response() {
+        use op_db::Repository;
+
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let result = op_db::VersionRepository::new(pool)
+            .create(op_db::CreateVersionDto {
+                project_id: 1,
+                name: String::new(),
+                description: None,
+                effective_date: None,
+                start_date: None,
+                status: Some("archived".into()),
+                sharing: None,
+                wiki_page_title: None,
+            })
+            .await;
+        let Err(op_db::RepositoryError::Validation(errors)) = result else {
+            panic!("expected a validation error");
+        };
+
+        let (status, body) = render(ApiError::validation(errors)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errorIdentifier"], MULTIPLE_ERRORS);
+
+        let errors = body["_embedded"]["errors"].as_array().unwrap();
+        let attributes: Vec<&str> = errors
+            .iter()
+            .map(|e| e["_embedded"]["details"]["attribute"].as_str().unwrap())
+            .collect();
+        assert_eq!(attributes, vec!["name", "status"]);
+        assert_eq!(errors[0]["errorIdentifier"], PROPERTY_CONSTRAINT_VIOLATION);
+        assert_eq!(errors[0]["message"], "name can't be blank");
+    }
+
+    #[tokio::test]
+    async fn test_validation_messages_render_in_the_request_locale() {
+        let error = || ApiError::invalid_property("email_frequency", "must be immediate, daily, weekly or never.");
+
+        let (_, body) = i18n::scope_locale(Some("de".into()), render(error())).await;
+        assert_eq!(body["errorIdentifier"], PROPERTY_CONSTRAINT_VIOLATION);
+        assert_eq!(body["message"], "E-Mail-Häufigkeit muss immediate, daily, weekly oder never sein.");
+        assert_eq!(body["_embedded"]["details"]["attribute"], "emailFrequency");
+
+        let (_, body) = i18n::scope_locale(Some("en".into()), render(error())).await;
+        assert_eq!(body["message"], "emailFrequency must be immediate, daily, weekly or never.");
+    }
+
+    #[tokio::test]
+    async fn test_query_timeout_is_a_validation_error() {
+        let (status, body) = render(ApiError::query(op_db::RepositoryError::Timeout(
+            "canceling statement due to statement timeout".into(),
+        )))
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["message"], QUERY_TIMEOUT_MESSAGE);
+        assert_eq!(body["_embedded"]["details"]["attribute"], "base");
+
+        let (status, _) = render(ApiError::query(op_db::RepositoryError::NotFound("query".into()))).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_filters_are_detailed_one_by_one() {
+        use op_queries::{Filter, FilterOperator, FilterValue};
+
+        let errors = [
+            Filter::equals("statsu_id", FilterValue::Id(1)),
+            Filter::new("id", FilterOperator::Contains, FilterValue::String("12".into())),
+        ]
+        .iter()
+        .map(|filter| op_db::validate_filter(filter).unwrap_err())
+        .collect();
+        let (status, body) = render(ApiError::query(op_db::RepositoryError::InvalidQuery(
+            op_db::QueryValidationError { errors },
+        )))
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errorIdentifier"], MULTIPLE_ERRORS);
+
+        let mut messages: Vec<&str> = body["_embedded"]["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["message"].as_str().unwrap())
+            .collect();
+        messages.sort();
+        assert_eq!(messages, vec!["id does not support the operator '~'", "statsuId is not a known filter"]);
+    }
+
+    #[tokio::test]
+    async fn test_base_error_message_is_not_prefixed() {
+        let (_, body) = render(ApiError::validation(vec![PropertyError::from(
+            "The deleted user placeholder cannot be deleted",
+        )]))
+        .await;
+
+        assert_eq!(body["message"], "The deleted user placeholder cannot be deleted");
+        assert_eq!(body["_embedded"]["details"]["attribute"], "base");
+    }
+}
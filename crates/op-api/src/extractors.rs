@@ -2,27 +2,146 @@
 
 use axum::{
     async_trait,
-    extract::{FromRef, FromRequestParts, Query},
+    extract::{FromRef, FromRequestParts, OriginalUri, Query},
     http::request::Parts,
 };
+use op_attachments::{AttachmentService, LocalStorage, PgAttachmentStore};
 use op_auth::permissions::CurrentUser;
+use op_auth::{AuthConfig, AuthError, AuthResult, Authenticator, RequestHeaders};
+use op_core::audit::{AuditEvent, AuditEventType, AuditLog};
+use op_core::clock::{parse_time_zone, Tz};
+use op_core::error::ValidationErrors;
+use op_core::events::{EventBus, EventQueue, EventSubscriber};
+use op_core::i18n::I18n;
+use op_core::metrics::DomainMetrics;
+use op_core::traits::Id;
+use op_notifications::{
+    InboundConfig, JobQueue, MemoryJobQueue, MemoryNotificationStore, NotificationStore, NotificationStreams, SummaryMailer,
+};
+use op_db::{
+    ApiKeyRepository, ApiKeyStore, CountStrategy, IdempotencyKeyRepository, IdempotencyStore, JournalRepository,
+    JournalStore, MemoryApiKeyStore, MemoryIdempotencyStore, MemoryJournalStore, MemoryProjectStore,
+    MemoryQueryResultCache, MemoryUserStore, MemoryWorkPackageStore, ProjectRepository, ProjectStore, QueryResultCache,
+    SettingRepository, UserRepository, UserStore, WorkPackageQueryExecutor, WorkPackageRepository, WorkPackageStore,
+    DEFAULT_EXACT_COUNT_THRESHOLD,
+};
+use op_services::base_contracts::UserContext;
+use op_services::reporting::StatusHistoryCache;
+use op_services::work_packages::WatcherNotifications;
 use sqlx::PgPool;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::error::ApiError;
+use crate::idempotency::IdempotencyConfig;
+use crate::maintenance::MaintenanceMode;
+use crate::representers::CollectionQuery;
+
+/// Default of [`AppConfig::relation_graph_limit`]
+pub const DEFAULT_RELATION_GRAPH_LIMIT: usize = 500;
 
 /// Application state with database pool
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<AppConfig>,
     pub db: Option<PgPool>,
+    pub audit: AuditLog,
+    pub i18n: Arc<I18n>,
+    /// Queue for background jobs such as project template instantiation
+    pub jobs: Arc<dyn JobQueue>,
+    /// In-app notifications shown in the notification center
+    pub notifications: Arc<dyn NotificationStore>,
+    /// Event streams of clients subscribed to their notifications
+    pub notification_streams: Arc<NotificationStreams>,
+    /// Domain events published after changes committed, notifying the
+    /// watchers of work packages in `notifications` among others
+    pub events: EventBus,
+    /// Attachment files and records; unset when no storage is configured
+    pub attachments: Option<Arc<Attachments>>,
+    /// Results of work package queries; unset when caching is disabled
+    pub query_cache: Option<Arc<dyn QueryResultCache>>,
+    /// Idempotency keys of POST requests and their responses
+    pub idempotency: Arc<dyn IdempotencyStore>,
+    /// API keys of the users
+    pub api_keys: Arc<dyn ApiKeyStore>,
+    /// Domain counters such as query cache hits
+    pub metrics: Option<Arc<DomainMetrics>>,
+    /// Maintenance mode blocking writes while active
+    pub maintenance: Arc<MaintenanceMode>,
+    /// Sender of the summary emails of bulk operations; unset sends none
+    pub summaries: Option<Arc<SummaryMailer>>,
+    /// Memoized status counts of past days for the project reports
+    pub status_history: Arc<StatusHistoryCache>,
+    /// Projects, work packages, users and journals of the embedded mode;
+    /// unset keeps them in the database
+    pub stores: Option<EntityStores>,
 }
 
+/// Stores of the entities the core handlers work with
+#[derive(Clone)]
+pub struct EntityStores {
+    pub projects: Arc<dyn ProjectStore>,
+    pub work_packages: Arc<dyn WorkPackageStore>,
+    pub users: Arc<dyn UserStore>,
+    pub journals: Arc<dyn JournalStore>,
+}
+
+impl EntityStores {
+    /// Stores of the database behind the pool
+    pub fn database(pool: &PgPool) -> Self {
+        Self {
+            projects: Arc::new(ProjectRepository::new(pool.clone())),
+            work_packages: Arc::new(WorkPackageRepository::new(pool.clone())),
+            users: Arc::new(UserRepository::new(pool.clone())),
+            journals: Arc::new(JournalRepository::new(pool.clone())),
+        }
+    }
+
+    /// Empty stores of a single process
+    pub fn memory() -> Self {
+        Self {
+            projects: Arc::new(MemoryProjectStore::new()),
+            work_packages: Arc::new(MemoryWorkPackageStore::new()),
+            users: Arc::new(MemoryUserStore::new()),
+            journals: Arc::new(MemoryJournalStore::new()),
+        }
+    }
+}
+
+/// Domain events of a state delivering notifications to the store
+fn default_events(notifications: Arc<dyn NotificationStore>) -> EventBus {
+    EventBus::new().with_subscriber(Arc::new(WatcherNotifications::new(notifications)))
+}
+
+/// Attachment service of the instance
+pub type Attachments = AttachmentService<PgAttachmentStore, LocalStorage>;
+
 #[derive(Clone)]
 pub struct AppConfig {
     pub api_version: String,
     pub base_url: String,
+    /// Whether requests without credentials are rejected while the
+    /// `login_required` setting is unset
     pub require_authentication: bool,
+    /// Shared token of the inbound email endpoint; unset disables it
+    pub inbound_email_token: Option<String>,
+    /// Processing of replies to notification emails
+    pub inbound_email: InboundConfig,
+    /// Lifetime of cached work package query results; unset disables the
+    /// cache
+    pub query_cache_ttl: Option<Duration>,
+    /// Replaying of POST requests retried with the same idempotency key
+    pub idempotency: IdempotencyConfig,
+    /// Time zone of the instance, for users without one in their
+    /// preferences
+    pub time_zone: Tz,
+    /// Counting of the totals of work package queries
+    pub count_strategy: CountStrategy,
+    /// Duration after which work package queries are canceled; unset
+    /// lets them run
+    pub statement_timeout: Option<Duration>,
+    /// Most work packages the relation graph of one request may involve
+    pub relation_graph_limit: usize,
 }
 
 impl Default for AppConfig {
@@ -31,29 +150,351 @@ impl Default for AppConfig {
             api_version: "3".into(),
             base_url: "http://localhost:8080".into(),
             require_authentication: true,
+            inbound_email_token: None,
+            inbound_email: InboundConfig::default(),
+            query_cache_ttl: None,
+            idempotency: IdempotencyConfig::default(),
+            time_zone: Tz::UTC,
+            count_strategy: CountStrategy::Estimated { threshold: DEFAULT_EXACT_COUNT_THRESHOLD },
+            statement_timeout: Some(Duration::from_secs(30)),
+            relation_graph_limit: DEFAULT_RELATION_GRAPH_LIMIT,
         }
     }
 }
 
 impl Default for AppState {
     fn default() -> Self {
+        let notifications: Arc<dyn NotificationStore> = Arc::new(MemoryNotificationStore::new());
         Self {
             config: Arc::new(AppConfig::default()),
             db: None,
+            audit: AuditLog::tracing(),
+            i18n: I18n::shared(),
+            jobs: Arc::new(MemoryJobQueue::new()),
+            notifications: notifications.clone(),
+            notification_streams: Arc::new(NotificationStreams::new()),
+            events: default_events(notifications),
+            attachments: None,
+            query_cache: None,
+            idempotency: Arc::new(MemoryIdempotencyStore::new()),
+            api_keys: Arc::new(MemoryApiKeyStore::new()),
+            metrics: None,
+            maintenance: Arc::new(MaintenanceMode::new()),
+            summaries: None,
+            status_history: Arc::new(StatusHistoryCache::new()),
+            stores: None,
         }
     }
 }
 
 impl AppState {
+    /// State backed by a database pool; audit events are persisted as well as
+    /// logged, and idempotency keys are shared with other instances
+    pub fn with_pool(pool: PgPool) -> Self {
+        let audit = AuditLog::tracing()
+            .with_sink(Arc::new(op_db::AuditEventRepository::new(pool.clone())));
+        let notifications: Arc<dyn NotificationStore> = Arc::new(MemoryNotificationStore::new());
+        Self {
+            config: Arc::new(AppConfig::default()),
+            db: Some(pool.clone()),
+            audit,
+            i18n: I18n::shared(),
+            jobs: Arc::new(MemoryJobQueue::new()),
+            notifications: notifications.clone(),
+            notification_streams: Arc::new(NotificationStreams::new()),
+            events: default_events(notifications),
+            attachments: None,
+            query_cache: None,
+            idempotency: Arc::new(IdempotencyKeyRepository::new(pool.clone())),
+            api_keys: Arc::new(ApiKeyRepository::new(pool.clone())),
+            metrics: None,
+            maintenance: Arc::new(MaintenanceMode::with_pool(pool)),
+            summaries: None,
+            status_history: Arc::new(StatusHistoryCache::new()),
+            stores: None,
+        }
+    }
+
+    /// State of the embedded mode, running without a database: projects,
+    /// work packages, users and journals are kept in memory, and the
+    /// features needing the database fail as without one
+    pub fn embedded() -> Self {
+        Self::default().with_stores(EntityStores::memory())
+    }
+
+    /// Keep projects, work packages, users and journals in the stores
+    /// rather than the database
+    pub fn with_stores(mut self, stores: EntityStores) -> Self {
+        self.stores = Some(stores);
+        self
+    }
+
+    /// Use the configuration, caching query results in memory when it sets
+    /// a lifetime for them
+    pub fn with_config(mut self, config: AppConfig) -> Self {
+        self.query_cache = config
+            .query_cache_ttl
+            .map(|ttl| Arc::new(MemoryQueryResultCache::new(ttl)) as Arc<dyn QueryResultCache>);
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Use a shared query result cache, e.g. one shared by several instances
+    pub fn with_query_cache(mut self, cache: Arc<dyn QueryResultCache>) -> Self {
+        self.query_cache = Some(cache);
+        self
+    }
+
+    /// Use a shared idempotency key store
+    pub fn with_idempotency_store(mut self, store: Arc<dyn IdempotencyStore>) -> Self {
+        self.idempotency = store;
+        self
+    }
+
+    /// Use a shared API key store
+    pub fn with_api_keys(mut self, api_keys: Arc<dyn ApiKeyStore>) -> Self {
+        self.api_keys = api_keys;
+        self
+    }
+
+    /// Record domain counters in the given metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<DomainMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Use a shared maintenance mode, e.g. the one the server's middleware
+    /// and job workers follow
+    pub fn with_maintenance(mut self, maintenance: Arc<MaintenanceMode>) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Use a shared job queue, e.g. the one the job workers consume
+    pub fn with_jobs(mut self, jobs: Arc<dyn JobQueue>) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Use a shared notification store, e.g. the one notifications are delivered to
+    pub fn with_notifications(mut self, notifications: Arc<dyn NotificationStore>) -> Self {
+        self.events = self.events.with_subscriber(Arc::new(WatcherNotifications::new(notifications.clone())));
+        self.notifications = notifications;
+        self
+    }
+
+    /// Register a subscriber of the domain events, after the ones
+    /// registered before
+    pub fn with_event_subscriber(mut self, subscriber: Arc<dyn EventSubscriber>) -> Self {
+        self.events = self.events.with_subscriber(subscriber);
+        self
+    }
+
+    /// Enqueue the events of queued subscribers, e.g. to the job queue the
+    /// workers consume
+    pub fn with_event_queue(mut self, queue: Arc<dyn EventQueue>) -> Self {
+        self.events = self.events.with_queue(queue);
+        self
+    }
+
+    /// Use shared notification streams, e.g. the ones the notification service publishes to
+    pub fn with_notification_streams(mut self, streams: Arc<NotificationStreams>) -> Self {
+        self.notification_streams = streams;
+        self
+    }
+
+    /// Email watchers and assignees a summary of each bulk operation
+    pub fn with_summary_mailer(mut self, mailer: Arc<SummaryMailer>) -> Self {
+        self.summaries = Some(mailer);
+        self
+    }
+
+    /// Use the attachment service, e.g. one storing files under the configured path
+    pub fn with_attachments(mut self, attachments: Arc<Attachments>) -> Self {
+        self.attachments = Some(attachments);
+        self
+    }
+
     /// Get database pool, returns error if not configured
     pub fn pool(&self) -> Result<&PgPool, ApiError> {
         self.db.as_ref().ok_or_else(|| ApiError::internal("Database not configured"))
     }
+
+    /// Stores of the embedded mode, or else of the database
+    pub fn entity_stores(&self) -> Result<EntityStores, ApiError> {
+        match &self.stores {
+            Some(stores) => Ok(stores.clone()),
+            None => self.pool().map(EntityStores::database),
+        }
+    }
+
+    /// Get attachment service, returns error if not configured
+    pub fn attachments(&self) -> Result<&Attachments, ApiError> {
+        self.attachments
+            .as_deref()
+            .ok_or_else(|| ApiError::internal("Attachment storage not configured"))
+    }
+
+    /// Executor of work package queries of the user, using the query cache
+    /// if any. Relative date filters are evaluated in the user's time zone,
+    /// totals are counted with the configured strategy and queries are
+    /// canceled after the statement timeout.
+    pub async fn work_package_queries(&self, user_id: Id) -> Result<WorkPackageQueryExecutor, ApiError> {
+        let time_zone = self.time_zone(user_id).await?;
+        let mut executor = WorkPackageQueryExecutor::new(self.pool()?)
+            .in_time_zone(time_zone)
+            .with_count_strategy(self.config.count_strategy);
+        if let Some(cache) = &self.query_cache {
+            executor = executor.with_cache(cache.clone());
+        }
+        if let Some(metrics) = &self.metrics {
+            executor = executor.with_metrics(metrics.clone());
+        }
+        if let Some(timeout) = self.config.statement_timeout {
+            executor = executor.with_statement_timeout(timeout);
+        }
+        Ok(executor)
+    }
+
+    /// Time zone of the user's preferences, falling back to the instance's
+    /// when the user chose none or one that is unknown
+    pub async fn time_zone(&self, user_id: Id) -> Result<Tz, ApiError> {
+        let preferred = UserRepository::new(self.pool()?.clone())
+            .find_time_zone(user_id)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+        Ok(preferred
+            .as_deref()
+            .and_then(parse_time_zone)
+            .unwrap_or(self.config.time_zone))
+    }
+
+    /// Render the request in the user's language unless its
+    /// `Accept-Language` header named an available one
+    pub async fn adopt_user_locale(&self, user_id: Id) -> Result<(), ApiError> {
+        let Some(pool) = &self.db else {
+            return Ok(());
+        };
+        if op_core::i18n::current_locale().is_some() {
+            return Ok(());
+        }
+        let language = UserRepository::new(pool.clone())
+            .find_language(user_id)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+        if let Some(locale) = language.as_deref().and_then(|language| self.i18n.available_locale(language)) {
+            op_core::i18n::set_current_locale(locale);
+        }
+        Ok(())
+    }
+
+    /// Whether requests must be authenticated: the `login_required` setting,
+    /// or the configured default while it is unset
+    pub async fn login_required(&self) -> Result<bool, ApiError> {
+        let Some(pool) = &self.db else {
+            return Ok(self.config.require_authentication);
+        };
+        let stored = SettingRepository::new(pool.clone())
+            .get(LOGIN_REQUIRED_SETTING)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+        Ok(match stored.as_deref() {
+            Some("1") | Some("true") => true,
+            Some("0") | Some("false") => false,
+            _ => self.config.require_authentication,
+        })
+    }
+
+    /// Outdate cached query results of projects whose work packages were
+    /// created, changed or deleted
+    pub async fn work_packages_changed(&self, project_ids: &[Id]) {
+        if let Some(cache) = &self.query_cache {
+            for project_id in project_ids {
+                cache.invalidate_project(*project_id).await;
+            }
+        }
+    }
+
+    /// Build a validation error with messages in the user's language,
+    /// falling back to the instance default locale
+    pub fn validation_error(&self, language: Option<&str>, errors: &ValidationErrors) -> ApiError {
+        let locale = self.i18n.resolve_locale(language);
+        let mut localized = ValidationErrors::new();
+        for message in errors.localized_messages(&self.i18n, &locale) {
+            localized.add_base(message);
+        }
+        ApiError::Validation(localized)
+    }
+
+    /// Record an audit event performed by `user` from `client`
+    pub async fn audit(&self, user: &CurrentUser, client: &ClientInfo, event: AuditEvent) {
+        let event = event
+            .actor(user.id())
+            .client(client.ip_address.clone(), client.user_agent.clone());
+        self.audit.emit(event).await;
+    }
+
+    /// Record a denied admin action and build the matching error
+    pub async fn deny(
+        &self,
+        user: &CurrentUser,
+        client: &ClientInfo,
+        action: &str,
+        message: &str,
+    ) -> ApiError {
+        let event = AuditEvent::new(AuditEventType::PermissionDenied).detail("action", action);
+        self.audit(user, client, event).await;
+        ApiError::forbidden(message)
+    }
+}
+
+/// Name of the setting letting anonymous users read public projects when off
+pub const LOGIN_REQUIRED_SETTING: &str = "login_required";
+
+/// Paths anonymous users may read unless login is required: public projects
+/// and their contents. Private data such as notifications, API keys and
+/// other users stays behind authentication.
+const ANONYMOUS_READABLE_PATHS: [&str; 9] = [
+    "/api/v3/projects",
+    "/api/v3/work_packages",
+    "/api/v3/forums",
+    "/api/v3/messages",
+    "/api/v3/versions",
+    "/api/v3/categories",
+    "/api/v3/statuses",
+    "/api/v3/types",
+    "/api/v3/priorities",
+];
+
+/// Whether an anonymous user may send a request: reads of the paths above
+/// and of their own user, `/api/v3/users/me`
+pub fn anonymous_allows(write: bool, path: &str) -> bool {
+    if write {
+        return false;
+    }
+    path == "/api/v3/users/me"
+        || ANONYMOUS_READABLE_PATHS
+            .iter()
+            .any(|prefix| path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
 }
 
 /// Authenticated user extractor
+///
+/// Requests without credentials are served as [`anonymous`](Self::anonymous)
+/// if login is not required and [`anonymous_allows`] them, and are rejected
+/// with a 401 otherwise.
 pub struct AuthenticatedUser(pub CurrentUser);
 
+impl AuthenticatedUser {
+    /// The anonymous user of requests without credentials
+    pub fn anonymous() -> Self {
+        Self(CurrentUser::anonymous())
+    }
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthenticatedUser
 where
@@ -64,29 +505,87 @@ where
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let app_state = AppState::from_ref(state);
+        let write = !matches!(parts.method.as_str(), "GET" | "HEAD" | "OPTIONS");
+        let path = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map_or(parts.uri.path(), |uri| uri.0.path());
+
+        if let Some(user) = api_key_user(&app_state, parts).await? {
+            if !user.scope_allows(write, path) {
+                return Err(ApiError::insufficient_scope(
+                    "The scopes of the API key do not allow this request.",
+                ));
+            }
+            app_state.adopt_user_locale(user.id()).await?;
+            return Ok(AuthenticatedUser(user));
+        }
 
         // Check Authorization header
         if let Some(auth) = parts.headers.get("authorization") {
             if let Ok(auth_str) = auth.to_str() {
                 if auth_str.starts_with("Basic ") || auth_str.starts_with("Bearer ") {
                     // Mock user for now
-                    return Ok(AuthenticatedUser(CurrentUser::new(
-                        1,
-                        "api_user",
-                        "api@example.com",
-                    )));
+                    let user = CurrentUser::new(1, "api_user", "api@example.com");
+                    app_state.adopt_user_locale(user.id()).await?;
+                    return Ok(AuthenticatedUser(user));
                 }
             }
         }
 
-        if !app_state.config.require_authentication {
-            return Ok(AuthenticatedUser(CurrentUser::anonymous()));
+        if anonymous_allows(write, path) && !app_state.login_required().await? {
+            return Ok(AuthenticatedUser::anonymous());
         }
 
         Err(ApiError::unauthorized("Authentication required"))
     }
 }
 
+/// User of the API key the request is sent with, if any, restricted to the
+/// key's scopes
+async fn api_key_user(state: &AppState, parts: &Parts) -> Result<Option<CurrentUser>, ApiError> {
+    let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let headers = RequestHeaders {
+        authorization: header("authorization"),
+        api_key: header("x-openproject-api-key"),
+        ..Default::default()
+    };
+    if headers.api_key.is_none() && headers.basic_api_key().is_none() {
+        return Ok(None);
+    }
+
+    let authenticator = Authenticator::new(AuthConfig::api_keys(state.api_keys.clone()));
+    match authenticator.authenticate(&headers).await {
+        AuthResult::Authenticated(user) => stored_account(state, user).await.map(Some),
+        AuthResult::Failed(AuthError::Internal(e)) => Err(ApiError::internal(e)),
+        _ => Err(ApiError::unauthorized("Invalid API key")),
+    }
+}
+
+/// The user of an API key with the login, email and admin flag of the
+/// stored user, if users are stored; locked users are rejected
+async fn stored_account(state: &AppState, mut user: CurrentUser) -> Result<CurrentUser, ApiError> {
+    let Ok(stores) = state.entity_stores() else {
+        return Ok(user);
+    };
+    let Some(row) = stores
+        .users
+        .find_by_id(user.id())
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+    else {
+        return Ok(user);
+    };
+    if row.is_locked() {
+        return Err(ApiError::unauthorized("Invalid API key"));
+    }
+
+    user.login = row.login;
+    user.email = row.mail;
+    user.is_admin = row.admin;
+    Ok(user)
+}
+
 impl std::ops::Deref for AuthenticatedUser {
     type Target = CurrentUser;
     fn deref(&self) -> &Self::Target {
@@ -94,6 +593,64 @@ impl std::ops::Deref for AuthenticatedUser {
     }
 }
 
+/// Lets services check the user's permissions
+impl UserContext for AuthenticatedUser {
+    fn id(&self) -> Id {
+        self.0.id()
+    }
+
+    fn is_admin(&self) -> bool {
+        self.0.is_admin()
+    }
+
+    fn is_anonymous(&self) -> bool {
+        self.0.is_anonymous()
+    }
+
+    fn allowed_in_project(&self, permission: &str, project_id: Id) -> bool {
+        self.0.allowed_in_project(permission, project_id)
+    }
+
+    fn allowed_globally(&self, permission: &str) -> bool {
+        self.0.allowed_globally(permission)
+    }
+}
+
+/// Client details recorded in audit events
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        let ip_address = header("x-forwarded-for")
+            .and_then(|v| v.split(',').next().map(|ip| ip.trim().to_string()))
+            .or_else(|| header("x-real-ip"))
+            .filter(|ip| !ip.is_empty());
+
+        Ok(ClientInfo {
+            ip_address,
+            user_agent: header("user-agent"),
+        })
+    }
+}
+
 /// Pagination parameters
 #[derive(Debug, Clone, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -141,6 +698,64 @@ impl std::ops::Deref for Pagination {
     }
 }
 
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DryRunParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// `dryRun=true` on a create or update request: validate the payload and
+/// respond with the resource as it would be saved, without saving it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DryRun(pub bool);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for DryRun
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<DryRunParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::invalid_property("dryRun", "must be true or false"))?;
+        Ok(DryRun(params.dry_run))
+    }
+}
+
+impl DryRun {
+    /// Representation of a resource that was validated but not created; as
+    /// it was never saved, it has no id
+    pub fn unsaved<T: serde::Serialize>(resource: &T) -> serde_json::Value {
+        let mut json = serde_json::to_value(resource).unwrap_or_default();
+        if let Some(object) = json.as_object_mut() {
+            object.remove("id");
+        }
+        json
+    }
+}
+
+/// Query parameters of a collection request, for pagination links. Uses
+/// the original URI so nested routers keep their full path.
+#[async_trait]
+impl<S> FromRequestParts<S> for CollectionQuery
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let uri = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map(|original| &original.0)
+            .unwrap_or(&parts.uri);
+        Ok(CollectionQuery::from_query_string(uri.path(), uri.query()))
+    }
+}
+
 /// HAL+JSON response wrapper
 pub struct HalResponse<T: serde::Serialize>(pub T);
 
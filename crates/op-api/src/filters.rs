@@ -0,0 +1,150 @@
+//! API v3 Filter and Sort Parameters
+//!
+//! Collections are filtered with `filters`, a JSON list of filters keyed by
+//! name, e.g. `[{"principal":{"operator":"=","values":["5"]}}]`, and sorted
+//! with `sortBy`, a JSON list of attribute and direction pairs, e.g.
+//! `[["name","asc"]]`. Both are parsed the way saved queries are, so
+//! operators and values mean the same in every collection.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use op_core::traits::Id;
+use op_queries::export::{FilterEntry, SortEntry};
+use op_queries::{Filter, FilterValue, SortCriterion};
+use serde::Deserialize;
+
+use crate::error::{ApiError, ApiResult};
+
+#[derive(Debug, Deserialize)]
+struct FilterDto {
+    operator: String,
+    #[serde(default)]
+    values: Vec<String>,
+}
+
+/// Parse a `filters` parameter; no parameter means no filters
+pub fn parse_filters(filters: Option<&str>) -> ApiResult<Vec<Filter>> {
+    let Some(filters) = filters else {
+        return Ok(Vec::new());
+    };
+
+    let filters: Vec<BTreeMap<String, FilterDto>> = serde_json::from_str(filters)
+        .map_err(|e| ApiError::bad_request(format!("Invalid filters: {}", e)))?;
+
+    filters
+        .into_iter()
+        .flatten()
+        .map(|(name, filter)| {
+            FilterEntry {
+                attribute: name,
+                operator: filter.operator,
+                values: filter.values,
+            }
+            .to_filter()
+            .map_err(|e| ApiError::invalid_property(e.property(), e.to_string()))
+        })
+        .collect()
+}
+
+/// Parse a `sortBy` parameter; no parameter means the default order
+pub fn parse_sort_by(sort_by: Option<&str>) -> ApiResult<Vec<SortCriterion>> {
+    let Some(sort_by) = sort_by else {
+        return Ok(Vec::new());
+    };
+
+    let sort_by: Vec<(String, String)> = serde_json::from_str(sort_by)
+        .map_err(|e| ApiError::bad_request(format!("Invalid sortBy: {}", e)))?;
+
+    sort_by
+        .into_iter()
+        .map(|(attribute, direction)| {
+            SortEntry { attribute, direction }
+                .to_criterion()
+                .map_err(|e| ApiError::invalid_property(e.property(), e.to_string()))
+        })
+        .collect()
+}
+
+/// Ids a filter matches, `me` standing for the current user
+pub fn filter_ids(filter: &Filter, current_user: Id) -> ApiResult<Vec<Id>> {
+    match &filter.values {
+        FilterValue::Id(id) => Ok(vec![*id]),
+        FilterValue::Ids(ids) => Ok(ids.clone()),
+        FilterValue::Me => Ok(vec![current_user]),
+        FilterValue::IdsAndMe(ids) => Ok(std::iter::once(current_user).chain(ids.iter().copied()).collect()),
+        _ => Err(ApiError::invalid_property(
+            &filter.attribute,
+            "filter values must be ids",
+        )),
+    }
+}
+
+/// First and last day of a `<>d` filter, either left open when blank
+pub fn filter_date_range(filter: &Filter) -> ApiResult<(Option<NaiveDate>, Option<NaiveDate>)> {
+    let FilterValue::DateRange { from, to } = &filter.values else {
+        return Err(ApiError::invalid_property(
+            &filter.attribute,
+            "filter values must be a first and a last day",
+        ));
+    };
+
+    let parse = |day: &str| {
+        if day.is_empty() {
+            return Ok(None);
+        }
+        NaiveDate::parse_from_str(day, "%Y-%m-%d").map(Some).map_err(|_| {
+            ApiError::invalid_property(&filter.attribute, format!("'{}' is not a valid date", day))
+        })
+    };
+    Ok((parse(from)?, parse(to)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_queries::{FilterOperator, SortDirection};
+
+    #[test]
+    fn test_parse_filters() {
+        let filters = parse_filters(Some(
+            r#"[{"principal":{"operator":"=","values":["5","me"]}},{"createdAt":{"operator":"<>d","values":["2024-01-01",""]}}]"#,
+        ))
+        .unwrap();
+
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].attribute, "principal");
+        assert_eq!(filters[0].operator, FilterOperator::Equals);
+        assert_eq!(filter_ids(&filters[0], 1).unwrap(), vec![1, 5]);
+        assert_eq!(filters[1].operator, FilterOperator::Between);
+        assert_eq!(
+            filter_date_range(&filters[1]).unwrap(),
+            (NaiveDate::from_ymd_opt(2024, 1, 1), None)
+        );
+        assert!(parse_filters(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_filters_rejects_invalid_input() {
+        assert!(matches!(parse_filters(Some("{")), Err(ApiError::BadRequest(_))));
+        assert!(matches!(
+            parse_filters(Some(r#"[{"principal":{"operator":"?","values":[]}}]"#)),
+            Err(ApiError::Validation(_))
+        ));
+
+        let filters = parse_filters(Some(r#"[{"principal":{"operator":"=","values":["alice"]}}]"#)).unwrap();
+        assert!(filter_ids(&filters[0], 1).is_err());
+        assert!(filter_date_range(&filters[0]).is_err());
+    }
+
+    #[test]
+    fn test_parse_sort_by() {
+        let sort_by = parse_sort_by(Some(r#"[["name","desc"],["id","asc"]]"#)).unwrap();
+        assert_eq!(sort_by[0].attribute, "name");
+        assert_eq!(sort_by[0].direction, SortDirection::Desc);
+        assert_eq!(sort_by[1].direction, SortDirection::Asc);
+
+        assert!(parse_sort_by(Some(r#"[["name","up"]]"#)).is_err());
+        assert!(parse_sort_by(Some(r#"["name"]"#)).is_err());
+    }
+}
@@ -0,0 +1,471 @@
+//! Formatted Text Rendering
+//!
+//! Mirrors: lib/open_project/text_formatting/matchers/resource_links_matcher.rb
+//!
+//! Renders markdown written by users, such as work package descriptions and
+//! comments, to HTML. Cross-references become links: `#123` links to work
+//! package 123 and `##123` additionally shows its type, subject and status.
+//! Revision references (`r123` and commit hashes) are recognized too, but
+//! stay plain text until a resolver knows where they link to.
+//!
+//! References are resolved in a single resolver call per batch of rendered
+//! documents. Only work packages the viewing user may see are linked; any
+//! other reference is rendered as the text it was written as.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use axum::async_trait;
+use op_auth::CurrentUser;
+use op_core::traits::Id;
+use op_db::{WorkPackageReferenceRow, WorkPackageRepository, WorkPackageStore};
+use sqlx::PgPool;
+
+use crate::error::{ApiError, ApiResult};
+
+/// Shortest commit hash recognized as a revision reference
+const MIN_HASH_LEN: usize = 7;
+
+/// Longest commit hash (SHA-1)
+const MAX_HASH_LEN: usize = 40;
+
+/// Part of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// Text rendered as is
+    Text(&'a str),
+    /// `#123`, or `##123` when `embed` is set
+    WorkPackage { id: Id, embed: bool, source: &'a str },
+    /// `r123` or a commit hash
+    Revision(&'a str),
+}
+
+/// Split a document into text and references. Inline code spans are left
+/// as text.
+pub fn parse_references(text: &str) -> Vec<Segment<'_>> {
+    let bytes = text.as_bytes();
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'`' {
+            i = text[i + 1..].find('`').map_or(bytes.len(), |end| i + end + 2);
+            continue;
+        }
+
+        let at_boundary = i == 0 || !blocks_reference(bytes[i - 1]);
+        if let Some((segment, end)) = at_boundary.then(|| reference_at(text, i)).flatten() {
+            if text_start < i {
+                segments.push(Segment::Text(&text[text_start..i]));
+            }
+            segments.push(segment);
+            text_start = end;
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+
+    if text_start < text.len() {
+        segments.push(Segment::Text(&text[text_start..]));
+    }
+    segments
+}
+
+/// Whether a reference may not start right after the byte. Bytes of
+/// non-ASCII characters count as word characters.
+fn blocks_reference(byte: u8) -> bool {
+    is_word(byte) || matches!(byte, b'#' | b'/' | b'&')
+}
+
+fn is_word(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii()
+}
+
+/// The reference starting at `start`, with the index after it
+fn reference_at(text: &str, start: usize) -> Option<(Segment<'_>, usize)> {
+    let rest = &text[start..];
+    let word_end = |from: usize| {
+        start + from + rest[from..].bytes().take_while(|&b| is_word(b)).count()
+    };
+
+    let (hashes, embed) = if rest.starts_with("##") {
+        (2, true)
+    } else if rest.starts_with('#') {
+        (1, false)
+    } else if rest.starts_with('r') {
+        let end = word_end(1);
+        let digits = &text[start + 1..end];
+        let valid = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+        return valid.then(|| (Segment::Revision(&text[start..end]), end));
+    } else {
+        let end = word_end(0);
+        return is_commit_hash(&text[start..end]).then(|| (Segment::Revision(&text[start..end]), end));
+    };
+
+    let end = word_end(hashes);
+    let id = text[start + hashes..end]
+        .bytes()
+        .all(|b| b.is_ascii_digit())
+        .then(|| text[start + hashes..end].parse::<Id>().ok())
+        .flatten()?;
+    Some((
+        Segment::WorkPackage {
+            id,
+            embed,
+            source: &text[start..end],
+        },
+        end,
+    ))
+}
+
+/// Hex words of commit hash length with both digits and letters, so that
+/// plain numbers and words are not mistaken for hashes
+fn is_commit_hash(word: &str) -> bool {
+    (MIN_HASH_LEN..=MAX_HASH_LEN).contains(&word.len())
+        && word.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        && word.bytes().any(|b| b.is_ascii_digit())
+        && word.bytes().any(|b| b.is_ascii_alphabetic())
+}
+
+/// Referenced work package as shown in the link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferencedWorkPackage {
+    pub id: Id,
+    pub subject: String,
+    pub type_name: Option<String>,
+    pub status_name: Option<String>,
+    pub closed: bool,
+}
+
+impl From<WorkPackageReferenceRow> for ReferencedWorkPackage {
+    fn from(row: WorkPackageReferenceRow) -> Self {
+        Self {
+            id: row.id,
+            subject: row.subject,
+            type_name: row.type_name,
+            status_name: row.status_name,
+            closed: row.status_is_closed.unwrap_or(false),
+        }
+    }
+}
+
+/// Looks up the targets of the references in rendered documents
+#[async_trait]
+pub trait ReferenceResolver: Send + Sync {
+    /// The referenced work packages the viewing user may see; the others
+    /// are left out
+    async fn work_packages(&self, ids: &[Id]) -> ApiResult<HashMap<Id, ReferencedWorkPackage>>;
+
+    /// Link targets of referenced revisions. Repositories are not
+    /// supported, so by default no revision is resolved.
+    async fn revisions(&self, _revisions: &[String]) -> ApiResult<HashMap<String, String>> {
+        Ok(HashMap::new())
+    }
+}
+
+/// Resolves references to the work packages the user may view
+pub struct DbReferenceResolver {
+    pool: PgPool,
+    /// Restrict to this user's visible work packages; unset for admins
+    visible_to: Option<Id>,
+}
+
+impl DbReferenceResolver {
+    pub fn new(pool: PgPool, user: &CurrentUser) -> Self {
+        Self {
+            pool,
+            visible_to: (!user.is_admin()).then_some(user.id),
+        }
+    }
+}
+
+#[async_trait]
+impl ReferenceResolver for DbReferenceResolver {
+    async fn work_packages(&self, ids: &[Id]) -> ApiResult<HashMap<Id, ReferencedWorkPackage>> {
+        let rows = WorkPackageRepository::new(self.pool.clone())
+            .find_references(ids, self.visible_to)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.into())).collect())
+    }
+}
+
+/// Resolves references against a work package store, as in embedded mode.
+/// Without types and statuses, links show the subject only and no work
+/// package counts as closed.
+pub struct StoreReferenceResolver {
+    work_packages: Arc<dyn WorkPackageStore>,
+}
+
+impl StoreReferenceResolver {
+    pub fn new(work_packages: Arc<dyn WorkPackageStore>) -> Self {
+        Self { work_packages }
+    }
+}
+
+#[async_trait]
+impl ReferenceResolver for StoreReferenceResolver {
+    async fn work_packages(&self, ids: &[Id]) -> ApiResult<HashMap<Id, ReferencedWorkPackage>> {
+        let mut found = HashMap::new();
+        for &id in ids {
+            let row = self
+                .work_packages
+                .find_by_id(id)
+                .await
+                .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+            if let Some(row) = row {
+                found.insert(
+                    row.id,
+                    ReferencedWorkPackage {
+                        id: row.id,
+                        subject: row.subject,
+                        type_name: None,
+                        status_name: None,
+                        closed: false,
+                    },
+                );
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// Renders markdown documents, linking their references
+pub struct MarkdownRenderer<R: ReferenceResolver> {
+    resolver: R,
+}
+
+impl<R: ReferenceResolver> MarkdownRenderer<R> {
+    pub fn new(resolver: R) -> Self {
+        Self { resolver }
+    }
+
+    /// Render a single document
+    pub async fn render(&self, text: &str) -> ApiResult<String> {
+        Ok(self.render_all(&[text]).await?.pop().unwrap_or_default())
+    }
+
+    /// Render documents, resolving the references of all of them at once
+    pub async fn render_all(&self, texts: &[&str]) -> ApiResult<Vec<String>> {
+        let documents: Vec<Vec<Segment>> = texts.iter().map(|text| parse_references(text)).collect();
+
+        let mut ids = BTreeSet::new();
+        let mut revisions = BTreeSet::new();
+        for segment in documents.iter().flatten() {
+            match segment {
+                Segment::WorkPackage { id, .. } => {
+                    ids.insert(*id);
+                }
+                Segment::Revision(revision) => {
+                    revisions.insert(revision.to_string());
+                }
+                Segment::Text(_) => {}
+            }
+        }
+
+        let work_packages = if ids.is_empty() {
+            HashMap::new()
+        } else {
+            self.resolver.work_packages(&ids.into_iter().collect::<Vec<_>>()).await?
+        };
+        let revision_links = if revisions.is_empty() {
+            HashMap::new()
+        } else {
+            self.resolver.revisions(&revisions.into_iter().collect::<Vec<_>>()).await?
+        };
+
+        Ok(documents
+            .iter()
+            .map(|segments| {
+                let html: String = segments
+                    .iter()
+                    .map(|segment| render_segment(segment, &work_packages, &revision_links))
+                    .collect();
+                format!("<p>{}</p>", html)
+            })
+            .collect())
+    }
+}
+
+fn render_segment(
+    segment: &Segment,
+    work_packages: &HashMap<Id, ReferencedWorkPackage>,
+    revision_links: &HashMap<String, String>,
+) -> String {
+    match segment {
+        Segment::Text(text) => html_escape(text),
+        Segment::WorkPackage { id, embed, source } => match work_packages.get(id) {
+            Some(work_package) if *embed => work_package_quickinfo(work_package),
+            Some(work_package) => work_package_link(work_package, &format!("#{}", work_package.id)),
+            None => html_escape(source),
+        },
+        Segment::Revision(revision) => match revision_links.get(*revision) {
+            Some(href) => format!(
+                r#"<a class="changeset" href="{}">{}</a>"#,
+                html_escape(href),
+                html_escape(revision)
+            ),
+            None => html_escape(revision),
+        },
+    }
+}
+
+/// Link with the classes the frontend attaches the hover preview to
+fn work_package_link(work_package: &ReferencedWorkPackage, label: &str) -> String {
+    format!(
+        r#"<a class="issue work_package{} preview-trigger" href="/work_packages/{}" data-work-package-id="{}">{}</a>"#,
+        if work_package.closed { " closed" } else { "" },
+        work_package.id,
+        work_package.id,
+        html_escape(label)
+    )
+}
+
+fn work_package_quickinfo(work_package: &ReferencedWorkPackage) -> String {
+    let label = match &work_package.type_name {
+        Some(type_name) => format!("{} #{}", type_name, work_package.id),
+        None => format!("#{}", work_package.id),
+    };
+    let status = work_package
+        .status_name
+        .as_deref()
+        .map(|status| format!(r#" <span class="work-package--status">{}</span>"#, html_escape(status)))
+        .unwrap_or_default();
+
+    format!(
+        r#"<span class="work-package--quickinfo" data-work-package-id="{}">{}: <span class="work-package--subject">{}</span>{}</span>"#,
+        work_package.id,
+        work_package_link(work_package, &label),
+        html_escape(&work_package.subject),
+        status
+    )
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Resolver over fixed work packages, some of them invisible to the
+    /// viewer, recording its calls
+    struct FakeResolver {
+        work_packages: Vec<ReferencedWorkPackage>,
+        invisible: Vec<Id>,
+        calls: Mutex<Vec<Vec<Id>>>,
+    }
+
+    impl FakeResolver {
+        fn new(invisible: Vec<Id>) -> Self {
+            let work_package = |id: Id, subject: &str, closed: bool| ReferencedWorkPackage {
+                id,
+                subject: subject.into(),
+                type_name: Some("Bug".into()),
+                status_name: Some(if closed { "Closed" } else { "New" }.into()),
+                closed,
+            };
+            Self {
+                work_packages: vec![
+                    work_package(12, "Crash <on> save", false),
+                    work_package(13, "Old report", true),
+                    work_package(99, "Private project plans", false),
+                ],
+                invisible,
+                calls: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ReferenceResolver for FakeResolver {
+        async fn work_packages(&self, ids: &[Id]) -> ApiResult<HashMap<Id, ReferencedWorkPackage>> {
+            self.calls.lock().unwrap().push(ids.to_vec());
+            Ok(self
+                .work_packages
+                .iter()
+                .filter(|wp| ids.contains(&wp.id) && !self.invisible.contains(&wp.id))
+                .map(|wp| (wp.id, wp.clone()))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let segments = parse_references("See #12, ##13 and r42; not a#1, #1a, ### or `#14`. Fix deadbeef1");
+        let references: Vec<&Segment> = segments.iter().filter(|s| !matches!(s, Segment::Text(_))).collect();
+        assert_eq!(
+            references,
+            vec![
+                &Segment::WorkPackage { id: 12, embed: false, source: "#12" },
+                &Segment::WorkPackage { id: 13, embed: true, source: "##13" },
+                &Segment::Revision("r42"),
+                &Segment::Revision("deadbeef1"),
+            ]
+        );
+
+        let text: String = segments
+            .iter()
+            .map(|s| match s {
+                Segment::Text(t) => t.to_string(),
+                Segment::WorkPackage { source, .. } => source.to_string(),
+                Segment::Revision(r) => r.to_string(),
+            })
+            .collect();
+        assert_eq!(text, "See #12, ##13 and r42; not a#1, #1a, ### or `#14`. Fix deadbeef1");
+
+        assert!(parse_references("café#5 1234567 abcdefg").iter().all(|s| matches!(s, Segment::Text(_))));
+    }
+
+    #[tokio::test]
+    async fn test_references_render_as_links() {
+        let renderer = MarkdownRenderer::new(FakeResolver::new(vec![]));
+
+        let html = renderer.render("Caused by #12, fixed in ##13 & r7").await.unwrap();
+        assert_eq!(
+            html,
+            "<p>Caused by <a class=\"issue work_package preview-trigger\" href=\"/work_packages/12\" \
+             data-work-package-id=\"12\">#12</a>, fixed in <span class=\"work-package--quickinfo\" \
+             data-work-package-id=\"13\"><a class=\"issue work_package closed preview-trigger\" \
+             href=\"/work_packages/13\" data-work-package-id=\"13\">Bug #13</a>: \
+             <span class=\"work-package--subject\">Old report</span> \
+             <span class=\"work-package--status\">Closed</span></span> &amp; r7</p>"
+        );
+
+        let html = renderer.render("##12").await.unwrap();
+        assert!(html.contains("Crash &lt;on&gt; save"));
+    }
+
+    #[tokio::test]
+    async fn test_invisible_and_unknown_references_stay_text() {
+        // Work package 99 is in a private project the viewer is not a member of
+        let renderer = MarkdownRenderer::new(FakeResolver::new(vec![99]));
+
+        let html = renderer.render("See #99, ##99 and #404 <b>").await.unwrap();
+        assert_eq!(html, "<p>See #99, ##99 and #404 &lt;b&gt;</p>");
+        assert!(!html.contains("Private project plans"));
+    }
+
+    #[tokio::test]
+    async fn test_references_are_resolved_once_per_batch() {
+        let resolver = FakeResolver::new(vec![]);
+        let renderer = MarkdownRenderer::new(resolver);
+
+        let html = renderer.render_all(&["#13 and #12", "#12 again", "no references"]).await.unwrap();
+        assert_eq!(html.len(), 3);
+        assert_eq!(html[2], "<p>no references</p>");
+        assert_eq!(*renderer.resolver.calls.lock().unwrap(), vec![vec![12, 13]]);
+
+        renderer.render("nothing to resolve").await.unwrap();
+        assert_eq!(renderer.resolver.calls.lock().unwrap().len(), 1);
+    }
+}
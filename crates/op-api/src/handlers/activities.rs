@@ -110,7 +110,7 @@ pub async fn create_activity(
         .create(create_dto)
         .await
         .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
@@ -146,7 +146,7 @@ pub async fn update_activity(
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("TimeEntryActivity", id),
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
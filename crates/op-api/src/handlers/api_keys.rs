@@ -0,0 +1,175 @@
+//! API keys API handlers
+//!
+//! Mirrors: app/controllers/my/access_tokens_controller.rb
+//!
+//! Users manage their own API keys. A key's secret is only returned when
+//! the key is created; afterwards only its last characters are shown.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use op_auth::{ApiKey, ApiKeyScope, ApiKeyService};
+use op_core::representations::Link;
+use op_core::traits::Id;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+
+/// List the API keys of the current user
+///
+/// GET /api/v3/users/me/api_keys
+pub async fn list_my_api_keys(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> ApiResult<impl IntoResponse> {
+    require_user(&user)?;
+
+    let elements: Vec<ApiKeyResponse> = state
+        .api_keys
+        .find_by_user(user.id())
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .into_iter()
+        .map(|row| ApiKeyResponse::new(row.into(), None))
+        .collect();
+
+    Ok(HalResponse(ApiKeyCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        elements,
+    }))
+}
+
+/// Create an API key for the current user, restricted to the scopes if
+/// any; the response holds the secret, which is not shown again
+///
+/// POST /api/v3/users/me/api_keys
+pub async fn create_my_api_key(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(dto): Json<CreateApiKeyRequest>,
+) -> ApiResult<impl IntoResponse> {
+    require_user(&user)?;
+
+    let scopes = dto
+        .scopes
+        .map(|scopes| {
+            scopes
+                .iter()
+                .map(|scope| {
+                    scope
+                        .parse::<ApiKeyScope>()
+                        .map_err(|_| ApiError::invalid_property("scopes", format!("'{}' is not a scope.", scope)))
+                })
+                .collect::<ApiResult<Vec<_>>>()
+        })
+        .transpose()?;
+
+    let created = ApiKeyService::new()
+        .create(state.api_keys.as_ref(), user.id(), dto.name, scopes)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+
+    Ok((StatusCode::CREATED, HalResponse(ApiKeyResponse::new(created.key, Some(created.secret)))))
+}
+
+/// Revoke an API key of the current user
+///
+/// DELETE /api/v3/users/me/api_keys/:id
+pub async fn revoke_my_api_key(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    require_user(&user)?;
+
+    let revoked = state
+        .api_keys
+        .revoke(user.id(), id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if !revoked {
+        return Err(ApiError::not_found("ApiKey", id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn require_user(user: &AuthenticatedUser) -> ApiResult<()> {
+    if user.is_anonymous() {
+        return Err(ApiError::unauthorized("Authentication required"));
+    }
+    Ok(())
+}
+
+// DTOs
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: Option<String>,
+    /// Scopes to restrict the key to, the full power of the user when omitted
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiKeyCollection {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    #[serde(rename = "_embedded")]
+    elements: Vec<ApiKeyResponse>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiKeyResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    name: Option<String>,
+    /// Last characters of the key
+    display_value: String,
+    /// Null for a key with the full power of its user
+    scopes: Option<Vec<ApiKeyScope>>,
+    /// Only set in the response creating the key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "_links")]
+    links: ApiKeyLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiKeyLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    user: Link,
+}
+
+impl ApiKeyResponse {
+    fn new(key: ApiKey, secret: Option<String>) -> Self {
+        Self {
+            type_name: "ApiKey".into(),
+            id: key.id,
+            name: key.name,
+            display_value: key.last_chars,
+            scopes: key.scopes,
+            secret,
+            created_at: key.created_at,
+            last_used_at: key.last_used_at,
+            expires_at: key.expires_at,
+            links: ApiKeyLinks {
+                self_link: Link::new(format!("/api/v3/users/me/api_keys/{}", key.id)),
+                user: Link::new(format!("/api/v3/users/{}", key.user_id)),
+            },
+        }
+    }
+}
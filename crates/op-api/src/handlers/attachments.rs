@@ -3,17 +3,32 @@
 //! Mirrors: lib/api/v3/attachments/*
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{FromRequest, Multipart, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use op_attachments::{
+    range, AttachmentError, ContainerType, CreateAttachmentParams, StorageError, Unsatisfiable, UpdateAttachmentParams,
+};
+use op_core::representations::{
+    Attachment, AttachmentLinks, Collection, CreateAttachment, Link, UploadAttachmentMetadata,
+};
 use op_core::traits::Id;
-use op_db::{attachment_status, AttachmentRepository, Repository};
-use serde::{Deserialize, Serialize};
+use op_db::{attachment_status, AttachmentRepository, MemberRepository, Repository};
+use op_services::permissions::PermissionService;
+use serde::Deserialize;
+use sqlx::PgPool;
 
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::representers::CollectionQuery;
+
+/// Form part holding the JSON metadata of an upload
+const METADATA_FIELD: &str = "metadata";
+
+/// Form part holding the uploaded file
+const FILE_FIELD: &str = "file";
 
 /// List all attachments
 ///
@@ -22,6 +37,7 @@ pub async fn list_attachments(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     pagination: Pagination,
+    collection_query: CollectionQuery,
     Query(filters): Query<AttachmentFilters>,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
@@ -66,19 +82,14 @@ pub async fn list_attachments(
         (rows, total)
     };
 
-    let elements: Vec<AttachmentResponse> = rows
+    let elements: Vec<Attachment> = rows
         .into_iter()
-        .map(|row| AttachmentResponse::from_row(row))
+        .map(attachment_response)
         .collect();
 
-    let collection = AttachmentCollection {
-        type_name: "Collection".into(),
-        total: total as usize,
-        count: elements.len(),
-        page_size: pagination.page_size,
-        offset: pagination.offset,
-        elements,
-    };
+    let collection = Collection::new(elements, total as usize, pagination.offset, pagination.page_size).with_links(
+        collection_query.pagination_links(total, pagination.offset as i64, pagination.page_size as i64),
+    );
 
     Ok(HalResponse(collection))
 }
@@ -100,17 +111,155 @@ pub async fn get_attachment(
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("Attachment", id))?;
 
-    Ok(HalResponse(AttachmentResponse::from_row(row)))
+    Ok(HalResponse(attachment_response(row)))
+}
+
+/// Download the content of an attachment
+///
+/// GET /api/v3/attachments/:id/content
+///
+/// A `Range` header for a single byte range is answered with 206 and that
+/// range, one past the end of the file with 416. With an `If-Range` header
+/// the range is only served if it matches the ETag, the whole file
+/// otherwise. Only downloads of the whole file count towards `downloads`.
+pub async fn download_attachment(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let attachments = state.attachments()?;
+    let attachment = attachments
+        .get(id)
+        .await
+        .map_err(|e| download_error(id, e))?
+        .ok_or_else(|| ApiError::not_found("Attachment", id))?;
+
+    let size = attachment.filesize.max(0) as u64;
+    let etag = range::etag(&attachment.digest);
+    let requested = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| if_range_allows(&headers, etag.as_deref()));
+
+    let (status, content_range, data) = match requested.map(|r| range::parse_range(r, size)) {
+        Some(Ok(Some(range))) => {
+            let (_, data) = attachments
+                .download_range(id, range)
+                .await
+                .map_err(|e| download_error(id, e))?;
+            (StatusCode::PARTIAL_CONTENT, Some(range.content_range(size)), data)
+        }
+        Some(Err(Unsatisfiable)) => {
+            let headers = [
+                (header::ACCEPT_RANGES, "bytes".to_string()),
+                (header::CONTENT_RANGE, Unsatisfiable::content_range(size)),
+            ];
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+        None | Some(Ok(None)) => {
+            let (_, data) = attachments.download(id).await.map_err(|e| download_error(id, e))?;
+            (StatusCode::OK, None, data)
+        }
+    };
+
+    let mut response = (status, data).into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(content_type) = HeaderValue::from_str(&attachment.content_type) {
+        response_headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    if let Ok(disposition) = HeaderValue::from_str(&content_disposition(&attachment.filename)) {
+        response_headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    if let Some(content_range) = content_range.and_then(|r| HeaderValue::from_str(&r).ok()) {
+        response_headers.insert(header::CONTENT_RANGE, content_range);
+    }
+
+    Ok(response)
+}
+
+/// Whether the `If-Range` header, if any, lets a range be served
+fn if_range_allows(headers: &HeaderMap, etag: Option<&str>) -> bool {
+    match headers.get(header::IF_RANGE) {
+        None => true,
+        Some(if_range) => match (if_range.to_str(), etag) {
+            (Ok(if_range), Some(etag)) => range::if_range_matches(if_range, etag),
+            _ => false,
+        },
+    }
+}
+
+/// `Content-Disposition` of a download: an ASCII fallback of the filename
+/// and the filename itself percent-encoded as UTF-8 (RFC 6266)
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' ' => ' ',
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' => (b as char).to_string(),
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+fn download_error(id: Id, e: AttachmentError) -> ApiError {
+    match e {
+        AttachmentError::NotFound(_) | AttachmentError::StorageError(StorageError::NotFound(_)) => {
+            ApiError::not_found("Attachment", id)
+        }
+        e => ApiError::internal(e.to_string()),
+    }
 }
 
-/// Create a new attachment (metadata only, file upload handled separately)
+/// Create a new attachment
 ///
 /// POST /api/v3/attachments
+///
+/// A multipart form uploads the file: a `metadata` part with the JSON
+/// metadata and a `file` part with the content. A JSON body only records
+/// the metadata of a file stored elsewhere.
 pub async fn create_attachment(
     State(state): State<AppState>,
     user: AuthenticatedUser,
-    Json(dto): Json<CreateAttachmentRequest>,
+    request: Request,
 ) -> ApiResult<impl IntoResponse> {
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("multipart/form-data"));
+
+    let row = if is_form {
+        upload_attachment(&state, &user, request).await?
+    } else {
+        let Json(dto) = Json::<CreateAttachment>::from_request(request, &state)
+            .await
+            .map_err(|e| ApiError::bad_request(e.body_text()))?;
+        record_attachment(&state, &user, dto).await?
+    };
+
+    Ok((StatusCode::CREATED, HalResponse(attachment_response(row))))
+}
+
+async fn record_attachment(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    dto: CreateAttachment,
+) -> ApiResult<op_db::AttachmentRow> {
     let pool = state.pool()?;
     let repo = AttachmentRepository::new(pool.clone());
 
@@ -127,20 +276,99 @@ pub async fn create_attachment(
         status: dto.status.and_then(|s| attachment_status::from_string(&s)),
     };
 
-    let row = repo
-        .create(create_dto)
+    repo.create(create_dto).await.map_err(|e| match e {
+        op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+        _ => ApiError::internal(format!("Database error: {}", e)),
+    })
+}
+
+/// Store the `file` part of the form through the attachment service
+async fn upload_attachment(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    request: Request,
+) -> ApiResult<op_db::AttachmentRow> {
+    let mut form = Multipart::from_request(request, state)
         .await
-        .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
-            _ => ApiError::internal(format!("Database error: {}", e)),
-        })?;
+        .map_err(|e| ApiError::bad_request(e.body_text()))?;
 
-    Ok((StatusCode::CREATED, HalResponse(AttachmentResponse::from_row(row))))
+    let mut metadata = UploadAttachmentMetadata::default();
+    let mut file = None;
+    while let Some(field) = form
+        .next_field()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?
+    {
+        match field.name() {
+            Some(METADATA_FIELD) => {
+                let bytes = field.bytes().await.map_err(|e| ApiError::bad_request(e.body_text()))?;
+                metadata = serde_json::from_slice(&bytes)
+                    .map_err(|e| ApiError::bad_request(format!("Invalid metadata: {}", e)))?;
+            }
+            Some(FILE_FIELD) => {
+                let file_name = field.file_name().map(str::to_string);
+                let content_type = field.content_type().map(str::to_string);
+                let data = field.bytes().await.map_err(|e| ApiError::bad_request(e.body_text()))?;
+                file = Some((file_name, content_type, data));
+            }
+            _ => {}
+        }
+    }
+
+    let (file_name, content_type, data) =
+        file.ok_or_else(|| ApiError::invalid_property("file", "can't be blank."))?;
+    let filename = metadata
+        .file_name
+        .or(file_name)
+        .ok_or_else(|| ApiError::invalid_property("fileName", "can't be blank."))?;
+
+    let mut params = CreateAttachmentParams::new(filename);
+    if let Some(content_type) = content_type {
+        params = params.content_type(content_type);
+    }
+    if let Some(description) = metadata.description {
+        params = params.description(description);
+    }
+    if let (Some(container_type), Some(container_id)) = (metadata.container_type, metadata.container_id) {
+        let container_type = ContainerType::from_str(&container_type)
+            .ok_or_else(|| ApiError::invalid_property("containerType", "is not a valid container type."))?;
+        params = params.container(container_type, container_id);
+    }
+
+    let created = state
+        .attachments()?
+        .create(params, data, user.id())
+        .await
+        .map_err(upload_error)?;
+    let id = created
+        .attachment
+        .id
+        .ok_or_else(|| ApiError::internal("Stored attachment has no id"))?;
+
+    AttachmentRepository::new(state.pool()?.clone())
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Attachment", id))
+}
+
+pub(crate) fn upload_error(e: AttachmentError) -> ApiError {
+    match e {
+        AttachmentError::PermissionDenied => ApiError::forbidden(e.to_string()),
+        AttachmentError::ContainerNotFound(..) => ApiError::invalid_property("container", e.to_string()),
+        AttachmentError::NotFound(_) | AttachmentError::StorageError(_) | AttachmentError::Database(_) => {
+            ApiError::internal(e.to_string())
+        }
+        _ => ApiError::invalid_property("file", e.to_string()),
+    }
 }
 
 /// Update an attachment
 ///
 /// PATCH /api/v3/attachments/:id
+///
+/// `fileName` and `description` are changed through the attachment
+/// service; the stored file keeps its disk filename.
 pub async fn update_attachment(
     State(state): State<AppState>,
     user: AuthenticatedUser,
@@ -150,22 +378,33 @@ pub async fn update_attachment(
     let pool = state.pool()?;
     let repo = AttachmentRepository::new(pool.clone());
 
-    // Check if user is author or admin
     let existing = repo
         .find_by_id(id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("Attachment", id))?;
 
-    if existing.author_id != user.0.id && !user.0.is_admin() {
-        return Err(ApiError::forbidden("You can only update your own attachments."));
+    if !attachment_editable(pool, &user, &existing).await? {
+        return Err(ApiError::forbidden("You are not authorized to edit this attachment."));
+    }
+
+    if dto.file_name.is_some() || dto.description.is_some() {
+        let params = UpdateAttachmentParams {
+            filename: dto.file_name,
+            description: dto.description,
+        };
+        state
+            .attachments()?
+            .update_metadata(id, params)
+            .await
+            .map_err(metadata_error)?;
     }
 
     let update_dto = op_db::UpdateAttachmentDto {
         container_id: dto.container_id,
         container_type: dto.container_type,
-        description: dto.description,
         status: dto.status.and_then(|s| attachment_status::from_string(&s)),
+        ..Default::default()
     };
 
     let row = repo
@@ -176,7 +415,51 @@ pub async fn update_attachment(
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
-    Ok(HalResponse(AttachmentResponse::from_row(row)))
+    Ok(HalResponse(attachment_response(row)))
+}
+
+/// Whether the user may edit the attachment. Attachments of a container
+/// need the permission to edit the container, e.g. edit_work_packages for
+/// a work package; others may be edited by their author.
+async fn attachment_editable(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    attachment: &op_db::AttachmentRow,
+) -> ApiResult<bool> {
+    let (Some(container_type), Some(container_id)) = (attachment.container_type.as_deref(), attachment.container_id)
+    else {
+        return Ok(attachment.author_id == user.id() || user.is_admin());
+    };
+
+    let container = ContainerType::from_str(container_type);
+    let permission = container.and_then(|container| container.edit_permission());
+    let project_id = AttachmentRepository::new(pool.clone())
+        .project_for_container(container_type, container_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let (Some(permission), Some(project_id)) = (permission, project_id) else {
+        return Ok(user.is_admin());
+    };
+
+    let permissions = PermissionService::new(MemberRepository::new(pool.clone()));
+    let allowed = if container == Some(ContainerType::WorkPackage) {
+        permissions
+            .allowed_on_work_package(user, permission, container_id, project_id)
+            .await
+    } else {
+        permissions.allowed_in_project(user, permission, project_id).await
+    };
+
+    allowed.map_err(|e| ApiError::internal(format!("Database error: {}", e)))
+}
+
+fn metadata_error(e: AttachmentError) -> ApiError {
+    match e {
+        AttachmentError::PermissionDenied => ApiError::forbidden(e.to_string()),
+        AttachmentError::NotFound(id) => ApiError::not_found("Attachment", id),
+        AttachmentError::StorageError(_) | AttachmentError::Database(_) => ApiError::internal(e.to_string()),
+        _ => ApiError::invalid_property("fileName", e.to_string()),
+    }
 }
 
 /// Delete an attachment
@@ -219,6 +502,7 @@ pub async fn list_work_package_attachments(
     _user: AuthenticatedUser,
     Path(work_package_id): Path<Id>,
     pagination: Pagination,
+    collection_query: CollectionQuery,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
     let repo = AttachmentRepository::new(pool.clone());
@@ -234,20 +518,14 @@ pub async fn list_work_package_attachments(
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    let elements: Vec<AttachmentResponse> = result
+    let elements: Vec<Attachment> = result
         .items
         .into_iter()
-        .map(|row| AttachmentResponse::from_row(row))
+        .map(attachment_response)
         .collect();
 
-    let collection = AttachmentCollection {
-        type_name: "Collection".into(),
-        total: result.total as usize,
-        count: elements.len(),
-        page_size: pagination.page_size,
-        offset: pagination.offset,
-        elements,
-    };
+    let collection = Collection::new(elements, result.total as usize, pagination.offset, pagination.page_size)
+        .with_links(collection_query.pagination_links(result.total, pagination.offset as i64, pagination.page_size as i64));
 
     Ok(HalResponse(collection))
 }
@@ -262,125 +540,80 @@ pub struct AttachmentFilters {
 }
 
 // Request types
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CreateAttachmentRequest {
-    pub container_id: Option<i64>,
-    pub container_type: Option<String>,
-    pub filename: String,
-    pub disk_filename: Option<String>,
-    pub filesize: i64,
-    pub content_type: String,
-    pub digest: Option<String>,
-    pub description: Option<String>,
-    pub status: Option<String>,
-}
-
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdateAttachmentRequest {
     pub container_id: Option<Option<i64>>,
     pub container_type: Option<Option<String>>,
+    pub file_name: Option<String>,
     pub description: Option<Option<String>>,
     pub status: Option<String>,
 }
 
-// Response types
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct AttachmentCollection {
-    #[serde(rename = "_type")]
-    type_name: String,
-    total: usize,
-    count: usize,
-    page_size: usize,
-    offset: usize,
-    #[serde(rename = "_embedded")]
-    elements: Vec<AttachmentResponse>,
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct AttachmentResponse {
-    #[serde(rename = "_type")]
-    type_name: String,
-    id: Id,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    filename: Option<String>,
-    filesize: i64,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    content_type: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    digest: Option<String>,
-    downloads: i32,
-    status: String,
-    created_at: String,
-    updated_at: String,
-    #[serde(rename = "_links")]
-    links: AttachmentLinks,
-}
+pub(crate) fn attachment_response(row: op_db::AttachmentRow) -> Attachment {
+    let id = row.id;
+    let author_id = row.author_id;
+    let status = row.status_name().to_string();
+
+    let container_link = match (&row.container_type, row.container_id) {
+        (Some(ct), Some(cid)) => {
+            let href = match ct.as_str() {
+                "WorkPackage" => format!("/api/v3/work_packages/{}", cid),
+                "Project" => format!("/api/v3/projects/{}", cid),
+                "WikiPage" => format!("/api/v3/wiki_pages/{}", cid),
+                _ => format!("/api/v3/{}s/{}", ct.to_lowercase(), cid),
+            };
+            Some(Link { href })
+        }
+        _ => None,
+    };
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct AttachmentLinks {
-    #[serde(rename = "self")]
-    self_link: Link,
-    author: Link,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    container: Option<Link>,
-    download_location: Link,
+    Attachment {
+        type_name: "Attachment".into(),
+        id,
+        filename: row.filename,
+        filesize: row.filesize,
+        content_type: row.content_type,
+        description: row.description,
+        digest: row.digest,
+        downloads: row.downloads,
+        status,
+        created_at: row.created_at.to_rfc3339(),
+        updated_at: row.updated_at.to_rfc3339(),
+        links: AttachmentLinks {
+            self_link: Link::new(format!("/api/v3/attachments/{}", id)),
+            author: Link::new(format!("/api/v3/users/{}", author_id)),
+            container: container_link,
+            download_location: Link::new(format!("/api/v3/attachments/{}/content", id)),
+        },
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct Link {
-    href: String,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("notes.txt"),
+            "attachment; filename=\"notes.txt\"; filename*=UTF-8''notes.txt"
+        );
+        assert_eq!(
+            content_disposition("Über \"final\".pdf"),
+            "attachment; filename=\"_ber _final_.pdf\"; filename*=UTF-8''%C3%9Cber%20%22final%22.pdf"
+        );
+    }
 
-impl AttachmentResponse {
-    fn from_row(row: op_db::AttachmentRow) -> Self {
-        let id = row.id;
-        let author_id = row.author_id;
-        let status = row.status_name().to_string();
-
-        let container_link = match (&row.container_type, row.container_id) {
-            (Some(ct), Some(cid)) => {
-                let href = match ct.as_str() {
-                    "WorkPackage" => format!("/api/v3/work_packages/{}", cid),
-                    "Project" => format!("/api/v3/projects/{}", cid),
-                    "WikiPage" => format!("/api/v3/wiki_pages/{}", cid),
-                    _ => format!("/api/v3/{}s/{}", ct.to_lowercase(), cid),
-                };
-                Some(Link { href })
-            }
-            _ => None,
-        };
+    #[test]
+    fn test_if_range_allows() {
+        let mut headers = HeaderMap::new();
+        assert!(if_range_allows(&headers, None));
 
-        AttachmentResponse {
-            type_name: "Attachment".into(),
-            id,
-            filename: row.filename,
-            filesize: row.filesize,
-            content_type: row.content_type,
-            description: row.description,
-            digest: row.digest,
-            downloads: row.downloads,
-            status,
-            created_at: row.created_at.to_rfc3339(),
-            updated_at: row.updated_at.to_rfc3339(),
-            links: AttachmentLinks {
-                self_link: Link {
-                    href: format!("/api/v3/attachments/{}", id),
-                },
-                author: Link {
-                    href: format!("/api/v3/users/{}", author_id),
-                },
-                container: container_link,
-                download_location: Link {
-                    href: format!("/api/v3/attachments/{}/content", id),
-                },
-            },
-        }
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"abc\""));
+        assert!(if_range_allows(&headers, Some("\"abc\"")));
+        assert!(!if_range_allows(&headers, Some("\"def\"")));
+        // Without an ETag of its own the file never matches
+        assert!(!if_range_allows(&headers, None));
     }
 }
@@ -0,0 +1,132 @@
+//! Audit events API handlers
+//!
+//! Admin-only read access to the security audit trail.
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use op_core::audit::{AuditEvent, AuditEventType, AuditFilter};
+use op_core::traits::Id;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, ClientInfo, HalResponse, Pagination};
+
+/// List audit events (admin only)
+///
+/// GET /api/v3/audit_events
+pub async fn list_audit_events(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    client: ClientInfo,
+    pagination: Pagination,
+    Query(filters): Query<AuditEventFilters>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(state
+            .deny(&user, &client, "list_audit_events", "Only administrators can view audit events.")
+            .await);
+    }
+
+    let event_type = match filters.event_type.as_deref() {
+        Some(value) => Some(
+            AuditEventType::parse(value)
+                .ok_or_else(|| ApiError::bad_request(format!("Unknown event type: {}", value)))?,
+        ),
+        None => None,
+    };
+
+    let filter = AuditFilter {
+        actor_id: filters.actor_id,
+        event_type,
+        from: filters.from,
+        to: filters.to,
+        limit: pagination.page_size,
+        offset: pagination.offset,
+    };
+
+    let events = state
+        .audit
+        .query(&filter)
+        .await
+        .map_err(|e| ApiError::internal(format!("Audit log error: {}", e)))?;
+
+    let elements: Vec<AuditEventResponse> =
+        events.into_iter().map(AuditEventResponse::from_event).collect();
+
+    let collection = AuditEventCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        page_size: pagination.page_size,
+        offset: pagination.offset,
+        elements,
+    };
+
+    Ok(HalResponse(collection))
+}
+
+// Query parameters
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEventFilters {
+    pub actor_id: Option<Id>,
+    pub event_type: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+// Response types
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditEventCollection {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    page_size: usize,
+    offset: usize,
+    #[serde(rename = "_embedded")]
+    elements: Vec<AuditEventResponse>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditEventResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Id>,
+    event_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actor_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    details: serde_json::Value,
+    occurred_at: String,
+}
+
+impl AuditEventResponse {
+    fn from_event(event: AuditEvent) -> Self {
+        Self {
+            type_name: "AuditEvent".into(),
+            id: event.id,
+            event_type: event.event_type.as_str().into(),
+            actor_id: event.actor_id,
+            target_type: event.target_type,
+            target_id: event.target_id,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            details: event.details,
+            occurred_at: event.occurred_at.to_rfc3339(),
+        }
+    }
+}
@@ -154,7 +154,7 @@ pub async fn create_category(
         .create(create_dto)
         .await
         .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
@@ -188,7 +188,7 @@ pub async fn update_category(
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Category", id),
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
@@ -0,0 +1,432 @@
+//! Custom actions API handlers
+//!
+//! Mirrors: lib/api/v3/custom_actions/*
+//!
+//! Administrators manage custom actions; users execute the ones offered on
+//! a work package, listed as `customActions` in its links. Executing runs
+//! the changes through the update contract and journals them with the
+//! action's comment.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use op_core::representations::{HalLink, HalLinks};
+use op_core::traits::Id;
+use op_db::work_packages::WorkPackageRow;
+use op_db::{
+    CustomActionRepository, CustomActionRow, MemberRepository, Repository, RepositoryError, UpdateWorkPackageDto,
+    WorkPackageRepository,
+};
+use op_services::custom_actions::{
+    applicable_actions, validate_changes, CustomAction, CustomActionChange, CustomActionConditions,
+    CustomActionContext, CustomActionError, ExecuteCustomActionService,
+};
+use op_services::permissions::PermissionService;
+use op_services::work_packages::WorkPackageEntity;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+use crate::handlers::work_packages::{render_description, work_package_response};
+
+/// List all custom actions in their order
+///
+/// GET /api/v3/custom_actions
+pub async fn list_custom_actions(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let rows = CustomActionRepository::new(pool.clone())
+        .find_ordered()
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let elements: Vec<CustomActionResponse> = rows.into_iter().map(CustomActionResponse::from_row).collect();
+    Ok(HalResponse(CustomActionCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        elements,
+    }))
+}
+
+/// Get a single custom action
+///
+/// GET /api/v3/custom_actions/:id
+pub async fn get_custom_action(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let row = find_custom_action(pool, id).await?;
+
+    Ok(HalResponse(CustomActionResponse::from_row(row)))
+}
+
+/// Create a custom action after the existing ones (admin only)
+///
+/// POST /api/v3/custom_actions
+pub async fn create_custom_action(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(dto): Json<CreateCustomActionRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can create custom actions."));
+    }
+    validate_changes(&dto.actions).map_err(ApiError::Validation)?;
+
+    let pool = state.pool()?;
+    let row = CustomActionRepository::new(pool.clone())
+        .create(op_db::CreateCustomActionDto {
+            name: dto.name,
+            description: dto.description,
+            conditions: to_json(&dto.conditions)?,
+            actions: to_json(&dto.actions)?,
+        })
+        .await
+        .map_err(|e| match e {
+            RepositoryError::Validation(errors) => ApiError::validation(errors),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+
+    Ok((StatusCode::CREATED, HalResponse(CustomActionResponse::from_row(row))))
+}
+
+/// Update a custom action (admin only)
+///
+/// PATCH /api/v3/custom_actions/:id
+pub async fn update_custom_action(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<UpdateCustomActionRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can update custom actions."));
+    }
+    if let Some(actions) = &dto.actions {
+        validate_changes(actions).map_err(ApiError::Validation)?;
+    }
+
+    let pool = state.pool()?;
+    let row = CustomActionRepository::new(pool.clone())
+        .update(
+            id,
+            op_db::UpdateCustomActionDto {
+                name: dto.name,
+                description: dto.description,
+                position: dto.position,
+                conditions: dto.conditions.as_ref().map(to_json).transpose()?,
+                actions: dto.actions.as_ref().map(to_json).transpose()?,
+            },
+        )
+        .await
+        .map_err(|e| match e {
+            RepositoryError::NotFound(_) => ApiError::not_found("CustomAction", id),
+            RepositoryError::Validation(errors) => ApiError::validation(errors),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+
+    Ok(HalResponse(CustomActionResponse::from_row(row)))
+}
+
+/// Delete a custom action (admin only)
+///
+/// DELETE /api/v3/custom_actions/:id
+pub async fn delete_custom_action(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can delete custom actions."));
+    }
+
+    let pool = state.pool()?;
+    CustomActionRepository::new(pool.clone())
+        .delete(id)
+        .await
+        .map_err(|e| match e {
+            RepositoryError::NotFound(_) => ApiError::not_found("CustomAction", id),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Apply a custom action to a work package
+///
+/// POST /api/v3/work_packages/:id/custom_actions/:action_id/execute
+///
+/// `lockVersion` is the version of the work package the action was offered
+/// at. If the work package changed since, or the action no longer applies
+/// to it, nothing is changed and 409 is returned.
+pub async fn execute_custom_action(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((id, action_id)): Path<(Id, Id)>,
+    Json(dto): Json<ExecuteCustomActionRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let repo = WorkPackageRepository::new(pool.clone());
+
+    let row = repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
+    let members = MemberRepository::new(pool.clone());
+    let role_ids = members
+        .role_ids_in_project(user.id(), row.project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let visible = PermissionService::new(members)
+        .work_package_visible(&user, row.id, row.project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if !visible {
+        return Err(ApiError::not_found("WorkPackage", id));
+    }
+
+    let action_row = find_custom_action(pool, action_id).await?;
+    let action = CustomAction::from_row(&action_row)
+        .map_err(|e| ApiError::internal(format!("Custom action {} is unreadable: {}", action_id, e)))?;
+
+    let original = work_package_entity(&row);
+    let mut service = ExecuteCustomActionService::new(&user, &action);
+    if let Some(metrics) = &state.metrics {
+        service = service.with_metrics(metrics);
+    }
+    let applied = service
+        .call(original.clone(), role_ids.clone(), dto.lock_version)
+        .map_err(|e| match e {
+            CustomActionError::Conflict(msg) => ApiError::conflict(msg),
+            CustomActionError::Invalid(errors) => ApiError::Validation(errors),
+        })?;
+
+    let row = repo
+        .update_journaled(id, update_dto(&original, &applied.work_package), user.id(), &applied.notes)
+        .await
+        .map_err(|e| match e {
+            RepositoryError::Conflict(msg) => ApiError::conflict(msg),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+    state.work_packages_changed(&[row.project_id]).await;
+
+    let links = custom_action_links(pool, &row, role_ids).await?;
+    let description = render_description(pool, &user, &row).await?;
+    let mut work_package = work_package_response(row, description);
+    work_package.links.extend(links);
+    Ok(HalResponse(work_package))
+}
+
+/// Links executing the custom actions offered on the work package to a
+/// user with the roles
+pub(crate) async fn custom_action_links(pool: &PgPool, row: &WorkPackageRow, role_ids: Vec<Id>) -> ApiResult<HalLinks> {
+    let rows = CustomActionRepository::new(pool.clone())
+        .find_ordered()
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let context = CustomActionContext {
+        status_id: row.status_id,
+        type_id: row.type_id,
+        project_id: row.project_id,
+        role_ids,
+    };
+
+    let links: Vec<HalLink> = applicable_actions(&rows, &context)
+        .into_iter()
+        .map(|action| {
+            HalLink::with_title(
+                format!("/api/v3/work_packages/{}/custom_actions/{}/execute", row.id, action.id),
+                action.name,
+            )
+            .method("post")
+        })
+        .collect();
+    Ok(HalLinks::new().with_array("customActions", links))
+}
+
+async fn find_custom_action(pool: &PgPool, id: Id) -> ApiResult<CustomActionRow> {
+    CustomActionRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("CustomAction", id))
+}
+
+fn to_json<T: Serialize>(value: &T) -> ApiResult<serde_json::Value> {
+    serde_json::to_value(value).map_err(|e| ApiError::internal(format!("Serialization error: {}", e)))
+}
+
+/// The work package as the update service sees it
+fn work_package_entity(row: &WorkPackageRow) -> WorkPackageEntity {
+    let mut work_package = WorkPackageEntity::new(row.project_id, row.type_id, row.author_id);
+    work_package.id = Some(row.id);
+    work_package.subject = row.subject.clone();
+    work_package.description = row.description.clone();
+    work_package.status_id = row.status_id;
+    if let Some(priority_id) = row.priority_id {
+        work_package.priority_id = priority_id;
+    }
+    work_package.assigned_to_id = row.assigned_to_id;
+    work_package.responsible_id = row.responsible_id;
+    work_package.start_date = row.start_date;
+    work_package.due_date = row.due_date;
+    work_package.duration = row.duration;
+    work_package.ignore_non_working_days = row.ignore_non_working_days;
+    work_package.estimated_hours = row.estimated_hours;
+    work_package.done_ratio = row.done_ratio;
+    work_package.parent_id = row.parent_id;
+    work_package.version_id = row.version_id;
+    work_package.category_id = row.category_id;
+    work_package.lock_version = row.lock_version;
+    work_package
+}
+
+/// Update saving the changed work package. Attributes the update keeps
+/// unless sent are only sent when they changed; the others always are.
+fn update_dto(original: &WorkPackageEntity, changed: &WorkPackageEntity) -> UpdateWorkPackageDto {
+    fn if_changed<T: PartialEq + Clone>(original: &T, changed: &T) -> Option<T> {
+        (original != changed).then(|| changed.clone())
+    }
+
+    UpdateWorkPackageDto {
+        subject: if_changed(&original.subject, &changed.subject),
+        description: if_changed(&original.description, &changed.description).flatten(),
+        type_id: if_changed(&original.type_id, &changed.type_id),
+        status_id: if_changed(&original.status_id, &changed.status_id),
+        priority_id: if_changed(&original.priority_id, &changed.priority_id),
+        assigned_to_id: changed.assigned_to_id,
+        responsible_id: changed.responsible_id,
+        start_date: changed.start_date,
+        due_date: changed.due_date,
+        estimated_hours: changed.estimated_hours,
+        done_ratio: if_changed(&original.done_ratio, &changed.done_ratio),
+        parent_id: changed.parent_id,
+        version_id: changed.version_id,
+        category_id: changed.category_id,
+        duration: if_changed(&original.duration, &changed.duration).flatten(),
+        ignore_non_working_days: if_changed(&original.ignore_non_working_days, &changed.ignore_non_working_days),
+        lock_version: original.lock_version,
+    }
+}
+
+// Request types
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCustomActionRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub conditions: CustomActionConditions,
+    pub actions: Vec<CustomActionChange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateCustomActionRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub position: Option<i32>,
+    pub conditions: Option<CustomActionConditions>,
+    pub actions: Option<Vec<CustomActionChange>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecuteCustomActionRequest {
+    pub lock_version: i32,
+}
+
+// Response types
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CustomActionCollection {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    #[serde(rename = "_embedded")]
+    elements: Vec<CustomActionResponse>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CustomActionResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    position: i32,
+    conditions: serde_json::Value,
+    actions: serde_json::Value,
+    #[serde(rename = "_links")]
+    links: CustomActionLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct CustomActionLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+}
+
+#[derive(Debug, Serialize)]
+struct Link {
+    href: String,
+}
+
+impl CustomActionResponse {
+    fn from_row(row: CustomActionRow) -> Self {
+        Self {
+            type_name: "CustomAction".into(),
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            position: row.position,
+            conditions: row.conditions,
+            actions: row.actions,
+            links: CustomActionLinks {
+                self_link: Link {
+                    href: format!("/api/v3/custom_actions/{}", row.id),
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_dto_only_sends_changed_kept_attributes() {
+        let mut original = WorkPackageEntity::new(1, 1, 1);
+        original.id = Some(100);
+        original.lock_version = 3;
+        original.assigned_to_id = Some(4);
+        original.start_date = chrono::NaiveDate::from_ymd_opt(2026, 1, 5);
+
+        let mut changed = original.clone();
+        changed.status_id = 5;
+        let dto = update_dto(&original, &changed);
+
+        assert_eq!(dto.status_id, Some(5));
+        assert_eq!(dto.priority_id, None);
+        assert_eq!(dto.subject, None);
+        assert_eq!(dto.assigned_to_id, Some(4));
+        assert_eq!(dto.start_date, original.start_date);
+        assert_eq!(dto.lock_version, 3);
+    }
+}
@@ -0,0 +1,498 @@
+//! Documents API handlers
+//!
+//! Mirrors: modules/documents/app/controllers/documents_controller.rb
+//!
+//! Members with `view_documents` read the documents of a project and their
+//! attachments; everything else goes through `DocumentService`. Files are
+//! uploaded through the attachments endpoints with container type
+//! `Document`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use op_contracts::documents::permissions::VIEW_DOCUMENTS;
+use op_core::representations::{Attachment, FormattableText};
+use op_core::traits::Id;
+use op_db::{
+    journable_type, AttachmentRepository, DocumentCategoryRow, DocumentOrder, DocumentRepository, DocumentRow,
+    MemberRepository, Repository, RepositoryError,
+};
+use op_services::documents::{DocumentParams, DocumentService, PgDocumentStore, UpdateDocumentParams};
+use op_services::permissions::PermissionService;
+use op_services::ServiceResult;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::formatting::{DbReferenceResolver, MarkdownRenderer};
+use crate::handlers::attachments::attachment_response;
+
+/// List the documents of a project, grouped by category or creation date
+///
+/// GET /api/v3/projects/:id/documents?sortBy=category|date
+pub async fn list_project_documents(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(project_id): Path<Id>,
+    Query(params): Query<DocumentListParams>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let order = match params.sort_by.as_deref() {
+        None | Some("category") => DocumentOrder::Category,
+        Some("date") => DocumentOrder::Date,
+        Some(other) => {
+            return Err(ApiError::bad_request(format!(
+                "Unknown sortBy '{}', expected 'category' or 'date'",
+                other
+            )))
+        }
+    };
+    if !allowed(pool, &user, VIEW_DOCUMENTS, project_id).await? {
+        return Err(ApiError::forbidden("You are not authorized to view the documents of this project."));
+    }
+
+    let documents = DocumentRepository::new(pool.clone())
+        .find_by_project(project_id, order)
+        .await
+        .map_err(database_error)?;
+
+    let groups = group_documents(&documents, order);
+    let elements = render_documents(documents, pool, &user).await?;
+    Ok(HalResponse(DocumentCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        groups,
+        elements,
+    }))
+}
+
+/// Create a document in a project, notifying members who subscribed
+///
+/// POST /api/v3/projects/:id/documents
+pub async fn create_project_document(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(project_id): Path<Id>,
+    Json(dto): Json<CreateDocumentRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+
+    let store = PgDocumentStore::new(pool.clone());
+    let permissions = permissions(pool);
+    let params = DocumentParams {
+        category_id: dto.links.and_then(|links| links.category).map(|c| c.id()).transpose()?,
+        title: dto.title,
+        description: dto.description.map(|d| d.raw),
+    };
+    let document = into_api_result(
+        DocumentService::new(&user, &store, &permissions)
+            .with_notifications(state.notifications.as_ref())
+            .with_streams(&state.notification_streams)
+            .create(project_id, params)
+            .await,
+    )?;
+
+    Ok((StatusCode::CREATED, HalResponse(render_document(document, pool, &user).await?)))
+}
+
+/// Get a document
+///
+/// GET /api/v3/documents/:id
+pub async fn get_document(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let document = ensure_document_visible(pool, &user, id).await?;
+    Ok(HalResponse(render_document(document, pool, &user).await?))
+}
+
+/// Retitle, describe or recategorize a document
+///
+/// PATCH /api/v3/documents/:id
+pub async fn update_document(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<UpdateDocumentRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let document = ensure_document_visible(pool, &user, id).await?;
+
+    let store = PgDocumentStore::new(pool.clone());
+    let permissions = permissions(pool);
+    let params = UpdateDocumentParams {
+        category_id: dto.links.and_then(|links| links.category).map(|c| c.id()).transpose()?,
+        title: dto.title,
+        description: dto.description.map(|d| d.raw),
+    };
+    let document = into_api_result(
+        DocumentService::new(&user, &store, &permissions)
+            .update(document.id, params)
+            .await,
+    )?;
+
+    Ok(HalResponse(render_document(document, pool, &user).await?))
+}
+
+/// Delete a document with its attachments
+///
+/// DELETE /api/v3/documents/:id
+pub async fn delete_document(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let document = ensure_document_visible(pool, &user, id).await?;
+
+    let store = PgDocumentStore::new(pool.clone());
+    let permissions = permissions(pool);
+    into_api_result(
+        DocumentService::new(&user, &store, &permissions)
+            .delete(document.id, state.attachments.as_deref())
+            .await,
+    )?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the attachments of a document
+///
+/// GET /api/v3/documents/:id/attachments
+pub async fn list_document_attachments(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    pagination: Pagination,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let document = ensure_document_visible(pool, &user, id).await?;
+
+    let result = AttachmentRepository::new(pool.clone())
+        .find_by_container(
+            journable_type::DOCUMENT,
+            document.id,
+            op_db::Pagination {
+                limit: pagination.page_size as i64,
+                offset: pagination.offset as i64,
+            },
+        )
+        .await
+        .map_err(database_error)?;
+
+    let elements: Vec<Attachment> = result.items.into_iter().map(attachment_response).collect();
+    Ok(HalResponse(op_core::representations::Collection::new(
+        elements,
+        result.total as usize,
+        pagination.offset,
+        pagination.page_size,
+    )))
+}
+
+/// List the active document categories
+///
+/// GET /api/v3/document_categories
+pub async fn list_document_categories(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> ApiResult<impl IntoResponse> {
+    let categories = DocumentRepository::new(state.pool()?.clone())
+        .categories()
+        .await
+        .map_err(database_error)?;
+
+    let elements: Vec<CategoryResponse> = categories.into_iter().map(CategoryResponse::from_row).collect();
+    Ok(HalResponse(DocumentCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        groups: Vec::new(),
+        elements,
+    }))
+}
+
+fn permissions(pool: &PgPool) -> PermissionService<MemberRepository> {
+    PermissionService::new(MemberRepository::new(pool.clone()))
+}
+
+fn database_error(e: RepositoryError) -> ApiError {
+    ApiError::internal(format!("Database error: {}", e))
+}
+
+async fn allowed(pool: &PgPool, user: &AuthenticatedUser, permission: &str, project_id: Id) -> ApiResult<bool> {
+    permissions(pool)
+        .allowed_in_project(user, permission, project_id)
+        .await
+        .map_err(database_error)
+}
+
+/// The document, if the user may view the documents of its project
+async fn ensure_document_visible(pool: &PgPool, user: &AuthenticatedUser, id: Id) -> ApiResult<DocumentRow> {
+    let document = DocumentRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| ApiError::not_found("Document", id))?;
+
+    if allowed(pool, user, VIEW_DOCUMENTS, document.project_id).await? {
+        Ok(document)
+    } else {
+        Err(ApiError::not_found("Document", id))
+    }
+}
+
+fn into_api_result<T>(result: ServiceResult<T>) -> ApiResult<T> {
+    if result.is_failure() {
+        return Err(ApiError::Validation(result.errors().clone()));
+    }
+    Ok(result.unwrap())
+}
+
+/// Consecutive documents sharing a category or creation date, in the order
+/// of the listing
+fn group_documents(documents: &[DocumentRow], order: DocumentOrder) -> Vec<GroupResponse> {
+    let mut groups: Vec<GroupResponse> = Vec::new();
+    for document in documents {
+        let value = match order {
+            DocumentOrder::Category => document.category_name.clone(),
+            DocumentOrder::Date => Some(document.created_at.date_naive().to_string()),
+        };
+        match groups.last_mut() {
+            Some(group) if group.value == value => group.count += 1,
+            _ => groups.push(GroupResponse { value, count: 1 }),
+        }
+    }
+    groups
+}
+
+async fn render_document(document: DocumentRow, pool: &PgPool, user: &AuthenticatedUser) -> ApiResult<DocumentResponse> {
+    Ok(render_documents(vec![document], pool, user).await?.remove(0))
+}
+
+/// Documents with the references in their descriptions resolved together for the user
+async fn render_documents(
+    documents: Vec<DocumentRow>,
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+) -> ApiResult<Vec<DocumentResponse>> {
+    let descriptions: Vec<&str> = documents
+        .iter()
+        .map(|d| d.description.as_deref().unwrap_or_default())
+        .collect();
+    let rendered = MarkdownRenderer::new(DbReferenceResolver::new(pool.clone(), &user.0))
+        .render_all(&descriptions)
+        .await?;
+
+    Ok(documents
+        .into_iter()
+        .zip(rendered)
+        .map(|(document, html)| DocumentResponse::from_row(document, html))
+        .collect())
+}
+
+// Query parameters
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentListParams {
+    /// `category` (default) or `date`
+    pub sort_by: Option<String>,
+}
+
+// Request types
+#[derive(Debug, Deserialize)]
+pub struct DescriptionRequest {
+    pub raw: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkRequest {
+    pub href: String,
+}
+
+impl LinkRequest {
+    /// Id of the linked category, e.g. 3 for `/api/v3/document_categories/3`
+    fn id(&self) -> ApiResult<Id> {
+        self.href
+            .strip_prefix("/api/v3/document_categories/")
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| ApiError::invalid_property("category", "is not a document category link."))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentLinksRequest {
+    pub category: Option<LinkRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDocumentRequest {
+    pub title: String,
+    pub description: Option<DescriptionRequest>,
+    #[serde(rename = "_links")]
+    pub links: Option<DocumentLinksRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDocumentRequest {
+    pub title: Option<String>,
+    pub description: Option<DescriptionRequest>,
+    #[serde(rename = "_links")]
+    pub links: Option<DocumentLinksRequest>,
+}
+
+// Response types
+#[derive(Debug, Serialize)]
+struct DocumentCollection<T: Serialize> {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    /// Groups of consecutive elements, empty for ungrouped collections
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<GroupResponse>,
+    #[serde(rename = "_embedded")]
+    elements: Vec<T>,
+}
+
+#[derive(Debug, Serialize)]
+struct GroupResponse {
+    /// Category name or creation date; `null` for documents without a category
+    value: Option<String>,
+    count: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    title: String,
+    description: FormattableText,
+    created_at: String,
+    updated_at: String,
+    #[serde(rename = "_links")]
+    links: DocumentLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct DocumentLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    project: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<Link>,
+    attachments: Link,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CategoryResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    name: String,
+    position: i32,
+    is_default: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Link {
+    href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+}
+
+fn link(href: String) -> Link {
+    Link { href, title: None }
+}
+
+impl DocumentResponse {
+    fn from_row(document: DocumentRow, description_html: String) -> Self {
+        Self {
+            type_name: "Document".into(),
+            id: document.id,
+            title: document.title,
+            description: FormattableText::markdown_rendered(
+                document.description.as_deref().unwrap_or_default(),
+                description_html,
+            ),
+            created_at: document.created_at.to_rfc3339(),
+            updated_at: document.updated_at.to_rfc3339(),
+            links: DocumentLinks {
+                self_link: link(format!("/api/v3/documents/{}", document.id)),
+                project: link(format!("/api/v3/projects/{}", document.project_id)),
+                category: document.category_id.map(|id| Link {
+                    href: format!("/api/v3/document_categories/{}", id),
+                    title: document.category_name,
+                }),
+                attachments: link(format!("/api/v3/documents/{}/attachments", document.id)),
+            },
+        }
+    }
+}
+
+impl CategoryResponse {
+    fn from_row(category: DocumentCategoryRow) -> Self {
+        Self {
+            type_name: "DocumentCategory".into(),
+            id: category.id,
+            name: category.name,
+            position: category.position,
+            is_default: category.is_default,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn row(id: Id, category: Option<&str>, day: u32) -> DocumentRow {
+        let created_at = Utc.with_ymd_and_hms(2026, 3, day, 12, 0, 0).unwrap();
+        DocumentRow {
+            id,
+            project_id: 1,
+            category_id: category.map(|_| 1),
+            category_name: category.map(str::to_string),
+            category_position: None,
+            title: format!("Document {}", id),
+            description: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    #[test]
+    fn test_group_documents() {
+        let documents = [row(1, Some("Documentation"), 2), row(2, Some("Documentation"), 1), row(3, None, 1)];
+
+        let groups = group_documents(&documents, DocumentOrder::Category);
+        let groups: Vec<_> = groups.iter().map(|g| (g.value.as_deref(), g.count)).collect();
+        assert_eq!(groups, vec![(Some("Documentation"), 2), (None, 1)]);
+
+        let groups = group_documents(&documents, DocumentOrder::Date);
+        let groups: Vec<_> = groups.iter().map(|g| (g.value.as_deref(), g.count)).collect();
+        assert_eq!(groups, vec![(Some("2026-03-02"), 1), (Some("2026-03-01"), 2)]);
+    }
+
+    #[test]
+    fn test_category_link() {
+        let category = LinkRequest {
+            href: "/api/v3/document_categories/3".into(),
+        };
+        assert_eq!(category.id().unwrap(), 3);
+        let priority = LinkRequest {
+            href: "/api/v3/priorities/3".into(),
+        };
+        assert!(priority.id().is_err());
+    }
+}
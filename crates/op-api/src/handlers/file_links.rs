@@ -0,0 +1,454 @@
+//! File links API handlers
+//!
+//! Mirrors: modules/storages/lib/api/v3/file_links/*
+//!
+//! File links point from a work package to files in a storage. Each link
+//! reports whether its file can still be opened, as the storage's provider
+//! tells.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use op_contracts::file_links::permissions::{MANAGE_FILE_LINKS, VIEW_FILE_LINKS};
+use op_core::traits::Id;
+use op_db::{
+    file_link_container_type, CreateFileLinkDto, FileLinkRepository, FileLinkRow, MemberRepository, Repository,
+    StorageRepository, StorageRow,
+};
+use op_services::permissions::PermissionService;
+use op_services::storages::{provider_for, LinkStatus};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+use crate::handlers::storages::storage_id_from_href;
+use crate::handlers::watchers::ensure_work_package_visible;
+
+/// List the file links of a work package
+///
+/// GET /api/v3/work_packages/:work_package_id/file_links
+pub async fn list_work_package_file_links(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(work_package_id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let work_package = ensure_work_package_visible(pool, &user, work_package_id).await?;
+    let can_manage = authorize(pool, &user, work_package.id, work_package.project_id, false).await?;
+
+    let links = FileLinkRepository::new(pool.clone())
+        .find_by_work_package(work_package.id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(HalResponse(represent_collection(pool, work_package.id, links, can_manage).await?))
+}
+
+/// Link files of storages to a work package
+///
+/// POST /api/v3/work_packages/:work_package_id/file_links
+pub async fn create_work_package_file_links(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(work_package_id): Path<Id>,
+    Json(dto): Json<CreateFileLinksRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let elements = dto.embedded.elements;
+    if elements.is_empty() {
+        return Err(ApiError::bad_request("No file links given."));
+    }
+    let storage_ids = elements
+        .iter()
+        .map(|element| {
+            storage_id_from_href(&element.links.storage.href).ok_or_else(|| {
+                ApiError::invalid_property("storage", format!("'{}' is not a storage", element.links.storage.href))
+            })
+        })
+        .collect::<ApiResult<Vec<Id>>>()?;
+
+    let pool = state.pool()?;
+    let work_package = ensure_work_package_visible(pool, &user, work_package_id).await?;
+    authorize(pool, &user, work_package.id, work_package.project_id, true).await?;
+
+    let repo = FileLinkRepository::new(pool.clone());
+    let mut links = Vec::with_capacity(elements.len());
+    for (element, storage_id) in elements.into_iter().zip(storage_ids) {
+        let origin = element.origin_data;
+        let link = repo
+            .create(CreateFileLinkDto {
+                storage_id,
+                creator_id: user.0.id,
+                container_id: work_package.id,
+                container_type: file_link_container_type::WORK_PACKAGE.to_string(),
+                origin_id: origin.id,
+                origin_name: origin.name,
+                origin_mime_type: origin.mime_type,
+                origin_created_by_name: origin.created_by_name,
+                origin_created_at: origin.created_at,
+                origin_updated_at: origin.last_modified_at,
+            })
+            .await
+            .map_err(|e| match e {
+                op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+                _ => ApiError::internal(format!("Database error: {}", e)),
+            })?;
+        links.push(link);
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        HalResponse(represent_collection(pool, work_package.id, links, true).await?),
+    ))
+}
+
+/// Get a single file link
+///
+/// GET /api/v3/file_links/:id
+pub async fn get_file_link(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let link = find_visible_link(pool, &user, id).await?;
+    let work_package = ensure_work_package_visible(pool, &user, link.container_id).await?;
+    let can_manage = authorize(pool, &user, work_package.id, work_package.project_id, false).await?;
+
+    let storages = storages_by_id(pool, &[link.storage_id]).await?;
+    let status = probe(storages.get(&link.storage_id), &link).await;
+
+    Ok(HalResponse(FileLinkResponse::from_row(
+        link,
+        storages.values().next(),
+        status,
+        can_manage,
+    )))
+}
+
+/// Remove a file link; the file itself stays in the storage
+///
+/// DELETE /api/v3/file_links/:id
+pub async fn delete_file_link(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let link = find_visible_link(pool, &user, id).await?;
+    let work_package = ensure_work_package_visible(pool, &user, link.container_id).await?;
+    authorize(pool, &user, work_package.id, work_package.project_id, true).await?;
+
+    FileLinkRepository::new(pool.clone())
+        .delete(id)
+        .await
+        .map_err(|e| match e {
+            op_db::RepositoryError::NotFound(_) => ApiError::not_found("FileLink", id),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check the user may see (or, with `manage`, change) the file links of a
+/// work package; returns whether they may change them
+async fn authorize(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    work_package_id: Id,
+    project_id: Id,
+    manage: bool,
+) -> ApiResult<bool> {
+    let permissions = PermissionService::new(MemberRepository::new(pool.clone()));
+    let allowed = |permission: &'static str| {
+        let permissions = &permissions;
+        async move {
+            permissions
+                .allowed_on_work_package(user, permission, work_package_id, project_id)
+                .await
+                .map_err(|e| ApiError::internal(format!("Database error: {}", e)))
+        }
+    };
+
+    let can_manage = allowed(MANAGE_FILE_LINKS).await?;
+    if manage && !can_manage {
+        return Err(ApiError::forbidden("You are not authorized to manage the file links of this work package."));
+    }
+    if !can_manage && !allowed(VIEW_FILE_LINKS).await? {
+        return Err(ApiError::forbidden("You are not authorized to view the file links of this work package."));
+    }
+
+    Ok(can_manage)
+}
+
+/// Links of work packages the user cannot see do not exist for them
+async fn find_visible_link(pool: &PgPool, user: &AuthenticatedUser, id: Id) -> ApiResult<FileLinkRow> {
+    let link = FileLinkRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|link| link.container_type == file_link_container_type::WORK_PACKAGE)
+        .ok_or_else(|| ApiError::not_found("FileLink", id))?;
+
+    ensure_work_package_visible(pool, user, link.container_id)
+        .await
+        .map_err(|_| ApiError::not_found("FileLink", id))?;
+
+    Ok(link)
+}
+
+async fn storages_by_id(pool: &PgPool, ids: &[Id]) -> ApiResult<HashMap<Id, StorageRow>> {
+    let storages = StorageRepository::new(pool.clone())
+        .find_by_ids(ids)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(storages.into_iter().map(|storage| (storage.id, storage)).collect())
+}
+
+async fn probe(storage: Option<&StorageRow>, link: &FileLinkRow) -> LinkStatus {
+    match storage.and_then(provider_for) {
+        Some(provider) => provider.probe_origin(link).await,
+        None => LinkStatus::Error,
+    }
+}
+
+async fn represent_collection(
+    pool: &PgPool,
+    work_package_id: Id,
+    links: Vec<FileLinkRow>,
+    can_manage: bool,
+) -> ApiResult<FileLinkCollection> {
+    let mut storage_ids: Vec<Id> = links.iter().map(|link| link.storage_id).collect();
+    storage_ids.sort_unstable();
+    storage_ids.dedup();
+    let storages = storages_by_id(pool, &storage_ids).await?;
+
+    let mut elements = Vec::with_capacity(links.len());
+    for link in links {
+        let storage = storages.get(&link.storage_id);
+        let status = probe(storage, &link).await;
+        elements.push(FileLinkResponse::from_row(link, storage, status, can_manage));
+    }
+
+    Ok(FileLinkCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        elements,
+        links: CollectionLinks {
+            self_link: Link::new(format!("/api/v3/work_packages/{}/file_links", work_package_id)),
+        },
+    })
+}
+
+// Request types
+#[derive(Debug, Deserialize)]
+pub struct CreateFileLinksRequest {
+    #[serde(rename = "_embedded")]
+    pub embedded: FileLinkElementsRequest,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileLinkElementsRequest {
+    pub elements: Vec<FileLinkRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileLinkRequest {
+    pub origin_data: OriginDataRequest,
+    #[serde(rename = "_links")]
+    pub links: FileLinkRequestLinks,
+}
+
+/// What the storage reports about the linked file
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OriginDataRequest {
+    pub id: String,
+    pub name: String,
+    pub mime_type: Option<String>,
+    pub created_by_name: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_modified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileLinkRequestLinks {
+    pub storage: HrefRequest,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HrefRequest {
+    pub href: String,
+}
+
+// Response types
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileLinkCollection {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    #[serde(rename = "_embedded")]
+    elements: Vec<FileLinkResponse>,
+    #[serde(rename = "_links")]
+    links: CollectionLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct CollectionLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileLinkResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    created_at: String,
+    updated_at: String,
+    origin_data: OriginDataResponse,
+    #[serde(rename = "_links")]
+    links: FileLinkLinks,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OriginDataResponse {
+    id: String,
+    name: String,
+    mime_type: Option<String>,
+    created_by_name: Option<String>,
+    created_at: Option<String>,
+    last_modified_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileLinkLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    storage: Link,
+    container: Link,
+    creator: Link,
+    permission: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin_open: Option<Link>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delete: Option<Link>,
+}
+
+#[derive(Debug, Serialize)]
+struct Link {
+    href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+}
+
+impl Link {
+    fn new(href: String) -> Self {
+        Self { href, title: None }
+    }
+
+    fn titled(href: String, title: impl Into<String>) -> Self {
+        Self {
+            href,
+            title: Some(title.into()),
+        }
+    }
+}
+
+impl FileLinkResponse {
+    fn from_row(row: FileLinkRow, storage: Option<&StorageRow>, status: LinkStatus, can_manage: bool) -> Self {
+        let self_href = format!("/api/v3/file_links/{}", row.id);
+        let storage_href = format!("/api/v3/storages/{}", row.storage_id);
+        let origin_open = storage.and_then(provider_for).map(|provider| Link::new(provider.origin_href(&row)));
+
+        FileLinkResponse {
+            type_name: "FileLink".into(),
+            id: row.id,
+            created_at: row.created_at.to_rfc3339(),
+            updated_at: row.updated_at.to_rfc3339(),
+            links: FileLinkLinks {
+                self_link: Link::titled(self_href.clone(), row.origin_name.clone()),
+                storage: match storage {
+                    Some(storage) => Link::titled(storage_href, storage.name.clone()),
+                    None => Link::new(storage_href),
+                },
+                container: Link::new(format!("/api/v3/work_packages/{}", row.container_id)),
+                creator: Link::new(format!("/api/v3/users/{}", row.creator_id)),
+                permission: Link::titled(status.urn(), status.title()),
+                origin_open,
+                delete: can_manage.then(|| Link::new(self_href)),
+            },
+            origin_data: OriginDataResponse {
+                id: row.origin_id,
+                name: row.origin_name,
+                mime_type: row.origin_mime_type,
+                created_by_name: row.origin_created_by_name,
+                created_at: row.origin_created_at.map(|at| at.to_rfc3339()),
+                last_modified_at: row.origin_updated_at.map(|at| at.to_rfc3339()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_models::StorageType;
+
+    #[test]
+    fn test_file_link_response() {
+        let storage = StorageRow {
+            id: 3,
+            provider_type: StorageType::Nextcloud.provider_type().to_string(),
+            name: "Cloud".into(),
+            host: "https://cloud.example.com".into(),
+            creator_id: 1,
+            oauth_client_id: None,
+            oauth_client_secret: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let link = FileLinkRow {
+            id: 9,
+            storage_id: 3,
+            creator_id: 1,
+            container_id: 17,
+            container_type: file_link_container_type::WORK_PACKAGE.to_string(),
+            origin_id: "5503".into(),
+            origin_name: "logo.png".into(),
+            origin_mime_type: Some("image/png".into()),
+            origin_created_by_name: None,
+            origin_created_at: None,
+            origin_updated_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let json =
+            serde_json::to_value(FileLinkResponse::from_row(link, Some(&storage), LinkStatus::ViewAllowed, false))
+                .unwrap();
+        assert_eq!(json["originData"]["mimeType"], "image/png");
+        assert_eq!(json["_links"]["storage"]["title"], "Cloud");
+        assert_eq!(json["_links"]["container"]["href"], "/api/v3/work_packages/17");
+        assert_eq!(json["_links"]["originOpen"]["href"], "https://cloud.example.com/index.php/f/5503");
+        assert_eq!(
+            json["_links"]["permission"]["href"],
+            "urn:openproject-org:api:v3:file-links:permission:ViewAllowed"
+        );
+        assert!(json["_links"].get("delete").is_none());
+    }
+}
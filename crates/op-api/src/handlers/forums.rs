@@ -0,0 +1,634 @@
+//! Forums API handlers
+//!
+//! Mirrors:
+//! - app/controllers/forums_controller.rb
+//! - app/controllers/messages_controller.rb
+//!
+//! Forums of a project hold topics, and topics their replies. Members with
+//! `view_messages` read them; everything else goes through `MessageService`.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use op_contracts::forums::permissions::{MANAGE_FORUMS, VIEW_MESSAGES};
+use op_core::representations::FormattableText;
+use op_core::traits::Id;
+use op_db::{
+    CreateForumDto, ForumRepository, ForumRow, MemberRepository, MessageRepository, MessageRow,
+    MessageVersionRow, Repository, RepositoryError, UpdateForumDto,
+};
+use op_services::forums::{MessageParams, MessageService, PgForumStore, UpdateMessageParams};
+use op_services::permissions::PermissionService;
+use op_services::ServiceResult;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::formatting::{DbReferenceResolver, MarkdownRenderer};
+
+/// List the forums of a project
+///
+/// GET /api/v3/projects/:id/forums
+pub async fn list_project_forums(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(project_id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    if !allowed(pool, &user, VIEW_MESSAGES, project_id).await? {
+        return Err(ApiError::forbidden("You are not authorized to view the forums of this project."));
+    }
+
+    let forums = ForumRepository::new(pool.clone())
+        .find_by_project(project_id)
+        .await
+        .map_err(database_error)?;
+
+    let elements: Vec<ForumResponse> = forums.into_iter().map(ForumResponse::from_row).collect();
+    Ok(HalResponse(Collection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        page_size: None,
+        offset: None,
+        elements,
+    }))
+}
+
+/// Create a forum in a project
+///
+/// POST /api/v3/projects/:id/forums
+pub async fn create_project_forum(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(project_id): Path<Id>,
+    Json(dto): Json<CreateForumRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    if !allowed(pool, &user, MANAGE_FORUMS, project_id).await? {
+        return Err(ApiError::forbidden("You are not authorized to manage the forums of this project."));
+    }
+
+    let forum = ForumRepository::new(pool.clone())
+        .create(CreateForumDto {
+            project_id,
+            name: dto.name,
+            description: dto.description,
+        })
+        .await
+        .map_err(|e| match e {
+            RepositoryError::Validation(errors) => ApiError::validation(errors),
+            e => database_error(e),
+        })?;
+
+    Ok((StatusCode::CREATED, HalResponse(ForumResponse::from_row(forum))))
+}
+
+/// Get a forum
+///
+/// GET /api/v3/forums/:id
+pub async fn get_forum(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let forum = ensure_forum_visible(state.pool()?, &user, id).await?;
+    Ok(HalResponse(ForumResponse::from_row(forum)))
+}
+
+/// Rename, describe or move a forum
+///
+/// PATCH /api/v3/forums/:id
+pub async fn update_forum(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<UpdateForumRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let forum = ensure_forum_visible(pool, &user, id).await?;
+    if !allowed(pool, &user, MANAGE_FORUMS, forum.project_id).await? {
+        return Err(ApiError::forbidden("You are not authorized to manage the forums of this project."));
+    }
+
+    let forum = ForumRepository::new(pool.clone())
+        .update(
+            forum.id,
+            UpdateForumDto {
+                name: dto.name,
+                description: dto.description,
+                position: dto.position,
+            },
+        )
+        .await
+        .map_err(|e| match e {
+            RepositoryError::NotFound(_) => ApiError::not_found("Forum", id),
+            RepositoryError::Validation(errors) => ApiError::validation(errors),
+            e => database_error(e),
+        })?;
+
+    Ok(HalResponse(ForumResponse::from_row(forum)))
+}
+
+/// List the topics of a forum, sticky ones first, then by latest activity
+///
+/// GET /api/v3/forums/:id/topics
+pub async fn list_forum_topics(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    pagination: Pagination,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let forum = ensure_forum_visible(pool, &user, id).await?;
+
+    let topics = MessageRepository::new(pool.clone())
+        .find_topics(forum.id, pagination.page_size as i64, pagination.offset as i64)
+        .await
+        .map_err(database_error)?;
+
+    let elements = render_messages(topics, pool, &user).await?;
+    Ok(HalResponse(Collection {
+        type_name: "Collection".into(),
+        total: forum.topics_count as usize,
+        count: elements.len(),
+        page_size: Some(pagination.page_size),
+        offset: Some(pagination.offset),
+        elements,
+    }))
+}
+
+/// Start a topic in a forum, notifying the forum's watchers
+///
+/// POST /api/v3/forums/:id/topics
+pub async fn create_forum_topic(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<CreateMessageRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let forum = ensure_forum_visible(pool, &user, id).await?;
+
+    let store = PgForumStore::new(pool.clone());
+    let permissions = permissions(pool);
+    let topic = into_api_result(
+        MessageService::new(&user, &store, &permissions)
+            .with_notifications(state.notifications.as_ref())
+            .with_streams(&state.notification_streams)
+            .create_topic(forum.id, dto.into_params())
+            .await,
+    )?;
+
+    Ok((StatusCode::CREATED, HalResponse(render_message(topic, pool, &user).await?)))
+}
+
+/// Get a topic or reply
+///
+/// GET /api/v3/messages/:id
+pub async fn get_message(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let message = ensure_message_visible(pool, &user, id).await?;
+    Ok(HalResponse(render_message(message, pool, &user).await?))
+}
+
+/// Edit a message; locking and pinning topics needs `manage_forums`
+///
+/// PATCH /api/v3/messages/:id
+pub async fn update_message(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<UpdateMessageRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let message = ensure_message_visible(pool, &user, id).await?;
+
+    let store = PgForumStore::new(pool.clone());
+    let permissions = permissions(pool);
+    let params = UpdateMessageParams {
+        subject: dto.subject,
+        content: dto.content.map(|c| c.raw),
+        locked: dto.locked,
+        sticky: dto.sticky,
+    };
+    let message = into_api_result(
+        MessageService::new(&user, &store, &permissions)
+            .update(message.id, params)
+            .await,
+    )?;
+
+    Ok(HalResponse(render_message(message, pool, &user).await?))
+}
+
+/// Delete a message; deleting a topic deletes its replies and their attachments
+///
+/// DELETE /api/v3/messages/:id
+pub async fn delete_message(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let message = ensure_message_visible(pool, &user, id).await?;
+
+    let store = PgForumStore::new(pool.clone());
+    let permissions = permissions(pool);
+    into_api_result(
+        MessageService::new(&user, &store, &permissions)
+            .delete(message.id, state.attachments.as_deref())
+            .await,
+    )?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the replies to a topic, oldest first
+///
+/// GET /api/v3/messages/:id/replies
+pub async fn list_message_replies(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let topic = ensure_message_visible(pool, &user, id).await?;
+
+    let replies = MessageRepository::new(pool.clone())
+        .find_replies(topic.id)
+        .await
+        .map_err(database_error)?;
+
+    let elements = render_messages(replies, pool, &user).await?;
+    Ok(HalResponse(Collection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        page_size: None,
+        offset: None,
+        elements,
+    }))
+}
+
+/// Reply to a topic; replies to locked topics are rejected with 422
+///
+/// POST /api/v3/messages/:id/replies
+pub async fn create_message_reply(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<CreateMessageRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let topic = ensure_message_visible(pool, &user, id).await?;
+
+    let store = PgForumStore::new(pool.clone());
+    let permissions = permissions(pool);
+    let reply = into_api_result(
+        MessageService::new(&user, &store, &permissions)
+            .reply(topic.id, dto.into_params())
+            .await,
+    )?;
+
+    Ok((StatusCode::CREATED, HalResponse(render_message(reply, pool, &user).await?)))
+}
+
+/// List the versions of a message, oldest first
+///
+/// GET /api/v3/messages/:id/versions
+pub async fn list_message_versions(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let message = ensure_message_visible(pool, &user, id).await?;
+
+    let versions = MessageRepository::new(pool.clone())
+        .find_versions(message.id)
+        .await
+        .map_err(database_error)?;
+
+    let elements: Vec<MessageVersionResponse> = versions
+        .into_iter()
+        .map(|version| MessageVersionResponse::from_row(message.id, version))
+        .collect();
+    Ok(HalResponse(Collection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        page_size: None,
+        offset: None,
+        elements,
+    }))
+}
+
+fn permissions(pool: &PgPool) -> PermissionService<MemberRepository> {
+    PermissionService::new(MemberRepository::new(pool.clone()))
+}
+
+fn database_error(e: RepositoryError) -> ApiError {
+    ApiError::internal(format!("Database error: {}", e))
+}
+
+async fn allowed(pool: &PgPool, user: &AuthenticatedUser, permission: &str, project_id: Id) -> ApiResult<bool> {
+    permissions(pool)
+        .allowed_in_project(user, permission, project_id)
+        .await
+        .map_err(database_error)
+}
+
+/// The forum, if the user may view its messages
+async fn ensure_forum_visible(pool: &PgPool, user: &AuthenticatedUser, id: Id) -> ApiResult<ForumRow> {
+    let forum = ForumRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| ApiError::not_found("Forum", id))?;
+
+    if allowed(pool, user, VIEW_MESSAGES, forum.project_id).await? {
+        Ok(forum)
+    } else {
+        Err(ApiError::not_found("Forum", id))
+    }
+}
+
+/// The message, if the user may view the messages of its forum
+async fn ensure_message_visible(pool: &PgPool, user: &AuthenticatedUser, id: Id) -> ApiResult<MessageRow> {
+    let message = MessageRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| ApiError::not_found("Message", id))?;
+
+    ensure_forum_visible(pool, user, message.forum_id)
+        .await
+        .map_err(|_| ApiError::not_found("Message", id))?;
+    Ok(message)
+}
+
+fn into_api_result<T>(result: ServiceResult<T>) -> ApiResult<T> {
+    if result.is_failure() {
+        return Err(ApiError::Validation(result.errors().clone()));
+    }
+    Ok(result.unwrap())
+}
+
+async fn render_message(message: MessageRow, pool: &PgPool, user: &AuthenticatedUser) -> ApiResult<MessageResponse> {
+    Ok(render_messages(vec![message], pool, user).await?.remove(0))
+}
+
+/// Messages with the references in their content resolved together for the user
+async fn render_messages(
+    messages: Vec<MessageRow>,
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+) -> ApiResult<Vec<MessageResponse>> {
+    let contents: Vec<&str> = messages.iter().map(|m| m.content.as_deref().unwrap_or_default()).collect();
+    let rendered = MarkdownRenderer::new(DbReferenceResolver::new(pool.clone(), &user.0))
+        .render_all(&contents)
+        .await?;
+
+    Ok(messages
+        .into_iter()
+        .zip(rendered)
+        .map(|(message, html)| MessageResponse::from_row(message, html))
+        .collect())
+}
+
+// Request types
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateForumRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateForumRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub position: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContentRequest {
+    pub raw: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageRequest {
+    /// Required for topics; replies default to "RE: " and the topic's subject
+    #[serde(default)]
+    pub subject: String,
+    pub content: Option<ContentRequest>,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub sticky: bool,
+}
+
+impl CreateMessageRequest {
+    fn into_params(self) -> MessageParams {
+        MessageParams {
+            subject: self.subject,
+            content: self.content.map(|c| c.raw),
+            locked: self.locked,
+            sticky: self.sticky,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateMessageRequest {
+    pub subject: Option<String>,
+    pub content: Option<ContentRequest>,
+    pub locked: Option<bool>,
+    pub sticky: Option<bool>,
+}
+
+// Response types
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Collection<T: Serialize> {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<usize>,
+    #[serde(rename = "_embedded")]
+    elements: Vec<T>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ForumResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    name: String,
+    description: Option<String>,
+    position: i32,
+    topics_count: i32,
+    messages_count: i32,
+    #[serde(rename = "_links")]
+    links: ForumLinks,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ForumLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    project: Link,
+    topics: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_message: Option<Link>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    subject: String,
+    content: FormattableText,
+    replies_count: i32,
+    locked: bool,
+    sticky: bool,
+    created_at: String,
+    updated_at: String,
+    last_reply_at: Option<String>,
+    #[serde(rename = "_links")]
+    links: MessageLinks,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    forum: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<Link>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<Link>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replies: Option<Link>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_reply: Option<Link>,
+    versions: Link,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageVersionResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    version: i32,
+    subject: String,
+    content: FormattableText,
+    locked: bool,
+    sticky: bool,
+    created_at: String,
+    #[serde(rename = "_links")]
+    links: MessageVersionLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct MessageVersionLinks {
+    message: Link,
+    user: Link,
+}
+
+#[derive(Debug, Serialize)]
+struct Link {
+    href: String,
+}
+
+fn link(href: String) -> Link {
+    Link { href }
+}
+
+impl ForumResponse {
+    fn from_row(forum: ForumRow) -> Self {
+        Self {
+            type_name: "Forum".into(),
+            id: forum.id,
+            name: forum.name,
+            description: forum.description,
+            position: forum.position,
+            topics_count: forum.topics_count,
+            messages_count: forum.messages_count,
+            links: ForumLinks {
+                self_link: link(format!("/api/v3/forums/{}", forum.id)),
+                project: link(format!("/api/v3/projects/{}", forum.project_id)),
+                topics: link(format!("/api/v3/forums/{}/topics", forum.id)),
+                last_message: forum.last_message_id.map(|id| link(format!("/api/v3/messages/{}", id))),
+            },
+        }
+    }
+}
+
+impl MessageResponse {
+    fn from_row(message: MessageRow, content_html: String) -> Self {
+        let is_topic = message.is_topic();
+        let is_sticky = message.is_sticky();
+        Self {
+            type_name: "Message".into(),
+            id: message.id,
+            subject: message.subject,
+            content: FormattableText::markdown_rendered(message.content.as_deref().unwrap_or_default(), content_html),
+            replies_count: message.replies_count,
+            locked: message.locked,
+            sticky: is_sticky,
+            created_at: message.created_at.to_rfc3339(),
+            updated_at: message.updated_at.to_rfc3339(),
+            last_reply_at: message.last_reply_at.map(|at| at.to_rfc3339()),
+            links: MessageLinks {
+                self_link: link(format!("/api/v3/messages/{}", message.id)),
+                forum: link(format!("/api/v3/forums/{}", message.forum_id)),
+                author: message.author_id.map(|id| link(format!("/api/v3/users/{}", id))),
+                parent: message.parent_id.map(|id| link(format!("/api/v3/messages/{}", id))),
+                replies: is_topic.then(|| link(format!("/api/v3/messages/{}/replies", message.id))),
+                last_reply: message.last_reply_id.map(|id| link(format!("/api/v3/messages/{}", id))),
+                versions: link(format!("/api/v3/messages/{}/versions", message.id)),
+            },
+        }
+    }
+}
+
+impl MessageVersionResponse {
+    fn from_row(message_id: Id, version: MessageVersionRow) -> Self {
+        Self {
+            type_name: "MessageVersion".into(),
+            id: version.journal_id,
+            version: version.version,
+            subject: version.subject,
+            content: FormattableText::markdown(version.content.as_deref().unwrap_or_default()),
+            locked: version.locked,
+            sticky: version.sticky > 0,
+            created_at: version.created_at.to_rfc3339(),
+            links: MessageVersionLinks {
+                message: link(format!("/api/v3/messages/{}", message_id)),
+                user: link(format!("/api/v3/users/{}", version.user_id)),
+            },
+        }
+    }
+}
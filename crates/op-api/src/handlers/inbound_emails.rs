@@ -0,0 +1,248 @@
+//! Inbound email API handler
+//!
+//! Mirrors: app/controllers/mail_handler_controller.rb
+//!
+//! Inbound mail services (e.g. SendGrid inbound parse) post replies to
+//! notification emails here; they become comments on the work package.
+//! Calls carry the shared token configured in `AppConfig::inbound_email_token`.
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Multipart, Query, Request, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use op_core::traits::Id;
+use op_db::{
+    journable_type, AttachmentRepository, CreateAttachmentDto, JournalRepository, MemberRepository,
+    Repository, RepositoryError, UserRepository, WorkPackageRepository,
+};
+use op_notifications::{
+    CommentSink, InboundComment, InboundError, InboundMailProcessor, SenderDirectory,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::AppState;
+
+/// Header carrying the inbound email token
+pub const INBOUND_TOKEN_HEADER: &str = "x-openproject-inbound-token";
+
+/// Largest accepted inbound message, attachments included
+pub const INBOUND_EMAIL_BODY_LIMIT: usize = 25 * 1024 * 1024;
+
+/// Form field holding the raw message in multipart posts
+const RAW_MESSAGE_FIELD: &str = "email";
+
+const ADD_WORK_PACKAGE_NOTES: &str = "add_work_package_notes";
+
+#[derive(Debug, Deserialize)]
+pub struct InboundEmailParams {
+    pub token: Option<String>,
+}
+
+/// Receive a reply to a notification email
+///
+/// POST /api/v3/inbound_emails
+///
+/// Accepts the raw RFC 822 message as the request body, or as the `email`
+/// field of a multipart form as sent by SendGrid inbound parse.
+pub async fn receive_inbound_email(
+    State(state): State<AppState>,
+    Query(params): Query<InboundEmailParams>,
+    request: Request,
+) -> ApiResult<impl IntoResponse> {
+    let expected = state
+        .config
+        .inbound_email_token
+        .as_deref()
+        .ok_or_else(|| ApiError::forbidden("Inbound email is not enabled."))?;
+
+    let token = request
+        .headers()
+        .get(INBOUND_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or(params.token);
+    if !token.is_some_and(|t| constant_time_eq(t.as_bytes(), expected.as_bytes())) {
+        return Err(ApiError::unauthorized("Invalid inbound email token."));
+    }
+
+    let raw = read_raw_message(&state, request).await?;
+    let pool = state.pool()?;
+
+    let processor = InboundMailProcessor::new(
+        DbSenderDirectory::new(pool.clone()),
+        DbCommentSink::new(pool.clone()),
+        state.config.inbound_email.clone(),
+    );
+
+    let reply = processor.process(&raw).await.map_err(|e| match e {
+        InboundError::UnknownSender(_) | InboundError::Rejected(_) => ApiError::forbidden(e.to_string()),
+        e if e.is_transient() => ApiError::internal(e.to_string()),
+        e => ApiError::bad_request(e.to_string()),
+    })?;
+
+    Ok((StatusCode::CREATED, Json(reply)))
+}
+
+async fn read_raw_message(state: &AppState, request: Request) -> ApiResult<Bytes> {
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("multipart/form-data"));
+
+    if !is_form {
+        return Bytes::from_request(request, state)
+            .await
+            .map_err(|e| ApiError::bad_request(e.body_text()));
+    }
+
+    let mut form = Multipart::from_request(request, state)
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?;
+
+    while let Some(field) = form
+        .next_field()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?
+    {
+        if field.name() == Some(RAW_MESSAGE_FIELD) {
+            return field
+                .bytes()
+                .await
+                .map_err(|e| ApiError::bad_request(e.body_text()));
+        }
+    }
+
+    Err(ApiError::bad_request("Missing the raw message field \"email\"."))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Senders looked up among the active users
+struct DbSenderDirectory {
+    users: UserRepository,
+}
+
+impl DbSenderDirectory {
+    fn new(pool: PgPool) -> Self {
+        Self {
+            users: UserRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl SenderDirectory for DbSenderDirectory {
+    async fn find_user_id(&self, email: &str) -> Result<Option<Id>, InboundError> {
+        let user = self
+            .users
+            .find_by_email(email)
+            .await
+            .map_err(|e| InboundError::CommentFailed(e.to_string()))?;
+
+        Ok(user.filter(|u| u.is_active()).map(|u| u.id))
+    }
+
+    async fn anonymous_user_id(&self) -> Result<Option<Id>, InboundError> {
+        let user = self
+            .users
+            .find_anonymous()
+            .await
+            .map_err(|e| InboundError::CommentFailed(e.to_string()))?;
+
+        Ok(user.map(|u| u.id))
+    }
+}
+
+/// Comments stored as work package journals
+///
+/// Attachment records are created for the email's files; like uploads
+/// through the attachments endpoint, the file contents are stored separately.
+struct DbCommentSink {
+    pool: PgPool,
+}
+
+impl DbCommentSink {
+    fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn allowed_to_comment(&self, user_id: Id, project_id: Id) -> Result<bool, RepositoryError> {
+        let admin = UserRepository::new(self.pool.clone())
+            .find_by_id(user_id)
+            .await?
+            .is_some_and(|u| u.admin);
+
+        if admin {
+            return Ok(true);
+        }
+
+        MemberRepository::new(self.pool.clone())
+            .allowed_in_project(user_id, project_id, ADD_WORK_PACKAGE_NOTES)
+            .await
+    }
+}
+
+#[async_trait]
+impl CommentSink for DbCommentSink {
+    async fn create_comment(&self, comment: InboundComment) -> Result<Id, InboundError> {
+        let failed = |e: RepositoryError| InboundError::CommentFailed(e.to_string());
+
+        let work_package = WorkPackageRepository::new(self.pool.clone())
+            .find_by_id(comment.work_package_id)
+            .await
+            .map_err(failed)?
+            .ok_or_else(|| {
+                InboundError::Rejected(format!("work package {} not found", comment.work_package_id))
+            })?;
+
+        if !self
+            .allowed_to_comment(comment.user_id, work_package.project_id)
+            .await
+            .map_err(failed)?
+        {
+            return Err(InboundError::Rejected(
+                "sender may not comment on this work package".to_string(),
+            ));
+        }
+
+        let journal = JournalRepository::new(self.pool.clone())
+            .create_comment(
+                journable_type::WORK_PACKAGE,
+                work_package.id,
+                comment.user_id,
+                &comment.notes,
+            )
+            .await
+            .map_err(failed)?;
+
+        let attachments = AttachmentRepository::new(self.pool.clone());
+        for attachment in comment.attachments {
+            attachments
+                .create(CreateAttachmentDto {
+                    container_id: Some(work_package.id),
+                    container_type: Some(journable_type::WORK_PACKAGE.to_string()),
+                    filename: attachment.filename,
+                    disk_filename: None,
+                    filesize: attachment.data.len() as i64,
+                    content_type: attachment.content_type,
+                    digest: Some(format!("{:x}", md5::compute(&attachment.data))),
+                    author_id: comment.user_id,
+                    description: None,
+                    status: None,
+                })
+                .await
+                .map_err(failed)?;
+        }
+
+        Ok(journal.id)
+    }
+}
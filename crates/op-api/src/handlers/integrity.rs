@@ -0,0 +1,158 @@
+//! Integrity audit API handlers
+//!
+//! Administrators audit the references between records for rows left
+//! behind by deletions, and repair them in bounded batches. Repairs are dry
+//! runs unless explicitly asked otherwise.
+
+use axum::{extract::State, response::IntoResponse, Json};
+use op_core::representations::HalLink;
+use op_core::traits::Id;
+use op_db::{IntegrityOptions, IntegrityReport, IntegrityRepository, OrphanAction, OrphanCount};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, ClientInfo, HalResponse};
+
+/// Orphans repaired per transaction at most
+const MAX_BATCH_SIZE: i64 = 10_000;
+
+/// Audit the references between records (admin only)
+///
+/// GET /api/v3/integrity
+pub async fn get_integrity_report(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    client: ClientInfo,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(state
+            .deny(&user, &client, "get_integrity_report", "Only administrators can audit the database integrity.")
+            .await);
+    }
+
+    let report = IntegrityRepository::new(state.pool()?.clone())
+        .audit(IntegrityOptions::default())
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(HalResponse(IntegrityReportResponse::new(report)))
+}
+
+/// Repair orphaned rows, as a dry run unless `dryRun` is false (admin only)
+///
+/// POST /api/v3/integrity/repair
+pub async fn repair_integrity(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    client: ClientInfo,
+    Json(dto): Json<RepairIntegrityRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(state
+            .deny(&user, &client, "repair_integrity", "Only administrators can repair the database integrity.")
+            .await);
+    }
+
+    let mut options = IntegrityOptions {
+        repair: dto.dry_run == Some(false),
+        ..Default::default()
+    };
+    if let Some(batch_size) = dto.batch_size {
+        if !(1..=MAX_BATCH_SIZE).contains(&batch_size) {
+            return Err(ApiError::invalid_property(
+                "batchSize",
+                format!("must be between 1 and {}.", MAX_BATCH_SIZE),
+            ));
+        }
+        options.batch_size = batch_size;
+    }
+    if let Some(max_batches) = dto.max_batches {
+        if max_batches == 0 {
+            return Err(ApiError::invalid_property("maxBatches", "must be greater than 0."));
+        }
+        options.max_batches = max_batches;
+    }
+
+    let report = IntegrityRepository::new(state.pool()?.clone())
+        .audit(options)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if options.repair {
+        tracing::info!(user = user.0.id, repaired = report.repaired, "Repaired orphaned rows");
+    }
+
+    Ok(HalResponse(IntegrityReportResponse::new(report)))
+}
+
+// DTOs
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairIntegrityRequest {
+    /// Only report what would be repaired; true unless given as false
+    pub dry_run: Option<bool>,
+    pub batch_size: Option<i64>,
+    pub max_batches: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IntegrityReportResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    dry_run: bool,
+    orphans: i64,
+    repaired: u64,
+    checks: Vec<OrphanCountResponse>,
+    #[serde(rename = "_links")]
+    links: IntegrityLinks,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OrphanCountResponse {
+    check: String,
+    references: String,
+    action: OrphanAction,
+    orphans: i64,
+    sample_ids: Vec<Id>,
+    repaired: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct IntegrityLinks {
+    #[serde(rename = "self")]
+    self_link: HalLink,
+    repair: HalLink,
+}
+
+impl IntegrityReportResponse {
+    fn new(report: IntegrityReport) -> Self {
+        Self {
+            type_name: "IntegrityReport".into(),
+            dry_run: report.dry_run,
+            orphans: report.orphans,
+            repaired: report.repaired,
+            checks: report.checks.into_iter().map(OrphanCountResponse::from).collect(),
+            links: IntegrityLinks {
+                self_link: HalLink::new("/api/v3/integrity"),
+                repair: HalLink::post("/api/v3/integrity/repair"),
+            },
+        }
+    }
+}
+
+impl From<OrphanCount> for OrphanCountResponse {
+    fn from(count: OrphanCount) -> Self {
+        Self {
+            check: count.check,
+            references: count.references.into(),
+            action: count.action,
+            orphans: count.orphans,
+            sample_ids: count.sample_ids,
+            repaired: count.repaired,
+            error: count.error,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Job status API handlers
+//!
+//! Mirrors: lib/api/v3/job_statuses/*
+//!
+//! Long-running operations such as instantiating a project template run in
+//! the background; clients poll the job status until it settles.
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use op_db::ProjectRepository;
+use op_notifications::{Job, JobStatus};
+use op_services::projects::{InstantiateTemplateArgs, INSTANTIATE_TEMPLATE_JOB, JOB_USER_METADATA_KEY};
+use serde::Serialize;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+
+/// GET /api/v3/job_statuses/:id
+pub async fn get_job_status(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let job = state
+        .jobs
+        .get(&id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Queue error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("JobStatus", &id))?;
+
+    // Jobs are only visible to the user who started them
+    let owner = job.metadata.get(JOB_USER_METADATA_KEY);
+    if !user.0.is_admin() && owner != Some(&user.0.id().to_string()) {
+        return Err(ApiError::not_found("JobStatus", &id));
+    }
+
+    let mut response = JobStatusResponse::from_job(&job);
+
+    if job.status == JobStatus::Completed && job.job_type == INSTANTIATE_TEMPLATE_JOB {
+        response.links.project = created_project_link(&state, &job).await?;
+    }
+
+    Ok(HalResponse(response))
+}
+
+/// Link to the project created by a completed template job
+async fn created_project_link(state: &AppState, job: &Job) -> ApiResult<Option<Link>> {
+    let Ok(args) = serde_json::from_value::<InstantiateTemplateArgs>(job.args.clone()) else {
+        return Ok(None);
+    };
+
+    let pool = state.pool()?;
+    let project = ProjectRepository::new(pool.clone())
+        .find_by_identifier(&args.params.identifier)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(project.map(|p| Link {
+        href: format!("/api/v3/projects/{}", p.id),
+        title: Some(p.name),
+    }))
+}
+
+pub(crate) fn job_status_href(job_id: &str) -> String {
+    format!("/api/v3/job_statuses/{}", job_id)
+}
+
+/// Status names as used by OpenProject's job status API
+fn status_name(status: JobStatus) -> &'static str {
+    match status {
+        JobStatus::Pending | JobStatus::Retrying => "in_queue",
+        JobStatus::Running => "in_process",
+        JobStatus::Completed => "success",
+        JobStatus::Failed | JobStatus::Dead => "failure",
+    }
+}
+
+// DTOs
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JobStatusResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    job_id: String,
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// Summary the job reported on completion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<serde_json::Value>,
+    #[serde(rename = "_links")]
+    links: JobStatusLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct JobStatusLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project: Option<Link>,
+}
+
+#[derive(Debug, Serialize)]
+struct Link {
+    href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+}
+
+impl JobStatusResponse {
+    pub(crate) fn from_job(job: &Job) -> Self {
+        let message = match job.status {
+            JobStatus::Failed | JobStatus::Dead => job.error.clone(),
+            _ => None,
+        };
+
+        Self {
+            type_name: "JobStatus".into(),
+            job_id: job.id.clone(),
+            status: status_name(job.status).into(),
+            message,
+            payload: job.result.clone(),
+            links: JobStatusLinks {
+                self_link: Link {
+                    href: job_status_href(&job.id),
+                    title: None,
+                },
+                project: None,
+            },
+        }
+    }
+}
@@ -4,22 +4,28 @@
 
 use axum::{
     extract::{Path, Query, State},
+    http::StatusCode,
     response::IntoResponse,
     Json,
 };
+use op_contracts::base::UserContext;
+use op_contracts::work_packages::permissions::ADD_WORK_PACKAGE_NOTES;
+use op_core::events::DomainEvent;
 use op_core::traits::Id;
-use op_db::{JournalRepository, Repository};
+use op_db::{journable_type, JournalRepository, Repository};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::formatting::{DbReferenceResolver, MarkdownRenderer, ReferenceResolver, StoreReferenceResolver};
 
 /// List all activities/journals
 ///
 /// GET /api/v3/activities
 pub async fn list_activities(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     pagination: Pagination,
     Query(filters): Query<ActivityFilters>,
 ) -> ApiResult<impl IntoResponse> {
@@ -62,10 +68,7 @@ pub async fn list_activities(
         (journals, total)
     };
 
-    let elements: Vec<ActivityResponse> = journals
-        .into_iter()
-        .map(|j| ActivityResponse::from_journal(j))
-        .collect();
+    let elements = render_activities(journals, pool, &user).await?;
 
     let collection = ActivityCollection {
         type_name: "Collection".into(),
@@ -84,7 +87,7 @@ pub async fn list_activities(
 /// GET /api/v3/activities/:id
 pub async fn get_activity(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
@@ -96,7 +99,7 @@ pub async fn get_activity(
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("Activity", id))?;
 
-    Ok(HalResponse(ActivityResponse::from_journal(journal)))
+    Ok(HalResponse(render_activities(vec![journal], pool, &user).await?.remove(0)))
 }
 
 /// Update an activity/journal (update notes)
@@ -136,7 +139,7 @@ pub async fn update_activity(
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
-    Ok(HalResponse(ActivityResponse::from_journal(journal)))
+    Ok(HalResponse(render_activities(vec![journal], pool, &user).await?.remove(0)))
 }
 
 /// List activities for a work package
@@ -144,15 +147,15 @@ pub async fn update_activity(
 /// GET /api/v3/work_packages/:work_package_id/activities
 pub async fn list_work_package_activities(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     Path(work_package_id): Path<Id>,
     pagination: Pagination,
 ) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
-    let repo = JournalRepository::new(pool.clone());
-
-    let result = repo
-        .find_by_work_package(
+    let result = state
+        .entity_stores()?
+        .journals
+        .find_by_journable(
+            journable_type::WORK_PACKAGE,
             work_package_id,
             op_db::Pagination {
                 limit: pagination.page_size as i64,
@@ -162,11 +165,7 @@ pub async fn list_work_package_activities(
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    let elements: Vec<ActivityResponse> = result
-        .items
-        .into_iter()
-        .map(|j| ActivityResponse::from_journal(j))
-        .collect();
+    let elements = render_stored_activities(result.items, &state, &user).await?;
 
     let collection = ActivityCollection {
         type_name: "Collection".into(),
@@ -180,12 +179,54 @@ pub async fn list_work_package_activities(
     Ok(HalResponse(collection))
 }
 
+/// Comment on a work package
+///
+/// POST /api/v3/work_packages/:work_package_id/activities
+pub async fn create_work_package_comment(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(work_package_id): Path<Id>,
+    Json(dto): Json<CreateCommentRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if dto.comment.raw.trim().is_empty() {
+        return Err(ApiError::invalid_property("comment", "can't be blank"));
+    }
+
+    let stores = state.entity_stores()?;
+    let work_package = stores
+        .work_packages
+        .find_by_id(work_package_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", work_package_id))?;
+    if !user.allowed_in_project(ADD_WORK_PACKAGE_NOTES, work_package.project_id) {
+        return Err(ApiError::forbidden("You are not authorized to comment on this work package."));
+    }
+
+    let journal = stores
+        .journals
+        .create_comment(journable_type::WORK_PACKAGE, work_package.id, user.id(), &dto.comment.raw)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    state
+        .events
+        .publish(DomainEvent::CommentAdded {
+            work_package_id: work_package.id,
+            journal_id: journal.id,
+            author_id: user.id(),
+        })
+        .await;
+
+    let mut elements = render_stored_activities(vec![journal], &state, &user).await?;
+    Ok((StatusCode::CREATED, HalResponse(elements.remove(0))))
+}
+
 /// List revisions (changing journals) for a work package
 ///
 /// GET /api/v3/work_packages/:work_package_id/revisions
 pub async fn list_work_package_revisions(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     Path(work_package_id): Path<Id>,
     pagination: Pagination,
 ) -> ApiResult<impl IntoResponse> {
@@ -204,11 +245,7 @@ pub async fn list_work_package_revisions(
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    let elements: Vec<ActivityResponse> = result
-        .items
-        .into_iter()
-        .map(|j| ActivityResponse::from_journal(j))
-        .collect();
+    let elements = render_activities(result.items, pool, &user).await?;
 
     let collection = ActivityCollection {
         type_name: "Collection".into(),
@@ -222,6 +259,58 @@ pub async fn list_work_package_revisions(
     Ok(HalResponse(collection))
 }
 
+/// Activities of the journals, with the references in their comments
+/// resolved together for the user
+async fn render_activities(
+    journals: Vec<op_db::JournalRow>,
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+) -> ApiResult<Vec<ActivityResponse>> {
+    render_activities_with(journals, MarkdownRenderer::new(DbReferenceResolver::new(pool.clone(), &user.0))).await
+}
+
+/// Render activities, resolving references in the database or, without
+/// one, in the entity stores
+async fn render_stored_activities(
+    journals: Vec<op_db::JournalRow>,
+    state: &AppState,
+    user: &AuthenticatedUser,
+) -> ApiResult<Vec<ActivityResponse>> {
+    match &state.db {
+        Some(pool) => render_activities(journals, pool, user).await,
+        None => {
+            let resolver = StoreReferenceResolver::new(state.entity_stores()?.work_packages);
+            render_activities_with(journals, MarkdownRenderer::new(resolver)).await
+        }
+    }
+}
+
+async fn render_activities_with<R: ReferenceResolver>(
+    journals: Vec<op_db::JournalRow>,
+    renderer: MarkdownRenderer<R>,
+) -> ApiResult<Vec<ActivityResponse>> {
+    let comments: Vec<&str> = journals
+        .iter()
+        .filter_map(|j| j.notes.as_deref().filter(|n| !n.is_empty()))
+        .collect();
+    let mut rendered = renderer
+        .render_all(&comments)
+        .await?
+        .into_iter();
+
+    Ok(journals
+        .into_iter()
+        .map(|journal| {
+            let comment_html = journal
+                .notes
+                .as_deref()
+                .filter(|n| !n.is_empty())
+                .and_then(|_| rendered.next());
+            ActivityResponse::from_journal(journal, comment_html)
+        })
+        .collect())
+}
+
 // Query parameters
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -237,6 +326,16 @@ pub struct UpdateActivityRequest {
     pub comment: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateCommentRequest {
+    pub comment: CommentRequest,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    pub raw: String,
+}
+
 // Response types
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -299,16 +398,21 @@ struct Link {
 }
 
 impl ActivityResponse {
-    fn from_journal(journal: op_db::JournalRow) -> Self {
+    fn from_journal(journal: op_db::JournalRow, comment_html: Option<String>) -> Self {
         let id = journal.id;
         let user_id = journal.user_id;
         let journable_id = journal.journable_id;
 
-        let comment = journal.notes.as_ref().filter(|n| !n.is_empty()).map(|n| CommentResponse {
-            format: "markdown".into(),
-            raw: n.clone(),
-            html: format!("<p>{}</p>", n), // Simplified HTML rendering
-        });
+        let comment = journal
+            .notes
+            .as_ref()
+            .filter(|n| !n.is_empty())
+            .zip(comment_html)
+            .map(|(n, html)| CommentResponse {
+                format: "markdown".into(),
+                raw: n.clone(),
+                html,
+            });
 
         ActivityResponse {
             type_name: "Activity".into(),
@@ -0,0 +1,149 @@
+//! Maintenance mode API handlers
+//!
+//! Administrators enable the maintenance mode before migrations or storage
+//! moves, optionally with a message for clients and a scheduled end, and
+//! disable it afterwards. Every toggle is recorded in the audit trail.
+
+use axum::{extract::State, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use op_core::audit::{AuditEvent, AuditEventType};
+use op_core::representations::Link;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, ClientInfo, HalResponse};
+use crate::maintenance::{MaintenanceState, MAINTENANCE_PATH, MAINTENANCE_SETTING};
+
+/// Show the maintenance mode (admin only)
+///
+/// GET /api/v3/maintenance
+pub async fn get_maintenance(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    client: ClientInfo,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(state
+            .deny(&user, &client, "get_maintenance", "Only administrators can view the maintenance mode.")
+            .await);
+    }
+
+    Ok(HalResponse(MaintenanceResponse::new(state.maintenance.current())))
+}
+
+/// Enable the maintenance mode, until the scheduled end if one is given
+/// (admin only)
+///
+/// POST /api/v3/maintenance
+pub async fn enable_maintenance(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    client: ClientInfo,
+    Json(dto): Json<EnableMaintenanceRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(state
+            .deny(&user, &client, "enable_maintenance", "Only administrators can enable the maintenance mode.")
+            .await);
+    }
+    if dto.ends_at.is_some_and(|ends_at| ends_at <= Utc::now()) {
+        return Err(ApiError::invalid_property("ends_at", "must be in the future."));
+    }
+
+    let message = dto.message.filter(|message| !message.trim().is_empty());
+    let maintenance = MaintenanceState::enabled(message, dto.ends_at);
+    toggle(&state, &user, &client, maintenance.clone()).await?;
+
+    Ok(HalResponse(MaintenanceResponse::new(maintenance)))
+}
+
+/// Disable the maintenance mode (admin only)
+///
+/// DELETE /api/v3/maintenance
+pub async fn disable_maintenance(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    client: ClientInfo,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(state
+            .deny(&user, &client, "disable_maintenance", "Only administrators can disable the maintenance mode.")
+            .await);
+    }
+
+    toggle(&state, &user, &client, MaintenanceState::default()).await?;
+
+    Ok(HalResponse(MaintenanceResponse::new(MaintenanceState::default())))
+}
+
+/// Store the state and record the toggle
+async fn toggle(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    client: &ClientInfo,
+    maintenance: MaintenanceState,
+) -> ApiResult<()> {
+    state
+        .maintenance
+        .set(maintenance.clone())
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let mut event = AuditEvent::new(AuditEventType::SettingsChanged)
+        .detail("setting", MAINTENANCE_SETTING)
+        .detail("enabled", maintenance.enabled);
+    if let Some(ends_at) = maintenance.ends_at {
+        event = event.detail("ends_at", ends_at.to_rfc3339());
+    }
+    if let Some(message) = maintenance.message {
+        event = event.detail("message", message);
+    }
+    state.audit(user, client, event).await;
+
+    Ok(())
+}
+
+// DTOs
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnableMaintenanceRequest {
+    /// Shown to clients whose requests are blocked
+    pub message: Option<String>,
+    /// Scheduled end, until further notice when omitted
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MaintenanceResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    enabled: bool,
+    /// False once the scheduled end has passed
+    active: bool,
+    message: Option<String>,
+    ends_at: Option<DateTime<Utc>>,
+    #[serde(rename = "_links")]
+    links: MaintenanceLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct MaintenanceLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+}
+
+impl MaintenanceResponse {
+    fn new(maintenance: MaintenanceState) -> Self {
+        Self {
+            type_name: "MaintenanceMode".into(),
+            active: maintenance.is_active_at(Utc::now()),
+            enabled: maintenance.enabled,
+            message: maintenance.message,
+            ends_at: maintenance.ends_at,
+            links: MaintenanceLinks {
+                self_link: Link::new(MAINTENANCE_PATH),
+            },
+        }
+    }
+}
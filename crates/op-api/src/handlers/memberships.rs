@@ -8,69 +8,59 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use chrono::NaiveTime;
+use op_core::audit::{AuditEvent, AuditEventType};
+use op_core::events::DomainEvent;
 use op_core::traits::Id;
-use op_db::{MemberRepository, Repository};
+use op_db::{principal_type, MemberListRow, MemberOrder, MemberQuery, MemberRepository, MemberRow, Repository};
+use op_queries::{Filter, FilterOperator, SortDirection};
+use op_services::revoked_access::CleanupRevokedAccessArgs;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{ApiError, ApiResult};
-use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
-
-/// List all memberships
+use crate::extractors::{AppState, AuthenticatedUser, ClientInfo, HalResponse, Pagination};
+use crate::filters::{filter_date_range, filter_ids, parse_filters, parse_sort_by};
+use crate::representers::{CollectionQuery, HalCollection, HalLink, PrincipalRepresenter, PrincipalType};
+
+/// List project and global memberships, filtered by `principal`,
+/// `project`, `principalType`, `role` and `createdAt` and sorted by `id`,
+/// `name` or `created_at`. Administrators see every membership, other users
+/// those of projects they have `view_members` or `manage_members` in.
 ///
 /// GET /api/v3/memberships
 pub async fn list_memberships(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     pagination: Pagination,
-    Query(filters): Query<MembershipFilters>,
+    collection_query: CollectionQuery,
+    Query(params): Query<MembershipFilters>,
 ) -> ApiResult<impl IntoResponse> {
+    let mut query = params.to_query(user.id())?;
+    query.visible_to = (!user.0.is_admin()).then(|| user.id());
+
     let pool = state.pool()?;
     let repo = MemberRepository::new(pool.clone());
 
-    let (members, total) = if let Some(project_id) = filters.project_id {
-        let result = repo
-            .find_by_project(
-                project_id,
-                op_db::Pagination {
-                    limit: pagination.page_size as i64,
-                    offset: pagination.offset as i64,
-                },
-            )
-            .await
-            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-        (result.items, result.total)
-    } else if let Some(user_id) = filters.principal_id {
-        let members = repo
-            .find_by_user(user_id)
-            .await
-            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-        let total = members.len() as i64;
-        (members, total)
-    } else {
-        let result = repo
-            .find_all_with_roles(op_db::Pagination {
+    let result = repo
+        .list(
+            &query,
+            op_db::Pagination {
                 limit: pagination.page_size as i64,
                 offset: pagination.offset as i64,
-            })
-            .await
-            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-        (result.items, result.total)
-    };
+            },
+        )
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    let elements: Vec<MembershipResponse> = members
-        .into_iter()
-        .map(|m| MembershipResponse::from_member_with_roles(m))
-        .collect();
-
-    let collection = MembershipCollection {
-        type_name: "Collection".into(),
-        total: total as usize,
-        count: elements.len(),
-        page_size: pagination.page_size,
-        offset: pagination.offset,
+    let elements = result.items.into_iter().map(MembershipResponse::from_row).collect();
+    let collection = HalCollection::new(
+        "Collection",
         elements,
-    };
-
+        result.total,
+        pagination.page_size as i64,
+        pagination.offset as i64,
+    )
+    .with_pagination_links(&collection_query);
     Ok(HalResponse(collection))
 }
 
@@ -85,13 +75,13 @@ pub async fn get_membership(
     let pool = state.pool()?;
     let repo = MemberRepository::new(pool.clone());
 
-    let member_with_roles = repo
-        .find_by_id_with_roles(id)
+    let row = repo
+        .find_listed(id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("Membership", id))?;
 
-    Ok(HalResponse(MembershipResponse::from_member_with_roles(member_with_roles)))
+    Ok(HalResponse(MembershipResponse::from_row(row)))
 }
 
 /// Create a new membership
@@ -100,11 +90,12 @@ pub async fn get_membership(
 pub async fn create_membership(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Json(dto): Json<CreateMembershipRequest>,
 ) -> ApiResult<impl IntoResponse> {
     // Only admins can create memberships
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can create memberships."));
+        return Err(state.deny(&user, &client, "create_membership", "Only administrators can create memberships.").await);
     }
 
     let pool = state.pool()?;
@@ -122,19 +113,30 @@ pub async fn create_membership(
         .create(create_dto)
         .await
         .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
-    // Get the member with roles
-    let member_with_roles = repo
-        .find_by_id_with_roles(member.id)
+    // Get the member with its principal and roles
+    let row = repo
+        .find_listed(member.id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::internal("Failed to retrieve created membership".to_string()))?;
 
-    Ok((StatusCode::CREATED, HalResponse(MembershipResponse::from_member_with_roles(member_with_roles))))
+    state
+        .audit(
+            &user,
+            &client,
+            AuditEvent::new(AuditEventType::MembershipChanged)
+                .target("Membership", member.id)
+                .detail("change", "created"),
+        )
+        .await;
+    publish_membership_changed(&state, &user, &member).await;
+
+    Ok((StatusCode::CREATED, HalResponse(MembershipResponse::from_row(row))))
 }
 
 /// Update a membership
@@ -143,11 +145,12 @@ pub async fn create_membership(
 pub async fn update_membership(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
     Json(dto): Json<UpdateMembershipRequest>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can update memberships."));
+        return Err(state.deny(&user, &client, "update_membership", "Only administrators can update memberships.").await);
     }
 
     let pool = state.pool()?;
@@ -162,19 +165,42 @@ pub async fn update_membership(
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Membership", id),
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
-    // Get the member with roles
-    let member_with_roles = repo
-        .find_by_id_with_roles(member.id)
+    // Get the member with its principal and roles
+    let row = repo
+        .find_listed(member.id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::internal("Failed to retrieve updated membership".to_string()))?;
 
-    Ok(HalResponse(MembershipResponse::from_member_with_roles(member_with_roles)))
+    // Roles no longer granting to view work packages leave watchers and
+    // notifications behind
+    if let Some(project_id) = member.project_id {
+        let can_view = repo
+            .allowed_in_project(member.user_id, project_id, "view_work_packages")
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+        if !can_view {
+            cleanup_revoked_access(&state, &user, &member).await?;
+        }
+    }
+
+    state
+        .audit(
+            &user,
+            &client,
+            AuditEvent::new(AuditEventType::MembershipChanged)
+                .target("Membership", id)
+                .detail("change", "updated"),
+        )
+        .await;
+    publish_membership_changed(&state, &user, &member).await;
+
+    Ok(HalResponse(MembershipResponse::from_row(row)))
 }
 
 /// Delete a membership
@@ -183,15 +209,22 @@ pub async fn update_membership(
 pub async fn delete_membership(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can delete memberships."));
+        return Err(state.deny(&user, &client, "delete_membership", "Only administrators can delete memberships.").await);
     }
 
     let pool = state.pool()?;
     let repo = MemberRepository::new(pool.clone());
 
+    let member = repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Membership", id))?;
+
     repo.delete(id)
         .await
         .map_err(|e| match e {
@@ -200,15 +233,139 @@ pub async fn delete_membership(
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
+    state
+        .audit(
+            &user,
+            &client,
+            AuditEvent::new(AuditEventType::MembershipChanged)
+                .target("Membership", id)
+                .detail("change", "deleted"),
+        )
+        .await;
+    publish_membership_changed(&state, &user, &member).await;
+
+    cleanup_revoked_access(&state, &user, &member).await?;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Let the subscribers know about the change of a project membership;
+/// global memberships are not published
+async fn publish_membership_changed(state: &AppState, user: &AuthenticatedUser, member: &MemberRow) {
+    if let Some(project_id) = member.project_id {
+        state
+            .events
+            .publish(DomainEvent::MembershipChanged {
+                project_id,
+                user_id: member.user_id,
+                actor_id: user.id(),
+            })
+            .await;
+    }
+}
+
+/// Remove in the background the watchers and notifications the member's
+/// user keeps on work packages of the project they can no longer see
+async fn cleanup_revoked_access(state: &AppState, user: &AuthenticatedUser, member: &MemberRow) -> ApiResult<()> {
+    let Some(project_id) = member.project_id else {
+        return Ok(());
+    };
+
+    let job = CleanupRevokedAccessArgs::users(project_id, [member.user_id]).into_job(user.id());
+    state
+        .jobs
+        .enqueue(job)
+        .await
+        .map_err(|e| ApiError::internal(format!("Queue error: {}", e)))?;
+    Ok(())
+}
+
 // Query parameters
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MembershipFilters {
     pub project_id: Option<i64>,
     pub principal_id: Option<i64>,
+    /// JSON-encoded API v3 filter list
+    pub filters: Option<String>,
+    /// JSON-encoded API v3 sort criteria
+    pub sort_by: Option<String>,
+}
+
+impl MembershipFilters {
+    /// Memberships to list; `me` in id filters stands for `current_user`
+    fn to_query(&self, current_user: Id) -> ApiResult<MemberQuery> {
+        let mut query = MemberQuery {
+            project_ids: self.project_id.map(|id| vec![id]),
+            principal_ids: self.principal_id.map(|id| vec![id]),
+            ..Default::default()
+        };
+
+        for filter in parse_filters(self.filters.as_deref())? {
+            let attribute = filter.attribute.as_str();
+            if attribute == "createdAt" {
+                if filter.operator != FilterOperator::Between {
+                    return Err(unsupported_operator(&filter));
+                }
+                let (from, to) = filter_date_range(&filter)?;
+                query.created_from = from.map(|day| day.and_time(NaiveTime::MIN).and_utc());
+                query.created_until = to
+                    .and_then(|day| day.succ_opt())
+                    .map(|day| day.and_time(NaiveTime::MIN).and_utc());
+                continue;
+            }
+
+            if filter.operator != FilterOperator::Equals {
+                return Err(unsupported_operator(&filter));
+            }
+            match attribute {
+                "principal" => query.principal_ids = Some(filter_ids(&filter, current_user)?),
+                "project" => query.project_ids = Some(filter_ids(&filter, current_user)?),
+                "role" => query.role_ids = Some(filter_ids(&filter, current_user)?),
+                "principalType" => {
+                    let types = filter.values.as_strings();
+                    if let Some(unknown) = types.iter().find(|t| !principal_type::PRINCIPALS.contains(&t.as_str())) {
+                        return Err(ApiError::invalid_property(
+                            "principalType",
+                            format!("'{}' is not a principal type", unknown),
+                        ));
+                    }
+                    query.principal_types = Some(types);
+                }
+                name => {
+                    return Err(ApiError::invalid_property(
+                        "filters",
+                        format!("filter '{}' is not supported", name),
+                    ))
+                }
+            }
+        }
+
+        // Ties, and every criterion after the first, are ordered by id
+        if let Some(sort) = parse_sort_by(self.sort_by.as_deref())?.first() {
+            query.order = match sort.attribute.as_str() {
+                "id" => MemberOrder::Id,
+                "name" => MemberOrder::Name,
+                "created_at" | "createdAt" => MemberOrder::CreatedAt,
+                attribute => {
+                    return Err(ApiError::invalid_property(
+                        "sortBy",
+                        format!("sorting by '{}' is not supported", attribute),
+                    ))
+                }
+            };
+            query.descending = sort.direction == SortDirection::Desc;
+        }
+
+        Ok(query)
+    }
+}
+
+fn unsupported_operator(filter: &Filter) -> ApiError {
+    ApiError::invalid_property(
+        &filter.attribute,
+        format!("filter does not support operator '{}'", filter.operator.to_string()),
+    )
 }
 
 // Request types
@@ -227,19 +384,6 @@ pub struct UpdateMembershipRequest {
 }
 
 // Response types
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct MembershipCollection {
-    #[serde(rename = "_type")]
-    type_name: String,
-    total: usize,
-    count: usize,
-    page_size: usize,
-    offset: usize,
-    #[serde(rename = "_embedded")]
-    elements: Vec<MembershipResponse>,
-}
-
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct MembershipResponse {
@@ -256,51 +400,124 @@ struct MembershipResponse {
 #[serde(rename_all = "camelCase")]
 struct MembershipLinks {
     #[serde(rename = "self")]
-    self_link: Link,
-    principal: Link,
+    self_link: HalLink,
+    schema: HalLink,
+    principal: HalLink,
     #[serde(skip_serializing_if = "Option::is_none")]
-    project: Option<Link>,
-    roles: Vec<Link>,
-}
-
-#[derive(Debug, Serialize)]
-struct Link {
-    href: String,
+    project: Option<HalLink>,
+    roles: Vec<HalLink>,
 }
 
 impl MembershipResponse {
-    fn from_member_with_roles(member_with_roles: op_db::MemberWithRoles) -> Self {
-        let member = member_with_roles.member;
-        let role_ids = member_with_roles.role_ids;
-        let id = member.id;
-        let user_id = member.user_id;
-
-        let project_link = member.project_id.map(|pid| Link {
-            href: format!("/api/v3/projects/{}", pid),
+    fn from_row(row: MemberListRow) -> Self {
+        let principal_type = PrincipalType::from_discriminator(&row.principal_type);
+
+        let project_link = row.project_id.map(|pid| {
+            let href = format!("/api/v3/projects/{}", pid);
+            match row.project_name {
+                Some(name) => HalLink::with_title(href, name),
+                None => HalLink::new(href),
+            }
         });
 
-        let role_links: Vec<Link> = role_ids
+        let role_links = row
+            .role_ids
             .iter()
-            .map(|rid| Link {
-                href: format!("/api/v3/roles/{}", rid),
-            })
+            .zip(&row.role_names)
+            .map(|(rid, name)| HalLink::with_title(format!("/api/v3/roles/{}", rid), name))
             .collect();
 
         MembershipResponse {
             type_name: "Membership".into(),
-            id,
-            created_at: member.created_at.to_rfc3339(),
-            updated_at: member.updated_at.to_rfc3339(),
+            id: row.id,
+            created_at: row.created_at.to_rfc3339(),
+            updated_at: row.updated_at.to_rfc3339(),
             links: MembershipLinks {
-                self_link: Link {
-                    href: format!("/api/v3/memberships/{}", id),
-                },
-                principal: Link {
-                    href: format!("/api/v3/users/{}", user_id),
-                },
+                self_link: HalLink::with_title(format!("/api/v3/memberships/{}", row.id), &row.principal_name),
+                schema: HalLink::new("/api/v3/memberships/schema"),
+                principal: PrincipalRepresenter::link(principal_type, row.user_id, &row.principal_name),
                 project: project_link,
                 roles: role_links,
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn filters(filters: &str, sort_by: Option<&str>) -> MembershipFilters {
+        MembershipFilters {
+            project_id: None,
+            principal_id: None,
+            filters: Some(filters.into()),
+            sort_by: sort_by.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn test_filters_build_member_query() {
+        let query = filters(
+            r#"[{"principal":{"operator":"=","values":["me","7"]}},
+                {"project":{"operator":"=","values":["3"]}},
+                {"principalType":{"operator":"=","values":["Group","PlaceholderUser"]}},
+                {"role":{"operator":"=","values":["4"]}},
+                {"createdAt":{"operator":"<>d","values":["2024-01-01","2024-01-31"]}}]"#,
+            Some(r#"[["name","desc"]]"#),
+        )
+        .to_query(1)
+        .unwrap();
+
+        assert_eq!(query.principal_ids, Some(vec![1, 7]));
+        assert_eq!(query.project_ids, Some(vec![3]));
+        assert_eq!(query.principal_types, Some(vec!["Group".into(), "PlaceholderUser".into()]));
+        assert_eq!(query.role_ids, Some(vec![4]));
+        assert_eq!(query.created_from, Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()));
+        assert_eq!(query.created_until, Some(Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap()));
+        assert_eq!(query.order, MemberOrder::Name);
+        assert!(query.descending);
+        assert_eq!(query.visible_to, None);
+    }
+
+    #[test]
+    fn test_unsupported_filters_are_rejected() {
+        for invalid in [
+            r#"[{"principalType":{"operator":"=","values":["AnonymousUser"]}}]"#,
+            r#"[{"principal":{"operator":"!","values":["5"]}}]"#,
+            r#"[{"createdAt":{"operator":"=","values":["2024-01-01"]}}]"#,
+            r#"[{"status":{"operator":"=","values":["1"]}}]"#,
+        ] {
+            assert!(matches!(filters(invalid, None).to_query(1), Err(ApiError::Validation(_))), "{}", invalid);
+        }
+        assert!(filters("[]", Some(r#"[["email","asc"]]"#)).to_query(1).is_err());
+    }
+
+    #[test]
+    fn test_membership_json_shape() {
+        let row = MemberListRow {
+            id: 12,
+            user_id: 5,
+            project_id: Some(3),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            principal_type: principal_type::GROUP.into(),
+            principal_name: "Developers".into(),
+            project_name: Some("Demo project".into()),
+            role_ids: vec![4, 6],
+            role_names: vec!["Member".into(), "Reader".into()],
+        };
+        let json = serde_json::to_value(MembershipResponse::from_row(row)).unwrap();
+
+        assert_eq!(json["_type"], "Membership");
+        assert_eq!(json["id"], 12);
+        assert!(json["createdAt"].is_string());
+        let links = &json["_links"];
+        assert_eq!(links["self"], serde_json::json!({ "href": "/api/v3/memberships/12", "title": "Developers" }));
+        assert_eq!(links["schema"]["href"], "/api/v3/memberships/schema");
+        assert_eq!(links["principal"], serde_json::json!({ "href": "/api/v3/groups/5", "title": "Developers" }));
+        assert_eq!(links["project"], serde_json::json!({ "href": "/api/v3/projects/3", "title": "Demo project" }));
+        assert_eq!(links["roles"][1], serde_json::json!({ "href": "/api/v3/roles/6", "title": "Reader" }));
+    }
+}
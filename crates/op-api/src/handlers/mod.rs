@@ -17,8 +17,25 @@ pub mod queries;
 pub mod time_entries;
 pub mod relations;
 pub mod watchers;
+pub mod shares;
 pub mod attachments;
+pub mod uploads;
 pub mod journals;
+pub mod audit_events;
+pub mod maintenance;
+pub mod integrity;
+pub mod job_statuses;
+pub mod notifications;
+pub mod notification_settings;
+pub mod inbound_emails;
+pub mod principals;
+pub mod storages;
+pub mod file_links;
+pub mod forums;
+pub mod documents;
+pub mod api_keys;
+pub mod custom_actions;
+pub mod reports;
 
 pub use work_packages::*;
 pub use projects::*;
@@ -35,5 +52,8 @@ pub use queries::*;
 pub use time_entries::*;
 pub use relations::*;
 pub use watchers::*;
+pub use shares::*;
 pub use attachments::*;
+pub use uploads::*;
 pub use journals::*;
+pub use audit_events::*;
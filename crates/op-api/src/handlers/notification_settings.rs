@@ -0,0 +1,280 @@
+//! Notification settings API handlers
+//!
+//! Mirrors: lib/api/v3/users/notification_settings (the `notifications`
+//! of the user preferences)
+//!
+//! Settings are read and changed by the user or an administrator. A PATCH
+//! is validated as a whole and then written in one store update, so a
+//! rejected request changes nothing.
+
+use std::collections::{BTreeMap, HashSet};
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use op_core::error::ValidationErrors;
+use op_core::representations::Link;
+use op_core::traits::Id;
+use op_db::{MemberRepository, ProjectRepository, Repository, UserRepository};
+use op_notifications::{DateAlerts, EmailFrequency, NotificationSettings, ProjectNotificationSettings, ReasonSettings};
+use serde::Deserialize;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+use crate::representers::notification::{parse_days_duration, project_href};
+use crate::representers::NotificationRepresenter;
+
+/// Get a user's notification settings
+///
+/// GET /api/v3/users/:id/notification_settings
+pub async fn get_notification_settings(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    ensure_self_or_admin(&user, id)?;
+
+    let settings = state
+        .notifications
+        .get_settings(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?;
+
+    Ok(HalResponse(NotificationRepresenter::represent_settings(settings)))
+}
+
+/// Change a user's notification settings
+///
+/// PATCH /api/v3/users/:id/notification_settings
+///
+/// Omitted properties are kept. `projectOverrides` replaces the overrides;
+/// each override starts from the project's current one, or from the
+/// global settings for projects without one.
+pub async fn update_notification_settings(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(request): Json<UpdateNotificationSettingsRequest>,
+) -> ApiResult<impl IntoResponse> {
+    ensure_self_or_admin(&user, id)?;
+
+    let mut settings = state
+        .notifications
+        .get_settings(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?;
+
+    let mut errors = ValidationErrors::new();
+    request.apply(&mut settings, &mut errors);
+    if !errors.is_empty() {
+        return Err(ApiError::validation(errors));
+    }
+
+    if !settings.project_overrides.is_empty() {
+        let project_ids: Vec<Id> = settings.project_overrides.iter().map(|o| o.project_id).collect();
+        let visible = visible_projects(&state, &user, id, &project_ids).await?;
+        for project_id in project_ids.iter().filter(|id| !visible.contains(id)) {
+            errors.add(
+                "project_overrides",
+                format!("references project {} which does not exist.", project_href(*project_id)),
+            );
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::validation(errors));
+        }
+    }
+
+    state
+        .notifications
+        .update_settings(&settings)
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?;
+
+    Ok(HalResponse(NotificationRepresenter::represent_settings(settings)))
+}
+
+fn ensure_self_or_admin(user: &AuthenticatedUser, id: Id) -> ApiResult<()> {
+    if user.0.id() != id && !user.0.is_admin() {
+        return Err(ApiError::forbidden("You are not authorized to access this resource."));
+    }
+    Ok(())
+}
+
+/// The projects among `project_ids` the settings' owner can see: active
+/// projects that are public or that they are a member of, and every active
+/// project for administrators. Invisible projects are reported as missing.
+async fn visible_projects(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    owner_id: Id,
+    project_ids: &[Id],
+) -> ApiResult<HashSet<Id>> {
+    let pool = state.pool()?;
+
+    let owner_is_admin = if owner_id == user.0.id() {
+        user.0.is_admin()
+    } else {
+        UserRepository::new(pool.clone())
+            .find_by_id(owner_id)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+            .ok_or_else(|| ApiError::not_found("User", owner_id))?
+            .admin
+    };
+
+    let projects = ProjectRepository::new(pool.clone())
+        .find_by_ids(project_ids)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let members = MemberRepository::new(pool.clone());
+
+    let mut visible = HashSet::new();
+    for project in projects.into_iter().filter(|p| p.active) {
+        let member = owner_is_admin
+            || project.public
+            || members
+                .membership_exists(owner_id, Some(project.id), None, None)
+                .await
+                .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+        if member {
+            visible.insert(project.id);
+        }
+    }
+
+    Ok(visible)
+}
+
+// Request types
+
+/// Changed settings; reasons and date alerts are keyed as in the response
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateNotificationSettingsRequest {
+    pub in_app: Option<BTreeMap<String, bool>>,
+    pub email: Option<BTreeMap<String, bool>>,
+    pub email_frequency: Option<String>,
+    pub date_alerts: Option<BTreeMap<String, Option<String>>>,
+    pub project_overrides: Option<Vec<ProjectOverrideRequest>>,
+}
+
+/// Settings of the linked project
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectOverrideRequest {
+    #[serde(rename = "_links")]
+    pub links: ProjectOverrideLinks,
+    pub in_app: Option<BTreeMap<String, bool>>,
+    pub email: Option<BTreeMap<String, bool>>,
+    pub date_alerts: Option<BTreeMap<String, Option<String>>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectOverrideLinks {
+    pub project: Link,
+}
+
+impl UpdateNotificationSettingsRequest {
+    /// Apply the changes, adding an error per invalid property
+    fn apply(&self, settings: &mut NotificationSettings, errors: &mut ValidationErrors) {
+        if let Some(changes) = &self.in_app {
+            apply_reasons(&mut settings.in_app_reasons, changes, "in_app", errors);
+        }
+        if let Some(changes) = &self.email {
+            apply_reasons(&mut settings.email_reasons, changes, "email", errors);
+        }
+        if let Some(frequency) = &self.email_frequency {
+            match serde_json::from_value::<EmailFrequency>(serde_json::Value::String(frequency.clone())) {
+                Ok(frequency) => settings.email_frequency = frequency,
+                Err(_) => errors.add("email_frequency", "must be immediate, daily, weekly or never."),
+            }
+        }
+        if let Some(changes) = &self.date_alerts {
+            apply_date_alerts(&mut settings.date_alerts, changes, "date_alerts", errors);
+        }
+
+        if let Some(overrides) = &self.project_overrides {
+            let mut replaced = Vec::with_capacity(overrides.len());
+            for request in overrides {
+                let Some(project_id) = parse_project_href(&request.links.project.href) else {
+                    errors.add(
+                        "project_overrides",
+                        format!("links '{}' which is not a project.", request.links.project.href),
+                    );
+                    continue;
+                };
+                if replaced.iter().any(|o: &ProjectNotificationSettings| o.project_id == project_id) {
+                    errors.add(
+                        "project_overrides",
+                        format!("lists project {} more than once.", project_href(project_id)),
+                    );
+                    continue;
+                }
+
+                let mut project = settings
+                    .project_override(Some(project_id))
+                    .cloned()
+                    .unwrap_or_else(|| ProjectNotificationSettings::inherit(project_id, settings));
+                if let Some(changes) = &request.in_app {
+                    apply_reasons(&mut project.in_app, changes, "project_overrides", errors);
+                }
+                if let Some(changes) = &request.email {
+                    apply_reasons(&mut project.email, changes, "project_overrides", errors);
+                }
+                if let Some(changes) = &request.date_alerts {
+                    apply_date_alerts(&mut project.date_alerts, changes, "project_overrides", errors);
+                }
+                replaced.push(project);
+            }
+            settings.project_overrides = replaced;
+        }
+    }
+}
+
+fn apply_reasons(
+    reasons: &mut ReasonSettings,
+    changes: &BTreeMap<String, bool>,
+    property: &str,
+    errors: &mut ValidationErrors,
+) {
+    for (key, enabled) in changes {
+        match reasons.flag_mut(key) {
+            Some(flag) => *flag = *enabled,
+            None => errors.add(property, format!("has no reason '{}'.", key)),
+        }
+    }
+}
+
+fn apply_date_alerts(
+    alerts: &mut DateAlerts,
+    changes: &BTreeMap<String, Option<String>>,
+    property: &str,
+    errors: &mut ValidationErrors,
+) {
+    for (key, duration) in changes {
+        let (alert, allowed) = match key.as_str() {
+            "startDate" => (&mut alerts.start_date, &DateAlerts::LEAD_DAYS[..]),
+            "dueDate" => (&mut alerts.due_date, &DateAlerts::LEAD_DAYS[..]),
+            "overdue" => (&mut alerts.overdue, &DateAlerts::OVERDUE_DAYS[..]),
+            _ => {
+                errors.add(property, format!("has no date alert '{}'.", key));
+                continue;
+            }
+        };
+        match duration.as_deref().map(parse_days_duration) {
+            None => *alert = None,
+            Some(Some(days)) if allowed.contains(&days) => *alert = Some(days),
+            Some(_) => errors.add(
+                property,
+                format!("has an unsupported {} alert '{}'.", key, duration.as_deref().unwrap_or_default()),
+            ),
+        }
+    }
+}
+
+/// Project id of a project href, e.g. `/api/v3/projects/3`
+fn parse_project_href(href: &str) -> Option<Id> {
+    href.strip_prefix("/api/v3/projects/")?.parse().ok()
+}
+
@@ -0,0 +1,266 @@
+//! Notification API handlers
+//!
+//! Mirrors: lib/api/v3/notifications/*
+//!
+//! The notification center groups notifications by resource. Groups are
+//! listed without their notifications; clients fetch a group's details
+//! when it is expanded. Notifications embed the condensed work package they
+//! are about and, selected with `embed`, who triggered them and in which
+//! project; the group list embeds both unless told otherwise, a group's
+//! details only on request. Clients subscribe to a stream of notification
+//! events to keep the unread count current without polling.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+};
+use futures::stream::{self, Stream};
+use op_core::traits::Id;
+use op_db::{PgIncludeLoader, WorkPackageRepository};
+use op_notifications::{Notification, NotificationGroup, Received, StreamEvent, StreamEventKind, Subscription};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+use crate::handlers::work_packages::record_view;
+use crate::representers::{CondensedWorkPackages, NotificationEmbed, NotificationEmbeds, NotificationRepresenter};
+use crate::visibility::UserVisibility;
+
+/// List the current user's notifications grouped by resource
+///
+/// GET /api/v3/notifications/groups
+pub async fn list_notification_groups(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Query(filters): Query<NotificationGroupFilters>,
+) -> ApiResult<impl IntoResponse> {
+    let embed = filters.embed(NotificationEmbed::ALL)?;
+    let groups = state
+        .notifications
+        .get_for_user_grouped(user.0.id(), filters.unread_only.unwrap_or(false))
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?;
+
+    let newest: Vec<&Notification> = groups.iter().map(|group| &group.newest).collect();
+    let embeds = notification_embeds(&state, &user, &newest, embed).await?;
+    Ok(HalResponse(NotificationRepresenter::represent_groups(groups, &embeds)))
+}
+
+/// Get one group with its notifications embedded as details
+///
+/// GET /api/v3/notifications/groups/:resource_type/:resource_id
+pub async fn get_notification_group(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((resource_type, resource_id)): Path<(String, Id)>,
+    Query(filters): Query<NotificationGroupFilters>,
+) -> ApiResult<impl IntoResponse> {
+    let unread_only = filters.unread_only.unwrap_or(false);
+    let embed = filters.embed(NotificationEmbed::default())?;
+    let details = state
+        .notifications
+        .get_group(user.0.id(), &resource_type, resource_id, unread_only)
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?;
+
+    let group = NotificationGroup::group(details.clone())
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::not_found("NotificationGroup", format!("{}/{}", resource_type, resource_id)))?;
+
+    let embeds = notification_embeds(&state, &user, &details.iter().collect::<Vec<_>>(), embed).await?;
+    Ok(HalResponse(NotificationRepresenter::represent_group(group, Some(details), &embeds)))
+}
+
+/// Work packages the notifications are about, condensed and loaded in one
+/// query, and their actors and projects requested by `embed`, one query
+/// each. Without a database the notifications are shown without them.
+async fn notification_embeds(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    notifications: &[&Notification],
+    embed: NotificationEmbed,
+) -> ApiResult<NotificationEmbeds> {
+    let Some(pool) = state.db.as_ref() else {
+        return Ok(NotificationEmbeds::default());
+    };
+
+    let ids = notifications
+        .iter()
+        .filter(|notification| notification.resource_type == "WorkPackage")
+        .map(|notification| notification.resource_id);
+    let visible_to = (!user.0.is_admin()).then(|| user.0.id());
+    let resources = CondensedWorkPackages::load(&WorkPackageRepository::new(pool.clone()), ids, visible_to)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let embeds = NotificationEmbeds::new(resources);
+    if embed.is_empty() {
+        return Ok(embeds);
+    }
+
+    let actor_ids: Vec<Id> = notifications
+        .iter()
+        .filter_map(|notification| notification.actor_id.filter(|_| embed.actor))
+        .collect();
+    let visibility = UserVisibility::load(state, user, &actor_ids).await?;
+    embeds
+        .with_related(&PgIncludeLoader::new(pool.clone()), notifications.iter().copied(), embed, &visibility)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))
+}
+
+/// Mark all notifications of a group as read
+///
+/// POST /api/v3/notifications/groups/:resource_type/:resource_id/read_ian
+pub async fn read_notification_group(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((resource_type, resource_id)): Path<(String, Id)>,
+) -> ApiResult<impl IntoResponse> {
+    let unread_ids: Vec<Id> = state
+        .notifications
+        .get_group(user.0.id(), &resource_type, resource_id, true)
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?
+        .into_iter()
+        .filter_map(|n| n.id)
+        .collect();
+
+    let marked = state
+        .notifications
+        .mark_group_read(user.0.id(), &resource_type, resource_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?;
+
+    // Reading the notifications of a work package counts as viewing it
+    if let (Some(pool), "WorkPackage") = (state.db.as_ref(), resource_type.as_str()) {
+        record_view(pool, &user, resource_id);
+    }
+
+    if marked > 0 {
+        if let Err(e) = state
+            .notification_streams
+            .publish_from(state.notifications.as_ref(), user.0.id(), StreamEventKind::Read, unread_ids)
+            .await
+        {
+            tracing::warn!(error = %e, "Failed to publish notification event");
+        }
+    }
+
+    Ok(HalResponse(ReadGroupResponse {
+        type_name: "NotificationGroupRead".into(),
+        count: marked,
+    }))
+}
+
+/// Interval of the heartbeat comments keeping idle streams open through proxies
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Stream the current user's notification events
+///
+/// GET /api/v3/notifications/stream
+///
+/// Sends `notification` events with the new unread count. A client
+/// reconnecting with `Last-Event-ID` first receives the events it missed;
+/// when they are no longer buffered, or the client fell behind, it receives
+/// a `count` event to resynchronize instead.
+pub async fn stream_notifications(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+) -> ApiResult<impl IntoResponse> {
+    let user_id = user.0.id();
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    // Subscribe before reading the count so no change is lost in between
+    let mut subscription = state.notification_streams.subscribe(user_id, last_event_id);
+    let mut pending: VecDeque<StreamEvent> = std::mem::take(&mut subscription.missed).into();
+    if last_event_id.is_none() || subscription.gap {
+        pending.clear();
+        pending.push_back(count_event(&state, user_id).await?);
+    }
+
+    let stream = notification_events(state, user_id, subscription, pending);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL).text("heartbeat")))
+}
+
+fn notification_events(
+    state: AppState,
+    user_id: Id,
+    subscription: Subscription,
+    pending: VecDeque<StreamEvent>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(
+        (state, subscription, pending),
+        move |(state, mut subscription, mut pending)| async move {
+            let event = match pending.pop_front() {
+                Some(event) => event,
+                None => match subscription.recv().await? {
+                    Received::Event(event) => event,
+                    // The client stopped reading; skip what it missed
+                    Received::Lagged(skipped) => {
+                        tracing::debug!(user_id, skipped, "Notification stream lagged");
+                        count_event(&state, user_id).await.ok()?
+                    }
+                },
+            };
+            Some((Ok(sse_event(&event)), (state, subscription, pending)))
+        },
+    )
+}
+
+async fn count_event(state: &AppState, user_id: Id) -> ApiResult<StreamEvent> {
+    let unread_count = state
+        .notifications
+        .unread_count(user_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Notification store error: {}", e)))?;
+    Ok(state.notification_streams.count_event(unread_count))
+}
+
+fn sse_event(event: &StreamEvent) -> Event {
+    Event::default()
+        .event("notification")
+        .id(event.id.to_string())
+        .data(serde_json::to_string(event).unwrap_or_default())
+}
+
+// Query parameters
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationGroupFilters {
+    pub unread_only: Option<bool>,
+    /// Comma separated related resources to embed, `actor` and `project`
+    pub embed: Option<String>,
+}
+
+impl NotificationGroupFilters {
+    /// Related resources to embed, `default` when not given
+    fn embed(&self, default: NotificationEmbed) -> ApiResult<NotificationEmbed> {
+        match self.embed.as_deref() {
+            None => Ok(default),
+            Some(value) => NotificationEmbed::parse(value).map_err(|name| {
+                ApiError::invalid_property("embed", format!("must list actor or project, not '{}'", name))
+            }),
+        }
+    }
+}
+
+// DTOs
+#[derive(Debug, Serialize)]
+struct ReadGroupResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    count: usize,
+}
@@ -0,0 +1,189 @@
+//! Principals API handlers
+//!
+//! Mirrors: lib/api/v3/principals/*, lib/api/v3/groups/* and
+//! lib/api/v3/placeholder_users/*
+//!
+//! Principals are rendered by their type, see [`PrincipalRepresenter`].
+//! Email addresses and locked users are shown as [`UserVisibility`]
+//! allows; principals are listed e.g. to mention them.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use op_core::traits::Id;
+use op_db::{ProjectRepository, Repository, UserRepository, UserRow};
+use serde::Deserialize;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::representers::{CollectionQuery, HalCollection, PrincipalRepresenter, PrincipalType};
+use crate::visibility::{can_see_locked_users, UserVisibility, Viewer};
+
+/// List users, groups and placeholder users
+///
+/// GET /api/v3/principals?type=User,Group
+pub async fn list_principals(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    pagination: Pagination,
+    collection_query: CollectionQuery,
+    Query(filters): Query<PrincipalFilters>,
+) -> ApiResult<impl IntoResponse> {
+    let types = filters.types()?;
+    let repo = UserRepository::new(state.pool()?.clone());
+
+    let result = repo
+        .find_principals(
+            &types,
+            can_see_locked_users(&Viewer::of(&user)),
+            op_db::Pagination {
+                limit: pagination.page_size as i64,
+                offset: pagination.offset as i64,
+            },
+        )
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let elements = represent_all(&state, &repo, &user, &result.items).await?;
+    let collection = HalCollection::new(
+        "Collection",
+        elements,
+        result.total,
+        pagination.page_size as i64,
+        pagination.offset as i64,
+    )
+    .with_pagination_links(&collection_query);
+    Ok(HalResponse(collection))
+}
+
+/// Get a group with its members
+///
+/// GET /api/v3/groups/:id
+pub async fn get_group(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    get_principal(&state, &user, id, PrincipalType::Group, "Group").await
+}
+
+/// Get a placeholder user
+///
+/// GET /api/v3/placeholder_users/:id
+pub async fn get_placeholder_user(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    get_principal(&state, &user, id, PrincipalType::PlaceholderUser, "PlaceholderUser").await
+}
+
+/// List the principals work packages of a project can be assigned to
+///
+/// GET /api/v3/projects/:id/available_assignees?type=User,Group
+pub async fn list_available_assignees(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(project_id): Path<Id>,
+    Query(filters): Query<PrincipalFilters>,
+) -> ApiResult<impl IntoResponse> {
+    let types = filters.types()?;
+    let pool = state.pool()?;
+
+    if !ProjectRepository::new(pool.clone())
+        .exists(project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+    {
+        return Err(ApiError::not_found("Project", project_id));
+    }
+
+    let repo = UserRepository::new(pool.clone());
+    let rows = repo
+        .find_assignable(project_id, &types, can_see_locked_users(&Viewer::of(&user)))
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let elements = represent_all(&state, &repo, &user, &rows).await?;
+    let total = elements.len() as i64;
+    Ok(HalResponse(HalCollection::new("Collection", elements, total, total, 0)))
+}
+
+async fn get_principal(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    id: Id,
+    principal_type: PrincipalType,
+    resource: &'static str,
+) -> ApiResult<impl IntoResponse> {
+    let repo = UserRepository::new(state.pool()?.clone());
+
+    let row = repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|row| row.principal_type == principal_type.discriminator())
+        .ok_or_else(|| ApiError::not_found(resource, id))?;
+
+    let mut elements = represent_all(state, &repo, user, std::slice::from_ref(&row)).await?;
+    Ok(HalResponse(elements.remove(0)))
+}
+
+/// Represent principals, loading the members of the groups among them
+async fn represent_all(
+    state: &AppState,
+    repo: &UserRepository,
+    user: &AuthenticatedUser,
+    rows: &[UserRow],
+) -> ApiResult<Vec<impl serde::Serialize>> {
+    let group_ids: Vec<Id> = rows.iter().filter(|row| row.is_group()).map(|row| row.id).collect();
+    let mut members: HashMap<Id, Vec<UserRow>> = HashMap::new();
+    for (group_id, member) in repo
+        .find_group_members(&group_ids)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+    {
+        members.entry(group_id).or_default().push(member);
+    }
+
+    let ids: Vec<Id> = rows.iter().map(|row| row.id).collect();
+    let visibility = UserVisibility::load(state, user, &ids).await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let group_members = members.get(&row.id).map(Vec::as_slice).unwrap_or_default();
+            PrincipalRepresenter::represent(row, group_members, visibility.can_see_email(row.id))
+        })
+        .collect())
+}
+
+// Query parameters
+#[derive(Debug, Default, Deserialize)]
+pub struct PrincipalFilters {
+    /// Comma-separated `_type` names, e.g. `User,Group`
+    #[serde(rename = "type")]
+    pub principal_type: Option<String>,
+}
+
+impl PrincipalFilters {
+    /// `users.type` values to filter by; empty for all principals
+    fn types(&self) -> ApiResult<Vec<&'static str>> {
+        let Some(names) = self.principal_type.as_deref() else {
+            return Ok(Vec::new());
+        };
+        names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                PrincipalType::from_name(name)
+                    .map(PrincipalType::discriminator)
+                    .ok_or_else(|| ApiError::bad_request(format!("Unknown principal type '{}'.", name)))
+            })
+            .collect()
+    }
+}
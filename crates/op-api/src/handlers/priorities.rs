@@ -3,7 +3,7 @@
 //! Mirrors: lib/api/v3/priorities/*
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
@@ -15,6 +15,8 @@ use serde::{Deserialize, Serialize};
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
 
+use super::statuses::{DeleteReassigningParams, ReassignedResponse};
+
 /// List all priorities
 ///
 /// GET /api/v3/priorities
@@ -147,10 +149,14 @@ pub async fn update_priority(
 /// Delete a priority (admin only)
 ///
 /// DELETE /api/v3/priorities/:id
+///
+/// Refused while work packages use the priority, unless `reassignTo` names
+/// the priority to move them to first.
 pub async fn delete_priority(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     Path(id): Path<Id>,
+    Query(params): Query<DeleteReassigningParams>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
         return Err(ApiError::forbidden("Only administrators can delete priorities."));
@@ -159,15 +165,26 @@ pub async fn delete_priority(
     let pool = state.pool()?;
     let repo = PriorityRepository::new(pool.clone());
 
-    repo.delete(id)
-        .await
-        .map_err(|e| match e {
-            op_db::RepositoryError::NotFound(_) => ApiError::not_found("Priority", id),
-            op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
-            _ => ApiError::internal(format!("Database error: {}", e)),
-        })?;
+    let map_error = |e: op_db::RepositoryError| match e {
+        op_db::RepositoryError::NotFound(_) => ApiError::not_found("Priority", id),
+        op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
+        op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+        _ => ApiError::internal(format!("Database error: {}", e)),
+    };
 
-    Ok(StatusCode::NO_CONTENT)
+    match params.reassign_to {
+        Some(reassign_to) => {
+            let reassigned_work_packages = repo
+                .delete_reassigning(id, reassign_to, user.id())
+                .await
+                .map_err(map_error)?;
+            Ok(Json(ReassignedResponse { reassigned_work_packages }).into_response())
+        }
+        None => {
+            repo.delete(id).await.map_err(map_error)?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+    }
 }
 
 // Request types
@@ -3,130 +3,241 @@
 //! Mirrors: lib/api/v3/projects/*
 
 use axum::{
+    async_trait,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, StatusCode},
     response::IntoResponse,
     Json,
 };
+use std::collections::{HashMap, HashSet};
+
+use op_contracts::projects::ProjectBaseContract;
+use op_core::representations::{
+    Collection, CreateProject, CustomFieldValues, Link, Project, ProjectLinks, UpdateProject,
+};
+use op_core::error::ValidationErrors;
+use op_core::events::DomainEvent;
 use op_core::traits::Id;
-use op_db::{ProjectRepository, Repository};
+use op_db::{
+    AttachmentRepository, CopyDependency, CustomFieldRepository, CustomValueFilter, CustomValueOperator, CustomValueRow,
+    MemberRepository, ProjectOrder, ProjectRepository, RelationRepository, RelationRow, Repository, SummaryCount,
+    SummaryDimension, SummaryRepository, WorkPackageRepository, PROJECT_CUSTOMIZED_TYPE,
+};
+use op_db::work_packages::WorkPackageRow;
+use op_models::{custom_field, CustomField};
+use op_notifications::service::{ServiceError, ServiceResult as NotificationResult};
+use op_notifications::{ProjectEmailSettings, ProjectEmailSettingsSource};
+use op_services::projects::{
+    generate_identifier, identifier_errors, CopyProjectParams, CreateProjectService, InstantiateTemplateArgs, ProjectParams,
+};
+use op_queries::{FilterOperator, SortDirection};
+use op_services::permissions::PermissionService;
+use op_services::revoked_access::CleanupRevokedAccessArgs;
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
 use crate::error::{ApiError, ApiResult};
-use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::filters::{parse_filters, parse_sort_by};
+use crate::extractors::{AppState, AuthenticatedUser, DryRun, HalResponse, Pagination};
+use crate::representers::{CollectionQuery, ProjectSchemaRepresenter};
+use crate::handlers::job_statuses::{job_status_href, JobStatusResponse};
 
 /// GET /api/v3/projects
 pub async fn list_projects(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     pagination: Pagination,
+    collection_query: CollectionQuery,
     Query(filters): Query<ProjectFilters>,
 ) -> ApiResult<impl IntoResponse> {
+    let templated = templated_filter(filters.filters.as_deref())?;
+    let custom_values = custom_value_filters(filters.filters.as_deref())?;
+    let order = project_order(filters.sort_by.as_deref())?;
+
     let pool = state.pool()?;
     let repo = ProjectRepository::new(pool.clone());
+    let custom_fields = CustomFieldRepository::new(pool.clone());
+    for filter in &custom_values {
+        let found = custom_fields
+            .find_project_custom_field(filter.custom_field_id)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+        if found.is_none() {
+            let name = custom_field::attribute_name(filter.custom_field_id);
+            return Err(ApiError::invalid_property(&name, "filter is not a project custom field"));
+        }
+    }
 
-    let (rows, total) = if filters.active_only.unwrap_or(false) {
-        let result = repo
-            .find_active(op_db::Pagination {
+    // Templates are only listed when filtered for explicitly
+    let result = repo
+        .find_by_templated(
+            templated,
+            filters.active_only.unwrap_or(false),
+            user.is_anonymous(),
+            &custom_values,
+            order,
+            op_db::Pagination {
                 limit: pagination.page_size as i64,
                 offset: pagination.offset as i64,
-            })
-            .await
-            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-        (result.items, result.total)
-    } else {
-        let rows = repo
-            .find_all(pagination.page_size as i64, pagination.offset as i64)
-            .await
-            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-        let total = repo
-            .count()
-            .await
-            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-        (rows, total)
-    };
+            },
+        )
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let (rows, total) = (result.items, result.total);
 
-    let elements: Vec<ProjectResponse> = rows
+    let ids: Vec<Id> = rows.iter().map(|row| row.id).collect();
+    let mut values = custom_values_by_project(&custom_fields, &ids).await?;
+    let elements: Vec<Project> = rows
         .into_iter()
-        .map(|row| ProjectResponse::from_row(row))
+        .map(|row| {
+            let values = values.remove(&row.id).unwrap_or_default();
+            project_response(row, values)
+        })
         .collect();
 
-    let collection = ProjectCollection {
-        type_name: "Collection".into(),
-        total: total as usize,
-        count: elements.len(),
-        page_size: pagination.page_size,
-        offset: pagination.offset,
-        elements,
-    };
+    let collection = Collection::new(elements, total as usize, pagination.offset, pagination.page_size).with_links(
+        collection_query.pagination_links(total, pagination.offset as i64, pagination.page_size as i64),
+    );
     Ok(HalResponse(collection))
 }
 
 /// GET /api/v3/projects/:id
 pub async fn get_project(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
-    let repo = ProjectRepository::new(pool.clone());
+    let projects = state.entity_stores()?.projects;
 
-    let row = repo
+    // Anonymous users only see public projects
+    let row = projects
         .find_by_id(id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|row| !user.is_anonymous() || (row.public && row.active))
         .ok_or_else(|| ApiError::not_found("Project", id))?;
+    let values = match &state.db {
+        Some(pool) => custom_values_of(&CustomFieldRepository::new(pool.clone()), id).await?,
+        None => CustomFieldValues::new(),
+    };
+
+    Ok(HalResponse(project_response(row, values)))
+}
+
+/// GET /api/v3/projects/schema
+///
+/// Attributes of projects, including the project custom fields that apply
+/// to new projects.
+pub async fn get_project_schema(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let custom_fields = CustomFieldRepository::new(pool.clone())
+        .project_custom_fields(None)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    Ok(HalResponse(ProjectResponse::from_row(row)))
+    Ok(HalResponse(ProjectSchemaRepresenter::represent(&custom_fields)))
 }
 
 /// POST /api/v3/projects
+///
+/// With `dryRun=true` the project is validated, including the uniqueness of
+/// its identifier, and returned as it would be created (200), without an id.
 pub async fn create_project(
     State(state): State<AppState>,
     user: AuthenticatedUser,
-    Json(dto): Json<CreateProjectDto>,
-) -> ApiResult<impl IntoResponse> {
+    dry_run: DryRun,
+    Json(dto): Json<CreateProject>,
+) -> ApiResult<axum::response::Response> {
     // Only admins can create projects
     if !user.0.is_admin() {
         return Err(ApiError::forbidden("Only administrators can create projects."));
     }
 
-    let pool = state.pool()?;
-    let repo = ProjectRepository::new(pool.clone());
+    let repo = state.entity_stores()?.projects;
+
+    // Derive the identifier from the name when none is given
+    let identifier = match dto.identifier {
+        Some(identifier) => identifier,
+        None => generate_identifier(repo.as_ref(), &dto.name)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?,
+    };
+
+    // Custom fields are kept in the database only
+    let custom_field_repo = state.db.clone().map(CustomFieldRepository::new);
+    let custom_fields = match &custom_field_repo {
+        Some(custom_field_repo) => custom_field_repo
+            .project_custom_fields(None)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?,
+        None => Vec::new(),
+    };
+    let custom_values = stored_custom_values(&dto.custom_fields, &custom_fields)?;
 
-    // Check identifier uniqueness
-    let is_unique = repo
-        .is_identifier_unique(&dto.identifier, None)
+    let mut errors = identifier_errors(&user, repo.as_ref(), &identifier, None)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
-
-    if !is_unique {
-        return Err(ApiError::conflict("Identifier has already been taken"));
+    ProjectBaseContract::new(&user).validate_custom_values(&custom_fields, &present(&custom_values), &mut errors);
+    if !errors.is_empty() {
+        return Err(ApiError::validation(errors));
     }
 
     let create_dto = op_db::CreateProjectDto {
         name: dto.name,
         description: dto.description,
-        identifier: dto.identifier,
+        identifier,
         public: dto.public.unwrap_or(false),
         parent_id: dto.parent_id,
         active: dto.active.unwrap_or(true),
+        templated: dto.templated.unwrap_or(false),
     };
 
+    if dry_run.0 {
+        let params = ProjectParams {
+            name: Some(create_dto.name.clone()),
+            identifier: Some(create_dto.identifier.clone()),
+            description: create_dto.description.clone(),
+            public: Some(create_dto.public),
+            active: Some(create_dto.active),
+            parent_id: create_dto.parent_id,
+            send_notifications: false,
+        };
+        let result = CreateProjectService::without_notifications(&user).dry_run().call(params);
+        if result.is_failure() {
+            return Err(ApiError::validation(result.errors().clone()));
+        }
+        let representation = project_response(create_dto.preview(), represented(&custom_fields, &custom_values));
+        return Ok(HalResponse(DryRun::unsaved(&representation)).into_response());
+    }
+
     let row = repo
         .create(create_dto)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if let Some(custom_field_repo) = &custom_field_repo {
+        custom_field_repo
+            .set_values(PROJECT_CUSTOMIZED_TYPE, row.id, &custom_values)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    }
 
-    Ok((StatusCode::CREATED, HalResponse(ProjectResponse::from_row(row))))
+    let values = represented(&custom_fields, &custom_values);
+    Ok((StatusCode::CREATED, HalResponse(project_response(row, values))).into_response())
 }
 
 /// PATCH /api/v3/projects/:id
+///
+/// With `dryRun=true` the changes are validated and the project is returned
+/// as they would leave it; nothing is saved and no cleanup is scheduled.
 pub async fn update_project(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    dry_run: DryRun,
     Path(id): Path<Id>,
-    Json(dto): Json<UpdateProjectDto>,
+    Json(dto): Json<UpdateProject>,
 ) -> ApiResult<impl IntoResponse> {
     // Only admins can update projects (simplified permission check)
     if !user.0.is_admin() {
@@ -137,18 +248,55 @@ pub async fn update_project(
     let repo = ProjectRepository::new(pool.clone());
 
     // Verify project exists
-    if !repo.exists(id).await.map_err(|e| ApiError::internal(format!("Database error: {}", e)))? {
-        return Err(ApiError::not_found("Project", id));
+    let project = repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Project", id))?;
+
+    // Only the custom fields given are validated, so projects created
+    // before a field became required can still be changed otherwise
+    let custom_field_repo = CustomFieldRepository::new(pool.clone());
+    let custom_fields = custom_field_repo
+        .project_custom_fields(Some(id))
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let custom_values = stored_custom_values(&dto.custom_fields, &custom_fields)?;
+    let changed_fields: Vec<CustomField> = custom_fields
+        .iter()
+        .filter(|custom_field| custom_values.iter().any(|(field_id, _)| *field_id == custom_field.id))
+        .cloned()
+        .collect();
+
+    // Renaming the identifier changes the project's URLs
+    let identifier = dto.identifier.filter(|identifier| *identifier != project.identifier);
+    let mut errors = match identifier {
+        Some(ref identifier) => identifier_errors(&user, &repo, identifier, Some(id))
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?,
+        None => ValidationErrors::new(),
+    };
+    ProjectBaseContract::new(&user).validate_custom_values(&changed_fields, &present(&custom_values), &mut errors);
+    if !errors.is_empty() {
+        return Err(ApiError::validation(errors));
     }
 
     let update_dto = op_db::UpdateProjectDto {
         name: dto.name,
         description: dto.description,
+        identifier,
         public: dto.public,
         parent_id: None, // Parent change not allowed via simple update
         active: dto.active,
+        templated: dto.templated,
     };
 
+    if dry_run.0 {
+        let mut values = custom_values_of(&custom_field_repo, id).await?;
+        values.extend(represented(&custom_fields, &custom_values));
+        return Ok(HalResponse(project_response(update_dto.preview(project), values)));
+    }
+
     let row = repo
         .update(id, update_dto)
         .await
@@ -156,8 +304,23 @@ pub async fn update_project(
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Project", id),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
+    custom_field_repo
+        .set_values(PROJECT_CUSTOMIZED_TYPE, id, &custom_values)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    // Non-members watching or notified about its work packages lose access
+    // when the project becomes private
+    if project.public && !row.public {
+        state
+            .jobs
+            .enqueue(CleanupRevokedAccessArgs::project(id).into_job(user.id()))
+            .await
+            .map_err(|e| ApiError::internal(format!("Queue error: {}", e)))?;
+    }
 
-    Ok(HalResponse(ProjectResponse::from_row(row)))
+    let values = custom_values_of(&custom_field_repo, id).await?;
+    Ok(HalResponse(project_response(row, values)))
 }
 
 /// DELETE /api/v3/projects/:id
@@ -171,10 +334,10 @@ pub async fn delete_project(
         return Err(ApiError::forbidden("Only administrators can delete projects."));
     }
 
-    let pool = state.pool()?;
-    let repo = ProjectRepository::new(pool.clone());
-
-    repo.delete(id)
+    state
+        .entity_stores()?
+        .projects
+        .delete(id)
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Project", id),
@@ -208,6 +371,13 @@ pub async fn archive_project(
     repo.archive(id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    state
+        .events
+        .publish(DomainEvent::ProjectArchived {
+            project_id: id,
+            actor_id: user.id(),
+        })
+        .await;
 
     // Return updated project
     let updated = repo
@@ -216,7 +386,8 @@ pub async fn archive_project(
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("Project", id))?;
 
-    Ok(HalResponse(ProjectResponse::from_row(updated)))
+    let values = custom_values_of(&CustomFieldRepository::new(pool.clone()), id).await?;
+    Ok(HalResponse(project_response(updated, values)))
 }
 
 /// POST /api/v3/projects/:id/unarchive
@@ -250,7 +421,387 @@ pub async fn unarchive_project(
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("Project", id))?;
 
-    Ok(HalResponse(ProjectResponse::from_row(updated)))
+    let values = custom_values_of(&CustomFieldRepository::new(pool.clone()), id).await?;
+    Ok(HalResponse(project_response(updated, values)))
+}
+
+/// GET /api/v3/projects/:id/storage
+///
+/// Attachment storage used by the project against its quota.
+pub async fn get_project_storage(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can view project storage."));
+    }
+
+    let pool = state.pool()?;
+    let repo = ProjectRepository::new(pool.clone());
+    let attachments = AttachmentRepository::new(pool.clone());
+
+    if !repo.exists(id).await.map_err(|e| ApiError::internal(format!("Database error: {}", e)))? {
+        return Err(ApiError::not_found("Project", id));
+    }
+
+    let quota = repo
+        .attachment_quota(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let breakdown = attachments
+        .usage_by_container_type(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(HalResponse(ProjectStorageResponse::new(id, quota, breakdown)))
+}
+
+/// GET /api/v3/projects/:id/work_package_summary
+///
+/// Work package counts of the project grouped by the comma separated
+/// `groupBy` attributes (type, status, priority), for the overview widgets.
+pub async fn get_work_package_summary(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Query(params): Query<WorkPackageSummaryParams>,
+) -> ApiResult<impl IntoResponse> {
+    let dimensions = summary_dimensions(params.group_by.as_deref())?;
+
+    let pool = state.pool()?;
+    ProjectRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|project| !user.is_anonymous() || (project.public && project.active))
+        .ok_or_else(|| ApiError::not_found("Project", id))?;
+
+    let allowed = PermissionService::new(MemberRepository::new(pool.clone()))
+        .allowed_in_project(&user, "view_work_packages", id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if !allowed {
+        return Err(ApiError::forbidden("You are not allowed to view the work packages of this project."));
+    }
+
+    let counts = SummaryRepository::new(pool.clone())
+        .counts_for_project(id, &dimensions)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(Json(WorkPackageSummaryResponse::new(id, counts)))
+}
+
+/// GET /api/v3/projects/:id/timeline
+///
+/// The project's work packages with dates and the precedes relations
+/// between them with their lag, to draw a Gantt chart.
+pub async fn get_timeline(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    ProjectRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|project| !user.is_anonymous() || (project.public && project.active))
+        .ok_or_else(|| ApiError::not_found("Project", id))?;
+
+    let allowed = PermissionService::new(MemberRepository::new(pool.clone()))
+        .allowed_in_project(&user, "view_work_packages", id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if !allowed {
+        return Err(ApiError::forbidden("You are not allowed to view the work packages of this project."));
+    }
+
+    let work_packages = WorkPackageRepository::new(pool.clone())
+        .find_scheduled_in_project(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let ids: HashSet<Id> = work_packages.iter().map(|wp| wp.id).collect();
+    let relations = RelationRepository::new(pool.clone())
+        .find_precedes_touching(&ids.iter().copied().collect::<Vec<_>>())
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .into_iter()
+        .filter(|relation| ids.contains(&relation.from_id) && ids.contains(&relation.to_id))
+        .collect();
+
+    Ok(Json(TimelineResponse::new(id, work_packages, relations)))
+}
+
+/// Dimensions of a `groupBy` parameter, none when omitted
+fn summary_dimensions(group_by: Option<&str>) -> ApiResult<Vec<SummaryDimension>> {
+    let mut dimensions = Vec::new();
+    for name in group_by.unwrap_or("").split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let dimension = SummaryDimension::parse(name).ok_or_else(|| {
+            ApiError::invalid_property("groupBy", format!("contains unknown attribute '{}'", name))
+        })?;
+        if !dimensions.contains(&dimension) {
+            dimensions.push(dimension);
+        }
+    }
+    Ok(dimensions)
+}
+
+/// POST /api/v3/projects/from_template
+///
+/// Copies the template in the background and returns the job status to poll.
+pub async fn instantiate_template(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(dto): Json<InstantiateTemplateDto>,
+) -> ApiResult<impl IntoResponse> {
+    // Only admins can create projects
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can create projects."));
+    }
+
+    let dependencies = match dto.dependencies {
+        Some(names) => names
+            .iter()
+            .map(|name| {
+                CopyDependency::parse(name).ok_or_else(|| {
+                    ApiError::invalid_property(
+                        "dependencies",
+                        format!("contains unknown dependency '{}'", name),
+                    )
+                })
+            })
+            .collect::<ApiResult<Vec<_>>>()?,
+        None => CopyDependency::ALL.to_vec(),
+    };
+
+    let pool = state.pool()?;
+    let repo = ProjectRepository::new(pool.clone());
+
+    let template = repo
+        .find_by_id(dto.template_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Project", dto.template_id))?;
+
+    if !template.templated {
+        return Err(ApiError::invalid_property(
+            "templateId",
+            "is not a project template",
+        ));
+    }
+
+    let errors = identifier_errors(&user, &repo, &dto.identifier, None)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if !errors.is_empty() {
+        return Err(ApiError::validation(errors));
+    }
+
+    let args = InstantiateTemplateArgs {
+        template_id: template.id,
+        params: CopyProjectParams {
+            name: dto.name,
+            identifier: dto.identifier,
+            description: dto.description,
+            public: dto.public,
+            parent_id: dto.parent_id,
+            dependencies,
+        },
+    };
+
+    let job = args.into_job(user.0.id());
+    let job_id = state
+        .jobs
+        .enqueue(job.clone())
+        .await
+        .map_err(|e| ApiError::internal(format!("Queue error: {}", e)))?;
+
+    let status = JobStatusResponse::from_job(&job);
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, job_status_href(&job_id))],
+        HalResponse(status),
+    ))
+}
+
+/// Parse the `templated` filter from an API v3 filter list, e.g.
+/// `[{"templated":{"operator":"=","values":["t"]}}]`. Other filters are
+/// not supported yet and ignored.
+fn templated_filter(filters: Option<&str>) -> ApiResult<bool> {
+    let filters = parse_filters(filters)?;
+    let Some(filter) = filters.iter().find(|f| f.attribute == "templated") else {
+        return Ok(false);
+    };
+
+    let value = match filter.values.as_strings().first().map(String::as_str) {
+        Some("t") | Some("true") => true,
+        Some("f") | Some("false") => false,
+        _ => {
+            return Err(ApiError::invalid_property(
+                "templated",
+                "filter values must be 't' or 'f'",
+            ))
+        }
+    };
+
+    match filter.operator {
+        FilterOperator::Equals => Ok(value),
+        FilterOperator::NotEquals => Ok(!value),
+        ref op => Err(ApiError::invalid_property(
+            "templated",
+            format!("filter does not support operator '{}'", op.to_string()),
+        )),
+    }
+}
+
+/// Filters on project custom fields from an API v3 filter list, e.g.
+/// `[{"customField3":{"operator":"=","values":["Build"]}}]`
+fn custom_value_filters(filters: Option<&str>) -> ApiResult<Vec<CustomValueFilter>> {
+    let mut custom_values = Vec::new();
+    for filter in parse_filters(filters)? {
+        let Some(custom_field_id) = custom_field::attribute_id(&filter.attribute) else {
+            continue;
+        };
+        let operator = match filter.operator {
+            FilterOperator::Equals => CustomValueOperator::Equals,
+            FilterOperator::NotEquals => CustomValueOperator::NotEquals,
+            FilterOperator::IsNotNull => CustomValueOperator::Any,
+            FilterOperator::IsNull => CustomValueOperator::None,
+            ref op => {
+                return Err(ApiError::invalid_property(
+                    &filter.attribute,
+                    format!("filter does not support operator '{}'", op.to_string()),
+                ))
+            }
+        };
+        custom_values.push(CustomValueFilter {
+            custom_field_id,
+            operator,
+            values: filter.values.as_strings(),
+        });
+    }
+    Ok(custom_values)
+}
+
+/// Values to store for the custom field attributes of a payload, e.g.
+/// `"customField3": 5`; null or an empty string removes a value
+fn stored_custom_values(
+    attributes: &CustomFieldValues,
+    custom_fields: &[CustomField],
+) -> ApiResult<Vec<(Id, Option<String>)>> {
+    attributes
+        .iter()
+        .map(|(name, value)| {
+            let custom_field = custom_field::attribute_id(name)
+                .and_then(|id| custom_fields.iter().find(|custom_field| custom_field.id == id))
+                .ok_or_else(|| ApiError::invalid_property(name, "is not a custom field of the project"))?;
+            let value = match value {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(value) if value.is_empty() => None,
+                serde_json::Value::String(value) => Some(value.clone()),
+                serde_json::Value::Number(value) => Some(value.to_string()),
+                serde_json::Value::Bool(value) => Some(if *value { "t" } else { "f" }.to_string()),
+                _ => return Err(ApiError::invalid_property(name, "must be a string, number or boolean")),
+            };
+            Ok((custom_field.id, value))
+        })
+        .collect()
+}
+
+/// Values that are set, by custom field
+fn present(values: &[(Id, Option<String>)]) -> HashMap<Id, String> {
+    values
+        .iter()
+        .filter_map(|(id, value)| Some((*id, value.clone()?)))
+        .collect()
+}
+
+/// Representation of values to store
+fn represented(custom_fields: &[CustomField], values: &[(Id, Option<String>)]) -> CustomFieldValues {
+    values
+        .iter()
+        .filter_map(|(id, value)| {
+            let custom_field = custom_fields.iter().find(|custom_field| custom_field.id == *id)?;
+            let value = value.as_deref().map_or(serde_json::Value::Null, |value| custom_field.typed_value(value));
+            Some((custom_field.attribute_name(), value))
+        })
+        .collect()
+}
+
+/// Represented custom field values of the projects, by project
+async fn custom_values_by_project(
+    repo: &CustomFieldRepository,
+    ids: &[Id],
+) -> ApiResult<HashMap<Id, CustomFieldValues>> {
+    let rows = repo
+        .values(PROJECT_CUSTOMIZED_TYPE, ids)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let mut values: HashMap<Id, CustomFieldValues> = HashMap::new();
+    for CustomValueRow { customized_id, custom_field_id, field_format, value } in rows {
+        let value = value.map_or(serde_json::Value::Null, |value| custom_field::typed_value(&field_format, &value));
+        values
+            .entry(customized_id)
+            .or_default()
+            .insert(custom_field::attribute_name(custom_field_id), value);
+    }
+    Ok(values)
+}
+
+/// Represented custom field values of a project
+async fn custom_values_of(repo: &CustomFieldRepository, id: Id) -> ApiResult<CustomFieldValues> {
+    Ok(custom_values_by_project(repo, &[id]).await?.remove(&id).unwrap_or_default())
+}
+
+/// Email settings of projects, read from their settings
+pub struct DbProjectEmailSettings {
+    projects: ProjectRepository,
+}
+
+impl DbProjectEmailSettings {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            projects: ProjectRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl ProjectEmailSettingsSource for DbProjectEmailSettings {
+    async fn email_settings(&self, project_id: Id) -> NotificationResult<Option<ProjectEmailSettings>> {
+        let settings = self
+            .projects
+            .email_settings(project_id)
+            .await
+            .map_err(|e| ServiceError::StorageError(e.to_string()))?;
+
+        settings
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| ServiceError::StorageError(format!("Invalid project email settings: {}", e)))
+    }
+}
+
+/// Order of the project listing from the first `sortBy` criterion; the
+/// project tree unless sorted by `latestActivityAt`
+fn project_order(sort_by: Option<&str>) -> ApiResult<ProjectOrder> {
+    let Some(sort) = parse_sort_by(sort_by)?.into_iter().next() else {
+        return Ok(ProjectOrder::Hierarchy);
+    };
+
+    match sort.attribute.as_str() {
+        "lft" => Ok(ProjectOrder::Hierarchy),
+        "latestActivityAt" | "latest_activity_at" => Ok(ProjectOrder::LatestActivity {
+            descending: sort.direction == SortDirection::Desc,
+        }),
+        attribute => Err(ApiError::invalid_property(
+            "sortBy",
+            format!("sorting by '{}' is not supported", attribute),
+        )),
+    }
 }
 
 // Query parameters
@@ -258,94 +809,241 @@ pub async fn unarchive_project(
 #[serde(rename_all = "camelCase")]
 pub struct ProjectFilters {
     pub active_only: Option<bool>,
+    /// JSON-encoded API v3 filter list
+    pub filters: Option<String>,
+    /// JSON-encoded sort criteria
+    pub sort_by: Option<String>,
+}
+
+fn project_response(row: op_db::ProjectRow, custom_fields: CustomFieldValues) -> Project {
+    let parent_link = row.parent_id.map(|pid| Link::new(format!("/api/v3/projects/{}", pid)));
+
+    Project {
+        type_name: "Project".into(),
+        id: row.id,
+        name: row.name,
+        identifier: row.identifier.clone(),
+        description: row.description,
+        public: row.public,
+        active: row.active,
+        templated: row.templated,
+        parent_id: row.parent_id,
+        created_at: row.created_at.to_rfc3339(),
+        updated_at: row.updated_at.to_rfc3339(),
+        custom_fields,
+        links: ProjectLinks {
+            self_link: Link::new(format!("/api/v3/projects/{}", row.id)),
+            parent: parent_link,
+            work_packages: Link::new(format!("/api/v3/projects/{}/work_packages", row.id)),
+            categories: Link::new(format!("/api/v3/projects/{}/categories", row.id)),
+            versions: Link::new(format!("/api/v3/projects/{}/versions", row.id)),
+            memberships: Link::new(format!("/api/v3/memberships?filters=[{{\"project\":{{\"operator\":\"=\",\"values\":[\"{}\"]}}}}]", row.id)),
+        },
+    }
 }
 
 // DTOs
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct ProjectCollection {
+struct ProjectStorageResponse {
     #[serde(rename = "_type")]
     type_name: String,
-    total: usize,
-    count: usize,
-    page_size: usize,
-    offset: usize,
-    #[serde(rename = "_embedded")]
-    elements: Vec<ProjectResponse>,
+    used_bytes: i64,
+    /// Unlimited when null
+    quota_bytes: Option<i64>,
+    breakdown: Vec<ContainerUsage>,
+    #[serde(rename = "_links")]
+    links: ProjectStorageLinks,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct ProjectResponse {
+struct ContainerUsage {
+    container_type: String,
+    used_bytes: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectStorageLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    project: Link,
+}
+
+impl ProjectStorageResponse {
+    fn new(project_id: Id, quota: Option<i64>, breakdown: Vec<(String, i64)>) -> Self {
+        let breakdown: Vec<ContainerUsage> = breakdown
+            .into_iter()
+            .map(|(container_type, used_bytes)| ContainerUsage {
+                container_type,
+                used_bytes,
+            })
+            .collect();
+
+        Self {
+            type_name: "ProjectStorage".into(),
+            used_bytes: breakdown.iter().map(|u| u.used_bytes).sum(),
+            quota_bytes: quota,
+            breakdown,
+            links: ProjectStorageLinks {
+                self_link: Link::new(format!("/api/v3/projects/{}/storage", project_id)),
+                project: Link::new(format!("/api/v3/projects/{}", project_id)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkPackageSummaryParams {
+    pub group_by: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkPackageSummaryResponse {
     #[serde(rename = "_type")]
     type_name: String,
-    id: Id,
-    name: String,
-    identifier: String,
+    total: i64,
+    groups: Vec<WorkPackageSummaryGroup>,
+    #[serde(rename = "_links")]
+    links: ProjectStorageLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkPackageSummaryGroup {
+    count: i64,
+    #[serde(rename = "_links")]
+    links: WorkPackageSummaryGroupLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkPackageSummaryGroupLinks {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    type_link: Option<Link>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
-    public: bool,
-    active: bool,
+    status: Option<Link>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    parent_id: Option<Id>,
-    created_at: String,
-    updated_at: String,
+    priority: Option<Link>,
+}
+
+impl WorkPackageSummaryResponse {
+    fn new(project_id: Id, counts: Vec<SummaryCount>) -> Self {
+        let groups: Vec<WorkPackageSummaryGroup> = counts
+            .into_iter()
+            .map(|count| WorkPackageSummaryGroup {
+                count: count.count,
+                links: WorkPackageSummaryGroupLinks {
+                    type_link: count.type_id.map(|id| Link::new(format!("/api/v3/types/{}", id))),
+                    status: count.status_id.map(|id| Link::new(format!("/api/v3/statuses/{}", id))),
+                    priority: count.priority_id.map(|id| Link::new(format!("/api/v3/priorities/{}", id))),
+                },
+            })
+            .collect();
+
+        Self {
+            type_name: "WorkPackageSummary".into(),
+            total: groups.iter().map(|group| group.count).sum(),
+            groups,
+            links: ProjectStorageLinks {
+                self_link: Link::new(format!("/api/v3/projects/{}/work_package_summary", project_id)),
+                project: Link::new(format!("/api/v3/projects/{}", project_id)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TimelineResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    work_packages: Vec<TimelineWorkPackage>,
+    relations: Vec<TimelineRelation>,
     #[serde(rename = "_links")]
-    links: ProjectLinks,
+    links: ProjectStorageLinks,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct ProjectLinks {
+struct TimelineWorkPackage {
+    id: Id,
+    subject: String,
+    start_date: Option<String>,
+    due_date: Option<String>,
+    /// Working days, or days when ignoring non-working days
+    duration: Option<i32>,
+    ignore_non_working_days: bool,
+    #[serde(rename = "_links")]
+    links: TimelineWorkPackageLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct TimelineWorkPackageLinks {
     #[serde(rename = "self")]
     self_link: Link,
     #[serde(skip_serializing_if = "Option::is_none")]
     parent: Option<Link>,
-    work_packages: Link,
-    categories: Link,
-    versions: Link,
-    memberships: Link,
 }
 
 #[derive(Debug, Serialize)]
-struct Link {
-    href: String,
+#[serde(rename_all = "camelCase")]
+struct TimelineRelation {
+    id: Id,
+    #[serde(rename = "type")]
+    relation_type: String,
+    /// Working days between the predecessor's finish and the successor's
+    /// start
+    lag: i32,
+    #[serde(rename = "_links")]
+    links: TimelineRelationLinks,
 }
 
-impl ProjectResponse {
-    fn from_row(row: op_db::ProjectRow) -> Self {
-        let parent_link = row.parent_id.map(|pid| Link {
-            href: format!("/api/v3/projects/{}", pid),
-        });
+#[derive(Debug, Serialize)]
+struct TimelineRelationLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    from: Link,
+    to: Link,
+}
 
-        ProjectResponse {
-            type_name: "Project".into(),
-            id: row.id,
-            name: row.name,
-            identifier: row.identifier.clone(),
-            description: row.description,
-            public: row.public,
-            active: row.active,
-            parent_id: row.parent_id,
-            created_at: row.created_at.to_rfc3339(),
-            updated_at: row.updated_at.to_rfc3339(),
-            links: ProjectLinks {
-                self_link: Link {
-                    href: format!("/api/v3/projects/{}", row.id),
+impl TimelineResponse {
+    fn new(project_id: Id, work_packages: Vec<WorkPackageRow>, relations: Vec<RelationRow>) -> Self {
+        let work_packages = work_packages
+            .into_iter()
+            .map(|wp| TimelineWorkPackage {
+                id: wp.id,
+                subject: wp.subject,
+                start_date: wp.start_date.map(|date| date.to_string()),
+                due_date: wp.due_date.map(|date| date.to_string()),
+                duration: wp.duration,
+                ignore_non_working_days: wp.ignore_non_working_days,
+                links: TimelineWorkPackageLinks {
+                    self_link: Link::new(format!("/api/v3/work_packages/{}", wp.id)),
+                    parent: wp.parent_id.map(|id| Link::new(format!("/api/v3/work_packages/{}", id))),
                 },
-                parent: parent_link,
-                work_packages: Link {
-                    href: format!("/api/v3/projects/{}/work_packages", row.id),
-                },
-                categories: Link {
-                    href: format!("/api/v3/projects/{}/categories", row.id),
-                },
-                versions: Link {
-                    href: format!("/api/v3/projects/{}/versions", row.id),
-                },
-                memberships: Link {
-                    href: format!("/api/v3/memberships?filters=[{{\"project\":{{\"operator\":\"=\",\"values\":[\"{}\"]}}}}]", row.id),
+            })
+            .collect();
+        let relations = relations
+            .into_iter()
+            .map(|relation| TimelineRelation {
+                id: relation.id,
+                relation_type: relation.relation_type,
+                lag: relation.lag.unwrap_or(0),
+                links: TimelineRelationLinks {
+                    self_link: Link::new(format!("/api/v3/relations/{}", relation.id)),
+                    from: Link::new(format!("/api/v3/work_packages/{}", relation.from_id)),
+                    to: Link::new(format!("/api/v3/work_packages/{}", relation.to_id)),
                 },
+            })
+            .collect();
+
+        Self {
+            type_name: "Timeline".into(),
+            work_packages,
+            relations,
+            links: ProjectStorageLinks {
+                self_link: Link::new(format!("/api/v3/projects/{}/timeline", project_id)),
+                project: Link::new(format!("/api/v3/projects/{}", project_id)),
             },
         }
     }
@@ -353,21 +1051,14 @@ impl ProjectResponse {
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct CreateProjectDto {
+pub struct InstantiateTemplateDto {
+    pub template_id: Id,
     pub name: String,
     pub identifier: String,
     #[serde(default)]
     pub description: Option<String>,
     pub public: Option<bool>,
-    pub active: Option<bool>,
     pub parent_id: Option<Id>,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct UpdateProjectDto {
-    pub name: Option<String>,
-    pub description: Option<String>,
-    pub public: Option<bool>,
-    pub active: Option<bool>,
+    /// Dependencies to copy, all when omitted
+    pub dependencies: Option<Vec<String>>,
 }
@@ -9,17 +9,22 @@ use axum::{
     Json,
 };
 use op_core::traits::Id;
-use op_db::{QueryRepository, Repository};
+use op_db::{QueryRepository, QuerySubscriptionRepository, QuerySubscriptionRow, Repository};
+use op_queries::{registry, FilterDefinition, GroupBy, QueryDocument, Timestamps};
+use op_services::query_subscriptions::SubscriptionFrequency;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::representers::filter_schema::SCHEMAS_PATH;
+use crate::representers::{CollectionQuery, FilterSchemaRepresenter, HalCollection, HalLink, HalLinks};
 
 /// GET /api/v3/queries
 pub async fn list_queries(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     pagination: Pagination,
+    collection_query: CollectionQuery,
     Query(filters): Query<QueryFilters>,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
@@ -49,6 +54,11 @@ pub async fn list_queries(
         count: elements.len(),
         page_size: pagination.page_size,
         offset: pagination.offset,
+        links: collection_query.pagination_links(
+            result.total,
+            pagination.offset as i64,
+            pagination.page_size as i64,
+        ),
         elements,
     };
     Ok(HalResponse(collection))
@@ -71,6 +81,7 @@ pub async fn get_default_query(
         timeline_visible: false,
         include_subprojects: true,
         timestamps: None,
+        warnings: Vec::new(),
         links: QueryLinks::default_links(),
     }))
 }
@@ -99,6 +110,9 @@ pub async fn create_query(
     user: AuthenticatedUser,
     Json(dto): Json<CreateQueryRequest>,
 ) -> ApiResult<impl IntoResponse> {
+    let timestamps = validate_timestamps(dto.timestamps)?;
+    let group_by = validate_group_by(dto.group_by)?;
+
     let pool = state.pool()?;
     let repo = QueryRepository::new(pool.clone());
 
@@ -109,19 +123,19 @@ pub async fn create_query(
         filters: dto.filters,
         column_names: dto.column_names,
         sort_criteria: dto.sort_criteria,
-        group_by: dto.group_by,
+        group_by,
         display_sums: dto.sums,
         show_hierarchies: dto.show_hierarchies,
         include_subprojects: dto.include_subprojects,
         timeline_visible: dto.timeline_visible,
-        timestamps: dto.timestamps,
+        timestamps,
     };
 
     let query = repo
         .create(create_dto)
         .await
         .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
@@ -133,6 +147,28 @@ pub async fn create_query(
     Ok((StatusCode::CREATED, HalResponse(QueryResponse::from_query_with_starred(qws))))
 }
 
+/// Reject unparseable timestamps and store them in canonical form
+fn validate_timestamps(timestamps: Option<String>) -> ApiResult<Option<String>> {
+    timestamps
+        .map(|value| {
+            Timestamps::parse(&value)
+                .map(|parsed| parsed.to_string())
+                .map_err(|e| ApiError::invalid_property("timestamps", e.to_string()))
+        })
+        .transpose()
+}
+
+/// Reject groupings that cannot be parsed, e.g. a granularity of an
+/// attribute that is not a date
+fn validate_group_by(group_by: Option<String>) -> ApiResult<Option<String>> {
+    match group_by {
+        Some(value) if GroupBy::parse(&value).is_none() => {
+            Err(ApiError::invalid_property("groupBy", format!("Cannot group by '{}'", value)))
+        }
+        group_by => Ok(group_by),
+    }
+}
+
 /// PATCH /api/v3/queries/:id
 pub async fn update_query(
     State(state): State<AppState>,
@@ -140,6 +176,15 @@ pub async fn update_query(
     Path(id): Path<Id>,
     Json(dto): Json<UpdateQueryRequest>,
 ) -> ApiResult<impl IntoResponse> {
+    let timestamps = match dto.timestamps {
+        Some(value) => Some(validate_timestamps(value)?),
+        None => None,
+    };
+    let group_by = match dto.group_by {
+        Some(value) => Some(validate_group_by(value)?),
+        None => None,
+    };
+
     let pool = state.pool()?;
     let repo = QueryRepository::new(pool.clone());
 
@@ -159,12 +204,12 @@ pub async fn update_query(
         filters: dto.filters,
         column_names: dto.column_names,
         sort_criteria: dto.sort_criteria,
-        group_by: dto.group_by,
+        group_by,
         display_sums: dto.sums,
         show_hierarchies: dto.show_hierarchies,
         include_subprojects: dto.include_subprojects,
         timeline_visible: dto.timeline_visible,
-        timestamps: dto.timestamps,
+        timestamps,
     };
 
     let query = repo
@@ -172,7 +217,7 @@ pub async fn update_query(
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Query", id),
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
@@ -263,6 +308,131 @@ pub async fn unstar_query(
     Ok(HalResponse(QueryResponse::from_query_with_starred(qws)))
 }
 
+/// Subscribe the current user to changes of the query results, or change
+/// the frequency of the subscription. Resumes a suspended subscription.
+///
+/// POST /api/v3/queries/:id/subscription
+pub async fn subscribe_query(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    dto: Option<Json<SubscribeQueryRequest>>,
+) -> ApiResult<impl IntoResponse> {
+    let frequency = match dto.and_then(|Json(dto)| dto.frequency) {
+        Some(name) => SubscriptionFrequency::parse(&name).ok_or_else(|| {
+            ApiError::invalid_property(
+                "frequency",
+                format!("must be one of hourly, daily or weekly, not '{}'", name),
+            )
+        })?,
+        None => SubscriptionFrequency::default(),
+    };
+
+    let pool = state.pool()?;
+    let subscription = QuerySubscriptionRepository::new(pool.clone())
+        .subscribe(id, user.0.id, frequency.as_str())
+        .await
+        .map_err(|e| match e {
+            op_db::RepositoryError::NotFound(_) => ApiError::not_found("Query", id),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+
+    Ok(HalResponse(QuerySubscriptionResponse::from_row(subscription)))
+}
+
+/// DELETE /api/v3/queries/:id/subscription
+pub async fn unsubscribe_query(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let removed = QuerySubscriptionRepository::new(pool.clone())
+        .unsubscribe(id, user.0.id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    if !removed {
+        return Err(ApiError::not_found("QuerySubscription", id));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Export a query's configuration as a shareable document
+///
+/// GET /api/v3/queries/:id/export
+pub async fn export_query(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let repo = QueryRepository::new(pool.clone());
+
+    let row = repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Query", id))?;
+
+    let query = row
+        .to_query()
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let references = repo
+        .references(row.project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(Json(QueryDocument::export(&query, &references)))
+}
+
+/// Create a query from an exported document. Parts of the document that
+/// don't apply to the target project are dropped and reported as warnings.
+///
+/// POST /api/v3/queries/import
+pub async fn import_query(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(dto): Json<ImportQueryRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let document = serde_json::from_value::<QueryDocument>(dto.document)
+        .map_err(|e| op_queries::ImportError::Malformed(e.to_string()))
+        .and_then(|document| document.validate().map(|_| document))
+        .map_err(|e| ApiError::invalid_property(e.property(), e.to_string()))?;
+
+    let pool = state.pool()?;
+    let repo = QueryRepository::new(pool.clone());
+
+    let references = repo
+        .references(dto.project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let imported = document
+        .import(dto.project_id, &references)
+        .map_err(|e| ApiError::invalid_property(e.property(), e.to_string()))?;
+
+    let query = repo
+        .create(op_db::CreateQueryDto::from_query(&imported.query, user.0.id))
+        .await
+        .map_err(|e| match e {
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+
+    let qws = op_db::QueryWithStarred {
+        query,
+        starred: false,
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        HalResponse(ImportQueryResponse {
+            query: QueryResponse::from_query_with_starred(qws),
+            warnings: imported.warnings.iter().map(ToString::to_string).collect(),
+        }),
+    ))
+}
+
 /// GET /api/v3/queries/available_projects
 pub async fn available_projects(
     State(state): State<AppState>,
@@ -310,6 +480,59 @@ pub async fn query_form(
     }))
 }
 
+/// GET /api/v3/queries/filter_instance_schemas
+///
+/// Schemas of the built-in filters, followed by those of the work package
+/// custom fields of `projectId`, or of every project without it
+pub async fn list_filter_instance_schemas(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(filters): Query<QueryFilters>,
+) -> ApiResult<impl IntoResponse> {
+    let definitions = filter_definitions(&state, filters.project_id).await?;
+    let elements: Vec<serde_json::Value> = definitions.iter().map(FilterSchemaRepresenter::represent).collect();
+    let total = elements.len() as i64;
+
+    Ok(HalResponse(
+        HalCollection::new("Collection", elements, total, total, 0).with_link("self", HalLink::new(SCHEMAS_PATH)),
+    ))
+}
+
+/// GET /api/v3/queries/filter_instance_schemas/:id
+pub async fn get_filter_instance_schema(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let definition = match registry::find_by_name(&id) {
+        Some(definition) => definition,
+        None => filter_definitions(&state, None)
+            .await?
+            .into_iter()
+            .find(|definition| definition.name == id)
+            .ok_or_else(|| ApiError::not_found("QueryFilterInstanceSchema", &id))?,
+    };
+
+    Ok(HalResponse(FilterSchemaRepresenter::represent(&definition)))
+}
+
+/// Built-in filters and those of the custom fields available in the project
+async fn filter_definitions(state: &AppState, project_id: Option<Id>) -> ApiResult<Vec<FilterDefinition>> {
+    let pool = state.pool()?;
+    let custom_fields = QueryRepository::new(pool.clone())
+        .filterable_custom_fields(project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let mut definitions = registry::filters();
+    definitions.extend(
+        custom_fields
+            .iter()
+            .map(|field| FilterDefinition::custom_field(field.id, &field.name, &field.field_format)),
+    );
+    Ok(definitions)
+}
+
 // Query parameters
 #[derive(Debug, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -351,7 +574,28 @@ pub struct UpdateQueryRequest {
     pub timestamps: Option<Option<String>>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscribeQueryRequest {
+    /// `hourly`, `daily` (the default) or `weekly`
+    pub frequency: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportQueryRequest {
+    pub project_id: Option<i64>,
+    pub document: serde_json::Value,
+}
+
 // Response DTOs
+#[derive(Debug, Serialize)]
+struct ImportQueryResponse {
+    #[serde(flatten)]
+    query: QueryResponse,
+    warnings: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct QueryCollection {
@@ -361,6 +605,8 @@ struct QueryCollection {
     count: usize,
     page_size: usize,
     offset: usize,
+    #[serde(rename = "_links")]
+    links: HalLinks,
     #[serde(rename = "_embedded")]
     elements: Vec<QueryResponse>,
 }
@@ -380,6 +626,10 @@ struct QueryResponse {
     include_subprojects: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     timestamps: Option<String>,
+    /// Parts of settings stored by the Ruby application that could not be
+    /// read and are left out
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
     #[serde(rename = "_links")]
     links: QueryLinks,
 }
@@ -431,6 +681,10 @@ impl QueryResponse {
         let project_link = project_id.map(|pid| Link {
             href: format!("/api/v3/projects/{}", pid),
         });
+        let warnings = query
+            .load_query()
+            .map(|loaded| loaded.warnings.iter().map(ToString::to_string).collect())
+            .unwrap_or_default();
 
         QueryResponse {
             type_name: "Query".into(),
@@ -443,6 +697,7 @@ impl QueryResponse {
             timeline_visible: query.timeline_visible,
             include_subprojects: query.include_subprojects,
             timestamps: query.timestamps,
+            warnings,
             links: QueryLinks {
                 self_link: Link {
                     href: format!("/api/v3/queries/{}", id),
@@ -462,6 +717,56 @@ impl QueryResponse {
     }
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QuerySubscriptionResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    frequency: String,
+    suspended: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suspended_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checked_at: Option<String>,
+    created_at: String,
+    #[serde(rename = "_links")]
+    links: QuerySubscriptionLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct QuerySubscriptionLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    query: Link,
+    user: Link,
+}
+
+impl QuerySubscriptionResponse {
+    fn from_row(row: QuerySubscriptionRow) -> Self {
+        QuerySubscriptionResponse {
+            type_name: "QuerySubscription".into(),
+            id: row.id,
+            suspended: row.is_suspended(),
+            frequency: row.frequency,
+            suspended_reason: row.suspended_reason,
+            checked_at: row.checked_at.map(|at| at.to_rfc3339()),
+            created_at: row.created_at.to_rfc3339(),
+            links: QuerySubscriptionLinks {
+                self_link: Link {
+                    href: format!("/api/v3/queries/{}/subscription", row.query_id),
+                },
+                query: Link {
+                    href: format!("/api/v3/queries/{}", row.query_id),
+                },
+                user: Link {
+                    href: format!("/api/v3/users/{}", row.user_id),
+                },
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct AvailableProjectsResponse {
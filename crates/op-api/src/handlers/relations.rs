@@ -9,21 +9,29 @@ use axum::{
     Json,
 };
 use op_core::traits::Id;
-use op_db::{relation_type, RelationRepository, Repository};
+use op_db::{relation_type, RelationRepository, Repository, WorkPackageRepository};
+use op_services::work_packages::{Dependency, RescheduleService};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::representers::{CondensedWorkPackageRepresentation, CondensedWorkPackages};
 
 /// List all relations
 ///
 /// GET /api/v3/relations
 pub async fn list_relations(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     pagination: Pagination,
     Query(filters): Query<RelationFilters>,
 ) -> ApiResult<impl IntoResponse> {
+    if let Some(involved) = &filters.involved {
+        let graph = relation_graph(&state, &user, involved, filters.expand.as_deref()).await?;
+        return Ok(HalResponse(graph));
+    }
+
     let pool = state.pool()?;
     let repo = RelationRepository::new(pool.clone());
 
@@ -80,6 +88,94 @@ pub async fn list_relations(
     Ok(HalResponse(collection))
 }
 
+/// The relations among the work packages of `involved`, a JSON array of
+/// ids, for drawing their dependency graph. With `expand=neighbors` the
+/// relations to their immediate neighbors are included too, embedding the
+/// neighbor condensed. Relations to work packages the user cannot see are
+/// left out.
+async fn relation_graph(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    involved: &str,
+    expand: Option<&str>,
+) -> ApiResult<RelationCollection> {
+    let ids: BTreeSet<Id> = serde_json::from_str::<Vec<Id>>(involved)
+        .map_err(|_| ApiError::bad_request("involved must be a JSON array of work package ids"))?
+        .into_iter()
+        .collect();
+    let limit = state.config.relation_graph_limit;
+    if ids.len() > limit {
+        return Err(ApiError::bad_request(format!(
+            "The relation graph may involve at most {} work packages.",
+            limit
+        )));
+    }
+    let neighbors = match expand {
+        None => false,
+        Some("neighbors") => true,
+        Some(other) => return Err(ApiError::bad_request(format!("Unknown expansion: {}", other))),
+    };
+
+    let pool = state.pool()?;
+    let repo = RelationRepository::new(pool.clone());
+    let ids: Vec<Id> = ids.into_iter().collect();
+    let mut rows = repo
+        .find_among(&ids)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let mut leaving = Vec::new();
+    if neighbors {
+        leaving = repo
+            .find_leaving(&ids)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    }
+
+    let outside = |row: &op_db::RelationRow| {
+        if ids.binary_search(&row.from_id).is_ok() {
+            row.to_id
+        } else {
+            row.from_id
+        }
+    };
+    let visible_to = (!user.0.is_admin()).then(|| user.0.id());
+    let visible = CondensedWorkPackages::load(
+        &WorkPackageRepository::new(pool.clone()),
+        ids.iter().copied().chain(leaving.iter().map(outside)),
+        visible_to,
+    )
+    .await
+    .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    rows.retain(|row| visible.get(row.from_id).is_some() && visible.get(row.to_id).is_some());
+    let mut elements: Vec<RelationResponse> = rows.into_iter().map(RelationResponse::from_row).collect();
+    for row in leaving {
+        if visible.get(row.from_id).is_none() || visible.get(row.to_id).is_none() {
+            continue;
+        }
+        let neighbor = outside(&row);
+        let embedded = visible.get(neighbor).cloned();
+        let embedded = if neighbor == row.from_id {
+            RelationEmbedded { from: embedded, to: None }
+        } else {
+            RelationEmbedded { from: None, to: embedded }
+        };
+        let mut element = RelationResponse::from_row(row);
+        element.embedded = Some(embedded);
+        elements.push(element);
+    }
+    elements.sort_by_key(|element| element.id);
+
+    Ok(RelationCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        page_size: elements.len(),
+        offset: 0,
+        elements,
+    })
+}
+
 /// Get a single relation
 ///
 /// GET /api/v3/relations/:id
@@ -123,10 +219,13 @@ pub async fn create_relation(
         .create(create_dto)
         .await
         .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
+    if let Some(dep) = Dependency::of(&row) {
+        reschedule_successors(&state, &[dep.successor_id]).await?;
+    }
 
     Ok((StatusCode::CREATED, HalResponse(RelationResponse::from_row(row))))
 }
@@ -153,13 +252,35 @@ pub async fn update_relation(
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Relation", id),
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
+    // A longer lag may push the successor back
+    if let Some(dep) = Dependency::of(&row).filter(|_| dto.lag.is_some()) {
+        reschedule_successors(&state, &[dep.successor_id]).await?;
+    }
 
     Ok(HalResponse(RelationResponse::from_row(row)))
 }
 
+/// Move the successors of the work packages, transitively, to the dates
+/// their predecessors and the lags of the relations allow
+pub(crate) async fn reschedule_successors(state: &AppState, work_package_ids: &[Id]) -> ApiResult<()> {
+    let pool = state.pool()?;
+    let moved = RescheduleService::new()
+        .call(
+            &WorkPackageRepository::new(pool.clone()),
+            &RelationRepository::new(pool.clone()),
+            work_package_ids,
+        )
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let project_ids: Vec<Id> = moved.iter().map(|row| row.project_id).collect();
+    state.work_packages_changed(&project_ids).await;
+
+    Ok(())
+}
+
 /// Delete a relation
 ///
 /// DELETE /api/v3/relations/:id
@@ -228,6 +349,9 @@ pub async fn list_work_package_relations(
 pub struct RelationFilters {
     pub work_package_id: Option<i64>,
     pub relation_type: Option<String>,
+    /// JSON array of the work package ids whose relation graph to list
+    pub involved: Option<String>,
+    pub expand: Option<String>,
 }
 
 // Request types
@@ -279,6 +403,17 @@ struct RelationResponse {
     updated_at: String,
     #[serde(rename = "_links")]
     links: RelationLinks,
+    #[serde(rename = "_embedded", skip_serializing_if = "Option::is_none")]
+    embedded: Option<RelationEmbedded>,
+}
+
+/// The neighbor at the end of a relation leaving a relation graph
+#[derive(Debug, Serialize)]
+struct RelationEmbedded {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<CondensedWorkPackageRepresentation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<CondensedWorkPackageRepresentation>,
 }
 
 #[derive(Debug, Serialize)]
@@ -324,6 +459,7 @@ impl RelationResponse {
                     href: format!("/api/v3/work_packages/{}", to_id),
                 },
             },
+            embedded: None,
         }
     }
 }
@@ -0,0 +1,241 @@
+//! Report API handlers
+//!
+//! Charts of how a project's work packages moved through the statuses,
+//! reconstructed from their journals by the
+//! [`ReportingService`](op_services::reporting::ReportingService).
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Duration, NaiveDate};
+use op_core::representations::{HalLink, HalLinks};
+use op_core::traits::Id;
+use op_db::{JournalRepository, MemberRepository, ProjectRepository, Repository, StatusRepository, StatusRow};
+use op_services::permissions::PermissionService;
+use op_services::reporting::{CumulativeFlow, Granularity, ReportRange, ReportingService};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser};
+
+/// Days a report covers when `from` is omitted
+const DEFAULT_REPORT_DAYS: i64 = 30;
+
+#[derive(Debug, Deserialize)]
+pub struct CumulativeFlowParams {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub granularity: Option<String>,
+}
+
+/// GET /api/v3/projects/:id/reports/cumulative_flow
+///
+/// Work packages of the project per status at the end of each day, week,
+/// month or quarter (`granularity`) from `from` to `to`, by default the
+/// last 30 days by day, and how many were closed during each of them.
+pub async fn get_cumulative_flow(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Query(params): Query<CumulativeFlowParams>,
+) -> ApiResult<impl IntoResponse> {
+    let granularity = match params.granularity.as_deref() {
+        None => Granularity::Day,
+        Some(name) => Granularity::parse(name).ok_or_else(|| {
+            ApiError::invalid_property("granularity", format!("must be day, week, month or quarter, not '{}'", name))
+        })?,
+    };
+
+    let pool = state.pool()?;
+    ProjectRepository::new(pool.clone())
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|project| !user.is_anonymous() || (project.public && project.active))
+        .ok_or_else(|| ApiError::not_found("Project", id))?;
+
+    let allowed = PermissionService::new(MemberRepository::new(pool.clone()))
+        .allowed_in_project(&user, "view_work_packages", id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if !allowed {
+        return Err(ApiError::forbidden("You are not allowed to view the work packages of this project."));
+    }
+
+    let service = ReportingService::new(JournalRepository::new(pool.clone()), state.status_history.clone());
+    let today = service.today();
+    let to = parse_date("to", params.to.as_deref())?.unwrap_or(today);
+    let from = parse_date("from", params.from.as_deref())?.unwrap_or(to - Duration::days(DEFAULT_REPORT_DAYS - 1));
+    let range = ReportRange::new(from, to, granularity, today).map_err(ApiError::validation)?;
+
+    let statuses = StatusRepository::new(pool.clone())
+        .find_all(1000, 0)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let closed: HashSet<Id> = statuses.iter().filter(|status| status.is_closed()).map(|status| status.id).collect();
+
+    let flow = service
+        .cumulative_flow(id, &range, &closed)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(Json(CumulativeFlowResponse::new(id, &range, &flow, &statuses)))
+}
+
+/// Date of an ISO 8601 parameter such as `2024-03-01`
+fn parse_date(property: &str, value: Option<&str>) -> ApiResult<Option<NaiveDate>> {
+    value
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ApiError::invalid_property(property, format!("is not a date: '{}'", value)))
+        })
+        .transpose()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CumulativeFlowResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    from: NaiveDate,
+    to: NaiveDate,
+    granularity: &'static str,
+    buckets: Vec<ReportBucket>,
+    /// Work packages per status at the end of each bucket
+    series: Vec<StatusSeries>,
+    /// Work packages closed during each bucket
+    throughput: Vec<i64>,
+    #[serde(rename = "_links")]
+    links: HalLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportBucket {
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusSeries {
+    counts: Vec<i64>,
+    #[serde(rename = "_links")]
+    links: HalLinks,
+}
+
+impl CumulativeFlowResponse {
+    /// Series in the order of the statuses, each titled with the current
+    /// name of its status; those of statuses deleted since go untitled last
+    fn new(project_id: Id, range: &ReportRange, flow: &CumulativeFlow, statuses: &[StatusRow]) -> Self {
+        let positions: HashMap<Id, (i32, &str)> = statuses
+            .iter()
+            .map(|status| (status.id, (status.position, status.name.as_str())))
+            .collect();
+        let mut status_ids: Vec<Id> = flow.status_ids().into_iter().collect();
+        status_ids.sort_by_key(|id| (positions.get(id).map_or(i32::MAX, |(position, _)| *position), *id));
+
+        let series = status_ids
+            .into_iter()
+            .map(|status_id| {
+                let href = format!("/api/v3/statuses/{}", status_id);
+                let link = match positions.get(&status_id) {
+                    Some((_, name)) => HalLink::with_title(href, *name),
+                    None => HalLink::new(href),
+                };
+                StatusSeries {
+                    counts: flow.series(status_id),
+                    links: HalLinks::new().with("status", link),
+                }
+            })
+            .collect();
+
+        Self {
+            type_name: "CumulativeFlow".into(),
+            from: range.from,
+            to: range.to,
+            granularity: range.granularity.as_str(),
+            buckets: flow
+                .buckets
+                .iter()
+                .map(|bucket| ReportBucket {
+                    start: bucket.bucket.start,
+                    end: bucket.bucket.end,
+                })
+                .collect(),
+            series,
+            throughput: flow.buckets.iter().map(|bucket| bucket.closed).collect(),
+            links: HalLinks::new()
+                .with(
+                    "self",
+                    HalLink::new(format!(
+                        "/api/v3/projects/{}/reports/cumulative_flow?from={}&to={}&granularity={}",
+                        project_id,
+                        range.from,
+                        range.to,
+                        range.granularity.as_str()
+                    )),
+                )
+                .with("project", HalLink::new(format!("/api/v3/projects/{}", project_id))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use op_services::reporting::{Bucket, FlowBucket};
+    use std::collections::BTreeMap;
+
+    fn status(id: Id, name: &str, position: i32) -> StatusRow {
+        StatusRow {
+            id,
+            name: name.into(),
+            is_closed: false,
+            is_default: false,
+            is_readonly: false,
+            position,
+            default_done_ratio: 0,
+            color_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_series_carry_the_current_status_names() {
+        let day: NaiveDate = "2024-03-01".parse().unwrap();
+        let range = ReportRange::new(day, day, Granularity::Day, day).unwrap();
+        let flow = CumulativeFlow {
+            buckets: vec![FlowBucket {
+                bucket: Bucket { start: day, end: day },
+                counts: BTreeMap::from([(1, 2), (2, 1), (9, 4)]),
+                closed: 1,
+            }],
+        };
+        // Status 2 was renamed since and sorts first, status 9 was deleted
+        let statuses = vec![status(1, "New", 2), status(2, "Doing", 1)];
+
+        let response = serde_json::to_value(CumulativeFlowResponse::new(5, &range, &flow, &statuses)).unwrap();
+        let titles: Vec<_> = response["series"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|series| (series["_links"]["status"]["title"].clone(), series["counts"][0].clone()))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                (serde_json::json!("Doing"), serde_json::json!(1)),
+                (serde_json::json!("New"), serde_json::json!(2)),
+                (serde_json::Value::Null, serde_json::json!(4)),
+            ]
+        );
+        assert_eq!(response["throughput"], serde_json::json!([1]));
+        assert_eq!(response["buckets"][0]["end"], "2024-03-01");
+    }
+}
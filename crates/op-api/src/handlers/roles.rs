@@ -8,12 +8,13 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use op_core::audit::{AuditEvent, AuditEventType};
 use op_core::traits::Id;
 use op_db::{Repository, RoleRepository};
 use serde::{Deserialize, Serialize};
 
 use crate::error::{ApiError, ApiResult};
-use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::extractors::{AppState, AuthenticatedUser, ClientInfo, HalResponse, Pagination};
 
 /// List all roles
 ///
@@ -89,10 +90,11 @@ pub async fn get_role(
 pub async fn create_role(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Json(dto): Json<CreateRoleRequest>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can create roles."));
+        return Err(state.deny(&user, &client, "create_role", "Only administrators can create roles.").await);
     }
 
     let pool = state.pool()?;
@@ -124,6 +126,16 @@ pub async fn create_role(
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
+    state
+        .audit(
+            &user,
+            &client,
+            AuditEvent::new(AuditEventType::RoleChanged)
+                .target("Role", row.id)
+                .detail("change", "created"),
+        )
+        .await;
+
     Ok((StatusCode::CREATED, HalResponse(RoleResponse::from_row(row, permissions))))
 }
 
@@ -133,11 +145,12 @@ pub async fn create_role(
 pub async fn update_role(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
     Json(dto): Json<UpdateRoleRequest>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can update roles."));
+        return Err(state.deny(&user, &client, "update_role", "Only administrators can update roles.").await);
     }
 
     let pool = state.pool()?;
@@ -169,6 +182,16 @@ pub async fn update_role(
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
+    state
+        .audit(
+            &user,
+            &client,
+            AuditEvent::new(AuditEventType::RoleChanged)
+                .target("Role", id)
+                .detail("change", "updated"),
+        )
+        .await;
+
     Ok(HalResponse(RoleResponse::from_row(row, permissions)))
 }
 
@@ -178,10 +201,11 @@ pub async fn update_role(
 pub async fn delete_role(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can delete roles."));
+        return Err(state.deny(&user, &client, "delete_role", "Only administrators can delete roles.").await);
     }
 
     let pool = state.pool()?;
@@ -195,6 +219,16 @@ pub async fn delete_role(
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
+    state
+        .audit(
+            &user,
+            &client,
+            AuditEvent::new(AuditEventType::RoleChanged)
+                .target("Role", id)
+                .detail("change", "deleted"),
+        )
+        .await;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -0,0 +1,283 @@
+//! Work package shares API handlers
+//!
+//! Mirrors: lib/api/v3/shares/*
+//!
+//! Shares give users outside the project access to a single work package.
+//! Users who have no account yet are invited by email.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use op_core::traits::Id;
+use op_db::MemberRepository;
+use op_services::permissions::PermissionService;
+use op_services::shares::{
+    PgShareStore, Share, ShareParams, ShareRole, ShareService, ShareStore, ShareWith,
+};
+use op_services::ServiceResult;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+use crate::handlers::watchers::ensure_work_package_visible;
+
+const SHARE_WORK_PACKAGES: &str = "share_work_packages";
+const VIEW_SHARED_WORK_PACKAGES: &str = "view_shared_work_packages";
+
+/// List the shares of a work package
+///
+/// GET /api/v3/work_packages/:work_package_id/shares
+pub async fn list_work_package_shares(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(work_package_id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let work_package = ensure_work_package_visible(pool, &user, work_package_id).await?;
+
+    let permissions = permissions(pool);
+    if !allowed(&permissions, &user, VIEW_SHARED_WORK_PACKAGES, work_package.project_id).await?
+        && !allowed(&permissions, &user, SHARE_WORK_PACKAGES, work_package.project_id).await?
+    {
+        return Err(ApiError::forbidden("You are not authorized to view the shares of this work package."));
+    }
+
+    let store = PgShareStore::new(pool.clone());
+    let shares = into_api_result(
+        ShareService::new(&user, &store, &permissions)
+            .list(work_package.id, work_package.project_id)
+            .await,
+    )?;
+
+    let elements: Vec<ShareResponse> = shares.into_iter().map(ShareResponse::from_share).collect();
+    Ok(HalResponse(ShareCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        elements,
+    }))
+}
+
+/// Share a work package with a user, inviting them by email if needed
+///
+/// POST /api/v3/work_packages/:work_package_id/shares
+pub async fn create_work_package_share(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(work_package_id): Path<Id>,
+    Json(dto): Json<CreateShareDto>,
+) -> ApiResult<impl IntoResponse> {
+    let role = parse_role(&dto.role)?;
+    let recipient = match (dto.user_id, dto.email) {
+        (Some(user_id), None) => ShareWith::User(user_id),
+        (None, Some(email)) => ShareWith::Email(email),
+        _ => {
+            return Err(ApiError::invalid_property(
+                "userId",
+                "either userId or email must be given",
+            ))
+        }
+    };
+
+    let pool = state.pool()?;
+    let work_package = ensure_work_package_visible(pool, &user, work_package_id).await?;
+    let permissions = permissions(pool);
+    authorize_sharing(&permissions, &user, work_package.project_id).await?;
+
+    let store = PgShareStore::new(pool.clone());
+    let share = into_api_result(
+        ShareService::new(&user, &store, &permissions)
+            .with_jobs(state.jobs.as_ref())
+            .create(work_package.id, work_package.project_id, ShareParams { recipient, role })
+            .await,
+    )?;
+
+    Ok((StatusCode::CREATED, HalResponse(ShareResponse::from_share(share))))
+}
+
+/// Change the role of a share
+///
+/// PATCH /api/v3/work_packages/:work_package_id/shares/:id
+pub async fn update_work_package_share(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((work_package_id, share_id)): Path<(Id, Id)>,
+    Json(dto): Json<UpdateShareDto>,
+) -> ApiResult<impl IntoResponse> {
+    let role = parse_role(&dto.role)?;
+
+    let pool = state.pool()?;
+    let work_package = ensure_work_package_visible(pool, &user, work_package_id).await?;
+    let permissions = permissions(pool);
+    authorize_sharing(&permissions, &user, work_package.project_id).await?;
+
+    let store = PgShareStore::new(pool.clone());
+    ensure_share_exists(&store, work_package.id, share_id).await?;
+
+    let share = into_api_result(
+        ShareService::new(&user, &store, &permissions)
+            .update(work_package.id, work_package.project_id, share_id, role)
+            .await,
+    )?;
+
+    Ok(HalResponse(ShareResponse::from_share(share)))
+}
+
+/// Remove a share, revoking the user's access to the work package
+///
+/// DELETE /api/v3/work_packages/:work_package_id/shares/:id
+pub async fn delete_work_package_share(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((work_package_id, share_id)): Path<(Id, Id)>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let work_package = ensure_work_package_visible(pool, &user, work_package_id).await?;
+    let permissions = permissions(pool);
+    authorize_sharing(&permissions, &user, work_package.project_id).await?;
+
+    let store = PgShareStore::new(pool.clone());
+    ensure_share_exists(&store, work_package.id, share_id).await?;
+
+    into_api_result(
+        ShareService::new(&user, &store, &permissions)
+            .delete(work_package.id, work_package.project_id, share_id)
+            .await,
+    )?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn permissions(pool: &PgPool) -> PermissionService<MemberRepository> {
+    PermissionService::new(MemberRepository::new(pool.clone()))
+}
+
+async fn allowed(
+    permissions: &PermissionService<MemberRepository>,
+    user: &AuthenticatedUser,
+    permission: &str,
+    project_id: Id,
+) -> ApiResult<bool> {
+    permissions
+        .allowed_in_project(user, permission, project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))
+}
+
+async fn authorize_sharing(
+    permissions: &PermissionService<MemberRepository>,
+    user: &AuthenticatedUser,
+    project_id: Id,
+) -> ApiResult<()> {
+    if allowed(permissions, user, SHARE_WORK_PACKAGES, project_id).await? {
+        Ok(())
+    } else {
+        Err(ApiError::forbidden("You are not authorized to share this work package."))
+    }
+}
+
+async fn ensure_share_exists(store: &PgShareStore, work_package_id: Id, share_id: Id) -> ApiResult<()> {
+    store
+        .find_share(share_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|share| share.work_package_id == work_package_id)
+        .map(|_| ())
+        .ok_or_else(|| ApiError::not_found("Share", share_id))
+}
+
+fn parse_role(role: &str) -> ApiResult<ShareRole> {
+    ShareRole::parse(role).ok_or_else(|| {
+        ApiError::invalid_property("role", format!("must be one of view, comment or edit, not '{}'", role))
+    })
+}
+
+fn into_api_result<T>(result: ServiceResult<T>) -> ApiResult<T> {
+    if result.is_failure() {
+        return Err(ApiError::Validation(result.errors().clone()));
+    }
+    Ok(result.unwrap())
+}
+
+// Request types
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateShareDto {
+    /// Id of the user to share with
+    pub user_id: Option<Id>,
+    /// Email address of the user to share with, invited if unknown
+    pub email: Option<String>,
+    /// One of view, comment or edit
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateShareDto {
+    pub role: String,
+}
+
+// Response types
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShareCollection {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    #[serde(rename = "_embedded")]
+    elements: Vec<ShareResponse>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShareResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    role: ShareRole,
+    created_at: String,
+    updated_at: String,
+    #[serde(rename = "_links")]
+    links: ShareLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct ShareLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    entity: Link,
+    principal: Link,
+}
+
+#[derive(Debug, Serialize)]
+struct Link {
+    href: String,
+}
+
+impl ShareResponse {
+    fn from_share(share: Share) -> Self {
+        Self {
+            type_name: "Share".into(),
+            id: share.id,
+            role: share.role,
+            created_at: share.created_at.to_rfc3339(),
+            updated_at: share.updated_at.to_rfc3339(),
+            links: ShareLinks {
+                self_link: Link {
+                    href: format!("/api/v3/work_packages/{}/shares/{}", share.work_package_id, share.id),
+                },
+                entity: Link {
+                    href: format!("/api/v3/work_packages/{}", share.work_package_id),
+                },
+                principal: Link {
+                    href: format!("/api/v3/users/{}", share.user_id),
+                },
+            },
+        }
+    }
+}
@@ -3,7 +3,7 @@
 //! Mirrors: lib/api/v3/statuses/*
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
@@ -102,7 +102,7 @@ pub async fn create_status(
         .create(create_dto)
         .await
         .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
@@ -141,7 +141,7 @@ pub async fn update_status(
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Status", id),
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
@@ -152,10 +152,14 @@ pub async fn update_status(
 /// Delete a status (admin only)
 ///
 /// DELETE /api/v3/statuses/:id
+///
+/// Refused while work packages use the status, unless `reassignTo` names
+/// the status to move them to first.
 pub async fn delete_status(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     Path(id): Path<Id>,
+    Query(params): Query<DeleteReassigningParams>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
         return Err(ApiError::forbidden("Only administrators can delete statuses."));
@@ -164,18 +168,44 @@ pub async fn delete_status(
     let pool = state.pool()?;
     let repo = StatusRepository::new(pool.clone());
 
-    repo.delete(id)
-        .await
-        .map_err(|e| match e {
-            op_db::RepositoryError::NotFound(_) => ApiError::not_found("Status", id),
-            op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
-            _ => ApiError::internal(format!("Database error: {}", e)),
-        })?;
+    let map_error = |e: op_db::RepositoryError| match e {
+        op_db::RepositoryError::NotFound(_) => ApiError::not_found("Status", id),
+        op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
+        op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+        _ => ApiError::internal(format!("Database error: {}", e)),
+    };
 
-    Ok(StatusCode::NO_CONTENT)
+    match params.reassign_to {
+        Some(reassign_to) => {
+            let reassigned_work_packages = repo
+                .delete_reassigning(id, reassign_to, user.id())
+                .await
+                .map_err(map_error)?;
+            Ok(Json(ReassignedResponse { reassigned_work_packages }).into_response())
+        }
+        None => {
+            repo.delete(id).await.map_err(map_error)?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+    }
 }
 
 // Request types
+
+/// Query of the admin DELETE endpoints of statuses, types and priorities
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteReassigningParams {
+    /// Move work packages using the deleted record to this one
+    pub reassign_to: Option<Id>,
+}
+
+/// Response of a delete that reassigned work packages
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReassignedResponse {
+    pub reassigned_work_packages: u64,
+}
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateStatusRequest {
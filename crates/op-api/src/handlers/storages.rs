@@ -0,0 +1,416 @@
+//! Storages API handlers
+//!
+//! Mirrors: modules/storages/lib/api/v3/storages/*
+//!
+//! Storages are external file stores whose files can be linked to work
+//! packages. Only administrators set them up; everyone logged in can see
+//! which storages exist.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use op_core::traits::Id;
+use op_db::{normalize_host, CreateStorageDto, Repository, StorageRepository, StorageRow, UpdateStorageDto};
+use op_models::StorageType;
+use op_services::storages::provider;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+
+/// List all storages
+///
+/// GET /api/v3/storages
+pub async fn list_storages(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let repo = StorageRepository::new(pool.clone());
+
+    let rows = repo
+        .find_all(i64::MAX, 0)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let elements: Vec<StorageResponse> = rows
+        .into_iter()
+        .map(|row| StorageResponse::from_row(row, user.0.is_admin()))
+        .collect();
+
+    Ok(HalResponse(StorageCollection {
+        type_name: "Collection".into(),
+        total: elements.len(),
+        count: elements.len(),
+        elements,
+    }))
+}
+
+/// Get a single storage
+///
+/// GET /api/v3/storages/:id
+pub async fn get_storage(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let row = find_storage(&StorageRepository::new(pool.clone()), id).await?;
+
+    Ok(HalResponse(StorageResponse::from_row(row, user.0.is_admin())))
+}
+
+/// Set up a storage; the storage has to be reachable at its host
+///
+/// POST /api/v3/storages
+pub async fn create_storage(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(dto): Json<CreateStorageRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can create storages."));
+    }
+
+    let storage_type = match dto.links.storage_type {
+        Some(link) => StorageType::from_urn(&link.href)
+            .ok_or_else(|| ApiError::invalid_property("type", format!("'{}' is not a storage type", link.href)))?,
+        None => StorageType::default(),
+    };
+    let host = dto
+        .links
+        .origin
+        .map(|link| normalize_host(&link.href))
+        .ok_or_else(|| ApiError::invalid_property("host", "can't be blank"))?;
+    validate_connection(storage_type, &host).await?;
+
+    let pool = state.pool()?;
+    let repo = StorageRepository::new(pool.clone());
+    let row = repo
+        .create(CreateStorageDto {
+            provider_type: storage_type.provider_type().to_string(),
+            name: dto.name,
+            host,
+            creator_id: user.0.id,
+            oauth_client_id: None,
+            oauth_client_secret: None,
+        })
+        .await
+        .map_err(|e| match e {
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+            op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
+            _ => ApiError::internal(format!("Database error: {}", e)),
+        })?;
+
+    Ok((StatusCode::CREATED, HalResponse(StorageResponse::from_row(row, true))))
+}
+
+/// Rename a storage or move it to another host
+///
+/// PATCH /api/v3/storages/:id
+pub async fn update_storage(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<UpdateStorageRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can update storages."));
+    }
+
+    let pool = state.pool()?;
+    let repo = StorageRepository::new(pool.clone());
+    let storage = find_storage(&repo, id).await?;
+
+    let host = dto.links.and_then(|links| links.origin).map(|link| normalize_host(&link.href));
+    if let (Some(host), Some(storage_type)) = (&host, storage.storage_type()) {
+        if *host != storage.host {
+            validate_connection(storage_type, host).await?;
+        }
+    }
+
+    let row = repo
+        .update(
+            id,
+            UpdateStorageDto {
+                name: dto.name,
+                host,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| storage_error(e, id))?;
+
+    Ok(HalResponse(StorageResponse::from_row(row, true)))
+}
+
+/// Set the OAuth client OpenProject authenticates with at the storage
+///
+/// POST /api/v3/storages/:id/oauth_client_credentials
+pub async fn set_oauth_client_credentials(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<OAuthClientCredentialsRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can configure storages."));
+    }
+    if dto.client_id.trim().is_empty() {
+        return Err(ApiError::invalid_property("clientId", "can't be blank"));
+    }
+
+    let pool = state.pool()?;
+    let row = StorageRepository::new(pool.clone())
+        .update(
+            id,
+            UpdateStorageDto {
+                oauth_client_id: Some(dto.client_id),
+                oauth_client_secret: dto.client_secret,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| storage_error(e, id))?;
+
+    Ok((StatusCode::CREATED, HalResponse(StorageResponse::from_row(row, true))))
+}
+
+/// Delete a storage along with all links to its files
+///
+/// DELETE /api/v3/storages/:id
+pub async fn delete_storage(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can delete storages."));
+    }
+
+    let pool = state.pool()?;
+    StorageRepository::new(pool.clone())
+        .delete(id)
+        .await
+        .map_err(|e| storage_error(e, id))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn find_storage(repo: &StorageRepository, id: Id) -> ApiResult<StorageRow> {
+    repo.find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Storage", id))
+}
+
+/// Id of the storage an API href points to
+pub(crate) fn storage_id_from_href(href: &str) -> Option<Id> {
+    href.strip_prefix("/api/v3/storages/")?.parse().ok()
+}
+
+async fn validate_connection(storage_type: StorageType, host: &str) -> ApiResult<()> {
+    let Some(provider) = provider(storage_type, host) else {
+        return Ok(());
+    };
+    provider
+        .validate_connection()
+        .await
+        .map_err(|e| ApiError::invalid_property("host", e.to_string()))
+}
+
+fn storage_error(e: op_db::RepositoryError, id: Id) -> ApiError {
+    match e {
+        op_db::RepositoryError::NotFound(_) => ApiError::not_found("Storage", id),
+        op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+        op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
+        _ => ApiError::internal(format!("Database error: {}", e)),
+    }
+}
+
+// Request types
+#[derive(Debug, Deserialize)]
+pub struct HrefRequest {
+    pub href: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StorageRequestLinks {
+    /// Base URL of the storage
+    pub origin: Option<HrefRequest>,
+    /// Storage type URN, Nextcloud if left out
+    #[serde(rename = "type")]
+    pub storage_type: Option<HrefRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateStorageRequest {
+    pub name: String,
+    #[serde(rename = "_links")]
+    pub links: StorageRequestLinks,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateStorageRequest {
+    pub name: Option<String>,
+    #[serde(rename = "_links")]
+    pub links: Option<StorageRequestLinks>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthClientCredentialsRequest {
+    pub client_id: String,
+    /// Kept from the previous client if left out
+    pub client_secret: Option<String>,
+}
+
+// Response types
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageCollection {
+    #[serde(rename = "_type")]
+    type_name: String,
+    total: usize,
+    count: usize,
+    #[serde(rename = "_embedded")]
+    elements: Vec<StorageResponse>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    id: Id,
+    name: String,
+    has_oauth_client_credentials: bool,
+    created_at: String,
+    updated_at: String,
+    #[serde(rename = "_embedded", skip_serializing_if = "Option::is_none")]
+    embedded: Option<StorageEmbedded>,
+    #[serde(rename = "_links")]
+    links: StorageLinks,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageEmbedded {
+    oauth_client_credentials: OAuthClientCredentialsResponse,
+}
+
+/// The client secret is never shown, only whether there is one
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OAuthClientCredentialsResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    client_id: String,
+    confidential: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct StorageLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    #[serde(rename = "type")]
+    storage_type: Link,
+    origin: Link,
+    open: Link,
+}
+
+#[derive(Debug, Serialize)]
+struct Link {
+    href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+}
+
+impl StorageResponse {
+    /// Only administrators see the OAuth client of a storage
+    fn from_row(row: StorageRow, admin: bool) -> Self {
+        let storage_type = row.storage_type();
+        let open = match storage_type {
+            Some(StorageType::Nextcloud) => format!("{}/index.php/apps/files", row.host),
+            _ => row.host.clone(),
+        };
+        let embedded = match (&row.oauth_client_id, admin) {
+            (Some(client_id), true) => Some(StorageEmbedded {
+                oauth_client_credentials: OAuthClientCredentialsResponse {
+                    type_name: "OAuthClientCredentials".into(),
+                    client_id: client_id.clone(),
+                    confidential: row.oauth_client_secret.is_some(),
+                },
+            }),
+            _ => None,
+        };
+
+        StorageResponse {
+            type_name: "Storage".into(),
+            id: row.id,
+            has_oauth_client_credentials: row.oauth_client_id.is_some(),
+            created_at: row.created_at.to_rfc3339(),
+            updated_at: row.updated_at.to_rfc3339(),
+            embedded,
+            links: StorageLinks {
+                self_link: Link {
+                    href: format!("/api/v3/storages/{}", row.id),
+                    title: Some(row.name.clone()),
+                },
+                storage_type: Link {
+                    href: storage_type.map(|t| t.urn()).unwrap_or_default(),
+                    title: storage_type.map(|t| t.name().to_string()),
+                },
+                origin: Link {
+                    href: row.host,
+                    title: None,
+                },
+                open: Link { href: open, title: None },
+            },
+            name: row.name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn storage_row() -> StorageRow {
+        StorageRow {
+            id: 3,
+            provider_type: StorageType::Nextcloud.provider_type().to_string(),
+            name: "Cloud".into(),
+            host: "https://cloud.example.com".into(),
+            creator_id: 1,
+            oauth_client_id: Some("client".into()),
+            oauth_client_secret: Some("secret".into()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_storage_response_hides_oauth_client_from_non_admins() {
+        let json = serde_json::to_value(StorageResponse::from_row(storage_row(), true)).unwrap();
+        assert_eq!(json["_links"]["type"]["href"], "urn:openproject-org:api:v3:storages:Nextcloud");
+        assert_eq!(json["_links"]["open"]["href"], "https://cloud.example.com/index.php/apps/files");
+        assert_eq!(json["_embedded"]["oauthClientCredentials"]["clientId"], "client");
+        assert_eq!(json["_embedded"]["oauthClientCredentials"]["confidential"], true);
+        assert!(json.to_string().find("secret").is_none());
+
+        let json = serde_json::to_value(StorageResponse::from_row(storage_row(), false)).unwrap();
+        assert_eq!(json["hasOauthClientCredentials"], true);
+        assert!(json.get("_embedded").is_none());
+    }
+
+    #[test]
+    fn test_storage_id_from_href() {
+        assert_eq!(storage_id_from_href("/api/v3/storages/3"), Some(3));
+        assert_eq!(storage_id_from_href("/api/v3/projects/3"), None);
+    }
+}
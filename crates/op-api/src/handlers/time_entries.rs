@@ -8,19 +8,23 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use chrono::NaiveDate;
+use op_core::duration::{parse_iso8601_date, DurationValue};
+use op_core::representations::{Collection, CreateTimeEntry, Link, TimeEntry, TimeEntryLinks, UpdateTimeEntry};
 use op_core::traits::Id;
-use op_db::{Repository, TimeEntryRepository, Pagination as DbPagination};
+use op_db::{Repository, TimeEntryGroupBy, TimeEntryReport, TimeEntryRepository, TimeEntryRow, Pagination as DbPagination};
+use op_services::costs::RefreshCostsArgs;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::representers::CollectionQuery;
 
 /// GET /api/v3/time_entries
 pub async fn list_time_entries(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     pagination: Pagination,
+    collection_query: CollectionQuery,
     Query(filters): Query<TimeEntryFilters>,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
@@ -74,20 +78,91 @@ pub async fn list_time_entries(
         (rows, total)
     };
 
-    let elements: Vec<TimeEntryResponse> = rows
+    let elements: Vec<TimeEntry> = rows.into_iter().map(time_entry_response).collect();
+
+    let collection = Collection::new(elements, total as usize, pagination.offset, pagination.page_size).with_links(
+        collection_query.pagination_links(total, pagination.offset as i64, pagination.page_size as i64),
+    );
+    Ok(HalResponse(collection))
+}
+
+/// Hours summed up per user, project, work package, activity, week or
+/// month over a period. Users only see the entries of projects granting
+/// `view_time_entries`, and their own ones where `view_own_time_entries`
+/// is granted.
+///
+/// GET /api/v3/time_entries/aggregate
+pub async fn aggregate_time_entries(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    pagination: Pagination,
+    Query(params): Query<AggregateParams>,
+) -> ApiResult<impl IntoResponse> {
+    let mut group_by = Vec::new();
+    for name in params.group_by.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let group = TimeEntryGroupBy::parse(name)
+            .ok_or_else(|| ApiError::invalid_property("groupBy", format!("Cannot group by '{}'", name)))?;
+        if !group_by.contains(&group) {
+            group_by.push(group);
+        }
+    }
+    if group_by.is_empty() {
+        return Err(ApiError::invalid_property("groupBy", "At least one grouping is required"));
+    }
+
+    let from = parse_iso8601_date(&params.from).map_err(|e| ApiError::invalid_property("from", e.to_string()))?;
+    let to = parse_iso8601_date(&params.to).map_err(|e| ApiError::invalid_property("to", e.to_string()))?;
+    if to < from {
+        return Err(ApiError::invalid_property("to", "The end of the period is before its start"));
+    }
+
+    let user_ids = params
+        .user_ids
+        .as_deref()
+        .map(|ids| {
+            ids.split(',')
+                .map(|id| id.trim().parse::<Id>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| ApiError::invalid_property("userIds", "Expected a comma separated list of user ids"))
+        })
+        .transpose()?;
+
+    let report = TimeEntryReport {
+        project_id: params.project_id,
+        user_ids,
+        visible_to: (!user.is_admin()).then_some(user.id),
+        ..TimeEntryReport::new(from, to, group_by)
+    };
+
+    let pool = state.pool()?;
+    let aggregate = TimeEntryRepository::new(pool.clone())
+        .aggregate(
+            &report,
+            DbPagination {
+                limit: pagination.page_size as i64,
+                offset: pagination.offset as i64,
+            },
+        )
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    let elements: Vec<TimeEntryAggregateResponse> = aggregate
+        .groups
+        .items
         .into_iter()
-        .map(|row| TimeEntryResponse::from_row(row))
+        .map(TimeEntryAggregateResponse::from_row)
         .collect();
 
-    let collection = TimeEntryCollection {
-        type_name: "Collection".into(),
-        total: total as usize,
+    Ok(HalResponse(TimeEntryAggregateCollection {
+        type_name: "TimeEntryAggregate".into(),
+        total: aggregate.groups.total as usize,
         count: elements.len(),
         page_size: pagination.page_size,
         offset: pagination.offset,
-        elements,
-    };
-    Ok(HalResponse(collection))
+        group_by: report.group_by.iter().map(TimeEntryGroupBy::name).collect(),
+        total_hours: aggregate.total_hours,
+        embedded: AggregateEmbedded { elements },
+    }))
 }
 
 /// GET /api/v3/time_entries/:id
@@ -105,32 +180,37 @@ pub async fn get_time_entry(
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("TimeEntry", id))?;
 
-    Ok(HalResponse(TimeEntryResponse::from_row(row)))
+    Ok(HalResponse(time_entry_response(row)))
 }
 
 /// POST /api/v3/time_entries
 pub async fn create_time_entry(
     State(state): State<AppState>,
     user: AuthenticatedUser,
-    Json(dto): Json<CreateTimeEntryDto>,
+    Json(dto): Json<CreateTimeEntry>,
 ) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
-    let repo = TimeEntryRepository::new(pool.clone());
+    let hours = dto
+        .hours
+        .to_hours()
+        .map_err(|e| ApiError::invalid_property("hours", e.to_string()))?;
 
     // Parse spent_on date
     let spent_on = dto
         .spent_on
-        .as_ref()
-        .map(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d"))
+        .as_deref()
+        .map(parse_iso8601_date)
         .transpose()
-        .map_err(|_| ApiError::bad_request("Invalid date format for spent_on. Use YYYY-MM-DD"))?
+        .map_err(|e| ApiError::invalid_property("spentOn", e.to_string()))?
         .unwrap_or_else(|| chrono::Utc::now().date_naive());
 
+    let pool = state.pool()?;
+    let repo = TimeEntryRepository::new(pool.clone());
+
     let create_dto = op_db::CreateTimeEntryDto {
         project_id: dto.project_id,
         user_id: dto.user_id.unwrap_or_else(|| user.id()),
         work_package_id: dto.work_package_id,
-        hours: dto.hours,
+        hours,
         comments: dto.comments,
         activity_id: dto.activity_id,
         spent_on,
@@ -141,8 +221,9 @@ pub async fn create_time_entry(
         .create(create_dto)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    refresh_costs(&state, [row.work_package_id]).await?;
 
-    Ok((StatusCode::CREATED, HalResponse(TimeEntryResponse::from_row(row))))
+    Ok((StatusCode::CREATED, HalResponse(time_entry_response(row))))
 }
 
 /// PATCH /api/v3/time_entries/:id
@@ -150,22 +231,30 @@ pub async fn update_time_entry(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     Path(id): Path<Id>,
-    Json(dto): Json<UpdateTimeEntryDto>,
+    Json(dto): Json<UpdateTimeEntry>,
 ) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
-    let repo = TimeEntryRepository::new(pool.clone());
+    let hours = dto
+        .hours
+        .as_ref()
+        .map(DurationValue::to_hours)
+        .transpose()
+        .map_err(|e| ApiError::invalid_property("hours", e.to_string()))?;
 
     // Parse spent_on date if provided
     let spent_on = dto
         .spent_on
-        .as_ref()
-        .map(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d"))
+        .as_deref()
+        .map(parse_iso8601_date)
         .transpose()
-        .map_err(|_| ApiError::bad_request("Invalid date format for spent_on. Use YYYY-MM-DD"))?;
+        .map_err(|e| ApiError::invalid_property("spentOn", e.to_string()))?;
+
+    let pool = state.pool()?;
+    let repo = TimeEntryRepository::new(pool.clone());
+    let previous = find_time_entry(&repo, id).await?;
 
     let update_dto = op_db::UpdateTimeEntryDto {
         work_package_id: dto.work_package_id,
-        hours: dto.hours,
+        hours,
         comments: dto.comments,
         activity_id: dto.activity_id,
         spent_on,
@@ -178,8 +267,10 @@ pub async fn update_time_entry(
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("TimeEntry", id),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
+    // Moving an entry changes the costs of both work packages
+    refresh_costs(&state, [previous.work_package_id, row.work_package_id]).await?;
 
-    Ok(HalResponse(TimeEntryResponse::from_row(row)))
+    Ok(HalResponse(time_entry_response(row)))
 }
 
 /// DELETE /api/v3/time_entries/:id
@@ -190,6 +281,7 @@ pub async fn delete_time_entry(
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
     let repo = TimeEntryRepository::new(pool.clone());
+    let previous = find_time_entry(&repo, id).await?;
 
     repo.delete(id)
         .await
@@ -197,10 +289,34 @@ pub async fn delete_time_entry(
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("TimeEntry", id),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
+    refresh_costs(&state, [previous.work_package_id]).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+async fn find_time_entry(repo: &TimeEntryRepository, id: Id) -> ApiResult<TimeEntryRow> {
+    repo.find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("TimeEntry", id))
+}
+
+/// Have the costs of the work packages the entries are logged on refreshed
+/// in the background
+async fn refresh_costs(state: &AppState, work_package_ids: impl IntoIterator<Item = Option<Id>>) -> ApiResult<()> {
+    let args = RefreshCostsArgs::work_packages(work_package_ids.into_iter().flatten());
+    if matches!(&args, RefreshCostsArgs::WorkPackages { ids } if ids.is_empty()) {
+        return Ok(());
+    }
+
+    state
+        .jobs
+        .enqueue(args.into_job())
+        .await
+        .map_err(|e| ApiError::internal(format!("Queue error: {}", e)))?;
+    Ok(())
+}
+
 // Query filters
 #[derive(Debug, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -210,112 +326,99 @@ pub struct TimeEntryFilters {
     pub user_id: Option<Id>,
 }
 
+/// Parameters of the aggregation
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregateParams {
+    /// Comma separated groupings, e.g. `user,month`
+    pub group_by: String,
+    /// First day of the period, inclusive
+    pub from: String,
+    /// Last day of the period, inclusive
+    pub to: String,
+    /// Only entries of the project and its subprojects
+    pub project_id: Option<Id>,
+    /// Comma separated ids of the users whose entries are summed up
+    pub user_ids: Option<String>,
+}
+
 // DTOs
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct TimeEntryCollection {
+struct TimeEntryAggregateCollection {
     #[serde(rename = "_type")]
     type_name: String,
     total: usize,
     count: usize,
     page_size: usize,
     offset: usize,
+    group_by: Vec<&'static str>,
+    /// Hours of all groups, not only of this page
+    total_hours: f64,
     #[serde(rename = "_embedded")]
-    elements: Vec<TimeEntryResponse>,
+    embedded: AggregateEmbedded,
 }
 
 #[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct TimeEntryResponse {
-    #[serde(rename = "_type")]
-    type_name: String,
-    id: Id,
-    project_id: Id,
-    user_id: Id,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    work_package_id: Option<Id>,
-    hours: f64,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    comments: Option<String>,
-    activity_id: Id,
-    spent_on: String,
-    created_at: String,
-    updated_at: String,
-    #[serde(rename = "_links")]
-    links: TimeEntryLinks,
+struct AggregateEmbedded {
+    elements: Vec<TimeEntryAggregateResponse>,
 }
 
+/// Group keys are left out unless grouped by; so is the work package of
+/// hours logged on the project itself
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct TimeEntryLinks {
-    #[serde(rename = "self")]
-    self_link: Link,
-    project: Link,
-    user: Link,
+struct TimeEntryAggregateResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
-    work_package: Option<Link>,
-    activity: Link,
-}
-
-#[derive(Debug, Serialize)]
-struct Link {
-    href: String,
+    user_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    work_package_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    activity_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    week: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    month: Option<String>,
+    hours: f64,
 }
 
-impl TimeEntryResponse {
-    fn from_row(row: op_db::TimeEntryRow) -> Self {
+impl TimeEntryAggregateResponse {
+    fn from_row(row: op_db::TimeEntryAggregateRow) -> Self {
         Self {
-            type_name: "TimeEntry".into(),
-            id: row.id,
-            project_id: row.project_id,
             user_id: row.user_id,
+            project_id: row.project_id,
             work_package_id: row.work_package_id,
-            hours: row.hours,
-            comments: row.comments,
             activity_id: row.activity_id,
-            spent_on: row.spent_on.to_string(),
-            created_at: row.created_at.to_rfc3339(),
-            updated_at: row.updated_at.to_rfc3339(),
-            links: TimeEntryLinks {
-                self_link: Link {
-                    href: format!("/api/v3/time_entries/{}", row.id),
-                },
-                project: Link {
-                    href: format!("/api/v3/projects/{}", row.project_id),
-                },
-                user: Link {
-                    href: format!("/api/v3/users/{}", row.user_id),
-                },
-                work_package: row.work_package_id.map(|id| Link {
-                    href: format!("/api/v3/work_packages/{}", id),
-                }),
-                activity: Link {
-                    href: format!("/api/v3/time_entries/activities/{}", row.activity_id),
-                },
-            },
+            week: row.week.map(|date| date.to_string()),
+            month: row.month.map(|date| date.to_string()),
+            hours: row.hours,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CreateTimeEntryDto {
-    pub project_id: Id,
-    pub user_id: Option<Id>,
-    pub work_package_id: Option<Id>,
-    pub hours: f64,
-    #[serde(default)]
-    pub comments: Option<String>,
-    pub activity_id: Id,
-    pub spent_on: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct UpdateTimeEntryDto {
-    pub work_package_id: Option<Id>,
-    pub hours: Option<f64>,
-    pub comments: Option<String>,
-    pub activity_id: Option<Id>,
-    pub spent_on: Option<String>,
+fn time_entry_response(row: op_db::TimeEntryRow) -> TimeEntry {
+    TimeEntry {
+        type_name: "TimeEntry".into(),
+        id: row.id,
+        project_id: row.project_id,
+        user_id: row.user_id,
+        work_package_id: row.work_package_id,
+        hours: row.hours,
+        comments: row.comments,
+        activity_id: row.activity_id,
+        spent_on: row.spent_on.to_string(),
+        created_at: row.created_at.to_rfc3339(),
+        updated_at: row.updated_at.to_rfc3339(),
+        links: TimeEntryLinks {
+            self_link: Link::new(format!("/api/v3/time_entries/{}", row.id)),
+            project: Link::new(format!("/api/v3/projects/{}", row.project_id)),
+            user: Link::new(format!("/api/v3/users/{}", row.user_id)),
+            work_package: row
+                .work_package_id
+                .map(|id| Link::new(format!("/api/v3/work_packages/{}", id))),
+            activity: Link::new(format!("/api/v3/time_entries/activities/{}", row.activity_id)),
+        },
+    }
 }
@@ -3,18 +3,21 @@
 //! Mirrors: lib/api/v3/types/*
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
 use op_core::traits::Id;
 use op_db::{Repository, TypeRepository};
+use op_models::FormConfiguration;
 use serde::{Deserialize, Serialize};
 
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
 
+use super::statuses::{DeleteReassigningParams, ReassignedResponse};
+
 /// List all types
 ///
 /// GET /api/v3/types
@@ -183,10 +186,14 @@ pub async fn update_type(
 /// Delete a type (admin only)
 ///
 /// DELETE /api/v3/types/:id
+///
+/// Refused while work packages use the type, unless `reassignTo` names
+/// the type to move them to first.
 pub async fn delete_type(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     Path(id): Path<Id>,
+    Query(params): Query<DeleteReassigningParams>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
         return Err(ApiError::forbidden("Only administrators can delete types."));
@@ -195,15 +202,88 @@ pub async fn delete_type(
     let pool = state.pool()?;
     let repo = TypeRepository::new(pool.clone());
 
-    repo.delete(id)
+    let map_error = |e: op_db::RepositoryError| match e {
+        op_db::RepositoryError::NotFound(_) => ApiError::not_found("Type", id),
+        op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
+        op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
+        _ => ApiError::internal(format!("Database error: {}", e)),
+    };
+
+    match params.reassign_to {
+        Some(reassign_to) => {
+            let reassigned_work_packages = repo
+                .delete_reassigning(id, reassign_to, user.id())
+                .await
+                .map_err(map_error)?;
+            Ok(Json(ReassignedResponse { reassigned_work_packages }).into_response())
+        }
+        None => {
+            repo.delete(id).await.map_err(map_error)?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+    }
+}
+
+/// Form configuration of a type, flagging stored attributes that no longer
+/// exist (admin only)
+///
+/// GET /api/v3/types/:id/form_configuration
+pub async fn get_form_configuration(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can view form configurations."));
+    }
+
+    let pool = state.pool()?;
+    let repo = TypeRepository::new(pool.clone());
+
+    let row = repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Type", id))?;
+    let custom_field_ids = repo
+        .custom_field_ids()
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(HalResponse(FormConfigurationResponse::new(&row, &custom_field_ids)))
+}
+
+/// Replace the form configuration of a type, `null` resetting it to the
+/// default layout (admin only)
+///
+/// PATCH /api/v3/types/:id/form_configuration
+pub async fn update_form_configuration(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<UpdateFormConfigurationRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if !user.0.is_admin() {
+        return Err(ApiError::forbidden("Only administrators can update form configurations."));
+    }
+
+    let pool = state.pool()?;
+    let repo = TypeRepository::new(pool.clone());
+
+    let row = repo
+        .set_form_configuration(id, dto.attribute_groups.as_ref())
         .await
         .map_err(|e| match e {
             op_db::RepositoryError::NotFound(_) => ApiError::not_found("Type", id),
-            op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
+    let custom_field_ids = repo
+        .custom_field_ids()
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(HalResponse(FormConfigurationResponse::new(&row, &custom_field_ids)))
 }
 
 // Request types
@@ -231,6 +311,13 @@ pub struct UpdateTypeRequest {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateFormConfigurationRequest {
+    /// Groups of the form, the default layout when `null`
+    pub attribute_groups: Option<FormConfiguration>,
+}
+
 // Response types
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -270,6 +357,52 @@ struct TypeLinks {
     color: Option<Link>,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FormConfigurationResponse {
+    #[serde(rename = "_type")]
+    type_name: String,
+    attribute_groups: FormConfiguration,
+    /// Whether the type uses the default layout
+    is_default: bool,
+    /// Stored attributes that no longer exist, e.g. deleted custom fields;
+    /// work package forms leave them out
+    unknown_attributes: Vec<String>,
+    #[serde(rename = "_links")]
+    links: FormConfigurationLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct FormConfigurationLinks {
+    #[serde(rename = "self")]
+    self_link: Link,
+    #[serde(rename = "type")]
+    type_link: Link,
+}
+
+impl FormConfigurationResponse {
+    fn new(row: &op_db::TypeRow, custom_field_ids: &[Id]) -> Self {
+        let stored = row.form_configuration();
+        let is_default = stored.is_none();
+        let attribute_groups = stored.unwrap_or_else(|| FormConfiguration::default_for(custom_field_ids));
+
+        FormConfigurationResponse {
+            type_name: "TypeFormConfiguration".into(),
+            unknown_attributes: attribute_groups.unknown_attributes(custom_field_ids),
+            attribute_groups,
+            is_default,
+            links: FormConfigurationLinks {
+                self_link: Link {
+                    href: format!("/api/v3/types/{}/form_configuration", row.id),
+                },
+                type_link: Link {
+                    href: format!("/api/v3/types/{}", row.id),
+                },
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct Link {
     href: String,
@@ -0,0 +1,172 @@
+//! Resumable upload handlers
+//!
+//! Very large attachments are uploaded in numbered chunks to an upload
+//! session, which is completed into an attachment once all bytes arrived.
+//! A client that lost its connection reads the session to resume with the
+//! next chunk.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use op_attachments::{AttachmentError, ContainerType, CreateAttachmentParams, DigestAlgorithm, UploadSession};
+use op_core::representations::{CompleteUpload, CreateUpload, Link, ReceivedRange, Upload, UploadLinks};
+use op_db::{AttachmentRepository, Repository};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser, HalResponse};
+use crate::handlers::attachments::{attachment_response, upload_error};
+
+/// Largest accepted chunk
+pub const CHUNK_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Header with the digest of a chunk in the upload's algorithm, checked
+/// when given
+pub const CHUNK_DIGEST_HEADER: &str = "x-chunk-digest";
+
+/// Start a resumable upload
+///
+/// POST /api/v3/uploads
+///
+/// The expected size and type of the file are checked against the limits
+/// and quotas right away, so that a client learns before sending any data
+/// that the file would be rejected.
+pub async fn create_upload(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(dto): Json<CreateUpload>,
+) -> ApiResult<impl IntoResponse> {
+    let digest_algorithm = DigestAlgorithm::parse(&dto.digest_algorithm)
+        .ok_or_else(|| ApiError::invalid_property("digestAlgorithm", "is not a supported digest algorithm."))?;
+
+    let mut params = CreateAttachmentParams::new(dto.file_name);
+    if let Some(content_type) = dto.content_type {
+        params = params.content_type(content_type);
+    }
+    if let Some(description) = dto.description {
+        params = params.description(description);
+    }
+    if let (Some(container_type), Some(container_id)) = (dto.container_type, dto.container_id) {
+        let container_type = ContainerType::from_str(&container_type)
+            .ok_or_else(|| ApiError::invalid_property("containerType", "is not a valid container type."))?;
+        params = params.container(container_type, container_id);
+    }
+
+    let session = state
+        .attachments()?
+        .start_upload(params, dto.file_size, digest_algorithm, user.id())
+        .await
+        .map_err(session_error)?;
+
+    Ok((StatusCode::CREATED, HalResponse(upload_response(&session))))
+}
+
+/// Get an upload with the ranges received so far
+///
+/// GET /api/v3/uploads/:id
+pub async fn get_upload(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let session = state
+        .attachments()?
+        .upload_session(&id, user.id())
+        .await
+        .map_err(session_error)?;
+
+    Ok(HalResponse(upload_response(&session)))
+}
+
+/// Send a chunk of an upload
+///
+/// PUT /api/v3/uploads/:id/chunks/:number
+///
+/// The body is the raw data of the chunk. Chunks are numbered from 0 and
+/// accepted in sequence only.
+pub async fn upload_chunk(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((id, number)): Path<(String, u32)>,
+    headers: HeaderMap,
+    data: Bytes,
+) -> ApiResult<impl IntoResponse> {
+    let checksum = headers.get(CHUNK_DIGEST_HEADER).and_then(|v| v.to_str().ok());
+
+    let session = state
+        .attachments()?
+        .upload_chunk(&id, number, data, checksum, user.id())
+        .await
+        .map_err(session_error)?;
+
+    Ok(HalResponse(upload_response(&session)))
+}
+
+/// Complete an upload into an attachment
+///
+/// POST /api/v3/uploads/:id/complete
+///
+/// The digest of the complete file is verified; an upload whose data does
+/// not match it is discarded.
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<String>,
+    Json(dto): Json<CompleteUpload>,
+) -> ApiResult<impl IntoResponse> {
+    let created = state
+        .attachments()?
+        .complete_upload(&id, &dto.digest, user.id())
+        .await
+        .map_err(session_error)?;
+    let attachment_id = created
+        .attachment
+        .id
+        .ok_or_else(|| ApiError::internal("Stored attachment has no id"))?;
+
+    let row = AttachmentRepository::new(state.pool()?.clone())
+        .find_by_id(attachment_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Attachment", attachment_id))?;
+
+    Ok((StatusCode::CREATED, HalResponse(attachment_response(row))))
+}
+
+fn session_error(e: AttachmentError) -> ApiError {
+    match e {
+        AttachmentError::UploadNotFound(ref id) => ApiError::not_found("Upload", id),
+        AttachmentError::ChunkOutOfOrder { .. } => ApiError::conflict(e.to_string()),
+        AttachmentError::DigestMismatch { .. } => ApiError::invalid_property("digest", e.to_string()),
+        AttachmentError::IncompleteUpload { .. } => ApiError::invalid_property("fileSize", e.to_string()),
+        _ => upload_error(e),
+    }
+}
+
+fn upload_response(session: &UploadSession) -> Upload {
+    let href = format!("/api/v3/uploads/{}", session.id);
+
+    Upload {
+        type_name: "Upload".into(),
+        id: session.id.clone(),
+        file_name: session.params.filename.clone(),
+        file_size: session.size,
+        digest_algorithm: session.digest_algorithm.as_str().to_string(),
+        next_chunk: session.next_chunk(),
+        received_ranges: session
+            .received_ranges()
+            .into_iter()
+            .map(|range| ReceivedRange { start: range.start, end: range.end })
+            .collect(),
+        created_at: session.created_at.to_rfc3339(),
+        updated_at: session.updated_at.to_rfc3339(),
+        links: UploadLinks {
+            next_chunk: Link::new(format!("{}/chunks/{}", href, session.next_chunk())),
+            complete: Link::new(format!("{}/complete", href)),
+            self_link: Link::new(href),
+        },
+    }
+}
@@ -4,16 +4,23 @@
 
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use op_core::audit::{AuditEvent, AuditEventType};
+use op_core::email::normalize_email;
+use op_core::representations::{Collection, CreateUser, Link, UpdateUser, User, UserLinks};
 use op_core::traits::Id;
 use op_db::{Repository, UserRepository};
-use serde::{Deserialize, Serialize};
+use op_services::users::ReassignOrphanedContentArgs;
 
 use crate::error::{ApiError, ApiResult};
-use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::extractors::{AppState, AuthenticatedUser, ClientInfo, HalResponse, Pagination};
+use crate::representers::user::SystemUserRepresentation;
+use crate::representers::CollectionQuery;
+use crate::handlers::job_statuses::{job_status_href, JobStatusResponse};
+use crate::visibility::UserVisibility;
 
 /// List users
 ///
@@ -22,6 +29,7 @@ pub async fn list_users(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     pagination: Pagination,
+    collection_query: CollectionQuery,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
     let repo = UserRepository::new(pool.clone());
@@ -34,19 +42,16 @@ pub async fn list_users(
             .await
             .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-        let elements: Vec<UserResponse> = self_user
+        let visibility = UserVisibility::load(&state, &user, &[user.id()]).await?;
+        let elements: Vec<User> = self_user
             .into_iter()
-            .map(|row| UserResponse::from_row(row, true))
+            .map(|row| user_response(row, &visibility))
             .collect();
 
-        let collection = UserCollection {
-            type_name: "Collection".into(),
-            total: elements.len(),
-            count: elements.len(),
-            page_size: pagination.page_size,
-            offset: pagination.offset,
-            elements,
-        };
+        let total = elements.len();
+        let collection = Collection::new(elements, total, pagination.offset, pagination.page_size).with_links(
+            collection_query.pagination_links(total as i64, pagination.offset as i64, pagination.page_size as i64),
+        );
         return Ok(HalResponse(collection));
     }
 
@@ -60,19 +65,16 @@ pub async fn list_users(
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    let elements: Vec<UserResponse> = rows
+    let ids: Vec<Id> = rows.iter().map(|row| row.id).collect();
+    let visibility = UserVisibility::load(&state, &user, &ids).await?;
+    let elements: Vec<User> = rows
         .into_iter()
-        .map(|row| UserResponse::from_row(row, true))
+        .map(|row| user_response(row, &visibility))
         .collect();
 
-    let collection = UserCollection {
-        type_name: "Collection".into(),
-        total: total as usize,
-        count: elements.len(),
-        page_size: pagination.page_size,
-        offset: pagination.offset,
-        elements,
-    };
+    let collection = Collection::new(elements, total as usize, pagination.offset, pagination.page_size).with_links(
+        collection_query.pagination_links(total, pagination.offset as i64, pagination.page_size as i64),
+    );
     Ok(HalResponse(collection))
 }
 
@@ -92,35 +94,39 @@ pub async fn get_user(
         return Err(ApiError::forbidden("You are not authorized to access this resource."));
     }
 
-    let pool = state.pool()?;
-    let repo = UserRepository::new(pool.clone());
-
-    let row = repo
+    let row = state
+        .entity_stores()?
+        .users
         .find_by_id(id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("User", id))?;
 
-    Ok(HalResponse(UserResponse::from_row(row, is_self || is_admin)))
+    let visibility = UserVisibility::load(&state, &user, &[row.id]).await?;
+    Ok(HalResponse(user_response(row, &visibility)))
 }
 
-/// Get current user (me)
+/// Get current user (me); the anonymous user without credentials
 ///
 /// GET /api/v3/users/me
 pub async fn get_me(
     State(state): State<AppState>,
     user: AuthenticatedUser,
-) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
-    let repo = UserRepository::new(pool.clone());
+) -> ApiResult<Response> {
+    if user.is_anonymous() {
+        return Ok(HalResponse(SystemUserRepresentation::anonymous()).into_response());
+    }
 
-    let row = repo
+    let row = state
+        .entity_stores()?
+        .users
         .find_by_id(user.id())
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("User", user.id()))?;
 
-    Ok(HalResponse(UserResponse::from_row(row, true)))
+    let visibility = UserVisibility::load(&state, &user, &[row.id]).await?;
+    Ok(HalResponse(user_response(row, &visibility)).into_response())
 }
 
 /// Create a new user (admin only)
@@ -129,12 +135,17 @@ pub async fn get_me(
 pub async fn create_user(
     State(state): State<AppState>,
     user: AuthenticatedUser,
-    Json(dto): Json<CreateUserRequest>,
+    client: ClientInfo,
+    Json(mut dto): Json<CreateUser>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can create users."));
+        return Err(state.deny(&user, &client, "create_user", "Only administrators can create users.").await);
     }
 
+    dto.email = valid_email(&dto.email)?;
+    #[cfg(feature = "mx-lookup")]
+    verify_email_domain(&dto.email).await?;
+
     let pool = state.pool()?;
     let repo = UserRepository::new(pool.clone());
 
@@ -192,7 +203,12 @@ pub async fn create_user(
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    Ok((StatusCode::CREATED, HalResponse(UserResponse::from_row(row, true))))
+    state
+        .audit(&user, &client, AuditEvent::new(AuditEventType::UserCreated).target("User", row.id))
+        .await;
+
+    let visibility = UserVisibility::load(&state, &user, &[row.id]).await?;
+    Ok((StatusCode::CREATED, HalResponse(user_response(row, &visibility))))
 }
 
 /// Update a user
@@ -201,8 +217,9 @@ pub async fn create_user(
 pub async fn update_user(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
-    Json(dto): Json<UpdateUserRequest>,
+    Json(mut dto): Json<UpdateUser>,
 ) -> ApiResult<impl IntoResponse> {
     let is_self = user.id() == id;
     let is_admin = user.0.is_admin();
@@ -211,6 +228,8 @@ pub async fn update_user(
         return Err(ApiError::forbidden("You are not authorized to update this user."));
     }
 
+    dto.email = dto.email.as_deref().map(valid_email).transpose()?;
+
     let pool = state.pool()?;
     let repo = UserRepository::new(pool.clone());
 
@@ -271,19 +290,49 @@ pub async fn update_user(
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
 
-    Ok(HalResponse(UserResponse::from_row(row, true)))
+    if let Some(admin) = admin {
+        state
+            .audit(
+                &user,
+                &client,
+                AuditEvent::new(AuditEventType::RoleChanged)
+                    .target("User", id)
+                    .detail("admin", admin),
+            )
+            .await;
+    }
+
+    match status {
+        Some(op_db::user_status::LOCKED) => {
+            state
+                .audit(&user, &client, AuditEvent::new(AuditEventType::UserLocked).target("User", id))
+                .await;
+        }
+        Some(op_db::user_status::ACTIVE) => {
+            state
+                .audit(&user, &client, AuditEvent::new(AuditEventType::UserUnlocked).target("User", id))
+                .await;
+        }
+        _ => {}
+    }
+
+    let visibility = UserVisibility::load(&state, &user, &[row.id]).await?;
+    Ok(HalResponse(user_response(row, &visibility)))
 }
 
 /// Delete a user (admin only)
 ///
+/// The user is soft deleted by a background job; responds with its status.
+///
 /// DELETE /api/v3/users/:id
 pub async fn delete_user(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can delete users."));
+        return Err(state.deny(&user, &client, "delete_user", "Only administrators can delete users.").await);
     }
 
     if user.id() == id {
@@ -293,14 +342,30 @@ pub async fn delete_user(
     let pool = state.pool()?;
     let repo = UserRepository::new(pool.clone());
 
-    repo.delete(id)
+    let row = repo
+        .find_by_id(id)
         .await
-        .map_err(|e| match e {
-            op_db::RepositoryError::NotFound(_) => ApiError::not_found("User", id),
-            _ => ApiError::internal(format!("Database error: {}", e)),
-        })?;
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|row| !row.is_deleted())
+        .ok_or_else(|| ApiError::not_found("User", id))?;
 
-    Ok(StatusCode::NO_CONTENT)
+    // The row is anonymized and the user's content reassigned in the background
+    let job = ReassignOrphanedContentArgs::new(row.id).into_job(user.id());
+    let job_id = state
+        .jobs
+        .enqueue(job.clone())
+        .await
+        .map_err(|e| ApiError::internal(format!("Queue error: {}", e)))?;
+
+    state
+        .audit(&user, &client, AuditEvent::new(AuditEventType::UserDeleted).target("User", id))
+        .await;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, job_status_href(&job_id))],
+        HalResponse(JobStatusResponse::from_job(&job)),
+    ))
 }
 
 /// Lock a user (admin only)
@@ -309,10 +374,11 @@ pub async fn delete_user(
 pub async fn lock_user(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can lock users."));
+        return Err(state.deny(&user, &client, "lock_user", "Only administrators can lock users.").await);
     }
 
     if user.id() == id {
@@ -340,7 +406,12 @@ pub async fn lock_user(
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("User", id))?;
 
-    Ok(HalResponse(UserResponse::from_row(updated, true)))
+    state
+        .audit(&user, &client, AuditEvent::new(AuditEventType::UserLocked).target("User", id))
+        .await;
+
+    let visibility = UserVisibility::load(&state, &user, &[updated.id]).await?;
+    Ok(HalResponse(user_response(updated, &visibility)))
 }
 
 /// Unlock a user (admin only)
@@ -349,10 +420,11 @@ pub async fn lock_user(
 pub async fn unlock_user(
     State(state): State<AppState>,
     user: AuthenticatedUser,
+    client: ClientInfo,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     if !user.0.is_admin() {
-        return Err(ApiError::forbidden("Only administrators can unlock users."));
+        return Err(state.deny(&user, &client, "unlock_user", "Only administrators can unlock users.").await);
     }
 
     let pool = state.pool()?;
@@ -376,7 +448,38 @@ pub async fn unlock_user(
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("User", id))?;
 
-    Ok(HalResponse(UserResponse::from_row(updated, true)))
+    state
+        .audit(&user, &client, AuditEvent::new(AuditEventType::UserUnlocked).target("User", id))
+        .await;
+
+    let visibility = UserVisibility::load(&state, &user, &[updated.id]).await?;
+    Ok(HalResponse(user_response(updated, &visibility)))
+}
+
+/// Normalized form of a user's email address
+fn valid_email(email: &str) -> ApiResult<String> {
+    normalize_email(email).map_err(|e| ApiError::invalid_property("email", format!("Email {}.", e)))
+}
+
+/// How long user creation waits for the MX lookup of the email domain
+#[cfg(feature = "mx-lookup")]
+const MX_LOOKUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Reject an address whose domain is known not to accept mail. Failed or
+/// slow lookups let the address through.
+#[cfg(feature = "mx-lookup")]
+async fn verify_email_domain(email: &str) -> ApiResult<()> {
+    use op_core::email::{verify_mx, DnsResolver, MxVerification};
+
+    let Ok(resolver) = DnsResolver::from_system() else {
+        return Ok(());
+    };
+    match verify_mx(&resolver, email, MX_LOOKUP_TIMEOUT).await {
+        MxVerification::Undeliverable => {
+            Err(ApiError::invalid_property("email", "Email has a domain that does not accept mail."))
+        }
+        MxVerification::Deliverable | MxVerification::Unknown => Ok(()),
+    }
 }
 
 // Password hashing helpers (simplified - in production use bcrypt/argon2)
@@ -398,134 +501,37 @@ fn hash_password(password: &str, salt: &str) -> String {
     format!("{:x}", hasher.finish())
 }
 
-// Request types
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CreateUserRequest {
-    pub login: String,
-    pub firstname: String,
-    pub lastname: String,
-    pub email: String,
-    pub password: Option<String>,
-    pub admin: Option<bool>,
-    pub status: Option<String>,
-    pub language: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct UpdateUserRequest {
-    pub firstname: Option<String>,
-    pub lastname: Option<String>,
-    pub email: Option<String>,
-    pub password: Option<String>,
-    pub admin: Option<bool>,
-    pub status: Option<String>,
-    pub language: Option<String>,
-}
-
-// Response types
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct UserCollection {
-    #[serde(rename = "_type")]
-    type_name: String,
-    total: usize,
-    count: usize,
-    page_size: usize,
-    offset: usize,
-    #[serde(rename = "_embedded")]
-    elements: Vec<UserResponse>,
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct UserResponse {
-    #[serde(rename = "_type")]
-    type_name: String,
-    id: Id,
-    login: String,
-    #[serde(rename = "firstName")]
-    first_name: String,
-    #[serde(rename = "lastName")]
-    last_name: String,
-    name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    email: Option<String>,
-    admin: bool,
-    status: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    language: Option<String>,
-    created_at: String,
-    updated_at: String,
-    #[serde(rename = "_links")]
-    links: UserLinks,
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct UserLinks {
-    #[serde(rename = "self")]
-    self_link: Link,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    lock: Option<Link>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    unlock: Option<Link>,
-    memberships: Link,
-}
-
-#[derive(Debug, Serialize)]
-struct Link {
-    href: String,
-}
+fn user_response(row: op_db::UserRow, visibility: &UserVisibility) -> User {
+    let status_str = match row.status {
+        1 => "active",
+        2 => "registered",
+        3 => "locked",
+        4 => "invited",
+        5 => "deleted",
+        _ => "unknown",
+    };
 
-impl UserResponse {
-    fn from_row(row: op_db::UserRow, include_email: bool) -> Self {
-        let status_str = match row.status {
-            1 => "active",
-            2 => "registered",
-            3 => "locked",
-            4 => "invited",
-            _ => "unknown",
-        };
-
-        let is_locked = row.status == 3;
-
-        UserResponse {
-            type_name: "User".into(),
-            id: row.id,
-            login: row.login,
-            first_name: row.firstname.clone(),
-            last_name: row.lastname.clone(),
-            name: format!("{} {}", row.firstname, row.lastname),
-            email: if include_email { Some(row.mail) } else { None },
-            admin: row.admin,
-            status: status_str.to_string(),
-            language: row.language,
-            created_at: row.created_at.to_rfc3339(),
-            updated_at: row.updated_at.to_rfc3339(),
-            links: UserLinks {
-                self_link: Link {
-                    href: format!("/api/v3/users/{}", row.id),
-                },
-                lock: if !is_locked {
-                    Some(Link {
-                        href: format!("/api/v3/users/{}/lock", row.id),
-                    })
-                } else {
-                    None
-                },
-                unlock: if is_locked {
-                    Some(Link {
-                        href: format!("/api/v3/users/{}/lock", row.id),
-                    })
-                } else {
-                    None
-                },
-                memberships: Link {
-                    href: format!("/api/v3/memberships?filters=[{{\"principal\":{{\"operator\":\"=\",\"values\":[\"{}\"]}}}}]", row.id),
-                },
-            },
-        }
+    let is_locked = row.status == 3;
+    let lock = Link::new(format!("/api/v3/users/{}/lock", row.id));
+
+    User {
+        type_name: "User".into(),
+        id: row.id,
+        login: row.login,
+        first_name: row.firstname.clone(),
+        last_name: row.lastname.clone(),
+        name: format!("{} {}", row.firstname, row.lastname),
+        email: visibility.can_see_email(row.id).then_some(row.mail),
+        admin: row.admin,
+        status: status_str.to_string(),
+        language: row.language,
+        created_at: row.created_at.to_rfc3339(),
+        updated_at: row.updated_at.to_rfc3339(),
+        links: UserLinks {
+            self_link: Link::new(format!("/api/v3/users/{}", row.id)),
+            lock: (!is_locked).then(|| lock.clone()),
+            unlock: is_locked.then_some(lock),
+            memberships: Link::new(format!("/api/v3/memberships?filters=[{{\"principal\":{{\"operator\":\"=\",\"values\":[\"{}\"]}}}}]", row.id)),
+        },
     }
 }
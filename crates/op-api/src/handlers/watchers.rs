@@ -9,8 +9,10 @@ use axum::{
     Json,
 };
 use op_core::traits::Id;
-use op_db::{Repository, WatcherRepository};
+use op_db::{MemberRepository, Repository, WatcherRepository, WorkPackageRepository};
+use op_services::permissions::PermissionService;
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
 use crate::error::{ApiError, ApiResult};
 use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
@@ -78,7 +80,7 @@ pub async fn add_work_package_watcher(
         .create(create_dto)
         .await
         .map_err(|e| match e {
-            op_db::RepositoryError::Validation(msg) => ApiError::bad_request(msg),
+            op_db::RepositoryError::Validation(errors) => ApiError::validation(errors),
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(msg),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
@@ -149,12 +151,16 @@ pub async fn is_watching_work_package(
 /// Watch a work package as the current user
 ///
 /// POST /api/v3/work_packages/:work_package_id/watch
+///
+/// Only requires permission to view the work package. Watching an already
+/// watched work package succeeds without changes.
 pub async fn watch_work_package(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     Path(work_package_id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
+    ensure_work_package_visible(pool, &user, work_package_id).await?;
     let repo = WatcherRepository::new(pool.clone());
 
     let create_dto = op_db::CreateWatcherDto {
@@ -169,7 +175,7 @@ pub async fn watch_work_package(
             // Already watching - treat as success (idempotent)
             Ok(StatusCode::NO_CONTENT)
         }
-        Err(op_db::RepositoryError::Validation(msg)) => Err(ApiError::bad_request(msg)),
+        Err(op_db::RepositoryError::Validation(errors)) => Err(ApiError::validation(errors)),
         Err(e) => Err(ApiError::internal(format!("Database error: {}", e))),
     }
 }
@@ -177,12 +183,16 @@ pub async fn watch_work_package(
 /// Unwatch a work package as the current user
 ///
 /// DELETE /api/v3/work_packages/:work_package_id/watch
+///
+/// Unwatching stops future notifications; notifications already received
+/// are kept. Unwatching a work package that is not watched succeeds.
 pub async fn unwatch_work_package(
     State(state): State<AppState>,
     user: AuthenticatedUser,
     Path(work_package_id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
     let pool = state.pool()?;
+    ensure_work_package_visible(pool, &user, work_package_id).await?;
     let repo = WatcherRepository::new(pool.clone());
 
     repo.delete_by_user_and_watchable(user.0.id, "WorkPackage", work_package_id)
@@ -192,6 +202,32 @@ pub async fn unwatch_work_package(
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Check the user may view the work package, through its project or a
+/// share. Invisible work packages are reported as missing so their
+/// existence is not disclosed.
+pub(crate) async fn ensure_work_package_visible(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    work_package_id: Id,
+) -> ApiResult<op_db::work_packages::WorkPackageRow> {
+    let work_package = WorkPackageRepository::new(pool.clone())
+        .find_by_id(work_package_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", work_package_id))?;
+
+    let visible = PermissionService::new(MemberRepository::new(pool.clone()))
+        .work_package_visible(user, work_package.id, work_package.project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    if visible {
+        Ok(work_package)
+    } else {
+        Err(ApiError::not_found("WorkPackage", work_package_id))
+    }
+}
+
 // Request types
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
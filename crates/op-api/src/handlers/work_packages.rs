@@ -1,310 +1,1234 @@
 //! Work Package API handlers
 
+use std::sync::Arc;
+
 use axum::{
-    extract::{Path, State},
+    async_trait,
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
-    Json,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use chrono::{NaiveDate, Utc};
+use op_core::duration::{parse_iso8601_date, parse_iso8601_duration};
+use op_core::events::DomainEvent;
+use op_core::representations::{
+    BulkUpdateWorkPackages, Collection, CollectionGroup, CreateWorkPackage, HalLinks, UpdateWorkPackage, WorkPackage,
 };
 use op_core::traits::Id;
-use op_db::{Repository, WorkPackageRepository};
+use op_db::query_executor::group_attribute_to_columns;
+use op_db::work_packages::WorkPackageRow;
+use op_db::{
+    DbExecutor, MemberRepository, ProjectRepository, QueryRepository, Repository, SettingRepository, TypeRepository,
+    UserRepository, WatcherRepository, WorkPackageQueryExecutor, WorkPackageRepository, WorkPackageViewRepository,
+};
+use op_contracts::work_packages::{DeleteWorkPackageContract, DeleteWorkPackageData};
+use op_contracts::Contract;
+use op_queries::{FilterSet, GroupBy};
+use op_notifications::service::{ServiceError, ServiceResult as NotificationResult};
+use op_notifications::{BulkOperation, SummaryDirectory, SummaryRecipient, SuppressionWindow};
+use op_services::permissions::PermissionService;
+use op_services::work_packages::{
+    CopyWorkPackageParams, CopyWorkPackageService, CreateWorkPackageService, DeleteWorkPackageService,
+    RestoreWorkPackageService, Substitution, WorkPackageDefaults, WorkPackageEntity, WorkPackageParams,
+};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
 use crate::error::{ApiError, ApiResult};
-use crate::extractors::{AppState, AuthenticatedUser, HalResponse, Pagination};
+use crate::extractors::{AppState, AuthenticatedUser, DryRun, HalResponse, Pagination, PaginationParams};
+use crate::filters::parse_filters;
+use crate::formatting::{DbReferenceResolver, MarkdownRenderer, StoreReferenceResolver};
+use crate::handlers::custom_actions::custom_action_links;
+use crate::handlers::relations::reschedule_successors;
+use crate::links::WorkPackageAssociations;
+use crate::representers::work_package::FormattableText;
+use crate::representers::{
+    CollectionQuery, CondensedWorkPackageRepresenter, HalCollection, HalLink, SimilarWorkPackageRepresentation,
+    WorkPackageSchemaRepresenter,
+};
+use crate::streaming::{CollectionEnvelope, StreamingCollection, STREAMING_CHUNK_SIZE, STREAMING_PAGE_SIZE_THRESHOLD};
+use crate::url_root::UrlRoot;
 
 /// GET /api/v3/work_packages
+///
+/// The work packages the user may view, for anonymous users those of public
+/// projects. Pages larger than [`STREAMING_PAGE_SIZE_THRESHOLD`] are
+/// streamed.
 pub async fn list_work_packages(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     pagination: Pagination,
-) -> ApiResult<impl IntoResponse> {
+    collection_query: CollectionQuery,
+    root: Option<Extension<UrlRoot>>,
+) -> ApiResult<Response> {
+    let query = op_queries::Query::new("Work packages")
+        .with_group_by(group_by_param(&collection_query)?)
+        .with_filters(filters_param(&collection_query)?);
+    if state.stores.is_some() {
+        return stored_work_package_page(&state, &user, &query, pagination.0, &collection_query).await;
+    }
     let pool = state.pool()?;
-    let repo = WorkPackageRepository::new(pool.clone());
 
-    let rows = repo
-        .find_all(pagination.page_size as i64, pagination.offset as i64)
+    let mut executor = state.work_package_queries(user.id()).await?;
+    if !user.0.is_admin() {
+        executor = executor.visible_to(user.id());
+    }
+
+    let page = WorkPackagePage {
+        executor,
+        query,
+        pagination: pagination.0,
+        collection_query,
+    };
+    page.respond(pool, user, root.map(|Extension(root)| root)).await
+}
+
+/// GET /api/v3/projects/:id/work_packages
+///
+/// Work packages of the project and, unless `includeSubprojects=false`,
+/// of its active subprojects
+pub async fn list_project_work_packages(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(project_id): Path<Id>,
+    pagination: Pagination,
+    collection_query: CollectionQuery,
+    Query(params): Query<ProjectWorkPackagesParams>,
+    root: Option<Extension<UrlRoot>>,
+) -> ApiResult<Response> {
+    let group_by = group_by_param(&collection_query)?;
+    let filters = filters_param(&collection_query)?;
+
+    state
+        .entity_stores()?
+        .projects
+        .find_by_id(project_id)
         .await
-        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|project| !user.is_anonymous() || (project.public && project.active))
+        .ok_or_else(|| ApiError::not_found("Project", project_id))?;
+
+    let query = op_queries::Query::for_project("Work packages", project_id)
+        .with_subprojects(params.include_subprojects.unwrap_or(true))
+        .with_group_by(group_by)
+        .with_filters(filters);
+    if state.stores.is_some() {
+        return stored_work_package_page(&state, &user, &query, pagination.0, &collection_query).await;
+    }
+    let pool = state.pool()?;
+    let mut executor = state.work_package_queries(user.id()).await?;
+    if !user.0.is_admin() {
+        executor = executor.visible_to(user.id());
+    }
 
-    let total = repo
-        .count()
+    let page = WorkPackagePage {
+        executor,
+        query,
+        pagination: pagination.0,
+        collection_query,
+    };
+    page.respond(pool, user, root.map(|Extension(root)| root)).await
+}
+
+/// Setting holding the trigram similarity, between 0 and 1, above which
+/// work packages are suggested as possible duplicates
+pub const SIMILARITY_THRESHOLD_SETTING: &str = "work_package_similarity_threshold";
+
+/// Similarity threshold when the setting is missing or not a number
+pub const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.3;
+
+/// Suggestions returned when `pageSize` is omitted, and at most
+const DEFAULT_SIMILAR_LIMIT: i64 = 5;
+const MAX_SIMILAR_LIMIT: i64 = 20;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimilarWorkPackagesParams {
+    pub subject: Option<String>,
+    pub page_size: Option<i64>,
+}
+
+/// GET /api/v3/projects/:id/work_packages/similar?subject=...
+///
+/// Work packages of the project whose subject resembles `subject`, most
+/// similar first, so possible duplicates can be pointed out while a work
+/// package is being created
+pub async fn list_similar_work_packages(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(project_id): Path<Id>,
+    Query(params): Query<SimilarWorkPackagesParams>,
+    collection_query: CollectionQuery,
+) -> ApiResult<Json<HalCollection<SimilarWorkPackageRepresentation>>> {
+    let subject = params.subject.unwrap_or_default();
+    if subject.trim().is_empty() {
+        return Err(ApiError::invalid_property("subject", "can't be blank"));
+    }
+    let limit = params.page_size.unwrap_or(DEFAULT_SIMILAR_LIMIT).clamp(1, MAX_SIMILAR_LIMIT);
+
+    let pool = state.pool()?;
+    ProjectRepository::new(pool.clone())
+        .find_by_id(project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .filter(|project| !user.is_anonymous() || (project.public && project.active))
+        .ok_or_else(|| ApiError::not_found("Project", project_id))?;
+
+    let elements = similar_work_packages(pool, &user, project_id, &subject, limit).await?;
+    let total = elements.len() as i64;
+    Ok(Json(
+        HalCollection::new("Collection", elements, total, limit, 0)
+            .with_link("self", HalLink::new(collection_query.href(0, limit))),
+    ))
+}
+
+/// The work packages of a project with a subject similar to `subject`,
+/// above the threshold of [`SIMILARITY_THRESHOLD_SETTING`]
+async fn similar_work_packages(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    project_id: Id,
+    subject: &str,
+    limit: i64,
+) -> ApiResult<Vec<SimilarWorkPackageRepresentation>> {
+    let threshold = SettingRepository::new(pool.clone())
+        .get(SIMILARITY_THRESHOLD_SETTING)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .and_then(|value| value.trim().parse::<f32>().ok())
+        .filter(|threshold| (0.0..=1.0).contains(threshold))
+        .unwrap_or(DEFAULT_SIMILARITY_THRESHOLD);
+    let visible_to = (!user.0.is_admin()).then(|| user.id());
+
+    let rows = WorkPackageRepository::new(pool.clone())
+        .find_similar_subjects(project_id, subject, limit, threshold, visible_to)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    Ok(rows.into_iter().map(CondensedWorkPackageRepresenter::represent_similar).collect())
+}
 
-    let elements: Vec<WorkPackageResponse> = rows
+/// Grouping of the `groupBy` parameter, e.g. `status` or `due_date:month`;
+/// none when omitted
+fn group_by_param(collection_query: &CollectionQuery) -> ApiResult<GroupBy> {
+    let Some(value) = collection_query.param("groupBy").filter(|value| !value.is_empty()) else {
+        return Ok(GroupBy::none());
+    };
+    GroupBy::parse(value)
+        .filter(|group_by| group_by.attribute.as_deref().and_then(group_attribute_to_columns).is_some())
+        .ok_or_else(|| ApiError::invalid_property("groupBy", format!("Cannot group by '{}'", value)))
+}
+
+/// Groups of a grouped query, labelled and linked to their grouping
+async fn work_package_groups(
+    executor: &WorkPackageQueryExecutor,
+    query: &op_queries::Query,
+    user: &AuthenticatedUser,
+) -> ApiResult<Vec<CollectionGroup>> {
+    let Some(param) = query.group_by.to_param() else {
+        return Ok(Vec::new());
+    };
+    let groups = executor.execute_groups(query, Some(user.id())).await.map_err(ApiError::query)?;
+    Ok(groups
         .into_iter()
-        .map(|row| WorkPackageResponse {
-            type_name: "WorkPackage".into(),
-            id: row.id,
-            subject: row.subject,
-            description: row.description,
-            project_id: row.project_id,
-            type_id: row.type_id,
-            status_id: row.status_id,
-            priority_id: row.priority_id,
-            author_id: row.author_id,
-            assigned_to_id: row.assigned_to_id,
-            start_date: row.start_date.map(|d| d.to_string()),
-            due_date: row.due_date.map(|d| d.to_string()),
-            estimated_hours: row.estimated_hours,
-            done_ratio: row.done_ratio,
-            lock_version: row.lock_version,
-            created_at: row.created_at.to_rfc3339(),
-            updated_at: row.updated_at.to_rfc3339(),
+        .map(|group| CollectionGroup {
+            label: group.label.or_else(|| group.value.clone()).unwrap_or_else(|| "none".into()),
+            value: group.value,
+            count: group.count as usize,
+            links: HalLinks::new().with("groupBy", HalLink::new(format!("/api/v3/queries/group_bys/{}", param))),
+        })
+        .collect())
+}
+
+/// A page of a work package collection
+struct WorkPackagePage {
+    executor: WorkPackageQueryExecutor,
+    query: op_queries::Query,
+    pagination: PaginationParams,
+    collection_query: CollectionQuery,
+}
+
+impl WorkPackagePage {
+    /// The page as a collection, streamed in chunks if it is larger than
+    /// [`STREAMING_PAGE_SIZE_THRESHOLD`]
+    async fn respond(self, pool: &PgPool, user: AuthenticatedUser, root: Option<UrlRoot>) -> ApiResult<Response> {
+        let page_size = self.pagination.page_size;
+        let offset = self.pagination.offset;
+        let streamed = page_size > STREAMING_PAGE_SIZE_THRESHOLD;
+        let limit = if streamed { STREAMING_CHUNK_SIZE } else { page_size };
+
+        // The first chunk carries the total, so it is loaded before the
+        // response starts
+        let result = self
+            .executor
+            .execute(
+                &self.query,
+                &op_db::Pagination {
+                    limit: limit as i64,
+                    offset: offset as i64,
+                },
+                Some(user.id()),
+            )
+            .await
+            .map_err(ApiError::query)?;
+        let groups = work_package_groups(&self.executor, &self.query, &user).await?;
+        let rows: Vec<WorkPackageRow> = result.items.into_iter().map(WorkPackageRow::from).collect();
+        let loaded = rows.len();
+        let elements = work_package_elements(pool, &user, rows).await?;
+        let links = self
+            .collection_query
+            .pagination_links(result.total, offset as i64, page_size as i64);
+
+        if !streamed {
+            let collection = Collection::new(elements, result.total as usize, offset, page_size)
+                .with_estimated_total(result.total_is_estimate)
+                .with_groups(groups)
+                .with_links(links);
+            return Ok(HalResponse(collection).into_response());
+        }
+
+        let envelope = CollectionEnvelope::new(result.total as usize, offset, page_size)
+            .with_estimated_total(result.total_is_estimate)
+            .with_groups(groups)
+            .with_links(links);
+        let next = (loaded == limit).then_some(offset + loaded);
+        let rest = self.chunks_from(pool.clone(), user, next).map_ok(|chunk| stream::iter(chunk.into_iter().map(Ok)));
+        let elements = stream::iter(elements.into_iter().map(Ok)).chain(rest.try_flatten());
+        Ok(StreamingCollection::new(envelope, elements).under_root(root).into_response())
+    }
+
+    /// The chunks of the page from the offset on, loaded as the response
+    /// body is written; its matches are not counted again
+    fn chunks_from(
+        self,
+        pool: PgPool,
+        user: AuthenticatedUser,
+        next: Option<usize>,
+    ) -> impl Stream<Item = ApiResult<Vec<WorkPackage>>> + Send + 'static {
+        let end = self.pagination.offset + self.pagination.page_size;
+        let page = Arc::new((self, pool, user));
+        stream::try_unfold(next, move |next| {
+            let page = page.clone();
+            async move {
+                let Some(offset) = next.filter(|offset| *offset < end) else {
+                    return Ok(None);
+                };
+                let (page, pool, user) = &*page;
+                let limit = STREAMING_CHUNK_SIZE.min(end - offset);
+                let rows = page
+                    .executor
+                    .execute_uncounted(
+                        &page.query,
+                        &op_db::Pagination {
+                            limit: limit as i64,
+                            offset: offset as i64,
+                        },
+                        Some(user.id()),
+                    )
+                    .await
+                    .map_err(ApiError::query)?;
+                let next = (rows.len() == limit).then_some(offset + limit);
+                let elements = work_package_elements(pool, user, rows.into_iter().map(WorkPackageRow::from).collect()).await?;
+                Ok(Some((elements, next)))
+            }
+        })
+    }
+}
+
+/// The `filters` parameter, in the format of the API queries
+fn filters_param(collection_query: &CollectionQuery) -> ApiResult<FilterSet> {
+    let mut filters = FilterSet::new();
+    for filter in parse_filters(collection_query.param("filters"))? {
+        filters.add(filter);
+    }
+    Ok(filters)
+}
+
+/// A page of the work packages matching the query in the entity stores,
+/// neither grouped nor streamed
+async fn stored_work_package_page(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    query: &op_queries::Query,
+    pagination: PaginationParams,
+    collection_query: &CollectionQuery,
+) -> ApiResult<Response> {
+    let work_packages = state.entity_stores()?.work_packages;
+    let result = work_packages
+        .find_matching(
+            query,
+            &op_db::Pagination {
+                limit: pagination.page_size as i64,
+                offset: pagination.offset as i64,
+            },
+            Some(user.id()),
+        )
+        .await
+        .map_err(ApiError::query)?;
+
+    let descriptions: Vec<&str> = result.items.iter().filter_map(|row| row.description.as_deref()).collect();
+    let mut rendered = MarkdownRenderer::new(StoreReferenceResolver::new(work_packages.clone()))
+        .render_all(&descriptions)
+        .await?
+        .into_iter();
+    let elements: Vec<WorkPackage> = result
+        .items
+        .into_iter()
+        .map(|row| {
+            let description = row.description.as_ref().and_then(|_| rendered.next());
+            work_package_response(row, description)
         })
         .collect();
 
-    let collection = WorkPackageCollection {
-        type_name: "Collection".into(),
-        total: total as usize,
-        count: elements.len(),
-        page_size: pagination.page_size,
-        offset: pagination.offset,
-        elements,
-    };
-    Ok(HalResponse(collection))
+    let links = collection_query.pagination_links(result.total, pagination.offset as i64, pagination.page_size as i64);
+    let collection = Collection::new(elements, result.total as usize, pagination.offset, pagination.page_size)
+        .with_estimated_total(result.total_is_estimate)
+        .with_links(links);
+    Ok(HalResponse(collection).into_response())
+}
+
+/// Work packages of a page, with their descriptions rendered and marked
+/// if they changed since the user last viewed them
+async fn work_package_elements(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    rows: Vec<WorkPackageRow>,
+) -> ApiResult<Vec<WorkPackage>> {
+    let descriptions: Vec<&str> = rows.iter().filter_map(|row| row.description.as_deref()).collect();
+    let mut rendered = renderer(pool, user).render_all(&descriptions).await?.into_iter();
+    let elements: Vec<WorkPackage> = rows
+        .into_iter()
+        .map(|row| {
+            let description = row.description.as_ref().and_then(|_| rendered.next());
+            work_package_response(row, description)
+        })
+        .collect();
+    mark_unseen_changes(pool, user, elements).await
 }
 
 /// GET /api/v3/work_packages/:id
 pub async fn get_work_package(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
-    let repo = WorkPackageRepository::new(pool.clone());
-
-    let row = repo
+    let row = state
+        .entity_stores()?
+        .work_packages
         .find_by_id(id)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
         .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
 
-    Ok(HalResponse(WorkPackageResponse {
-        type_name: "WorkPackage".into(),
-        id: row.id,
-        subject: row.subject,
-        description: row.description,
-        project_id: row.project_id,
-        type_id: row.type_id,
-        status_id: row.status_id,
-        priority_id: row.priority_id,
-        author_id: row.author_id,
-        assigned_to_id: row.assigned_to_id,
-        start_date: row.start_date.map(|d| d.to_string()),
-        due_date: row.due_date.map(|d| d.to_string()),
-        estimated_hours: row.estimated_hours,
-        done_ratio: row.done_ratio,
-        lock_version: row.lock_version,
-        created_at: row.created_at.to_rfc3339(),
-        updated_at: row.updated_at.to_rfc3339(),
-    }))
+    // Without a database, as in embedded mode, there are no memberships to
+    // check and no custom actions
+    let links = match &state.db {
+        Some(pool) => visible_work_package_links(pool, &user, &row).await?,
+        None => HalLinks::new(),
+    };
+
+    let description = render_stored_description(&state, &user, &row).await?;
+    let mut work_package = work_package_response(row, description);
+    work_package.links.extend(links);
+    Ok(HalResponse(work_package))
 }
 
-/// POST /api/v3/work_packages
-pub async fn create_work_package(
+/// Custom action links of a work package the user may view, recording the
+/// view; not found if the user may not view it
+async fn visible_work_package_links(pool: &PgPool, user: &AuthenticatedUser, row: &WorkPackageRow) -> ApiResult<HalLinks> {
+    // Through a membership, a share or, for anonymous users, the Anonymous
+    // role of a public project
+    let members = MemberRepository::new(pool.clone());
+    let role_ids = if user.is_anonymous() {
+        Vec::new()
+    } else {
+        members
+            .role_ids_in_project(user.id(), row.project_id)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+    };
+    let visible = PermissionService::new(members)
+        .work_package_visible(user, row.id, row.project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    if !visible {
+        return Err(ApiError::not_found("WorkPackage", row.id));
+    }
+
+    record_view(pool, user, row.id);
+
+    custom_action_links(pool, row, role_ids).await
+}
+
+/// Record in the background that the user viewed the work package, so the
+/// response does not wait for the write; views of anonymous users are not
+/// recorded
+pub(crate) fn record_view(pool: &PgPool, user: &AuthenticatedUser, work_package_id: Id) {
+    if user.is_anonymous() {
+        return;
+    }
+    let views = WorkPackageViewRepository::new(pool.clone());
+    let user_id = user.id();
+    tokio::spawn(async move {
+        if let Err(e) = views.record_view(user_id, work_package_id, Utc::now()).await {
+            tracing::warn!(error = %e, work_package_id, "Failed to record the work package view");
+        }
+    });
+}
+
+/// Mark the work packages changed since the user last viewed them, for
+/// users other than the anonymous one
+async fn mark_unseen_changes(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    mut elements: Vec<WorkPackage>,
+) -> ApiResult<Vec<WorkPackage>> {
+    if user.is_anonymous() {
+        return Ok(elements);
+    }
+    let ids: Vec<Id> = elements.iter().map(|element| element.id).collect();
+    let unseen = WorkPackageViewRepository::new(pool.clone())
+        .unseen(user.id(), &ids)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    for element in &mut elements {
+        element.unseen_changes = Some(unseen.contains(&element.id));
+    }
+    Ok(elements)
+}
+
+/// Schema of work packages of a type in a project, with the form layout
+/// of the type
+///
+/// GET /api/v3/work_packages/schemas/:project_id-:type_id
+pub async fn get_work_package_schema(
     State(state): State<AppState>,
-    user: AuthenticatedUser,
-    Json(dto): Json<CreateWorkPackageDto>,
+    _user: AuthenticatedUser,
+    Path(id): Path<String>,
 ) -> ApiResult<impl IntoResponse> {
+    let (project_id, type_id) = id
+        .split_once('-')
+        .and_then(|(project_id, type_id)| Some((project_id.parse::<Id>().ok()?, type_id.parse::<Id>().ok()?)))
+        .ok_or_else(|| ApiError::not_found("Schema", &id))?;
+
     let pool = state.pool()?;
-    let repo = WorkPackageRepository::new(pool.clone());
+    ProjectRepository::new(pool.clone())
+        .find_by_id(project_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Project", project_id))?;
+    let type_row = TypeRepository::new(pool.clone())
+        .find_by_id(type_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("Type", type_id))?;
+    let custom_fields = QueryRepository::new(pool.clone())
+        .filterable_custom_fields(Some(project_id))
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
 
-    let create_dto = op_db::CreateWorkPackageDto {
-        subject: dto.subject,
+    Ok(HalResponse(WorkPackageSchemaRepresenter::represent(project_id, &type_row, &custom_fields)))
+}
+
+/// Setting whether new work packages sent without an assignee are assigned
+/// to their authors
+pub const ASSIGN_TO_AUTHOR_SETTING: &str = "work_package_assign_to_author";
+
+/// POST /api/v3/work_packages
+///
+/// Attributes not sent default to those of the type and project, see
+/// [`op_services::work_packages::DefaultsResolver`]. With `dryRun=true` the
+/// work package is validated against the create contract and returned as
+/// it would be created (200), without an id; the attributes defaulted are
+/// listed in `_embedded.defaultedAttributes` for forms to pre-fill.
+pub async fn create_work_package(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    dry_run: DryRun,
+    Json(dto): Json<CreateWorkPackage>,
+) -> ApiResult<axum::response::Response> {
+    let linked = WorkPackageAssociations::from_links(&dto.links)?;
+    let mut params = WorkPackageParams {
+        subject: Some(dto.subject),
         description: dto.description,
-        project_id: dto.project_id.unwrap_or(1),
-        type_id: dto.type_id.unwrap_or(1),
-        status_id: dto.status_id.unwrap_or(1),
-        priority_id: dto.priority_id,
-        author_id: user.id(),
-        assigned_to_id: dto.assigned_to_id,
-        responsible_id: None,
-        start_date: None,
-        due_date: None,
+        project_id: Some(linked.project_id.or(dto.project_id).unwrap_or(1)),
+        type_id: Some(linked.type_id.or(dto.type_id).unwrap_or(1)),
+        status_id: linked.status_id.or(dto.status_id),
+        priority_id: linked.priority_id.or(dto.priority_id),
+        assigned_to_id: linked.assigned_to_id.unwrap_or(dto.assigned_to_id),
+        responsible_id: linked.responsible_id.flatten(),
         estimated_hours: dto.estimated_hours,
-        done_ratio: 0,
-        parent_id: dto.parent_id,
-        version_id: None,
-        category_id: None,
+        parent_id: linked.parent_id.unwrap_or(dto.parent_id),
+        version_id: linked.version_id.flatten(),
+        category_id: linked.category_id.flatten(),
+        send_notifications: false,
+        ..Default::default()
     };
+    for (attribute, link) in [("assigned_to_id", linked.assigned_to_id), ("version_id", linked.version_id)] {
+        if link == Some(None) {
+            params = params.with_null(attribute);
+        }
+    }
 
-    let row = repo
+    let defaults = work_package_defaults(&state, params.project_id.unwrap_or(1), params.type_id.unwrap_or(1)).await?;
+    let result = CreateWorkPackageService::without_notifications(&user)
+        .with_defaults(defaults)
+        .dry_run()
+        .call(params.clone());
+    if result.is_failure() {
+        return Err(ApiError::validation(result.errors().clone()));
+    }
+    let entity = result.unwrap();
+    let create_dto = create_dto(&entity, &params);
+
+    if dry_run.0 {
+        let row = create_dto.preview();
+        let description = render_stored_description(&state, &user, &row).await?;
+        let mut unsaved = DryRun::unsaved(&work_package_response(row, description));
+        let embedded = unsaved
+            .as_object_mut()
+            .map(|object| object.entry("_embedded").or_insert_with(|| serde_json::json!({})))
+            .and_then(serde_json::Value::as_object_mut);
+
+        if let Some(embedded) = embedded {
+            embedded.insert("defaultedAttributes".into(), serde_json::json!(entity.derived_attributes));
+
+            // Possible duplicates of the work package, for the create form
+            // to point out before it is saved
+            if let (Some(pool), false) = (state.db.as_ref(), create_dto.subject.trim().is_empty()) {
+                let similar = similar_work_packages(
+                    pool,
+                    &user,
+                    create_dto.project_id,
+                    &create_dto.subject,
+                    DEFAULT_SIMILAR_LIMIT,
+                )
+                .await?;
+                embedded.insert("similarWorkPackages".into(), serde_json::json!(similar));
+            }
+        }
+        return Ok(HalResponse(unsaved).into_response());
+    }
+
+    let row = state
+        .entity_stores()?
+        .work_packages
         .create(create_dto)
         .await
         .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    state.work_packages_changed(&[row.project_id]).await;
+    state
+        .events
+        .publish(DomainEvent::WorkPackageCreated {
+            work_package_id: row.id,
+            project_id: row.project_id,
+            author_id: row.author_id,
+        })
+        .await;
 
+    let description = render_stored_description(&state, &user, &row).await?;
     Ok((
         StatusCode::CREATED,
-        HalResponse(WorkPackageResponse {
-            type_name: "WorkPackage".into(),
-            id: row.id,
-            subject: row.subject,
-            description: row.description,
-            project_id: row.project_id,
-            type_id: row.type_id,
-            status_id: row.status_id,
-            priority_id: row.priority_id,
-            author_id: row.author_id,
-            assigned_to_id: row.assigned_to_id,
-            start_date: row.start_date.map(|d| d.to_string()),
-            due_date: row.due_date.map(|d| d.to_string()),
-            estimated_hours: row.estimated_hours,
-            done_ratio: row.done_ratio,
-            lock_version: row.lock_version,
-            created_at: row.created_at.to_rfc3339(),
-            updated_at: row.updated_at.to_rfc3339(),
-        }),
-    ))
+        HalResponse(work_package_response(row, description)),
+    )
+        .into_response())
+}
+
+/// POST /api/v3/work_packages/form
+///
+/// Validates a work package to create, as a dry run of the create
+pub async fn work_package_create_form(
+    state: State<AppState>,
+    user: AuthenticatedUser,
+    dto: Json<CreateWorkPackage>,
+) -> ApiResult<Response> {
+    create_work_package(state, user, DryRun(true), dto).await
+}
+
+/// POST /api/v3/work_packages/:id/form
+///
+/// Validates changes to a work package, as a dry run of the update
+pub async fn work_package_update_form(
+    state: State<AppState>,
+    user: AuthenticatedUser,
+    id: Path<Id>,
+    dto: Json<UpdateWorkPackage>,
+) -> ApiResult<Response> {
+    Ok(update_work_package(state, user, DryRun(true), id, dto).await?.into_response())
+}
+
+/// Defaults of new work packages of the type in the project; nothing is
+/// defaulted without a database
+async fn work_package_defaults(state: &AppState, project_id: Id, type_id: Id) -> ApiResult<WorkPackageDefaults> {
+    let Some(pool) = state.db.as_ref() else {
+        return Ok(WorkPackageDefaults::default());
+    };
+
+    let row = WorkPackageRepository::new(pool.clone())
+        .find_defaults(project_id, type_id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let assign_to_author = SettingRepository::new(pool.clone())
+        .get(ASSIGN_TO_AUTHOR_SETTING)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+    Ok(WorkPackageDefaults::from(row)
+        .assigning_to_author(matches!(assign_to_author.as_deref(), Some("1") | Some("true"))))
+}
+
+/// Work package to insert for the one the create service resolved. The
+/// priority is left to the database unless sent or defaulted.
+fn create_dto(entity: &WorkPackageEntity, params: &WorkPackageParams) -> op_db::CreateWorkPackageDto {
+    let priority_set = params.priority_id.is_some() || entity.derived_attributes.contains(&"priority_id");
+    op_db::CreateWorkPackageDto {
+        subject: entity.subject.clone(),
+        description: entity.description.clone(),
+        project_id: entity.project_id,
+        type_id: entity.type_id,
+        status_id: entity.status_id,
+        priority_id: priority_set.then_some(entity.priority_id),
+        author_id: entity.author_id,
+        assigned_to_id: entity.assigned_to_id,
+        responsible_id: entity.responsible_id,
+        start_date: entity.start_date,
+        due_date: entity.due_date,
+        estimated_hours: entity.estimated_hours,
+        done_ratio: entity.done_ratio,
+        parent_id: entity.parent_id,
+        version_id: entity.version_id,
+        category_id: entity.category_id,
+        duration: entity.duration,
+        ignore_non_working_days: None,
+        journal_cause: entity.journal_cause(),
+    }
 }
 
 /// PATCH /api/v3/work_packages/:id
+///
+/// With `dryRun=true` the update runs in a transaction that is rolled back,
+/// so the lock version and database constraints are checked as usual.
 pub async fn update_work_package(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
+    dry_run: DryRun,
     Path(id): Path<Id>,
-    Json(dto): Json<UpdateWorkPackageDto>,
+    Json(dto): Json<UpdateWorkPackage>,
 ) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
-    let repo = WorkPackageRepository::new(pool.clone());
+    let start_date = parse_date_property("startDate", dto.start_date.as_deref())?;
+    let due_date = parse_date_property("dueDate", dto.due_date.as_deref())?;
+    let estimated_hours = match dto.estimated_time.as_deref() {
+        Some(value) => Some(
+            parse_iso8601_duration(value)
+                .map_err(|e| ApiError::invalid_property("estimatedTime", e.to_string()))?,
+        ),
+        None => dto.estimated_hours,
+    };
+
+    let linked = WorkPackageAssociations::from_links(&dto.links)?;
+
+    // Associations neither linked nor given by id are kept
+    let current = state
+        .entity_stores()?
+        .work_packages
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
 
     let update_dto = op_db::UpdateWorkPackageDto {
         subject: dto.subject,
         description: dto.description,
-        type_id: dto.type_id,
-        status_id: dto.status_id,
-        priority_id: dto.priority_id,
-        assigned_to_id: dto.assigned_to_id,
-        responsible_id: None,
-        start_date: None,
-        due_date: None,
-        estimated_hours: dto.estimated_hours,
+        type_id: linked.type_id.or(dto.type_id),
+        status_id: linked.status_id.or(dto.status_id),
+        priority_id: linked.priority_id.or(dto.priority_id),
+        assigned_to_id: linked.assigned_to_id.unwrap_or(dto.assigned_to_id.or(current.assigned_to_id)),
+        responsible_id: linked.responsible_id.unwrap_or(current.responsible_id),
+        start_date,
+        due_date,
+        estimated_hours,
         done_ratio: dto.done_ratio,
-        parent_id: None,
-        version_id: None,
-        category_id: None,
+        parent_id: linked.parent_id.unwrap_or(current.parent_id),
+        version_id: linked.version_id.unwrap_or(current.version_id),
+        category_id: linked.category_id.unwrap_or(current.category_id),
+        duration: None,
+        ignore_non_working_days: None,
         lock_version: dto.lock_version,
     };
 
-    let row = repo
-        .update(id, update_dto)
-        .await
-        .map_err(|e| match e {
+    let update_error = |e: op_db::RepositoryError| match e {
+        op_db::RepositoryError::Conflict(msg) => ApiError::conflict(&msg),
+        op_db::RepositoryError::NotFound(_) => ApiError::not_found("WorkPackage", id),
+        _ => ApiError::internal(format!("Database error: {}", e)),
+    };
+
+    let row = if dry_run.0 {
+        let executor = DbExecutor::begin(state.pool()?)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+        let repo = WorkPackageRepository::with_executor(executor.clone());
+        let row = repo.update(id, update_dto).await.map_err(update_error)?;
+        drop(repo);
+        executor
+            .rollback()
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+        row
+    } else {
+        let row = state
+            .entity_stores()?
+            .work_packages
+            .update(id, update_dto)
+            .await
+            .map_err(update_error)?;
+        state.work_packages_changed(&[row.project_id]).await;
+        state
+            .events
+            .publish(DomainEvent::WorkPackageUpdated {
+                work_package_id: row.id,
+                project_id: row.project_id,
+                actor_id: user.id(),
+            })
+            .await;
+        // Relations are kept in the database only
+        if state.db.is_some() && (row.start_date.is_some() || row.due_date.is_some()) {
+            reschedule_successors(&state, &[row.id]).await?;
+        }
+        row
+    };
+
+    let description = render_stored_description(&state, &user, &row).await?;
+    Ok(HalResponse(work_package_response(row, description)))
+}
+
+/// PATCH /api/v3/work_packages/bulk
+///
+/// Applies the same changes to each of the work packages, regardless of
+/// their lock versions. Watchers and assignees are not notified about each
+/// work package; once all are updated, they receive one summary email per
+/// project.
+pub async fn bulk_update_work_packages(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(dto): Json<BulkUpdateWorkPackages>,
+) -> ApiResult<impl IntoResponse> {
+    if dto.ids.is_empty() {
+        return Err(ApiError::invalid_property("ids", "can't be blank."));
+    }
+    let start_date = parse_date_property("startDate", dto.start_date.as_deref())?;
+    let due_date = parse_date_property("dueDate", dto.due_date.as_deref())?;
+
+    let pool = state.pool()?;
+    let repo = WorkPackageRepository::new(pool.clone());
+    let window = SuppressionWindow::new();
+
+    // Nothing is changed unless all work packages exist
+    let mut current = Vec::with_capacity(dto.ids.len());
+    for &id in &dto.ids {
+        let row = repo
+            .find_by_id(id)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+            .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
+        current.push(row);
+    }
+
+    let mut rows = Vec::with_capacity(current.len());
+    let mut previous_assignees = Vec::with_capacity(current.len());
+    for current in current {
+        let id = current.id;
+        let update_dto = op_db::UpdateWorkPackageDto {
+            subject: None,
+            description: None,
+            type_id: dto.type_id,
+            status_id: dto.status_id,
+            priority_id: dto.priority_id,
+            assigned_to_id: dto.assigned_to_id,
+            responsible_id: None,
+            start_date,
+            due_date,
+            estimated_hours: None,
+            done_ratio: dto.done_ratio,
+            parent_id: None,
+            version_id: None,
+            category_id: None,
+            duration: None,
+            ignore_non_working_days: None,
+            lock_version: current.lock_version,
+        };
+        let row = repo.update(id, update_dto).await.map_err(|e| match e {
             op_db::RepositoryError::Conflict(msg) => ApiError::conflict(&msg),
-            op_db::RepositoryError::NotFound(msg) => ApiError::not_found("WorkPackage", id),
+            op_db::RepositoryError::NotFound(_) => ApiError::not_found("WorkPackage", id),
             _ => ApiError::internal(format!("Database error: {}", e)),
         })?;
+        previous_assignees.push(current.assigned_to_id);
+        rows.push(row);
+    }
 
-    Ok(HalResponse(WorkPackageResponse {
-        type_name: "WorkPackage".into(),
-        id: row.id,
-        subject: row.subject,
-        description: row.description,
-        project_id: row.project_id,
-        type_id: row.type_id,
-        status_id: row.status_id,
-        priority_id: row.priority_id,
-        author_id: row.author_id,
-        assigned_to_id: row.assigned_to_id,
-        start_date: row.start_date.map(|d| d.to_string()),
-        due_date: row.due_date.map(|d| d.to_string()),
-        estimated_hours: row.estimated_hours,
-        done_ratio: row.done_ratio,
-        lock_version: row.lock_version,
-        created_at: row.created_at.to_rfc3339(),
-        updated_at: row.updated_at.to_rfc3339(),
-    }))
+    let ids: Vec<Id> = rows.iter().map(|row| row.id).collect();
+    let watchers = WatcherRepository::new(pool.clone())
+        .find_by_work_packages(&ids)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    for (row, previous_assignee) in rows.iter().zip(previous_assignees) {
+        let recipients: Vec<Id> = watchers
+            .iter()
+            .filter(|watcher| watcher.watchable_id == row.id)
+            .map(|watcher| watcher.user_id)
+            .chain(row.assigned_to_id)
+            .chain(previous_assignee)
+            .collect();
+        window.record(BulkOperation::Update, user.id(), row.project_id, row.id, &recipients);
+    }
+
+    let mut project_ids: Vec<Id> = rows.iter().map(|row| row.project_id).collect();
+    project_ids.sort_unstable();
+    project_ids.dedup();
+    state.work_packages_changed(&project_ids).await;
+    if start_date.is_some() || due_date.is_some() {
+        reschedule_successors(&state, &ids).await?;
+    }
+
+    let summaries = window.close();
+    if let Some(mailer) = &state.summaries {
+        mailer
+            .send(&summaries, &DbSummaryDirectory::new(pool.clone()))
+            .await
+            .map_err(|e| ApiError::internal(format!("Failed to send summaries: {}", e)))?;
+    }
+
+    let descriptions: Vec<&str> = rows.iter().filter_map(|row| row.description.as_deref()).collect();
+    let mut rendered = renderer(pool, &user).render_all(&descriptions).await?.into_iter();
+    let total = rows.len();
+    let elements: Vec<WorkPackage> = rows
+        .into_iter()
+        .map(|row| {
+            let description = row.description.as_ref().and_then(|_| rendered.next());
+            work_package_response(row, description)
+        })
+        .collect();
+    Ok(HalResponse(Collection::new(elements, total, 0, total)))
+}
+
+/// Names and addresses of the users told about bulk operations
+struct DbSummaryDirectory {
+    users: UserRepository,
+    projects: ProjectRepository,
+}
+
+impl DbSummaryDirectory {
+    fn new(pool: PgPool) -> Self {
+        Self {
+            users: UserRepository::new(pool.clone()),
+            projects: ProjectRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl SummaryDirectory for DbSummaryDirectory {
+    async fn user_name(&self, user_id: Id) -> NotificationResult<Option<String>> {
+        let user = self
+            .users
+            .find_by_id(user_id)
+            .await
+            .map_err(|e| ServiceError::StorageError(e.to_string()))?;
+
+        Ok(user.map(|u| u.full_name()))
+    }
+
+    async fn project_name(&self, project_id: Id) -> NotificationResult<Option<String>> {
+        let project = self
+            .projects
+            .find_by_id(project_id)
+            .await
+            .map_err(|e| ServiceError::StorageError(e.to_string()))?;
+
+        Ok(project.map(|p| p.name))
+    }
+
+    async fn recipients(&self, user_ids: &[Id]) -> NotificationResult<Vec<SummaryRecipient>> {
+        let users = self
+            .users
+            .find_by_ids(user_ids)
+            .await
+            .map_err(|e| ServiceError::StorageError(e.to_string()))?;
+
+        Ok(users
+            .into_iter()
+            .filter(|u| u.is_active() && !u.mail.is_empty())
+            .map(|u| SummaryRecipient {
+                id: u.id,
+                name: Some(u.full_name()),
+                email: u.mail,
+                language: u.language,
+            })
+            .collect())
+    }
+}
+
+/// Renderer linking the references the user may see
+fn renderer(pool: &PgPool, user: &AuthenticatedUser) -> MarkdownRenderer<DbReferenceResolver> {
+    MarkdownRenderer::new(DbReferenceResolver::new(pool.clone(), &user.0))
+}
+
+/// HTML of the work package's description
+pub(crate) async fn render_description(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    row: &WorkPackageRow,
+) -> ApiResult<Option<String>> {
+    match row.description.as_deref() {
+        Some(description) => Ok(Some(renderer(pool, user).render(description).await?)),
+        None => Ok(None),
+    }
+}
+
+/// HTML of the work package's description, resolving references in the
+/// database or, without one, in the entity stores
+async fn render_stored_description(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    row: &WorkPackageRow,
+) -> ApiResult<Option<String>> {
+    if let Some(pool) = &state.db {
+        return render_description(pool, user, row).await;
+    }
+    match row.description.as_deref() {
+        Some(description) => {
+            let resolver = StoreReferenceResolver::new(state.entity_stores()?.work_packages);
+            Ok(Some(MarkdownRenderer::new(resolver).render(description).await?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Parse an optional date property, rejecting datetime values with a 422
+fn parse_date_property(property: &str, value: Option<&str>) -> ApiResult<Option<NaiveDate>> {
+    value
+        .map(parse_iso8601_date)
+        .transpose()
+        .map_err(|e| ApiError::invalid_property(property, e.to_string()))
 }
 
 /// DELETE /api/v3/work_packages/:id
+///
+/// Moves the work package and its descendants to the trash
 pub async fn delete_work_package(
     State(state): State<AppState>,
-    _user: AuthenticatedUser,
+    user: AuthenticatedUser,
     Path(id): Path<Id>,
 ) -> ApiResult<impl IntoResponse> {
-    let pool = state.pool()?;
+    let Some(pool) = &state.db else {
+        return delete_stored_work_package(&state, &user, id).await;
+    };
     let repo = WorkPackageRepository::new(pool.clone());
 
-    repo.delete(id)
+    let row = repo
+        .find_by_id(id)
         .await
-        .map_err(|e| match e {
-            op_db::RepositoryError::NotFound(_) => ApiError::not_found("WorkPackage", id),
-            _ => ApiError::internal(format!("Database error: {}", e)),
-        })?;
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
+
+    let mut work_package = WorkPackageEntity::new(row.project_id, row.type_id, row.author_id);
+    work_package.id = Some(row.id);
+
+    let trash = repo
+        .begin_trash()
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let mut service = DeleteWorkPackageService::new(&user).with_events(&state.events);
+    if let Some(metrics) = &state.metrics {
+        service = service.with_metrics(metrics);
+    }
+    let result = service.call(&work_package, trash).await;
+    if result.is_failure() {
+        return Err(ApiError::Validation(result.errors().clone()));
+    }
+    state.work_packages_changed(&[row.project_id]).await;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Delete a work package from the entity stores, which have no trash to
+/// move it to
+async fn delete_stored_work_package(state: &AppState, user: &AuthenticatedUser, id: Id) -> ApiResult<StatusCode> {
+    let work_packages = state.entity_stores()?.work_packages;
+    let row = work_packages
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
+
+    let data = DeleteWorkPackageData {
+        id: row.id,
+        project_id: row.project_id,
+        descendant_count: 0,
+    };
+    DeleteWorkPackageContract::new(user, row.project_id, row.id)
+        .validate(&data)
+        .map_err(ApiError::Validation)?;
+
+    work_packages
+        .delete(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    state.work_packages_changed(&[row.project_id]).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/v3/work_packages/:id/restore
+///
+/// Takes a work package in the trash out again, with the descendants
+/// trashed along with it
+pub async fn restore_work_package(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let repo = WorkPackageRepository::new(pool.clone());
+
+    let trashed = repo
+        .find_trashed(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
+
+    let trash = repo
+        .begin_trash()
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let result = RestoreWorkPackageService::new(&user).call(trashed.id, trash).await;
+    if result.is_failure() {
+        return Err(ApiError::Validation(result.errors().clone()));
+    }
+    state.work_packages_changed(&[trashed.project_id]).await;
+
+    let row = repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| ApiError::not_found("WorkPackage", id))?;
+    let description = render_description(pool, &user, &row).await?;
+    Ok(HalResponse(work_package_response(row, description)))
+}
+
+/// POST /api/v3/work_packages/:id/copy
+pub async fn copy_work_package(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Id>,
+    Json(dto): Json<CopyWorkPackageDto>,
+) -> ApiResult<impl IntoResponse> {
+    let pool = state.pool()?;
+    let attachments = state.attachments()?;
+    let repo = WorkPackageRepository::new(pool.clone());
+
+    if !repo
+        .exists(id)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?
+    {
+        return Err(ApiError::not_found("WorkPackage", id));
+    }
+
+    let copy = repo
+        .begin_copy()
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    let result = CopyWorkPackageService::new(&user)
+        .call(id, dto.into_params(), copy, attachments)
+        .await;
+    if result.is_failure() {
+        return Err(ApiError::Validation(result.errors().clone()));
+    }
+    let copied = result.unwrap();
+    state.work_packages_changed(&[copied.work_package.project_id]).await;
+
+    let description = render_description(pool, &user, &copied.work_package).await?;
+    Ok((
+        StatusCode::CREATED,
+        HalResponse(CopiedWorkPackageResponse {
+            work_package: work_package_response(copied.work_package, description),
+            meta: CopyMeta {
+                substitutions: copied.substitutions,
+            },
+        }),
+    ))
+}
+
 // DTOs
+/// The copy, with the types and statuses replaced in the target project
 #[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct WorkPackageCollection {
-    #[serde(rename = "_type")]
-    type_name: String,
-    total: usize,
-    count: usize,
-    page_size: usize,
-    offset: usize,
-    #[serde(rename = "_embedded")]
-    elements: Vec<WorkPackageResponse>,
+struct CopiedWorkPackageResponse {
+    #[serde(flatten)]
+    work_package: WorkPackage,
+    #[serde(rename = "_meta")]
+    meta: CopyMeta,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct WorkPackageResponse {
-    #[serde(rename = "_type")]
-    type_name: String,
-    id: Id,
-    subject: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
-    project_id: Id,
-    type_id: Id,
-    status_id: Id,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    priority_id: Option<Id>,
-    author_id: Id,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    assigned_to_id: Option<Id>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    start_date: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    due_date: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    estimated_hours: Option<f64>,
-    done_ratio: i32,
-    lock_version: i32,
-    created_at: String,
-    updated_at: String,
+struct CopyMeta {
+    substitutions: Vec<Substitution>,
+}
+
+pub(crate) fn work_package_response(row: WorkPackageRow, description_html: Option<String>) -> WorkPackage {
+    WorkPackage {
+        type_name: "WorkPackage".into(),
+        id: row.id,
+        description: row
+            .description
+            .zip(description_html)
+            .map(|(raw, html)| FormattableText::markdown_rendered(&raw, html)),
+        subject: row.subject,
+        project_id: row.project_id,
+        type_id: row.type_id,
+        status_id: row.status_id,
+        priority_id: row.priority_id,
+        author_id: row.author_id,
+        assigned_to_id: row.assigned_to_id,
+        start_date: row.start_date.map(|d| d.to_string()),
+        due_date: row.due_date.map(|d| d.to_string()),
+        estimated_hours: row.estimated_hours,
+        done_ratio: row.done_ratio,
+        lock_version: row.lock_version,
+        created_at: row.created_at.to_rfc3339(),
+        updated_at: row.updated_at.to_rfc3339(),
+        unseen_changes: None,
+        links: HalLinks::new(),
+    }
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct CreateWorkPackageDto {
-    pub subject: String,
-    #[serde(default)]
-    pub description: Option<String>,
-    pub project_id: Option<Id>,
-    pub type_id: Option<Id>,
-    pub status_id: Option<Id>,
-    pub priority_id: Option<Id>,
-    pub assigned_to_id: Option<Id>,
-    pub parent_id: Option<Id>,
-    pub estimated_hours: Option<f64>,
+pub struct ProjectWorkPackagesParams {
+    pub include_subprojects: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Options of a copy; attachments are copied unless disabled
+#[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct UpdateWorkPackageDto {
-    pub subject: Option<String>,
-    pub description: Option<String>,
-    pub type_id: Option<Id>,
-    pub status_id: Option<Id>,
-    pub priority_id: Option<Id>,
-    pub assigned_to_id: Option<Id>,
-    pub estimated_hours: Option<f64>,
-    pub done_ratio: Option<i32>,
-    #[serde(default)]
-    pub lock_version: i32,
+pub struct CopyWorkPackageDto {
+    pub project_id: Option<Id>,
+    pub subject_prefix: Option<String>,
+    pub copy_attachments: Option<bool>,
+    pub copy_watchers: Option<bool>,
+    pub copy_children: Option<bool>,
+}
+
+impl CopyWorkPackageDto {
+    fn into_params(self) -> CopyWorkPackageParams {
+        let defaults = CopyWorkPackageParams::default();
+        CopyWorkPackageParams {
+            project_id: self.project_id,
+            subject_prefix: self.subject_prefix.unwrap_or(defaults.subject_prefix),
+            copy_attachments: self.copy_attachments.unwrap_or(defaults.copy_attachments),
+            copy_watchers: self.copy_watchers.unwrap_or(defaults.copy_watchers),
+            copy_children: self.copy_children.unwrap_or(defaults.copy_children),
+        }
+    }
 }
@@ -0,0 +1,383 @@
+//! Idempotent POST requests
+//!
+//! Creating handlers wrapped with [`idempotent`] accept an `Idempotency-Key`
+//! header. The first request with a key runs the handler; when it succeeds
+//! its response is stored, and retries with the same key get that response
+//! back, marked with `Idempotent-Replayed: true`, without running the
+//! handler again. A retry with the same key but a different method, path or
+//! body is rejected with a 422; one arriving while the first request still
+//! runs gets a 409. Failed requests release their key, so they can be
+//! retried.
+//!
+//! Keys are scoped to the authenticated user and expire after the
+//! configured lifetime. Requests without the header, of anonymous users or
+//! with idempotency disabled run as usual.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{FromRequest, FromRequestParts, OriginalUri, Request},
+    handler::Handler,
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use op_core::error::ValidationErrors;
+use op_db::{IdempotencyClaim, IdempotencyRequest, StoredResponse, DEFAULT_IDEMPOTENCY_TTL};
+use sha2::{Digest, Sha256};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser};
+
+/// Header carrying the client's key of a request
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header marking a replayed response
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Longest accepted key
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Whether and for how long idempotency keys are honored
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    pub enabled: bool,
+    /// How long a key and its response are kept
+    pub ttl: Duration,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl: DEFAULT_IDEMPOTENCY_TTL,
+        }
+    }
+}
+
+/// Handler honoring idempotency keys, see [`idempotent`]
+#[derive(Clone)]
+pub struct Idempotent<H>(H);
+
+/// Let retries of the handler's requests with the same idempotency key
+/// replay the first response
+pub fn idempotent<H>(handler: H) -> Idempotent<H> {
+    Idempotent(handler)
+}
+
+impl<H, T> Handler<T, AppState> for Idempotent<H>
+where
+    H: Handler<T, AppState>,
+    T: 'static,
+{
+    type Future = Pin<Box<dyn Future<Output = Response> + Send>>;
+
+    fn call(self, request: Request, state: AppState) -> Self::Future {
+        Box::pin(async move {
+            match call_idempotently(self.0, request, state).await {
+                Ok(response) => response,
+                Err(e) => e.into_response(),
+            }
+        })
+    }
+}
+
+async fn call_idempotently<H, T>(handler: H, request: Request, state: AppState) -> ApiResult<Response>
+where
+    H: Handler<T, AppState>,
+{
+    let key = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) if state.config.idempotency.enabled => parse_key(value)?,
+        _ => return Ok(handler.call(request, state).await),
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let user = AuthenticatedUser::from_request_parts(&mut parts, &state).await?;
+    if user.0.is_anonymous() {
+        // Anonymous clients cannot be told apart, so their keys would clash
+        return Ok(handler.call(Request::from_parts(parts, body), state).await);
+    }
+
+    // Buffered within the body limit the handler's extractors apply
+    let body = match Bytes::from_request(Request::from_parts(parts.clone(), body), &state).await {
+        Ok(body) => body,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    let request_key = IdempotencyRequest {
+        key,
+        user_id: user.0.id(),
+        fingerprint: fingerprint(&parts, &body),
+    };
+
+    let store = state.idempotency.clone();
+    let claim = store
+        .claim(&request_key, state.config.idempotency.ttl)
+        .await
+        .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+    match claim {
+        IdempotencyClaim::Claimed => {}
+        IdempotencyClaim::Replay(stored) => return Ok(replay(stored)),
+        IdempotencyClaim::InProgress => {
+            return Err(ApiError::conflict(
+                "A request with this Idempotency-Key is still being processed.",
+            ))
+        }
+        IdempotencyClaim::Mismatch => {
+            let mut errors = ValidationErrors::new();
+            errors.add_base("Idempotency-Key was already used for a different request.");
+            return Err(ApiError::Validation(errors));
+        }
+    }
+
+    let response = handler.call(Request::from_parts(parts, Body::from(body)), state).await;
+    if !response.status().is_success() {
+        if let Err(e) = store.release(&request_key).await {
+            tracing::warn!(error = %e, "Failed to release idempotency key");
+        }
+        return Ok(response);
+    }
+
+    let (response_parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| ApiError::internal(format!("Response error: {}", e)))?;
+    match String::from_utf8(body.to_vec()) {
+        Ok(text) => {
+            let stored = StoredResponse {
+                status: response_parts.status.as_u16(),
+                content_type: response_parts
+                    .headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+                resource_id: serde_json::from_str::<serde_json::Value>(&text)
+                    .ok()
+                    .and_then(|json| json["id"].as_i64()),
+                body: text,
+            };
+            // The request succeeded; a retry finds the key claimed until it expires
+            if let Err(e) = store.complete(&request_key, stored).await {
+                tracing::warn!(error = %e, "Failed to store idempotent response");
+            }
+        }
+        Err(_) => {
+            if let Err(e) = store.release(&request_key).await {
+                tracing::warn!(error = %e, "Failed to release idempotency key");
+            }
+        }
+    }
+
+    Ok(Response::from_parts(response_parts, Body::from(body)))
+}
+
+fn parse_key(value: &HeaderValue) -> ApiResult<String> {
+    value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            ApiError::bad_request(format!(
+                "Idempotency-Key must consist of 1 to {} visible ASCII characters.",
+                MAX_KEY_LENGTH
+            ))
+        })
+}
+
+/// Hash of the method, full path and body of a request
+fn fingerprint(parts: &Parts, body: &[u8]) -> String {
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map(|original| &original.0)
+        .unwrap_or(&parts.uri);
+
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str());
+    hasher.update(b"\n");
+    hasher.update(uri.path_and_query().map_or(uri.path(), |p| p.as_str()));
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.body).into_response();
+    let headers = response.headers_mut();
+    match stored.content_type.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        Some(content_type) => {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+        None => {
+            headers.remove(header::CONTENT_TYPE);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::post, Json, Router};
+    use op_db::{IdempotencyStore, MemoryIdempotencyStore};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    /// Handler counting its runs; a subject of "fail" is rejected and one
+    /// of "wait" blocks until released
+    #[derive(Clone, Default)]
+    struct Probe {
+        runs: Arc<AtomicUsize>,
+        started: Arc<Notify>,
+        release: Arc<Notify>,
+    }
+
+    fn app(probe: Probe, state: AppState) -> Router {
+        let create = move |State(_): State<AppState>, _user: AuthenticatedUser, Json(body): Json<serde_json::Value>| {
+            let probe = probe.clone();
+            async move {
+                let run = probe.runs.fetch_add(1, Ordering::SeqCst) as i64 + 1;
+                match body["subject"].as_str() {
+                    Some("fail") => return ApiError::bad_request("Subject is invalid").into_response(),
+                    Some("wait") => {
+                        probe.started.notify_one();
+                        probe.release.notified().await;
+                    }
+                    _ => {}
+                }
+                (StatusCode::CREATED, Json(serde_json::json!({ "id": run, "subject": body["subject"] }))).into_response()
+            }
+        };
+        Router::new()
+            .route("/api/v3/work_packages", post(idempotent(create)))
+            .with_state(state)
+    }
+
+    fn request(key: Option<&str>, subject: &str) -> Request {
+        let mut builder = Request::builder()
+            .method("POST")
+            .uri("/api/v3/work_packages")
+            .header("content-type", "application/json")
+            .header("authorization", "Basic YXBpOnNlY3JldA==");
+        if let Some(key) = key {
+            builder = builder.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        builder
+            .body(Body::from(serde_json::json!({ "subject": subject }).to_string()))
+            .unwrap()
+    }
+
+    async fn send(app: &Router, request: Request) -> (StatusCode, Option<String>, serde_json::Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let replayed = response
+            .headers()
+            .get(IDEMPOTENT_REPLAYED_HEADER)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, replayed, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_retry_replays_the_first_response() {
+        let probe = Probe::default();
+        let app = app(probe.clone(), AppState::default());
+
+        let (status, replayed, first) = send(&app, request(Some("create-1"), "Task")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(replayed, None);
+
+        let (status, replayed, retry) = send(&app, request(Some("create-1"), "Task")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(replayed.as_deref(), Some("true"));
+        assert_eq!(retry, first);
+        assert_eq!(probe.runs.load(Ordering::SeqCst), 1);
+
+        // Another key, or none, creates again
+        send(&app, request(Some("create-2"), "Task")).await;
+        send(&app, request(None, "Task")).await;
+        send(&app, request(None, "Task")).await;
+        assert_eq!(probe.runs.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_key_reused_for_a_different_body_is_rejected() {
+        let probe = Probe::default();
+        let app = app(probe.clone(), AppState::default());
+
+        send(&app, request(Some("create-1"), "Task")).await;
+        let (status, _, body) = send(&app, request(Some("create-1"), "Bug")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().contains("different request"));
+        assert_eq!(probe.runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_can_be_retried() {
+        let probe = Probe::default();
+        let app = app(probe.clone(), AppState::default());
+
+        let (status, _, _) = send(&app, request(Some("create-1"), "fail")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, replayed, _) = send(&app, request(Some("create-1"), "fail")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(replayed, None);
+        assert_eq!(probe.runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_requests_run_once() {
+        let probe = Probe::default();
+        let app = app(probe.clone(), AppState::default());
+
+        let first = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, request(Some("create-1"), "wait")).await }
+        });
+        probe.started.notified().await;
+
+        let (status, _, _) = send(&app, request(Some("create-1"), "wait")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        probe.release.notify_one();
+        let (status, _, _) = first.await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, replayed, _) = send(&app, request(Some("create-1"), "wait")).await;
+        assert_eq!(replayed.as_deref(), Some("true"));
+        assert_eq!(probe.runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_ignored_when_disabled() {
+        let probe = Probe::default();
+        let store = Arc::new(MemoryIdempotencyStore::new());
+        let config = crate::extractors::AppConfig {
+            idempotency: IdempotencyConfig { enabled: false, ..Default::default() },
+            ..Default::default()
+        };
+        let state = AppState::default()
+            .with_config(config)
+            .with_idempotency_store(store.clone() as Arc<dyn IdempotencyStore>);
+        let app = app(probe.clone(), state);
+
+        send(&app, request(Some("create-1"), "Task")).await;
+        let (_, replayed, _) = send(&app, request(Some("create-1"), "Task")).await;
+        assert_eq!(replayed, None);
+        assert_eq!(probe.runs.load(Ordering::SeqCst), 2);
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_key_is_rejected() {
+        let app = app(Probe::default(), AppState::default());
+        let (status, _, _) = send(&app, request(Some(&"k".repeat(256)), "Task")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -4,11 +4,37 @@
 //!
 //! This crate implements the HAL+JSON API matching OpenProject's API v3.
 
+// The component schemas of the OpenAPI document are one large `json!`
+#![recursion_limit = "256"]
+
+pub mod capabilities;
 pub mod error;
 pub mod extractors;
+pub mod filters;
+pub mod formatting;
 pub mod handlers;
+pub mod idempotency;
+pub mod links;
+pub mod locale;
+pub mod maintenance;
+pub mod openapi;
+pub mod query_budget;
 pub mod representers;
+pub mod request_id;
 pub mod routes;
+pub mod streaming;
+pub mod url_root;
+pub mod version;
+pub mod visibility;
 
-pub use routes::router;
+pub use capabilities::{Capability, CapabilityRegistry};
+pub use routes::{router, router_with_features, router_with_root};
+pub use idempotency::{idempotent, IdempotencyConfig, IDEMPOTENCY_KEY_HEADER};
+pub use locale::{locale_middleware, RequestLocale};
+pub use maintenance::{maintenance_middleware, MaintenanceMode, MaintenanceState};
+pub use query_budget::{query_budget_middleware, QueryBudget, DEFAULT_QUERY_BUDGET};
+pub use request_id::{request_id_middleware, RequestId};
+pub use url_root::{url_root_middleware, UrlRoot};
+pub use version::{version_header_middleware, OP_RS_VERSION, OP_RS_VERSION_HEADER};
+pub use visibility::{UserVisibility, Viewer};
 pub use representers::{HalCollection, HalError, HalLink, HalLinks, HalResource};
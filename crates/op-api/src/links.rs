@@ -0,0 +1,142 @@
+//! Associations in the `_links` of Request Bodies
+//!
+//! Clients set the associations of a resource by linking to the associated
+//! resources, e.g. `"_links": {"assignee": {"href": "/api/v3/users/5"}}`,
+//! and clear them with a null href. The links are mapped onto the ids the
+//! handlers work with; a link pointing at another kind of resource fails
+//! with a 422 for its relation.
+
+use op_core::error::ValidationErrors;
+use op_core::representations::{parse_resource_href, AssociationLinks};
+use op_core::traits::Id;
+
+use crate::error::{ApiError, ApiResult};
+
+/// Principals work packages may be assigned to
+const PRINCIPALS: &[&str] = &["users", "groups", "placeholder_users"];
+
+/// Associations of a work package set through links. Required
+/// associations cannot be cleared; for the others, `Some(None)` stands for
+/// a null href and `None` for an absent link.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WorkPackageAssociations {
+    pub project_id: Option<Id>,
+    pub type_id: Option<Id>,
+    pub status_id: Option<Id>,
+    pub priority_id: Option<Id>,
+    pub assigned_to_id: Option<Option<Id>>,
+    pub responsible_id: Option<Option<Id>>,
+    pub version_id: Option<Option<Id>>,
+    pub category_id: Option<Option<Id>>,
+    pub parent_id: Option<Option<Id>>,
+}
+
+impl WorkPackageAssociations {
+    /// Map the links of a work package payload onto ids; links of other
+    /// relations are ignored
+    pub fn from_links(links: &AssociationLinks) -> ApiResult<Self> {
+        let mut errors = ValidationErrors::new();
+        let mut required = |relation: &str, resource_types: &[&str]| {
+            match linked_id(links, relation, resource_types, &mut errors) {
+                Some(None) => {
+                    errors.add(relation, "can't be blank");
+                    None
+                }
+                linked => linked.flatten(),
+            }
+        };
+        let project_id = required("project", &["projects"]);
+        let type_id = required("type", &["types"]);
+        let status_id = required("status", &["statuses"]);
+        let priority_id = required("priority", &["priorities"]);
+
+        let associations = Self {
+            project_id,
+            type_id,
+            status_id,
+            priority_id,
+            assigned_to_id: linked_id(links, "assignee", PRINCIPALS, &mut errors),
+            responsible_id: linked_id(links, "responsible", PRINCIPALS, &mut errors),
+            version_id: linked_id(links, "version", &["versions"], &mut errors),
+            category_id: linked_id(links, "category", &["categories"], &mut errors),
+            parent_id: linked_id(links, "parent", &["work_packages"], &mut errors),
+        };
+        if errors.is_empty() {
+            Ok(associations)
+        } else {
+            Err(ApiError::validation(errors))
+        }
+    }
+}
+
+/// Id of the resource linked for the relation: `None` without a link,
+/// `Some(None)` for a null href. Hrefs not pointing at one of the resource
+/// types are reported for the relation.
+fn linked_id(
+    links: &AssociationLinks,
+    relation: &str,
+    resource_types: &[&str],
+    errors: &mut ValidationErrors,
+) -> Option<Option<Id>> {
+    let link = links.get(relation)?;
+    let Some(href) = link.href.as_deref() else {
+        return Some(None);
+    };
+
+    match parse_resource_href(href) {
+        Some(resource) if resource_types.contains(&resource.resource_type.as_str()) => Some(Some(resource.id)),
+        _ => {
+            errors.add(
+                relation,
+                format!("is expected to be a link like '/api/v3/{}/:id', but got '{}'", resource_types[0], href),
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_core::representations::AssociationLink;
+
+    fn links(entries: &[(&str, AssociationLink)]) -> AssociationLinks {
+        entries.iter().map(|(relation, link)| (relation.to_string(), link.clone())).collect()
+    }
+
+    #[test]
+    fn test_links_map_onto_ids() {
+        let links = links(&[
+            ("assignee", AssociationLink::new("/api/v3/users/5")),
+            ("responsible", AssociationLink::new("/api/v3/groups/7/")),
+            ("status", AssociationLink::new("/api/v3/statuses/3")),
+            ("version", AssociationLink::null()),
+            ("self", AssociationLink::new("/api/v3/work_packages/1")),
+        ]);
+
+        let associations = WorkPackageAssociations::from_links(&links).unwrap();
+        assert_eq!(associations.assigned_to_id, Some(Some(5)));
+        assert_eq!(associations.responsible_id, Some(Some(7)));
+        assert_eq!(associations.status_id, Some(3));
+        assert_eq!(associations.version_id, Some(None));
+        assert_eq!(associations.category_id, None);
+        assert_eq!(associations.parent_id, None);
+    }
+
+    #[test]
+    fn test_links_to_other_resources_are_rejected() {
+        let links = links(&[
+            ("status", AssociationLink::new("/api/v3/types/3")),
+            ("assignee", AssociationLink::new("users/5")),
+            ("type", AssociationLink::null()),
+        ]);
+
+        let Err(ApiError::Validation(errors)) = WorkPackageAssociations::from_links(&links) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(errors.errors.len(), 3);
+        assert!(errors.errors["status"][0].contains("'/api/v3/statuses/:id'"));
+        assert!(errors.errors.contains_key("assignee"));
+        assert_eq!(errors.errors["type"], vec!["can't be blank".to_string()]);
+    }
+}
@@ -0,0 +1,114 @@
+//! Request locale middleware
+//!
+//! Negotiates the locale of a request from its `Accept-Language` header and
+//! runs the rest of the stack within it through `op_core::i18n`, so error
+//! bodies and representers render in it. Requests naming no available
+//! language take their user's language once authenticated (see
+//! [`AuthenticatedUser`](crate::extractors::AuthenticatedUser)), and the
+//! instance default otherwise. The response names the locale in its
+//! `Content-Language` header; JSON property names are never translated.
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request},
+    http::{header, request::Parts, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use op_core::i18n::{self, I18n};
+
+use crate::error::ApiError;
+
+/// Locale of the current request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestLocale(pub String);
+
+impl RequestLocale {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Negotiate the request's locale and run the rest of the stack within it
+pub async fn locale_middleware(request: Request, next: Next) -> Response {
+    let i18n = I18n::shared();
+    let negotiated = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|accept_language| i18n.negotiate(accept_language));
+
+    let (mut response, locale) = i18n::scope_locale(negotiated, async {
+        let response = next.run(request).await;
+        (response, i18n.current_locale())
+    })
+    .await;
+
+    if let Ok(value) = HeaderValue::from_str(&locale) {
+        response.headers_mut().insert(header::CONTENT_LANGUAGE, value);
+    }
+    response
+}
+
+/// The locale resolved so far; extract it after the
+/// [`AuthenticatedUser`](crate::extractors::AuthenticatedUser) for the
+/// user's language to be taken into account
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestLocale
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(_parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(RequestLocale(I18n::shared().current_locale()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn locale_handler(locale: RequestLocale) -> String {
+        locale.0
+    }
+
+    async fn adopting_handler() -> String {
+        i18n::set_current_locale("fr".into());
+        I18n::shared().current_locale()
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/locale", get(locale_handler))
+            .route("/adopt", get(adopting_handler))
+            .layer(middleware::from_fn(locale_middleware))
+    }
+
+    async fn get_with_language(uri: &str, accept_language: Option<&str>) -> (String, String) {
+        let mut request = axum::http::Request::builder().uri(uri);
+        if let Some(accept_language) = accept_language {
+            request = request.header("Accept-Language", accept_language);
+        }
+        let response = app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+
+        let content_language = response.headers()[header::CONTENT_LANGUAGE].to_str().unwrap().to_string();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (String::from_utf8(bytes.to_vec()).unwrap(), content_language)
+    }
+
+    #[tokio::test]
+    async fn test_accept_language_is_negotiated() {
+        assert_eq!(get_with_language("/locale", Some("de-DE,de;q=0.9")).await, ("de".into(), "de".into()));
+        assert_eq!(get_with_language("/locale", Some("ja, fr;q=0.5")).await, ("fr".into(), "fr".into()));
+        assert_eq!(get_with_language("/locale", Some("ja")).await, ("en".into(), "en".into()));
+        assert_eq!(get_with_language("/locale", None).await, ("en".into(), "en".into()));
+    }
+
+    #[tokio::test]
+    async fn test_locale_set_by_the_stack_is_reported() {
+        assert_eq!(get_with_language("/adopt", None).await, ("fr".into(), "fr".into()));
+    }
+}
@@ -0,0 +1,245 @@
+//! Maintenance mode
+//!
+//! Operators quiesce the instance before migrations or storage moves by
+//! enabling the maintenance mode, optionally until a scheduled end. While it
+//! is active, [`maintenance_middleware`] answers every request other than a
+//! read with a 503 carrying a `Retry-After` header and the operator's
+//! message; the toggle endpoint and the health checks stay available. Job
+//! workers pausing on [`MaintenanceMode::paused`] stop dequeuing new jobs
+//! and let the running ones finish.
+//!
+//! The mode is stored in the `maintenance_mode` setting and cached in a
+//! watch channel. [`MaintenanceMode::monitor`] reloads the setting, so
+//! instances sharing the database follow a toggle, and ends the mode once
+//! its scheduled end has passed.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use op_db::{RepositoryResult, SettingRepository};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::watch;
+
+use crate::error::ApiError;
+
+/// Name of the setting storing the mode
+pub const MAINTENANCE_SETTING: &str = "maintenance_mode";
+
+/// Path of the admin endpoint toggling the mode
+pub const MAINTENANCE_PATH: &str = "/api/v3/maintenance";
+
+/// `Retry-After` of blocked requests when the mode has no scheduled end
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// How often [`MaintenanceMode::monitor`] reloads the setting
+pub const MAINTENANCE_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Stored state of the maintenance mode
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    /// Message of the operator shown to blocked clients
+    pub message: Option<String>,
+    /// Scheduled end; the mode is over once it has passed
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceState {
+    /// Maintenance until further notice or the scheduled end
+    pub fn enabled(message: Option<String>, ends_at: Option<DateTime<Utc>>) -> Self {
+        Self { enabled: true, message, ends_at }
+    }
+
+    /// Whether writes are blocked at `now`
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> bool {
+        match self.ends_at {
+            Some(ends_at) => self.enabled && now < ends_at,
+            None => self.enabled,
+        }
+    }
+
+    /// Seconds clients should wait before retrying, until the scheduled end
+    /// if there is one
+    pub fn retry_after(&self, now: DateTime<Utc>) -> u64 {
+        match self.ends_at {
+            Some(ends_at) if ends_at > now => (ends_at - now).num_seconds().max(1) as u64,
+            _ => DEFAULT_RETRY_AFTER.as_secs(),
+        }
+    }
+
+    /// Error answering a blocked request
+    pub fn error(&self, now: DateTime<Utc>) -> ApiError {
+        let mut message = String::from("The instance is undergoing maintenance; only reading is possible until it ends.");
+        if let Some(operator_message) = &self.message {
+            message.push(' ');
+            message.push_str(operator_message);
+        }
+        ApiError::service_unavailable(message, self.retry_after(now))
+    }
+}
+
+/// Maintenance mode of the instance, cached from the settings
+pub struct MaintenanceMode {
+    state: watch::Sender<MaintenanceState>,
+    paused: watch::Sender<bool>,
+    pool: Option<PgPool>,
+}
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MaintenanceMode {
+    /// Mode kept in memory only, disabled
+    pub fn new() -> Self {
+        Self {
+            state: watch::channel(MaintenanceState::default()).0,
+            paused: watch::channel(false).0,
+            pool: None,
+        }
+    }
+
+    /// Mode stored in the settings of the database; call
+    /// [`reload`](Self::reload) to read the stored state
+    pub fn with_pool(pool: PgPool) -> Self {
+        Self { pool: Some(pool), ..Self::new() }
+    }
+
+    /// Current state, possibly past its scheduled end
+    pub fn current(&self) -> MaintenanceState {
+        self.state.borrow().clone()
+    }
+
+    /// Whether writes are blocked now
+    pub fn is_active(&self) -> bool {
+        self.state.borrow().is_active_at(Utc::now())
+    }
+
+    /// Changes of the state
+    pub fn subscribe(&self) -> watch::Receiver<MaintenanceState> {
+        self.state.subscribe()
+    }
+
+    /// Flag set while the mode is active, e.g. to pause job workers
+    pub fn paused(&self) -> watch::Receiver<bool> {
+        self.paused.subscribe()
+    }
+
+    /// Store and publish the state
+    pub async fn set(&self, state: MaintenanceState) -> RepositoryResult<()> {
+        if let Some(pool) = &self.pool {
+            let value = serde_json::to_string(&state).expect("maintenance state serializes");
+            SettingRepository::new(pool.clone()).set(MAINTENANCE_SETTING, &value).await?;
+        }
+        self.publish(state);
+        Ok(())
+    }
+
+    /// Read the stored state, e.g. after another instance toggled the mode
+    pub async fn reload(&self) -> RepositoryResult<()> {
+        let Some(pool) = &self.pool else {
+            self.publish(self.current());
+            return Ok(());
+        };
+
+        let stored = SettingRepository::new(pool.clone()).get(MAINTENANCE_SETTING).await?;
+        let state = match stored.as_deref().map(serde_json::from_str::<MaintenanceState>) {
+            Some(Ok(state)) => state,
+            Some(Err(e)) => {
+                tracing::warn!(error = %e, "Invalid maintenance mode setting, treating it as disabled");
+                MaintenanceState::default()
+            }
+            None => MaintenanceState::default(),
+        };
+        self.publish(state);
+        Ok(())
+    }
+
+    /// Reload the state every `interval` until shutdown
+    pub async fn monitor(self: Arc<Self>, interval: Duration, mut shutdown: watch::Receiver<bool>) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.reload().await {
+                        tracing::warn!(error = %e, "Failed to reload the maintenance mode");
+                    }
+                }
+                _ = shutdown.changed() => break,
+            }
+        }
+    }
+
+    fn publish(&self, state: MaintenanceState) {
+        let active = state.is_active_at(Utc::now());
+        self.state.send_if_modified(|current| {
+            let modified = *current != state;
+            *current = state;
+            modified
+        });
+        self.paused.send_if_modified(|paused| std::mem::replace(paused, active) != active);
+    }
+}
+
+/// Answer requests other than reads with a 503 while the maintenance mode
+/// is active; the toggle endpoint and health checks are let through
+pub async fn maintenance_middleware(
+    State(mode): State<Arc<MaintenanceMode>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let now = Utc::now();
+    let state = mode.current();
+    if state.is_active_at(now) && !is_exempt(request.method(), request.uri().path()) {
+        return state.error(now).into_response();
+    }
+    next.run(request).await
+}
+
+fn is_exempt(method: &Method, path: &str) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        || path == MAINTENANCE_PATH
+        || path == "/health"
+        || path.starts_with("/health/")
+        || path.starts_with("/health_checks/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_ends_at_the_scheduled_end() {
+        let now = Utc::now();
+        let state = MaintenanceState::enabled(None, Some(now + chrono::Duration::seconds(90)));
+
+        assert!(state.is_active_at(now));
+        assert_eq!(state.retry_after(now), 90);
+        assert!(!state.is_active_at(now + chrono::Duration::seconds(90)));
+        assert_eq!(MaintenanceState::enabled(None, None).retry_after(now), 300);
+    }
+
+    #[tokio::test]
+    async fn test_paused_follows_the_mode() {
+        let mode = MaintenanceMode::new();
+        let paused = mode.paused();
+        assert!(!*paused.borrow());
+
+        mode.set(MaintenanceState::enabled(Some("Moving storage".into()), None)).await.unwrap();
+        assert!(*paused.borrow());
+        assert!(mode.is_active());
+
+        mode.set(MaintenanceState::default()).await.unwrap();
+        assert!(!*paused.borrow());
+    }
+}
@@ -0,0 +1,1132 @@
+//! OpenAPI specification
+//!
+//! Describes the subset of API v3 implemented by this crate. The operations
+//! are maintained by hand next to the router; the tests compare them with
+//! the routes registered in `routes.rs` so the two cannot drift apart.
+
+use axum::Json;
+use serde_json::{json, Map, Value};
+
+/// Path of the served specification
+pub const SPEC_PATH: &str = "/api/v3/spec.json";
+
+/// A documented API operation
+#[derive(Debug, Clone, Copy)]
+pub struct Operation {
+    /// Lowercase HTTP method
+    pub method: &'static str,
+    /// Path in router syntax (`/api/v3/work_packages/:id`)
+    pub path: &'static str,
+    pub tag: &'static str,
+    pub summary: &'static str,
+    /// Request body schema, if the operation takes one
+    pub request: Option<&'static str>,
+    pub status: u16,
+    /// Response body schema, `None` for empty responses
+    pub response: Option<&'static str>,
+    /// Whether the response is a collection of `response` elements
+    pub collection: bool,
+    /// Whether the operation can be called without credentials
+    pub public: bool,
+    /// Whether retries with the same `Idempotency-Key` replay the response
+    pub idempotent: bool,
+}
+
+impl Operation {
+    const fn new(method: &'static str, path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            tag,
+            summary,
+            request: None,
+            status: 200,
+            response: Some("Resource"),
+            collection: false,
+            public: false,
+            idempotent: false,
+        }
+    }
+
+    const fn get(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Self::new("get", path, tag, summary)
+    }
+
+    const fn post(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Self::new("post", path, tag, summary)
+    }
+
+    const fn patch(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Self::new("patch", path, tag, summary)
+    }
+
+    const fn put(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Self::new("put", path, tag, summary)
+    }
+
+    const fn delete(path: &'static str, tag: &'static str, summary: &'static str) -> Self {
+        Self::new("delete", path, tag, summary).no_content()
+    }
+
+    const fn request(mut self, schema: &'static str) -> Self {
+        self.request = Some(schema);
+        self
+    }
+
+    const fn returns(mut self, status: u16, schema: &'static str) -> Self {
+        self.status = status;
+        self.response = Some(schema);
+        self
+    }
+
+    const fn collection(mut self, schema: &'static str) -> Self {
+        self.response = Some(schema);
+        self.collection = true;
+        self
+    }
+
+    const fn no_content(mut self) -> Self {
+        self.status = 204;
+        self.response = None;
+        self
+    }
+
+    const fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    const fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /// Path in OpenAPI syntax (`/api/v3/work_packages/{id}`)
+    pub fn openapi_path(&self) -> String {
+        self.path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn path_params(&self) -> impl Iterator<Item = &'static str> {
+        self.path.split('/').filter_map(|segment| segment.strip_prefix(':'))
+    }
+}
+
+/// The implemented operations
+pub const OPERATIONS: &[Operation] = &[
+    Operation::get("/api/v3", "Root", "View the API root").public(),
+    Operation::get(SPEC_PATH, "Root", "View this OpenAPI specification")
+        .returns(200, "OpenApi")
+        .public(),
+    Operation::get("/api/v3/capabilities", "Root", "List the capabilities of this server")
+        .returns(200, "Capabilities")
+        .public(),
+    // Work packages
+    Operation::get("/api/v3/work_packages", "Work Packages", "List work packages").collection("WorkPackage"),
+    Operation::post("/api/v3/work_packages", "Work Packages", "Create a work package")
+        .request("WorkPackageCreate")
+        .returns(201, "WorkPackage")
+        .idempotent(),
+    Operation::post("/api/v3/work_packages/form", "Work Packages", "Validate a work package to create")
+        .request("WorkPackageCreate")
+        .returns(200, "WorkPackage"),
+    Operation::patch("/api/v3/work_packages/bulk", "Work Packages", "Update several work packages")
+        .request("WorkPackageBulkUpdate")
+        .collection("WorkPackage"),
+    Operation::get("/api/v3/work_packages/:id", "Work Packages", "View a work package").returns(200, "WorkPackage"),
+    Operation::patch("/api/v3/work_packages/:id", "Work Packages", "Update a work package")
+        .request("WorkPackageUpdate")
+        .returns(200, "WorkPackage"),
+    Operation::delete("/api/v3/work_packages/:id", "Work Packages", "Move a work package to the trash"),
+    Operation::post("/api/v3/work_packages/:id/form", "Work Packages", "Validate changes to a work package")
+        .request("WorkPackageUpdate")
+        .returns(200, "WorkPackage"),
+    Operation::post("/api/v3/work_packages/:id/restore", "Work Packages", "Restore a work package from the trash")
+        .returns(200, "WorkPackage"),
+    Operation::post(
+        "/api/v3/work_packages/:id/custom_actions/:action_id/execute",
+        "Custom Actions",
+        "Execute a custom action on a work package",
+    )
+    .request("Resource")
+    .returns(200, "WorkPackage"),
+    Operation::post("/api/v3/work_packages/:id/copy", "Work Packages", "Copy a work package")
+        .request("WorkPackageCopy")
+        .returns(201, "WorkPackage"),
+    Operation::get("/api/v3/work_packages/schemas/:id", "Work Packages", "View a work package schema"),
+    Operation::get("/api/v3/work_packages/:id/relations", "Relations", "List relations of a work package")
+        .collection("Resource"),
+    Operation::get("/api/v3/work_packages/:id/watchers", "Watchers", "List watchers of a work package")
+        .collection("User"),
+    Operation::post("/api/v3/work_packages/:id/watchers", "Watchers", "Add a watcher")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::delete("/api/v3/work_packages/:id/watchers/:user_id", "Watchers", "Remove a watcher"),
+    Operation::get("/api/v3/work_packages/:id/watching", "Watchers", "Check whether the current user watches"),
+    Operation::post("/api/v3/work_packages/:id/watch", "Watchers", "Watch a work package").no_content(),
+    Operation::delete("/api/v3/work_packages/:id/watch", "Watchers", "Stop watching a work package"),
+    Operation::get("/api/v3/work_packages/:id/shares", "Shares", "List shares of a work package")
+        .collection("Resource"),
+    Operation::post("/api/v3/work_packages/:id/shares", "Shares", "Share a work package")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::patch("/api/v3/work_packages/:id/shares/:share_id", "Shares", "Change the role of a share")
+        .request("Resource")
+        .returns(200, "Resource"),
+    Operation::delete("/api/v3/work_packages/:id/shares/:share_id", "Shares", "Revoke a share"),
+    Operation::get("/api/v3/work_packages/:id/file_links", "File Links", "List file links of a work package")
+        .collection("Resource"),
+    Operation::post("/api/v3/work_packages/:id/file_links", "File Links", "Link files to a work package")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/work_packages/:id/attachments", "Attachments", "List attachments of a work package")
+        .collection("Resource"),
+    Operation::get("/api/v3/work_packages/:id/activities", "Activities", "List activities of a work package")
+        .collection("Resource"),
+    Operation::post("/api/v3/work_packages/:id/activities", "Activities", "Comment on a work package")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/work_packages/:id/revisions", "Activities", "List revisions of a work package")
+        .collection("Resource"),
+    // Projects
+    Operation::get("/api/v3/projects", "Projects", "List projects").collection("Resource"),
+    Operation::post("/api/v3/projects", "Projects", "Create a project")
+        .request("Resource")
+        .returns(201, "Resource")
+        .idempotent(),
+    Operation::post("/api/v3/projects/from_template", "Projects", "Create a project from a template")
+        .request("Resource")
+        .returns(202, "Resource"),
+    Operation::get("/api/v3/projects/schema", "Projects", "View the project schema"),
+    Operation::get("/api/v3/projects/:id", "Projects", "View a project"),
+    Operation::patch("/api/v3/projects/:id", "Projects", "Update a project").request("Resource"),
+    Operation::delete("/api/v3/projects/:id", "Projects", "Delete a project"),
+    Operation::post("/api/v3/projects/:id/archive", "Projects", "Archive a project"),
+    Operation::post("/api/v3/projects/:id/unarchive", "Projects", "Unarchive a project"),
+    Operation::get("/api/v3/projects/:id/storage", "Projects", "View the attachment storage used by a project"),
+    Operation::get("/api/v3/projects/:id/types", "Types", "List types of a project").collection("Resource"),
+    Operation::get("/api/v3/projects/:id/versions", "Versions", "List versions of a project").collection("Resource"),
+    Operation::get("/api/v3/projects/:id/categories", "Categories", "List categories of a project")
+        .collection("Resource"),
+    Operation::get("/api/v3/projects/:id/forums", "Forums", "List forums of a project").collection("Resource"),
+    Operation::post("/api/v3/projects/:id/forums", "Forums", "Create a forum in a project")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/projects/:id/documents", "Documents", "List documents of a project")
+        .collection("Resource"),
+    Operation::post("/api/v3/projects/:id/documents", "Documents", "Create a document in a project")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get(
+        "/api/v3/projects/:id/work_package_summary",
+        "Work Packages",
+        "Count work packages of a project by type, status and priority",
+    ),
+    Operation::get(
+        "/api/v3/projects/:id/timeline",
+        "Work Packages",
+        "Get the dated work packages of a project and the relations between them",
+    ),
+    Operation::get(
+        "/api/v3/projects/:id/reports/cumulative_flow",
+        "Work Packages",
+        "Count work packages of a project per status over time",
+    ),
+    Operation::get("/api/v3/projects/:id/work_packages", "Work Packages", "List work packages of a project")
+        .collection("WorkPackage"),
+    Operation::get(
+        "/api/v3/projects/:id/work_packages/similar",
+        "Work Packages",
+        "List work packages of a project with a similar subject",
+    )
+    .collection("WorkPackage"),
+    Operation::get("/api/v3/projects/:id/available_assignees", "Principals", "List available assignees of a project")
+        .collection("Resource"),
+    // Users
+    Operation::get("/api/v3/users", "Users", "List users").collection("User"),
+    Operation::post("/api/v3/users", "Users", "Create a user")
+        .request("UserCreate")
+        .returns(201, "User"),
+    Operation::get("/api/v3/users/me", "Users", "View the current user").returns(200, "User"),
+    Operation::get("/api/v3/users/me/api_keys", "API Keys", "List my API keys").collection("Resource"),
+    Operation::post("/api/v3/users/me/api_keys", "API Keys", "Create an API key")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::delete("/api/v3/users/me/api_keys/:id", "API Keys", "Revoke an API key"),
+    Operation::get("/api/v3/users/:id", "Users", "View a user").returns(200, "User"),
+    Operation::patch("/api/v3/users/:id", "Users", "Update a user")
+        .request("UserUpdate")
+        .returns(200, "User"),
+    Operation::delete("/api/v3/users/:id", "Users", "Delete a user").returns(202, "Resource"),
+    Operation::get("/api/v3/users/:id/notification_settings", "Notifications", "View notification settings"),
+    Operation::patch("/api/v3/users/:id/notification_settings", "Notifications", "Update notification settings")
+        .request("Resource")
+        .returns(200, "Resource"),
+    Operation::post("/api/v3/users/:id/lock", "Users", "Lock a user").returns(200, "User"),
+    Operation::delete("/api/v3/users/:id/lock", "Users", "Unlock a user").returns(200, "User"),
+    // Principals
+    Operation::get("/api/v3/principals", "Principals", "List principals").collection("Resource"),
+    Operation::get("/api/v3/groups/:id", "Principals", "View a group"),
+    Operation::get("/api/v3/placeholder_users/:id", "Principals", "View a placeholder user"),
+    // Queries
+    Operation::get("/api/v3/queries", "Queries", "List queries").collection("Resource"),
+    Operation::post("/api/v3/queries", "Queries", "Create a query")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/queries/default", "Queries", "View the default query"),
+    Operation::get("/api/v3/queries/form", "Queries", "View the query form"),
+    Operation::get("/api/v3/queries/available_projects", "Queries", "List projects available to queries")
+        .collection("Resource"),
+    Operation::post("/api/v3/queries/import", "Queries", "Import a query")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/queries/filter_instance_schemas", "Queries", "List filter instance schemas")
+        .collection("Resource"),
+    Operation::get("/api/v3/queries/filter_instance_schemas/:id", "Queries", "View a filter instance schema"),
+    Operation::get("/api/v3/queries/:id", "Queries", "View a query"),
+    Operation::patch("/api/v3/queries/:id", "Queries", "Update a query").request("Resource"),
+    Operation::delete("/api/v3/queries/:id", "Queries", "Delete a query"),
+    Operation::get("/api/v3/queries/:id/export", "Queries", "Export a query"),
+    Operation::post("/api/v3/queries/:id/star", "Queries", "Star a query"),
+    Operation::delete("/api/v3/queries/:id/star", "Queries", "Unstar a query").returns(200, "Resource"),
+    Operation::post("/api/v3/queries/:id/subscription", "Queries", "Subscribe to changes of the query results")
+        .request("Resource"),
+    Operation::delete("/api/v3/queries/:id/subscription", "Queries", "Unsubscribe from the query results"),
+    // Statuses
+    Operation::get("/api/v3/statuses", "Statuses", "List statuses").collection("Resource"),
+    Operation::post("/api/v3/statuses", "Statuses", "Create a status")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/statuses/:id", "Statuses", "View a status"),
+    Operation::patch("/api/v3/statuses/:id", "Statuses", "Update a status").request("Resource"),
+    Operation::delete("/api/v3/statuses/:id", "Statuses", "Delete a status"),
+    // Types
+    Operation::get("/api/v3/types", "Types", "List types").collection("Resource"),
+    Operation::post("/api/v3/types", "Types", "Create a type")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/types/:id", "Types", "View a type"),
+    Operation::patch("/api/v3/types/:id", "Types", "Update a type").request("Resource"),
+    Operation::delete("/api/v3/types/:id", "Types", "Delete a type"),
+    Operation::get("/api/v3/types/:id/form_configuration", "Types", "View the form configuration of a type"),
+    Operation::patch("/api/v3/types/:id/form_configuration", "Types", "Update the form configuration of a type")
+        .request("Resource"),
+    // Priorities
+    Operation::get("/api/v3/priorities", "Priorities", "List priorities").collection("Resource"),
+    Operation::post("/api/v3/priorities", "Priorities", "Create a priority")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/priorities/:id", "Priorities", "View a priority"),
+    Operation::patch("/api/v3/priorities/:id", "Priorities", "Update a priority").request("Resource"),
+    Operation::delete("/api/v3/priorities/:id", "Priorities", "Delete a priority"),
+    // Roles
+    Operation::get("/api/v3/roles", "Roles", "List roles").collection("Resource"),
+    Operation::post("/api/v3/roles", "Roles", "Create a role")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/roles/:id", "Roles", "View a role"),
+    Operation::patch("/api/v3/roles/:id", "Roles", "Update a role").request("Resource"),
+    Operation::delete("/api/v3/roles/:id", "Roles", "Delete a role"),
+    // Versions
+    Operation::get("/api/v3/versions", "Versions", "List versions").collection("Resource"),
+    Operation::post("/api/v3/versions", "Versions", "Create a version")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/versions/:id", "Versions", "View a version"),
+    Operation::patch("/api/v3/versions/:id", "Versions", "Update a version").request("Resource"),
+    Operation::delete("/api/v3/versions/:id", "Versions", "Delete a version"),
+    // Memberships
+    Operation::get("/api/v3/memberships", "Memberships", "List memberships").collection("Resource"),
+    Operation::post("/api/v3/memberships", "Memberships", "Create a membership")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/memberships/:id", "Memberships", "View a membership"),
+    Operation::patch("/api/v3/memberships/:id", "Memberships", "Update a membership").request("Resource"),
+    Operation::delete("/api/v3/memberships/:id", "Memberships", "Delete a membership"),
+    // Categories
+    Operation::get("/api/v3/categories", "Categories", "List categories").collection("Resource"),
+    Operation::post("/api/v3/categories", "Categories", "Create a category")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/categories/:id", "Categories", "View a category"),
+    Operation::patch("/api/v3/categories/:id", "Categories", "Update a category").request("Resource"),
+    Operation::delete("/api/v3/categories/:id", "Categories", "Delete a category"),
+    // Custom actions
+    Operation::get("/api/v3/custom_actions", "Custom Actions", "List custom actions").collection("Resource"),
+    Operation::post("/api/v3/custom_actions", "Custom Actions", "Create a custom action")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/custom_actions/:id", "Custom Actions", "View a custom action"),
+    Operation::patch("/api/v3/custom_actions/:id", "Custom Actions", "Update a custom action").request("Resource"),
+    Operation::delete("/api/v3/custom_actions/:id", "Custom Actions", "Delete a custom action"),
+    // Forums
+    Operation::get("/api/v3/forums/:id", "Forums", "View a forum"),
+    Operation::patch("/api/v3/forums/:id", "Forums", "Update a forum").request("Resource"),
+    Operation::get("/api/v3/forums/:id/topics", "Forums", "List topics of a forum").collection("Resource"),
+    Operation::post("/api/v3/forums/:id/topics", "Forums", "Start a topic")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/messages/:id", "Forums", "View a message"),
+    Operation::patch("/api/v3/messages/:id", "Forums", "Update a message").request("Resource"),
+    Operation::delete("/api/v3/messages/:id", "Forums", "Delete a message"),
+    Operation::get("/api/v3/messages/:id/replies", "Forums", "List replies to a topic").collection("Resource"),
+    Operation::post("/api/v3/messages/:id/replies", "Forums", "Reply to a topic")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/messages/:id/versions", "Forums", "List versions of a message").collection("Resource"),
+    // Documents
+    Operation::get("/api/v3/documents/:id", "Documents", "View a document"),
+    Operation::patch("/api/v3/documents/:id", "Documents", "Update a document").request("Resource"),
+    Operation::delete("/api/v3/documents/:id", "Documents", "Delete a document with its attachments"),
+    Operation::get("/api/v3/documents/:id/attachments", "Documents", "List attachments of a document")
+        .collection("Resource"),
+    Operation::get("/api/v3/document_categories", "Documents", "List document categories").collection("Resource"),
+    // Time entries
+    Operation::get("/api/v3/time_entries", "Time Entries", "List time entries").collection("Resource"),
+    Operation::post("/api/v3/time_entries", "Time Entries", "Create a time entry")
+        .request("Resource")
+        .returns(201, "Resource")
+        .idempotent(),
+    Operation::get("/api/v3/time_entries/aggregate", "Time Entries", "Sum up hours per group over a period")
+        .returns(200, "TimeEntryAggregate"),
+    Operation::get("/api/v3/time_entries/:id", "Time Entries", "View a time entry"),
+    Operation::patch("/api/v3/time_entries/:id", "Time Entries", "Update a time entry").request("Resource"),
+    Operation::delete("/api/v3/time_entries/:id", "Time Entries", "Delete a time entry"),
+    Operation::get("/api/v3/time_entries/activities", "Time Entries", "List time entry activities")
+        .collection("Resource"),
+    Operation::post("/api/v3/time_entries/activities", "Time Entries", "Create a time entry activity")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/time_entries/activities/:id", "Time Entries", "View a time entry activity"),
+    Operation::patch("/api/v3/time_entries/activities/:id", "Time Entries", "Update a time entry activity")
+        .request("Resource"),
+    Operation::delete("/api/v3/time_entries/activities/:id", "Time Entries", "Delete a time entry activity"),
+    // Relations
+    Operation::get("/api/v3/relations", "Relations", "List relations").collection("Resource"),
+    Operation::post("/api/v3/relations", "Relations", "Create a relation")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/relations/:id", "Relations", "View a relation"),
+    Operation::patch("/api/v3/relations/:id", "Relations", "Update a relation").request("Resource"),
+    Operation::delete("/api/v3/relations/:id", "Relations", "Delete a relation"),
+    // Attachments
+    Operation::get("/api/v3/attachments", "Attachments", "List attachments").collection("Resource"),
+    Operation::post("/api/v3/attachments", "Attachments", "Upload an attachment")
+        .request("Resource")
+        .returns(201, "Resource")
+        .idempotent(),
+    Operation::get("/api/v3/attachments/:id", "Attachments", "View an attachment"),
+    Operation::get("/api/v3/attachments/:id/content", "Attachments", "Download the content of an attachment"),
+    Operation::patch("/api/v3/attachments/:id", "Attachments", "Update an attachment").request("Resource"),
+    Operation::delete("/api/v3/attachments/:id", "Attachments", "Delete an attachment"),
+    Operation::post("/api/v3/uploads", "Attachments", "Start a resumable upload")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/uploads/:id", "Attachments", "View a resumable upload"),
+    Operation::put("/api/v3/uploads/:id/chunks/:number", "Attachments", "Send a chunk of a resumable upload"),
+    Operation::post("/api/v3/uploads/:id/complete", "Attachments", "Complete a resumable upload")
+        .request("Resource")
+        .returns(201, "Resource"),
+    // Storages
+    Operation::get("/api/v3/storages", "Storages", "List storages").collection("Resource"),
+    Operation::post("/api/v3/storages", "Storages", "Create a storage")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/storages/:id", "Storages", "View a storage"),
+    Operation::patch("/api/v3/storages/:id", "Storages", "Update a storage").request("Resource"),
+    Operation::delete("/api/v3/storages/:id", "Storages", "Delete a storage"),
+    Operation::post("/api/v3/storages/:id/oauth_client_credentials", "Storages", "Set the OAuth client of a storage")
+        .request("Resource")
+        .returns(201, "Resource"),
+    Operation::get("/api/v3/file_links/:id", "File Links", "View a file link"),
+    Operation::delete("/api/v3/file_links/:id", "File Links", "Remove a file link"),
+    // Activities
+    Operation::get("/api/v3/activities", "Activities", "List activities").collection("Resource"),
+    Operation::get("/api/v3/activities/:id", "Activities", "View an activity"),
+    Operation::patch("/api/v3/activities/:id", "Activities", "Update an activity comment").request("Resource"),
+    // Administration
+    Operation::get("/api/v3/audit_events", "Audit Events", "List audit events").collection("Resource"),
+    Operation::get("/api/v3/job_statuses/:id", "Job Statuses", "View the status of a background job"),
+    Operation::get("/api/v3/maintenance", "Maintenance", "View the maintenance mode"),
+    Operation::post("/api/v3/maintenance", "Maintenance", "Enable the maintenance mode").request("Resource"),
+    Operation::delete("/api/v3/maintenance", "Maintenance", "Disable the maintenance mode").returns(200, "Resource"),
+    Operation::get("/api/v3/integrity", "Integrity", "Audit the database for orphaned rows"),
+    Operation::post("/api/v3/integrity/repair", "Integrity", "Repair orphaned rows in batches")
+        .request("Resource")
+        .returns(200, "Resource"),
+    // Notifications
+    Operation::get("/api/v3/notifications/stream", "Notifications", "Stream notification events")
+        .returns(200, "EventStream"),
+    Operation::get("/api/v3/notifications/groups", "Notifications", "List notification groups")
+        .collection("Resource"),
+    Operation::get(
+        "/api/v3/notifications/groups/:resource_type/:resource_id",
+        "Notifications",
+        "View the notifications of a resource",
+    ),
+    Operation::post(
+        "/api/v3/notifications/groups/:resource_type/:resource_id/read_ian",
+        "Notifications",
+        "Mark the notifications of a resource read",
+    ),
+    Operation::post("/api/v3/inbound_emails", "Notifications", "Receive a reply to a notification email")
+        .returns(201, "Resource")
+        .public(),
+    #[cfg(feature = "swagger-ui")]
+    Operation::get("/api/v3/docs", "Root", "View the Swagger UI")
+        .returns(200, "Html")
+        .public(),
+];
+
+/// Build the OpenAPI document
+pub fn spec() -> Value {
+    let mut paths = Map::new();
+    for operation in OPERATIONS {
+        let path = paths
+            .entry(operation.openapi_path())
+            .or_insert_with(|| Value::Object(Map::new()));
+        path[operation.method] = operation_object(operation);
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "OpenProject RS API v3",
+            "description": "The subset of the OpenProject API v3 implemented by OpenProject RS.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": "/" }],
+        "security": [{ "basicAuth": [] }, { "bearerAuth": [] }],
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "basicAuth": {
+                    "type": "http",
+                    "scheme": "basic",
+                    "description": "Use `apikey` as the username and an API token as the password.",
+                },
+                "bearerAuth": {
+                    "type": "http",
+                    "scheme": "bearer",
+                },
+            },
+            "schemas": schemas(),
+        },
+    })
+}
+
+fn operation_object(operation: &Operation) -> Value {
+    let mut object = json!({
+        "tags": [operation.tag],
+        "summary": operation.summary,
+        "operationId": operation_id(operation),
+        "responses": responses(operation),
+    });
+
+    let mut parameters: Vec<Value> = operation
+        .path_params()
+        .map(|name| {
+            let schema = if name == "id" || name.ends_with("_id") {
+                json!({ "type": "integer", "format": "int64" })
+            } else {
+                json!({ "type": "string" })
+            };
+            json!({ "name": name, "in": "path", "required": true, "schema": schema })
+        })
+        .collect();
+    if operation.collection {
+        parameters.push(query_param("offset", json!({ "type": "integer", "minimum": 0 })));
+        parameters.push(query_param("pageSize", json!({ "type": "integer", "minimum": 1 })));
+    }
+    if operation.idempotent {
+        parameters.push(json!({
+            "name": "Idempotency-Key",
+            "in": "header",
+            "required": false,
+            "description": "Retries with the same key replay the first response",
+            "schema": { "type": "string", "maxLength": 255 },
+        }));
+    }
+    if !parameters.is_empty() {
+        object["parameters"] = Value::Array(parameters);
+    }
+
+    if let Some(schema) = operation.request {
+        object["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema_ref(schema) } },
+        });
+    }
+    if operation.public {
+        object["security"] = json!([]);
+    }
+
+    object
+}
+
+fn operation_id(operation: &Operation) -> String {
+    let mut id = operation.method.to_string();
+    for segment in operation.path.trim_start_matches("/api/v3").split(['/', '.']) {
+        let segment = segment.trim_start_matches(':');
+        for word in segment.split('_').filter(|word| !word.is_empty()) {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                id.extend(first.to_uppercase());
+                id.push_str(chars.as_str());
+            }
+        }
+    }
+    id
+}
+
+fn query_param(name: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "schema": schema })
+}
+
+fn responses(operation: &Operation) -> Value {
+    let success = match (operation.response, operation.collection) {
+        (None, _) => json!({ "description": "No content" }),
+        (Some("Html"), _) => json!({
+            "description": "HTML page",
+            "content": { "text/html": { "schema": { "type": "string" } } },
+        }),
+        (Some("EventStream"), _) => json!({
+            "description": "Server-sent events",
+            "content": { "text/event-stream": { "schema": { "type": "string" } } },
+        }),
+        (Some(schema), true) => hal_content(json!({
+            "allOf": [
+                schema_ref("Collection"),
+                {
+                    "type": "object",
+                    "properties": {
+                        "_embedded": {
+                            "type": "object",
+                            "properties": {
+                                "elements": { "type": "array", "items": schema_ref(schema) },
+                            },
+                        },
+                    },
+                },
+            ],
+        })),
+        (Some(schema), false) => hal_content(schema_ref(schema)),
+    };
+
+    let mut responses = Map::new();
+    responses.insert(operation.status.to_string(), success);
+    if !operation.public {
+        responses.insert("401".to_string(), error_response("Authentication required"));
+        responses.insert("403".to_string(), error_response("Missing permission"));
+    }
+    if operation.path_params().next().is_some() {
+        responses.insert("404".to_string(), error_response("Resource not found"));
+    }
+    if operation.request.is_some() {
+        responses.insert("422".to_string(), error_response("Validation failed"));
+    }
+    Value::Object(responses)
+}
+
+fn hal_content(schema: Value) -> Value {
+    json!({
+        "description": "Success",
+        "content": { "application/hal+json": { "schema": schema } },
+    })
+}
+
+fn error_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/hal+json": { "schema": schema_ref("Error") } },
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn schemas() -> Value {
+    let string = json!({ "type": "string" });
+    let nullable_string = json!({ "type": "string", "nullable": true });
+    let integer = json!({ "type": "integer", "format": "int64" });
+    let nullable_integer = json!({ "type": "integer", "format": "int64", "nullable": true });
+    let date = json!({ "type": "string", "format": "date" });
+    let date_time = json!({ "type": "string", "format": "date-time" });
+    let duration = json!({ "type": "string", "description": "ISO 8601 duration" });
+    let links = json!({ "type": "object", "additionalProperties": schema_ref("Link") });
+    let association_links = json!({
+        "type": "object",
+        "description": "Associations by relation, e.g. `assignee`, taking precedence over the ids; a null href clears one",
+        "additionalProperties": { "type": "object", "properties": { "href": nullable_string } },
+    });
+
+    json!({
+        "Link": {
+            "type": "object",
+            "properties": {
+                "href": nullable_string,
+                "title": string,
+                "method": string,
+                "templated": { "type": "boolean" },
+                "payload": { "type": "object" },
+                "identifier": string,
+                "type": string,
+            },
+        },
+        "Error": {
+            "type": "object",
+            "required": ["_type", "errorIdentifier", "message"],
+            "properties": {
+                "_type": { "type": "string", "enum": ["Error"] },
+                "errorIdentifier": string,
+                "message": string,
+                "requestId": string,
+            },
+        },
+        "Resource": {
+            "type": "object",
+            "description": "A HAL resource",
+            "properties": {
+                "_type": string,
+                "id": integer,
+                "_links": links,
+                "_embedded": { "type": "object" },
+            },
+            "additionalProperties": true,
+        },
+        "Collection": {
+            "type": "object",
+            "required": ["_type", "total", "count"],
+            "properties": {
+                "_type": { "type": "string", "enum": ["Collection"] },
+                "total": integer,
+                "count": integer,
+                "pageSize": integer,
+                "offset": integer,
+                "_links": links,
+                "_embedded": { "type": "object" },
+            },
+        },
+        "FormattableText": {
+            "type": "object",
+            "properties": {
+                "format": string,
+                "raw": string,
+                "html": string,
+            },
+        },
+        "WorkPackage": {
+            "type": "object",
+            "required": ["_type", "id", "lockVersion", "subject"],
+            "properties": {
+                "_type": { "type": "string", "enum": ["WorkPackage"] },
+                "id": integer,
+                "lockVersion": integer,
+                "subject": string,
+                "description": schema_ref("FormattableText"),
+                "scheduleManually": { "type": "boolean" },
+                "startDate": date,
+                "dueDate": date,
+                "derivedStartDate": date,
+                "derivedDueDate": date,
+                "duration": duration,
+                "estimatedTime": duration,
+                "derivedEstimatedTime": duration,
+                "spentTime": duration,
+                "percentageDone": integer,
+                "derivedPercentageDone": integer,
+                "createdAt": date_time,
+                "updatedAt": date_time,
+                "position": integer,
+                "storyPoints": integer,
+                "remainingTime": duration,
+                "watchers": integer,
+                "fileLinks": integer,
+                "laborCosts": { "type": "number" },
+                "materialCosts": { "type": "number" },
+                "overallCosts": { "type": "number" },
+                "_links": links,
+                "_embedded": { "type": "object" },
+                "_meta": {
+                    "type": "object",
+                    "description": "On a copy, the types and statuses replaced as the target project lacks them",
+                },
+            },
+        },
+        "WorkPackageCreate": {
+            "type": "object",
+            "required": ["subject"],
+            "properties": {
+                "subject": string,
+                "description": nullable_string,
+                "projectId": nullable_integer,
+                "typeId": nullable_integer,
+                "statusId": nullable_integer,
+                "priorityId": nullable_integer,
+                "assignedToId": nullable_integer,
+                "parentId": nullable_integer,
+                "estimatedHours": { "type": "number", "nullable": true },
+                "_links": association_links,
+            },
+        },
+        "WorkPackageUpdate": {
+            "type": "object",
+            "properties": {
+                "subject": nullable_string,
+                "description": nullable_string,
+                "typeId": nullable_integer,
+                "statusId": nullable_integer,
+                "priorityId": nullable_integer,
+                "assignedToId": nullable_integer,
+                "startDate": { "type": "string", "format": "date", "nullable": true },
+                "dueDate": { "type": "string", "format": "date", "nullable": true },
+                "estimatedHours": { "type": "number", "nullable": true },
+                "estimatedTime": { "type": "string", "description": "ISO 8601 duration", "nullable": true },
+                "doneRatio": { "type": "integer", "nullable": true },
+                "lockVersion": integer,
+                "_links": association_links,
+            },
+        },
+        "WorkPackageBulkUpdate": {
+            "type": "object",
+            "required": ["ids"],
+            "properties": {
+                "ids": { "type": "array", "items": integer },
+                "typeId": nullable_integer,
+                "statusId": nullable_integer,
+                "priorityId": nullable_integer,
+                "assignedToId": nullable_integer,
+                "startDate": { "type": "string", "format": "date", "nullable": true },
+                "dueDate": { "type": "string", "format": "date", "nullable": true },
+                "doneRatio": { "type": "integer", "nullable": true },
+            },
+        },
+        "WorkPackageCopy": {
+            "type": "object",
+            "properties": {
+                "projectId": { "type": "integer", "format": "int64", "nullable": true, "description": "Project of the copy, the source project by default" },
+                "subjectPrefix": { "type": "string", "nullable": true, "description": "Put in front of the subject, \"Copy of \" by default" },
+                "copyAttachments": { "type": "boolean", "nullable": true, "description": "true by default" },
+                "copyWatchers": { "type": "boolean", "nullable": true },
+                "copyChildren": { "type": "boolean", "nullable": true, "description": "Copy the descendants with the relations between them" },
+            },
+        },
+        "User": {
+            "type": "object",
+            "required": ["_type", "id", "login", "name"],
+            "properties": {
+                "_type": { "type": "string", "enum": ["User"] },
+                "id": integer,
+                "login": string,
+                "firstName": string,
+                "lastName": string,
+                "name": string,
+                "email": string,
+                "admin": { "type": "boolean" },
+                "status": string,
+                "language": string,
+                "createdAt": date_time,
+                "updatedAt": date_time,
+                "_links": links,
+            },
+        },
+        "UserCreate": {
+            "type": "object",
+            "required": ["login", "firstname", "lastname", "email"],
+            "properties": {
+                "login": string,
+                "firstname": string,
+                "lastname": string,
+                "email": string,
+                "password": nullable_string,
+                "admin": { "type": "boolean", "nullable": true },
+                "status": nullable_string,
+                "language": nullable_string,
+            },
+        },
+        "UserUpdate": {
+            "type": "object",
+            "properties": {
+                "firstname": nullable_string,
+                "lastname": nullable_string,
+                "email": nullable_string,
+                "password": nullable_string,
+                "admin": { "type": "boolean", "nullable": true },
+                "status": nullable_string,
+                "language": nullable_string,
+            },
+        },
+        "OpenApi": {
+            "type": "object",
+            "description": "An OpenAPI 3.0 document",
+        },
+        "TimeEntryAggregate": {
+            "type": "object",
+            "properties": {
+                "_type": { "type": "string", "enum": ["TimeEntryAggregate"] },
+                "total": { "type": "integer", "description": "Number of groups" },
+                "count": { "type": "integer" },
+                "pageSize": { "type": "integer" },
+                "offset": { "type": "integer" },
+                "groupBy": { "type": "array", "items": { "type": "string" } },
+                "totalHours": { "type": "number", "description": "Hours of all groups" },
+                "_embedded": {
+                    "type": "object",
+                    "properties": {
+                        "elements": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "userId": { "type": "integer" },
+                                    "projectId": { "type": "integer" },
+                                    "workPackageId": { "type": "integer" },
+                                    "activityId": { "type": "integer" },
+                                    "week": { "type": "string", "format": "date" },
+                                    "month": { "type": "string", "format": "date" },
+                                    "hours": { "type": "number" },
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+        },
+        "Capabilities": {
+            "type": "object",
+            "properties": {
+                "_type": { "type": "string", "enum": ["Capabilities"] },
+                "version": { "type": "string", "description": "Version of op-rs" },
+                "capabilities": {
+                    "type": "object",
+                    "description": "Implemented capabilities by name, with metadata on partly implemented ones",
+                    "additionalProperties": { "type": "object" },
+                },
+            },
+        },
+    })
+}
+
+/// Serve the OpenAPI document
+pub async fn spec_json() -> Json<Value> {
+    Json(spec())
+}
+
+/// Serve a Swagger UI page rendering the specification
+#[cfg(feature = "swagger-ui")]
+pub async fn swagger_ui() -> axum::response::Html<String> {
+    axum::response::Html(format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>OpenProject RS API v3</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({{ url: "{}", dom_id: "#swagger-ui" }});</script>
+</body>
+</html>"##,
+        SPEC_PATH
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::representers::work_package::{FormattableText, WorkPackageRepresentation};
+
+    /// Routes registered in `routes.rs`, as (method, path) in router syntax
+    ///
+    /// axum cannot list the routes of a built `Router`, so the registrations
+    /// are read from the source: `.route("path", method(..))` and
+    /// `.nest("prefix", child_router())` calls, expanded from
+    /// `router_with_features()` with every feature mounted.
+    fn registered_routes() -> BTreeSet<(String, String)> {
+        let source = include_str!("routes.rs");
+        let source = source.split("#[cfg(test)]").next().unwrap();
+
+        let mut bodies = HashMap::new();
+        for chunk in source.split("\nfn ").chain(source.split("\npub fn ")).skip(1) {
+            if let Some((signature, rest)) = chunk.split_once(" -> Router<AppState> {") {
+                let name = signature.split('(').next().unwrap();
+                let body = rest.split("\n}").next().unwrap();
+                bodies.insert(name.to_string(), body.to_string());
+            }
+        }
+
+        let mut routes = BTreeSet::new();
+        expand(&bodies, "router_with_features", "", &mut routes);
+        routes
+    }
+
+    fn expand(
+        bodies: &HashMap<String, String>,
+        name: &str,
+        prefix: &str,
+        routes: &mut BTreeSet<(String, String)>,
+    ) {
+        let body = bodies
+            .get(name)
+            .unwrap_or_else(|| panic!("router function {} not found", name));
+
+        for (call, kind) in [(".route(\"", "route"), (".nest(\"", "nest")] {
+            for rest in body.split(call).skip(1) {
+                let (path, rest) = rest.split_once("\", ").unwrap();
+                let target = rest.split('(').next().unwrap().trim();
+                let full = format!("{}{}", prefix, path);
+                if kind == "nest" {
+                    expand(bodies, target, &full, routes);
+                } else {
+                    let full = if path == "/" { prefix.to_string() } else { full };
+                    routes.insert((target.to_string(), full));
+                }
+            }
+        }
+    }
+
+    fn documented_routes() -> BTreeSet<(String, String)> {
+        OPERATIONS
+            .iter()
+            .map(|op| (op.method.to_string(), op.path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_every_route_is_documented() {
+        let mut registered = registered_routes();
+        if !cfg!(feature = "swagger-ui") {
+            registered.remove(&("get".to_string(), "/api/v3/docs".to_string()));
+        }
+        assert!(registered.len() > 100, "route discovery found {}", registered.len());
+        assert!(registered.contains(&("get".to_string(), SPEC_PATH.to_string())));
+
+        let documented = documented_routes();
+        let missing: Vec<_> = registered.difference(&documented).collect();
+        assert!(missing.is_empty(), "routes missing from the OpenAPI spec: {:?}", missing);
+
+        let stale: Vec<_> = documented.difference(&registered).collect();
+        assert!(stale.is_empty(), "documented routes that are not registered: {:?}", stale);
+    }
+
+    #[test]
+    fn test_spec_paths_and_parameters() {
+        let spec = spec();
+        assert_eq!(spec["openapi"], "3.0.3");
+        assert_eq!(
+            spec["components"]["securitySchemes"]["basicAuth"]["scheme"],
+            "basic"
+        );
+
+        let operation = &spec["paths"]["/api/v3/work_packages/{id}/watchers/{user_id}"]["delete"];
+        assert_eq!(operation["operationId"], "deleteWorkPackagesIdWatchersUserId");
+        let params: Vec<_> = operation["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(params, vec!["id", "user_id"]);
+        assert!(operation["responses"]["204"].is_object());
+
+        let list = &spec["paths"]["/api/v3/work_packages"]["get"];
+        assert_eq!(
+            list["responses"]["200"]["content"]["application/hal+json"]["schema"]["allOf"][1]
+                ["properties"]["_embedded"]["properties"]["elements"]["items"]["$ref"],
+            "#/components/schemas/WorkPackage"
+        );
+        assert_eq!(spec["paths"][SPEC_PATH]["get"]["security"], json!([]));
+        assert_eq!(
+            spec["paths"]["/api/v3/work_packages"]["post"]["parameters"][0]["name"],
+            "Idempotency-Key"
+        );
+        assert!(spec["paths"]["/api/v3/work_packages/{id}"]["patch"]["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|p| p["in"] == "path"));
+
+        let ids: BTreeSet<_> = OPERATIONS.iter().map(operation_id).collect();
+        assert_eq!(ids.len(), OPERATIONS.len(), "operation ids must be unique");
+    }
+
+    #[test]
+    fn test_schema_refs_resolve() {
+        let spec = spec();
+        let text = spec.to_string();
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(
+                spec["components"]["schemas"][name].is_object(),
+                "unresolved schema {}",
+                name
+            );
+        }
+    }
+
+    fn assert_documented(schema: &str, value: &Value) {
+        let spec = spec();
+        let properties = &spec["components"]["schemas"][schema]["properties"];
+        for key in value.as_object().unwrap().keys() {
+            assert!(properties[key].is_object(), "{} schema lacks {}", schema, key);
+        }
+    }
+
+    #[test]
+    fn test_work_package_schema_matches_representation() {
+        let now = chrono::Utc::now();
+        let representation = WorkPackageRepresentation {
+            id: 1,
+            lock_version: 0,
+            subject: "Subject".to_string(),
+            description: Some(FormattableText::plain("text")),
+            schedule_manually: false,
+            start_date: Some("2024-01-01".to_string()),
+            due_date: Some("2024-01-02".to_string()),
+            derived_start_date: Some("2024-01-01".to_string()),
+            derived_due_date: Some("2024-01-02".to_string()),
+            duration: Some("P2D".to_string()),
+            estimated_time: Some("PT1H".to_string()),
+            derived_estimated_time: Some("PT1H".to_string()),
+            spent_time: Some("PT1H".to_string()),
+            percentage_done: 0,
+            derived_percentage_done: Some(0),
+            created_at: now,
+            updated_at: now,
+            position: Some(1),
+            story_points: Some(3),
+            remaining_time: Some("PT1H".to_string()),
+            watchers: Some(2),
+            file_links: Some(1),
+            labor_costs: Some(10.0),
+            material_costs: Some(5.0),
+            overall_costs: Some(15.0),
+        };
+
+        assert_documented("WorkPackage", &serde_json::to_value(representation).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_error_schema_matches_error_response() {
+        use axum::response::IntoResponse;
+
+        let response = crate::error::ApiError::not_found("WorkPackage", 1).into_response();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_documented("Error", &body);
+    }
+}
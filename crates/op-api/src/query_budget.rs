@@ -0,0 +1,99 @@
+//! Repository call budget middleware
+//!
+//! Counts the repository calls of each request in an
+//! `op_db::RepositoryContext`. A request exceeding the budget still
+//! completes; it is logged with its request ID and counted in the domain
+//! metrics, so N+1 query regressions show up in production.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use op_core::metrics::DomainMetrics;
+use op_db::RepositoryContext;
+
+/// Repository calls a request may make before it is reported
+pub const DEFAULT_QUERY_BUDGET: usize = 100;
+
+/// Budget of the repository calls of a request
+#[derive(Clone)]
+pub struct QueryBudget {
+    pub max_calls: usize,
+    /// Counts requests over budget; unset only logs them
+    pub metrics: Option<Arc<DomainMetrics>>,
+}
+
+impl Default for QueryBudget {
+    fn default() -> Self {
+        Self {
+            max_calls: DEFAULT_QUERY_BUDGET,
+            metrics: None,
+        }
+    }
+}
+
+impl QueryBudget {
+    /// Count requests over budget in the metrics
+    pub fn with_metrics(mut self, metrics: Arc<DomainMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+/// Run the rest of the stack within a repository context of the budget
+pub async fn query_budget_middleware(State(budget): State<QueryBudget>, request: Request, next: Next) -> Response {
+    let mut context = RepositoryContext::new(budget.max_calls);
+    if let Some(metrics) = budget.metrics {
+        context = context.with_metrics(metrics);
+    }
+    context.scope(next.run(request)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::State as AxumState, middleware, routing::get, Router};
+    use op_db::DbExecutor;
+    use std::sync::atomic::Ordering;
+    use tower::ServiceExt;
+
+    /// Acquires connections of a pool that is never reached; each attempt
+    /// counts as a call
+    async fn chatty_handler(AxumState(calls): AxumState<usize>) -> &'static str {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(1))
+            .connect_lazy("postgres://localhost:1/unreachable")
+            .unwrap();
+        let executor = DbExecutor::from(pool);
+        for _ in 0..calls {
+            let _ = executor.acquire().await;
+        }
+        "done"
+    }
+
+    async fn request(metrics: Arc<DomainMetrics>, calls: usize) {
+        let budget = QueryBudget { max_calls: 2, metrics: None }.with_metrics(metrics);
+        let app = Router::new()
+            .route("/", get(chatty_handler))
+            .with_state(calls)
+            .layer(middleware::from_fn_with_state(budget, query_budget_middleware));
+        app.oneshot(axum::http::Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requests_over_budget_are_counted() {
+        let metrics = Arc::new(DomainMetrics::new());
+
+        request(metrics.clone(), 2).await;
+        assert_eq!(metrics.query_budget_exceeded.load(Ordering::Relaxed), 0);
+
+        request(metrics.clone(), 5).await;
+        request(metrics.clone(), 3).await;
+        assert_eq!(metrics.query_budget_exceeded.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,229 @@
+//! Condensed Work Package HAL Representer
+//!
+//! The notification center shows the work package of every notification.
+//! The full work package representation is too heavy to build per row, so
+//! notifications embed a condensed one: the subject, the status with its
+//! color, the type and the project. The work packages referenced by a page
+//! of notifications are loaded with a single query.
+
+use std::collections::{BTreeSet, HashMap};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use op_db::{CondensedWorkPackageRow, RepositoryResult, SimilarWorkPackageRow, WorkPackageRepository};
+use serde::Serialize;
+
+use super::hal::HalLink;
+
+/// Condensed work package representation
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CondensedWorkPackageRepresentation {
+    #[serde(rename = "_type")]
+    pub type_name: &'static str,
+    pub id: Id,
+    pub subject: String,
+    pub updated_at: DateTime<Utc>,
+    #[serde(rename = "_links")]
+    pub links: CondensedWorkPackageLinks,
+}
+
+/// Links of a condensed work package
+#[derive(Debug, Clone, Serialize)]
+pub struct CondensedWorkPackageLinks {
+    #[serde(rename = "self")]
+    pub self_link: HalLink,
+    pub status: StatusLink,
+    #[serde(rename = "type")]
+    pub work_package_type: HalLink,
+    pub project: HalLink,
+}
+
+/// Status link carrying the color the status is shown in
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusLink {
+    pub href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Hex code, e.g. `#1A67A3`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// Condensed work package suggested as a possible duplicate
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarWorkPackageRepresentation {
+    #[serde(flatten)]
+    pub work_package: CondensedWorkPackageRepresentation,
+    /// Trigram similarity of the subjects, between 0 and 1
+    pub similarity: f32,
+}
+
+/// Condensed work package representer
+pub struct CondensedWorkPackageRepresenter;
+
+impl CondensedWorkPackageRepresenter {
+    pub fn represent(row: CondensedWorkPackageRow) -> CondensedWorkPackageRepresentation {
+        let self_link = HalLink::with_title(format!("/api/v3/work_packages/{}", row.id), row.subject.clone());
+        CondensedWorkPackageRepresentation {
+            type_name: "WorkPackage",
+            id: row.id,
+            subject: row.subject,
+            updated_at: row.updated_at,
+            links: CondensedWorkPackageLinks {
+                self_link,
+                status: StatusLink {
+                    href: format!("/api/v3/statuses/{}", row.status_id),
+                    title: row.status_name,
+                    color: row.status_color,
+                },
+                work_package_type: titled_link(format!("/api/v3/types/{}", row.type_id), row.type_name),
+                project: titled_link(format!("/api/v3/projects/{}", row.project_id), row.project_name),
+            },
+        }
+    }
+
+    /// Condensed work package with the similarity of its subject to the
+    /// one being entered
+    pub fn represent_similar(row: SimilarWorkPackageRow) -> SimilarWorkPackageRepresentation {
+        SimilarWorkPackageRepresentation {
+            work_package: Self::represent(row.work_package),
+            similarity: row.similarity,
+        }
+    }
+}
+
+fn titled_link(href: String, title: Option<String>) -> HalLink {
+    match title {
+        Some(title) => HalLink::with_title(href, title),
+        None => HalLink::new(href),
+    }
+}
+
+/// Source of condensed work packages, one call per page
+#[async_trait]
+pub trait CondensedWorkPackageSource: Send + Sync {
+    /// The work packages among `ids`, only those `visible_to` may view
+    /// when given
+    async fn find_condensed(&self, ids: &[Id], visible_to: Option<Id>) -> RepositoryResult<Vec<CondensedWorkPackageRow>>;
+}
+
+#[async_trait]
+impl CondensedWorkPackageSource for WorkPackageRepository {
+    async fn find_condensed(&self, ids: &[Id], visible_to: Option<Id>) -> RepositoryResult<Vec<CondensedWorkPackageRow>> {
+        WorkPackageRepository::find_condensed(self, ids, visible_to).await
+    }
+}
+
+/// Condensed work packages by id
+#[derive(Debug, Clone, Default)]
+pub struct CondensedWorkPackages(HashMap<Id, CondensedWorkPackageRepresentation>);
+
+impl CondensedWorkPackages {
+    /// Load the distinct work packages among `ids` in one call; work
+    /// packages that do not exist or are not visible are left out
+    pub async fn load<S: CondensedWorkPackageSource + ?Sized>(
+        source: &S,
+        ids: impl IntoIterator<Item = Id>,
+        visible_to: Option<Id>,
+    ) -> RepositoryResult<Self> {
+        let ids: Vec<Id> = ids.into_iter().collect::<BTreeSet<_>>().into_iter().collect();
+        if ids.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let rows = source.find_condensed(&ids, visible_to).await?;
+        Ok(Self(
+            rows.into_iter()
+                .map(|row| (row.id, CondensedWorkPackageRepresenter::represent(row)))
+                .collect(),
+        ))
+    }
+
+    pub fn get(&self, id: Id) -> Option<&CondensedWorkPackageRepresentation> {
+        self.0.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct CountingSource {
+        calls: Mutex<Vec<Vec<Id>>>,
+    }
+
+    #[async_trait]
+    impl CondensedWorkPackageSource for CountingSource {
+        async fn find_condensed(&self, ids: &[Id], _visible_to: Option<Id>) -> RepositoryResult<Vec<CondensedWorkPackageRow>> {
+            self.calls.lock().unwrap().push(ids.to_vec());
+            Ok(ids.iter().filter(|&&id| id < 100).map(|&id| row(id)).collect())
+        }
+    }
+
+    fn row(id: Id) -> CondensedWorkPackageRow {
+        CondensedWorkPackageRow {
+            id,
+            subject: format!("Work package {}", id),
+            project_id: 3,
+            project_name: Some("Demo".into()),
+            type_id: 1,
+            type_name: Some("Task".into()),
+            status_id: 2,
+            status_name: Some("In progress".into()),
+            status_color: Some("#1A67A3".into()),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_query_per_page() {
+        let source = CountingSource::default();
+        let ids = [5, 1, 5, 7, 1, 100];
+        let resources = CondensedWorkPackages::load(&source, ids, Some(4)).await.unwrap();
+
+        assert_eq!(*source.calls.lock().unwrap(), vec![vec![1, 5, 7, 100]]);
+        assert_eq!(resources.len(), 3);
+        assert!(resources.get(100).is_none());
+
+        CondensedWorkPackages::load(&source, [], None).await.unwrap();
+        assert_eq!(source.calls.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_condensed_json_shape() {
+        let json = serde_json::to_value(CondensedWorkPackageRepresenter::represent(row(5))).unwrap();
+
+        assert_eq!(json["_type"], "WorkPackage");
+        assert_eq!(json["id"], 5);
+        assert_eq!(json["subject"], "Work package 5");
+        assert!(json["updatedAt"].is_string());
+        assert_eq!(json["_links"]["status"]["href"], "/api/v3/statuses/2");
+        assert_eq!(json["_links"]["status"]["title"], "In progress");
+        assert_eq!(json["_links"]["status"]["color"], "#1A67A3");
+        assert_eq!(json["_links"]["type"]["title"], "Task");
+        assert_eq!(json["_links"]["project"]["href"], "/api/v3/projects/3");
+        assert_eq!(json.as_object().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_similar_json_carries_the_similarity() {
+        let similar = SimilarWorkPackageRow { work_package: row(5), similarity: 0.75 };
+        let json = serde_json::to_value(CondensedWorkPackageRepresenter::represent_similar(similar)).unwrap();
+
+        assert_eq!(json["id"], 5);
+        assert_eq!(json["similarity"], 0.75);
+        assert_eq!(json["_links"]["status"]["title"], "In progress");
+    }
+}
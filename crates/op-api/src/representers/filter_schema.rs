@@ -0,0 +1,146 @@
+//! Query Filter Instance Schema Representer
+//!
+//! Mirrors: lib/api/v3/queries/schemas/filter_instance_schema_representer.rb
+//!
+//! Tells clients which operators a filter supports and, through a schema
+//! dependency on the operator, which values each operator takes.
+
+use op_queries::{FilterDefinition, FilterOperator, ValueSchema};
+use serde_json::{json, Map, Value};
+
+/// Path of the filter instance schemas
+pub const SCHEMAS_PATH: &str = "/api/v3/queries/filter_instance_schemas";
+
+/// Filter instance schema representer
+pub struct FilterSchemaRepresenter;
+
+impl FilterSchemaRepresenter {
+    /// Schema of a filter
+    pub fn represent(definition: &FilterDefinition) -> Value {
+        let operators = definition.kind.operators();
+
+        let allowed_operators: Vec<Value> = operators
+            .iter()
+            .map(|symbol| json!({ "href": operator_href(symbol), "title": operator_title(symbol) }))
+            .collect();
+
+        let mut dependencies = Map::new();
+        for symbol in &operators {
+            let fields = match definition.kind.values(symbol) {
+                Some(values) => json!({ "values": values_field(&values) }),
+                None => json!({}),
+            };
+            dependencies.insert(operator_href(symbol), fields);
+        }
+
+        json!({
+            "_type": "QueryFilterInstanceSchema",
+            "_dependencies": [{
+                "_type": "SchemaDependency",
+                "on": "operator",
+                "dependencies": dependencies,
+            }],
+            "name": field("String", "Name", true, false),
+            "filter": with_links(field("QueryFilter", "Filter", false, true), json!({})),
+            "operator": with_links(
+                field("QueryOperator", "Operator", false, true),
+                json!({ "allowedValues": allowed_operators }),
+            ),
+            "_links": {
+                "self": { "href": schema_href(&definition.name), "title": definition.title },
+                "filter": { "href": format!("/api/v3/queries/filters/{}", definition.name), "title": definition.title },
+            },
+        })
+    }
+}
+
+/// Href of the schema of a filter by its API name
+pub fn schema_href(name: &str) -> String {
+    format!("{}/{}", SCHEMAS_PATH, name)
+}
+
+fn operator_href(symbol: &str) -> String {
+    format!("/api/v3/queries/operators/{}", symbol)
+}
+
+fn operator_title(symbol: &str) -> &'static str {
+    // Relative date operators only parse with their number of days
+    let operator = match symbol {
+        "t-" | "t+" | "<t-" | ">t-" | "<t+" | ">t+" => FilterOperator::from_str(&format!("{}1", symbol)),
+        symbol => FilterOperator::from_str(symbol),
+    };
+    operator.map(|operator| operator.label()).unwrap_or_default()
+}
+
+fn field(type_name: &str, name: &str, has_default: bool, writable: bool) -> Value {
+    json!({
+        "type": type_name,
+        "name": name,
+        "required": true,
+        "hasDefault": has_default,
+        "writable": writable,
+    })
+}
+
+fn with_links(mut field: Value, links: Value) -> Value {
+    field["_links"] = links;
+    field
+}
+
+fn values_field(values: &ValueSchema) -> Value {
+    let links = match values.value_type.allowed_values_href() {
+        Some(href) => json!({ "allowedValues": { "href": href } }),
+        None => json!({}),
+    };
+    with_links(field(&values.type_name(), "Values", false, true), links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_queries::registry;
+
+    /// Schemas of the same filters in the form OpenProject renders them
+    fn fixture(name: &str) -> Value {
+        let json = match name {
+            "status" => include_str!("../../fixtures/filter_instance_schemas/status.json"),
+            "assignee" => include_str!("../../fixtures/filter_instance_schemas/assignee.json"),
+            "dueDate" => include_str!("../../fixtures/filter_instance_schemas/due_date.json"),
+            name => panic!("no fixture for {}", name),
+        };
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_schemas_match_fixtures() {
+        for name in ["status", "assignee", "dueDate"] {
+            let definition = registry::find_by_name(name).unwrap();
+            assert_eq!(FilterSchemaRepresenter::represent(&definition), fixture(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_schema_lists_every_operator_of_the_registry() {
+        for definition in registry::filters() {
+            let schema = FilterSchemaRepresenter::represent(&definition);
+            let allowed = schema["operator"]["_links"]["allowedValues"].as_array().unwrap();
+            let dependencies = schema["_dependencies"][0]["dependencies"].as_object().unwrap();
+            assert_eq!(allowed.len(), definition.kind.operators().len());
+            assert_eq!(dependencies.len(), allowed.len());
+            for operator in allowed {
+                assert_ne!(operator["title"], "", "{} {}", definition.name, operator["href"]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_field_schema() {
+        let definition = FilterDefinition::custom_field(5, "Severity", "list");
+        let schema = FilterSchemaRepresenter::represent(&definition);
+        assert_eq!(schema["_links"]["self"]["href"], "/api/v3/queries/filter_instance_schemas/customField5");
+        assert_eq!(
+            schema["_dependencies"][0]["dependencies"]["/api/v3/queries/operators/="]["values"]["type"],
+            "[]CustomOption"
+        );
+    }
+}
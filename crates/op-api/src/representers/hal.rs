@@ -6,116 +6,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-/// A HAL link
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct HalLink {
-    pub href: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub title: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub method: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub templated: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub payload: Option<serde_json::Value>,
-}
-
-impl HalLink {
-    /// Create a simple link with just an href
-    pub fn new(href: impl Into<String>) -> Self {
-        Self {
-            href: href.into(),
-            title: None,
-            method: None,
-            templated: None,
-            payload: None,
-        }
-    }
-
-    /// Create a link with title
-    pub fn with_title(href: impl Into<String>, title: impl Into<String>) -> Self {
-        Self {
-            href: href.into(),
-            title: Some(title.into()),
-            method: None,
-            templated: None,
-            payload: None,
-        }
-    }
-
-    /// Create a templated link
-    pub fn templated(href: impl Into<String>) -> Self {
-        Self {
-            href: href.into(),
-            title: None,
-            method: None,
-            templated: Some(true),
-            payload: None,
-        }
-    }
-
-    /// Add method to link
-    pub fn method(mut self, method: impl Into<String>) -> Self {
-        self.method = Some(method.into());
-        self
-    }
-
-    /// Add payload schema
-    pub fn payload(mut self, payload: serde_json::Value) -> Self {
-        self.payload = Some(payload);
-        self
-    }
-}
-
-/// Collection of HAL links
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct HalLinks(HashMap<String, HalLinkValue>);
-
-/// A link value can be a single link or an array of links
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum HalLinkValue {
-    Single(HalLink),
-    Array(Vec<HalLink>),
-}
-
-impl HalLinks {
-    pub fn new() -> Self {
-        Self(HashMap::new())
-    }
-
-    /// Add a single link
-    pub fn add(&mut self, rel: impl Into<String>, link: HalLink) {
-        self.0.insert(rel.into(), HalLinkValue::Single(link));
-    }
-
-    /// Add multiple links for a relation
-    pub fn add_array(&mut self, rel: impl Into<String>, links: Vec<HalLink>) {
-        self.0.insert(rel.into(), HalLinkValue::Array(links));
-    }
-
-    /// Builder pattern: add single link
-    pub fn with(mut self, rel: impl Into<String>, link: HalLink) -> Self {
-        self.add(rel, link);
-        self
-    }
-
-    /// Builder pattern: add link array
-    pub fn with_array(mut self, rel: impl Into<String>, links: Vec<HalLink>) -> Self {
-        self.add_array(rel, links);
-        self
-    }
-
-    /// Check if a relation exists
-    pub fn has(&self, rel: &str) -> bool {
-        self.0.contains_key(rel)
-    }
-
-    /// Get a link by relation
-    pub fn get(&self, rel: &str) -> Option<&HalLinkValue> {
-        self.0.get(rel)
-    }
-}
+pub use op_core::representations::{parse_resource_href, HalLink, HalLinkValue, HalLinks, ResourceHref};
 
 /// Embedded resources
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -240,53 +131,124 @@ impl<T: Serialize> HalCollection<T> {
         }
     }
 
-    /// Add pagination links
-    pub fn with_pagination_links(mut self, base_url: &str, page: i64, per_page: i64) -> Self {
-        let total_pages = (self.total + per_page - 1) / per_page;
+    /// Add pagination links echoing the request's query parameters
+    pub fn with_pagination_links(mut self, query: &CollectionQuery) -> Self {
+        let links = query.pagination_links(self.total, self.offset, self.page_size);
+        self.links.extend(links);
+        self
+    }
 
-        // Self link
-        self.links.add(
-            "self",
-            HalLink::new(format!("{}?offset={}&pageSize={}", base_url, self.offset, self.page_size)),
-        );
+    /// Add a custom link
+    pub fn with_link(mut self, rel: impl Into<String>, link: HalLink) -> Self {
+        self.links.add(rel, link);
+        self
+    }
+}
 
-        // Jump to page link (templated)
-        self.links.add(
-            "jumpTo",
-            HalLink::templated(format!("{}{{?offset,pageSize}}", base_url)),
-        );
+/// Query parameters of a collection request
+///
+/// Pagination links reproduce the parameters a collection was requested
+/// with (filters, sortBy, select, ...) so following them keeps the filter
+/// context. Only `offset` and `pageSize` are substituted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollectionQuery {
+    base_url: String,
+    /// Decoded parameters except offset and pageSize, in request order
+    params: Vec<(String, String)>,
+}
 
-        // Change size link (templated)
-        self.links.add(
-            "changeSize",
-            HalLink::templated(format!("{}{{?offset,pageSize}}", base_url)),
-        );
+impl CollectionQuery {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Parse the raw (encoded) query string of a request
+    pub fn from_query_string(base_url: impl Into<String>, query: Option<&str>) -> Self {
+        let params = form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+            .filter(|(key, _)| key != "offset" && key != "pageSize")
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
 
-        // Previous page
-        if page > 1 {
-            let prev_offset = (page - 2) * per_page;
-            self.links.add(
+        Self {
+            base_url: base_url.into(),
+            params,
+        }
+    }
+
+    /// Add a parameter to echo
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Path of the collection
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Decoded value of an echoed parameter
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Href of a page, offset and page size given as already encoded values
+    fn page_href(&self, offset: &str, page_size: &str) -> String {
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        for (key, value) in &self.params {
+            serializer.append_pair(key, value);
+        }
+        let echoed = serializer.finish();
+
+        let separator = if echoed.is_empty() { "" } else { "&" };
+        format!(
+            "{}?{}{}offset={}&pageSize={}",
+            self.base_url, echoed, separator, offset, page_size
+        )
+    }
+
+    /// Href of the page at an offset
+    pub fn href(&self, offset: i64, page_size: i64) -> String {
+        self.page_href(&offset.to_string(), &page_size.to_string())
+    }
+
+    /// Self, jumpTo, changeSize and previous/next page links
+    pub fn pagination_links(&self, total: i64, offset: i64, page_size: i64) -> HalLinks {
+        let mut links = HalLinks::new()
+            .with("self", HalLink::new(self.href(offset, page_size)))
+            .with(
+                "jumpTo",
+                HalLink::templated(self.page_href("{offset}", &page_size.to_string())),
+            )
+            .with(
+                "changeSize",
+                HalLink::templated(self.page_href(&offset.to_string(), "{size}")),
+            );
+
+        if page_size <= 0 {
+            return links;
+        }
+
+        if offset > 0 {
+            links.add(
                 "previousByOffset",
-                HalLink::new(format!("{}?offset={}&pageSize={}", base_url, prev_offset, per_page)),
+                HalLink::new(self.href((offset - page_size).max(0), page_size)),
             );
         }
 
-        // Next page
-        if page < total_pages {
-            let next_offset = page * per_page;
-            self.links.add(
+        if offset + page_size < total {
+            links.add(
                 "nextByOffset",
-                HalLink::new(format!("{}?offset={}&pageSize={}", base_url, next_offset, per_page)),
+                HalLink::new(self.href(offset + page_size, page_size)),
             );
         }
 
-        self
-    }
-
-    /// Add a custom link
-    pub fn with_link(mut self, rel: impl Into<String>, link: HalLink) -> Self {
-        self.links.add(rel, link);
-        self
+        links
     }
 }
 
@@ -492,7 +454,7 @@ mod tests {
 
         let items = vec![Item { id: 1 }, Item { id: 2 }];
         let collection = HalCollection::new("Items", items, 10, 20, 0)
-            .with_pagination_links("/api/v3/items", 1, 20);
+            .with_pagination_links(&CollectionQuery::new("/api/v3/items"));
 
         let json = serde_json::to_value(&collection).unwrap();
         assert_eq!(json["_type"], "Items");
@@ -501,6 +463,67 @@ mod tests {
         assert_eq!(json["pageSize"], 20);
     }
 
+    fn decoded_query(href: &str) -> Vec<(String, String)> {
+        let (_, query) = href.split_once('?').unwrap();
+        form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+    }
+
+    #[test]
+    fn test_pagination_links_echo_filters() {
+        let filters = r#"[{"subject":{"operator":"~","values":["Bücher \"quoted\" [1] & more"]}}]"#;
+        let raw: String = form_urlencoded::Serializer::new(String::new())
+            .append_pair("filters", filters)
+            .append_pair("offset", "20")
+            .append_pair("sortBy", r#"[["id","asc"]]"#)
+            .append_pair("pageSize", "10")
+            .finish();
+        let query = CollectionQuery::from_query_string("/api/v3/work_packages", Some(&raw));
+        assert_eq!(query.param("filters"), Some(filters));
+
+        let links = serde_json::to_value(query.pagination_links(45, 20, 10)).unwrap();
+        let next = links["nextByOffset"]["href"].as_str().unwrap();
+        assert!(next.starts_with("/api/v3/work_packages?filters=%5B%7B%22subject%22"));
+        assert!(!next.contains('"') && !next.contains('[') && !next.contains(' '));
+        assert_eq!(
+            decoded_query(next),
+            vec![
+                ("filters".to_string(), filters.to_string()),
+                ("sortBy".to_string(), r#"[["id","asc"]]"#.to_string()),
+                ("offset".to_string(), "30".to_string()),
+                ("pageSize".to_string(), "10".to_string()),
+            ]
+        );
+
+        let previous = links["previousByOffset"]["href"].as_str().unwrap();
+        assert!(previous.ends_with("&offset=10&pageSize=10"));
+        assert!(links["self"]["href"].as_str().unwrap().ends_with("&offset=20&pageSize=10"));
+
+        let jump_to = &links["jumpTo"];
+        assert_eq!(jump_to["templated"], true);
+        assert!(jump_to["href"].as_str().unwrap().ends_with("&offset={offset}&pageSize=10"));
+        let change_size = &links["changeSize"];
+        assert_eq!(change_size["templated"], true);
+        assert!(change_size["href"].as_str().unwrap().ends_with("&offset=20&pageSize={size}"));
+    }
+
+    #[test]
+    fn test_pagination_links_at_collection_bounds() {
+        let query = CollectionQuery::new("/api/v3/projects");
+
+        let links = serde_json::to_value(query.pagination_links(5, 0, 20)).unwrap();
+        assert_eq!(links["self"]["href"], "/api/v3/projects?offset=0&pageSize=20");
+        assert!(links.get("previousByOffset").is_none());
+        assert!(links.get("nextByOffset").is_none());
+
+        let query = query.with_param("select", "elements/id,total");
+        let links = serde_json::to_value(query.pagination_links(50, 40, 20)).unwrap();
+        assert_eq!(
+            links["previousByOffset"]["href"],
+            "/api/v3/projects?select=elements%2Fid%2Ctotal&offset=20&pageSize=20"
+        );
+        assert!(links.get("nextByOffset").is_none());
+    }
+
     #[test]
     fn test_hal_error() {
         let error = HalError::not_found("WorkPackage");
@@ -5,10 +5,30 @@
 
 pub mod hal;
 pub mod work_package;
+pub mod condensed_work_package;
 pub mod project;
 pub mod user;
+pub mod principal;
 pub mod query;
+pub mod filter_schema;
+pub mod work_package_schema;
+pub mod project_schema;
+pub mod notification;
 
 // Re-exports
-pub use hal::{HalCollection, HalEmbedded, HalError, HalLink, HalLinks, HalResource};
-pub use work_package::{WorkPackageData, WorkPackageRepresenter, EmbedOptions};
+pub use condensed_work_package::{
+    CondensedWorkPackageRepresentation, CondensedWorkPackageRepresenter, CondensedWorkPackageSource,
+    CondensedWorkPackages, SimilarWorkPackageRepresentation,
+};
+pub use notification::{
+    NotificationEmbed, NotificationEmbeds, NotificationGroupRepresentation, NotificationRepresentation,
+    NotificationRepresenter, NotificationSettingsRepresentation,
+};
+pub use filter_schema::FilterSchemaRepresenter;
+pub use work_package_schema::WorkPackageSchemaRepresenter;
+pub use project_schema::ProjectSchemaRepresenter;
+pub use principal::{PrincipalRepresentation, PrincipalRepresenter, PrincipalType};
+pub use hal::{CollectionQuery, HalCollection, HalEmbedded, HalError, HalLink, HalLinks, HalResource};
+pub use work_package::{
+    EmbedOptions, TimelineData, TimelineRepresenter, WorkPackageData, WorkPackageRepresenter,
+};
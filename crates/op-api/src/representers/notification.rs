@@ -0,0 +1,730 @@
+//! Notification HAL Representer
+//!
+//! Converts notifications and notification groups to HAL+JSON format for the
+//! notification center. Groups only embed their newest notification; the
+//! constituent notifications are fetched per group via the `details` link.
+//! Notifications about work packages embed them condensed as `resource`,
+//! and, when requested with `embed`, their `actor` and `project` condensed.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use op_db::{IncludeLoader, RepositoryResult};
+use op_notifications::{
+    AttributeChange, DateAlerts, EmailFrequency, Notification, NotificationGroup, NotificationReason, NotificationSettings,
+    NotificationSnapshot, ProjectNotificationSettings, ReasonSettings,
+};
+use serde::Serialize;
+
+use super::condensed_work_package::CondensedWorkPackages;
+use super::hal::{HalCollection, HalEmbedded, HalLink, HalLinks, HalResource};
+use super::principal::{CondensedPrincipalRepresentation, PrincipalRepresenter};
+use super::project::{CondensedProjectRepresentation, ProjectRepresenter};
+use crate::visibility::UserVisibility;
+
+/// Notification representation for API responses
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationRepresentation {
+    pub id: Option<Id>,
+    /// "Bug #123: Crash on save" when a snapshot was captured, the resource
+    /// type and id otherwise
+    pub subject: String,
+    pub reason: NotificationReason,
+    #[serde(rename = "readIAN")]
+    pub read_ian: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<SnapshotRepresentation>,
+}
+
+/// What the notification's journal changed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotRepresentation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_excerpt: Option<String>,
+    pub changes: Vec<AttributeChange>,
+}
+
+impl From<NotificationSnapshot> for SnapshotRepresentation {
+    fn from(snapshot: NotificationSnapshot) -> Self {
+        Self {
+            project_name: snapshot.project_name,
+            comment_excerpt: snapshot.comment_excerpt,
+            changes: snapshot.changes,
+        }
+    }
+}
+
+/// Notification group representation for API responses
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationGroupRepresentation {
+    pub resource_type: String,
+    pub resource_id: Id,
+    pub count: usize,
+    pub reasons: Vec<NotificationReason>,
+    #[serde(rename = "readIAN")]
+    pub read_ian: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Related resources notifications embed besides their work package, as
+/// requested with the `embed` parameter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NotificationEmbed {
+    pub actor: bool,
+    pub project: bool,
+}
+
+impl NotificationEmbed {
+    pub const ALL: Self = Self { actor: true, project: true };
+
+    /// Parse a comma separated list of `actor` and `project`; an empty list
+    /// embeds neither. The unknown name is returned on error.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut embed = Self::default();
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "actor" => embed.actor = true,
+                "project" => embed.project = true,
+                other => return Err(other.to_string()),
+            }
+        }
+        Ok(embed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What the notifications of a page embed, each kind loaded with one call
+/// for the whole page
+#[derive(Debug, Clone, Default)]
+pub struct NotificationEmbeds {
+    resources: CondensedWorkPackages,
+    actors: HashMap<Id, HalResource<CondensedPrincipalRepresentation>>,
+    projects: HashMap<Id, HalResource<CondensedProjectRepresentation>>,
+}
+
+impl NotificationEmbeds {
+    pub fn new(resources: CondensedWorkPackages) -> Self {
+        Self { resources, ..Self::default() }
+    }
+
+    /// Load the distinct actors and projects of the notifications requested
+    /// by `embed`; email addresses of actors are only shown where
+    /// `visibility` allows
+    pub async fn with_related<'a, L: IncludeLoader + ?Sized>(
+        mut self,
+        loader: &L,
+        notifications: impl IntoIterator<Item = &'a Notification>,
+        embed: NotificationEmbed,
+        visibility: &UserVisibility,
+    ) -> RepositoryResult<Self> {
+        let (mut actor_ids, mut project_ids) = (BTreeSet::new(), BTreeSet::new());
+        for notification in notifications {
+            actor_ids.extend(notification.actor_id.filter(|_| embed.actor));
+            project_ids.extend(notification.project_id.filter(|_| embed.project));
+        }
+
+        if !actor_ids.is_empty() {
+            let ids: Vec<Id> = actor_ids.into_iter().collect();
+            self.actors = loader
+                .load_users(&ids)
+                .await?
+                .iter()
+                .map(|row| (row.id, PrincipalRepresenter::represent_condensed(row, visibility.can_see_email(row.id))))
+                .collect();
+        }
+        if !project_ids.is_empty() {
+            let ids: Vec<Id> = project_ids.into_iter().collect();
+            self.projects = loader
+                .load_projects(&ids)
+                .await?
+                .iter()
+                .map(|row| (row.id, ProjectRepresenter::represent_condensed(row)))
+                .collect();
+        }
+        Ok(self)
+    }
+
+    /// What a notification embeds, `None` when nothing
+    fn embedded(&self, notification: &Notification) -> Option<HalEmbedded> {
+        let mut embedded = HalEmbedded::new();
+        if notification.resource_type == "WorkPackage" {
+            if let Some(resource) = self.resources.get(notification.resource_id) {
+                embedded.add("resource", resource);
+            }
+        }
+        if let Some(actor) = notification.actor_id.and_then(|id| self.actors.get(&id)) {
+            embedded.add("actor", actor);
+        }
+        if let Some(project) = notification.project_id.and_then(|id| self.projects.get(&id)) {
+            embedded.add("project", project);
+        }
+        (!embedded.is_empty()).then_some(embedded)
+    }
+}
+
+/// Notification representer
+pub struct NotificationRepresenter;
+
+impl NotificationRepresenter {
+    /// Create a HAL resource for a single notification
+    pub fn represent(notification: Notification) -> HalResource<NotificationRepresentation> {
+        let subject = match notification.snapshot {
+            Some(ref snapshot) => snapshot.title(notification.resource_id, "Work Package"),
+            None => format!("{} #{}", notification.resource_type, notification.resource_id),
+        };
+        let mut hal = HalResource::new(
+            "Notification",
+            NotificationRepresentation {
+                id: notification.id,
+                subject,
+                reason: notification.reason,
+                read_ian: !notification.is_unread(),
+                created_at: notification.created_at,
+                updated_at: notification.updated_at,
+                details: notification.snapshot.map(SnapshotRepresentation::from),
+            },
+        )
+        .with_link(
+            "resource",
+            HalLink::new(resource_href(&notification.resource_type, notification.resource_id)),
+        );
+
+        if let Some(id) = notification.id {
+            hal = hal.with_self_link(format!("/api/v3/notifications/{}", id));
+        }
+        if let Some(actor_id) = notification.actor_id {
+            hal = hal.with_link("actor", HalLink::new(format!("/api/v3/users/{}", actor_id)));
+        }
+        if let Some(project_id) = notification.project_id {
+            hal = hal.with_link("project", HalLink::new(format!("/api/v3/projects/{}", project_id)));
+        }
+        if let Some(journal_id) = notification.journal_id {
+            hal = hal.with_link("activity", HalLink::new(format!("/api/v3/activities/{}", journal_id)));
+        }
+
+        hal
+    }
+
+    /// Create a HAL resource for a notification, embedding its work package,
+    /// actor and project when among the embeds
+    pub fn represent_embedded(
+        notification: Notification,
+        embeds: &NotificationEmbeds,
+    ) -> HalResource<NotificationRepresentation> {
+        let embedded = embeds.embedded(&notification);
+        let hal = Self::represent(notification);
+        match embedded {
+            Some(embedded) => hal.with_embedded(embedded),
+            None => hal,
+        }
+    }
+
+    /// Create a HAL resource for a group, optionally embedding its details
+    pub fn represent_group(
+        group: NotificationGroup,
+        details: Option<Vec<Notification>>,
+        embeds: &NotificationEmbeds,
+    ) -> HalResource<NotificationGroupRepresentation> {
+        let href = group_href(&group.resource_type, group.resource_id);
+        let rep = NotificationGroupRepresentation {
+            resource_type: group.resource_type.clone(),
+            resource_id: group.resource_id,
+            count: group.count,
+            reasons: group.reasons,
+            read_ian: !group.newest.is_unread(),
+            created_at: group.newest.created_at,
+        };
+
+        let mut embedded = HalEmbedded::new().with("newest", Self::represent_embedded(group.newest, embeds));
+        if let Some(details) = details {
+            let elements: Vec<_> = details
+                .into_iter()
+                .map(|notification| Self::represent_embedded(notification, embeds))
+                .collect();
+            let total = elements.len() as i64;
+            embedded.add("details", HalCollection::new("Collection", elements, total, total, 1));
+        }
+
+        HalResource::new("NotificationGroup", rep)
+            .with_self_link(href.clone())
+            .with_link("details", HalLink::new(href.clone()))
+            .with_link("readIAN", HalLink::post(format!("{}/read_ian", href)))
+            .with_link(
+                "resource",
+                HalLink::new(resource_href(&group.resource_type, group.resource_id)),
+            )
+            .with_embedded(embedded)
+    }
+
+    /// Create a HAL collection of groups without details
+    pub fn represent_groups(
+        groups: Vec<NotificationGroup>,
+        embeds: &NotificationEmbeds,
+    ) -> HalCollection<HalResource<NotificationGroupRepresentation>> {
+        let elements: Vec<_> = groups
+            .into_iter()
+            .map(|group| Self::represent_group(group, None, embeds))
+            .collect();
+        let total = elements.len() as i64;
+
+        let mut collection = HalCollection::new("Collection", elements, total, total, 1);
+        collection
+            .links
+            .add("self", HalLink::new("/api/v3/notifications/groups"));
+        collection
+    }
+}
+
+/// Notification settings as shown on the user's notification settings page
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationSettingsRepresentation {
+    pub in_app: ReasonSettings,
+    pub email: ReasonSettings,
+    pub email_frequency: EmailFrequency,
+    pub date_alerts: DateAlertsRepresentation,
+    pub project_overrides: Vec<ProjectOverrideRepresentation>,
+}
+
+/// Date alert lead times as ISO 8601 durations, e.g. `P1D`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DateAlertsRepresentation {
+    pub start_date: Option<String>,
+    pub due_date: Option<String>,
+    pub overdue: Option<String>,
+}
+
+impl From<DateAlerts> for DateAlertsRepresentation {
+    fn from(alerts: DateAlerts) -> Self {
+        Self {
+            start_date: alerts.start_date.map(days_duration),
+            due_date: alerts.due_date.map(days_duration),
+            overdue: alerts.overdue.map(days_duration),
+        }
+    }
+}
+
+/// Settings of one project, linked to it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectOverrideRepresentation {
+    pub in_app: ReasonSettings,
+    pub email: ReasonSettings,
+    pub date_alerts: DateAlertsRepresentation,
+    #[serde(rename = "_links")]
+    pub links: HalLinks,
+}
+
+impl From<ProjectNotificationSettings> for ProjectOverrideRepresentation {
+    fn from(project: ProjectNotificationSettings) -> Self {
+        let mut links = HalLinks::new();
+        links.add("project", HalLink::new(project_href(project.project_id)));
+        Self {
+            in_app: project.in_app,
+            email: project.email,
+            date_alerts: project.date_alerts.into(),
+            links,
+        }
+    }
+}
+
+impl NotificationRepresenter {
+    /// Create a HAL resource for a user's notification settings
+    pub fn represent_settings(settings: NotificationSettings) -> HalResource<NotificationSettingsRepresentation> {
+        let href = settings_href(settings.user_id);
+        let user_id = settings.user_id;
+        let rep = NotificationSettingsRepresentation {
+            in_app: settings.in_app_reasons,
+            email: settings.email_reasons,
+            email_frequency: settings.email_frequency,
+            date_alerts: settings.date_alerts.into(),
+            project_overrides: settings.project_overrides.into_iter().map(Into::into).collect(),
+        };
+
+        HalResource::new("NotificationSettings", rep)
+            .with_self_link(href.clone())
+            .with_link("update", HalLink::patch(href))
+            .with_link("user", HalLink::new(format!("/api/v3/users/{}", user_id)))
+    }
+}
+
+/// Path of a user's notification settings
+pub fn settings_href(user_id: Id) -> String {
+    format!("/api/v3/users/{}/notification_settings", user_id)
+}
+
+/// Path of a project, as project overrides are keyed
+pub fn project_href(project_id: Id) -> String {
+    format!("/api/v3/projects/{}", project_id)
+}
+
+/// ISO 8601 duration of whole days
+pub fn days_duration(days: u32) -> String {
+    format!("P{}D", days)
+}
+
+/// Days of an ISO 8601 duration of whole days, `None` for other durations
+pub fn parse_days_duration(duration: &str) -> Option<u32> {
+    duration.strip_prefix('P')?.strip_suffix('D')?.parse().ok()
+}
+
+/// Path of a notification group
+pub fn group_href(resource_type: &str, resource_id: Id) -> String {
+    format!("/api/v3/notifications/groups/{}/{}", resource_type, resource_id)
+}
+
+fn resource_href(resource_type: &str, resource_id: Id) -> String {
+    match resource_type {
+        "WorkPackage" => format!("/api/v3/work_packages/{}", resource_id),
+        "Project" => format!("/api/v3/projects/{}", resource_id),
+        "Document" => format!("/api/v3/documents/{}", resource_id),
+        other => format!("/api/v3/{}/{}", other.to_lowercase(), resource_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::representers::CondensedWorkPackageSource;
+    use op_db::CondensedWorkPackageRow;
+    use op_notifications::NotificationType;
+
+    fn notification(id: Id, reason: NotificationReason) -> Notification {
+        let mut notification =
+            Notification::work_package(1, NotificationType::WorkPackageUpdated, reason, 42);
+        notification.id = Some(id);
+        notification
+    }
+
+    #[test]
+    fn test_group_links_details_lazily() {
+        let groups = NotificationGroup::group(vec![
+            notification(1, NotificationReason::Watched),
+            notification(2, NotificationReason::Mentioned),
+        ]);
+        let collection = NotificationRepresenter::represent_groups(groups, &NotificationEmbeds::default());
+        let json = serde_json::to_value(&collection).unwrap();
+
+        let group = &json["_embedded"]["elements"][0];
+        assert_eq!(group["_type"], "NotificationGroup");
+        assert_eq!(group["count"], 2);
+        assert_eq!(group["_links"]["details"]["href"], "/api/v3/notifications/groups/WorkPackage/42");
+        assert_eq!(group["_links"]["resource"]["href"], "/api/v3/work_packages/42");
+        assert!(group["_embedded"]["newest"].is_object());
+        assert!(group["_embedded"].get("details").is_none());
+    }
+
+    #[test]
+    fn test_group_embeds_requested_details() {
+        let details = vec![
+            notification(2, NotificationReason::Mentioned),
+            notification(1, NotificationReason::Watched),
+        ];
+        let group = NotificationGroup::group(details.clone()).remove(0);
+        let json = serde_json::to_value(NotificationRepresenter::represent_group(
+            group,
+            Some(details),
+            &NotificationEmbeds::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(json["_embedded"]["details"]["count"], 2);
+        assert_eq!(json["_embedded"]["details"]["_embedded"]["elements"][0]["reason"], "mentioned");
+    }
+
+    #[tokio::test]
+    async fn test_notifications_embed_their_work_package() {
+        struct Source;
+
+        #[axum::async_trait]
+        impl CondensedWorkPackageSource for Source {
+            async fn find_condensed(&self, ids: &[Id], _visible_to: Option<Id>) -> op_db::RepositoryResult<Vec<CondensedWorkPackageRow>> {
+                Ok(ids
+                    .iter()
+                    .map(|&id| CondensedWorkPackageRow {
+                        id,
+                        subject: "Crash on save".into(),
+                        project_id: 3,
+                        project_name: None,
+                        type_id: 1,
+                        type_name: None,
+                        status_id: 2,
+                        status_name: Some("New".into()),
+                        status_color: None,
+                        updated_at: chrono::Utc::now(),
+                    })
+                    .collect())
+            }
+        }
+
+        let groups = NotificationGroup::group(vec![notification(1, NotificationReason::Watched)]);
+        let resources = CondensedWorkPackages::load(&Source, [42], None).await.unwrap();
+        let embeds = NotificationEmbeds::new(resources);
+        let json = serde_json::to_value(NotificationRepresenter::represent_groups(groups, &embeds)).unwrap();
+
+        let resource = &json["_embedded"]["elements"][0]["_embedded"]["newest"]["_embedded"]["resource"];
+        assert_eq!(resource["id"], 42);
+        assert_eq!(resource["subject"], "Crash on save");
+        assert_eq!(resource["_links"]["status"]["title"], "New");
+    }
+
+    /// Loader recording the batches it is asked for
+    #[derive(Default)]
+    struct CountingLoader {
+        calls: std::sync::Mutex<Vec<(&'static str, Vec<Id>)>>,
+    }
+
+    impl CountingLoader {
+        fn calls(&self) -> Vec<(&'static str, Vec<Id>)> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[axum::async_trait]
+    impl IncludeLoader for CountingLoader {
+        async fn load_statuses(&self, _ids: &[Id]) -> RepositoryResult<Vec<op_db::StatusRow>> {
+            unreachable!("notifications embed no statuses")
+        }
+
+        async fn load_types(&self, _ids: &[Id]) -> RepositoryResult<Vec<op_db::TypeRow>> {
+            unreachable!("notifications embed no types")
+        }
+
+        async fn load_priorities(&self, _ids: &[Id]) -> RepositoryResult<Vec<op_db::PriorityRow>> {
+            unreachable!("notifications embed no priorities")
+        }
+
+        async fn load_users(&self, ids: &[Id]) -> RepositoryResult<Vec<op_db::UserRow>> {
+            self.calls.lock().unwrap().push(("users", ids.to_vec()));
+            let at = fixed_time();
+            Ok(ids
+                .iter()
+                .map(|&id| op_db::UserRow {
+                    id,
+                    principal_type: op_db::principal_type::USER.to_string(),
+                    login: format!("user{}", id),
+                    firstname: "User".to_string(),
+                    lastname: id.to_string(),
+                    mail: format!("user{}@example.com", id),
+                    admin: false,
+                    status: 1,
+                    language: None,
+                    hashed_password: None,
+                    salt: None,
+                    created_at: at,
+                    updated_at: at,
+                    last_login_on: None,
+                })
+                .collect())
+        }
+
+        async fn load_projects(&self, ids: &[Id]) -> RepositoryResult<Vec<op_db::ProjectRow>> {
+            self.calls.lock().unwrap().push(("projects", ids.to_vec()));
+            let at = fixed_time();
+            Ok(ids
+                .iter()
+                .map(|&id| op_db::ProjectRow {
+                    id,
+                    name: format!("Project {}", id),
+                    description: None,
+                    identifier: format!("project-{}", id),
+                    public: false,
+                    parent_id: None,
+                    lft: 1,
+                    rgt: 2,
+                    active: true,
+                    templated: false,
+                    created_at: at,
+                    updated_at: at,
+                })
+                .collect())
+        }
+
+        async fn load_versions(&self, _ids: &[Id]) -> RepositoryResult<Vec<op_db::VersionRow>> {
+            unreachable!("notifications embed no versions")
+        }
+    }
+
+    fn fixed_time() -> DateTime<Utc> {
+        "2024-03-01T09:00:00Z".parse().unwrap()
+    }
+
+    /// Notifications from users 10 and 11 about work packages of projects 3
+    /// and 4, the newest first
+    fn page_of_two_actors() -> Vec<Notification> {
+        [(1, 42, 10, 3), (2, 43, 11, 4), (3, 44, 10, 4), (4, 42, 11, 3)]
+            .into_iter()
+            .map(|(id, work_package_id, actor_id, project_id)| {
+                let mut notification = Notification::work_package(
+                    1,
+                    NotificationType::WorkPackageUpdated,
+                    NotificationReason::Watched,
+                    work_package_id,
+                )
+                .with_actor(actor_id)
+                .with_project(project_id);
+                notification.id = Some(id);
+                notification.created_at = fixed_time() + chrono::Duration::minutes(id);
+                notification.updated_at = notification.created_at;
+                notification
+            })
+            .collect()
+    }
+
+    /// User 1 looking at the page, with only user 10 showing their email
+    fn visibility() -> UserVisibility {
+        let preferences = crate::visibility::MailPreferences {
+            hidden_by_default: true,
+            hidden: HashMap::from([(10, false)]),
+        };
+        UserVisibility::new(crate::visibility::Viewer { id: 1, admin: false, anonymous: false }, preferences)
+    }
+
+    #[tokio::test]
+    async fn test_actors_and_projects_load_once_per_page() {
+        let notifications = page_of_two_actors();
+        let loader = CountingLoader::default();
+        NotificationEmbeds::default()
+            .with_related(&loader, &notifications, NotificationEmbed::ALL, &visibility())
+            .await
+            .unwrap();
+        assert_eq!(loader.calls(), vec![("users", vec![10, 11]), ("projects", vec![3, 4])]);
+
+        let loader = CountingLoader::default();
+        let only_actors = NotificationEmbed::parse("actor").unwrap();
+        NotificationEmbeds::default()
+            .with_related(&loader, &notifications, only_actors, &visibility())
+            .await
+            .unwrap();
+        assert_eq!(loader.calls(), vec![("users", vec![10, 11])]);
+
+        let loader = CountingLoader::default();
+        NotificationEmbeds::default()
+            .with_related(&loader, &notifications, NotificationEmbed::default(), &visibility())
+            .await
+            .unwrap();
+        assert!(loader.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_groups_embed_actors_and_projects() {
+        let notifications = page_of_two_actors();
+        let embeds = NotificationEmbeds::default()
+            .with_related(&CountingLoader::default(), &notifications, NotificationEmbed::ALL, &visibility())
+            .await
+            .unwrap();
+
+        let groups = NotificationGroup::group(notifications);
+        let json = serde_json::to_value(NotificationRepresenter::represent_groups(groups, &embeds)).unwrap();
+        let expected: serde_json::Value =
+            serde_json::from_str(include_str!("../../fixtures/notifications/groups_with_actors_and_projects.json"))
+                .unwrap();
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_embed_parameter() {
+        assert_eq!(NotificationEmbed::parse("actor, project"), Ok(NotificationEmbed::ALL));
+        assert!(NotificationEmbed::parse("").unwrap().is_empty());
+        assert_eq!(NotificationEmbed::parse("actor,author"), Err("author".to_string()));
+    }
+
+    #[test]
+    fn test_notification_renders_snapshot() {
+        let snapshot = NotificationSnapshot::new("Crash on save")
+            .with_type("Bug")
+            .with_project("Demo")
+            .with_change("status", Some("New".into()), Some("In Progress".into()));
+        let with_snapshot = notification(1, NotificationReason::Watched).with_snapshot(9, snapshot);
+        let json = serde_json::to_value(NotificationRepresenter::represent(with_snapshot)).unwrap();
+
+        assert_eq!(json["subject"], "Bug #42: Crash on save");
+        assert_eq!(json["details"]["projectName"], "Demo");
+        assert_eq!(json["details"]["changes"][0]["new"], "In Progress");
+        assert_eq!(json["_links"]["activity"]["href"], "/api/v3/activities/9");
+
+        // Notifications stored without a snapshot
+        let json = serde_json::to_value(NotificationRepresenter::represent(notification(2, NotificationReason::Watched))).unwrap();
+        assert_eq!(json["subject"], "WorkPackage #42");
+        assert!(json.get("details").is_none());
+        assert!(json["_links"].get("activity").is_none());
+    }
+
+    #[test]
+    fn test_settings_json_shape() {
+        let mut settings = NotificationSettings::for_user(4);
+        settings.email_frequency = EmailFrequency::Daily;
+        let mut project = ProjectNotificationSettings::inherit(3, &settings);
+        project.in_app.watched = false;
+        project.date_alerts.overdue = Some(7);
+        settings.project_overrides.push(project);
+
+        let json = serde_json::to_value(NotificationRepresenter::represent_settings(settings)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "_type": "NotificationSettings",
+                "inApp": {
+                    "involved": false,
+                    "watched": true,
+                    "mentioned": true,
+                    "assignee": true,
+                    "responsible": false
+                },
+                "email": {
+                    "involved": true,
+                    "watched": true,
+                    "mentioned": true,
+                    "assignee": true,
+                    "responsible": true
+                },
+                "emailFrequency": "daily",
+                "dateAlerts": { "startDate": "P1D", "dueDate": "P1D", "overdue": null },
+                "projectOverrides": [{
+                    "inApp": {
+                        "involved": false,
+                        "watched": false,
+                        "mentioned": true,
+                        "assignee": true,
+                        "responsible": false
+                    },
+                    "email": {
+                        "involved": true,
+                        "watched": true,
+                        "mentioned": true,
+                        "assignee": true,
+                        "responsible": true
+                    },
+                    "dateAlerts": { "startDate": "P1D", "dueDate": "P1D", "overdue": "P7D" },
+                    "_links": { "project": { "href": "/api/v3/projects/3" } }
+                }],
+                "_links": {
+                    "self": { "href": "/api/v3/users/4/notification_settings" },
+                    "update": { "href": "/api/v3/users/4/notification_settings", "method": "patch" },
+                    "user": { "href": "/api/v3/users/4" }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_days_durations() {
+        assert_eq!(days_duration(3), "P3D");
+        assert_eq!(parse_days_duration("P0D"), Some(0));
+        assert_eq!(parse_days_duration("P7D"), Some(7));
+        assert_eq!(parse_days_duration("PT7H"), None);
+        assert_eq!(parse_days_duration("7"), None);
+    }
+}
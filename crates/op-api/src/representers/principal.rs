@@ -0,0 +1,295 @@
+//! Principal HAL Representer
+//!
+//! Mirrors: lib/api/v3/principals/principal_representer_factory.rb
+//!
+//! Users, groups and placeholder users share the users table and are told
+//! apart by its `type` column. Links to principals, e.g. a work package's
+//! assignee, point to the resource of their type, and each type is
+//! rendered with its own properties.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use op_db::{principal_type, UserRow};
+use serde::Serialize;
+
+use super::hal::{rels, HalLink, HalLinks, HalResource};
+use super::user::{UserData, UserRepresentation, UserRepresenter};
+
+/// Kind of principal, rendered as its `_type`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PrincipalType {
+    #[default]
+    User,
+    Group,
+    PlaceholderUser,
+}
+
+impl PrincipalType {
+    /// Type of a `users.type` value; built-in users such as the system
+    /// user are rendered as users
+    pub fn from_discriminator(discriminator: &str) -> Self {
+        match discriminator {
+            principal_type::GROUP => Self::Group,
+            principal_type::PLACEHOLDER_USER => Self::PlaceholderUser,
+            _ => Self::User,
+        }
+    }
+
+    /// Type of an API `_type` name, e.g. in a `type` filter
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "User" => Some(Self::User),
+            "Group" => Some(Self::Group),
+            "PlaceholderUser" => Some(Self::PlaceholderUser),
+            _ => None,
+        }
+    }
+
+    /// The `_type` of the representation
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::User => "User",
+            Self::Group => "Group",
+            Self::PlaceholderUser => "PlaceholderUser",
+        }
+    }
+
+    /// The `users.type` value of principals of this type
+    pub fn discriminator(self) -> &'static str {
+        match self {
+            Self::User => principal_type::USER,
+            Self::Group => principal_type::GROUP,
+            Self::PlaceholderUser => principal_type::PLACEHOLDER_USER,
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            Self::User => "users",
+            Self::Group => "groups",
+            Self::PlaceholderUser => "placeholder_users",
+        }
+    }
+}
+
+/// Group representation for API responses
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRepresentation {
+    pub id: Id,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Placeholder user representation for API responses; placeholders have
+/// neither a login nor an email address
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaceholderUserRepresentation {
+    pub id: Id,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Representation of any principal
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum PrincipalRepresentation {
+    User(UserRepresentation),
+    Group(GroupRepresentation),
+    PlaceholderUser(PlaceholderUserRepresentation),
+}
+
+/// Principal condensed to what is shown next to what it did, e.g. as the
+/// actor of a notification
+#[derive(Debug, Clone, Serialize)]
+pub struct CondensedPrincipalRepresentation {
+    pub id: Id,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+/// Principal representer
+pub struct PrincipalRepresenter;
+
+impl PrincipalRepresenter {
+    /// API path of a principal
+    pub fn href(principal_type: PrincipalType, id: Id) -> String {
+        format!("/api/v3/{}/{}", principal_type.path(), id)
+    }
+
+    /// Link to a principal titled with its name
+    pub fn link(principal_type: PrincipalType, id: Id, name: &str) -> HalLink {
+        HalLink::with_title(Self::href(principal_type, id), name)
+    }
+
+    /// Link to the principal of a row
+    pub fn link_to(row: &UserRow) -> HalLink {
+        Self::link(PrincipalType::from_discriminator(&row.principal_type), row.id, &row.full_name())
+    }
+
+    /// Create a HAL resource for a principal; `members` are the users of a
+    /// group and ignored for other principals
+    pub fn represent(row: &UserRow, members: &[UserRow], can_view_email: bool) -> HalResource<PrincipalRepresentation> {
+        let principal_type = PrincipalType::from_discriminator(&row.principal_type);
+        match principal_type {
+            PrincipalType::User => {
+                let user = UserRepresenter::represent(UserData::from(row), can_view_email);
+                HalResource::new(principal_type.name(), PrincipalRepresentation::User(user.resource))
+                    .with_links(user.links)
+            }
+            PrincipalType::Group => {
+                let rep = GroupRepresentation {
+                    id: row.id,
+                    name: row.full_name(),
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                };
+                let mut links = Self::build_links(row, principal_type);
+                links.add_array("members", members.iter().map(Self::link_to).collect());
+                HalResource::new(principal_type.name(), PrincipalRepresentation::Group(rep)).with_links(links)
+            }
+            PrincipalType::PlaceholderUser => {
+                let rep = PlaceholderUserRepresentation {
+                    id: row.id,
+                    name: row.full_name(),
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                };
+                let links = Self::build_links(row, principal_type);
+                HalResource::new(principal_type.name(), PrincipalRepresentation::PlaceholderUser(rep))
+                    .with_links(links)
+            }
+        }
+    }
+
+    /// Create a condensed HAL resource for a principal, linking users to
+    /// their avatar
+    pub fn represent_condensed(row: &UserRow, can_view_email: bool) -> HalResource<CondensedPrincipalRepresentation> {
+        let principal_type = PrincipalType::from_discriminator(&row.principal_type);
+        let mut links = HalLinks::new().with(rels::SELF, Self::link_to(row));
+        if principal_type == PrincipalType::User {
+            links.add("avatar", HalLink::new(UserRepresenter::gravatar_url(&row.mail, 64)));
+        }
+        let rep = CondensedPrincipalRepresentation {
+            id: row.id,
+            name: row.full_name(),
+            email: (can_view_email && principal_type == PrincipalType::User).then(|| row.mail.clone()),
+        };
+        HalResource::new(principal_type.name(), rep).with_links(links)
+    }
+
+    fn build_links(row: &UserRow, principal_type: PrincipalType) -> HalLinks {
+        HalLinks::new()
+            .with(rels::SELF, Self::link(principal_type, row.id, &row.full_name()))
+            .with("memberships", HalLink::new(memberships_href(row.id)))
+    }
+}
+
+/// Memberships of a principal, as filtered collection
+pub(crate) fn memberships_href(principal_id: Id) -> String {
+    format!(
+        "/api/v3/memberships?filters=[{{\"principal\":{{\"operator\":\"=\",\"values\":[\"{}\"]}}}}]",
+        principal_id
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn principal(id: Id, principal_type: &str, login: &str, lastname: &str) -> UserRow {
+        let at = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        UserRow {
+            id,
+            principal_type: principal_type.to_string(),
+            login: login.to_string(),
+            firstname: if login.is_empty() { String::new() } else { "John".to_string() },
+            lastname: lastname.to_string(),
+            mail: if login.is_empty() { String::new() } else { format!("{}@example.com", login) },
+            admin: false,
+            status: 1,
+            language: None,
+            hashed_password: None,
+            salt: None,
+            created_at: at,
+            updated_at: at,
+            last_login_on: None,
+        }
+    }
+
+    #[test]
+    fn test_user_json() {
+        let user = principal(1, principal_type::USER, "john.doe", "Doe");
+        let json = serde_json::to_value(PrincipalRepresenter::represent(&user, &[], false)).unwrap();
+
+        assert_eq!(json["_type"], "User");
+        assert_eq!(json["login"], "john.doe");
+        assert_eq!(json["name"], "John Doe");
+        assert!(json["email"].is_null());
+        assert_eq!(json["_links"]["self"]["href"], "/api/v3/users/1");
+    }
+
+    #[test]
+    fn test_group_json() {
+        let group = principal(5, principal_type::GROUP, "", "Developers");
+        let member = principal(1, principal_type::USER, "john.doe", "Doe");
+        let json = serde_json::to_value(PrincipalRepresenter::represent(&group, &[member], true)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "_type": "Group",
+                "id": 5,
+                "name": "Developers",
+                "createdAt": "2024-01-01T00:00:00Z",
+                "updatedAt": "2024-01-01T00:00:00Z",
+                "_links": {
+                    "self": { "href": "/api/v3/groups/5", "title": "Developers" },
+                    "memberships": {
+                        "href": "/api/v3/memberships?filters=[{\"principal\":{\"operator\":\"=\",\"values\":[\"5\"]}}]"
+                    },
+                    "members": [{ "href": "/api/v3/users/1", "title": "John Doe" }]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_placeholder_user_json() {
+        let placeholder = principal(7, principal_type::PLACEHOLDER_USER, "", "Designer");
+        let json = serde_json::to_value(PrincipalRepresenter::represent(&placeholder, &[], true)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "_type": "PlaceholderUser",
+                "id": 7,
+                "name": "Designer",
+                "createdAt": "2024-01-01T00:00:00Z",
+                "updatedAt": "2024-01-01T00:00:00Z",
+                "_links": {
+                    "self": { "href": "/api/v3/placeholder_users/7", "title": "Designer" },
+                    "memberships": {
+                        "href": "/api/v3/memberships?filters=[{\"principal\":{\"operator\":\"=\",\"values\":[\"7\"]}}]"
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_principal_types() {
+        assert_eq!(PrincipalType::from_discriminator("SystemUser"), PrincipalType::User);
+        assert_eq!(PrincipalType::from_name("Group"), Some(PrincipalType::Group));
+        assert_eq!(PrincipalType::from_name("Admin"), None);
+        assert_eq!(
+            PrincipalType::PlaceholderUser.discriminator(),
+            principal_type::PLACEHOLDER_USER
+        );
+    }
+}
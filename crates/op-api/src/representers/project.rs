@@ -4,9 +4,10 @@
 
 use chrono::{DateTime, Utc};
 use op_core::traits::Id;
+use op_db::ProjectRow;
 use serde::Serialize;
 
-use super::hal::{HalCollection, HalLink, HalLinks, HalResource, rels};
+use super::hal::{CollectionQuery, HalCollection, HalLink, HalLinks, HalResource, rels};
 
 /// Project representation for API responses
 #[derive(Debug, Clone, Serialize)]
@@ -34,6 +35,14 @@ pub struct FormattableText {
     pub html: String,
 }
 
+/// Project condensed to its name, e.g. as the project of a notification
+#[derive(Debug, Clone, Serialize)]
+pub struct CondensedProjectRepresentation {
+    pub id: Id,
+    pub identifier: String,
+    pub name: String,
+}
+
 /// Project representer
 pub struct ProjectRepresenter;
 
@@ -61,29 +70,39 @@ impl ProjectRepresenter {
         HalResource::new("Project", rep).with_links(links)
     }
 
+    /// Create a condensed HAL resource for a project
+    pub fn represent_condensed(project: &ProjectRow) -> HalResource<CondensedProjectRepresentation> {
+        let rep = CondensedProjectRepresentation {
+            id: project.id,
+            identifier: project.identifier.clone(),
+            name: project.name.clone(),
+        };
+        let self_link = HalLink::with_title(format!("/api/v3/projects/{}", project.id), &project.name);
+        HalResource::new("Project", rep).with_links(HalLinks::new().with(rels::SELF, self_link))
+    }
+
     /// Create a HAL collection of projects
     pub fn represent_collection(
         projects: Vec<ProjectData>,
         total: i64,
         offset: i64,
         page_size: i64,
-        base_url: &str,
+        query: &CollectionQuery,
     ) -> HalCollection<HalResource<ProjectRepresentation>> {
-        let page = (offset / page_size) + 1;
         let elements: Vec<HalResource<ProjectRepresentation>> = projects
             .into_iter()
             .map(|p| Self::represent(p))
             .collect();
 
         HalCollection::new("ProjectCollection", elements, total, page_size, offset)
-            .with_pagination_links(base_url, page, page_size)
+            .with_pagination_links(query)
             .with_link(
                 "createProject",
-                HalLink::new("/api/v3/projects/form").method("POST"),
+                HalLink::post("/api/v3/projects/form"),
             )
             .with_link(
                 "createProjectImmediate",
-                HalLink::new("/api/v3/projects").method("POST"),
+                HalLink::post("/api/v3/projects"),
             )
     }
 
@@ -95,11 +114,11 @@ impl ProjectRepresenter {
         let mut links = HalLinks::new()
             .with(rels::SELF, HalLink::new(&base))
             .with(rels::SCHEMA, HalLink::new("/api/v3/projects/schema"))
-            .with(rels::UPDATE, HalLink::new(format!("{}/form", base)).method("POST"))
-            .with(rels::UPDATE_IMMEDIATELY, HalLink::new(&base).method("PATCH"))
-            .with(rels::DELETE, HalLink::new(&base).method("DELETE"))
-            .with("createWorkPackage", HalLink::new(format!("{}/work_packages/form", base)).method("POST"))
-            .with("createWorkPackageImmediate", HalLink::new(format!("{}/work_packages", base)).method("POST"))
+            .with(rels::UPDATE, HalLink::post(format!("{}/form", base)))
+            .with(rels::UPDATE_IMMEDIATELY, HalLink::patch(&base))
+            .with(rels::DELETE, HalLink::delete(&base))
+            .with("createWorkPackage", HalLink::post(format!("{}/work_packages/form", base)))
+            .with("createWorkPackageImmediate", HalLink::post(format!("{}/work_packages", base)))
             .with("workPackages", HalLink::new(format!("{}/work_packages", base)))
             .with("categories", HalLink::new(format!("{}/categories", base)))
             .with("versions", HalLink::new(format!("{}/versions", base)))
@@ -0,0 +1,126 @@
+//! Project Schema Representer
+//!
+//! Mirrors: lib/api/v3/projects/schemas/project_schema_representer.rb
+//!
+//! Describes the attributes of projects, including the project custom
+//! fields with their types and whether they are required. The sections of
+//! the custom fields are listed in `_attributeGroups`, in order.
+
+use op_models::form_configuration::api_attribute_name;
+use op_models::CustomField;
+use serde_json::{json, Map, Value};
+
+/// Path of the project schema
+pub const SCHEMA_PATH: &str = "/api/v3/projects/schema";
+
+/// Attributes of every project: key, type, name, required, writable
+const ATTRIBUTES: &[(&str, &str, &str, bool, bool)] = &[
+    ("id", "Integer", "ID", true, false),
+    ("name", "String", "Name", true, true),
+    ("identifier", "String", "Identifier", true, true),
+    ("description", "Formattable", "Description", false, true),
+    ("public", "Boolean", "Public", true, true),
+    ("active", "Boolean", "Active", true, true),
+    ("templated", "Boolean", "Template", true, true),
+    ("parent", "Project", "Subproject of", false, true),
+    ("created_at", "DateTime", "Created on", true, false),
+    ("updated_at", "DateTime", "Updated on", true, false),
+];
+
+/// Project schema representer
+pub struct ProjectSchemaRepresenter;
+
+impl ProjectSchemaRepresenter {
+    /// Schema of projects given the project custom fields, by section and
+    /// position
+    pub fn represent(custom_fields: &[CustomField]) -> Value {
+        let mut schema = Map::new();
+        schema.insert("_type".into(), json!("Schema"));
+        schema.insert("_dependencies".into(), json!([]));
+        for (key, type_name, name, required, writable) in ATTRIBUTES {
+            schema.insert(api_attribute_name(key), field(type_name, name, *required, *writable));
+        }
+        for custom_field in custom_fields {
+            schema.insert(
+                custom_field.attribute_name(),
+                field(custom_field.schema_type(), &custom_field.name, custom_field.is_required, true),
+            );
+        }
+        schema.insert("_attributeGroups".into(), sections(custom_fields));
+        schema.insert("_links".into(), json!({ "self": { "href": SCHEMA_PATH } }));
+        Value::Object(schema)
+    }
+}
+
+/// Sections with the custom fields in them; fields without a section are
+/// not grouped
+fn sections(custom_fields: &[CustomField]) -> Value {
+    let mut groups: Vec<Value> = Vec::new();
+    let mut current = None;
+    for custom_field in custom_fields {
+        let Some(section) = &custom_field.section else { continue };
+        if current != Some(section.id) {
+            current = Some(section.id);
+            groups.push(json!({
+                "_type": "ProjectFormCustomFieldSection",
+                "name": section.name,
+                "attributes": [],
+            }));
+        }
+        if let Some(attributes) = groups.last_mut().and_then(|group| group["attributes"].as_array_mut()) {
+            attributes.push(json!(custom_field.attribute_name()));
+        }
+    }
+    Value::Array(groups)
+}
+
+fn field(type_name: &str, name: &str, required: bool, writable: bool) -> Value {
+    json!({
+        "type": type_name,
+        "name": name,
+        "required": required,
+        "hasDefault": false,
+        "writable": writable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_models::CustomFieldSection;
+
+    fn custom_field(id: i64, field_format: &str, is_required: bool, section: Option<(i64, &str)>) -> CustomField {
+        CustomField {
+            id,
+            name: format!("Field {}", id),
+            field_format: field_format.into(),
+            is_required,
+            position: 1,
+            section: section.map(|(id, name)| CustomFieldSection { id, name: name.into(), position: 1 }),
+        }
+    }
+
+    #[test]
+    fn test_custom_fields_are_described_and_grouped_by_section() {
+        let schema = ProjectSchemaRepresenter::represent(&[
+            custom_field(3, "list", true, Some((1, "Portfolio"))),
+            custom_field(4, "int", false, Some((1, "Portfolio"))),
+            custom_field(5, "date", false, Some((2, "Dates"))),
+            custom_field(6, "string", false, None),
+        ]);
+
+        assert_eq!(schema["customField3"]["type"], "CustomOption");
+        assert_eq!(schema["customField3"]["required"], true);
+        assert_eq!(schema["customField4"]["type"], "Integer");
+        assert_eq!(schema["customField6"]["writable"], true);
+        assert_eq!(schema["createdAt"]["writable"], false);
+        assert_eq!(
+            schema["_attributeGroups"],
+            json!([
+                { "_type": "ProjectFormCustomFieldSection", "name": "Portfolio", "attributes": ["customField3", "customField4"] },
+                { "_type": "ProjectFormCustomFieldSection", "name": "Dates", "attributes": ["customField5"] },
+            ])
+        );
+        assert_eq!(schema["_links"]["self"]["href"], SCHEMA_PATH);
+    }
+}
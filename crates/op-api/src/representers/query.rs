@@ -5,12 +5,13 @@
 use chrono::{DateTime, Utc};
 use op_core::traits::Id;
 use op_queries::{
-    DisplayRepresentation, Filter, FilterOperator, FilterValue, Query, QueryVisibility,
+    registry, DisplayRepresentation, Filter, FilterOperator, FilterValue, Query, QueryVisibility,
     SortCriterion, SortDirection,
 };
 use serde::Serialize;
 
-use super::hal::{HalCollection, HalLink, HalLinks, HalResource, rels};
+use super::filter_schema::schema_href;
+use super::hal::{CollectionQuery, HalCollection, HalLink, HalLinks, HalResource, rels};
 
 /// Query representation for API responses
 #[derive(Debug, Clone, Serialize)]
@@ -28,6 +29,7 @@ pub struct QueryRepresentation {
     pub show_hierarchies: bool,
     pub starred: bool,
     pub public: bool,
+    pub timestamps: Vec<String>,
     #[serde(rename = "createdAt", skip_serializing_if = "Option::is_none")]
     pub created_at: Option<DateTime<Utc>>,
     #[serde(rename = "updatedAt", skip_serializing_if = "Option::is_none")]
@@ -71,6 +73,7 @@ impl QueryRepresenter {
             show_hierarchies: query.show_hierarchies,
             starred: query.starred,
             public: query.is_public(),
+            timestamps: query.timestamps.iter().map(|t| t.to_string()).collect(),
             created_at: None, // Would come from database
             updated_at: None, // Would come from database
         };
@@ -86,20 +89,19 @@ impl QueryRepresenter {
         total: i64,
         offset: i64,
         page_size: i64,
-        base_url: &str,
+        query: &CollectionQuery,
         project_id: Option<Id>,
     ) -> HalCollection<HalResource<QueryRepresentation>> {
-        let page = (offset / page_size) + 1;
         let elements: Vec<HalResource<QueryRepresentation>> = queries
             .into_iter()
             .map(|q| Self::represent(q, project_id))
             .collect();
 
         HalCollection::new("QueryCollection", elements, total, page_size, offset)
-            .with_pagination_links(base_url, page, page_size)
+            .with_pagination_links(query)
             .with_link(
                 "createQuery",
-                HalLink::new("/api/v3/queries/form").method("POST"),
+                HalLink::post("/api/v3/queries/form"),
             )
     }
 
@@ -111,11 +113,11 @@ impl QueryRepresenter {
         if let Some(id) = query.id {
             let base = format!("/api/v3/queries/{}", id);
             links.add(rels::SELF, HalLink::new(&base));
-            links.add(rels::UPDATE, HalLink::new(format!("{}/form", base)).method("POST"));
-            links.add(rels::UPDATE_IMMEDIATELY, HalLink::new(&base).method("PATCH"));
-            links.add(rels::DELETE, HalLink::new(&base).method("DELETE"));
-            links.add("star", HalLink::new(format!("{}/star", base)).method("PATCH"));
-            links.add("unstar", HalLink::new(format!("{}/unstar", base)).method("PATCH"));
+            links.add(rels::UPDATE, HalLink::post(format!("{}/form", base)));
+            links.add(rels::UPDATE_IMMEDIATELY, HalLink::patch(&base));
+            links.add(rels::DELETE, HalLink::delete(&base));
+            links.add("star", HalLink::patch(format!("{}/star", base)));
+            links.add("unstar", HalLink::patch(format!("{}/unstar", base)));
         } else {
             // Unsaved query
             links.add(rels::SELF, HalLink::templated("/api/v3/queries/new"));
@@ -179,13 +181,10 @@ impl QueryRepresenter {
         }
 
         // Group by link
-        if let Some(ref group_attr) = query.group_by.attribute {
+        if let Some(group_by) = query.group_by.to_param() {
             links.add(
                 "groupBy",
-                HalLink::with_title(
-                    format!("/api/v3/queries/group_bys/{}", group_attr),
-                    group_attr.clone(),
-                ),
+                HalLink::with_title(format!("/api/v3/queries/group_bys/{}", group_by), group_by),
             );
         }
 
@@ -199,19 +198,17 @@ impl QueryRepresenter {
             .map(|filter| {
                 let operator_href = Self::operator_href(&filter.operator);
                 let values = Self::values_to_links(&filter.attribute, &filter.values);
+                // Filters are linked by their API name where the registry knows it
+                let name = registry::find(&filter.attribute)
+                    .map(|definition| definition.name)
+                    .unwrap_or_else(|| filter.attribute.clone());
 
                 FilterRepresentation {
                     name: filter.attribute.clone(),
                     links: FilterLinks {
-                        filter: HalLink::new(format!(
-                            "/api/v3/queries/filters/{}",
-                            filter.attribute
-                        )),
+                        filter: HalLink::new(format!("/api/v3/queries/filters/{}", name)),
                         operator: HalLink::new(operator_href),
-                        schema: Some(HalLink::new(format!(
-                            "/api/v3/queries/filter_instance_schemas/{}",
-                            filter.attribute
-                        ))),
+                        schema: Some(HalLink::new(schema_href(&name))),
                         values,
                     },
                 }
@@ -221,31 +218,7 @@ impl QueryRepresenter {
 
     /// Get operator href
     fn operator_href(op: &FilterOperator) -> String {
-        let op_name = match op {
-            FilterOperator::Equals => "=",
-            FilterOperator::NotEquals => "!",
-            FilterOperator::Contains => "~",
-            FilterOperator::NotContains => "!~",
-            FilterOperator::StartsWith => "**",
-            FilterOperator::EndsWith => "*~",
-            FilterOperator::GreaterThan => ">",
-            FilterOperator::GreaterThanOrEqual => ">=",
-            FilterOperator::LessThan => "<",
-            FilterOperator::LessThanOrEqual => "<=",
-            FilterOperator::Between => "<>d",
-            FilterOperator::IsNull => "o",
-            FilterOperator::IsNotNull => "c",
-            FilterOperator::Today => "t",
-            FilterOperator::ThisWeek => "w",
-            FilterOperator::DaysAgo(_) => "t-",
-            FilterOperator::DaysFromNow(_) => "t+",
-            FilterOperator::LessThanDaysAgo(_) => "<t-",
-            FilterOperator::MoreThanDaysAgo(_) => ">t-",
-            FilterOperator::LessThanDaysFromNow(_) => "<t+",
-            FilterOperator::MoreThanDaysFromNow(_) => ">t+",
-            FilterOperator::CurrentUser => "=",
-        };
-        format!("/api/v3/queries/operators/{}", op_name)
+        format!("/api/v3/queries/operators/{}", op.symbol())
     }
 
     /// Convert filter values to links
@@ -254,6 +227,9 @@ impl QueryRepresenter {
             FilterValue::Id(id) => vec![Self::value_link(attribute, *id)],
             FilterValue::Ids(ids) => ids.iter().map(|id| Self::value_link(attribute, *id)).collect(),
             FilterValue::Me => vec![HalLink::with_title("/api/v3/users/me", "Me")],
+            FilterValue::IdsAndMe(ids) => std::iter::once(HalLink::with_title("/api/v3/users/me", "Me"))
+                .chain(ids.iter().map(|id| Self::value_link(attribute, *id)))
+                .collect(),
             _ => vec![],
         }
     }
@@ -270,7 +246,7 @@ impl QueryRepresenter {
             }
             "version_id" => format!("/api/v3/versions/{}", id),
             "category_id" => format!("/api/v3/categories/{}", id),
-            "parent_id" => format!("/api/v3/work_packages/{}", id),
+            "parent_id" | "subtree_of" => format!("/api/v3/work_packages/{}", id),
             _ => format!("/api/v3/custom_options/{}", id),
         };
         HalLink::new(href)
@@ -309,6 +285,20 @@ mod tests {
         assert_eq!(json["name"], "My Query");
     }
 
+    #[test]
+    fn test_query_timestamps() {
+        let query = QueryBuilder::new()
+            .name("Baseline")
+            .timestamps(op_queries::Timestamps::parse("oneWeekAgo@12:00+00:00,PT0S").unwrap())
+            .build();
+
+        let json = serde_json::to_value(QueryRepresenter::represent(&query, None)).unwrap();
+        assert_eq!(json["timestamps"], serde_json::json!(["oneWeekAgo@12:00+00:00", "PT0S"]));
+
+        let json = serde_json::to_value(QueryRepresenter::represent(&QueryBuilder::new().build(), None)).unwrap();
+        assert_eq!(json["timestamps"], serde_json::json!(["PT0S"]));
+    }
+
     #[test]
     fn test_filter_representation() {
         let query = QueryBuilder::new()
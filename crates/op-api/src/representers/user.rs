@@ -3,10 +3,13 @@
 //! Converts user models to HAL+JSON format compatible with OpenProject API v3.
 
 use chrono::{DateTime, Utc};
+use op_core::i18n::I18n;
 use op_core::traits::Id;
+use op_db::UserRow;
 use serde::Serialize;
 
-use super::hal::{HalCollection, HalLink, HalLinks, HalResource, rels};
+use super::hal::{CollectionQuery, HalCollection, HalLink, HalLinks, HalResource, rels};
+use super::principal::memberships_href;
 
 /// User representation for API responses
 #[derive(Debug, Clone, Serialize)]
@@ -72,17 +75,16 @@ impl UserRepresenter {
         total: i64,
         offset: i64,
         page_size: i64,
-        base_url: &str,
+        query: &CollectionQuery,
         can_view_emails: bool,
     ) -> HalCollection<HalResource<UserRepresentation>> {
-        let page = (offset / page_size) + 1;
         let elements: Vec<HalResource<UserRepresentation>> = users
             .into_iter()
             .map(|u| Self::represent(u, can_view_emails))
             .collect();
 
         HalCollection::new("UserCollection", elements, total, page_size, offset)
-            .with_pagination_links(base_url, page, page_size)
+            .with_pagination_links(query)
     }
 
     /// Build links for a user
@@ -92,17 +94,14 @@ impl UserRepresenter {
         let mut links = HalLinks::new()
             .with(rels::SELF, HalLink::new(&base))
             .with("showUser", HalLink::new(format!("/users/{}", user.id)))
-            .with("memberships", HalLink::new(format!(
-                "/api/v3/memberships?filters=[{{\"principal\":{{\"operator\":\"=\",\"values\":[\"{}\"]}}}}]",
-                user.id
-            )));
+            .with("memberships", HalLink::new(memberships_href(user.id)));
 
         if can_manage {
-            links.add(rels::UPDATE, HalLink::new(format!("{}/form", base)).method("POST"));
-            links.add(rels::UPDATE_IMMEDIATELY, HalLink::new(&base).method("PATCH"));
-            links.add(rels::DELETE, HalLink::new(&base).method("DELETE"));
-            links.add("lock", HalLink::new(format!("{}/lock", base)).method("POST"));
-            links.add("unlock", HalLink::new(format!("{}/unlock", base)).method("DELETE"));
+            links.add(rels::UPDATE, HalLink::post(format!("{}/form", base)));
+            links.add(rels::UPDATE_IMMEDIATELY, HalLink::patch(&base));
+            links.add(rels::DELETE, HalLink::delete(&base));
+            links.add("lock", HalLink::post(format!("{}/lock", base)));
+            links.add("unlock", HalLink::delete(format!("{}/unlock", base)));
         }
 
         links
@@ -116,12 +115,13 @@ impl UserRepresenter {
             2 => "registered".to_string(),
             3 => "locked".to_string(),
             4 => "invited".to_string(),
+            5 => "deleted".to_string(),
             _ => "unknown".to_string(),
         }
     }
 
     /// Generate gravatar URL from email
-    fn gravatar_url(email: &str, size: u32) -> String {
+    pub(crate) fn gravatar_url(email: &str, size: u32) -> String {
         let hash = md5_hash(email.trim().to_lowercase().as_bytes());
         format!(
             "https://secure.gravatar.com/avatar/{}?default=404&secure=true&size={}",
@@ -145,6 +145,23 @@ pub struct UserData {
     pub updated_at: DateTime<Utc>,
 }
 
+impl From<&UserRow> for UserData {
+    fn from(row: &UserRow) -> Self {
+        Self {
+            id: row.id,
+            login: row.login.clone(),
+            first_name: row.firstname.clone(),
+            last_name: row.lastname.clone(),
+            email: row.mail.clone(),
+            admin: row.admin,
+            status: row.status,
+            language: row.language.clone(),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
 /// Simple MD5 hash (for gravatar URLs)
 fn md5_hash(data: &[u8]) -> String {
     use std::fmt::Write;
@@ -159,12 +176,10 @@ fn md5_hash(data: &[u8]) -> String {
     result
 }
 
-/// Placeholder user representation
-#[derive(Debug, Clone, Serialize)]
-pub struct PlaceholderUserRepresentation {
-    #[serde(rename = "_type")]
-    pub user_type: String,
-    pub name: String,
+/// Name of a system user in the locale of the current request
+fn system_user_name(key: &str) -> String {
+    let i18n = I18n::shared();
+    i18n.t(&i18n.current_locale(), &format!("users.{}", key), &[])
 }
 
 /// System user representation
@@ -182,7 +197,7 @@ impl SystemUserRepresentation {
         Self {
             user_type: "User".to_string(),
             id: "system",
-            name: "System".to_string(),
+            name: system_user_name("system"),
         }
     }
 
@@ -191,7 +206,7 @@ impl SystemUserRepresentation {
         Self {
             user_type: "User".to_string(),
             id: "deleted_user",
-            name: "Deleted user".to_string(),
+            name: system_user_name("deleted"),
         }
     }
 
@@ -200,7 +215,7 @@ impl SystemUserRepresentation {
         Self {
             user_type: "User".to_string(),
             id: "anonymous",
-            name: "Anonymous".to_string(),
+            name: system_user_name("anonymous"),
         }
     }
 }
@@ -4,9 +4,14 @@
 
 use chrono::{DateTime, NaiveDate, Utc};
 use op_core::traits::Id;
+use op_db::{Includes, ResolvedIncludes, WorkPackageRow};
+use op_services::costs::WorkPackageCosts;
 use serde::Serialize;
 
-use super::hal::{HalCollection, HalEmbedded, HalLink, HalLinks, HalResource, rels};
+pub use op_core::representations::FormattableText;
+
+use super::hal::{CollectionQuery, HalCollection, HalEmbedded, HalLink, HalLinks, HalResource, rels};
+use super::principal::{PrincipalRepresenter, PrincipalType};
 
 /// Work package representation for API responses
 #[derive(Debug, Clone, Serialize)]
@@ -49,33 +54,20 @@ pub struct WorkPackageRepresentation {
     pub story_points: Option<i32>,
     #[serde(rename = "remainingTime", skip_serializing_if = "Option::is_none")]
     pub remaining_time: Option<String>,
-}
-
-/// Formattable text (supports HTML/Markdown)
-#[derive(Debug, Clone, Serialize)]
-pub struct FormattableText {
-    pub format: String,
-    pub raw: String,
-    pub html: String,
-}
-
-impl FormattableText {
-    pub fn plain(text: &str) -> Self {
-        Self {
-            format: "plain".to_string(),
-            raw: text.to_string(),
-            html: format!("<p>{}</p>", html_escape(text)),
-        }
-    }
-
-    pub fn markdown(text: &str) -> Self {
-        Self {
-            format: "markdown".to_string(),
-            raw: text.to_string(),
-            // TODO: Actually render markdown to HTML
-            html: format!("<p>{}</p>", html_escape(text)),
-        }
-    }
+    /// Number of users watching the work package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watchers: Option<i64>,
+    /// Number of files in storages linked to the work package
+    #[serde(rename = "fileLinks", skip_serializing_if = "Option::is_none")]
+    pub file_links: Option<i64>,
+    /// Costs of the work package and its descendants, as far as the user
+    /// may see them
+    #[serde(rename = "laborCosts", skip_serializing_if = "Option::is_none")]
+    pub labor_costs: Option<f64>,
+    #[serde(rename = "materialCosts", skip_serializing_if = "Option::is_none")]
+    pub material_costs: Option<f64>,
+    #[serde(rename = "overallCosts", skip_serializing_if = "Option::is_none")]
+    pub overall_costs: Option<f64>,
 }
 
 /// Work package representer - builds HAL responses
@@ -108,6 +100,11 @@ impl WorkPackageRepresenter {
             position: wp.position,
             story_points: wp.story_points,
             remaining_time: wp.remaining_hours.map(|h| format_duration(h)),
+            watchers: wp.watcher_count,
+            file_links: wp.file_link_count,
+            labor_costs: wp.costs.map(|c| c.labor_costs),
+            material_costs: wp.costs.map(|c| c.material_costs),
+            overall_costs: wp.costs.map(|c| c.overall_costs),
         };
 
         let links = Self::build_links(&wp);
@@ -124,24 +121,23 @@ impl WorkPackageRepresenter {
         total: i64,
         offset: i64,
         page_size: i64,
-        base_url: &str,
+        query: &CollectionQuery,
         embed_options: &EmbedOptions,
     ) -> HalCollection<HalResource<WorkPackageRepresentation>> {
-        let page = (offset / page_size) + 1;
         let elements: Vec<HalResource<WorkPackageRepresentation>> = work_packages
             .into_iter()
             .map(|wp| Self::represent(wp, embed_options))
             .collect();
 
         HalCollection::new("WorkPackageCollection", elements, total, page_size, offset)
-            .with_pagination_links(base_url, page, page_size)
+            .with_pagination_links(query)
             .with_link(
                 "createWorkPackage",
-                HalLink::new("/api/v3/work_packages/form").method("POST"),
+                HalLink::post("/api/v3/work_packages/form"),
             )
             .with_link(
                 "createWorkPackageImmediate",
-                HalLink::new("/api/v3/work_packages").method("POST"),
+                HalLink::post("/api/v3/work_packages"),
             )
             .with_link("schemas", HalLink::new("/api/v3/work_packages/schemas"))
     }
@@ -155,16 +151,16 @@ impl WorkPackageRepresenter {
             .with(rels::SCHEMA, HalLink::new(format!("{}/schema", base)))
             .with(
                 rels::UPDATE,
-                HalLink::new(format!("{}/form", base)).method("POST"),
+                HalLink::post(format!("{}/form", base)),
             )
             .with(
                 rels::UPDATE_IMMEDIATELY,
-                HalLink::new(&base).method("PATCH"),
+                HalLink::patch(&base),
             )
-            .with(rels::DELETE, HalLink::new(&base).method("DELETE"))
+            .with(rels::DELETE, HalLink::delete(&base))
             .with(
                 rels::LOG_TIME,
-                HalLink::new("/api/v3/time_entries/form").method("POST"),
+                HalLink::post("/api/v3/time_entries/form"),
             )
             .with(rels::MOVE, HalLink::new(format!("{}/move", base)))
             .with(rels::COPY, HalLink::new(format!("{}/copy", base)))
@@ -172,9 +168,23 @@ impl WorkPackageRepresenter {
             .with("revisions", HalLink::new(format!("{}/revisions", base)))
             .with("watchers", HalLink::new(format!("{}/watchers", base)))
             .with("attachments", HalLink::new(format!("{}/attachments", base)))
+            .with("fileLinks", HalLink::new(format!("{}/file_links", base)))
             .with("relations", HalLink::new(format!("{}/relations", base)))
             .with("children", HalLink::new(format!("{}/children", base)));
 
+        // Watch toggle for the current user
+        match wp.watching {
+            Some(true) => links.add(
+                rels::UNWATCH,
+                HalLink::delete(format!("{}/watch", base)),
+            ),
+            Some(false) => links.add(
+                rels::WATCH,
+                HalLink::post(format!("{}/watch", base)),
+            ),
+            None => {}
+        }
+
         // Project link
         links.add(
             "project",
@@ -217,10 +227,7 @@ impl WorkPackageRepresenter {
         if let Some(author_id) = wp.author_id {
             links.add(
                 "author",
-                HalLink::with_title(
-                    format!("/api/v3/users/{}", author_id),
-                    wp.author_name.as_deref().unwrap_or(""),
-                ),
+                PrincipalRepresenter::link(wp.author_type, author_id, wp.author_name.as_deref().unwrap_or("")),
             );
         }
 
@@ -228,10 +235,7 @@ impl WorkPackageRepresenter {
         if let Some(assignee_id) = wp.assigned_to_id {
             links.add(
                 "assignee",
-                HalLink::with_title(
-                    format!("/api/v3/users/{}", assignee_id),
-                    wp.assignee_name.as_deref().unwrap_or(""),
-                ),
+                PrincipalRepresenter::link(wp.assignee_type, assignee_id, wp.assignee_name.as_deref().unwrap_or("")),
             );
         }
 
@@ -239,10 +243,7 @@ impl WorkPackageRepresenter {
         if let Some(responsible_id) = wp.responsible_id {
             links.add(
                 "responsible",
-                HalLink::with_title(
-                    format!("/api/v3/users/{}", responsible_id),
-                    wp.responsible_name.as_deref().unwrap_or(""),
-                ),
+                PrincipalRepresenter::link(wp.responsible_type, responsible_id, wp.responsible_name.as_deref().unwrap_or("")),
             );
         }
 
@@ -284,16 +285,14 @@ impl WorkPackageRepresenter {
         let mut embedded = HalEmbedded::new();
 
         if options.embed_status {
-            if let (Some(name), Some(color), Some(is_closed)) =
-                (&wp.status_name, &wp.status_color, wp.status_is_closed)
-            {
+            if let (Some(name), Some(is_closed)) = (&wp.status_name, wp.status_is_closed) {
                 embedded.add(
                     "status",
                     StatusEmbedded {
                         _type: "Status".to_string(),
                         id: wp.status_id,
                         name: name.clone(),
-                        color: color.clone(),
+                        color: wp.status_color.clone(),
                         is_closed,
                     },
                 );
@@ -328,10 +327,112 @@ impl WorkPackageRepresenter {
             }
         }
 
+        let users = [
+            ("author", options.embed_author, wp.author_id, &wp.author_name, wp.author_type),
+            ("assignee", options.embed_assignee, wp.assigned_to_id, &wp.assignee_name, wp.assignee_type),
+            ("responsible", options.embed_responsible, wp.responsible_id, &wp.responsible_name, wp.responsible_type),
+        ];
+        for (rel, embed, id, name, principal_type) in users {
+            if let (true, Some(id), Some(name)) = (embed, id, name) {
+                embedded.add(rel, NamedEmbedded::new(principal_type.name(), id, name));
+            }
+        }
+
+        if options.embed_project {
+            if let Some(name) = &wp.project_name {
+                embedded.add("project", NamedEmbedded::new("Project", wp.project_id, name));
+            }
+        }
+
+        if options.embed_version {
+            if let (Some(version_id), Some(name)) = (wp.version_id, &wp.version_name) {
+                embedded.add("version", NamedEmbedded::new("Version", version_id, name));
+            }
+        }
+
         embedded
     }
 }
 
+/// Trimmed work package representation for the timeline (Gantt) view.
+/// Skips description and all links except self and parent so that
+/// responses with thousands of elements stay small.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineRepresentation {
+    pub id: Id,
+    pub subject: String,
+    #[serde(rename = "startDate", skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<String>,
+    #[serde(rename = "dueDate", skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<String>,
+    #[serde(rename = "isMilestone")]
+    pub is_milestone: bool,
+    #[serde(rename = "typeColor", skip_serializing_if = "Option::is_none")]
+    pub type_color: Option<String>,
+    #[serde(rename = "statusColor", skip_serializing_if = "Option::is_none")]
+    pub status_color: Option<String>,
+}
+
+/// Work package data for the timeline view
+#[derive(Debug, Clone)]
+pub struct TimelineData {
+    pub id: Id,
+    pub subject: String,
+    pub parent_id: Option<Id>,
+    pub start_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub is_milestone: bool,
+    pub type_color: Option<String>,
+    pub status_color: Option<String>,
+}
+
+/// Timeline representer - builds trimmed HAL responses for the Gantt view
+pub struct TimelineRepresenter;
+
+impl TimelineRepresenter {
+    /// Create a HAL resource for a single timeline element
+    pub fn represent(wp: TimelineData) -> HalResource<TimelineRepresentation> {
+        let mut links = HalLinks::new().with(
+            rels::SELF,
+            HalLink::new(format!("/api/v3/work_packages/{}", wp.id)),
+        );
+
+        // Parent link, needed to draw the hierarchy
+        if let Some(parent_id) = wp.parent_id {
+            links.add(
+                "parent",
+                HalLink::new(format!("/api/v3/work_packages/{}", parent_id)),
+            );
+        }
+
+        let rep = TimelineRepresentation {
+            id: wp.id,
+            subject: wp.subject,
+            start_date: wp.start_date.map(|d| d.to_string()),
+            due_date: wp.due_date.map(|d| d.to_string()),
+            is_milestone: wp.is_milestone,
+            type_color: wp.type_color,
+            status_color: wp.status_color,
+        };
+
+        HalResource::new("WorkPackage", rep).with_links(links)
+    }
+
+    /// Create a HAL collection of timeline elements
+    pub fn represent_collection(
+        work_packages: Vec<TimelineData>,
+        total: i64,
+        offset: i64,
+        page_size: i64,
+        query: &CollectionQuery,
+    ) -> HalCollection<HalResource<TimelineRepresentation>> {
+        let elements = work_packages.into_iter().map(Self::represent).collect();
+
+        HalCollection::new("WorkPackageCollection", elements, total, page_size, offset)
+            .with_pagination_links(query)
+    }
+}
+
 /// Work package data for representation
 #[derive(Debug, Clone)]
 pub struct WorkPackageData {
@@ -353,10 +454,13 @@ pub struct WorkPackageData {
     pub priority_color: Option<String>,
     pub author_id: Option<Id>,
     pub author_name: Option<String>,
+    pub author_type: PrincipalType,
     pub assigned_to_id: Option<Id>,
     pub assignee_name: Option<String>,
+    pub assignee_type: PrincipalType,
     pub responsible_id: Option<Id>,
     pub responsible_name: Option<String>,
+    pub responsible_type: PrincipalType,
     pub category_id: Option<Id>,
     pub version_id: Option<Id>,
     pub version_name: Option<String>,
@@ -372,17 +476,91 @@ pub struct WorkPackageData {
     pub duration: Option<i32>,
     pub position: Option<i32>,
     pub story_points: Option<i32>,
+    /// Whether the current user watches the work package (None = unknown)
+    pub watching: Option<bool>,
+    pub watcher_count: Option<i64>,
+    pub file_link_count: Option<i64>,
+    /// Costs visible to the current user (None = not computed)
+    pub costs: Option<WorkPackageCosts>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl WorkPackageData {
+    /// Build from a query result row, taking the names of referenced
+    /// resources from the resolved includes. Resources that were not
+    /// resolved are left out and hence not embedded.
+    pub fn from_row(row: WorkPackageRow, includes: &ResolvedIncludes) -> Self {
+        let status = includes.status(row.status_id);
+        let work_package_type = includes.work_package_type(row.type_id);
+        let priority = includes.priority(row.priority_id);
+        let user_name = |id| includes.user(id).map(|u| u.full_name());
+        let user_type = |id| {
+            includes
+                .user(id)
+                .map(|u| PrincipalType::from_discriminator(&u.principal_type))
+                .unwrap_or_default()
+        };
+
+        Self {
+            id: row.id,
+            lock_version: row.lock_version,
+            subject: row.subject,
+            description: row.description,
+            project_id: row.project_id,
+            project_name: includes.project(row.project_id).map(|p| p.name.clone()),
+            type_id: row.type_id,
+            type_name: work_package_type.map(|t| t.name.clone()),
+            type_color: None,
+            status_id: row.status_id,
+            status_name: status.map(|s| s.name.clone()),
+            status_color: None,
+            status_is_closed: status.map(|s| s.is_closed),
+            priority_id: row.priority_id,
+            priority_name: priority.map(|p| p.name.clone()),
+            priority_color: None,
+            author_id: row.author_id,
+            author_name: user_name(row.author_id),
+            author_type: user_type(row.author_id),
+            assigned_to_id: row.assigned_to_id,
+            assignee_name: user_name(row.assigned_to_id),
+            assignee_type: user_type(row.assigned_to_id),
+            responsible_id: row.responsible_id,
+            responsible_name: user_name(row.responsible_id),
+            responsible_type: user_type(row.responsible_id),
+            category_id: row.category_id,
+            version_id: row.version_id,
+            version_name: includes.version(row.version_id).map(|v| v.name.clone()),
+            parent_id: row.parent_id,
+            parent_subject: None,
+            start_date: row.start_date,
+            due_date: row.due_date,
+            estimated_hours: row.estimated_hours,
+            spent_hours: None,
+            remaining_hours: row.remaining_hours,
+            done_ratio: row.done_ratio,
+            schedule_manually: row.schedule_manually,
+            duration: row.duration,
+            position: row.position,
+            story_points: row.story_points,
+            watching: None,
+            watcher_count: None,
+            file_link_count: None,
+            costs: None,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
 /// Embedded status representation
 #[derive(Debug, Clone, Serialize)]
 struct StatusEmbedded {
     _type: String,
     id: Id,
     name: String,
-    color: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
     #[serde(rename = "isClosed")]
     is_closed: bool,
 }
@@ -407,6 +585,25 @@ struct PriorityEmbedded {
     color: Option<String>,
 }
 
+/// Embedded representation of resources shown by name (users, projects,
+/// versions)
+#[derive(Debug, Clone, Serialize)]
+struct NamedEmbedded {
+    _type: String,
+    id: Id,
+    name: String,
+}
+
+impl NamedEmbedded {
+    fn new(type_name: &str, id: Id, name: &str) -> Self {
+        Self {
+            _type: type_name.to_string(),
+            id,
+            name: name.to_string(),
+        }
+    }
+}
+
 /// Options for embedding related resources
 #[derive(Debug, Clone, Default)]
 pub struct EmbedOptions {
@@ -440,6 +637,18 @@ impl EmbedOptions {
         }
     }
 
+    /// Resources to batch load for these embeds
+    pub fn includes(&self) -> Includes {
+        Includes {
+            statuses: self.embed_status,
+            types: self.embed_type,
+            priorities: self.embed_priority,
+            users: self.embed_author || self.embed_assignee || self.embed_responsible,
+            projects: self.embed_project,
+            versions: self.embed_version,
+        }
+    }
+
     pub fn from_query_params(params: &str) -> Self {
         let mut options = Self::none();
 
@@ -468,24 +677,7 @@ impl EmbedOptions {
 
 /// Format hours as ISO 8601 duration
 fn format_duration(hours: f64) -> String {
-    let total_minutes = (hours * 60.0).round() as i64;
-    let h = total_minutes / 60;
-    let m = total_minutes % 60;
-
-    if m == 0 {
-        format!("PT{}H", h)
-    } else {
-        format!("PT{}H{}M", h, m)
-    }
-}
-
-/// Simple HTML escaping
-fn html_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&#39;")
+    op_core::duration::format_iso8601_duration(hours)
 }
 
 #[cfg(test)]
@@ -500,12 +692,6 @@ mod tests {
         assert_eq!(format_duration(8.0), "PT8H");
     }
 
-    #[test]
-    fn test_html_escape() {
-        assert_eq!(html_escape("<script>"), "&lt;script&gt;");
-        assert_eq!(html_escape("A & B"), "A &amp; B");
-    }
-
     #[test]
     fn test_embed_options_from_query() {
         let options = EmbedOptions::from_query_params("status,type,priority");
@@ -515,11 +701,188 @@ mod tests {
         assert!(!options.embed_author);
     }
 
+    fn timeline_data(id: Id) -> TimelineData {
+        TimelineData {
+            id,
+            subject: "Implement the quarterly release of the reporting module".to_string(),
+            parent_id: Some(1),
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1),
+            due_date: NaiveDate::from_ymd_opt(2024, 3, 31),
+            is_milestone: false,
+            type_color: Some("#1A67A3".to_string()),
+            status_color: Some("#35C53F".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_timeline_representation_is_trimmed() {
+        let json = serde_json::to_value(TimelineRepresenter::represent(timeline_data(42))).unwrap();
+
+        assert_eq!(json["isMilestone"], false);
+        assert_eq!(json["typeColor"], "#1A67A3");
+        assert_eq!(json["startDate"], "2024-01-01");
+        assert!(json.get("description").is_none());
+        assert_eq!(json["_links"].as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_timeline_payload_size_budget() {
+        const ELEMENTS: i64 = 5_000;
+        const BYTES_PER_ELEMENT: usize = 400;
+
+        let elements = (100_000..100_000 + ELEMENTS).map(timeline_data).collect();
+        let collection = TimelineRepresenter::represent_collection(
+            elements,
+            ELEMENTS,
+            0,
+            ELEMENTS,
+            &CollectionQuery::new("/api/v3/work_packages"),
+        );
+        let bytes = serde_json::to_vec(&collection).unwrap().len();
+
+        let per_element = bytes / ELEMENTS as usize;
+        assert!(
+            per_element < BYTES_PER_ELEMENT,
+            "timeline element is {} bytes, budget is {}",
+            per_element,
+            BYTES_PER_ELEMENT
+        );
+    }
+
+    fn work_package_data(watching: Option<bool>) -> WorkPackageData {
+        let now = Utc::now();
+        WorkPackageData {
+            id: 42,
+            lock_version: 0,
+            subject: "Watch me".to_string(),
+            description: None,
+            project_id: 1,
+            project_name: None,
+            type_id: 1,
+            type_name: None,
+            type_color: None,
+            status_id: 1,
+            status_name: None,
+            status_color: None,
+            status_is_closed: None,
+            priority_id: None,
+            priority_name: None,
+            priority_color: None,
+            author_id: None,
+            author_name: None,
+            author_type: PrincipalType::User,
+            assigned_to_id: None,
+            assignee_name: None,
+            assignee_type: PrincipalType::User,
+            responsible_id: None,
+            responsible_name: None,
+            responsible_type: PrincipalType::User,
+            category_id: None,
+            version_id: None,
+            version_name: None,
+            parent_id: None,
+            parent_subject: None,
+            start_date: None,
+            due_date: None,
+            estimated_hours: None,
+            spent_hours: None,
+            remaining_hours: None,
+            done_ratio: 0,
+            schedule_manually: false,
+            duration: None,
+            position: None,
+            story_points: None,
+            watching,
+            watcher_count: watching.map(|w| w as i64),
+            file_link_count: None,
+            costs: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_watch_links_follow_watch_state() {
+        let options = EmbedOptions::default();
+        let represent = |watching| {
+            serde_json::to_value(WorkPackageRepresenter::represent(work_package_data(watching), &options)).unwrap()
+        };
+
+        let unwatched = represent(Some(false));
+        assert_eq!(unwatched["_links"]["watch"]["href"], "/api/v3/work_packages/42/watch");
+        assert_eq!(unwatched["_links"]["watch"]["method"], "post");
+        assert!(unwatched["_links"].get("unwatch").is_none());
+        assert_eq!(unwatched["watchers"], 0);
+
+        let watched = represent(Some(true));
+        assert_eq!(watched["_links"]["unwatch"]["method"], "delete");
+        assert!(watched["_links"].get("watch").is_none());
+        assert_eq!(watched["watchers"], 1);
+
+        let unknown = represent(None);
+        assert!(unknown["_links"].get("watch").is_none());
+        assert!(unknown.get("watchers").is_none());
+    }
+
+    #[test]
+    fn test_file_links_count_and_link() {
+        let options = EmbedOptions::default();
+        let mut wp = work_package_data(None);
+        let json = serde_json::to_value(WorkPackageRepresenter::represent(wp.clone(), &options)).unwrap();
+        assert_eq!(json["_links"]["fileLinks"]["href"], "/api/v3/work_packages/42/file_links");
+        assert!(json.get("fileLinks").is_none());
+
+        wp.file_link_count = Some(2);
+        let json = serde_json::to_value(WorkPackageRepresenter::represent(wp, &options)).unwrap();
+        assert_eq!(json["fileLinks"], 2);
+    }
+
+    #[test]
+    fn test_costs_are_shown_when_computed() {
+        let options = EmbedOptions::default();
+        let mut wp = work_package_data(None);
+        let json = serde_json::to_value(WorkPackageRepresenter::represent(wp.clone(), &options)).unwrap();
+        assert!(json.get("overallCosts").is_none());
+
+        wp.costs = Some(WorkPackageCosts {
+            labor_costs: 250.0,
+            material_costs: 50.5,
+            overall_costs: 300.5,
+        });
+        let json = serde_json::to_value(WorkPackageRepresenter::represent(wp, &options)).unwrap();
+        assert_eq!(json["laborCosts"], 250.0);
+        assert_eq!(json["materialCosts"], 50.5);
+        assert_eq!(json["overallCosts"], 300.5);
+    }
+
     #[test]
-    fn test_formattable_text() {
-        let text = FormattableText::plain("Hello <world>");
-        assert_eq!(text.format, "plain");
-        assert_eq!(text.raw, "Hello <world>");
-        assert!(text.html.contains("&lt;world&gt;"));
+    fn test_embeds_users_project_and_version() {
+        let options = EmbedOptions::from_query_params("author,assignee,project,version");
+        let includes = options.includes();
+        assert!(includes.users && includes.projects && includes.versions);
+        assert!(!includes.statuses);
+        assert!(EmbedOptions::none().includes().is_empty());
+
+        let mut wp = work_package_data(None);
+        wp.author_id = Some(3);
+        wp.author_name = Some("Ada Lovelace".to_string());
+        wp.assigned_to_id = Some(4);
+        wp.responsible_id = Some(5);
+        wp.responsible_name = Some("Developers".to_string());
+        wp.responsible_type = PrincipalType::Group;
+        wp.project_name = Some("Engines".to_string());
+        wp.version_id = Some(7);
+        wp.version_name = Some("1.0".to_string());
+
+        let json = serde_json::to_value(WorkPackageRepresenter::represent(wp, &options)).unwrap();
+        let embedded = &json["_embedded"];
+        assert_eq!(embedded["author"]["_type"], "User");
+        assert_eq!(embedded["author"]["name"], "Ada Lovelace");
+        // Unresolved users are not embedded
+        assert!(embedded.get("assignee").is_none());
+        assert_eq!(json["_links"]["responsible"]["href"], "/api/v3/groups/5");
+        assert_eq!(json["_links"]["assignee"]["href"], "/api/v3/users/4");
+        assert_eq!(embedded["project"]["name"], "Engines");
+        assert_eq!(embedded["version"]["id"], 7);
     }
 }
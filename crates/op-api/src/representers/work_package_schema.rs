@@ -0,0 +1,204 @@
+//! Work Package Schema Representer
+//!
+//! Mirrors: lib/api/v3/work_packages/schema/work_package_schema_representer.rb
+//!
+//! Describes the attributes of work packages of a type in a project and, in
+//! `_attributeGroups`, how the form lays them out following the form
+//! configuration of the type. Attributes that no longer exist or whose
+//! custom field is not active in the project are left out of the groups.
+
+use op_db::{FilterableCustomField, TypeRow};
+use op_models::custom_field::schema_type;
+use op_models::form_configuration::api_attribute_name;
+use op_models::{FormConfiguration, FormGroup};
+use serde_json::{json, Map, Value};
+
+/// Path of the work package schemas
+pub const SCHEMAS_PATH: &str = "/api/v3/work_packages/schemas";
+
+/// Attributes of every schema: key, type, name, required, writable
+const ATTRIBUTES: &[(&str, &str, &str, bool, bool)] = &[
+    ("id", "Integer", "ID", true, false),
+    ("subject", "String", "Subject", true, true),
+    ("description", "Formattable", "Description", false, true),
+    ("type", "Type", "Type", true, true),
+    ("status", "Status", "Status", true, true),
+    ("project", "Project", "Project", true, true),
+    ("parent", "WorkPackage", "Parent", false, true),
+    ("author", "User", "Author", true, false),
+    ("assignee", "User", "Assignee", false, true),
+    ("responsible", "User", "Accountable", false, true),
+    ("priority", "Priority", "Priority", true, true),
+    ("category", "Category", "Category", false, true),
+    ("version", "Version", "Version", false, true),
+    ("date", "Date", "Date", false, true),
+    ("start_date", "Date", "Start date", false, true),
+    ("due_date", "Date", "Finish date", false, true),
+    ("duration", "Duration", "Duration", false, true),
+    ("estimated_time", "Duration", "Work", false, true),
+    ("remaining_time", "Duration", "Remaining work", false, true),
+    ("spent_time", "Duration", "Spent time", false, false),
+    ("percentage_done", "Integer", "% Complete", false, true),
+    ("story_points", "Integer", "Story points", false, true),
+    ("budget", "Budget", "Budget", false, true),
+    ("created_at", "DateTime", "Created on", true, false),
+    ("updated_at", "DateTime", "Updated on", true, false),
+];
+
+/// Work package schema representer
+pub struct WorkPackageSchemaRepresenter;
+
+impl WorkPackageSchemaRepresenter {
+    /// Schema of work packages of the type in the project, given the custom
+    /// fields active in the project
+    pub fn represent(project_id: i64, type_row: &TypeRow, custom_fields: &[FilterableCustomField]) -> Value {
+        let mut schema = Map::new();
+        schema.insert("_type".into(), json!("Schema"));
+        schema.insert("_dependencies".into(), json!([]));
+        for (key, type_name, name, required, writable) in ATTRIBUTES {
+            schema.insert(api_attribute_name(key), field(type_name, name, *required, *writable));
+        }
+        for custom_field in custom_fields {
+            schema.insert(
+                format!("customField{}", custom_field.id),
+                field(schema_type(&custom_field.field_format), &custom_field.name, false, true),
+            );
+        }
+
+        let custom_field_ids: Vec<i64> = custom_fields.iter().map(|custom_field| custom_field.id).collect();
+        let configuration = type_row
+            .form_configuration()
+            .unwrap_or_else(|| FormConfiguration::default_for(&custom_field_ids))
+            .without_unknown(&custom_field_ids);
+        schema.insert("_attributeGroups".into(), attribute_groups(&configuration));
+
+        schema.insert(
+            "_links".into(),
+            json!({
+                "self": { "href": schema_href(project_id, type_row.id) },
+                "project": { "href": format!("/api/v3/projects/{}", project_id) },
+                "type": { "href": format!("/api/v3/types/{}", type_row.id), "title": type_row.name },
+            }),
+        );
+        Value::Object(schema)
+    }
+}
+
+/// Href of the schema of work packages of the type in the project
+pub fn schema_href(project_id: i64, type_id: i64) -> String {
+    format!("{}/{}-{}", SCHEMAS_PATH, project_id, type_id)
+}
+
+/// Form groups with API attribute names
+fn attribute_groups(configuration: &FormConfiguration) -> Value {
+    let names = |keys: &[String]| -> Vec<String> { keys.iter().map(|key| api_attribute_name(key)).collect() };
+    let groups: Vec<Value> = configuration
+        .groups
+        .iter()
+        .map(|group| match group {
+            FormGroup::AttributeGroup { name, attributes } => json!({
+                "_type": "WorkPackageFormAttributeGroup",
+                "name": name,
+                "attributes": names(attributes),
+            }),
+            FormGroup::QueryGroup { name, query } => {
+                let sort_by: Vec<[String; 2]> = query
+                    .sort_by
+                    .iter()
+                    .map(|[attribute, direction]| [api_attribute_name(attribute), direction.clone()])
+                    .collect();
+                json!({
+                    "_type": "WorkPackageFormQueryGroup",
+                    "name": name,
+                    "query": {
+                        "columns": names(&query.columns),
+                        "filters": query.filters,
+                        "sortBy": sort_by,
+                    },
+                })
+            }
+        })
+        .collect();
+    Value::Array(groups)
+}
+
+fn field(type_name: &str, name: &str, required: bool, writable: bool) -> Value {
+    json!({
+        "type": type_name,
+        "name": name,
+        "required": required,
+        "hasDefault": false,
+        "writable": writable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn type_row(form_configuration: Option<Value>) -> TypeRow {
+        TypeRow {
+            id: 2,
+            name: "Bug".into(),
+            position: 1,
+            is_default: false,
+            is_in_roadmap: true,
+            is_milestone: false,
+            is_standard: false,
+            color_id: None,
+            description: None,
+            form_configuration,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn severity() -> FilterableCustomField {
+        FilterableCustomField { id: 3, name: "Severity".into(), field_format: "list".into() }
+    }
+
+    #[test]
+    fn test_attribute_groups_follow_the_form_configuration() {
+        let configuration = json!([
+            { "_type": "WorkPackageFormAttributeGroup", "name": "Triage", "attributes": ["cf_3", "priority", "cf_9"] },
+            { "_type": "WorkPackageFormAttributeGroup", "name": "Removed", "attributes": ["cf_9"] },
+            {
+                "_type": "WorkPackageFormQueryGroup",
+                "name": "Children",
+                "query": { "columns": ["subject", "estimated_time"], "sortBy": [["start_date", "asc"]] },
+            },
+        ]);
+
+        let schema = WorkPackageSchemaRepresenter::represent(1, &type_row(Some(configuration)), &[severity()]);
+
+        assert_eq!(
+            schema["_attributeGroups"],
+            json!([
+                { "_type": "WorkPackageFormAttributeGroup", "name": "Triage", "attributes": ["customField3", "priority"] },
+                {
+                    "_type": "WorkPackageFormQueryGroup",
+                    "name": "Children",
+                    "query": { "columns": ["subject", "estimatedTime"], "filters": [], "sortBy": [["startDate", "asc"]] },
+                },
+            ])
+        );
+        assert_eq!(schema["customField3"]["type"], "CustomOption");
+        assert_eq!(schema["_links"]["self"]["href"], "/api/v3/work_packages/schemas/1-2");
+    }
+
+    #[test]
+    fn test_unconfigured_types_use_the_default_layout() {
+        let schema = WorkPackageSchemaRepresenter::represent(1, &type_row(None), &[severity()]);
+        let groups = schema["_attributeGroups"].as_array().unwrap();
+
+        assert_eq!(groups[0]["attributes"], json!(["assignee", "responsible"]));
+        assert_eq!(groups.last().unwrap()["attributes"], json!(["customField3"]));
+        // Every attribute of a group is described by the schema
+        for group in groups {
+            for attribute in group["attributes"].as_array().unwrap() {
+                assert!(schema[attribute.as_str().unwrap()].is_object(), "{}", attribute);
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Request ID middleware
+//!
+//! Accepts an incoming `X-Request-ID` header (or generates a UUID), exposes it
+//! to handlers as a request extension, tags the request's tracing span with it,
+//! makes it available to error bodies and enqueued jobs through
+//! `op_core::request_id`, and echoes it in the response.
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use op_core::request_id::{self, REQUEST_ID_HEADER};
+use tracing::Instrument;
+
+use crate::error::ApiError;
+
+/// ID of the current request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Assign a request ID and run the rest of the stack within its scope
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(request_id::sanitize)
+        .unwrap_or_else(request_id::generate);
+
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        uri = %request.uri().path(),
+    );
+
+    let mut response = request_id::scope(id.clone(), next.run(request).instrument(span)).await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestId
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<RequestId>()
+            .cloned()
+            .ok_or_else(|| ApiError::internal("Request ID middleware not installed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::State, http::StatusCode, middleware, routing::get, Router};
+    use op_notifications::{Job, JobQueue, MemoryJobQueue};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn enqueue_handler(
+        State(queue): State<Arc<MemoryJobQueue>>,
+        request_id: RequestId,
+    ) -> String {
+        queue
+            .enqueue(Job::new("test_job", serde_json::json!({})))
+            .await
+            .unwrap();
+        request_id.0
+    }
+
+    async fn failing_handler() -> Result<(), ApiError> {
+        Err(ApiError::not_found("WorkPackage", 1))
+    }
+
+    fn app(queue: Arc<MemoryJobQueue>) -> Router {
+        Router::new()
+            .route("/enqueue", get(enqueue_handler))
+            .route("/fail", get(failing_handler))
+            .with_state(queue)
+            .layer(middleware::from_fn(request_id_middleware))
+    }
+
+    async fn body_string(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_incoming_header_round_trips() {
+        let response = app(Arc::new(MemoryJobQueue::new()))
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/enqueue")
+                    .header("X-Request-ID", "support-1234")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "support-1234");
+        assert_eq!(body_string(response).await, "support-1234");
+    }
+
+    #[tokio::test]
+    async fn test_generates_id_when_missing() {
+        let response = app(Arc::new(MemoryJobQueue::new()))
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/enqueue")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let header = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        assert_eq!(header.len(), 36);
+        assert_eq!(body_string(response).await, header);
+    }
+
+    #[tokio::test]
+    async fn test_enqueued_job_carries_request_id() {
+        let queue = Arc::new(MemoryJobQueue::new());
+
+        app(queue.clone())
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/enqueue")
+                    .header("X-Request-ID", "job-corr-1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let job = queue.dequeue("default").await.unwrap().unwrap();
+        assert_eq!(job.request_id(), Some("job-corr-1"));
+        assert_eq!(job.metadata["request_id"], "job-corr-1");
+    }
+
+    #[tokio::test]
+    async fn test_error_body_includes_request_id() {
+        let response = app(Arc::new(MemoryJobQueue::new()))
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/fail")
+                    .header("X-Request-ID", "err-77")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+        assert_eq!(body["requestId"], "err-77");
+    }
+}
@@ -2,26 +2,69 @@
 //!
 //! Mirrors: config/routes.rb API v3 section
 
+use std::sync::Arc;
+
 use axum::{
-    routing::{delete, get, patch, post},
-    Router,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, patch, post, put},
+    Extension, Router,
 };
+use op_core::config::FeatureFlags;
 use serde::Serialize;
 
+use crate::capabilities::{self, Capability, CapabilityRegistry};
 use crate::extractors::AppState;
-use crate::handlers::{activities, attachments, categories, journals, memberships, priorities, projects, queries, relations, roles, statuses, time_entries, types, users, versions, watchers, work_packages};
+use crate::idempotency::idempotent;
+use crate::locale::locale_middleware;
+use crate::openapi;
+use crate::query_budget::{query_budget_middleware, QueryBudget};
+use crate::request_id::request_id_middleware;
+use crate::url_root::{url_root_middleware, UrlRoot};
+use crate::version::version_header_middleware;
+use crate::handlers::{activities, api_keys, attachments, audit_events, categories, custom_actions, documents, file_links, forums, inbound_emails, integrity, job_statuses, journals, maintenance, memberships, notification_settings, notifications, principals, priorities, projects, queries, relations, reports, roles, shares, statuses, storages, time_entries, types, uploads, users, versions, watchers, work_packages};
 
-/// Create the complete API router
+/// Create the complete API router with the default feature flags
 pub fn router() -> Router<AppState> {
-    Router::new().nest("/api/v3", api_v3_router())
+    router_with_features(&FeatureFlags::default())
 }
 
-fn api_v3_router() -> Router<AppState> {
+/// Create the API router, mounting only the route groups of enabled features
+pub fn router_with_features(features: &FeatureFlags) -> Router<AppState> {
     Router::new()
+        .nest("/api/v3", api_v3_router(features))
+        .layer(middleware::from_fn_with_state(QueryBudget::default(), query_budget_middleware))
+        .layer(middleware::from_fn(locale_middleware))
+        .layer(middleware::from_fn(version_header_middleware))
+        .layer(middleware::from_fn(request_id_middleware))
+}
+
+/// Create the API router nested under a relative URL root such as
+/// `/openproject`, rendering links under it
+pub fn router_with_root(features: &FeatureFlags, root: &str) -> Router<AppState> {
+    let root = UrlRoot::new(root);
+    if root.is_empty() {
+        return router_with_features(features);
+    }
+    let router = router_with_features(features).layer(middleware::from_fn_with_state(root.clone(), url_root_middleware));
+    Router::new().nest(root.as_str(), router)
+}
+
+/// Mount the API v3 route groups and register the capability of each
+fn api_v3_router(features: &FeatureFlags) -> Router<AppState> {
+    // Replies may carry attachments beyond the default body limit
+    let email_body_limit = DefaultBodyLimit::max(inbound_emails::INBOUND_EMAIL_BODY_LIMIT);
+    let mut registry = CapabilityRegistry::new();
+
+    let mut router = Router::new()
         .route("/", get(api_root))
+        .route("/spec.json", get(openapi::spec_json))
         .nest("/work_packages", work_packages_router())
-        .nest("/projects", projects_router())
+        .nest("/projects", projects_router(features))
         .nest("/users", users_router())
+        .route("/principals", get(principals::list_principals))
+        .route("/groups/:id", get(principals::get_group))
+        .route("/placeholder_users/:id", get(principals::get_placeholder_user))
         .nest("/queries", queries_router())
         .nest("/statuses", statuses_router())
         .nest("/types", types_router())
@@ -30,19 +73,111 @@ fn api_v3_router() -> Router<AppState> {
         .nest("/versions", versions_router())
         .nest("/memberships", memberships_router())
         .nest("/categories", categories_router())
-        .nest("/time_entries", time_entries_router())
+        .nest("/custom_actions", custom_actions_router())
+        .nest("/forums", forums_router())
+        .nest("/messages", messages_router())
         .nest("/relations", relations_router())
         .nest("/attachments", attachments_router())
+        .nest("/uploads", uploads_router())
+        .nest("/storages", storages_router())
+        .route("/file_links/:id", get(file_links::get_file_link))
+        .route("/file_links/:id", delete(file_links::delete_file_link))
         .nest("/activities", journals_router())
+        .nest("/audit_events", audit_events_router())
+        .route("/maintenance", get(maintenance::get_maintenance))
+        .route("/maintenance", post(maintenance::enable_maintenance))
+        .route("/maintenance", delete(maintenance::disable_maintenance))
+        .route("/integrity", get(integrity::get_integrity_report))
+        .route("/integrity/repair", post(integrity::repair_integrity))
+        .route("/job_statuses/:id", get(job_statuses::get_job_status))
+        .nest("/notifications", notifications_router())
+        .route("/inbound_emails", post(inbound_emails::receive_inbound_email).layer(email_body_limit));
+    for capability in [
+        Capability::new("api.openapi"),
+        // The collection is not filtered yet; saved queries keep their filters
+        Capability::new("work_packages.crud").with_metadata("filters", false),
+        Capability::new("work_packages.copy"),
+        Capability::new("work_packages.trash"),
+        Capability::new("work_packages.watchers"),
+        Capability::new("work_packages.shares"),
+        Capability::new("work_packages.file_links"),
+        Capability::new("work_packages.relations"),
+        Capability::new("work_packages.activities"),
+        Capability::new("work_packages.schemas"),
+        Capability::new("work_packages.summaries"),
+        Capability::new("work_packages.similar"),
+        Capability::new("projects.reports").with_metadata("granularities", vec!["day", "week", "month", "quarter"]),
+        Capability::new("projects.crud"),
+        Capability::new("projects.templates"),
+        Capability::new("users.crud"),
+        // Timestamps are stored with a query but results are not compared
+        Capability::new("queries.crud").with_metadata("baselineTimestamps", false),
+        Capability::new("queries.export").with_metadata("format", "document"),
+        Capability::new("queries.subscriptions"),
+        Capability::new("statuses.crud"),
+        Capability::new("types.crud"),
+        Capability::new("types.form_configuration"),
+        Capability::new("priorities.crud"),
+        Capability::new("roles.crud"),
+        Capability::new("versions.crud"),
+        Capability::new("memberships.crud"),
+        Capability::new("categories.crud"),
+        Capability::new("custom_actions.crud"),
+        Capability::new("forums.crud"),
+        Capability::new("attachments.crud"),
+        Capability::new("attachments.uploads").with_metadata("digestAlgorithms", vec!["sha256"]),
+        Capability::new("storages.crud"),
+        Capability::new("activities.journals"),
+        Capability::new("audit_events.read"),
+        Capability::new("maintenance.toggle"),
+        Capability::new("integrity.audit"),
+        Capability::new("jobs.status"),
+        Capability::new("notifications.in_app"),
+        Capability::new("notifications.stream"),
+        Capability::new("notifications.email_replies"),
+        Capability::new("notifications.settings"),
+    ] {
+        registry.register(capability);
+    }
+
+    if features.costs_enabled {
+        router = router.nest("/time_entries", time_entries_router());
+        registry.register(Capability::new("time_entries.crud"));
+        registry.register(Capability::new("time_entries.activities"));
+        registry.register(Capability::new("time_entries.aggregate"));
+    }
+
+    if features.documents_enabled {
+        router = router
+            .nest("/documents", documents_router())
+            .route("/document_categories", get(documents::list_document_categories));
+        registry.register(Capability::new("documents.crud"));
+    }
+
+    #[cfg(feature = "swagger-ui")]
+    {
+        router = router.route("/docs", get(openapi::swagger_ui));
+        registry.register(Capability::new("api.swagger_ui"));
+    }
+
+    // Registered last so the registry lists every group mounted above
+    router.route("/capabilities", get(capabilities::list_capabilities).layer(Extension(Arc::new(registry))))
 }
 
 fn work_packages_router() -> Router<AppState> {
     Router::new()
         .route("/", get(work_packages::list_work_packages))
-        .route("/", post(work_packages::create_work_package))
+        .route("/", post(idempotent(work_packages::create_work_package)))
+        .route("/form", post(work_packages::work_package_create_form))
+        .route("/bulk", patch(work_packages::bulk_update_work_packages))
         .route("/:id", get(work_packages::get_work_package))
         .route("/:id", patch(work_packages::update_work_package))
         .route("/:id", delete(work_packages::delete_work_package))
+        .route("/:id/form", post(work_packages::work_package_update_form))
+        .route("/:id/copy", post(work_packages::copy_work_package))
+        .route("/:id/restore", post(work_packages::restore_work_package))
+        .route("/:id/custom_actions/:action_id/execute", post(custom_actions::execute_custom_action))
+        .route("/schemas/:id", get(work_packages::get_work_package_schema))
         // Relations
         .route("/:id/relations", get(relations::list_work_package_relations))
         // Watchers
@@ -52,25 +187,52 @@ fn work_packages_router() -> Router<AppState> {
         .route("/:id/watching", get(watchers::is_watching_work_package))
         .route("/:id/watch", post(watchers::watch_work_package))
         .route("/:id/watch", delete(watchers::unwatch_work_package))
+        .route("/:id/shares", get(shares::list_work_package_shares))
+        .route("/:id/shares", post(shares::create_work_package_share))
+        .route("/:id/shares/:share_id", patch(shares::update_work_package_share))
+        .route("/:id/shares/:share_id", delete(shares::delete_work_package_share))
+        // File links
+        .route("/:id/file_links", get(file_links::list_work_package_file_links))
+        .route("/:id/file_links", post(file_links::create_work_package_file_links))
         // Attachments
         .route("/:id/attachments", get(attachments::list_work_package_attachments))
         // Activities (journals)
         .route("/:id/activities", get(journals::list_work_package_activities))
+        .route("/:id/activities", post(journals::create_work_package_comment))
         .route("/:id/revisions", get(journals::list_work_package_revisions))
 }
 
-fn projects_router() -> Router<AppState> {
-    Router::new()
+fn projects_router(features: &FeatureFlags) -> Router<AppState> {
+    let router = Router::new()
         .route("/", get(projects::list_projects))
-        .route("/", post(projects::create_project))
+        .route("/", post(idempotent(projects::create_project)))
+        .route("/from_template", post(projects::instantiate_template))
+        .route("/schema", get(projects::get_project_schema))
         .route("/:id", get(projects::get_project))
         .route("/:id", patch(projects::update_project))
         .route("/:id", delete(projects::delete_project))
         .route("/:id/archive", post(projects::archive_project))
         .route("/:id/unarchive", post(projects::unarchive_project))
+        .route("/:id/storage", get(projects::get_project_storage))
         .route("/:id/types", get(types::list_project_types))
         .route("/:id/versions", get(versions::list_project_versions))
         .route("/:id/categories", get(categories::list_project_categories))
+        .route("/:id/forums", get(forums::list_project_forums))
+        .route("/:id/forums", post(forums::create_project_forum))
+        .route("/:id/work_packages", get(work_packages::list_project_work_packages))
+        .route("/:id/work_packages/similar", get(work_packages::list_similar_work_packages))
+        .route("/:id/work_package_summary", get(projects::get_work_package_summary))
+        .route("/:id/timeline", get(projects::get_timeline))
+        .route("/:id/reports/cumulative_flow", get(reports::get_cumulative_flow))
+        .route("/:id/available_assignees", get(principals::list_available_assignees));
+
+    if features.documents_enabled {
+        router
+            .route("/:id/documents", get(documents::list_project_documents))
+            .route("/:id/documents", post(documents::create_project_document))
+    } else {
+        router
+    }
 }
 
 fn users_router() -> Router<AppState> {
@@ -78,9 +240,14 @@ fn users_router() -> Router<AppState> {
         .route("/", get(users::list_users))
         .route("/", post(users::create_user))
         .route("/me", get(users::get_me))
+        .route("/me/api_keys", get(api_keys::list_my_api_keys))
+        .route("/me/api_keys", post(api_keys::create_my_api_key))
+        .route("/me/api_keys/:id", delete(api_keys::revoke_my_api_key))
         .route("/:id", get(users::get_user))
         .route("/:id", patch(users::update_user))
         .route("/:id", delete(users::delete_user))
+        .route("/:id/notification_settings", get(notification_settings::get_notification_settings))
+        .route("/:id/notification_settings", patch(notification_settings::update_notification_settings))
         .route("/:id/lock", post(users::lock_user))
         .route("/:id/lock", delete(users::unlock_user))
 }
@@ -101,6 +268,8 @@ fn types_router() -> Router<AppState> {
         .route("/:id", get(types::get_type))
         .route("/:id", patch(types::update_type))
         .route("/:id", delete(types::delete_type))
+        .route("/:id/form_configuration", get(types::get_form_configuration))
+        .route("/:id/form_configuration", patch(types::update_form_configuration))
 }
 
 fn priorities_router() -> Router<AppState> {
@@ -148,6 +317,41 @@ fn categories_router() -> Router<AppState> {
         .route("/:id", delete(categories::delete_category))
 }
 
+fn custom_actions_router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(custom_actions::list_custom_actions))
+        .route("/", post(custom_actions::create_custom_action))
+        .route("/:id", get(custom_actions::get_custom_action))
+        .route("/:id", patch(custom_actions::update_custom_action))
+        .route("/:id", delete(custom_actions::delete_custom_action))
+}
+
+fn forums_router() -> Router<AppState> {
+    Router::new()
+        .route("/:id", get(forums::get_forum))
+        .route("/:id", patch(forums::update_forum))
+        .route("/:id/topics", get(forums::list_forum_topics))
+        .route("/:id/topics", post(forums::create_forum_topic))
+}
+
+fn messages_router() -> Router<AppState> {
+    Router::new()
+        .route("/:id", get(forums::get_message))
+        .route("/:id", patch(forums::update_message))
+        .route("/:id", delete(forums::delete_message))
+        .route("/:id/replies", get(forums::list_message_replies))
+        .route("/:id/replies", post(forums::create_message_reply))
+        .route("/:id/versions", get(forums::list_message_versions))
+}
+
+fn documents_router() -> Router<AppState> {
+    Router::new()
+        .route("/:id", get(documents::get_document))
+        .route("/:id", patch(documents::update_document))
+        .route("/:id", delete(documents::delete_document))
+        .route("/:id/attachments", get(documents::list_document_attachments))
+}
+
 fn queries_router() -> Router<AppState> {
     Router::new()
         .route("/", get(queries::list_queries))
@@ -155,17 +359,24 @@ fn queries_router() -> Router<AppState> {
         .route("/default", get(queries::get_default_query))
         .route("/form", get(queries::query_form))
         .route("/available_projects", get(queries::available_projects))
+        .route("/import", post(queries::import_query))
+        .route("/filter_instance_schemas", get(queries::list_filter_instance_schemas))
+        .route("/filter_instance_schemas/:id", get(queries::get_filter_instance_schema))
         .route("/:id", get(queries::get_query))
         .route("/:id", patch(queries::update_query))
         .route("/:id", delete(queries::delete_query))
+        .route("/:id/export", get(queries::export_query))
         .route("/:id/star", post(queries::star_query))
         .route("/:id/star", delete(queries::unstar_query))
+        .route("/:id/subscription", post(queries::subscribe_query))
+        .route("/:id/subscription", delete(queries::unsubscribe_query))
 }
 
 fn time_entries_router() -> Router<AppState> {
     Router::new()
         .route("/", get(time_entries::list_time_entries))
-        .route("/", post(time_entries::create_time_entry))
+        .route("/", post(idempotent(time_entries::create_time_entry)))
+        .route("/aggregate", get(time_entries::aggregate_time_entries))
         .route("/:id", get(time_entries::get_time_entry))
         .route("/:id", patch(time_entries::update_time_entry))
         .route("/:id", delete(time_entries::delete_time_entry))
@@ -193,12 +404,34 @@ fn relations_router() -> Router<AppState> {
 fn attachments_router() -> Router<AppState> {
     Router::new()
         .route("/", get(attachments::list_attachments))
-        .route("/", post(attachments::create_attachment))
+        .route("/", post(idempotent(attachments::create_attachment)))
         .route("/:id", get(attachments::get_attachment))
+        .route("/:id/content", get(attachments::download_attachment))
         .route("/:id", patch(attachments::update_attachment))
         .route("/:id", delete(attachments::delete_attachment))
 }
 
+fn uploads_router() -> Router<AppState> {
+    // Chunks of very large files may each exceed the default body limit
+    let chunk_body_limit = DefaultBodyLimit::max(uploads::CHUNK_BODY_LIMIT);
+
+    Router::new()
+        .route("/", post(uploads::create_upload))
+        .route("/:id", get(uploads::get_upload))
+        .route("/:id/chunks/:number", put(uploads::upload_chunk).layer(chunk_body_limit))
+        .route("/:id/complete", post(uploads::complete_upload))
+}
+
+fn storages_router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(storages::list_storages))
+        .route("/", post(storages::create_storage))
+        .route("/:id", get(storages::get_storage))
+        .route("/:id", patch(storages::update_storage))
+        .route("/:id", delete(storages::delete_storage))
+        .route("/:id/oauth_client_credentials", post(storages::set_oauth_client_credentials))
+}
+
 fn journals_router() -> Router<AppState> {
     Router::new()
         .route("/", get(journals::list_activities))
@@ -206,6 +439,18 @@ fn journals_router() -> Router<AppState> {
         .route("/:id", patch(journals::update_activity))
 }
 
+fn audit_events_router() -> Router<AppState> {
+    Router::new().route("/", get(audit_events::list_audit_events))
+}
+
+fn notifications_router() -> Router<AppState> {
+    Router::new()
+        .route("/stream", get(notifications::stream_notifications))
+        .route("/groups", get(notifications::list_notification_groups))
+        .route("/groups/:resource_type/:resource_id", get(notifications::get_notification_group))
+        .route("/groups/:resource_type/:resource_id/read_ian", post(notifications::read_notification_group))
+}
+
 async fn api_root() -> axum::Json<ApiRoot> {
     axum::Json(ApiRoot {
         type_name: "Root".into(),
@@ -222,3 +467,1256 @@ struct ApiRoot {
     instance_name: String,
     core_version: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    async fn send(method: &str, uri: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        send_with_state(AppState::default(), method, uri, body).await
+    }
+
+    async fn send_with_state(
+        state: AppState,
+        method: &str,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = router()
+            .with_state(state)
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("authorization", "Bearer test")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_work_package_patch_rejects_datetime_due_date() {
+        let (status, body) = send(
+            "PATCH",
+            "/api/v3/work_packages/1",
+            serde_json::json!({ "dueDate": "2024-01-01T10:00", "lockVersion": 0 }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("dueDate "));
+    }
+
+    #[tokio::test]
+    async fn test_work_package_patch_accepts_iso_durations() {
+        for estimated_time in ["PT1H30M", "P1DT4H"] {
+            let (status, _) = send(
+                "PATCH",
+                "/api/v3/work_packages/1",
+                serde_json::json!({ "estimatedTime": estimated_time, "dueDate": "2024-01-01" }),
+            )
+            .await;
+
+            // Parsing succeeded; the request only fails for lack of a database
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        let (status, body) = send(
+            "PATCH",
+            "/api/v3/work_packages/1",
+            serde_json::json!({ "estimatedTime": "P1Y" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("estimatedTime "));
+    }
+
+    #[tokio::test]
+    async fn test_time_entry_parses_hours_and_spent_on() {
+        let (status, _) = send(
+            "POST",
+            "/api/v3/time_entries",
+            serde_json::json!({ "projectId": 1, "activityId": 1, "hours": "PT1H30M", "spentOn": "2024-01-01" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let (status, body) = send(
+            "POST",
+            "/api/v3/time_entries",
+            serde_json::json!({ "projectId": 1, "activityId": 1, "hours": "PT1H", "spentOn": "2024-01-01T10:00" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("spentOn "));
+    }
+
+    #[tokio::test]
+    async fn test_query_rejects_invalid_timestamps() {
+        let (status, body) = send(
+            "POST",
+            "/api/v3/queries",
+            serde_json::json!({ "name": "Baseline", "timestamps": "sometimeAgo,PT0S" }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("timestamps "));
+    }
+
+    #[tokio::test]
+    async fn test_query_subscription_rejects_unknown_frequency() {
+        let (status, body) = send(
+            "POST",
+            "/api/v3/queries/1/subscription",
+            serde_json::json!({ "frequency": "monthly" }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("frequency "));
+    }
+
+    #[tokio::test]
+    async fn test_query_import_validates_document() {
+        let (status, body) = send(
+            "POST",
+            "/api/v3/queries/import",
+            serde_json::json!({ "projectId": 1, "document": { "schemaVersion": 99, "name": "Shared" } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("schemaVersion "));
+
+        let (status, _) = send(
+            "POST",
+            "/api/v3/queries/import",
+            serde_json::json!({ "projectId": 1, "document": { "schemaVersion": 1, "name": "Shared" } }),
+        )
+        .await;
+        // The document is valid; the request only fails for lack of a database
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_project_list_rejects_invalid_templated_filter() {
+        let filters = r#"[{"templated":{"operator":"=","values":["maybe"]}}]"#;
+        let (status, body) = send(
+            "GET",
+            &format!("/api/v3/projects?filters={}", urlencode(filters)),
+            serde_json::Value::Null,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("templated "));
+    }
+
+    #[tokio::test]
+    async fn test_project_list_rejects_unsupported_custom_field_operator() {
+        let filters = r#"[{"customField3":{"operator":"~","values":["Build"]}}]"#;
+        let (status, body) = send(
+            "GET",
+            &format!("/api/v3/projects?filters={}", urlencode(filters)),
+            serde_json::Value::Null,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().starts_with("customField3 "));
+    }
+
+    #[tokio::test]
+    async fn test_project_from_template_requires_admin() {
+        let (status, _) = send(
+            "POST",
+            "/api/v3/projects/from_template",
+            serde_json::json!({ "templateId": 1, "name": "New", "identifier": "new-project" }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_project_storage_requires_admin() {
+        let (status, _) = send("GET", "/api/v3/projects/1/storage", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_work_package_summary_rejects_unknown_group() {
+        let (status, body) =
+            send("GET", "/api/v3/projects/1/work_package_summary?groupBy=status,author", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.to_string().contains("groupBy"));
+
+        let (status, _) =
+            send("GET", "/api/v3/projects/1/work_package_summary?groupBy=status,type", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_work_packages_reject_invalid_grouping() {
+        for uri in [
+            "/api/v3/work_packages?groupBy=due_date:fortnight",
+            "/api/v3/work_packages?groupBy=status:month",
+            "/api/v3/projects/1/work_packages?groupBy=unknown",
+        ] {
+            let (status, body) = send("GET", uri, serde_json::Value::Null).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", uri);
+            assert!(body.to_string().contains("groupBy"), "{}", uri);
+        }
+
+        let (status, _) = send(
+            "POST",
+            "/api/v3/queries",
+            serde_json::json!({ "name": "By period", "groupBy": "subject:week" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_cumulative_flow_rejects_unknown_granularity() {
+        let (status, body) = send(
+            "GET",
+            "/api/v3/projects/1/reports/cumulative_flow?granularity=year",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.to_string().contains("granularity"));
+    }
+
+    #[tokio::test]
+    async fn test_share_rejects_unknown_role() {
+        let (status, body) = send(
+            "POST",
+            "/api/v3/work_packages/1/shares",
+            serde_json::json!({ "userId": 2, "role": "owner" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.to_string().contains("role"));
+    }
+
+    #[tokio::test]
+    async fn test_storage_create_requires_admin() {
+        let (status, _) = send(
+            "POST",
+            "/api/v3/storages",
+            serde_json::json!({ "name": "Cloud", "_links": { "origin": { "href": "https://cloud.example.com" } } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_forum_routes_are_mounted() {
+        let topic = serde_json::json!({ "subject": "Release planning", "content": { "raw": "What goes into 2.0?" } });
+        for (method, uri, body) in [
+            ("GET", "/api/v3/projects/1/forums", serde_json::Value::Null),
+            ("POST", "/api/v3/forums/1/topics", topic.clone()),
+            ("POST", "/api/v3/messages/1/replies", topic),
+            ("GET", "/api/v3/messages/1/versions", serde_json::Value::Null),
+        ] {
+            // Routed and parsed; the request only fails for lack of a database
+            let (status, _) = send(method, uri, body).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{} {}", method, uri);
+        }
+
+        let body = capabilities(router()).await;
+        assert!(body["capabilities"]["forums.crud"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_document_routes_follow_the_feature_flag() {
+        let document = serde_json::json!({
+            "title": "Handbook",
+            "_links": { "category": { "href": "/api/v3/document_categories/1" } }
+        });
+        for (method, uri, body) in [
+            ("GET", "/api/v3/projects/1/documents?sortBy=date", serde_json::Value::Null),
+            ("POST", "/api/v3/projects/1/documents", document),
+            ("DELETE", "/api/v3/documents/1", serde_json::Value::Null),
+            ("GET", "/api/v3/documents/1/attachments", serde_json::Value::Null),
+            ("GET", "/api/v3/document_categories", serde_json::Value::Null),
+        ] {
+            // Routed and parsed; the request only fails for lack of a database
+            let (status, _) = send(method, uri, body).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{} {}", method, uri);
+        }
+        let body = capabilities(router()).await;
+        assert!(body["capabilities"]["documents.crud"].is_object());
+
+        let features = FeatureFlags {
+            documents_enabled: false,
+            ..FeatureFlags::default()
+        };
+        let body = capabilities(router_with_features(&features)).await;
+        assert!(body["capabilities"].get("documents.crud").is_none());
+        let response = get_raw(router_with_features(&features), "/api/v3/projects/1/documents").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_filter_instance_schemas() {
+        // Built-in filters need no database
+        let (status, body) = send("GET", "/api/v3/queries/filter_instance_schemas/memberOfGroup", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["_type"], "QueryFilterInstanceSchema");
+        assert_eq!(
+            body["_dependencies"][0]["dependencies"]["/api/v3/queries/operators/="]["values"]["type"],
+            "[]Group"
+        );
+
+        // Custom fields are looked up
+        let (status, _) = send("GET", "/api/v3/queries/filter_instance_schemas/customField9", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_file_links_reject_foreign_storage_href() {
+        let (status, body) = send(
+            "POST",
+            "/api/v3/work_packages/1/file_links",
+            serde_json::json!({ "_embedded": { "elements": [{
+                "originData": { "id": "5503", "name": "logo.png" },
+                "_links": { "storage": { "href": "/api/v3/projects/1" } }
+            }] } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.to_string().contains("storage"));
+    }
+
+    #[tokio::test]
+    async fn test_user_delete_requires_admin() {
+        let (status, _) = send("DELETE", "/api/v3/users/2", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_integrity_audit_requires_admin() {
+        let (status, _) = send("GET", "/api/v3/integrity", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send("POST", "/api/v3/integrity/repair", serde_json::json!({ "dryRun": false })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_user_email_is_validated() {
+        let (status, body) = send("PATCH", "/api/v3/users/1", serde_json::json!({ "email": "me@localhost" })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.to_string().contains("email"));
+    }
+
+    #[tokio::test]
+    async fn test_principal_type_filter_is_validated() {
+        let (status, body) = send("GET", "/api/v3/principals?type=User,Admin", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().contains("Admin"));
+
+        let uri = "/api/v3/projects/1/available_assignees?type=Robot";
+        let (status, _) = send("GET", uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Known types only fail for lack of a database
+        let (status, _) = send("GET", "/api/v3/principals?type=Group,PlaceholderUser", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_job_status_is_polled_by_owner() {
+        use op_notifications::{JobQueue, MemoryJobQueue};
+        use op_services::projects::{CopyProjectParams, InstantiateTemplateArgs};
+        use std::sync::Arc;
+
+        let queue = Arc::new(MemoryJobQueue::new());
+        let args = InstantiateTemplateArgs {
+            template_id: 1,
+            params: CopyProjectParams::new("New", "new-project"),
+        };
+        let own = queue.enqueue(args.clone().into_job(1)).await.unwrap();
+        let other = queue.enqueue(args.into_job(2)).await.unwrap();
+        let state = AppState::default().with_jobs(queue);
+
+        let uri = format!("/api/v3/job_statuses/{}", own);
+        let (status, body) = send_with_state(state.clone(), "GET", &uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["_type"], "JobStatus");
+        assert_eq!(body["status"], "in_queue");
+        assert_eq!(body["_links"]["self"]["href"], uri);
+
+        let uri = format!("/api/v3/job_statuses/{}", other);
+        let (status, _) = send_with_state(state, "GET", &uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_create_is_validated_without_saving() {
+        use op_core::metrics::DomainMetrics;
+        use std::sync::Arc;
+        use std::sync::atomic::Ordering;
+
+        let metrics = Arc::new(DomainMetrics::default());
+        let state = AppState::default().with_metrics(metrics.clone());
+        let payload = serde_json::json!({
+            "subject": "Preview",
+            "_links": {
+                "project": { "href": "/api/v3/projects/1" },
+                "type": { "href": "/api/v3/types/1" },
+                "status": { "href": "/api/v3/statuses/1" },
+                "priority": { "href": "/api/v3/priorities/1" }
+            }
+        });
+
+        // The mock user may not add work packages, which the contract
+        // reports before any database is needed
+        let (status, body) = send_with_state(state.clone(), "POST", "/api/v3/work_packages?dryRun=true", payload.clone()).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["_type"], "Error");
+        assert_eq!(metrics.work_packages_created.load(Ordering::Relaxed), 0);
+
+        let (status, _) = send_with_state(state, "POST", "/api/v3/work_packages?dryRun=maybe", payload).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_similar_work_packages_require_a_subject() {
+        let uri = "/api/v3/projects/1/work_packages/similar?subject=%20";
+        let (status, body) = send("GET", uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["_type"], "Error");
+    }
+
+    #[tokio::test]
+    async fn test_notification_groups_are_listed_and_read() {
+        use op_notifications::{
+            MemoryNotificationStore, Notification, NotificationReason, NotificationStore, NotificationType,
+        };
+        use std::sync::Arc;
+
+        let store = Arc::new(MemoryNotificationStore::new());
+        for reason in [NotificationReason::Watched, NotificationReason::Mentioned] {
+            let mut notification = Notification::work_package(1, NotificationType::WorkPackageUpdated, reason, 42);
+            store.create(&mut notification).await.unwrap();
+        }
+        let mut other = Notification::work_package(2, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 42);
+        store.create(&mut other).await.unwrap();
+        let state = AppState::default().with_notifications(store.clone());
+
+        let (status, body) = send_with_state(state.clone(), "GET", "/api/v3/notifications/groups", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["_embedded"]["elements"][0]["count"], 2);
+
+        let uri = "/api/v3/notifications/groups/WorkPackage/42";
+        let (status, body) = send_with_state(state.clone(), "GET", uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["_embedded"]["details"]["count"], 2);
+
+        let (status, body) = send_with_state(state.clone(), "POST", &format!("{}/read_ian", uri), serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["count"], 2);
+        assert_eq!(store.unread_count(1).await.unwrap(), 0);
+        assert_eq!(store.unread_count(2).await.unwrap(), 1);
+
+        let (_, body) = send_with_state(state.clone(), "GET", "/api/v3/notifications/groups?unreadOnly=true", serde_json::Value::Null).await;
+        assert_eq!(body["total"], 0);
+
+        let uri = "/api/v3/notifications/groups?embed=actor,author";
+        let (status, _) = send_with_state(state, "GET", uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    async fn open_stream(state: AppState, last_event_id: Option<u64>) -> axum::body::BodyDataStream {
+        let mut request = Request::builder()
+            .uri("/api/v3/notifications/stream")
+            .header("authorization", "Bearer test");
+        if let Some(id) = last_event_id {
+            request = request.header("last-event-id", id.to_string());
+        }
+        let response = router()
+            .with_state(state)
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        response.into_body().into_data_stream()
+    }
+
+    /// Read the next SSE frame, returning its id and JSON payload
+    async fn next_frame(stream: &mut axum::body::BodyDataStream) -> (u64, serde_json::Value) {
+        use futures::StreamExt;
+
+        let mut frame = String::new();
+        while !frame.ends_with("\n\n") {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(1), stream.next())
+                .await
+                .expect("no event within a second")
+                .unwrap()
+                .unwrap();
+            frame.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+
+        assert!(frame.contains("event: notification\n"), "{}", frame);
+        let field = |name: &str| {
+            frame
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .unwrap()
+                .to_string()
+        };
+        (field("id: ").parse().unwrap(), serde_json::from_str(&field("data: ")).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_notification_settings_round_trip() {
+        use op_notifications::{EmailFrequency, MemoryNotificationStore, NotificationStore};
+        use std::sync::Arc;
+
+        let store = Arc::new(MemoryNotificationStore::new());
+        let state = AppState::default().with_notifications(store.clone());
+        let uri = "/api/v3/users/1/notification_settings";
+
+        let (status, body) = send_with_state(state.clone(), "GET", uri, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["_type"], "NotificationSettings");
+        assert_eq!(body["inApp"]["watched"], true);
+        assert_eq!(body["emailFrequency"], "immediate");
+        assert_eq!(body["projectOverrides"], serde_json::json!([]));
+
+        let changes = serde_json::json!({
+            "inApp": { "responsible": true, "watched": false },
+            "email": { "involved": false },
+            "emailFrequency": "weekly",
+            "dateAlerts": { "dueDate": "P3D", "overdue": "P1D" }
+        });
+        let (status, body) = send_with_state(state.clone(), "PATCH", uri, changes).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["inApp"]["responsible"], true);
+        assert_eq!(body["dateAlerts"]["dueDate"], "P3D");
+
+        let (_, body) = send_with_state(state.clone(), "GET", uri, serde_json::Value::Null).await;
+        assert_eq!(body["inApp"]["watched"], false);
+        assert_eq!(body["email"]["involved"], false);
+        assert_eq!(body["dateAlerts"]["overdue"], "P1D");
+        let stored = store.get_settings(1).await.unwrap();
+        assert_eq!(stored.email_frequency, EmailFrequency::Weekly);
+        assert_eq!(stored.date_alerts.start_date, Some(1));
+
+        // Another user's settings
+        let (status, _) = send_with_state(state, "GET", "/api/v3/users/2/notification_settings", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_notification_settings_change_nothing() {
+        use op_notifications::{MemoryNotificationStore, NotificationStore};
+        use std::sync::Arc;
+
+        let store = Arc::new(MemoryNotificationStore::new());
+        let state = AppState::default().with_notifications(store.clone());
+        let uri = "/api/v3/users/1/notification_settings";
+
+        let changes = serde_json::json!({
+            "inApp": { "responsible": true, "bogus": true },
+            "dateAlerts": { "startDate": "P2D" },
+            "projectOverrides": [{ "_links": { "project": { "href": "/api/v3/users/5" } } }]
+        });
+        let (status, body) = send_with_state(state.clone(), "PATCH", uri, changes).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let attributes: Vec<&str> = body["_embedded"]["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["_embedded"]["details"]["attribute"].as_str().unwrap())
+            .collect();
+        assert_eq!(attributes, vec!["dateAlerts", "inApp", "projectOverrides"]);
+        assert_eq!(body["_embedded"]["errors"][1]["message"], "inApp has no reason 'bogus'.");
+
+        // The valid part of the request was not applied
+        assert!(!store.get_settings(1).await.unwrap().in_app_reasons.responsible);
+
+        let (status, body) = send_with_state(state, "PATCH", uri, serde_json::json!({ "emailFrequency": "hourly" })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["_embedded"]["details"]["attribute"], "emailFrequency");
+    }
+
+    #[tokio::test]
+    async fn test_notification_stream_with_two_clients() {
+        use op_notifications::email::{EmailAddress, MemoryEmailSender};
+        use op_notifications::{
+            EmailRenderer, MemoryJobQueue, MemoryNotificationStore, NotificationReason, NotificationService,
+            NotificationStreams, NotificationType,
+        };
+        use std::sync::Arc;
+
+        let store = Arc::new(MemoryNotificationStore::new());
+        let streams = Arc::new(NotificationStreams::new());
+        let service = NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(MemoryEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        )
+        .with_streams(streams.clone());
+        let state = AppState::default()
+            .with_notifications(store.clone())
+            .with_notification_streams(streams.clone());
+
+        // Both clients start with the unread count
+        let mut first = open_stream(state.clone(), None).await;
+        let mut second = open_stream(state.clone(), None).await;
+        for client in [&mut first, &mut second] {
+            let (_, data) = next_frame(client).await;
+            assert_eq!((data["type"].as_str(), data["unreadCount"].as_u64()), (Some("count"), Some(0)));
+        }
+        assert_eq!(streams.subscriber_count(1), 2);
+
+        let event = service
+            .notify(1, NotificationType::WorkPackageAssigned, NotificationReason::Assigned, "WorkPackage", 42, Some(2), None)
+            .await
+            .unwrap();
+        let mut created_id = 0;
+        for client in [&mut first, &mut second] {
+            let (id, data) = next_frame(client).await;
+            assert_eq!(data["type"], "created");
+            assert_eq!(data["unreadCount"], 1);
+            assert_eq!(data["notificationIds"][0], event.notification.id.unwrap());
+            created_id = id;
+        }
+
+        // The second client disconnects and misses the read event
+        drop(second);
+        assert_eq!(streams.subscriber_count(1), 1);
+        let (status, _) = send_with_state(
+            state.clone(),
+            "POST",
+            "/api/v3/notifications/groups/WorkPackage/42/read_ian",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (read_id, data) = next_frame(&mut first).await;
+        assert_eq!((data["type"].as_str(), data["unreadCount"].as_u64()), (Some("read"), Some(0)));
+
+        // Reconnecting replays it
+        let mut second = open_stream(state.clone(), Some(created_id)).await;
+        let (id, data) = next_frame(&mut second).await;
+        assert_eq!(id, read_id);
+        assert_eq!(data["type"], "read");
+
+        // Every event since the last one seen is replayed in order
+        let mut third = open_stream(state, Some(created_id - 1)).await;
+        assert_eq!(next_frame(&mut third).await.0, created_id);
+        assert_eq!(next_frame(&mut third).await.0, read_id);
+    }
+
+    #[tokio::test]
+    async fn test_spec_is_served_without_authentication() {
+        let response = router()
+            .with_state(AppState::default())
+            .oneshot(Request::builder().uri(openapi::SPEC_PATH).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let spec: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(spec["paths"]["/api/v3/work_packages/{id}"]["patch"].is_object());
+    }
+
+    async fn get_raw(router: Router<AppState>, uri: &str) -> axum::response::Response {
+        router
+            .with_state(AppState::default())
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn capabilities(router: Router<AppState>) -> serde_json::Value {
+        let response = get_raw(router, "/api/v3/capabilities").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_follow_feature_flags() {
+        let body = capabilities(router()).await;
+        assert_eq!(body["_type"], "Capabilities");
+        assert!(body["capabilities"]["time_entries.crud"].is_object());
+        assert_eq!(body["capabilities"]["queries.crud"]["baselineTimestamps"], false);
+        let response = get_raw(router(), "/api/v3/time_entries").await;
+        assert_ne!(response.status(), StatusCode::NOT_FOUND);
+
+        let features = FeatureFlags {
+            costs_enabled: false,
+            ..FeatureFlags::default()
+        };
+        let body = capabilities(router_with_features(&features)).await;
+        assert!(body["capabilities"].get("time_entries.crud").is_none());
+        assert!(body["capabilities"]["work_packages.crud"].is_object());
+        let response = get_raw(router_with_features(&features), "/api/v3/time_entries").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_responses_carry_version_header() {
+        for uri in ["/api/v3", "/api/v3/no_such_resource"] {
+            let response = get_raw(router(), uri).await;
+            assert_eq!(response.headers()[crate::version::OP_RS_VERSION_HEADER], crate::version::OP_RS_VERSION);
+        }
+    }
+
+    async fn post_email(state: AppState, uri: &str, content_type: &str, body: &str) -> StatusCode {
+        router()
+            .with_state(state)
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header("content-type", content_type)
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    fn inbound_email_state() -> AppState {
+        let config = crate::extractors::AppConfig {
+            inbound_email_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        AppState {
+            config: std::sync::Arc::new(config),
+            ..AppState::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inbound_email_requires_token() {
+        let raw = "From: jane@example.com\nTo: wp+1@example.com\n\nDone";
+        let uri = "/api/v3/inbound_emails";
+
+        // Disabled unless a token is configured
+        let status = post_email(AppState::default(), uri, "message/rfc822", raw).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let status = post_email(inbound_email_state(), uri, "message/rfc822", raw).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let status = post_email(inbound_email_state(), "/api/v3/inbound_emails?token=wrong", "message/rfc822", raw).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Accepted; without a database the comment cannot be stored
+        let status = post_email(inbound_email_state(), "/api/v3/inbound_emails?token=s3cret", "message/rfc822", raw).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_inbound_email_form_without_message() {
+        let body = "--x\r\nContent-Disposition: form-data; name=\"subject\"\r\n\r\nRe: Bug\r\n--x--\r\n";
+        let status = post_email(
+            inbound_email_state(),
+            "/api/v3/inbound_emails?token=s3cret",
+            "multipart/form-data; boundary=x",
+            body,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    fn urlencode(s: &str) -> String {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+
+    async fn send_with_api_key(
+        state: AppState,
+        method: &str,
+        uri: &str,
+        api_key: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = router()
+            .with_state(state)
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("x-openproject-api-key", api_key)
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_read_only_api_key_cannot_patch() {
+        use op_auth::{ApiKeyScope, ApiKeyService};
+
+        let state = AppState::default();
+        let read_only = ApiKeyService::new()
+            .create(state.api_keys.as_ref(), 1, None, Some(vec![ApiKeyScope::ReadOnly]))
+            .await
+            .unwrap();
+        let patch = serde_json::json!({ "dueDate": "2024-01-01T10:00", "lockVersion": 0 });
+
+        let (status, body) =
+            send_with_api_key(state.clone(), "PATCH", "/api/v3/work_packages/1", &read_only.secret, patch.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["errorIdentifier"], "urn:openproject-org:api:v3:errors:InsufficientScope");
+
+        // Reading is allowed
+        let uri = "/api/v3/users/me/api_keys";
+        let (status, body) = send_with_api_key(state.clone(), "GET", uri, &read_only.secret, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["_embedded"][0]["scopes"], serde_json::json!(["read_only"]));
+        assert!(body["_embedded"][0]["lastUsedAt"].is_string());
+
+        // A key with the scope gets past the check to the validation of the body
+        let writer = ApiKeyService::new()
+            .create(state.api_keys.as_ref(), 1, None, Some(vec![ApiKeyScope::WorkPackagesWrite]))
+            .await
+            .unwrap();
+        let (status, _) = send_with_api_key(state.clone(), "PATCH", "/api/v3/work_packages/1", &writer.secret, patch).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, _) = send_with_api_key(state, "GET", uri, "unknown-key", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_api_key_management() {
+        let state = AppState::default();
+        let uri = "/api/v3/users/me/api_keys";
+
+        let request = serde_json::json!({ "name": "CI", "scopes": ["read_only", "time_entries:write"] });
+        let (status, created) = send_with_state(state.clone(), "POST", uri, request).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let secret = created["secret"].as_str().unwrap();
+        assert_eq!(created["displayValue"], format!("****{}", &secret[secret.len() - 4..]));
+        assert_eq!(created["scopes"], serde_json::json!(["read_only", "time_entries:write"]));
+
+        let (status, unscoped) = send_with_state(state.clone(), "POST", uri, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(unscoped["scopes"].is_null());
+
+        let (status, _) = send_with_state(state.clone(), "POST", uri, serde_json::json!({ "scopes": ["root"] })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // The secret is not shown again
+        let (_, body) = send_with_state(state.clone(), "GET", uri, serde_json::Value::Null).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["_embedded"][0]["name"], "CI");
+        assert!(body["_embedded"][0].get("secret").is_none());
+        assert!(!body.to_string().contains(secret));
+
+        // A key cannot create keys without the admin scope
+        let (status, _) = send_with_api_key(state.clone(), "POST", uri, secret, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Keys without scopes have the full power of their user
+        let full = unscoped["secret"].as_str().unwrap();
+        let key_uri = format!("{}/{}", uri, created["id"]);
+        let (status, _) = send_with_api_key(state.clone(), "DELETE", &key_uri, full, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send_with_api_key(state.clone(), "DELETE", &key_uri, full, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send_with_api_key(state, "GET", uri, secret, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_blocks_writes_but_not_reads() {
+        use crate::maintenance::{maintenance_middleware, MaintenanceState};
+
+        let state = AppState::default();
+        state
+            .maintenance
+            .set(MaintenanceState::enabled(Some("Back at noon.".into()), None))
+            .await
+            .unwrap();
+        let app = router()
+            .layer(middleware::from_fn_with_state(state.maintenance.clone(), maintenance_middleware))
+            .with_state(state);
+        let request = |method: &str, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("authorization", "Bearer test")
+                .header("content-type", "application/json")
+                .body(Body::from("{}"))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request("PATCH", "/api/v3/work_packages/1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "300");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(body["message"].as_str().unwrap().ends_with("Back at noon."));
+
+        let response = app.clone().oneshot(request("GET", "/api/v3")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The toggle is reached, and only administrators may use it
+        let response = app.oneshot(request("DELETE", "/api/v3/maintenance")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_form_configuration_and_schema_routes() {
+        let groups = serde_json::json!({ "attributeGroups": [
+            { "_type": "WorkPackageFormAttributeGroup", "name": "People", "attributes": ["assignee"] },
+        ] });
+        let (status, _) = send("PATCH", "/api/v3/types/1/form_configuration", groups).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send("GET", "/api/v3/types/1/form_configuration", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Schemas are routed apart from work packages; without a database
+        // only the id is checked
+        let (status, _) = send("GET", "/api/v3/work_packages/schemas/1-2", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let (status, _) = send("GET", "/api/v3/work_packages/schemas/bug", serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_projects_sort_by_latest_activity() {
+        // sortBy=[["latestActivityAt","desc"]] is accepted, other criteria are not
+        let (status, _) = send(
+            "GET",
+            "/api/v3/projects?sortBy=%5B%5B%22latestActivityAt%22%2C%22desc%22%5D%5D",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let (status, body) = send(
+            "GET",
+            "/api/v3/projects?sortBy=%5B%5B%22name%22%2C%22asc%22%5D%5D",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().contains("'name'"));
+    }
+
+    /// Status and body of a request without credentials
+    async fn send_anonymous(state: AppState, method: &str, uri: &str) -> (StatusCode, serde_json::Value) {
+        let response = router()
+            .with_state(state)
+            .oneshot(Request::builder().method(method).uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_reads_unless_login_is_required() {
+        for uri in ["/api/v3/projects", "/api/v3/work_packages/1", "/api/v3/users/me"] {
+            let (status, _) = send_anonymous(AppState::default(), "GET", uri).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+
+        let public = AppState {
+            config: std::sync::Arc::new(crate::extractors::AppConfig {
+                require_authentication: false,
+                ..Default::default()
+            }),
+            ..AppState::default()
+        };
+        let (status, body) = send_anonymous(public.clone(), "GET", "/api/v3/users/me").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body["id"].as_str(), body["name"].as_str()), (Some("anonymous"), Some("Anonymous")));
+
+        // Reads of public projects are served; only the database is missing
+        for uri in ["/api/v3/projects", "/api/v3/projects/1/work_packages", "/api/v3/work_packages/1"] {
+            let (status, _) = send_anonymous(public.clone(), "GET", uri).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", uri);
+        }
+
+        // Writes and private data still need a real user
+        for (method, uri) in [
+            ("POST", "/api/v3/projects"),
+            ("PATCH", "/api/v3/work_packages/1"),
+            ("GET", "/api/v3/notifications/groups"),
+            ("GET", "/api/v3/users/me/api_keys"),
+            ("GET", "/api/v3/users/1"),
+        ] {
+            let (status, _) = send_anonymous(public.clone(), method, uri).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors_follow_accept_language() {
+        let public = AppState {
+            config: std::sync::Arc::new(crate::extractors::AppConfig {
+                require_authentication: false,
+                ..Default::default()
+            }),
+            ..AppState::default()
+        };
+        let response = router()
+            .with_state(public.clone())
+            .oneshot(
+                Request::builder()
+                    .method("PATCH")
+                    .uri("/api/v3/users/1/notification_settings")
+                    .header("authorization", "Bearer test")
+                    .header("content-type", "application/json")
+                    .header("accept-language", "de-DE,de;q=0.9,en;q=0.8")
+                    .body(Body::from(serde_json::json!({ "emailFrequency": "hourly" }).to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.headers()["content-language"], "de");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["message"], "E-Mail-Häufigkeit muss immediate, daily, weekly oder never sein.");
+        assert_eq!(body["errorIdentifier"], "urn:openproject-org:api:v3:errors:PropertyConstraintViolation");
+        assert_eq!(body["_embedded"]["details"]["attribute"], "emailFrequency");
+
+        // Display names of system users are translated as well
+        let response = router()
+            .with_state(public)
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v3/users/me")
+                    .header("accept-language", "fr")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!((body["_type"].as_str(), body["name"].as_str()), (Some("User"), Some("Anonyme")));
+    }
+
+    /// API key of an administrator stored in the embedded state
+    async fn embedded_admin_key(state: &AppState) -> String {
+        use op_auth::ApiKeyService;
+        use op_db::CreateUserDto;
+
+        let admin = state
+            .entity_stores()
+            .unwrap()
+            .users
+            .create(CreateUserDto {
+                login: "admin".into(),
+                firstname: "Ada".into(),
+                lastname: "Admin".into(),
+                mail: "admin@example.com".into(),
+                admin: true,
+                status: 1,
+                language: None,
+                hashed_password: None,
+                salt: None,
+            })
+            .await
+            .unwrap();
+        ApiKeyService::new()
+            .create(state.api_keys.as_ref(), admin.id, None, None)
+            .await
+            .unwrap()
+            .secret
+    }
+
+    #[tokio::test]
+    async fn test_embedded_mode_serves_crud_without_a_database() {
+        let state = AppState::embedded();
+        let key = embedded_admin_key(&state).await;
+        let request = serde_json::json!({ "name": "Embedded" });
+        let (status, project) = send_with_api_key(state.clone(), "POST", "/api/v3/projects", &key, request).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(project["identifier"], "embedded");
+        let project_id = project["id"].as_i64().unwrap();
+
+        for subject in ["Write the harness", "Ship the release"] {
+            let request = serde_json::json!({ "subject": subject, "projectId": project_id });
+            let (status, _) = send_with_api_key(state.clone(), "POST", "/api/v3/work_packages", &key, request).await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+        let uri = "/api/v3/work_packages/1";
+        let (_, work_package) = send_with_api_key(state.clone(), "GET", uri, &key, serde_json::Value::Null).await;
+        assert_eq!(work_package["subject"], "Write the harness");
+
+        let comment = serde_json::json!({ "comment": { "raw": "Runs without Postgres" } });
+        let activities_uri = "/api/v3/work_packages/1/activities";
+        let (status, activity) = send_with_api_key(state.clone(), "POST", activities_uri, &key, comment).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(activity["comment"]["raw"], "Runs without Postgres");
+        let (_, activities) =
+            send_with_api_key(state.clone(), "GET", activities_uri, &key, serde_json::Value::Null).await;
+        assert_eq!(activities["total"], 1);
+
+        let filtered = format!(
+            "/api/v3/projects/{}/work_packages?filters=%5B%7B%22subject%22%3A%7B%22operator%22%3A%22~%22%2C%22values%22%3A%5B%22harness%22%5D%7D%7D%5D",
+            project_id
+        );
+        let (status, listed) = send_with_api_key(state.clone(), "GET", &filtered, &key, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(listed["total"], 1);
+        assert_eq!(listed["_embedded"][0]["subject"], "Write the harness");
+
+        let (status, _) = send_with_api_key(state.clone(), "DELETE", uri, &key, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send_with_api_key(state, "GET", uri, &key, serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_create_form_lists_defaulted_attributes() {
+        let state = AppState::embedded();
+        let key = embedded_admin_key(&state).await;
+        let request = serde_json::json!({ "subject": "Defaults", "_links": { "version": { "href": null } } });
+
+        // Without a database nothing is configured to default to
+        let uri = "/api/v3/work_packages/form";
+        let (status, form) = send_with_api_key(state.clone(), "POST", uri, &key, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(form["_embedded"]["defaultedAttributes"], serde_json::json!([]));
+        assert_eq!(form["versionId"], serde_json::Value::Null);
+
+        // Creates are validated like their forms
+        let request = serde_json::json!({ "subject": " " });
+        let (status, body) = send_with_api_key(state.clone(), "POST", "/api/v3/work_packages", &key, request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["_embedded"]["details"]["attribute"], "subject");
+        assert_eq!(state.entity_stores().unwrap().work_packages.count().await.unwrap(), 0);
+    }
+
+    /// Records the created work packages, and whether they were stored by
+    /// the time their events were published
+    struct CreatedWorkPackages {
+        work_packages: Arc<dyn op_db::WorkPackageStore>,
+        created: std::sync::Mutex<Vec<(op_core::traits::Id, bool)>>,
+    }
+
+    #[axum::async_trait]
+    impl op_core::events::EventSubscriber for CreatedWorkPackages {
+        fn name(&self) -> &str {
+            "created_work_packages"
+        }
+
+        async fn handle(&self, event: &op_core::events::DomainEvent) -> op_core::events::EventResult<()> {
+            if let op_core::events::DomainEvent::WorkPackageCreated { work_package_id, .. } = event {
+                let stored = self.work_packages.find_by_id(*work_package_id).await.unwrap().is_some();
+                self.created.lock().unwrap().push((*work_package_id, stored));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_publishes_one_event_after_the_work_package_is_stored() {
+        let state = AppState::embedded();
+        let subscriber = Arc::new(CreatedWorkPackages {
+            work_packages: state.entity_stores().unwrap().work_packages,
+            created: Default::default(),
+        });
+        let state = state.with_event_subscriber(subscriber.clone());
+        let key = embedded_admin_key(&state).await;
+        let request = serde_json::json!({ "subject": "Published" });
+
+        // Neither forms nor rejected creates publish
+        let uri = "/api/v3/work_packages/form";
+        let (status, _) = send_with_api_key(state.clone(), "POST", uri, &key, request.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        let blank = serde_json::json!({ "subject": " " });
+        let (status, _) = send_with_api_key(state.clone(), "POST", "/api/v3/work_packages", &key, blank).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(subscriber.created.lock().unwrap().is_empty());
+
+        let (status, created) = send_with_api_key(state.clone(), "POST", "/api/v3/work_packages", &key, request).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(*subscriber.created.lock().unwrap(), vec![(created["id"].as_i64().unwrap(), true)]);
+    }
+
+    #[tokio::test]
+    async fn test_work_package_associations_are_set_through_links() {
+        let state = AppState::embedded();
+        let key = embedded_admin_key(&state).await;
+        let links = serde_json::json!({
+            "assignee": { "href": "/api/v3/users/4" },
+            "version": { "href": "/api/v3/versions/2" },
+        });
+        let request = serde_json::json!({ "subject": "Linked", "assignedToId": 3, "_links": links });
+
+        // The form accepts the same shape, without saving
+        let uri = "/api/v3/work_packages/form";
+        let (status, form) = send_with_api_key(state.clone(), "POST", uri, &key, request.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(form["assignedToId"], 4);
+        let work_packages = state.entity_stores().unwrap().work_packages;
+        assert_eq!(work_packages.count().await.unwrap(), 0);
+
+        let (status, created) = send_with_api_key(state.clone(), "POST", "/api/v3/work_packages", &key, request).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["id"], 1);
+        assert_eq!(work_packages.find_by_id(1).await.unwrap().unwrap().version_id, Some(2));
+
+        let patch = serde_json::json!({
+            "lockVersion": 0,
+            "_links": { "assignee": { "href": "/api/v3/users/5" }, "version": { "href": null } },
+        });
+        let uri = "/api/v3/work_packages/1";
+        let (status, updated) = send_with_api_key(state.clone(), "PATCH", uri, &key, patch).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["assignedToId"], 5);
+        let row = work_packages.find_by_id(1).await.unwrap().unwrap();
+        assert_eq!((row.assigned_to_id, row.version_id), (Some(5), None));
+
+        // Associations left out are kept
+        let patch = serde_json::json!({ "lockVersion": 1, "subject": "Renamed" });
+        let (status, updated) = send_with_api_key(state.clone(), "PATCH", uri, &key, patch).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["assignedToId"], 5);
+
+        let patch = serde_json::json!({ "lockVersion": 2, "_links": { "status": { "href": "/api/v3/types/1" } } });
+        let (status, body) = send_with_api_key(state, "PATCH", uri, &key, patch).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errorIdentifier"], op_core::representations::error_identifier::PROPERTY_CONSTRAINT_VIOLATION);
+        assert_eq!(body["_embedded"]["details"]["attribute"], "status");
+    }
+}
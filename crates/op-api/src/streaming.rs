@@ -0,0 +1,342 @@
+//! Streamed Collections
+//!
+//! Large pages of a collection are not collected and serialized as a
+//! whole: a [`StreamingCollection`] writes the opening of the HAL envelope,
+//! then each element as its stream produces it, then closes the envelope
+//! with the `count` of written elements. Memory stays flat with the size of
+//! the page, at the price of a `total` that must be known, or estimated,
+//! before the first element is written.
+//!
+//! Once the response has started, an error can no longer change its
+//! status: the body is cut off instead, so clients fail to parse it rather
+//! than taking a partial page for a complete one.
+
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use op_core::representations::{CollectionGroup, HalLinks};
+use serde::Serialize;
+
+use crate::error::{ApiError, ApiResult};
+use crate::url_root::{HrefsPrefixed, UrlRoot};
+
+/// Page sizes above which collections are streamed
+pub const STREAMING_PAGE_SIZE_THRESHOLD: usize = 500;
+
+/// Elements loaded at once for a streamed page
+pub const STREAMING_CHUNK_SIZE: usize = 200;
+
+/// Serialized elements buffered before they are sent as one chunk
+const FLUSH_BYTES: usize = 16 * 1024;
+
+/// Envelope of a streamed collection, written before its elements
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionEnvelope {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub total: usize,
+    pub page_size: usize,
+    pub offset: usize,
+    /// Set when `total` is an estimate of a large result
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub total_is_estimate: bool,
+    /// Groups of the matches of a grouped collection
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<CollectionGroup>,
+    #[serde(rename = "_links", skip_serializing_if = "HalLinks::is_empty")]
+    pub links: HalLinks,
+}
+
+impl CollectionEnvelope {
+    pub fn new(total: usize, offset: usize, page_size: usize) -> Self {
+        Self {
+            type_name: "Collection".into(),
+            total,
+            page_size,
+            offset,
+            total_is_estimate: false,
+            groups: Vec::new(),
+            links: HalLinks::new(),
+        }
+    }
+
+    /// Add the groups of a grouped collection
+    pub fn with_groups(mut self, groups: Vec<CollectionGroup>) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    /// Mark the total as an estimate
+    pub fn with_estimated_total(mut self, total_is_estimate: bool) -> Self {
+        self.total_is_estimate = total_is_estimate;
+        self
+    }
+
+    /// Add pagination links
+    pub fn with_links(mut self, links: HalLinks) -> Self {
+        self.links = links;
+        self
+    }
+}
+
+/// A collection whose elements are serialized into the response body as
+/// they are produced, in the shape of a [`Collection`]
+///
+/// [`Collection`]: op_core::representations::Collection
+pub struct StreamingCollection {
+    envelope: CollectionEnvelope,
+    elements: BoxStream<'static, ApiResult<serde_json::Value>>,
+    root: Option<UrlRoot>,
+}
+
+impl StreamingCollection {
+    pub fn new<S, T>(envelope: CollectionEnvelope, elements: S) -> Self
+    where
+        S: Stream<Item = ApiResult<T>> + Send + 'static,
+        T: Serialize,
+    {
+        let elements = elements
+            .map(|element| {
+                element.and_then(|element| {
+                    serde_json::to_value(element)
+                        .map_err(|e| ApiError::internal(format!("Failed to serialize: {}", e)))
+                })
+            })
+            .boxed();
+        Self { envelope, elements, root: None }
+    }
+
+    /// Prefix the `href`s of the envelope and the elements with the URL
+    /// root, which the URL root middleware cannot do without buffering the
+    /// whole body
+    pub fn under_root(mut self, root: Option<UrlRoot>) -> Self {
+        self.root = root.filter(|root| !root.is_empty());
+        self
+    }
+
+    /// The serialized document, in chunks of about [`FLUSH_BYTES`]
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Send {
+        let Self { envelope, elements, root } = self;
+        let head = Self::head(&envelope, root.as_ref());
+
+        let state = Writer {
+            elements: Some(elements),
+            buffer: head,
+            count: 0,
+            root,
+        };
+        stream::unfold(state, |mut writer| async move {
+            let mut elements = writer.elements.take()?;
+            while writer.buffer.len() < FLUSH_BYTES {
+                match elements.next().await {
+                    Some(Ok(element)) => {
+                        if let Err(e) = writer.write(element) {
+                            return Some((Err(e), writer));
+                        }
+                    }
+                    Some(Err(e)) => {
+                        tracing::error!(error = ?e, "Streaming the collection failed, cutting off the response");
+                        return Some((Err(std::io::Error::other("streaming the collection failed")), writer));
+                    }
+                    None => {
+                        writer.buffer.extend_from_slice(format!("],\"count\":{}}}", writer.count).as_bytes());
+                        return Some((Ok(Bytes::from(std::mem::take(&mut writer.buffer))), writer));
+                    }
+                }
+            }
+            writer.elements = Some(elements);
+            let chunk = std::mem::replace(&mut writer.buffer, Vec::with_capacity(FLUSH_BYTES));
+            Some((Ok(Bytes::from(chunk)), writer))
+        })
+    }
+
+    /// The envelope up to the opening of the elements' array
+    fn head(envelope: &CollectionEnvelope, root: Option<&UrlRoot>) -> Vec<u8> {
+        let mut head = serde_json::to_value(envelope).unwrap_or_default();
+        if let Some(root) = root {
+            root.prefix_hrefs(&mut head);
+        }
+        let mut buffer = serde_json::to_vec(&head).unwrap_or_else(|_| b"{}".to_vec());
+        buffer.pop();
+        if buffer.len() > 1 {
+            buffer.push(b',');
+        }
+        buffer.extend_from_slice(b"\"_embedded\":[");
+        buffer
+    }
+}
+
+/// State of a collection being written
+struct Writer {
+    /// `None` once the envelope is closed
+    elements: Option<BoxStream<'static, ApiResult<serde_json::Value>>>,
+    buffer: Vec<u8>,
+    count: usize,
+    root: Option<UrlRoot>,
+}
+
+impl Writer {
+    fn write(&mut self, mut element: serde_json::Value) -> Result<(), std::io::Error> {
+        if let Some(root) = &self.root {
+            root.prefix_hrefs(&mut element);
+        }
+        if self.count > 0 {
+            self.buffer.push(b',');
+        }
+        serde_json::to_writer(&mut self.buffer, &element)?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+impl IntoResponse for StreamingCollection {
+    fn into_response(self) -> Response {
+        let prefixed = self.root.is_some();
+        let mut response = Response::builder()
+            .status(200)
+            .header(header::CONTENT_TYPE, "application/hal+json; charset=utf-8")
+            .body(Body::from_stream(self.into_stream()))
+            .unwrap();
+        if prefixed {
+            response.extensions_mut().insert(HrefsPrefixed);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    use op_core::representations::HalLink;
+
+    /// Allocator keeping track of the bytes allocated by each thread
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+        static PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    fn track(delta: isize) {
+        // Threads being torn down no longer count
+        let _ = ALLOCATED.try_with(|allocated| {
+            allocated.set(allocated.get() + delta);
+            let _ = PEAK.try_with(|peak| peak.set(peak.get().max(allocated.get())));
+        });
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            track(layout.size() as isize);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            track(-(layout.size() as isize));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Restart tracking the peak from the bytes allocated on this thread
+    /// now, which are returned
+    fn reset_peak() -> isize {
+        let allocated = ALLOCATED.with(Cell::get);
+        PEAK.with(|peak| peak.set(allocated));
+        allocated
+    }
+
+    #[derive(Serialize)]
+    struct Row {
+        id: usize,
+        subject: String,
+        #[serde(rename = "_links")]
+        links: HalLinks,
+    }
+
+    fn rows(count: usize) -> impl Stream<Item = ApiResult<Row>> + Send {
+        stream::iter(0..count).map(|id| {
+            Ok(Row {
+                id,
+                subject: format!("Synthetic work package {:>64}", id),
+                links: HalLinks::new().with("self", HalLink::new(format!("/api/v3/work_packages/{}", id))),
+            })
+        })
+    }
+
+    async fn collect(collection: StreamingCollection) -> Result<Vec<u8>, std::io::Error> {
+        let mut stream = std::pin::pin!(collection.into_stream());
+        let mut body = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(body)
+    }
+
+    #[tokio::test]
+    async fn test_streamed_collection_has_the_shape_of_a_collection() {
+        let envelope = CollectionEnvelope::new(10, 0, 1000)
+            .with_estimated_total(true)
+            .with_links(HalLinks::new().with("self", HalLink::new("/api/v3/work_packages?offset=0")));
+        let body = collect(StreamingCollection::new(envelope, rows(3))).await.unwrap();
+
+        let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(document["_type"], "Collection");
+        assert_eq!((document["total"].as_u64(), document["count"].as_u64()), (Some(10), Some(3)));
+        assert_eq!(document["totalIsEstimate"], true);
+        assert_eq!(document["_embedded"][2]["id"], 2);
+
+        let empty = collect(StreamingCollection::new(CollectionEnvelope::new(0, 0, 1000), rows(0)))
+            .await
+            .unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&empty).unwrap();
+        assert_eq!(document["count"], 0);
+        assert_eq!(document["_embedded"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_hrefs_are_under_the_root() {
+        let envelope = CollectionEnvelope::new(1, 0, 1000)
+            .with_links(HalLinks::new().with("self", HalLink::new("/api/v3/work_packages")));
+        let collection = StreamingCollection::new(envelope, rows(1)).under_root(Some(UrlRoot::new("/openproject")));
+        let body = collect(collection).await.unwrap();
+
+        let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(document["_links"]["self"]["href"], "/openproject/api/v3/work_packages");
+        assert_eq!(document["_embedded"][0]["_links"]["self"]["href"], "/openproject/api/v3/work_packages/0");
+    }
+
+    #[tokio::test]
+    async fn test_failing_elements_cut_off_the_body() {
+        let elements = rows(2).chain(stream::once(async { Err(ApiError::internal("connection lost")) }));
+        let result = collect(StreamingCollection::new(CollectionEnvelope::new(3, 0, 1000), elements)).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_stays_flat_with_the_number_of_elements() {
+        let baseline = reset_peak();
+        let collection = StreamingCollection::new(CollectionEnvelope::new(10_000, 0, 10_000), rows(10_000));
+        let mut stream = std::pin::pin!(collection.into_stream());
+        let (mut written, mut chunks) = (0, 0);
+        while let Some(chunk) = stream.next().await {
+            written += chunk.unwrap().len();
+            chunks += 1;
+        }
+        let peak = PEAK.with(Cell::get) - baseline;
+
+        assert!(written > 1_000_000, "wrote {} bytes", written);
+        assert!(chunks > 50, "wrote {} chunks", chunks);
+        // A few chunks in flight, far from the megabytes of the document
+        assert!(peak < 8 * FLUSH_BYTES as isize, "peak of {} bytes", peak);
+    }
+}
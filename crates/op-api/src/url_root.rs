@@ -0,0 +1,230 @@
+//! Relative URL Root
+//!
+//! Deployments behind a reverse proxy may serve OpenProject under a path
+//! such as `/openproject` (`RAILS_RELATIVE_URL_ROOT`). The router is then
+//! nested under that root, while handlers and representers keep working
+//! with root-relative paths: [`url_root_middleware`] hands them the request
+//! URI without the root and prefixes the root to the `href`s of JSON
+//! responses and to `Location` headers, once, on the way out. Handlers
+//! streaming their response find the root in the request's extensions and
+//! prefix the `href`s themselves.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Request, State},
+    http::{header, HeaderValue, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use op_core::config::normalize_url_root;
+use serde_json::Value;
+
+use crate::error::ApiError;
+
+/// Path the API is served under, e.g. `/openproject`; empty at the root of
+/// the host
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrlRoot(Arc<str>);
+
+impl UrlRoot {
+    pub fn new(root: &str) -> Self {
+        Self(normalize_url_root(root).into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The href under the root if it is a path on this host
+    pub fn prefix(&self, href: &str) -> Option<String> {
+        (href.starts_with('/') && !href.starts_with("//")).then(|| format!("{}{}", self.0, href))
+    }
+
+    /// The URI without the root, if it is under it
+    pub fn strip(&self, uri: &Uri) -> Option<Uri> {
+        let rest = uri.path().strip_prefix(self.as_str())?;
+        if !(rest.is_empty() || rest.starts_with('/')) {
+            return None;
+        }
+        let path = if rest.is_empty() { "/" } else { rest };
+        let path_and_query = match uri.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        };
+        path_and_query.parse().ok()
+    }
+
+    /// Prefix every `href` in the document that is a path on this host
+    pub fn prefix_hrefs(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    match value {
+                        Value::String(href) if key == "href" => {
+                            if let Some(prefixed) = self.prefix(href) {
+                                *href = prefixed;
+                            }
+                        }
+                        value => self.prefix_hrefs(value),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.prefix_hrefs(item)),
+            _ => {}
+        }
+    }
+
+    /// Prefix the `Location` header and, in JSON bodies, the `href`s of the
+    /// response
+    async fn prefix_response(&self, response: Response) -> Response {
+        let (mut parts, body) = response.into_parts();
+
+        let location = parts
+            .headers
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|location| self.prefix(location))
+            .and_then(|location| HeaderValue::from_str(&location).ok());
+        if let Some(location) = location {
+            parts.headers.insert(header::LOCATION, location);
+        }
+
+        let is_json = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|content_type| content_type.contains("json"));
+        if !is_json || parts.extensions.get::<HrefsPrefixed>().is_some() {
+            return Response::from_parts(parts, body);
+        }
+
+        let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(bytes) => bytes,
+            Err(e) => return ApiError::internal(format!("Failed to read the response: {}", e)).into_response(),
+        };
+        let body = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(mut document) => {
+                self.prefix_hrefs(&mut document);
+                parts.headers.remove(header::CONTENT_LENGTH);
+                Body::from(serde_json::to_vec(&document).unwrap_or_else(|_| bytes.to_vec()))
+            }
+            Err(_) => Body::from(bytes),
+        };
+        Response::from_parts(parts, body)
+    }
+}
+
+/// Marks responses whose body already carries the root in its `href`s,
+/// e.g. a [`StreamingCollection`](crate::streaming::StreamingCollection)
+/// that is not buffered to prefix them
+#[derive(Debug, Clone, Copy)]
+pub struct HrefsPrefixed;
+
+/// Serve the request under the URL root: extractors see the original URI
+/// without it, and the links of the response carry it
+pub async fn url_root_middleware(State(root): State<UrlRoot>, mut request: Request, next: Next) -> Response {
+    if root.is_empty() {
+        return next.run(request).await;
+    }
+
+    let stripped = request
+        .extensions()
+        .get::<OriginalUri>()
+        .and_then(|original| root.strip(&original.0));
+    if let Some(uri) = stripped {
+        request.extensions_mut().insert(OriginalUri(uri));
+    }
+    request.extensions_mut().insert(root.clone());
+
+    let response = next.run(request).await;
+    root.prefix_response(response).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::{middleware, routing::get, Router};
+    use op_core::config::FeatureFlags;
+    use tower::ServiceExt;
+
+    use crate::extractors::{AppState, HalResponse};
+    use crate::representers::{CollectionQuery, HalCollection, HalLink, HalResource};
+    use crate::routes::router_with_root;
+
+    async fn get_json(app: Router, uri: &str) -> (StatusCode, Value) {
+        let request = axum::http::Request::builder()
+            .uri(uri)
+            .header("Authorization", "Bearer test")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    fn work_package_app() -> Router {
+        let work_package = || async {
+            HalResponse(
+                HalResource::new("WorkPackage", serde_json::json!({ "id": 42 }))
+                    .with_self_link("/api/v3/work_packages/42")
+                    .with_link("project", HalLink::new("/api/v3/projects/1"))
+                    .with_link("homepage", HalLink::new("https://example.com/")),
+            )
+        };
+        let work_packages = |query: CollectionQuery| async move {
+            HalResponse(HalCollection::new("Collection", vec![1, 2], 5, 2, 1).with_pagination_links(&query))
+        };
+        let app = Router::new()
+            .route("/api/v3/work_packages/42", get(work_package))
+            .route("/api/v3/work_packages", get(work_packages))
+            .layer(middleware::from_fn_with_state(UrlRoot::new("/op"), url_root_middleware));
+        Router::new().nest("/op", app)
+    }
+
+    #[test]
+    fn test_strips_the_root_from_uris_under_it() {
+        let root = UrlRoot::new("op/");
+        let strip = |uri: &str| root.strip(&uri.parse().unwrap()).map(|uri| uri.to_string());
+        assert_eq!(strip("/op/api/v3?x=1"), Some("/api/v3?x=1".into()));
+        assert_eq!(strip("/op"), Some("/".into()));
+        assert_eq!(strip("/open/api/v3"), None);
+        assert_eq!(root.prefix("//cdn.example.com/a.js"), None);
+    }
+
+    #[tokio::test]
+    async fn test_work_package_links_carry_the_root() {
+        let (status, json) = get_json(work_package_app(), "/op/api/v3/work_packages/42").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["_links"]["self"]["href"], "/op/api/v3/work_packages/42");
+        assert_eq!(json["_links"]["project"]["href"], "/op/api/v3/projects/1");
+        assert_eq!(json["_links"]["homepage"]["href"], "https://example.com/");
+    }
+
+    #[tokio::test]
+    async fn test_pagination_links_carry_the_root_once() {
+        let (_, json) = get_json(work_package_app(), "/op/api/v3/work_packages?pageSize=2&offset=1").await;
+        let next = json["_links"]["nextByOffset"]["href"].as_str().unwrap();
+        assert!(next.starts_with("/op/api/v3/work_packages?"), "{}", next);
+    }
+
+    #[tokio::test]
+    async fn test_router_is_served_under_the_root() {
+        let app = || router_with_root(&FeatureFlags::default(), "/op/").with_state(AppState::default());
+
+        let (status, json) = get_json(app(), "/op/api/v3/notifications/groups").await;
+        assert_eq!(status, StatusCode::OK);
+        let self_link = json["_links"]["self"]["href"].as_str().unwrap();
+        assert!(self_link.starts_with("/op/api/v3/notifications/groups"), "{}", self_link);
+
+        let (status, _) = get_json(app(), "/api/v3/notifications/groups").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
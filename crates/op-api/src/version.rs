@@ -0,0 +1,22 @@
+//! Version header middleware
+//!
+//! Adds the `X-OP-RS-Version` header to every response, so clients can tell
+//! which op-rs release answered regardless of the OpenProject core version
+//! the API reports.
+
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+
+/// Response header carrying the op-rs version
+pub const OP_RS_VERSION_HEADER: &str = "x-op-rs-version";
+
+/// Version of this op-rs build
+pub const OP_RS_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Add the op-rs version to the response
+pub async fn version_header_middleware(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(OP_RS_VERSION_HEADER, HeaderValue::from_static(OP_RS_VERSION));
+    response
+}
@@ -0,0 +1,193 @@
+//! Visibility of user data
+//!
+//! Whom the email addresses of users are shown to, and who sees locked
+//! users when picking principals, e.g. to assign or mention them. Every
+//! endpoint rendering users consults [`UserVisibility`] instead of deciding
+//! on its own.
+//!
+//! Administrators see all email addresses and users see their own. Other
+//! users see an address unless its owner hides it, through the `hide_mail`
+//! preference or, for users who never chose, the instance's
+//! [`DEFAULT_HIDE_MAIL_SETTING`]. The anonymous user sees none.
+
+use std::collections::HashMap;
+
+use op_core::traits::Id;
+use op_db::{SettingRepository, UserRepository};
+
+use crate::error::{ApiError, ApiResult};
+use crate::extractors::{AppState, AuthenticatedUser};
+
+/// Setting whether users who never chose hide their email address;
+/// addresses are hidden while it is unset
+pub const DEFAULT_HIDE_MAIL_SETTING: &str = "default_hide_mail";
+
+/// Who looks at user data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewer {
+    pub id: Id,
+    pub admin: bool,
+    pub anonymous: bool,
+}
+
+impl Viewer {
+    pub fn of(user: &AuthenticatedUser) -> Self {
+        Self {
+            id: user.0.id(),
+            admin: user.0.is_admin(),
+            anonymous: user.0.is_anonymous(),
+        }
+    }
+}
+
+/// Whether users hide their email addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailPreferences {
+    /// For users who never chose
+    pub hidden_by_default: bool,
+    /// Choices of users, by user id
+    pub hidden: HashMap<Id, bool>,
+}
+
+impl Default for MailPreferences {
+    fn default() -> Self {
+        Self {
+            hidden_by_default: true,
+            hidden: HashMap::new(),
+        }
+    }
+}
+
+impl MailPreferences {
+    pub fn hides_mail(&self, user_id: Id) -> bool {
+        self.hidden.get(&user_id).copied().unwrap_or(self.hidden_by_default)
+    }
+}
+
+/// Whether the viewer may see the email address of user `subject`
+pub fn can_see_email(viewer: &Viewer, subject: Id, preferences: &MailPreferences) -> bool {
+    if viewer.anonymous {
+        return false;
+    }
+    viewer.admin || viewer.id == subject || !preferences.hides_mail(subject)
+}
+
+/// Whether the viewer sees locked users among the principals to pick
+pub fn can_see_locked_users(viewer: &Viewer) -> bool {
+    viewer.admin && !viewer.anonymous
+}
+
+/// The policy applied for a viewer to a set of users
+#[derive(Debug, Clone)]
+pub struct UserVisibility {
+    viewer: Viewer,
+    preferences: MailPreferences,
+}
+
+impl UserVisibility {
+    pub fn new(viewer: Viewer, preferences: MailPreferences) -> Self {
+        Self { viewer, preferences }
+    }
+
+    /// Load the mail preferences of `subjects` the policy depends on for
+    /// the user; administrators and anonymous users need none
+    pub async fn load(state: &AppState, user: &AuthenticatedUser, subjects: &[Id]) -> ApiResult<Self> {
+        let viewer = Viewer::of(user);
+        let others: Vec<Id> = subjects.iter().copied().filter(|id| *id != viewer.id).collect();
+        let pool = match &state.db {
+            Some(pool) if !viewer.admin && !viewer.anonymous && !others.is_empty() => pool,
+            _ => return Ok(Self::new(viewer, MailPreferences::default())),
+        };
+
+        let setting = SettingRepository::new(pool.clone())
+            .get(DEFAULT_HIDE_MAIL_SETTING)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+        let hidden = UserRepository::new(pool.clone())
+            .find_hide_mail(&others)
+            .await
+            .map_err(|e| ApiError::internal(format!("Database error: {}", e)))?;
+
+        Ok(Self::new(
+            viewer,
+            MailPreferences {
+                hidden_by_default: !matches!(setting.as_deref(), Some("0") | Some("false")),
+                hidden,
+            },
+        ))
+    }
+
+    pub fn can_see_email(&self, subject: Id) -> bool {
+        can_see_email(&self.viewer, subject, &self.preferences)
+    }
+
+    pub fn can_see_locked_users(&self) -> bool {
+        can_see_locked_users(&self.viewer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWER: Id = 1;
+    const SUBJECT: Id = 2;
+
+    fn viewer(admin: bool, anonymous: bool) -> Viewer {
+        Viewer { id: VIEWER, admin, anonymous }
+    }
+
+    #[test]
+    fn test_email_visibility_table() {
+        // (admin, anonymous, own address, preference, hidden by default, visible)
+        let table = [
+            (true, false, false, None, true, true),
+            (true, false, false, Some(true), true, true),
+            (true, false, false, Some(true), false, true),
+            (true, false, true, Some(true), true, true),
+            (false, false, true, None, true, true),
+            (false, false, true, Some(true), true, true),
+            (false, false, false, None, true, false),
+            (false, false, false, None, false, true),
+            (false, false, false, Some(true), false, false),
+            (false, false, false, Some(false), true, true),
+            (false, false, false, Some(false), false, true),
+            (false, true, false, Some(false), false, false),
+            (false, true, false, None, false, false),
+            (false, true, true, None, false, false),
+        ];
+
+        for (admin, anonymous, own, preference, hidden_by_default, visible) in table {
+            let subject = if own { VIEWER } else { SUBJECT };
+            let preferences = MailPreferences {
+                hidden_by_default,
+                hidden: preference.map(|hide| HashMap::from([(subject, hide)])).unwrap_or_default(),
+            };
+            assert_eq!(
+                can_see_email(&viewer(admin, anonymous), subject, &preferences),
+                visible,
+                "admin: {}, anonymous: {}, own: {}, preference: {:?}, hidden by default: {}",
+                admin,
+                anonymous,
+                own,
+                preference,
+                hidden_by_default
+            );
+        }
+    }
+
+    #[test]
+    fn test_locked_users_visibility_table() {
+        for (admin, anonymous, visible) in [(true, false, true), (false, false, false), (false, true, false)] {
+            assert_eq!(can_see_locked_users(&viewer(admin, anonymous)), visible);
+        }
+    }
+
+    #[test]
+    fn test_preferences_default_to_hidden() {
+        let visibility = UserVisibility::new(viewer(false, false), MailPreferences::default());
+        assert!(visibility.can_see_email(VIEWER));
+        assert!(!visibility.can_see_email(SUBJECT));
+        assert!(!visibility.can_see_locked_users());
+    }
+}
@@ -6,7 +6,8 @@
 //!
 //! - Storage abstraction (local filesystem, S3-compatible)
 //! - Attachment metadata management
-//! - File upload and download
+//! - File upload and download, including byte ranges
+//! - Resumable uploads of very large files in chunks
 //! - Container associations (work packages, wiki pages, etc.)
 //!
 //! ## Example
@@ -28,13 +29,21 @@
 //! ```
 
 pub mod model;
+pub mod pg_store;
+pub mod range;
+pub mod references;
 pub mod service;
 pub mod storage;
+pub mod uploads;
+pub mod validation;
 
 pub use model::{
     Attachment, AttachmentThumbnail, AttachmentWithUrl, ContainerType, CreateAttachmentParams,
-    ImageDimensions, ThumbnailSize,
+    ImageDimensions, ThumbnailSize, UpdateAttachmentParams,
 };
+pub use pg_store::{PgAttachmentStore, PgUploadSessionStore};
+pub use range::{ByteRange, Unsatisfiable};
+pub use references::{referenced_attachment_ids, rewrite_attachment_references};
 pub use service::{
     AllowedFileTypes, AttachmentConfig, AttachmentError, AttachmentResult, AttachmentService,
     AttachmentStore, MemoryAttachmentStore,
@@ -43,3 +52,5 @@ pub use storage::{
     generate_disk_filename, generate_key, FileMetadata, LocalStorage, MemoryStorage, S3Config,
     S3Storage, Storage, StorageError, StorageResult,
 };
+pub use uploads::{DigestAlgorithm, MemoryUploadSessionStore, UploadChunk, UploadSession, UploadSessionStore};
+pub use validation::FileRule;
@@ -4,6 +4,7 @@
 
 use chrono::{DateTime, Utc};
 use op_core::traits::Id;
+use op_models::permissions;
 use serde::{Deserialize, Serialize};
 
 /// Container types that can have attachments
@@ -53,6 +54,21 @@ impl ContainerType {
             _ => None,
         }
     }
+
+    /// Permission in the container's project needed to edit its attachments.
+    /// Attachments of containers without one are edited by administrators only.
+    pub fn edit_permission(&self) -> Option<&'static str> {
+        match self {
+            Self::WorkPackage => Some(permissions::EDIT_WORK_PACKAGES),
+            Self::WikiPage => Some(permissions::EDIT_WIKI_PAGES),
+            Self::Message => Some(permissions::EDIT_MESSAGES),
+            Self::Project => Some(permissions::EDIT_PROJECT),
+            Self::Meeting | Self::MeetingContent => Some(permissions::EDIT_MEETINGS),
+            Self::Version => Some(permissions::MANAGE_VERSIONS),
+            Self::Document => Some(permissions::MANAGE_DOCUMENTS),
+            Self::News | Self::User => None,
+        }
+    }
 }
 
 impl std::fmt::Display for ContainerType {
@@ -225,6 +241,31 @@ impl CreateAttachmentParams {
     }
 }
 
+/// Parameters for changing the metadata of an attachment
+#[derive(Debug, Clone, Default)]
+pub struct UpdateAttachmentParams {
+    /// New name of the file; the stored file keeps its disk filename
+    pub filename: Option<String>,
+    /// New description, `Some(None)` removes it
+    pub description: Option<Option<String>>,
+}
+
+impl UpdateAttachmentParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    pub fn description(mut self, desc: Option<String>) -> Self {
+        self.description = Some(desc);
+        self
+    }
+}
+
 /// Attachment with embedded download URL
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttachmentWithUrl {
@@ -366,6 +407,13 @@ mod tests {
         assert_eq!(ContainerType::from_str("Unknown"), None);
     }
 
+    #[test]
+    fn test_container_edit_permissions() {
+        assert_eq!(ContainerType::WorkPackage.edit_permission(), Some("edit_work_packages"));
+        assert_eq!(ContainerType::WikiPage.edit_permission(), Some("edit_wiki_pages"));
+        assert_eq!(ContainerType::User.edit_permission(), None);
+    }
+
     #[test]
     fn test_thumbnail_sizes() {
         assert_eq!(ThumbnailSize::Small.max_dimension(), 64);
@@ -0,0 +1,311 @@
+//! PostgreSQL attachment store
+//!
+//! Attachment records in the `attachments` table, with usage summed in SQL,
+//! and upload sessions in the `attachment_uploads` table.
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+use op_db::{
+    AttachmentRepository, AttachmentRow, AttachmentUploadRepository, AttachmentUploadRow,
+    CreateAttachmentDto, CreateAttachmentUploadDto, Pagination, ProjectRepository, Repository,
+    RepositoryError, UpdateAttachmentDto, UploadChunkRow,
+};
+use sqlx::PgPool;
+
+use crate::model::{Attachment, ContainerType, CreateAttachmentParams};
+use crate::service::{AttachmentError, AttachmentResult, AttachmentStore};
+use crate::uploads::{DigestAlgorithm, UploadChunk, UploadSession, UploadSessionStore};
+
+impl From<RepositoryError> for AttachmentError {
+    fn from(e: RepositoryError) -> Self {
+        AttachmentError::Database(e.to_string())
+    }
+}
+
+/// Attachment store backed by the database
+pub struct PgAttachmentStore {
+    attachments: AttachmentRepository,
+    projects: ProjectRepository,
+}
+
+impl PgAttachmentStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            attachments: AttachmentRepository::new(pool.clone()),
+            projects: ProjectRepository::new(pool),
+        }
+    }
+}
+
+fn to_attachment(row: AttachmentRow) -> Attachment {
+    Attachment {
+        id: Some(row.id),
+        container_type: row.container_type.unwrap_or_default(),
+        container_id: row.container_id,
+        filename: row.filename.unwrap_or_default(),
+        disk_filename: row.disk_filename.unwrap_or_default(),
+        filesize: row.filesize,
+        content_type: row.content_type.unwrap_or_default(),
+        digest: row.digest.unwrap_or_default(),
+        downloads: row.downloads,
+        author_id: row.author_id,
+        description: row.description,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+        file_token: None,
+    }
+}
+
+#[async_trait]
+impl AttachmentStore for PgAttachmentStore {
+    async fn create(&self, attachment: &mut Attachment) -> AttachmentResult<Id> {
+        let row = self
+            .attachments
+            .create(CreateAttachmentDto {
+                container_id: attachment.container_id,
+                container_type: Some(attachment.container_type.clone())
+                    .filter(|t| !t.is_empty()),
+                filename: attachment.filename.clone(),
+                disk_filename: Some(attachment.disk_filename.clone()),
+                filesize: attachment.filesize,
+                content_type: attachment.content_type.clone(),
+                digest: Some(attachment.digest.clone()),
+                author_id: attachment.author_id,
+                description: attachment.description.clone(),
+                status: None,
+            })
+            .await?;
+
+        attachment.id = Some(row.id);
+        attachment.created_at = row.created_at;
+        attachment.updated_at = row.updated_at;
+        Ok(row.id)
+    }
+
+    async fn get(&self, id: Id) -> AttachmentResult<Option<Attachment>> {
+        Ok(self.attachments.find_by_id(id).await?.map(to_attachment))
+    }
+
+    async fn get_for_container(
+        &self,
+        container_type: ContainerType,
+        container_id: Id,
+    ) -> AttachmentResult<Vec<Attachment>> {
+        let all = Pagination {
+            limit: i64::MAX,
+            offset: 0,
+        };
+        let result = self
+            .attachments
+            .find_by_container(container_type.as_str(), container_id, all)
+            .await?;
+
+        Ok(result.items.into_iter().map(to_attachment).collect())
+    }
+
+    async fn update(&self, attachment: &Attachment) -> AttachmentResult<()> {
+        let id = attachment.id.ok_or_else(|| {
+            AttachmentError::InvalidFile("attachment has not been stored".to_string())
+        })?;
+        let existing = self
+            .attachments
+            .find_by_id(id)
+            .await?
+            .ok_or(AttachmentError::NotFound(id))?;
+
+        self.attachments
+            .update(
+                id,
+                UpdateAttachmentDto {
+                    container_id: Some(attachment.container_id),
+                    container_type: Some(Some(attachment.container_type.clone())),
+                    description: Some(attachment.description.clone()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        if attachment.downloads > existing.downloads {
+            self.attachments.increment_downloads(id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn update_metadata(&self, attachment: &Attachment) -> AttachmentResult<()> {
+        let id = attachment.id.ok_or_else(|| {
+            AttachmentError::InvalidFile("attachment has not been stored".to_string())
+        })?;
+
+        self.attachments
+            .update(
+                id,
+                UpdateAttachmentDto {
+                    filename: Some(attachment.filename.clone()),
+                    content_type: Some(attachment.content_type.clone()),
+                    description: Some(attachment.description.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| match e {
+                RepositoryError::NotFound(_) => AttachmentError::NotFound(id),
+                e => e.into(),
+            })?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: Id) -> AttachmentResult<()> {
+        Ok(self.attachments.delete(id).await?)
+    }
+
+    async fn get_orphaned(
+        &self,
+        older_than: chrono::DateTime<chrono::Utc>,
+    ) -> AttachmentResult<Vec<Attachment>> {
+        let rows = self.attachments.find_orphaned().await?;
+
+        Ok(rows
+            .into_iter()
+            .filter(|row| row.created_at < older_than)
+            .map(to_attachment)
+            .collect())
+    }
+
+    async fn count_for_container(
+        &self,
+        container_type: ContainerType,
+        container_id: Id,
+    ) -> AttachmentResult<usize> {
+        let result = self
+            .attachments
+            .find_by_container(
+                container_type.as_str(),
+                container_id,
+                Pagination { limit: 1, offset: 0 },
+            )
+            .await?;
+
+        Ok(result.total as usize)
+    }
+
+    async fn total_size(&self) -> AttachmentResult<i64> {
+        Ok(self.attachments.total_size().await?)
+    }
+
+    async fn total_size_for_project(&self, project_id: Id) -> AttachmentResult<i64> {
+        Ok(self.attachments.total_size_for_project(project_id).await?)
+    }
+
+    async fn project_for_container(
+        &self,
+        container_type: ContainerType,
+        container_id: Id,
+    ) -> AttachmentResult<Option<Id>> {
+        Ok(self
+            .attachments
+            .project_for_container(container_type.as_str(), container_id)
+            .await?)
+    }
+
+    async fn project_quota(&self, project_id: Id) -> AttachmentResult<Option<i64>> {
+        Ok(self.projects.attachment_quota(project_id).await?)
+    }
+}
+
+/// Upload session store backed by the database
+pub struct PgUploadSessionStore {
+    uploads: AttachmentUploadRepository,
+}
+
+impl PgUploadSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            uploads: AttachmentUploadRepository::new(pool),
+        }
+    }
+}
+
+fn to_upload_session(row: AttachmentUploadRow) -> AttachmentResult<UploadSession> {
+    let digest_algorithm = DigestAlgorithm::parse(&row.digest_algorithm).ok_or_else(|| {
+        AttachmentError::Database(format!("unknown digest algorithm {}", row.digest_algorithm))
+    })?;
+
+    Ok(UploadSession {
+        id: row.id,
+        author_id: row.author_id,
+        params: CreateAttachmentParams {
+            filename: row.filename,
+            content_type: row.content_type,
+            description: row.description,
+            container_type: row.container_type.as_deref().and_then(ContainerType::from_str),
+            container_id: row.container_id,
+        },
+        size: row.filesize,
+        digest_algorithm,
+        chunks: row
+            .chunks
+            .0
+            .into_iter()
+            .map(|chunk| UploadChunk {
+                number: chunk.number as u32,
+                size: chunk.size,
+                digest: chunk.digest,
+            })
+            .collect(),
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    })
+}
+
+#[async_trait]
+impl UploadSessionStore for PgUploadSessionStore {
+    async fn create(&self, session: &UploadSession) -> AttachmentResult<()> {
+        self.uploads
+            .create(CreateAttachmentUploadDto {
+                id: session.id.clone(),
+                author_id: session.author_id,
+                filename: session.params.filename.clone(),
+                content_type: session.params.content_type.clone(),
+                description: session.params.description.clone(),
+                container_type: session.params.container_type.as_ref().map(|t| t.as_str().to_string()),
+                container_id: session.params.container_id,
+                filesize: session.size,
+                digest_algorithm: session.digest_algorithm.as_str().to_string(),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> AttachmentResult<Option<UploadSession>> {
+        self.uploads.find(id).await?.map(to_upload_session).transpose()
+    }
+
+    async fn append_chunk(&self, id: &str, chunk: &UploadChunk) -> AttachmentResult<bool> {
+        let row = UploadChunkRow {
+            number: chunk.number as i32,
+            size: chunk.size,
+            digest: chunk.digest.clone(),
+        };
+
+        Ok(self.uploads.append_chunk(id, chunk.number as usize, &row).await?)
+    }
+
+    async fn delete(&self, id: &str) -> AttachmentResult<()> {
+        Ok(self.uploads.delete(id).await?)
+    }
+
+    async fn get_stale(
+        &self,
+        older_than: chrono::DateTime<chrono::Utc>,
+    ) -> AttachmentResult<Vec<UploadSession>> {
+        self.uploads
+            .find_stale(older_than)
+            .await?
+            .into_iter()
+            .map(to_upload_session)
+            .collect()
+    }
+}
@@ -0,0 +1,181 @@
+//! Byte Ranges
+//!
+//! Parses the `Range` and `If-Range` headers of partial downloads
+//! (RFC 9110, section 14). Only a single range is served; a request for
+//! several ranges is answered with the whole file, which the RFC allows.
+
+/// A range of bytes of a file, both ends inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// Number of bytes in the range
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// A range holds at least one byte
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Value of the `Content-Range` header of the range in a file of `size` bytes
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+/// The range does not overlap the file, answered with 416
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsatisfiable;
+
+impl Unsatisfiable {
+    /// Value of the `Content-Range` header of a 416 for a file of `size` bytes
+    pub fn content_range(size: u64) -> String {
+        format!("bytes */{}", size)
+    }
+}
+
+/// Range requested by a `Range` header in a file of `size` bytes
+///
+/// `None` if the whole file is to be sent: the header is malformed, is not
+/// in bytes or asks for several ranges. Ends past the end of the file are
+/// cut to it; a range starting past the end, or an empty suffix, is
+/// unsatisfiable.
+pub fn parse_range(header: &str, size: u64) -> Result<Option<ByteRange>, Unsatisfiable> {
+    let Some((unit, spec)) = header.trim().split_once('=') else {
+        return Ok(None);
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") || spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+
+    match (first.trim(), last.trim()) {
+        // bytes=-500: the last 500 bytes
+        ("", suffix) => {
+            let Some(length) = parse_position(suffix) else {
+                return Ok(None);
+            };
+            if length == 0 || size == 0 {
+                return Err(Unsatisfiable);
+            }
+            Ok(Some(ByteRange {
+                start: size.saturating_sub(length),
+                end: size - 1,
+            }))
+        }
+        (first, last) => {
+            let Some(start) = parse_position(first) else {
+                return Ok(None);
+            };
+            let end = match last {
+                // bytes=500-: from byte 500 on
+                "" => None,
+                last => match parse_position(last) {
+                    Some(end) if end >= start => Some(end),
+                    _ => return Ok(None),
+                },
+            };
+            if start >= size {
+                return Err(Unsatisfiable);
+            }
+            Ok(Some(ByteRange {
+                start,
+                end: end.map_or(size - 1, |end| end.min(size - 1)),
+            }))
+        }
+    }
+}
+
+fn parse_position(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Whether the `If-Range` header of a request allows serving a range of a
+/// file with the entity tag `etag`
+///
+/// Ranges are only served for a strong match of the entity tag. Weak tags
+/// and dates never match, so the whole file is sent in their place.
+pub fn if_range_matches(header: &str, etag: &str) -> bool {
+    let header = header.trim();
+    !header.starts_with("W/") && header == etag
+}
+
+/// Strong entity tag of a file by its digest, `None` without a digest
+pub fn etag(digest: &str) -> Option<String> {
+    (!digest.is_empty()).then(|| format!("\"{}\"", digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> Option<ByteRange> {
+        Some(ByteRange { start, end })
+    }
+
+    #[test]
+    fn test_closed_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(range(0, 99)));
+        assert_eq!(parse_range("bytes=500-500", 1000), Ok(range(500, 500)));
+        assert_eq!(parse_range("Bytes = 10-19", 1000), Ok(range(10, 19)));
+    }
+
+    #[test]
+    fn test_open_ended_range() {
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(range(900, 999)));
+        assert_eq!(parse_range("bytes=0-", 1000), Ok(range(0, 999)));
+    }
+
+    #[test]
+    fn test_suffix_range() {
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(range(900, 999)));
+        // A suffix longer than the file is the whole file
+        assert_eq!(parse_range("bytes=-5000", 1000), Ok(range(0, 999)));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(Unsatisfiable));
+        assert_eq!(parse_range("bytes=-10", 0), Err(Unsatisfiable));
+    }
+
+    #[test]
+    fn test_range_past_the_end() {
+        assert_eq!(parse_range("bytes=900-5000", 1000), Ok(range(900, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(Unsatisfiable));
+        assert_eq!(parse_range("bytes=1000-1100", 1000), Err(Unsatisfiable));
+        assert_eq!(parse_range("bytes=0-", 0), Err(Unsatisfiable));
+    }
+
+    #[test]
+    fn test_ignored_ranges() {
+        for header in ["", "bytes", "items=0-10", "bytes=0-10,20-30", "bytes=10-5", "bytes=a-b", "bytes=-", "bytes=+1-2"] {
+            assert_eq!(parse_range(header, 1000), Ok(None), "{}", header);
+        }
+    }
+
+    #[test]
+    fn test_content_range() {
+        let range = ByteRange { start: 900, end: 999 };
+        assert_eq!(range.len(), 100);
+        assert_eq!(range.content_range(1000), "bytes 900-999/1000");
+        assert_eq!(Unsatisfiable::content_range(1000), "bytes */1000");
+    }
+
+    #[test]
+    fn test_if_range() {
+        let tag = etag("abc").unwrap();
+        assert_eq!(tag, "\"abc\"");
+        assert!(if_range_matches("\"abc\"", &tag));
+        assert!(!if_range_matches("\"def\"", &tag));
+        assert!(!if_range_matches("W/\"abc\"", &tag));
+        assert!(!if_range_matches("Wed, 21 Oct 2015 07:28:00 GMT", &tag));
+        assert_eq!(etag(""), None);
+    }
+}
@@ -0,0 +1,213 @@
+//! Inline Attachment References
+//!
+//! Mirrors: app/services/copy/concerns/copy_attachments.rb (update_references)
+//!
+//! Formattable texts show images of their container's attachments through
+//! content URLs, e.g. `![](/api/v3/attachments/123/content)`. Copying the
+//! container copies its attachments under new ids, so the references of
+//! the copied text are rewritten to the copies. References to attachments
+//! that were not copied, such as ones of other containers, are left as they
+//! are, as is everything in code blocks and code spans.
+
+use std::collections::HashMap;
+
+use op_core::traits::Id;
+
+/// Path of attachment content URLs up to the id
+const CONTENT_PATH_PREFIX: &str = "/api/v3/attachments/";
+
+/// Path of attachment content URLs after the id
+const CONTENT_PATH_SUFFIX: &str = "/content";
+
+/// Ids of the attachments the text references outside of code, in order of
+/// appearance, each once
+pub fn referenced_attachment_ids(text: &str) -> Vec<Id> {
+    let mut ids = Vec::new();
+    for_each_reference(text, |id| {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+        None
+    });
+    ids
+}
+
+/// The text with references to the attachments in `copies`, mapping the ids
+/// of the originals to the ids of their copies, pointing to the copies.
+/// `None` when nothing was rewritten.
+pub fn rewrite_attachment_references(text: &str, copies: &HashMap<Id, Id>) -> Option<String> {
+    let rewritten = for_each_reference(text, |id| copies.get(&id).copied());
+    (rewritten != text).then_some(rewritten)
+}
+
+/// Call `f` with the id of every reference outside of code and replace the
+/// id by the one it returns, if any
+fn for_each_reference(text: &str, mut f: impl FnMut(Id) -> Option<Id>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut fence: Option<(char, usize)> = None;
+
+    for line in text.split_inclusive('\n') {
+        match (fence, code_fence(line)) {
+            (None, Some(opening)) => {
+                fence = Some(opening);
+                output.push_str(line);
+            }
+            (Some((marker, length)), Some((closing, closing_length)))
+                if closing == marker && closing_length >= length && is_closing_fence(line) =>
+            {
+                fence = None;
+                output.push_str(line);
+            }
+            (Some(_), _) => output.push_str(line),
+            (None, None) => rewrite_line(line, &mut f, &mut output),
+        }
+    }
+    output
+}
+
+/// Marker and length of the fence a line opens or closes
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = rest.len() - rest.trim_start_matches(marker).len();
+    (length >= 3).then_some((marker, length))
+}
+
+/// Closing fences have nothing but whitespace after the marker
+fn is_closing_fence(line: &str) -> bool {
+    let rest = line.trim_start_matches(' ');
+    let marker = rest.chars().next().unwrap_or_default();
+    rest.trim_start_matches(marker).trim().is_empty()
+}
+
+/// Rewrite the references of a line outside of its code spans
+fn rewrite_line(line: &str, f: &mut impl FnMut(Id) -> Option<Id>, output: &mut String) {
+    let mut rest = line;
+    while let Some(start) = rest.find('`') {
+        rewrite_text(&rest[..start], f, output);
+        let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let after = &rest[start + ticks..];
+        match find_closing_ticks(after, ticks) {
+            Some(end) => {
+                output.push_str(&rest[start..start + ticks + end + ticks]);
+                rest = &after[end + ticks..];
+            }
+            // An unmatched run of backticks is literal text
+            None => {
+                output.push_str(&rest[start..start + ticks]);
+                rest = after;
+            }
+        }
+    }
+    rewrite_text(rest, f, output);
+}
+
+/// Offset of the run of exactly `ticks` backticks closing a code span
+fn find_closing_ticks(text: &str, ticks: usize) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('`') {
+        let start = offset + start;
+        let length = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if length == ticks {
+            return Some(start);
+        }
+        offset = start + length;
+    }
+    None
+}
+
+fn rewrite_text(text: &str, f: &mut impl FnMut(Id) -> Option<Id>, output: &mut String) {
+    let mut rest = text;
+    while let Some(start) = rest.find(CONTENT_PATH_PREFIX) {
+        let id_start = start + CONTENT_PATH_PREFIX.len();
+        let digits = rest[id_start..].len() - rest[id_start..].trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let id_end = id_start + digits;
+        let id = rest[id_start..id_end].parse::<Id>().ok();
+
+        match id.filter(|_| is_content_path_end(&rest[id_end..])) {
+            Some(id) => {
+                output.push_str(&rest[..id_start]);
+                output.push_str(&f(id).unwrap_or(id).to_string());
+                rest = &rest[id_end..];
+            }
+            None => {
+                output.push_str(&rest[..id_start]);
+                rest = &rest[id_start..];
+            }
+        }
+    }
+    output.push_str(rest);
+}
+
+/// Whether the URL continues with the content path and ends there
+fn is_content_path_end(rest: &str) -> bool {
+    rest.strip_prefix(CONTENT_PATH_SUFFIX)
+        .is_some_and(|after| !after.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copies() -> HashMap<Id, Id> {
+        HashMap::from([(123, 456), (7, 8)])
+    }
+
+    #[test]
+    fn test_rewrites_every_reference_to_a_copied_attachment() {
+        let text = "![](/api/v3/attachments/123/content) and again\n\
+                    <img src=\"https://op.example.com/api/v3/attachments/123/content\"> ![](/api/v3/attachments/7/content)";
+
+        assert_eq!(
+            rewrite_attachment_references(text, &copies()).as_deref(),
+            Some(
+                "![](/api/v3/attachments/456/content) and again\n\
+                 <img src=\"https://op.example.com/api/v3/attachments/456/content\"> ![](/api/v3/attachments/8/content)"
+            )
+        );
+        assert_eq!(referenced_attachment_ids(text), vec![123, 7]);
+    }
+
+    #[test]
+    fn test_unknown_references_are_left_untouched() {
+        // Another container's attachment, an id starting with a copied one,
+        // and URLs other than the content
+        let text = "![](/api/v3/attachments/99/content) ![](/api/v3/attachments/1234/content) \
+                    [log](/api/v3/attachments/123) /api/v3/attachments/123/contents";
+
+        assert_eq!(rewrite_attachment_references(text, &copies()), None);
+        assert_eq!(rewrite_attachment_references("No images", &copies()), None);
+    }
+
+    #[test]
+    fn test_references_in_code_are_not_rewritten() {
+        let text = "Embed it with `![](/api/v3/attachments/123/content)`:\n\
+                    ```markdown\n\
+                    ![](/api/v3/attachments/123/content)\n\
+                    ````\n\
+                    ![](/api/v3/attachments/123/content)\n\
+                    ~~~\n\
+                    /api/v3/attachments/7/content\n\
+                    ~~~\n\
+                    ``not `code` ![](/api/v3/attachments/7/content)`` but ![](/api/v3/attachments/7/content)";
+
+        assert_eq!(
+            rewrite_attachment_references(text, &copies()).as_deref(),
+            Some(
+                "Embed it with `![](/api/v3/attachments/123/content)`:\n\
+                 ```markdown\n\
+                 ![](/api/v3/attachments/123/content)\n\
+                 ````\n\
+                 ![](/api/v3/attachments/456/content)\n\
+                 ~~~\n\
+                 /api/v3/attachments/7/content\n\
+                 ~~~\n\
+                 ``not `code` ![](/api/v3/attachments/7/content)`` but ![](/api/v3/attachments/8/content)"
+            )
+        );
+    }
+}
@@ -2,18 +2,27 @@
 //!
 //! Orchestrates attachment operations, storage, and metadata management.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use op_core::config::StorageConfig;
 use op_core::traits::Id;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, info, instrument, warn};
 
-use crate::model::{Attachment, AttachmentWithUrl, ContainerType, CreateAttachmentParams};
+use crate::model::{
+    Attachment, AttachmentWithUrl, ContainerType, CreateAttachmentParams, UpdateAttachmentParams,
+};
+use crate::range::ByteRange;
 use crate::storage::{generate_disk_filename, Storage, StorageError};
+use crate::uploads::{DigestAlgorithm, MemoryUploadSessionStore, UploadChunk, UploadSession, UploadSessionStore};
+use crate::validation::{
+    detect_executable, file_extensions, normalize_extension, FileRule, DEFAULT_BLOCKED_EXTENSIONS,
+};
 
 /// Service errors
 #[derive(Debug, Error)]
@@ -28,10 +37,32 @@ pub enum AttachmentError {
     FileTooLarge { size: i64, max: i64 },
     #[error("Invalid content type: {0}")]
     InvalidContentType(String),
+    #[error("File rejected by {0}")]
+    FileRejected(#[from] FileRule),
     #[error("Permission denied")]
     PermissionDenied,
     #[error("Container not found: {0} {1}")]
     ContainerNotFound(String, Id),
+    /// The upload would exceed the quota of the project, or of the instance
+    /// when `project_id` is `None`
+    #[error("Attachment quota exceeded: {size} bytes requested, {used} of {limit} bytes used")]
+    QuotaExceeded {
+        project_id: Option<Id>,
+        size: i64,
+        used: i64,
+        limit: i64,
+    },
+    #[error("Upload not found: {0}")]
+    UploadNotFound(String),
+    /// Chunks of an upload are accepted in sequence only
+    #[error("Chunk {received} is out of order, expected chunk {expected}")]
+    ChunkOutOfOrder { expected: u32, received: u32 },
+    #[error("Digest mismatch: expected {expected}, computed {actual}")]
+    DigestMismatch { expected: String, actual: String },
+    #[error("Upload incomplete: {received} of {size} bytes received")]
+    IncompleteUpload { received: i64, size: i64 },
+    #[error("Database error: {0}")]
+    Database(String),
 }
 
 pub type AttachmentResult<T> = Result<T, AttachmentError>;
@@ -55,6 +86,9 @@ pub trait AttachmentStore: Send + Sync {
     /// Update an attachment
     async fn update(&self, attachment: &Attachment) -> AttachmentResult<()>;
 
+    /// Store the filename, content type and description of an attachment
+    async fn update_metadata(&self, attachment: &Attachment) -> AttachmentResult<()>;
+
     /// Delete an attachment
     async fn delete(&self, id: Id) -> AttachmentResult<()>;
 
@@ -68,12 +102,30 @@ pub trait AttachmentStore: Send + Sync {
         container_type: ContainerType,
         container_id: Id,
     ) -> AttachmentResult<usize>;
+
+    /// Total size of all attachments in bytes
+    async fn total_size(&self) -> AttachmentResult<i64>;
+
+    /// Total size in bytes of the attachments of a project's containers
+    async fn total_size_for_project(&self, project_id: Id) -> AttachmentResult<i64>;
+
+    /// Project a container belongs to, if any
+    async fn project_for_container(
+        &self,
+        container_type: ContainerType,
+        container_id: Id,
+    ) -> AttachmentResult<Option<Id>>;
+
+    /// Attachment quota of a project in bytes; `None` means unlimited
+    async fn project_quota(&self, project_id: Id) -> AttachmentResult<Option<i64>>;
 }
 
 /// In-memory attachment store for testing
 pub struct MemoryAttachmentStore {
     attachments: RwLock<Vec<Attachment>>,
     next_id: std::sync::atomic::AtomicI64,
+    container_projects: RwLock<HashMap<(String, Id), Id>>,
+    project_quotas: RwLock<HashMap<Id, i64>>,
 }
 
 impl Default for MemoryAttachmentStore {
@@ -87,8 +139,43 @@ impl MemoryAttachmentStore {
         Self {
             attachments: RwLock::new(Vec::new()),
             next_id: std::sync::atomic::AtomicI64::new(1),
+            container_projects: RwLock::new(HashMap::new()),
+            project_quotas: RwLock::new(HashMap::new()),
         }
     }
+
+    /// Record the project a container belongs to
+    pub async fn set_container_project(
+        &self,
+        container_type: ContainerType,
+        container_id: Id,
+        project_id: Id,
+    ) {
+        self.container_projects
+            .write()
+            .await
+            .insert((container_type.to_string(), container_id), project_id);
+    }
+
+    /// Set or clear the attachment quota of a project
+    pub async fn set_project_quota(&self, project_id: Id, quota: Option<i64>) {
+        let mut quotas = self.project_quotas.write().await;
+        match quota {
+            Some(quota) => quotas.insert(project_id, quota),
+            None => quotas.remove(&project_id),
+        };
+    }
+
+    async fn project_of(&self, container_type: &str, container_id: Id) -> Option<Id> {
+        if container_type == ContainerType::Project.as_str() {
+            return Some(container_id);
+        }
+        self.container_projects
+            .read()
+            .await
+            .get(&(container_type.to_string(), container_id))
+            .copied()
+    }
 }
 
 #[async_trait]
@@ -134,6 +221,19 @@ impl AttachmentStore for MemoryAttachmentStore {
         Ok(())
     }
 
+    async fn update_metadata(&self, attachment: &Attachment) -> AttachmentResult<()> {
+        let mut attachments = self.attachments.write().await;
+        let stored = attachments
+            .iter_mut()
+            .find(|a| a.id == attachment.id)
+            .ok_or(AttachmentError::NotFound(attachment.id.unwrap_or_default()))?;
+        stored.filename = attachment.filename.clone();
+        stored.content_type = attachment.content_type.clone();
+        stored.description = attachment.description.clone();
+        stored.updated_at = attachment.updated_at;
+        Ok(())
+    }
+
     async fn delete(&self, id: Id) -> AttachmentResult<()> {
         let mut attachments = self.attachments.write().await;
         attachments.retain(|a| a.id != Some(id));
@@ -166,6 +266,36 @@ impl AttachmentStore for MemoryAttachmentStore {
             })
             .count())
     }
+
+    async fn total_size(&self) -> AttachmentResult<i64> {
+        let attachments = self.attachments.read().await;
+        Ok(attachments.iter().map(|a| a.filesize).sum())
+    }
+
+    async fn total_size_for_project(&self, project_id: Id) -> AttachmentResult<i64> {
+        let attachments = self.attachments.read().await;
+        let mut total = 0;
+        for attachment in attachments.iter() {
+            if let Some(container_id) = attachment.container_id {
+                if self.project_of(&attachment.container_type, container_id).await == Some(project_id) {
+                    total += attachment.filesize;
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    async fn project_for_container(
+        &self,
+        container_type: ContainerType,
+        container_id: Id,
+    ) -> AttachmentResult<Option<Id>> {
+        Ok(self.project_of(container_type.as_str(), container_id).await)
+    }
+
+    async fn project_quota(&self, project_id: Id) -> AttachmentResult<Option<i64>> {
+        Ok(self.project_quotas.read().await.get(&project_id).copied())
+    }
 }
 
 /// Allowed file types configuration
@@ -175,6 +305,12 @@ pub struct AllowedFileTypes {
     pub allowed_mime_types: Vec<String>,
     /// Blocked MIME types
     pub blocked_mime_types: Vec<String>,
+    /// Allowed extensions (empty = allow all)
+    pub allowed_extensions: Vec<String>,
+    /// Blocked extensions, checked against every extension of a filename
+    pub blocked_extensions: Vec<String>,
+    /// Reject executable or script content not declared as such
+    pub strict_content_match: bool,
     /// Maximum file size in bytes
     pub max_file_size: i64,
 }
@@ -187,12 +323,33 @@ impl Default for AllowedFileTypes {
                 "application/x-msdownload".to_string(),
                 "application/x-executable".to_string(),
             ],
+            allowed_extensions: Vec::new(),
+            blocked_extensions: DEFAULT_BLOCKED_EXTENSIONS
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            strict_content_match: false,
             max_file_size: 100 * 1024 * 1024, // 100 MB
         }
     }
 }
 
 impl AllowedFileTypes {
+    /// Build from the storage configuration, adding its blocked extensions
+    /// to the default denylist
+    pub fn from_storage_config(config: &StorageConfig) -> Self {
+        let mut types = Self {
+            allowed_extensions: config.allowed_extensions.clone(),
+            strict_content_match: config.strict_content_match,
+            max_file_size: config.max_attachment_size as i64,
+            ..Default::default()
+        };
+        types
+            .blocked_extensions
+            .extend(config.blocked_extensions.iter().cloned());
+        types
+    }
+
     /// Check if a content type is allowed
     pub fn is_allowed(&self, content_type: &str) -> bool {
         // Check blocked list first
@@ -208,6 +365,70 @@ impl AllowedFileTypes {
         // Check allowed list
         self.allowed_mime_types.iter().any(|t| t == content_type)
     }
+
+    /// Check the extensions of a filename
+    ///
+    /// Every extension is checked against the denylist, so "invoice.pdf.exe"
+    /// and "setup.exe.txt" are rejected as double extensions. The allowlist
+    /// applies to the final extension only.
+    pub fn check_filename(&self, filename: &str) -> Result<(), FileRule> {
+        let extensions = file_extensions(filename);
+        let Some((last, inner)) = extensions.split_last() else {
+            if self.allowed_extensions.is_empty() {
+                return Ok(());
+            }
+            return Err(FileRule::MissingExtension(filename.to_string()));
+        };
+
+        let double_extension = |extension: &String| FileRule::DoubleExtension {
+            filename: filename.to_string(),
+            extension: extension.clone(),
+        };
+
+        if self.is_blocked_extension(last) {
+            if inner.is_empty() {
+                return Err(FileRule::ExtensionBlocked(last.clone()));
+            }
+            return Err(double_extension(last));
+        }
+
+        if let Some(hidden) = inner.iter().find(|e| self.is_blocked_extension(e)) {
+            return Err(double_extension(hidden));
+        }
+
+        if !self.allowed_extensions.is_empty()
+            && !self
+                .allowed_extensions
+                .iter()
+                .any(|e| normalize_extension(e) == *last)
+        {
+            return Err(FileRule::ExtensionNotAllowed(last.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Cross-check the content against the claimed content type. Only
+    /// executable and script content is sniffed, and only in strict mode.
+    pub fn check_content(&self, content_type: &str, data: &[u8]) -> Result<(), FileRule> {
+        if !self.strict_content_match {
+            return Ok(());
+        }
+
+        match detect_executable(data) {
+            Some(detected) if detected != content_type => Err(FileRule::ContentMismatch {
+                claimed: content_type.to_string(),
+                detected: detected.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_blocked_extension(&self, extension: &str) -> bool {
+        self.blocked_extensions
+            .iter()
+            .any(|e| normalize_extension(e) == extension)
+    }
 }
 
 /// Attachment service configuration
@@ -216,6 +437,8 @@ pub struct AttachmentConfig {
     pub allowed_types: AllowedFileTypes,
     pub url_expiry: Duration,
     pub cleanup_orphans_after: Duration,
+    /// Total attachment storage of the instance in bytes (None = unlimited)
+    pub instance_quota: Option<i64>,
 }
 
 impl Default for AttachmentConfig {
@@ -224,15 +447,54 @@ impl Default for AttachmentConfig {
             allowed_types: AllowedFileTypes::default(),
             url_expiry: Duration::from_secs(3600), // 1 hour
             cleanup_orphans_after: Duration::from_secs(86400), // 24 hours
+            instance_quota: None,
+        }
+    }
+}
+
+impl AttachmentConfig {
+    /// Configuration from the instance storage settings
+    pub fn from_storage_config(config: &StorageConfig) -> Self {
+        Self {
+            allowed_types: AllowedFileTypes::from_storage_config(config),
+            instance_quota: config.attachment_quota,
+            ..Default::default()
         }
     }
 }
 
+/// Bytes of uploads that passed the quota check but are not stored yet
+#[derive(Debug, Default)]
+struct PendingUploads {
+    instance: i64,
+    projects: HashMap<Id, i64>,
+}
+
+/// Space reserved for an upload until it is stored
+#[derive(Debug)]
+struct Reservation {
+    instance: i64,
+    project_id: Option<Id>,
+    size: i64,
+}
+
 /// Attachment service
+///
+/// Uploads are checked against the instance and project quotas under a lock
+/// and reserve their size until stored, so concurrent uploads through one
+/// service cannot together exceed a quota. Uploads by other processes
+/// sharing the store are not reserved: across processes enforcement is soft,
+/// and a quota may be exceeded by the uploads in flight at the same time.
+///
+/// Resumable uploads keep their sessions in an [`UploadSessionStore`], in
+/// memory unless another is given with
+/// [`with_upload_sessions`](Self::with_upload_sessions).
 pub struct AttachmentService<St: AttachmentStore, S: Storage> {
     store: Arc<St>,
     storage: Arc<S>,
     config: AttachmentConfig,
+    pending: Mutex<PendingUploads>,
+    uploads: Arc<dyn UploadSessionStore>,
 }
 
 impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
@@ -241,6 +503,84 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
             store,
             storage,
             config,
+            pending: Mutex::new(PendingUploads::default()),
+            uploads: Arc::new(MemoryUploadSessionStore::new()),
+        }
+    }
+
+    /// Keep the sessions of resumable uploads in the given store
+    pub fn with_upload_sessions(mut self, uploads: Arc<dyn UploadSessionStore>) -> Self {
+        self.uploads = uploads;
+        self
+    }
+
+    /// Reserve `size` bytes in the target project and, if `counts_for_instance`,
+    /// in the instance; fails if a quota would be exceeded
+    async fn reserve(
+        &self,
+        project_id: Option<Id>,
+        size: i64,
+        counts_for_instance: bool,
+    ) -> AttachmentResult<Reservation> {
+        let mut pending = self.pending.lock().await;
+
+        let instance = if counts_for_instance { size } else { 0 };
+        if let (Some(limit), true) = (self.config.instance_quota, counts_for_instance) {
+            let used = self.store.total_size().await? + pending.instance;
+            if used + size > limit {
+                return Err(AttachmentError::QuotaExceeded {
+                    project_id: None,
+                    size,
+                    used,
+                    limit,
+                });
+            }
+        }
+
+        if let Some(project_id) = project_id {
+            if let Some(limit) = self.store.project_quota(project_id).await? {
+                let used = self.store.total_size_for_project(project_id).await?
+                    + pending.projects.get(&project_id).copied().unwrap_or(0);
+                if used + size > limit {
+                    return Err(AttachmentError::QuotaExceeded {
+                        project_id: Some(project_id),
+                        size,
+                        used,
+                        limit,
+                    });
+                }
+            }
+            *pending.projects.entry(project_id).or_default() += size;
+        }
+        pending.instance += instance;
+
+        Ok(Reservation {
+            instance,
+            project_id,
+            size,
+        })
+    }
+
+    /// Release a reservation once the upload is stored or has failed
+    async fn release(&self, reservation: Reservation) {
+        let mut pending = self.pending.lock().await;
+        pending.instance -= reservation.instance;
+        if let Some(project_id) = reservation.project_id {
+            if let Some(reserved) = pending.projects.get_mut(&project_id) {
+                *reserved -= reservation.size;
+                if *reserved <= 0 {
+                    pending.projects.remove(&project_id);
+                }
+            }
+        }
+    }
+
+    async fn project_for(&self, container: Option<(ContainerType, Id)>) -> AttachmentResult<Option<Id>> {
+        match container {
+            Some((container_type, container_id)) => {
+                self.store.project_for_container(container_type, container_id).await
+            }
+            None => Ok(None),
         }
     }
 
@@ -253,7 +593,25 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
         author_id: Id,
     ) -> AttachmentResult<AttachmentWithUrl> {
         let size = data.len() as i64;
+        let content_type = self.check_file(&params, size)?;
+
+        // In strict mode, check the actual content
+        self.config.allowed_types.check_content(&content_type, &data)?;
 
+        // Check quotas
+        let project_id = self
+            .project_for(params.container_type.zip(params.container_id))
+            .await?;
+        let reservation = self.reserve(project_id, size, true).await?;
+
+        let result = self.store_upload(params, data, content_type, author_id).await;
+        self.release(reservation).await;
+        result
+    }
+
+    /// Check the size, content type and extensions of a file to upload;
+    /// its content type
+    fn check_file(&self, params: &CreateAttachmentParams, size: i64) -> AttachmentResult<String> {
         // Check file size
         if size > self.config.allowed_types.max_file_size {
             return Err(AttachmentError::FileTooLarge {
@@ -274,6 +632,170 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
             return Err(AttachmentError::InvalidContentType(content_type));
         }
 
+        // Check extensions
+        self.config.allowed_types.check_filename(&params.filename)?;
+
+        Ok(content_type)
+    }
+
+    /// Start a resumable upload of a file of `size` bytes
+    ///
+    /// The file is checked like an upload in one request, and the quotas
+    /// must have room for it; the space is not reserved until the upload is
+    /// completed, where the checks are repeated.
+    #[instrument(skip(self, params, author_id), fields(filename = %params.filename))]
+    pub async fn start_upload(
+        &self,
+        params: CreateAttachmentParams,
+        size: i64,
+        digest_algorithm: DigestAlgorithm,
+        author_id: Id,
+    ) -> AttachmentResult<UploadSession> {
+        if size <= 0 {
+            return Err(AttachmentError::InvalidFile("file size must be greater than 0".to_string()));
+        }
+        self.check_file(&params, size)?;
+
+        let project_id = self
+            .project_for(params.container_type.zip(params.container_id))
+            .await?;
+        let reservation = self.reserve(project_id, size, true).await?;
+        self.release(reservation).await;
+
+        let session = UploadSession::new(params, size, digest_algorithm, author_id);
+        self.uploads.create(&session).await?;
+        info!(upload = %session.id, size = size, "Upload started");
+
+        Ok(session)
+    }
+
+    /// Upload session of the user
+    pub async fn upload_session(&self, id: &str, user_id: Id) -> AttachmentResult<UploadSession> {
+        self.uploads
+            .get(id)
+            .await?
+            .filter(|session| session.author_id == user_id)
+            .ok_or_else(|| AttachmentError::UploadNotFound(id.to_string()))
+    }
+
+    /// Append chunk `number` to an upload, checking its data against the
+    /// `checksum` in the session's digest algorithm if one is given
+    ///
+    /// Only the chunk the session expects next is accepted, so a chunk
+    /// resent after a lost response is rejected as out of order; the
+    /// session tells which chunk comes next.
+    #[instrument(skip(self, data, checksum))]
+    pub async fn upload_chunk(
+        &self,
+        id: &str,
+        number: u32,
+        data: Bytes,
+        checksum: Option<&str>,
+        user_id: Id,
+    ) -> AttachmentResult<UploadSession> {
+        let session = self.upload_session(id, user_id).await?;
+        if number != session.next_chunk() {
+            return Err(AttachmentError::ChunkOutOfOrder {
+                expected: session.next_chunk(),
+                received: number,
+            });
+        }
+        if data.is_empty() {
+            return Err(AttachmentError::InvalidFile("chunk is empty".to_string()));
+        }
+        let received = session.received() + data.len() as i64;
+        if received > session.size {
+            return Err(AttachmentError::InvalidFile(format!(
+                "chunks exceed the expected size of {} bytes",
+                session.size
+            )));
+        }
+
+        let chunk = UploadChunk {
+            number,
+            size: data.len() as i64,
+            digest: session.digest_algorithm.digest(&data),
+        };
+        if let Some(checksum) = checksum.filter(|checksum| !DigestAlgorithm::matches(checksum, &chunk.digest)) {
+            return Err(AttachmentError::DigestMismatch {
+                expected: checksum.to_string(),
+                actual: chunk.digest,
+            });
+        }
+
+        let key = session.chunk_key(&chunk);
+        self.storage.put(&key, data).await?;
+        if !self.uploads.append_chunk(id, &chunk).await? {
+            // Another request appended the chunk first; its data is kept
+            // unless it is the same
+            let current = self.upload_session(id, user_id).await?;
+            if current.chunks.get(number as usize) != Some(&chunk) {
+                self.storage.delete(&key).await?;
+            }
+            return Err(AttachmentError::ChunkOutOfOrder {
+                expected: current.next_chunk(),
+                received: number,
+            });
+        }
+        debug!(upload = %id, chunk = number, received = received, "Chunk received");
+
+        self.upload_session(id, user_id).await
+    }
+
+    /// Assemble the chunks of a complete upload into an attachment, once the
+    /// digest of the file matches `digest`
+    ///
+    /// The attachment is created like an upload in one request. A digest
+    /// that does not match discards the upload; when the file is rejected,
+    /// e.g. by a quota, the upload is kept to be completed later.
+    #[instrument(skip(self, digest))]
+    pub async fn complete_upload(&self, id: &str, digest: &str, user_id: Id) -> AttachmentResult<AttachmentWithUrl> {
+        let session = self.upload_session(id, user_id).await?;
+        if !session.is_complete() {
+            return Err(AttachmentError::IncompleteUpload {
+                received: session.received(),
+                size: session.size,
+            });
+        }
+
+        let mut data = bytes::BytesMut::with_capacity(session.size as usize);
+        for chunk in &session.chunks {
+            data.extend_from_slice(&self.storage.get(&session.chunk_key(chunk)).await?);
+        }
+        let data = data.freeze();
+
+        let actual = session.digest_algorithm.digest(&data);
+        if !DigestAlgorithm::matches(digest, &actual) {
+            self.discard_upload(&session).await?;
+            return Err(AttachmentError::DigestMismatch {
+                expected: digest.to_string(),
+                actual,
+            });
+        }
+
+        let created = self.create(session.params.clone(), data, session.author_id).await?;
+        self.discard_upload(&session).await?;
+        info!(upload = %id, id = created.attachment.id, "Upload completed");
+
+        Ok(created)
+    }
+
+    /// Delete the staged chunks and the session of an upload
+    async fn discard_upload(&self, session: &UploadSession) -> AttachmentResult<()> {
+        for chunk in &session.chunks {
+            self.storage.delete(&session.chunk_key(chunk)).await?;
+        }
+        self.uploads.delete(&session.id).await
+    }
+
+    /// Store the file and its record
+    async fn store_upload(
+        &self,
+        params: CreateAttachmentParams,
+        data: Bytes,
+        content_type: String,
+        author_id: Id,
+    ) -> AttachmentResult<AttachmentWithUrl> {
         // Generate storage key
         let disk_filename = generate_disk_filename(&params.filename);
 
@@ -342,6 +864,9 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
     }
 
     /// Download attachment data
+    ///
+    /// Every download of the whole file counts towards the downloads of the
+    /// attachment; ranges, see `download_range`, do not.
     #[instrument(skip(self))]
     pub async fn download(&self, id: Id) -> AttachmentResult<(Attachment, Bytes)> {
         let attachment = self
@@ -362,6 +887,26 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
         Ok((attachment, data))
     }
 
+    /// Download a range of the attachment data
+    ///
+    /// Clients resuming a download or seeking in a video fetch a file in
+    /// many ranges, so ranges leave the download count alone.
+    #[instrument(skip(self))]
+    pub async fn download_range(&self, id: Id, range: ByteRange) -> AttachmentResult<(Attachment, Bytes)> {
+        let attachment = self
+            .store
+            .get(id)
+            .await?
+            .ok_or(AttachmentError::NotFound(id))?;
+
+        let data = self
+            .storage
+            .get_range(&attachment.disk_filename, range.start, range.end)
+            .await?;
+
+        Ok((attachment, data))
+    }
+
     /// Attach to a container
     pub async fn attach_to(
         &self,
@@ -375,11 +920,30 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
             .await?
             .ok_or(AttachmentError::NotFound(id))?;
 
+        // Moving between projects counts against the target project's quota
+        let current_project = match (
+            ContainerType::from_str(&attachment.container_type),
+            attachment.container_id,
+        ) {
+            (Some(ct), Some(cid)) => self.project_for(Some((ct, cid))).await?,
+            _ => None,
+        };
+        let target_project = self.project_for(Some((container_type, container_id))).await?;
+        let reservation = if target_project != current_project {
+            Some(self.reserve(target_project, attachment.filesize, false).await?)
+        } else {
+            None
+        };
+
         attachment.container_type = container_type.to_string();
         attachment.container_id = Some(container_id);
         attachment.updated_at = chrono::Utc::now();
 
-        self.store.update(&attachment).await?;
+        let result = self.store.update(&attachment).await;
+        if let Some(reservation) = reservation {
+            self.release(reservation).await;
+        }
+        result?;
 
         info!(
             id = id,
@@ -391,6 +955,51 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
         Ok(attachment)
     }
 
+    /// Rename an attachment or change its description
+    ///
+    /// The stored file keeps its disk filename. A new name passes the same
+    /// extension rules as an upload; when it changes the extension, the
+    /// content type is derived from the new name. Names need not be unique,
+    /// not even within a container.
+    #[instrument(skip(self, params))]
+    pub async fn update_metadata(&self, id: Id, params: UpdateAttachmentParams) -> AttachmentResult<Attachment> {
+        let mut attachment = self
+            .store
+            .get(id)
+            .await?
+            .ok_or(AttachmentError::NotFound(id))?;
+
+        if let Some(filename) = params.filename {
+            let filename = filename.trim().to_string();
+            if filename.is_empty() {
+                return Err(AttachmentError::InvalidFile("filename can't be blank".to_string()));
+            }
+            if filename.contains(['/', '\\']) {
+                return Err(AttachmentError::InvalidFile(format!("{} is not a file name", filename)));
+            }
+            self.config.allowed_types.check_filename(&filename)?;
+
+            if file_extensions(&filename).last() != file_extensions(&attachment.filename).last() {
+                let content_type = mime_guess::from_path(&filename).first_or_octet_stream().to_string();
+                if !self.config.allowed_types.is_allowed(&content_type) {
+                    return Err(AttachmentError::InvalidContentType(content_type));
+                }
+                attachment.content_type = content_type;
+            }
+            attachment.filename = filename;
+        }
+
+        if let Some(description) = params.description {
+            attachment.description = description;
+        }
+
+        attachment.updated_at = chrono::Utc::now();
+        self.store.update_metadata(&attachment).await?;
+        info!(id = id, filename = %attachment.filename, "Attachment metadata updated");
+
+        Ok(attachment)
+    }
+
     /// Delete an attachment
     #[instrument(skip(self))]
     pub async fn delete(&self, id: Id) -> AttachmentResult<()> {
@@ -411,12 +1020,20 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
         Ok(())
     }
 
-    /// Cleanup orphaned attachments
+    /// Cleanup orphaned attachments, and uploads that received no chunk for
+    /// as long
     #[instrument(skip(self))]
     pub async fn cleanup_orphans(&self) -> AttachmentResult<usize> {
         let cutoff = chrono::Utc::now() - chrono::Duration::from_std(self.config.cleanup_orphans_after)
             .unwrap_or(chrono::Duration::days(1));
 
+        for session in self.uploads.get_stale(cutoff).await? {
+            match self.discard_upload(&session).await {
+                Ok(()) => info!(upload = %session.id, "Stale upload purged"),
+                Err(e) => warn!(upload = %session.id, error = %e, "Failed to purge stale upload"),
+            }
+        }
+
         let orphans = self.store.get_orphaned(cutoff).await?;
         let count = orphans.len();
 
@@ -447,6 +1064,25 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
             .await?
             .ok_or(AttachmentError::NotFound(id))?;
 
+        // Check quotas
+        let project_id = self.project_for(Some((container_type, container_id))).await?;
+        let reservation = self.reserve(project_id, source.filesize, true).await?;
+
+        let result = self
+            .store_copy(&source, container_type, container_id, author_id)
+            .await;
+        self.release(reservation).await;
+        result
+    }
+
+    /// Copy the file and create the record of the copy
+    async fn store_copy(
+        &self,
+        source: &Attachment,
+        container_type: ContainerType,
+        container_id: Id,
+        author_id: Id,
+    ) -> AttachmentResult<AttachmentWithUrl> {
         // Create new disk filename
         let new_disk_filename = generate_disk_filename(&source.filename);
 
@@ -471,7 +1107,7 @@ impl<St: AttachmentStore, S: Storage> AttachmentService<St, S> {
         let new_id = self.store.create(&mut new_attachment).await?;
 
         info!(
-            source_id = id,
+            source_id = source.id,
             new_id = new_id,
             container = %container_type,
             "Attachment copied"
@@ -552,6 +1188,26 @@ mod tests {
         assert_eq!(updated.downloads, 1);
     }
 
+    #[tokio::test]
+    async fn test_download_range_leaves_count() {
+        let service = create_service();
+
+        let created = service
+            .create(CreateAttachmentParams::new("video.txt"), Bytes::from("0123456789"), 1)
+            .await
+            .unwrap();
+        let id = created.attachment.id.unwrap();
+
+        let (_, downloaded) = service
+            .download_range(id, ByteRange { start: 2, end: 4 })
+            .await
+            .unwrap();
+        assert_eq!(downloaded, "234");
+
+        let updated = service.get(id).await.unwrap().unwrap();
+        assert_eq!(updated.downloads, 0);
+    }
+
     #[tokio::test]
     async fn test_delete_attachment() {
         let service = create_service();
@@ -605,6 +1261,99 @@ mod tests {
         assert!(matches!(result, Err(AttachmentError::InvalidContentType(_))));
     }
 
+    #[tokio::test]
+    async fn test_double_extension_rejected() {
+        let service = create_service();
+
+        for filename in ["invoice.pdf.exe", "setup.exe.txt"] {
+            let result = service
+                .create(
+                    CreateAttachmentParams::new(filename).content_type("application/pdf"),
+                    Bytes::from("MZ"),
+                    1,
+                )
+                .await;
+            let err = result.unwrap_err();
+            assert!(matches!(
+                err,
+                AttachmentError::FileRejected(FileRule::DoubleExtension { ref extension, .. }) if extension == "exe"
+            ));
+            assert!(err.to_string().contains("double extension"));
+        }
+    }
+
+    #[test]
+    fn test_extension_rules() {
+        let mut allowed = AllowedFileTypes {
+            allowed_extensions: vec![".PDF".to_string(), "докс".to_string()],
+            ..Default::default()
+        };
+
+        assert!(allowed.check_filename("report.pdf").is_ok());
+        assert!(allowed.check_filename("report.v2.PDF.").is_ok());
+        assert!(allowed.check_filename("отчёт.ДОКС").is_ok());
+        assert_eq!(
+            allowed.check_filename("README"),
+            Err(FileRule::MissingExtension("README".to_string()))
+        );
+        assert_eq!(
+            allowed.check_filename("notes.txt"),
+            Err(FileRule::ExtensionNotAllowed("txt".to_string()))
+        );
+        assert_eq!(
+            allowed.check_filename("virus.exe..."),
+            Err(FileRule::ExtensionBlocked("exe".to_string()))
+        );
+        assert!(matches!(
+            allowed.check_filename("invoice.pdf.exe"),
+            Err(FileRule::DoubleExtension { .. })
+        ));
+
+        allowed.allowed_extensions.clear();
+        assert!(allowed.check_filename("README").is_ok());
+        assert!(allowed.check_filename("notes.txt").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_strict_content_match() {
+        let store = Arc::new(MemoryAttachmentStore::new());
+        let storage = Arc::new(MemoryStorage::new());
+        let mut config = AttachmentConfig::default();
+        config.allowed_types.strict_content_match = true;
+        let service = AttachmentService::new(store, storage, config);
+
+        let result = service
+            .create(CreateAttachmentParams::new("cat.png"), Bytes::from_static(b"MZ\x90\x00"), 1)
+            .await;
+        let err = result.unwrap_err();
+        assert!(matches!(err, AttachmentError::FileRejected(FileRule::ContentMismatch { .. })));
+        assert!(err.to_string().contains("declared as image/png"));
+
+        let result = service
+            .create(CreateAttachmentParams::new("notes.txt"), Bytes::from("#!/bin/sh\necho hi"), 1)
+            .await;
+        assert!(matches!(result, Err(AttachmentError::FileRejected(_))));
+
+        let result = service
+            .create(CreateAttachmentParams::new("notes.txt"), Bytes::from("just notes"), 1)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_from_storage_config() {
+        let mut config = op_core::config::AppConfig::default().storage;
+        config.allowed_extensions = vec!["pdf".to_string()];
+        config.blocked_extensions = vec!["sh".to_string()];
+        config.strict_content_match = true;
+
+        let allowed = AllowedFileTypes::from_storage_config(&config);
+        assert!(allowed.strict_content_match);
+        assert_eq!(allowed.max_file_size, config.max_attachment_size as i64);
+        assert!(matches!(allowed.check_filename("run.sh"), Err(FileRule::ExtensionBlocked(_))));
+        assert!(matches!(allowed.check_filename("run.bat"), Err(FileRule::ExtensionBlocked(_))));
+    }
+
     #[tokio::test]
     async fn test_attach_to_container() {
         let service = create_service();
@@ -724,4 +1473,347 @@ mod tests {
             .unwrap();
         assert_eq!(count, 5);
     }
+
+    async fn create_quota_service(
+        project_quota: Option<i64>,
+        instance_quota: Option<i64>,
+    ) -> (
+        AttachmentService<MemoryAttachmentStore, MemoryStorage>,
+        Arc<MemoryAttachmentStore>,
+    ) {
+        let store = Arc::new(MemoryAttachmentStore::new());
+        store.set_container_project(ContainerType::WorkPackage, 100, 1).await;
+        store.set_container_project(ContainerType::WorkPackage, 200, 2).await;
+        store.set_project_quota(1, project_quota).await;
+
+        let config = AttachmentConfig {
+            instance_quota,
+            ..Default::default()
+        };
+        let service = AttachmentService::new(store.clone(), Arc::new(MemoryStorage::new()), config);
+        (service, store)
+    }
+
+    fn upload_to(container_id: Id) -> CreateAttachmentParams {
+        CreateAttachmentParams::new("notes.txt").container(ContainerType::WorkPackage, container_id)
+    }
+
+    #[tokio::test]
+    async fn test_project_quota() {
+        let (service, store) = create_quota_service(Some(10), None).await;
+
+        service.create(upload_to(100), Bytes::from("123456"), 1).await.unwrap();
+        let err = service
+            .create(upload_to(100), Bytes::from("12345"), 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AttachmentError::QuotaExceeded { project_id: Some(1), size: 5, used: 6, limit: 10 }
+        ));
+
+        // Fits exactly, and other projects are unaffected
+        service.create(upload_to(100), Bytes::from("1234"), 1).await.unwrap();
+        service.create(upload_to(200), Bytes::from("12345678901"), 1).await.unwrap();
+        assert_eq!(store.total_size_for_project(1).await.unwrap(), 10);
+        assert_eq!(store.total_size_for_project(2).await.unwrap(), 11);
+    }
+
+    #[tokio::test]
+    async fn test_instance_quota() {
+        let (service, _store) = create_quota_service(None, Some(8)).await;
+
+        service.create(upload_to(200), Bytes::from("12345"), 1).await.unwrap();
+        let err = service
+            .create(CreateAttachmentParams::new("orphan.txt"), Bytes::from("1234"), 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AttachmentError::QuotaExceeded { project_id: None, used: 5, limit: 8, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_copy_and_attach_enforce_quota() {
+        let (service, _store) = create_quota_service(Some(10), None).await;
+
+        let source = service.create(upload_to(200), Bytes::from("1234567"), 1).await.unwrap();
+        let source_id = source.attachment.id.unwrap();
+
+        service.copy_to(source_id, ContainerType::WorkPackage, 100, 1).await.unwrap();
+        let err = service
+            .copy_to(source_id, ContainerType::WorkPackage, 100, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AttachmentError::QuotaExceeded { project_id: Some(1), .. }));
+
+        let err = service
+            .attach_to(source_id, ContainerType::WorkPackage, 100)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AttachmentError::QuotaExceeded { project_id: Some(1), .. }));
+
+        // Moving within a project does not count again
+        let orphan = service
+            .create(CreateAttachmentParams::new("orphan.txt"), Bytes::from("123"), 1)
+            .await
+            .unwrap();
+        let orphan_id = orphan.attachment.id.unwrap();
+        service.attach_to(orphan_id, ContainerType::WorkPackage, 100).await.unwrap();
+        service.attach_to(orphan_id, ContainerType::Project, 1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_uploads_cannot_exceed_quota() {
+        let (service, store) = create_quota_service(Some(10), None).await;
+
+        let upload = || service.create(upload_to(100), Bytes::from("123456"), 1);
+        let (a, b, c) = tokio::join!(upload(), upload(), upload());
+        let results = [a, b, c];
+
+        let stored = results.iter().filter(|r| r.is_ok()).count();
+        assert_eq!(stored, 1);
+        assert!(results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|e| matches!(e, AttachmentError::QuotaExceeded { .. })));
+        assert_eq!(store.total_size_for_project(1).await.unwrap(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_rename_keeps_stored_file() {
+        let service = create_service();
+        let created = service
+            .create(CreateAttachmentParams::new("notes.txt").description("Draft"), Bytes::from("notes"), 1)
+            .await
+            .unwrap()
+            .attachment;
+        let id = created.id.unwrap();
+
+        let renamed = service
+            .update_metadata(id, UpdateAttachmentParams::new().filename("Meeting notes.TXT").description(None))
+            .await
+            .unwrap();
+        assert_eq!(renamed.filename, "Meeting notes.TXT");
+        assert_eq!(renamed.content_type, "text/plain");
+        assert_eq!(renamed.description, None);
+        assert_eq!(renamed.disk_filename, created.disk_filename);
+
+        let (stored, data) = service.download(id).await.unwrap();
+        assert_eq!(stored.filename, "Meeting notes.TXT");
+        assert_eq!(data, Bytes::from("notes"));
+    }
+
+    #[tokio::test]
+    async fn test_rename_to_another_extension_derives_content_type() {
+        let service = create_service();
+        let id = service
+            .create(CreateAttachmentParams::new("export.txt"), Bytes::from("a,b"), 1)
+            .await
+            .unwrap()
+            .attachment
+            .id
+            .unwrap();
+
+        let renamed = service
+            .update_metadata(id, UpdateAttachmentParams::new().filename("export.csv"))
+            .await
+            .unwrap();
+        assert_eq!(renamed.content_type, "text/csv");
+
+        for filename in ["export.exe", "export.csv.exe", "  ", "../export.csv"] {
+            let result = service
+                .update_metadata(id, UpdateAttachmentParams::new().filename(filename))
+                .await;
+            assert!(result.is_err(), "{} was accepted", filename);
+        }
+        assert_eq!(service.get(id).await.unwrap().unwrap().filename, "export.csv");
+    }
+
+    #[tokio::test]
+    async fn test_rename_allows_names_taken_in_the_container() {
+        let service = create_service();
+        let upload = |filename: &str| {
+            service.create(
+                CreateAttachmentParams::new(filename).container(ContainerType::WorkPackage, 100),
+                Bytes::from("content"),
+                1,
+            )
+        };
+        upload("report.pdf").await.unwrap();
+        let other = upload("summary.pdf").await.unwrap().attachment.id.unwrap();
+
+        // Filenames are not unique, the attachments stay apart by id
+        service
+            .update_metadata(other, UpdateAttachmentParams::new().filename("report.pdf"))
+            .await
+            .unwrap();
+        let attachments = service.get_for_container(ContainerType::WorkPackage, 100).await.unwrap();
+        assert_eq!(attachments.len(), 2);
+        assert!(attachments.iter().all(|a| a.filename == "report.pdf"));
+    }
+
+    #[tokio::test]
+    async fn test_update_metadata_of_missing_attachment() {
+        let service = create_service();
+
+        let result = service.update_metadata(42, UpdateAttachmentParams::new().filename("a.txt")).await;
+        assert!(matches!(result, Err(AttachmentError::NotFound(42))));
+    }
+
+    /// Start an upload of the data and send its chunks from `first` on
+    async fn upload_chunks(
+        service: &AttachmentService<MemoryAttachmentStore, MemoryStorage>,
+        session: &UploadSession,
+        chunks: &[&'static str],
+        first: usize,
+    ) -> AttachmentResult<UploadSession> {
+        let mut current = session.clone();
+        for (number, chunk) in chunks.iter().enumerate().skip(first) {
+            current = service
+                .upload_chunk(&session.id, number as u32, Bytes::from(*chunk), None, session.author_id)
+                .await?;
+        }
+        Ok(current)
+    }
+
+    #[tokio::test]
+    async fn test_chunks_out_of_order_are_rejected() {
+        let service = create_service();
+        let session = service
+            .start_upload(CreateAttachmentParams::new("model.ifc"), 12, DigestAlgorithm::Sha256, 1)
+            .await
+            .unwrap();
+
+        let skipped = service.upload_chunk(&session.id, 1, Bytes::from("uvwx"), None, 1).await;
+        assert!(matches!(skipped, Err(AttachmentError::ChunkOutOfOrder { expected: 0, received: 1 })));
+
+        upload_chunks(&service, &session, &["abcd"], 0).await.unwrap();
+        let repeated = service.upload_chunk(&session.id, 0, Bytes::from("abcd"), None, 1).await;
+        assert!(matches!(repeated, Err(AttachmentError::ChunkOutOfOrder { expected: 1, received: 0 })));
+
+        let corrupted = service
+            .upload_chunk(&session.id, 1, Bytes::from("efgh"), Some(&DigestAlgorithm::Sha256.digest(b"efgX")), 1)
+            .await;
+        assert!(matches!(corrupted, Err(AttachmentError::DigestMismatch { .. })));
+        let oversized = service.upload_chunk(&session.id, 1, Bytes::from("efghijklm"), None, 1).await;
+        assert!(matches!(oversized, Err(AttachmentError::InvalidFile(_))));
+
+        // Sessions of other users are not found
+        let foreign = service.upload_chunk(&session.id, 1, Bytes::from("efgh"), None, 2).await;
+        assert!(matches!(foreign, Err(AttachmentError::UploadNotFound(_))));
+        assert_eq!(service.upload_session(&session.id, 1).await.unwrap().received(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_upload_resumes_after_disconnect() {
+        let service = create_service();
+        let chunks = ["BIM ", "mode", "l da", "ta"];
+        let session = service
+            .start_upload(
+                CreateAttachmentParams::new("model.ifc").container(ContainerType::WorkPackage, 100),
+                14,
+                DigestAlgorithm::Sha256,
+                1,
+            )
+            .await
+            .unwrap();
+        upload_chunks(&service, &session, &chunks[..2], 0).await.unwrap();
+
+        // The client reconnects and learns where to go on
+        let resumed = service.upload_session(&session.id, 1).await.unwrap();
+        assert_eq!(resumed.next_chunk(), 2);
+        assert_eq!(resumed.received_ranges(), vec![ByteRange { start: 0, end: 3 }, ByteRange { start: 4, end: 7 }]);
+        let incomplete = service.complete_upload(&session.id, "", 1).await;
+        assert!(matches!(incomplete, Err(AttachmentError::IncompleteUpload { received: 8, size: 14 })));
+
+        let uploaded = upload_chunks(&service, &resumed, &chunks, 2).await.unwrap();
+        assert!(uploaded.is_complete());
+        let digest = DigestAlgorithm::Sha256.digest(b"BIM model data");
+        let created = service.complete_upload(&session.id, &digest, 1).await.unwrap();
+
+        assert_eq!(created.attachment.filesize, 14);
+        assert_eq!(created.attachment.digest, digest);
+        assert_eq!(created.attachment.container_id, Some(100));
+        let (_, data) = service.download(created.attachment.id.unwrap()).await.unwrap();
+        assert_eq!(data, Bytes::from("BIM model data"));
+
+        // The staged chunks and the session are gone
+        assert!(!service.storage.exists(&session.chunk_key(&uploaded.chunks[0])).await.unwrap());
+        assert!(matches!(
+            service.upload_session(&session.id, 1).await,
+            Err(AttachmentError::UploadNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_digest_mismatch_at_completion_discards_upload() {
+        let service = create_service();
+        let session = service
+            .start_upload(CreateAttachmentParams::new("model.ifc"), 8, DigestAlgorithm::Sha256, 1)
+            .await
+            .unwrap();
+        let uploaded = upload_chunks(&service, &session, &["abcd", "efgh"], 0).await.unwrap();
+
+        let result = service
+            .complete_upload(&session.id, &DigestAlgorithm::Sha256.digest(b"abcdefgX"), 1)
+            .await;
+        assert!(matches!(result, Err(AttachmentError::DigestMismatch { .. })));
+        assert!(service.upload_session(&session.id, 1).await.is_err());
+        assert!(!service.storage.exists(&session.chunk_key(&uploaded.chunks[1])).await.unwrap());
+        assert_eq!(service.store.total_size().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_upload_limits_apply_at_start_and_completion() {
+        let config = AttachmentConfig {
+            instance_quota: Some(10),
+            ..Default::default()
+        };
+        let service = AttachmentService::new(
+            Arc::new(MemoryAttachmentStore::new()),
+            Arc::new(MemoryStorage::new()),
+            config,
+        );
+        let start = |filename: &'static str, size: i64| {
+            service.start_upload(CreateAttachmentParams::new(filename), size, DigestAlgorithm::Sha256, 1)
+        };
+        assert!(matches!(start("model.ifc", 11).await, Err(AttachmentError::QuotaExceeded { .. })));
+        assert!(matches!(start("setup.exe", 4).await, Err(AttachmentError::FileRejected(_))));
+        assert!(matches!(start("model.ifc", 0).await, Err(AttachmentError::InvalidFile(_))));
+
+        // Room when started, but the space went to another upload since
+        let session = start("model.ifc", 8).await.unwrap();
+        upload_chunks(&service, &session, &["abcdefgh"], 0).await.unwrap();
+        service.create(CreateAttachmentParams::new("notes.txt"), Bytes::from("notes"), 1).await.unwrap();
+        let digest = DigestAlgorithm::Sha256.digest(b"abcdefgh");
+        let result = service.complete_upload(&session.id, &digest, 1).await;
+        assert!(matches!(result, Err(AttachmentError::QuotaExceeded { .. })));
+
+        // The upload is kept to be completed once there is room
+        assert!(service.upload_session(&session.id, 1).await.unwrap().is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_purges_stale_uploads() {
+        let config = AttachmentConfig {
+            cleanup_orphans_after: Duration::ZERO,
+            ..Default::default()
+        };
+        let service = AttachmentService::new(
+            Arc::new(MemoryAttachmentStore::new()),
+            Arc::new(MemoryStorage::new()),
+            config,
+        );
+        let session = service
+            .start_upload(CreateAttachmentParams::new("model.ifc"), 8, DigestAlgorithm::Sha256, 1)
+            .await
+            .unwrap();
+        let uploaded = upload_chunks(&service, &session, &["abcd"], 0).await.unwrap();
+
+        service.cleanup_orphans().await.unwrap();
+        assert!(service.upload_session(&session.id, 1).await.is_err());
+        assert!(!service.storage.exists(&session.chunk_key(&uploaded.chunks[0])).await.unwrap());
+    }
 }
@@ -10,7 +10,7 @@ use bytes::Bytes;
 use sha2::{Digest, Sha256};
 use thiserror::Error;
 use tokio::fs;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tracing::{debug, error, info, instrument};
 use uuid::Uuid;
 
@@ -55,6 +55,10 @@ pub trait Storage: Send + Sync {
     /// Retrieve data by key
     async fn get(&self, key: &str) -> StorageResult<Bytes>;
 
+    /// Retrieve bytes `start` to `end`, both inclusive, of the data of a key;
+    /// an end past the end of the data is cut to it
+    async fn get_range(&self, key: &str, start: u64, end: u64) -> StorageResult<Bytes>;
+
     /// Delete data by key
     async fn delete(&self, key: &str) -> StorageResult<()>;
 
@@ -171,6 +175,25 @@ impl Storage for LocalStorage {
         Ok(Bytes::from(buffer))
     }
 
+    #[instrument(skip(self), fields(storage = "local"))]
+    async fn get_range(&self, key: &str, start: u64, end: u64) -> StorageResult<Bytes> {
+        let path = self.resolve_path(key)?;
+
+        if !path.exists() {
+            return Err(StorageError::NotFound(key.to_string()));
+        }
+
+        // Only the range is read, not the whole file
+        let mut file = fs::File::open(&path).await?;
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+        let mut buffer = Vec::new();
+        file.take(end.saturating_sub(start).saturating_add(1))
+            .read_to_end(&mut buffer)
+            .await?;
+
+        Ok(Bytes::from(buffer))
+    }
+
     #[instrument(skip(self), fields(storage = "local"))]
     async fn delete(&self, key: &str) -> StorageResult<()> {
         let path = self.resolve_path(key)?;
@@ -283,6 +306,13 @@ impl Storage for MemoryStorage {
             .ok_or_else(|| StorageError::NotFound(key.to_string()))
     }
 
+    async fn get_range(&self, key: &str, start: u64, end: u64) -> StorageResult<Bytes> {
+        let data = self.get(key).await?;
+        let len = data.len() as u64;
+        let end = end.saturating_add(1).min(len);
+        Ok(data.slice(start.min(end) as usize..end as usize))
+    }
+
     async fn delete(&self, key: &str) -> StorageResult<()> {
         let mut files = self.files.write().await;
         files.remove(key);
@@ -395,6 +425,12 @@ impl Storage for S3Storage {
         Err(StorageError::BackendError("S3 not implemented".to_string()))
     }
 
+    async fn get_range(&self, _key: &str, _start: u64, _end: u64) -> StorageResult<Bytes> {
+        // Would be a GET of the object with `Range: bytes={start}-{end}`
+        error!("S3 storage not fully implemented");
+        Err(StorageError::BackendError("S3 not implemented".to_string()))
+    }
+
     async fn delete(&self, key: &str) -> StorageResult<()> {
         error!("S3 storage not fully implemented");
         Err(StorageError::BackendError("S3 not implemented".to_string()))
@@ -488,6 +524,30 @@ mod tests {
         assert_eq!(copied, data);
     }
 
+    #[tokio::test]
+    async fn test_get_range() {
+        let memory = MemoryStorage::new();
+        let dir = std::env::temp_dir().join(format!("openproject-attachments-{}", Uuid::new_v4()));
+        let local = LocalStorage::new(&dir, "/attachments");
+        let storages: [&dyn Storage; 2] = [&memory, &local];
+
+        for storage in storages {
+            storage.put("range.txt", Bytes::from("0123456789")).await.unwrap();
+
+            assert_eq!(storage.get_range("range.txt", 0, 3).await.unwrap(), "0123");
+            assert_eq!(storage.get_range("range.txt", 7, 9).await.unwrap(), "789");
+            assert_eq!(storage.get_range("range.txt", 5, 5).await.unwrap(), "5");
+            // The end is cut to the end of the data
+            assert_eq!(storage.get_range("range.txt", 8, 100).await.unwrap(), "89");
+            assert!(matches!(
+                storage.get_range("missing.txt", 0, 1).await,
+                Err(StorageError::NotFound(_))
+            ));
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[tokio::test]
     async fn test_memory_storage_not_found() {
         let storage = MemoryStorage::new();
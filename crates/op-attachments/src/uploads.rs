@@ -0,0 +1,231 @@
+//! Resumable Uploads
+//!
+//! Very large files are uploaded in chunks to an upload session instead of
+//! in one request. Chunks are numbered from 0 and accepted in sequence
+//! only; each is staged in the attachment storage until the session is
+//! completed into an attachment. A client that lost its connection reads
+//! the session to learn which bytes arrived and resumes with the next
+//! chunk. Sessions that receive nothing for a while are purged with the
+//! orphaned attachments.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::model::CreateAttachmentParams;
+use crate::range::ByteRange;
+use crate::service::AttachmentResult;
+
+/// Algorithm of the digests of chunks and of the complete file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" | "sha-256" => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    /// Hex digest of the data
+    pub fn digest(&self, data: &[u8]) -> String {
+        match self {
+            Self::Sha256 => hex::encode(Sha256::digest(data)),
+        }
+    }
+
+    /// Whether a digest given by a client, in any case, is the computed one
+    pub fn matches(expected: &str, actual: &str) -> bool {
+        expected.trim().eq_ignore_ascii_case(actual)
+    }
+}
+
+/// A chunk received by an upload session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadChunk {
+    pub number: u32,
+    pub size: i64,
+    /// Digest of the chunk's data in the session's algorithm
+    pub digest: String,
+}
+
+/// An upload in progress
+#[derive(Debug, Clone)]
+pub struct UploadSession {
+    pub id: String,
+    pub author_id: Id,
+    /// Name, description and container of the attachment to create
+    pub params: CreateAttachmentParams,
+    /// Size in bytes the complete file is expected to have
+    pub size: i64,
+    pub digest_algorithm: DigestAlgorithm,
+    /// Chunks received so far, in order
+    pub chunks: Vec<UploadChunk>,
+    pub created_at: DateTime<Utc>,
+    /// When the last chunk was received
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UploadSession {
+    pub fn new(params: CreateAttachmentParams, size: i64, digest_algorithm: DigestAlgorithm, author_id: Id) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            author_id,
+            params,
+            size,
+            digest_algorithm,
+            chunks: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Number of the chunk expected next
+    pub fn next_chunk(&self) -> u32 {
+        self.chunks.len() as u32
+    }
+
+    /// Bytes received so far
+    pub fn received(&self) -> i64 {
+        self.chunks.iter().map(|chunk| chunk.size).sum()
+    }
+
+    /// Bytes of the file each chunk holds, in order
+    pub fn received_ranges(&self) -> Vec<ByteRange> {
+        let mut start = 0;
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                let range = ByteRange {
+                    start,
+                    end: start + chunk.size as u64 - 1,
+                };
+                start += chunk.size as u64;
+                range
+            })
+            .collect()
+    }
+
+    /// Whether all the bytes of the file arrived
+    pub fn is_complete(&self) -> bool {
+        self.received() == self.size
+    }
+
+    /// Storage key the data of a chunk is staged under
+    pub fn chunk_key(&self, chunk: &UploadChunk) -> String {
+        format!("uploads/{}/{}-{}", self.id, chunk.number, chunk.digest)
+    }
+}
+
+/// Upload session store trait
+#[async_trait]
+pub trait UploadSessionStore: Send + Sync {
+    /// Store a new session
+    async fn create(&self, session: &UploadSession) -> AttachmentResult<()>;
+
+    /// Get a session by id
+    async fn get(&self, id: &str) -> AttachmentResult<Option<UploadSession>>;
+
+    /// Append the chunk if it is the one the session expects next; whether
+    /// it was appended
+    async fn append_chunk(&self, id: &str, chunk: &UploadChunk) -> AttachmentResult<bool>;
+
+    /// Delete a session
+    async fn delete(&self, id: &str) -> AttachmentResult<()>;
+
+    /// Sessions that received nothing since `older_than`
+    async fn get_stale(&self, older_than: DateTime<Utc>) -> AttachmentResult<Vec<UploadSession>>;
+}
+
+/// In-memory upload session store for testing
+#[derive(Default)]
+pub struct MemoryUploadSessionStore {
+    sessions: RwLock<HashMap<String, UploadSession>>,
+}
+
+impl MemoryUploadSessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UploadSessionStore for MemoryUploadSessionStore {
+    async fn create(&self, session: &UploadSession) -> AttachmentResult<()> {
+        self.sessions.write().await.insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> AttachmentResult<Option<UploadSession>> {
+        Ok(self.sessions.read().await.get(id).cloned())
+    }
+
+    async fn append_chunk(&self, id: &str, chunk: &UploadChunk) -> AttachmentResult<bool> {
+        let mut sessions = self.sessions.write().await;
+        let Some(session) = sessions.get_mut(id).filter(|session| session.next_chunk() == chunk.number) else {
+            return Ok(false);
+        };
+        session.chunks.push(chunk.clone());
+        session.updated_at = Utc::now();
+        Ok(true)
+    }
+
+    async fn delete(&self, id: &str) -> AttachmentResult<()> {
+        self.sessions.write().await.remove(id);
+        Ok(())
+    }
+
+    async fn get_stale(&self, older_than: DateTime<Utc>) -> AttachmentResult<Vec<UploadSession>> {
+        Ok(self
+            .sessions
+            .read()
+            .await
+            .values()
+            .filter(|session| session.updated_at < older_than)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_received_ranges() {
+        let mut session = UploadSession::new(CreateAttachmentParams::new("model.ifc"), 10, DigestAlgorithm::Sha256, 1);
+        assert_eq!(session.received_ranges(), vec![]);
+
+        for (number, size) in [(0, 4), (1, 4)] {
+            session.chunks.push(UploadChunk { number, size, digest: String::new() });
+        }
+        assert_eq!(
+            session.received_ranges(),
+            vec![ByteRange { start: 0, end: 3 }, ByteRange { start: 4, end: 7 }]
+        );
+        assert_eq!((session.next_chunk(), session.received(), session.is_complete()), (2, 8, false));
+    }
+
+    #[test]
+    fn test_digest_algorithm() {
+        assert_eq!(DigestAlgorithm::parse("SHA-256"), Some(DigestAlgorithm::Sha256));
+        assert_eq!(DigestAlgorithm::parse("md5"), None);
+        let digest = DigestAlgorithm::Sha256.digest(b"abc");
+        assert!(digest.starts_with("ba7816bf"));
+        assert!(DigestAlgorithm::matches(&digest.to_uppercase(), &digest));
+    }
+}
@@ -0,0 +1,102 @@
+//! Upload Validation
+//!
+//! Filename and content checks applied before an upload is stored. The
+//! claimed content type is derived from the filename unless given, so it is
+//! trivially spoofed; these rules look at the extensions themselves and,
+//! optionally, at the leading bytes of the file.
+
+use thiserror::Error;
+
+/// Content type reported for files starting with a shebang line
+pub const SCRIPT_CONTENT_TYPE: &str = "text/x-shellscript";
+
+/// Extensions blocked by default: executables and scripts run by double click
+pub const DEFAULT_BLOCKED_EXTENSIONS: &[&str] = &[
+    "exe", "com", "bat", "cmd", "msi", "scr", "pif", "cpl", "hta", "vbs", "vbe", "wsf", "ps1",
+];
+
+/// Upload rule that rejected a file
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FileRule {
+    #[error("extension allowlist: '{0}' has no extension")]
+    MissingExtension(String),
+    #[error("extension allowlist: '.{0}' is not an allowed extension")]
+    ExtensionNotAllowed(String),
+    #[error("extension denylist: '.{0}' is a blocked extension")]
+    ExtensionBlocked(String),
+    #[error("double extension: '{filename}' contains the blocked extension '.{extension}'")]
+    DoubleExtension { filename: String, extension: String },
+    #[error("content match: content is {detected} but was declared as {claimed}")]
+    ContentMismatch { claimed: String, detected: String },
+}
+
+/// Extensions of a filename, lowercased, outermost last
+///
+/// Directories are stripped and trailing dots and whitespace are ignored, as
+/// Windows drops them when saving ("virus.exe." opens as "virus.exe"). A
+/// leading dot marks a hidden file rather than an extension.
+pub fn file_extensions(filename: &str) -> Vec<String> {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+    let name = name.trim_end_matches(|c: char| c == '.' || c.is_whitespace());
+    let name = name.trim_start_matches('.');
+
+    name.split('.')
+        .skip(1)
+        .map(|segment| segment.trim().to_lowercase())
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Normalize a configured extension (".PDF" matches "pdf")
+pub fn normalize_extension(extension: &str) -> String {
+    extension.trim().trim_start_matches('.').to_lowercase()
+}
+
+/// Content type of executable or script content, sniffed from magic bytes
+pub fn detect_executable(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"#!") {
+        return Some(SCRIPT_CONTENT_TYPE);
+    }
+
+    infer::get(data)
+        .filter(|kind| kind.matcher_type() == infer::MatcherType::App)
+        .map(|kind| kind.mime_type())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_extensions() {
+        assert!(file_extensions("README").is_empty());
+        assert!(file_extensions(".bashrc").is_empty());
+        assert_eq!(file_extensions("invoice.pdf.exe"), vec!["pdf", "exe"]);
+        assert_eq!(file_extensions("C:\\Temp\\Setup.EXE"), vec!["exe"]);
+        assert_eq!(file_extensions("uploads/v1.2/notes"), vec![] as Vec<String>);
+    }
+
+    #[test]
+    fn test_trailing_dots_are_ignored() {
+        assert_eq!(file_extensions("virus.exe."), vec!["exe"]);
+        assert_eq!(file_extensions("virus.exe . ."), vec!["exe"]);
+        assert!(file_extensions("no_extension...").is_empty());
+    }
+
+    #[test]
+    fn test_unicode_extensions() {
+        assert_eq!(file_extensions("отчёт.ДОКС"), vec!["докс"]);
+        assert_eq!(normalize_extension(".ÄRZTE"), "ärzte");
+    }
+
+    #[test]
+    fn test_detect_executable() {
+        assert_eq!(detect_executable(b"#!/bin/sh\nrm -rf /"), Some(SCRIPT_CONTENT_TYPE));
+        assert_eq!(
+            detect_executable(b"MZ\x90\x00\x03\x00\x00\x00"),
+            Some("application/vnd.microsoft.portable-executable")
+        );
+        assert_eq!(detect_executable(b"%PDF-1.7"), None);
+        assert_eq!(detect_executable(b"plain text"), None);
+    }
+}
@@ -1,7 +1,13 @@
 //! API Key Authentication
 //!
 //! Mirrors: lib/open_project/authentication/strategies/api_key_strategy.rb
+//!
+//! Keys may be restricted to scopes. A scoped key reads everything its user
+//! may read, but only writes what its scopes allow; keys without scopes,
+//! such as keys created before scopes existed, have the full power of their
+//! user.
 
+use op_db::{ApiKeyRow, ApiKeyStore, CreateApiKeyDto};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -16,6 +22,83 @@ pub enum ApiKeyError {
     Expired,
     #[error("Invalid API key format")]
     InvalidFormat,
+    #[error("Unknown API key scope: {0}")]
+    UnknownScope(String),
+    #[error("API key store error: {0}")]
+    Store(String),
+}
+
+impl From<op_db::RepositoryError> for ApiKeyError {
+    fn from(e: op_db::RepositoryError) -> Self {
+        ApiKeyError::Store(e.to_string())
+    }
+}
+
+/// What a scoped API key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiKeyScope {
+    /// Read only; every scoped key may read
+    #[serde(rename = "read_only")]
+    ReadOnly,
+    /// Create, change and delete work packages
+    #[serde(rename = "work_packages:write")]
+    WorkPackagesWrite,
+    /// Log, change and delete time entries
+    #[serde(rename = "time_entries:write")]
+    TimeEntriesWrite,
+    /// Everything the user may do
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl ApiKeyScope {
+    pub const ALL: [ApiKeyScope; 4] = [
+        ApiKeyScope::ReadOnly,
+        ApiKeyScope::WorkPackagesWrite,
+        ApiKeyScope::TimeEntriesWrite,
+        ApiKeyScope::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::ReadOnly => "read_only",
+            ApiKeyScope::WorkPackagesWrite => "work_packages:write",
+            ApiKeyScope::TimeEntriesWrite => "time_entries:write",
+            ApiKeyScope::Admin => "admin",
+        }
+    }
+
+    /// Whether a key with the scopes may send a request; `write` for any
+    /// method but GET, HEAD and OPTIONS. Writes need `admin`, or the write
+    /// scope of a resource on the path, e.g. `work_packages:write` for
+    /// `/api/v3/projects/1/work_packages`.
+    pub fn allow(scopes: &[ApiKeyScope], write: bool, path: &str) -> bool {
+        if !write || scopes.contains(&ApiKeyScope::Admin) {
+            return true;
+        }
+        path.split('/').any(|segment| match segment {
+            "work_packages" => scopes.contains(&ApiKeyScope::WorkPackagesWrite),
+            "time_entries" => scopes.contains(&ApiKeyScope::TimeEntriesWrite),
+            _ => false,
+        })
+    }
+}
+
+impl std::str::FromStr for ApiKeyScope {
+    type Err = ApiKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| ApiKeyError::UnknownScope(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for ApiKeyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// API key data
@@ -39,6 +122,36 @@ pub struct ApiKey {
     pub created_at: chrono::DateTime<chrono::Utc>,
     /// Last used date
     pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Scopes the key is restricted to, `None` for the full power of its user
+    pub scopes: Option<Vec<ApiKeyScope>>,
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    /// Scopes that are not known are dropped, leaving the key less power
+    fn from(row: ApiKeyRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            hashed_value: row.value,
+            last_chars: row.last_chars,
+            name: row.name,
+            active: true,
+            expires_at: row.expires_on,
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+            scopes: row
+                .scopes
+                .map(|scopes| scopes.iter().filter_map(|s| s.parse().ok()).collect()),
+        }
+    }
+}
+
+/// A newly created key with its secret, which is not stored and so cannot
+/// be shown again
+#[derive(Debug, Clone)]
+pub struct CreatedApiKey {
+    pub key: ApiKey,
+    pub secret: String,
 }
 
 impl ApiKey {
@@ -56,6 +169,13 @@ impl ApiKey {
 
         true
     }
+
+    /// Whether the key may send a request, see [`ApiKeyScope::allow`]
+    pub fn allows(&self, write: bool, path: &str) -> bool {
+        self.scopes
+            .as_deref()
+            .is_none_or(|scopes| ApiKeyScope::allow(scopes, write, path))
+    }
 }
 
 /// API key service for validating keys
@@ -152,6 +272,49 @@ impl ApiKeyService {
             .collect()
     }
 
+    /// Create a key for a user, restricted to the scopes if any
+    ///
+    /// Stored keys are found by the SHA-256 hash of their secret, whatever
+    /// the hash algorithm of the service.
+    pub async fn create(
+        &self,
+        store: &dyn ApiKeyStore,
+        user_id: i64,
+        name: Option<String>,
+        scopes: Option<Vec<ApiKeyScope>>,
+    ) -> Result<CreatedApiKey, ApiKeyError> {
+        let secret = Self::generate_key();
+        let row = store
+            .create(CreateApiKeyDto {
+                user_id,
+                name,
+                value: lookup_hash(&secret),
+                last_chars: Self::get_display_suffix(&secret, 4),
+                scopes: scopes.map(|scopes| scopes.iter().map(|s| s.as_str().to_string()).collect()),
+                expires_on: None,
+            })
+            .await?;
+
+        Ok(CreatedApiKey { key: row.into(), secret })
+    }
+
+    /// The stored key of a secret, recording its use
+    pub async fn authenticate(&self, store: &dyn ApiKeyStore, secret: &str) -> Result<ApiKey, ApiKeyError> {
+        if secret.is_empty() {
+            return Err(ApiKeyError::InvalidFormat);
+        }
+        let key: ApiKey = store
+            .find_by_value(&lookup_hash(secret))
+            .await?
+            .ok_or(ApiKeyError::NotFound)?
+            .into();
+        if !key.is_valid() {
+            return Err(ApiKeyError::Expired);
+        }
+        store.touch(key.id).await?;
+        Ok(key)
+    }
+
     /// Get the last N characters of a key for display
     pub fn get_display_suffix(key: &str, n: usize) -> String {
         if key.len() <= n {
@@ -162,6 +325,11 @@ impl ApiKeyService {
     }
 }
 
+/// Hash a key is looked up by
+fn lookup_hash(secret: &str) -> String {
+    ApiKeyService::new().hash_key(secret)
+}
+
 /// Constant-time comparison to prevent timing attacks
 fn constant_time_compare(a: &str, b: &str) -> bool {
     if a.len() != b.len() {
@@ -277,6 +445,7 @@ mod tests {
             expires_at: None,
             created_at: chrono::Utc::now(),
             last_used_at: None,
+            scopes: None,
         };
 
         assert!(key.is_valid());
@@ -288,4 +457,79 @@ mod tests {
         key.expires_at = Some(chrono::Utc::now() - chrono::Duration::hours(1));
         assert!(!key.is_valid());
     }
+
+    #[test]
+    fn test_scopes_restrict_writes() {
+        use ApiKeyScope::*;
+
+        let read_only = [ReadOnly];
+        assert!(ApiKeyScope::allow(&read_only, false, "/api/v3/work_packages/1"));
+        assert!(!ApiKeyScope::allow(&read_only, true, "/api/v3/work_packages/1"));
+
+        let work_packages = [WorkPackagesWrite];
+        assert!(ApiKeyScope::allow(&work_packages, true, "/api/v3/work_packages/1"));
+        assert!(ApiKeyScope::allow(&work_packages, true, "/api/v3/projects/1/work_packages"));
+        assert!(!ApiKeyScope::allow(&work_packages, true, "/api/v3/time_entries"));
+        assert!(!ApiKeyScope::allow(&work_packages, true, "/api/v3/projects/1"));
+
+        assert!(ApiKeyScope::allow(&[TimeEntriesWrite], true, "/api/v3/time_entries/3"));
+        assert!(ApiKeyScope::allow(&[Admin], true, "/api/v3/users/me/api_keys"));
+        assert!(!ApiKeyScope::allow(&[], true, "/api/v3/work_packages"));
+    }
+
+    #[test]
+    fn test_scope_names() {
+        for scope in ApiKeyScope::ALL {
+            assert_eq!(scope.as_str().parse::<ApiKeyScope>().ok(), Some(scope));
+            assert_eq!(serde_json::to_value(scope).unwrap(), scope.as_str());
+        }
+        assert!(matches!(
+            "write_everything".parse::<ApiKeyScope>(),
+            Err(ApiKeyError::UnknownScope(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_created_keys_authenticate() {
+        let store = op_db::MemoryApiKeyStore::new();
+        let service = ApiKeyService::new();
+
+        let created = service
+            .create(&store, 7, Some("CI".into()), Some(vec![ApiKeyScope::ReadOnly]))
+            .await
+            .unwrap();
+        assert_eq!(created.key.last_chars, ApiKeyService::get_display_suffix(&created.secret, 4));
+        assert_ne!(created.key.hashed_value, created.secret);
+
+        let key = service.authenticate(&store, &created.secret).await.unwrap();
+        assert_eq!(key.user_id, 7);
+        assert_eq!(key.scopes, Some(vec![ApiKeyScope::ReadOnly]));
+        assert!(key.allows(false, "/api/v3/work_packages"));
+        assert!(!key.allows(true, "/api/v3/work_packages/1"));
+        assert!(store.find_by_user(7).await.unwrap()[0].last_used_at.is_some());
+
+        assert!(matches!(service.authenticate(&store, "wrong").await, Err(ApiKeyError::NotFound)));
+    }
+
+    #[test]
+    fn test_keys_without_scopes_have_full_power() {
+        let row = ApiKeyRow {
+            id: 1,
+            user_id: 1,
+            name: None,
+            value: "hash".into(),
+            last_chars: String::new(),
+            scopes: None,
+            expires_on: None,
+            created_at: chrono::Utc::now(),
+            last_used_at: None,
+        };
+        let key = ApiKey::from(row.clone());
+        assert!(key.allows(true, "/api/v3/projects/1"));
+
+        // Unknown scopes grant nothing
+        let key = ApiKey::from(ApiKeyRow { scopes: Some(vec!["everything".into()]), ..row });
+        assert_eq!(key.scopes, Some(vec![]));
+        assert!(!key.allows(true, "/api/v3/projects/1"));
+    }
 }
@@ -5,7 +5,7 @@
 //! ## Features
 //!
 //! - JWT authentication
-//! - API key authentication
+//! - API key authentication, optionally restricted to scopes
 //! - Session-based authentication
 //! - Permission system with role-based access control
 
@@ -15,7 +15,7 @@ pub mod middleware;
 pub mod permissions;
 pub mod session;
 
-pub use api_key::{ApiKey, ApiKeyService};
+pub use api_key::{ApiKey, ApiKeyError, ApiKeyScope, ApiKeyService, CreatedApiKey};
 pub use jwt::{Claims, JwtError, JwtService};
 pub use middleware::{AuthConfig, AuthError, AuthResult, AuthStrategy, Authenticator, RequestHeaders};
 pub use permissions::CurrentUser;
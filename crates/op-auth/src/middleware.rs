@@ -2,10 +2,13 @@
 //!
 //! Provides axum middleware for authenticating requests using various strategies.
 
+use crate::api_key::{ApiKeyError, ApiKeyService};
 use crate::jwt::{extract_bearer_token, JwtService};
 use crate::permissions::CurrentUser;
 use crate::session::{extract_session_id, CookieConfig, SessionStore};
 
+use op_core::audit::{AuditEvent, AuditEventType, AuditLog};
+use op_db::ApiKeyStore;
 use std::sync::Arc;
 use thiserror::Error;
 
@@ -57,12 +60,16 @@ pub struct AuthConfig {
     pub jwt_service: Option<Arc<JwtService>>,
     /// Session store for session-based auth
     pub session_store: Option<Arc<dyn SessionStore>>,
+    /// Store of API keys; without one any long enough key is accepted
+    pub api_keys: Option<Arc<dyn ApiKeyStore>>,
     /// Cookie configuration
     pub cookie_config: CookieConfig,
     /// Whether to allow anonymous access
     pub allow_anonymous: bool,
     /// Enabled authentication strategies (in order of preference)
     pub strategies: Vec<AuthStrategy>,
+    /// Audit log receiving login successes and failures
+    pub audit: Option<AuditLog>,
 }
 
 impl Default for AuthConfig {
@@ -70,6 +77,7 @@ impl Default for AuthConfig {
         Self {
             jwt_service: None,
             session_store: None,
+            api_keys: None,
             cookie_config: CookieConfig::default(),
             allow_anonymous: false,
             strategies: vec![
@@ -77,6 +85,7 @@ impl Default for AuthConfig {
                 AuthStrategy::ApiKey,
                 AuthStrategy::Session,
             ],
+            audit: None,
         }
     }
 }
@@ -109,6 +118,24 @@ impl AuthConfig {
         self
     }
 
+    /// Create config with API key authentication
+    pub fn api_keys(store: Arc<dyn ApiKeyStore>) -> Self {
+        Self {
+            api_keys: Some(store),
+            strategies: vec![AuthStrategy::ApiKey],
+            ..Default::default()
+        }
+    }
+
+    /// Look up API keys in the store
+    pub fn with_api_keys(mut self, store: Arc<dyn ApiKeyStore>) -> Self {
+        self.api_keys = Some(store);
+        if !self.strategies.contains(&AuthStrategy::ApiKey) {
+            self.strategies.push(AuthStrategy::ApiKey);
+        }
+        self
+    }
+
     /// Add session support
     pub fn with_session(mut self, store: Arc<dyn SessionStore>) -> Self {
         self.session_store = Some(store);
@@ -129,6 +156,12 @@ impl AuthConfig {
         self.cookie_config = config;
         self
     }
+
+    /// Record login attempts in the audit log
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
 }
 
 /// Authenticator for validating requests
@@ -144,26 +177,37 @@ impl Authenticator {
 
     /// Authenticate a request using available headers
     pub async fn authenticate(&self, headers: &RequestHeaders) -> AuthResult {
+        let (strategy, result) = self.resolve(headers).await;
+
+        if let (Some(audit), Some(strategy)) = (&self.config.audit, strategy) {
+            audit_login(audit, headers, strategy, &result).await;
+        }
+
+        result
+    }
+
+    /// Run the configured strategies, returning the one that decided the result
+    async fn resolve(&self, headers: &RequestHeaders) -> (Option<AuthStrategy>, AuthResult) {
         for strategy in &self.config.strategies {
             match strategy {
                 AuthStrategy::Jwt => {
                     if let Some(result) = self.try_jwt_auth(headers).await {
-                        return result;
+                        return (Some(*strategy), result);
                     }
                 }
                 AuthStrategy::ApiKey => {
                     if let Some(result) = self.try_api_key_auth(headers).await {
-                        return result;
+                        return (Some(*strategy), result);
                     }
                 }
                 AuthStrategy::Session => {
                     if let Some(result) = self.try_session_auth(headers).await {
-                        return result;
+                        return (Some(*strategy), result);
                     }
                 }
                 AuthStrategy::Basic => {
                     if let Some(result) = self.try_basic_auth(headers).await {
-                        return result;
+                        return (Some(*strategy), result);
                     }
                 }
                 AuthStrategy::OAuth2 => {
@@ -175,9 +219,9 @@ impl Authenticator {
 
         // No authentication found
         if self.config.allow_anonymous {
-            AuthResult::Anonymous
+            (None, AuthResult::Anonymous)
         } else {
-            AuthResult::Failed(AuthError::Required)
+            (None, AuthResult::Failed(AuthError::Required))
         }
     }
 
@@ -206,9 +250,24 @@ impl Authenticator {
     }
 
     /// Try API key authentication
+    ///
+    /// The user of a stored key is restricted to the key's scopes, if any.
     async fn try_api_key_auth(&self, headers: &RequestHeaders) -> Option<AuthResult> {
-        // Check X-OpenProject-API-Key header
-        let api_key = headers.api_key.as_ref()?;
+        // Check X-OpenProject-API-Key header, then Basic auth as `apikey`
+        let api_key = headers.api_key.clone().or_else(|| headers.basic_api_key())?;
+
+        if let Some(store) = &self.config.api_keys {
+            return Some(match ApiKeyService::new().authenticate(store.as_ref(), &api_key).await {
+                Ok(key) => {
+                    let user = CurrentUser::new(key.user_id, format!("user_{}", key.user_id), "")
+                        .with_api_key_scopes(key.scopes);
+                    AuthResult::Authenticated(user)
+                }
+                Err(ApiKeyError::Expired) => AuthResult::Failed(AuthError::TokenExpired),
+                Err(ApiKeyError::Store(e)) => AuthResult::Failed(AuthError::Internal(e)),
+                Err(_) => AuthResult::Failed(AuthError::InvalidCredentials),
+            });
+        }
 
         // In a real implementation, we would look up the API key in the database
         // For now, we just validate the format
@@ -261,6 +320,29 @@ impl Authenticator {
     }
 }
 
+/// Record the outcome of a credential check; credentials themselves are never logged
+async fn audit_login(
+    audit: &AuditLog,
+    headers: &RequestHeaders,
+    strategy: AuthStrategy,
+    result: &AuthResult,
+) {
+    let event = match result {
+        AuthResult::Authenticated(user) => {
+            AuditEvent::new(AuditEventType::LoginSucceeded).actor(user.id())
+        }
+        AuthResult::Failed(error) => {
+            AuditEvent::new(AuditEventType::LoginFailed).detail("reason", error.to_string())
+        }
+        AuthResult::Anonymous => return,
+    };
+
+    let event = event
+        .client(headers.client_ip(), headers.user_agent.clone())
+        .detail("strategy", format!("{:?}", strategy).to_lowercase());
+    audit.emit(event).await;
+}
+
 /// Request headers relevant for authentication
 #[derive(Debug, Default)]
 pub struct RequestHeaders {
@@ -292,6 +374,26 @@ impl RequestHeaders {
 
         headers
     }
+
+    /// API key sent as the password of Basic auth with `apikey` as username
+    pub fn basic_api_key(&self) -> Option<String> {
+        let auth_header = self.authorization.as_ref()?;
+        if !auth_header.to_lowercase().starts_with("basic ") {
+            return None;
+        }
+        let credentials = String::from_utf8(base64_decode(&auth_header[6..])?).ok()?;
+        let (username, password) = credentials.split_once(':')?;
+        username.eq_ignore_ascii_case("apikey").then(|| password.to_string())
+    }
+
+    /// Originating client IP (first entry of X-Forwarded-For)
+    pub fn client_ip(&self) -> Option<String> {
+        self.x_forwarded_for
+            .as_deref()
+            .and_then(|v| v.split(',').next())
+            .map(|ip| ip.trim().to_string())
+            .filter(|ip| !ip.is_empty())
+    }
 }
 
 /// Simple base64 decode
@@ -367,4 +469,78 @@ mod tests {
         assert_eq!(headers.api_key, Some("api-key-123".to_string()));
         assert_eq!(headers.cookie, Some("_session=abc".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_api_key_scopes_are_attached() {
+        use crate::api_key::ApiKeyScope;
+
+        let store = Arc::new(op_db::MemoryApiKeyStore::new());
+        let created = ApiKeyService::new()
+            .create(store.as_ref(), 5, None, Some(vec![ApiKeyScope::ReadOnly]))
+            .await
+            .unwrap();
+        let authenticator = Authenticator::new(AuthConfig::api_keys(store));
+
+        use base64::Engine;
+        let basic = base64::engine::general_purpose::STANDARD.encode(format!("apikey:{}", created.secret));
+        for headers in [
+            RequestHeaders { api_key: Some(created.secret.clone()), ..Default::default() },
+            RequestHeaders { authorization: Some(format!("Basic {}", basic)), ..Default::default() },
+        ] {
+            match authenticator.authenticate(&headers).await {
+                AuthResult::Authenticated(user) => {
+                    assert_eq!(user.id(), 5);
+                    assert_eq!(user.api_key_scopes(), Some(&[ApiKeyScope::ReadOnly][..]));
+                    assert!(!user.scope_allows(true, "/api/v3/work_packages/1"));
+                }
+                _ => panic!("Expected authenticated result"),
+            }
+        }
+
+        let unknown = RequestHeaders { api_key: Some("not-a-key".into()), ..Default::default() };
+        assert!(matches!(
+            authenticator.authenticate(&unknown).await,
+            AuthResult::Failed(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_login_attempts_are_audited() {
+        use op_core::audit::{AuditEventType, MemoryAuditSink};
+
+        let sink = Arc::new(MemoryAuditSink::new());
+        let config = AuthConfig {
+            strategies: vec![AuthStrategy::Basic],
+            ..Default::default()
+        }
+        .with_audit(AuditLog::new().with_sink(sink.clone()));
+        let authenticator = Authenticator::new(config);
+
+        let ok = RequestHeaders {
+            // admin:s3cret
+            authorization: Some("Basic YWRtaW46czNjcmV0".into()),
+            x_forwarded_for: Some("203.0.113.7, 10.0.0.1".into()),
+            user_agent: Some("curl/8.0".into()),
+            ..Default::default()
+        };
+        authenticator.authenticate(&ok).await;
+
+        let bad = RequestHeaders {
+            // admin:
+            authorization: Some("Basic YWRtaW46".into()),
+            ..Default::default()
+        };
+        authenticator.authenticate(&bad).await;
+
+        let events = sink.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_type, AuditEventType::LoginSucceeded);
+        assert_eq!(events[0].ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(events[0].user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!(events[1].event_type, AuditEventType::LoginFailed);
+
+        let serialized = serde_json::to_string(&events).unwrap();
+        assert!(!serialized.contains("s3cret"));
+        assert!(!serialized.contains("YWRtaW46"));
+    }
 }
@@ -5,6 +5,8 @@
 use op_core::traits::Id;
 use std::collections::{HashMap, HashSet};
 
+use crate::api_key::ApiKeyScope;
+
 // ============================================================================
 // Permission Definition
 // ============================================================================
@@ -125,6 +127,8 @@ pub struct CurrentUser {
     global_permissions: HashSet<String>,
     project_permissions: HashMap<Id, HashSet<String>>,
     work_package_permissions: HashMap<Id, HashSet<String>>,
+    /// Scopes of the API key the user authenticated with, if restricted
+    api_key_scopes: Option<Box<[ApiKeyScope]>>,
 }
 
 impl CurrentUser {
@@ -139,6 +143,7 @@ impl CurrentUser {
             global_permissions: HashSet::new(),
             project_permissions: HashMap::new(),
             work_package_permissions: HashMap::new(),
+            api_key_scopes: None,
         }
     }
 
@@ -153,6 +158,7 @@ impl CurrentUser {
             global_permissions: HashSet::new(),
             project_permissions: HashMap::new(),
             work_package_permissions: HashMap::new(),
+            api_key_scopes: None,
         }
     }
 
@@ -195,6 +201,24 @@ impl CurrentUser {
         self.global_permissions.contains(permission)
     }
 
+    /// Restrict the user to the scopes of the API key they authenticated with
+    pub fn with_api_key_scopes(mut self, scopes: Option<Vec<ApiKeyScope>>) -> Self {
+        self.api_key_scopes = scopes.map(Vec::into_boxed_slice);
+        self
+    }
+
+    /// Scopes of the API key the user authenticated with, if restricted
+    pub fn api_key_scopes(&self) -> Option<&[ApiKeyScope]> {
+        self.api_key_scopes.as_deref()
+    }
+
+    /// Whether the scopes of the user's API key, if any, allow a request;
+    /// permissions are checked separately
+    pub fn scope_allows(&self, write: bool, path: &str) -> bool {
+        self.api_key_scopes()
+            .is_none_or(|scopes| ApiKeyScope::allow(scopes, write, path))
+    }
+
     /// Get user ID
     pub fn id(&self) -> Id {
         self.id
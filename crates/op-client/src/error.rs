@@ -0,0 +1,115 @@
+//! Client errors
+
+use op_core::representations::{error_identifier, ErrorResponse};
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Client error types
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The API answered with an error response
+    #[error("{status}: {}", .error.message)]
+    Api {
+        status: StatusCode,
+        error: Box<ErrorResponse>,
+    },
+
+    /// The API answered with an error that is no error response, e.g. from
+    /// a proxy in front of it
+    #[error("{status}: {body}")]
+    UnexpectedResponse { status: StatusCode, body: String },
+
+    /// A successful response did not match the expected representation
+    #[error("Invalid response body: {0}")]
+    Decode(#[from] serde_json::Error),
+
+    #[error("Invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+impl ClientError {
+    /// Build the error of an unsuccessful response from its body
+    pub(crate) fn from_response(status: StatusCode, body: &[u8]) -> Self {
+        match serde_json::from_slice::<ErrorResponse>(body) {
+            Ok(error) => ClientError::Api {
+                status,
+                error: Box::new(error),
+            },
+            Err(_) => ClientError::UnexpectedResponse {
+                status,
+                body: String::from_utf8_lossy(body).into_owned(),
+            },
+        }
+    }
+
+    /// Status of the response, `None` when there was none
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Api { status, .. } | ClientError::UnexpectedResponse { status, .. } => Some(*status),
+            ClientError::Http(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// `errorIdentifier` of an error response
+    pub fn error_identifier(&self) -> Option<&str> {
+        match self {
+            ClientError::Api { error, .. } => Some(&error.error_identifier),
+            _ => None,
+        }
+    }
+
+    /// The error response, `None` for other errors
+    pub fn response(&self) -> Option<&ErrorResponse> {
+        match self {
+            ClientError::Api { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.error_identifier() == Some(error_identifier::NOT_FOUND)
+    }
+
+    /// Whether the update was based on an outdated `lockVersion`
+    pub fn is_conflict(&self) -> bool {
+        self.error_identifier() == Some(error_identifier::UPDATE_CONFLICT)
+    }
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_response_is_parsed() {
+        let body = br#"{
+            "_type": "Error",
+            "errorIdentifier": "urn:openproject-org:api:v3:errors:PropertyConstraintViolation",
+            "message": "dueDate is not a valid date.",
+            "_embedded": { "details": { "attribute": "dueDate" } },
+            "requestId": "abc"
+        }"#;
+
+        let error = ClientError::from_response(StatusCode::UNPROCESSABLE_ENTITY, body);
+        assert_eq!(error.status(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+        assert_eq!(error.error_identifier(), Some(error_identifier::PROPERTY_CONSTRAINT_VIOLATION));
+        let response = error.response().unwrap();
+        assert_eq!(response.attribute(), Some("dueDate"));
+        assert_eq!(response.request_id.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_other_bodies_are_kept() {
+        let error = ClientError::from_response(StatusCode::BAD_GATEWAY, b"<html>Bad Gateway</html>");
+
+        assert!(matches!(error, ClientError::UnexpectedResponse { .. }));
+        assert_eq!(error.error_identifier(), None);
+        assert_eq!(error.to_string(), "502 Bad Gateway: <html>Bad Gateway</html>");
+    }
+}
@@ -0,0 +1,21 @@
+//! # op-client
+//!
+//! Typed client for the OpenProject RS API v3.
+//!
+//! Requests and responses use the representations in
+//! [`op_core::representations`], the same types the server renders, so
+//! the client cannot drift from the API it talks to. It provides:
+//!
+//! - Authentication with an API key or an OAuth bearer token
+//! - Reading, creating and updating work packages, projects, users and
+//!   time entries
+//! - Attachment uploads as multipart forms, and renaming attachments
+//! - Collections iterated page by page as a `Stream`
+//! - Error responses mapped to [`ClientError`], keeping their `errorIdentifier`
+
+pub mod client;
+pub mod error;
+
+pub use client::{Auth, Client, DEFAULT_PAGE_SIZE};
+pub use error::{ClientError, ClientResult};
+pub use op_core::representations;
@@ -0,0 +1,512 @@
+//! The client against the real API router, served in-process
+
+use op_api::extractors::AppState;
+use op_client::representations::{error_identifier, CreateProject, UpdateWorkPackage};
+use op_client::{Auth, Client};
+use reqwest::StatusCode;
+
+/// Serve the API on a free local port, returning its base URL
+async fn serve(state: AppState) -> String {
+    serve_router(op_api::router().with_state(state)).await
+}
+
+async fn serve_router(router: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    format!("http://{}", address)
+}
+
+async fn authenticated_client(state: AppState) -> Client {
+    Client::new(&serve(state).await)
+        .unwrap()
+        .with_auth(Auth::Bearer("test".into()))
+}
+
+#[tokio::test]
+async fn test_missing_credentials_are_rejected() {
+    let client = Client::new(&serve(AppState::default()).await).unwrap();
+
+    let error = client.work_package(1).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::UNAUTHORIZED));
+    assert_eq!(error.error_identifier(), Some(error_identifier::UNAUTHENTICATED));
+}
+
+#[tokio::test]
+async fn test_property_errors_name_the_attribute() {
+    let client = authenticated_client(AppState::default()).await;
+
+    let changes = UpdateWorkPackage {
+        due_date: Some("2024-01-01T10:00:00Z".into()),
+        ..Default::default()
+    };
+    let error = client.update_work_package(1, &changes).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+    assert_eq!(error.error_identifier(), Some(error_identifier::PROPERTY_CONSTRAINT_VIOLATION));
+    assert_eq!(error.response().unwrap().attribute(), Some("dueDate"));
+}
+
+#[tokio::test]
+async fn test_missing_permissions_are_reported() {
+    let client = authenticated_client(AppState::default()).await;
+
+    let project = CreateProject {
+        name: "Demo".into(),
+        ..Default::default()
+    };
+    let error = client.create_project(&project).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::FORBIDDEN));
+    assert_eq!(error.error_identifier(), Some(error_identifier::MISSING_PERMISSION));
+}
+
+#[cfg(feature = "pg-tests")]
+mod pg_tests {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use futures::TryStreamExt;
+    use op_attachments::{AttachmentConfig, AttachmentService, LocalStorage, PgAttachmentStore};
+    use op_client::representations::{
+        BulkUpdateWorkPackages, CreateWorkPackage, UpdateAttachment, UploadAttachmentMetadata,
+    };
+    use op_core::traits::Id;
+    use op_db::MIGRATOR;
+    use op_notifications::email::{EmailAddress, MemoryEmailSender};
+    use op_notifications::{EmailRenderer, MemoryNotificationStore, NotificationStore, SummaryMailer};
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+    use sqlx::PgPool;
+
+    use super::*;
+
+    /// The server commits its changes, so the test runs in a schema of its
+    /// own which is dropped afterwards
+    struct TestSchema {
+        name: String,
+        admin: PgPool,
+        pool: PgPool,
+    }
+
+    impl TestSchema {
+        async fn create() -> Self {
+            let url = std::env::var("DATABASE_URL").expect("pg-tests need DATABASE_URL to be set");
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos();
+            let name = format!("op_client_{}_{}", std::process::id(), nanos);
+
+            let admin = PgPool::connect(&url).await.expect("connect to the test database");
+            sqlx::query(&format!("CREATE SCHEMA {}", name))
+                .execute(&admin)
+                .await
+                .unwrap();
+
+            let options = PgConnectOptions::from_str(&url)
+                .unwrap()
+                .options([("search_path", name.as_str())]);
+            let pool = PgPoolOptions::new().connect_with(options).await.unwrap();
+            MIGRATOR.run(&pool).await.expect("migrate the test schema");
+
+            Self { name, admin, pool }
+        }
+
+        async fn drop(self) {
+            self.pool.close().await;
+            sqlx::query(&format!("DROP SCHEMA {} CASCADE", self.name))
+                .execute(&self.admin)
+                .await
+                .unwrap();
+        }
+
+        async fn insert(&self, sql: &str) -> Id {
+            sqlx::query_scalar(sql).fetch_one(&self.pool).await.unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_work_package_cycle() {
+        let schema = TestSchema::create().await;
+        // Authenticated requests act as user 1
+        schema
+            .insert("INSERT INTO users (id, login) VALUES (1, 'api_user') RETURNING id")
+            .await;
+        let project_id = schema
+            .insert("INSERT INTO projects (name, identifier) VALUES ('Demo', 'demo') RETURNING id")
+            .await;
+        let type_id = schema.insert("INSERT INTO types (name) VALUES ('Task') RETURNING id").await;
+        let status_id = schema.insert("INSERT INTO statuses (name) VALUES ('New') RETURNING id").await;
+
+        let storage = std::env::temp_dir().join(&schema.name);
+        let attachments = AttachmentService::new(
+            Arc::new(PgAttachmentStore::new(schema.pool.clone())),
+            Arc::new(LocalStorage::new(&storage, "/attachments")),
+            AttachmentConfig::default(),
+        );
+        let state = AppState::with_pool(schema.pool.clone()).with_attachments(Arc::new(attachments));
+        let client = authenticated_client(state).await;
+
+        let new_work_package = |subject: &str| CreateWorkPackage {
+            subject: subject.into(),
+            project_id: Some(project_id),
+            type_id: Some(type_id),
+            status_id: Some(status_id),
+            ..Default::default()
+        };
+        let created = client.create_work_package(&new_work_package("Draft")).await.unwrap();
+        client.create_work_package(&new_work_package("Second")).await.unwrap();
+
+        let read = client.work_package(created.id).await.unwrap();
+        assert_eq!(read.subject, "Draft");
+        assert_eq!(read.project_id, project_id);
+
+        let changes = UpdateWorkPackage {
+            subject: Some("Final".into()),
+            lock_version: read.lock_version,
+            ..Default::default()
+        };
+        let updated = client.update_work_package(created.id, &changes).await.unwrap();
+        assert_eq!(updated.subject, "Final");
+        assert!(updated.lock_version > read.lock_version);
+
+        let stale = client.update_work_package(created.id, &changes).await.unwrap_err();
+        assert!(stale.is_conflict());
+
+        // One request per work package
+        let subjects: Vec<String> = client
+            .clone()
+            .with_page_size(1)
+            .work_packages()
+            .map_ok(|work_package| work_package.subject)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(subjects.len(), 2);
+        assert!(subjects.contains(&"Final".to_string()));
+
+        let metadata = UploadAttachmentMetadata {
+            container_type: Some("WorkPackage".into()),
+            container_id: Some(created.id),
+            ..Default::default()
+        };
+        let attachment = client
+            .upload_attachment("notes.txt", b"meeting notes".to_vec(), &metadata)
+            .await
+            .unwrap();
+        assert_eq!(attachment.filename.as_deref(), Some("notes.txt"));
+        assert_eq!(attachment.filesize, 13);
+        assert_eq!(
+            attachment.links.container.unwrap().href,
+            format!("/api/v3/work_packages/{}", created.id)
+        );
+        assert_eq!(client.attachment(attachment.id).await.unwrap().filesize, 13);
+
+        // Attachments of a work package are edited with the permission to
+        // edit it, which the author alone does not grant
+        let rename = UpdateAttachment {
+            file_name: Some("minutes.md".into()),
+            ..Default::default()
+        };
+        let denied = client.update_attachment(attachment.id, &rename).await.unwrap_err();
+        assert_eq!(denied.status(), Some(StatusCode::FORBIDDEN));
+
+        let role_id = schema.insert("INSERT INTO roles (name) VALUES ('Member') RETURNING id").await;
+        schema
+            .insert(&format!(
+                "INSERT INTO role_permissions (role_id, permission) VALUES ({}, 'edit_work_packages') RETURNING id",
+                role_id
+            ))
+            .await;
+        let member_id = schema
+            .insert(&format!(
+                "INSERT INTO members (user_id, project_id) VALUES (1, {}) RETURNING id",
+                project_id
+            ))
+            .await;
+        schema
+            .insert(&format!(
+                "INSERT INTO member_roles (member_id, role_id) VALUES ({}, {}) RETURNING id",
+                member_id, role_id
+            ))
+            .await;
+
+        let renamed = client.update_attachment(attachment.id, &rename).await.unwrap();
+        assert_eq!(renamed.filename.as_deref(), Some("minutes.md"));
+        assert_eq!(renamed.content_type.as_deref(), Some("text/markdown"));
+
+        assert!(client.work_package(created.id + 100).await.unwrap_err().is_not_found());
+
+        schema.drop().await;
+        let _ = std::fs::remove_dir_all(storage);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_update_sends_one_summary_per_recipient() {
+        let schema = TestSchema::create().await;
+        schema
+            .insert("INSERT INTO users (id, login, firstname, lastname) VALUES (1, 'alice', 'Alice', 'Admin') RETURNING id")
+            .await;
+        for (id, login) in [(2, "watcher"), (3, "assignee")] {
+            schema
+                .insert(&format!(
+                    "INSERT INTO users (id, login, mail) VALUES ({}, '{}', '{}@example.com') RETURNING id",
+                    id, login, login
+                ))
+                .await;
+        }
+        let project_id = schema
+            .insert("INSERT INTO projects (name, identifier) VALUES ('Project X', 'project-x') RETURNING id")
+            .await;
+        let type_id = schema.insert("INSERT INTO types (name) VALUES ('Task') RETURNING id").await;
+        let status_id = schema.insert("INSERT INTO statuses (name) VALUES ('New') RETURNING id").await;
+        let closed_id = schema.insert("INSERT INTO statuses (name) VALUES ('Closed') RETURNING id").await;
+
+        let sender = Arc::new(MemoryEmailSender::new());
+        let mailer = SummaryMailer::new(
+            sender.clone(),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        );
+        let notifications = Arc::new(MemoryNotificationStore::new());
+        let state = AppState::with_pool(schema.pool.clone())
+            .with_notifications(notifications.clone())
+            .with_summary_mailer(Arc::new(mailer));
+        let client = authenticated_client(state).await;
+
+        let mut ids = Vec::new();
+        for number in 1..=50 {
+            let work_package = CreateWorkPackage {
+                subject: format!("Imported {}", number),
+                project_id: Some(project_id),
+                type_id: Some(type_id),
+                status_id: Some(status_id),
+                ..Default::default()
+            };
+            ids.push(client.create_work_package(&work_package).await.unwrap().id);
+        }
+        sqlx::query(
+            "INSERT INTO watchers (watchable_type, watchable_id, user_id) SELECT 'WorkPackage', id, 2 FROM work_packages",
+        )
+        .execute(&schema.pool)
+        .await
+        .unwrap();
+
+        let changes = BulkUpdateWorkPackages {
+            ids: ids.clone(),
+            status_id: Some(closed_id),
+            assigned_to_id: Some(3),
+            ..Default::default()
+        };
+        let updated = client.bulk_update_work_packages(&changes).await.unwrap();
+        assert_eq!(updated.len(), 50);
+        assert!(updated.iter().all(|work_package| work_package.status_id == closed_id));
+
+        // One summary for the watcher and one for the new assignee, none
+        // for the actor
+        let messages = sender.sent_messages().await;
+        let mut recipients: Vec<&str> = messages.iter().map(|message| message.to[0].email.as_str()).collect();
+        recipients.sort_unstable();
+        assert_eq!(recipients, vec!["assignee@example.com", "watcher@example.com"]);
+        for message in &messages {
+            assert_eq!(message.subject, "[OpenProject] 50 work packages updated in Project X by Alice Admin");
+            assert!(message
+                .text_body
+                .contains(&format!("http://localhost/projects/{}/work_packages?filters=", project_id)));
+        }
+        for user_id in [1, 2, 3] {
+            assert_eq!(notifications.unread_count(user_id).await.unwrap(), 0);
+        }
+
+        let unknown = BulkUpdateWorkPackages {
+            ids: vec![ids[0] + 1000],
+            ..Default::default()
+        };
+        assert!(client.bulk_update_work_packages(&unknown).await.unwrap_err().is_not_found());
+
+        schema.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_relation_graph_of_a_star() {
+        let schema = TestSchema::create().await;
+        schema
+            .insert("INSERT INTO users (id, login) VALUES (1, 'api_user') RETURNING id")
+            .await;
+        let visible_project = schema
+            .insert("INSERT INTO projects (name, identifier) VALUES ('Demo', 'demo') RETURNING id")
+            .await;
+        let hidden_project = schema
+            .insert("INSERT INTO projects (name, identifier) VALUES ('Secret', 'secret') RETURNING id")
+            .await;
+        let type_id = schema.insert("INSERT INTO types (name) VALUES ('Task') RETURNING id").await;
+        let status_id = schema.insert("INSERT INTO statuses (name) VALUES ('New') RETURNING id").await;
+        let role_id = schema.insert("INSERT INTO roles (name) VALUES ('Reader') RETURNING id").await;
+        schema
+            .insert(&format!(
+                "INSERT INTO role_permissions (role_id, permission) VALUES ({}, 'view_work_packages') RETURNING id",
+                role_id
+            ))
+            .await;
+        let member_id = schema
+            .insert(&format!(
+                "INSERT INTO members (user_id, project_id) VALUES (1, {}) RETURNING id",
+                visible_project
+            ))
+            .await;
+        schema
+            .insert(&format!(
+                "INSERT INTO member_roles (member_id, role_id) VALUES ({}, {}) RETURNING id",
+                member_id, role_id
+            ))
+            .await;
+
+        let work_package = |subject: &str, project_id: Id| {
+            format!(
+                "INSERT INTO work_packages (subject, project_id, type_id, status_id, author_id) \
+                 VALUES ('{}', {}, {}, {}, 1) RETURNING id",
+                subject, project_id, type_id, status_id
+            )
+        };
+        let hub = schema.insert(&work_package("Hub", visible_project)).await;
+        let mut spokes = Vec::new();
+        for subject in ["North", "East", "South"] {
+            spokes.push(schema.insert(&work_package(subject, visible_project)).await);
+        }
+        let hidden = schema.insert(&work_package("Hidden", hidden_project)).await;
+        let neighbor = schema.insert(&work_package("Neighbor", visible_project)).await;
+        let hidden_neighbor = schema.insert(&work_package("Hidden neighbor", hidden_project)).await;
+
+        let relation = |from_id: Id, to_id: Id| {
+            format!(
+                "INSERT INTO relations (from_id, to_id, relation_type) VALUES ({}, {}, 'precedes') RETURNING id",
+                from_id, to_id
+            )
+        };
+        let mut star = Vec::new();
+        for &spoke in &spokes {
+            star.push(schema.insert(&relation(hub, spoke)).await);
+        }
+        schema.insert(&relation(hub, hidden)).await;
+        let leaving = schema.insert(&relation(neighbor, spokes[0])).await;
+        schema.insert(&relation(spokes[1], hidden_neighbor)).await;
+
+        let config = op_api::extractors::AppConfig {
+            relation_graph_limit: 5,
+            ..Default::default()
+        };
+        let base = serve(AppState::with_pool(schema.pool.clone()).with_config(config)).await;
+        let http = reqwest::Client::new();
+        let graph = |ids: Vec<Id>, expand: &str| {
+            let url = format!(
+                "{}/api/v3/relations?involved={}{}",
+                base,
+                serde_json::to_string(&ids).unwrap(),
+                expand
+            );
+            http.get(url).bearer_auth("test").send()
+        };
+        let relation_ids = |collection: &serde_json::Value| -> Vec<Id> {
+            collection["_embedded"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|relation| relation["id"].as_i64().unwrap())
+                .collect()
+        };
+
+        // The relation to the work package the user cannot see is left out
+        let involved = vec![hub, spokes[0], spokes[1], spokes[2], hidden];
+        let response = graph(involved.clone(), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let collection: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(relation_ids(&collection), star);
+        assert_eq!(collection["total"], 3);
+
+        let response = graph(involved.clone(), "&expand=neighbors").await.unwrap();
+        let collection: serde_json::Value = response.json().await.unwrap();
+        let mut expected = star.clone();
+        expected.push(leaving);
+        assert_eq!(relation_ids(&collection), expected);
+        let embedded = &collection["_embedded"][3]["_embedded"];
+        assert_eq!(embedded["from"]["id"], neighbor);
+        assert_eq!(embedded["from"]["subject"], "Neighbor");
+        assert!(embedded.get("to").is_none());
+        assert!(collection["_embedded"][0].get("_embedded").is_none());
+
+        let mut too_many = involved;
+        too_many.push(neighbor);
+        let response = graph(too_many, "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        schema.drop().await;
+    }
+
+    #[tokio::test]
+    async fn test_work_package_links_carry_the_url_root() {
+        let schema = TestSchema::create().await;
+        schema
+            .insert("INSERT INTO users (id, login) VALUES (1, 'api_user') RETURNING id")
+            .await;
+        let project_id = schema
+            .insert("INSERT INTO projects (name, identifier) VALUES ('Demo', 'demo') RETURNING id")
+            .await;
+        let type_id = schema.insert("INSERT INTO types (name) VALUES ('Task') RETURNING id").await;
+        let status_id = schema.insert("INSERT INTO statuses (name) VALUES ('New') RETURNING id").await;
+        let role_id = schema.insert("INSERT INTO roles (name) VALUES ('Reader') RETURNING id").await;
+        schema
+            .insert(&format!(
+                "INSERT INTO role_permissions (role_id, permission) VALUES ({}, 'view_work_packages') RETURNING id",
+                role_id
+            ))
+            .await;
+        let member_id = schema
+            .insert(&format!(
+                "INSERT INTO members (user_id, project_id) VALUES (1, {}) RETURNING id",
+                project_id
+            ))
+            .await;
+        schema
+            .insert(&format!(
+                "INSERT INTO member_roles (member_id, role_id) VALUES ({}, {}) RETURNING id",
+                member_id, role_id
+            ))
+            .await;
+        let work_package = |subject: &str| {
+            format!(
+                "INSERT INTO work_packages (subject, project_id, type_id, status_id, author_id) \
+                 VALUES ('{}', {}, {}, {}, 1) RETURNING id",
+                subject, project_id, type_id, status_id
+            )
+        };
+        let design = schema.insert(&work_package("Design")).await;
+        let build = schema.insert(&work_package("Build")).await;
+        schema
+            .insert(&format!(
+                "INSERT INTO relations (from_id, to_id, relation_type) VALUES ({}, {}, 'precedes') RETURNING id",
+                design, build
+            ))
+            .await;
+
+        let router = op_api::router_with_root(&Default::default(), "/op/")
+            .with_state(AppState::with_pool(schema.pool.clone()));
+        let base = serve_router(router).await;
+        let get = |path: String| reqwest::Client::new().get(format!("{}{}", base, path)).bearer_auth("test").send();
+
+        // The neighbor is embedded as a condensed work package
+        let graph = format!("/op/api/v3/relations?involved=[{}]&expand=neighbors", design);
+        let response = get(graph).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let collection: serde_json::Value = response.json().await.unwrap();
+        let relation = &collection["_embedded"][0];
+        assert_eq!(relation["_links"]["from"]["href"], format!("/op/api/v3/work_packages/{}", design));
+        let work_package = &relation["_embedded"]["to"];
+        assert_eq!(work_package["subject"], "Build");
+        assert_eq!(work_package["_links"]["self"]["href"], format!("/op/api/v3/work_packages/{}", build));
+        assert_eq!(work_package["_links"]["project"]["href"], format!("/op/api/v3/projects/{}", project_id));
+
+        let response = get(format!("/api/v3/work_packages/{}", design)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        schema.drop().await;
+    }
+}
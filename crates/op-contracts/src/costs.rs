@@ -0,0 +1,13 @@
+//! Cost contracts
+//!
+//! Mirrors: modules/costs/app/contracts/*
+//!
+//! Costs are shown in full to users allowed to view the cost entries of a
+//! project; everyone else sees only what their own entries cost.
+
+/// Permissions required for cost operations
+pub mod permissions {
+    pub const LOG_COSTS: &str = "log_costs";
+    pub const VIEW_COST_ENTRIES: &str = "view_cost_entries";
+    pub const VIEW_OWN_COST_ENTRIES: &str = "view_own_cost_entries";
+}
@@ -0,0 +1,13 @@
+//! Document contracts
+//!
+//! Mirrors: modules/documents/app/contracts/documents/*
+//!
+//! Members with `view_documents` see the documents of a project and their
+//! attachments. Creating, changing and deleting documents, and their
+//! attachments, needs `manage_documents`.
+
+/// Permissions required for document operations
+pub mod permissions {
+    pub const VIEW_DOCUMENTS: &str = "view_documents";
+    pub const MANAGE_DOCUMENTS: &str = "manage_documents";
+}
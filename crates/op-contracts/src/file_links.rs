@@ -0,0 +1,12 @@
+//! File link contracts
+//!
+//! Mirrors: modules/storages/app/contracts/storages/file_links/*
+//!
+//! File links are viewed and managed through the project of the linked
+//! work package; storages themselves are managed by administrators.
+
+/// Permissions required for file link operations
+pub mod permissions {
+    pub const VIEW_FILE_LINKS: &str = "view_file_links";
+    pub const MANAGE_FILE_LINKS: &str = "manage_file_links";
+}
@@ -0,0 +1,19 @@
+//! Forum contracts
+//!
+//! Mirrors: app/contracts/messages/*
+//!
+//! Members with `add_messages` start topics and reply to them unless the
+//! topic is locked. Locking and pinning topics, and changing the forums of a
+//! project, need `manage_forums`. Authors edit and delete their own messages
+//! with `edit_own_messages` and `delete_own_messages`.
+
+/// Permissions required for forum operations
+pub mod permissions {
+    pub const VIEW_MESSAGES: &str = "view_messages";
+    pub const ADD_MESSAGES: &str = "add_messages";
+    pub const EDIT_MESSAGES: &str = "edit_messages";
+    pub const EDIT_OWN_MESSAGES: &str = "edit_own_messages";
+    pub const DELETE_MESSAGES: &str = "delete_messages";
+    pub const DELETE_OWN_MESSAGES: &str = "delete_own_messages";
+    pub const MANAGE_FORUMS: &str = "manage_forums";
+}
@@ -9,6 +9,10 @@ pub mod base;
 pub mod work_packages;
 pub mod projects;
 pub mod users;
+pub mod file_links;
+pub mod costs;
+pub mod forums;
+pub mod documents;
 
 pub use base::*;
 pub use work_packages::{
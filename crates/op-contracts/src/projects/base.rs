@@ -4,22 +4,74 @@
 
 use op_core::error::ValidationErrors;
 use op_core::traits::Id;
+use op_models::CustomField;
 use regex::Regex;
+use std::collections::HashMap;
 use std::sync::LazyLock;
 
 use crate::base::{Contract, UserContext, ValidationResult};
 
-/// Valid identifier pattern (alphanumeric, hyphens, underscores)
+/// Characters allowed in an identifier
 static IDENTIFIER_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"^[a-z0-9][a-z0-9_-]*[a-z0-9]$|^[a-z0-9]$").unwrap()
+    Regex::new(r"^[a-z0-9_-]+$").unwrap()
 });
 
-/// Reserved identifiers that cannot be used
-const RESERVED_IDENTIFIERS: &[&str] = &[
+/// Maximum length of an identifier
+pub const IDENTIFIER_MAX_LENGTH: usize = 100;
+
+/// Identifiers that collide with project routes
+pub const RESERVED_IDENTIFIERS: &[&str] = &[
     "new", "edit", "delete", "destroy", "create", "update",
-    "admin", "api", "settings", "logout", "login",
+    "archive", "unarchive", "menu", "queries", "export", "settings",
+    "admin", "api", "projects", "login", "logout",
 ];
 
+/// Identifier derived from a project name
+///
+/// Transliterates the name to ASCII, lowercases it and joins the words with
+/// dashes: `"Café Übersicht"` becomes `"cafe-ubersicht"`. Names starting
+/// with a number get a `project-` prefix, so the result only fails
+/// validation when it is reserved or taken.
+pub fn identifier_from_name(name: &str) -> String {
+    // Apostrophes join their word: "O'Brien" is "obrien", not "o-brien"
+    let ascii = deunicode::deunicode(name).to_lowercase().replace('\'', "");
+    let words: Vec<&str> = ascii
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let identifier = match words.first() {
+        None => return "project".to_string(),
+        Some(first) if first.starts_with(|c: char| c.is_ascii_digit()) => {
+            format!("project-{}", words.join("-"))
+        }
+        Some(_) => words.join("-"),
+    };
+    truncate_identifier(&identifier, IDENTIFIER_MAX_LENGTH)
+}
+
+/// First of `base`, `base-2`, `base-3`, ... that is neither reserved nor taken
+pub fn unique_identifier(base: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    let available = |candidate: &str| !RESERVED_IDENTIFIERS.contains(&candidate) && !is_taken(candidate);
+    if available(base) {
+        return base.to_string();
+    }
+
+    (2..)
+        .map(|n: usize| {
+            let suffix = format!("-{}", n);
+            let base = truncate_identifier(base, IDENTIFIER_MAX_LENGTH - suffix.len());
+            format!("{}{}", base, suffix)
+        })
+        .find(|candidate| available(candidate))
+        .expect("an unbounded range has a free suffix")
+}
+
+/// Cut an identifier to `max` characters without leaving a trailing dash
+fn truncate_identifier(identifier: &str, max: usize) -> String {
+    identifier.chars().take(max).collect::<String>().trim_end_matches('-').to_string()
+}
+
 /// Project data for validation
 pub trait ProjectData: Send + Sync {
     fn id(&self) -> Option<Id>;
@@ -41,28 +93,40 @@ impl<'a, U: UserContext> ProjectBaseContract<'a, U> {
         Self { user }
     }
 
-    /// Validate identifier format and uniqueness
+    /// Validate identifier format
+    ///
+    /// Identifiers are URL segments: lowercase letters, digits, dashes and
+    /// underscores, not starting with a digit and not a reserved word.
     pub fn validate_identifier(&self, identifier: &str, errors: &mut ValidationErrors) {
         if identifier.is_empty() {
-            errors.add("identifier", "can't be blank");
+            errors.add_with_code("identifier", "blank", "can't be blank");
             return;
         }
 
-        if identifier.len() < 1 || identifier.len() > 100 {
-            errors.add("identifier", "is too long (maximum is 100 characters)");
+        if identifier.chars().count() > IDENTIFIER_MAX_LENGTH {
+            errors.add_with_code("identifier", "too_long", "is too long (maximum is 100 characters)");
             return;
         }
 
         if !IDENTIFIER_PATTERN.is_match(identifier) {
-            errors.add(
+            errors.add_with_code(
                 "identifier",
-                "is invalid. Only lowercase letters, numbers, dashes and underscores allowed. \
-                 It must start with a letter or number.",
+                "invalid",
+                "is invalid. Only lowercase letters, numbers, dashes and underscores allowed.",
             );
+        } else if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+            errors.add_with_code("identifier", "invalid", "must not start with a number");
         }
 
         if RESERVED_IDENTIFIERS.contains(&identifier) {
-            errors.add("identifier", "is reserved and cannot be used");
+            errors.add_with_code("identifier", "exclusion", "is reserved and cannot be used");
+        }
+    }
+
+    /// Reject an identifier another project already uses
+    pub fn validate_identifier_available(&self, taken: bool, errors: &mut ValidationErrors) {
+        if taken {
+            errors.add_with_code("identifier", "taken", "has already been taken");
         }
     }
 
@@ -85,6 +149,32 @@ impl<'a, U: UserContext> ProjectBaseContract<'a, U> {
         }
     }
 
+    /// Validate the values of the project custom fields, by field id
+    ///
+    /// Required fields need a value that is not blank and values have to
+    /// fit the format of their field; errors are on e.g. `customField3`.
+    pub fn validate_custom_values(
+        &self,
+        custom_fields: &[CustomField],
+        values: &HashMap<Id, String>,
+        errors: &mut ValidationErrors,
+    ) {
+        for custom_field in custom_fields {
+            let value = values.get(&custom_field.id).map(|value| value.trim()).filter(|value| !value.is_empty());
+            match value {
+                None if custom_field.is_required => {
+                    errors.add_with_code(custom_field.attribute_name(), "blank", "can't be blank");
+                }
+                None => {}
+                Some(value) => {
+                    if let Some((code, message)) = custom_field.format_error(value) {
+                        errors.add_with_code(custom_field.attribute_name(), code, message);
+                    }
+                }
+            }
+        }
+    }
+
     /// Get the user context
     pub fn user(&self) -> &'a U {
         self.user
@@ -191,6 +281,98 @@ mod tests {
         assert!(result.unwrap_err().has_error("identifier"));
     }
 
+    fn identifier_errors(identifier: &str) -> ValidationErrors {
+        let user = MockUser { id: 1, admin: true };
+        let mut errors = ValidationErrors::new();
+        ProjectBaseContract::new(&user).validate_identifier(identifier, &mut errors);
+        errors
+    }
+
+    #[test]
+    fn test_identifier_rules() {
+        for valid in ["a", "my_project-2", "x-1", &"a".repeat(100)] {
+            assert!(identifier_errors(valid).is_empty(), "{} should be valid", valid);
+        }
+
+        for (invalid, code) in [
+            ("", "blank"),
+            ("1project", "invalid"),
+            ("my.project", "invalid"),
+            ("über", "invalid"),
+            ("projects", "exclusion"),
+            (&"a".repeat(101), "too_long"),
+        ] {
+            let errors = identifier_errors(invalid);
+            let messages = errors.get("identifier").expect(invalid);
+            assert_eq!(errors.code("identifier", &messages[0]), code, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_identifier_from_name() {
+        assert_eq!(identifier_from_name("My Project"), "my-project");
+        assert_eq!(identifier_from_name("  Café -- Übersicht! "), "cafe-ubersicht");
+        assert_eq!(identifier_from_name("Łódź 2024"), "lodz-2024");
+        assert_eq!(identifier_from_name("Проект Альфа"), "proekt-alfa");
+        assert_eq!(identifier_from_name("O'Brien's team"), "obriens-team");
+        assert_eq!(identifier_from_name("2024 Roadmap"), "project-2024-roadmap");
+        assert_eq!(identifier_from_name("!!!"), "project");
+        assert_eq!(identifier_from_name(&"Long name ".repeat(20)).len(), 99);
+    }
+
+    #[test]
+    fn test_generated_identifiers_are_valid() {
+        for name in ["北京 Office", "Ærø ümlaut", "9 lives", "a_b c"] {
+            let identifier = identifier_from_name(name);
+            assert!(identifier_errors(&identifier).is_empty(), "{} -> {}", name, identifier);
+        }
+    }
+
+    #[test]
+    fn test_unique_identifier() {
+        let taken: HashSet<&str> = ["alpha", "alpha-2"].into_iter().collect();
+
+        assert_eq!(unique_identifier("beta", |id| taken.contains(id)), "beta");
+        assert_eq!(unique_identifier("alpha", |id| taken.contains(id)), "alpha-3");
+        assert_eq!(unique_identifier("admin", |id| taken.contains(id)), "admin-2");
+
+        let long = "a".repeat(100);
+        let suffixed = unique_identifier(&long, |id| id == long);
+        assert_eq!(suffixed.len(), 100);
+        assert!(suffixed.ends_with("-2"));
+    }
+
+    fn custom_field(id: Id, field_format: &str, is_required: bool) -> CustomField {
+        CustomField {
+            id,
+            name: format!("Field {}", id),
+            field_format: field_format.into(),
+            is_required,
+            position: 1,
+            section: None,
+        }
+    }
+
+    #[test]
+    fn test_required_custom_field_blocks_a_project_without_value() {
+        let user = MockUser { id: 1, admin: true };
+        let contract = ProjectBaseContract::new(&user);
+        let custom_fields = [custom_field(3, "string", true), custom_field(4, "int", false)];
+
+        let mut errors = ValidationErrors::new();
+        contract.validate_custom_values(&custom_fields, &HashMap::from([(3, "  ".to_string())]), &mut errors);
+        let messages = errors.get("customField3").expect("blank required field");
+        assert_eq!(errors.code("customField3", &messages[0]), "blank");
+        assert!(!errors.has_error("customField4"));
+
+        let mut errors = ValidationErrors::new();
+        let values = HashMap::from([(3, "Build".to_string()), (4, "many".to_string())]);
+        contract.validate_custom_values(&custom_fields, &values, &mut errors);
+        assert!(!errors.has_error("customField3"));
+        let messages = errors.get("customField4").expect("malformed integer");
+        assert_eq!(errors.code("customField4", &messages[0]), "not_an_integer");
+    }
+
     #[test]
     fn test_reserved_identifier() {
         let user = MockUser { id: 1, admin: true };
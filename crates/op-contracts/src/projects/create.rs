@@ -13,6 +13,7 @@ use super::permissions;
 pub struct CreateProjectContract<'a, U: UserContext> {
     base: ProjectBaseContract<'a, U>,
     user: &'a U,
+    identifier_taken: bool,
 }
 
 impl<'a, U: UserContext> CreateProjectContract<'a, U> {
@@ -20,9 +21,17 @@ impl<'a, U: UserContext> CreateProjectContract<'a, U> {
         Self {
             base: ProjectBaseContract::new(user),
             user,
+            identifier_taken: false,
         }
     }
 
+    /// Whether another project already uses the identifier, as looked up
+    /// by the caller (`ProjectRepository::is_identifier_unique`)
+    pub fn with_identifier_taken(mut self, taken: bool) -> Self {
+        self.identifier_taken = taken;
+        self
+    }
+
     /// Validate user has permission to create projects
     fn validate_user_allowed_to_create(&self, errors: &mut ValidationErrors) {
         let can_create = self.user.is_admin()
@@ -32,12 +41,6 @@ impl<'a, U: UserContext> CreateProjectContract<'a, U> {
             errors.add("base", "You are not authorized to create projects");
         }
     }
-
-    /// Validate identifier uniqueness (would check database in real impl)
-    fn validate_identifier_unique(&self, _identifier: &str, _errors: &mut ValidationErrors) {
-        // In a real implementation, this would check the database
-        // For now, we assume it's unique
-    }
 }
 
 impl<'a, U: UserContext, T: ProjectData> Contract<T> for CreateProjectContract<'a, U> {
@@ -53,7 +56,7 @@ impl<'a, U: UserContext, T: ProjectData> Contract<T> for CreateProjectContract<'
         }
 
         // Check identifier uniqueness
-        self.validate_identifier_unique(entity.identifier(), &mut errors);
+        self.base.validate_identifier_available(self.identifier_taken, &mut errors);
 
         if errors.is_empty() {
             Ok(())
@@ -150,6 +153,21 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_taken_identifier() {
+        let user = MockUser { id: 1, admin: true, global_permissions: HashSet::new() };
+        let contract = CreateProjectContract::new(&user).with_identifier_taken(true);
+
+        let project = MockProject {
+            identifier: "test-project".to_string(),
+            name: "Test Project".to_string(),
+        };
+
+        let errors = contract.validate(&project).unwrap_err();
+        assert_eq!(errors.get("identifier").unwrap(), &vec!["has already been taken".to_string()]);
+        assert_eq!(errors.code("identifier", "has already been taken"), "taken");
+    }
+
     #[test]
     fn test_writable_attributes() {
         let user = MockUser { id: 1, admin: true, global_permissions: HashSet::new() };
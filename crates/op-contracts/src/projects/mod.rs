@@ -11,7 +11,10 @@ mod create;
 mod update;
 mod delete;
 
-pub use base::{ProjectBaseContract, ProjectData};
+pub use base::{
+    identifier_from_name, unique_identifier, ProjectBaseContract, ProjectData, IDENTIFIER_MAX_LENGTH,
+    RESERVED_IDENTIFIERS,
+};
 pub use create::CreateProjectContract;
 pub use update::UpdateProjectContract;
 pub use delete::{DeleteProjectContract, DeleteProjectData};
@@ -15,6 +15,7 @@ pub struct UpdateProjectContract<'a, U: UserContext> {
     user: &'a U,
     project_id: Id,
     changes: ChangeTracker,
+    identifier_taken: bool,
 }
 
 impl<'a, U: UserContext> UpdateProjectContract<'a, U> {
@@ -24,9 +25,16 @@ impl<'a, U: UserContext> UpdateProjectContract<'a, U> {
             user,
             project_id,
             changes: ChangeTracker::new(),
+            identifier_taken: false,
         }
     }
 
+    /// Whether another project already uses the new identifier
+    pub fn with_identifier_taken(mut self, taken: bool) -> Self {
+        self.identifier_taken = taken;
+        self
+    }
+
     /// Mark an attribute as changed
     pub fn mark_changed(&mut self, attribute: impl Into<String>) {
         self.changes.mark_changed(attribute);
@@ -47,10 +55,17 @@ impl<'a, U: UserContext> UpdateProjectContract<'a, U> {
         }
     }
 
-    /// Validate that identifier is not changed (it's immutable after creation)
-    fn validate_identifier_not_changed(&self, errors: &mut ValidationErrors) {
-        if self.changes.is_changed("identifier") {
-            errors.add("identifier", "cannot be changed after project creation");
+    /// Validate a changed identifier; only admins may rename the URL of a
+    /// project once it is set
+    fn validate_identifier_change(&self, errors: &mut ValidationErrors) {
+        if !self.changes.is_changed("identifier") {
+            return;
+        }
+
+        if !self.user.is_admin() {
+            errors.add_with_code("identifier", "error_readonly", "cannot be changed after project creation");
+        } else {
+            self.base.validate_identifier_available(self.identifier_taken, errors);
         }
     }
 
@@ -88,7 +103,7 @@ impl<'a, U: UserContext, T: ProjectData> Contract<T> for UpdateProjectContract<'
         self.validate_user_allowed_to_edit(&mut errors);
 
         // Check immutable fields
-        self.validate_identifier_not_changed(&mut errors);
+        self.validate_identifier_change(&mut errors);
 
         // Check parent change
         self.validate_parent_change(entity.parent_id(), &mut errors);
@@ -96,11 +111,9 @@ impl<'a, U: UserContext, T: ProjectData> Contract<T> for UpdateProjectContract<'
         // Run base validations (but skip identifier if not changed)
         if let Err(base_errors) = self.base.validate(entity) {
             // Filter out identifier errors if identifier wasn't changed
-            for (field, messages) in base_errors.errors {
-                if field != "identifier" || self.changes.is_changed("identifier") {
-                    for msg in messages {
-                        errors.add(&field, msg);
-                    }
+            for error in base_errors.property_errors() {
+                if error.property != "identifier" || self.changes.is_changed("identifier") {
+                    errors.add_property_error(error);
                 }
             }
         }
@@ -113,9 +126,9 @@ impl<'a, U: UserContext, T: ProjectData> Contract<T> for UpdateProjectContract<'
     }
 
     fn is_writable(&self, attribute: &str) -> bool {
-        // Identifier is not writable on update
+        // Identifier is only writable on update for admins
         if attribute == "identifier" {
-            return false;
+            return self.user.is_admin();
         }
 
         matches!(
@@ -208,13 +221,50 @@ mod tests {
     }
 
     #[test]
-    fn test_identifier_not_writable() {
-        let user = MockUser { id: 1, admin: true, project_permissions: HashSet::new() };
+    fn test_identifier_writable_only_for_admins() {
+        let mut permissions = HashSet::new();
+        permissions.insert((permissions::EDIT_PROJECT.to_string(), 1));
+        let user = MockUser { id: 2, admin: false, project_permissions: permissions };
         let contract = UpdateProjectContract::new(&user, 1);
 
         // Use fully qualified syntax for trait method
         assert!(!<UpdateProjectContract<'_, MockUser> as Contract<MockProject>>::is_writable(&contract, "identifier"));
         assert!(<UpdateProjectContract<'_, MockUser> as Contract<MockProject>>::is_writable(&contract, "name"));
+
+        let admin = MockUser { id: 1, admin: true, project_permissions: HashSet::new() };
+        let contract = UpdateProjectContract::new(&admin, 1);
+        assert!(<UpdateProjectContract<'_, MockUser> as Contract<MockProject>>::is_writable(&contract, "identifier"));
+    }
+
+    #[test]
+    fn test_identifier_change() {
+        let project = MockProject {
+            id: Some(1),
+            identifier: "renamed".to_string(),
+            name: "Test".to_string(),
+            parent_id: None,
+        };
+
+        let mut permissions = HashSet::new();
+        permissions.insert((permissions::EDIT_PROJECT.to_string(), 1));
+        let user = MockUser { id: 2, admin: false, project_permissions: permissions };
+        let mut contract = UpdateProjectContract::new(&user, 1);
+        contract.mark_changed("identifier");
+        assert!(contract.validate(&project).unwrap_err().has_error("identifier"));
+
+        let admin = MockUser { id: 1, admin: true, project_permissions: HashSet::new() };
+        let mut contract = UpdateProjectContract::new(&admin, 1);
+        contract.mark_changed("identifier");
+        assert!(contract.validate(&project).is_ok());
+
+        let mut contract = UpdateProjectContract::new(&admin, 1).with_identifier_taken(true);
+        contract.mark_changed("identifier");
+        assert!(contract.validate(&project).unwrap_err().has_error("identifier"));
+
+        let invalid = MockProject { identifier: "Renamed!".to_string(), ..project };
+        let mut contract = UpdateProjectContract::new(&admin, 1);
+        contract.mark_changed("identifier");
+        assert!(contract.validate(&invalid).unwrap_err().has_error("identifier"));
     }
 
     #[test]
@@ -2,6 +2,7 @@
 //!
 //! Mirrors: app/contracts/users/base_contract.rb
 
+use op_core::email::normalize_email;
 use op_core::error::ValidationErrors;
 use op_core::traits::Id;
 use regex::Regex;
@@ -9,11 +10,6 @@ use std::sync::LazyLock;
 
 use crate::base::{Contract, UserContext, ValidationResult};
 
-/// Valid email pattern
-static EMAIL_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").unwrap()
-});
-
 /// Valid login pattern
 static LOGIN_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"^[a-zA-Z0-9_@.\-]+$").unwrap()
@@ -97,7 +93,7 @@ impl<'a, U: UserContext> UserBaseContract<'a, U> {
             return;
         }
 
-        if !EMAIL_PATTERN.is_match(email) {
+        if normalize_email(email).is_err() {
             errors.add("mail", "is not a valid email address");
         }
     }
@@ -197,6 +193,21 @@ mod tests {
         assert!(result.unwrap_err().has_error("mail"));
     }
 
+    #[test]
+    fn test_internationalized_email() {
+        let ctx = MockUserContext { admin: true };
+        let contract = UserBaseContract::new(&ctx);
+
+        let user = MockUser {
+            login: "john.doe".to_string(),
+            firstname: "John".to_string(),
+            lastname: "Doe".to_string(),
+            mail: "john@bücher.de".to_string(),
+        };
+
+        assert!(contract.validate(&user).is_ok());
+    }
+
     #[test]
     fn test_reserved_login() {
         let ctx = MockUserContext { admin: true };
@@ -22,6 +22,19 @@ pub trait WorkPackageData: Send + Sync {
     fn done_ratio(&self) -> i32;
     fn estimated_hours(&self) -> Option<f64>;
     fn lock_version(&self) -> i32;
+
+    fn start_date(&self) -> Option<chrono::NaiveDate> {
+        None
+    }
+
+    fn due_date(&self) -> Option<chrono::NaiveDate> {
+        None
+    }
+
+    /// Working days from the start to the due date, both included
+    fn duration(&self) -> Option<i32> {
+        None
+    }
 }
 
 /// Base contract for work packages with common validations
@@ -81,6 +94,27 @@ impl<'a, U: UserContext> WorkPackageBaseContract<'a, U> {
         }
     }
 
+    /// Validate the due date is not before the start date
+    pub fn validate_dates(
+        &self,
+        start_date: Option<chrono::NaiveDate>,
+        due_date: Option<chrono::NaiveDate>,
+        errors: &mut ValidationErrors,
+    ) {
+        if let (Some(start), Some(due)) = (start_date, due_date) {
+            if due < start {
+                errors.add("due_date", "must be greater than or equal to the start date");
+            }
+        }
+    }
+
+    /// Validate duration is positive if present
+    pub fn validate_duration(&self, duration: Option<i32>, errors: &mut ValidationErrors) {
+        if duration.is_some_and(|d| d < 1) {
+            errors.add("duration", "must be greater than 0");
+        }
+    }
+
     /// Check if user can edit work packages in the project
     pub fn user_allowed_to_edit(&self) -> bool {
         self.user.is_admin()
@@ -114,6 +148,8 @@ impl<'a, U: UserContext, T: WorkPackageData> Contract<T> for WorkPackageBaseCont
         self.validate_status(entity.status_id(), &mut errors);
         self.validate_done_ratio(entity.done_ratio(), &mut errors);
         self.validate_estimated_hours(entity.estimated_hours(), &mut errors);
+        self.validate_dates(entity.start_date(), entity.due_date(), &mut errors);
+        self.validate_duration(entity.duration(), &mut errors);
 
         if errors.is_empty() {
             Ok(())
@@ -223,4 +259,21 @@ mod tests {
         let result = contract.validate(&wp);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_invalid_schedule() {
+        let user = MockUser { id: 1, admin: true, permissions: HashSet::new() };
+        let contract = WorkPackageBaseContract::new(&user, 1);
+        let mut errors = ValidationErrors::new();
+
+        let start = chrono::NaiveDate::from_ymd_opt(2024, 1, 5);
+        contract.validate_dates(start, start, &mut errors);
+        contract.validate_duration(Some(1), &mut errors);
+        assert!(errors.is_empty());
+
+        contract.validate_dates(start, chrono::NaiveDate::from_ymd_opt(2024, 1, 4), &mut errors);
+        contract.validate_duration(Some(0), &mut errors);
+        assert!(errors.has_error("due_date"));
+        assert!(errors.has_error("duration"));
+    }
 }
@@ -78,6 +78,8 @@ impl<'a, U: UserContext, T: WorkPackageData> Contract<T> for CreateWorkPackageCo
                 | "parent_id"
                 | "start_date"
                 | "due_date"
+                | "duration"
+                | "ignore_non_working_days"
                 | "estimated_hours"
                 | "done_ratio"
                 | "category_id"
@@ -34,6 +34,24 @@ impl<'a, U: UserContext> DeleteWorkPackageContract<'a, U> {
         }
     }
 
+    /// Deleting a parent deletes its subtree, which requires managing subtasks
+    fn validate_user_allowed_to_delete_descendants(
+        &self,
+        entity: &DeleteWorkPackageData,
+        errors: &mut ValidationErrors,
+    ) {
+        if entity.descendant_count == 0 || self.user.is_admin() {
+            return;
+        }
+
+        if !self.user.allowed_in_project(permissions::MANAGE_SUBTASKS, self.project_id) {
+            errors.add(
+                "base",
+                "You are not authorized to delete the children of this work package",
+            );
+        }
+    }
+
     /// Get the work package ID
     pub fn work_package_id(&self) -> Id {
         self.work_package_id
@@ -49,18 +67,17 @@ impl<'a, U: UserContext> DeleteWorkPackageContract<'a, U> {
 pub struct DeleteWorkPackageData {
     pub id: Id,
     pub project_id: Id,
+    /// Number of descendants deleted along with the work package
+    pub descendant_count: usize,
 }
 
 impl<'a, U: UserContext> Contract<DeleteWorkPackageData> for DeleteWorkPackageContract<'a, U> {
-    fn validate(&self, _entity: &DeleteWorkPackageData) -> ValidationResult {
+    fn validate(&self, entity: &DeleteWorkPackageData) -> ValidationResult {
         let mut errors = ValidationErrors::new();
 
         // Check permissions
         self.validate_user_allowed_to_delete(&mut errors);
-
-        // Additional validations could include:
-        // - Check if work package has children (may need to delete children first)
-        // - Check if work package is referenced by other entities
+        self.validate_user_allowed_to_delete_descendants(entity, &mut errors);
 
         if errors.is_empty() {
             Ok(())
@@ -101,7 +118,7 @@ mod tests {
         let user = MockUser { id: 1, admin: true, permissions: HashSet::new() };
         let contract = DeleteWorkPackageContract::new(&user, 1, 1);
 
-        let data = DeleteWorkPackageData { id: 1, project_id: 1 };
+        let data = DeleteWorkPackageData { id: 1, project_id: 1, descendant_count: 0 };
         assert!(contract.validate(&data).is_ok());
     }
 
@@ -110,7 +127,7 @@ mod tests {
         let user = MockUser { id: 1, admin: false, permissions: HashSet::new() };
         let contract = DeleteWorkPackageContract::new(&user, 1, 1);
 
-        let data = DeleteWorkPackageData { id: 1, project_id: 1 };
+        let data = DeleteWorkPackageData { id: 1, project_id: 1, descendant_count: 0 };
         let result = contract.validate(&data);
         assert!(result.is_err());
     }
@@ -123,7 +140,28 @@ mod tests {
         let user = MockUser { id: 1, admin: false, permissions };
         let contract = DeleteWorkPackageContract::new(&user, 1, 1);
 
-        let data = DeleteWorkPackageData { id: 1, project_id: 1 };
+        let data = DeleteWorkPackageData { id: 1, project_id: 1, descendant_count: 0 };
+        assert!(contract.validate(&data).is_ok());
+    }
+
+    #[test]
+    fn test_deleting_descendants_requires_manage_subtasks() {
+        let mut permissions = HashSet::new();
+        permissions.insert((permissions::DELETE_WORK_PACKAGES.to_string(), 1));
+
+        let user = MockUser { id: 1, admin: false, permissions };
+        let contract = DeleteWorkPackageContract::new(&user, 1, 1);
+
+        let data = DeleteWorkPackageData { id: 1, project_id: 1, descendant_count: 2 };
+        let errors = contract.validate(&data).unwrap_err();
+        assert!(errors.has_error("base"));
+
+        let mut permissions = HashSet::new();
+        permissions.insert((permissions::DELETE_WORK_PACKAGES.to_string(), 1));
+        permissions.insert((permissions::MANAGE_SUBTASKS.to_string(), 1));
+
+        let user = MockUser { id: 1, admin: false, permissions };
+        let contract = DeleteWorkPackageContract::new(&user, 1, 1);
         assert!(contract.validate(&data).is_ok());
     }
 }
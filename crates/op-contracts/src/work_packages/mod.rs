@@ -10,11 +10,13 @@ mod base;
 mod create;
 mod update;
 mod delete;
+mod restore;
 
 pub use base::{WorkPackageBaseContract, WorkPackageData};
 pub use create::CreateWorkPackageContract;
 pub use update::UpdateWorkPackageContract;
 pub use delete::{DeleteWorkPackageContract, DeleteWorkPackageData};
+pub use restore::{RestoreWorkPackageContract, RestoreWorkPackageData};
 
 /// Permissions required for work package operations
 pub mod permissions {
@@ -27,4 +29,6 @@ pub mod permissions {
     pub const EDIT_OWN_WORK_PACKAGE_NOTES: &str = "edit_own_work_package_notes";
     pub const ASSIGN_VERSIONS: &str = "assign_versions";
     pub const LOG_TIME: &str = "log_time";
+    pub const SHARE_WORK_PACKAGES: &str = "share_work_packages";
+    pub const VIEW_SHARED_WORK_PACKAGES: &str = "view_shared_work_packages";
 }
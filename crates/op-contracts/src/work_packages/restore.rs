@@ -0,0 +1,147 @@
+//! Restore contract for work packages in the trash
+
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+
+use crate::base::{Contract, UserContext, ValidationResult};
+use super::permissions;
+
+/// Contract for taking a work package out of the trash
+pub struct RestoreWorkPackageContract<'a, U: UserContext> {
+    user: &'a U,
+    project_id: Id,
+}
+
+impl<'a, U: UserContext> RestoreWorkPackageContract<'a, U> {
+    pub fn new(user: &'a U, project_id: Id) -> Self {
+        Self { user, project_id }
+    }
+
+    /// Restoring needs the permissions deleting would need
+    fn validate_user_allowed_to_restore(&self, entity: &RestoreWorkPackageData, errors: &mut ValidationErrors) {
+        if self.user.is_admin() {
+            return;
+        }
+
+        if !self.user.allowed_in_project(permissions::DELETE_WORK_PACKAGES, self.project_id) {
+            errors.add("base", "You are not authorized to restore this work package");
+        } else if entity.descendant_count > 0
+            && !self.user.allowed_in_project(permissions::MANAGE_SUBTASKS, self.project_id)
+        {
+            errors.add(
+                "base",
+                "You are not authorized to restore the children of this work package",
+            );
+        }
+    }
+
+    /// The restored work packages must fit where they are put back
+    fn validate_restorable(&self, entity: &RestoreWorkPackageData, errors: &mut ValidationErrors) {
+        if !entity.project_active {
+            errors.add("project", "is archived or no longer exists");
+        }
+        if !entity.statuses_exist {
+            errors.add("status", "no longer exists");
+        }
+        if entity.parent_trashed {
+            errors.add("parent", "is in the trash; restore it first");
+        }
+    }
+}
+
+/// Minimal data needed for restore validation
+pub struct RestoreWorkPackageData {
+    pub id: Id,
+    pub project_id: Id,
+    /// Number of descendants restored along with the work package
+    pub descendant_count: usize,
+    /// Whether the project exists and is not archived
+    pub project_active: bool,
+    /// Whether the statuses of all restored work packages still exist
+    pub statuses_exist: bool,
+    /// Whether the parent is still in the trash
+    pub parent_trashed: bool,
+}
+
+impl<'a, U: UserContext> Contract<RestoreWorkPackageData> for RestoreWorkPackageContract<'a, U> {
+    fn validate(&self, entity: &RestoreWorkPackageData) -> ValidationResult {
+        let mut errors = ValidationErrors::new();
+
+        self.validate_user_allowed_to_restore(entity, &mut errors);
+        if errors.is_empty() {
+            self.validate_restorable(entity, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn is_writable(&self, _attribute: &str) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    struct MockUser {
+        admin: bool,
+        permissions: HashSet<(String, Id)>,
+    }
+
+    impl UserContext for MockUser {
+        fn id(&self) -> Id { 1 }
+        fn is_admin(&self) -> bool { self.admin }
+        fn is_anonymous(&self) -> bool { false }
+        fn allowed_in_project(&self, permission: &str, project_id: Id) -> bool {
+            self.admin || self.permissions.contains(&(permission.to_string(), project_id))
+        }
+        fn allowed_globally(&self, _permission: &str) -> bool { false }
+    }
+
+    fn restorable() -> RestoreWorkPackageData {
+        RestoreWorkPackageData {
+            id: 1,
+            project_id: 1,
+            descendant_count: 0,
+            project_active: true,
+            statuses_exist: true,
+            parent_trashed: false,
+        }
+    }
+
+    #[test]
+    fn test_restore_requires_delete_permission() {
+        let user = MockUser { admin: false, permissions: HashSet::new() };
+        let contract = RestoreWorkPackageContract::new(&user, 1);
+        assert!(contract.validate(&restorable()).unwrap_err().has_error("base"));
+
+        let permissions = HashSet::from([(permissions::DELETE_WORK_PACKAGES.to_string(), 1)]);
+        let user = MockUser { admin: false, permissions };
+        let contract = RestoreWorkPackageContract::new(&user, 1);
+        assert!(contract.validate(&restorable()).is_ok());
+
+        let data = RestoreWorkPackageData { descendant_count: 1, ..restorable() };
+        assert!(contract.validate(&data).unwrap_err().has_error("base"));
+    }
+
+    #[test]
+    fn test_restore_fails_where_the_work_package_no_longer_fits() {
+        let user = MockUser { admin: true, permissions: HashSet::new() };
+        let contract = RestoreWorkPackageContract::new(&user, 1);
+
+        let data = RestoreWorkPackageData { project_active: false, ..restorable() };
+        assert!(contract.validate(&data).unwrap_err().has_error("project"));
+
+        let data = RestoreWorkPackageData { statuses_exist: false, ..restorable() };
+        assert!(contract.validate(&data).unwrap_err().has_error("status"));
+
+        let data = RestoreWorkPackageData { parent_trashed: true, ..restorable() };
+        assert!(contract.validate(&data).unwrap_err().has_error("parent"));
+    }
+}
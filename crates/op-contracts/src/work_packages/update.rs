@@ -100,6 +100,8 @@ impl<'a, U: UserContext, T: WorkPackageData> Contract<T> for UpdateWorkPackageCo
                 | "parent_id"
                 | "start_date"
                 | "due_date"
+                | "duration"
+                | "ignore_non_working_days"
                 | "estimated_hours"
                 | "done_ratio"
                 | "category_id"
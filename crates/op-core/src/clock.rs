@@ -0,0 +1,123 @@
+//! Clock
+//!
+//! Source of the current time for services that keep time-based state in
+//! memory, such as rate limits. Tests use [`ManualClock`] to move time
+//! forward without sleeping.
+//!
+//! Relative dates such as "today" depend on where the user is: at 23:30
+//! UTC it is already tomorrow in Brisbane. [`today_in`] and
+//! [`start_of_week`] compute the calendar days every date filter and date
+//! alert compares against.
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+pub use chrono_tz::Tz;
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Calendar day it currently is in the time zone
+    fn today(&self, tz: Tz) -> NaiveDate {
+        today_in(self.now(), tz)
+    }
+}
+
+/// Calendar day of `now` in the time zone
+pub fn today_in(now: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    now.with_timezone(&tz).date_naive()
+}
+
+/// Monday of the week containing `day`, as Postgres' `date_trunc('week', ...)`
+pub fn start_of_week(day: NaiveDate) -> NaiveDate {
+    day - Duration::days(day.weekday().num_days_from_monday() as i64)
+}
+
+/// Time zone of an IANA name such as "Europe/Berlin"
+pub fn parse_time_zone(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+/// Wall clock time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that only moves when told to
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: Mutex::new(now) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_advances() {
+        let start = Utc::now();
+        let clock = ManualClock::new(start);
+        clock.advance(Duration::minutes(5));
+        assert_eq!(clock.now(), start + Duration::minutes(5));
+
+        clock.set(start);
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn test_today_depends_on_the_time_zone() {
+        let brisbane = parse_time_zone("Australia/Brisbane").unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+
+        // 13:59 UTC is 23:59 in Brisbane (UTC+10), 14:00 UTC is midnight
+        let clock = ManualClock::new("2024-03-05T13:59:00Z".parse().unwrap());
+        assert_eq!(clock.today(brisbane), day(5));
+        clock.advance(Duration::minutes(1));
+        assert_eq!(clock.today(brisbane), day(6));
+        assert_eq!(clock.today(Tz::UTC), day(5));
+
+        assert!(parse_time_zone("Mars/Olympus_Mons").is_none());
+    }
+
+    #[test]
+    fn test_start_of_week_is_monday() {
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        // 2024-03-04 is a Monday
+        assert_eq!(start_of_week(day(4)), day(4));
+        assert_eq!(start_of_week(day(6)), day(4));
+        assert_eq!(start_of_week(day(10)), day(4));
+        assert_eq!(start_of_week(day(11)), day(11));
+    }
+}
@@ -5,6 +5,9 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// JWT secret of the default configuration, only fit for development
+pub const DEFAULT_JWT_SECRET: &str = "change-me-in-production";
+
 /// Main application configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AppConfig {
@@ -28,6 +31,11 @@ pub struct AppConfig {
 
     /// Instance-specific settings
     pub instance: InstanceConfig,
+
+    /// Values of environment variables that could not be parsed, reported
+    /// by [`AppConfig::validate`]
+    #[serde(skip)]
+    pub env_errors: Vec<FatalError>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -35,7 +43,44 @@ pub struct DatabaseConfig {
     pub url: String,
     pub pool_size: u32,
     pub pool_timeout_seconds: u64,
+    /// Seconds after which work package queries are canceled; 0 disables
+    /// the timeout
     pub statement_timeout_seconds: u64,
+    /// What to do about the schema at startup
+    #[serde(default)]
+    pub schema: SchemaMode,
+}
+
+impl DatabaseConfig {
+    /// Timeout of work package queries, if any
+    pub fn statement_timeout(&self) -> Option<std::time::Duration> {
+        (self.statement_timeout_seconds > 0).then(|| std::time::Duration::from_secs(self.statement_timeout_seconds))
+    }
+}
+
+/// How op-server treats the database schema at startup
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaMode {
+    /// Apply the embedded migrations, for databases op-rs manages
+    Migrate,
+    /// Only report tables and columns missing from a database managed by
+    /// the Rails app
+    #[default]
+    Check,
+    /// Assume the schema is in place
+    Skip,
+}
+
+impl SchemaMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "migrate" => Some(Self::Migrate),
+            "check" => Some(Self::Check),
+            "skip" => Some(Self::Skip),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -46,6 +91,68 @@ pub struct ServerConfig {
     pub request_timeout_seconds: u64,
     pub max_body_size_bytes: usize,
     pub rails_relative_url_root: Option<String>,
+    /// Running in development, where insecure defaults are tolerated
+    #[serde(default)]
+    pub development: bool,
+    #[serde(default)]
+    pub cors: CorsConfig,
+}
+
+/// Cross-origin requests the API accepts
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API, `*` for any
+    pub allowed_origins: Vec<String>,
+    /// Whether browsers send cookies and credentials along
+    pub allow_credentials: bool,
+}
+
+impl CorsConfig {
+    /// Whether any origin is allowed
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin.trim() == "*")
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_string()],
+            allow_credentials: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Path the instance is served under, e.g. `/openproject`; empty when
+    /// served at the root of its host
+    pub fn url_root(&self) -> String {
+        normalize_url_root(self.rails_relative_url_root.as_deref().unwrap_or_default())
+    }
+}
+
+/// A relative URL root with a leading and without a trailing slash, or
+/// empty for the root of the host: `openproject/` becomes `/openproject`
+pub fn normalize_url_root(root: &str) -> String {
+    let root = root.trim().trim_matches('/');
+    if root.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", root)
+    }
+}
+
+/// Base of absolute URLs, the scheme and host of `base_url` followed by
+/// the URL root exactly once, without a trailing slash
+pub fn join_url_root(base_url: &str, root: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let root = normalize_url_root(root);
+    if base_url.ends_with(&root) {
+        base_url.to_string()
+    } else {
+        format!("{}{}", base_url, root)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -97,6 +204,40 @@ pub struct LdapConfig {
     pub bind_password: Option<String>,
     pub filter: Option<String>,
     pub attribute_mapping: LdapAttributeMapping,
+    /// Synchronization of the source's groups, disabled when unset
+    #[serde(default)]
+    pub group_sync: Option<LdapGroupSyncConfig>,
+}
+
+/// Groups of an LDAP source mirrored as local groups
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LdapGroupSyncConfig {
+    /// Base DN groups are searched below, the source's base DN when empty
+    pub base_dn: String,
+    /// Filter selecting the synchronized groups
+    pub filter: String,
+    /// Attribute listing the members, as DNs or logins
+    pub membership_attribute: String,
+    /// Attribute naming the local group
+    pub name_attribute: String,
+    /// Attribute identifying a group across renames
+    pub id_attribute: String,
+    /// Entries and member values fetched per LDAP request
+    pub page_size: u32,
+}
+
+impl Default for LdapGroupSyncConfig {
+    fn default() -> Self {
+        Self {
+            base_dn: String::new(),
+            filter: "(objectClass=groupOfNames)".into(),
+            membership_attribute: "member".into(),
+            name_attribute: "cn".into(),
+            id_attribute: "entryUUID".into(),
+            page_size: 500,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -116,6 +257,34 @@ pub struct EmailConfig {
     pub ms_graph: Option<MsGraphConfig>,
     pub from_address: String,
     pub from_name: String,
+    /// Soft limits and circuit breaker for outbound email
+    #[serde(default)]
+    pub send_limits: EmailSendLimits,
+}
+
+/// Outbound email limits; emails over a limit are deferred, not dropped
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EmailSendLimits {
+    /// Emails sent per hour across the instance (None = unlimited)
+    pub per_hour: Option<u32>,
+    /// Emails sent to one recipient per hour (None = unlimited)
+    pub per_recipient_per_hour: Option<u32>,
+    /// Consecutive delivery failures pausing all sends (0 = never pause)
+    pub failure_threshold: u32,
+    /// How long sends are paused once the failure threshold is reached
+    pub cooldown_seconds: u64,
+}
+
+impl Default for EmailSendLimits {
+    fn default() -> Self {
+        Self {
+            per_hour: None,
+            per_recipient_per_hour: None,
+            failure_threshold: 5,
+            cooldown_seconds: 300,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
@@ -163,6 +332,15 @@ pub struct StorageConfig {
     pub max_attachment_size: usize,
     /// Allowed file extensions
     pub allowed_extensions: Vec<String>,
+    /// Blocked file extensions, in addition to the built-in denylist
+    #[serde(default)]
+    pub blocked_extensions: Vec<String>,
+    /// Reject uploads whose content is executable but declared otherwise
+    #[serde(default)]
+    pub strict_content_match: bool,
+    /// Total attachment storage of the instance in bytes (None = unlimited)
+    #[serde(default)]
+    pub attachment_quota: Option<i64>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -240,6 +418,9 @@ pub struct InstanceConfig {
     pub first_day_of_week: u8,
     /// First week of year calculation
     pub first_week_of_year: u8,
+    /// Days work packages stay in the trash before they are deleted
+    /// permanently
+    pub work_package_trash_retention_days: u32,
 }
 
 impl Default for AppConfig {
@@ -250,17 +431,20 @@ impl Default for AppConfig {
                 pool_size: 10,
                 pool_timeout_seconds: 5,
                 statement_timeout_seconds: 30,
+                schema: SchemaMode::default(),
             },
             server: ServerConfig {
                 host: "0.0.0.0".to_string(),
                 port: 8080,
                 workers: None,
                 request_timeout_seconds: 60,
-                max_body_size_bytes: 100 * 1024 * 1024, // 100MB
+                max_body_size_bytes: 257 * 1024 * 1024, // the largest attachment and the rest of the form
                 rails_relative_url_root: None,
+                development: false,
+                cors: CorsConfig::default(),
             },
             auth: AuthConfig {
-                jwt_secret: "change-me-in-production".to_string(),
+                jwt_secret: DEFAULT_JWT_SECRET.to_string(),
                 token_expiration_seconds: 86400, // 24 hours
                 session_timeout_minutes: 30,
                 self_registration: SelfRegistration::Disabled,
@@ -274,12 +458,16 @@ impl Default for AppConfig {
                 ms_graph: None,
                 from_address: "openproject@example.com".to_string(),
                 from_name: "OpenProject".to_string(),
+                send_limits: EmailSendLimits::default(),
             },
             storage: StorageConfig {
                 local_path: "/var/openproject/assets".to_string(),
                 s3: None,
                 max_attachment_size: 256 * 1024 * 1024, // 256MB
                 allowed_extensions: vec![],
+                blocked_extensions: vec![],
+                strict_content_match: false,
+                attachment_quota: None,
             },
             features: FeatureFlags::default(),
             instance: InstanceConfig {
@@ -291,7 +479,9 @@ impl Default for AppConfig {
                 date_format: "%Y-%m-%d".to_string(),
                 first_day_of_week: 1,
                 first_week_of_year: 1,
+                work_package_trash_retention_days: 30,
             },
+            env_errors: Vec::new(),
         }
     }
 }
@@ -354,8 +544,13 @@ pub enum ConfigError {
 
 impl AppConfig {
     /// Load configuration from environment variables
+    ///
+    /// Values that cannot be parsed are not replaced by defaults but kept in
+    /// `env_errors`, for [`AppConfig::validate`] to report along with the
+    /// other problems.
     pub fn from_env() -> Result<Self, ConfigError> {
         let mut config = Self::default();
+        let mut errors = Vec::new();
 
         // Database - check DATABASE_URL first, then Railway's individual vars
         if let Ok(url) = std::env::var("DATABASE_URL") {
@@ -364,20 +559,48 @@ impl AppConfig {
             // Railway provides PGHOST, PGPORT, PGUSER, PGPASSWORD, PGDATABASE
             config.database.url = url;
         }
-        if let Ok(size) = std::env::var("DATABASE_POOL_SIZE") {
-            config.database.pool_size = size.parse().unwrap_or(10);
+        if let Some(size) = env_number("DATABASE_POOL_SIZE", &mut errors) {
+            config.database.pool_size = size;
+        }
+        if let Some(seconds) = env_number("DATABASE_STATEMENT_TIMEOUT", &mut errors) {
+            config.database.statement_timeout_seconds = seconds;
+        }
+        if let Ok(mode) = std::env::var("DATABASE_SCHEMA_MODE") {
+            match SchemaMode::parse(&mode) {
+                Some(schema) => config.database.schema = schema,
+                None => errors.push(FatalError::new(
+                    "DATABASE_SCHEMA_MODE",
+                    format!("expected migrate, check or skip, got '{}'", mode),
+                )),
+            }
         }
 
         // Server
         if let Ok(host) = std::env::var("HOST") {
             config.server.host = host;
         }
-        if let Ok(port) = std::env::var("PORT") {
-            config.server.port = port.parse().unwrap_or(8080);
+        if let Some(port) = env_number("PORT", &mut errors) {
+            config.server.port = port;
         }
         if let Ok(root) = std::env::var("RAILS_RELATIVE_URL_ROOT") {
             config.server.rails_relative_url_root = Some(root);
         }
+        if let Ok(env) = std::env::var("RAILS_ENV") {
+            config.server.development = env == "development";
+        }
+        if let Some(size) = env_number("OPENPROJECT_MAX_BODY_SIZE", &mut errors) {
+            config.server.max_body_size_bytes = size;
+        }
+        if let Ok(origins) = std::env::var("OPENPROJECT_CORS_ALLOWED_ORIGINS") {
+            config.server.cors.allowed_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        if let Ok(v) = std::env::var("OPENPROJECT_CORS_ALLOW_CREDENTIALS") {
+            config.server.cors.allow_credentials = v == "true" || v == "1";
+        }
 
         // Auth
         if let Ok(secret) = std::env::var("SECRET_KEY_BASE") {
@@ -390,6 +613,9 @@ impl AppConfig {
         if let Ok(path) = std::env::var("OPENPROJECT_ATTACHMENTS_STORAGE_PATH") {
             config.storage.local_path = path;
         }
+        if let Some(size) = env_number("OPENPROJECT_ATTACHMENT_MAX_SIZE", &mut errors) {
+            config.storage.max_attachment_size = size;
+        }
 
         // S3 storage
         if let Ok(bucket) = std::env::var("S3_BUCKET") {
@@ -405,14 +631,20 @@ impl AppConfig {
             });
         }
 
-        // Email
-        if let Ok(host) = std::env::var("SMTP_HOST") {
+        // Email, configured by any SMTP variable so a missing host is noticed
+        const SMTP_VARS: [&str; 7] = [
+            "SMTP_HOST",
+            "SMTP_PORT",
+            "SMTP_USERNAME",
+            "SMTP_PASSWORD",
+            "SMTP_AUTH",
+            "SMTP_STARTTLS",
+            "SMTP_SSL",
+        ];
+        if SMTP_VARS.iter().any(|key| std::env::var(key).is_ok()) {
             config.email.smtp = Some(SmtpConfig {
-                host,
-                port: std::env::var("SMTP_PORT")
-                    .ok()
-                    .and_then(|p| p.parse().ok())
-                    .unwrap_or(587),
+                host: std::env::var("SMTP_HOST").unwrap_or_default(),
+                port: env_number("SMTP_PORT", &mut errors).unwrap_or(587),
                 username: std::env::var("SMTP_USERNAME").ok(),
                 password: std::env::var("SMTP_PASSWORD").ok(),
                 authentication: std::env::var("SMTP_AUTH").ok(),
@@ -428,26 +660,28 @@ impl AppConfig {
             config.email.from_address = from;
         }
 
-        // Microsoft Graph (Office 365) email
-        if let Ok(tenant_id) = std::env::var("MS_GRAPH_TENANT_ID") {
-            if let (Ok(client_id), Ok(client_secret), Ok(sender)) = (
-                std::env::var("MS_GRAPH_CLIENT_ID"),
-                std::env::var("MS_GRAPH_CLIENT_SECRET"),
-                std::env::var("MS_GRAPH_SENDER"),
-            ) {
-                config.email.ms_graph = Some(MsGraphConfig {
-                    tenant_id,
-                    client_id,
-                    client_secret,
-                    sender,
-                });
+        // Microsoft Graph (Office 365) email, kept when partially configured
+        // so validation can tell what is missing
+        const MS_GRAPH_VARS: [&str; 4] =
+            ["MS_GRAPH_TENANT_ID", "MS_GRAPH_CLIENT_ID", "MS_GRAPH_CLIENT_SECRET", "MS_GRAPH_SENDER"];
+        if MS_GRAPH_VARS.iter().any(|key| std::env::var(key).is_ok()) {
+            let [tenant_id, client_id, client_secret, sender] =
+                MS_GRAPH_VARS.map(|key| std::env::var(key).unwrap_or_default());
+            let ms_graph = MsGraphConfig {
+                tenant_id,
+                client_id,
+                client_secret,
+                sender,
+            };
+            if ms_graph.missing().is_empty() {
                 // Auto-set delivery method to MsGraph if configured
                 config.email.delivery_method = EmailDeliveryMethod::MsGraph;
                 // Use sender as from_address if not explicitly set
                 if config.email.from_address == "openproject@example.com" {
-                    config.email.from_address = config.email.ms_graph.as_ref().unwrap().sender.clone();
+                    config.email.from_address = ms_graph.sender.clone();
                 }
             }
+            config.email.ms_graph = Some(ms_graph);
         }
 
         // Instance
@@ -460,7 +694,9 @@ impl AppConfig {
         if let Ok(tz) = std::env::var("TZ") {
             config.instance.timezone = tz;
         }
-
+        if let Some(days) = env_number("OPENPROJECT_WORK_PACKAGE_TRASH_RETENTION_DAYS", &mut errors) {
+            config.instance.work_package_trash_retention_days = days;
+        }
         // Features - all business features enabled by default
         // Can be disabled via environment variables
         let parse_bool = |v: String| v == "true" || v == "1" || v == "yes";
@@ -496,6 +732,7 @@ impl AppConfig {
             config.features.backlogs_enabled = parse_bool(v);
         }
 
+        config.env_errors = errors;
         Ok(config)
     }
 
@@ -520,6 +757,157 @@ impl AppConfig {
         let ip: std::net::IpAddr = self.server.host.parse().unwrap_or([0, 0, 0, 0].into());
         SocketAddr::new(ip, self.server.port)
     }
+
+    /// Check the configuration as a whole, returning the warnings to log
+    /// when it is fit to start with and every fatal error otherwise
+    pub fn validate(&self) -> Result<Vec<Warning>, Vec<FatalError>> {
+        let mut warnings = Vec::new();
+        let mut errors = self.env_errors.clone();
+
+        let secret = self.auth.jwt_secret.trim();
+        if secret.is_empty() || secret == DEFAULT_JWT_SECRET {
+            let message = "is unset or the insecure default, set SECRET_KEY_BASE to a long random value";
+            if self.server.development {
+                warnings.push(Warning::new("SECRET_KEY_BASE", message));
+            } else {
+                errors.push(FatalError::new("SECRET_KEY_BASE", message));
+            }
+        }
+
+        if let Some(smtp) = &self.email.smtp {
+            if smtp.host.trim().is_empty() {
+                errors.push(FatalError::new("SMTP_HOST", "is required when SMTP is configured"));
+            }
+        }
+
+        if let Some(ms_graph) = &self.email.ms_graph {
+            let missing = ms_graph.missing();
+            if !missing.is_empty() {
+                errors.push(FatalError::new(
+                    "MS_GRAPH_*",
+                    format!("Microsoft Graph is partially configured, {} missing", missing.join(", ")),
+                ));
+            }
+        }
+
+        if let Some(s3) = &self.storage.s3 {
+            for (key, value) in [
+                ("S3_ACCESS_KEY_ID", &s3.access_key_id),
+                ("S3_SECRET_ACCESS_KEY", &s3.secret_access_key),
+            ] {
+                if value.trim().is_empty() {
+                    errors.push(FatalError::new(key, format!("is required with S3_BUCKET '{}'", s3.bucket)));
+                }
+            }
+        }
+
+        if self.server.max_body_size_bytes < self.storage.max_attachment_size {
+            warnings.push(Warning::new(
+                "OPENPROJECT_MAX_BODY_SIZE",
+                format!(
+                    "of {} bytes is smaller than the attachment size limit of {} bytes, larger uploads are rejected",
+                    self.server.max_body_size_bytes, self.storage.max_attachment_size
+                ),
+            ));
+        }
+
+        if self.server.cors.allow_credentials && self.server.cors.allows_any_origin() {
+            errors.push(FatalError::new(
+                "OPENPROJECT_CORS_ALLOW_CREDENTIALS",
+                "cannot be combined with any origin, list the allowed origins in OPENPROJECT_CORS_ALLOWED_ORIGINS",
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl MsGraphConfig {
+    /// Variables of the settings left empty
+    fn missing(&self) -> Vec<&'static str> {
+        [
+            ("MS_GRAPH_TENANT_ID", &self.tenant_id),
+            ("MS_GRAPH_CLIENT_ID", &self.client_id),
+            ("MS_GRAPH_CLIENT_SECRET", &self.client_secret),
+            ("MS_GRAPH_SENDER", &self.sender),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.trim().is_empty())
+        .map(|(key, _)| key)
+        .collect()
+    }
+}
+
+/// Parse a numeric environment variable; a value that is not a number is
+/// recorded as an error instead of falling back to the default
+fn env_number<T: std::str::FromStr>(key: &str, errors: &mut Vec<FatalError>) -> Option<T> {
+    let value = std::env::var(key).ok()?;
+    match value.trim().parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            errors.push(FatalError::new(key, format!("expected a non-negative number, got '{}'", value)));
+            None
+        }
+    }
+}
+
+/// Configuration problem the instance still starts with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    /// Variable to fix
+    pub key: String,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.key, self.message)
+    }
+}
+
+/// Configuration problem the instance refuses to start with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FatalError {
+    /// Variable to fix
+    pub key: String,
+    pub message: String,
+}
+
+impl FatalError {
+    pub fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FatalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.key, self.message)
+    }
+}
+
+/// Message listing every fatal error, one per line
+pub fn fatal_report(errors: &[FatalError]) -> String {
+    let mut report = format!("Invalid configuration, {} problem(s) to fix before starting:", errors.len());
+    for error in errors {
+        report.push_str(&format!("\n  - {}", error));
+    }
+    report
 }
 
 #[cfg(test)]
@@ -531,6 +919,14 @@ mod tests {
         let config = AppConfig::default();
         assert_eq!(config.server.port, 8080);
         assert_eq!(config.database.pool_size, 10);
+        assert_eq!(config.database.schema, SchemaMode::Check);
+    }
+
+    #[test]
+    fn test_schema_mode_parse() {
+        assert_eq!(SchemaMode::parse("migrate"), Some(SchemaMode::Migrate));
+        assert_eq!(SchemaMode::parse("skip"), Some(SchemaMode::Skip));
+        assert_eq!(SchemaMode::parse("Migrate"), None);
     }
 
     #[test]
@@ -545,6 +941,121 @@ mod tests {
         assert_eq!(settings.get_int("key3"), Some(42));
     }
 
+    #[test]
+    fn test_url_root_is_normalized() {
+        let mut config = AppConfig::default();
+        assert_eq!(config.server.url_root(), "");
+        config.server.rails_relative_url_root = Some("openproject/".into());
+        assert_eq!(config.server.url_root(), "/openproject");
+        assert_eq!(normalize_url_root("/"), "");
+        assert_eq!(normalize_url_root("/op"), "/op");
+    }
+
+    #[test]
+    fn test_url_root_is_joined_once() {
+        assert_eq!(join_url_root("https://example.com/", "/op/"), "https://example.com/op");
+        assert_eq!(join_url_root("https://example.com", "op"), "https://example.com/op");
+        assert_eq!(join_url_root("https://example.com/op", "/op"), "https://example.com/op");
+        assert_eq!(join_url_root("https://example.com/", ""), "https://example.com");
+    }
+
+    /// A configuration fit for production
+    fn valid_config() -> AppConfig {
+        let mut config = AppConfig::default();
+        config.auth.jwt_secret = "d41d8cd98f00b204e9800998ecf8427e".to_string();
+        config
+    }
+
+    fn fatal_keys(config: &AppConfig) -> Vec<String> {
+        config.validate().unwrap_err().into_iter().map(|error| error.key).collect()
+    }
+
+    #[test]
+    fn test_default_secret_is_fatal_outside_development() {
+        assert_eq!(valid_config().validate(), Ok(vec![]));
+        assert_eq!(fatal_keys(&AppConfig::default()), vec!["SECRET_KEY_BASE"]);
+
+        let mut config = AppConfig::default();
+        config.server.development = true;
+        let warnings = config.validate().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].key, "SECRET_KEY_BASE");
+    }
+
+    #[test]
+    fn test_all_fatal_errors_are_collected() {
+        let mut config = valid_config();
+        config.env_errors.push(FatalError::new("PORT", "expected a non-negative number, got '80a'"));
+        config.email.smtp = Some(SmtpConfig {
+            host: String::new(),
+            port: 587,
+            username: Some("mailer".into()),
+            password: None,
+            authentication: None,
+            enable_starttls: true,
+            ssl: false,
+        });
+        config.email.ms_graph = Some(MsGraphConfig {
+            tenant_id: "tenant".into(),
+            client_id: "client".into(),
+            client_secret: String::new(),
+            sender: String::new(),
+        });
+        config.storage.s3 = Some(S3Config {
+            bucket: "attachments".into(),
+            region: "us-east-1".into(),
+            access_key_id: "key".into(),
+            secret_access_key: String::new(),
+            endpoint: None,
+            path_style: false,
+        });
+        config.server.cors.allow_credentials = true;
+
+        assert_eq!(
+            fatal_keys(&config),
+            vec!["PORT", "SMTP_HOST", "MS_GRAPH_*", "S3_SECRET_ACCESS_KEY", "OPENPROJECT_CORS_ALLOW_CREDENTIALS"]
+        );
+        let errors = config.validate().unwrap_err();
+        assert!(errors[2].message.contains("MS_GRAPH_CLIENT_SECRET, MS_GRAPH_SENDER"));
+
+        let report = fatal_report(&errors);
+        assert!(report.starts_with("Invalid configuration, 5 problem(s)"));
+        assert!(report.contains("\n  - PORT expected a non-negative number, got '80a'"));
+    }
+
+    #[test]
+    fn test_credentials_need_listed_origins() {
+        let mut config = valid_config();
+        config.server.cors.allow_credentials = true;
+        config.server.cors.allowed_origins = vec!["https://op.example.com".into()];
+        assert_eq!(config.validate(), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_small_body_size_is_a_warning() {
+        let mut config = valid_config();
+        config.server.max_body_size_bytes = 10 * 1024 * 1024;
+        let warnings = config.validate().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].key, "OPENPROJECT_MAX_BODY_SIZE");
+    }
+
+    #[test]
+    fn test_unparseable_numbers_are_errors() {
+        let mut errors = Vec::new();
+        std::env::set_var("OP_CONFIG_TEST_NUMBER", "4O");
+        assert_eq!(env_number::<u32>("OP_CONFIG_TEST_NUMBER", &mut errors), None);
+        std::env::set_var("OP_CONFIG_TEST_NUMBER", " 40 ");
+        assert_eq!(env_number::<u32>("OP_CONFIG_TEST_NUMBER", &mut errors), Some(40));
+        std::env::remove_var("OP_CONFIG_TEST_NUMBER");
+        assert_eq!(env_number::<u32>("OP_CONFIG_TEST_NUMBER", &mut errors), None);
+
+        assert_eq!(
+            errors,
+            vec![FatalError::new("OP_CONFIG_TEST_NUMBER", "expected a non-negative number, got '4O'")]
+        );
+    }
+
     #[test]
     fn test_server_addr() {
         let config = AppConfig::default();
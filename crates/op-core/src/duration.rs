@@ -0,0 +1,190 @@
+//! Duration and date parsing
+//!
+//! API v3 exchanges durations as ISO 8601 strings (`PT8H`, `P2D`, `PT1H30M`)
+//! and dates as `YYYY-MM-DD`. Durations are stored as fractional hours.
+//! Year and month components are rejected since their length is ambiguous,
+//! as OpenProject does.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors from parsing API durations and dates
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ParseError {
+    #[error("is not a valid ISO 8601 duration (e.g. PT8H, P1DT4H)")]
+    InvalidDuration,
+
+    #[error("must not contain {0} (only weeks, days, hours, minutes and seconds are supported)")]
+    UnsupportedComponent(&'static str),
+
+    #[error("is not a valid date (expected YYYY-MM-DD)")]
+    InvalidDate,
+
+    #[error("must be a date without a time component (expected YYYY-MM-DD)")]
+    DateTimeNotAllowed,
+}
+
+const HOURS_PER_DAY: f64 = 24.0;
+const HOURS_PER_WEEK: f64 = 7.0 * HOURS_PER_DAY;
+
+/// Parse an ISO 8601 duration into fractional hours
+pub fn parse_iso8601_duration(input: &str) -> Result<f64, ParseError> {
+    let input = input.trim();
+    let rest = input
+        .strip_prefix('P')
+        .or_else(|| input.strip_prefix('p'))
+        .ok_or(ParseError::InvalidDuration)?;
+
+    let mut hours = 0.0;
+    let mut in_time = false;
+    let mut seen_component = false;
+    let mut number = String::new();
+
+    for c in rest.chars() {
+        match c.to_ascii_uppercase() {
+            'T' if !in_time && number.is_empty() => in_time = true,
+            d if d.is_ascii_digit() || d == '.' || d == ',' => {
+                number.push(if d == ',' { '.' } else { d })
+            }
+            unit => {
+                if number.is_empty() {
+                    return Err(ParseError::InvalidDuration);
+                }
+                let value: f64 = number.parse().map_err(|_| ParseError::InvalidDuration)?;
+                number.clear();
+
+                hours += match (in_time, unit) {
+                    (false, 'Y') => return Err(ParseError::UnsupportedComponent("years")),
+                    (false, 'M') => return Err(ParseError::UnsupportedComponent("months")),
+                    (false, 'W') => value * HOURS_PER_WEEK,
+                    (false, 'D') => value * HOURS_PER_DAY,
+                    (true, 'H') => value,
+                    (true, 'M') => value / 60.0,
+                    (true, 'S') => value / 3600.0,
+                    _ => return Err(ParseError::InvalidDuration),
+                };
+                seen_component = true;
+            }
+        }
+    }
+
+    // Trailing number without unit, or "P"/"PT" alone
+    if !number.is_empty() || !seen_component {
+        return Err(ParseError::InvalidDuration);
+    }
+
+    Ok(hours)
+}
+
+/// Format fractional hours as an ISO 8601 duration, rounded to minutes
+pub fn format_iso8601_duration(hours: f64) -> String {
+    let total_minutes = (hours * 60.0).round() as i64;
+    let h = total_minutes / 60;
+    let m = total_minutes % 60;
+
+    if m == 0 {
+        format!("PT{}H", h)
+    } else {
+        format!("PT{}H{}M", h, m)
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date, rejecting datetime values
+pub fn parse_iso8601_date(input: &str) -> Result<NaiveDate, ParseError> {
+    let input = input.trim();
+
+    if input.contains(['T', 't', ' ', ':']) {
+        return Err(ParseError::DateTimeNotAllowed);
+    }
+
+    NaiveDate::parse_from_str(input, "%Y-%m-%d").map_err(|_| ParseError::InvalidDate)
+}
+
+/// Duration given either as an ISO 8601 string or as plain hours
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DurationValue {
+    Hours(f64),
+    Iso8601(String),
+}
+
+impl DurationValue {
+    /// Value in fractional hours
+    pub fn to_hours(&self) -> Result<f64, ParseError> {
+        match self {
+            Self::Hours(hours) => Ok(*hours),
+            Self::Iso8601(s) => parse_iso8601_duration(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_iso8601_duration("PT8H"), Ok(8.0));
+        assert_eq!(parse_iso8601_duration("PT1H30M"), Ok(1.5));
+        assert_eq!(parse_iso8601_duration("PT45M"), Ok(0.75));
+        assert_eq!(parse_iso8601_duration("PT1.5H"), Ok(1.5));
+        assert_eq!(parse_iso8601_duration("P2D"), Ok(48.0));
+        assert_eq!(parse_iso8601_duration("P1DT4H"), Ok(28.0));
+        assert_eq!(parse_iso8601_duration("P1W"), Ok(168.0));
+        assert_eq!(parse_iso8601_duration("PT3600S"), Ok(1.0));
+    }
+
+    #[test]
+    fn test_parse_duration_errors() {
+        assert_eq!(parse_iso8601_duration("P1Y"), Err(ParseError::UnsupportedComponent("years")));
+        assert_eq!(parse_iso8601_duration("P2M"), Err(ParseError::UnsupportedComponent("months")));
+        for invalid in ["", "P", "PT", "8H", "PT8", "PTH", "P1H", "PT1D", "PT1H2X"] {
+            assert_eq!(parse_iso8601_duration(invalid), Err(ParseError::InvalidDuration), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_iso8601_date("2024-01-31"),
+            Ok(NaiveDate::from_ymd_opt(2024, 1, 31).unwrap())
+        );
+        assert_eq!(parse_iso8601_date("2024-01-01T10:00"), Err(ParseError::DateTimeNotAllowed));
+        assert_eq!(parse_iso8601_date("2024-01-01 10:00"), Err(ParseError::DateTimeNotAllowed));
+        assert_eq!(parse_iso8601_date("2024-02-30"), Err(ParseError::InvalidDate));
+        assert_eq!(parse_iso8601_date("01/02/2024"), Err(ParseError::InvalidDate));
+    }
+
+    #[test]
+    fn test_duration_value() {
+        let value: DurationValue = serde_json::from_str("\"PT2H15M\"").unwrap();
+        assert_eq!(value.to_hours(), Ok(2.25));
+
+        let value: DurationValue = serde_json::from_str("1.5").unwrap();
+        assert_eq!(value.to_hours(), Ok(1.5));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_format_then_parse_round_trips(minutes in 0i64..1_000_000) {
+            let hours = minutes as f64 / 60.0;
+            let parsed = parse_iso8601_duration(&format_iso8601_duration(hours)).unwrap();
+            prop_assert!((parsed - hours).abs() < 1e-9);
+        }
+
+        #[test]
+        fn prop_parse_then_format_is_canonical(h in 0u32..10_000, m in 0u32..60) {
+            let input = if m == 0 { format!("PT{}H", h) } else { format!("PT{}H{}M", h, m) };
+            let hours = parse_iso8601_duration(&input).unwrap();
+            prop_assert_eq!(format_iso8601_duration(hours), input);
+        }
+
+        #[test]
+        fn prop_days_are_whole_hours(d in 0u32..1_000, h in 0u32..24) {
+            let hours = parse_iso8601_duration(&format!("P{}DT{}H", d, h)).unwrap();
+            prop_assert_eq!(hours, (d * 24 + h) as f64);
+        }
+    }
+}
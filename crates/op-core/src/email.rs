@@ -0,0 +1,509 @@
+//! Email address validation and normalization
+//!
+//! Addresses are checked against the RFC 5321 mailbox syntax, in a
+//! pragmatic subset: the local part is a dot-atom or a quoted string, the
+//! domain a host name of at least two labels or an address literal, and a
+//! comment is only allowed at either end of the local part or the domain,
+//! never nested. Normalizing keeps the local part as given, since only the
+//! receiving host may interpret it, lowercases the domain and converts
+//! internationalized domains to punycode.
+//!
+//! With the `mx-lookup` feature, the domain of an address can further be
+//! checked for accepting mail; see [`verify_mx`].
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use thiserror::Error;
+
+/// Longest address a path in SMTP can hold (RFC 5321, section 4.5.3.1.3)
+pub const MAX_ADDRESS_LENGTH: usize = 254;
+
+/// Longest local part (RFC 5321, section 4.5.3.1.1)
+pub const MAX_LOCAL_PART_LENGTH: usize = 64;
+
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// Characters of an atom besides letters and digits (RFC 5322, section 3.2.3)
+const ATEXT_SPECIALS: &str = "!#$%&'*+-/=?^_`{|}~";
+
+/// Why an email address is invalid
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("is blank")]
+    Blank,
+
+    #[error("has no @ separating the local part from the domain")]
+    MissingAt,
+
+    #[error("has an invalid local part")]
+    InvalidLocalPart,
+
+    #[error("has an invalid domain")]
+    InvalidDomain,
+
+    #[error("has a nested comment")]
+    NestedComment,
+
+    #[error("is too long")]
+    TooLong,
+}
+
+/// Normalized form of an email address, or why it is invalid
+///
+/// Surrounding whitespace and comments are dropped, the domain is lowercased
+/// and converted to punycode. Two addresses naming the same mailbox at the
+/// same host normalize to the same string, up to the case of the local part.
+pub fn normalize_email(input: &str) -> Result<String, AddressError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(AddressError::Blank);
+    }
+
+    // The domain holds no @, a quoted local part may
+    let (local, domain) = input.rsplit_once('@').ok_or(AddressError::MissingAt)?;
+    let local = strip_comments(local, AddressError::InvalidLocalPart)?;
+    let domain = strip_comments(domain, AddressError::InvalidDomain)?;
+
+    validate_local_part(local)?;
+    let domain = normalize_domain(domain)?;
+
+    let address = format!("{}@{}", local, domain);
+    if address.len() > MAX_ADDRESS_LENGTH {
+        return Err(AddressError::TooLong);
+    }
+    Ok(address)
+}
+
+/// Whether the input is a valid email address
+pub fn is_valid_email(input: &str) -> bool {
+    normalize_email(input).is_ok()
+}
+
+/// Domain of a normalized address
+pub fn email_domain(address: &str) -> &str {
+    address.rsplit_once('@').map_or("", |(_, domain)| domain)
+}
+
+/// Part without a comment at its start or end, `unbalanced` if a
+/// parenthesis is left open
+fn strip_comments(part: &str, unbalanced: AddressError) -> Result<&str, AddressError> {
+    let mut part = part;
+    if let Some(rest) = part.strip_prefix('(') {
+        let (comment, rest) = rest.split_once(')').ok_or(unbalanced.clone())?;
+        if comment.contains('(') {
+            return Err(AddressError::NestedComment);
+        }
+        part = rest;
+    }
+    if let Some(rest) = part.strip_suffix(')') {
+        let (rest, comment) = rest.rsplit_once('(').ok_or(unbalanced)?;
+        if comment.contains(')') {
+            return Err(AddressError::NestedComment);
+        }
+        part = rest;
+    }
+    Ok(part)
+}
+
+fn validate_local_part(local: &str) -> Result<(), AddressError> {
+    if local.len() > MAX_LOCAL_PART_LENGTH {
+        return Err(AddressError::TooLong);
+    }
+
+    let valid = match local.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(quoted) => is_quoted_content(quoted),
+        None => is_dot_atom(local),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(AddressError::InvalidLocalPart)
+    }
+}
+
+fn is_dot_atom(value: &str) -> bool {
+    !value.is_empty()
+        && value.split('.').all(|atom| {
+            !atom.is_empty()
+                && atom.chars().all(|c| c.is_ascii_alphanumeric() || ATEXT_SPECIALS.contains(c))
+        })
+}
+
+/// Content of a quoted string: printable ASCII, with quotes and backslashes
+/// escaped by a backslash
+fn is_quoted_content(value: &str) -> bool {
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped == ' ' || escaped.is_ascii_graphic() => {}
+                _ => return false,
+            },
+            '"' => return false,
+            c if c == ' ' || c.is_ascii_graphic() => {}
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn normalize_domain(domain: &str) -> Result<String, AddressError> {
+    if let Some(literal) = domain.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        return normalize_address_literal(literal);
+    }
+    if domain.is_empty() || domain.ends_with('.') {
+        return Err(AddressError::InvalidDomain);
+    }
+
+    let ascii = idna::domain_to_ascii_cow(domain.as_bytes(), idna::AsciiDenyList::STD3)
+        .map_err(|_| AddressError::InvalidDomain)?;
+    if ascii.len() > MAX_DOMAIN_LENGTH {
+        return Err(AddressError::TooLong);
+    }
+
+    let labels: Vec<&str> = ascii.split('.').collect();
+    let valid_labels = labels.iter().all(|label| {
+        !label.is_empty()
+            && label.len() <= MAX_LABEL_LENGTH
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    });
+    // A bare top-level domain does not receive mail, and an all-numeric one
+    // is an IP address that lacks its brackets
+    let valid_tld = labels.len() >= 2
+        && labels.last().is_some_and(|tld| !tld.bytes().all(|b| b.is_ascii_digit()));
+    if !valid_labels || !valid_tld {
+        return Err(AddressError::InvalidDomain);
+    }
+    Ok(ascii.into_owned())
+}
+
+/// An IP address in brackets (RFC 5321, section 4.1.3)
+fn normalize_address_literal(literal: &str) -> Result<String, AddressError> {
+    let ipv6 = literal
+        .get(..5)
+        .filter(|tag| tag.eq_ignore_ascii_case("IPv6:"))
+        .map(|_| &literal[5..]);
+    match ipv6 {
+        Some(address) => address
+            .parse::<Ipv6Addr>()
+            .map(|address| format!("[IPv6:{}]", address))
+            .map_err(|_| AddressError::InvalidDomain),
+        None => literal
+            .parse::<Ipv4Addr>()
+            .map(|address| format!("[{}]", address))
+            .map_err(|_| AddressError::InvalidDomain),
+    }
+}
+
+#[cfg(feature = "mx-lookup")]
+pub use mx::{verify_mx, DnsResolver, MxResolver, MxVerification};
+
+#[cfg(feature = "mx-lookup")]
+mod mx {
+    use std::io;
+    use std::net::{IpAddr, SocketAddr};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use async_trait::async_trait;
+    use tokio::net::UdpSocket;
+
+    use super::email_domain;
+
+    const DNS_PORT: u16 = 53;
+    const QTYPE_MX: u16 = 15;
+    const QCLASS_IN: u16 = 1;
+    const RCODE_NXDOMAIN: u8 = 3;
+
+    /// Outcome of checking whether the domain of an address accepts mail
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MxVerification {
+        /// The domain has a mail exchanger, or an address standing in for one
+        Deliverable,
+        /// The domain does not exist or has no host to deliver to
+        Undeliverable,
+        /// The lookup failed or timed out
+        Unknown,
+    }
+
+    /// Looks up whether a domain accepts mail
+    #[async_trait]
+    pub trait MxResolver: Send + Sync {
+        /// Whether the domain has MX records, or an address record serving as
+        /// its implicit MX (RFC 5321, section 5.1)
+        async fn accepts_mail(&self, domain: &str) -> io::Result<bool>;
+    }
+
+    /// Resolver sending MX queries to a name server over UDP
+    pub struct DnsResolver {
+        nameserver: SocketAddr,
+    }
+
+    impl DnsResolver {
+        pub fn new(nameserver: SocketAddr) -> Self {
+            Self { nameserver }
+        }
+
+        /// Resolver using the first name server of `/etc/resolv.conf`
+        pub fn from_system() -> io::Result<Self> {
+            let conf = std::fs::read_to_string("/etc/resolv.conf")?;
+            conf.lines()
+                .filter_map(|line| line.trim().strip_prefix("nameserver"))
+                .find_map(|address| address.trim().parse::<IpAddr>().ok())
+                .map(|ip| Self::new(SocketAddr::new(ip, DNS_PORT)))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no name server configured"))
+        }
+    }
+
+    #[async_trait]
+    impl MxResolver for DnsResolver {
+        async fn accepts_mail(&self, domain: &str) -> io::Result<bool> {
+            let bind: SocketAddr = if self.nameserver.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0u16; 8], 0).into()
+            };
+            let socket = UdpSocket::bind(bind).await?;
+            socket.connect(self.nameserver).await?;
+
+            let id = query_id();
+            socket.send(&mx_query(id, domain)).await?;
+            let mut response = [0u8; 512];
+            let len = socket.recv(&mut response).await?;
+            let response = &response[..len];
+
+            if len < 12 || response[..2] != id.to_be_bytes() || response[2] & 0x80 == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response"));
+            }
+            match response[3] & 0x0f {
+                0 if u16::from_be_bytes([response[6], response[7]]) > 0 => Ok(true),
+                // Without MX records, an address of the domain receives its mail
+                0 => Ok(tokio::net::lookup_host((domain, 25)).await.is_ok_and(|mut addrs| addrs.next().is_some())),
+                RCODE_NXDOMAIN => Ok(false),
+                rcode => Err(io::Error::other(format!("DNS lookup failed with rcode {}", rcode))),
+            }
+        }
+    }
+
+    fn query_id() -> u16 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.subsec_nanos() as u16)
+    }
+
+    /// MX query for the domain, asking for recursion
+    fn mx_query(id: u16, domain: &str) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(domain.len() + 18);
+        packet.extend_from_slice(&id.to_be_bytes());
+        // Recursion desired, one question
+        packet.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        for label in domain.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&QTYPE_MX.to_be_bytes());
+        packet.extend_from_slice(&QCLASS_IN.to_be_bytes());
+        packet
+    }
+
+    /// Check that the domain of a normalized address accepts mail, giving up
+    /// after `timeout`
+    ///
+    /// Lookups are slow and may fail for reasons unrelated to the address, so
+    /// only [`MxVerification::Undeliverable`] should reject it.
+    pub async fn verify_mx(resolver: &dyn MxResolver, address: &str, timeout: Duration) -> MxVerification {
+        let domain = email_domain(address);
+        // Address literals name their host directly
+        if domain.starts_with('[') {
+            return MxVerification::Deliverable;
+        }
+        match tokio::time::timeout(timeout, resolver.accepts_mail(domain)).await {
+            Ok(Ok(true)) => MxVerification::Deliverable,
+            Ok(Ok(false)) => MxVerification::Undeliverable,
+            Ok(Err(e)) => {
+                tracing::debug!(domain, error = %e, "MX lookup failed");
+                MxVerification::Unknown
+            }
+            Err(_) => MxVerification::Unknown,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        struct StaticResolver(Option<bool>, Duration);
+
+        #[async_trait]
+        impl MxResolver for StaticResolver {
+            async fn accepts_mail(&self, _domain: &str) -> io::Result<bool> {
+                tokio::time::sleep(self.1).await;
+                self.0.ok_or_else(|| io::Error::other("SERVFAIL"))
+            }
+        }
+
+        #[test]
+        fn test_mx_query() {
+            let query = mx_query(0x1234, "example.com");
+            assert_eq!(&query[..4], &[0x12, 0x34, 0x01, 0x00]);
+            assert_eq!(&query[12..25], b"\x07example\x03com\x00");
+            assert_eq!(&query[25..], &[0, 15, 0, 1]);
+        }
+
+        #[tokio::test]
+        async fn test_verify_mx() {
+            let timeout = Duration::from_millis(50);
+            let found = StaticResolver(Some(true), Duration::ZERO);
+            let missing = StaticResolver(Some(false), Duration::ZERO);
+            let failing = StaticResolver(None, Duration::ZERO);
+            let slow = StaticResolver(Some(false), Duration::from_secs(5));
+
+            assert_eq!(verify_mx(&found, "a@example.com", timeout).await, MxVerification::Deliverable);
+            assert_eq!(verify_mx(&missing, "a@example.com", timeout).await, MxVerification::Undeliverable);
+            assert_eq!(verify_mx(&failing, "a@example.com", timeout).await, MxVerification::Unknown);
+            assert_eq!(verify_mx(&slow, "a@example.com", timeout).await, MxVerification::Unknown);
+            assert_eq!(verify_mx(&missing, "a@[192.0.2.1]", timeout).await, MxVerification::Deliverable);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const VALID: &[(&str, &str)] = &[
+        ("user@example.com", "user@example.com"),
+        ("User@Example.COM", "User@example.com"),
+        ("  first.last@example.com ", "first.last@example.com"),
+        ("user+tag@sub.example.co.uk", "user+tag@sub.example.co.uk"),
+        ("o'brien@example.ie", "o'brien@example.ie"),
+        ("x@a-b.example", "x@a-b.example"),
+        ("\"john doe\"@example.com", "\"john doe\"@example.com"),
+        ("\"a@b\"@example.com", "\"a@b\"@example.com"),
+        ("\"quote\\\"d\"@example.com", "\"quote\\\"d\"@example.com"),
+        ("john(comment)@example.com", "john@example.com"),
+        ("(comment)john@example.com", "john@example.com"),
+        ("john@(comment)example.com", "john@example.com"),
+        ("user@bücher.de", "user@xn--bcher-kva.de"),
+        ("user@BÜCHER.de", "user@xn--bcher-kva.de"),
+        ("user@例え.テスト", "user@xn--r8jz45g.xn--zckzah"),
+        ("user@xn--bcher-kva.de", "user@xn--bcher-kva.de"),
+        ("user@[192.0.2.1]", "user@[192.0.2.1]"),
+        ("user@[ipv6:2001:DB8::1]", "user@[IPv6:2001:db8::1]"),
+    ];
+
+    const INVALID: &[(&str, AddressError)] = &[
+        ("", AddressError::Blank),
+        ("   ", AddressError::Blank),
+        ("user.example.com", AddressError::MissingAt),
+        ("@example.com", AddressError::InvalidLocalPart),
+        (".user@example.com", AddressError::InvalidLocalPart),
+        ("user.@example.com", AddressError::InvalidLocalPart),
+        ("us..er@example.com", AddressError::InvalidLocalPart),
+        ("us er@example.com", AddressError::InvalidLocalPart),
+        ("a@b@example.com", AddressError::InvalidLocalPart),
+        ("\"unterminated@example.com", AddressError::InvalidLocalPart),
+        ("\"bad\"quote\"@example.com", AddressError::InvalidLocalPart),
+        ("jöhn@example.com", AddressError::InvalidLocalPart),
+        ("john((nested))@example.com", AddressError::NestedComment),
+        ("john@((nested))example.com", AddressError::NestedComment),
+        ("user@", AddressError::InvalidDomain),
+        ("user@localhost", AddressError::InvalidDomain),
+        ("user@example.com.", AddressError::InvalidDomain),
+        ("user@exa mple.com", AddressError::InvalidDomain),
+        ("user@-example.com", AddressError::InvalidDomain),
+        ("user@example-.com", AddressError::InvalidDomain),
+        ("user@exa_mple.com", AddressError::InvalidDomain),
+        ("user@example..com", AddressError::InvalidDomain),
+        ("user@192.0.2.1", AddressError::InvalidDomain),
+        ("user@[192.0.2.256]", AddressError::InvalidDomain),
+        ("user@[IPv6:nope]", AddressError::InvalidDomain),
+    ];
+
+    #[test]
+    fn test_valid_addresses() {
+        for (input, normalized) in VALID {
+            assert_eq!(normalize_email(input).as_deref(), Ok(*normalized), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        for (input, error) in INVALID {
+            assert_eq!(normalize_email(input).as_ref(), Err(error), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_lengths() {
+        let local = "a".repeat(MAX_LOCAL_PART_LENGTH);
+        assert!(is_valid_email(&format!("{}@example.com", local)));
+        assert_eq!(normalize_email(&format!("a{}@example.com", local)), Err(AddressError::TooLong));
+
+        let label = "a".repeat(MAX_LABEL_LENGTH);
+        assert!(is_valid_email(&format!("user@{}.com", label)));
+        assert_eq!(normalize_email(&format!("user@a{}.com", label)), Err(AddressError::InvalidDomain));
+
+        let domain = format!("{0}.{0}.{0}.{0}.com", label);
+        assert_eq!(normalize_email(&format!("user@{}", domain)), Err(AddressError::TooLong));
+    }
+
+    #[test]
+    fn test_email_domain() {
+        assert_eq!(email_domain("\"a@b\"@example.com"), "example.com");
+        assert_eq!(email_domain("invalid"), "");
+    }
+
+    fn local_part() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]{1,20}(\\.[a-zA-Z0-9_+-]{1,10}){0,2}"
+    }
+
+    fn label() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[a-z0-9]{1,10}(-[a-z0-9]{1,5})?",
+            "[a-zäöüéñç]{1,10}",
+            "[\u{4e00}-\u{4e20}]{1,5}",
+            "[а-я]{2,10}",
+        ]
+    }
+
+    fn domain() -> impl Strategy<Value = String> {
+        (prop::collection::vec(label(), 1..3), "[a-z]{2,6}")
+            .prop_map(|(labels, tld)| format!("{}.{}", labels.join("."), tld))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_generated_addresses_are_valid(local in local_part(), domain in domain()) {
+            let normalized = normalize_email(&format!("{}@{}", local, domain)).unwrap();
+            prop_assert!(normalized.is_ascii());
+            prop_assert_eq!(normalized.rsplit_once('@').unwrap().0, local.as_str());
+        }
+
+        #[test]
+        fn prop_normalizing_is_idempotent(local in local_part(), domain in domain()) {
+            let normalized = normalize_email(&format!("{}@{}", local, domain)).unwrap();
+            prop_assert_eq!(normalize_email(&normalized), Ok(normalized.clone()));
+        }
+
+        #[test]
+        fn prop_domain_case_does_not_matter(local in local_part(), domain in domain()) {
+            let lower = normalize_email(&format!("{}@{}", local, domain)).unwrap();
+            let upper = normalize_email(&format!("{}@{}", local, domain.to_uppercase())).unwrap();
+            prop_assert_eq!(lower, upper);
+        }
+
+        #[test]
+        fn prop_whitespace_in_local_part_is_invalid(a in "[a-z]{1,10}", b in "[a-z]{1,10}", domain in domain()) {
+            let address = format!("{} {}@{}", a, b, domain);
+            prop_assert!(!is_valid_email(&address));
+        }
+
+        #[test]
+        fn prop_never_panics(input in "\\PC{0,80}") {
+            let _ = normalize_email(&input);
+        }
+    }
+}
@@ -3,8 +3,11 @@
 //! Maps to Ruby's error handling patterns and contract validation errors.
 
 use std::collections::HashMap;
+use std::fmt;
 use thiserror::Error;
 
+use crate::i18n::I18n;
+
 /// Core error type for all OpenProject operations
 #[derive(Error, Debug)]
 pub enum OpError {
@@ -46,6 +49,82 @@ pub enum OpError {
     Conflict { message: String },
 }
 
+/// Property errors not tied to an attribute are reported on `base`
+pub const BASE_PROPERTY: &str = "base";
+
+/// Code of errors given without one (mirrors ActiveModel's `:invalid`)
+pub const INVALID_CODE: &str = "invalid";
+
+/// A single failed validation (mirrors ActiveModel::Error)
+///
+/// `code` is the machine-readable reason such as `blank` or `inclusion`,
+/// `message` the text following the attribute name in the full message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertyError {
+    pub property: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl PropertyError {
+    pub fn new(
+        property: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            property: property.into(),
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Error on `base`, whose message is already a full sentence
+    pub fn base(message: impl Into<String>) -> Self {
+        Self::new(BASE_PROPERTY, INVALID_CODE, message)
+    }
+
+    pub fn is_base(&self) -> bool {
+        self.property == BASE_PROPERTY
+    }
+
+    /// Message prefixed with the humanized attribute, e.g. "Start date can't be blank"
+    pub fn full_message(&self) -> String {
+        if self.is_base() {
+            return self.message.clone();
+        }
+        format!("{} {}", humanize(&self.property), self.message)
+    }
+}
+
+impl fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.full_message())
+    }
+}
+
+impl From<&str> for PropertyError {
+    fn from(message: &str) -> Self {
+        Self::base(message)
+    }
+}
+
+impl From<String> for PropertyError {
+    fn from(message: String) -> Self {
+        Self::base(message)
+    }
+}
+
+/// `start_date` -> `Start date`
+fn humanize(attribute: &str) -> String {
+    let words = attribute.replace('_', " ");
+    let mut chars = words.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => words,
+    }
+}
+
 /// Validation errors collection (mirrors Rails ActiveModel::Errors)
 #[derive(Error, Debug, Default, Clone)]
 #[error("Validation errors: {errors:?}")]
@@ -54,6 +133,8 @@ pub struct ValidationErrors {
     pub errors: HashMap<String, Vec<String>>,
     /// Base errors not tied to a specific field
     pub base_errors: Vec<String>,
+    /// Codes of the errors added with one, by field and message
+    codes: HashMap<(String, String), String>,
 }
 
 impl ValidationErrors {
@@ -72,6 +153,50 @@ impl ValidationErrors {
         self.base_errors.push(message.into());
     }
 
+    /// Add an error along with its machine-readable code
+    pub fn add_with_code(
+        &mut self,
+        field: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        self.add_property_error(PropertyError::new(field, code, message));
+    }
+
+    pub fn add_property_error(&mut self, error: PropertyError) {
+        let PropertyError { property, code, message } = error;
+        self.codes.insert((property.clone(), message.clone()), code);
+        if property == BASE_PROPERTY {
+            self.add_base(message);
+        } else {
+            self.add(property, message);
+        }
+    }
+
+    /// Code of an error, `invalid` when it was added without one
+    pub fn code(&self, field: &str, message: &str) -> &str {
+        self.codes
+            .get(&(field.to_string(), message.to_string()))
+            .map(String::as_str)
+            .unwrap_or(INVALID_CODE)
+    }
+
+    /// All errors with their property and code; base errors first, then
+    /// fields in alphabetical order
+    pub fn property_errors(&self) -> Vec<PropertyError> {
+        let mut fields: Vec<&String> = self.errors.keys().collect();
+        fields.sort();
+
+        let base = self.base_errors.iter().map(|msg| (BASE_PROPERTY, msg));
+        let fields = fields
+            .into_iter()
+            .flat_map(|field| self.errors[field].iter().map(move |msg| (field.as_str(), msg)));
+
+        base.chain(fields)
+            .map(|(field, msg)| PropertyError::new(field, self.code(field, msg), msg.clone()))
+            .collect()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.errors.is_empty() && self.base_errors.is_empty()
     }
@@ -91,6 +216,7 @@ impl ValidationErrors {
             self.errors.entry(field).or_default().extend(messages);
         }
         self.base_errors.extend(other.base_errors);
+        self.codes.extend(other.codes);
     }
 
     pub fn full_messages(&self) -> Vec<String> {
@@ -102,6 +228,43 @@ impl ValidationErrors {
         }
         messages
     }
+
+    /// Full messages translated into the given locale
+    pub fn localized_messages(&self, i18n: &I18n, locale: &str) -> Vec<String> {
+        let mut messages: Vec<String> = self
+            .base_errors
+            .iter()
+            .map(|msg| i18n.message(locale, msg))
+            .collect();
+
+        for (field, field_messages) in &self.errors {
+            for msg in field_messages {
+                let message = i18n.message(locale, msg);
+                if field == "base" {
+                    messages.push(message);
+                    continue;
+                }
+
+                let attribute = i18n.attribute(locale, field);
+                messages.push(i18n.t(
+                    locale,
+                    "errors.format",
+                    &[("attribute", &attribute), ("message", &message)],
+                ));
+            }
+        }
+        messages
+    }
+}
+
+impl From<Vec<PropertyError>> for ValidationErrors {
+    fn from(errors: Vec<PropertyError>) -> Self {
+        let mut validation_errors = ValidationErrors::new();
+        for error in errors {
+            validation_errors.add_property_error(error);
+        }
+        validation_errors
+    }
 }
 
 /// Contract validation error (mirrors OpenProject's Contract errors)
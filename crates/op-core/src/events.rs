@@ -0,0 +1,430 @@
+//! Domain Events
+//!
+//! Services publish what happened, e.g. a work package created, once the
+//! unit of work has committed, so subscribers never see changes that are
+//! rolled back. The subscribers registered at startup react to them:
+//! notification fan-out, webhooks, summary maintenance, the audit log.
+//!
+//! Ordering: events reach the subscribers in the order they are published,
+//! each event all of them in the order they were registered before the next
+//! one. Queued subscribers get their events enqueued in that order and
+//! handled later by the job workers.
+//!
+//! Isolation: a subscriber failing or panicking is logged and reported by
+//! [`EventBus::publish`]; the other subscribers still get the event, and the
+//! change that caused it stands.
+
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::traits::Id;
+
+/// Something that happened in the domain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    WorkPackageCreated {
+        work_package_id: Id,
+        project_id: Id,
+        author_id: Id,
+    },
+    WorkPackageUpdated {
+        work_package_id: Id,
+        project_id: Id,
+        actor_id: Id,
+    },
+    /// Moved to the trash, together with the users watching it until then
+    WorkPackageDeleted {
+        work_package_id: Id,
+        project_id: Id,
+        actor_id: Id,
+        watcher_ids: Vec<Id>,
+    },
+    CommentAdded {
+        work_package_id: Id,
+        journal_id: Id,
+        author_id: Id,
+    },
+    MembershipChanged {
+        project_id: Id,
+        user_id: Id,
+        actor_id: Id,
+    },
+    ProjectArchived {
+        project_id: Id,
+        actor_id: Id,
+    },
+}
+
+impl DomainEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::WorkPackageCreated { .. } => "work_package_created",
+            Self::WorkPackageUpdated { .. } => "work_package_updated",
+            Self::WorkPackageDeleted { .. } => "work_package_deleted",
+            Self::CommentAdded { .. } => "comment_added",
+            Self::MembershipChanged { .. } => "membership_changed",
+            Self::ProjectArchived { .. } => "project_archived",
+        }
+    }
+}
+
+/// Event errors
+#[derive(Debug, Error)]
+pub enum EventError {
+    #[error("Subscriber failed: {0}")]
+    Failed(String),
+    #[error("Subscriber panicked")]
+    Panicked,
+    #[error("Could not enqueue the event: {0}")]
+    Queue(String),
+    #[error("No subscriber named {0}")]
+    UnknownSubscriber(String),
+}
+
+pub type EventResult<T> = Result<T, EventError>;
+
+/// How a subscriber gets its events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Handled while publishing, before the publisher carries on
+    Inline,
+    /// Enqueued as a job, handled by the job workers
+    Queued,
+}
+
+/// Reaction to domain events
+#[async_trait]
+pub trait EventSubscriber: Send + Sync {
+    /// Unique name, identifying the subscriber in queued jobs
+    fn name(&self) -> &str;
+
+    fn delivery(&self) -> Delivery {
+        Delivery::Inline
+    }
+
+    /// Whether the subscriber reacts to the event at all
+    fn handles(&self, _event: &DomainEvent) -> bool {
+        true
+    }
+
+    async fn handle(&self, event: &DomainEvent) -> EventResult<()>;
+}
+
+/// Queue the events of queued subscribers are enqueued to, e.g. the job
+/// queue of the workers
+#[async_trait]
+pub trait EventQueue: Send + Sync {
+    async fn enqueue(&self, subscriber: &str, event: &DomainEvent) -> EventResult<()>;
+}
+
+/// Subscriber that did not get an event
+#[derive(Debug)]
+pub struct DeliveryFailure {
+    pub subscriber: String,
+    pub event: &'static str,
+    pub error: EventError,
+}
+
+/// Publishes domain events to the subscribers
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Vec<Arc<dyn EventSubscriber>>,
+    queue: Option<Arc<dyn EventQueue>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bus recording the events published, for tests to assert on
+    pub fn recording() -> (Self, Arc<EventRecorder>) {
+        let recorder = Arc::new(EventRecorder::new());
+        (Self::new().with_subscriber(recorder.clone()), recorder)
+    }
+
+    /// Add a subscriber, in place of the one registered under its name if
+    /// any
+    pub fn with_subscriber(mut self, subscriber: Arc<dyn EventSubscriber>) -> Self {
+        match self.subscribers.iter().position(|registered| registered.name() == subscriber.name()) {
+            Some(index) => self.subscribers[index] = subscriber,
+            None => self.subscribers.push(subscriber),
+        }
+        self
+    }
+
+    /// Enqueue the events of queued subscribers to the queue; without one
+    /// they are handled inline
+    pub fn with_queue(mut self, queue: Arc<dyn EventQueue>) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Names of the subscribers, in the order they get events
+    pub fn subscriber_names(&self) -> Vec<&str> {
+        self.subscribers.iter().map(|subscriber| subscriber.name()).collect()
+    }
+
+    /// Deliver a committed event to its subscribers; failures are logged
+    /// and returned, never passed on to the other subscribers
+    pub async fn publish(&self, event: DomainEvent) -> Vec<DeliveryFailure> {
+        let mut failures = Vec::new();
+        for subscriber in self.subscribers.iter().filter(|subscriber| subscriber.handles(&event)) {
+            let result = match (subscriber.delivery(), &self.queue) {
+                (Delivery::Queued, Some(queue)) => queue.enqueue(subscriber.name(), &event).await,
+                _ => handle(subscriber.as_ref(), &event).await,
+            };
+            if let Err(error) = result {
+                tracing::error!(
+                    subscriber = subscriber.name(),
+                    event = event.name(),
+                    "Failed to deliver domain event: {}",
+                    error
+                );
+                failures.push(DeliveryFailure {
+                    subscriber: subscriber.name().to_string(),
+                    event: event.name(),
+                    error,
+                });
+            }
+        }
+        failures
+    }
+
+    /// Publish the events one after the other
+    pub async fn publish_all(&self, events: impl IntoIterator<Item = DomainEvent>) -> Vec<DeliveryFailure> {
+        let mut failures = Vec::new();
+        for event in events {
+            failures.extend(self.publish(event).await);
+        }
+        failures
+    }
+
+    /// Handle an event dequeued for the named subscriber
+    pub async fn deliver(&self, subscriber: &str, event: &DomainEvent) -> EventResult<()> {
+        let subscriber = self
+            .subscribers
+            .iter()
+            .find(|registered| registered.name() == subscriber)
+            .ok_or_else(|| EventError::UnknownSubscriber(subscriber.to_string()))?;
+        handle(subscriber.as_ref(), event).await
+    }
+}
+
+/// Run the subscriber, turning a panic into an error
+async fn handle(subscriber: &dyn EventSubscriber, event: &DomainEvent) -> EventResult<()> {
+    std::panic::AssertUnwindSafe(subscriber.handle(event))
+        .catch_unwind()
+        .await
+        .unwrap_or(Err(EventError::Panicked))
+}
+
+/// Subscriber recording the events it gets, for tests
+#[derive(Debug, Default)]
+pub struct EventRecorder {
+    events: RwLock<Vec<DomainEvent>>,
+}
+
+impl EventRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All recorded events, oldest first
+    pub fn events(&self) -> Vec<DomainEvent> {
+        self.events.read().unwrap().clone()
+    }
+
+    /// Recorded events of the given name
+    pub fn named(&self, name: &str) -> Vec<DomainEvent> {
+        self.events().into_iter().filter(|event| event.name() == name).collect()
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for EventRecorder {
+    fn name(&self) -> &str {
+        "recorder"
+    }
+
+    async fn handle(&self, event: &DomainEvent) -> EventResult<()> {
+        self.events.write().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn created(work_package_id: Id) -> DomainEvent {
+        DomainEvent::WorkPackageCreated {
+            work_package_id,
+            project_id: 1,
+            author_id: 2,
+        }
+    }
+
+    /// Subscriber appending its name and the events it gets to a shared log
+    struct Logging {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+        outcome: fn() -> EventResult<()>,
+    }
+
+    impl Logging {
+        fn new(name: &'static str, log: &Arc<Mutex<Vec<String>>>) -> Self {
+            Self {
+                name,
+                log: log.clone(),
+                outcome: || Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl EventSubscriber for Logging {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn handles(&self, event: &DomainEvent) -> bool {
+            !matches!(event, DomainEvent::ProjectArchived { .. })
+        }
+
+        async fn handle(&self, event: &DomainEvent) -> EventResult<()> {
+            let DomainEvent::WorkPackageCreated { work_package_id, .. } = event else {
+                return Ok(());
+            };
+            self.log.lock().unwrap().push(format!("{} {}", self.name, work_package_id));
+            (self.outcome)()
+        }
+    }
+
+    struct Panicking;
+
+    #[async_trait]
+    impl EventSubscriber for Panicking {
+        fn name(&self) -> &str {
+            "panicking"
+        }
+
+        async fn handle(&self, _event: &DomainEvent) -> EventResult<()> {
+            panic!("subscriber bug")
+        }
+    }
+
+    #[derive(Default)]
+    struct Queue {
+        enqueued: Mutex<Vec<(String, DomainEvent)>>,
+    }
+
+    #[async_trait]
+    impl EventQueue for Queue {
+        async fn enqueue(&self, subscriber: &str, event: &DomainEvent) -> EventResult<()> {
+            self.enqueued.lock().unwrap().push((subscriber.to_string(), event.clone()));
+            Ok(())
+        }
+    }
+
+    struct Queued(Logging);
+
+    #[async_trait]
+    impl EventSubscriber for Queued {
+        fn name(&self) -> &str {
+            self.0.name()
+        }
+
+        fn delivery(&self) -> Delivery {
+            Delivery::Queued
+        }
+
+        async fn handle(&self, event: &DomainEvent) -> EventResult<()> {
+            self.0.handle(event).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_reach_subscribers_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let bus = EventBus::new()
+            .with_subscriber(Arc::new(Logging::new("first", &log)))
+            .with_subscriber(Arc::new(Logging::new("second", &log)));
+
+        let failures = bus.publish_all([created(1), created(2)]).await;
+        assert!(failures.is_empty());
+        assert_eq!(*log.lock().unwrap(), vec!["first 1", "second 1", "first 2", "second 2"]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_subscribers_do_not_affect_others() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let failing = Logging {
+            outcome: || Err(EventError::Failed("webhook down".into())),
+            ..Logging::new("failing", &log)
+        };
+        let bus = EventBus::new()
+            .with_subscriber(Arc::new(failing))
+            .with_subscriber(Arc::new(Panicking))
+            .with_subscriber(Arc::new(Logging::new("last", &log)));
+
+        let failures = bus.publish(created(1)).await;
+        assert_eq!(*log.lock().unwrap(), vec!["failing 1", "last 1"]);
+        let failed: Vec<&str> = failures.iter().map(|failure| failure.subscriber.as_str()).collect();
+        assert_eq!(failed, vec!["failing", "panicking"]);
+        assert!(matches!(failures[1].error, EventError::Panicked));
+        assert_eq!(failures[0].event, "work_package_created");
+    }
+
+    #[tokio::test]
+    async fn test_queued_subscribers_get_events_through_the_queue() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let queue = Arc::new(Queue::default());
+        let bus = EventBus::new()
+            .with_subscriber(Arc::new(Queued(Logging::new("queued", &log))))
+            .with_subscriber(Arc::new(Logging::new("inline", &log)));
+
+        // Without a queue, queued subscribers are handled inline
+        bus.publish(created(1)).await;
+        assert_eq!(*log.lock().unwrap(), vec!["queued 1", "inline 1"]);
+
+        let bus = bus.with_queue(queue.clone());
+        bus.publish(created(2)).await;
+        assert_eq!(log.lock().unwrap().last().unwrap(), "inline 2");
+        let enqueued = queue.enqueued.lock().unwrap().clone();
+        assert_eq!(enqueued, vec![("queued".to_string(), created(2))]);
+
+        bus.deliver("queued", &enqueued[0].1).await.unwrap();
+        assert_eq!(log.lock().unwrap().last().unwrap(), "queued 2");
+        assert!(matches!(bus.deliver("unknown", &created(2)).await, Err(EventError::UnknownSubscriber(_))));
+    }
+
+    #[tokio::test]
+    async fn test_recording_bus_and_subscriber_replacement() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (bus, recorder) = EventBus::recording();
+        let bus = bus
+            .with_subscriber(Arc::new(Logging::new("logging", &log)))
+            .with_subscriber(Arc::new(Logging::new("logging", &log)));
+        assert_eq!(bus.subscriber_names(), vec!["recorder", "logging"]);
+
+        let archived = DomainEvent::ProjectArchived { project_id: 1, actor_id: 2 };
+        bus.publish_all([created(1), archived.clone()]).await;
+        assert_eq!(recorder.events(), vec![created(1), archived]);
+        assert_eq!(recorder.named("project_archived").len(), 1);
+        assert_eq!(log.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_events_serialize_with_their_name() {
+        let json = serde_json::to_value(created(5)).unwrap();
+        assert_eq!(json["type"], created(5).name());
+        assert_eq!(serde_json::from_value::<DomainEvent>(json).unwrap(), created(5));
+    }
+}
@@ -0,0 +1,509 @@
+//! Internationalization
+//!
+//! Key → template catalogs per locale, embedded from `locales/*.json`.
+//! Nested JSON objects are flattened into dotted keys (`email.body.intro`),
+//! except for the top-level `messages` object which maps the English
+//! validation messages used by contracts to their translation.
+//!
+//! Templates use `{name}` placeholders. Interpolation is a single pass, so
+//! values are inserted verbatim and never re-expanded. Keys missing from a
+//! locale fall back to English with a warning.
+//!
+//! The locale of the API request being processed lives in a task-local,
+//! like its request ID, so error bodies and representers render in it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, LazyLock};
+
+use serde_json::Value;
+
+use crate::config::InstanceConfig;
+
+/// Locale every other locale falls back to
+pub const FALLBACK_LOCALE: &str = "en";
+
+/// Catalogs bundled with the binary
+const EMBEDDED_CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+    ("fr", include_str!("../locales/fr.json")),
+];
+
+static EMBEDDED: LazyLock<Arc<I18n>> = LazyLock::new(|| Arc::new(I18n::embedded()));
+
+tokio::task_local! {
+    static LOCALE: RefCell<Option<String>>;
+}
+
+/// Run a future with the given locale as the current one; `None` leaves it
+/// to [`set_current_locale`] or the instance default
+pub async fn scope_locale<F: Future>(locale: Option<String>, future: F) -> F::Output {
+    LOCALE.scope(RefCell::new(locale), future).await
+}
+
+/// Locale of the request being processed by the current task, if resolved
+pub fn current_locale() -> Option<String> {
+    LOCALE.try_with(|locale| locale.borrow().clone()).ok().flatten()
+}
+
+/// Set the locale of the request being processed, e.g. once its user's
+/// language is known; does nothing outside a [`scope_locale`]
+pub fn set_current_locale(locale: String) {
+    let _ = LOCALE.try_with(|current| *current.borrow_mut() = Some(locale));
+}
+
+/// Language tags of an `Accept-Language` header, most preferred first;
+/// tags with a weight of 0 and the `*` wildcard are left out
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            let weight = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && weight > 0.0).then(|| (tag.to_string(), weight))
+        })
+        .collect();
+    // Stable, so equally weighted tags keep their order
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Placeholder values for a template
+pub type Args<'a> = &'a [(&'a str, &'a dyn Display)];
+
+/// Plural category of a count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluralCategory {
+    One,
+    Other,
+}
+
+impl PluralCategory {
+    /// Plural rule for a locale (CLDR cardinal rules for the bundled locales)
+    pub fn for_count(locale: &str, count: u64) -> Self {
+        let one = match language(locale) {
+            // French treats 0 and 1 as singular
+            "fr" => count <= 1,
+            _ => count == 1,
+        };
+        if one {
+            Self::One
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::One => "one",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Translations for one locale
+#[derive(Debug, Clone, Default)]
+struct Catalog {
+    keys: HashMap<String, String>,
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    fn from_json(source: &str) -> Result<Self, serde_json::Error> {
+        let value: Value = serde_json::from_str(source)?;
+        let mut catalog = Self::default();
+
+        if let Value::Object(map) = value {
+            for (key, value) in map {
+                if key == "messages" {
+                    if let Value::Object(messages) = value {
+                        for (english, translated) in messages {
+                            if let Value::String(translated) = translated {
+                                catalog.messages.insert(english, translated);
+                            }
+                        }
+                    }
+                } else {
+                    flatten(&key, value, &mut catalog.keys);
+                }
+            }
+        }
+
+        Ok(catalog)
+    }
+}
+
+fn flatten(prefix: &str, value: Value, out: &mut HashMap<String, String>) {
+    match value {
+        Value::String(s) => {
+            out.insert(prefix.to_string(), s);
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(&format!("{}.{}", prefix, key), value, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Language part of a locale tag ("de-AT" → "de")
+pub fn language(locale: &str) -> &str {
+    locale.split(['-', '_']).next().unwrap_or(locale)
+}
+
+/// Translation catalogs and locale selection
+#[derive(Debug, Clone)]
+pub struct I18n {
+    catalogs: HashMap<String, Catalog>,
+    default_locale: String,
+    available_locales: Vec<String>,
+}
+
+impl Default for I18n {
+    fn default() -> Self {
+        Self::embedded()
+    }
+}
+
+impl I18n {
+    /// Load the bundled catalogs
+    pub fn embedded() -> Self {
+        let mut catalogs = HashMap::new();
+        for (locale, source) in EMBEDDED_CATALOGS {
+            let catalog = Catalog::from_json(source)
+                .unwrap_or_else(|e| panic!("invalid embedded catalog {}: {}", locale, e));
+            catalogs.insert(locale.to_string(), catalog);
+        }
+
+        Self {
+            available_locales: EMBEDDED_CATALOGS.iter().map(|(l, _)| l.to_string()).collect(),
+            catalogs,
+            default_locale: FALLBACK_LOCALE.to_string(),
+        }
+    }
+
+    /// Shared instance of the bundled catalogs
+    pub fn shared() -> Arc<Self> {
+        EMBEDDED.clone()
+    }
+
+    /// Bundled catalogs restricted to the instance's locale settings
+    pub fn from_config(config: &InstanceConfig) -> Self {
+        Self::embedded()
+            .with_available_locales(config.available_locales.clone())
+            .with_default_locale(&config.default_locale)
+    }
+
+    /// Set the instance default locale
+    pub fn with_default_locale(mut self, locale: impl Into<String>) -> Self {
+        self.default_locale = locale.into();
+        self
+    }
+
+    /// Restrict the locales users may select
+    pub fn with_available_locales(mut self, locales: Vec<String>) -> Self {
+        self.available_locales = locales;
+        self
+    }
+
+    /// Add or replace a catalog from JSON source
+    pub fn with_catalog(mut self, locale: impl Into<String>, source: &str) -> Result<Self, serde_json::Error> {
+        let locale = locale.into();
+        self.catalogs.insert(locale.clone(), Catalog::from_json(source)?);
+        if !self.available_locales.contains(&locale) {
+            self.available_locales.push(locale);
+        }
+        Ok(self)
+    }
+
+    pub fn default_locale(&self) -> &str {
+        &self.default_locale
+    }
+
+    /// Pick the locale for a user's language preference, falling back to the
+    /// instance default when unset or not available
+    pub fn resolve_locale(&self, preferred: Option<&str>) -> String {
+        preferred
+            .and_then(|preferred| self.available_locale(preferred))
+            .unwrap_or_else(|| self.default_locale.clone())
+    }
+
+    /// Available locale matching a language tag, by the full tag or its
+    /// language ("de-AT" → "de")
+    pub fn available_locale(&self, tag: &str) -> Option<String> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return None;
+        }
+        [tag, language(tag)]
+            .into_iter()
+            .find(|candidate| {
+                self.catalogs.contains_key(*candidate) && self.available_locales.iter().any(|l| l == candidate)
+            })
+            .map(str::to_string)
+    }
+
+    /// First available locale of an `Accept-Language` header, if any
+    pub fn negotiate(&self, accept_language: &str) -> Option<String> {
+        parse_accept_language(accept_language)
+            .iter()
+            .find_map(|tag| self.available_locale(tag))
+    }
+
+    /// Locale of the request being processed, or the instance default
+    pub fn current_locale(&self) -> String {
+        current_locale().unwrap_or_else(|| self.default_locale.clone())
+    }
+
+    /// Look up a template without falling back or logging
+    pub fn lookup(&self, locale: &str, key: &str) -> Option<&str> {
+        self.catalogs
+            .get(locale)
+            .or_else(|| self.catalogs.get(language(locale)))
+            .and_then(|c| c.keys.get(key))
+            .map(String::as_str)
+    }
+
+    fn template(&self, locale: &str, key: &str) -> Option<&str> {
+        if let Some(template) = self.lookup(locale, key) {
+            return Some(template);
+        }
+
+        let fallback = self.lookup(FALLBACK_LOCALE, key);
+        if fallback.is_some() {
+            tracing::warn!(locale, key, "Missing translation, falling back to English");
+        } else {
+            tracing::warn!(locale, key, "Missing translation key");
+        }
+        fallback
+    }
+
+    /// Translate a key, interpolating `{name}` placeholders
+    pub fn t(&self, locale: &str, key: &str, args: Args<'_>) -> String {
+        match self.template(locale, key) {
+            Some(template) => interpolate(template, args),
+            None => key.to_string(),
+        }
+    }
+
+    /// Translate a pluralized key (`key.one` / `key.other`), with `{count}`
+    /// available as a placeholder
+    pub fn t_plural(&self, locale: &str, key: &str, count: u64, args: Args<'_>) -> String {
+        let category = PluralCategory::for_count(locale, count);
+        let key = format!("{}.{}", key, category.as_str());
+
+        let mut all: Vec<(&str, &dyn Display)> = vec![("count", &count)];
+        all.extend_from_slice(args);
+
+        self.t(locale, &key, &all)
+    }
+
+    /// Translate an English validation message
+    pub fn message(&self, locale: &str, message: &str) -> String {
+        let translated = self
+            .catalogs
+            .get(locale)
+            .or_else(|| self.catalogs.get(language(locale)))
+            .and_then(|c| c.messages.get(message));
+
+        match translated {
+            Some(translated) => translated.clone(),
+            None => {
+                if language(locale) != FALLBACK_LOCALE {
+                    tracing::warn!(locale, message, "Missing message translation, falling back to English");
+                }
+                message.to_string()
+            }
+        }
+    }
+
+    /// Human-readable attribute name, falling back to the field name
+    pub fn attribute(&self, locale: &str, field: &str) -> String {
+        let key = format!("attributes.{}", field);
+        self.lookup(locale, &key)
+            .or_else(|| self.lookup(FALLBACK_LOCALE, &key))
+            .map(str::to_string)
+            .unwrap_or_else(|| field.to_string())
+    }
+}
+
+/// Replace `{name}` placeholders in a single pass; unknown placeholders are
+/// left as-is and inserted values are never re-scanned
+pub fn interpolate(template: &str, args: Args<'_>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        match after.find('}') {
+            Some(end) => {
+                let name = &after[..end];
+                match args.iter().find(|(n, _)| *n == name) {
+                    Some((_, value)) => out.push_str(&value.to_string()),
+                    None => {
+                        out.push('{');
+                        out.push_str(name);
+                        out.push('}');
+                    }
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Escape a value for inclusion in HTML
+pub fn escape_html(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_with_fallback() {
+        let i18n = I18n::embedded();
+
+        assert_eq!(
+            i18n.t("de", "email.subject.work_package.updated", &[("app", &"OpenProject"), ("id", &42)]),
+            "[OpenProject] Arbeitspaket #42 aktualisiert"
+        );
+
+        let i18n = i18n
+            .with_catalog("pt", r#"{"email": {"body": {"intro": "Nova notificação em {app}."}}}"#)
+            .unwrap();
+        assert_eq!(i18n.t("pt", "email.body.intro", &[("app", &"OP")]), "Nova notificação em OP.");
+        assert_eq!(
+            i18n.t("pt", "email.body.footer", &[]),
+            "You received this email because you are subscribed to notifications."
+        );
+        assert_eq!(i18n.t("pt", "does.not.exist", &[]), "does.not.exist");
+    }
+
+    #[test]
+    fn test_interpolation_is_single_pass() {
+        let injected = "{app}";
+        assert_eq!(
+            interpolate("Project {name} in {app}", &[("name", &injected), ("app", &"OP")]),
+            "Project {app} in OP"
+        );
+        assert_eq!(interpolate("Unknown {missing} and {open", &[]), "Unknown {missing} and {open");
+    }
+
+    #[test]
+    fn test_pluralization() {
+        let i18n = I18n::embedded();
+        let args: Args<'_> = &[("app", &"OP"), ("period", &"daily")];
+
+        assert_eq!(i18n.t_plural("en", "email.digest.subject", 1, args), "[OP] Your daily digest (1 notification)");
+        assert_eq!(i18n.t_plural("en", "email.digest.subject", 3, args), "[OP] Your daily digest (3 notifications)");
+        assert_eq!(i18n.t_plural("en", "email.digest.subject", 0, args), "[OP] Your daily digest (0 notifications)");
+
+        assert_eq!(PluralCategory::for_count("fr", 0), PluralCategory::One);
+        assert_eq!(PluralCategory::for_count("de", 0), PluralCategory::Other);
+    }
+
+    #[test]
+    fn test_resolve_locale() {
+        let i18n = I18n::embedded().with_default_locale("de");
+
+        assert_eq!(i18n.resolve_locale(Some("fr")), "fr");
+        assert_eq!(i18n.resolve_locale(Some("fr-CA")), "fr");
+        assert_eq!(i18n.resolve_locale(Some("ja")), "de");
+        assert_eq!(i18n.resolve_locale(None), "de");
+
+        let restricted = I18n::embedded().with_available_locales(vec!["en".into(), "de".into()]);
+        assert_eq!(restricted.resolve_locale(Some("fr")), "en");
+    }
+
+    #[test]
+    fn test_negotiate_accept_language() {
+        assert_eq!(parse_accept_language("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.7, *;q=0.5"), vec!["fr-CH", "fr", "en", "de"]);
+        assert_eq!(parse_accept_language("en;q=0.5, de"), vec!["de", "en"]);
+        assert_eq!(parse_accept_language("de;q=0, ja"), vec!["ja"]);
+
+        let i18n = I18n::embedded().with_available_locales(vec!["en".into(), "de".into()]);
+        assert_eq!(i18n.negotiate("de-AT,en;q=0.8"), Some("de".to_string()));
+        assert_eq!(i18n.negotiate("fr, en;q=0.1"), Some("en".to_string()));
+        assert_eq!(i18n.negotiate("ja"), None);
+    }
+
+    #[tokio::test]
+    async fn test_current_locale_scope() {
+        assert_eq!(current_locale(), None);
+        set_current_locale("de".into());
+        assert_eq!(current_locale(), None);
+
+        let inner = scope_locale(None, async {
+            let unresolved = I18n::embedded().current_locale();
+            set_current_locale("fr".into());
+            (unresolved, current_locale())
+        })
+        .await;
+        assert_eq!(inner, ("en".to_string(), Some("fr".to_string())));
+        assert_eq!(scope_locale(Some("de".into()), async { current_locale() }).await, Some("de".to_string()));
+    }
+
+    #[test]
+    fn test_messages_and_attributes() {
+        let i18n = I18n::embedded();
+
+        assert_eq!(i18n.message("de", "can't be blank"), "muss ausgefüllt werden");
+        assert_eq!(i18n.message("fr", "Unknown message"), "Unknown message");
+        assert_eq!(i18n.attribute("fr", "firstname"), "Prénom");
+        assert_eq!(i18n.attribute("fr", "custom_field_7"), "custom_field_7");
+    }
+
+    #[test]
+    fn test_localized_validation_errors() {
+        let i18n = I18n::embedded();
+        let mut errors = crate::error::ValidationErrors::new();
+        errors.add("name", "can't be blank");
+        errors.add("base", "You are not authorized to create projects");
+
+        let mut messages = errors.localized_messages(&i18n, "de");
+        messages.sort();
+        assert_eq!(
+            messages,
+            vec![
+                "Name muss ausgefüllt werden".to_string(),
+                "Sie sind nicht berechtigt, Projekte anzulegen".to_string(),
+            ]
+        );
+
+        assert_eq!(errors.localized_messages(&i18n, "en").len(), 2);
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("<b>\"A&B\"</b>"), "&lt;b&gt;&quot;A&amp;B&quot;&lt;/b&gt;");
+    }
+}
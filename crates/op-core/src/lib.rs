@@ -9,6 +9,15 @@
 //! - Pagination types
 //! - Service result types (ServiceResult)
 //! - Configuration types
+//! - Domain metrics
+//! - Security audit log
+//! - Domain events published after commit
+//! - Request correlation IDs
+//! - Internationalization
+//! - ISO 8601 duration and date parsing
+//! - Email address validation and normalization
+//! - Clock abstraction for time-based state
+//! - API v3 representations shared by the server and the client
 
 pub mod error;
 pub mod result;
@@ -16,6 +25,15 @@ pub mod traits;
 pub mod types;
 pub mod pagination;
 pub mod config;
+pub mod metrics;
+pub mod audit;
+pub mod events;
+pub mod request_id;
+pub mod i18n;
+pub mod duration;
+pub mod email;
+pub mod clock;
+pub mod representations;
 
 pub use error::*;
 pub use result::*;
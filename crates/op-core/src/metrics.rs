@@ -0,0 +1,406 @@
+//! Domain Metrics
+//!
+//! Counters and histograms for domain-level events (work package writes,
+//! notifications, emails, background jobs) and repository query timings.
+//!
+//! A single `DomainMetrics` handle is created by the server and shared with
+//! services, job workers and repositories, which opt in via `with_metrics`.
+//! Label values are `&'static str` so the set of series stays bounded by
+//! construction: there are no per-project or per-user labels.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bounds (in seconds) of the query duration histogram buckets
+pub const QUERY_DURATION_BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Fixed-bucket histogram
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    /// Cumulative counts per bucket in `QUERY_DURATION_BUCKETS`
+    buckets: [u64; QUERY_DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    /// Record a single observation
+    pub fn observe(&mut self, seconds: f64) {
+        for (i, bound) in QUERY_DURATION_BUCKETS.iter().enumerate() {
+            if seconds <= *bound {
+                self.buckets[i] += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    /// Number of observations
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of all observations in seconds
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+}
+
+/// Collector for domain events and repository timings
+pub struct DomainMetrics {
+    /// Work package writes
+    pub work_packages_created: AtomicU64,
+    pub work_packages_updated: AtomicU64,
+    pub work_packages_deleted: AtomicU64,
+    /// Notifications created
+    pub notifications_created: AtomicU64,
+    /// Background jobs
+    pub jobs_processed: AtomicU64,
+    pub jobs_failed: AtomicU64,
+    pub jobs_retried: AtomicU64,
+    /// Work package query results served from the cache, or not
+    pub query_cache_hits: AtomicU64,
+    pub query_cache_misses: AtomicU64,
+    /// Requests exceeding their budget of repository calls
+    pub query_budget_exceeded: AtomicU64,
+    /// Emails by sender type
+    emails_sent: Mutex<BTreeMap<&'static str, u64>>,
+    emails_failed: Mutex<BTreeMap<&'static str, u64>>,
+    /// Query durations by (repository, method)
+    query_durations: Mutex<BTreeMap<(&'static str, &'static str), Histogram>>,
+}
+
+impl Default for DomainMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DomainMetrics {
+    pub fn new() -> Self {
+        Self {
+            work_packages_created: AtomicU64::new(0),
+            work_packages_updated: AtomicU64::new(0),
+            work_packages_deleted: AtomicU64::new(0),
+            notifications_created: AtomicU64::new(0),
+            jobs_processed: AtomicU64::new(0),
+            jobs_failed: AtomicU64::new(0),
+            jobs_retried: AtomicU64::new(0),
+            query_cache_hits: AtomicU64::new(0),
+            query_cache_misses: AtomicU64::new(0),
+            query_budget_exceeded: AtomicU64::new(0),
+            emails_sent: Mutex::new(BTreeMap::new()),
+            emails_failed: Mutex::new(BTreeMap::new()),
+            query_durations: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Record a work package creation
+    pub fn record_work_package_created(&self) {
+        self.work_packages_created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a work package update
+    pub fn record_work_package_updated(&self) {
+        self.work_packages_updated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a work package deletion
+    pub fn record_work_package_deleted(&self) {
+        self.work_packages_deleted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a notification creation
+    pub fn record_notification_created(&self) {
+        self.notifications_created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a successfully sent email
+    pub fn record_email_sent(&self, sender: &'static str) {
+        *self.emails_sent.lock().unwrap().entry(sender).or_insert(0) += 1;
+    }
+
+    /// Record an email that failed to send
+    pub fn record_email_failed(&self, sender: &'static str) {
+        *self.emails_failed.lock().unwrap().entry(sender).or_insert(0) += 1;
+    }
+
+    /// Record a successfully processed job
+    pub fn record_job_processed(&self) {
+        self.jobs_processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a failed job
+    pub fn record_job_failed(&self) {
+        self.jobs_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a job scheduled for retry
+    pub fn record_job_retried(&self) {
+        self.jobs_retried.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a query result served from the cache
+    pub fn record_query_cache_hit(&self) {
+        self.query_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a query executed for lack of a cached result
+    pub fn record_query_cache_miss(&self) {
+        self.query_cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a request exceeding its budget of repository calls
+    pub fn record_query_budget_exceeded(&self) {
+        self.query_budget_exceeded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the duration of a repository query
+    pub fn observe_query(&self, repository: &'static str, method: &'static str, duration: Duration) {
+        self.query_durations
+            .lock()
+            .unwrap()
+            .entry((repository, method))
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Emails sent through the given sender type
+    pub fn emails_sent(&self, sender: &str) -> u64 {
+        self.emails_sent.lock().unwrap().get(sender).copied().unwrap_or(0)
+    }
+
+    /// Emails failed through the given sender type
+    pub fn emails_failed(&self, sender: &str) -> u64 {
+        self.emails_failed.lock().unwrap().get(sender).copied().unwrap_or(0)
+    }
+
+    /// Snapshot of the query histogram for a repository method
+    pub fn query_histogram(&self, repository: &str, method: &str) -> Option<Histogram> {
+        self.query_durations
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|((r, m), _)| *r == repository && *m == method)
+            .map(|(_, h)| h.clone())
+    }
+
+    /// Export metrics in Prometheus format
+    pub fn export_prometheus(&self) -> String {
+        let mut output = String::new();
+
+        let counters = [
+            ("work_packages_created_total", "Total work packages created", &self.work_packages_created),
+            ("work_packages_updated_total", "Total work packages updated", &self.work_packages_updated),
+            ("work_packages_deleted_total", "Total work packages deleted", &self.work_packages_deleted),
+            ("notifications_created_total", "Total notifications created", &self.notifications_created),
+            ("domain_jobs_processed_total", "Total background jobs processed by workers", &self.jobs_processed),
+            ("domain_jobs_failed_total", "Total background job executions that failed", &self.jobs_failed),
+            ("domain_jobs_retried_total", "Total background jobs scheduled for retry", &self.jobs_retried),
+            ("query_cache_hits_total", "Total work package query results served from the cache", &self.query_cache_hits),
+            ("query_cache_misses_total", "Total work package queries executed for lack of a cached result", &self.query_cache_misses),
+            ("query_budget_exceeded_total", "Total requests exceeding their budget of repository calls", &self.query_budget_exceeded),
+        ];
+
+        for (name, help, value) in counters {
+            output.push_str(&format!("# HELP {} {}\n", name, help));
+            output.push_str(&format!("# TYPE {} counter\n", name));
+            output.push_str(&format!("{} {}\n", name, value.load(Ordering::Relaxed)));
+        }
+
+        let labeled = [
+            ("emails_sent_total", "Total emails sent by sender type", &self.emails_sent),
+            ("emails_failed_total", "Total emails that failed to send by sender type", &self.emails_failed),
+        ];
+
+        for (name, help, values) in labeled {
+            output.push_str(&format!("# HELP {} {}\n", name, help));
+            output.push_str(&format!("# TYPE {} counter\n", name));
+            for (sender, value) in values.lock().unwrap().iter() {
+                output.push_str(&format!("{}{{sender=\"{}\"}} {}\n", name, sender, value));
+            }
+        }
+
+        output.push_str(
+            "# HELP repository_query_duration_seconds Repository query duration in seconds\n",
+        );
+        output.push_str("# TYPE repository_query_duration_seconds histogram\n");
+        for ((repository, method), histogram) in self.query_durations.lock().unwrap().iter() {
+            let labels = format!("repository=\"{}\",method=\"{}\"", repository, method);
+            for (bound, count) in QUERY_DURATION_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                output.push_str(&format!(
+                    "repository_query_duration_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                    labels, bound, count
+                ));
+            }
+            output.push_str(&format!(
+                "repository_query_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n",
+                labels, histogram.count
+            ));
+            output.push_str(&format!(
+                "repository_query_duration_seconds_sum{{{}}} {}\n",
+                labels, histogram.sum
+            ));
+            output.push_str(&format!(
+                "repository_query_duration_seconds_count{{{}}} {}\n",
+                labels, histogram.count
+            ));
+        }
+
+        output
+    }
+
+    /// Export metrics as JSON
+    pub fn export_json(&self) -> serde_json::Value {
+        let queries: Vec<serde_json::Value> = self
+            .query_durations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((repository, method), h)| {
+                serde_json::json!({
+                    "repository": repository,
+                    "method": method,
+                    "count": h.count,
+                    "sum_seconds": h.sum,
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "work_packages": {
+                "created": self.work_packages_created.load(Ordering::Relaxed),
+                "updated": self.work_packages_updated.load(Ordering::Relaxed),
+                "deleted": self.work_packages_deleted.load(Ordering::Relaxed),
+            },
+            "notifications": {
+                "created": self.notifications_created.load(Ordering::Relaxed),
+            },
+            "emails": {
+                "sent": *self.emails_sent.lock().unwrap(),
+                "failed": *self.emails_failed.lock().unwrap(),
+            },
+            "jobs": {
+                "processed": self.jobs_processed.load(Ordering::Relaxed),
+                "failed": self.jobs_failed.load(Ordering::Relaxed),
+                "retried": self.jobs_retried.load(Ordering::Relaxed),
+            },
+            "query_cache": {
+                "hits": self.query_cache_hits.load(Ordering::Relaxed),
+                "misses": self.query_cache_misses.load(Ordering::Relaxed),
+            },
+            "query_budget_exceeded": self.query_budget_exceeded.load(Ordering::Relaxed),
+            "queries": queries,
+        })
+    }
+}
+
+/// Guard that records a repository query duration when dropped
+///
+/// # Example
+/// ```ignore
+/// let _timer = QueryTimer::start(self.metrics.as_deref(), "work_packages", "find_by_id");
+/// let row = sqlx::query_as(...).fetch_optional(&self.pool).await?;
+/// ```
+pub struct QueryTimer<'a> {
+    metrics: Option<&'a DomainMetrics>,
+    repository: &'static str,
+    method: &'static str,
+    started: Instant,
+}
+
+impl<'a> QueryTimer<'a> {
+    /// Start timing; a `None` metrics handle makes the timer a no-op
+    pub fn start(
+        metrics: Option<&'a DomainMetrics>,
+        repository: &'static str,
+        method: &'static str,
+    ) -> Self {
+        Self {
+            metrics,
+            repository,
+            method,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for QueryTimer<'_> {
+    fn drop(&mut self) {
+        if let Some(metrics) = self.metrics {
+            metrics.observe_query(self.repository, self.method, self.started.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let metrics = DomainMetrics::new();
+        metrics.record_work_package_created();
+        metrics.record_work_package_created();
+        metrics.record_notification_created();
+        metrics.record_query_cache_hit();
+        metrics.record_query_cache_miss();
+        metrics.record_query_cache_miss();
+
+        assert_eq!(metrics.work_packages_created.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.notifications_created.load(Ordering::Relaxed), 1);
+        assert!(metrics.export_prometheus().contains("query_cache_misses_total 2"));
+        assert_eq!(metrics.export_json()["query_cache"]["hits"], 1);
+    }
+
+    #[test]
+    fn test_email_counters_by_sender() {
+        let metrics = DomainMetrics::new();
+        metrics.record_email_sent("memory");
+        metrics.record_email_sent("memory");
+        metrics.record_email_failed("ms_graph");
+
+        assert_eq!(metrics.emails_sent("memory"), 2);
+        assert_eq!(metrics.emails_failed("ms_graph"), 1);
+        assert_eq!(metrics.emails_sent("ms_graph"), 0);
+
+        let output = metrics.export_prometheus();
+        assert!(output.contains("emails_sent_total{sender=\"memory\"} 2"));
+        assert!(output.contains("emails_failed_total{sender=\"ms_graph\"} 1"));
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.003);
+        histogram.observe(0.2);
+
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.buckets[0], 0);
+        assert_eq!(histogram.buckets[1], 1);
+        assert_eq!(histogram.buckets[6], 2);
+    }
+
+    #[test]
+    fn test_query_timer() {
+        let metrics = DomainMetrics::new();
+        {
+            let _timer = QueryTimer::start(Some(&metrics), "work_packages", "find_by_id");
+        }
+        {
+            let _timer = QueryTimer::start(None, "work_packages", "find_by_id");
+        }
+
+        let histogram = metrics.query_histogram("work_packages", "find_by_id").unwrap();
+        assert_eq!(histogram.count(), 1);
+
+        let output = metrics.export_prometheus();
+        assert!(output.contains(
+            "repository_query_duration_seconds_count{repository=\"work_packages\",method=\"find_by_id\"} 1"
+        ));
+    }
+}
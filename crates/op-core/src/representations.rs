@@ -0,0 +1,1062 @@
+//! API v3 representations
+//!
+//! The HAL+JSON bodies of the resources op-api serves, defined once for
+//! the server rendering them and op-client parsing them. Request bodies
+//! live here as well, so a client can only send what the handlers accept.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::duration::DurationValue;
+use crate::traits::Id;
+
+/// Error identifiers of API v3 error responses
+pub mod error_identifier {
+    pub const NOT_FOUND: &str = "urn:openproject-org:api:v3:errors:NotFound";
+    pub const PROPERTY_CONSTRAINT_VIOLATION: &str = "urn:openproject-org:api:v3:errors:PropertyConstraintViolation";
+    pub const MULTIPLE_ERRORS: &str = "urn:openproject-org:api:v3:errors:MultipleErrors";
+    pub const UNAUTHENTICATED: &str = "urn:openproject-org:api:v3:errors:Unauthenticated";
+    pub const MISSING_PERMISSION: &str = "urn:openproject-org:api:v3:errors:MissingPermission";
+    pub const INSUFFICIENT_SCOPE: &str = "urn:openproject-org:api:v3:errors:InsufficientScope";
+    pub const INVALID_REQUEST_BODY: &str = "urn:openproject-org:api:v3:errors:InvalidRequestBody";
+    pub const UPDATE_CONFLICT: &str = "urn:openproject-org:api:v3:errors:UpdateConflict";
+    pub const INTERNAL_ERROR: &str = "urn:openproject-org:api:v3:errors:InternalError";
+    pub const SERVICE_UNAVAILABLE: &str = "urn:openproject-org:api:v3:errors:ServiceUnavailable";
+}
+
+/// A HAL link
+///
+/// Unset attributes are left out, so links serialize like the ones of the
+/// Ruby API.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HalLink {
+    pub href: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Set when `href` is a URI template, e.g. `/api/v3/work_packages{?filters}`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub templated: bool,
+    /// Body to send along, e.g. the validated payload of a form's commit link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    /// Identifier of the linked resource, e.g. a project's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    /// Media type of the link target
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+}
+
+impl HalLink {
+    /// Create a simple link with just an href
+    pub fn new(href: impl Into<String>) -> Self {
+        Self {
+            href: href.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Create a link with title
+    pub fn with_title(href: impl Into<String>, title: impl Into<String>) -> Self {
+        Self::new(href).title(title)
+    }
+
+    /// Create a templated link
+    pub fn templated(href: impl Into<String>) -> Self {
+        Self {
+            templated: true,
+            ..Self::new(href)
+        }
+    }
+
+    /// Create a link to POST to
+    pub fn post(href: impl Into<String>) -> Self {
+        Self::new(href).method("post")
+    }
+
+    /// Create a link to PATCH
+    pub fn patch(href: impl Into<String>) -> Self {
+        Self::new(href).method("patch")
+    }
+
+    /// Create a link to DELETE
+    pub fn delete(href: impl Into<String>) -> Self {
+        Self::new(href).method("delete")
+    }
+
+    /// Add title to link
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Add method to link
+    pub fn method(mut self, method: impl Into<String>) -> Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    /// Add payload schema
+    pub fn payload(mut self, payload: serde_json::Value) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Add the identifier of the linked resource
+    pub fn identifier(mut self, identifier: impl Into<String>) -> Self {
+        self.identifier = Some(identifier.into());
+        self
+    }
+
+    /// Add the media type of the link target
+    pub fn media_type(mut self, media_type: impl Into<String>) -> Self {
+        self.media_type = Some(media_type.into());
+        self
+    }
+
+    /// Type and id of the resource the link points to, see
+    /// [`parse_resource_href`]
+    pub fn resource(&self) -> Option<ResourceHref> {
+        parse_resource_href(&self.href)
+    }
+}
+
+/// Resource an API href points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceHref {
+    /// Path of the resource's collection below the API version, e.g.
+    /// `users` or `time_entries/activities`
+    pub resource_type: String,
+    pub id: Id,
+}
+
+/// Type and id of the resource an href points to, as in
+/// `/api/v3/users/5`
+///
+/// The href may be absolute, start below a URL root such as
+/// `/openproject`, and end in a slash; query and fragment are ignored. Hrefs
+/// not ending in a numeric id below a versioned API prefix yield `None`.
+pub fn parse_resource_href(href: &str) -> Option<ResourceHref> {
+    let path = href.split(['?', '#']).next().unwrap_or_default();
+    let path = match path.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |slash| &rest[slash..]),
+        None => path,
+    };
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    let version = segments.windows(2).position(|pair| {
+        pair[0] == "api" && pair[1].strip_prefix('v').is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    })?;
+    let (id, resource) = segments[version + 2..].split_last()?;
+    if resource.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(ResourceHref {
+        resource_type: resource.join("/"),
+        id: id.parse().ok()?,
+    })
+}
+
+/// Link setting an association in a request body, as in
+/// `"_links": { "assignee": { "href": "/api/v3/users/5" } }`; a null href
+/// clears the association
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssociationLink {
+    pub href: Option<String>,
+}
+
+impl AssociationLink {
+    pub fn new(href: impl Into<String>) -> Self {
+        Self { href: Some(href.into()) }
+    }
+
+    /// Link clearing the association
+    pub fn null() -> Self {
+        Self { href: None }
+    }
+}
+
+/// Associations set in the `_links` of a request body, by relation
+pub type AssociationLinks = BTreeMap<String, AssociationLink>;
+
+/// Collection of HAL links
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HalLinks(HashMap<String, HalLinkValue>);
+
+/// A link value can be a single link or an array of links
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HalLinkValue {
+    Single(HalLink),
+    Array(Vec<HalLink>),
+}
+
+impl HalLinks {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Add a single link
+    pub fn add(&mut self, rel: impl Into<String>, link: HalLink) {
+        self.0.insert(rel.into(), HalLinkValue::Single(link));
+    }
+
+    /// Add multiple links for a relation
+    pub fn add_array(&mut self, rel: impl Into<String>, links: Vec<HalLink>) {
+        self.0.insert(rel.into(), HalLinkValue::Array(links));
+    }
+
+    /// Builder pattern: add single link
+    pub fn with(mut self, rel: impl Into<String>, link: HalLink) -> Self {
+        self.add(rel, link);
+        self
+    }
+
+    /// Builder pattern: add link array
+    pub fn with_array(mut self, rel: impl Into<String>, links: Vec<HalLink>) -> Self {
+        self.add_array(rel, links);
+        self
+    }
+
+    /// Add all links of `other`, replacing relations present in both
+    pub fn extend(&mut self, other: HalLinks) {
+        self.0.extend(other.0);
+    }
+
+    /// Check if a relation exists
+    pub fn has(&self, rel: &str) -> bool {
+        self.0.contains_key(rel)
+    }
+
+    /// Get a link by relation
+    pub fn get(&self, rel: &str) -> Option<&HalLinkValue> {
+        self.0.get(rel)
+    }
+
+    /// Href of a single link relation
+    pub fn href(&self, rel: &str) -> Option<&str> {
+        match self.0.get(rel)? {
+            HalLinkValue::Single(link) => Some(&link.href),
+            HalLinkValue::Array(_) => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Link of a fixed relation of a resource
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    pub href: String,
+}
+
+impl Link {
+    pub fn new(href: impl Into<String>) -> Self {
+        Self { href: href.into() }
+    }
+}
+
+/// A page of a collection
+///
+/// `offset` counts elements, so the next page starts at `offset + count`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection<T> {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub total: usize,
+    pub count: usize,
+    pub page_size: usize,
+    pub offset: usize,
+    /// Set when `total` is an estimate of a large result
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub total_is_estimate: bool,
+    /// Groups of the matches of a grouped collection, across all pages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<CollectionGroup>,
+    #[serde(rename = "_links", default, skip_serializing_if = "HalLinks::is_empty")]
+    pub links: HalLinks,
+    #[serde(rename = "_embedded")]
+    pub elements: Vec<T>,
+}
+
+impl<T> Collection<T> {
+    pub fn new(elements: Vec<T>, total: usize, offset: usize, page_size: usize) -> Self {
+        Self {
+            type_name: "Collection".into(),
+            total,
+            count: elements.len(),
+            page_size,
+            offset,
+            total_is_estimate: false,
+            groups: Vec::new(),
+            links: HalLinks::new(),
+            elements,
+        }
+    }
+
+    /// Add the groups of a grouped collection
+    pub fn with_groups(mut self, groups: Vec<CollectionGroup>) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    /// Mark the total as an estimate
+    pub fn with_estimated_total(mut self, total_is_estimate: bool) -> Self {
+        self.total_is_estimate = total_is_estimate;
+        self
+    }
+
+    /// Add pagination links
+    pub fn with_links(mut self, links: HalLinks) -> Self {
+        self.links = links;
+        self
+    }
+
+    /// Offset of the following page, `None` on the last one
+    pub fn next_offset(&self) -> Option<usize> {
+        let next = self.offset + self.count;
+        (self.count > 0 && next < self.total).then_some(next)
+    }
+}
+
+/// The matches of a grouped collection in one of its groups
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionGroup {
+    /// Grouped value, e.g. the id of a status or a period like `2024-03`;
+    /// `null` for the matches without a value
+    pub value: Option<String>,
+    /// Human readable name of the group, e.g. "March 2024" or "CW 12"
+    pub label: String,
+    pub count: usize,
+    #[serde(rename = "_links", default, skip_serializing_if = "HalLinks::is_empty")]
+    pub links: HalLinks,
+}
+
+/// Formattable text (supports HTML/Markdown)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormattableText {
+    pub format: String,
+    pub raw: String,
+    pub html: String,
+}
+
+impl FormattableText {
+    pub fn plain(text: &str) -> Self {
+        Self {
+            format: "plain".to_string(),
+            raw: text.to_string(),
+            html: format!("<p>{}</p>", html_escape(text)),
+        }
+    }
+
+    pub fn markdown(text: &str) -> Self {
+        Self {
+            format: "markdown".to_string(),
+            raw: text.to_string(),
+            // TODO: Actually render markdown to HTML
+            html: format!("<p>{}</p>", html_escape(text)),
+        }
+    }
+
+    /// Markdown rendered by op-api's `MarkdownRenderer`
+    pub fn markdown_rendered(text: &str, html: String) -> Self {
+        Self {
+            format: "markdown".to_string(),
+            raw: text.to_string(),
+            html,
+        }
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Error response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    #[serde(rename = "errorIdentifier")]
+    pub error_identifier: String,
+    pub message: String,
+    #[serde(rename = "_embedded", skip_serializing_if = "Option::is_none")]
+    pub embedded: Option<ErrorEmbedded>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Either the violated property of a single error or the individual
+/// errors of a `MultipleErrors` one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorEmbedded {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<ErrorDetails>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorDetails {
+    pub attribute: String,
+}
+
+impl ErrorResponse {
+    pub fn new(identifier: &str, message: String) -> Self {
+        Self {
+            type_name: "Error".into(),
+            error_identifier: identifier.into(),
+            message,
+            embedded: None,
+            request_id: None,
+        }
+    }
+
+    /// Attribute violating a constraint
+    pub fn attribute(&self) -> Option<&str> {
+        let details = self.embedded.as_ref()?.details.as_ref()?;
+        Some(&details.attribute)
+    }
+
+    /// Individual errors of a `MultipleErrors` response
+    pub fn errors(&self) -> &[ErrorResponse] {
+        self.embedded.as_ref().map(|e| e.errors.as_slice()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkPackage {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub id: Id,
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<FormattableText>,
+    pub project_id: Id,
+    pub type_id: Id,
+    pub status_id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_id: Option<Id>,
+    pub author_id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned_to_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_hours: Option<f64>,
+    pub done_ratio: i32,
+    pub lock_version: i32,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Whether the work package changed since the current user last viewed
+    /// it; only in collections, and not for anonymous users
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unseen_changes: Option<bool>,
+    /// Actions offered to the current user, e.g. `customActions`
+    #[serde(rename = "_links", default, skip_serializing_if = "HalLinks::is_empty")]
+    pub links: HalLinks,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateWorkPackage {
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned_to_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_hours: Option<f64>,
+    /// Associations given as links, taking precedence over the ids
+    #[serde(rename = "_links", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: AssociationLinks,
+}
+
+/// Changes to a work package; `lockVersion` must match the current one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateWorkPackage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned_to_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_hours: Option<f64>,
+    /// ISO 8601 duration, takes precedence over `estimatedHours`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub done_ratio: Option<i32>,
+    #[serde(default)]
+    pub lock_version: i32,
+    /// Associations given as links, taking precedence over the ids; a null
+    /// href clears the association
+    #[serde(rename = "_links", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: AssociationLinks,
+}
+
+/// The same changes applied to several work packages, whatever their lock
+/// versions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkUpdateWorkPackages {
+    pub ids: Vec<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned_to_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub done_ratio: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub id: Id,
+    pub name: String,
+    pub identifier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub public: bool,
+    pub active: bool,
+    pub templated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Id>,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Values of the project custom fields, e.g. `customField3`
+    #[serde(flatten, deserialize_with = "custom_field_values")]
+    pub custom_fields: CustomFieldValues,
+    #[serde(rename = "_links")]
+    pub links: ProjectLinks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Link>,
+    pub work_packages: Link,
+    pub categories: Link,
+    pub versions: Link,
+    pub memberships: Link,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateProject {
+    pub name: String,
+    /// Derived from the name when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub templated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Id>,
+    /// Values of project custom fields, e.g. `customField3`
+    #[serde(flatten, deserialize_with = "custom_field_values")]
+    pub custom_fields: CustomFieldValues,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateProject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub templated: Option<bool>,
+    /// Values of project custom fields to change; null removes a value
+    #[serde(flatten, deserialize_with = "custom_field_values")]
+    pub custom_fields: CustomFieldValues,
+}
+
+/// Custom field values of a resource by attribute name, e.g. `customField3`
+pub type CustomFieldValues = BTreeMap<String, serde_json::Value>;
+
+/// Custom field values among the attributes of a resource the other fields
+/// do not take
+fn custom_field_values<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CustomFieldValues, D::Error> {
+    let mut attributes = CustomFieldValues::deserialize(deserializer)?;
+    attributes.retain(|name, _| name.starts_with("customField"));
+    Ok(attributes)
+}
+
+/// A user; `email` is only shown to the user and to admins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub id: Id,
+    pub login: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub admin: bool,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    #[serde(rename = "_links")]
+    pub links: UserLinks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock: Option<Link>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unlock: Option<Link>,
+    pub memberships: Link,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUser {
+    pub login: String,
+    pub firstname: String,
+    pub lastname: String,
+    pub email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateUser {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub firstname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lastname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeEntry {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub id: Id,
+    pub project_id: Id,
+    pub user_id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_package_id: Option<Id>,
+    pub hours: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<String>,
+    pub activity_id: Id,
+    pub spent_on: String,
+    pub created_at: String,
+    pub updated_at: String,
+    #[serde(rename = "_links")]
+    pub links: TimeEntryLinks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeEntryLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    pub project: Link,
+    pub user: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_package: Option<Link>,
+    pub activity: Link,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTimeEntry {
+    pub project_id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_package_id: Option<Id>,
+    /// ISO 8601 duration (`PT1H30M`) or plain hours
+    pub hours: DurationValue,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comments: Option<String>,
+    pub activity_id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spent_on: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateTimeEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_package_id: Option<Id>,
+    /// ISO 8601 duration (`PT1H30M`) or plain hours
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hours: Option<DurationValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spent_on: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    pub filesize: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    pub downloads: i32,
+    pub status: String,
+    pub created_at: String,
+    pub updated_at: String,
+    #[serde(rename = "_links")]
+    pub links: AttachmentLinks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    pub author: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<Link>,
+    pub download_location: Link,
+}
+
+/// Metadata of an attachment whose file is stored elsewhere
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAttachment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_type: Option<String>,
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_filename: Option<String>,
+    pub filesize: i64,
+    pub content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+/// `metadata` part of a multipart attachment upload; the file itself is
+/// the `file` part
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadAttachmentMetadata {
+    /// Taken from the `file` part when omitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<Id>,
+}
+
+/// Changes to an attachment's metadata; the stored file stays the same
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateAttachment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Session of a resumable upload, sent in chunks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Upload {
+    #[serde(rename = "_type")]
+    pub type_name: String,
+    pub id: String,
+    pub file_name: String,
+    /// Size in bytes the complete file is expected to have
+    pub file_size: i64,
+    pub digest_algorithm: String,
+    /// Number of the chunk expected next
+    pub next_chunk: u32,
+    /// Bytes received so far, one range per chunk
+    pub received_ranges: Vec<ReceivedRange>,
+    pub created_at: String,
+    pub updated_at: String,
+    #[serde(rename = "_links")]
+    pub links: UploadLinks,
+}
+
+/// Inclusive byte range of the file received in a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceivedRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    /// Where to PUT the chunk expected next
+    pub next_chunk: Link,
+    pub complete: Link,
+}
+
+/// Start of a resumable upload
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUpload {
+    pub file_name: String,
+    pub file_size: i64,
+    pub digest_algorithm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<Id>,
+}
+
+/// Completion of a resumable upload into an attachment
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompleteUpload {
+    /// Digest of the complete file in the upload's algorithm
+    pub digest: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_escape() {
+        assert_eq!(html_escape("<script>"), "&lt;script&gt;");
+        assert_eq!(html_escape("A & B"), "A &amp; B");
+    }
+
+    #[test]
+    fn test_formattable_text() {
+        let text = FormattableText::plain("Hello <world>");
+        assert_eq!(text.format, "plain");
+        assert_eq!(text.raw, "Hello <world>");
+        assert!(text.html.contains("&lt;world&gt;"));
+    }
+
+    #[test]
+    fn test_hal_link_omits_unset_attributes() {
+        let json = serde_json::to_value(HalLink::new("/api/v3/users/1")).unwrap();
+        assert_eq!(json, serde_json::json!({ "href": "/api/v3/users/1" }));
+
+        let link = HalLink::templated("/api/v3/work_packages{?filters,sortBy}");
+        let json = serde_json::to_value(&link).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "href": "/api/v3/work_packages{?filters,sortBy}", "templated": true })
+        );
+
+        let link = HalLink::delete("/api/v3/projects/1")
+            .payload(serde_json::json!({ "confirm": true }))
+            .identifier("demo")
+            .media_type("application/hal+json");
+        let json = serde_json::to_value(&link).unwrap();
+        assert_eq!(json["method"], "delete");
+        assert_eq!(json["payload"]["confirm"], true);
+        assert_eq!(json["identifier"], "demo");
+        assert_eq!(json["type"], "application/hal+json");
+
+        let parsed: HalLink = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, link);
+        let parsed: HalLink = serde_json::from_value(serde_json::json!({ "href": "/api/v3/users/1" })).unwrap();
+        assert!(!parsed.templated);
+    }
+
+    #[test]
+    fn test_parse_resource_href() {
+        let user = ResourceHref {
+            resource_type: "users".into(),
+            id: 5,
+        };
+        assert_eq!(parse_resource_href("/api/v3/users/5"), Some(user.clone()));
+        assert_eq!(parse_resource_href("/api/v3/users/5/"), Some(user.clone()));
+        assert_eq!(parse_resource_href("/openproject/api/v3/users/5"), Some(user.clone()));
+        assert_eq!(parse_resource_href("https://example.com/api/v3/users/5?embed=true"), Some(user.clone()));
+        assert_eq!(HalLink::new("/api/v3/users/5").resource(), Some(user));
+
+        let activity = parse_resource_href("/api/v3/time_entries/activities/3").unwrap();
+        assert_eq!((activity.resource_type.as_str(), activity.id), ("time_entries/activities", 3));
+
+        assert_eq!(parse_resource_href("/api/v3/users"), None);
+        assert_eq!(parse_resource_href("/api/v3/5"), None);
+        assert_eq!(parse_resource_href("/api/v3/projects/demo"), None);
+        assert_eq!(parse_resource_href("/api/users/5"), None);
+        assert_eq!(parse_resource_href("/users/5"), None);
+        assert_eq!(parse_resource_href(""), None);
+    }
+
+    #[test]
+    fn test_collection_round_trip() {
+        let collection = Collection::new(vec![Link::new("/api/v3/users/1")], 3, 0, 1)
+            .with_links(HalLinks::new().with("self", HalLink::new("/api/v3/users?offset=0&pageSize=1")));
+
+        let json = serde_json::to_value(&collection).unwrap();
+        assert_eq!(json["_type"], "Collection");
+        assert_eq!(json["pageSize"], 1);
+        assert_eq!(json["_embedded"][0]["href"], "/api/v3/users/1");
+
+        let parsed: Collection<Link> = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.elements, collection.elements);
+        assert_eq!(parsed.links.href("self"), Some("/api/v3/users?offset=0&pageSize=1"));
+        assert_eq!(parsed.next_offset(), Some(1));
+    }
+
+    #[test]
+    fn test_project_custom_field_values() {
+        let json = serde_json::json!({
+            "name": "Demo",
+            "customField3": "Build",
+            "customField4": null,
+            "_links": { "parent": { "href": "/api/v3/projects/1" } },
+        });
+
+        let project: CreateProject = serde_json::from_value(json).unwrap();
+        assert_eq!(project.custom_fields.len(), 2);
+        assert_eq!(project.custom_fields["customField3"], "Build");
+
+        let json = serde_json::to_value(&project).unwrap();
+        assert_eq!(json["customField3"], "Build");
+        assert!(json.get("_links").is_none());
+    }
+
+    #[test]
+    fn test_collection_without_links() {
+        let collection: Collection<Link> = Collection::new(Vec::new(), 0, 0, 20);
+
+        let json = serde_json::to_value(&collection).unwrap();
+        assert!(json.get("_links").is_none());
+
+        let parsed: Collection<Link> = serde_json::from_value(json).unwrap();
+        assert!(parsed.links.is_empty());
+        assert_eq!(parsed.next_offset(), None);
+    }
+
+    #[test]
+    fn test_error_response_round_trip() {
+        let json = serde_json::json!({
+            "_type": "Error",
+            "errorIdentifier": error_identifier::MULTIPLE_ERRORS,
+            "message": "Multiple field constraints have been violated.",
+            "_embedded": {
+                "errors": [{
+                    "_type": "Error",
+                    "errorIdentifier": error_identifier::PROPERTY_CONSTRAINT_VIOLATION,
+                    "message": "subject can't be blank.",
+                    "_embedded": { "details": { "attribute": "subject" } }
+                }]
+            }
+        });
+
+        let error: ErrorResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(error.attribute(), None);
+        assert_eq!(error.errors().len(), 1);
+        assert_eq!(error.errors()[0].attribute(), Some("subject"));
+    }
+}
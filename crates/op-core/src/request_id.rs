@@ -0,0 +1,70 @@
+//! Request Correlation
+//!
+//! Carries the ID of the API request currently being processed so that logs,
+//! error bodies and background jobs enqueued during the request can be tied
+//! back to it. The ID lives in a task-local set by the HTTP middleware.
+
+use std::future::Future;
+
+/// Header used to accept and return request IDs
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Job metadata key holding the originating request ID
+pub const REQUEST_ID_METADATA_KEY: &str = "request_id";
+
+/// Maximum accepted length of an incoming request ID
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Generate a new request ID
+pub fn generate() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Accept a client-provided request ID if it is reasonably sized printable ASCII
+pub fn sanitize(incoming: &str) -> Option<String> {
+    let incoming = incoming.trim();
+    if incoming.is_empty()
+        || incoming.len() > MAX_REQUEST_ID_LEN
+        || !incoming.chars().all(|c| c.is_ascii_graphic())
+    {
+        return None;
+    }
+    Some(incoming.to_string())
+}
+
+/// Run a future with the given request ID as the current one
+pub async fn scope<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// ID of the request being processed by the current task, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize(" abc-123 "), Some("abc-123".to_string()));
+        assert_eq!(sanitize(""), None);
+        assert_eq!(sanitize("has space"), None);
+        assert_eq!(sanitize(&"x".repeat(200)), None);
+    }
+
+    #[tokio::test]
+    async fn test_scope() {
+        assert_eq!(current(), None);
+
+        let inner = scope("req-1".into(), async { current() }).await;
+        assert_eq!(inner, Some("req-1".to_string()));
+
+        assert_eq!(current(), None);
+    }
+}
@@ -0,0 +1,237 @@
+-- Schema of the tables the repository tests touch
+--
+-- A subset of the OpenProject schema, which is otherwise managed by the
+-- Rails migrations. Applied by the `pg-tests` harness only; the statements
+-- leave existing tables alone.
+
+CREATE TABLE IF NOT EXISTS users (
+    id BIGSERIAL PRIMARY KEY,
+    type VARCHAR(255) NOT NULL DEFAULT 'User',
+    login VARCHAR(255) NOT NULL,
+    firstname VARCHAR(255) NOT NULL DEFAULT '',
+    lastname VARCHAR(255) NOT NULL DEFAULT '',
+    mail VARCHAR(255) NOT NULL DEFAULT '',
+    admin BOOLEAN NOT NULL DEFAULT FALSE,
+    status INTEGER NOT NULL DEFAULT 1,
+    language VARCHAR(255),
+    hashed_password VARCHAR(255),
+    salt VARCHAR(255),
+    last_login_on TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS projects (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    identifier VARCHAR(255) NOT NULL UNIQUE,
+    public BOOLEAN NOT NULL DEFAULT FALSE,
+    parent_id BIGINT REFERENCES projects (id),
+    lft INTEGER NOT NULL DEFAULT 0,
+    rgt INTEGER NOT NULL DEFAULT 0,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    templated BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS types (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    position INTEGER NOT NULL DEFAULT 1,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    is_in_roadmap BOOLEAN NOT NULL DEFAULT TRUE,
+    is_milestone BOOLEAN NOT NULL DEFAULT FALSE,
+    is_standard BOOLEAN NOT NULL DEFAULT FALSE,
+    color_id BIGINT,
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS statuses (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    is_closed BOOLEAN NOT NULL DEFAULT FALSE,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    is_readonly BOOLEAN NOT NULL DEFAULT FALSE,
+    position INTEGER NOT NULL DEFAULT 1,
+    default_done_ratio INTEGER NOT NULL DEFAULT 0,
+    color_id BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS roles (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    position INTEGER NOT NULL DEFAULT 1,
+    builtin INTEGER NOT NULL DEFAULT 0,
+    type VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions (
+    id BIGSERIAL PRIMARY KEY,
+    role_id BIGINT NOT NULL REFERENCES roles (id),
+    permission VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (role_id, permission)
+);
+
+CREATE TABLE IF NOT EXISTS members (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    project_id BIGINT REFERENCES projects (id),
+    entity_type VARCHAR(255),
+    entity_id BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS member_roles (
+    id BIGSERIAL PRIMARY KEY,
+    member_id BIGINT NOT NULL REFERENCES members (id),
+    role_id BIGINT NOT NULL REFERENCES roles (id),
+    inherited_from BIGINT,
+    UNIQUE (member_id, role_id)
+);
+
+CREATE TABLE IF NOT EXISTS work_packages (
+    id BIGSERIAL PRIMARY KEY,
+    subject VARCHAR(255) NOT NULL,
+    description TEXT,
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    type_id BIGINT NOT NULL REFERENCES types (id),
+    status_id BIGINT NOT NULL REFERENCES statuses (id),
+    priority_id BIGINT,
+    author_id BIGINT NOT NULL REFERENCES users (id),
+    assigned_to_id BIGINT REFERENCES users (id),
+    responsible_id BIGINT REFERENCES users (id),
+    start_date DATE,
+    due_date DATE,
+    estimated_hours DOUBLE PRECISION,
+    done_ratio INTEGER NOT NULL DEFAULT 0,
+    parent_id BIGINT REFERENCES work_packages (id),
+    version_id BIGINT,
+    category_id BIGINT,
+    lock_version INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS journals (
+    id BIGSERIAL PRIMARY KEY,
+    journable_type VARCHAR(255) NOT NULL,
+    journable_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    notes TEXT,
+    version INTEGER NOT NULL,
+    data_type VARCHAR(255) NOT NULL,
+    data_id BIGINT NOT NULL,
+    cause JSONB NOT NULL DEFAULT '{}',
+    restricted BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (journable_type, journable_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS work_package_journals (
+    id BIGSERIAL PRIMARY KEY,
+    type_id BIGINT NOT NULL,
+    project_id BIGINT NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    description TEXT,
+    due_date DATE,
+    category_id BIGINT,
+    status_id BIGINT NOT NULL,
+    assigned_to_id BIGINT,
+    priority_id BIGINT NOT NULL,
+    version_id BIGINT,
+    author_id BIGINT NOT NULL,
+    done_ratio INTEGER,
+    estimated_hours DOUBLE PRECISION,
+    start_date DATE,
+    parent_id BIGINT,
+    responsible_id BIGINT,
+    derived_estimated_hours DOUBLE PRECISION,
+    schedule_manually BOOLEAN,
+    duration INTEGER,
+    ignore_non_working_days BOOLEAN NOT NULL DEFAULT FALSE,
+    derived_remaining_hours DOUBLE PRECISION,
+    derived_done_ratio INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS watchers (
+    id BIGSERIAL PRIMARY KEY,
+    watchable_type VARCHAR(255) NOT NULL,
+    watchable_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users (id)
+);
+
+CREATE TABLE IF NOT EXISTS relations (
+    id BIGSERIAL PRIMARY KEY,
+    from_id BIGINT NOT NULL REFERENCES work_packages (id),
+    to_id BIGINT NOT NULL REFERENCES work_packages (id),
+    relation_type VARCHAR(255) NOT NULL,
+    lag INTEGER,
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS attachments (
+    id BIGSERIAL PRIMARY KEY,
+    container_id BIGINT,
+    container_type VARCHAR(255),
+    filename VARCHAR(255),
+    disk_filename VARCHAR(255),
+    filesize BIGINT NOT NULL DEFAULT 0,
+    content_type VARCHAR(255),
+    digest VARCHAR(255),
+    downloads INTEGER NOT NULL DEFAULT 0,
+    author_id BIGINT NOT NULL REFERENCES users (id),
+    description TEXT,
+    status INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS time_entries (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    work_package_id BIGINT REFERENCES work_packages (id),
+    hours DOUBLE PRECISION NOT NULL,
+    comments VARCHAR(255),
+    activity_id BIGINT NOT NULL,
+    spent_on DATE NOT NULL,
+    tyear INTEGER NOT NULL,
+    tmonth INTEGER NOT NULL,
+    tweek INTEGER NOT NULL,
+    overridden_costs DOUBLE PRECISION,
+    costs DOUBLE PRECISION,
+    rate_id BIGINT,
+    logged_by_id BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS notifications (
+    id BIGSERIAL PRIMARY KEY,
+    recipient_id BIGINT NOT NULL REFERENCES users (id),
+    actor_id BIGINT,
+    project_id BIGINT,
+    resource_type VARCHAR(255) NOT NULL,
+    resource_id BIGINT NOT NULL,
+    journal_id BIGINT,
+    reason INTEGER,
+    read_ian BOOLEAN NOT NULL DEFAULT FALSE,
+    mail_reminder_sent BOOLEAN NOT NULL DEFAULT FALSE,
+    mail_alert_sent BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
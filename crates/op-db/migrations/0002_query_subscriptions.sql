@@ -0,0 +1,36 @@
+-- Saved queries and the subscriptions emailing users about their results
+--
+-- Query subscriptions have no counterpart in the Rails schema; the
+-- snapshot holds the ids of the results seen at the last check.
+
+CREATE TABLE IF NOT EXISTS queries (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT REFERENCES projects (id),
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    name VARCHAR(255) NOT NULL,
+    filters TEXT,
+    column_names TEXT,
+    sort_criteria TEXT,
+    group_by VARCHAR(255),
+    display_sums BOOLEAN NOT NULL DEFAULT FALSE,
+    show_hierarchies BOOLEAN NOT NULL DEFAULT FALSE,
+    include_subprojects BOOLEAN NOT NULL DEFAULT TRUE,
+    timeline_visible BOOLEAN NOT NULL DEFAULT FALSE,
+    timestamps VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS query_subscriptions (
+    id BIGSERIAL PRIMARY KEY,
+    query_id BIGINT NOT NULL REFERENCES queries (id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    frequency VARCHAR(255) NOT NULL DEFAULT 'daily',
+    snapshot BIGINT[],
+    checked_at TIMESTAMPTZ,
+    suspended_at TIMESTAMPTZ,
+    suspended_reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (query_id, user_id)
+);
@@ -0,0 +1,204 @@
+-- Remaining tables and columns read or written by op-rs
+--
+-- Together with the earlier migrations this is enough to run op-server
+-- against an empty database. Databases managed by the Rails migrations
+-- already have these tables; point op-server at them in schema check mode
+-- instead of migrating.
+
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS settings JSONB NOT NULL DEFAULT '{}';
+
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS position INTEGER;
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS story_points INTEGER;
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS remaining_hours DOUBLE PRECISION;
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS schedule_manually BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS duration INTEGER;
+
+CREATE TABLE IF NOT EXISTS colors (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    hexcode VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Priorities and time entry activities, told apart by `type`
+CREATE TABLE IF NOT EXISTS enumerations (
+    id BIGSERIAL PRIMARY KEY,
+    type VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    position INTEGER NOT NULL DEFAULT 1,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    color_id BIGINT REFERENCES colors (id),
+    project_id BIGINT REFERENCES projects (id),
+    parent_id BIGINT REFERENCES enumerations (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS versions (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    effective_date DATE,
+    start_date DATE,
+    status VARCHAR(255) NOT NULL DEFAULT 'open',
+    sharing VARCHAR(255) NOT NULL DEFAULT 'none',
+    wiki_page_title VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS categories (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    name VARCHAR(255) NOT NULL,
+    assigned_to_id BIGINT REFERENCES users (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS projects_types (
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    type_id BIGINT NOT NULL REFERENCES types (id),
+    UNIQUE (project_id, type_id)
+);
+
+CREATE TABLE IF NOT EXISTS enabled_modules (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT REFERENCES projects (id),
+    name VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS custom_fields (
+    id BIGSERIAL PRIMARY KEY,
+    type VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    field_format VARCHAR(255) NOT NULL DEFAULT 'string',
+    is_required BOOLEAN NOT NULL DEFAULT FALSE,
+    is_for_all BOOLEAN NOT NULL DEFAULT FALSE,
+    position INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS custom_fields_projects (
+    custom_field_id BIGINT NOT NULL REFERENCES custom_fields (id),
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    UNIQUE (custom_field_id, project_id)
+);
+
+CREATE TABLE IF NOT EXISTS views (
+    id BIGSERIAL PRIMARY KEY,
+    query_id BIGINT NOT NULL REFERENCES queries (id),
+    type VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Starred queries
+CREATE TABLE IF NOT EXISTS query_menu_items (
+    id BIGSERIAL PRIMARY KEY,
+    navigatable_id BIGINT NOT NULL UNIQUE,
+    name VARCHAR(255) NOT NULL,
+    title VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS audit_events (
+    id BIGSERIAL PRIMARY KEY,
+    event_type VARCHAR(255) NOT NULL,
+    actor_id BIGINT,
+    target_type VARCHAR(255),
+    target_id BIGINT,
+    ip_address VARCHAR(255),
+    user_agent TEXT,
+    details JSONB NOT NULL DEFAULT '{}',
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS tokens (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT REFERENCES users (id),
+    type VARCHAR(255),
+    value VARCHAR(128) NOT NULL DEFAULT '',
+    expires_on TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS settings (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    value TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Containers of attachments, counted towards the attachment quota of
+-- their project
+
+CREATE TABLE IF NOT EXISTS wikis (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    start_page VARCHAR(255) NOT NULL DEFAULT 'Wiki',
+    status INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS wiki_pages (
+    id BIGSERIAL PRIMARY KEY,
+    wiki_id BIGINT NOT NULL REFERENCES wikis (id),
+    title VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS documents (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    title VARCHAR(255) NOT NULL DEFAULT '',
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS news (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT REFERENCES projects (id),
+    title VARCHAR(255) NOT NULL DEFAULT '',
+    description TEXT,
+    author_id BIGINT REFERENCES users (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS forums (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    name VARCHAR(255) NOT NULL DEFAULT '',
+    description VARCHAR(255),
+    position INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id BIGSERIAL PRIMARY KEY,
+    forum_id BIGINT NOT NULL REFERENCES forums (id),
+    parent_id BIGINT REFERENCES messages (id),
+    subject VARCHAR(255) NOT NULL DEFAULT '',
+    content TEXT,
+    author_id BIGINT REFERENCES users (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS meetings (
+    id BIGSERIAL PRIMARY KEY,
+    project_id BIGINT REFERENCES projects (id),
+    title VARCHAR(255),
+    author_id BIGINT REFERENCES users (id),
+    start_time TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
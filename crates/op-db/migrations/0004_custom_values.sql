@@ -0,0 +1,12 @@
+-- Values of custom fields, per customized record
+
+CREATE TABLE IF NOT EXISTS custom_values (
+    id BIGSERIAL PRIMARY KEY,
+    customized_type VARCHAR(255) NOT NULL,
+    customized_id BIGINT NOT NULL,
+    custom_field_id BIGINT NOT NULL REFERENCES custom_fields (id),
+    value TEXT
+);
+
+CREATE INDEX IF NOT EXISTS index_custom_values_on_customized
+    ON custom_values (customized_type, customized_id);
@@ -0,0 +1,7 @@
+-- Last run markers of recurring jobs, advanced by the scheduler
+
+CREATE TABLE IF NOT EXISTS scheduled_jobs (
+    name VARCHAR(255) PRIMARY KEY,
+    last_run_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
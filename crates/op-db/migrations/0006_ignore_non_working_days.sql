@@ -0,0 +1,3 @@
+-- Whether weekends and non-working days count towards a work package's duration
+
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS ignore_non_working_days BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,42 @@
+-- External file storages, e.g. Nextcloud, and the files linked to work packages
+
+CREATE TABLE IF NOT EXISTS storages (
+    id BIGSERIAL PRIMARY KEY,
+    provider_type VARCHAR(255) NOT NULL,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    host VARCHAR(255) NOT NULL UNIQUE,
+    creator_id BIGINT NOT NULL REFERENCES users (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- OAuth clients OpenProject uses to access other applications
+CREATE TABLE IF NOT EXISTS oauth_clients (
+    id BIGSERIAL PRIMARY KEY,
+    client_id VARCHAR(255) NOT NULL,
+    client_secret VARCHAR(255),
+    integration_type VARCHAR(255) NOT NULL,
+    integration_id BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (integration_type, integration_id)
+);
+
+CREATE TABLE IF NOT EXISTS file_links (
+    id BIGSERIAL PRIMARY KEY,
+    storage_id BIGINT NOT NULL REFERENCES storages (id) ON DELETE CASCADE,
+    creator_id BIGINT NOT NULL REFERENCES users (id),
+    container_id BIGINT NOT NULL,
+    container_type VARCHAR(255) NOT NULL,
+    origin_id VARCHAR(255) NOT NULL,
+    origin_name VARCHAR(255) NOT NULL,
+    origin_mime_type VARCHAR(255),
+    origin_created_by_name VARCHAR(255),
+    origin_created_at TIMESTAMPTZ,
+    origin_updated_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (container_type, container_id, storage_id, origin_id)
+);
+
+CREATE INDEX IF NOT EXISTS index_file_links_on_container ON file_links (container_type, container_id);
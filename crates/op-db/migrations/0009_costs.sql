@@ -0,0 +1,53 @@
+-- Unit costs, hourly and unit rates, and the costs of work packages derived
+-- from their time and cost entries
+
+CREATE TABLE IF NOT EXISTS cost_types (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    unit VARCHAR(255) NOT NULL,
+    unit_plural VARCHAR(255) NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    deleted_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS cost_entries (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    project_id BIGINT NOT NULL REFERENCES projects (id),
+    work_package_id BIGINT REFERENCES work_packages (id),
+    cost_type_id BIGINT NOT NULL REFERENCES cost_types (id),
+    units DOUBLE PRECISION NOT NULL,
+    spent_on DATE NOT NULL,
+    comments VARCHAR(255),
+    overridden_costs DOUBLE PRECISION,
+    costs DOUBLE PRECISION,
+    rate_id BIGINT,
+    logged_by_id BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_cost_entries_on_work_package_id ON cost_entries (work_package_id);
+
+-- HourlyRate: a user's rate in a project; DefaultHourlyRate: a user's rate
+-- in all other projects; CostRate: the price of a unit of a cost type
+CREATE TABLE IF NOT EXISTS rates (
+    id BIGSERIAL PRIMARY KEY,
+    type VARCHAR(255) NOT NULL,
+    rate DOUBLE PRECISION NOT NULL,
+    valid_from DATE NOT NULL,
+    user_id BIGINT REFERENCES users (id) ON DELETE CASCADE,
+    project_id BIGINT REFERENCES projects (id) ON DELETE CASCADE,
+    cost_type_id BIGINT REFERENCES cost_types (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_rates_on_user_id ON rates (user_id);
+
+-- Costs of a work package and its descendants, refreshed in the background
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS labor_costs DOUBLE PRECISION;
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS material_costs DOUBLE PRECISION;
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS overall_costs DOUBLE PRECISION;
@@ -0,0 +1,17 @@
+-- Responses of POST requests sent with an Idempotency-Key header, replayed
+-- when a client retries the request with the same key
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    id BIGSERIAL PRIMARY KEY,
+    key VARCHAR(255) NOT NULL,
+    user_id BIGINT NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    response_status INTEGER,
+    response_content_type VARCHAR(255),
+    response_body TEXT,
+    resource_id BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (key, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS index_idempotency_keys_on_created_at ON idempotency_keys (created_at);
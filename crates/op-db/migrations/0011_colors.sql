@@ -0,0 +1,9 @@
+-- Colors of statuses, types and priorities, referenced by their color_id
+
+CREATE TABLE IF NOT EXISTS colors (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    hexcode VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,27 @@
+-- Forums of projects with their topics and replies, as in OpenProject.
+-- Forums and topics cache their counts and latest message; message edits are
+-- journaled into message_journals.
+
+ALTER TABLE forums ADD COLUMN IF NOT EXISTS topics_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE forums ADD COLUMN IF NOT EXISTS messages_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE forums ADD COLUMN IF NOT EXISTS last_message_id BIGINT;
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS replies_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS last_reply_id BIGINT;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS locked BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS sticky INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS sticked_on TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS index_messages_on_forum_id ON messages (forum_id);
+CREATE INDEX IF NOT EXISTS index_messages_on_parent_id ON messages (parent_id);
+
+CREATE TABLE IF NOT EXISTS message_journals (
+    id BIGSERIAL PRIMARY KEY,
+    forum_id BIGINT NOT NULL,
+    parent_id BIGINT,
+    subject VARCHAR(255) NOT NULL DEFAULT '',
+    content TEXT,
+    author_id BIGINT,
+    locked BOOLEAN NOT NULL DEFAULT FALSE,
+    sticky INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,29 @@
+-- Boards, stored as grids of work package query widgets
+
+CREATE TABLE IF NOT EXISTS grids (
+    id BIGSERIAL PRIMARY KEY,
+    type VARCHAR(255) NOT NULL,
+    project_id BIGINT REFERENCES projects (id) ON DELETE CASCADE,
+    user_id BIGINT REFERENCES users (id),
+    name VARCHAR(255),
+    row_count INTEGER NOT NULL DEFAULT 1,
+    column_count INTEGER NOT NULL DEFAULT 1,
+    options JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_grids_on_project_id ON grids (project_id);
+
+CREATE TABLE IF NOT EXISTS grid_widgets (
+    id BIGSERIAL PRIMARY KEY,
+    grid_id BIGINT NOT NULL REFERENCES grids (id) ON DELETE CASCADE,
+    identifier VARCHAR(255) NOT NULL,
+    start_row INTEGER NOT NULL,
+    end_row INTEGER NOT NULL,
+    start_column INTEGER NOT NULL,
+    end_column INTEGER NOT NULL,
+    options JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS index_grid_widgets_on_grid_id ON grid_widgets (grid_id);
@@ -0,0 +1,10 @@
+-- Names, scopes and last use of API keys, stored as tokens of type
+-- Token::API. Keys without scopes, among them every key created before
+-- scopes existed, have the full power of their user.
+
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS name VARCHAR(255);
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS last_chars VARCHAR(16) NOT NULL DEFAULT '';
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS scopes TEXT[];
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS index_tokens_on_value ON tokens (value);
@@ -0,0 +1,7 @@
+-- Categories of documents, stored as enumerations of type DocumentCategory
+-- like priorities. Documents without one predate categories.
+
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS category_id BIGINT REFERENCES enumerations (id);
+
+CREATE INDEX IF NOT EXISTS index_documents_on_project_id ON documents (project_id);
+CREATE INDEX IF NOT EXISTS index_documents_on_category_id ON documents (category_id);
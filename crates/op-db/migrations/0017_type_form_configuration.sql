@@ -0,0 +1,4 @@
+-- Form configuration of work package types: attribute and query groups as
+-- JSON. Types without one use the default layout.
+
+ALTER TABLE types ADD COLUMN IF NOT EXISTS form_configuration JSONB;
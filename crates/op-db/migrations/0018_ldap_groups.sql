@@ -0,0 +1,15 @@
+-- Local groups mirroring the groups of an LDAP source. The entry id stays
+-- the same when the LDAP group is renamed or moved, the DN does not.
+
+CREATE TABLE IF NOT EXISTS ldap_groups_synchronized_groups (
+    id BIGSERIAL PRIMARY KEY,
+    group_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    ldap_source VARCHAR(255) NOT NULL,
+    entry_id VARCHAR(255) NOT NULL,
+    dn TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (ldap_source, entry_id)
+);
+
+CREATE INDEX IF NOT EXISTS index_member_roles_on_inherited_from ON member_roles (inherited_from);
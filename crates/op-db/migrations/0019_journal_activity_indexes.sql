@@ -0,0 +1,8 @@
+-- Latest activity of work packages and projects is the newest journal of
+-- their work packages, and "updated by" looks for journals of a user
+
+CREATE INDEX IF NOT EXISTS index_journals_on_journable_and_created_at
+    ON journals (journable_type, journable_id, created_at);
+CREATE INDEX IF NOT EXISTS index_journals_on_user_id_and_journable
+    ON journals (user_id, journable_type, journable_id);
+CREATE INDEX IF NOT EXISTS index_work_packages_on_project_id ON work_packages (project_id);
@@ -0,0 +1,18 @@
+-- Counts of work packages by project, type, status and priority for the
+-- overview widgets, kept by the writes of work packages. Work packages
+-- without a priority count under priority 0.
+
+CREATE TABLE IF NOT EXISTS work_package_summaries (
+    project_id BIGINT NOT NULL REFERENCES projects (id) ON DELETE CASCADE,
+    type_id BIGINT NOT NULL,
+    status_id BIGINT NOT NULL,
+    priority_id BIGINT NOT NULL DEFAULT 0,
+    count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (project_id, type_id, status_id, priority_id)
+);
+
+INSERT INTO work_package_summaries (project_id, type_id, status_id, priority_id, count)
+SELECT project_id, type_id, status_id, COALESCE(priority_id, 0), COUNT(*)
+FROM work_packages
+GROUP BY project_id, type_id, status_id, COALESCE(priority_id, 0)
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,17 @@
+-- Project custom fields are grouped in sections on the project overview and
+-- settings, ordered by the position of the section and then of the field
+
+CREATE TABLE IF NOT EXISTS custom_field_sections (
+    id BIGSERIAL PRIMARY KEY,
+    type VARCHAR(255) NOT NULL DEFAULT 'ProjectCustomFieldSection',
+    name VARCHAR(255) NOT NULL,
+    position INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE custom_fields
+    ADD COLUMN IF NOT EXISTS custom_field_section_id BIGINT REFERENCES custom_field_sections (id);
+
+CREATE INDEX IF NOT EXISTS index_custom_values_on_custom_field_and_value
+    ON custom_values (custom_field_id, customized_type, value);
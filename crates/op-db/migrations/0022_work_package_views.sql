@@ -0,0 +1,13 @@
+-- When users last opened work packages, to mark the ones changed since.
+-- Written when a work package is served to a user or its notifications are
+-- read, at most once a minute per user and work package.
+
+CREATE TABLE IF NOT EXISTS work_package_views (
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    work_package_id BIGINT NOT NULL REFERENCES work_packages (id) ON DELETE CASCADE,
+    viewed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, work_package_id)
+);
+
+CREATE INDEX IF NOT EXISTS index_work_package_views_on_work_package_id
+    ON work_package_views (work_package_id);
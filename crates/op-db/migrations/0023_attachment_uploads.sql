@@ -0,0 +1,20 @@
+-- Sessions of resumable uploads. The chunks are staged in the attachment
+-- storage; a session lists the ones received, in order, until it is
+-- completed into an attachment or purged as stale.
+
+CREATE TABLE IF NOT EXISTS attachment_uploads (
+    id VARCHAR(64) PRIMARY KEY,
+    author_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255),
+    description TEXT,
+    container_type VARCHAR(30),
+    container_id BIGINT,
+    filesize BIGINT NOT NULL,
+    digest_algorithm VARCHAR(20) NOT NULL,
+    chunks JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS index_attachment_uploads_on_updated_at ON attachment_uploads (updated_at);
@@ -0,0 +1,10 @@
+-- Deleted work packages go to the trash first: they keep their rows with
+-- the time and user of the deletion until they are restored or purged
+-- after the retention. Trashed work packages are left out of queries and
+-- summaries, so they count as absent.
+
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+ALTER TABLE work_packages ADD COLUMN IF NOT EXISTS deleted_by BIGINT REFERENCES users (id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS index_work_packages_on_deleted_at
+    ON work_packages (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,15 @@
+-- Custom actions: buttons on work packages applying a set of changes, shown
+-- where their conditions on status, type, project and role match. Both
+-- the conditions and the changes are stored as JSON.
+
+CREATE TABLE IF NOT EXISTS custom_actions (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    position INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE custom_actions ADD COLUMN IF NOT EXISTS conditions JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE custom_actions ADD COLUMN IF NOT EXISTS actions JSONB NOT NULL DEFAULT '[]';
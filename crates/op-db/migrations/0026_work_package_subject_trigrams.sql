@@ -0,0 +1,17 @@
+-- Work packages with subjects similar to the one being entered, suggested
+-- as possible duplicates while a work package is created. The trigram
+-- index serves the similarity operator `%`.
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- The extension may already be installed in a schema outside the search
+-- path, so the operator class is qualified with the schema it lives in
+DO $$
+BEGIN
+    EXECUTE format(
+        'CREATE INDEX IF NOT EXISTS index_work_packages_on_subject_trigrams '
+        'ON work_packages USING gin (subject %s.gin_trgm_ops)',
+        (SELECT extnamespace::regnamespace FROM pg_extension WHERE extname = 'pg_trgm')
+    );
+END
+$$;
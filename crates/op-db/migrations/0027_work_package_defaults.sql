@@ -0,0 +1,18 @@
+-- Sources of the defaults of new work packages: the workflows of a type,
+-- whose transitions start from the statuses its work packages may be
+-- created in, and the version a project files new work packages under.
+
+CREATE TABLE IF NOT EXISTS workflows (
+    id BIGSERIAL PRIMARY KEY,
+    type_id BIGINT NOT NULL REFERENCES types (id),
+    old_status_id BIGINT NOT NULL REFERENCES statuses (id),
+    new_status_id BIGINT NOT NULL REFERENCES statuses (id),
+    role_id BIGINT NOT NULL REFERENCES roles (id),
+    assignee BOOLEAN NOT NULL DEFAULT FALSE,
+    author BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS index_workflows_on_type_id_and_old_status_id
+    ON workflows (type_id, old_status_id);
+
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS default_version_id BIGINT;
@@ -266,7 +266,7 @@ impl Repository<ActivityRow, CreateActivityDto, UpdateActivityDto> for ActivityR
     async fn create(&self, dto: CreateActivityDto) -> Result<ActivityRow, RepositoryError> {
         // Validate name
         if dto.name.trim().is_empty() {
-            return Err(RepositoryError::Validation("Name can't be blank".to_string()));
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
         }
 
         // Check name uniqueness
@@ -327,7 +327,7 @@ impl Repository<ActivityRow, CreateActivityDto, UpdateActivityDto> for ActivityR
 
         // Validate name
         if name.trim().is_empty() {
-            return Err(RepositoryError::Validation("Name can't be blank".to_string()));
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
         }
 
         // Check name uniqueness
@@ -0,0 +1,298 @@
+//! API keys
+//!
+//! Mirrors: app/models/token/api.rb
+//!
+//! API keys are tokens of type `Token::API`. Only the hash of a key is
+//! stored, along with its last characters for telling keys apart, so the
+//! key itself is known to its user alone. Keys may be restricted to scopes;
+//! keys without scopes have the full power of their user.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+
+/// Token type of API keys
+pub const API_KEY_TOKEN_TYPE: &str = "Token::API";
+
+/// Stored API key
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct ApiKeyRow {
+    pub id: Id,
+    pub user_id: Id,
+    pub name: Option<String>,
+    /// Hash of the key
+    pub value: String,
+    pub last_chars: String,
+    /// Scopes the key is restricted to, `None` for the full power of its user
+    pub scopes: Option<Vec<String>>,
+    pub expires_on: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// API key to store
+#[derive(Debug, Clone)]
+pub struct CreateApiKeyDto {
+    pub user_id: Id,
+    pub name: Option<String>,
+    /// Hash of the key
+    pub value: String,
+    pub last_chars: String,
+    pub scopes: Option<Vec<String>>,
+    pub expires_on: Option<DateTime<Utc>>,
+}
+
+/// Store of API keys
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    /// Store a new key
+    async fn create(&self, dto: CreateApiKeyDto) -> RepositoryResult<ApiKeyRow>;
+
+    /// Key with the hash
+    async fn find_by_value(&self, value: &str) -> RepositoryResult<Option<ApiKeyRow>>;
+
+    /// Keys of a user, oldest first
+    async fn find_by_user(&self, user_id: Id) -> RepositoryResult<Vec<ApiKeyRow>>;
+
+    /// Delete a key of the user; false if the user has no such key
+    async fn revoke(&self, user_id: Id, id: Id) -> RepositoryResult<bool>;
+
+    /// Record that a key was used
+    async fn touch(&self, id: Id) -> RepositoryResult<()>;
+}
+
+const API_KEY_COLUMNS: &str =
+    "id, user_id, name, value, last_chars, scopes, expires_on, created_at, last_used_at";
+
+/// API keys in the `tokens` table
+pub struct ApiKeyRepository {
+    db: DbExecutor,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for ApiKeyRepository {
+    async fn create(&self, dto: CreateApiKeyDto) -> RepositoryResult<ApiKeyRow> {
+        let row = sqlx::query_as::<_, ApiKeyRow>(&format!(
+            r#"
+            INSERT INTO tokens (user_id, type, name, value, last_chars, scopes, expires_on)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING {}
+            "#,
+            API_KEY_COLUMNS
+        ))
+        .bind(dto.user_id)
+        .bind(API_KEY_TOKEN_TYPE)
+        .bind(&dto.name)
+        .bind(&dto.value)
+        .bind(&dto.last_chars)
+        .bind(&dto.scopes)
+        .bind(dto.expires_on)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn find_by_value(&self, value: &str) -> RepositoryResult<Option<ApiKeyRow>> {
+        let row = sqlx::query_as::<_, ApiKeyRow>(&format!(
+            "SELECT {} FROM tokens WHERE type = $1 AND value = $2",
+            API_KEY_COLUMNS
+        ))
+        .bind(API_KEY_TOKEN_TYPE)
+        .bind(value)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn find_by_user(&self, user_id: Id) -> RepositoryResult<Vec<ApiKeyRow>> {
+        let rows = sqlx::query_as::<_, ApiKeyRow>(&format!(
+            "SELECT {} FROM tokens WHERE type = $1 AND user_id = $2 ORDER BY created_at, id",
+            API_KEY_COLUMNS
+        ))
+        .bind(API_KEY_TOKEN_TYPE)
+        .bind(user_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn revoke(&self, user_id: Id, id: Id) -> RepositoryResult<bool> {
+        let result = sqlx::query("DELETE FROM tokens WHERE type = $1 AND user_id = $2 AND id = $3")
+            .bind(API_KEY_TOKEN_TYPE)
+            .bind(user_id)
+            .bind(id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn touch(&self, id: Id) -> RepositoryResult<()> {
+        sqlx::query("UPDATE tokens SET last_used_at = NOW() WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// API keys of a single process
+#[derive(Default)]
+pub struct MemoryApiKeyStore {
+    keys: Mutex<HashMap<Id, ApiKeyRow>>,
+}
+
+impl MemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for MemoryApiKeyStore {
+    async fn create(&self, dto: CreateApiKeyDto) -> RepositoryResult<ApiKeyRow> {
+        let mut keys = self.keys.lock().unwrap();
+        let id = keys.keys().max().copied().unwrap_or(0) + 1;
+        let row = ApiKeyRow {
+            id,
+            user_id: dto.user_id,
+            name: dto.name,
+            value: dto.value,
+            last_chars: dto.last_chars,
+            scopes: dto.scopes,
+            expires_on: dto.expires_on,
+            created_at: Utc::now(),
+            last_used_at: None,
+        };
+        keys.insert(id, row.clone());
+        Ok(row)
+    }
+
+    async fn find_by_value(&self, value: &str) -> RepositoryResult<Option<ApiKeyRow>> {
+        let keys = self.keys.lock().unwrap();
+        Ok(keys.values().find(|key| key.value == value).cloned())
+    }
+
+    async fn find_by_user(&self, user_id: Id) -> RepositoryResult<Vec<ApiKeyRow>> {
+        let keys = self.keys.lock().unwrap();
+        let mut rows: Vec<ApiKeyRow> = keys.values().filter(|key| key.user_id == user_id).cloned().collect();
+        rows.sort_by_key(|key| (key.created_at, key.id));
+        Ok(rows)
+    }
+
+    async fn revoke(&self, user_id: Id, id: Id) -> RepositoryResult<bool> {
+        let mut keys = self.keys.lock().unwrap();
+        if keys.get(&id).is_some_and(|key| key.user_id == user_id) {
+            keys.remove(&id);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn touch(&self, id: Id) -> RepositoryResult<()> {
+        if let Some(key) = self.keys.lock().unwrap().get_mut(&id) {
+            key.last_used_at = Some(Utc::now());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dto(user_id: Id, value: &str) -> CreateApiKeyDto {
+        CreateApiKeyDto {
+            user_id,
+            name: None,
+            value: value.to_string(),
+            last_chars: "****abcd".to_string(),
+            scopes: Some(vec!["read_only".to_string()]),
+            expires_on: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_revoked_by_their_user() {
+        let store = MemoryApiKeyStore::new();
+        let mine = store.create(dto(1, "hash-1")).await.unwrap();
+        let theirs = store.create(dto(2, "hash-2")).await.unwrap();
+
+        assert_eq!(store.find_by_value("hash-1").await.unwrap(), Some(mine.clone()));
+        assert_eq!(store.find_by_user(1).await.unwrap(), vec![mine.clone()]);
+
+        assert!(!store.revoke(1, theirs.id).await.unwrap());
+        assert!(store.revoke(1, mine.id).await.unwrap());
+        assert_eq!(store.find_by_value("hash-1").await.unwrap(), None);
+        assert!(store.find_by_value("hash-2").await.unwrap().is_some());
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{TestDb, UserFixture};
+
+    #[tokio::test]
+    async fn test_api_keys_are_stored_as_tokens() {
+        let db = TestDb::connect().await;
+        let repo = db.api_keys();
+        let user = db.insert_user(UserFixture::new("keyholder")).await;
+
+        let scoped = repo
+            .create(CreateApiKeyDto {
+                user_id: user,
+                name: Some("CI".to_string()),
+                value: "hash-scoped".to_string(),
+                last_chars: "****wxyz".to_string(),
+                scopes: Some(vec!["read_only".to_string(), "time_entries:write".to_string()]),
+                expires_on: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(scoped.scopes.as_deref(), Some(&["read_only".to_string(), "time_entries:write".to_string()][..]));
+        assert_eq!(scoped.last_used_at, None);
+
+        // Keys from before scopes existed have none
+        sqlx::query("INSERT INTO tokens (user_id, type, value) VALUES ($1, $2, 'hash-legacy')")
+            .bind(user)
+            .bind(API_KEY_TOKEN_TYPE)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        let legacy = repo.find_by_value("hash-legacy").await.unwrap().unwrap();
+        assert_eq!(legacy.scopes, None);
+
+        repo.touch(scoped.id).await.unwrap();
+        let keys = repo.find_by_user(user).await.unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys[0].last_used_at.is_some());
+
+        assert!(!repo.revoke(user + 1, scoped.id).await.unwrap());
+        assert!(repo.revoke(user, scoped.id).await.unwrap());
+        assert_eq!(repo.find_by_value("hash-scoped").await.unwrap(), None);
+    }
+}
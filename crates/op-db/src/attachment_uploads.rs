@@ -0,0 +1,189 @@
+//! Attachment uploads repository
+//!
+//! Table: attachment_uploads
+//!
+//! Sessions of resumable uploads and the chunks received so far. The data
+//! of the chunks is staged in the attachment storage, not in the database.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+
+/// Chunk of an upload, in the order received
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadChunkRow {
+    pub number: i32,
+    pub size: i64,
+    pub digest: String,
+}
+
+/// Upload session row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct AttachmentUploadRow {
+    pub id: String,
+    pub author_id: Id,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub description: Option<String>,
+    pub container_type: Option<String>,
+    pub container_id: Option<Id>,
+    /// Size the complete file is expected to have
+    pub filesize: i64,
+    pub digest_algorithm: String,
+    pub chunks: Json<Vec<UploadChunkRow>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// DTO for starting an upload
+#[derive(Debug, Clone)]
+pub struct CreateAttachmentUploadDto {
+    pub id: String,
+    pub author_id: Id,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub description: Option<String>,
+    pub container_type: Option<String>,
+    pub container_id: Option<Id>,
+    pub filesize: i64,
+    pub digest_algorithm: String,
+}
+
+/// Attachment uploads repository
+pub struct AttachmentUploadRepository {
+    db: DbExecutor,
+}
+
+impl AttachmentUploadRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(&self, dto: CreateAttachmentUploadDto) -> RepositoryResult<AttachmentUploadRow> {
+        let row = sqlx::query_as::<_, AttachmentUploadRow>(
+            r#"
+            INSERT INTO attachment_uploads
+                (id, author_id, filename, content_type, description, container_type, container_id,
+                 filesize, digest_algorithm)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING *
+            "#,
+        )
+        .bind(&dto.id)
+        .bind(dto.author_id)
+        .bind(&dto.filename)
+        .bind(&dto.content_type)
+        .bind(&dto.description)
+        .bind(&dto.container_type)
+        .bind(dto.container_id)
+        .bind(dto.filesize)
+        .bind(&dto.digest_algorithm)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn find(&self, id: &str) -> RepositoryResult<Option<AttachmentUploadRow>> {
+        let row = sqlx::query_as::<_, AttachmentUploadRow>("SELECT * FROM attachment_uploads WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(row)
+    }
+
+    /// Append a chunk, provided the upload has `received` chunks so far;
+    /// whether it was appended, which fails when another request appended
+    /// a chunk in the meantime
+    pub async fn append_chunk(&self, id: &str, received: usize, chunk: &UploadChunkRow) -> RepositoryResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE attachment_uploads
+            SET chunks = chunks || $3, updated_at = NOW()
+            WHERE id = $1 AND jsonb_array_length(chunks) = $2
+            "#,
+        )
+        .bind(id)
+        .bind(received as i32)
+        .bind(Json(vec![chunk]))
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete(&self, id: &str) -> RepositoryResult<()> {
+        sqlx::query("DELETE FROM attachment_uploads WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Uploads that received no chunk since `before`
+    pub async fn find_stale(&self, before: DateTime<Utc>) -> RepositoryResult<Vec<AttachmentUploadRow>> {
+        let rows = sqlx::query_as::<_, AttachmentUploadRow>(
+            "SELECT * FROM attachment_uploads WHERE updated_at < $1 ORDER BY updated_at",
+        )
+        .bind(before)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{TestDb, UserFixture};
+
+    #[tokio::test]
+    async fn test_chunks_are_appended_in_sequence() {
+        let db = TestDb::connect().await;
+        let uploads = db.attachment_uploads();
+        let author = db.insert_user(UserFixture::new("uploader")).await;
+        let upload = uploads
+            .create(CreateAttachmentUploadDto {
+                id: "upload-1".into(),
+                author_id: author,
+                filename: "model.ifc".into(),
+                content_type: None,
+                description: None,
+                container_type: None,
+                container_id: None,
+                filesize: 10,
+                digest_algorithm: "sha256".into(),
+            })
+            .await
+            .unwrap();
+        assert!(upload.chunks.0.is_empty());
+
+        let chunk = |number: i32| UploadChunkRow { number, size: 5, digest: format!("digest-{}", number) };
+        assert!(uploads.append_chunk("upload-1", 0, &chunk(0)).await.unwrap());
+        // A request racing with the first one expected no chunks either
+        assert!(!uploads.append_chunk("upload-1", 0, &chunk(0)).await.unwrap());
+        assert!(uploads.append_chunk("upload-1", 1, &chunk(1)).await.unwrap());
+
+        let found = uploads.find("upload-1").await.unwrap().unwrap();
+        assert_eq!(found.chunks.0, vec![chunk(0), chunk(1)]);
+        assert_eq!(uploads.find_stale(found.updated_at).await.unwrap().len(), 0);
+        assert_eq!(uploads.find_stale(Utc::now() + chrono::Duration::minutes(1)).await.unwrap().len(), 1);
+
+        uploads.delete("upload-1").await.unwrap();
+        assert!(uploads.find("upload-1").await.unwrap().is_none());
+    }
+}
@@ -7,6 +7,8 @@ use chrono::{DateTime, Utc};
 use sqlx::{FromRow, PgPool};
 
 use crate::{Pagination, PaginatedResult, Repository, RepositoryError};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
 
 /// Attachment status enum
 pub mod status {
@@ -39,6 +41,28 @@ pub mod status {
     }
 }
 
+/// Containers of project `$1` that can hold attachments, as a CTE
+const PROJECT_CONTAINERS: &str = r#"
+    project_containers (container_type, container_id) AS (
+        SELECT 'Project', $1::BIGINT
+        UNION ALL SELECT 'WorkPackage', id FROM work_packages WHERE project_id = $1
+        UNION ALL SELECT 'WikiPage', p.id FROM wiki_pages p JOIN wikis w ON w.id = p.wiki_id
+                  WHERE w.project_id = $1
+        UNION ALL SELECT 'Document', id FROM documents WHERE project_id = $1
+        UNION ALL SELECT 'News', id FROM news WHERE project_id = $1
+        UNION ALL SELECT 'Message', m.id FROM messages m JOIN forums f ON f.id = m.forum_id
+                  WHERE f.project_id = $1
+        UNION ALL SELECT 'Version', id FROM versions WHERE project_id = $1
+        UNION ALL SELECT 'Meeting', id FROM meetings WHERE project_id = $1
+    )
+"#;
+
+/// Attachments whose work package is gone are unattached, so the cleanup
+/// of uncontainered attachments removes them along with their files
+pub const ATTACHMENT_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::polymorphic("attachments", "container_id", "container_type", "WorkPackage", "work_packages", OrphanAction::Nullify),
+];
+
 /// Attachment row from database
 #[derive(Debug, Clone, FromRow)]
 pub struct AttachmentRow {
@@ -120,6 +144,9 @@ pub struct CreateAttachmentDto {
 pub struct UpdateAttachmentDto {
     pub container_id: Option<Option<i64>>,
     pub container_type: Option<Option<String>>,
+    /// New name of the file; it keeps its disk filename
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
     pub description: Option<Option<String>>,
     pub status: Option<i32>,
 }
@@ -172,6 +199,7 @@ impl AttachmentRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -228,6 +256,7 @@ impl AttachmentRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -260,6 +289,94 @@ impl AttachmentRepository {
         Ok(())
     }
 
+    /// Total size of all attachments in bytes
+    pub async fn total_size(&self) -> Result<i64, RepositoryError> {
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COALESCE(SUM(filesize), 0)::BIGINT FROM attachments",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(total)
+    }
+
+    /// Total size in bytes of the attachments of a project's containers
+    pub async fn total_size_for_project(&self, project_id: i64) -> Result<i64, RepositoryError> {
+        let total = sqlx::query_scalar::<_, i64>(&format!(
+            r#"
+            WITH {}
+            SELECT COALESCE(SUM(a.filesize), 0)::BIGINT
+            FROM attachments a
+            JOIN project_containers c
+              ON c.container_type = a.container_type AND c.container_id = a.container_id
+            "#,
+            PROJECT_CONTAINERS
+        ))
+        .bind(project_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(total)
+    }
+
+    /// Size in bytes of a project's attachments per container type
+    pub async fn usage_by_container_type(
+        &self,
+        project_id: i64,
+    ) -> Result<Vec<(String, i64)>, RepositoryError> {
+        let rows = sqlx::query_as::<_, (String, i64)>(&format!(
+            r#"
+            WITH {}
+            SELECT a.container_type, COALESCE(SUM(a.filesize), 0)::BIGINT
+            FROM attachments a
+            JOIN project_containers c
+              ON c.container_type = a.container_type AND c.container_id = a.container_id
+            GROUP BY a.container_type
+            ORDER BY a.container_type
+            "#,
+            PROJECT_CONTAINERS
+        ))
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Project a container belongs to, if any
+    pub async fn project_for_container(
+        &self,
+        container_type: &str,
+        container_id: i64,
+    ) -> Result<Option<i64>, RepositoryError> {
+        let project_id = sqlx::query_scalar::<_, Option<i64>>(
+            r#"
+            SELECT CASE $1
+                WHEN 'Project' THEN (SELECT id FROM projects WHERE id = $2)
+                WHEN 'WorkPackage' THEN (SELECT project_id FROM work_packages WHERE id = $2)
+                WHEN 'WikiPage' THEN (
+                    SELECT w.project_id FROM wiki_pages p JOIN wikis w ON w.id = p.wiki_id
+                    WHERE p.id = $2
+                )
+                WHEN 'Document' THEN (SELECT project_id FROM documents WHERE id = $2)
+                WHEN 'News' THEN (SELECT project_id FROM news WHERE id = $2)
+                WHEN 'Message' THEN (
+                    SELECT f.project_id FROM messages m JOIN forums f ON f.id = m.forum_id
+                    WHERE m.id = $2
+                )
+                WHEN 'Version' THEN (SELECT project_id FROM versions WHERE id = $2)
+                WHEN 'Meeting' THEN (SELECT project_id FROM meetings WHERE id = $2)
+            END
+            "#,
+        )
+        .bind(container_type)
+        .bind(container_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(project_id)
+    }
+
     /// Find orphaned attachments (no container)
     pub async fn find_orphaned(&self) -> Result<Vec<AttachmentRow>, RepositoryError> {
         let rows = sqlx::query_as::<_, AttachmentRow>(
@@ -338,15 +455,11 @@ impl Repository<AttachmentRow, CreateAttachmentDto, UpdateAttachmentDto> for Att
     async fn create(&self, dto: CreateAttachmentDto) -> Result<AttachmentRow, RepositoryError> {
         // Validate required fields
         if dto.filename.is_empty() {
-            return Err(RepositoryError::Validation(
-                "Filename can't be blank".to_string(),
-            ));
+            return Err(RepositoryError::invalid("filename", "blank", "can't be blank"));
         }
 
         if dto.content_type.is_empty() {
-            return Err(RepositoryError::Validation(
-                "Content type can't be blank".to_string(),
-            ));
+            return Err(RepositoryError::invalid("content_type", "blank", "can't be blank"));
         }
 
         let status = dto.status.unwrap_or(status::UPLOADED);
@@ -397,6 +510,13 @@ impl Repository<AttachmentRow, CreateAttachmentDto, UpdateAttachmentDto> for Att
             None => existing.container_type,
         };
 
+        if dto.filename.as_ref().is_some_and(|f| f.trim().is_empty()) {
+            return Err(RepositoryError::invalid("filename", "blank", "can't be blank"));
+        }
+
+        let new_filename = dto.filename.or(existing.filename);
+        let new_content_type = dto.content_type.or(existing.content_type);
+
         let new_description = match dto.description {
             Some(d) => d,
             None => existing.description,
@@ -408,7 +528,7 @@ impl Repository<AttachmentRow, CreateAttachmentDto, UpdateAttachmentDto> for Att
             r#"
             UPDATE attachments
             SET container_id = $2, container_type = $3, description = $4,
-                status = $5, updated_at = NOW()
+                status = $5, filename = $6, content_type = $7, updated_at = NOW()
             WHERE id = $1
             RETURNING id, container_id, container_type, filename, disk_filename,
                       filesize, content_type, digest, downloads, author_id,
@@ -420,6 +540,8 @@ impl Repository<AttachmentRow, CreateAttachmentDto, UpdateAttachmentDto> for Att
         .bind(&new_container_type)
         .bind(&new_description)
         .bind(new_status)
+        .bind(&new_filename)
+        .bind(&new_content_type)
         .fetch_one(&self.pool)
         .await?;
 
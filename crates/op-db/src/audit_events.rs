@@ -0,0 +1,132 @@
+//! Audit events repository
+//!
+//! Database-backed `AuditSink` storing security events in `audit_events`.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::audit::{AuditError, AuditEvent, AuditEventType, AuditFilter, AuditResult, AuditSink};
+use sqlx::{FromRow, PgPool};
+
+/// Audit event row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct AuditEventRow {
+    pub id: i64,
+    pub event_type: String,
+    pub actor_id: Option<i64>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i64>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: serde_json::Value,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl AuditEventRow {
+    /// Convert into a domain event; unknown event types are skipped
+    pub fn into_event(self) -> Option<AuditEvent> {
+        let event_type = AuditEventType::parse(&self.event_type)?;
+        Some(AuditEvent {
+            id: Some(self.id),
+            event_type,
+            actor_id: self.actor_id,
+            target_type: self.target_type,
+            target_id: self.target_id,
+            ip_address: self.ip_address,
+            user_agent: self.user_agent,
+            details: self.details,
+            occurred_at: self.occurred_at,
+        })
+    }
+}
+
+/// Audit event repository
+pub struct AuditEventRepository {
+    pool: PgPool,
+}
+
+impl AuditEventRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditSink for AuditEventRepository {
+    async fn record(&self, event: &AuditEvent) -> AuditResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_events (event_type, actor_id, target_type, target_id,
+                                      ip_address, user_agent, details, occurred_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(event.event_type.as_str())
+        .bind(event.actor_id)
+        .bind(&event.target_type)
+        .bind(event.target_id)
+        .bind(&event.ip_address)
+        .bind(&event.user_agent)
+        .bind(&event.details)
+        .bind(event.occurred_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AuditError::Storage(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> AuditResult<Vec<AuditEvent>> {
+        let rows = sqlx::query_as::<_, AuditEventRow>(
+            r#"
+            SELECT id, event_type, actor_id, target_type, target_id,
+                   ip_address, user_agent, details, occurred_at
+            FROM audit_events
+            WHERE ($1::bigint IS NULL OR actor_id = $1)
+              AND ($2::varchar IS NULL OR event_type = $2)
+              AND ($3::timestamptz IS NULL OR occurred_at >= $3)
+              AND ($4::timestamptz IS NULL OR occurred_at <= $4)
+            ORDER BY occurred_at DESC, id DESC
+            LIMIT $5 OFFSET $6
+            "#,
+        )
+        .bind(filter.actor_id)
+        .bind(filter.event_type.map(|t| t.as_str()))
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.limit as i64)
+        .bind(filter.offset as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AuditError::Storage(e.to_string()))?;
+
+        Ok(rows.into_iter().filter_map(AuditEventRow::into_event).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(event_type: &str) -> AuditEventRow {
+        AuditEventRow {
+            id: 1,
+            event_type: event_type.into(),
+            actor_id: Some(1),
+            target_type: Some("User".into()),
+            target_id: Some(2),
+            ip_address: Some("10.0.0.1".into()),
+            user_agent: None,
+            details: serde_json::json!({}),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_row_into_event() {
+        let event = row("user_locked").into_event().unwrap();
+        assert_eq!(event.event_type, AuditEventType::UserLocked);
+        assert_eq!(event.target_id, Some(2));
+
+        assert!(row("something_else").into_event().is_none());
+    }
+}
@@ -0,0 +1,197 @@
+//! Boards repository
+//!
+//! Mirrors:
+//! - modules/boards/app/models/boards/grid.rb
+//! - app/models/grids/widget.rb
+//!
+//! A board is a grid of the project with one row of columns. Each column is
+//! a `work_package_query` widget showing the work packages of a saved query;
+//! the board's options tell how moving cards between columns changes them,
+//! e.g. `{"type": "action", "attribute": "status"}`.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::repository::RepositoryResult;
+
+/// Grid type of boards
+pub const BOARD_GRID_TYPE: &str = "Boards::Grid";
+
+/// Widget identifier of board columns
+pub const QUERY_WIDGET: &str = "work_package_query";
+
+/// Board row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct BoardRow {
+    pub id: Id,
+    pub project_id: Id,
+    pub user_id: Option<Id>,
+    pub name: String,
+    pub column_count: i32,
+    pub options: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Column of a board, showing the work packages of its query
+#[derive(Debug, Clone, FromRow)]
+pub struct BoardColumnRow {
+    pub id: Id,
+    pub grid_id: Id,
+    pub start_column: i32,
+    pub options: serde_json::Value,
+}
+
+impl BoardColumnRow {
+    /// Saved query of the column
+    pub fn query_id(&self) -> Option<Id> {
+        self.options.get("queryId").and_then(|id| id.as_i64())
+    }
+}
+
+/// DTO for creating a board
+#[derive(Debug, Clone)]
+pub struct CreateBoardDto {
+    pub project_id: Id,
+    pub user_id: Option<Id>,
+    pub name: String,
+    pub options: serde_json::Value,
+    /// Queries of the columns, from left to right
+    pub query_ids: Vec<Id>,
+}
+
+/// Board repository
+pub struct BoardRepository {
+    db: DbExecutor,
+}
+
+impl BoardRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Boards of a project by name
+    pub async fn find_by_project(&self, project_id: Id) -> RepositoryResult<Vec<BoardRow>> {
+        let rows = sqlx::query_as::<_, BoardRow>(
+            r#"
+            SELECT id, project_id, user_id, COALESCE(name, '') AS name, column_count, options,
+                   created_at, updated_at
+            FROM grids
+            WHERE type = $1 AND project_id = $2
+            ORDER BY name, id
+            "#,
+        )
+        .bind(BOARD_GRID_TYPE)
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Columns of a board from left to right
+    pub async fn find_columns(&self, board_id: Id) -> RepositoryResult<Vec<BoardColumnRow>> {
+        let rows = sqlx::query_as::<_, BoardColumnRow>(
+            r#"
+            SELECT id, grid_id, start_column, options
+            FROM grid_widgets
+            WHERE grid_id = $1 AND identifier = $2
+            ORDER BY start_column, id
+            "#,
+        )
+        .bind(board_id)
+        .bind(QUERY_WIDGET)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Create a board with a column per query
+    pub async fn create(&self, dto: CreateBoardDto) -> RepositoryResult<BoardRow> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let board = sqlx::query_as::<_, BoardRow>(
+            r#"
+            INSERT INTO grids (type, project_id, user_id, name, row_count, column_count, options,
+                               created_at, updated_at)
+            VALUES ($1, $2, $3, $4, 1, $5, $6, NOW(), NOW())
+            RETURNING id, project_id, user_id, COALESCE(name, '') AS name, column_count, options,
+                      created_at, updated_at
+            "#,
+        )
+        .bind(BOARD_GRID_TYPE)
+        .bind(dto.project_id)
+        .bind(dto.user_id)
+        .bind(&dto.name)
+        .bind(dto.query_ids.len().max(1) as i32)
+        .bind(&dto.options)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for (column, query_id) in (1..).zip(&dto.query_ids) {
+            sqlx::query(
+                r#"
+                INSERT INTO grid_widgets (grid_id, identifier, start_row, end_row, start_column,
+                                          end_column, options)
+                VALUES ($1, $2, 1, 2, $3, $4, $5)
+                "#,
+            )
+            .bind(board.id)
+            .bind(QUERY_WIDGET)
+            .bind(column)
+            .bind(column + 1)
+            .bind(serde_json::json!({ "queryId": query_id }))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(board)
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture};
+
+    #[tokio::test]
+    async fn test_board_columns_keep_their_order() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("boarder")).await;
+        let project = db.insert_project(ProjectFixture::new("boards")).await;
+        let new = db.insert_query(user, Some(project), "New").await;
+        let done = db.insert_query(user, Some(project), "Done").await;
+
+        let boards = BoardRepository::with_executor(db.executor());
+        let board = boards
+            .create(CreateBoardDto {
+                project_id: project,
+                user_id: Some(user),
+                name: "Kanban".into(),
+                options: serde_json::json!({ "type": "action", "attribute": "status" }),
+                query_ids: vec![new, done],
+            })
+            .await
+            .unwrap();
+        assert_eq!(board.column_count, 2);
+        assert_eq!(board.options["attribute"], "status");
+
+        let listed = boards.find_by_project(project).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "Kanban");
+
+        let columns = boards.find_columns(board.id).await.unwrap();
+        let query_ids: Vec<_> = columns.iter().map(BoardColumnRow::query_id).collect();
+        assert_eq!(query_ids, vec![Some(new), Some(done)]);
+    }
+}
@@ -77,6 +77,7 @@ impl CategoryRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -188,7 +189,7 @@ impl Repository<CategoryRow, CreateCategoryDto, UpdateCategoryDto> for CategoryR
     async fn create(&self, dto: CreateCategoryDto) -> Result<CategoryRow, RepositoryError> {
         // Validate name
         if dto.name.trim().is_empty() {
-            return Err(RepositoryError::Validation("Name can't be blank".to_string()));
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
         }
 
         // Check name uniqueness
@@ -225,7 +226,7 @@ impl Repository<CategoryRow, CreateCategoryDto, UpdateCategoryDto> for CategoryR
 
         // Validate name
         if name.trim().is_empty() {
-            return Err(RepositoryError::Validation("Name can't be blank".to_string()));
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
         }
 
         // Check name uniqueness
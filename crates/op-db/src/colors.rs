@@ -0,0 +1,74 @@
+//! Colors repository
+//!
+//! Mirrors: app/models/color.rb
+//!
+//! Statuses, types and priorities reference their color by `color_id`.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+
+/// Color row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct ColorRow {
+    pub id: Id,
+    pub name: String,
+    pub hexcode: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Color repository
+pub struct ColorRepository {
+    db: DbExecutor,
+}
+
+impl ColorRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Find a color by name, ignoring case
+    pub async fn find_by_name(&self, name: &str) -> RepositoryResult<Option<ColorRow>> {
+        let row = sqlx::query_as::<_, ColorRow>(
+            r#"
+            SELECT id, name, hexcode, created_at, updated_at
+            FROM colors
+            WHERE LOWER(name) = LOWER($1)
+            ORDER BY id
+            LIMIT 1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Create a color from its name and hex code, e.g. `#1A67A3`
+    pub async fn create(&self, name: &str, hexcode: &str) -> RepositoryResult<ColorRow> {
+        let row = sqlx::query_as::<_, ColorRow>(
+            r#"
+            INSERT INTO colors (name, hexcode, created_at, updated_at)
+            VALUES ($1, $2, NOW(), NOW())
+            RETURNING id, name, hexcode, created_at, updated_at
+            "#,
+        )
+        .bind(name)
+        .bind(hexcode.to_uppercase())
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+}
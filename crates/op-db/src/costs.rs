@@ -0,0 +1,475 @@
+//! Costs repository
+//!
+//! Mirrors:
+//! - modules/costs/app/models/cost_type.rb
+//! - modules/costs/app/models/cost_entry.rb
+//! - modules/costs/app/models/rate.rb and its subclasses
+//!
+//! Work packages cost the time logged on them, priced by the hourly rates
+//! of the users, plus the units of cost types booked on them, priced by the
+//! unit rates. The totals of a work package and its descendants are kept
+//! in `work_packages.labor_costs`, `material_costs` and `overall_costs`.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::{RepositoryError, RepositoryResult};
+use crate::time_entries::TimeEntryRow;
+
+/// `rates.type` values
+pub mod rate_type {
+    /// Rate of a user in one project
+    pub const HOURLY: &str = "HourlyRate";
+    /// Rate of a user in projects without a project rate
+    pub const DEFAULT_HOURLY: &str = "DefaultHourlyRate";
+    /// Price of a unit of a cost type
+    pub const COST: &str = "CostRate";
+}
+
+const COST_ENTRY_COLUMNS: &str = "id, user_id, project_id, work_package_id, cost_type_id, units, spent_on, comments, \
+     overridden_costs, costs, rate_id, logged_by_id, created_at, updated_at";
+
+const RATE_COLUMNS: &str = "id, type AS rate_type, rate, valid_from, user_id, project_id, cost_type_id";
+
+/// Cost type row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct CostTypeRow {
+    pub id: Id,
+    pub name: String,
+    pub unit: String,
+    pub unit_plural: String,
+    pub is_default: bool,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Cost entry row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct CostEntryRow {
+    pub id: Id,
+    pub user_id: Id,
+    pub project_id: Id,
+    pub work_package_id: Option<Id>,
+    pub cost_type_id: Id,
+    pub units: f64,
+    pub spent_on: NaiveDate,
+    pub comments: Option<String>,
+    /// Costs set by hand, taking precedence over the priced units
+    pub overridden_costs: Option<f64>,
+    pub costs: Option<f64>,
+    pub rate_id: Option<Id>,
+    pub logged_by_id: Option<Id>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Rate row from database
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct RateRow {
+    pub id: Id,
+    /// One of [`rate_type`]
+    pub rate_type: String,
+    pub rate: f64,
+    /// First day the rate applies on; it applies until a later rate does
+    pub valid_from: NaiveDate,
+    pub user_id: Option<Id>,
+    pub project_id: Option<Id>,
+    pub cost_type_id: Option<Id>,
+}
+
+/// Cached costs of a work package and its descendants
+#[derive(Debug, Clone, Copy, PartialEq, Default, FromRow)]
+pub struct WorkPackageCostsRow {
+    pub labor_costs: f64,
+    pub material_costs: f64,
+    pub overall_costs: f64,
+}
+
+/// DTO for creating a cost type
+#[derive(Debug, Clone)]
+pub struct CreateCostTypeDto {
+    pub name: String,
+    pub unit: String,
+    pub unit_plural: String,
+    pub is_default: bool,
+}
+
+/// DTO for creating a cost entry
+#[derive(Debug, Clone)]
+pub struct CreateCostEntryDto {
+    pub user_id: Id,
+    pub project_id: Id,
+    pub work_package_id: Option<Id>,
+    pub cost_type_id: Id,
+    pub units: f64,
+    pub spent_on: NaiveDate,
+    pub comments: Option<String>,
+    pub overridden_costs: Option<f64>,
+    pub logged_by_id: Option<Id>,
+}
+
+/// DTO for creating a rate
+#[derive(Debug, Clone)]
+pub struct CreateRateDto {
+    /// One of [`rate_type`]
+    pub rate_type: String,
+    pub rate: f64,
+    pub valid_from: NaiveDate,
+    pub user_id: Option<Id>,
+    pub project_id: Option<Id>,
+    pub cost_type_id: Option<Id>,
+}
+
+/// Costs repository
+pub struct CostRepository {
+    db: DbExecutor,
+}
+
+impl CostRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    pub async fn create_cost_type(&self, dto: CreateCostTypeDto) -> RepositoryResult<CostTypeRow> {
+        if dto.name.trim().is_empty() {
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
+        }
+
+        let row = sqlx::query_as::<_, CostTypeRow>(
+            r#"
+            INSERT INTO cost_types (name, unit, unit_plural, is_default, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            RETURNING id, name, unit, unit_plural, is_default, deleted_at
+            "#,
+        )
+        .bind(dto.name.trim())
+        .bind(&dto.unit)
+        .bind(&dto.unit_plural)
+        .bind(dto.is_default)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn create_cost_entry(&self, dto: CreateCostEntryDto) -> RepositoryResult<CostEntryRow> {
+        if dto.units < 0.0 {
+            return Err(RepositoryError::invalid(
+                "units",
+                "greater_than_or_equal_to",
+                "must be greater than or equal to 0",
+            ));
+        }
+
+        let row = sqlx::query_as::<_, CostEntryRow>(&format!(
+            r#"
+            INSERT INTO cost_entries (user_id, project_id, work_package_id, cost_type_id, units, spent_on,
+                                      comments, overridden_costs, logged_by_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, NOW(), NOW())
+            RETURNING {}
+            "#,
+            COST_ENTRY_COLUMNS
+        ))
+        .bind(dto.user_id)
+        .bind(dto.project_id)
+        .bind(dto.work_package_id)
+        .bind(dto.cost_type_id)
+        .bind(dto.units)
+        .bind(dto.spent_on)
+        .bind(&dto.comments)
+        .bind(dto.overridden_costs)
+        .bind(dto.logged_by_id)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn create_rate(&self, dto: CreateRateDto) -> RepositoryResult<RateRow> {
+        let scoped = match dto.rate_type.as_str() {
+            rate_type::HOURLY => dto.user_id.is_some() && dto.project_id.is_some(),
+            rate_type::DEFAULT_HOURLY => dto.user_id.is_some() && dto.project_id.is_none(),
+            rate_type::COST => dto.cost_type_id.is_some(),
+            _ => return Err(RepositoryError::invalid("type", "inclusion", "is not included in the list")),
+        };
+        if !scoped {
+            return Err(RepositoryError::invalid(
+                "base",
+                "invalid",
+                format!("A {} needs a user, project or cost type to apply to", dto.rate_type),
+            ));
+        }
+        if dto.rate < 0.0 {
+            return Err(RepositoryError::invalid(
+                "rate",
+                "greater_than_or_equal_to",
+                "must be greater than or equal to 0",
+            ));
+        }
+
+        let row = sqlx::query_as::<_, RateRow>(&format!(
+            r#"
+            INSERT INTO rates (type, rate, valid_from, user_id, project_id, cost_type_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW(), NOW())
+            RETURNING {}
+            "#,
+            RATE_COLUMNS
+        ))
+        .bind(&dto.rate_type)
+        .bind(dto.rate)
+        .bind(dto.valid_from)
+        .bind(dto.user_id)
+        .bind(dto.project_id)
+        .bind(dto.cost_type_id)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// The work package followed by its descendants at any depth
+    pub async fn subtree_ids(&self, work_package_id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            r#"
+            WITH RECURSIVE subtree AS (
+                SELECT id FROM work_packages WHERE id = $1
+                UNION ALL
+                SELECT wp.id FROM work_packages wp JOIN subtree s ON wp.parent_id = s.id
+            )
+            SELECT id FROM subtree
+            "#,
+        )
+        .bind(work_package_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// The work packages followed by all their ancestors, whose costs
+    /// include theirs
+    pub async fn with_ancestor_ids(&self, work_package_ids: &[Id]) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            r#"
+            WITH RECURSIVE lineage AS (
+                SELECT id, parent_id FROM work_packages WHERE id = ANY($1)
+                UNION
+                SELECT wp.id, wp.parent_id FROM work_packages wp JOIN lineage l ON wp.id = l.parent_id
+            )
+            SELECT DISTINCT id FROM lineage ORDER BY id
+            "#,
+        )
+        .bind(work_package_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Time entries logged on the work packages
+    pub async fn time_entries(&self, work_package_ids: &[Id]) -> RepositoryResult<Vec<TimeEntryRow>> {
+        let rows = sqlx::query_as::<_, TimeEntryRow>(
+            r#"
+            SELECT id, project_id, user_id, work_package_id, hours, comments, activity_id, spent_on,
+                   tyear, tmonth, tweek, created_at, updated_at, overridden_costs, costs, rate_id, logged_by_id
+            FROM time_entries
+            WHERE work_package_id = ANY($1)
+            ORDER BY id
+            "#,
+        )
+        .bind(work_package_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Cost entries booked on the work packages
+    pub async fn cost_entries(&self, work_package_ids: &[Id]) -> RepositoryResult<Vec<CostEntryRow>> {
+        let rows = sqlx::query_as::<_, CostEntryRow>(&format!(
+            "SELECT {} FROM cost_entries WHERE work_package_id = ANY($1) ORDER BY id",
+            COST_ENTRY_COLUMNS
+        ))
+        .bind(work_package_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Hourly and default hourly rates of the users
+    pub async fn hourly_rates(&self, user_ids: &[Id]) -> RepositoryResult<Vec<RateRow>> {
+        let rows = sqlx::query_as::<_, RateRow>(&format!(
+            "SELECT {} FROM rates WHERE type IN ($1, $2) AND user_id = ANY($3) ORDER BY id",
+            RATE_COLUMNS
+        ))
+        .bind(rate_type::HOURLY)
+        .bind(rate_type::DEFAULT_HOURLY)
+        .bind(user_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Unit rates of the cost types
+    pub async fn cost_rates(&self, cost_type_ids: &[Id]) -> RepositoryResult<Vec<RateRow>> {
+        let rows = sqlx::query_as::<_, RateRow>(&format!(
+            "SELECT {} FROM rates WHERE type = $1 AND cost_type_id = ANY($2) ORDER BY id",
+            RATE_COLUMNS
+        ))
+        .bind(rate_type::COST)
+        .bind(cost_type_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Work packages the user logged time on, whose costs change with the
+    /// user's rates
+    pub async fn work_packages_of_user(&self, user_id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            "SELECT DISTINCT work_package_id FROM time_entries \
+             WHERE user_id = $1 AND work_package_id IS NOT NULL ORDER BY work_package_id",
+        )
+        .bind(user_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Work packages with units of the cost type, whose costs change with
+    /// the unit rates
+    pub async fn work_packages_of_cost_type(&self, cost_type_id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            "SELECT DISTINCT work_package_id FROM cost_entries \
+             WHERE cost_type_id = $1 AND work_package_id IS NOT NULL ORDER BY work_package_id",
+        )
+        .bind(cost_type_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Cached costs of the work packages; those never computed are left out
+    pub async fn find_work_package_costs(
+        &self,
+        work_package_ids: &[Id],
+    ) -> RepositoryResult<HashMap<Id, WorkPackageCostsRow>> {
+        let rows = sqlx::query_as::<_, (Id, f64, f64, f64)>(
+            r#"
+            SELECT id, labor_costs, material_costs, overall_costs
+            FROM work_packages
+            WHERE id = ANY($1) AND overall_costs IS NOT NULL
+            "#,
+        )
+        .bind(work_package_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, labor_costs, material_costs, overall_costs)| {
+                (id, WorkPackageCostsRow { labor_costs, material_costs, overall_costs })
+            })
+            .collect())
+    }
+
+    /// Cache the costs of a work package. Leaves `updated_at` and
+    /// `lock_version` alone, as the work package itself did not change.
+    pub async fn save_work_package_costs(&self, work_package_id: Id, costs: WorkPackageCostsRow) -> RepositoryResult<()> {
+        sqlx::query(
+            "UPDATE work_packages SET labor_costs = $2, material_costs = $3, overall_costs = $4 WHERE id = $1",
+        )
+        .bind(work_package_id)
+        .bind(costs.labor_costs)
+        .bind(costs.material_costs)
+        .bind(costs.overall_costs)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    #[tokio::test]
+    async fn test_rates_and_entries_of_work_packages() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("cost-user")).await;
+        let project = db.insert_project(ProjectFixture::new("cost-project")).await;
+        let parent = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+        let child = db.insert_work_package(WorkPackageFixture::new(project, user).with_parent(parent)).await;
+        let grandchild = db.insert_work_package(WorkPackageFixture::new(project, user).with_parent(child)).await;
+        let costs = db.costs();
+
+        assert_eq!(costs.subtree_ids(child).await.unwrap(), vec![child, grandchild]);
+        assert_eq!(costs.with_ancestor_ids(&[grandchild]).await.unwrap(), vec![parent, child, grandchild]);
+
+        let material = costs
+            .create_cost_type(CreateCostTypeDto {
+                name: "Concrete".into(),
+                unit: "ton".into(),
+                unit_plural: "tons".into(),
+                is_default: false,
+            })
+            .await
+            .unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        costs
+            .create_cost_entry(CreateCostEntryDto {
+                user_id: user,
+                project_id: project,
+                work_package_id: Some(grandchild),
+                cost_type_id: material.id,
+                units: 2.0,
+                spent_on: day,
+                comments: None,
+                overridden_costs: None,
+                logged_by_id: Some(user),
+            })
+            .await
+            .unwrap();
+        assert_eq!(costs.cost_entries(&[child, grandchild]).await.unwrap().len(), 1);
+        assert_eq!(costs.work_packages_of_cost_type(material.id).await.unwrap(), vec![grandchild]);
+
+        let rate = |rate_type: &str, user_id, project_id, cost_type_id| CreateRateDto {
+            rate_type: rate_type.to_string(),
+            rate: 50.0,
+            valid_from: day,
+            user_id,
+            project_id,
+            cost_type_id,
+        };
+        costs.create_rate(rate(rate_type::HOURLY, Some(user), Some(project), None)).await.unwrap();
+        costs.create_rate(rate(rate_type::DEFAULT_HOURLY, Some(user), None, None)).await.unwrap();
+        costs.create_rate(rate(rate_type::COST, None, None, Some(material.id))).await.unwrap();
+        // A project rate needs its project
+        let invalid = costs.create_rate(rate(rate_type::HOURLY, Some(user), None, None)).await;
+        assert!(matches!(invalid, Err(RepositoryError::Validation(_))));
+
+        assert_eq!(costs.hourly_rates(&[user]).await.unwrap().len(), 2);
+        assert_eq!(costs.cost_rates(&[material.id]).await.unwrap()[0].rate_type, rate_type::COST);
+
+        assert!(costs.find_work_package_costs(&[parent]).await.unwrap().is_empty());
+        let cached = WorkPackageCostsRow { labor_costs: 100.0, material_costs: 20.0, overall_costs: 120.0 };
+        costs.save_work_package_costs(parent, cached).await.unwrap();
+        assert_eq!(costs.find_work_package_costs(&[parent, child]).await.unwrap(), HashMap::from([(parent, cached)]));
+    }
+}
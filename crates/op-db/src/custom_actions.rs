@@ -0,0 +1,243 @@
+//! Custom actions repository
+//!
+//! Mirrors: app/models/custom_action.rb
+//!
+//! A custom action is a button on work packages applying a predefined set
+//! of changes. Its `conditions` tell on which work packages and for which
+//! users it is offered, e.g. `{"statusIds": [1], "roleIds": [3]}`; its
+//! `actions` the changes, e.g. `[{"status": 5}, {"assignedTo": "me"}]`.
+//! Both are stored as JSON and interpreted by op-services.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::{Repository, RepositoryError, RepositoryResult};
+
+const CUSTOM_ACTION_COLUMNS: &str = "id, name, description, position, conditions, actions, created_at, updated_at";
+
+/// Custom action row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct CustomActionRow {
+    pub id: Id,
+    pub name: String,
+    pub description: Option<String>,
+    pub position: i32,
+    pub conditions: serde_json::Value,
+    pub actions: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// DTO for creating a custom action, placed after the existing ones
+#[derive(Debug, Clone)]
+pub struct CreateCustomActionDto {
+    pub name: String,
+    pub description: Option<String>,
+    pub conditions: serde_json::Value,
+    pub actions: serde_json::Value,
+}
+
+/// DTO for updating a custom action; `None` keeps the current value
+#[derive(Debug, Clone, Default)]
+pub struct UpdateCustomActionDto {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub position: Option<i32>,
+    pub conditions: Option<serde_json::Value>,
+    pub actions: Option<serde_json::Value>,
+}
+
+/// Custom action repository
+pub struct CustomActionRepository {
+    db: DbExecutor,
+}
+
+impl CustomActionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// All custom actions in their order, as offered on work packages
+    pub async fn find_ordered(&self) -> RepositoryResult<Vec<CustomActionRow>> {
+        let rows = sqlx::query_as::<_, CustomActionRow>(&format!(
+            "SELECT {} FROM custom_actions ORDER BY position, id",
+            CUSTOM_ACTION_COLUMNS
+        ))
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    fn validate_name(name: &str) -> RepositoryResult<()> {
+        if name.trim().is_empty() {
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
+        }
+        if name.trim().chars().count() > 255 {
+            return Err(RepositoryError::invalid("name", "too_long", "is too long (maximum is 255 characters)"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Repository<CustomActionRow, CreateCustomActionDto, UpdateCustomActionDto> for CustomActionRepository {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<CustomActionRow>> {
+        let row = sqlx::query_as::<_, CustomActionRow>(&format!(
+            "SELECT {} FROM custom_actions WHERE id = $1",
+            CUSTOM_ACTION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<CustomActionRow>> {
+        let rows = sqlx::query_as::<_, CustomActionRow>(&format!(
+            "SELECT {} FROM custom_actions ORDER BY position, id LIMIT $1 OFFSET $2",
+            CUSTOM_ACTION_COLUMNS
+        ))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM custom_actions")
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn create(&self, dto: CreateCustomActionDto) -> RepositoryResult<CustomActionRow> {
+        Self::validate_name(&dto.name)?;
+
+        let row = sqlx::query_as::<_, CustomActionRow>(&format!(
+            r#"
+            INSERT INTO custom_actions (name, description, position, conditions, actions, created_at, updated_at)
+            VALUES ($1, $2, (SELECT COALESCE(MAX(position), 0) + 1 FROM custom_actions), $3, $4, NOW(), NOW())
+            RETURNING {}
+            "#,
+            CUSTOM_ACTION_COLUMNS
+        ))
+        .bind(dto.name.trim())
+        .bind(&dto.description)
+        .bind(&dto.conditions)
+        .bind(&dto.actions)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn update(&self, id: Id, dto: UpdateCustomActionDto) -> RepositoryResult<CustomActionRow> {
+        if let Some(name) = &dto.name {
+            Self::validate_name(name)?;
+        }
+
+        let row = sqlx::query_as::<_, CustomActionRow>(&format!(
+            r#"
+            UPDATE custom_actions SET
+                name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                position = COALESCE($4, position),
+                conditions = COALESCE($5, conditions),
+                actions = COALESCE($6, actions),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING {}
+            "#,
+            CUSTOM_ACTION_COLUMNS
+        ))
+        .bind(id)
+        .bind(dto.name.as_deref().map(str::trim))
+        .bind(&dto.description)
+        .bind(dto.position)
+        .bind(&dto.conditions)
+        .bind(&dto.actions)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        row.ok_or_else(|| RepositoryError::NotFound(format!("Custom action {} not found", id)))
+    }
+
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        let result = sqlx::query("DELETE FROM custom_actions WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Custom action {} not found", id)));
+        }
+
+        Ok(())
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM custom_actions WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(exists)
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::TestDb;
+
+    fn create_dto(name: &str) -> CreateCustomActionDto {
+        CreateCustomActionDto {
+            name: name.into(),
+            description: None,
+            conditions: serde_json::json!({ "statusIds": [1] }),
+            actions: serde_json::json!([{ "status": 2 }]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_actions_are_appended_and_reordered() {
+        let db = TestDb::connect().await;
+        let repo = CustomActionRepository::with_executor(db.executor());
+
+        let close = repo.create(create_dto("Close")).await.unwrap();
+        let reject = repo.create(create_dto("Reject")).await.unwrap();
+        assert!(reject.position > close.position);
+        assert_eq!(close.actions[0]["status"], 2);
+
+        repo.update(
+            reject.id,
+            UpdateCustomActionDto {
+                position: Some(close.position - 1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let names: Vec<_> = repo.find_ordered().await.unwrap().into_iter().map(|row| row.name).collect();
+        assert_eq!(names, vec!["Reject", "Close"]);
+
+        let blank = repo.create(create_dto("  ")).await;
+        assert!(matches!(blank, Err(RepositoryError::Validation(_))));
+
+        repo.delete(close.id).await.unwrap();
+        assert!(matches!(repo.delete(close.id).await, Err(RepositoryError::NotFound(_))));
+    }
+}
@@ -0,0 +1,303 @@
+//! Custom field repository
+//!
+//! Tables: custom_fields, custom_field_sections, custom_fields_projects,
+//! custom_values
+//!
+//! Project custom fields and the values of customized records. A project
+//! custom field applies to a project when it is for all projects, required,
+//! or activated in the project; new projects get the fields for all
+//! projects and the required ones.
+
+use op_core::traits::Id;
+use op_models::custom_field::PROJECT_CUSTOM_FIELD;
+use op_models::{CustomField, CustomFieldSection};
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::repository::RepositoryResult;
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// `custom_values.customized_type` of the values of projects
+pub const PROJECT_CUSTOMIZED_TYPE: &str = "Project";
+
+/// Custom fields `cf` with their section `s`
+const SELECT_CUSTOM_FIELDS: &str = "SELECT cf.id, cf.name, cf.field_format, cf.is_required, cf.position, \
+    s.id AS section_id, s.name AS section_name, s.position AS section_position \
+    FROM custom_fields cf LEFT JOIN custom_field_sections s ON s.id = cf.custom_field_section_id";
+
+/// Custom values of customized records that are gone
+pub const CUSTOM_VALUE_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::polymorphic("custom_values", "customized_id", "customized_type", "WorkPackage", "work_packages", OrphanAction::Delete),
+    OrphanCheck::polymorphic("custom_values", "customized_id", "customized_type", PROJECT_CUSTOMIZED_TYPE, "projects", OrphanAction::Delete),
+    OrphanCheck::new("custom_values", "custom_field_id", "custom_fields", OrphanAction::Delete),
+];
+
+#[derive(Debug, FromRow)]
+struct CustomFieldRow {
+    id: Id,
+    name: String,
+    field_format: String,
+    is_required: bool,
+    position: i32,
+    section_id: Option<Id>,
+    section_name: Option<String>,
+    section_position: Option<i32>,
+}
+
+impl From<CustomFieldRow> for CustomField {
+    fn from(row: CustomFieldRow) -> Self {
+        let section = match (row.section_id, row.section_name) {
+            (Some(id), Some(name)) => Some(CustomFieldSection {
+                id,
+                name,
+                position: row.section_position.unwrap_or(1),
+            }),
+            _ => None,
+        };
+        Self {
+            id: row.id,
+            name: row.name,
+            field_format: row.field_format,
+            is_required: row.is_required,
+            position: row.position,
+            section,
+        }
+    }
+}
+
+/// Value of a custom field of a customized record
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct CustomValueRow {
+    pub customized_id: Id,
+    pub custom_field_id: Id,
+    /// Format of the custom field, to read the value
+    pub field_format: String,
+    pub value: Option<String>,
+}
+
+/// How a [`CustomValueFilter`] compares the values of a custom field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomValueOperator {
+    /// Any of the values is stored
+    Equals,
+    /// None of the values is stored
+    NotEquals,
+    /// A non-empty value is stored
+    Any,
+    /// No non-empty value is stored
+    None,
+}
+
+/// Condition on the stored values of a custom field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomValueFilter {
+    pub custom_field_id: Id,
+    pub operator: CustomValueOperator,
+    pub values: Vec<String>,
+}
+
+impl CustomValueFilter {
+    /// Append ` AND` and the condition on the values of the records of
+    /// `customized_type` whose id is the SQL expression `customized_id`
+    pub(crate) fn push_condition<'a>(
+        &'a self,
+        sql: &mut QueryBuilder<'a, Postgres>,
+        customized_type: &'static str,
+        customized_id: &str,
+    ) {
+        let negated = matches!(self.operator, CustomValueOperator::NotEquals | CustomValueOperator::None);
+        sql.push(if negated { " AND NOT EXISTS (" } else { " AND EXISTS (" });
+        sql.push("SELECT 1 FROM custom_values cv WHERE cv.customized_type = ");
+        sql.push_bind(customized_type);
+        sql.push(format_args!(" AND cv.customized_id = {} AND cv.custom_field_id = ", customized_id));
+        sql.push_bind(self.custom_field_id);
+        match self.operator {
+            CustomValueOperator::Equals | CustomValueOperator::NotEquals => {
+                sql.push(" AND cv.value = ANY(");
+                sql.push_bind(self.values.as_slice());
+                sql.push("))");
+            }
+            CustomValueOperator::Any | CustomValueOperator::None => {
+                sql.push(" AND cv.value <> '')");
+            }
+        }
+    }
+}
+
+/// Custom field repository
+pub struct CustomFieldRepository {
+    db: DbExecutor,
+}
+
+impl CustomFieldRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Project custom fields of the project, `None` for a new one, by
+    /// section and position
+    pub async fn project_custom_fields(&self, project_id: Option<Id>) -> RepositoryResult<Vec<CustomField>> {
+        let rows = sqlx::query_as::<_, CustomFieldRow>(&format!(
+            r#"
+            {}
+            WHERE cf.type = $1
+              AND (cf.is_for_all OR cf.is_required OR cf.id IN (
+                  SELECT custom_field_id FROM custom_fields_projects WHERE project_id = $2
+              ))
+            ORDER BY s.position NULLS LAST, s.id, cf.position, cf.id
+            "#,
+            SELECT_CUSTOM_FIELDS
+        ))
+        .bind(PROJECT_CUSTOM_FIELD)
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows.into_iter().map(CustomField::from).collect())
+    }
+
+    /// Project custom field by id, whether or not it applies to a project
+    pub async fn find_project_custom_field(&self, id: Id) -> RepositoryResult<Option<CustomField>> {
+        let row = sqlx::query_as::<_, CustomFieldRow>(&format!("{} WHERE cf.type = $1 AND cf.id = $2", SELECT_CUSTOM_FIELDS))
+            .bind(PROJECT_CUSTOM_FIELD)
+            .bind(id)
+            .fetch_optional(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(row.map(CustomField::from))
+    }
+
+    /// Values of the records of `customized_type`, by record and field
+    pub async fn values(&self, customized_type: &str, customized_ids: &[Id]) -> RepositoryResult<Vec<CustomValueRow>> {
+        if customized_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, CustomValueRow>(
+            r#"
+            SELECT cv.customized_id, cv.custom_field_id, cf.field_format, cv.value
+            FROM custom_values cv
+            JOIN custom_fields cf ON cf.id = cv.custom_field_id
+            WHERE cv.customized_type = $1 AND cv.customized_id = ANY($2)
+            ORDER BY cv.customized_id, cv.custom_field_id, cv.id
+            "#,
+        )
+        .bind(customized_type)
+        .bind(customized_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Replace the values of the given custom fields of a record; a `None`
+    /// value removes it
+    pub async fn set_values(
+        &self,
+        customized_type: &str,
+        customized_id: Id,
+        values: &[(Id, Option<String>)],
+    ) -> RepositoryResult<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        for (custom_field_id, value) in values {
+            sqlx::query(
+                "DELETE FROM custom_values WHERE customized_type = $1 AND customized_id = $2 AND custom_field_id = $3",
+            )
+            .bind(customized_type)
+            .bind(customized_id)
+            .bind(custom_field_id)
+            .execute(&mut *tx)
+            .await?;
+
+            if let Some(value) = value {
+                sqlx::query(
+                    "INSERT INTO custom_values (customized_type, customized_id, custom_field_id, value) \
+                     VALUES ($1, $2, $3, $4)",
+                )
+                .bind(customized_type)
+                .bind(customized_id)
+                .bind(custom_field_id)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::projects::ProjectOrder;
+    use crate::repository::Pagination;
+    use crate::testing::{ProjectFixture, TestDb};
+
+    #[tokio::test]
+    async fn test_project_custom_fields_and_values() {
+        let db = TestDb::connect().await;
+        let repo = db.custom_fields();
+        let project = db.insert_project(ProjectFixture::new("alpha")).await;
+        let other = db.insert_project(ProjectFixture::new("beta")).await;
+        let stage = db.insert_project_custom_field("Stage", "string", true).await;
+        let budget = db.insert_project_custom_field("Budget", "int", false).await;
+
+        // Only the required field applies until the other is activated
+        let fields = repo.project_custom_fields(Some(project)).await.unwrap();
+        assert_eq!(fields.iter().map(|field| field.id).collect::<Vec<_>>(), vec![stage]);
+        db.activate_custom_field(budget, project).await;
+        assert_eq!(repo.project_custom_fields(Some(project)).await.unwrap().len(), 2);
+        assert_eq!(repo.find_project_custom_field(budget).await.unwrap().unwrap().field_format, "int");
+
+        repo.set_values(PROJECT_CUSTOMIZED_TYPE, project, &[(stage, Some("Build".into())), (budget, Some("5".into()))])
+            .await
+            .unwrap();
+        repo.set_values(PROJECT_CUSTOMIZED_TYPE, project, &[(budget, None)]).await.unwrap();
+        repo.set_values(PROJECT_CUSTOMIZED_TYPE, other, &[(stage, Some("Plan".into()))]).await.unwrap();
+
+        let values = repo.values(PROJECT_CUSTOMIZED_TYPE, &[project]).await.unwrap();
+        assert_eq!(
+            values,
+            vec![CustomValueRow {
+                customized_id: project,
+                custom_field_id: stage,
+                field_format: "string".into(),
+                value: Some("Build".into()),
+            }]
+        );
+
+        let projects = db.projects();
+        let filtered = |operator, values: &[&str]| CustomValueFilter {
+            custom_field_id: stage,
+            operator,
+            values: values.iter().map(|value| value.to_string()).collect(),
+        };
+        for (filter, expected) in [
+            (filtered(CustomValueOperator::Equals, &["Build"]), vec![project]),
+            (filtered(CustomValueOperator::NotEquals, &["Build"]), vec![other]),
+            (filtered(CustomValueOperator::Any, &[]), vec![project, other]),
+            (filtered(CustomValueOperator::None, &[]), vec![]),
+        ] {
+            let found = projects
+                .find_by_templated(false, false, false, &[filter.clone()], ProjectOrder::Hierarchy, Pagination::default())
+                .await
+                .unwrap();
+            let mut ids: Vec<Id> = found.items.iter().map(|row| row.id).collect();
+            ids.retain(|id| [project, other].contains(id));
+            assert_eq!(ids, expected, "{:?}", filter.operator);
+        }
+    }
+}
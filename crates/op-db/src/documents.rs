@@ -0,0 +1,482 @@
+//! Documents repository
+//!
+//! Mirrors:
+//! - modules/documents/app/models/document.rb
+//! - modules/documents/app/models/document_category.rb
+//!
+//! Documents belong to a project and, optionally, to a category. Categories
+//! are enumerations of type `DocumentCategory`, like priorities. The files of
+//! a document are attachments with container type `Document`.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::journals::journable_type;
+use crate::repository::{Repository, RepositoryError, RepositoryResult};
+
+/// Enumeration type of document categories
+pub const DOCUMENT_CATEGORY_TYPE: &str = "DocumentCategory";
+
+const DOCUMENT_COLUMNS: &str = "d.id, d.project_id, d.category_id, c.name AS category_name, c.position AS category_position, \
+     d.title, d.description, d.created_at, d.updated_at";
+
+const DOCUMENT_FROM: &str = "documents d LEFT JOIN enumerations c ON c.id = d.category_id";
+
+/// Document row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct DocumentRow {
+    pub id: Id,
+    pub project_id: Id,
+    pub category_id: Option<Id>,
+    /// Name of the category
+    pub category_name: Option<String>,
+    /// Position of the category, which orders documents by category
+    pub category_position: Option<i32>,
+    pub title: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// DTO for creating a document
+#[derive(Debug, Clone)]
+pub struct CreateDocumentDto {
+    pub project_id: Id,
+    /// Category of the document, the default category when `None`
+    pub category_id: Option<Id>,
+    pub title: String,
+    pub description: Option<String>,
+}
+
+/// DTO for updating a document
+#[derive(Debug, Clone, Default)]
+pub struct UpdateDocumentDto {
+    pub category_id: Option<Id>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Document category row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct DocumentCategoryRow {
+    pub id: Id,
+    pub name: String,
+    pub position: i32,
+    pub is_default: bool,
+    pub active: bool,
+}
+
+/// Order of the documents of a project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DocumentOrder {
+    /// By category position, then title; the default of the Ruby UI
+    #[default]
+    Category,
+    /// Newest first
+    Date,
+}
+
+impl DocumentOrder {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Category => "c.position NULLS LAST, c.id, d.title, d.id",
+            Self::Date => "d.created_at DESC, d.id DESC",
+        }
+    }
+}
+
+/// Document repository
+pub struct DocumentRepository {
+    db: DbExecutor,
+}
+
+impl DocumentRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Documents of a project in the order
+    pub async fn find_by_project(&self, project_id: Id, order: DocumentOrder) -> RepositoryResult<Vec<DocumentRow>> {
+        let rows = sqlx::query_as::<_, DocumentRow>(&format!(
+            "SELECT {} FROM {} WHERE d.project_id = $1 ORDER BY {}",
+            DOCUMENT_COLUMNS,
+            DOCUMENT_FROM,
+            order.sql()
+        ))
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Active document categories in their order
+    pub async fn categories(&self) -> RepositoryResult<Vec<DocumentCategoryRow>> {
+        let rows = sqlx::query_as::<_, DocumentCategoryRow>(
+            r#"
+            SELECT id, name, position, is_default, active FROM enumerations
+            WHERE type = $1 AND active AND project_id IS NULL
+            ORDER BY position, id
+            "#,
+        )
+        .bind(DOCUMENT_CATEGORY_TYPE)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Create a shared document category at the end of the list
+    pub async fn create_category(&self, name: &str, is_default: bool) -> RepositoryResult<DocumentCategoryRow> {
+        if name.trim().is_empty() {
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
+        }
+
+        let row = sqlx::query_as::<_, DocumentCategoryRow>(
+            r#"
+            INSERT INTO enumerations (type, name, position, is_default)
+            VALUES ($1, $2, (SELECT COALESCE(MAX(position), 0) + 1 FROM enumerations WHERE type = $1), $3)
+            RETURNING id, name, position, is_default, active
+            "#,
+        )
+        .bind(DOCUMENT_CATEGORY_TYPE)
+        .bind(name.trim())
+        .bind(is_default)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Members of the project allowed to `view_documents`, and administrators
+    /// among them. They hear about new documents.
+    pub async fn member_ids(&self, project_id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            r#"
+            SELECT DISTINCT m.user_id FROM members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.project_id = $1 AND m.entity_type IS NULL
+              AND (u.admin OR EXISTS (
+                  SELECT 1 FROM member_roles mr
+                  JOIN role_permissions rp ON rp.role_id = mr.role_id
+                  WHERE mr.member_id = m.id AND rp.permission = 'view_documents'
+              ))
+            ORDER BY m.user_id
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Delete the document with its attachment records and notifications,
+    /// returning the ids of the attachments so their files can be removed
+    pub async fn delete_with_attachments(&self, id: Id) -> RepositoryResult<Vec<Id>> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let attachment_ids = sqlx::query_scalar::<_, Id>(
+            "SELECT id FROM attachments WHERE container_type = $1 AND container_id = $2 ORDER BY id",
+        )
+        .bind(journable_type::DOCUMENT)
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for table in [
+            "DELETE FROM attachments WHERE container_type = $1 AND container_id = $2",
+            "DELETE FROM journals WHERE journable_type = $1 AND journable_id = $2",
+            "DELETE FROM notifications WHERE resource_type = $1 AND resource_id = $2",
+        ] {
+            sqlx::query(table)
+                .bind(journable_type::DOCUMENT)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let result = sqlx::query("DELETE FROM documents WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Document {} not found", id)));
+        }
+
+        tx.commit().await?;
+        Ok(attachment_ids)
+    }
+
+    fn validate_title(title: &str) -> RepositoryResult<()> {
+        if title.trim().is_empty() {
+            return Err(RepositoryError::invalid("title", "blank", "can't be blank"));
+        }
+        if title.trim().chars().count() > 255 {
+            return Err(RepositoryError::invalid("title", "too_long", "is too long (maximum is 255 characters)"));
+        }
+        Ok(())
+    }
+
+    /// Category ids must name an active document category
+    async fn validate_category(&self, category_id: Option<Id>) -> RepositoryResult<()> {
+        let Some(category_id) = category_id else {
+            return Ok(());
+        };
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM enumerations WHERE id = $1 AND type = $2 AND active)",
+        )
+        .bind(category_id)
+        .bind(DOCUMENT_CATEGORY_TYPE)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        if !exists {
+            return Err(RepositoryError::invalid("category", "inclusion", "is not set to one of the allowed values"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Repository<DocumentRow, CreateDocumentDto, UpdateDocumentDto> for DocumentRepository {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<DocumentRow>> {
+        let row = sqlx::query_as::<_, DocumentRow>(&format!(
+            "SELECT {} FROM {} WHERE d.id = $1",
+            DOCUMENT_COLUMNS, DOCUMENT_FROM
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<DocumentRow>> {
+        let rows = sqlx::query_as::<_, DocumentRow>(&format!(
+            "SELECT {} FROM {} ORDER BY d.project_id, d.id LIMIT $1 OFFSET $2",
+            DOCUMENT_COLUMNS, DOCUMENT_FROM
+        ))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM documents")
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Create a document, in the default category unless one is given
+    async fn create(&self, dto: CreateDocumentDto) -> RepositoryResult<DocumentRow> {
+        Self::validate_title(&dto.title)?;
+        self.validate_category(dto.category_id).await?;
+
+        let id = sqlx::query_scalar::<_, Id>(
+            r#"
+            INSERT INTO documents (project_id, category_id, title, description)
+            VALUES ($1, COALESCE($2, (
+                SELECT id FROM enumerations WHERE type = $5 AND is_default AND active ORDER BY position, id LIMIT 1
+            )), $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(dto.project_id)
+        .bind(dto.category_id)
+        .bind(dto.title.trim())
+        .bind(&dto.description)
+        .bind(DOCUMENT_CATEGORY_TYPE)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        self.find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("Document {} not found", id)))
+    }
+
+    async fn update(&self, id: Id, dto: UpdateDocumentDto) -> RepositoryResult<DocumentRow> {
+        if let Some(title) = &dto.title {
+            Self::validate_title(title)?;
+        }
+        self.validate_category(dto.category_id).await?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE documents SET
+                category_id = COALESCE($2, category_id),
+                title = COALESCE($3, title),
+                description = COALESCE($4, description),
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(dto.category_id)
+        .bind(dto.title.as_deref().map(str::trim))
+        .bind(&dto.description)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Document {} not found", id)));
+        }
+
+        self.find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("Document {} not found", id)))
+    }
+
+    /// Delete the document with its attachment records; see
+    /// [`DocumentRepository::delete_with_attachments`] to remove their files
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        self.delete_with_attachments(id).await.map(|_| ())
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM documents WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(exists)
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture};
+
+    async fn category(db: &TestDb, name: &str, position: i32, is_default: bool) -> Id {
+        sqlx::query_scalar(
+            "INSERT INTO enumerations (type, name, position, is_default) VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(DOCUMENT_CATEGORY_TYPE)
+        .bind(name)
+        .bind(position)
+        .bind(is_default)
+        .fetch_one(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap()
+    }
+
+    fn document(project_id: Id, category_id: Option<Id>, title: &str) -> CreateDocumentDto {
+        CreateDocumentDto {
+            project_id,
+            category_id,
+            title: title.into(),
+            description: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_documents_ordered_by_category_or_date() {
+        let db = TestDb::connect().await;
+        let project = db.insert_project(ProjectFixture::new("documents")).await;
+        let specs = category(&db, "Specification", 2, false).await;
+        let manuals = category(&db, "User documentation", 1, true).await;
+        let documents = db.documents();
+
+        let spec = documents.create(document(project, Some(specs), "API spec")).await.unwrap();
+        let manual = documents.create(document(project, None, "  Handbook ")).await.unwrap();
+        assert_eq!(manual.title, "Handbook");
+        assert_eq!(manual.category_id, Some(manuals));
+        assert_eq!(manual.category_name.as_deref(), Some("User documentation"));
+
+        let by_category = documents.find_by_project(project, DocumentOrder::Category).await.unwrap();
+        assert_eq!(by_category.iter().map(|d| d.id).collect::<Vec<_>>(), vec![manual.id, spec.id]);
+        let by_date = documents.find_by_project(project, DocumentOrder::Date).await.unwrap();
+        assert_eq!(by_date.iter().map(|d| d.id).collect::<Vec<_>>(), vec![manual.id, spec.id]);
+
+        let moved = UpdateDocumentDto {
+            category_id: Some(specs),
+            ..Default::default()
+        };
+        assert_eq!(documents.update(manual.id, moved).await.unwrap().category_id, Some(specs));
+
+        // Retired categories take no new documents
+        let retired = category(&db, "Retired", 3, false).await;
+        sqlx::query("UPDATE enumerations SET active = FALSE WHERE id = $1")
+            .bind(retired)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        let err = documents.create(document(project, Some(retired), "Old")).await.unwrap_err();
+        assert!(matches!(err, RepositoryError::Validation(_)));
+        let err = documents.create(document(project, None, " ")).await.unwrap_err();
+        assert!(matches!(err, RepositoryError::Validation(_)));
+    }
+
+    #[tokio::test]
+    async fn test_delete_returns_attachment_ids() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("document-author")).await;
+        let project = db.insert_project(ProjectFixture::new("document-files")).await;
+        let documents = db.documents();
+        let doc = documents.create(document(project, None, "Minutes")).await.unwrap();
+
+        let attachment: Id = sqlx::query_scalar(
+            r#"
+            INSERT INTO attachments (container_type, container_id, filename, disk_filename, author_id)
+            VALUES ('Document', $1, 'minutes.pdf', 'abc_minutes.pdf', $2)
+            RETURNING id
+            "#,
+        )
+        .bind(doc.id)
+        .bind(author)
+        .fetch_one(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(documents.delete_with_attachments(doc.id).await.unwrap(), vec![attachment]);
+        assert!(!documents.exists(doc.id).await.unwrap());
+        let left: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM attachments WHERE id = $1)")
+            .bind(attachment)
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert!(!left);
+        assert!(matches!(
+            documents.delete_with_attachments(doc.id).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_members_who_can_view_documents() {
+        let db = TestDb::connect().await;
+        let reader = db.insert_user(UserFixture::new("document-reader")).await;
+        let other = db.insert_user(UserFixture::new("document-other")).await;
+        let project = db.insert_project(ProjectFixture::new("document-members")).await;
+        let can_read = db.insert_role("Document reader", &["view_documents"]).await;
+        let cannot_read = db.insert_role("No documents", &["view_work_packages"]).await;
+        for (user_id, role_id) in [(reader, can_read), (other, cannot_read)] {
+            db.members()
+                .create(crate::members::CreateMemberDto {
+                    user_id,
+                    project_id: Some(project),
+                    role_ids: vec![role_id],
+                    entity_type: None,
+                    entity_id: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(db.documents().member_ids(project).await.unwrap(), vec![reader]);
+    }
+}
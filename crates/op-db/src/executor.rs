@@ -0,0 +1,152 @@
+//! Database executors
+//!
+//! Repositories run their queries on an executor: the connection pool, or a
+//! transaction shared by several repositories so that their changes are
+//! committed or rolled back together.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use sqlx::pool::PoolConnection;
+use sqlx::{Database, PgConnection, PgPool, Postgres, Transaction, TransactionManager};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::repository::{RepositoryContext, RepositoryError, RepositoryResult};
+
+type PgTransactionManager = <Postgres as Database>::TransactionManager;
+
+/// Where a repository runs its queries
+#[derive(Clone)]
+pub enum DbExecutor {
+    /// A pooled connection per query
+    Pool(PgPool),
+    /// One transaction shared by every repository holding a clone
+    Transaction(Arc<Mutex<Transaction<'static, Postgres>>>),
+}
+
+impl DbExecutor {
+    /// Start a transaction to hand to repositories
+    pub async fn begin(pool: &PgPool) -> RepositoryResult<Self> {
+        Ok(Self::Transaction(Arc::new(Mutex::new(pool.begin().await?))))
+    }
+
+    /// Connection for the next query. For a transaction, other queries on
+    /// it wait until the connection is dropped. Counts as a call of the
+    /// current [`RepositoryContext`].
+    pub async fn acquire(&self) -> RepositoryResult<DbConnection> {
+        RepositoryContext::record_call();
+        match self {
+            Self::Pool(pool) => Ok(DbConnection::Pool(Box::new(pool.acquire().await?))),
+            Self::Transaction(tx) => Ok(DbConnection::Transaction(tx.clone().lock_owned().await)),
+        }
+    }
+
+    /// Commit the shared transaction once no repository holds it anymore.
+    /// Committing a pool executor does nothing.
+    pub async fn commit(self) -> RepositoryResult<()> {
+        if let Some(tx) = self.into_transaction()? {
+            tx.commit().await?;
+        }
+        Ok(())
+    }
+
+    /// Roll back the shared transaction once no repository holds it anymore.
+    /// A transaction dropped without commit is rolled back as well.
+    pub async fn rollback(self) -> RepositoryResult<()> {
+        if let Some(tx) = self.into_transaction()? {
+            tx.rollback().await?;
+        }
+        Ok(())
+    }
+
+    fn into_transaction(self) -> RepositoryResult<Option<Transaction<'static, Postgres>>> {
+        match self {
+            Self::Pool(_) => Ok(None),
+            Self::Transaction(tx) => Arc::try_unwrap(tx)
+                .map(|tx| Some(tx.into_inner()))
+                .map_err(|_| RepositoryError::Conflict("Transaction is still in use".to_string())),
+        }
+    }
+}
+
+impl From<PgPool> for DbExecutor {
+    fn from(pool: PgPool) -> Self {
+        Self::Pool(pool)
+    }
+}
+
+/// Connection acquired from an executor
+pub enum DbConnection {
+    Pool(Box<PoolConnection<Postgres>>),
+    Transaction(OwnedMutexGuard<Transaction<'static, Postgres>>),
+}
+
+impl Deref for DbConnection {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        match self {
+            Self::Pool(conn) => conn,
+            Self::Transaction(tx) => tx,
+        }
+    }
+}
+
+impl DerefMut for DbConnection {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        match self {
+            Self::Pool(conn) => conn,
+            Self::Transaction(tx) => tx,
+        }
+    }
+}
+
+/// Transaction owning its connection, nested as a savepoint when the
+/// executor is a transaction already
+pub struct DbTransaction {
+    conn: DbConnection,
+    open: bool,
+}
+
+impl DbTransaction {
+    /// Begin a transaction on a connection of the executor
+    pub async fn begin(executor: &DbExecutor) -> RepositoryResult<Self> {
+        let mut conn = executor.acquire().await?;
+        PgTransactionManager::begin(&mut conn).await?;
+        Ok(Self { conn, open: true })
+    }
+
+    pub async fn commit(mut self) -> RepositoryResult<()> {
+        PgTransactionManager::commit(&mut self.conn).await?;
+        self.open = false;
+        Ok(())
+    }
+
+    pub async fn rollback(mut self) -> RepositoryResult<()> {
+        PgTransactionManager::rollback(&mut self.conn).await?;
+        self.open = false;
+        Ok(())
+    }
+}
+
+impl Deref for DbTransaction {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        &self.conn
+    }
+}
+
+impl DerefMut for DbTransaction {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        &mut self.conn
+    }
+}
+
+impl Drop for DbTransaction {
+    fn drop(&mut self) {
+        if self.open {
+            PgTransactionManager::start_rollback(&mut self.conn);
+        }
+    }
+}
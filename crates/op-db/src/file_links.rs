@@ -0,0 +1,306 @@
+//! File links repository
+//!
+//! Mirrors: modules/storages/app/models/storages/file_link.rb
+//!
+//! A file link points from a container, so far always a work package, to a
+//! file in a storage. The origin columns keep what the storage reported
+//! about the file when it was linked.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::{Repository, RepositoryError, RepositoryResult};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// `file_links.container_type` values
+pub mod container_type {
+    pub const WORK_PACKAGE: &str = "WorkPackage";
+}
+
+const FILE_LINK_COLUMNS: &str = "id, storage_id, creator_id, container_id, container_type, origin_id, origin_name, \
+     origin_mime_type, origin_created_by_name, origin_created_at, origin_updated_at, created_at, updated_at";
+
+/// Links of work packages that are gone; the files stay in the storage
+pub const FILE_LINK_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::polymorphic("file_links", "container_id", "container_type", container_type::WORK_PACKAGE, "work_packages", OrphanAction::Delete),
+];
+
+/// File link row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct FileLinkRow {
+    pub id: Id,
+    pub storage_id: Id,
+    pub creator_id: Id,
+    pub container_id: Id,
+    pub container_type: String,
+    /// Id of the file in the storage
+    pub origin_id: String,
+    pub origin_name: String,
+    pub origin_mime_type: Option<String>,
+    pub origin_created_by_name: Option<String>,
+    pub origin_created_at: Option<DateTime<Utc>>,
+    pub origin_updated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// DTO for creating a file link
+#[derive(Debug, Clone)]
+pub struct CreateFileLinkDto {
+    pub storage_id: Id,
+    pub creator_id: Id,
+    pub container_id: Id,
+    pub container_type: String,
+    pub origin_id: String,
+    pub origin_name: String,
+    pub origin_mime_type: Option<String>,
+    pub origin_created_by_name: Option<String>,
+    pub origin_created_at: Option<DateTime<Utc>>,
+    pub origin_updated_at: Option<DateTime<Utc>>,
+}
+
+/// DTO for updating a file link (no-op, file links are not updatable)
+#[derive(Debug, Clone, Default)]
+pub struct UpdateFileLinkDto {}
+
+/// File link repository
+pub struct FileLinkRepository {
+    db: DbExecutor,
+}
+
+impl FileLinkRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// File links of a work package, oldest first
+    pub async fn find_by_work_package(&self, work_package_id: Id) -> RepositoryResult<Vec<FileLinkRow>> {
+        let rows = sqlx::query_as::<_, FileLinkRow>(&format!(
+            "SELECT {} FROM file_links WHERE container_type = $1 AND container_id = $2 ORDER BY id",
+            FILE_LINK_COLUMNS
+        ))
+        .bind(container_type::WORK_PACKAGE)
+        .bind(work_package_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Number of file links per work package; work packages without links
+    /// are left out
+    pub async fn count_by_work_packages(&self, work_package_ids: &[Id]) -> RepositoryResult<HashMap<Id, i64>> {
+        if work_package_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let counts = sqlx::query_as::<_, (Id, i64)>(
+            r#"
+            SELECT container_id, COUNT(*)
+            FROM file_links
+            WHERE container_type = $1 AND container_id = ANY($2)
+            GROUP BY container_id
+            "#,
+        )
+        .bind(container_type::WORK_PACKAGE)
+        .bind(work_package_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(counts.into_iter().collect())
+    }
+}
+
+#[async_trait]
+impl Repository<FileLinkRow, CreateFileLinkDto, UpdateFileLinkDto> for FileLinkRepository {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<FileLinkRow>> {
+        let row = sqlx::query_as::<_, FileLinkRow>(&format!("SELECT {} FROM file_links WHERE id = $1", FILE_LINK_COLUMNS))
+            .bind(id)
+            .fetch_optional(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(row)
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<FileLinkRow>> {
+        let rows = sqlx::query_as::<_, FileLinkRow>(&format!(
+            "SELECT {} FROM file_links ORDER BY id LIMIT $1 OFFSET $2",
+            FILE_LINK_COLUMNS
+        ))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM file_links")
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Link a file; linking a file the container links already refreshes
+    /// the origin data of the existing link
+    async fn create(&self, dto: CreateFileLinkDto) -> RepositoryResult<FileLinkRow> {
+        if dto.origin_id.trim().is_empty() {
+            return Err(RepositoryError::invalid("originId", "blank", "can't be blank"));
+        }
+        if dto.origin_name.trim().is_empty() {
+            return Err(RepositoryError::invalid("originName", "blank", "can't be blank"));
+        }
+
+        let storage_exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM storages WHERE id = $1)")
+            .bind(dto.storage_id)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+        if !storage_exists {
+            return Err(RepositoryError::invalid("storage", "does_not_exist", "does not exist"));
+        }
+
+        let row = sqlx::query_as::<_, FileLinkRow>(&format!(
+            r#"
+            INSERT INTO file_links (storage_id, creator_id, container_id, container_type, origin_id, origin_name,
+                                    origin_mime_type, origin_created_by_name, origin_created_at, origin_updated_at,
+                                    created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW(), NOW())
+            ON CONFLICT (container_type, container_id, storage_id, origin_id)
+            DO UPDATE SET origin_name = EXCLUDED.origin_name,
+                          origin_mime_type = EXCLUDED.origin_mime_type,
+                          origin_updated_at = EXCLUDED.origin_updated_at,
+                          updated_at = NOW()
+            RETURNING {}
+            "#,
+            FILE_LINK_COLUMNS
+        ))
+        .bind(dto.storage_id)
+        .bind(dto.creator_id)
+        .bind(dto.container_id)
+        .bind(&dto.container_type)
+        .bind(dto.origin_id.trim())
+        .bind(dto.origin_name.trim())
+        .bind(&dto.origin_mime_type)
+        .bind(&dto.origin_created_by_name)
+        .bind(dto.origin_created_at)
+        .bind(dto.origin_updated_at)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn update(&self, id: Id, _dto: UpdateFileLinkDto) -> RepositoryResult<FileLinkRow> {
+        // File links cannot be updated, just return the existing one
+        self.find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("File link {} not found", id)))
+    }
+
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        let result = sqlx::query("DELETE FROM file_links WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("File link {} not found", id)));
+        }
+
+        Ok(())
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM file_links WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(exists)
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::storages::CreateStorageDto;
+    use op_models::StorageType;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    fn link(storage_id: Id, work_package_id: Id, creator_id: Id, origin_id: &str, name: &str) -> CreateFileLinkDto {
+        CreateFileLinkDto {
+            storage_id,
+            creator_id,
+            container_id: work_package_id,
+            container_type: container_type::WORK_PACKAGE.to_string(),
+            origin_id: origin_id.to_string(),
+            origin_name: name.to_string(),
+            origin_mime_type: Some("application/pdf".to_string()),
+            origin_created_by_name: None,
+            origin_created_at: None,
+            origin_updated_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_links_of_work_packages() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("file-link-author")).await;
+        let project = db.insert_project(ProjectFixture::new("file-link-project")).await;
+        let first = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+        let second = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+        let storage = db
+            .storages()
+            .create(CreateStorageDto {
+                provider_type: StorageType::Nextcloud.provider_type().to_string(),
+                name: "Linked cloud".to_string(),
+                host: "https://linked.example.com".to_string(),
+                creator_id: user,
+                oauth_client_id: None,
+                oauth_client_secret: None,
+            })
+            .await
+            .unwrap();
+        let links = db.file_links();
+
+        let spec = links.create(link(storage.id, first, user, "101", "spec.pdf")).await.unwrap();
+        links.create(link(storage.id, first, user, "102", "plan.pdf")).await.unwrap();
+        // Linking the same file again renames the existing link
+        let renamed = links.create(link(storage.id, first, user, "101", "spec-v2.pdf")).await.unwrap();
+        assert_eq!(renamed.id, spec.id);
+        assert_eq!(renamed.origin_name, "spec-v2.pdf");
+
+        let names: Vec<String> = links
+            .find_by_work_package(first)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|link| link.origin_name)
+            .collect();
+        assert_eq!(names, vec!["spec-v2.pdf", "plan.pdf"]);
+        assert_eq!(links.count_by_work_packages(&[first, second]).await.unwrap(), HashMap::from([(first, 2)]));
+
+        links.delete(spec.id).await.unwrap();
+        assert!(matches!(links.delete(spec.id).await, Err(RepositoryError::NotFound(_))));
+
+        // Deleting the storage removes its links
+        db.storages().delete(storage.id).await.unwrap();
+        assert!(links.find_by_work_package(first).await.unwrap().is_empty());
+
+        let orphan = links.create(link(storage.id, second, user, "103", "lost.pdf")).await;
+        assert!(matches!(orphan, Err(RepositoryError::Validation(errors)) if errors[0].property == "storage"));
+    }
+}
@@ -0,0 +1,902 @@
+//! Forums and messages repositories
+//!
+//! Mirrors:
+//! - app/models/forum.rb
+//! - app/models/message.rb
+//! - app/models/journal/message_journal.rb
+//!
+//! A message without a parent is a topic; replies point at their topic
+//! through `parent_id`. Forums cache their topic and message counts and
+//! their latest message, topics their reply count and latest reply. Every
+//! change of a message writes a journal version holding its new state.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::journals::journable_type;
+use crate::repository::{Repository, RepositoryError, RepositoryResult};
+
+/// Journal data type of message versions
+pub const MESSAGE_JOURNAL_TYPE: &str = "Journal::MessageJournal";
+
+/// Watchable type of forums
+pub const FORUM_WATCHABLE_TYPE: &str = "Forum";
+
+const FORUM_COLUMNS: &str =
+    "id, project_id, name, description, position, topics_count, messages_count, last_message_id";
+
+const MESSAGE_COLUMNS: &str = "m.id, m.forum_id, m.parent_id, m.subject, m.content, m.author_id, \
+     m.replies_count, m.last_reply_id, lr.created_at AS last_reply_at, m.locked, m.sticky, \
+     m.sticked_on, m.created_at, m.updated_at";
+
+/// Forum row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct ForumRow {
+    pub id: Id,
+    pub project_id: Id,
+    pub name: String,
+    pub description: Option<String>,
+    pub position: i32,
+    pub topics_count: i32,
+    pub messages_count: i32,
+    pub last_message_id: Option<Id>,
+}
+
+/// DTO for creating a forum
+#[derive(Debug, Clone)]
+pub struct CreateForumDto {
+    pub project_id: Id,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// DTO for updating a forum
+#[derive(Debug, Clone, Default)]
+pub struct UpdateForumDto {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub position: Option<i32>,
+}
+
+/// Message row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct MessageRow {
+    pub id: Id,
+    pub forum_id: Id,
+    pub parent_id: Option<Id>,
+    pub subject: String,
+    pub content: Option<String>,
+    pub author_id: Option<Id>,
+    pub replies_count: i32,
+    pub last_reply_id: Option<Id>,
+    /// When the latest reply was written
+    pub last_reply_at: Option<DateTime<Utc>>,
+    pub locked: bool,
+    pub sticky: i32,
+    pub sticked_on: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl MessageRow {
+    pub fn is_topic(&self) -> bool {
+        self.parent_id.is_none()
+    }
+
+    /// Sticky topics are listed before all others
+    pub fn is_sticky(&self) -> bool {
+        self.sticky > 0
+    }
+
+    /// Topic the message belongs to, the message itself for topics
+    pub fn topic_id(&self) -> Id {
+        self.parent_id.unwrap_or(self.id)
+    }
+}
+
+/// DTO for creating a topic, or a reply when `parent_id` is set
+#[derive(Debug, Clone)]
+pub struct CreateMessageDto {
+    pub forum_id: Id,
+    pub parent_id: Option<Id>,
+    pub subject: String,
+    pub content: Option<String>,
+    pub author_id: Id,
+    pub locked: bool,
+    pub sticky: bool,
+}
+
+/// DTO for updating a message
+#[derive(Debug, Clone, Default)]
+pub struct UpdateMessageDto {
+    pub subject: Option<String>,
+    pub content: Option<String>,
+    pub locked: Option<bool>,
+    pub sticky: Option<bool>,
+}
+
+/// Version of a message from its journal
+#[derive(Debug, Clone, FromRow)]
+pub struct MessageVersionRow {
+    pub journal_id: Id,
+    pub version: i32,
+    /// User who made the change
+    pub user_id: Id,
+    pub subject: String,
+    pub content: Option<String>,
+    pub locked: bool,
+    pub sticky: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Forum repository
+pub struct ForumRepository {
+    db: DbExecutor,
+}
+
+impl ForumRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Forums of a project in their order
+    pub async fn find_by_project(&self, project_id: Id) -> RepositoryResult<Vec<ForumRow>> {
+        let rows = sqlx::query_as::<_, ForumRow>(&format!(
+            "SELECT {} FROM forums WHERE project_id = $1 ORDER BY position, id",
+            FORUM_COLUMNS
+        ))
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Users watching the forum who may view its messages, as administrators,
+    /// members allowed to `view_messages` or because the project is public.
+    /// They hear about its new topics.
+    pub async fn watcher_ids(&self, forum_id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            r#"
+            SELECT w.user_id FROM watchers w
+            JOIN users u ON u.id = w.user_id
+            JOIN forums f ON f.id = w.watchable_id
+            JOIN projects p ON p.id = f.project_id
+            WHERE w.watchable_type = $1 AND w.watchable_id = $2
+              AND (u.admin OR p.public OR EXISTS (
+                  SELECT 1 FROM members m
+                  JOIN member_roles mr ON mr.member_id = m.id
+                  JOIN role_permissions rp ON rp.role_id = mr.role_id
+                  WHERE m.user_id = u.id AND m.project_id = p.id AND m.entity_type IS NULL
+                    AND rp.permission = 'view_messages'
+              ))
+            ORDER BY w.user_id
+            "#,
+        )
+        .bind(FORUM_WATCHABLE_TYPE)
+        .bind(forum_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    fn validate_name(name: &str) -> RepositoryResult<()> {
+        if name.trim().is_empty() {
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Repository<ForumRow, CreateForumDto, UpdateForumDto> for ForumRepository {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<ForumRow>> {
+        let row = sqlx::query_as::<_, ForumRow>(&format!("SELECT {} FROM forums WHERE id = $1", FORUM_COLUMNS))
+            .bind(id)
+            .fetch_optional(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(row)
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<ForumRow>> {
+        let rows = sqlx::query_as::<_, ForumRow>(&format!(
+            "SELECT {} FROM forums ORDER BY project_id, position, id LIMIT $1 OFFSET $2",
+            FORUM_COLUMNS
+        ))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM forums")
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn create(&self, dto: CreateForumDto) -> RepositoryResult<ForumRow> {
+        Self::validate_name(&dto.name)?;
+
+        let row = sqlx::query_as::<_, ForumRow>(&format!(
+            r#"
+            INSERT INTO forums (project_id, name, description, position)
+            VALUES ($1, $2, $3, (SELECT COALESCE(MAX(position), 0) + 1 FROM forums WHERE project_id = $1))
+            RETURNING {}
+            "#,
+            FORUM_COLUMNS
+        ))
+        .bind(dto.project_id)
+        .bind(dto.name.trim())
+        .bind(&dto.description)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn update(&self, id: Id, dto: UpdateForumDto) -> RepositoryResult<ForumRow> {
+        if let Some(name) = &dto.name {
+            Self::validate_name(name)?;
+        }
+
+        let row = sqlx::query_as::<_, ForumRow>(&format!(
+            r#"
+            UPDATE forums SET
+                name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                position = COALESCE($4, position)
+            WHERE id = $1
+            RETURNING {}
+            "#,
+            FORUM_COLUMNS
+        ))
+        .bind(id)
+        .bind(dto.name.as_deref().map(str::trim))
+        .bind(&dto.description)
+        .bind(dto.position)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        row.ok_or_else(|| RepositoryError::NotFound(format!("Forum {} not found", id)))
+    }
+
+    /// Delete the forum with all its messages and their attachment records
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let message_ids = sqlx::query_scalar::<_, Id>("SELECT id FROM messages WHERE forum_id = $1")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+        let attachment_ids = delete_messages(&mut tx, &message_ids).await?;
+        sqlx::query("DELETE FROM attachments WHERE id = ANY($1)")
+            .bind(&attachment_ids)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM forums WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Forum {} not found", id)));
+        }
+        sqlx::query("DELETE FROM watchers WHERE watchable_type = $1 AND watchable_id = $2")
+            .bind(FORUM_WATCHABLE_TYPE)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM forums WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(exists)
+    }
+}
+
+/// Message repository
+pub struct MessageRepository {
+    db: DbExecutor,
+}
+
+impl MessageRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    pub async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<MessageRow>> {
+        let row = sqlx::query_as::<_, MessageRow>(&format!(
+            "SELECT {} FROM messages m LEFT JOIN messages lr ON lr.id = m.last_reply_id WHERE m.id = $1",
+            MESSAGE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Topics of a forum, sticky ones first, then by their latest activity
+    pub async fn find_topics(&self, forum_id: Id, limit: i64, offset: i64) -> RepositoryResult<Vec<MessageRow>> {
+        let rows = sqlx::query_as::<_, MessageRow>(&format!(
+            r#"
+            SELECT {} FROM messages m
+            LEFT JOIN messages lr ON lr.id = m.last_reply_id
+            WHERE m.forum_id = $1 AND m.parent_id IS NULL
+            ORDER BY m.sticky DESC, m.sticked_on DESC NULLS LAST,
+                     COALESCE(lr.created_at, m.created_at) DESC, COALESCE(m.last_reply_id, m.id) DESC
+            LIMIT $2 OFFSET $3
+            "#,
+            MESSAGE_COLUMNS
+        ))
+        .bind(forum_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Replies to a topic, oldest first
+    pub async fn find_replies(&self, topic_id: Id) -> RepositoryResult<Vec<MessageRow>> {
+        let rows = sqlx::query_as::<_, MessageRow>(&format!(
+            r#"
+            SELECT {} FROM messages m
+            LEFT JOIN messages lr ON lr.id = m.last_reply_id
+            WHERE m.parent_id = $1
+            ORDER BY m.created_at, m.id
+            "#,
+            MESSAGE_COLUMNS
+        ))
+        .bind(topic_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Versions of a message, oldest first
+    pub async fn find_versions(&self, id: Id) -> RepositoryResult<Vec<MessageVersionRow>> {
+        let rows = sqlx::query_as::<_, MessageVersionRow>(
+            r#"
+            SELECT j.id AS journal_id, j.version, j.user_id, mj.subject, mj.content,
+                   mj.locked, mj.sticky, j.created_at
+            FROM journals j
+            JOIN message_journals mj ON mj.id = j.data_id
+            WHERE j.journable_type = $1 AND j.journable_id = $2 AND j.data_type = $3
+            ORDER BY j.version
+            "#,
+        )
+        .bind(journable_type::MESSAGE)
+        .bind(id)
+        .bind(MESSAGE_JOURNAL_TYPE)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Create a topic or a reply. Replies go to the forum of their topic;
+    /// locked topics and replies cannot be replied to. The counters and
+    /// latest messages of topic and forum change in the same transaction.
+    pub async fn create(&self, dto: CreateMessageDto) -> RepositoryResult<MessageRow> {
+        if dto.subject.trim().is_empty() {
+            return Err(RepositoryError::invalid("subject", "blank", "can't be blank"));
+        }
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let forum_id = match dto.parent_id {
+            Some(parent_id) => {
+                let parent: Option<(Id, Option<Id>, bool)> = sqlx::query_as(
+                    "SELECT forum_id, parent_id, locked FROM messages WHERE id = $1 FOR UPDATE",
+                )
+                .bind(parent_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+                let (forum_id, grandparent_id, locked) =
+                    parent.ok_or_else(|| RepositoryError::NotFound(format!("Message {} not found", parent_id)))?;
+                if grandparent_id.is_some() {
+                    return Err(RepositoryError::invalid("parent", "invalid", "Replies can only be made to topics."));
+                }
+                if locked {
+                    return Err(RepositoryError::invalid("parent", "locked", "The topic is locked."));
+                }
+                forum_id
+            }
+            None => dto.forum_id,
+        };
+
+        let forum_exists = sqlx::query_scalar::<_, Id>("SELECT id FROM forums WHERE id = $1 FOR UPDATE")
+            .bind(forum_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !forum_exists {
+            return Err(RepositoryError::NotFound(format!("Forum {} not found", forum_id)));
+        }
+
+        let id = sqlx::query_scalar::<_, Id>(
+            r#"
+            INSERT INTO messages (forum_id, parent_id, subject, content, author_id, locked, sticky,
+                                  sticked_on, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, CASE WHEN $7 > 0 THEN NOW() END, NOW(), NOW())
+            RETURNING id
+            "#,
+        )
+        .bind(forum_id)
+        .bind(dto.parent_id)
+        .bind(dto.subject.trim())
+        .bind(&dto.content)
+        .bind(dto.author_id)
+        .bind(dto.locked && dto.parent_id.is_none())
+        .bind(i32::from(dto.sticky && dto.parent_id.is_none()))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        write_version(&mut tx, id, dto.author_id).await?;
+
+        if let Some(parent_id) = dto.parent_id {
+            sqlx::query("UPDATE messages SET replies_count = replies_count + 1, last_reply_id = $2 WHERE id = $1")
+                .bind(parent_id)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query(
+            r#"
+            UPDATE forums SET
+                topics_count = topics_count + $2,
+                messages_count = messages_count + 1,
+                last_message_id = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(forum_id)
+        .bind(i32::from(dto.parent_id.is_none()))
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("Message {} not found", id)))
+    }
+
+    /// Change a message, journaling the new version as made by `user_id`.
+    /// Only topics are locked or made sticky.
+    pub async fn update(&self, id: Id, user_id: Id, dto: UpdateMessageDto) -> RepositoryResult<MessageRow> {
+        if dto.subject.as_deref().is_some_and(|s| s.trim().is_empty()) {
+            return Err(RepositoryError::invalid("subject", "blank", "can't be blank"));
+        }
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let parent_id: Option<Option<Id>> = sqlx::query_scalar("SELECT parent_id FROM messages WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let parent_id = parent_id.ok_or_else(|| RepositoryError::NotFound(format!("Message {} not found", id)))?;
+        if parent_id.is_some() && (dto.locked.is_some() || dto.sticky.is_some()) {
+            return Err(RepositoryError::invalid("base", "invalid", "Only topics can be locked or made sticky."));
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE messages SET
+                subject = COALESCE($2, subject),
+                content = COALESCE($3, content),
+                locked = COALESCE($4, locked),
+                sticky = COALESCE($5, sticky),
+                sticked_on = CASE
+                    WHEN $5 IS NULL THEN sticked_on
+                    WHEN $5 > 0 THEN COALESCE(sticked_on, NOW())
+                END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(dto.subject.as_deref().map(str::trim))
+        .bind(&dto.content)
+        .bind(dto.locked)
+        .bind(dto.sticky.map(i32::from))
+        .execute(&mut *tx)
+        .await?;
+
+        write_version(&mut tx, id, user_id).await?;
+        tx.commit().await?;
+
+        self.find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("Message {} not found", id)))
+    }
+
+    /// Ids of a message and, for topics, of its replies
+    pub async fn thread_ids(&self, id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>("SELECT id FROM messages WHERE id = $1 OR parent_id = $1 ORDER BY id")
+            .bind(id)
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(ids)
+    }
+
+    /// Delete a message; deleting a topic deletes its replies. Their
+    /// journals, watchers and notifications go with them, and the counters
+    /// of topic and forum are recounted. Returns the ids of the messages'
+    /// attachments, whose files are removed once the delete is committed.
+    pub async fn delete(&self, id: Id) -> RepositoryResult<Vec<Id>> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let message: Option<(Id, Option<Id>)> =
+            sqlx::query_as("SELECT forum_id, parent_id FROM messages WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let (forum_id, parent_id) =
+            message.ok_or_else(|| RepositoryError::NotFound(format!("Message {} not found", id)))?;
+
+        let ids = sqlx::query_scalar::<_, Id>("SELECT id FROM messages WHERE id = $1 OR parent_id = $1")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await?;
+        let attachment_ids = delete_messages(&mut tx, &ids).await?;
+
+        if let Some(parent_id) = parent_id {
+            sqlx::query(
+                r#"
+                UPDATE messages SET
+                    replies_count = (SELECT COUNT(*) FROM messages WHERE parent_id = $1),
+                    last_reply_id = (SELECT MAX(id) FROM messages WHERE parent_id = $1)
+                WHERE id = $1
+                "#,
+            )
+            .bind(parent_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query(
+            r#"
+            UPDATE forums SET
+                topics_count = (SELECT COUNT(*) FROM messages WHERE forum_id = $1 AND parent_id IS NULL),
+                messages_count = (SELECT COUNT(*) FROM messages WHERE forum_id = $1),
+                last_message_id = (SELECT MAX(id) FROM messages WHERE forum_id = $1)
+            WHERE id = $1
+            "#,
+        )
+        .bind(forum_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(attachment_ids)
+    }
+}
+
+/// Journal the current state of the message as its next version
+async fn write_version(tx: &mut DbTransaction, id: Id, user_id: Id) -> RepositoryResult<()> {
+    let data_id = sqlx::query_scalar::<_, Id>(
+        r#"
+        INSERT INTO message_journals (forum_id, parent_id, subject, content, author_id, locked, sticky)
+        SELECT forum_id, parent_id, subject, content, author_id, locked, sticky FROM messages WHERE id = $1
+        RETURNING id
+        "#,
+    )
+    .bind(id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO journals (journable_type, journable_id, user_id, notes, version,
+                              data_type, data_id, cause, restricted, created_at, updated_at)
+        VALUES ($1, $2, $3, '', (SELECT COALESCE(MAX(version), 0) + 1 FROM journals
+                                 WHERE journable_type = $1 AND journable_id = $2),
+                $4, $5, '{}', false, NOW(), NOW())
+        "#,
+    )
+    .bind(journable_type::MESSAGE)
+    .bind(id)
+    .bind(user_id)
+    .bind(MESSAGE_JOURNAL_TYPE)
+    .bind(data_id)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete messages with their journals, watchers and notifications, replies
+/// first. Returns the ids of their attachments.
+async fn delete_messages(tx: &mut DbTransaction, ids: &[Id]) -> RepositoryResult<Vec<Id>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let attachment_ids = sqlx::query_scalar::<_, Id>(
+        "SELECT id FROM attachments WHERE container_type = $1 AND container_id = ANY($2) ORDER BY id",
+    )
+    .bind(journable_type::MESSAGE)
+    .bind(ids)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM message_journals WHERE id IN (
+            SELECT data_id FROM journals
+            WHERE journable_type = $1 AND journable_id = ANY($2) AND data_type = $3
+        )
+        "#,
+    )
+    .bind(journable_type::MESSAGE)
+    .bind(ids)
+    .bind(MESSAGE_JOURNAL_TYPE)
+    .execute(&mut **tx)
+    .await?;
+
+    for table in [
+        "DELETE FROM journals WHERE journable_type = $1 AND journable_id = ANY($2)",
+        "DELETE FROM watchers WHERE watchable_type = $1 AND watchable_id = ANY($2)",
+        "DELETE FROM notifications WHERE resource_type = $1 AND resource_id = ANY($2)",
+    ] {
+        sqlx::query(table)
+            .bind(journable_type::MESSAGE)
+            .bind(ids)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    sqlx::query("DELETE FROM messages WHERE id = ANY($1) AND parent_id IS NOT NULL")
+        .bind(ids)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM messages WHERE id = ANY($1)")
+        .bind(ids)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(attachment_ids)
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture};
+
+    async fn forum(db: &TestDb, project_id: Id) -> ForumRow {
+        db.forums()
+            .create(CreateForumDto {
+                project_id,
+                name: "General".into(),
+                description: None,
+            })
+            .await
+            .unwrap()
+    }
+
+    fn topic(forum_id: Id, author_id: Id, subject: &str) -> CreateMessageDto {
+        CreateMessageDto {
+            forum_id,
+            parent_id: None,
+            subject: subject.into(),
+            content: Some("Hello".into()),
+            author_id,
+            locked: false,
+            sticky: false,
+        }
+    }
+
+    fn reply(topic: &MessageRow, author_id: Id) -> CreateMessageDto {
+        CreateMessageDto {
+            parent_id: Some(topic.id),
+            subject: format!("RE: {}", topic.subject),
+            ..self::topic(0, author_id, "")
+        }
+    }
+
+    async fn count(db: &TestDb, sql: &str, ids: &[Id]) -> i64 {
+        sqlx::query_scalar::<_, i64>(sql)
+            .bind(ids)
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replies_update_topic_and_forum() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("forum-author")).await;
+        let project = db.insert_project(ProjectFixture::new("forums")).await;
+        let forum = forum(&db, project).await;
+        let messages = db.messages();
+
+        let first = messages.create(topic(forum.id, author, "First")).await.unwrap();
+        let second = messages.create(topic(forum.id, author, "Second")).await.unwrap();
+        let answer = messages.create(reply(&first, author)).await.unwrap();
+        assert_eq!(answer.forum_id, forum.id);
+
+        let first = messages.find_by_id(first.id).await.unwrap().unwrap();
+        assert_eq!((first.replies_count, first.last_reply_id), (1, Some(answer.id)));
+        assert_eq!(first.last_reply_at, Some(answer.created_at));
+
+        let forum = db.forums().find_by_id(forum.id).await.unwrap().unwrap();
+        assert_eq!((forum.topics_count, forum.messages_count), (2, 3));
+        assert_eq!(forum.last_message_id, Some(answer.id));
+
+        // The topic with the latest reply comes first, unless another is sticky
+        let topics = messages.find_topics(forum.id, 10, 0).await.unwrap();
+        assert_eq!(topics.iter().map(|t| t.id).collect::<Vec<_>>(), vec![first.id, second.id]);
+        let sticky = UpdateMessageDto {
+            sticky: Some(true),
+            ..Default::default()
+        };
+        messages.update(second.id, author, sticky).await.unwrap();
+        let topics = messages.find_topics(forum.id, 10, 0).await.unwrap();
+        assert_eq!(topics.iter().map(|t| t.id).collect::<Vec<_>>(), vec![second.id, first.id]);
+        assert!(topics[0].is_sticky() && topics[0].sticked_on.is_some());
+
+        // Replies cannot be replied to
+        let err = messages.create(reply(&answer, author)).await.unwrap_err();
+        assert!(matches!(err, RepositoryError::Validation(_)));
+    }
+
+    #[tokio::test]
+    async fn test_watchers_who_can_view_the_forum() {
+        let db = TestDb::connect().await;
+        let member = db.insert_user(UserFixture::new("forum-member")).await;
+        let outsider = db.insert_user(UserFixture::new("forum-outsider")).await;
+        let project = db.insert_project(ProjectFixture::new("watched-forum")).await;
+        let reader = db.insert_role("Forum reader", &["view_messages"]).await;
+        db.members()
+            .create(crate::members::CreateMemberDto {
+                user_id: member,
+                project_id: Some(project),
+                role_ids: vec![reader],
+                entity_type: None,
+                entity_id: None,
+            })
+            .await
+            .unwrap();
+        let forum = forum(&db, project).await;
+        for user in [member, outsider] {
+            sqlx::query("INSERT INTO watchers (watchable_type, watchable_id, user_id) VALUES ('Forum', $1, $2)")
+                .bind(forum.id)
+                .bind(user)
+                .execute(&mut *db.executor().acquire().await.unwrap())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(db.forums().watcher_ids(forum.id).await.unwrap(), vec![member]);
+    }
+
+    #[tokio::test]
+    async fn test_locked_topics_take_no_replies() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("locker")).await;
+        let project = db.insert_project(ProjectFixture::new("locked-forum")).await;
+        let forum = forum(&db, project).await;
+        let messages = db.messages();
+
+        let topic = messages
+            .create(CreateMessageDto {
+                locked: true,
+                ..topic(forum.id, author, "Closed")
+            })
+            .await
+            .unwrap();
+        assert!(topic.locked);
+
+        let err = messages.create(reply(&topic, author)).await.unwrap_err();
+        assert!(err.to_string().contains("locked"), "{}", err);
+        let forum = db.forums().find_by_id(forum.id).await.unwrap().unwrap();
+        assert_eq!(forum.messages_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_edits_are_journaled() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("editor")).await;
+        let moderator = db.insert_user(UserFixture::new("moderator")).await;
+        let project = db.insert_project(ProjectFixture::new("journaled-forum")).await;
+        let forum = forum(&db, project).await;
+        let messages = db.messages();
+
+        let topic = messages.create(topic(forum.id, author, "Draft")).await.unwrap();
+        let edit = UpdateMessageDto {
+            subject: Some("Final".into()),
+            content: Some("Reworded".into()),
+            ..Default::default()
+        };
+        let topic = messages.update(topic.id, moderator, edit).await.unwrap();
+        assert_eq!(topic.subject, "Final");
+
+        let versions = messages.find_versions(topic.id).await.unwrap();
+        assert_eq!(versions.iter().map(|v| v.version).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!((versions[0].user_id, versions[0].subject.as_str()), (author, "Draft"));
+        assert_eq!((versions[1].user_id, versions[1].content.as_deref()), (moderator, Some("Reworded")));
+
+        let blank = UpdateMessageDto {
+            subject: Some(" ".into()),
+            ..Default::default()
+        };
+        assert!(messages.update(topic.id, author, blank).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deleting_a_topic_deletes_its_replies() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("thread-author")).await;
+        let project = db.insert_project(ProjectFixture::new("deleted-thread")).await;
+        let forum = forum(&db, project).await;
+        let messages = db.messages();
+
+        let kept = messages.create(topic(forum.id, author, "Kept")).await.unwrap();
+        let thread = messages.create(topic(forum.id, author, "Removed")).await.unwrap();
+        let first = messages.create(reply(&thread, author)).await.unwrap();
+        let second = messages.create(reply(&thread, author)).await.unwrap();
+        let attachment = sqlx::query_scalar::<_, Id>(
+            "INSERT INTO attachments (container_id, container_type, filename, author_id) \
+             VALUES ($1, 'Message', 'notes.txt', $2) RETURNING id",
+        )
+        .bind(second.id)
+        .bind(author)
+        .fetch_one(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        let ids = messages.thread_ids(thread.id).await.unwrap();
+        assert_eq!(ids, vec![thread.id, first.id, second.id]);
+
+        // Deleting a reply recounts its topic
+        assert!(messages.delete(first.id).await.unwrap().is_empty());
+        let topic = messages.find_by_id(thread.id).await.unwrap().unwrap();
+        assert_eq!((topic.replies_count, topic.last_reply_id), (1, Some(second.id)));
+
+        // The attachments are left for their files to be removed with them
+        assert_eq!(messages.delete(thread.id).await.unwrap(), vec![attachment]);
+        assert!(messages.find_by_id(second.id).await.unwrap().is_none());
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM journals WHERE journable_type = 'Message' AND journable_id = ANY($1)", &ids).await,
+            0
+        );
+
+        let forum = db.forums().find_by_id(forum.id).await.unwrap().unwrap();
+        assert_eq!((forum.topics_count, forum.messages_count), (1, 1));
+        assert_eq!(forum.last_message_id, Some(kept.id));
+        assert!(matches!(messages.delete(thread.id).await, Err(RepositoryError::NotFound(_))));
+    }
+}
@@ -0,0 +1,359 @@
+//! Groups repository
+//!
+//! Mirrors: app/services/groups/add_users_service.rb,
+//! app/services/groups/cleanup_inherited_roles_service.rb,
+//! modules/ldap_groups/app/models/ldap_groups/synchronized_group.rb
+//!
+//! Groups are principals in `users` with type `Group`, named by their last
+//! name. Members of a group inherit the group's project memberships: adding
+//! a user gives them a member role for every role of the group, with
+//! `inherited_from` pointing to the group's member role; removing the user
+//! takes those roles away again and drops memberships left without roles.
+//! Roles a user holds on their own are kept either way.
+//!
+//! Synchronized groups link local groups to the groups of an LDAP source.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::repository::RepositoryResult;
+use crate::users::{principal_type, status};
+
+/// Local group linked to a group of an LDAP source
+#[derive(Debug, Clone, FromRow)]
+pub struct SynchronizedGroupRow {
+    pub id: i64,
+    pub group_id: i64,
+    /// Name of the LDAP source
+    pub ldap_source: String,
+    /// Identifier of the LDAP group that survives renames
+    pub entry_id: String,
+    pub dn: String,
+    /// Name of the local group
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Groups repository
+pub struct GroupRepository {
+    db: DbExecutor,
+}
+
+impl GroupRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Ids of the group's members
+    pub async fn member_ids(&self, group_id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>("SELECT user_id FROM group_users WHERE group_id = $1 ORDER BY user_id")
+            .bind(group_id)
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(ids)
+    }
+
+    /// Add users to the group, giving them the roles the group holds in its
+    /// memberships. Users already in the group are skipped. Returns the
+    /// number of users added.
+    pub async fn add_users(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<u64> {
+        if user_ids.is_empty() {
+            return Ok(0);
+        }
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let added = sqlx::query_scalar::<_, Id>(
+            r#"
+            INSERT INTO group_users (group_id, user_id, created_at, updated_at)
+            SELECT $1, u.id, NOW(), NOW() FROM UNNEST($2::BIGINT[]) AS u (id)
+            ON CONFLICT (group_id, user_id) DO NOTHING
+            RETURNING user_id
+            "#,
+        )
+        .bind(group_id)
+        .bind(user_ids)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if !added.is_empty() {
+            sqlx::query(
+                r#"
+                INSERT INTO members (user_id, project_id, entity_type, entity_id, created_at, updated_at)
+                SELECT u.id, gm.project_id, gm.entity_type, gm.entity_id, NOW(), NOW()
+                FROM members gm, UNNEST($2::BIGINT[]) AS u (id)
+                WHERE gm.user_id = $1
+                  AND NOT EXISTS (
+                      SELECT 1 FROM members um
+                      WHERE um.user_id = u.id
+                        AND um.project_id IS NOT DISTINCT FROM gm.project_id
+                        AND um.entity_type IS NOT DISTINCT FROM gm.entity_type
+                        AND um.entity_id IS NOT DISTINCT FROM gm.entity_id
+                  )
+                "#,
+            )
+            .bind(group_id)
+            .bind(&added)
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO member_roles (member_id, role_id, inherited_from)
+                SELECT um.id, gmr.role_id, gmr.id
+                FROM members gm
+                JOIN member_roles gmr ON gmr.member_id = gm.id
+                JOIN members um ON um.project_id IS NOT DISTINCT FROM gm.project_id
+                               AND um.entity_type IS NOT DISTINCT FROM gm.entity_type
+                               AND um.entity_id IS NOT DISTINCT FROM gm.entity_id
+                WHERE gm.user_id = $1 AND um.user_id = ANY($2)
+                ON CONFLICT (member_id, role_id) DO NOTHING
+                "#,
+            )
+            .bind(group_id)
+            .bind(&added)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(added.len() as u64)
+    }
+
+    /// Remove users from the group together with the roles they inherited
+    /// from it. Returns the number of users removed.
+    pub async fn remove_users(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<u64> {
+        if user_ids.is_empty() {
+            return Ok(0);
+        }
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let removed = sqlx::query_scalar::<_, Id>(
+            "DELETE FROM group_users WHERE group_id = $1 AND user_id = ANY($2) RETURNING user_id",
+        )
+        .bind(group_id)
+        .bind(user_ids)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if !removed.is_empty() {
+            let emptied = sqlx::query_scalar::<_, Id>(
+                r#"
+                DELETE FROM member_roles mr
+                USING members um
+                WHERE mr.member_id = um.id AND um.user_id = ANY($2)
+                  AND mr.inherited_from IN (
+                      SELECT gmr.id FROM member_roles gmr
+                      JOIN members gm ON gm.id = gmr.member_id
+                      WHERE gm.user_id = $1
+                  )
+                RETURNING um.id
+                "#,
+            )
+            .bind(group_id)
+            .bind(&removed)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+                DELETE FROM members m
+                WHERE m.id = ANY($1)
+                  AND NOT EXISTS (SELECT 1 FROM member_roles mr WHERE mr.member_id = m.id)
+                "#,
+            )
+            .bind(&emptied)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(removed.len() as u64)
+    }
+
+    /// Groups synchronized with the LDAP source
+    pub async fn synchronized_groups(&self, ldap_source: &str) -> RepositoryResult<Vec<SynchronizedGroupRow>> {
+        let rows = sqlx::query_as::<_, SynchronizedGroupRow>(
+            r#"
+            SELECT s.id, s.group_id, s.ldap_source, s.entry_id, s.dn, g.lastname AS name,
+                   s.created_at, s.updated_at
+            FROM ldap_groups_synchronized_groups s
+            JOIN users g ON g.id = s.group_id
+            WHERE s.ldap_source = $1
+            ORDER BY s.id
+            "#,
+        )
+        .bind(ldap_source)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Create a group named `name` synchronized with the LDAP group
+    pub async fn create_synchronized(
+        &self,
+        ldap_source: &str,
+        entry_id: &str,
+        dn: &str,
+        name: &str,
+    ) -> RepositoryResult<SynchronizedGroupRow> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let group_id = sqlx::query_scalar::<_, Id>(
+            r#"
+            INSERT INTO users (type, login, firstname, lastname, mail, admin, status, created_at, updated_at)
+            VALUES ($1, '', '', $2, '', FALSE, $3, NOW(), NOW())
+            RETURNING id
+            "#,
+        )
+        .bind(principal_type::GROUP)
+        .bind(name)
+        .bind(status::ACTIVE)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let row = sqlx::query_as::<_, SynchronizedGroupRow>(
+            r#"
+            INSERT INTO ldap_groups_synchronized_groups (group_id, ldap_source, entry_id, dn, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            RETURNING id, group_id, ldap_source, entry_id, dn, $5::TEXT AS name, created_at, updated_at
+            "#,
+        )
+        .bind(group_id)
+        .bind(ldap_source)
+        .bind(entry_id)
+        .bind(dn)
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(row)
+    }
+
+    /// Follow a renamed or moved LDAP group, renaming the local group
+    pub async fn update_synchronized(&self, id: Id, dn: &str, name: &str) -> RepositoryResult<()> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let group_id = sqlx::query_scalar::<_, Id>(
+            "UPDATE ldap_groups_synchronized_groups SET dn = $2, updated_at = NOW() WHERE id = $1 RETURNING group_id",
+        )
+        .bind(id)
+        .bind(dn)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE users SET lastname = $2, updated_at = NOW() WHERE id = $1")
+            .bind(group_id)
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Stop synchronizing a group; the local group is kept
+    pub async fn delete_synchronized(&self, id: Id) -> RepositoryResult<()> {
+        sqlx::query("DELETE FROM ldap_groups_synchronized_groups WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use crate::members::CreateMemberDto;
+    use crate::repository::Repository;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture};
+    use op_core::traits::Id;
+
+    /// Sorted roles of the user in the project
+    async fn roles(db: &TestDb, project: Id, user: Id) -> Option<Vec<Id>> {
+        let member = db.members().find_by_project_and_user(project, user).await.unwrap()?;
+        let mut role_ids = member.role_ids;
+        role_ids.sort();
+        Some(role_ids)
+    }
+
+    #[tokio::test]
+    async fn test_members_inherit_the_group_memberships() {
+        let db = TestDb::connect().await;
+        let repo = db.groups();
+        let group = db.insert_user(UserFixture::group("Developers")).await;
+        let alice = db.insert_user(UserFixture::new("alice")).await;
+        let bob = db.insert_user(UserFixture::new("bob")).await;
+        let project = db.insert_project(ProjectFixture::new("demo")).await;
+        let developer = db.insert_role("Developer", &["view_work_packages"]).await;
+        let reviewer = db.insert_role("Reviewer", &["view_work_packages"]).await;
+        db.members()
+            .create(CreateMemberDto {
+                user_id: group,
+                project_id: Some(project),
+                role_ids: vec![developer, reviewer],
+                entity_type: None,
+                entity_id: None,
+            })
+            .await
+            .unwrap();
+        // Bob is a developer on his own
+        db.members()
+            .create(CreateMemberDto {
+                user_id: bob,
+                project_id: Some(project),
+                role_ids: vec![developer],
+                entity_type: None,
+                entity_id: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(repo.add_users(group, &[alice, bob]).await.unwrap(), 2);
+        assert_eq!(repo.add_users(group, &[alice]).await.unwrap(), 0);
+        assert_eq!(repo.member_ids(group).await.unwrap(), vec![alice, bob]);
+        assert_eq!(roles(&db, project, alice).await, Some(vec![developer, reviewer]));
+        assert_eq!(roles(&db, project, bob).await, Some(vec![developer, reviewer]));
+
+        assert_eq!(repo.remove_users(group, &[alice, bob]).await.unwrap(), 2);
+        assert!(repo.member_ids(group).await.unwrap().is_empty());
+        assert_eq!(roles(&db, project, alice).await, None);
+        assert_eq!(roles(&db, project, bob).await, Some(vec![developer]));
+    }
+
+    #[tokio::test]
+    async fn test_synchronized_groups_follow_renames() {
+        let db = TestDb::connect().await;
+        let repo = db.groups();
+
+        let created = repo
+            .create_synchronized("corp", "uuid-1", "cn=devs,ou=groups,dc=example,dc=com", "devs")
+            .await
+            .unwrap();
+        repo.update_synchronized(created.id, "cn=developers,ou=groups,dc=example,dc=com", "developers")
+            .await
+            .unwrap();
+
+        let groups = repo.synchronized_groups("corp").await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].group_id, created.group_id);
+        assert_eq!(groups[0].name, "developers");
+        assert_eq!(groups[0].dn, "cn=developers,ou=groups,dc=example,dc=com");
+        assert!(repo.synchronized_groups("other").await.unwrap().is_empty());
+
+        repo.delete_synchronized(created.id).await.unwrap();
+        assert!(repo.synchronized_groups("corp").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,405 @@
+//! Idempotency keys
+//!
+//! Clients retrying a POST that timed out send the same `Idempotency-Key`
+//! header again. The first request with a key claims it; once it succeeded,
+//! its response is stored and replayed for retries instead of running the
+//! request again. A key belongs to the user sending it and is forgotten
+//! after its lifetime.
+//!
+//! Claiming inserts the key first and relies on the unique constraint, so
+//! of simultaneous requests with the same key exactly one runs.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+
+/// Default lifetime of idempotency keys
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A request sent with an idempotency key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IdempotencyRequest {
+    pub key: String,
+    pub user_id: Id,
+    /// Hash of the method, path and body of the request
+    pub fingerprint: String,
+}
+
+/// Response stored for replaying
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: String,
+    /// Resource the request created, if any
+    pub resource_id: Option<Id>,
+}
+
+/// Outcome of claiming a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyClaim {
+    /// The key is new; run the request and store its response
+    Claimed,
+    /// The request ran before; replay its response
+    Replay(StoredResponse),
+    /// The first request with the key is still running
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+}
+
+/// Store of idempotency keys and their responses
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Claim the key of the request, unless an unexpired claim exists
+    async fn claim(&self, request: &IdempotencyRequest, ttl: Duration) -> RepositoryResult<IdempotencyClaim>;
+
+    /// Store the response of a claimed request
+    async fn complete(&self, request: &IdempotencyRequest, response: StoredResponse) -> RepositoryResult<()>;
+
+    /// Give up a claim, e.g. after the request failed, so a retry runs again
+    async fn release(&self, request: &IdempotencyRequest) -> RepositoryResult<()>;
+}
+
+/// Stored key, claimed or completed
+#[derive(Debug, Clone, FromRow)]
+pub struct IdempotencyKeyRow {
+    pub fingerprint: String,
+    pub response_status: Option<i32>,
+    pub response_content_type: Option<String>,
+    pub response_body: Option<String>,
+    pub resource_id: Option<Id>,
+}
+
+impl IdempotencyKeyRow {
+    /// What a request finding this row instead of claiming the key gets
+    fn claim_of(self, fingerprint: &str) -> IdempotencyClaim {
+        if self.fingerprint != fingerprint {
+            return IdempotencyClaim::Mismatch;
+        }
+        match self.response_status {
+            Some(status) => IdempotencyClaim::Replay(StoredResponse {
+                status: status as u16,
+                content_type: self.response_content_type,
+                body: self.response_body.unwrap_or_default(),
+                resource_id: self.resource_id,
+            }),
+            None => IdempotencyClaim::InProgress,
+        }
+    }
+}
+
+/// Idempotency keys in the `idempotency_keys` table, shared by all processes
+pub struct IdempotencyKeyRepository {
+    db: DbExecutor,
+}
+
+impl IdempotencyKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for IdempotencyKeyRepository {
+    async fn claim(&self, request: &IdempotencyRequest, ttl: Duration) -> RepositoryResult<IdempotencyClaim> {
+        let mut conn = self.db.acquire().await?;
+
+        // Expired keys may be claimed again
+        sqlx::query("DELETE FROM idempotency_keys WHERE created_at < NOW() - $1 * INTERVAL '1 second'")
+            .bind(ttl.as_secs_f64())
+            .execute(&mut *conn)
+            .await?;
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (key, user_id, fingerprint)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (key, user_id) DO NOTHING
+            "#,
+        )
+        .bind(&request.key)
+        .bind(request.user_id)
+        .bind(&request.fingerprint)
+        .execute(&mut *conn)
+        .await?;
+        if inserted.rows_affected() == 1 {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+
+        let row = sqlx::query_as::<_, IdempotencyKeyRow>(
+            r#"
+            SELECT fingerprint, response_status, response_content_type, response_body, resource_id
+            FROM idempotency_keys
+            WHERE key = $1 AND user_id = $2
+            "#,
+        )
+        .bind(&request.key)
+        .bind(request.user_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        // Released between the insert and the select; the retry may claim it
+        Ok(row.map_or(IdempotencyClaim::InProgress, |row| row.claim_of(&request.fingerprint)))
+    }
+
+    async fn complete(&self, request: &IdempotencyRequest, response: StoredResponse) -> RepositoryResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE idempotency_keys
+            SET response_status = $4, response_content_type = $5, response_body = $6, resource_id = $7
+            WHERE key = $1 AND user_id = $2 AND fingerprint = $3
+            "#,
+        )
+        .bind(&request.key)
+        .bind(request.user_id)
+        .bind(&request.fingerprint)
+        .bind(response.status as i32)
+        .bind(&response.content_type)
+        .bind(&response.body)
+        .bind(response.resource_id)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn release(&self, request: &IdempotencyRequest) -> RepositoryResult<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE key = $1 AND user_id = $2 AND fingerprint = $3 AND response_status IS NULL
+            "#,
+        )
+        .bind(&request.key)
+        .bind(request.user_id)
+        .bind(&request.fingerprint)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Keys kept at most by [`MemoryIdempotencyStore`]
+const MAX_KEYS: usize = 10_000;
+
+/// Idempotency keys of a single process
+#[derive(Default)]
+pub struct MemoryIdempotencyStore {
+    keys: Mutex<HashMap<(String, Id), MemoryKey>>,
+}
+
+struct MemoryKey {
+    claimed_at: Instant,
+    fingerprint: String,
+    response: Option<StoredResponse>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored keys, expired ones included
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn claim(&self, request: &IdempotencyRequest, ttl: Duration) -> RepositoryResult<IdempotencyClaim> {
+        let mut keys = self.keys.lock().unwrap();
+        if keys.len() >= MAX_KEYS {
+            keys.retain(|_, key| key.claimed_at.elapsed() < ttl);
+        }
+
+        let id = (request.key.clone(), request.user_id);
+        if let Some(key) = keys.get(&id).filter(|key| key.claimed_at.elapsed() < ttl) {
+            return Ok(if key.fingerprint != request.fingerprint {
+                IdempotencyClaim::Mismatch
+            } else {
+                key.response
+                    .clone()
+                    .map_or(IdempotencyClaim::InProgress, IdempotencyClaim::Replay)
+            });
+        }
+
+        keys.insert(
+            id,
+            MemoryKey {
+                claimed_at: Instant::now(),
+                fingerprint: request.fingerprint.clone(),
+                response: None,
+            },
+        );
+        Ok(IdempotencyClaim::Claimed)
+    }
+
+    async fn complete(&self, request: &IdempotencyRequest, response: StoredResponse) -> RepositoryResult<()> {
+        let mut keys = self.keys.lock().unwrap();
+        if let Some(key) = keys.get_mut(&(request.key.clone(), request.user_id)) {
+            if key.fingerprint == request.fingerprint {
+                key.response = Some(response);
+            }
+        }
+        Ok(())
+    }
+
+    async fn release(&self, request: &IdempotencyRequest) -> RepositoryResult<()> {
+        let mut keys = self.keys.lock().unwrap();
+        let id = (request.key.clone(), request.user_id);
+        if keys
+            .get(&id)
+            .is_some_and(|key| key.fingerprint == request.fingerprint && key.response.is_none())
+        {
+            keys.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn request(key: &str, fingerprint: &str) -> IdempotencyRequest {
+        IdempotencyRequest {
+            key: key.to_string(),
+            user_id: 1,
+            fingerprint: fingerprint.to_string(),
+        }
+    }
+
+    fn response() -> StoredResponse {
+        StoredResponse {
+            status: 201,
+            content_type: Some("application/hal+json".to_string()),
+            body: r#"{"id":7}"#.to_string(),
+            resource_id: Some(7),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completed_request_is_replayed() {
+        let store = MemoryIdempotencyStore::new();
+        let first = request("retry-me", "abc");
+
+        assert_eq!(store.claim(&first, DEFAULT_IDEMPOTENCY_TTL).await.unwrap(), IdempotencyClaim::Claimed);
+        assert_eq!(store.claim(&first, DEFAULT_IDEMPOTENCY_TTL).await.unwrap(), IdempotencyClaim::InProgress);
+        store.complete(&first, response()).await.unwrap();
+
+        assert_eq!(
+            store.claim(&first, DEFAULT_IDEMPOTENCY_TTL).await.unwrap(),
+            IdempotencyClaim::Replay(response())
+        );
+        assert_eq!(
+            store.claim(&request("retry-me", "other body"), DEFAULT_IDEMPOTENCY_TTL).await.unwrap(),
+            IdempotencyClaim::Mismatch
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keys_belong_to_their_user() {
+        let store = MemoryIdempotencyStore::new();
+        let mine = request("shared", "abc");
+        let theirs = IdempotencyRequest { user_id: 2, ..mine.clone() };
+
+        assert_eq!(store.claim(&mine, DEFAULT_IDEMPOTENCY_TTL).await.unwrap(), IdempotencyClaim::Claimed);
+        assert_eq!(store.claim(&theirs, DEFAULT_IDEMPOTENCY_TTL).await.unwrap(), IdempotencyClaim::Claimed);
+    }
+
+    #[tokio::test]
+    async fn test_released_and_expired_keys_can_be_claimed_again() {
+        let store = MemoryIdempotencyStore::new();
+        let failed = request("failed", "abc");
+        store.claim(&failed, DEFAULT_IDEMPOTENCY_TTL).await.unwrap();
+        store.release(&failed).await.unwrap();
+        assert_eq!(store.claim(&failed, DEFAULT_IDEMPOTENCY_TTL).await.unwrap(), IdempotencyClaim::Claimed);
+
+        let done = request("done", "abc");
+        store.claim(&done, DEFAULT_IDEMPOTENCY_TTL).await.unwrap();
+        store.complete(&done, response()).await.unwrap();
+        // Completed keys are kept until they expire
+        store.release(&done).await.unwrap();
+        assert!(matches!(store.claim(&done, DEFAULT_IDEMPOTENCY_TTL).await.unwrap(), IdempotencyClaim::Replay(_)));
+        assert_eq!(store.claim(&done, Duration::ZERO).await.unwrap(), IdempotencyClaim::Claimed);
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_claims_let_one_request_run() {
+        let store = Arc::new(MemoryIdempotencyStore::new());
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move { store.claim(&request("race", "abc"), DEFAULT_IDEMPOTENCY_TTL).await.unwrap() })
+            })
+            .collect();
+
+        let mut claimed = 0;
+        for task in tasks {
+            if task.await.unwrap() == IdempotencyClaim::Claimed {
+                claimed += 1;
+            }
+        }
+        assert_eq!(claimed, 1);
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::TestDb;
+
+    #[tokio::test]
+    async fn test_keys_are_claimed_once_and_replayed() {
+        let db = TestDb::connect().await;
+        let repo = db.idempotency_keys();
+        let request = IdempotencyRequest {
+            key: "create-wp-1".to_string(),
+            user_id: 1,
+            fingerprint: "abc".to_string(),
+        };
+        let ttl = DEFAULT_IDEMPOTENCY_TTL;
+
+        assert_eq!(repo.claim(&request, ttl).await.unwrap(), IdempotencyClaim::Claimed);
+        assert_eq!(repo.claim(&request, ttl).await.unwrap(), IdempotencyClaim::InProgress);
+        let other_body = IdempotencyRequest { fingerprint: "def".to_string(), ..request.clone() };
+        assert_eq!(repo.claim(&other_body, ttl).await.unwrap(), IdempotencyClaim::Mismatch);
+
+        let response = StoredResponse {
+            status: 201,
+            content_type: Some("application/hal+json; charset=utf-8".to_string()),
+            body: r#"{"id":42}"#.to_string(),
+            resource_id: Some(42),
+        };
+        repo.complete(&request, response.clone()).await.unwrap();
+        repo.release(&request).await.unwrap();
+        assert_eq!(repo.claim(&request, ttl).await.unwrap(), IdempotencyClaim::Replay(response));
+
+        // Another user's key of the same name is unrelated
+        let other_user = IdempotencyRequest { user_id: 2, ..request.clone() };
+        assert_eq!(repo.claim(&other_user, ttl).await.unwrap(), IdempotencyClaim::Claimed);
+        repo.release(&other_user).await.unwrap();
+        assert_eq!(repo.claim(&other_user, ttl).await.unwrap(), IdempotencyClaim::Claimed);
+    }
+}
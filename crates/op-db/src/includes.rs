@@ -0,0 +1,354 @@
+//! Include Resolution
+//!
+//! Resolves the resources referenced by a page of work packages so they can
+//! be embedded without joining them into the work package query. Each
+//! requested resource type is loaded with a single query over the distinct
+//! ids on the page, so the number of queries does not grow with page size.
+
+use std::collections::{BTreeSet, HashMap};
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+use sqlx::PgPool;
+
+use crate::priorities::{PriorityRepository, PriorityRow};
+use crate::projects::{ProjectRepository, ProjectRow};
+use crate::query_executor::WorkPackageRow;
+use crate::repository::RepositoryResult;
+use crate::statuses::{StatusRepository, StatusRow};
+use crate::types::{TypeRepository, TypeRow};
+use crate::users::{UserRepository, UserRow};
+use crate::versions::{VersionRepository, VersionRow};
+
+/// Resource types to resolve for a page of work packages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Includes {
+    pub statuses: bool,
+    pub types: bool,
+    pub priorities: bool,
+    /// Authors, assignees and accountable users
+    pub users: bool,
+    pub projects: bool,
+    pub versions: bool,
+}
+
+impl Includes {
+    /// Whether nothing needs to be resolved
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Batch loader for included resources, one call per resource type
+#[async_trait]
+pub trait IncludeLoader: Send + Sync {
+    async fn load_statuses(&self, ids: &[Id]) -> RepositoryResult<Vec<StatusRow>>;
+    async fn load_types(&self, ids: &[Id]) -> RepositoryResult<Vec<TypeRow>>;
+    async fn load_priorities(&self, ids: &[Id]) -> RepositoryResult<Vec<PriorityRow>>;
+    async fn load_users(&self, ids: &[Id]) -> RepositoryResult<Vec<UserRow>>;
+    async fn load_projects(&self, ids: &[Id]) -> RepositoryResult<Vec<ProjectRow>>;
+    async fn load_versions(&self, ids: &[Id]) -> RepositoryResult<Vec<VersionRow>>;
+}
+
+/// Loader backed by the repositories
+pub struct PgIncludeLoader {
+    pool: PgPool,
+}
+
+impl PgIncludeLoader {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl IncludeLoader for PgIncludeLoader {
+    async fn load_statuses(&self, ids: &[Id]) -> RepositoryResult<Vec<StatusRow>> {
+        StatusRepository::new(self.pool.clone()).find_by_ids(ids).await
+    }
+
+    async fn load_types(&self, ids: &[Id]) -> RepositoryResult<Vec<TypeRow>> {
+        TypeRepository::new(self.pool.clone()).find_by_ids(ids).await
+    }
+
+    async fn load_priorities(&self, ids: &[Id]) -> RepositoryResult<Vec<PriorityRow>> {
+        PriorityRepository::new(self.pool.clone()).find_by_ids(ids).await
+    }
+
+    async fn load_users(&self, ids: &[Id]) -> RepositoryResult<Vec<UserRow>> {
+        UserRepository::new(self.pool.clone()).find_by_ids(ids).await
+    }
+
+    async fn load_projects(&self, ids: &[Id]) -> RepositoryResult<Vec<ProjectRow>> {
+        ProjectRepository::new(self.pool.clone()).find_by_ids(ids).await
+    }
+
+    async fn load_versions(&self, ids: &[Id]) -> RepositoryResult<Vec<VersionRow>> {
+        VersionRepository::new(self.pool.clone()).find_by_ids(ids).await
+    }
+}
+
+/// Lookup maps of the resolved resources
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedIncludes {
+    pub statuses: HashMap<Id, StatusRow>,
+    pub types: HashMap<Id, TypeRow>,
+    pub priorities: HashMap<Id, PriorityRow>,
+    pub users: HashMap<Id, UserRow>,
+    pub projects: HashMap<Id, ProjectRow>,
+    pub versions: HashMap<Id, VersionRow>,
+}
+
+impl ResolvedIncludes {
+    pub fn status(&self, id: Id) -> Option<&StatusRow> {
+        self.statuses.get(&id)
+    }
+
+    pub fn work_package_type(&self, id: Id) -> Option<&TypeRow> {
+        self.types.get(&id)
+    }
+
+    pub fn priority(&self, id: Option<Id>) -> Option<&PriorityRow> {
+        id.and_then(|id| self.priorities.get(&id))
+    }
+
+    pub fn user(&self, id: Option<Id>) -> Option<&UserRow> {
+        id.and_then(|id| self.users.get(&id))
+    }
+
+    pub fn project(&self, id: Id) -> Option<&ProjectRow> {
+        self.projects.get(&id)
+    }
+
+    pub fn version(&self, id: Option<Id>) -> Option<&VersionRow> {
+        id.and_then(|id| self.versions.get(&id))
+    }
+}
+
+/// Resolves the includes of a page of work packages
+pub struct IncludeResolver<L: IncludeLoader> {
+    loader: L,
+}
+
+impl<L: IncludeLoader> IncludeResolver<L> {
+    pub fn new(loader: L) -> Self {
+        Self { loader }
+    }
+
+    /// Load the requested resources referenced by the rows
+    pub async fn resolve(
+        &self,
+        rows: &[WorkPackageRow],
+        includes: Includes,
+    ) -> RepositoryResult<ResolvedIncludes> {
+        let mut resolved = ResolvedIncludes::default();
+
+        if includes.statuses {
+            let ids = distinct_ids(rows.iter().map(|wp| Some(wp.status_id)));
+            if !ids.is_empty() {
+                resolved.statuses = by_id(self.loader.load_statuses(&ids).await?, |s| s.id);
+            }
+        }
+        if includes.types {
+            let ids = distinct_ids(rows.iter().map(|wp| Some(wp.type_id)));
+            if !ids.is_empty() {
+                resolved.types = by_id(self.loader.load_types(&ids).await?, |t| t.id);
+            }
+        }
+        if includes.priorities {
+            let ids = distinct_ids(rows.iter().map(|wp| wp.priority_id));
+            if !ids.is_empty() {
+                resolved.priorities = by_id(self.loader.load_priorities(&ids).await?, |p| p.id);
+            }
+        }
+        if includes.users {
+            let ids = distinct_ids(
+                rows.iter()
+                    .flat_map(|wp| [wp.author_id, wp.assigned_to_id, wp.responsible_id]),
+            );
+            if !ids.is_empty() {
+                resolved.users = by_id(self.loader.load_users(&ids).await?, |u| u.id);
+            }
+        }
+        if includes.projects {
+            let ids = distinct_ids(rows.iter().map(|wp| Some(wp.project_id)));
+            if !ids.is_empty() {
+                resolved.projects = by_id(self.loader.load_projects(&ids).await?, |p| p.id);
+            }
+        }
+        if includes.versions {
+            let ids = distinct_ids(rows.iter().map(|wp| wp.version_id));
+            if !ids.is_empty() {
+                resolved.versions = by_id(self.loader.load_versions(&ids).await?, |v| v.id);
+            }
+        }
+
+        Ok(resolved)
+    }
+}
+
+fn distinct_ids(ids: impl Iterator<Item = Option<Id>>) -> Vec<Id> {
+    ids.flatten().collect::<BTreeSet<_>>().into_iter().collect()
+}
+
+fn by_id<T>(rows: Vec<T>, id: impl Fn(&T) -> Id) -> HashMap<Id, T> {
+    rows.into_iter().map(|row| (id(&row), row)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::sync::Mutex;
+
+    /// Loader recording every batch it is asked for, standing in for a
+    /// counting pool wrapper
+    #[derive(Default)]
+    struct CountingLoader {
+        calls: Mutex<Vec<(&'static str, Vec<Id>)>>,
+    }
+
+    impl CountingLoader {
+        fn record(&self, kind: &'static str, ids: &[Id]) {
+            self.calls.lock().unwrap().push((kind, ids.to_vec()));
+        }
+
+        fn queries(&self) -> usize {
+            self.calls.lock().unwrap().len()
+        }
+    }
+
+    #[async_trait]
+    impl IncludeLoader for CountingLoader {
+        async fn load_statuses(&self, ids: &[Id]) -> RepositoryResult<Vec<StatusRow>> {
+            self.record("statuses", ids);
+            Ok(Vec::new())
+        }
+
+        async fn load_types(&self, ids: &[Id]) -> RepositoryResult<Vec<TypeRow>> {
+            self.record("types", ids);
+            Ok(Vec::new())
+        }
+
+        async fn load_priorities(&self, ids: &[Id]) -> RepositoryResult<Vec<PriorityRow>> {
+            self.record("priorities", ids);
+            Ok(Vec::new())
+        }
+
+        async fn load_users(&self, ids: &[Id]) -> RepositoryResult<Vec<UserRow>> {
+            self.record("users", ids);
+            let now = Utc::now();
+            Ok(ids
+                .iter()
+                .map(|&id| UserRow {
+                    id,
+                    principal_type: crate::users::principal_type::USER.to_string(),
+                    login: format!("user{}", id),
+                    firstname: "User".to_string(),
+                    lastname: id.to_string(),
+                    mail: format!("user{}@example.com", id),
+                    admin: false,
+                    status: 1,
+                    language: None,
+                    hashed_password: None,
+                    salt: None,
+                    created_at: now,
+                    updated_at: now,
+                    last_login_on: None,
+                })
+                .collect())
+        }
+
+        async fn load_projects(&self, ids: &[Id]) -> RepositoryResult<Vec<ProjectRow>> {
+            self.record("projects", ids);
+            Ok(Vec::new())
+        }
+
+        async fn load_versions(&self, ids: &[Id]) -> RepositoryResult<Vec<VersionRow>> {
+            self.record("versions", ids);
+            Ok(Vec::new())
+        }
+    }
+
+    fn rows(count: i64) -> Vec<WorkPackageRow> {
+        let now = Utc::now();
+        (1..=count)
+            .map(|id| WorkPackageRow {
+                id,
+                subject: format!("Work package {}", id),
+                description: None,
+                project_id: 1 + id % 3,
+                type_id: 1,
+                status_id: 1 + id % 5,
+                priority_id: Some(8),
+                author_id: Some(1),
+                assigned_to_id: Some(1 + id % 4),
+                responsible_id: None,
+                category_id: None,
+                version_id: (id % 2 == 0).then_some(7),
+                parent_id: None,
+                start_date: None,
+                due_date: None,
+                estimated_hours: None,
+                done_ratio: 0,
+                lock_version: 0,
+                created_at: now,
+                updated_at: now,
+                position: None,
+                story_points: None,
+                remaining_hours: None,
+                schedule_manually: false,
+                duration: None,
+                ignore_non_working_days: false,
+            })
+            .collect()
+    }
+
+    fn all() -> Includes {
+        Includes {
+            statuses: true,
+            types: true,
+            priorities: true,
+            users: true,
+            projects: true,
+            versions: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_count_is_independent_of_page_size() {
+        let mut counts = Vec::new();
+        for page_size in [2, 10, 500] {
+            let resolver = IncludeResolver::new(CountingLoader::default());
+            resolver.resolve(&rows(page_size), all()).await.unwrap();
+            counts.push(resolver.loader.queries());
+        }
+
+        assert_eq!(counts, vec![6, 6, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_resolves_distinct_ids() {
+        let resolver = IncludeResolver::new(CountingLoader::default());
+        let includes = Includes {
+            users: true,
+            ..Default::default()
+        };
+        let resolved = resolver.resolve(&rows(20), includes).await.unwrap();
+
+        let calls = resolver.loader.calls.lock().unwrap().clone();
+        assert_eq!(calls, vec![("users", vec![1, 2, 3, 4])]);
+        assert_eq!(resolved.user(Some(3)).unwrap().login, "user3");
+        assert!(resolved.user(None).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_nothing_loaded_without_includes() {
+        let resolver = IncludeResolver::new(CountingLoader::default());
+        assert!(Includes::default().is_empty());
+
+        resolver.resolve(&rows(50), Includes::default()).await.unwrap();
+        resolver.resolve(&[], all()).await.unwrap();
+        assert_eq!(resolver.loader.queries(), 0);
+    }
+}
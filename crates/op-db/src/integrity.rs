@@ -0,0 +1,533 @@
+//! Referential integrity audit
+//!
+//! Rows outliving what they reference accumulate wherever the schema has no
+//! foreign key: polymorphic references such as journals of work packages,
+//! and Rails-managed databases declaring few constraints at all. Each
+//! repository module lists the references it owns as [`OrphanCheck`]s;
+//! [`orphan_checks`] collects them, dependents after the rows they hang
+//! off, so an audit finds the orphans of every known relationship.
+//!
+//! An audit counts the orphans of each check along with a sample of their
+//! ids. Repairs apply the check's [`OrphanAction`] in bounded batches, each
+//! in its own transaction, and are only run when asked for; a failing check
+//! is reported without stopping the others.
+
+use op_core::traits::Id;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::repository::RepositoryResult;
+use crate::users::{deleted_user_placeholder, OrphanAction, UserReference, USER_REFERENCES};
+
+/// A column referencing the rows of another table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrphanCheck {
+    pub table: &'static str,
+    pub column: &'static str,
+    /// Table of the referenced rows
+    pub parent: &'static str,
+    /// Type column and the type of `parent` for polymorphic references
+    pub polymorphic: Option<(&'static str, &'static str)>,
+    pub action: OrphanAction,
+}
+
+impl OrphanCheck {
+    pub const fn new(table: &'static str, column: &'static str, parent: &'static str, action: OrphanAction) -> Self {
+        Self {
+            table,
+            column,
+            parent,
+            polymorphic: None,
+            action,
+        }
+    }
+
+    /// Reference to `parent` in the rows whose `type_column` is `type_name`
+    pub const fn polymorphic(
+        table: &'static str,
+        column: &'static str,
+        type_column: &'static str,
+        type_name: &'static str,
+        parent: &'static str,
+        action: OrphanAction,
+    ) -> Self {
+        Self {
+            table,
+            column,
+            parent,
+            polymorphic: Some((type_column, type_name)),
+            action,
+        }
+    }
+
+    /// `table.column`, followed by the type of polymorphic references, e.g.
+    /// `journals.journable_id[WorkPackage]`
+    pub fn name(&self) -> String {
+        match self.polymorphic {
+            Some((_, type_name)) => format!("{}.{}[{}]", self.table, self.column, type_name),
+            None => format!("{}.{}", self.table, self.column),
+        }
+    }
+
+    /// Condition on `t`, a row of `table`, holding for orphans
+    fn orphan_condition(&self) -> String {
+        let polymorphic = match self.polymorphic {
+            Some((type_column, type_name)) => format!(" AND t.{} = '{}'", type_column, type_name),
+            None => String::new(),
+        };
+        format!(
+            "t.{c} IS NOT NULL{polymorphic} AND NOT EXISTS (SELECT 1 FROM {p} p WHERE p.id = t.{c})",
+            c = self.column,
+            p = self.parent,
+        )
+    }
+
+    fn count_sql(&self) -> String {
+        format!("SELECT COUNT(*) FROM {} t WHERE {}", self.table, self.orphan_condition())
+    }
+
+    /// Ids of the first `$1` orphans
+    fn ids_sql(&self) -> String {
+        format!("SELECT t.id FROM {} t WHERE {} ORDER BY t.id LIMIT $1", self.table, self.orphan_condition())
+    }
+
+    /// Statement repairing the orphans of ids `$1`; reassignments bind the
+    /// deleted user placeholder as `$2`
+    fn repair_sql(&self) -> String {
+        match self.action {
+            OrphanAction::Reassign => format!("UPDATE {} SET {} = $2 WHERE id = ANY($1)", self.table, self.column),
+            OrphanAction::Nullify => format!("UPDATE {} SET {} = NULL WHERE id = ANY($1)", self.table, self.column),
+            OrphanAction::Delete => format!("DELETE FROM {} WHERE id = ANY($1)", self.table),
+        }
+    }
+}
+
+impl From<UserReference> for OrphanCheck {
+    fn from(reference: UserReference) -> Self {
+        Self::new(reference.table, reference.column, "users", reference.action)
+    }
+}
+
+/// Rows removed along with the row of `parent` they reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DependentRows {
+    pub parent: &'static str,
+    pub table: &'static str,
+    pub column: &'static str,
+}
+
+/// Rows deleted before the orphans they reference are, as their foreign
+/// keys would prevent the deletion otherwise
+pub const DEPENDENT_ROWS: &[DependentRows] = &[
+    DependentRows { parent: "members", table: "member_roles", column: "member_id" },
+];
+
+/// Checks of all known relationships, in the order they are audited
+pub fn orphan_checks() -> Vec<OrphanCheck> {
+    let mut checks: Vec<OrphanCheck> = USER_REFERENCES.iter().copied().map(OrphanCheck::from).collect();
+    for registered in [
+        crate::projects::PROJECT_ORPHANS,
+        crate::work_packages::WORK_PACKAGE_ORPHANS,
+        crate::relations::RELATION_ORPHANS,
+        crate::time_entries::TIME_ENTRY_ORPHANS,
+        crate::members::MEMBER_ORPHANS,
+        crate::journals::JOURNAL_ORPHANS,
+        crate::watchers::WATCHER_ORPHANS,
+        crate::attachments::ATTACHMENT_ORPHANS,
+        crate::file_links::FILE_LINK_ORPHANS,
+        crate::custom_fields::CUSTOM_VALUE_ORPHANS,
+        crate::notifications::NOTIFICATION_ORPHANS,
+    ] {
+        checks.extend_from_slice(registered);
+    }
+    checks
+}
+
+/// How an audit runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegrityOptions {
+    /// Repair the orphans instead of only reporting them
+    pub repair: bool,
+    /// Orphans repaired per transaction
+    pub batch_size: i64,
+    /// Batches per check, leaving the rest for the next run
+    pub max_batches: u32,
+    /// Orphan ids reported per check
+    pub sample_size: i64,
+}
+
+impl Default for IntegrityOptions {
+    fn default() -> Self {
+        Self {
+            repair: false,
+            batch_size: 500,
+            max_batches: 20,
+            sample_size: 10,
+        }
+    }
+}
+
+/// Orphans found and repaired by one check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrphanCount {
+    pub check: String,
+    pub references: &'static str,
+    pub action: OrphanAction,
+    /// Orphans found before repairing
+    pub orphans: i64,
+    pub sample_ids: Vec<Id>,
+    pub repaired: u64,
+    /// Why the check could not be run or its repair failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of an audit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IntegrityReport {
+    pub dry_run: bool,
+    /// Orphans found by all checks
+    pub orphans: i64,
+    pub repaired: u64,
+    pub checks: Vec<OrphanCount>,
+}
+
+impl IntegrityReport {
+    /// Result of the check named `name`
+    pub fn check(&self, name: &str) -> Option<&OrphanCount> {
+        self.checks.iter().find(|count| count.check == name)
+    }
+
+    /// Checks that failed
+    pub fn errors(&self) -> impl Iterator<Item = &OrphanCount> {
+        self.checks.iter().filter(|count| count.error.is_some())
+    }
+}
+
+/// Repository auditing references between tables
+pub struct IntegrityRepository {
+    db: DbExecutor,
+}
+
+impl IntegrityRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Run all registered checks
+    pub async fn audit(&self, options: IntegrityOptions) -> RepositoryResult<IntegrityReport> {
+        self.audit_checks(&orphan_checks(), options).await
+    }
+
+    /// Run the checks in order, repairing their orphans if asked to
+    pub async fn audit_checks(&self, checks: &[OrphanCheck], options: IntegrityOptions) -> RepositoryResult<IntegrityReport> {
+        let mut report = IntegrityReport {
+            dry_run: !options.repair,
+            orphans: 0,
+            repaired: 0,
+            checks: Vec::with_capacity(checks.len()),
+        };
+
+        for check in checks {
+            let mut count = OrphanCount {
+                check: check.name(),
+                references: check.parent,
+                action: check.action,
+                orphans: 0,
+                sample_ids: Vec::new(),
+                repaired: 0,
+                error: None,
+            };
+            if let Err(e) = self.run_check(check, options, &mut count).await {
+                tracing::warn!(check = %count.check, error = %e, "Integrity check failed");
+                count.error = Some(e.to_string());
+            }
+            report.orphans += count.orphans;
+            report.repaired += count.repaired;
+            report.checks.push(count);
+        }
+
+        Ok(report)
+    }
+
+    async fn run_check(&self, check: &OrphanCheck, options: IntegrityOptions, count: &mut OrphanCount) -> RepositoryResult<()> {
+        // Savepoint, so a failing check leaves a surrounding transaction usable
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        count.orphans = sqlx::query_scalar::<_, i64>(&check.count_sql()).fetch_one(&mut *tx).await?;
+        if count.orphans > 0 && options.sample_size > 0 {
+            count.sample_ids = sqlx::query_scalar::<_, Id>(&check.ids_sql())
+                .bind(options.sample_size)
+                .fetch_all(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        if !options.repair || count.orphans == 0 {
+            return Ok(());
+        }
+        for _ in 0..options.max_batches {
+            let mut tx = DbTransaction::begin(&self.db).await?;
+            let repaired = repair_batch(&mut tx, check, options.batch_size).await?;
+            tx.commit().await?;
+
+            count.repaired += repaired;
+            if repaired < options.batch_size as u64 {
+                break;
+            }
+        }
+        if count.repaired > 0 {
+            tracing::info!(check = %count.check, repaired = count.repaired, "Repaired orphaned rows");
+        }
+        Ok(())
+    }
+}
+
+/// Repair the next `limit` orphans of the check
+async fn repair_batch(conn: &mut PgConnection, check: &OrphanCheck, limit: i64) -> RepositoryResult<u64> {
+    let ids = sqlx::query_scalar::<_, Id>(&check.ids_sql()).bind(limit).fetch_all(&mut *conn).await?;
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    let sql = check.repair_sql();
+    let result = match check.action {
+        OrphanAction::Reassign => {
+            let placeholder_id = deleted_user_placeholder(conn).await?;
+            sqlx::query(&sql).bind(&ids).bind(placeholder_id).execute(&mut *conn).await?
+        }
+        OrphanAction::Nullify => sqlx::query(&sql).bind(&ids).execute(&mut *conn).await?,
+        OrphanAction::Delete => {
+            for dependent in DEPENDENT_ROWS.iter().filter(|dependent| dependent.parent == check.table) {
+                sqlx::query(&format!("DELETE FROM {} WHERE {} = ANY($1)", dependent.table, dependent.column))
+                    .bind(&ids)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            sqlx::query(&sql).bind(&ids).execute(&mut *conn).await?
+        }
+    };
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphan_condition() {
+        let check = OrphanCheck::polymorphic("journals", "journable_id", "journable_type", "WorkPackage", "work_packages", OrphanAction::Delete);
+        assert_eq!(check.name(), "journals.journable_id[WorkPackage]");
+        assert_eq!(
+            check.orphan_condition(),
+            "t.journable_id IS NOT NULL AND t.journable_type = 'WorkPackage' \
+             AND NOT EXISTS (SELECT 1 FROM work_packages p WHERE p.id = t.journable_id)"
+        );
+
+        let check = OrphanCheck::new("work_packages", "parent_id", "work_packages", OrphanAction::Nullify);
+        assert_eq!(check.repair_sql(), "UPDATE work_packages SET parent_id = NULL WHERE id = ANY($1)");
+    }
+
+    #[test]
+    fn test_checks_are_registered_once_and_dependents_come_last() {
+        let checks = orphan_checks();
+        let names: Vec<String> = checks.iter().map(OrphanCheck::name).collect();
+        let mut unique = names.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), names.len());
+
+        let position = |name: &str| names.iter().position(|n| n == name).unwrap();
+        assert!(position("members.user_id") < position("member_roles.member_id"));
+        assert!(position("members.project_id") < position("member_roles.member_id"));
+        assert!(names.contains(&"watchers.watchable_id[WorkPackage]".to_string()));
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    async fn execute(db: &TestDb, sql: &str) {
+        sqlx::query(sql).execute(&mut *db.executor().acquire().await.unwrap()).await.unwrap();
+    }
+
+    async fn insert(db: &TestDb, sql: &str) -> Id {
+        sqlx::query_scalar(sql).fetch_one(&mut *db.executor().acquire().await.unwrap()).await.unwrap()
+    }
+
+    async fn exists(db: &TestDb, table: &str, id: Id) -> bool {
+        sqlx::query_scalar(&format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = $1)", table))
+            .bind(id)
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_audit_detects_and_repairs_orphans() {
+        let db = TestDb::connect().await;
+        // Like Rails-managed databases, without the constraints preventing orphans
+        db.disable_foreign_keys().await;
+
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("demo")).await;
+        let work_package = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let child = db.insert_work_package(WorkPackageFixture::new(project, author).with_parent(work_package)).await;
+        let role = insert(&db, "INSERT INTO roles (name) VALUES ('Member') RETURNING id").await;
+
+        let kept_journal = insert(&db, &format!(
+            "INSERT INTO journals (journable_type, journable_id, user_id, version, data_type, data_id) \
+             VALUES ('WorkPackage', {}, {}, 1, 'Journal::WorkPackageJournal', 0) RETURNING id",
+            work_package, author,
+        ))
+        .await;
+        let orphaned_journal = insert(&db, &format!(
+            "INSERT INTO journals (journable_type, journable_id, user_id, version, data_type, data_id) \
+             VALUES ('WorkPackage', 999999, {}, 1, 'Journal::WorkPackageJournal', 0) RETURNING id",
+            author,
+        ))
+        .await;
+        let orphaned_member_role = insert(&db, &format!(
+            "INSERT INTO member_roles (member_id, role_id) VALUES (999999, {}) RETURNING id",
+            role,
+        ))
+        .await;
+        let orphaned_member = insert(&db, &format!(
+            "INSERT INTO members (user_id, project_id) VALUES ({}, 999999) RETURNING id",
+            author,
+        ))
+        .await;
+        let role_of_orphaned_member = insert(&db, &format!(
+            "INSERT INTO member_roles (member_id, role_id) VALUES ({}, {}) RETURNING id",
+            orphaned_member, role,
+        ))
+        .await;
+        let orphaned_watcher = insert(&db, &format!(
+            "INSERT INTO watchers (watchable_type, watchable_id, user_id) VALUES ('WorkPackage', {}, 999999) RETURNING id",
+            work_package,
+        ))
+        .await;
+        let orphaned_attachment = insert(&db, &format!(
+            "INSERT INTO attachments (container_type, container_id, filename, author_id) \
+             VALUES ('WorkPackage', 999999, 'lost.txt', {}) RETURNING id",
+            author,
+        ))
+        .await;
+        execute(&db, &format!("UPDATE work_packages SET parent_id = 999999 WHERE id = {}", child)).await;
+
+        // Dry run by default: reported, but left as they are
+        let repository = IntegrityRepository::with_executor(db.executor());
+        let report = repository.audit(IntegrityOptions::default()).await.unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.errors().count(), 0, "{:?}", report.errors().collect::<Vec<_>>());
+        assert_eq!(report.repaired, 0);
+        assert_eq!(report.orphans, 6);
+        let journals = report.check("journals.journable_id[WorkPackage]").unwrap();
+        assert_eq!((journals.orphans, journals.sample_ids.clone()), (1, vec![orphaned_journal]));
+        assert_eq!(report.check("member_roles.member_id").unwrap().sample_ids, vec![orphaned_member_role]);
+        assert_eq!(report.check("members.project_id").unwrap().sample_ids, vec![orphaned_member]);
+        assert_eq!(report.check("watchers.user_id").unwrap().sample_ids, vec![orphaned_watcher]);
+        assert_eq!(report.check("attachments.container_id[WorkPackage]").unwrap().sample_ids, vec![orphaned_attachment]);
+        assert_eq!(report.check("work_packages.parent_id").unwrap().sample_ids, vec![child]);
+        assert!(exists(&db, "journals", orphaned_journal).await);
+
+        // Repaired in batches of one
+        let options = IntegrityOptions {
+            repair: true,
+            batch_size: 1,
+            ..Default::default()
+        };
+        let report = repository.audit(options).await.unwrap();
+        assert_eq!(report.errors().count(), 0, "{:?}", report.errors().collect::<Vec<_>>());
+        assert!(!report.dry_run);
+        assert_eq!(report.repaired, 6);
+        assert!(!exists(&db, "journals", orphaned_journal).await);
+        assert!(exists(&db, "journals", kept_journal).await);
+        assert!(!exists(&db, "member_roles", orphaned_member_role).await);
+        assert!(!exists(&db, "members", orphaned_member).await);
+        assert!(!exists(&db, "member_roles", role_of_orphaned_member).await);
+        assert!(!exists(&db, "watchers", orphaned_watcher).await);
+        // Unattached, for the cleanup job to remove along with its file
+        let container_id: Option<Id> = sqlx::query_scalar("SELECT container_id FROM attachments WHERE id = $1")
+            .bind(orphaned_attachment)
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(container_id, None);
+        let parent_id: Option<Id> = sqlx::query_scalar("SELECT parent_id FROM work_packages WHERE id = $1")
+            .bind(child)
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(parent_id, None);
+
+        let report = repository.audit(IntegrityOptions::default()).await.unwrap();
+        assert_eq!(report.orphans, 0);
+    }
+
+    #[tokio::test]
+    async fn test_repairs_are_bounded_and_reassign_to_the_deleted_user() {
+        let db = TestDb::connect().await;
+        db.disable_foreign_keys().await;
+
+        let project = db.insert_project(ProjectFixture::new("demo")).await;
+        for version in 1..=3 {
+            execute(&db, &format!(
+                "INSERT INTO journals (journable_type, journable_id, user_id, version, data_type, data_id) \
+                 VALUES ('Project', {}, 999999, {}, 'Journal::ProjectJournal', 0)",
+                project, version,
+            ))
+            .await;
+        }
+
+        let checks = [OrphanCheck::from(USER_REFERENCES[3])];
+        assert_eq!(checks[0].name(), "journals.user_id");
+        let repository = IntegrityRepository::with_executor(db.executor());
+        let options = IntegrityOptions {
+            repair: true,
+            batch_size: 1,
+            max_batches: 2,
+            sample_size: 0,
+        };
+        let report = repository.audit_checks(&checks, options).await.unwrap();
+        assert_eq!(report.orphans, 3);
+        assert_eq!(report.repaired, 2);
+        assert!(report.checks[0].sample_ids.is_empty());
+
+        let report = repository.audit_checks(&checks, options).await.unwrap();
+        assert_eq!((report.orphans, report.repaired), (1, 1));
+        let authors: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT u.type FROM journals j JOIN users u ON u.id = j.user_id WHERE j.journable_id = $1",
+        )
+        .bind(project)
+        .fetch_all(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+        assert_eq!(authors, vec!["DeletedUser".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_checks_are_reported_without_stopping_the_audit() {
+        let db = TestDb::connect().await;
+        let checks = [
+            OrphanCheck::new("missing_table", "parent_id", "work_packages", OrphanAction::Delete),
+            OrphanCheck::new("work_packages", "parent_id", "work_packages", OrphanAction::Nullify),
+        ];
+
+        let report = IntegrityRepository::with_executor(db.executor())
+            .audit_checks(&checks, IntegrityOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(report.errors().count(), 1);
+        assert!(report.checks[0].error.as_deref().unwrap().contains("missing_table"));
+        assert_eq!(report.checks[1].error, None);
+    }
+}
@@ -3,13 +3,18 @@
 //! Maps to Rails: app/models/journal.rb
 //! Table: journals (polymorphic journable_type, journable_id)
 
+use std::sync::Mutex;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use sqlx::{FromRow, PgPool, Row};
 
+use crate::executor::{DbExecutor, DbTransaction};
 use crate::repository::{Pagination, PaginatedResult, Repository, RepositoryError, RepositoryResult};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
 
 /// Valid cause types for journals
 pub mod cause_type {
@@ -51,8 +56,17 @@ pub mod journable_type {
     pub const PROJECT: &str = "Project";
     pub const NEWS: &str = "News";
     pub const MESSAGE: &str = "Message";
+    pub const DOCUMENT: &str = "Document";
 }
 
+/// Journals of journaled records that are gone
+pub const JOURNAL_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::polymorphic("journals", "journable_id", "journable_type", journable_type::WORK_PACKAGE, "work_packages", OrphanAction::Delete),
+    OrphanCheck::polymorphic("journals", "journable_id", "journable_type", journable_type::PROJECT, "projects", OrphanAction::Delete),
+    OrphanCheck::polymorphic("journals", "journable_id", "journable_type", journable_type::MESSAGE, "messages", OrphanAction::Delete),
+    OrphanCheck::polymorphic("journals", "journable_id", "journable_type", journable_type::DOCUMENT, "documents", OrphanAction::Delete),
+];
+
 /// Journal row from database
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct JournalRow {
@@ -130,6 +144,18 @@ pub struct WorkPackageJournalRow {
     pub derived_done_ratio: Option<i32>,
 }
 
+/// Status and project of a work package as of one of its journals, until
+/// the next one
+#[derive(Debug, Clone, FromRow)]
+pub struct StatusTransitionRow {
+    pub work_package_id: i64,
+    pub project_id: i64,
+    pub status_id: i64,
+    pub created_at: DateTime<Utc>,
+    /// When the work package was moved to the trash, if it is in it
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
 /// Journal with associated user info
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JournalWithUser {
@@ -169,12 +195,18 @@ pub struct UpdateJournalDto {
 /// Journal repository
 #[derive(Clone)]
 pub struct JournalRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl JournalRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
     }
 
     /// Find journals by journable (work package, wiki page, etc.)
@@ -198,7 +230,7 @@ impl JournalRepository {
         .bind(journable_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total: i64 = sqlx::query_scalar(
@@ -206,7 +238,7 @@ impl JournalRepository {
         )
         .bind(journable_type)
         .bind(journable_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult {
@@ -214,6 +246,7 @@ impl JournalRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -256,12 +289,12 @@ impl JournalRepository {
         .bind(user_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM journals WHERE user_id = $1")
             .bind(user_id)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(PaginatedResult {
@@ -269,6 +302,7 @@ impl JournalRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -293,7 +327,7 @@ impl JournalRepository {
         .bind(journable_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total: i64 = sqlx::query_scalar(
@@ -301,7 +335,7 @@ impl JournalRepository {
         )
         .bind(journable_type)
         .bind(journable_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult {
@@ -309,6 +343,7 @@ impl JournalRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -325,7 +360,7 @@ impl JournalRepository {
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row.map(|r| JournalWithUser {
@@ -356,12 +391,66 @@ impl JournalRepository {
         )
         .bind(journable_type)
         .bind(journable_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(max_version.unwrap_or(0) + 1)
     }
 
+    /// Add a comment to a journable as a new journal version
+    ///
+    /// A comment changes no attributes, so the new version shares the data
+    /// of the latest one.
+    pub async fn create_comment(
+        &self,
+        journable_type: &str,
+        journable_id: i64,
+        user_id: i64,
+        notes: &str,
+    ) -> RepositoryResult<JournalRow> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let latest: Option<(i32, String, i64)> = sqlx::query_as(
+            r#"
+            SELECT version, data_type, data_id FROM journals
+            WHERE journable_type = $1 AND journable_id = $2
+            ORDER BY version DESC
+            LIMIT 1
+            FOR UPDATE
+            "#,
+        )
+        .bind(journable_type)
+        .bind(journable_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let (version, data_type, data_id) = latest.ok_or_else(|| {
+            RepositoryError::NotFound(format!("{} with id {} has no journal", journable_type, journable_id))
+        })?;
+
+        let row = sqlx::query_as::<_, JournalRow>(
+            r#"
+            INSERT INTO journals (journable_type, journable_id, user_id, notes, version,
+                                  data_type, data_id, cause, restricted, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, '{}', false, NOW(), NOW())
+            RETURNING id, journable_type, journable_id, user_id, notes, version,
+                      data_type, data_id, cause, restricted, created_at, updated_at
+            "#,
+        )
+        .bind(journable_type)
+        .bind(journable_id)
+        .bind(user_id)
+        .bind(notes)
+        .bind(version + 1)
+        .bind(&data_type)
+        .bind(data_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(row)
+    }
+
     /// Find predecessor journal
     pub async fn find_predecessor(
         &self,
@@ -386,7 +475,7 @@ impl JournalRepository {
         .bind(journable_type)
         .bind(journable_id)
         .bind(version)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?)
     }
 
@@ -410,10 +499,47 @@ impl JournalRepository {
         .bind(journable_type)
         .bind(journable_id)
         .bind(version)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?)
     }
 
+    /// Journals of the work packages that were in the project at some
+    /// point, created before `before`, ordered by work package and version.
+    /// Work packages purged from the trash took their journals with them.
+    pub async fn find_status_transitions(
+        &self,
+        project_id: i64,
+        before: DateTime<Utc>,
+    ) -> RepositoryResult<Vec<StatusTransitionRow>> {
+        let rows = sqlx::query_as::<_, StatusTransitionRow>(
+            r#"
+            SELECT j.journable_id AS work_package_id, wpj.project_id, wpj.status_id, j.created_at,
+                   wp.deleted_at
+            FROM journals j
+            JOIN work_package_journals wpj ON wpj.id = j.data_id
+            LEFT JOIN work_packages wp ON wp.id = j.journable_id
+            WHERE j.journable_type = 'WorkPackage'
+              AND j.data_type = 'Journal::WorkPackageJournal'
+              AND j.created_at < $2
+              AND j.journable_id IN (
+                  SELECT pj.journable_id
+                  FROM journals pj
+                  JOIN work_package_journals pwpj ON pwpj.id = pj.data_id
+                  WHERE pj.journable_type = 'WorkPackage'
+                    AND pj.data_type = 'Journal::WorkPackageJournal'
+                    AND pwpj.project_id = $1
+              )
+            ORDER BY j.journable_id, j.version
+            "#,
+        )
+        .bind(project_id)
+        .bind(before)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
     /// Find journals with work package data
     pub async fn find_work_package_journals_with_data(
         &self,
@@ -440,7 +566,7 @@ impl JournalRepository {
         .bind(work_package_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let items: Vec<JournalWithWorkPackageData> = rows
@@ -499,7 +625,7 @@ impl JournalRepository {
             "SELECT COUNT(*) FROM journals WHERE journable_type = 'WorkPackage' AND journable_id = $1",
         )
         .bind(work_package_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult {
@@ -507,6 +633,7 @@ impl JournalRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 }
@@ -523,7 +650,7 @@ impl Repository<JournalRow, CreateJournalDto, UpdateJournalDto> for JournalRepos
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?)
     }
 
@@ -539,7 +666,7 @@ impl Repository<JournalRow, CreateJournalDto, UpdateJournalDto> for JournalRepos
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?)
     }
 
@@ -565,7 +692,7 @@ impl Repository<JournalRow, CreateJournalDto, UpdateJournalDto> for JournalRepos
         .bind(dto.data_id)
         .bind(&cause)
         .bind(restricted)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await
         .map_err(|e| {
             if e.to_string().contains("unique constraint") {
@@ -595,14 +722,14 @@ impl Repository<JournalRow, CreateJournalDto, UpdateJournalDto> for JournalRepos
         )
         .bind(id)
         .bind(&notes)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?)
     }
 
     async fn delete(&self, id: i64) -> RepositoryResult<()> {
         let result = sqlx::query("DELETE FROM journals WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -617,19 +744,134 @@ impl Repository<JournalRow, CreateJournalDto, UpdateJournalDto> for JournalRepos
 
     async fn count(&self) -> RepositoryResult<i64> {
         Ok(sqlx::query_scalar("SELECT COUNT(*) FROM journals")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?)
     }
 
     async fn exists(&self, id: i64) -> RepositoryResult<bool> {
         let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM journals WHERE id = $1")
             .bind(id)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
         Ok(count > 0)
     }
 }
 
+/// Journals as the API handlers need them, stored in the database or, in
+/// the embedded mode, in memory
+#[async_trait]
+pub trait JournalStore: Send + Sync {
+    /// Journals of a journable, oldest first
+    async fn find_by_journable(
+        &self,
+        journable_type: &str,
+        journable_id: i64,
+        pagination: Pagination,
+    ) -> RepositoryResult<PaginatedResult<JournalRow>>;
+
+    /// Add a comment to a journable as a new journal version
+    async fn create_comment(
+        &self,
+        journable_type: &str,
+        journable_id: i64,
+        user_id: i64,
+        notes: &str,
+    ) -> RepositoryResult<JournalRow>;
+}
+
+#[async_trait]
+impl JournalStore for JournalRepository {
+    async fn find_by_journable(
+        &self,
+        journable_type: &str,
+        journable_id: i64,
+        pagination: Pagination,
+    ) -> RepositoryResult<PaginatedResult<JournalRow>> {
+        JournalRepository::find_by_journable(self, journable_type, journable_id, pagination).await
+    }
+
+    async fn create_comment(
+        &self,
+        journable_type: &str,
+        journable_id: i64,
+        user_id: i64,
+        notes: &str,
+    ) -> RepositoryResult<JournalRow> {
+        JournalRepository::create_comment(self, journable_type, journable_id, user_id, notes).await
+    }
+}
+
+/// Journals of a single process. Only comments are journaled, so the first
+/// comment on a journable is its first version and refers to no data.
+#[derive(Default)]
+pub struct MemoryJournalStore {
+    journals: Mutex<Vec<JournalRow>>,
+}
+
+impl MemoryJournalStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl JournalStore for MemoryJournalStore {
+    async fn find_by_journable(
+        &self,
+        journable_type: &str,
+        journable_id: i64,
+        pagination: Pagination,
+    ) -> RepositoryResult<PaginatedResult<JournalRow>> {
+        let journals = self.journals.lock().unwrap();
+        let matching: Vec<&JournalRow> = journals
+            .iter()
+            .filter(|journal| journal.journable_type == journable_type && journal.journable_id == journable_id)
+            .collect();
+        let items = matching
+            .iter()
+            .skip(pagination.offset as usize)
+            .take(pagination.limit as usize)
+            .map(|journal| (*journal).clone())
+            .collect();
+        Ok(PaginatedResult::new(items, matching.len() as i64, pagination))
+    }
+
+    async fn create_comment(
+        &self,
+        journable_type: &str,
+        journable_id: i64,
+        user_id: i64,
+        notes: &str,
+    ) -> RepositoryResult<JournalRow> {
+        let mut journals = self.journals.lock().unwrap();
+        let latest = journals
+            .iter()
+            .filter(|journal| journal.journable_type == journable_type && journal.journable_id == journable_id)
+            .max_by_key(|journal| journal.version);
+        let (version, data_type, data_id) = match latest {
+            Some(journal) => (journal.version + 1, journal.data_type.clone(), journal.data_id),
+            None => (1, format!("Journal::{}Journal", journable_type), 0),
+        };
+        let now = Utc::now();
+        let row = JournalRow {
+            id: journals.len() as i64 + 1,
+            journable_type: journable_type.to_string(),
+            journable_id,
+            user_id,
+            notes: Some(notes.to_string()),
+            version,
+            data_type,
+            data_id,
+            cause: serde_json::json!({}),
+            restricted: false,
+            created_at: now,
+            updated_at: now,
+        };
+        journals.push(row.clone());
+        Ok(row)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -664,4 +906,198 @@ mod tests {
         assert_eq!(journal.cause_type(), Some("status_changed"));
         assert!(!journal.is_internal());
     }
+
+    #[tokio::test]
+    async fn test_memory_store_versions_comments() {
+        let store = MemoryJournalStore::new();
+        let first = store.create_comment(journable_type::WORK_PACKAGE, 7, 1, "First").await.unwrap();
+        store.create_comment(journable_type::WORK_PACKAGE, 8, 1, "Elsewhere").await.unwrap();
+        let second = store.create_comment(journable_type::WORK_PACKAGE, 7, 2, "Second").await.unwrap();
+        assert_eq!((first.version, second.version), (1, 2));
+        assert_eq!(second.data_type, "Journal::WorkPackageJournal");
+
+        let page = store
+            .find_by_journable(journable_type::WORK_PACKAGE, 7, Pagination::new(10, 0))
+            .await
+            .unwrap();
+        let notes: Vec<_> = page.items.iter().map(|journal| journal.notes.as_deref()).collect();
+        assert_eq!((notes, page.total), (vec![Some("First"), Some("Second")], 2));
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    async fn setup(db: &TestDb) -> (i64, i64, i64) {
+        let user = db.insert_user(UserFixture::new("journalist")).await;
+        let project = db.insert_project(ProjectFixture::new("journals")).await;
+        let work_package = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+
+        let data_id = sqlx::query_scalar(
+            r#"
+            INSERT INTO work_package_journals (type_id, project_id, subject, status_id, priority_id, author_id)
+            VALUES (1, $1, 'Journaled', 1, 1, $2)
+            RETURNING id
+            "#,
+        )
+        .bind(project)
+        .bind(user)
+        .fetch_one(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        (user, work_package, data_id)
+    }
+
+    fn journal(work_package: i64, user: i64, version: i32, data_id: i64) -> CreateJournalDto {
+        CreateJournalDto {
+            journable_type: journable_type::WORK_PACKAGE.into(),
+            journable_id: work_package,
+            user_id: user,
+            notes: None,
+            version,
+            data_type: "Journal::WorkPackageJournal".into(),
+            data_id,
+            cause: None,
+            restricted: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_versions_and_neighbours() {
+        let db = TestDb::connect().await;
+        let (user, work_package, data_id) = setup(&db).await;
+        let repo = db.journals();
+
+        assert_eq!(repo.next_version(journable_type::WORK_PACKAGE, work_package).await.unwrap(), 1);
+        for version in 1..=3 {
+            repo.create(journal(work_package, user, version, data_id)).await.unwrap();
+        }
+        assert_eq!(repo.next_version(journable_type::WORK_PACKAGE, work_package).await.unwrap(), 4);
+
+
+        let page = repo
+            .find_by_work_package(work_package, Pagination { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.items.iter().map(|j| j.version).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(page.items[0].cause, serde_json::json!({}));
+
+        let changing = repo
+            .find_changing(journable_type::WORK_PACKAGE, work_package, Pagination { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(changing.total, 2);
+
+        let predecessor = repo.find_predecessor(journable_type::WORK_PACKAGE, work_package, 3).await.unwrap();
+        assert_eq!(predecessor.unwrap().version, 2);
+        let successor = repo.find_successor(journable_type::WORK_PACKAGE, work_package, 3).await.unwrap();
+        assert!(successor.is_none());
+
+        // Failing statements abort the test transaction, so this comes last
+        let duplicate = repo.create(journal(work_package, user, 2, data_id)).await;
+        assert!(matches!(duplicate, Err(RepositoryError::Conflict(_))));
+    }
+
+    /// Journal of a work package's state in a project at the given time
+    async fn journal_state(
+        db: &TestDb,
+        work_package: i64,
+        user: i64,
+        version: i32,
+        project: i64,
+        status: i64,
+        at: &str,
+    ) {
+        let data_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO work_package_journals (type_id, project_id, subject, status_id, priority_id, author_id)
+            VALUES (1, $1, 'Journaled', $2, 1, $3)
+            RETURNING id
+            "#,
+        )
+        .bind(project)
+        .bind(status)
+        .bind(user)
+        .fetch_one(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO journals (journable_type, journable_id, user_id, version, data_type, data_id, created_at) \
+             VALUES ('WorkPackage', $1, $2, $3, 'Journal::WorkPackageJournal', $4, $5::timestamptz)",
+        )
+        .bind(work_package)
+        .bind(user)
+        .bind(version)
+        .bind(data_id)
+        .bind(at)
+        .execute(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_status_transitions_follow_work_packages_into_the_project() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("flow-author")).await;
+        let project = db.insert_project(ProjectFixture::new("flow")).await;
+        let other = db.insert_project(ProjectFixture::new("flow-other")).await;
+        let stayed = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+        let moved_in = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+        let elsewhere = db.insert_work_package(WorkPackageFixture::new(other, user)).await;
+
+        journal_state(&db, stayed, user, 1, project, 1, "2024-03-01T10:00:00Z").await;
+        journal_state(&db, stayed, user, 2, project, 2, "2024-03-05T10:00:00Z").await;
+        journal_state(&db, moved_in, user, 1, other, 1, "2024-03-02T10:00:00Z").await;
+        journal_state(&db, moved_in, user, 2, project, 1, "2024-03-03T10:00:00Z").await;
+        journal_state(&db, elsewhere, user, 1, other, 1, "2024-03-02T10:00:00Z").await;
+        sqlx::query("UPDATE work_packages SET deleted_at = '2024-03-04T00:00:00Z', deleted_by = $2 WHERE id = $1")
+            .bind(moved_in)
+            .bind(user)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+
+        let before = "2024-03-04T00:00:00Z".parse().unwrap();
+        let rows = db.journals().find_status_transitions(project, before).await.unwrap();
+        let states: Vec<_> = rows
+            .iter()
+            .map(|row| (row.work_package_id, row.project_id, row.status_id, row.deleted_at.is_some()))
+            .collect();
+        assert_eq!(
+            states,
+            vec![(stayed, project, 1, false), (moved_in, other, 1, true), (moved_in, project, 1, true)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_comment_shares_latest_data_and_joins_user() {
+        let db = TestDb::connect().await;
+        let (user, work_package, data_id) = setup(&db).await;
+        let repo = db.journals();
+        repo.create(journal(work_package, user, 1, data_id)).await.unwrap();
+
+        let comment = repo
+            .create_comment(journable_type::WORK_PACKAGE, work_package, user, "Looks good")
+            .await
+            .unwrap();
+        assert_eq!((comment.version, comment.data_id), (2, data_id));
+        assert_eq!(comment.notes.as_deref(), Some("Looks good"));
+
+        let with_user = repo.find_by_id_with_user(comment.id).await.unwrap().unwrap();
+        assert_eq!(with_user.user_login, "journalist");
+
+        let with_data = repo
+            .find_work_package_journals_with_data(work_package, Pagination { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(with_data.total, 2);
+        assert!(with_data.items.iter().all(|j| j.data.as_ref().map(|d| d.subject.as_str()) == Some("Journaled")));
+
+        let missing = repo.create_comment(journable_type::WORK_PACKAGE, work_package + 1000, user, "?").await;
+        assert!(matches!(missing, Err(RepositoryError::NotFound(_))));
+    }
 }
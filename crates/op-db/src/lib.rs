@@ -7,6 +7,24 @@
 //! - Connection pool management
 //! - Repository pattern for CRUD operations
 //! - Entity mappings for work packages, users, and projects
+//! - Cost entries, rates and the cached costs of work packages
+//! - External file storages and the files linked to work packages
+//! - Executors running repository queries on the pool or a shared transaction
+//! - A cache of work package query results, outdated by work package writes
+//! - Idempotency keys of retried POST requests and their stored responses
+//! - API keys, optionally restricted to scopes
+//! - Removal of watchers and notifications users can no longer see
+//! - An audit of rows referencing missing rows, repairing them in batches
+//! - Group memberships and groups synchronized with LDAP
+//! - Work package counts by type, status and priority for overview widgets
+//! - When users last viewed work packages, to mark unseen changes
+//! - Project custom fields, their sections and the values of projects
+//! - Sessions of resumable attachment uploads and their received chunks
+//! - Boards as grids of saved query columns
+//! - Custom actions applying predefined changes to work packages
+//! - Instance-wide settings such as the maintenance mode
+//! - Embedded schema migrations and a schema check for Rails-managed databases
+//! - Database-backed test harness (`pg-tests` feature)
 //!
 //! ## Example
 //!
@@ -23,12 +41,22 @@
 //! ```
 
 pub mod pool;
+pub mod migrations;
 pub mod repository;
+pub mod executor;
 pub mod work_packages;
+pub mod work_package_summaries;
+pub mod work_package_views;
 pub mod users;
+pub mod groups;
 pub mod projects;
+pub mod custom_fields;
 pub mod query_executor;
+pub mod query_cache;
+pub mod idempotency;
+pub mod api_keys;
 pub mod time_entries;
+pub mod costs;
 pub mod statuses;
 pub mod priorities;
 pub mod types;
@@ -39,30 +67,123 @@ pub mod activities;
 pub mod categories;
 pub mod relations;
 pub mod watchers;
+pub mod revoked_access;
 pub mod attachments;
+pub mod attachment_uploads;
+pub mod storages;
+pub mod file_links;
 pub mod queries;
+pub mod query_subscriptions;
+pub mod scheduled_jobs;
 pub mod journals;
+pub mod forums;
+pub mod documents;
+pub mod colors;
+pub mod boards;
+pub mod custom_actions;
+pub mod audit_events;
+pub mod settings;
+pub mod notifications;
+pub mod includes;
+pub mod integrity;
+#[cfg(feature = "pg-tests")]
+pub mod testing;
 
 // Re-exports
 pub use pool::{Database, DatabaseConfig, PoolStats};
+pub use migrations::{SchemaMismatch, SchemaReport, MIGRATOR};
+pub use op_core::error::PropertyError;
 pub use repository::{
     Pagination, PaginatedResult, Repository, RepositoryContext, RepositoryError, RepositoryResult,
 };
-pub use work_packages::{CreateWorkPackageDto, UpdateWorkPackageDto, WorkPackageRepository};
-pub use users::{status as user_status, CreateUserDto, UpdateUserDto, UserRepository, UserRow};
-pub use projects::{CreateProjectDto, UpdateProjectDto, ProjectRepository, ProjectRow};
-pub use query_executor::{WorkPackageQueryExecutor, WorkPackageRow};
-pub use time_entries::{CreateTimeEntryDto, UpdateTimeEntryDto, TimeEntryRepository, TimeEntryRow};
+pub use executor::{DbConnection, DbExecutor, DbTransaction};
+pub use work_packages::{
+    CommittedCopy, CommittedWorkPackageCopy, CondensedWorkPackageRow, CopyCascade, CreateWorkPackageDto, DeleteCascade,
+    MemoryWorkPackageStore, SimilarWorkPackageRow, TrashCascade, TrashedWorkPackageRow, UpdateWorkPackageDto,
+    WorkPackageCopy, WorkPackageDefaultsRow, WorkPackageDeletion, WorkPackageReferenceRow, WorkPackageRepository,
+    WorkPackageStore, WorkPackageTrash, WorkPackageWatcherRow,
+};
+pub use users::{
+    principal_type, status as user_status, CreateUserDto, MemoryUserStore, OrphanAction, SoftDeleteReport, UpdateUserDto,
+    UserReference, UserRepository, UserRow, UserStore, DELETED_USER_LOGIN, USER_REFERENCES,
+};
+pub use groups::{GroupRepository, SynchronizedGroupRow};
+pub use work_package_summaries::{SummaryCount, SummaryDimension, SummaryRepository};
+pub use work_package_views::{unseen_changes_sql, WorkPackageViewRepository, VIEW_THROTTLE};
+pub use custom_fields::{
+    CustomFieldRepository, CustomValueFilter, CustomValueOperator, CustomValueRow, PROJECT_CUSTOMIZED_TYPE,
+};
+pub use projects::{
+    CopyDependency, CreateProjectDto, MemoryProjectStore, UpdateProjectDto, ProjectOrder, ProjectRepository, ProjectRow,
+    ProjectStore,
+};
+pub use query_executor::{
+    validate_filter, AttributesAtTimestamp, CountStrategy, FilterError, FilterErrorKind, QueryGroupRow, QueryValidationError,
+    TimelineRow, TimestampedWorkPackage, WorkPackageQueryExecutor, WorkPackageRow, WorkPackageSnapshot,
+    DEFAULT_EXACT_COUNT_THRESHOLD,
+};
+pub use query_cache::{
+    CachedQueryResult, MemoryQueryResultCache, QueryCacheKey, QueryResultCache, DEFAULT_QUERY_CACHE_TTL,
+};
+pub use idempotency::{
+    IdempotencyClaim, IdempotencyKeyRepository, IdempotencyRequest, IdempotencyStore, MemoryIdempotencyStore,
+    StoredResponse, DEFAULT_IDEMPOTENCY_TTL,
+};
+pub use api_keys::{ApiKeyRepository, ApiKeyRow, ApiKeyStore, CreateApiKeyDto, MemoryApiKeyStore, API_KEY_TOKEN_TYPE};
+pub use time_entries::{
+    CreateTimeEntryDto, UpdateTimeEntryDto, TimeEntryAggregate, TimeEntryAggregateRow, TimeEntryGroupBy,
+    TimeEntryReport, TimeEntryRepository, TimeEntryRow,
+};
+pub use costs::{
+    rate_type, CostEntryRow, CostRepository, CostTypeRow, CreateCostEntryDto, CreateCostTypeDto, CreateRateDto,
+    RateRow, WorkPackageCostsRow,
+};
 pub use statuses::{CreateStatusDto, UpdateStatusDto, StatusRepository, StatusRow};
 pub use priorities::{CreatePriorityDto, UpdatePriorityDto, PriorityRepository, PriorityRow};
 pub use types::{CreateTypeDto, UpdateTypeDto, TypeRepository, TypeRow};
-pub use roles::{CreateRoleDto, UpdateRoleDto, RoleRepository, RoleRow};
+pub use roles::{builtin as role_builtin, CreateRoleDto, UpdateRoleDto, RoleRepository, RoleRow};
 pub use versions::{CreateVersionDto, UpdateVersionDto, VersionRepository, VersionRow};
-pub use members::{CreateMemberDto, UpdateMemberDto, MemberRepository, MemberRow, MemberWithRoles};
+pub use members::{
+    entity_type as member_entity_type, CreateMemberDto, MemberListRow, MemberOrder, MemberQuery, MemberRepository, MemberRow,
+    MemberWithRoles, UpdateMemberDto,
+};
 pub use activities::{CreateActivityDto, UpdateActivityDto, ActivityRepository, ActivityRow};
 pub use categories::{CreateCategoryDto, UpdateCategoryDto, CategoryRepository, CategoryRow};
 pub use relations::{relation_type, CreateRelationDto, UpdateRelationDto, RelationRepository, RelationRow};
 pub use watchers::{CreateWatcherDto, UpdateWatcherDto, WatcherRepository, WatcherRow, WatcherWithUser};
+pub use revoked_access::{RevokedAccessBatch, RevokedAccessRepository};
 pub use attachments::{status as attachment_status, CreateAttachmentDto, UpdateAttachmentDto, AttachmentRepository, AttachmentRow};
-pub use queries::{CreateQueryDto, UpdateQueryDto, QueryRepository, QueryRow, QueryWithStarred};
-pub use journals::{cause_type, journable_type, CreateJournalDto, UpdateJournalDto, JournalRepository, JournalRow, JournalWithUser, JournalWithWorkPackageData, WorkPackageJournalRow};
+pub use attachment_uploads::{AttachmentUploadRepository, AttachmentUploadRow, CreateAttachmentUploadDto, UploadChunkRow};
+pub use storages::{
+    normalize_host, CreateStorageDto, StorageRepository, StorageRow,
+    UpdateStorageDto,
+};
+pub use file_links::{
+    container_type as file_link_container_type, CreateFileLinkDto, FileLinkRepository, FileLinkRow, UpdateFileLinkDto,
+};
+pub use queries::{CreateQueryDto, FilterableCustomField, UpdateQueryDto, QueryRepository, QueryRow, QueryWithStarred};
+pub use query_subscriptions::{frequency as subscription_frequency, QuerySubscriptionRepository, QuerySubscriptionRow};
+pub use scheduled_jobs::ScheduledJobRepository;
+pub use journals::{
+    cause_type, journable_type, CreateJournalDto, JournalRepository, JournalRow, JournalStore, JournalWithUser,
+    JournalWithWorkPackageData, MemoryJournalStore, StatusTransitionRow, UpdateJournalDto, WorkPackageJournalRow,
+};
+pub use forums::{
+    CreateForumDto, CreateMessageDto, ForumRepository, ForumRow, MessageRepository, MessageRow,
+    MessageVersionRow, UpdateForumDto, UpdateMessageDto,
+};
+pub use documents::{
+    CreateDocumentDto, DocumentCategoryRow, DocumentOrder, DocumentRepository, DocumentRow, UpdateDocumentDto,
+    DOCUMENT_CATEGORY_TYPE,
+};
+pub use colors::{ColorRepository, ColorRow};
+pub use boards::{BoardColumnRow, BoardRepository, BoardRow, CreateBoardDto};
+pub use custom_actions::{CreateCustomActionDto, CustomActionRepository, CustomActionRow, UpdateCustomActionDto};
+pub use audit_events::{AuditEventRepository, AuditEventRow};
+pub use settings::SettingRepository;
+pub use notifications::{NotificationRepository, NotificationRow};
+pub use integrity::{
+    orphan_checks, DependentRows, IntegrityOptions, IntegrityReport, IntegrityRepository, OrphanCheck, OrphanCount,
+    DEPENDENT_ROWS,
+};
+pub use includes::{IncludeLoader, IncludeResolver, Includes, PgIncludeLoader, ResolvedIncludes};
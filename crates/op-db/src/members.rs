@@ -4,9 +4,28 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use sqlx::{FromRow, PgPool};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
 
+use crate::executor::DbExecutor;
+use crate::roles::builtin as role_builtin;
 use crate::{Pagination, PaginatedResult, Repository, RepositoryError};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// Entity types of entity-scoped memberships
+pub mod entity_type {
+    /// Work package shares
+    pub const WORK_PACKAGE: &str = "WorkPackage";
+}
+
+/// Memberships of projects that are gone, and role assignments of
+/// memberships or roles that are
+pub const MEMBER_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::new("members", "project_id", "projects", OrphanAction::Delete),
+    OrphanCheck::new("member_roles", "member_id", "members", OrphanAction::Delete),
+    OrphanCheck::new("member_roles", "role_id", "roles", OrphanAction::Delete),
+];
 
 /// Member row from database
 #[derive(Debug, Clone, FromRow)]
@@ -52,14 +71,190 @@ pub struct UpdateMemberDto {
     pub role_ids: Option<Vec<i64>>,
 }
 
+/// Sort order of a membership listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemberOrder {
+    #[default]
+    Id,
+    /// Name of the principal, case-insensitive
+    Name,
+    CreatedAt,
+}
+
+impl MemberOrder {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Id => "listing.id",
+            Self::Name => "lower(listing.principal_name)",
+            Self::CreatedAt => "listing.created_at",
+        }
+    }
+}
+
+/// Project and global memberships to list; shares are never listed. Filters
+/// left `None` match every membership.
+#[derive(Debug, Clone, Default)]
+pub struct MemberQuery {
+    pub ids: Option<Vec<Id>>,
+    pub principal_ids: Option<Vec<Id>>,
+    pub project_ids: Option<Vec<Id>>,
+    /// Values of `users.type`, see [`crate::principal_type`]
+    pub principal_types: Option<Vec<String>>,
+    /// Memberships having any of these roles
+    pub role_ids: Option<Vec<Id>>,
+    /// Created at or after, inclusive
+    pub created_from: Option<DateTime<Utc>>,
+    /// Created before, exclusive
+    pub created_until: Option<DateTime<Utc>>,
+    /// Only memberships of projects where the user has `view_members` or
+    /// `manage_members`. Without it, as for administrators, every
+    /// membership is listed.
+    pub visible_to: Option<Id>,
+    pub order: MemberOrder,
+    pub descending: bool,
+}
+
+impl MemberQuery {
+    /// Memberships with the principal's name and type and the roles
+    /// aggregated, one row per membership
+    fn push_listing<'a>(&'a self, sql: &mut QueryBuilder<'a, Postgres>) {
+        sql.push(
+            "SELECT m.id, m.user_id, m.project_id, m.created_at, m.updated_at, \
+             u.type AS principal_type, \
+             CASE WHEN u.type IN ('Group', 'PlaceholderUser') THEN u.lastname \
+                  ELSE u.firstname || ' ' || u.lastname END AS principal_name, \
+             p.name AS project_name, \
+             COALESCE(roles.ids, '{}') AS role_ids, COALESCE(roles.names, '{}') AS role_names \
+             FROM members m \
+             JOIN users u ON u.id = m.user_id \
+             LEFT JOIN projects p ON p.id = m.project_id \
+             LEFT JOIN LATERAL ( \
+                 SELECT array_agg(r.id ORDER BY r.id) AS ids, array_agg(r.name ORDER BY r.id) AS names \
+                 FROM roles r WHERE r.id IN (SELECT mr.role_id FROM member_roles mr WHERE mr.member_id = m.id) \
+             ) roles ON TRUE \
+             WHERE m.entity_type IS NULL",
+        );
+
+        if let Some(ids) = &self.ids {
+            sql.push(" AND m.id = ANY(");
+            sql.push_bind(ids.as_slice());
+            sql.push(")");
+        }
+        if let Some(principal_ids) = &self.principal_ids {
+            sql.push(" AND m.user_id = ANY(");
+            sql.push_bind(principal_ids.as_slice());
+            sql.push(")");
+        }
+        if let Some(project_ids) = &self.project_ids {
+            sql.push(" AND m.project_id = ANY(");
+            sql.push_bind(project_ids.as_slice());
+            sql.push(")");
+        }
+        if let Some(principal_types) = &self.principal_types {
+            sql.push(" AND u.type = ANY(");
+            sql.push_bind(principal_types.as_slice());
+            sql.push(")");
+        }
+        if let Some(role_ids) = &self.role_ids {
+            sql.push(" AND EXISTS (SELECT 1 FROM member_roles mr WHERE mr.member_id = m.id AND mr.role_id = ANY(");
+            sql.push_bind(role_ids.as_slice());
+            sql.push("))");
+        }
+        if let Some(from) = self.created_from {
+            sql.push(" AND m.created_at >= ");
+            sql.push_bind(from);
+        }
+        if let Some(until) = self.created_until {
+            sql.push(" AND m.created_at < ");
+            sql.push_bind(until);
+        }
+        if let Some(user_id) = self.visible_to {
+            sql.push(
+                " AND m.project_id IN (SELECT v.project_id FROM members v \
+                 JOIN member_roles vr ON vr.member_id = v.id \
+                 JOIN role_permissions rp ON rp.role_id = vr.role_id \
+                 WHERE v.entity_type IS NULL AND rp.permission IN ('view_members', 'manage_members') \
+                 AND v.user_id = ",
+            );
+            sql.push_bind(user_id);
+            sql.push(")");
+        }
+    }
+
+    /// Query of one page of memberships; ties are broken by id so pages
+    /// are stable
+    fn rows_query(&self, pagination: Pagination) -> QueryBuilder<'_, Postgres> {
+        let mut sql = QueryBuilder::new("SELECT * FROM (");
+        self.push_listing(&mut sql);
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        sql.push(format_args!(") listing ORDER BY {} {}", self.order.sql(), direction));
+        if self.order != MemberOrder::Id {
+            sql.push(format_args!(", listing.id {}", direction));
+        }
+        sql.push(" LIMIT ");
+        sql.push_bind(pagination.limit);
+        sql.push(" OFFSET ");
+        sql.push_bind(pagination.offset);
+        sql
+    }
+
+    /// Query of the number of matching memberships
+    fn count_query(&self) -> QueryBuilder<'_, Postgres> {
+        let mut sql = QueryBuilder::new("SELECT COUNT(*) FROM (");
+        self.push_listing(&mut sql);
+        sql.push(") listing");
+        sql
+    }
+}
+
+/// Listed membership with its principal and roles
+#[derive(Debug, Clone, FromRow)]
+pub struct MemberListRow {
+    pub id: Id,
+    pub user_id: Id,
+    /// `None` for global memberships
+    pub project_id: Option<Id>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub principal_type: String,
+    pub principal_name: String,
+    pub project_name: Option<String>,
+    pub role_ids: Vec<Id>,
+    /// Names of `role_ids`, in the same order
+    pub role_names: Vec<String>,
+}
+
+impl From<MemberListRow> for MemberWithRoles {
+    fn from(row: MemberListRow) -> Self {
+        Self {
+            member: MemberRow {
+                id: row.id,
+                user_id: row.user_id,
+                project_id: row.project_id,
+                entity_type: None,
+                entity_id: None,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            },
+            role_ids: row.role_ids,
+        }
+    }
+}
+
 /// Member repository
 pub struct MemberRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl MemberRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
     }
 
     /// Find members by project
@@ -68,43 +263,60 @@ impl MemberRepository {
         project_id: i64,
         pagination: Pagination,
     ) -> Result<PaginatedResult<MemberWithRoles>, RepositoryError> {
-        let members = sqlx::query_as::<_, MemberRow>(
-            r#"
-            SELECT id, user_id, project_id, entity_type, entity_id, created_at, updated_at
-            FROM members
-            WHERE project_id = $1 AND entity_type IS NULL
-            ORDER BY created_at DESC
-            LIMIT $2 OFFSET $3
-            "#,
-        )
-        .bind(project_id)
-        .bind(pagination.limit)
-        .bind(pagination.offset)
-        .fetch_all(&self.pool)
-        .await?;
-
-        let total = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM members WHERE project_id = $1 AND entity_type IS NULL",
-        )
-        .bind(project_id)
-        .fetch_one(&self.pool)
-        .await?;
-
-        // Fetch roles for each member
-        let mut result = Vec::with_capacity(members.len());
-        for member in members {
-            let role_ids = self.get_role_ids(member.id).await?;
-            result.push(MemberWithRoles { member, role_ids });
-        }
+        let query = MemberQuery {
+            project_ids: Some(vec![project_id]),
+            order: MemberOrder::CreatedAt,
+            descending: true,
+            ..Default::default()
+        };
+        let page = self.list(&query, pagination).await?;
 
         Ok(PaginatedResult {
-            items: result,
-            total,
-            limit: pagination.limit,
-            offset: pagination.offset,
+            items: page.items.into_iter().map(MemberWithRoles::from).collect(),
+            total: page.total,
+            limit: page.limit,
+            offset: page.offset,
+            total_is_estimate: false,
         })
     }
 
+    /// One page of the memberships matching the query, with principals
+    /// and roles loaded in the same query
+    pub async fn list(
+        &self,
+        query: &MemberQuery,
+        pagination: Pagination,
+    ) -> Result<PaginatedResult<MemberListRow>, RepositoryError> {
+        let items = query
+            .rows_query(pagination)
+            .build_query_as::<MemberListRow>()
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+
+        let total = query
+            .count_query()
+            .build_query_scalar::<i64>()
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(PaginatedResult::new(items, total, pagination))
+    }
+
+    /// A project or global membership with its principal and roles
+    pub async fn find_listed(&self, id: Id) -> Result<Option<MemberListRow>, RepositoryError> {
+        let query = MemberQuery {
+            ids: Some(vec![id]),
+            ..Default::default()
+        };
+        let row = query
+            .rows_query(Pagination::new(1, 0))
+            .build_query_as::<MemberListRow>()
+            .fetch_optional(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(row)
+    }
+
     /// Find members by user
     pub async fn find_by_user(&self, user_id: i64) -> Result<Vec<MemberWithRoles>, RepositoryError> {
         let members = sqlx::query_as::<_, MemberRow>(
@@ -116,7 +328,7 @@ impl MemberRepository {
             "#,
         )
         .bind(user_id)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let mut result = Vec::with_capacity(members.len());
@@ -143,7 +355,7 @@ impl MemberRepository {
         )
         .bind(project_id)
         .bind(user_id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         match member {
@@ -165,7 +377,151 @@ impl MemberRepository {
             ORDER BY created_at DESC
             "#,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        let mut result = Vec::with_capacity(members.len());
+        for member in members {
+            let role_ids = self.get_role_ids(member.id).await?;
+            result.push(MemberWithRoles { member, role_ids });
+        }
+
+        Ok(result)
+    }
+
+    /// Check whether a user holds a permission in a project through their roles
+    pub async fn allowed_in_project(
+        &self,
+        user_id: i64,
+        project_id: i64,
+        permission: &str,
+    ) -> Result<bool, RepositoryError> {
+        let allowed = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM members m
+                JOIN member_roles mr ON mr.member_id = m.id
+                JOIN role_permissions rp ON rp.role_id = mr.role_id
+                WHERE m.user_id = $1 AND m.project_id = $2 AND m.entity_type IS NULL
+                  AND rp.permission = $3
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(project_id)
+        .bind(permission)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(allowed)
+    }
+
+    /// Permissions of the built-in Anonymous role in a project; none unless
+    /// the project is public and active
+    pub async fn anonymous_permissions_in_project(&self, project_id: i64) -> Result<Vec<String>, RepositoryError> {
+        let permissions = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT rp.permission
+            FROM projects p
+            JOIN roles r ON r.builtin = $2
+            JOIN role_permissions rp ON rp.role_id = r.id
+            WHERE p.id = $1 AND p.public AND p.active
+            "#,
+        )
+        .bind(project_id)
+        .bind(role_builtin::ANONYMOUS)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(permissions)
+    }
+
+    /// Permissions a user holds in a project through project memberships.
+    /// Entity-scoped memberships only grant permissions on their entity.
+    pub async fn permissions_in_project(
+        &self,
+        user_id: i64,
+        project_id: i64,
+    ) -> Result<Vec<String>, RepositoryError> {
+        let permissions = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT rp.permission
+            FROM members m
+            JOIN member_roles mr ON mr.member_id = m.id
+            JOIN role_permissions rp ON rp.role_id = mr.role_id
+            WHERE m.user_id = $1 AND m.project_id = $2 AND m.entity_type IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(permissions)
+    }
+
+    /// Roles a user holds in a project through the project membership
+    pub async fn role_ids_in_project(&self, user_id: i64, project_id: i64) -> Result<Vec<i64>, RepositoryError> {
+        let role_ids = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT DISTINCT mr.role_id
+            FROM members m
+            JOIN member_roles mr ON mr.member_id = m.id
+            WHERE m.user_id = $1 AND m.project_id = $2 AND m.entity_type IS NULL
+            ORDER BY mr.role_id
+            "#,
+        )
+        .bind(user_id)
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(role_ids)
+    }
+
+    /// Permissions a user holds on an entity through entity-scoped memberships
+    pub async fn permissions_on_entity(
+        &self,
+        user_id: i64,
+        entity_type: &str,
+        entity_id: i64,
+    ) -> Result<Vec<String>, RepositoryError> {
+        let permissions = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT rp.permission
+            FROM members m
+            JOIN member_roles mr ON mr.member_id = m.id
+            JOIN role_permissions rp ON rp.role_id = mr.role_id
+            WHERE m.user_id = $1 AND m.entity_type = $2 AND m.entity_id = $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(entity_type)
+        .bind(entity_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(permissions)
+    }
+
+    /// Find the entity-scoped memberships of an entity, oldest first
+    pub async fn find_by_entity(
+        &self,
+        entity_type: &str,
+        entity_id: i64,
+    ) -> Result<Vec<MemberWithRoles>, RepositoryError> {
+        let members = sqlx::query_as::<_, MemberRow>(
+            r#"
+            SELECT id, user_id, project_id, entity_type, entity_id, created_at, updated_at
+            FROM members
+            WHERE entity_type = $1 AND entity_id = $2
+            ORDER BY created_at ASC, id ASC
+            "#,
+        )
+        .bind(entity_type)
+        .bind(entity_id)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let mut result = Vec::with_capacity(members.len());
@@ -183,7 +539,7 @@ impl MemberRepository {
             "SELECT DISTINCT role_id FROM member_roles WHERE member_id = $1 ORDER BY role_id",
         )
         .bind(member_id)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(role_ids)
@@ -194,7 +550,7 @@ impl MemberRepository {
         // Delete existing non-inherited roles
         sqlx::query("DELETE FROM member_roles WHERE member_id = $1 AND inherited_from IS NULL")
             .bind(member_id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         // Insert new roles
@@ -204,7 +560,7 @@ impl MemberRepository {
             )
             .bind(member_id)
             .bind(role_id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
         }
 
@@ -224,7 +580,7 @@ impl MemberRepository {
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         match member {
@@ -236,42 +592,6 @@ impl MemberRepository {
         }
     }
 
-    /// Find all members with roles
-    pub async fn find_all_with_roles(
-        &self,
-        pagination: Pagination,
-    ) -> Result<PaginatedResult<MemberWithRoles>, RepositoryError> {
-        let members = sqlx::query_as::<_, MemberRow>(
-            r#"
-            SELECT id, user_id, project_id, entity_type, entity_id, created_at, updated_at
-            FROM members
-            ORDER BY created_at DESC
-            LIMIT $1 OFFSET $2
-            "#,
-        )
-        .bind(pagination.limit)
-        .bind(pagination.offset)
-        .fetch_all(&self.pool)
-        .await?;
-
-        let total = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM members")
-            .fetch_one(&self.pool)
-            .await?;
-
-        let mut result = Vec::with_capacity(members.len());
-        for member in members {
-            let role_ids = self.get_role_ids(member.id).await?;
-            result.push(MemberWithRoles { member, role_ids });
-        }
-
-        Ok(PaginatedResult {
-            items: result,
-            total,
-            limit: pagination.limit,
-            offset: pagination.offset,
-        })
-    }
-
     /// Check if a membership already exists
     pub async fn membership_exists(
         &self,
@@ -292,7 +612,7 @@ impl MemberRepository {
                 .bind(pid)
                 .bind(et)
                 .bind(eid)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?
             }
             (Some(pid), None, None) => {
@@ -304,7 +624,7 @@ impl MemberRepository {
                 )
                 .bind(user_id)
                 .bind(pid)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?
             }
             (None, None, None) => {
@@ -315,10 +635,10 @@ impl MemberRepository {
                     "#,
                 )
                 .bind(user_id)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?
             }
-            _ => return Err(RepositoryError::Validation("Invalid membership parameters".to_string())),
+            _ => return Err(RepositoryError::validation("Invalid membership parameters")),
         };
 
         Ok(count > 0)
@@ -336,7 +656,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -353,7 +673,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -361,7 +681,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
 
     async fn count(&self) -> Result<i64, RepositoryError> {
         let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM members")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(count)
@@ -371,7 +691,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
         let count =
             sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM members WHERE id = $1")
                 .bind(id)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(count > 0)
@@ -380,9 +700,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
     async fn create(&self, dto: CreateMemberDto) -> Result<MemberRow, RepositoryError> {
         // Validate role_ids not empty
         if dto.role_ids.is_empty() {
-            return Err(RepositoryError::Validation(
-                "Roles can't be blank".to_string(),
-            ));
+            return Err(RepositoryError::invalid("roles", "blank", "can't be blank"));
         }
 
         // Check if membership already exists
@@ -412,7 +730,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
         .bind(dto.project_id)
         .bind(&dto.entity_type)
         .bind(dto.entity_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         // Set roles
@@ -431,9 +749,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
         // Update roles if provided
         if let Some(role_ids) = dto.role_ids {
             if role_ids.is_empty() {
-                return Err(RepositoryError::Validation(
-                    "Roles can't be blank".to_string(),
-                ));
+                return Err(RepositoryError::invalid("roles", "blank", "can't be blank"));
             }
             self.set_roles(id, &role_ids).await?;
         }
@@ -448,7 +764,7 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
             "#,
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -468,14 +784,14 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
             "SELECT COUNT(*) FROM member_roles WHERE member_id = $1 AND inherited_from IS NULL",
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         let total_count = sqlx::query_scalar::<_, i64>(
             "SELECT COUNT(*) FROM member_roles WHERE member_id = $1",
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         if non_inherited_count == 0 && total_count > 0 {
@@ -487,13 +803,13 @@ impl Repository<MemberRow, CreateMemberDto, UpdateMemberDto> for MemberRepositor
         // Delete member roles first (cascade)
         sqlx::query("DELETE FROM member_roles WHERE member_id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         // Delete member
         sqlx::query("DELETE FROM members WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(())
@@ -544,3 +860,184 @@ mod tests {
         assert!(!member_with_roles.has_role(99));
     }
 }
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    fn project_member(user_id: i64, project_id: i64, role_ids: Vec<i64>) -> CreateMemberDto {
+        CreateMemberDto {
+            user_id,
+            project_id: Some(project_id),
+            role_ids,
+            entity_type: None,
+            entity_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_project_membership_lifecycle() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("member")).await;
+        let project = db.insert_project(ProjectFixture::new("members")).await;
+        let viewer = db.insert_role("Viewer", &["view_work_packages"]).await;
+        let editor = db.insert_role("Editor", &["view_work_packages", "edit_work_packages"]).await;
+        let repo = db.members();
+
+        let member = repo.create(project_member(user, project, vec![viewer])).await.unwrap();
+        let duplicate = repo.create(project_member(user, project, vec![editor])).await;
+        assert!(matches!(duplicate, Err(RepositoryError::Conflict(_))));
+        assert!(matches!(
+            repo.create(project_member(user, project, vec![])).await,
+            Err(RepositoryError::Validation(_))
+        ));
+
+        let found = repo.find_by_project_and_user(project, user).await.unwrap().unwrap();
+        assert_eq!(found.role_ids, vec![viewer]);
+        assert!(repo.allowed_in_project(user, project, "view_work_packages").await.unwrap());
+        assert!(!repo.allowed_in_project(user, project, "edit_work_packages").await.unwrap());
+
+        repo.update(member.id, UpdateMemberDto { role_ids: Some(vec![editor]) }).await.unwrap();
+        let mut permissions = repo.permissions_in_project(user, project).await.unwrap();
+        permissions.sort();
+        assert_eq!(permissions, vec!["edit_work_packages", "view_work_packages"]);
+
+        repo.delete(member.id).await.unwrap();
+        assert!(repo.find_by_project_and_user(project, user).await.unwrap().is_none());
+        assert!(!repo.allowed_in_project(user, project, "view_work_packages").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_shares_grant_only_their_entity() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let guest = db.insert_user(UserFixture::new("guest")).await;
+        let project = db.insert_project(ProjectFixture::new("shares")).await;
+        let shared = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let viewer = db.insert_role("Work package viewer", &["view_work_packages"]).await;
+        let repo = db.members();
+
+        repo.create(CreateMemberDto {
+            user_id: guest,
+            project_id: Some(project),
+            role_ids: vec![viewer],
+            entity_type: Some(entity_type::WORK_PACKAGE.into()),
+            entity_id: Some(shared),
+        })
+        .await
+        .unwrap();
+
+        let permissions = repo
+            .permissions_on_entity(guest, entity_type::WORK_PACKAGE, shared)
+            .await
+            .unwrap();
+        assert_eq!(permissions, vec!["view_work_packages"]);
+        assert!(repo.permissions_on_entity(guest, entity_type::WORK_PACKAGE, shared + 1).await.unwrap().is_empty());
+
+        // A share is neither a project membership nor grants project permissions
+        assert!(!repo.allowed_in_project(guest, project, "view_work_packages").await.unwrap());
+        assert!(repo.find_by_project_and_user(project, guest).await.unwrap().is_none());
+        assert_eq!(repo.find_by_project(project, Pagination { limit: 10, offset: 0 }).await.unwrap().total, 0);
+        assert_eq!(repo.find_by_entity(entity_type::WORK_PACKAGE, shared).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_filters_sorts_and_pages_in_sql() {
+        let db = TestDb::connect().await;
+        let bob = db.insert_user(UserFixture::new("bob")).await;
+        let alice = db.insert_user(UserFixture::new("alice")).await;
+        let carol = db.insert_user(UserFixture::new("carol")).await;
+        let developers = db.insert_user(UserFixture::group("Developers")).await;
+        let project = db.insert_project(ProjectFixture::new("listed")).await;
+        let other = db.insert_project(ProjectFixture::new("other")).await;
+        let viewer = db.insert_role("Viewer", &["view_work_packages"]).await;
+        let editor = db.insert_role("Editor", &["edit_work_packages"]).await;
+        let manager = db.insert_role("Manager", &["view_members"]).await;
+        let shared = db.insert_work_package(WorkPackageFixture::new(project, bob)).await;
+        let repo = db.members();
+
+        repo.create(project_member(bob, project, vec![viewer])).await.unwrap();
+        let alices = repo.create(project_member(alice, project, vec![editor, viewer])).await.unwrap();
+        repo.create(project_member(developers, project, vec![viewer])).await.unwrap();
+        let carols = repo.create(project_member(carol, other, vec![manager])).await.unwrap();
+        let share = repo
+            .create(CreateMemberDto {
+                user_id: carol,
+                project_id: Some(project),
+                role_ids: vec![viewer],
+                entity_type: Some(entity_type::WORK_PACKAGE.into()),
+                entity_id: Some(shared),
+            })
+            .await
+            .unwrap();
+
+        let by_name = MemberQuery {
+            project_ids: Some(vec![project]),
+            order: MemberOrder::Name,
+            ..Default::default()
+        };
+        let first = repo.list(&by_name, Pagination::new(2, 0)).await.unwrap();
+        assert_eq!(first.total, 3);
+        assert_eq!(first.items.iter().map(|m| m.user_id).collect::<Vec<_>>(), vec![alice, bob]);
+        assert_eq!(first.items[0].principal_name, "alice Tester");
+        assert_eq!(first.items[0].role_ids, vec![viewer, editor]);
+        assert_eq!(first.items[0].role_names, vec!["Viewer", "Editor"]);
+        assert_eq!(first.items[0].project_name.as_deref(), Some("listed"));
+        let second = repo.list(&by_name, Pagination::new(2, 2)).await.unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].principal_name, "Developers");
+        assert_eq!(second.items[0].principal_type, crate::principal_type::GROUP);
+
+        let groups = MemberQuery {
+            principal_types: Some(vec![crate::principal_type::GROUP.into()]),
+            ..Default::default()
+        };
+        assert_eq!(repo.list(&groups, Pagination::new(10, 0)).await.unwrap().items[0].user_id, developers);
+        let editors = MemberQuery {
+            role_ids: Some(vec![editor]),
+            ..Default::default()
+        };
+        assert_eq!(repo.list(&editors, Pagination::new(10, 0)).await.unwrap().items[0].id, alices.id);
+        let future = MemberQuery {
+            created_from: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        assert_eq!(repo.list(&future, Pagination::new(10, 0)).await.unwrap().total, 0);
+
+        // Only memberships of projects granting view_members are visible
+        let visible = |user_id| MemberQuery {
+            visible_to: Some(user_id),
+            ..Default::default()
+        };
+        let carols_view = repo.list(&visible(carol), Pagination::new(10, 0)).await.unwrap();
+        assert_eq!(carols_view.items.iter().map(|m| m.id).collect::<Vec<_>>(), vec![carols.id]);
+        assert_eq!(repo.list(&visible(bob), Pagination::new(10, 0)).await.unwrap().total, 0);
+
+        // Shares are not memberships
+        assert_eq!(repo.list(&MemberQuery::default(), Pagination::new(10, 0)).await.unwrap().total, 4);
+        assert!(repo.find_listed(share.id).await.unwrap().is_none());
+        assert_eq!(repo.find_listed(alices.id).await.unwrap().unwrap().user_id, alice);
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_permissions_only_in_public_projects() {
+        let db = TestDb::connect().await;
+        let public = db.insert_project(ProjectFixture::new("anonymous-public").with_public()).await;
+        let private = db.insert_project(ProjectFixture::new("anonymous-private")).await;
+        let anonymous = db.insert_role("Anonymous", &["view_work_packages"]).await;
+        let repo = db.members();
+
+        // Ordinary roles grant anonymous users nothing
+        assert!(repo.anonymous_permissions_in_project(public).await.unwrap().is_empty());
+
+        sqlx::query("UPDATE roles SET builtin = $1 WHERE id = $2")
+            .bind(role_builtin::ANONYMOUS)
+            .bind(anonymous)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(repo.anonymous_permissions_in_project(public).await.unwrap(), vec!["view_work_packages"]);
+        assert!(repo.anonymous_permissions_in_project(private).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+//! Schema migrations
+//!
+//! The `migrations/` directory holds the tables and columns op-rs reads and
+//! writes, embedded into the binary so op-server can set up an empty
+//! database. A database managed by the Rails app is not migrated; instead
+//! [`check_schema`] compares it with [`REQUIRED_COLUMNS`] and reports what
+//! is missing.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use sqlx::migrate::Migrator;
+use sqlx::PgConnection;
+
+/// Migrations of the op-rs schema
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Columns op-rs expects, per table
+pub const REQUIRED_COLUMNS: &[(&str, &[&str])] = &[
+    ("users", &[
+        "id", "type", "login", "firstname", "lastname", "mail", "admin", "status", "language",
+        "hashed_password", "salt", "last_login_on", "created_at", "updated_at",
+    ]),
+    ("user_preferences", &["id", "user_id", "settings"]),
+    ("projects", &[
+        "id", "name", "description", "identifier", "public", "parent_id", "lft", "rgt", "active",
+        "templated", "settings", "default_version_id", "created_at", "updated_at",
+    ]),
+    ("types", &[
+        "id", "name", "position", "is_default", "is_in_roadmap", "is_milestone", "is_standard",
+        "color_id", "description", "form_configuration", "created_at", "updated_at",
+    ]),
+    ("statuses", &[
+        "id", "name", "is_closed", "is_default", "is_readonly", "position", "default_done_ratio",
+        "color_id", "created_at", "updated_at",
+    ]),
+    ("colors", &["id", "hexcode"]),
+    ("enumerations", &[
+        "id", "type", "name", "position", "is_default", "active", "color_id", "project_id",
+        "parent_id", "created_at", "updated_at",
+    ]),
+    ("roles", &["id", "name", "position", "builtin", "type", "created_at", "updated_at"]),
+    ("role_permissions", &["id", "role_id", "permission"]),
+    ("members", &["id", "user_id", "project_id", "entity_type", "entity_id", "created_at", "updated_at"]),
+    ("member_roles", &["id", "member_id", "role_id", "inherited_from"]),
+    ("group_users", &["id", "group_id", "user_id"]),
+    ("ldap_groups_synchronized_groups", &[
+        "id", "group_id", "ldap_source", "entry_id", "dn", "created_at", "updated_at",
+    ]),
+    ("work_packages", &[
+        "id", "subject", "description", "project_id", "type_id", "status_id", "priority_id",
+        "author_id", "assigned_to_id", "responsible_id", "start_date", "due_date", "estimated_hours",
+        "done_ratio", "parent_id", "version_id", "category_id", "lock_version", "position",
+        "story_points", "remaining_hours", "schedule_manually", "duration", "ignore_non_working_days",
+        "labor_costs", "material_costs", "overall_costs", "deleted_at", "deleted_by", "created_at",
+        "updated_at",
+    ]),
+    ("work_package_summaries", &["project_id", "type_id", "status_id", "priority_id", "count"]),
+    ("work_package_views", &["user_id", "work_package_id", "viewed_at"]),
+    ("journals", &[
+        "id", "journable_type", "journable_id", "user_id", "notes", "version", "data_type", "data_id",
+        "cause", "restricted", "created_at", "updated_at",
+    ]),
+    ("work_package_journals", &[
+        "id", "type_id", "project_id", "subject", "description", "due_date", "category_id",
+        "status_id", "assigned_to_id", "priority_id", "version_id", "author_id", "done_ratio",
+        "estimated_hours", "start_date", "parent_id", "responsible_id", "derived_estimated_hours",
+        "schedule_manually", "duration", "ignore_non_working_days", "derived_remaining_hours",
+        "derived_done_ratio",
+    ]),
+    ("versions", &[
+        "id", "project_id", "name", "description", "effective_date", "start_date", "status",
+        "sharing", "wiki_page_title", "created_at", "updated_at",
+    ]),
+    ("categories", &["id", "project_id", "name", "assigned_to_id", "created_at", "updated_at"]),
+    ("projects_types", &["project_id", "type_id"]),
+    ("enabled_modules", &["project_id", "name"]),
+    ("custom_fields", &[
+        "id", "type", "name", "field_format", "is_required", "is_for_all", "position",
+        "custom_field_section_id",
+    ]),
+    ("custom_field_sections", &["id", "type", "name", "position"]),
+    ("custom_fields_projects", &["custom_field_id", "project_id"]),
+    ("custom_values", &["id", "customized_type", "customized_id", "custom_field_id", "value"]),
+    ("watchers", &["id", "watchable_type", "watchable_id", "user_id"]),
+    ("storages", &["id", "provider_type", "name", "host", "creator_id", "created_at", "updated_at"]),
+    ("oauth_clients", &["id", "client_id", "client_secret", "integration_type", "integration_id"]),
+    ("file_links", &[
+        "id", "storage_id", "creator_id", "container_id", "container_type", "origin_id", "origin_name",
+        "origin_mime_type", "origin_created_by_name", "origin_created_at", "origin_updated_at",
+        "created_at", "updated_at",
+    ]),
+    ("relations", &["id", "from_id", "to_id", "relation_type", "lag", "description", "created_at", "updated_at"]),
+    ("attachments", &[
+        "id", "container_id", "container_type", "filename", "disk_filename", "filesize",
+        "content_type", "digest", "downloads", "author_id", "description", "status", "created_at",
+        "updated_at",
+    ]),
+    ("attachment_uploads", &[
+        "id", "author_id", "filename", "content_type", "description", "container_type", "container_id",
+        "filesize", "digest_algorithm", "chunks", "created_at", "updated_at",
+    ]),
+    ("time_entries", &[
+        "id", "project_id", "user_id", "work_package_id", "hours", "comments", "activity_id",
+        "spent_on", "tyear", "tmonth", "tweek", "overridden_costs", "costs", "rate_id",
+        "logged_by_id", "created_at", "updated_at",
+    ]),
+    ("cost_types", &["id", "name", "unit", "unit_plural", "is_default", "deleted_at"]),
+    ("cost_entries", &[
+        "id", "user_id", "project_id", "work_package_id", "cost_type_id", "units", "spent_on",
+        "comments", "overridden_costs", "costs", "rate_id", "logged_by_id", "created_at", "updated_at",
+    ]),
+    ("rates", &["id", "type", "rate", "valid_from", "user_id", "project_id", "cost_type_id"]),
+    ("queries", &[
+        "id", "project_id", "user_id", "name", "filters", "column_names", "sort_criteria", "group_by",
+        "display_sums", "show_hierarchies", "include_subprojects", "timeline_visible", "timestamps",
+        "created_at", "updated_at",
+    ]),
+    ("query_subscriptions", &[
+        "id", "query_id", "user_id", "frequency", "snapshot", "checked_at", "suspended_at",
+        "suspended_reason", "created_at", "updated_at",
+    ]),
+    ("scheduled_jobs", &["name", "last_run_at", "updated_at"]),
+    ("idempotency_keys", &[
+        "id", "key", "user_id", "fingerprint", "response_status", "response_content_type",
+        "response_body", "resource_id", "created_at",
+    ]),
+    ("colors", &["id", "name", "hexcode"]),
+    ("views", &["query_id", "type"]),
+    ("query_menu_items", &["navigatable_id", "name", "title"]),
+    ("notifications", &[
+        "id", "recipient_id", "actor_id", "project_id", "resource_type", "resource_id", "journal_id",
+        "reason", "read_ian", "mail_reminder_sent", "mail_alert_sent", "created_at", "updated_at",
+    ]),
+    ("audit_events", &[
+        "id", "event_type", "actor_id", "target_type", "target_id", "ip_address", "user_agent",
+        "details", "occurred_at",
+    ]),
+    ("tokens", &[
+        "id", "user_id", "type", "value", "name", "last_chars", "scopes", "expires_on", "last_used_at",
+        "created_at",
+    ]),
+    ("settings", &["id", "name", "value"]),
+    ("workflows", &["id", "type_id", "old_status_id", "new_status_id", "role_id", "assignee", "author"]),
+    ("wikis", &["id", "project_id"]),
+    ("wiki_pages", &["id", "wiki_id"]),
+    ("documents", &["id", "project_id", "category_id", "title", "description", "created_at", "updated_at"]),
+    ("news", &["id", "project_id"]),
+    ("forums", &[
+        "id", "project_id", "name", "description", "position", "topics_count", "messages_count",
+        "last_message_id",
+    ]),
+    ("messages", &[
+        "id", "forum_id", "parent_id", "subject", "content", "author_id", "replies_count",
+        "last_reply_id", "locked", "sticky", "sticked_on", "created_at", "updated_at",
+    ]),
+    ("message_journals", &["id", "forum_id", "parent_id", "subject", "content", "author_id", "locked", "sticky"]),
+    ("grids", &["id", "type", "project_id", "user_id", "name", "row_count", "column_count", "options"]),
+    ("grid_widgets", &["id", "grid_id", "identifier", "start_row", "end_row", "start_column", "end_column", "options"]),
+    ("meetings", &["id", "project_id"]),
+    ("custom_actions", &["id", "name", "description", "position", "conditions", "actions", "created_at", "updated_at"]),
+];
+
+/// Difference between the database and the schema op-rs expects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaMismatch {
+    MissingTable(&'static str),
+    MissingColumn { table: &'static str, column: &'static str },
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingTable(table) => write!(f, "table {} is missing", table),
+            Self::MissingColumn { table, column } => write!(f, "column {}.{} is missing", table, column),
+        }
+    }
+}
+
+/// Result of comparing a database with [`REQUIRED_COLUMNS`]
+#[derive(Debug, Clone, Default)]
+pub struct SchemaReport {
+    pub mismatches: Vec<SchemaMismatch>,
+}
+
+impl SchemaReport {
+    /// Compare the columns per table found in a database with the required ones
+    pub fn compare(existing: &BTreeMap<String, BTreeSet<String>>) -> Self {
+        let mut mismatches = Vec::new();
+        for (table, columns) in REQUIRED_COLUMNS {
+            let Some(found) = existing.get(*table) else {
+                mismatches.push(SchemaMismatch::MissingTable(table));
+                continue;
+            };
+            mismatches.extend(
+                columns
+                    .iter()
+                    .filter(|column| !found.contains(**column))
+                    .map(|column| SchemaMismatch::MissingColumn { table, column }),
+            );
+        }
+        Self { mismatches }
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for SchemaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_compatible() {
+            return write!(f, "schema is compatible");
+        }
+        write!(f, "{} schema mismatches: ", self.mismatches.len())?;
+        for (i, mismatch) in self.mismatches.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the tables of the connection's current schema with
+/// [`REQUIRED_COLUMNS`], without changing anything
+pub async fn check_schema(conn: &mut PgConnection) -> Result<SchemaReport, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, String)>(
+        r#"
+        SELECT table_name::TEXT, column_name::TEXT
+        FROM information_schema.columns
+        WHERE table_schema = current_schema()
+        "#,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    let mut existing: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (table, column) in rows {
+        existing.entry(table).or_default().insert(column);
+    }
+    Ok(SchemaReport::compare(&existing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(tables: &[(&str, &[&str])]) -> BTreeMap<String, BTreeSet<String>> {
+        tables
+            .iter()
+            .map(|(table, columns)| (table.to_string(), columns.iter().map(|c| c.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_required_schema_is_compatible() {
+        let report = SchemaReport::compare(&schema(REQUIRED_COLUMNS));
+        assert!(report.is_compatible());
+        assert_eq!(report.to_string(), "schema is compatible");
+    }
+
+    #[test]
+    fn test_report_lists_missing_tables_and_columns() {
+        let mut existing = schema(REQUIRED_COLUMNS);
+        existing.remove("query_subscriptions");
+        existing.get_mut("work_packages").unwrap().remove("story_points");
+
+        let report = SchemaReport::compare(&existing);
+        assert_eq!(
+            report.mismatches,
+            vec![
+                SchemaMismatch::MissingColumn { table: "work_packages", column: "story_points" },
+                SchemaMismatch::MissingTable("query_subscriptions"),
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "2 schema mismatches: column work_packages.story_points is missing; table query_subscriptions is missing"
+        );
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::{
+        CreateProjectDto, CreateUserDto, CreateVersionDto, Database, DatabaseConfig, ProjectRepository, Repository,
+        UserRepository, VersionRepository,
+    };
+
+    /// Database whose connections work in an empty schema of their own
+    async fn temp_database(schema: &str) -> Database {
+        let url = std::env::var("DATABASE_URL").expect("pg-tests need DATABASE_URL to be set");
+        let admin = Database::connect(&DatabaseConfig::with_url(&url)).await.unwrap();
+        sqlx::query(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+            .execute(admin.pool())
+            .await
+            .unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {}", schema)).execute(admin.pool()).await.unwrap();
+        admin.close().await;
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let config = DatabaseConfig {
+            min_connections: 0,
+            ..DatabaseConfig::with_url(format!("{}{}options[search_path]={}", url, separator, schema))
+        };
+        Database::connect(&config).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_migrations_boot_an_empty_database() {
+        let db = temp_database("op_rs_migrate_test").await;
+        db.migrate().await.unwrap();
+        db.migrate().await.unwrap();
+        let report = db.check_schema().await.unwrap();
+        assert!(report.is_compatible(), "{}", report);
+
+        let user = UserRepository::new(db.pool().clone())
+            .create(CreateUserDto {
+                login: "admin".into(),
+                firstname: "Ada".into(),
+                lastname: "Admin".into(),
+                mail: "admin@example.com".into(),
+                admin: true,
+                status: crate::user_status::ACTIVE,
+                language: None,
+                hashed_password: None,
+                salt: None,
+            })
+            .await
+            .unwrap();
+        let projects = ProjectRepository::new(db.pool().clone());
+        let project = projects
+            .create(CreateProjectDto {
+                name: "Demo".into(),
+                description: None,
+                identifier: "demo".into(),
+                public: false,
+                parent_id: None,
+                active: true,
+                templated: false,
+            })
+            .await
+            .unwrap();
+        let versions = VersionRepository::new(db.pool().clone());
+        let version = versions
+            .create(CreateVersionDto {
+                project_id: project.id,
+                name: "1.0".into(),
+                description: None,
+                effective_date: None,
+                start_date: None,
+                status: None,
+                sharing: None,
+                wiki_page_title: None,
+            })
+            .await
+            .unwrap();
+
+        let users = UserRepository::new(db.pool().clone());
+        assert_eq!(users.find_by_id(user.id).await.unwrap().unwrap().login, "admin");
+        assert_eq!(projects.find_by_id(project.id).await.unwrap().unwrap().identifier, "demo");
+        assert_eq!(versions.find_by_id(version.id).await.unwrap().unwrap().project_id, project.id);
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_an_incomplete_schema_without_migrating() {
+        let db = temp_database("op_rs_check_test").await;
+        sqlx::query("CREATE TABLE users (id BIGSERIAL PRIMARY KEY, login VARCHAR(255) NOT NULL)")
+            .execute(db.pool())
+            .await
+            .unwrap();
+
+        let report = db.check_schema().await.unwrap();
+        assert!(report
+            .mismatches
+            .contains(&SchemaMismatch::MissingColumn { table: "users", column: "mail" }));
+        assert!(report.mismatches.contains(&SchemaMismatch::MissingTable("work_packages")));
+        assert!(!report.mismatches.contains(&SchemaMismatch::MissingColumn { table: "users", column: "login" }));
+
+        let migrated = sqlx::query_scalar::<_, bool>("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+        assert!(!migrated);
+        db.close().await;
+    }
+}
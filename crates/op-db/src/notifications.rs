@@ -0,0 +1,195 @@
+//! Notifications repository
+//!
+//! Mirrors: app/models/notification.rb
+//!
+//! Bookkeeping of the emails sent about notifications. A notification
+//! emailed immediately gets `mail_alert_sent`, one included in a digest
+//! `mail_reminder_sent`. Digest runs claim the notifications they send by
+//! setting the flag before sending, so a run that restarts, or that is
+//! split across workers, never sends a notification twice; a run that
+//! fails to send releases its claim.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// Notifications about work packages that are gone
+pub const NOTIFICATION_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::polymorphic("notifications", "resource_id", "resource_type", "WorkPackage", "work_packages", OrphanAction::Delete),
+];
+
+/// Notification row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct NotificationRow {
+    pub id: Id,
+    pub recipient_id: Id,
+    pub actor_id: Option<Id>,
+    pub project_id: Option<Id>,
+    pub resource_type: String,
+    pub resource_id: Id,
+    pub journal_id: Option<Id>,
+    pub reason: Option<i32>,
+    pub read_ian: bool,
+    pub mail_reminder_sent: bool,
+    pub mail_alert_sent: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const NOTIFICATION_COLUMNS: &str = "id, recipient_id, actor_id, project_id, resource_type, resource_id, journal_id, \
+     reason, read_ian, mail_reminder_sent, mail_alert_sent, created_at, updated_at";
+
+/// Notifications repository
+pub struct NotificationRepository {
+    db: DbExecutor,
+}
+
+impl NotificationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Notifications of the recipient neither included in a digest nor
+    /// emailed immediately, oldest first
+    pub async fn find_pending_reminders(&self, recipient_id: Id) -> RepositoryResult<Vec<NotificationRow>> {
+        let rows = sqlx::query_as::<_, NotificationRow>(&format!(
+            "SELECT {} FROM notifications \
+             WHERE recipient_id = $1 AND NOT mail_reminder_sent AND NOT mail_alert_sent \
+             ORDER BY id",
+            NOTIFICATION_COLUMNS
+        ))
+        .bind(recipient_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Claim up to `limit` pending notifications of the recipient for a
+    /// digest, oldest first, marking them as sent in the same statement.
+    /// Rows another run is claiming are skipped rather than waited for.
+    pub async fn claim_reminders(&self, recipient_id: Id, limit: i64) -> RepositoryResult<Vec<NotificationRow>> {
+        let mut rows = sqlx::query_as::<_, NotificationRow>(&format!(
+            r#"
+            UPDATE notifications SET mail_reminder_sent = TRUE, updated_at = NOW()
+            WHERE id IN (
+                SELECT id FROM notifications
+                WHERE recipient_id = $1 AND NOT mail_reminder_sent AND NOT mail_alert_sent
+                ORDER BY id
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            AND NOT mail_reminder_sent
+            RETURNING {}
+            "#,
+            NOTIFICATION_COLUMNS
+        ))
+        .bind(recipient_id)
+        .bind(limit)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        rows.sort_by_key(|row| row.id);
+        Ok(rows)
+    }
+
+    /// Release claimed notifications whose digest could not be sent,
+    /// returning the number released
+    pub async fn release_reminders(&self, ids: &[Id]) -> RepositoryResult<u64> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let result = sqlx::query(
+            "UPDATE notifications SET mail_reminder_sent = FALSE, updated_at = NOW() \
+             WHERE id = ANY($1) AND mail_reminder_sent",
+        )
+        .bind(ids)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Mark notifications as emailed immediately
+    pub async fn mark_alerts_sent(&self, ids: &[Id]) -> RepositoryResult<u64> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let result = sqlx::query(
+            "UPDATE notifications SET mail_alert_sent = TRUE, updated_at = NOW() \
+             WHERE id = ANY($1) AND NOT mail_alert_sent",
+        )
+        .bind(ids)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    async fn notify(db: &TestDb, user_id: Id, work_package_id: Id) -> Id {
+        sqlx::query_scalar(
+            "INSERT INTO notifications (recipient_id, resource_type, resource_id) \
+             VALUES ($1, 'WorkPackage', $2) RETURNING id",
+        )
+        .bind(user_id)
+        .bind(work_package_id)
+        .fetch_one(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reminders_are_claimed_once_and_released_on_failure() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("digest")).await;
+        let other = db.insert_user(UserFixture::new("other")).await;
+        let project = db.insert_project(ProjectFixture::new("digests")).await;
+        let work_package = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(notify(&db, user, work_package).await);
+        }
+        let alerted = notify(&db, user, work_package).await;
+        notify(&db, other, work_package).await;
+
+        let repo = db.notifications();
+        assert_eq!(repo.mark_alerts_sent(&[alerted]).await.unwrap(), 1);
+        let pending: Vec<Id> = repo.find_pending_reminders(user).await.unwrap().iter().map(|n| n.id).collect();
+        assert_eq!(pending, ids);
+
+        let first: Vec<Id> = repo.claim_reminders(user, 2).await.unwrap().iter().map(|n| n.id).collect();
+        assert_eq!(first, ids[..2]);
+        let second = repo.claim_reminders(user, 10).await.unwrap();
+        assert_eq!(second.iter().map(|n| n.id).collect::<Vec<_>>(), ids[2..]);
+        assert!(second[0].mail_reminder_sent);
+        assert!(repo.claim_reminders(user, 10).await.unwrap().is_empty());
+
+        // The failed batch is claimed again by the retry, and only once
+        assert_eq!(repo.release_reminders(&first).await.unwrap(), 2);
+        assert_eq!(repo.release_reminders(&first).await.unwrap(), 0);
+        let retried: Vec<Id> = repo.claim_reminders(user, 10).await.unwrap().iter().map(|n| n.id).collect();
+        assert_eq!(retried, first);
+        assert!(repo.claim_reminders(user, 10).await.unwrap().is_empty());
+        assert_eq!(repo.find_pending_reminders(other).await.unwrap().len(), 1);
+    }
+}
@@ -2,9 +2,12 @@
 //!
 //! Provides PostgreSQL connection pooling using SQLx.
 
+use sqlx::migrate::MigrateError;
 use sqlx::postgres::{PgPool, PgPoolOptions};
 use std::time::Duration;
 
+use crate::migrations::{self, SchemaReport, MIGRATOR};
+
 /// Database configuration
 #[derive(Debug, Clone)]
 pub struct DatabaseConfig {
@@ -113,6 +116,19 @@ impl Database {
         Ok(())
     }
 
+    /// Apply the embedded migrations not yet applied to the database
+    pub async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.pool).await?;
+        tracing::info!("Database schema migrated");
+        Ok(())
+    }
+
+    /// Compare the database schema with the one op-rs expects, without
+    /// changing it. For databases managed by the Rails app.
+    pub async fn check_schema(&self) -> Result<SchemaReport, sqlx::Error> {
+        migrations::check_schema(&mut *self.pool.acquire().await?).await
+    }
+
     /// Close the connection pool
     pub async fn close(&self) {
         self.pool.close().await;
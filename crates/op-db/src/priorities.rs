@@ -9,7 +9,9 @@ use chrono::{DateTime, Utc};
 use op_core::traits::Id;
 use sqlx::{FromRow, PgPool};
 
+use crate::executor::{DbExecutor, DbTransaction};
 use crate::repository::{Repository, RepositoryError, RepositoryResult};
+use crate::work_packages::{count_referencing, reassign_referencing, ReferenceColumn};
 
 const PRIORITY_TYPE: &str = "IssuePriority";
 
@@ -68,12 +70,39 @@ pub struct UpdatePriorityDto {
 
 /// Priority repository implementation
 pub struct PriorityRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl PriorityRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Find priorities by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[Id]) -> RepositoryResult<Vec<PriorityRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, PriorityRow>(
+            r#"
+            SELECT id, name, position, is_default, active, color_id, project_id, parent_id, created_at, updated_at
+            FROM enumerations
+            WHERE id = ANY($1) AND type = $2
+            "#,
+        )
+        .bind(ids)
+        .bind(PRIORITY_TYPE)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
     }
 
     /// Find the default priority
@@ -87,7 +116,7 @@ impl PriorityRepository {
             "#,
         )
         .bind(PRIORITY_TYPE)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -104,7 +133,7 @@ impl PriorityRepository {
             "#,
         )
         .bind(PRIORITY_TYPE)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -121,7 +150,7 @@ impl PriorityRepository {
             "#,
         )
         .bind(PRIORITY_TYPE)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -139,7 +168,7 @@ impl PriorityRepository {
         )
         .bind(PRIORITY_TYPE)
         .bind(project_id)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -156,7 +185,7 @@ impl PriorityRepository {
         )
         .bind(PRIORITY_TYPE)
         .bind(name)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -216,7 +245,7 @@ impl PriorityRepository {
             .bind(name),
         };
 
-        let unique = query.fetch_one(&self.pool).await?;
+        let unique = query.fetch_one(&mut *self.db.acquire().await?).await?;
         Ok(unique)
     }
 
@@ -226,7 +255,7 @@ impl PriorityRepository {
             "SELECT MAX(position) FROM enumerations WHERE type = $1",
         )
         .bind(PRIORITY_TYPE)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?
         .unwrap_or(0);
 
@@ -240,11 +269,40 @@ impl PriorityRepository {
         )
         .bind(PRIORITY_TYPE)
         .bind(id)
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
     }
+
+    /// Number of work packages of the priority
+    pub async fn work_package_count(&self, id: Id) -> RepositoryResult<i64> {
+        count_referencing(&mut *self.db.acquire().await?, ReferenceColumn::Priority, id).await
+    }
+
+    /// Delete the priority after moving its work packages to `reassign_to`,
+    /// all in one transaction. Each moved work package is journaled as a
+    /// system update by the user. Returns the number of work packages moved.
+    pub async fn delete_reassigning(&self, id: Id, reassign_to: Id, user_id: Id) -> RepositoryResult<u64> {
+        if reassign_to == id || !self.exists(reassign_to).await? {
+            return Err(RepositoryError::invalid("reassign_to", "invalid", "must be another existing priority"));
+        }
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        let moved = reassign_referencing(&mut tx, ReferenceColumn::Priority, id, reassign_to, user_id).await?;
+
+        let result = sqlx::query("DELETE FROM enumerations WHERE id = $1 AND type = $2")
+            .bind(id)
+            .bind(PRIORITY_TYPE)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Priority with id {} not found", id)));
+        }
+
+        tx.commit().await?;
+        Ok(moved)
+    }
 }
 
 #[async_trait]
@@ -259,7 +317,7 @@ impl Repository<PriorityRow, CreatePriorityDto, UpdatePriorityDto> for PriorityR
         )
         .bind(id)
         .bind(PRIORITY_TYPE)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -278,7 +336,7 @@ impl Repository<PriorityRow, CreatePriorityDto, UpdatePriorityDto> for PriorityR
         .bind(PRIORITY_TYPE)
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -289,7 +347,7 @@ impl Repository<PriorityRow, CreatePriorityDto, UpdatePriorityDto> for PriorityR
             "SELECT COUNT(*) FROM enumerations WHERE type = $1",
         )
         .bind(PRIORITY_TYPE)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(count)
@@ -325,7 +383,7 @@ impl Repository<PriorityRow, CreatePriorityDto, UpdatePriorityDto> for PriorityR
         .bind(dto.active)
         .bind(dto.color_id)
         .bind(dto.project_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         // If this is the new default, clear default on others
@@ -369,7 +427,7 @@ impl Repository<PriorityRow, CreatePriorityDto, UpdatePriorityDto> for PriorityR
         .bind(dto.color_id)
         .bind(id)
         .bind(PRIORITY_TYPE)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?
         .ok_or_else(|| RepositoryError::NotFound(format!("Priority with id {} not found", id)))?;
 
@@ -382,24 +440,19 @@ impl Repository<PriorityRow, CreatePriorityDto, UpdatePriorityDto> for PriorityR
     }
 
     async fn delete(&self, id: Id) -> RepositoryResult<()> {
-        // Check if any work packages use this priority
-        let has_work_packages = sqlx::query_scalar::<_, bool>(
-            "SELECT EXISTS(SELECT 1 FROM work_packages WHERE priority_id = $1)",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
-
-        if has_work_packages {
-            return Err(RepositoryError::Conflict(
-                "Cannot delete priority: work packages are using this priority".to_string(),
-            ));
+        // Work packages of the priority have to be moved first
+        let work_packages = self.work_package_count(id).await?;
+        if work_packages > 0 {
+            return Err(RepositoryError::Conflict(format!(
+                "Cannot delete priority: {} work packages are using this priority",
+                work_packages
+            )));
         }
 
         let result = sqlx::query("DELETE FROM enumerations WHERE id = $1 AND type = $2")
             .bind(id)
             .bind(PRIORITY_TYPE)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -418,7 +471,7 @@ impl Repository<PriorityRow, CreatePriorityDto, UpdatePriorityDto> for PriorityR
         )
         .bind(id)
         .bind(PRIORITY_TYPE)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(exists)
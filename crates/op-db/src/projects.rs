@@ -2,12 +2,25 @@
 //!
 //! Database operations for projects.
 
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use op_core::traits::Id;
-use sqlx::{FromRow, PgPool};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
 
+use crate::custom_fields::{CustomValueFilter, PROJECT_CUSTOMIZED_TYPE};
+use crate::executor::{DbExecutor, DbTransaction};
 use crate::repository::{Pagination, PaginatedResult, Repository, RepositoryError, RepositoryResult};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// Projects whose parent is gone become top-level projects
+pub const PROJECT_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::new("projects", "parent_id", "projects", OrphanAction::Nullify),
+];
 
 /// Project database entity
 #[derive(Debug, Clone, FromRow)]
@@ -21,6 +34,8 @@ pub struct ProjectRow {
     pub lft: i32,
     pub rgt: i32,
     pub active: bool,
+    /// Whether new projects can be instantiated from this one
+    pub templated: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -53,6 +68,7 @@ pub struct CreateProjectDto {
     pub public: bool,
     pub parent_id: Option<i64>,
     pub active: bool,
+    pub templated: bool,
 }
 
 /// DTO for updating a project
@@ -60,19 +76,214 @@ pub struct CreateProjectDto {
 pub struct UpdateProjectDto {
     pub name: Option<String>,
     pub description: Option<String>,
+    pub identifier: Option<String>,
     pub public: Option<bool>,
     pub parent_id: Option<i64>,
     pub active: Option<bool>,
+    pub templated: Option<bool>,
+}
+
+impl CreateProjectDto {
+    /// Row the project would be created as, for dry runs; it has no place
+    /// in the tree yet and an id of 0
+    pub fn preview(&self) -> ProjectRow {
+        let now = Utc::now();
+        ProjectRow {
+            id: 0,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            identifier: self.identifier.clone(),
+            public: self.public,
+            parent_id: self.parent_id,
+            lft: 0,
+            rgt: 0,
+            active: self.active,
+            templated: self.templated,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+impl UpdateProjectDto {
+    /// The project as updating it would leave it, for dry runs. Like the
+    /// update, the parent is kept.
+    pub fn preview(&self, project: ProjectRow) -> ProjectRow {
+        ProjectRow {
+            name: self.name.clone().unwrap_or(project.name),
+            description: self.description.clone().or(project.description),
+            identifier: self.identifier.clone().unwrap_or(project.identifier),
+            public: self.public.unwrap_or(project.public),
+            active: self.active.unwrap_or(project.active),
+            templated: self.templated.unwrap_or(project.templated),
+            updated_at: Utc::now(),
+            ..project
+        }
+    }
+}
+
+/// Associated data copied along with a project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyDependency {
+    Members,
+    Versions,
+    Categories,
+    WorkPackageTypes,
+    Modules,
+}
+
+impl CopyDependency {
+    pub const ALL: [CopyDependency; 5] = [
+        Self::Members,
+        Self::Versions,
+        Self::Categories,
+        Self::WorkPackageTypes,
+        Self::Modules,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.as_str() == s)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Members => "members",
+            Self::Versions => "versions",
+            Self::Categories => "categories",
+            Self::WorkPackageTypes => "work_package_types",
+            Self::Modules => "modules",
+        }
+    }
+
+    /// Statement copying the dependency from project `$1` to project `$2`
+    fn copy_sql(&self) -> &'static str {
+        match self {
+            Self::Members => {
+                r#"
+                WITH source AS (
+                    SELECT id, user_id, entity_type, entity_id
+                    FROM members
+                    WHERE project_id = $1
+                ), copied AS (
+                    INSERT INTO members (user_id, project_id, entity_type, entity_id, created_at, updated_at)
+                    SELECT user_id, $2, entity_type, entity_id, NOW(), NOW()
+                    FROM source
+                    RETURNING id, user_id, entity_type, entity_id
+                )
+                INSERT INTO member_roles (member_id, role_id)
+                SELECT c.id, mr.role_id
+                FROM copied c
+                JOIN source s ON s.user_id = c.user_id
+                    AND s.entity_type IS NOT DISTINCT FROM c.entity_type
+                    AND s.entity_id IS NOT DISTINCT FROM c.entity_id
+                JOIN member_roles mr ON mr.member_id = s.id
+                "#
+            }
+            Self::Versions => {
+                r#"
+                INSERT INTO versions (project_id, name, description, effective_date, start_date,
+                                      status, sharing, wiki_page_title, created_at, updated_at)
+                SELECT $2, name, description, effective_date, start_date,
+                       status, sharing, wiki_page_title, NOW(), NOW()
+                FROM versions
+                WHERE project_id = $1
+                "#
+            }
+            Self::Categories => {
+                r#"
+                INSERT INTO categories (project_id, name, assigned_to_id, created_at, updated_at)
+                SELECT $2, name, assigned_to_id, NOW(), NOW()
+                FROM categories
+                WHERE project_id = $1
+                "#
+            }
+            Self::WorkPackageTypes => {
+                r#"
+                INSERT INTO projects_types (project_id, type_id)
+                SELECT $2, type_id
+                FROM projects_types
+                WHERE project_id = $1
+                "#
+            }
+            Self::Modules => {
+                r#"
+                INSERT INTO enabled_modules (project_id, name)
+                SELECT $2, name
+                FROM enabled_modules
+                WHERE project_id = $1
+                "#
+            }
+        }
+    }
 }
 
 /// Project repository implementation
+/// Sort order of a project listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProjectOrder {
+    /// Position in the project tree
+    #[default]
+    Hierarchy,
+    /// Newest change to the project or one of its work packages
+    LatestActivity { descending: bool },
+}
+
+impl ProjectOrder {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Hierarchy => "p.lft ASC",
+            Self::LatestActivity { descending: false } => "GREATEST(p.updated_at, la.at) ASC, p.id ASC",
+            Self::LatestActivity { descending: true } => "GREATEST(p.updated_at, la.at) DESC, p.id DESC",
+        }
+    }
+
+    fn join(self) -> &'static str {
+        match self {
+            Self::Hierarchy => "",
+            Self::LatestActivity { .. } => {
+                "LEFT JOIN LATERAL (SELECT MAX(j.created_at) AS at FROM journals j \
+                 JOIN work_packages wp ON j.journable_type = 'WorkPackage' AND j.journable_id = wp.id \
+                 WHERE wp.project_id = p.id) la ON TRUE"
+            }
+        }
+    }
+}
+
 pub struct ProjectRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl ProjectRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Find projects by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[Id]) -> RepositoryResult<Vec<ProjectRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, ProjectRow>(
+            r#"
+            SELECT id, name, description, identifier, public, parent_id,
+                   lft, rgt, active, templated, created_at, updated_at
+            FROM projects
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
     }
 
     /// Find a project by identifier
@@ -80,13 +291,13 @@ impl ProjectRepository {
         let row = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE identifier = $1
             "#,
         )
         .bind(identifier)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -100,7 +311,7 @@ impl ProjectRepository {
         let items = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE parent_id IS NULL
             ORDER BY lft ASC
@@ -109,12 +320,12 @@ impl ProjectRepository {
         )
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total =
             sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM projects WHERE parent_id IS NULL")
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -125,14 +336,14 @@ impl ProjectRepository {
         let rows = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE parent_id = $1
             ORDER BY lft ASC
             "#,
         )
         .bind(parent_id)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -143,7 +354,7 @@ impl ProjectRepository {
         let rows = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE lft < $1 AND rgt > $2
             ORDER BY lft ASC
@@ -151,7 +362,7 @@ impl ProjectRepository {
         )
         .bind(project.lft)
         .bind(project.rgt)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -162,7 +373,7 @@ impl ProjectRepository {
         let rows = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE lft > $1 AND rgt < $2
             ORDER BY lft ASC
@@ -170,7 +381,7 @@ impl ProjectRepository {
         )
         .bind(project.lft)
         .bind(project.rgt)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -184,7 +395,7 @@ impl ProjectRepository {
         let items = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE active = true
             ORDER BY lft ASC
@@ -193,12 +404,12 @@ impl ProjectRepository {
         )
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total =
             sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM projects WHERE active = true")
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -212,7 +423,7 @@ impl ProjectRepository {
         let items = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE public = true AND active = true
             ORDER BY lft ASC
@@ -221,18 +432,195 @@ impl ProjectRepository {
         )
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
             "SELECT COUNT(*) FROM projects WHERE public = true AND active = true",
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
     }
 
+    /// Find projects by template flag; templates are only listed when asked
+    /// for. `public_only` leaves out private and archived projects, e.g. for
+    /// anonymous users. Projects also have to match every custom value
+    /// filter.
+    pub async fn find_by_templated(
+        &self,
+        templated: bool,
+        active_only: bool,
+        public_only: bool,
+        custom_values: &[CustomValueFilter],
+        order: ProjectOrder,
+        pagination: Pagination,
+    ) -> RepositoryResult<PaginatedResult<ProjectRow>> {
+        let push_conditions = |sql: &mut QueryBuilder<'_, Postgres>| {
+            sql.push(" WHERE p.templated = ");
+            sql.push_bind(templated);
+            if active_only {
+                sql.push(" AND p.active");
+            }
+            if public_only {
+                sql.push(" AND p.public AND p.active");
+            }
+        };
+
+        let mut rows = QueryBuilder::new(format!(
+            "SELECT p.id, p.name, p.description, p.identifier, p.public, p.parent_id, \
+             p.lft, p.rgt, p.active, p.templated, p.created_at, p.updated_at \
+             FROM projects p {}",
+            order.join()
+        ));
+        push_conditions(&mut rows);
+        for filter in custom_values {
+            filter.push_condition(&mut rows, PROJECT_CUSTOMIZED_TYPE, "p.id");
+        }
+        rows.push(format_args!(" ORDER BY {} LIMIT ", order.sql()));
+        rows.push_bind(pagination.limit);
+        rows.push(" OFFSET ");
+        rows.push_bind(pagination.offset);
+        let items = rows
+            .build_query_as::<ProjectRow>()
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM projects p");
+        push_conditions(&mut count);
+        for filter in custom_values {
+            filter.push_condition(&mut count, PROJECT_CUSTOMIZED_TYPE, "p.id");
+        }
+        let total = count
+            .build_query_scalar::<i64>()
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(PaginatedResult::new(items, total, pagination))
+    }
+
+    /// Attachment quota of a project in bytes, from the project settings;
+    /// `None` means unlimited
+    pub async fn attachment_quota(&self, id: Id) -> RepositoryResult<Option<i64>> {
+        let quota = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT (settings->>'attachment_quota')::BIGINT FROM projects WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(quota.flatten())
+    }
+
+    /// Set or clear the attachment quota of a project
+    pub async fn set_attachment_quota(&self, id: Id, quota: Option<i64>) -> RepositoryResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE projects
+            SET settings = CASE
+                    WHEN $2::BIGINT IS NULL THEN COALESCE(settings, '{}'::jsonb) - 'attachment_quota'
+                    ELSE jsonb_set(COALESCE(settings, '{}'::jsonb), '{attachment_quota}', to_jsonb($2::BIGINT))
+                END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(quota)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Email settings of a project, as stored under `email` in its
+    /// settings; `None` if it has none
+    pub async fn email_settings(&self, id: Id) -> RepositoryResult<Option<serde_json::Value>> {
+        let settings = sqlx::query_scalar::<_, Option<serde_json::Value>>(
+            "SELECT settings->'email' FROM projects WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(settings.flatten())
+    }
+
+    /// Set or clear the email settings of a project
+    pub async fn set_email_settings(&self, id: Id, settings: Option<&serde_json::Value>) -> RepositoryResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE projects
+            SET settings = CASE
+                    WHEN $2::JSONB IS NULL THEN COALESCE(settings, '{}'::jsonb) - 'email'
+                    ELSE jsonb_set(COALESCE(settings, '{}'::jsonb), '{email}', $2::JSONB)
+                END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(settings)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Create a project as a copy of `source_id`, copying the given
+    /// dependencies. Runs in a single transaction: on failure nothing is
+    /// left behind.
+    pub async fn copy(
+        &self,
+        source_id: Id,
+        dto: CreateProjectDto,
+        dependencies: &[CopyDependency],
+    ) -> RepositoryResult<ProjectRow> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let max_rgt = sqlx::query_scalar::<_, Option<i32>>("SELECT MAX(rgt) FROM projects")
+            .fetch_one(&mut *tx)
+            .await?
+            .unwrap_or(0);
+
+        let row = sqlx::query_as::<_, ProjectRow>(
+            r#"
+            INSERT INTO projects (
+                name, description, identifier, public, parent_id,
+                lft, rgt, active, templated, created_at, updated_at
+            ) VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, NOW(), NOW()
+            )
+            RETURNING id, name, description, identifier, public, parent_id,
+                      lft, rgt, active, templated, created_at, updated_at
+            "#,
+        )
+        .bind(&dto.name)
+        .bind(&dto.description)
+        .bind(&dto.identifier)
+        .bind(dto.public)
+        .bind(dto.parent_id)
+        .bind(max_rgt + 1)
+        .bind(max_rgt + 2)
+        .bind(dto.active)
+        .bind(dto.templated)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for dependency in dependencies {
+            sqlx::query(dependency.copy_sql())
+                .bind(source_id)
+                .bind(row.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(row)
+    }
+
     /// Check if identifier is unique
     pub async fn is_identifier_unique(
         &self,
@@ -251,15 +639,27 @@ impl ProjectRepository {
             .bind(identifier),
         };
 
-        let unique = query.fetch_one(&self.pool).await?;
+        let unique = query.fetch_one(&mut *self.db.acquire().await?).await?;
         Ok(unique)
     }
 
+    /// Identifiers starting with `prefix`, for picking a free one
+    pub async fn identifiers_with_prefix(&self, prefix: &str) -> RepositoryResult<Vec<String>> {
+        let identifiers = sqlx::query_scalar::<_, String>(
+            "SELECT identifier FROM projects WHERE left(identifier, length($1)) = $1",
+        )
+        .bind(prefix)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(identifiers)
+    }
+
     /// Archive a project (set active = false)
     pub async fn archive(&self, id: Id) -> RepositoryResult<()> {
         sqlx::query("UPDATE projects SET active = false, updated_at = NOW() WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(())
@@ -269,7 +669,7 @@ impl ProjectRepository {
     pub async fn unarchive(&self, id: Id) -> RepositoryResult<()> {
         sqlx::query("UPDATE projects SET active = true, updated_at = NOW() WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(())
@@ -284,7 +684,7 @@ impl ProjectRepository {
         let items = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT DISTINCT p.id, p.name, p.description, p.identifier, p.public, p.parent_id,
-                   p.lft, p.rgt, p.active, p.created_at, p.updated_at
+                   p.lft, p.rgt, p.active, p.templated, p.created_at, p.updated_at
             FROM projects p
             LEFT JOIN members m ON m.project_id = p.id AND m.user_id = $1
             WHERE p.active = true AND (p.public = true OR m.id IS NOT NULL)
@@ -295,7 +695,7 @@ impl ProjectRepository {
         .bind(user_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
@@ -307,7 +707,7 @@ impl ProjectRepository {
             "#,
         )
         .bind(user_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -320,13 +720,13 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
         let row = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             WHERE id = $1
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -336,7 +736,7 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
         let rows = sqlx::query_as::<_, ProjectRow>(
             r#"
             SELECT id, name, description, identifier, public, parent_id,
-                   lft, rgt, active, created_at, updated_at
+                   lft, rgt, active, templated, created_at, updated_at
             FROM projects
             ORDER BY lft ASC
             LIMIT $1 OFFSET $2
@@ -344,7 +744,7 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -352,7 +752,7 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
 
     async fn count(&self) -> RepositoryResult<i64> {
         let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM projects")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(count)
@@ -362,7 +762,7 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
         // For nested set, we need to calculate lft/rgt
         // This is a simplified version - real implementation needs proper nested set management
         let max_rgt = sqlx::query_scalar::<_, Option<i32>>("SELECT MAX(rgt) FROM projects")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?
             .unwrap_or(0);
 
@@ -373,12 +773,12 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
             r#"
             INSERT INTO projects (
                 name, description, identifier, public, parent_id,
-                lft, rgt, active, created_at, updated_at
+                lft, rgt, active, templated, created_at, updated_at
             ) VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, NOW(), NOW()
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, NOW(), NOW()
             )
             RETURNING id, name, description, identifier, public, parent_id,
-                      lft, rgt, active, created_at, updated_at
+                      lft, rgt, active, templated, created_at, updated_at
             "#,
         )
         .bind(&dto.name)
@@ -389,7 +789,8 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
         .bind(lft)
         .bind(rgt)
         .bind(dto.active)
-        .fetch_one(&self.pool)
+        .bind(dto.templated)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -403,18 +804,22 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
                 description = COALESCE($2, description),
                 public = COALESCE($3, public),
                 active = COALESCE($4, active),
+                templated = COALESCE($5, templated),
+                identifier = COALESCE($6, identifier),
                 updated_at = NOW()
-            WHERE id = $5
+            WHERE id = $7
             RETURNING id, name, description, identifier, public, parent_id,
-                      lft, rgt, active, created_at, updated_at
+                      lft, rgt, active, templated, created_at, updated_at
             "#,
         )
         .bind(&dto.name)
         .bind(&dto.description)
         .bind(dto.public)
         .bind(dto.active)
+        .bind(dto.templated)
+        .bind(&dto.identifier)
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?
         .ok_or_else(|| RepositoryError::NotFound(format!("Project with id {} not found", id)))?;
 
@@ -427,7 +832,7 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
             "SELECT EXISTS(SELECT 1 FROM projects WHERE parent_id = $1)",
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         if has_children {
@@ -436,9 +841,15 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
             ));
         }
 
+        sqlx::query("DELETE FROM custom_values WHERE customized_type = $1 AND customized_id = $2")
+            .bind(PROJECT_CUSTOMIZED_TYPE)
+            .bind(id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+
         let result = sqlx::query("DELETE FROM projects WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -455,9 +866,193 @@ impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for ProjectRepos
         let exists =
             sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM projects WHERE id = $1)")
                 .bind(id)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(exists)
     }
 }
+
+/// Projects as the API handlers need them, stored in the database or, in
+/// the embedded mode, in memory
+#[async_trait]
+pub trait ProjectStore: Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> {
+    /// Check if identifier is unique, ignoring the project `exclude_id`
+    async fn is_identifier_unique(&self, identifier: &str, exclude_id: Option<Id>) -> RepositoryResult<bool>;
+
+    /// Identifiers starting with `prefix`, for picking a free one
+    async fn identifiers_with_prefix(&self, prefix: &str) -> RepositoryResult<Vec<String>>;
+}
+
+#[async_trait]
+impl ProjectStore for ProjectRepository {
+    async fn is_identifier_unique(&self, identifier: &str, exclude_id: Option<Id>) -> RepositoryResult<bool> {
+        ProjectRepository::is_identifier_unique(self, identifier, exclude_id).await
+    }
+
+    async fn identifiers_with_prefix(&self, prefix: &str) -> RepositoryResult<Vec<String>> {
+        ProjectRepository::identifiers_with_prefix(self, prefix).await
+    }
+}
+
+/// Projects of a single process, without a place in the project tree
+#[derive(Default)]
+pub struct MemoryProjectStore {
+    projects: Mutex<BTreeMap<Id, ProjectRow>>,
+}
+
+impl MemoryProjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Repository<ProjectRow, CreateProjectDto, UpdateProjectDto> for MemoryProjectStore {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<ProjectRow>> {
+        Ok(self.projects.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<ProjectRow>> {
+        let projects = self.projects.lock().unwrap();
+        Ok(projects.values().skip(offset as usize).take(limit as usize).cloned().collect())
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        Ok(self.projects.lock().unwrap().len() as i64)
+    }
+
+    async fn create(&self, dto: CreateProjectDto) -> RepositoryResult<ProjectRow> {
+        let mut projects = self.projects.lock().unwrap();
+        if projects.values().any(|project| project.identifier == dto.identifier) {
+            return Err(RepositoryError::Conflict(format!("Identifier {} is already taken", dto.identifier)));
+        }
+        let id = projects.keys().next_back().copied().unwrap_or(0) + 1;
+        let row = ProjectRow { id, ..dto.preview() };
+        projects.insert(id, row.clone());
+        Ok(row)
+    }
+
+    async fn update(&self, id: Id, dto: UpdateProjectDto) -> RepositoryResult<ProjectRow> {
+        let mut projects = self.projects.lock().unwrap();
+        let project = projects
+            .get_mut(&id)
+            .ok_or_else(|| RepositoryError::NotFound(format!("Project with id {} not found", id)))?;
+        *project = dto.preview(project.clone());
+        Ok(project.clone())
+    }
+
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        let mut projects = self.projects.lock().unwrap();
+        if projects.values().any(|project| project.parent_id == Some(id)) {
+            return Err(RepositoryError::Conflict("Cannot delete project with children".to_string()));
+        }
+        projects
+            .remove(&id)
+            .map(|_| ())
+            .ok_or_else(|| RepositoryError::NotFound(format!("Project with id {} not found", id)))
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        Ok(self.projects.lock().unwrap().contains_key(&id))
+    }
+}
+
+#[async_trait]
+impl ProjectStore for MemoryProjectStore {
+    async fn is_identifier_unique(&self, identifier: &str, exclude_id: Option<Id>) -> RepositoryResult<bool> {
+        let projects = self.projects.lock().unwrap();
+        Ok(!projects
+            .values()
+            .any(|project| project.identifier == identifier && Some(project.id) != exclude_id))
+    }
+
+    async fn identifiers_with_prefix(&self, prefix: &str) -> RepositoryResult<Vec<String>> {
+        let projects = self.projects.lock().unwrap();
+        Ok(projects
+            .values()
+            .filter(|project| project.identifier.starts_with(prefix))
+            .map(|project| project.identifier.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_dependency_names() {
+        for dependency in CopyDependency::ALL {
+            assert_eq!(CopyDependency::parse(dependency.as_str()), Some(dependency));
+        }
+        assert_eq!(CopyDependency::parse("work_packages"), None);
+
+        let json = serde_json::to_string(&CopyDependency::WorkPackageTypes).unwrap();
+        assert_eq!(json, "\"work_package_types\"");
+    }
+
+    #[test]
+    fn test_copy_statements_bind_source_and_target() {
+        for dependency in CopyDependency::ALL {
+            let sql = dependency.copy_sql();
+            assert!(sql.contains("$1") && sql.contains("$2"), "{:?}", dependency);
+        }
+    }
+
+    #[test]
+    fn test_update_preview_keeps_unset_attributes() {
+        let project = CreateProjectDto {
+            name: "Demo".into(),
+            description: Some("Shown".into()),
+            identifier: "demo".into(),
+            public: true,
+            parent_id: Some(2),
+            active: true,
+            templated: false,
+        }
+        .preview();
+        assert_eq!(project.id, 0);
+
+        let update = UpdateProjectDto {
+            name: Some("Renamed".into()),
+            public: Some(false),
+            parent_id: Some(9),
+            ..Default::default()
+        };
+        let preview = update.preview(project);
+        assert_eq!(preview.name, "Renamed");
+        assert!(!preview.public);
+        assert_eq!(preview.identifier, "demo");
+        assert_eq!(preview.description.as_deref(), Some("Shown"));
+        assert_eq!(preview.parent_id, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_keeps_identifiers_unique() {
+        let store = MemoryProjectStore::new();
+        let dto = |identifier: &str, parent_id| CreateProjectDto {
+            name: identifier.to_uppercase(),
+            description: None,
+            identifier: identifier.into(),
+            public: false,
+            parent_id,
+            active: true,
+            templated: false,
+        };
+        let parent = store.create(dto("demo", None)).await.unwrap();
+        let child = store.create(dto("demo-2", Some(parent.id))).await.unwrap();
+        assert_eq!((parent.id, child.id), (1, 2));
+
+        assert!(matches!(store.create(dto("demo", None)).await, Err(RepositoryError::Conflict(_))));
+        assert!(!store.is_identifier_unique("demo", None).await.unwrap());
+        assert!(store.is_identifier_unique("demo", Some(parent.id)).await.unwrap());
+        assert_eq!(store.identifiers_with_prefix("demo").await.unwrap(), vec!["demo", "demo-2"]);
+
+        assert!(matches!(store.delete(parent.id).await, Err(RepositoryError::Conflict(_))));
+        store.delete(child.id).await.unwrap();
+        store.delete(parent.id).await.unwrap();
+        assert_eq!(store.count().await.unwrap(), 0);
+        assert!(matches!(store.delete(parent.id).await, Err(RepositoryError::NotFound(_))));
+    }
+}
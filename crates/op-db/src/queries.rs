@@ -4,7 +4,10 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use op_queries::export::{FilterEntry, QueryDocument, SortEntry};
+use op_queries::{legacy, ImportWarning, ImportedQuery, Query, References, ReferenceKind};
 use sqlx::{FromRow, PgPool};
+use std::collections::BTreeMap;
 
 use crate::{Pagination, PaginatedResult, Repository, RepositoryError};
 
@@ -43,6 +46,76 @@ impl QueryRow {
     pub fn is_grouped(&self) -> bool {
         self.group_by.is_some()
     }
+
+    /// Build the query model from the stored configuration.
+    ///
+    /// Filters are stored in API v3 form
+    /// (`[{"status_id":{"operator":"=","values":["1"]}}]`), sort criteria as
+    /// `[["id","asc"]]` and columns as a JSON array of names.
+    pub fn to_query(&self) -> Result<Query, RepositoryError> {
+        self.load_query().map(|loaded| loaded.query)
+    }
+
+    /// Build the query model like [`Self::to_query`], also reading settings
+    /// the Ruby application stored as YAML. The parts of those that can't
+    /// be read are left out and reported as warnings.
+    pub fn load_query(&self) -> Result<ImportedQuery, RepositoryError> {
+        let mut warnings = Vec::new();
+        let filters = match self.filters.as_deref() {
+            Some(yaml) if legacy::is_ruby_yaml(yaml) => collect(legacy::parse_filters(yaml), &mut warnings),
+            Some(json) if !json.trim().is_empty() => parse_stored_filters(json)?,
+            _ => Vec::new(),
+        };
+        let sort_by = match self.sort_criteria.as_deref() {
+            Some(yaml) if legacy::is_ruby_yaml(yaml) => collect(legacy::parse_sort_criteria(yaml), &mut warnings),
+            Some(json) if !json.trim().is_empty() => parse_stored_sorts(json)?,
+            _ => Vec::new(),
+        };
+        let columns = match self.column_names.as_deref() {
+            Some(yaml) if legacy::is_ruby_yaml(yaml) => {
+                Some(collect(legacy::parse_columns(yaml), &mut warnings)).filter(|columns| !columns.is_empty())
+            }
+            Some(json) if !json.trim().is_empty() => Some(parse_stored_columns(json)),
+            _ => None,
+        };
+        let columns = columns.unwrap_or_else(|| {
+            op_queries::ColumnSet::default_work_package()
+                .names()
+                .into_iter()
+                .map(String::from)
+                .collect()
+        });
+
+        let document = QueryDocument {
+            schema_version: op_queries::export::SCHEMA_VERSION,
+            name: self.name.clone(),
+            filters,
+            sort_by,
+            columns,
+            group_by: self.group_by.clone(),
+            display: op_queries::export::DisplaySettings {
+                show_sums: self.display_sums,
+                show_hierarchies: self.show_hierarchies,
+                include_subprojects: self.include_subprojects,
+                timeline_visible: self.timeline_visible,
+                timestamps: self.timestamps.clone(),
+                ..Default::default()
+            },
+        };
+
+        let mut query = document
+            .into_query(self.project_id)
+            .map_err(|e| RepositoryError::validation(format!("Query {} {}", self.id, e)))?;
+        query.id = Some(self.id);
+        query.user_id = Some(self.user_id);
+        Ok(ImportedQuery { query, warnings })
+    }
+}
+
+/// The setting read, keeping its warnings
+fn collect<T>((value, found): (T, Vec<ImportWarning>), warnings: &mut Vec<ImportWarning>) -> T {
+    warnings.extend(found);
+    value
 }
 
 /// Query with starred status
@@ -52,6 +125,14 @@ pub struct QueryWithStarred {
     pub starred: bool,
 }
 
+/// Work package custom field queries can filter by
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct FilterableCustomField {
+    pub id: i64,
+    pub name: String,
+    pub field_format: String,
+}
+
 /// DTO for creating a query
 #[derive(Debug, Clone)]
 pub struct CreateQueryDto {
@@ -69,6 +150,116 @@ pub struct CreateQueryDto {
     pub timestamps: Option<String>,
 }
 
+impl CreateQueryDto {
+    /// DTO storing a query model for `user_id`
+    pub fn from_query(query: &Query, user_id: i64) -> Self {
+        let document = QueryDocument::export(query, &References::new());
+
+        Self {
+            project_id: query.project_id,
+            user_id,
+            name: document.name,
+            filters: Some(stored_filters(&document.filters)),
+            column_names: Some(serde_json::json!(document.columns).to_string()),
+            sort_criteria: Some(stored_sorts(&document.sort_by)),
+            group_by: document.group_by,
+            display_sums: Some(document.display.show_sums),
+            show_hierarchies: Some(document.display.show_hierarchies),
+            include_subprojects: Some(document.display.include_subprojects),
+            timeline_visible: Some(document.display.timeline_visible),
+            timestamps: document.display.timestamps,
+        }
+    }
+}
+
+/// Filters in API v3 form: `[{"status_id":{"operator":"=","values":["1"]}}]`
+fn stored_filters(filters: &[FilterEntry]) -> String {
+    let filters: Vec<serde_json::Value> = filters
+        .iter()
+        .map(|f| serde_json::json!({ &f.attribute: { "operator": f.operator, "values": f.values } }))
+        .collect();
+    serde_json::Value::Array(filters).to_string()
+}
+
+fn parse_stored_filters(json: &str) -> Result<Vec<FilterEntry>, RepositoryError> {
+    #[derive(serde::Deserialize)]
+    struct Condition {
+        operator: String,
+        #[serde(default)]
+        values: Vec<String>,
+    }
+
+    let filters: Vec<BTreeMap<String, Condition>> = serde_json::from_str(json)
+        .map_err(|e| RepositoryError::invalid("filters", "invalid", format!("are invalid: {}", e)))?;
+
+    Ok(filters
+        .into_iter()
+        .flatten()
+        .map(|(attribute, condition)| FilterEntry {
+            attribute,
+            operator: condition.operator,
+            values: condition.values,
+        })
+        .collect())
+}
+
+/// Sort criteria as `[["id","asc"]]`
+fn stored_sorts(sorts: &[SortEntry]) -> String {
+    let sorts: Vec<[&str; 2]> = sorts
+        .iter()
+        .map(|s| [s.attribute.as_str(), s.direction.as_str()])
+        .collect();
+    serde_json::json!(sorts).to_string()
+}
+
+fn parse_stored_sorts(json: &str) -> Result<Vec<SortEntry>, RepositoryError> {
+    let sorts: Vec<(String, String)> = serde_json::from_str(json)
+        .map_err(|e| RepositoryError::invalid("sort_criteria", "invalid", format!("are invalid: {}", e)))?;
+    Ok(sorts
+        .into_iter()
+        .map(|(attribute, direction)| SortEntry { attribute, direction })
+        .collect())
+}
+
+/// Column names as a JSON array, or comma-separated
+fn parse_stored_columns(value: &str) -> Vec<String> {
+    serde_json::from_str(value).unwrap_or_else(|_| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+/// Settings the Ruby application stored as YAML in the native format, so
+/// that a query is rewritten when it is saved next; the parts that can't
+/// be read are dropped
+fn native_filters(stored: Option<String>) -> Option<String> {
+    match stored {
+        Some(yaml) if legacy::is_ruby_yaml(&yaml) => Some(stored_filters(&legacy::parse_filters(&yaml).0)),
+        stored => stored,
+    }
+}
+
+fn native_columns(stored: Option<String>) -> Option<String> {
+    match stored {
+        Some(yaml) if legacy::is_ruby_yaml(&yaml) => {
+            let (columns, _) = legacy::parse_columns(&yaml);
+            (!columns.is_empty()).then(|| serde_json::json!(columns).to_string())
+        }
+        stored => stored,
+    }
+}
+
+fn native_sorts(stored: Option<String>) -> Option<String> {
+    match stored {
+        Some(yaml) if legacy::is_ruby_yaml(&yaml) => Some(stored_sorts(&legacy::parse_sort_criteria(&yaml).0)),
+        stored => stored,
+    }
+}
+
 /// DTO for updating a query
 #[derive(Debug, Clone, Default)]
 pub struct UpdateQueryDto {
@@ -129,6 +320,7 @@ impl QueryRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -164,6 +356,7 @@ impl QueryRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -202,6 +395,7 @@ impl QueryRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -298,6 +492,7 @@ impl QueryRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -351,6 +546,87 @@ impl QueryRepository {
         Ok(count > 0)
     }
 
+    /// Names of the statuses, types and versions queries in `project_id`
+    /// can reference, and the custom fields available there
+    pub async fn references(&self, project_id: Option<i64>) -> Result<References, RepositoryError> {
+        let mut references = References::new();
+
+        let statuses = sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM statuses")
+            .fetch_all(&self.pool)
+            .await?;
+        for (id, name) in statuses {
+            references.add(ReferenceKind::Status, id, name);
+        }
+
+        let (types, versions) = match project_id {
+            Some(pid) => {
+                let types = sqlx::query_as::<_, (i64, String)>(
+                    r#"
+                    SELECT t.id, t.name FROM types t
+                    JOIN projects_types pt ON pt.type_id = t.id
+                    WHERE pt.project_id = $1
+                    "#,
+                )
+                .bind(pid)
+                .fetch_all(&self.pool)
+                .await?;
+                let versions = sqlx::query_as::<_, (i64, String)>(
+                    "SELECT id, name FROM versions WHERE project_id = $1 OR sharing = 'system'",
+                )
+                .bind(pid)
+                .fetch_all(&self.pool)
+                .await?;
+                (types, versions)
+            }
+            None => {
+                let types = sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM types")
+                    .fetch_all(&self.pool)
+                    .await?;
+                let versions = sqlx::query_as::<_, (i64, String)>(
+                    "SELECT id, name FROM versions WHERE sharing = 'system'",
+                )
+                .fetch_all(&self.pool)
+                .await?;
+                (types, versions)
+            }
+        };
+
+        for (id, name) in types {
+            references.add(ReferenceKind::Type, id, name);
+        }
+        for (id, name) in versions {
+            references.add(ReferenceKind::Version, id, name);
+        }
+        for custom_field in self.filterable_custom_fields(project_id).await? {
+            references.add_custom_field(custom_field.id);
+        }
+
+        Ok(references)
+    }
+
+    /// Work package custom fields available in `project_id`, or in every
+    /// project without one, by position
+    pub async fn filterable_custom_fields(
+        &self,
+        project_id: Option<i64>,
+    ) -> Result<Vec<FilterableCustomField>, RepositoryError> {
+        let rows = sqlx::query_as::<_, FilterableCustomField>(
+            r#"
+            SELECT cf.id, cf.name, cf.field_format FROM custom_fields cf
+            WHERE cf.type = 'WorkPackageCustomField'
+              AND (cf.is_for_all OR cf.id IN (
+                  SELECT custom_field_id FROM custom_fields_projects WHERE project_id = $1
+              ))
+            ORDER BY cf.position, cf.id
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
     /// Get query with starred status
     pub async fn find_by_id_with_starred(
         &self,
@@ -428,9 +704,7 @@ impl Repository<QueryRow, CreateQueryDto, UpdateQueryDto> for QueryRepository {
     async fn create(&self, dto: CreateQueryDto) -> Result<QueryRow, RepositoryError> {
         // Validate name
         if dto.name.trim().is_empty() {
-            return Err(RepositoryError::Validation(
-                "Name can't be blank".to_string(),
-            ));
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
         }
 
         let display_sums = dto.display_sums.unwrap_or(false);
@@ -479,24 +753,22 @@ impl Repository<QueryRow, CreateQueryDto, UpdateQueryDto> for QueryRepository {
         // Validate name if provided
         if let Some(ref name) = dto.name {
             if name.trim().is_empty() {
-                return Err(RepositoryError::Validation(
-                    "Name can't be blank".to_string(),
-                ));
+                return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
             }
         }
 
         let new_name = dto.name.unwrap_or(existing.name);
         let new_filters = match dto.filters {
             Some(f) => f,
-            None => existing.filters,
+            None => native_filters(existing.filters),
         };
         let new_column_names = match dto.column_names {
             Some(c) => c,
-            None => existing.column_names,
+            None => native_columns(existing.column_names),
         };
         let new_sort_criteria = match dto.sort_criteria {
             Some(s) => s,
-            None => existing.sort_criteria,
+            None => native_sorts(existing.sort_criteria),
         };
         let new_group_by = match dto.group_by {
             Some(g) => g,
@@ -604,4 +876,106 @@ mod tests {
 
         assert!(!project_query.is_global());
     }
+
+    #[test]
+    fn test_query_round_trips_through_storage() {
+        let query = op_queries::QueryBuilder::new()
+            .name("Open bugs")
+            .project(3)
+            .status(vec![1, 2])
+            .sort_by_priority()
+            .group_by_status()
+            .build();
+
+        let dto = CreateQueryDto::from_query(&query, 7);
+        assert_eq!(
+            dto.filters.as_deref(),
+            Some(r#"[{"status_id":{"operator":"=","values":["1","2"]}}]"#)
+        );
+        assert_eq!(dto.sort_criteria.as_deref(), Some(r#"[["priority","asc"]]"#));
+
+        let row = QueryRow {
+            id: 5,
+            project_id: dto.project_id,
+            user_id: dto.user_id,
+            name: dto.name,
+            filters: dto.filters,
+            column_names: dto.column_names,
+            sort_criteria: dto.sort_criteria,
+            group_by: dto.group_by,
+            display_sums: false,
+            show_hierarchies: true,
+            include_subprojects: true,
+            timeline_visible: false,
+            timestamps: dto.timestamps,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let stored = row.to_query().unwrap();
+
+        assert_eq!(stored.id, Some(5));
+        assert_eq!(stored.project_id, Some(3));
+        assert_eq!(
+            stored.filters.filters_for("status_id")[0].values,
+            op_queries::FilterValue::Ids(vec![1, 2])
+        );
+        assert_eq!(stored.columns.names(), query.columns.names());
+        assert_eq!(stored.group_by.attribute.as_deref(), Some("status"));
+
+        // Date groupings keep their granularity
+        let monthly = op_queries::QueryBuilder::new().name("By month").group_by_month("due_date").build();
+        let dto = CreateQueryDto::from_query(&monthly, 7);
+        assert_eq!(dto.group_by.as_deref(), Some("due_date:month"));
+        let stored = QueryRow { group_by: dto.group_by, ..row }.to_query().unwrap();
+        assert_eq!(stored.group_by.granularity, Some(op_queries::DateGranularity::Month));
+    }
+
+    #[test]
+    fn test_ruby_serialized_settings_are_read_and_rewritten() {
+        // As stored by the Ruby application
+        let row = QueryRow {
+            id: 9,
+            project_id: Some(3),
+            user_id: 1,
+            name: "Legacy".to_string(),
+            filters: Some(
+                "--- !ruby/hash:ActiveSupport::HashWithIndifferentAccess\n\
+                 status_id: !ruby/hash:ActiveSupport::HashWithIndifferentAccess\n  operator: o\n  values:\n  - ''\n\
+                 cf_4: !ruby/hash:ActiveSupport::HashWithIndifferentAccess\n  operator: \"=n\"\n  values:\n  - '1'\n"
+                    .to_string(),
+            ),
+            column_names: Some("---\n- :subject\n- :assigned_to\n".to_string()),
+            sort_criteria: Some("---\n- - id\n  - desc\n".to_string()),
+            group_by: None,
+            display_sums: false,
+            show_hierarchies: true,
+            include_subprojects: true,
+            timeline_visible: false,
+            timestamps: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let loaded = row.load_query().unwrap();
+        assert_eq!(loaded.query.filters.filters().len(), 1);
+        assert!(loaded.query.filters.has_filter_for("status_id"));
+        assert_eq!(loaded.query.columns.names(), vec!["subject", "assigned_to"]);
+        assert_eq!(loaded.query.sorts.criteria()[0].attribute, "id");
+        assert_eq!(loaded.warnings, vec![ImportWarning::UnreadableFilter { attribute: "cf_4".into() }]);
+
+        assert_eq!(
+            native_filters(row.filters.clone()).as_deref(),
+            Some(r#"[{"status_id":{"operator":"o","values":[""]}}]"#)
+        );
+        assert_eq!(native_columns(row.column_names.clone()).as_deref(), Some(r#"["subject","assigned_to"]"#));
+        assert_eq!(native_sorts(row.sort_criteria.clone()).as_deref(), Some(r#"[["id","desc"]]"#));
+        // Native settings are kept as they are
+        assert_eq!(native_sorts(Some("[]".into())).as_deref(), Some("[]"));
+    }
+
+    #[test]
+    fn test_stored_columns_accept_comma_separated_names() {
+        assert_eq!(parse_stored_columns("id, subject"), vec!["id", "subject"]);
+        assert!(parse_stored_filters("not json").is_err());
+    }
 }
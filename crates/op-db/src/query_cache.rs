@@ -0,0 +1,212 @@
+//! Query result cache
+//!
+//! Dashboards poll the same saved queries over and over while their results
+//! rarely change. The cache keeps the ids and total of a query page for a
+//! short time, keyed by the query's SQL, the viewer and the page.
+//!
+//! Writes do not flush the cache. Each project has a generation counter
+//! which work package writes bump; the generations of the projects a query
+//! covers are part of its key, so entries of affected projects are never
+//! read again and simply expire. Queries across all projects use the
+//! global generation, which every write bumps.
+//!
+//! Changes outside work packages, such as memberships granting visibility,
+//! do not invalidate entries; they show once the entries expire.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+
+/// Key of a cached query page
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryCacheKey {
+    /// Generated conditions and order of the query, which also carry the
+    /// values of `me` filters
+    pub fingerprint: String,
+    /// User the results are restricted to, if any
+    pub visible_to: Option<Id>,
+    pub limit: i64,
+    pub offset: i64,
+    /// Generations of the projects the query covers, see
+    /// [`QueryResultCache::generations`]
+    pub generations: Vec<u64>,
+}
+
+/// Cached page of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedQueryResult {
+    /// Work packages of the page, in order
+    pub ids: Vec<Id>,
+    /// Work packages matching the query on all pages
+    pub total: i64,
+    /// Whether `total` is an estimate
+    pub total_is_estimate: bool,
+}
+
+/// Store of query results
+#[async_trait]
+pub trait QueryResultCache: Send + Sync {
+    /// The unexpired result stored under `key`
+    async fn get(&self, key: &QueryCacheKey) -> Option<CachedQueryResult>;
+
+    /// Store a result
+    async fn put(&self, key: QueryCacheKey, result: CachedQueryResult);
+
+    /// Current generations of the given projects, or the global generation
+    /// for a query across all projects
+    async fn generations(&self, project_ids: &[Id]) -> Vec<u64>;
+
+    /// Outdate the results covering a project after one of its work
+    /// packages was created, changed or deleted
+    async fn invalidate_project(&self, project_id: Id);
+}
+
+/// Default lifetime of cached results
+pub const DEFAULT_QUERY_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Entries kept at most by [`MemoryQueryResultCache`]
+const MAX_ENTRIES: usize = 10_000;
+
+/// Query result cache of a single process
+pub struct MemoryQueryResultCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<QueryCacheKey, (Instant, CachedQueryResult)>>,
+    generations: Mutex<Generations>,
+}
+
+#[derive(Default)]
+struct Generations {
+    global: u64,
+    projects: HashMap<Id, u64>,
+}
+
+impl Default for MemoryQueryResultCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUERY_CACHE_TTL)
+    }
+}
+
+impl MemoryQueryResultCache {
+    /// Cache keeping results for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            generations: Mutex::new(Generations::default()),
+        }
+    }
+
+    /// Number of stored entries, expired ones included
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl QueryResultCache for MemoryQueryResultCache {
+    async fn get(&self, key: &QueryCacheKey) -> Option<CachedQueryResult> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((stored_at, result)) if stored_at.elapsed() < self.ttl => Some(result.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn put(&self, key: QueryCacheKey, result: CachedQueryResult) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            let ttl = self.ttl;
+            entries.retain(|_, (stored_at, _)| stored_at.elapsed() < ttl);
+        }
+        if entries.len() >= MAX_ENTRIES {
+            entries.clear();
+        }
+        entries.insert(key, (Instant::now(), result));
+    }
+
+    async fn generations(&self, project_ids: &[Id]) -> Vec<u64> {
+        let generations = self.generations.lock().unwrap();
+        if project_ids.is_empty() {
+            return vec![generations.global];
+        }
+        project_ids
+            .iter()
+            .map(|id| generations.projects.get(id).copied().unwrap_or_default())
+            .collect()
+    }
+
+    async fn invalidate_project(&self, project_id: Id) {
+        let mut generations = self.generations.lock().unwrap();
+        generations.global += 1;
+        *generations.projects.entry(project_id).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(generations: Vec<u64>) -> QueryCacheKey {
+        QueryCacheKey {
+            fingerprint: "wp.status_id IN (1)\nORDER BY wp.id".to_string(),
+            visible_to: Some(3),
+            limit: 20,
+            offset: 0,
+            generations,
+        }
+    }
+
+    fn result() -> CachedQueryResult {
+        CachedQueryResult { ids: vec![4, 2], total: 2, total_is_estimate: false }
+    }
+
+    #[tokio::test]
+    async fn test_writes_outdate_their_projects_only() {
+        let cache = MemoryQueryResultCache::default();
+        let project = key(cache.generations(&[1, 2]).await);
+        let unrelated = key(cache.generations(&[5]).await);
+        let global = key(cache.generations(&[]).await);
+        for key in [&project, &unrelated, &global] {
+            cache.put(key.clone(), result()).await;
+        }
+
+        cache.invalidate_project(2).await;
+
+        assert_ne!(key(cache.generations(&[1, 2]).await), project);
+        assert_eq!(key(cache.generations(&[5]).await), unrelated);
+        assert_eq!(cache.get(&unrelated).await, Some(result()));
+        assert_ne!(key(cache.generations(&[]).await), global);
+    }
+
+    #[tokio::test]
+    async fn test_entries_expire() {
+        let cache = MemoryQueryResultCache::new(Duration::ZERO);
+        cache.put(key(vec![0]), result()).await;
+
+        assert_eq!(cache.get(&key(vec![0])).await, None);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keys_differ_by_viewer_and_page() {
+        let cache = MemoryQueryResultCache::default();
+        cache.put(key(vec![0]), result()).await;
+
+        let other_viewer = QueryCacheKey { visible_to: Some(4), ..key(vec![0]) };
+        let next_page = QueryCacheKey { offset: 20, ..key(vec![0]) };
+        assert_eq!(cache.get(&other_viewer).await, None);
+        assert_eq!(cache.get(&next_page).await, None);
+        assert_eq!(cache.get(&key(vec![0])).await, Some(result()));
+    }
+}
@@ -4,73 +4,549 @@
 //! the OpenProject database. This provides full compatibility with
 //! OpenProject's query system.
 
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use op_core::clock::{start_of_week, Clock, SystemClock, Tz};
+use op_core::duration::parse_iso8601_date;
+use op_core::error::PropertyError;
+use op_core::metrics::DomainMetrics;
 use op_core::traits::Id;
+use op_queries::filters::attributes;
 use op_queries::{
-    Filter, FilterOperator, FilterSet, FilterValue,
-    Query, SortCriterion, SortDirection, SortOrder,
+    DateGranularity, Filter, FilterOperator, FilterSet, FilterValue, GroupBy,
+    Query, SortCriterion, SortDirection, SortOrder, Timestamp,
 };
+use serde_json::{json, Value as JsonValue};
 use sqlx::postgres::PgRow;
-use sqlx::{FromRow, PgPool, Row};
+use sqlx::{FromRow, PgExecutor, PgPool, Row};
 
+use crate::executor::DbExecutor;
+use crate::journals::WorkPackageJournalRow;
+use crate::query_cache::{CachedQueryResult, QueryCacheKey, QueryResultCache};
 use crate::repository::{Pagination, PaginatedResult, RepositoryError, RepositoryResult};
+use crate::work_package_views::unseen_changes_sql;
+
+/// Columns of [`WorkPackageRow`]
+const WORK_PACKAGE_COLUMNS: &str = "wp.id, wp.subject, wp.description, wp.project_id, wp.type_id, \
+    wp.status_id, wp.priority_id, wp.author_id, wp.assigned_to_id, wp.responsible_id, wp.category_id, \
+    wp.version_id, wp.parent_id, wp.start_date, wp.due_date, wp.estimated_hours, wp.done_ratio, \
+    wp.lock_version, wp.created_at, wp.updated_at, wp.position, wp.story_points, wp.remaining_hours, \
+    wp.schedule_manually, wp.duration, wp.ignore_non_working_days";
+
+/// Condition leaving out the work packages in the trash
+const NOT_TRASHED: &str = "wp.deleted_at IS NULL";
+
+/// Matches up to which [`CountStrategy::Estimated`] counts exactly by
+/// default
+pub const DEFAULT_EXACT_COUNT_THRESHOLD: i64 = 10_000;
+
+/// Share of all work packages the filters of a query must match, by the
+/// planner's estimate, for its total to be estimated
+const UNSELECTIVE_SHARE: f64 = 0.5;
+
+/// How the executor counts all matches of a query alongside a page
+///
+/// On large instances, counting every match dominates the response time of
+/// the default views. With `Estimated`, matches are counted exactly up to
+/// the threshold only. Above it, first pages of queries without filters,
+/// or with filters matching at least half of all work packages, report the
+/// planner's estimate instead; later pages count exactly, so the estimate
+/// is verified once a client pages on. Queries displaying sums always
+/// count exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CountStrategy {
+    /// Count every match
+    #[default]
+    Exact,
+    /// Count exactly up to `threshold` matches, estimate above it
+    Estimated { threshold: i64 },
+}
+
+/// Why a filter cannot be translated into a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterErrorKind {
+    /// No column or condition matches the attribute, e.g. a typo
+    UnknownAttribute,
+    /// The operator does not apply to the attribute, e.g. contains on an id
+    UnsupportedOperator,
+    /// A value does not fit the attribute, e.g. a word compared to an id
+    InvalidValue,
+}
+
+impl FilterErrorKind {
+    /// Machine-readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            FilterErrorKind::UnknownAttribute => "unknown_filter",
+            FilterErrorKind::UnsupportedOperator => "unsupported_operator",
+            FilterErrorKind::InvalidValue => "invalid_value",
+        }
+    }
+}
+
+/// A filter of a query that cannot be executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    /// Attribute of the filter, e.g. `status_id`
+    pub attribute: String,
+    /// Symbol of the filter's operator, e.g. `~`
+    pub operator: String,
+    pub kind: FilterErrorKind,
+    /// Text following the attribute name, e.g. "is not a known filter"
+    pub message: String,
+}
+
+impl FilterError {
+    pub(crate) fn new(filter: &Filter, kind: FilterErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            attribute: filter.attribute.clone(),
+            operator: filter.operator.symbol().to_string(),
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<FilterError> for PropertyError {
+    fn from(error: FilterError) -> Self {
+        PropertyError::new(error.attribute, error.kind.code(), error.message)
+    }
+}
+
+/// Filters of a query that cannot be executed. Rather than dropping them
+/// and matching more work packages than the query asks for, the executor
+/// fails with all of them.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid filters: {}", describe_filter_errors(.errors))]
+pub struct QueryValidationError {
+    pub errors: Vec<FilterError>,
+}
+
+fn describe_filter_errors(errors: &[FilterError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("{} {}", error.attribute, error.message))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
 /// Query executor for work packages
-pub struct WorkPackageQueryExecutor<'a> {
-    pool: &'a PgPool,
+#[derive(Clone)]
+pub struct WorkPackageQueryExecutor {
+    db: DbExecutor,
+    statement_timeout: Option<StdDuration>,
+    count_strategy: CountStrategy,
+    visible_to: Option<Id>,
+    cache: Option<Arc<dyn QueryResultCache>>,
+    metrics: Option<Arc<DomainMetrics>>,
+    clock: Arc<dyn Clock>,
+    time_zone: Tz,
+    lenient: bool,
 }
 
-impl<'a> WorkPackageQueryExecutor<'a> {
-    pub fn new(pool: &'a PgPool) -> Self {
-        Self { pool }
+impl WorkPackageQueryExecutor {
+    pub fn new(pool: &PgPool) -> Self {
+        Self::with_executor(pool.clone().into())
+    }
+
+    /// Executor running its queries on the executor, e.g. a transaction
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self {
+            db,
+            statement_timeout: None,
+            count_strategy: CountStrategy::Exact,
+            visible_to: None,
+            cache: None,
+            metrics: None,
+            clock: Arc::new(SystemClock),
+            time_zone: Tz::UTC,
+            lenient: false,
+        }
+    }
+
+    /// Drop filters that cannot be executed instead of failing with a
+    /// [`QueryValidationError`], matching on a best-effort basis. Only for
+    /// internal callers: users would believe results filtered that are not.
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Take the current time from the clock, e.g. a fixed one in tests
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Evaluate relative date filters such as "today" or "this week" in
+    /// the time zone of the user rather than UTC
+    pub fn in_time_zone(mut self, time_zone: Tz) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// Count the matches of queries with the strategy, e.g. estimating
+    /// large totals
+    pub fn with_count_strategy(mut self, strategy: CountStrategy) -> Self {
+        self.count_strategy = strategy;
+        self
+    }
+
+    /// Serve repeated [`execute`](Self::execute) calls from the cache
+    pub fn with_cache(mut self, cache: Arc<dyn QueryResultCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Record cache hits and misses into the given metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<DomainMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Cancel statements running longer than the timeout, e.g. of
+    /// pathological filter combinations, failing with
+    /// [`RepositoryError::Timeout`]. Queries then run in a transaction of
+    /// their own; on a caller's transaction they follow its settings.
+    pub fn with_statement_timeout(mut self, timeout: StdDuration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Only return work packages the user may view, through a project
+    /// membership or a share. Without it, as for administrators, every
+    /// work package matches.
+    pub fn visible_to(mut self, user_id: Id) -> Self {
+        self.visible_to = Some(user_id);
+        self
     }
 
     /// Execute a query and return paginated work package results
+    ///
+    /// With a cache, the ids and total of a page are kept until a work
+    /// package of a project in the query's scope is written or the entry
+    /// expires; hits only load the work packages by id.
     pub async fn execute(
         &self,
         query: &Query,
         pagination: &Pagination,
         current_user_id: Option<Id>,
     ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
-        let (where_clause, params) = self.build_where_clause(&query.filters, current_user_id);
-        let order_clause = self.build_order_clause(&query.sorts);
+        let bounded = self.bounded().await?;
+        let result = bounded.as_ref().unwrap_or(self).run(query, pagination, current_user_id).await;
+        Self::finish(bounded, result).await
+    }
+
+    /// Execute a query for the rows of a page without counting its matches,
+    /// e.g. for the chunks of a page streamed after its first one carried
+    /// the total. The cache is bypassed, as its entries hold counted pages.
+    pub async fn execute_uncounted(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<Vec<WorkPackageRow>> {
+        let bounded = self.bounded().await?;
+        let executor = bounded.as_ref().unwrap_or(self);
+        let result = async {
+            let scope = executor.scope(query).await?;
+            let (where_clause, _params) =
+                executor.query_where_clause(&query.scoped_filters(&scope), current_user_id)?;
+            let order_clause = executor.build_order_clause(query);
+            executor.fetch_rows(&where_clause, &order_clause, pagination).await
+        }
+        .await;
+        Self::finish(bounded, result).await
+    }
+
+    /// Count the matches of a grouped query per group, in the order the
+    /// groups appear in its pages; empty for ungrouped queries
+    ///
+    /// Date attributes grouped by period are truncated with `date_trunc`,
+    /// timestamps in the time zone of the executor, and keyed by the ISO
+    /// 8601 form of the period, e.g. `2024-03` or `2024-W12`. Matches
+    /// without a value form the last group, keyed `None`.
+    pub async fn execute_groups(
+        &self,
+        query: &Query,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<Vec<QueryGroupRow>> {
+        let Some(attribute) = query.group_by.attribute.as_deref() else {
+            return Ok(Vec::new());
+        };
+        let Some(columns) = group_columns(&query.group_by, self.time_zone) else {
+            return Ok(Vec::new());
+        };
+        let key = match (query.group_by.granularity, columns.last()) {
+            (Some(granularity), Some(period)) if columns.len() == 1 => {
+                format!("to_char({}, '{}')", period, period_key_format(granularity))
+            }
+            (_, Some(column)) => format!("{}::text", column),
+            (_, None) => return Ok(Vec::new()),
+        };
+        let label = group_label_sql(attribute).filter(|_| query.group_by.granularity.is_none());
+        let mut grouped = columns.clone();
+        grouped.extend(label.iter().cloned());
+
+        let bounded = self.bounded().await?;
+        let executor = bounded.as_ref().unwrap_or(self);
+        let result = async {
+            let scope = executor.scope(query).await?;
+            let (where_clause, _params) =
+                executor.query_where_clause(&query.scoped_filters(&scope), current_user_id)?;
+            let order_clause = group_order_sql(&query.group_by, group_direction(&query.sorts, attribute), self.time_zone);
+            let sql = format!(
+                r#"
+                SELECT {} AS value, {} AS label, COUNT(*) AS count
+                FROM work_packages wp
+                {}
+                {}
+                GROUP BY {}
+                ORDER BY {}
+                "#,
+                key,
+                label.as_deref().unwrap_or("NULL::text"),
+                build_join_clause(&[&where_clause, &grouped.join(", ")]),
+                if where_clause.is_empty() {
+                    String::new()
+                } else {
+                    format!("WHERE {}", where_clause)
+                },
+                grouped.join(", "),
+                order_clause.join(", ")
+            );
+
+            sqlx::query_as::<_, QueryGroupRow>(&sql)
+                .fetch_all(&mut *executor.db.acquire().await?)
+                .await
+                .map_err(RepositoryError::from)
+        }
+        .await;
+        let mut groups = Self::finish(bounded, result).await?;
+
+        if let Some(granularity) = query.group_by.granularity {
+            for group in &mut groups {
+                group.label = group.value.as_deref().and_then(|key| granularity.label(key));
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Copy of the executor running on a transaction of its own whose
+    /// statements time out; `None` without a timeout or on a caller's
+    /// transaction
+    async fn bounded(&self) -> RepositoryResult<Option<Self>> {
+        let (Some(timeout), DbExecutor::Pool(pool)) = (self.statement_timeout, &self.db) else {
+            return Ok(None);
+        };
+        let db = DbExecutor::begin(pool).await?;
+        sqlx::query(&format!("SET LOCAL statement_timeout = {}", timeout.as_millis().max(1)))
+            .execute(&mut *db.acquire().await?)
+            .await?;
+        Ok(Some(Self { db, ..self.clone() }))
+    }
+
+    /// End the transaction of a bounded copy; its queries only read
+    async fn finish<T>(bounded: Option<Self>, result: RepositoryResult<T>) -> RepositoryResult<T> {
+        if let Some(bounded) = bounded {
+            bounded.db.rollback().await?;
+        }
+        result
+    }
+
+    async fn run(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
+        let scope = self.scope(query).await?;
+        let (where_clause, _params) = self.query_where_clause(&query.scoped_filters(&scope), current_user_id)?;
+        let order_clause = self.build_order_clause(query);
+
+        // Sums are shown of all matches, so their count must be exact too
+        let exact = query.show_sums;
+
+        // Views do not outdate cached results, so the unseen changes of a
+        // user are always queried
+        let cache = self.cache.as_ref().filter(|_| !query.filters.has_filter_for(attributes::UNSEEN_CHANGES));
+        let Some(cache) = cache else {
+            return self.fetch_page(&where_clause, &order_clause, pagination, exact).await;
+        };
+
+        // Generations are read before querying, so a write racing with the
+        // query leaves its result under an already outdated key
+        let key = QueryCacheKey {
+            fingerprint: format!("{}\n{}", where_clause, order_clause),
+            visible_to: self.visible_to,
+            limit: pagination.limit,
+            offset: pagination.offset,
+            generations: cache.generations(&scope).await,
+        };
+
+        if let Some(cached) = cache.get(&key).await {
+            if let Some(metrics) = &self.metrics {
+                metrics.record_query_cache_hit();
+            }
+            return Ok(PaginatedResult {
+                items: self.find_in_order(&cached.ids).await?,
+                total: cached.total,
+                limit: pagination.limit,
+                offset: pagination.offset,
+                total_is_estimate: cached.total_is_estimate,
+            });
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_query_cache_miss();
+        }
+
+        let result = self.fetch_page(&where_clause, &order_clause, pagination, exact).await?;
+        let cached = CachedQueryResult {
+            ids: result.items.iter().map(|wp| wp.id).collect(),
+            total: result.total,
+            total_is_estimate: result.total_is_estimate,
+        };
+        cache.put(key, cached).await;
+
+        Ok(result)
+    }
+
+    /// Run a query's SQL for one page and count all its matches, exactly
+    /// if `exact` is set
+    async fn fetch_page(
+        &self,
+        where_clause: &str,
+        order_clause: &str,
+        pagination: &Pagination,
+        exact: bool,
+    ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
+        let rows = self.fetch_rows(where_clause, order_clause, pagination).await?;
+        let (total, total_is_estimate) = self.total(where_clause, pagination, rows.len(), exact).await?;
+
+        Ok(PaginatedResult {
+            items: rows,
+            total,
+            limit: pagination.limit,
+            offset: pagination.offset,
+            total_is_estimate,
+        })
+    }
+
+    /// Run a query's SQL for one page
+    async fn fetch_rows(
+        &self,
+        where_clause: &str,
+        order_clause: &str,
+        pagination: &Pagination,
+    ) -> RepositoryResult<Vec<WorkPackageRow>> {
+        let sql = format!(
+            r#"
+            SELECT {}
+            FROM work_packages wp
+            {}
+            {}
+            {}
+            LIMIT $1 OFFSET $2
+            "#,
+            WORK_PACKAGE_COLUMNS,
+            build_join_clause(&[where_clause, order_clause]),
+            if where_clause.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", where_clause)
+            },
+            order_clause
+        );
+
+        let rows = sqlx::query_as::<_, WorkPackageRow>(&sql)
+            .bind(pagination.limit)
+            .bind(pagination.offset)
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(rows)
+    }
+
+    /// Load work packages in the order of `ids`, skipping deleted ones
+    async fn find_in_order(&self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let sql = format!(
+            r#"
+            SELECT {}
+            FROM unnest($1::bigint[]) WITH ORDINALITY AS ids(id, position)
+            JOIN work_packages wp ON wp.id = ids.id
+            ORDER BY ids.position
+            "#,
+            WORK_PACKAGE_COLUMNS
+        );
+
+        let rows = sqlx::query_as::<_, WorkPackageRow>(&sql)
+            .bind(ids)
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(rows)
+    }
+
+    /// Execute a query for the Gantt view, selecting only what the timeline
+    /// renders. Combine with a `DateIntersects` filter to restrict results to
+    /// the visible window.
+    pub async fn execute_timeline(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<TimelineRow>> {
+        let bounded = self.bounded().await?;
+        let result = bounded.as_ref().unwrap_or(self).run_timeline(query, pagination, current_user_id).await;
+        Self::finish(bounded, result).await
+    }
+
+    async fn run_timeline(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<TimelineRow>> {
+        let filters = self.scoped_filters(query).await?;
+        let (where_clause, _params) = self.query_where_clause(&filters, current_user_id)?;
+        let order_clause = self.build_order_clause(query);
 
-        // Build the main query
         let sql = format!(
             r#"
             SELECT
                 wp.id,
                 wp.subject,
-                wp.description,
                 wp.project_id,
+                wp.parent_id,
                 wp.type_id,
                 wp.status_id,
-                wp.priority_id,
-                wp.author_id,
-                wp.assigned_to_id,
-                wp.responsible_id,
-                wp.category_id,
-                wp.version_id,
-                wp.parent_id,
                 wp.start_date,
                 wp.due_date,
-                wp.estimated_hours,
-                wp.done_ratio,
-                wp.lock_version,
-                wp.created_at,
-                wp.updated_at,
-                wp.position,
-                wp.story_points,
-                wp.remaining_hours,
-                wp.schedule_manually,
-                wp.duration
+                COALESCE(t.is_milestone, false) AS is_milestone,
+                tc.hexcode AS type_color,
+                sc.hexcode AS status_color
             FROM work_packages wp
             LEFT JOIN statuses s ON wp.status_id = s.id
             LEFT JOIN types t ON wp.type_id = t.id
             LEFT JOIN enumerations p ON wp.priority_id = p.id AND p.type = 'IssuePriority'
+            LEFT JOIN colors tc ON t.color_id = tc.id
+            LEFT JOIN colors sc ON s.color_id = sc.id
+            {}
             {}
             {}
             LIMIT $1 OFFSET $2
             "#,
+            // Statuses, types and priorities are joined above
+            lookup_joins(&[order_clause.as_str()], &["s", "t", "p"]),
             if where_clause.is_empty() {
                 String::new()
             } else {
@@ -79,16 +555,135 @@ impl<'a> WorkPackageQueryExecutor<'a> {
             order_clause
         );
 
-        // Build count query
+        let rows = sqlx::query_as::<_, TimelineRow>(&sql)
+            .bind(pagination.limit)
+            .bind(pagination.offset)
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        let (total, total_is_estimate) = self.total(&where_clause, pagination, rows.len(), query.show_sums).await?;
+
+        Ok(PaginatedResult {
+            items: rows,
+            total,
+            limit: pagination.limit,
+            offset: pagination.offset,
+            total_is_estimate,
+        })
+    }
+
+    /// Filters of the query, restricted to the projects in its scope
+    async fn scoped_filters(&self, query: &Query) -> RepositoryResult<FilterSet> {
+        Ok(query.scoped_filters(&self.scope(query).await?))
+    }
+
+    /// Projects of the query's scope; empty for queries across projects
+    async fn scope(&self, query: &Query) -> RepositoryResult<Vec<Id>> {
+        match query.project_id {
+            Some(project_id) => {
+                project_scope(&mut *self.db.acquire().await?, project_id, query.include_subprojects).await
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Total of the matches of a WHERE clause, given the page that returned
+    /// `returned` of them, and whether it is estimated; see
+    /// [`CountStrategy`]
+    async fn total(
+        &self,
+        where_clause: &str,
+        pagination: &Pagination,
+        returned: usize,
+        exact: bool,
+    ) -> RepositoryResult<(i64, bool)> {
+        let threshold = match self.count_strategy {
+            CountStrategy::Estimated { threshold } if !exact => threshold,
+            _ => return Ok((self.count(where_clause).await?, false)),
+        };
+
+        let capped = self.count_capped(where_clause, threshold + 1).await?;
+        if capped <= threshold {
+            return Ok((capped, false));
+        }
+        // Clients paging on get the exact total
+        if pagination.offset > 0 {
+            return Ok((self.count(where_clause).await?, false));
+        }
+
+        match self.estimate(where_clause).await? {
+            Some(estimate) => Ok((estimated_total(estimate, threshold, pagination.offset, returned), true)),
+            None => Ok((self.count(where_clause).await?, false)),
+        }
+    }
+
+    /// Count work packages matching a WHERE clause, stopping at `cap`
+    async fn count_capped(&self, where_clause: &str, cap: i64) -> RepositoryResult<i64> {
+        let count_sql = format!(
+            r#"
+            SELECT COUNT(*) FROM (
+                SELECT 1
+                FROM work_packages wp
+                {}
+                {}
+                LIMIT $1
+            ) matches
+            "#,
+            build_join_clause(&[where_clause]),
+            if where_clause.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", where_clause)
+            }
+        );
+
+        let count_row: (i64,) = sqlx::query_as(&count_sql)
+            .bind(cap)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(count_row.0)
+    }
+
+    /// Planner estimate of the work packages matching a WHERE clause;
+    /// `None` when the filters are too selective for an estimate, or the
+    /// table was never analyzed
+    async fn estimate(&self, where_clause: &str) -> RepositoryResult<Option<i64>> {
+        let mut conn = self.db.acquire().await?;
+        // -1 or 0 until the table is vacuumed or analyzed
+        let rows: f32 = sqlx::query_scalar("SELECT reltuples FROM pg_class WHERE oid = 'work_packages'::regclass")
+            .fetch_one(&mut *conn)
+            .await?;
+        if rows <= 0.0 {
+            return Ok(None);
+        }
+        if where_clause.is_empty() {
+            return Ok(Some(rows as i64));
+        }
+
+        let explain_sql = format!(
+            "EXPLAIN (FORMAT JSON) SELECT 1 FROM work_packages wp {} WHERE {}",
+            build_join_clause(&[where_clause]),
+            where_clause
+        );
+        let plan: JsonValue = sqlx::query_scalar(&explain_sql).fetch_one(&mut *conn).await?;
+        let matches = plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or_default();
+
+        Ok((matches >= f64::from(rows) * UNSELECTIVE_SHARE).then_some(matches as i64))
+    }
+
+    /// Count work packages matching a WHERE clause
+    async fn count(&self, where_clause: &str) -> RepositoryResult<i64> {
         let count_sql = format!(
             r#"
             SELECT COUNT(*) as count
             FROM work_packages wp
-            LEFT JOIN statuses s ON wp.status_id = s.id
-            LEFT JOIN types t ON wp.type_id = t.id
-            LEFT JOIN enumerations p ON wp.priority_id = p.id AND p.type = 'IssuePriority'
+            {}
             {}
             "#,
+            build_join_clause(&[where_clause]),
             if where_clause.is_empty() {
                 String::new()
             } else {
@@ -96,45 +691,185 @@ impl<'a> WorkPackageQueryExecutor<'a> {
             }
         );
 
-        // Execute count query
         let count_row: (i64,) = sqlx::query_as(&count_sql)
-            .fetch_one(self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await
-            .map_err(RepositoryError::Database)?;
-        let total = count_row.0;
+            .map_err(RepositoryError::from)?;
 
-        // Execute main query
-        let rows = sqlx::query_as::<_, WorkPackageRow>(&sql)
-            .bind(pagination.limit)
-            .bind(pagination.offset)
-            .fetch_all(self.pool)
-            .await
-            .map_err(RepositoryError::Database)?;
+        Ok(count_row.0)
+    }
+
+    /// Execute a query and annotate each result with the attributes that
+    /// changed since each of the query's historic timestamps
+    pub async fn execute_with_timestamps(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<TimestampedWorkPackage>> {
+        let bounded = self.bounded().await?;
+        let result = bounded.as_ref().unwrap_or(self).run_with_timestamps(query, pagination, current_user_id).await;
+        Self::finish(bounded, result).await
+    }
+
+    async fn run_with_timestamps(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<TimestampedWorkPackage>> {
+        let current = self.run(query, pagination, current_user_id).await?;
+        let now = Utc::now();
+        let columns = query.columns.names();
+        let ids: Vec<Id> = current.items.iter().map(|wp| wp.id).collect();
+
+        let mut baselines = Vec::new();
+        for timestamp in query.timestamps.historic() {
+            let at = timestamp.resolve(now);
+            baselines.push((timestamp, at, self.find_as_of(&ids, at).await?));
+        }
+
+        let items = current
+            .items
+            .into_iter()
+            .map(|work_package| {
+                let snapshot = WorkPackageSnapshot::from(&work_package);
+                let attributes_by_timestamp = baselines
+                    .iter()
+                    .map(|(timestamp, at, rows)| {
+                        let baseline = rows.get(&work_package.id).map(WorkPackageSnapshot::from);
+                        compare_at_timestamp(timestamp, *at, &columns, baseline.as_ref(), &snapshot)
+                    })
+                    .collect();
+
+                TimestampedWorkPackage {
+                    work_package,
+                    attributes_by_timestamp,
+                }
+            })
+            .collect();
 
         Ok(PaginatedResult {
-            items: rows,
-            total,
-            limit: pagination.limit,
-            offset: pagination.offset,
+            items,
+            total: current.total,
+            limit: current.limit,
+            offset: current.offset,
+            total_is_estimate: current.total_is_estimate,
         })
     }
 
-    /// Build WHERE clause from filter set
+    /// Journal data valid at `at` for the given work packages. Work packages
+    /// without a journal by then did not exist yet and are absent from the map;
+    /// the lookup only touches journals so deleted work packages are harmless.
+    async fn find_as_of(
+        &self,
+        ids: &[Id],
+        at: DateTime<Utc>,
+    ) -> RepositoryResult<HashMap<Id, WorkPackageJournalRow>> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query_as::<_, BaselineRow>(
+            r#"
+            SELECT ids.id AS work_package_id,
+                   wpj.id, wpj.type_id, wpj.project_id, wpj.subject, wpj.description,
+                   wpj.due_date, wpj.category_id, wpj.status_id, wpj.assigned_to_id,
+                   wpj.priority_id, wpj.version_id, wpj.author_id, wpj.done_ratio,
+                   wpj.estimated_hours, wpj.start_date, wpj.parent_id, wpj.responsible_id,
+                   wpj.derived_estimated_hours, wpj.schedule_manually, wpj.duration,
+                   wpj.ignore_non_working_days, wpj.derived_remaining_hours, wpj.derived_done_ratio
+            FROM unnest($1::bigint[]) AS ids(id)
+            JOIN LATERAL (
+                SELECT j.data_id
+                FROM journals j
+                WHERE j.journable_type = 'WorkPackage'
+                  AND j.journable_id = ids.id
+                  AND j.data_type = 'Journal::WorkPackageJournal'
+                  AND j.created_at <= $2
+                ORDER BY j.version DESC
+                LIMIT 1
+            ) baseline ON TRUE
+            JOIN work_package_journals wpj ON wpj.id = baseline.data_id
+            "#,
+        )
+        .bind(ids)
+        .bind(at)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await
+        .map_err(RepositoryError::from)?;
+
+        Ok(rows.into_iter().map(|r| (r.work_package_id, r.data)).collect())
+    }
+
+    /// WHERE clause of a query's filters leaving out the work packages in
+    /// the trash. Only executors not restricted to the work packages a user
+    /// may view, as for administrators, show the trash with the trashed
+    /// filter.
+    fn query_where_clause(
+        &self,
+        filters: &FilterSet,
+        current_user_id: Option<Id>,
+    ) -> Result<(String, Vec<SqlParam>), QueryValidationError> {
+        let (where_clause, params) = self.build_where_clause(filters, current_user_id)?;
+        if self.visible_to.is_none() && filters.has_filter_for(attributes::TRASHED) {
+            return Ok((where_clause, params));
+        }
+
+        let where_clause = if where_clause.is_empty() {
+            NOT_TRASHED.to_string()
+        } else {
+            format!("{} AND {}", NOT_TRASHED, where_clause)
+        };
+        Ok((where_clause, params))
+    }
+
+    /// Build WHERE clause from filter set, failing with every filter that
+    /// cannot be translated unless lenient
     fn build_where_clause(
         &self,
         filters: &FilterSet,
         current_user_id: Option<Id>,
-    ) -> (String, Vec<SqlParam>) {
-        let mut conditions = Vec::new();
+    ) -> Result<(String, Vec<SqlParam>), QueryValidationError> {
+        let mut conditions: Vec<String> = self.visible_to.map(visible_work_packages_sql).into_iter().collect();
         let mut params = Vec::new();
 
+        // A latest activity window also bounds the journals "updated by" looks for
+        let activity_window = filters
+            .filters()
+            .iter()
+            .find(|filter| filter.attribute == attributes::LATEST_ACTIVITY_AT)
+            .and_then(|filter| self.activity_window_sql("j.created_at", filter));
+
+        let mut errors = Vec::new();
         for filter in filters.filters() {
-            if let Some(condition) = self.filter_to_sql(filter, current_user_id, &mut params) {
-                conditions.push(condition);
+            if !self.lenient {
+                if let Err(error) = validate_filter(filter) {
+                    errors.push(error);
+                    continue;
+                }
+            }
+
+            let condition = if filter.attribute == attributes::UPDATED_BY {
+                updated_by_filter_sql(filter, current_user_id, activity_window.as_deref())
+            } else {
+                self.filter_to_sql(filter, current_user_id, &mut params)
+            };
+            match condition {
+                Some(condition) => conditions.push(condition),
+                None if self.lenient => {}
+                None => errors.push(FilterError::new(
+                    filter,
+                    FilterErrorKind::UnsupportedOperator,
+                    format!("does not support the operator '{}' with the given values", filter.operator.symbol()),
+                )),
             }
         }
 
-        (conditions.join(" AND "), params)
+        if !errors.is_empty() {
+            return Err(QueryValidationError { errors });
+        }
+        Ok((conditions.join(" AND "), params))
     }
 
     /// Convert a single filter to SQL condition
@@ -144,12 +879,30 @@ impl<'a> WorkPackageQueryExecutor<'a> {
         current_user_id: Option<Id>,
         _params: &mut Vec<SqlParam>,
     ) -> Option<String> {
-        let column = self.attribute_to_column(&filter.attribute)?;
-
-        match &filter.operator {
+        if filter.operator == FilterOperator::DateIntersects {
+            return date_intersects_sql(&filter.values);
+        }
+        if filter.attribute == attributes::LATEST_ACTIVITY_AT {
+            return self.activity_window_sql(&format!("{}.at", LATEST_ACTIVITY_ALIAS), filter);
+        }
+        if is_meta_attribute(&filter.attribute) {
+            return meta_filter_to_sql(filter, current_user_id);
+        }
+
+        let column = self.attribute_to_column(&filter.attribute)?;
+        if is_principal_attribute(&filter.attribute) {
+            if let Some(condition) = principal_filter_sql(&column, filter, current_user_id) {
+                return Some(condition);
+            }
+        }
+
+        match &filter.operator {
             FilterOperator::Equals => {
                 let values = self.values_to_sql(&filter.values, current_user_id);
-                if values.len() == 1 {
+                if values.is_empty() {
+                    // No value can match, as for a project scope excluding every selected project
+                    Some("1 = 0".to_string())
+                } else if values.len() == 1 {
                     Some(format!("{} = {}", column, values[0]))
                 } else {
                     Some(format!("{} IN ({})", column, values.join(", ")))
@@ -214,30 +967,19 @@ impl<'a> WorkPackageQueryExecutor<'a> {
                     None
                 }
             }
+            // Spans start and due date, handled above
+            FilterOperator::DateIntersects => None,
             FilterOperator::IsNull => Some(format!("{} IS NULL", column)),
             FilterOperator::IsNotNull => Some(format!("{} IS NOT NULL", column)),
-            FilterOperator::Today => Some(format!("{} = CURRENT_DATE", column)),
-            FilterOperator::ThisWeek => Some(format!(
-                "{} >= date_trunc('week', CURRENT_DATE) AND {} < date_trunc('week', CURRENT_DATE) + interval '1 week'",
-                column, column
-            )),
-            FilterOperator::DaysAgo(n) => {
-                Some(format!("{} = CURRENT_DATE - interval '{} days'", column, n))
-            }
-            FilterOperator::DaysFromNow(n) => {
-                Some(format!("{} = CURRENT_DATE + interval '{} days'", column, n))
-            }
-            FilterOperator::LessThanDaysAgo(n) => {
-                Some(format!("{} > CURRENT_DATE - interval '{} days'", column, n))
-            }
-            FilterOperator::MoreThanDaysAgo(n) => {
-                Some(format!("{} < CURRENT_DATE - interval '{} days'", column, n))
-            }
-            FilterOperator::LessThanDaysFromNow(n) => {
-                Some(format!("{} < CURRENT_DATE + interval '{} days'", column, n))
-            }
-            FilterOperator::MoreThanDaysFromNow(n) => {
-                Some(format!("{} > CURRENT_DATE + interval '{} days'", column, n))
+            FilterOperator::Today
+            | FilterOperator::ThisWeek
+            | FilterOperator::DaysAgo(_)
+            | FilterOperator::DaysFromNow(_)
+            | FilterOperator::LessThanDaysAgo(_)
+            | FilterOperator::MoreThanDaysAgo(_)
+            | FilterOperator::LessThanDaysFromNow(_)
+            | FilterOperator::MoreThanDaysFromNow(_) => {
+                relative_date_sql(&column, &filter.operator, self.clock.today(self.time_zone))
             }
             FilterOperator::CurrentUser => {
                 if let Some(user_id) = current_user_id {
@@ -247,6 +989,7 @@ impl<'a> WorkPackageQueryExecutor<'a> {
                     Some("1 = 0".to_string())
                 }
             }
+            FilterOperator::Open | FilterOperator::Closed => status_state_sql(filter),
         }
     }
 
@@ -255,14 +998,30 @@ impl<'a> WorkPackageQueryExecutor<'a> {
         attribute_to_column(attribute)
     }
 
+    /// Condition of a date filter on a timestamp column, compared by the
+    /// day in the time zone of the user
+    fn activity_window_sql(&self, column: &str, filter: &Filter) -> Option<String> {
+        let day = format!("({} AT TIME ZONE '{}')::date", column, self.time_zone.name());
+        match &filter.values {
+            FilterValue::DateRange { from, to } if filter.operator == FilterOperator::Between => {
+                let bound = |date: &str, comparison: &str| {
+                    parse_iso8601_date(date).ok().map(|date| format!("{} {} DATE '{}'", day, comparison, date))
+                };
+                let bounds: Vec<String> = [bound(from, ">="), bound(to, "<=")].into_iter().flatten().collect();
+                (!bounds.is_empty()).then(|| bounds.join(" AND "))
+            }
+            _ => relative_date_sql(&day, &filter.operator, self.clock.today(self.time_zone)),
+        }
+    }
+
     /// Convert filter values to SQL literals
     fn values_to_sql(&self, values: &FilterValue, current_user_id: Option<Id>) -> Vec<String> {
         values_to_sql(values, current_user_id)
     }
 
-    /// Build ORDER BY clause from sort order
-    fn build_order_clause(&self, sorts: &SortOrder) -> String {
-        build_order_clause(sorts)
+    /// Build ORDER BY clause from the query's grouping and sort order
+    fn build_order_clause(&self, query: &Query) -> String {
+        build_order_clause_in(&query.sorts, &query.group_by, self.time_zone)
     }
 
     /// Map sort attribute names to database columns
@@ -271,6 +1030,12 @@ impl<'a> WorkPackageQueryExecutor<'a> {
     }
 }
 
+/// Estimated total raised to what a page proved: more than `threshold`
+/// matches, and at least those up to the end of the page
+pub fn estimated_total(estimate: i64, threshold: i64, offset: i64, returned: usize) -> i64 {
+    estimate.max(threshold + 1).max(offset + returned as i64)
+}
+
 /// Map attribute names to database columns (standalone function for testing)
 pub fn attribute_to_column(attribute: &str) -> Option<String> {
     match attribute {
@@ -311,6 +1076,155 @@ pub fn attribute_to_column(attribute: &str) -> Option<String> {
     }
 }
 
+/// Type of the values of a work package column filters compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    Integer,
+    Float,
+    Text,
+    Date,
+    Timestamp,
+    Boolean,
+}
+
+impl ColumnType {
+    fn of(attribute: &str) -> Option<Self> {
+        let column = attribute_to_column(attribute)?;
+        Some(match column.as_str() {
+            "wp.estimated_hours" => Self::Float,
+            "wp.subject" | "wp.description" | "s.name" | "t.name" | "p.name" => Self::Text,
+            "wp.start_date" | "wp.due_date" => Self::Date,
+            "wp.created_at" | "wp.updated_at" => Self::Timestamp,
+            "s.is_closed" => Self::Boolean,
+            _ => Self::Integer,
+        })
+    }
+
+    fn is_date(&self) -> bool {
+        matches!(self, Self::Date | Self::Timestamp)
+    }
+
+    fn supports(&self, operator: &FilterOperator) -> bool {
+        match operator {
+            FilterOperator::Equals
+            | FilterOperator::NotEquals
+            | FilterOperator::IsNull
+            | FilterOperator::IsNotNull => true,
+            FilterOperator::Contains
+            | FilterOperator::NotContains
+            | FilterOperator::StartsWith
+            | FilterOperator::EndsWith => *self == Self::Text,
+            FilterOperator::GreaterThan
+            | FilterOperator::GreaterThanOrEqual
+            | FilterOperator::LessThan
+            | FilterOperator::LessThanOrEqual => matches!(self, Self::Integer | Self::Float) || self.is_date(),
+            FilterOperator::Between
+            | FilterOperator::Today
+            | FilterOperator::ThisWeek
+            | FilterOperator::DaysAgo(_)
+            | FilterOperator::DaysFromNow(_)
+            | FilterOperator::LessThanDaysAgo(_)
+            | FilterOperator::MoreThanDaysAgo(_)
+            | FilterOperator::LessThanDaysFromNow(_)
+            | FilterOperator::MoreThanDaysFromNow(_) => self.is_date(),
+            FilterOperator::CurrentUser => *self == Self::Integer,
+            // Apply to the status or the work package's dates only, whatever the attribute
+            FilterOperator::Open | FilterOperator::Closed | FilterOperator::DateIntersects => true,
+        }
+    }
+
+    /// Why the value does not fit the column, if it does not
+    fn value_error(&self, value: &FilterValue) -> Option<String> {
+        let literal = |text: &str| -> bool {
+            match self {
+                Self::Integer => text.parse::<i64>().is_ok(),
+                Self::Float => text.parse::<f64>().is_ok(),
+                Self::Text => true,
+                Self::Date | Self::Timestamp => parse_iso8601_date(text).is_ok(),
+                Self::Boolean => matches!(text, "t" | "f" | "true" | "false"),
+            }
+        };
+        let expected = match self {
+            Self::Integer => "an integer",
+            Self::Float => "a number",
+            Self::Text => "text",
+            Self::Date | Self::Timestamp => "a date",
+            Self::Boolean => "a boolean",
+        };
+        let invalid = |shown: String| Some(format!("value '{}' is not {}", shown, expected));
+
+        match value {
+            FilterValue::Id(_) | FilterValue::Ids(_) | FilterValue::Me | FilterValue::IdsAndMe(_) => {
+                (!matches!(self, Self::Integer | Self::Float)).then(|| format!("must be compared to {}", expected))
+            }
+            FilterValue::Number(n) => match self {
+                Self::Integer if n.fract() == 0.0 => None,
+                Self::Float => None,
+                _ => invalid(n.to_string()),
+            },
+            FilterValue::Bool(_) => (*self != Self::Boolean).then(|| format!("must be compared to {}", expected)),
+            FilterValue::String(text) | FilterValue::Date(text) => {
+                if literal(text) {
+                    None
+                } else {
+                    invalid(text.clone())
+                }
+            }
+            FilterValue::Strings(texts) => texts.iter().find(|text| !literal(text)).and_then(|text| invalid(text.clone())),
+            FilterValue::DateRange { from, to } => {
+                if !self.is_date() {
+                    return Some(format!("must be compared to {}", expected));
+                }
+                [from, to].into_iter().find(|date| !literal(date)).and_then(|date| invalid(date.clone()))
+            }
+            FilterValue::None => None,
+        }
+    }
+}
+
+/// Check that a filter can be executed: its attribute is known, and the
+/// operator and values fit the column it compares
+pub fn validate_filter(filter: &Filter) -> Result<(), FilterError> {
+    if filter.operator == FilterOperator::DateIntersects
+        || filter.attribute == attributes::LATEST_ACTIVITY_AT
+        || is_meta_attribute(&filter.attribute)
+    {
+        return Ok(());
+    }
+
+    let Some(column_type) = ColumnType::of(&filter.attribute) else {
+        return Err(FilterError::new(filter, FilterErrorKind::UnknownAttribute, "is not a known filter"));
+    };
+    if !column_type.supports(&filter.operator) {
+        return Err(FilterError::new(
+            filter,
+            FilterErrorKind::UnsupportedOperator,
+            format!("does not support the operator '{}'", filter.operator.symbol()),
+        ));
+    }
+    let compares_values = matches!(
+        filter.operator,
+        FilterOperator::Equals
+            | FilterOperator::NotEquals
+            | FilterOperator::Contains
+            | FilterOperator::NotContains
+            | FilterOperator::StartsWith
+            | FilterOperator::EndsWith
+            | FilterOperator::GreaterThan
+            | FilterOperator::GreaterThanOrEqual
+            | FilterOperator::LessThan
+            | FilterOperator::LessThanOrEqual
+            | FilterOperator::Between
+    );
+    if !compares_values {
+        return Ok(());
+    }
+    match column_type.value_error(&filter.values) {
+        Some(message) => Err(FilterError::new(filter, FilterErrorKind::InvalidValue, message)),
+        None => Ok(()),
+    }
+}
+
 /// Convert filter values to SQL literals (standalone function for testing)
 pub fn values_to_sql(values: &FilterValue, current_user_id: Option<Id>) -> Vec<String> {
     match values {
@@ -329,10 +1243,421 @@ pub fn values_to_sql(values: &FilterValue, current_user_id: Option<Id>) -> Vec<S
                 vec![]
             }
         }
+        FilterValue::IdsAndMe(ids) => current_user_id
+            .into_iter()
+            .chain(ids.iter().copied())
+            .map(|id| id.to_string())
+            .collect(),
         FilterValue::None => vec![],
     }
 }
 
+/// Condition of a filter relative to `today`, the current day of the user.
+/// The days are computed here rather than with `CURRENT_DATE`, which is the
+/// day in the database server's time zone.
+pub fn relative_date_sql(column: &str, operator: &FilterOperator, today: NaiveDate) -> Option<String> {
+    let day = |offset: i32| format!("DATE '{}'", today + Duration::days(offset as i64));
+
+    match operator {
+        FilterOperator::Today => Some(format!("{} = {}", column, day(0))),
+        FilterOperator::ThisWeek => {
+            let monday = start_of_week(today);
+            Some(format!(
+                "{} >= DATE '{}' AND {} < DATE '{}'",
+                column,
+                monday,
+                column,
+                monday + Duration::weeks(1)
+            ))
+        }
+        FilterOperator::DaysAgo(n) => Some(format!("{} = {}", column, day(-n))),
+        FilterOperator::DaysFromNow(n) => Some(format!("{} = {}", column, day(*n))),
+        FilterOperator::LessThanDaysAgo(n) => Some(format!("{} > {}", column, day(-n))),
+        FilterOperator::MoreThanDaysAgo(n) => Some(format!("{} < {}", column, day(-n))),
+        FilterOperator::LessThanDaysFromNow(n) => Some(format!("{} < {}", column, day(*n))),
+        FilterOperator::MoreThanDaysFromNow(n) => Some(format!("{} > {}", column, day(*n))),
+        _ => None,
+    }
+}
+
+/// Condition for work packages whose dates intersect an inclusive window.
+/// Open-ended work packages (no due date) intersect every window after
+/// their start. Returns `None` unless both bounds are valid dates.
+pub fn date_intersects_sql(values: &FilterValue) -> Option<String> {
+    let FilterValue::DateRange { from, to } = values else {
+        return None;
+    };
+    let from = parse_iso8601_date(from).ok()?;
+    let to = parse_iso8601_date(to).ok()?;
+
+    Some(format!(
+        "(wp.start_date <= '{}' AND (wp.due_date >= '{}' OR wp.due_date IS NULL))",
+        to, from
+    ))
+}
+
+/// Whether the attribute is matched through a related table rather than a
+/// work package column
+pub fn is_meta_attribute(attribute: &str) -> bool {
+    matches!(
+        attribute,
+        attributes::WATCHER_ID
+            | attributes::SUBTREE_OF
+            | attributes::MEMBER_OF_GROUP
+            | attributes::COMMENT
+            | attributes::ATTACHMENT_FILE_NAME
+            | attributes::ATTACHMENT_CONTENT
+            | attributes::UPDATED_BY
+            | attributes::UNSEEN_CHANGES
+            | attributes::TRASHED
+    )
+}
+
+/// Whether the attribute refers to a principal work packages are assigned
+/// to, which may be a group
+pub fn is_principal_attribute(attribute: &str) -> bool {
+    matches!(attribute, attributes::ASSIGNED_TO_ID | attributes::RESPONSIBLE_ID)
+}
+
+/// Condition of an equals or not equals filter on a principal column. As in
+/// OpenProject, a group among the values also matches the work packages of
+/// its members; a group without members only matches its own. The current
+/// user is never a group and is matched directly.
+pub fn principal_filter_sql(column: &str, filter: &Filter, current_user_id: Option<Id>) -> Option<String> {
+    let negated = match filter.operator {
+        FilterOperator::Equals => false,
+        FilterOperator::NotEquals => true,
+        _ => return None,
+    };
+
+    let principals = values_to_sql(&filter.values, current_user_id);
+    if principals.is_empty() {
+        // Anonymous "me" is assigned nothing
+        return Some(if negated { "1 = 1" } else { "1 = 0" }.to_string());
+    }
+    let groups = filter.values.as_ids();
+    if groups.is_empty() {
+        return None;
+    }
+
+    let members = group_members_sql(column, Some(&groups));
+    Some(if negated {
+        format!("({} NOT IN ({}) AND NOT {})", column, principals.join(", "), members)
+    } else {
+        format!("({} IN ({}) OR {})", column, principals.join(", "), members)
+    })
+}
+
+/// Condition matching principals in `column` who are members of one of
+/// the groups, or of any group without `groups`
+fn group_members_sql(column: &str, groups: Option<&[Id]>) -> String {
+    let groups = groups
+        .map(|groups| {
+            format!(
+                " AND gu.group_id IN ({})",
+                groups.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+            )
+        })
+        .unwrap_or_default();
+    format!("EXISTS (SELECT 1 FROM group_users gu WHERE gu.user_id = {}{})", column, groups)
+}
+
+/// Condition for work packages assigned to members of the filtered groups.
+/// As in OpenProject, work packages assigned to a group itself are not
+/// matched; `!*` and `*` match those assigned to a member of no or any group.
+fn member_of_group_filter_sql(filter: &Filter) -> Option<String> {
+    let groups = filter.values.as_ids();
+    let condition = match filter.operator {
+        FilterOperator::Equals if groups.is_empty() => "1 = 0".to_string(),
+        FilterOperator::Equals => group_members_sql("wp.assigned_to_id", Some(&groups)),
+        FilterOperator::NotEquals if groups.is_empty() => "1 = 1".to_string(),
+        FilterOperator::NotEquals => format!("NOT {}", group_members_sql("wp.assigned_to_id", Some(&groups))),
+        FilterOperator::IsNotNull => group_members_sql("wp.assigned_to_id", None),
+        FilterOperator::IsNull => format!("NOT {}", group_members_sql("wp.assigned_to_id", None)),
+        _ => return None,
+    };
+    Some(condition)
+}
+
+/// Condition for a meta filter, as an EXISTS subquery correlated with the
+/// work package. Values are inlined as escaped literals like in the rest
+/// of the WHERE clause.
+pub fn meta_filter_to_sql(filter: &Filter, current_user_id: Option<Id>) -> Option<String> {
+    match filter.attribute.as_str() {
+        attributes::WATCHER_ID => watcher_filter_sql(filter, current_user_id),
+        attributes::SUBTREE_OF => subtree_filter_sql(filter),
+        attributes::MEMBER_OF_GROUP => member_of_group_filter_sql(filter),
+        attributes::COMMENT => text_filter_sql(
+            "SELECT 1 FROM journals j WHERE j.journable_type = 'WorkPackage' \
+             AND j.journable_id = wp.id",
+            "j.notes",
+            filter,
+        ),
+        attributes::ATTACHMENT_FILE_NAME => text_filter_sql(ATTACHMENTS_SUBQUERY, "a.filename", filter),
+        attributes::ATTACHMENT_CONTENT => text_filter_sql(ATTACHMENTS_SUBQUERY, "a.fulltext", filter),
+        attributes::UPDATED_BY => updated_by_filter_sql(filter, current_user_id, None),
+        attributes::UNSEEN_CHANGES => unseen_changes_filter_sql(filter, current_user_id),
+        attributes::TRASHED => trashed_filter_sql(filter),
+        _ => None,
+    }
+}
+
+/// Condition on the closed flag of the work package's status for the
+/// open (o) and closed (c) operators, which only apply to the status
+pub fn status_state_sql(filter: &Filter) -> Option<String> {
+    if filter.attribute != attributes::STATUS_ID {
+        return None;
+    }
+    match filter.operator {
+        FilterOperator::Open => Some("s.is_closed = FALSE".to_string()),
+        FilterOperator::Closed => Some("s.is_closed = TRUE".to_string()),
+        _ => None,
+    }
+}
+
+const ATTACHMENTS_SUBQUERY: &str =
+    "SELECT 1 FROM attachments a WHERE a.container_type = 'WorkPackage' AND a.container_id = wp.id";
+
+fn watcher_filter_sql(filter: &Filter, current_user_id: Option<Id>) -> Option<String> {
+    let watchers = "SELECT 1 FROM watchers w WHERE w.watchable_type = 'WorkPackage' \
+                    AND w.watchable_id = wp.id";
+
+    let users = match &filter.operator {
+        FilterOperator::IsNull => return Some(format!("NOT EXISTS ({})", watchers)),
+        FilterOperator::IsNotNull => return Some(format!("EXISTS ({})", watchers)),
+        FilterOperator::CurrentUser => current_user_id.map(|id| vec![id.to_string()]).unwrap_or_default(),
+        FilterOperator::Equals | FilterOperator::NotEquals => values_to_sql(&filter.values, current_user_id),
+        _ => return None,
+    };
+
+    let negated = filter.operator == FilterOperator::NotEquals;
+    if users.is_empty() {
+        // Anonymous "me" watches nothing
+        return Some(if negated { "1 = 1" } else { "1 = 0" }.to_string());
+    }
+
+    Some(format!(
+        "{}EXISTS ({} AND w.user_id IN ({}))",
+        if negated { "NOT " } else { "" },
+        watchers,
+        users.join(", ")
+    ))
+}
+
+/// Condition for work packages with a journal by one of the users, e.g.
+/// "updated by me", created within `window` when given. `!` matches the
+/// work packages none of the users changed.
+pub fn updated_by_filter_sql(filter: &Filter, current_user_id: Option<Id>, window: Option<&str>) -> Option<String> {
+    let negated = match filter.operator {
+        FilterOperator::Equals => false,
+        FilterOperator::NotEquals => true,
+        _ => return None,
+    };
+
+    let users = values_to_sql(&filter.values, current_user_id);
+    if users.is_empty() {
+        // Anonymous "me" changed nothing
+        return Some(if negated { "1 = 1" } else { "1 = 0" }.to_string());
+    }
+
+    Some(format!(
+        "{}EXISTS (SELECT 1 FROM journals j WHERE j.journable_type = 'WorkPackage' \
+         AND j.journable_id = wp.id AND j.user_id IN ({}){})",
+        if negated { "NOT " } else { "" },
+        users.join(", "),
+        window.map(|window| format!(" AND {}", window)).unwrap_or_default()
+    ))
+}
+
+/// Condition for work packages changed, or not, since the current user last
+/// viewed them. Work packages the user never viewed are unseen; without a
+/// user every work package is.
+pub fn unseen_changes_filter_sql(filter: &Filter, current_user_id: Option<Id>) -> Option<String> {
+    let unseen = boolean_filter_value(filter)?;
+
+    let Some(user_id) = current_user_id else {
+        return Some(if unseen { "1 = 1" } else { "1 = 0" }.to_string());
+    };
+    let condition = unseen_changes_sql(user_id);
+    Some(if unseen { condition } else { format!("NOT {}", condition) })
+}
+
+/// Condition for work packages in the trash, or with `f` for those not in it
+pub fn trashed_filter_sql(filter: &Filter) -> Option<String> {
+    Some(if boolean_filter_value(filter)? { "wp.deleted_at IS NOT NULL" } else { NOT_TRASHED }.to_string())
+}
+
+/// Whether a boolean filter matches where the flag is set, given its
+/// operator and `t` or `f` value
+fn boolean_filter_value(filter: &Filter) -> Option<bool> {
+    let value = match &filter.values {
+        FilterValue::Bool(value) => *value,
+        FilterValue::String(value) => match value.as_str() {
+            "t" | "true" => true,
+            "f" | "false" => false,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match filter.operator {
+        FilterOperator::Equals => Some(value),
+        FilterOperator::NotEquals => Some(!value),
+        _ => None,
+    }
+}
+
+/// Condition for work packages in the subtrees of the filtered ones, which
+/// are expanded to their descendants at any depth. Direct children are
+/// matched by the plain `parent_id` filter instead.
+fn subtree_filter_sql(filter: &Filter) -> Option<String> {
+    let negated = match filter.operator {
+        FilterOperator::Equals => false,
+        FilterOperator::NotEquals => true,
+        _ => return None,
+    };
+
+    let roots = filter.values.as_ids();
+    if roots.is_empty() {
+        return Some(if negated { "1 = 1" } else { "1 = 0" }.to_string());
+    }
+
+    // UNION rather than UNION ALL ends the recursion should parents form a cycle
+    Some(format!(
+        "wp.id {}IN (WITH RECURSIVE subtree AS (SELECT id FROM work_packages WHERE id IN ({}) \
+         UNION SELECT c.id FROM work_packages c JOIN subtree st ON c.parent_id = st.id) \
+         SELECT id FROM subtree)",
+        if negated { "NOT " } else { "" },
+        roots.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+    ))
+}
+
+fn text_filter_sql(subquery: &str, column: &str, filter: &Filter) -> Option<String> {
+    let FilterValue::String(text) = &filter.values else {
+        return None;
+    };
+    let pattern = format!("'%{}%'", escape_like(text));
+
+    match filter.operator {
+        FilterOperator::Contains => {
+            Some(format!("EXISTS ({} AND {} ILIKE {})", subquery, column, pattern))
+        }
+        FilterOperator::NotContains => {
+            Some(format!("NOT EXISTS ({} AND {} ILIKE {})", subquery, column, pattern))
+        }
+        _ => None,
+    }
+}
+
+/// Projects a query of `project_id` covers: the project itself and, with
+/// `include_subprojects`, its active descendants at any depth
+pub async fn project_scope<'e, E: PgExecutor<'e>>(
+    executor: E,
+    project_id: Id,
+    include_subprojects: bool,
+) -> RepositoryResult<Vec<Id>> {
+    if !include_subprojects {
+        return Ok(vec![project_id]);
+    }
+
+    let ids = sqlx::query_scalar::<_, Id>(
+        r#"
+        WITH RECURSIVE scope AS (
+            SELECT id FROM projects WHERE id = $1
+            UNION
+            SELECT p.id FROM projects p
+            JOIN scope ON p.parent_id = scope.id
+            WHERE p.active = TRUE
+        )
+        SELECT id FROM scope ORDER BY id
+        "#,
+    )
+    .bind(project_id)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(ids)
+}
+
+/// Id of the anonymous user of unauthenticated requests
+const ANONYMOUS_USER_ID: Id = 0;
+
+/// Condition matching the work packages a user may view: those in projects
+/// where a membership grants `view_work_packages`, and those shared with the
+/// user. Shares are entity-scoped memberships, so revoking one takes effect
+/// on the next query. The anonymous user views those of the public projects
+/// if the Anonymous role may.
+pub fn visible_work_packages_sql(user_id: Id) -> String {
+    if user_id == ANONYMOUS_USER_ID {
+        return format!(
+            "(wp.project_id IN (SELECT id FROM projects WHERE public AND active) \
+             AND EXISTS (SELECT 1 FROM roles r JOIN role_permissions rp ON rp.role_id = r.id \
+             WHERE r.builtin = {} AND rp.permission = 'view_work_packages'))",
+            crate::roles::builtin::ANONYMOUS
+        );
+    }
+
+    let granting = "SELECT {select} FROM members m \
+                    JOIN member_roles mr ON mr.member_id = m.id \
+                    JOIN role_permissions rp ON rp.role_id = mr.role_id \
+                    WHERE rp.permission = 'view_work_packages' AND m.user_id = {user}";
+    let granting = granting.replace("{user}", &user_id.to_string());
+
+    format!(
+        "(wp.project_id IN ({} AND m.entity_type IS NULL) \
+         OR EXISTS ({} AND m.entity_type = 'WorkPackage' AND m.entity_id = wp.id))",
+        granting.replace("{select}", "m.project_id"),
+        granting.replace("{select}", "1"),
+    )
+}
+
+/// Alias of the newest journal of each work package
+const LATEST_ACTIVITY_ALIAS: &str = "la";
+
+/// The newest journal of each work package, aggregated once per row in the
+/// FROM clause rather than in the select list
+const LATEST_ACTIVITY_JOIN: &str = "LEFT JOIN LATERAL (SELECT MAX(j.created_at) AS at FROM journals j \
+    WHERE j.journable_type = 'WorkPackage' AND j.journable_id = wp.id) la ON TRUE";
+
+/// Joins of the lookup tables referenced by the given SQL fragments. Names
+/// of embedded resources are resolved separately, so the joins are only
+/// needed for filtering and sorting by status, type or priority, for
+/// grouping by assignee or version, and for the latest activity.
+pub fn build_join_clause(fragments: &[&str]) -> String {
+    lookup_joins(fragments, &[])
+}
+
+/// Joins of the lookup tables referenced by the fragments, leaving out the
+/// aliases a query joins itself
+fn lookup_joins(fragments: &[&str], joined: &[&str]) -> String {
+    const JOINS: [(&str, &str); 6] = [
+        ("s", "LEFT JOIN statuses s ON wp.status_id = s.id"),
+        ("t", "LEFT JOIN types t ON wp.type_id = t.id"),
+        ("p", "LEFT JOIN enumerations p ON wp.priority_id = p.id AND p.type = 'IssuePriority'"),
+        ("u", "LEFT JOIN users u ON wp.assigned_to_id = u.id"),
+        ("v", "LEFT JOIN versions v ON wp.version_id = v.id"),
+        (LATEST_ACTIVITY_ALIAS, LATEST_ACTIVITY_JOIN),
+    ];
+
+    JOINS
+        .iter()
+        .filter(|(alias, _)| !joined.contains(alias))
+        .filter(|(alias, _)| fragments.iter().any(|sql| references_alias(sql, alias)))
+        .map(|(_, join)| *join)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether SQL references a column of a table alias, e.g. "s.position"
+fn references_alias(sql: &str, alias: &str) -> bool {
+    let qualifier = format!("{}.", alias);
+    sql.match_indices(&qualifier).any(|(index, _)| {
+        sql[..index]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !(c.is_alphanumeric() || c == '_'))
+    })
+}
+
 /// Map sort attribute names to database columns (standalone function for testing)
 pub fn sort_attribute_to_column(attribute: &str) -> Option<String> {
     match attribute {
@@ -354,29 +1679,132 @@ pub fn sort_attribute_to_column(attribute: &str) -> Option<String> {
         "version" => Some("wp.version_id".to_string()),
         "category" => Some("wp.category_id".to_string()),
         "parent" => Some("wp.parent_id".to_string()),
+        attributes::LATEST_ACTIVITY_AT => Some(format!("{}.at", LATEST_ACTIVITY_ALIAS)),
         _ => None,
     }
 }
 
-/// Build ORDER BY clause from sort order (standalone function for testing)
-pub fn build_order_clause(sorts: &SortOrder) -> String {
-    if sorts.is_empty() {
-        return "ORDER BY wp.id DESC".to_string();
+/// Build ORDER BY clause from sort order (standalone function for testing).
+///
+/// Grouped results are ordered by their group first, so that a page never
+/// interleaves groups; a sort criterion on the grouped attribute only sets
+/// the direction of the groups. The id always comes last, keeping pages
+/// stable when the criteria tie. Timestamps grouped by period are
+/// truncated in UTC.
+pub fn build_order_clause(sorts: &SortOrder, group_by: &GroupBy) -> String {
+    build_order_clause_in(sorts, group_by, Tz::UTC)
+}
+
+/// Build ORDER BY clause like [`build_order_clause`], truncating
+/// timestamps grouped by period in the time zone
+pub fn build_order_clause_in(sorts: &SortOrder, group_by: &GroupBy, time_zone: Tz) -> String {
+    let group_attribute = group_by.attribute.as_deref();
+    let mut order_parts = Vec::new();
+
+    if let Some(attribute) = group_attribute {
+        order_parts.extend(group_order_sql(group_by, group_direction(sorts, attribute), time_zone));
+    }
+
+    order_parts.extend(
+        sorts
+            .criteria()
+            .iter()
+            .filter(|criterion| Some(criterion.attribute.as_str()) != group_attribute)
+            .filter_map(sort_to_sql),
+    );
+
+    if !sorts.criteria().iter().any(|criterion| criterion.attribute == "id") {
+        order_parts.push("wp.id DESC".to_string());
     }
 
-    let order_parts: Vec<String> = sorts
+    format!("ORDER BY {}", order_parts.join(", "))
+}
+
+/// Direction of the groups: that of a sort criterion on the grouped
+/// attribute, ascending without one
+fn group_direction(sorts: &SortOrder, attribute: &str) -> SortDirection {
+    sorts
         .criteria()
         .iter()
-        .filter_map(|criterion| sort_to_sql(criterion))
-        .collect();
+        .find(|criterion| criterion.attribute == attribute)
+        .map_or(SortDirection::Asc, |criterion| criterion.direction)
+}
 
-    if order_parts.is_empty() {
-        "ORDER BY wp.id DESC".to_string()
-    } else {
-        format!("ORDER BY {}", order_parts.join(", "))
+/// Columns ordering the groups of an attribute. Statuses, priorities and
+/// types follow their position, assignees and versions their name; the
+/// grouped id comes last so that groups of equal position or name stay apart.
+pub fn group_attribute_to_columns(attribute: &str) -> Option<Vec<String>> {
+    let columns: &[&str] = match attribute {
+        "status" => &["s.position", "wp.status_id"],
+        "priority" => &["p.position", "wp.priority_id"],
+        "type" => &["t.position", "wp.type_id"],
+        "assigned_to" | "assignee" => &["u.lastname", "u.firstname", "wp.assigned_to_id"],
+        "version" => &["v.name", "wp.version_id"],
+        _ => return sort_attribute_to_column(attribute).map(|column| vec![column]),
+    };
+
+    Some(columns.iter().map(|column| column.to_string()).collect())
+}
+
+/// Expressions a grouping groups by: the columns of its attribute, or the
+/// period of a date attribute grouped by granularity
+fn group_columns(group_by: &GroupBy, time_zone: Tz) -> Option<Vec<String>> {
+    let attribute = group_by.attribute.as_deref()?;
+    match (group_by.granularity, ColumnType::of(attribute)) {
+        (Some(granularity), Some(column_type)) if column_type.is_date() => {
+            let column = attribute_to_column(attribute)?;
+            let value = if column_type == ColumnType::Timestamp {
+                format!("({} AT TIME ZONE '{}')", column, time_zone.name())
+            } else {
+                column
+            };
+            Some(vec![format!("date_trunc('{}', {})", granularity.as_str(), value)])
+        }
+        _ => group_attribute_to_columns(attribute),
     }
 }
 
+/// `to_char` pattern of the ISO 8601 key of a period, as
+/// [`DateGranularity::period_key`] formats it
+pub fn period_key_format(granularity: DateGranularity) -> &'static str {
+    match granularity {
+        DateGranularity::Day => "YYYY-MM-DD",
+        DateGranularity::Week => "IYYY-\"W\"IW",
+        DateGranularity::Month => "YYYY-MM",
+        DateGranularity::Quarter => "YYYY-\"Q\"Q",
+        DateGranularity::Year => "YYYY",
+    }
+}
+
+/// Name of the group a work package is in, for attributes referencing a
+/// named resource
+fn group_label_sql(attribute: &str) -> Option<String> {
+    let label = match attribute {
+        "status" => "s.name",
+        "priority" => "p.name",
+        "type" => "t.name",
+        "assigned_to" | "assignee" => "concat_ws(' ', u.firstname, u.lastname)",
+        "version" => "v.name",
+        _ => return None,
+    };
+    Some(label.to_string())
+}
+
+/// ORDER BY parts of a group, with the work packages outside of any group
+/// last in either direction
+fn group_order_sql(group_by: &GroupBy, direction: SortDirection, time_zone: Tz) -> Vec<String> {
+    let direction = match direction {
+        SortDirection::Asc => "ASC",
+        SortDirection::Desc => "DESC",
+    };
+
+    group_columns(group_by, time_zone)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|column| format!("{} {} NULLS LAST", column, direction))
+        .collect()
+}
+
 /// Convert a sort criterion to SQL (standalone function)
 fn sort_to_sql(criterion: &SortCriterion) -> Option<String> {
     let column = sort_attribute_to_column(&criterion.attribute)?;
@@ -394,6 +1822,17 @@ fn sort_to_sql(criterion: &SortCriterion) -> Option<String> {
     Some(format!("{} {} {}", column, direction, nulls))
 }
 
+/// Matches of a query in one of its groups
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct QueryGroupRow {
+    /// Id of the grouped resource, the date or the ISO 8601 period; `None`
+    /// for the matches without a value
+    pub value: Option<String>,
+    /// Human readable name of the group, where known
+    pub label: Option<String>,
+    pub count: i64,
+}
+
 /// Work package row from database
 #[derive(Debug, Clone, FromRow)]
 pub struct WorkPackageRow {
@@ -422,6 +1861,231 @@ pub struct WorkPackageRow {
     pub remaining_hours: Option<f64>,
     pub schedule_manually: bool,
     pub duration: Option<i32>,
+    pub ignore_non_working_days: bool,
+}
+
+impl From<WorkPackageRow> for crate::work_packages::WorkPackageRow {
+    fn from(row: WorkPackageRow) -> Self {
+        Self {
+            id: row.id,
+            subject: row.subject,
+            description: row.description,
+            project_id: row.project_id,
+            type_id: row.type_id,
+            status_id: row.status_id,
+            priority_id: row.priority_id,
+            // The column is not null, the query row merely does not say so
+            author_id: row.author_id.unwrap_or_default(),
+            assigned_to_id: row.assigned_to_id,
+            responsible_id: row.responsible_id,
+            start_date: row.start_date,
+            due_date: row.due_date,
+            estimated_hours: row.estimated_hours,
+            done_ratio: row.done_ratio,
+            parent_id: row.parent_id,
+            version_id: row.version_id,
+            category_id: row.category_id,
+            lock_version: row.lock_version,
+            duration: row.duration,
+            ignore_non_working_days: row.ignore_non_working_days,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// Trimmed work package row for the timeline (Gantt) view
+#[derive(Debug, Clone, FromRow)]
+pub struct TimelineRow {
+    pub id: i64,
+    pub subject: String,
+    pub project_id: i64,
+    pub parent_id: Option<i64>,
+    pub type_id: i64,
+    pub status_id: i64,
+    pub start_date: Option<chrono::NaiveDate>,
+    pub due_date: Option<chrono::NaiveDate>,
+    /// Derived from the type's `is_milestone` column
+    pub is_milestone: bool,
+    pub type_color: Option<String>,
+    pub status_color: Option<String>,
+}
+
+#[derive(FromRow)]
+struct BaselineRow {
+    work_package_id: Id,
+    #[sqlx(flatten)]
+    data: WorkPackageJournalRow,
+}
+
+/// Work package attributes that can be compared between timestamps
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkPackageSnapshot {
+    pub subject: String,
+    pub project_id: Id,
+    pub type_id: Id,
+    pub status_id: Id,
+    pub priority_id: Option<Id>,
+    pub assigned_to_id: Option<Id>,
+    pub responsible_id: Option<Id>,
+    pub version_id: Option<Id>,
+    pub category_id: Option<Id>,
+    pub parent_id: Option<Id>,
+    pub start_date: Option<chrono::NaiveDate>,
+    pub due_date: Option<chrono::NaiveDate>,
+    pub estimated_hours: Option<f64>,
+    pub done_ratio: Option<i32>,
+}
+
+impl WorkPackageSnapshot {
+    /// Value of a query column, `None` if the column is not journaled
+    pub fn value(&self, column: &str) -> Option<JsonValue> {
+        let value = match column {
+            "subject" => json!(self.subject),
+            "project" => json!(self.project_id),
+            "type" => json!(self.type_id),
+            "status" => json!(self.status_id),
+            "priority" => json!(self.priority_id),
+            "assigned_to" => json!(self.assigned_to_id),
+            "responsible" => json!(self.responsible_id),
+            "version" => json!(self.version_id),
+            "category" => json!(self.category_id),
+            "parent" => json!(self.parent_id),
+            "start_date" => json!(self.start_date),
+            "due_date" => json!(self.due_date),
+            "estimated_hours" => json!(self.estimated_hours),
+            "done_ratio" => json!(self.done_ratio),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+impl From<&WorkPackageRow> for WorkPackageSnapshot {
+    fn from(row: &WorkPackageRow) -> Self {
+        Self {
+            subject: row.subject.clone(),
+            project_id: row.project_id,
+            type_id: row.type_id,
+            status_id: row.status_id,
+            priority_id: row.priority_id,
+            assigned_to_id: row.assigned_to_id,
+            responsible_id: row.responsible_id,
+            version_id: row.version_id,
+            category_id: row.category_id,
+            parent_id: row.parent_id,
+            start_date: row.start_date,
+            due_date: row.due_date,
+            estimated_hours: row.estimated_hours,
+            done_ratio: Some(row.done_ratio),
+        }
+    }
+}
+
+impl From<&WorkPackageJournalRow> for WorkPackageSnapshot {
+    fn from(row: &WorkPackageJournalRow) -> Self {
+        Self {
+            subject: row.subject.clone(),
+            project_id: row.project_id,
+            type_id: row.type_id,
+            status_id: row.status_id,
+            priority_id: Some(row.priority_id),
+            assigned_to_id: row.assigned_to_id,
+            responsible_id: row.responsible_id,
+            version_id: row.version_id,
+            category_id: row.category_id,
+            parent_id: row.parent_id,
+            start_date: row.start_date,
+            due_date: row.due_date,
+            estimated_hours: row.estimated_hours,
+            done_ratio: row.done_ratio,
+        }
+    }
+}
+
+/// A work package's state at one historic timestamp relative to now
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributesAtTimestamp {
+    /// Timestamp as given in the query
+    pub timestamp: Timestamp,
+    /// Point in time the timestamp resolved to
+    pub resolved_at: DateTime<Utc>,
+    /// Whether the work package existed at that time
+    pub exists: bool,
+    /// Selected columns whose value differed, with the value at that time
+    pub changed: BTreeMap<String, JsonValue>,
+}
+
+impl AttributesAtTimestamp {
+    /// API v3 `attributesByTimestamp` element
+    pub fn to_json(&self) -> JsonValue {
+        let mut element = serde_json::Map::new();
+        element.insert(
+            "_meta".into(),
+            json!({
+                "timestamp": self.timestamp.to_string(),
+                "exists": self.exists,
+            }),
+        );
+        for (column, value) in &self.changed {
+            element.insert(column.clone(), value.clone());
+        }
+        JsonValue::Object(element)
+    }
+}
+
+/// Query result with baseline comparison metadata
+#[derive(Debug, Clone)]
+pub struct TimestampedWorkPackage {
+    pub work_package: WorkPackageRow,
+    /// One entry per historic timestamp of the query, in query order
+    pub attributes_by_timestamp: Vec<AttributesAtTimestamp>,
+}
+
+impl TimestampedWorkPackage {
+    /// Whether the work package did not exist at the baseline
+    pub fn is_added(&self) -> bool {
+        self.attributes_by_timestamp
+            .first()
+            .map(|a| !a.exists)
+            .unwrap_or(false)
+    }
+
+    /// Whether any selected attribute changed since the baseline
+    pub fn is_changed(&self) -> bool {
+        self.attributes_by_timestamp
+            .first()
+            .map(|a| a.exists && !a.changed.is_empty())
+            .unwrap_or(false)
+    }
+}
+
+/// Compare the selected columns of a work package at a timestamp to now
+pub fn compare_at_timestamp(
+    timestamp: &Timestamp,
+    resolved_at: DateTime<Utc>,
+    columns: &[&str],
+    baseline: Option<&WorkPackageSnapshot>,
+    current: &WorkPackageSnapshot,
+) -> AttributesAtTimestamp {
+    let mut changed = BTreeMap::new();
+
+    if let Some(baseline) = baseline {
+        for column in columns {
+            if let (Some(then), Some(now)) = (baseline.value(column), current.value(column)) {
+                if then != now {
+                    changed.insert(column.to_string(), then);
+                }
+            }
+        }
+    }
+
+    AttributesAtTimestamp {
+        timestamp: timestamp.clone(),
+        resolved_at,
+        exists: baseline.is_some(),
+        changed,
+    }
 }
 
 /// Parameter for prepared statements
@@ -485,6 +2149,60 @@ mod tests {
         assert_eq!(attribute_to_column("unknown"), None);
     }
 
+    #[test]
+    fn test_validate_filter() {
+        let error = |filter: Filter| validate_filter(&filter).unwrap_err();
+
+        let typo = error(Filter::equals("statsu_id", FilterValue::Id(1)));
+        assert_eq!((typo.kind, typo.message.as_str()), (FilterErrorKind::UnknownAttribute, "is not a known filter"));
+
+        let contains = error(Filter::new("id", FilterOperator::Contains, FilterValue::String("12".into())));
+        assert_eq!((contains.kind, contains.operator.as_str()), (FilterErrorKind::UnsupportedOperator, "~"));
+
+        let word = error(Filter::equals("assigned_to_id", FilterValue::String("alice".into())));
+        assert_eq!(word.kind, FilterErrorKind::InvalidValue);
+        assert_eq!(word.message, "value 'alice' is not an integer");
+        let date = error(Filter::new("due_date", FilterOperator::GreaterThan, FilterValue::Date("soon".into())));
+        assert_eq!(date.message, "value 'soon' is not a date");
+        let subject = error(Filter::equals("subject", FilterValue::Id(5)));
+        assert_eq!(subject.message, "must be compared to text");
+
+        assert!(validate_filter(&Filter::equals("status_id", FilterValue::Strings(vec!["1".into(), "2".into()]))).is_ok());
+        assert!(validate_filter(&Filter::contains("subject", "budget")).is_ok());
+        assert!(validate_filter(&Filter::new("start_date", FilterOperator::DaysAgo(3), FilterValue::None)).is_ok());
+        assert!(validate_filter(&Filter::date_intersects("2024-01-01", "2024-01-31")).is_ok());
+        assert!(validate_filter(&Filter::equals(attributes::WATCHER_ID, FilterValue::Me)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_filters_fail_unless_lenient() {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let filters = FilterSet::new()
+            .with(Filter::equals("statsu_id", FilterValue::Id(1)))
+            .with(Filter::equals("project_id", FilterValue::Id(3)))
+            .with(Filter::new("id", FilterOperator::Contains, FilterValue::String("12".into())));
+
+        let error = WorkPackageQueryExecutor::new(&pool).build_where_clause(&filters, None).unwrap_err();
+        let attributes: Vec<&str> = error.errors.iter().map(|e| e.attribute.as_str()).collect();
+        assert_eq!(attributes, vec!["statsu_id", "id"]);
+        assert_eq!(
+            RepositoryError::from(error).to_string(),
+            "Invalid filters: statsu_id is not a known filter, id does not support the operator '~'"
+        );
+
+        // Lenient executors drop the unknown filter
+        let typo = FilterSet::new()
+            .with(Filter::equals("statsu_id", FilterValue::Id(1)))
+            .with(Filter::equals("project_id", FilterValue::Id(3)));
+        let (where_clause, _) = WorkPackageQueryExecutor::new(&pool)
+            .lenient()
+            .build_where_clause(&typo, None)
+            .unwrap();
+        assert_eq!(where_clause, "wp.project_id = 3");
+    }
+
     #[test]
     fn test_values_to_sql() {
         // Use standalone function directly - no pool needed
@@ -505,6 +2223,36 @@ mod tests {
             vec!["5"]
         );
         assert!(values_to_sql(&FilterValue::Me, None).is_empty());
+        assert_eq!(
+            values_to_sql(&FilterValue::IdsAndMe(vec![2, 3]), Some(5)),
+            vec!["5", "2", "3"]
+        );
+        assert_eq!(values_to_sql(&FilterValue::IdsAndMe(vec![2]), None), vec!["2"]);
+    }
+
+    #[test]
+    fn test_principal_filters_expand_groups() {
+        let assigned_to = |operator, values| {
+            principal_filter_sql("wp.assigned_to_id", &Filter::new("assigned_to_id", operator, values), Some(1))
+        };
+
+        assert_eq!(
+            assigned_to(FilterOperator::Equals, FilterValue::IdsAndMe(vec![4, 5])).unwrap(),
+            "(wp.assigned_to_id IN (1, 4, 5) OR EXISTS (SELECT 1 FROM group_users gu \
+             WHERE gu.user_id = wp.assigned_to_id AND gu.group_id IN (4, 5)))"
+        );
+        assert_eq!(
+            assigned_to(FilterOperator::NotEquals, FilterValue::Id(4)).unwrap(),
+            "(wp.assigned_to_id NOT IN (4) AND NOT EXISTS (SELECT 1 FROM group_users gu \
+             WHERE gu.user_id = wp.assigned_to_id AND gu.group_id IN (4)))"
+        );
+        // The current user alone needs no expansion
+        assert_eq!(assigned_to(FilterOperator::Equals, FilterValue::Me), None);
+        assert_eq!(assigned_to(FilterOperator::IsNull, FilterValue::None), None);
+        assert_eq!(
+            principal_filter_sql("wp.responsible_id", &Filter::not_equals("responsible_id", FilterValue::Me), None),
+            Some("1 = 1".to_string())
+        );
     }
 
     #[test]
@@ -513,23 +2261,326 @@ mod tests {
 
         // Empty sorts
         let empty = SortOrder::new();
-        assert_eq!(build_order_clause(&empty), "ORDER BY wp.id DESC");
+        assert_eq!(build_order_clause(&empty, &GroupBy::none()), "ORDER BY wp.id DESC");
 
         // Single sort
         let single = SortOrder::by_desc("updated_at");
         assert_eq!(
-            build_order_clause(&single),
-            "ORDER BY wp.updated_at DESC NULLS FIRST"
+            build_order_clause(&single, &GroupBy::none()),
+            "ORDER BY wp.updated_at DESC NULLS FIRST, wp.id DESC"
         );
 
         // Multiple sorts
         let multiple = SortOrder::by_asc("priority").then_desc("id");
         assert_eq!(
-            build_order_clause(&multiple),
+            build_order_clause(&multiple, &GroupBy::none()),
             "ORDER BY p.position ASC NULLS LAST, wp.id DESC NULLS FIRST"
         );
     }
 
+    #[test]
+    fn test_build_grouped_order_clause() {
+        // Groups come first, by position, with work packages without a status last
+        let by_due_date = SortOrder::by_asc("due_date");
+        assert_eq!(
+            build_order_clause(&by_due_date, &GroupBy::by("status")),
+            "ORDER BY s.position ASC NULLS LAST, wp.status_id ASC NULLS LAST, \
+             wp.due_date ASC NULLS LAST, wp.id DESC"
+        );
+
+        // Sorting by the grouped attribute orders the groups, the unassigned
+        // still last
+        let by_assignee = SortOrder::by_desc("assigned_to").then_asc("due_date");
+        let order_clause = build_order_clause(&by_assignee, &GroupBy::by("assigned_to"));
+        assert_eq!(
+            order_clause,
+            "ORDER BY u.lastname DESC NULLS LAST, u.firstname DESC NULLS LAST, \
+             wp.assigned_to_id DESC NULLS LAST, wp.due_date ASC NULLS LAST, wp.id DESC"
+        );
+        assert_eq!(build_join_clause(&[&order_clause]), "LEFT JOIN users u ON wp.assigned_to_id = u.id");
+
+        assert_eq!(
+            build_order_clause(&SortOrder::new(), &GroupBy::by("version")),
+            "ORDER BY v.name ASC NULLS LAST, wp.version_id ASC NULLS LAST, wp.id DESC"
+        );
+
+        // Periods order the groups, the sort criteria the work packages in them
+        assert_eq!(
+            build_order_clause(&SortOrder::by_desc("due_date").then_asc("subject"), &GroupBy::parse("due_date:month").unwrap()),
+            "ORDER BY date_trunc('month', wp.due_date) DESC NULLS LAST, wp.subject ASC NULLS LAST, wp.id DESC"
+        );
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            build_order_clause_in(&SortOrder::new(), &GroupBy::parse("created_at:week").unwrap(), berlin),
+            "ORDER BY date_trunc('week', (wp.created_at AT TIME ZONE 'Europe/Berlin')) ASC NULLS LAST, wp.id DESC"
+        );
+    }
+
+    #[test]
+    fn test_date_intersects_sql() {
+        let window = FilterValue::DateRange {
+            from: "2024-01-01".to_string(),
+            to: "2024-03-31".to_string(),
+        };
+        assert_eq!(
+            date_intersects_sql(&window).unwrap(),
+            "(wp.start_date <= '2024-03-31' AND (wp.due_date >= '2024-01-01' OR wp.due_date IS NULL))"
+        );
+
+        let injected = FilterValue::DateRange {
+            from: "2024-01-01' OR '1'='1".to_string(),
+            to: "2024-03-31".to_string(),
+        };
+        assert_eq!(date_intersects_sql(&injected), None);
+        assert_eq!(date_intersects_sql(&FilterValue::Date("2024-01-01".into())), None);
+    }
+
+    #[test]
+    fn test_relative_date_sql() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap();
+        let sql = |operator| relative_date_sql("wp.due_date", &operator, today).unwrap();
+
+        assert_eq!(sql(FilterOperator::Today), "wp.due_date = DATE '2024-03-06'");
+        assert_eq!(
+            sql(FilterOperator::ThisWeek),
+            "wp.due_date >= DATE '2024-03-04' AND wp.due_date < DATE '2024-03-11'"
+        );
+        assert_eq!(sql(FilterOperator::DaysAgo(7)), "wp.due_date = DATE '2024-02-28'");
+        assert_eq!(sql(FilterOperator::DaysFromNow(1)), "wp.due_date = DATE '2024-03-07'");
+        assert_eq!(sql(FilterOperator::LessThanDaysAgo(3)), "wp.due_date > DATE '2024-03-03'");
+        assert_eq!(sql(FilterOperator::MoreThanDaysFromNow(30)), "wp.due_date > DATE '2024-04-05'");
+        assert_eq!(relative_date_sql("wp.due_date", &FilterOperator::IsNull, today), None);
+    }
+
+    fn snapshot() -> WorkPackageSnapshot {
+        WorkPackageSnapshot {
+            subject: "Current".into(),
+            project_id: 1,
+            type_id: 1,
+            status_id: 2,
+            priority_id: Some(8),
+            assigned_to_id: Some(3),
+            responsible_id: None,
+            version_id: None,
+            category_id: None,
+            parent_id: None,
+            start_date: None,
+            due_date: None,
+            estimated_hours: Some(4.0),
+            done_ratio: Some(0),
+        }
+    }
+
+    #[test]
+    fn test_compare_at_timestamp_marks_changes() {
+        let timestamp = Timestamp::parse("P-1D").unwrap();
+        let current = snapshot();
+        let mut baseline = snapshot();
+        baseline.subject = "Before".into();
+        baseline.status_id = 1;
+        baseline.estimated_hours = Some(2.0);
+
+        let columns = ["id", "subject", "status", "assigned_to"];
+        let result = compare_at_timestamp(&timestamp, Utc::now(), &columns, Some(&baseline), &current);
+
+        assert!(result.exists);
+        // estimated_hours changed but is not a selected column
+        assert_eq!(result.changed.len(), 2);
+        assert_eq!(result.changed["subject"], json!("Before"));
+        assert_eq!(result.changed["status"], json!(1));
+
+        let meta = result.to_json();
+        assert_eq!(meta["_meta"]["timestamp"], "P-1D");
+        assert_eq!(meta["_meta"]["exists"], true);
+        assert_eq!(meta["subject"], "Before");
+    }
+
+    #[test]
+    fn test_compare_at_timestamp_flags_added() {
+        let timestamp = Timestamp::parse("oneWeekAgo@12:00+00:00").unwrap();
+        let result = compare_at_timestamp(&timestamp, Utc::now(), &["subject"], None, &snapshot());
+
+        assert!(!result.exists);
+        assert!(result.changed.is_empty());
+        assert_eq!(result.to_json()["_meta"]["exists"], false);
+    }
+
+    #[tokio::test]
+    async fn test_watched_by_me_preset_filters_by_watcher() {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let executor = WorkPackageQueryExecutor::new(&pool);
+        let query = op_queries::presets::watched_by_me();
+
+        let (where_clause, _) = executor.build_where_clause(&query.filters, Some(7)).unwrap();
+        assert_eq!(
+            where_clause,
+            "EXISTS (SELECT 1 FROM watchers w WHERE w.watchable_type = 'WorkPackage' \
+             AND w.watchable_id = wp.id AND w.user_id IN (7))"
+        );
+
+        let (anonymous, _) = executor.build_where_clause(&query.filters, None).unwrap();
+        assert_eq!(anonymous, "1 = 0");
+    }
+
+    #[tokio::test]
+    async fn test_visible_to_includes_shared_work_packages() {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let query = op_queries::presets::watched_by_me();
+
+        let (unrestricted, _) = WorkPackageQueryExecutor::new(&pool)
+            .build_where_clause(&query.filters, Some(7))
+            .unwrap();
+        let (restricted, _) = WorkPackageQueryExecutor::new(&pool)
+            .visible_to(7)
+            .build_where_clause(&query.filters, Some(7))
+            .unwrap();
+
+        let visible = visible_work_packages_sql(7);
+        assert_eq!(restricted, format!("{} AND {}", visible, unrestricted));
+        assert!(visible.contains(
+            "WHERE rp.permission = 'view_work_packages' AND m.user_id = 7 AND m.entity_type IS NULL)"
+        ));
+        assert!(visible.contains("m.entity_type = 'WorkPackage' AND m.entity_id = wp.id))"));
+        assert_eq!(build_join_clause(&[&visible]), "");
+
+        // Anonymous users have no memberships, only the Anonymous role
+        let anonymous = visible_work_packages_sql(0);
+        assert!(anonymous.starts_with("(wp.project_id IN (SELECT id FROM projects WHERE public AND active)"));
+        assert!(!anonymous.contains("members"));
+        assert_eq!(build_join_clause(&[&anonymous]), "");
+    }
+
+    #[tokio::test]
+    async fn test_open_preset_filters_by_closed_status_flag() {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let executor = WorkPackageQueryExecutor::new(&pool);
+
+        let (where_clause, _) = executor
+            .build_where_clause(&op_queries::presets::all_open().filters, Some(7))
+            .unwrap();
+        assert_eq!(where_clause, "s.is_closed = FALSE");
+        assert_eq!(build_join_clause(&[&where_clause]), "LEFT JOIN statuses s ON wp.status_id = s.id");
+
+        let (where_clause, _) = executor
+            .build_where_clause(&op_queries::presets::my_work_packages().filters, Some(7))
+            .unwrap();
+        assert_eq!(where_clause, "wp.assigned_to_id = 7 AND s.is_closed = FALSE");
+
+        let closed = op_queries::QueryBuilder::new().closed().build();
+        let (where_clause, _) = executor.build_where_clause(&closed.filters, None).unwrap();
+        assert_eq!(where_clause, "s.is_closed = TRUE");
+
+        // Only the status has an open or closed state
+        let filter = Filter::new("type_id", FilterOperator::Open, FilterValue::None);
+        assert_eq!(status_state_sql(&filter), None);
+    }
+
+    #[test]
+    fn test_watcher_filter_operators() {
+        let watched_by = |operator, values| {
+            meta_filter_to_sql(&Filter::new("watcher_id", operator, values), Some(1)).unwrap()
+        };
+
+        assert!(watched_by(FilterOperator::Equals, FilterValue::Ids(vec![2, 3]))
+            .ends_with("AND w.user_id IN (2, 3))"));
+        assert!(watched_by(FilterOperator::NotEquals, FilterValue::Me)
+            .starts_with("NOT EXISTS (SELECT 1 FROM watchers w"));
+        assert!(!watched_by(FilterOperator::IsNotNull, FilterValue::None).contains("user_id"));
+        assert!(watched_by(FilterOperator::IsNull, FilterValue::None).starts_with("NOT EXISTS"));
+    }
+
+    #[test]
+    fn test_comment_and_attachment_filters() {
+        let comment = Filter::new(
+            "comment",
+            FilterOperator::Contains,
+            FilterValue::String("50% o'clock".to_string()),
+        );
+        assert_eq!(
+            meta_filter_to_sql(&comment, None).unwrap(),
+            "EXISTS (SELECT 1 FROM journals j WHERE j.journable_type = 'WorkPackage' \
+             AND j.journable_id = wp.id AND j.notes ILIKE '%50\\% o''clock%')"
+        );
+
+        let file_name = Filter::new(
+            "attachment_file_name",
+            FilterOperator::NotContains,
+            FilterValue::String("invoice".to_string()),
+        );
+        assert!(meta_filter_to_sql(&file_name, None)
+            .unwrap()
+            .starts_with("NOT EXISTS (SELECT 1 FROM attachments a"));
+
+        let content = Filter::new(
+            "attachment_content",
+            FilterOperator::Contains,
+            FilterValue::String("budget".to_string()),
+        );
+        assert!(meta_filter_to_sql(&content, None)
+            .unwrap()
+            .ends_with("AND a.fulltext ILIKE '%budget%')"));
+
+        let unsupported = Filter::new("comment", FilterOperator::Equals, FilterValue::Ids(vec![1]));
+        assert_eq!(meta_filter_to_sql(&unsupported, None), None);
+        assert!(is_meta_attribute("watcher_id"));
+        assert!(!is_meta_attribute("subject"));
+    }
+
+    #[test]
+    fn test_subtree_filter_expands_descendants() {
+        let subtree = Filter::equals(attributes::SUBTREE_OF, FilterValue::Ids(vec![3, 8]));
+        let sql = meta_filter_to_sql(&subtree, None).unwrap();
+        assert!(sql.starts_with("wp.id IN (WITH RECURSIVE subtree AS (SELECT id FROM work_packages WHERE id IN (3, 8)"));
+        assert!(sql.contains("c.parent_id = st.id"));
+
+        let outside = Filter::not_equals(attributes::SUBTREE_OF, FilterValue::Id(3));
+        assert!(meta_filter_to_sql(&outside, None).unwrap().starts_with("wp.id NOT IN (WITH RECURSIVE"));
+
+        let nothing = Filter::equals(attributes::SUBTREE_OF, FilterValue::Ids(vec![]));
+        assert_eq!(meta_filter_to_sql(&nothing, None).unwrap(), "1 = 0");
+        assert!(meta_filter_to_sql(&Filter::is_null(attributes::SUBTREE_OF), None).is_none());
+        assert!(is_meta_attribute(attributes::SUBTREE_OF));
+    }
+
+    #[test]
+    fn test_build_join_clause() {
+        // Only the referenced lookup tables are joined
+        assert_eq!(build_join_clause(&["wp.status_id IN (1)", "ORDER BY wp.id DESC"]), "");
+        assert_eq!(
+            build_join_clause(&["", &build_order_clause(&SortOrder::by_asc("status"), &GroupBy::none())]),
+            "LEFT JOIN statuses s ON wp.status_id = s.id"
+        );
+
+        let joins = build_join_clause(&["t.name = 'Bug' AND p.position > 2", ""]);
+        assert!(!joins.contains("statuses"));
+        assert!(joins.contains("LEFT JOIN types t"));
+        assert!(joins.contains("LEFT JOIN enumerations p"));
+
+        // Columns merely ending in an alias do not count
+        assert_eq!(build_join_clause(&["wp.ids.x = 1", "wp.position"]), "");
+    }
+
+    #[tokio::test]
+    async fn test_empty_scope_matches_nothing() {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let query = Query::for_project("Outside", 5)
+            .with_filter(Filter::equals(attributes::PROJECT_ID, FilterValue::Id(9)));
+
+        let (where_clause, _) = WorkPackageQueryExecutor::new(&pool)
+            .build_where_clause(&query.scoped_filters(&[5]), None)
+            .unwrap();
+        assert_eq!(where_clause, "1 = 0");
+    }
+
     #[test]
     fn test_sort_attribute_to_column() {
         assert_eq!(
@@ -546,4 +2597,733 @@ mod tests {
         );
         assert_eq!(sort_attribute_to_column("unknown"), None);
     }
+
+    #[test]
+    fn test_latest_activity_is_a_lateral_join() {
+        let order = build_order_clause(&SortOrder::by_desc(attributes::LATEST_ACTIVITY_AT), &GroupBy::none());
+        assert_eq!(order, "ORDER BY la.at DESC NULLS FIRST, wp.id DESC");
+
+        // Aggregated once per row in the FROM clause, not per row in the select list
+        let joins = build_join_clause(&["", &order]);
+        assert!(joins.starts_with("LEFT JOIN LATERAL (SELECT MAX(j.created_at) AS at FROM journals j"));
+        assert!(joins.ends_with(") la ON TRUE"));
+        assert!(!WORK_PACKAGE_COLUMNS.contains("journals"));
+    }
+
+    #[test]
+    fn test_updated_by_filter_sql() {
+        let updated_by = Filter::equals(attributes::UPDATED_BY, FilterValue::Ids(vec![2, 3]));
+        assert_eq!(
+            updated_by_filter_sql(&updated_by, None, None).unwrap(),
+            "EXISTS (SELECT 1 FROM journals j WHERE j.journable_type = 'WorkPackage' \
+             AND j.journable_id = wp.id AND j.user_id IN (2, 3))"
+        );
+        assert!(updated_by_filter_sql(&updated_by, None, Some("j.created_at > NOW()"))
+            .unwrap()
+            .ends_with("AND j.user_id IN (2, 3) AND j.created_at > NOW())"));
+
+        let not_by_me = Filter::not_equals(attributes::UPDATED_BY, FilterValue::Me);
+        assert!(updated_by_filter_sql(&not_by_me, Some(7), None)
+            .unwrap()
+            .starts_with("NOT EXISTS (SELECT 1 FROM journals j"));
+        assert_eq!(updated_by_filter_sql(&not_by_me, None, None).unwrap(), "1 = 1");
+        assert!(updated_by_filter_sql(&Filter::is_null(attributes::UPDATED_BY), None, None).is_none());
+        assert!(is_meta_attribute(attributes::UPDATED_BY));
+    }
+
+    #[test]
+    fn test_unseen_changes_filter_sql() {
+        let unseen = Filter::equals(attributes::UNSEEN_CHANGES, FilterValue::String("t".into()));
+        let condition = unseen_changes_filter_sql(&unseen, Some(7)).unwrap();
+        assert!(condition.starts_with("NOT EXISTS (SELECT 1 FROM work_package_views wpv"));
+        assert!(condition.contains("wpv.user_id = 7"));
+
+        // Seen ones are those with a view at or after the latest change
+        let seen = Filter::not_equals(attributes::UNSEEN_CHANGES, FilterValue::Bool(false));
+        assert_eq!(unseen_changes_filter_sql(&seen, Some(7)).unwrap(), condition);
+        let seen = Filter::equals(attributes::UNSEEN_CHANGES, FilterValue::String("f".into()));
+        assert_eq!(unseen_changes_filter_sql(&seen, Some(7)).unwrap(), format!("NOT {}", condition));
+
+        assert_eq!(unseen_changes_filter_sql(&unseen, None).unwrap(), "1 = 1");
+        assert_eq!(unseen_changes_filter_sql(&seen, None).unwrap(), "1 = 0");
+        let invalid = Filter::equals(attributes::UNSEEN_CHANGES, FilterValue::String("maybe".into()));
+        assert!(unseen_changes_filter_sql(&invalid, Some(7)).is_none());
+        assert!(is_meta_attribute(attributes::UNSEEN_CHANGES));
+    }
+
+    #[test]
+    fn test_estimated_total_covers_the_page() {
+        assert_eq!(estimated_total(50_000, 10_000, 0, 20), 50_000);
+        // Stale statistics do not undercut what was counted or returned
+        assert_eq!(estimated_total(0, 10_000, 0, 20), 10_001);
+        assert_eq!(estimated_total(-1, 2, 0, 5), 5);
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::repository::Repository;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    /// Projects of the work packages a query matches, on the test transaction
+    async fn matching_projects(conn: &mut sqlx::PgConnection, query: &Query) -> Vec<Id> {
+        // The lazy pool only builds the SQL
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let scope = project_scope(&mut *conn, query.project_id.unwrap(), query.include_subprojects)
+            .await
+            .unwrap();
+        let (where_clause, _) = WorkPackageQueryExecutor::new(&pool)
+            .build_where_clause(&query.scoped_filters(&scope), None)
+            .unwrap();
+
+        sqlx::query_scalar(&format!(
+            "SELECT DISTINCT wp.project_id FROM work_packages wp WHERE {} ORDER BY wp.project_id",
+            where_clause
+        ))
+        .fetch_all(conn)
+        .await
+        .unwrap()
+    }
+
+    /// Subjects of the work packages of a project a filter matches for a user
+    async fn matching_subjects(conn: &mut sqlx::PgConnection, project_id: Id, filter: Filter, user_id: Id) -> Vec<String> {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let mut filters = FilterSet::new();
+        filters.add(filter);
+        let (where_clause, _) = WorkPackageQueryExecutor::new(&pool)
+            .build_where_clause(&filters, Some(user_id))
+            .unwrap();
+
+        sqlx::query_scalar(&format!(
+            "SELECT wp.subject FROM work_packages wp WHERE wp.project_id = {} AND {} ORDER BY wp.subject",
+            project_id, where_clause
+        ))
+        .fetch_all(conn)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_project_queries_cover_subprojects() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("scope-author")).await;
+        let root = db.insert_project(ProjectFixture::new("scope-root")).await;
+        let child = db.insert_project(ProjectFixture::new("scope-child").with_parent(root)).await;
+        let grandchild = db.insert_project(ProjectFixture::new("scope-grandchild").with_parent(child)).await;
+        let other = db.insert_project(ProjectFixture::new("scope-other")).await;
+        for project in [root, child, grandchild, other] {
+            db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        }
+
+        let mut conn = db.executor().acquire().await.unwrap();
+        let conn = &mut *conn;
+
+        let with_subprojects = Query::for_project("Tree", root);
+        assert_eq!(matching_projects(conn, &with_subprojects).await, vec![root, child, grandchild]);
+
+        let middle = Query::for_project("Middle", child);
+        assert_eq!(matching_projects(conn, &middle).await, vec![child, grandchild]);
+
+        let without_subprojects = Query::for_project("Root only", root).with_subprojects(false);
+        assert_eq!(matching_projects(conn, &without_subprojects).await, vec![root]);
+
+        // An explicit project filter intersects with the scope
+        let narrowed = Query::for_project("Narrowed", root)
+            .with_filter(Filter::equals(attributes::PROJECT_ID, FilterValue::Ids(vec![grandchild, other])));
+        assert_eq!(matching_projects(conn, &narrowed).await, vec![grandchild]);
+
+        let narrowed_root_only = Query::for_project("Narrowed root", root)
+            .with_subprojects(false)
+            .with_filter(Filter::equals(attributes::PROJECT_ID, FilterValue::Ids(vec![grandchild])));
+        assert!(matching_projects(conn, &narrowed_root_only).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_assignee_filters_expand_groups() {
+        let db = TestDb::connect().await;
+        let alice = db.insert_user(UserFixture::new("assignee-alice")).await;
+        let bob = db.insert_user(UserFixture::new("assignee-bob")).await;
+        let carol = db.insert_user(UserFixture::new("assignee-carol")).await;
+        let developers = db.insert_user(UserFixture::group("Assignee developers")).await;
+        let empty = db.insert_user(UserFixture::group("Assignee nobody")).await;
+        db.insert_group_member(developers, alice).await;
+        db.insert_group_member(developers, bob).await;
+        let project = db.insert_project(ProjectFixture::new("assignee-project")).await;
+        for (subject, assignee) in [("alice", alice), ("bob", bob), ("carol", carol), ("developers", developers), ("nobody", empty)] {
+            db.insert_work_package(WorkPackageFixture::new(project, alice).with_subject(subject).with_assignee(assignee))
+                .await;
+        }
+
+        let mut conn = db.executor().acquire().await.unwrap();
+        let conn = &mut *conn;
+        let group = Filter::equals(attributes::ASSIGNED_TO_ID, FilterValue::Id(developers));
+        assert_eq!(matching_subjects(conn, project, group, carol).await, vec!["alice", "bob", "developers"]);
+
+        let mixed = Filter::equals(attributes::ASSIGNED_TO_ID, FilterValue::IdsAndMe(vec![bob, empty]));
+        assert_eq!(matching_subjects(conn, project, mixed, carol).await, vec!["bob", "carol", "nobody"]);
+
+        let empty_group = Filter::equals(attributes::ASSIGNED_TO_ID, FilterValue::Id(empty));
+        assert_eq!(matching_subjects(conn, project, empty_group, carol).await, vec!["nobody"]);
+
+        let not_group = Filter::not_equals(attributes::ASSIGNED_TO_ID, FilterValue::Ids(vec![developers, carol]));
+        assert_eq!(matching_subjects(conn, project, not_group, carol).await, vec!["nobody"]);
+    }
+
+    #[tokio::test]
+    async fn test_member_of_group_filter() {
+        let db = TestDb::connect().await;
+        let alice = db.insert_user(UserFixture::new("group-filter-alice")).await;
+        let bob = db.insert_user(UserFixture::new("group-filter-bob")).await;
+        let carol = db.insert_user(UserFixture::new("group-filter-carol")).await;
+        let developers = db.insert_user(UserFixture::group("Group filter developers")).await;
+        let testers = db.insert_user(UserFixture::group("Group filter testers")).await;
+        db.insert_group_member(developers, alice).await;
+        db.insert_group_member(testers, bob).await;
+        let project = db.insert_project(ProjectFixture::new("group-filter-project")).await;
+        for (subject, assignee) in [("alice", Some(alice)), ("bob", Some(bob)), ("carol", Some(carol)), ("developers", Some(developers)), ("unassigned", None)] {
+            let mut fixture = WorkPackageFixture::new(project, alice).with_subject(subject);
+            if let Some(assignee) = assignee {
+                fixture = fixture.with_assignee(assignee);
+            }
+            db.insert_work_package(fixture).await;
+        }
+
+        let mut conn = db.executor().acquire().await.unwrap();
+        let conn = &mut *conn;
+        let filter = |operator, values| Filter::new(attributes::MEMBER_OF_GROUP, operator, values);
+
+        // Members only, not the group itself
+        let members = filter(FilterOperator::Equals, FilterValue::Id(developers));
+        assert_eq!(matching_subjects(conn, project, members, carol).await, vec!["alice"]);
+
+        let both = filter(FilterOperator::Equals, FilterValue::Ids(vec![developers, testers]));
+        assert_eq!(matching_subjects(conn, project, both, carol).await, vec!["alice", "bob"]);
+
+        let others = filter(FilterOperator::NotEquals, FilterValue::Id(developers));
+        assert_eq!(
+            matching_subjects(conn, project, others, carol).await,
+            vec!["bob", "carol", "developers", "unassigned"]
+        );
+
+        let any = filter(FilterOperator::IsNotNull, FilterValue::None);
+        assert_eq!(matching_subjects(conn, project, any, carol).await, vec!["alice", "bob"]);
+
+        let none = filter(FilterOperator::IsNull, FilterValue::None);
+        assert_eq!(
+            matching_subjects(conn, project, none, carol).await,
+            vec!["carol", "developers", "unassigned"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_children_and_subtree_filters() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("hierarchy-author")).await;
+        let project = db.insert_project(ProjectFixture::new("hierarchy-project")).await;
+        let insert = |subject: &'static str, parent: Option<Id>| {
+            let mut fixture = WorkPackageFixture::new(project, author).with_subject(subject);
+            if let Some(parent) = parent {
+                fixture = fixture.with_parent(parent);
+            }
+            db.insert_work_package(fixture)
+        };
+        // epic > feature > story > task, with a sibling feature and an unrelated root
+        let epic = insert("1 epic", None).await;
+        let feature = insert("2 feature", Some(epic)).await;
+        let story = insert("3 story", Some(feature)).await;
+        insert("4 task", Some(story)).await;
+        insert("5 other feature", Some(epic)).await;
+        insert("6 unrelated", None).await;
+
+        let mut conn = db.executor().acquire().await.unwrap();
+        let conn = &mut *conn;
+
+        let children = Filter::equals(attributes::PARENT_ID, FilterValue::Id(epic));
+        assert_eq!(matching_subjects(conn, project, children, author).await, vec!["2 feature", "5 other feature"]);
+
+        let subtree = Filter::equals(attributes::SUBTREE_OF, FilterValue::Id(epic));
+        assert_eq!(
+            matching_subjects(conn, project, subtree, author).await,
+            vec!["1 epic", "2 feature", "3 story", "4 task", "5 other feature"]
+        );
+
+        let children = Filter::equals(attributes::PARENT_ID, FilterValue::Id(feature));
+        assert_eq!(matching_subjects(conn, project, children, author).await, vec!["3 story"]);
+
+        let subtree = Filter::equals(attributes::SUBTREE_OF, FilterValue::Id(feature));
+        assert_eq!(matching_subjects(conn, project, subtree, author).await, vec!["2 feature", "3 story", "4 task"]);
+
+        let outside = Filter::not_equals(attributes::SUBTREE_OF, FilterValue::Id(feature));
+        assert_eq!(
+            matching_subjects(conn, project, outside, author).await,
+            vec!["1 epic", "5 other feature", "6 unrelated"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grouped_results_keep_groups_together() {
+        let db = TestDb::connect().await;
+        // Assignees of the same name still form groups of their own
+        let mut same_name = UserFixture::new("group-first");
+        same_name.firstname = "Alex".into();
+        let first = db.insert_user(same_name.clone()).await;
+        same_name.login = "group-second".into();
+        let second = db.insert_user(same_name).await;
+        let project = db.insert_project(ProjectFixture::new("group-project")).await;
+        for (subject, assignee) in [("a", Some(first)), ("b", None), ("c", Some(second)), ("d", Some(first))] {
+            let mut work_package = WorkPackageFixture::new(project, first).with_subject(subject);
+            work_package.assigned_to_id = assignee;
+            db.insert_work_package(work_package).await;
+        }
+
+        for direction in [SortDirection::Asc, SortDirection::Desc] {
+            let sorts = SortOrder::new().then(SortCriterion::new("assigned_to", direction));
+            let order_clause = build_order_clause(&sorts, &GroupBy::by("assigned_to"));
+            let assignees: Vec<Option<Id>> = sqlx::query_scalar(&format!(
+                "SELECT wp.assigned_to_id FROM work_packages wp {} WHERE wp.project_id = $1 {}",
+                build_join_clause(&[&order_clause]),
+                order_clause
+            ))
+            .bind(project)
+            .fetch_all(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+
+            let (low, high) = (first.min(second), first.max(second));
+            let expected = match direction {
+                SortDirection::Asc => vec![Some(low), Some(high), None],
+                SortDirection::Desc => vec![Some(high), Some(low), None],
+            };
+            let mut groups = assignees.clone();
+            groups.dedup();
+            assert_eq!(groups, expected);
+            assert_eq!(assignees.iter().filter(|assignee| **assignee == Some(first)).count(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_groups_of_due_dates_by_period() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("period-author")).await;
+        let project = db.insert_project(ProjectFixture::new("period-project")).await;
+        let due_dates = [Some("2024-02-28"), Some("2024-02-29"), Some("2024-03-01"), Some("2024-12-30"), Some("2025-01-02"), None];
+        for due_date in due_dates {
+            let id = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+            sqlx::query("UPDATE work_packages SET due_date = $1::date WHERE id = $2")
+                .bind(due_date)
+                .bind(id)
+                .execute(&mut *db.executor().acquire().await.unwrap())
+                .await
+                .unwrap();
+        }
+
+        let executor = WorkPackageQueryExecutor::with_executor(db.executor());
+        let groups = |grouping: &str| {
+            let query = Query::for_project("By period", project).with_group_by(GroupBy::parse(grouping).unwrap());
+            let executor = &executor;
+            async move {
+                executor
+                    .execute_groups(&query, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|group| (group.value, group.label, group.count))
+                    .collect::<Vec<_>>()
+            }
+        };
+        let group = |value: &str, label: &str, count| (Some(value.to_string()), Some(label.to_string()), count);
+
+        // The leap day belongs to February, the matches without a date come last
+        assert_eq!(
+            groups("due_date:month").await,
+            vec![
+                group("2024-02", "February 2024", 2),
+                group("2024-03", "March 2024", 1),
+                group("2024-12", "December 2024", 1),
+                group("2025-01", "January 2025", 1),
+                (None, None, 1),
+            ]
+        );
+        // 2024-12-30 is in the first ISO week of 2025
+        assert_eq!(
+            groups("due_date:week").await,
+            vec![
+                group("2024-W09", "CW 9", 3),
+                group("2025-W01", "CW 1", 2),
+                (None, None, 1),
+            ]
+        );
+        assert_eq!(groups("due_date:year").await[0], group("2024", "2024", 4));
+    }
+
+    #[tokio::test]
+    async fn test_today_is_the_day_in_the_users_time_zone() {
+        use op_core::clock::{parse_time_zone, ManualClock};
+
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("tz-author")).await;
+        let project = db.insert_project(ProjectFixture::new("tz-project")).await;
+        for (subject, due_date) in [("Tuesday", "2024-03-05"), ("Wednesday", "2024-03-06")] {
+            let id = db
+                .insert_work_package(WorkPackageFixture::new(project, author).with_subject(subject))
+                .await;
+            sqlx::query("UPDATE work_packages SET due_date = $1::date WHERE id = $2")
+                .bind(due_date)
+                .bind(id)
+                .execute(&mut *db.executor().acquire().await.unwrap())
+                .await
+                .unwrap();
+        }
+
+        // 23:59 in Brisbane (UTC+10), still the same day in UTC
+        let clock = Arc::new(ManualClock::new("2024-03-05T13:59:00Z".parse().unwrap()));
+        let brisbane = parse_time_zone("Australia/Brisbane").unwrap();
+        let query = Query::for_project("Due today", project)
+            .with_filter(Filter::new(attributes::DUE_DATE, FilterOperator::Today, FilterValue::None));
+        let due_today = |executor: WorkPackageQueryExecutor| {
+            let query = query.clone();
+            async move {
+                let result = executor.execute(&query, &Pagination::new(20, 0), None).await.unwrap();
+                result.items.into_iter().map(|wp| wp.subject).collect::<Vec<_>>()
+            }
+        };
+        let executor = |time_zone| {
+            WorkPackageQueryExecutor::with_executor(db.executor())
+                .with_clock(clock.clone())
+                .in_time_zone(time_zone)
+        };
+
+        assert_eq!(due_today(executor(brisbane)).await, vec!["Tuesday"]);
+        assert_eq!(due_today(executor(Tz::UTC)).await, vec!["Tuesday"]);
+
+        // Past midnight in Brisbane
+        clock.advance(chrono::Duration::minutes(1));
+        assert_eq!(due_today(executor(brisbane)).await, vec!["Wednesday"]);
+        assert_eq!(due_today(executor(Tz::UTC)).await, vec!["Tuesday"]);
+    }
+
+    #[tokio::test]
+    async fn test_cached_results_follow_writes_in_their_projects() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("cache-author")).await;
+        let project = db.insert_project(ProjectFixture::new("cache-project")).await;
+        let unrelated = db.insert_project(ProjectFixture::new("cache-unrelated")).await;
+        let open = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        db.insert_work_package(WorkPackageFixture::new(unrelated, author)).await;
+        let status = db.work_packages().find_by_id(open).await.unwrap().unwrap().status_id;
+        let closed: Id = sqlx::query_scalar("INSERT INTO statuses (name, is_closed) VALUES ('Closed', TRUE) RETURNING id")
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+
+        let cache = Arc::new(crate::query_cache::MemoryQueryResultCache::default());
+        let metrics = Arc::new(DomainMetrics::new());
+        let executor = WorkPackageQueryExecutor::with_executor(db.executor())
+            .with_cache(cache.clone())
+            .with_metrics(metrics.clone());
+        let query = Query::for_project("Open", project)
+            .with_filter(Filter::equals(attributes::STATUS_ID, FilterValue::Ids(vec![status])));
+        let page = Pagination::new(20, 0);
+        let hits = || metrics.query_cache_hits.load(std::sync::atomic::Ordering::Relaxed);
+
+        assert_eq!(executor.execute(&query, &page, None).await.unwrap().total, 2);
+        assert_eq!(executor.execute(&query, &page, None).await.unwrap().total, 2);
+        assert_eq!(hits(), 1);
+
+        // A write in the query's project shows on the very next call
+        let row = db.work_packages().find_by_id(open).await.unwrap().unwrap();
+        db.work_packages().update_status(open, closed, row.lock_version).await.unwrap();
+        cache.invalidate_project(project).await;
+        let result = executor.execute(&query, &page, None).await.unwrap();
+        assert_eq!(result.total, 1);
+        assert!(result.items.iter().all(|wp| wp.id != open));
+        assert_eq!(hits(), 1);
+
+        // A write elsewhere keeps the entry
+        db.insert_work_package(WorkPackageFixture::new(unrelated, author)).await;
+        cache.invalidate_project(unrelated).await;
+        assert_eq!(executor.execute(&query, &page, None).await.unwrap().total, 1);
+        assert_eq!(hits(), 2);
+        assert_eq!(metrics.query_cache_misses.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_large_totals_are_estimated_on_first_pages() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("count-author")).await;
+        let project = db.insert_project(ProjectFixture::new("count-project")).await;
+        for _ in 0..5 {
+            db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        }
+        sqlx::query("ANALYZE work_packages")
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+
+        let exact = WorkPackageQueryExecutor::with_executor(db.executor());
+        let estimating = WorkPackageQueryExecutor::with_executor(db.executor())
+            .with_count_strategy(CountStrategy::Estimated { threshold: 2 });
+        let query = Query::new("All");
+        let all = exact.execute(&query, &Pagination::new(1000, 0), None).await.unwrap();
+        assert!(!all.total_is_estimate);
+
+        let first = estimating.execute(&query, &Pagination::new(1000, 0), None).await.unwrap();
+        assert!(first.total_is_estimate);
+        assert_eq!(first.items.len() as i64, all.total);
+        assert!(first.total >= first.items.len() as i64);
+
+        // Later pages and queries showing sums count exactly
+        let second = estimating.execute(&query, &Pagination::new(2, 2), None).await.unwrap();
+        assert!(!second.total_is_estimate);
+        assert_eq!(second.total, all.total);
+        let mut sums = query.clone();
+        sums.show_sums = true;
+        let summed = estimating.execute(&sums, &Pagination::new(2, 0), None).await.unwrap();
+        assert_eq!((summed.total, summed.total_is_estimate), (all.total, false));
+
+        // Totals below the threshold are exact
+        let small = WorkPackageQueryExecutor::with_executor(db.executor())
+            .with_count_strategy(CountStrategy::Estimated { threshold: all.total })
+            .execute(&query, &Pagination::new(2, 0), None)
+            .await
+            .unwrap();
+        assert_eq!((small.total, small.total_is_estimate), (all.total, false));
+    }
+
+    /// Journal of a work package by a user at the given time
+    async fn insert_journal(db: &TestDb, work_package: Id, user: Id, version: i32, created_at: &str) {
+        sqlx::query(
+            "INSERT INTO journals (journable_type, journable_id, user_id, version, data_type, data_id, created_at) \
+             VALUES ('WorkPackage', $1, $2, $3, 'Journal::WorkPackageJournal', 0, $4::timestamptz)",
+        )
+        .bind(work_package)
+        .bind(user)
+        .bind(version)
+        .bind(created_at)
+        .execute(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_latest_activity_and_updated_by_come_from_journals() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("activity-author")).await;
+        let editor = db.insert_user(UserFixture::new("activity-editor")).await;
+        let project = db.insert_project(ProjectFixture::new("activity-project")).await;
+        let mut ids = Vec::new();
+        for subject in ["Older", "Newer", "Untouched"] {
+            ids.push(
+                db.insert_work_package(WorkPackageFixture::new(project, author).with_subject(subject))
+                    .await,
+            );
+        }
+        insert_journal(&db, ids[0], editor, 1, "2024-02-01T10:00:00Z").await;
+        insert_journal(&db, ids[0], author, 2, "2024-03-01T10:00:00Z").await;
+        insert_journal(&db, ids[1], editor, 1, "2024-03-10T10:00:00Z").await;
+
+        let executor = WorkPackageQueryExecutor::with_executor(db.executor());
+
+        // Work packages without journals have no activity and come first
+        let mut by_activity = Query::for_project("Recently active", project);
+        by_activity.sorts = SortOrder::by_desc(attributes::LATEST_ACTIVITY_AT);
+        assert_eq!(subjects(&executor, &by_activity).await, vec!["Untouched", "Newer", "Older"]);
+
+        let mut updated_by = Query::for_project("Updated by", project)
+            .with_filter(Filter::equals(attributes::UPDATED_BY, FilterValue::Id(editor)));
+        updated_by.sorts = SortOrder::by_asc("subject");
+        assert_eq!(subjects(&executor, &updated_by).await, vec!["Newer", "Older"]);
+
+        // Within a latest activity window only the journals in it count
+        let since = |from: &str| {
+            Filter::new(
+                attributes::LATEST_ACTIVITY_AT,
+                FilterOperator::Between,
+                FilterValue::DateRange { from: from.to_string(), to: String::new() },
+            )
+        };
+        assert_eq!(subjects(&executor, &updated_by.clone().with_filter(since("2024-02-25"))).await, vec!["Newer"]);
+        assert_eq!(
+            subjects(&executor, &updated_by.with_filter(since("2024-01-01"))).await,
+            vec!["Newer", "Older"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_users_view_public_projects() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("public-author")).await;
+        let public = db.insert_project(ProjectFixture::new("public-project").with_public()).await;
+        let private = db.insert_project(ProjectFixture::new("private-project")).await;
+        db.insert_work_package(WorkPackageFixture::new(public, author).with_subject("Public")).await;
+        db.insert_work_package(WorkPackageFixture::new(private, author).with_subject("Private")).await;
+        let executor = WorkPackageQueryExecutor::with_executor(db.executor()).visible_to(0);
+        let in_project = |project| Query::for_project("Anonymous", project);
+
+        // Unless the Anonymous role may view work packages
+        assert!(subjects(&executor, &in_project(public)).await.is_empty());
+
+        let anonymous = db.insert_role("Anonymous", &["view_work_packages"]).await;
+        sqlx::query("UPDATE roles SET builtin = $1 WHERE id = $2")
+            .bind(crate::roles::builtin::ANONYMOUS)
+            .bind(anonymous)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(subjects(&executor, &in_project(public)).await, vec!["Public"]);
+        assert!(subjects(&executor, &in_project(private)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_statement_timeout_cancels_slow_queries() {
+        let db = TestDb::connect().await;
+        sqlx::query("SET LOCAL statement_timeout = 20")
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        let result: Result<_, RepositoryError> = sqlx::query("SELECT pg_sleep(1)")
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .map_err(RepositoryError::from);
+        assert!(matches!(result, Err(RepositoryError::Timeout(_))), "{:?}", result);
+
+        // A query waiting for a lock on the work packages times out as well
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let pool = sqlx::postgres::PgPoolOptions::new().max_connections(2).connect(&url).await.unwrap();
+        let mut lock = pool.begin().await.unwrap();
+        sqlx::query("LOCK TABLE work_packages IN ACCESS EXCLUSIVE MODE").execute(&mut *lock).await.unwrap();
+        let executor = WorkPackageQueryExecutor::new(&pool).with_statement_timeout(StdDuration::from_millis(50));
+        let result = executor.execute(&Query::new("Slow"), &Pagination::new(20, 0), None).await;
+        lock.rollback().await.unwrap();
+        assert!(matches!(result, Err(RepositoryError::Timeout(_))), "{:?}", result.map(|r| r.total));
+
+        // The timeout is local to the executor's transaction
+        let timeout: String = sqlx::query_scalar("SHOW statement_timeout").fetch_one(&pool).await.unwrap();
+        assert_eq!(timeout, "0");
+        assert!(executor.execute(&Query::new("Fast"), &Pagination::new(1, 0), None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unseen_changes_filter_follows_views() {
+        let db = TestDb::connect().await;
+        let reader = db.insert_user(UserFixture::new("unseen-reader")).await;
+        let project = db.insert_project(ProjectFixture::new("unseen-project")).await;
+        let seen = db
+            .insert_work_package(WorkPackageFixture::new(project, reader).with_subject("Seen"))
+            .await;
+        db.insert_work_package(WorkPackageFixture::new(project, reader).with_subject("Never viewed"))
+            .await;
+        db.work_package_views()
+            .record_view(reader, seen, Utc::now() + Duration::minutes(1))
+            .await
+            .unwrap();
+
+        let executor = &WorkPackageQueryExecutor::with_executor(db.executor());
+        let filtered = |value: &str| {
+            let mut query = Query::for_project("Unseen", project)
+                .with_filter(Filter::equals(attributes::UNSEEN_CHANGES, FilterValue::String(value.into())));
+            query.sorts = SortOrder::by_asc("subject");
+            query
+        };
+        let subjects_for = |query: Query| async move {
+            let result = executor.execute(&query, &Pagination::new(20, 0), Some(reader)).await.unwrap();
+            result.items.into_iter().map(|wp| wp.subject).collect::<Vec<_>>()
+        };
+        assert_eq!(subjects_for(filtered("t")).await, vec!["Never viewed"]);
+        assert_eq!(subjects_for(filtered("f")).await, vec!["Seen"]);
+
+        // A journal after the view is a change the reader has not seen
+        insert_journal(&db, seen, reader, 1, &(Utc::now() + Duration::minutes(2)).to_rfc3339()).await;
+        assert_eq!(subjects_for(filtered("t")).await, vec!["Never viewed", "Seen"]);
+    }
+
+    #[tokio::test]
+    async fn test_misspelled_filter_fails_instead_of_matching_everything() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("typo-author")).await;
+        let project = db.insert_project(ProjectFixture::new("typo-project")).await;
+        db.insert_work_package(WorkPackageFixture::new(project, author).with_subject("Mine")).await;
+        db.insert_work_package(WorkPackageFixture::new(project, author).with_subject("Theirs")).await;
+
+        let query = Query::for_project("Typo", project)
+            .with_filter(Filter::equals("asigned_to_id", FilterValue::Id(author)));
+        let executor = WorkPackageQueryExecutor::with_executor(db.executor());
+        let result = executor.execute(&query, &Pagination::new(20, 0), Some(author)).await;
+        let Err(RepositoryError::InvalidQuery(error)) = result else {
+            panic!("expected the misspelled filter to fail the query");
+        };
+        assert_eq!(error.errors[0].attribute, "asigned_to_id");
+
+        // Lenient executors drop it, matching every work package of the project
+        assert_eq!(subjects(&executor.lenient(), &query).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_trashed_work_packages_only_show_with_the_trashed_filter() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("trash-author")).await;
+        let project = db.insert_project(ProjectFixture::new("trash-project")).await;
+        db.insert_work_package(WorkPackageFixture::new(project, author).with_subject("Kept"))
+            .await;
+        let trashed = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_subject("Trashed"))
+            .await;
+        sqlx::query("UPDATE work_packages SET deleted_at = NOW(), deleted_by = $2 WHERE id = $1")
+            .bind(trashed)
+            .bind(author)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+
+        let mut query = Query::for_project("Trash", project);
+        query.sorts = SortOrder::by_asc("subject");
+        let trash = query
+            .clone()
+            .with_filter(Filter::equals(attributes::TRASHED, FilterValue::String("t".into())));
+        let executor = WorkPackageQueryExecutor::with_executor(db.executor());
+        assert_eq!(subjects(&executor, &query).await, vec!["Kept"]);
+        assert_eq!(subjects(&executor, &trash).await, vec!["Trashed"]);
+
+        // Users restricted to what they may view never see the trash
+        assert!(subjects(&executor.visible_to(author), &trash).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_uncounted_pages_continue_counted_ones() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("chunk-author")).await;
+        let project = db.insert_project(ProjectFixture::new("chunk-project")).await;
+        for subject in ["A", "B", "C", "D", "E"] {
+            db.insert_work_package(WorkPackageFixture::new(project, author).with_subject(subject))
+                .await;
+        }
+
+        let mut query = Query::for_project("Chunks", project);
+        query.sorts = SortOrder::by_asc("subject");
+        let executor = WorkPackageQueryExecutor::with_executor(db.executor());
+        let first = executor.execute(&query, &Pagination::new(2, 0), None).await.unwrap();
+        assert_eq!(first.total, 5);
+
+        let mut subjects: Vec<String> = first.items.into_iter().map(|wp| wp.subject).collect();
+        for offset in [2, 4] {
+            let rows = executor
+                .execute_uncounted(&query, &Pagination::new(2, offset), None)
+                .await
+                .unwrap();
+            subjects.extend(rows.into_iter().map(|wp| wp.subject));
+        }
+        assert_eq!(subjects, vec!["A", "B", "C", "D", "E"]);
+    }
+
+    /// Subjects of the first page of a query
+    async fn subjects(executor: &WorkPackageQueryExecutor, query: &Query) -> Vec<String> {
+        let result = executor.execute(query, &Pagination::new(20, 0), None).await.unwrap();
+        result.items.into_iter().map(|wp| wp.subject).collect()
+    }
 }
@@ -0,0 +1,213 @@
+//! Query subscriptions repository
+//!
+//! A user subscribed to a saved query is emailed when work packages enter
+//! or leave its results. The ids of the results seen at the last check are
+//! kept with the subscription as an id array.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::DbExecutor;
+use crate::repository::{RepositoryError, RepositoryResult};
+
+/// How often subscribed queries are checked
+pub mod frequency {
+    pub const HOURLY: &str = "hourly";
+    pub const DAILY: &str = "daily";
+    pub const WEEKLY: &str = "weekly";
+}
+
+/// Query subscription row from database
+#[derive(Debug, Clone, FromRow)]
+pub struct QuerySubscriptionRow {
+    pub id: i64,
+    pub query_id: i64,
+    pub user_id: i64,
+    pub frequency: String,
+    /// Ids of the results at the last check; `None` until the first check
+    pub snapshot: Option<Vec<i64>>,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
+    pub suspended_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl QuerySubscriptionRow {
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_at.is_some()
+    }
+}
+
+/// Query subscription repository
+pub struct QuerySubscriptionRepository {
+    db: DbExecutor,
+}
+
+impl QuerySubscriptionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// The user's subscription to a query
+    pub async fn find(&self, query_id: Id, user_id: Id) -> RepositoryResult<Option<QuerySubscriptionRow>> {
+        let row = sqlx::query_as::<_, QuerySubscriptionRow>(
+            r#"
+            SELECT id, query_id, user_id, frequency, snapshot, checked_at,
+                   suspended_at, suspended_reason, created_at, updated_at
+            FROM query_subscriptions
+            WHERE query_id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(query_id)
+        .bind(user_id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Subscribe the user to a query, or change the frequency of an existing
+    /// subscription. A suspended subscription is resumed.
+    pub async fn subscribe(&self, query_id: Id, user_id: Id, frequency: &str) -> RepositoryResult<QuerySubscriptionRow> {
+        sqlx::query_as::<_, QuerySubscriptionRow>(
+            r#"
+            INSERT INTO query_subscriptions (query_id, user_id, frequency, created_at, updated_at)
+            SELECT q.id, $2, $3, NOW(), NOW()
+            FROM queries q
+            WHERE q.id = $1
+            ON CONFLICT (query_id, user_id) DO UPDATE
+            SET frequency = EXCLUDED.frequency,
+                suspended_at = NULL,
+                suspended_reason = NULL,
+                updated_at = NOW()
+            RETURNING id, query_id, user_id, frequency, snapshot, checked_at,
+                      suspended_at, suspended_reason, created_at, updated_at
+            "#,
+        )
+        .bind(query_id)
+        .bind(user_id)
+        .bind(frequency)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?
+        .ok_or_else(|| RepositoryError::NotFound(format!("Query {} not found", query_id)))
+    }
+
+    /// Remove the user's subscription, returning whether there was one
+    pub async fn unsubscribe(&self, query_id: Id, user_id: Id) -> RepositoryResult<bool> {
+        let result = sqlx::query("DELETE FROM query_subscriptions WHERE query_id = $1 AND user_id = $2")
+            .bind(query_id)
+            .bind(user_id)
+            .execute(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Subscriptions that are not suspended
+    pub async fn find_active(&self) -> RepositoryResult<Vec<QuerySubscriptionRow>> {
+        let rows = sqlx::query_as::<_, QuerySubscriptionRow>(
+            r#"
+            SELECT id, query_id, user_id, frequency, snapshot, checked_at,
+                   suspended_at, suspended_reason, created_at, updated_at
+            FROM query_subscriptions
+            WHERE suspended_at IS NULL
+            ORDER BY id ASC
+            "#,
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Store the result ids of a check
+    pub async fn update_snapshot(&self, id: Id, snapshot: &[Id], checked_at: DateTime<Utc>) -> RepositoryResult<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE query_subscriptions
+            SET snapshot = $2, checked_at = $3, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(snapshot)
+        .bind(checked_at)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Query subscription {} not found", id)));
+        }
+        Ok(())
+    }
+
+    /// Stop checking a subscription until the user subscribes again
+    pub async fn suspend(&self, id: Id, reason: &str) -> RepositoryResult<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE query_subscriptions
+            SET suspended_at = NOW(), suspended_reason = $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(reason)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Query subscription {} not found", id)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture};
+
+    #[tokio::test]
+    async fn test_subscription_lifecycle() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("subscriber")).await;
+        let project = db.insert_project(ProjectFixture::new("subscriptions")).await;
+        let query = db.insert_query(user, Some(project), "Open bugs").await;
+        let repo = db.query_subscriptions();
+
+        let subscription = repo.subscribe(query, user, frequency::DAILY).await.unwrap();
+        assert_eq!(subscription.snapshot, None);
+        assert!(matches!(
+            repo.subscribe(query + 1000, user, frequency::DAILY).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        let checked_at = Utc::now();
+        repo.update_snapshot(subscription.id, &[3, 1, 2], checked_at).await.unwrap();
+        let found = repo.find(query, user).await.unwrap().unwrap();
+        assert_eq!(found.snapshot, Some(vec![3, 1, 2]));
+        assert!(found.checked_at.is_some());
+
+        repo.suspend(subscription.id, "Project not found").await.unwrap();
+        assert!(repo.find_active().await.unwrap().iter().all(|s| s.id != subscription.id));
+
+        let resumed = repo.subscribe(query, user, frequency::WEEKLY).await.unwrap();
+        assert_eq!(resumed.id, subscription.id);
+        assert_eq!(resumed.frequency, frequency::WEEKLY);
+        assert!(!resumed.is_suspended());
+        assert_eq!(resumed.snapshot, Some(vec![3, 1, 2]));
+        assert!(repo.find_active().await.unwrap().iter().any(|s| s.id == subscription.id));
+
+        assert!(repo.unsubscribe(query, user).await.unwrap());
+        assert!(!repo.unsubscribe(query, user).await.unwrap());
+        assert!(repo.find(query, user).await.unwrap().is_none());
+    }
+}
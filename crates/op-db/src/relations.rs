@@ -7,6 +7,8 @@ use chrono::{DateTime, Utc};
 use sqlx::{FromRow, PgPool};
 
 use crate::{Pagination, PaginatedResult, Repository, RepositoryError};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
 
 /// Relation type constants
 pub mod relation_type {
@@ -64,7 +66,37 @@ pub mod relation_type {
 /// Maximum lag value
 pub const MAX_LAG: i32 = 2000;
 /// Minimum lag value
-pub const MIN_LAG: i32 = -2000;
+pub const MIN_LAG: i32 = 0;
+
+/// Validate the lag of a relation: working days between a predecessor
+/// and its successor, so only follows and precedes relations have one
+pub fn validate_lag(relation_type: &str, lag: Option<i32>) -> Result<(), RepositoryError> {
+    let Some(lag) = lag else {
+        return Ok(());
+    };
+    if !(MIN_LAG..=MAX_LAG).contains(&lag) {
+        return Err(RepositoryError::invalid(
+            "lag",
+            "inclusion",
+            format!("must be between {} and {}", MIN_LAG, MAX_LAG),
+        ));
+    }
+    if lag != 0 && !matches!(relation_type, relation_type::PRECEDES | relation_type::FOLLOWS) {
+        return Err(RepositoryError::invalid(
+            "lag",
+            "only_for_precedes_and_follows",
+            "can only be set on follows and precedes relations",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Relations are meaningless once either end is gone
+pub const RELATION_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::new("relations", "from_id", "work_packages", OrphanAction::Delete),
+    OrphanCheck::new("relations", "to_id", "work_packages", OrphanAction::Delete),
+];
 
 /// Relation row from database
 #[derive(Debug, Clone, FromRow)]
@@ -166,6 +198,7 @@ impl RelationRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -203,6 +236,72 @@ impl RelationRepository {
         Ok(rows)
     }
 
+    /// Find the precedes relations from or to any of the work packages,
+    /// which their schedules depend on
+    pub async fn find_precedes_touching(&self, work_package_ids: &[i64]) -> Result<Vec<RelationRow>, RepositoryError> {
+        if work_package_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, RelationRow>(
+            r#"
+            SELECT id, from_id, to_id, relation_type, lag, description, created_at, updated_at
+            FROM relations
+            WHERE relation_type = $1 AND (from_id = ANY($2) OR to_id = ANY($2))
+            ORDER BY id
+            "#,
+        )
+        .bind(relation_type::PRECEDES)
+        .bind(work_package_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Find the relations with both ends among the work packages
+    pub async fn find_among(&self, work_package_ids: &[i64]) -> Result<Vec<RelationRow>, RepositoryError> {
+        if work_package_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, RelationRow>(
+            r#"
+            SELECT id, from_id, to_id, relation_type, lag, description, created_at, updated_at
+            FROM relations
+            WHERE from_id = ANY($1) AND to_id = ANY($1)
+            ORDER BY id
+            "#,
+        )
+        .bind(work_package_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Find the relations with exactly one end among the work packages,
+    /// those to their immediate neighbors
+    pub async fn find_leaving(&self, work_package_ids: &[i64]) -> Result<Vec<RelationRow>, RepositoryError> {
+        if work_package_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, RelationRow>(
+            r#"
+            SELECT id, from_id, to_id, relation_type, lag, description, created_at, updated_at
+            FROM relations
+            WHERE (from_id = ANY($1)) <> (to_id = ANY($1))
+            ORDER BY id
+            "#,
+        )
+        .bind(work_package_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
     /// Find follows relations with lag
     pub async fn find_follows_with_lag(&self) -> Result<Vec<RelationRow>, RepositoryError> {
         let rows = sqlx::query_as::<_, RelationRow>(
@@ -253,6 +352,7 @@ impl RelationRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -342,26 +442,21 @@ impl Repository<RelationRow, CreateRelationDto, UpdateRelationDto> for RelationR
     async fn create(&self, dto: CreateRelationDto) -> Result<RelationRow, RepositoryError> {
         // Validate relation type
         if !relation_type::is_valid(&dto.relation_type) {
-            return Err(RepositoryError::Validation(format!(
-                "Invalid relation type: {}",
-                dto.relation_type
-            )));
+            return Err(RepositoryError::invalid(
+                "relation_type",
+                "inclusion",
+                format!("is not a valid relation type: {}", dto.relation_type),
+            ));
         }
 
-        // Validate lag range
-        if let Some(lag) = dto.lag {
-            if lag < MIN_LAG || lag > MAX_LAG {
-                return Err(RepositoryError::Validation(format!(
-                    "Lag must be between {} and {}",
-                    MIN_LAG, MAX_LAG
-                )));
-            }
-        }
+        validate_lag(&dto.relation_type, dto.lag)?;
 
         // Validate from and to are different
         if dto.from_id == dto.to_id {
-            return Err(RepositoryError::Validation(
-                "A work package cannot be related to itself".to_string(),
+            return Err(RepositoryError::invalid(
+                "to",
+                "cant_link_a_work_package_with_itself",
+                "can't be the work package itself",
             ));
         }
 
@@ -400,15 +495,7 @@ impl Repository<RelationRow, CreateRelationDto, UpdateRelationDto> for RelationR
             .await?
             .ok_or_else(|| RepositoryError::NotFound(format!("Relation {} not found", id)))?;
 
-        // Validate lag if provided
-        if let Some(lag) = dto.lag {
-            if lag < MIN_LAG || lag > MAX_LAG {
-                return Err(RepositoryError::Validation(format!(
-                    "Lag must be between {} and {}",
-                    MIN_LAG, MAX_LAG
-                )));
-            }
-        }
+        validate_lag(&existing.relation_type, dto.lag)?;
 
         let new_lag = dto.lag.or(existing.lag);
         let new_description = match dto.description {
@@ -475,4 +562,16 @@ mod tests {
         assert!(relation_type::is_valid(relation_type::FOLLOWS));
         assert!(!relation_type::is_valid("invalid"));
     }
+
+    #[test]
+    fn test_validate_lag() {
+        assert!(validate_lag(relation_type::FOLLOWS, Some(2)).is_ok());
+        assert!(validate_lag(relation_type::PRECEDES, Some(MAX_LAG)).is_ok());
+        assert!(validate_lag(relation_type::RELATES, Some(0)).is_ok());
+        assert!(validate_lag(relation_type::RELATES, None).is_ok());
+
+        assert!(validate_lag(relation_type::PRECEDES, Some(-1)).is_err());
+        assert!(validate_lag(relation_type::PRECEDES, Some(MAX_LAG + 1)).is_err());
+        assert!(validate_lag(relation_type::BLOCKS, Some(2)).is_err());
+    }
 }
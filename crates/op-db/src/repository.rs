@@ -2,9 +2,14 @@
 //!
 //! Provides generic CRUD operations for database entities.
 
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
 use async_trait::async_trait;
+use op_core::error::PropertyError;
+use op_core::metrics::DomainMetrics;
 use op_core::traits::Id;
-use sqlx::PgPool;
 
 /// Error type for repository operations
 #[derive(Debug, thiserror::Error)]
@@ -13,16 +18,62 @@ pub enum RepositoryError {
     NotFound(String),
 
     #[error("Database error: {0}")]
-    Database(#[from] sqlx::Error),
+    Database(sqlx::Error),
+
+    /// A statement ran longer than the statement timeout and was canceled
+    #[error("Query timed out: {0}")]
+    Timeout(String),
 
-    #[error("Validation error: {0}")]
-    Validation(String),
+    #[error("Validation error: {}", full_messages(.0))]
+    Validation(Vec<PropertyError>),
 
     #[error("Conflict: {0}")]
     Conflict(String),
 
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
+
+    /// A work package query has filters that cannot be executed
+    #[error("{0}")]
+    InvalidQuery(#[from] crate::query_executor::QueryValidationError),
+}
+
+impl RepositoryError {
+    /// Validation failure with a single error; a plain message is reported on `base`
+    pub fn validation(error: impl Into<PropertyError>) -> Self {
+        RepositoryError::Validation(vec![error.into()])
+    }
+
+    /// Validation failure of one property, e.g. `invalid("name", "blank", "can't be blank")`
+    pub fn invalid(
+        property: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self::validation(PropertyError::new(property, code, message))
+    }
+}
+
+/// SQLSTATE of a statement canceled, e.g. by the statement timeout
+const QUERY_CANCELED: &str = "57014";
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::Database(e) if e.code().as_deref() == Some(QUERY_CANCELED) => {
+                RepositoryError::Timeout(e.message().to_string())
+            }
+            _ => RepositoryError::Database(error),
+        }
+    }
+}
+
+fn full_messages(errors: &[PropertyError]) -> String {
+    errors
+        .iter()
+        .map(PropertyError::full_message)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 /// Result type for repository operations
@@ -53,26 +104,65 @@ pub trait Repository<T, CreateDto, UpdateDto>: Send + Sync {
     async fn exists(&self, id: Id) -> RepositoryResult<bool>;
 }
 
-/// Repository context with database pool
+tokio::task_local! {
+    static CONTEXT: RepositoryContext;
+}
+
+/// Repository calls of one request, e.g. an API request, counted against
+/// its budget
+///
+/// Every connection acquired from a [`DbExecutor`](crate::DbExecutor)
+/// while the context is [in scope](Self::scope) counts as a call. A request
+/// exceeding its budget is not aborted; it is logged once and counted in
+/// the metrics, to catch N+1 queries.
+#[derive(Clone)]
 pub struct RepositoryContext {
-    pool: PgPool,
+    calls: Arc<AtomicUsize>,
+    budget: usize,
+    metrics: Option<Arc<DomainMetrics>>,
 }
 
 impl RepositoryContext {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    /// Context allowing up to `budget` calls
+    pub fn new(budget: usize) -> Self {
+        Self {
+            calls: Arc::new(AtomicUsize::new(0)),
+            budget,
+            metrics: None,
+        }
     }
 
-    pub fn pool(&self) -> &PgPool {
-        &self.pool
+    /// Count requests exceeding their budget in the metrics
+    pub fn with_metrics(mut self, metrics: Arc<DomainMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
     }
-}
 
-impl Clone for RepositoryContext {
-    fn clone(&self) -> Self {
-        Self {
-            pool: self.pool.clone(),
-        }
+    /// Run a future with this context as the current one
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, future).await
+    }
+
+    /// Calls counted so far
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Count a call in the current context, if any
+    pub(crate) fn record_call() {
+        let _ = CONTEXT.try_with(|context| {
+            let calls = context.calls.fetch_add(1, Ordering::Relaxed) + 1;
+            if calls == context.budget + 1 {
+                tracing::warn!(budget = context.budget, "Request exceeded its repository call budget");
+                if let Some(metrics) = &context.metrics {
+                    metrics.record_query_budget_exceeded();
+                }
+            }
+        });
     }
 }
 
@@ -118,6 +208,9 @@ pub struct PaginatedResult<T> {
     pub total: i64,
     pub limit: i64,
     pub offset: i64,
+    /// Whether `total` is an estimate rather than an exact count, see
+    /// [`CountStrategy`](crate::query_executor::CountStrategy)
+    pub total_is_estimate: bool,
 }
 
 impl<T> PaginatedResult<T> {
@@ -127,6 +220,7 @@ impl<T> PaginatedResult<T> {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         }
     }
 
@@ -159,6 +253,27 @@ impl<T> PaginatedResult<T> {
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_calls_over_budget_are_counted_once() {
+        let metrics = Arc::new(DomainMetrics::new());
+        let context = RepositoryContext::new(2).with_metrics(metrics.clone());
+
+        // Outside of a scope nothing is counted
+        RepositoryContext::record_call();
+
+        let calls = context
+            .clone()
+            .scope(async {
+                for _ in 0..4 {
+                    RepositoryContext::record_call();
+                }
+                CONTEXT.with(RepositoryContext::calls)
+            })
+            .await;
+        assert_eq!((calls, context.calls()), (4, 4));
+        assert_eq!(metrics.query_budget_exceeded.load(Ordering::Relaxed), 1);
+    }
+
     #[test]
     fn test_pagination_default() {
         let p = Pagination::default();
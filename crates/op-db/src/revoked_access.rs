@@ -0,0 +1,304 @@
+//! Revoked access repository
+//!
+//! Mirrors: app/services/members/cleanup_service.rb
+//!
+//! Users who can no longer view the work packages of a project keep their
+//! watchers and notifications on them until these are removed. A user views
+//! the work packages of a project as an administrator, through a project
+//! membership granting `view_work_packages`, or because the project is
+//! public; work packages shared with the user stay visible regardless.
+//! Removal runs in batches of work packages so large projects do not hold
+//! long locks.
+
+use op_core::traits::Id;
+use sqlx::PgPool;
+
+use crate::executor::DbExecutor;
+use crate::members::entity_type;
+use crate::repository::RepositoryResult;
+
+/// Condition holding when the user of `{user}` views the work package of
+/// `{work_package}` through a share
+const SHARED_WITH_USER: &str = "EXISTS (
+    SELECT 1 FROM members m
+    JOIN member_roles mr ON mr.member_id = m.id
+    JOIN role_permissions rp ON rp.role_id = mr.role_id
+    WHERE m.user_id = {user} AND m.entity_type = '{entity_type}' AND m.entity_id = {work_package}
+      AND rp.permission = 'view_work_packages'
+)";
+
+/// What removing one batch changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RevokedAccessBatch {
+    /// Last work package of the batch, `None` once all were visited
+    pub last_work_package_id: Option<Id>,
+    pub watchers_removed: u64,
+    pub notifications_removed: u64,
+}
+
+/// Repository removing what users keep of projects they cannot view
+pub struct RevokedAccessRepository {
+    db: DbExecutor,
+}
+
+impl RevokedAccessRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Users watching or notified about work packages of the project
+    pub async fn subscribed_users(&self, project_id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            r#"
+            SELECT w.user_id FROM watchers w
+            JOIN work_packages wp ON wp.id = w.watchable_id
+            WHERE w.watchable_type = 'WorkPackage' AND wp.project_id = $1
+            UNION
+            SELECT n.recipient_id FROM notifications n
+            JOIN work_packages wp ON wp.id = n.resource_id
+            WHERE n.resource_type = 'WorkPackage' AND wp.project_id = $1
+            ORDER BY 1
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Those of the users who cannot view the work packages of the project
+    pub async fn users_without_access(&self, project_id: Id, user_ids: &[Id]) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            r#"
+            SELECT u.id FROM users u
+            WHERE u.id = ANY($2) AND NOT u.admin
+              AND NOT EXISTS (SELECT 1 FROM projects p WHERE p.id = $1 AND p.public)
+              AND NOT EXISTS (
+                  SELECT 1 FROM members m
+                  JOIN member_roles mr ON mr.member_id = m.id
+                  JOIN role_permissions rp ON rp.role_id = mr.role_id
+                  WHERE m.user_id = u.id AND m.project_id = $1 AND m.entity_type IS NULL
+                    AND rp.permission = 'view_work_packages'
+              )
+            ORDER BY u.id
+            "#,
+        )
+        .bind(project_id)
+        .bind(user_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Remove the users' watchers and notifications on the next `limit` work
+    /// packages of the project after `after_id`, keeping those on work
+    /// packages shared with them. Removing twice removes nothing more.
+    pub async fn remove_batch(
+        &self,
+        project_id: Id,
+        user_ids: &[Id],
+        after_id: Id,
+        limit: i64,
+    ) -> RepositoryResult<RevokedAccessBatch> {
+        let mut conn = self.db.acquire().await?;
+
+        let work_package_ids = sqlx::query_scalar::<_, Id>(
+            "SELECT id FROM work_packages WHERE project_id = $1 AND id > $2 ORDER BY id LIMIT $3",
+        )
+        .bind(project_id)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let Some(&last_id) = work_package_ids.last() else {
+            return Ok(RevokedAccessBatch::default());
+        };
+
+        let watchers = sqlx::query(&format!(
+            "DELETE FROM watchers w \
+             WHERE w.watchable_type = 'WorkPackage' AND w.watchable_id = ANY($1) AND w.user_id = ANY($2) \
+               AND NOT {}",
+            shared_with_user("w.user_id", "w.watchable_id"),
+        ))
+        .bind(&work_package_ids)
+        .bind(user_ids)
+        .execute(&mut *conn)
+        .await?;
+
+        let notifications = sqlx::query(&format!(
+            "DELETE FROM notifications n \
+             WHERE n.resource_type = 'WorkPackage' AND n.resource_id = ANY($1) AND n.recipient_id = ANY($2) \
+               AND NOT {}",
+            shared_with_user("n.recipient_id", "n.resource_id"),
+        ))
+        .bind(&work_package_ids)
+        .bind(user_ids)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(RevokedAccessBatch {
+            last_work_package_id: Some(last_id),
+            watchers_removed: watchers.rows_affected(),
+            notifications_removed: notifications.rows_affected(),
+        })
+    }
+}
+
+fn shared_with_user(user_column: &str, work_package_column: &str) -> String {
+    SHARED_WITH_USER
+        .replace("{user}", user_column)
+        .replace("{entity_type}", entity_type::WORK_PACKAGE)
+        .replace("{work_package}", work_package_column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_with_user_condition() {
+        let sql = shared_with_user("w.user_id", "w.watchable_id");
+        assert!(sql.contains("m.user_id = w.user_id AND m.entity_type = 'WorkPackage' AND m.entity_id = w.watchable_id"));
+        assert!(!sql.contains('{'));
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::members::CreateMemberDto;
+    use crate::repository::Repository;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    async fn watch(db: &TestDb, user_id: Id, work_package_id: Id) {
+        sqlx::query("INSERT INTO watchers (watchable_type, watchable_id, user_id) VALUES ('WorkPackage', $1, $2)")
+            .bind(work_package_id)
+            .bind(user_id)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+    }
+
+    async fn notify(db: &TestDb, user_id: Id, project_id: Id, work_package_id: Id) {
+        sqlx::query(
+            "INSERT INTO notifications (recipient_id, project_id, resource_type, resource_id) \
+             VALUES ($1, $2, 'WorkPackage', $3)",
+        )
+        .bind(user_id)
+        .bind(project_id)
+        .bind(work_package_id)
+        .execute(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_removes_watchers_and_notifications_in_batches() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let member = db.insert_user(UserFixture::new("member")).await;
+        let former = db.insert_user(UserFixture::new("former")).await;
+        let admin = db.insert_user(UserFixture::new("site-admin").with_admin()).await;
+        let project = db.insert_project(ProjectFixture::new("revoked")).await;
+        let viewer = db.insert_role("Viewer", &["view_work_packages"]).await;
+        db.members()
+            .create(CreateMemberDto {
+                user_id: member,
+                project_id: Some(project),
+                role_ids: vec![viewer],
+                entity_type: None,
+                entity_id: None,
+            })
+            .await
+            .unwrap();
+
+        let mut work_packages = Vec::new();
+        for _ in 0..3 {
+            work_packages.push(db.insert_work_package(WorkPackageFixture::new(project, author)).await);
+        }
+        let shared = work_packages[2];
+        db.members()
+            .create(CreateMemberDto {
+                user_id: former,
+                project_id: Some(project),
+                role_ids: vec![viewer],
+                entity_type: Some(entity_type::WORK_PACKAGE.into()),
+                entity_id: Some(shared),
+            })
+            .await
+            .unwrap();
+        for &work_package in &work_packages {
+            for user in [member, former, admin] {
+                watch(&db, user, work_package).await;
+                notify(&db, user, project, work_package).await;
+            }
+        }
+
+        let repo = db.revoked_access();
+        assert_eq!(repo.subscribed_users(project).await.unwrap(), vec![member, former, admin]);
+        let without_access = repo.users_without_access(project, &[member, former, admin]).await.unwrap();
+        assert_eq!(without_access, vec![former]);
+
+        let first = repo.remove_batch(project, &without_access, 0, 2).await.unwrap();
+        assert_eq!(first.last_work_package_id, Some(work_packages[1]));
+        assert_eq!((first.watchers_removed, first.notifications_removed), (2, 2));
+
+        // The shared work package keeps its watcher and notification
+        let second = repo.remove_batch(project, &without_access, work_packages[1], 2).await.unwrap();
+        assert_eq!(second.last_work_package_id, Some(shared));
+        assert_eq!((second.watchers_removed, second.notifications_removed), (0, 0));
+        assert_eq!(repo.remove_batch(project, &without_access, shared, 2).await.unwrap(), RevokedAccessBatch::default());
+
+        // Running again removes nothing more
+        let again = repo.remove_batch(project, &without_access, 0, 10).await.unwrap();
+        assert_eq!((again.watchers_removed, again.notifications_removed), (0, 0));
+        assert_eq!(repo.subscribed_users(project).await.unwrap(), vec![member, former, admin]);
+    }
+
+    #[tokio::test]
+    async fn test_regained_access_restores_nothing() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let user = db.insert_user(UserFixture::new("returning")).await;
+        let project = db.insert_project(ProjectFixture::new("regained")).await;
+        let work_package = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        watch(&db, user, work_package).await;
+
+        let repo = db.revoked_access();
+        let removed = repo.remove_batch(project, &[user], 0, 10).await.unwrap();
+        assert_eq!(removed.watchers_removed, 1);
+
+        let viewer = db.insert_role("Viewer", &["view_work_packages"]).await;
+        db.members()
+            .create(CreateMemberDto {
+                user_id: user,
+                project_id: Some(project),
+                role_ids: vec![viewer],
+                entity_type: None,
+                entity_id: None,
+            })
+            .await
+            .unwrap();
+        assert!(repo.users_without_access(project, &[user]).await.unwrap().is_empty());
+        assert!(repo.subscribed_users(project).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_public_projects_are_visible_to_everyone() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("visitor")).await;
+        let project = db.insert_project(ProjectFixture::new("open").with_public()).await;
+
+        let repo = db.revoked_access();
+        assert!(repo.users_without_access(project, &[user]).await.unwrap().is_empty());
+    }
+}
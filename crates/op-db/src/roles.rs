@@ -170,6 +170,23 @@ impl RoleRepository {
         Ok(row)
     }
 
+    /// Find the builtin role of the given kind, e.g. a work package share role
+    pub async fn find_by_builtin(&self, builtin: i32) -> RepositoryResult<Option<RoleRow>> {
+        let row = sqlx::query_as::<_, RoleRow>(
+            r#"
+            SELECT id, name, position, builtin, type, created_at, updated_at
+            FROM roles
+            WHERE builtin = $1
+            LIMIT 1
+            "#,
+        )
+        .bind(builtin)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row)
+    }
+
     /// Find role by name
     pub async fn find_by_name(&self, name: &str) -> RepositoryResult<Option<RoleRow>> {
         let row = sqlx::query_as::<_, RoleRow>(
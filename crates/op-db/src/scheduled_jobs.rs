@@ -0,0 +1,106 @@
+//! Scheduled jobs repository
+//!
+//! Keeps when each recurring job was last considered by the scheduler. The
+//! marker only moves with a compare-and-set, so of several schedulers
+//! sharing the database only one enqueues a given run.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+
+/// Scheduled jobs repository
+pub struct ScheduledJobRepository {
+    db: DbExecutor,
+}
+
+impl ScheduledJobRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// When the job was last run, if ever
+    pub async fn last_run_at(&self, name: &str) -> RepositoryResult<Option<DateTime<Utc>>> {
+        let last_run_at = sqlx::query_scalar("SELECT last_run_at FROM scheduled_jobs WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(last_run_at)
+    }
+
+    /// Move the marker of the job from `from` to `to`, creating it when
+    /// `from` is `None`; false when the marker was not at `from`
+    pub async fn advance(
+        &self,
+        name: &str,
+        from: Option<DateTime<Utc>>,
+        to: DateTime<Utc>,
+    ) -> RepositoryResult<bool> {
+        let mut conn = self.db.acquire().await?;
+        let result = match from {
+            Some(from) => {
+                sqlx::query(
+                    r#"
+                    UPDATE scheduled_jobs SET last_run_at = $3, updated_at = NOW()
+                    WHERE name = $1 AND last_run_at = $2
+                    "#,
+                )
+                .bind(name)
+                .bind(from)
+                .bind(to)
+                .execute(&mut *conn)
+                .await?
+            }
+            None => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO scheduled_jobs (name, last_run_at, updated_at)
+                    VALUES ($1, $2, NOW())
+                    ON CONFLICT (name) DO NOTHING
+                    "#,
+                )
+                .bind(name)
+                .bind(to)
+                .execute(&mut *conn)
+                .await?
+            }
+        };
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::TestDb;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_marker_advances_once() {
+        let db = TestDb::connect().await;
+        let repo = db.scheduled_jobs();
+        let started = Utc::now();
+        let next = started + Duration::hours(1);
+
+        assert_eq!(repo.last_run_at("Test::Job").await.unwrap(), None);
+        assert!(repo.advance("Test::Job", None, started).await.unwrap());
+        assert!(!repo.advance("Test::Job", None, started).await.unwrap());
+
+        let last_run_at = repo.last_run_at("Test::Job").await.unwrap().unwrap();
+        assert!(repo.advance("Test::Job", Some(last_run_at), next).await.unwrap());
+        assert!(!repo.advance("Test::Job", Some(last_run_at), next).await.unwrap());
+        assert_eq!(
+            repo.last_run_at("Test::Job").await.unwrap().map(|t| t.timestamp_micros()),
+            Some(next.timestamp_micros())
+        );
+    }
+}
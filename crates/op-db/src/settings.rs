@@ -0,0 +1,74 @@
+//! Settings repository
+//!
+//! Mirrors: app/models/setting.rb
+//!
+//! Instance-wide settings stored as one text value per name, e.g. the
+//! maintenance mode serialized as JSON. Unset settings have no row.
+
+use sqlx::PgPool;
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+
+/// Settings repository
+pub struct SettingRepository {
+    db: DbExecutor,
+}
+
+impl SettingRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Value of the setting, `None` when it was never set
+    pub async fn get(&self, name: &str) -> RepositoryResult<Option<String>> {
+        let value = sqlx::query_scalar::<_, Option<String>>("SELECT value FROM settings WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(value.flatten())
+    }
+
+    /// Store the value of the setting, replacing the previous one
+    pub async fn set(&self, name: &str, value: &str) -> RepositoryResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO settings (name, value) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET value = EXCLUDED.value, updated_at = NOW()
+            "#,
+        )
+        .bind(name)
+        .bind(value)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use crate::testing::TestDb;
+
+    #[tokio::test]
+    async fn test_set_replaces_the_value() {
+        let db = TestDb::connect().await;
+        let repo = db.settings();
+        assert_eq!(repo.get("maintenance_mode").await.unwrap(), None);
+
+        repo.set("maintenance_mode", r#"{"enabled":true}"#).await.unwrap();
+        repo.set("maintenance_mode", r#"{"enabled":false}"#).await.unwrap();
+
+        assert_eq!(
+            repo.get("maintenance_mode").await.unwrap().as_deref(),
+            Some(r#"{"enabled":false}"#)
+        );
+    }
+}
@@ -8,7 +8,9 @@ use chrono::{DateTime, Utc};
 use op_core::traits::Id;
 use sqlx::{FromRow, PgPool};
 
+use crate::executor::{DbExecutor, DbTransaction};
 use crate::repository::{Repository, RepositoryError, RepositoryResult};
+use crate::work_packages::{count_referencing, reassign_referencing, ReferenceColumn};
 
 /// Status database entity
 #[derive(Debug, Clone, FromRow)]
@@ -68,12 +70,39 @@ pub struct UpdateStatusDto {
 
 /// Status repository implementation
 pub struct StatusRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl StatusRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Find statuses by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[Id]) -> RepositoryResult<Vec<StatusRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, StatusRow>(
+            r#"
+            SELECT id, name, is_closed, is_default, is_readonly, position,
+                   default_done_ratio, color_id, created_at, updated_at
+            FROM statuses
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
     }
 
     /// Find the default status for new work packages
@@ -87,7 +116,7 @@ impl StatusRepository {
             LIMIT 1
             "#,
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -104,7 +133,7 @@ impl StatusRepository {
             ORDER BY position ASC
             "#,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -121,7 +150,7 @@ impl StatusRepository {
             ORDER BY position ASC
             "#,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -138,7 +167,7 @@ impl StatusRepository {
             "#,
         )
         .bind(name)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -158,14 +187,14 @@ impl StatusRepository {
             .bind(name),
         };
 
-        let unique = query.fetch_one(&self.pool).await?;
+        let unique = query.fetch_one(&mut *self.db.acquire().await?).await?;
         Ok(unique)
     }
 
     /// Get max position for ordering
     async fn get_max_position(&self) -> RepositoryResult<i32> {
         let max_pos = sqlx::query_scalar::<_, Option<i32>>("SELECT MAX(position) FROM statuses")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?
             .unwrap_or(0);
 
@@ -173,10 +202,38 @@ impl StatusRepository {
     }
 
     /// Clear is_default flag on all other statuses
+    /// Number of work packages in the status
+    pub async fn work_package_count(&self, id: Id) -> RepositoryResult<i64> {
+        count_referencing(&mut *self.db.acquire().await?, ReferenceColumn::Status, id).await
+    }
+
+    /// Delete the status after moving its work packages to `reassign_to`,
+    /// all in one transaction. Each moved work package is journaled as a
+    /// system update by the user. Returns the number of work packages moved.
+    pub async fn delete_reassigning(&self, id: Id, reassign_to: Id, user_id: Id) -> RepositoryResult<u64> {
+        if reassign_to == id || !self.exists(reassign_to).await? {
+            return Err(RepositoryError::invalid("reassign_to", "invalid", "must be another existing status"));
+        }
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        let moved = reassign_referencing(&mut tx, ReferenceColumn::Status, id, reassign_to, user_id).await?;
+
+        let result = sqlx::query("DELETE FROM statuses WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Status with id {} not found", id)));
+        }
+
+        tx.commit().await?;
+        Ok(moved)
+    }
+
     async fn clear_default_except(&self, id: Id) -> RepositoryResult<()> {
         sqlx::query("UPDATE statuses SET is_default = false, updated_at = NOW() WHERE id != $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(())
@@ -195,7 +252,7 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -213,7 +270,7 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -221,7 +278,7 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
 
     async fn count(&self) -> RepositoryResult<i64> {
         let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM statuses")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(count)
@@ -230,16 +287,12 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
     async fn create(&self, dto: CreateStatusDto) -> RepositoryResult<StatusRow> {
         // Validate done ratio
         if dto.default_done_ratio < 0 || dto.default_done_ratio > 100 {
-            return Err(RepositoryError::Validation(
-                "default_done_ratio must be between 0 and 100".to_string(),
-            ));
+            return Err(RepositoryError::invalid("default_done_ratio", "inclusion", "must be between 0 and 100"));
         }
 
         // Validate: default status cannot be readonly
         if dto.is_default && dto.is_readonly {
-            return Err(RepositoryError::Validation(
-                "Default status cannot be read-only".to_string(),
-            ));
+            return Err(RepositoryError::invalid("is_readonly", "invalid", "can't be set on the default status"));
         }
 
         // Check name uniqueness
@@ -273,7 +326,7 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
         .bind(position)
         .bind(dto.default_done_ratio)
         .bind(dto.color_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         // If this is the new default, clear default on others
@@ -288,9 +341,7 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
         // Validate done ratio if provided
         if let Some(ratio) = dto.default_done_ratio {
             if ratio < 0 || ratio > 100 {
-                return Err(RepositoryError::Validation(
-                    "default_done_ratio must be between 0 and 100".to_string(),
-                ));
+                return Err(RepositoryError::invalid("default_done_ratio", "inclusion", "must be between 0 and 100"));
             }
         }
 
@@ -327,7 +378,7 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
         .bind(dto.default_done_ratio)
         .bind(dto.color_id)
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?
         .ok_or_else(|| RepositoryError::NotFound(format!("Status with id {} not found", id)))?;
 
@@ -340,23 +391,18 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
     }
 
     async fn delete(&self, id: Id) -> RepositoryResult<()> {
-        // Check if any work packages use this status
-        let has_work_packages = sqlx::query_scalar::<_, bool>(
-            "SELECT EXISTS(SELECT 1 FROM work_packages WHERE status_id = $1)",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
-
-        if has_work_packages {
-            return Err(RepositoryError::Conflict(
-                "Cannot delete status: work packages are using this status".to_string(),
-            ));
+        // Work packages using the status have to be moved first
+        let work_packages = self.work_package_count(id).await?;
+        if work_packages > 0 {
+            return Err(RepositoryError::Conflict(format!(
+                "Cannot delete status: {} work packages are using this status",
+                work_packages
+            )));
         }
 
         let result = sqlx::query("DELETE FROM statuses WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -373,7 +419,7 @@ impl Repository<StatusRow, CreateStatusDto, UpdateStatusDto> for StatusRepositor
         let exists =
             sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM statuses WHERE id = $1)")
                 .bind(id)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(exists)
@@ -421,3 +467,67 @@ mod tests {
         assert!(!status.is_closed());
     }
 }
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::journals::cause_type;
+    use crate::repository::Pagination;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    fn status(name: &str) -> CreateStatusDto {
+        CreateStatusDto {
+            name: name.to_string(),
+            is_closed: false,
+            is_default: false,
+            is_readonly: false,
+            position: None,
+            default_done_ratio: 0,
+            color_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_reassigns_work_packages() {
+        let db = TestDb::connect().await;
+        let admin = db.insert_user(UserFixture::new("status-admin").with_admin()).await;
+        let project = db.insert_project(ProjectFixture::new("status-project")).await;
+        let statuses = db.statuses();
+        let obsolete = statuses.create(status("Obsolete")).await.unwrap().id;
+        let target = statuses.create(status("Target")).await.unwrap().id;
+        let mut work_packages = Vec::new();
+        for _ in 0..2 {
+            let fixture = WorkPackageFixture::new(project, admin).with_status(obsolete);
+            work_packages.push(db.insert_work_package(fixture).await);
+        }
+
+        assert_eq!(statuses.work_package_count(obsolete).await.unwrap(), 2);
+        let err = statuses.delete(obsolete).await.unwrap_err();
+        assert!(matches!(err, RepositoryError::Conflict(ref msg) if msg.contains("2 work packages")));
+        assert!(matches!(
+            statuses.delete_reassigning(obsolete, obsolete, admin).await,
+            Err(RepositoryError::Validation(_))
+        ));
+
+        assert_eq!(statuses.delete_reassigning(obsolete, target, admin).await.unwrap(), 2);
+        assert!(!statuses.exists(obsolete).await.unwrap());
+        assert_eq!(statuses.work_package_count(target).await.unwrap(), 2);
+
+        // Moving again adds a journal version on top
+        let last = statuses.create(status("Last")).await.unwrap().id;
+        assert_eq!(statuses.delete_reassigning(target, last, admin).await.unwrap(), 2);
+
+        let all = Pagination { limit: 10, offset: 0 };
+        for id in work_packages {
+            let journals = db.journals().find_work_package_journals_with_data(id, all).await.unwrap();
+            let moves: Vec<_> = journals
+                .items
+                .iter()
+                .map(|j| (j.journal.version, j.data.as_ref().unwrap().status_id))
+                .collect();
+            assert_eq!(moves, vec![(1, target), (2, last)]);
+            assert!(journals.items.iter().all(|j| j.journal.user_id == admin
+                && j.journal.cause_type() == Some(cause_type::SYSTEM_UPDATE)));
+        }
+    }
+}
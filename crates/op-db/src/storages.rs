@@ -0,0 +1,382 @@
+//! Storages repository
+//!
+//! Mirrors: modules/storages/app/models/storages/storage.rb
+//!
+//! Storages are external file stores, e.g. a Nextcloud instance, whose
+//! files can be linked to work packages. The OAuth client OpenProject uses
+//! to access a storage is kept in `oauth_clients`.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use op_models::StorageType;
+use sqlx::{FromRow, PgPool};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::repository::{Repository, RepositoryError, RepositoryResult};
+
+/// `oauth_clients.integration_type` of storage clients
+const OAUTH_INTEGRATION_TYPE: &str = "Storages::Storage";
+
+const STORAGE_COLUMNS: &str = "s.id, s.provider_type, s.name, s.host, s.creator_id, \
+     oc.client_id AS oauth_client_id, oc.client_secret AS oauth_client_secret, s.created_at, s.updated_at";
+
+/// Storage row with its OAuth client
+#[derive(Debug, Clone, FromRow)]
+pub struct StorageRow {
+    pub id: Id,
+    /// See [`StorageType::provider_type`]
+    pub provider_type: String,
+    pub name: String,
+    /// Base URL, without a trailing slash
+    pub host: String,
+    pub creator_id: Id,
+    pub oauth_client_id: Option<String>,
+    pub oauth_client_secret: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StorageRow {
+    /// Type of the storage; `None` for providers op-rs does not know
+    pub fn storage_type(&self) -> Option<StorageType> {
+        StorageType::from_provider_type(&self.provider_type)
+    }
+}
+
+/// DTO for creating a storage
+#[derive(Debug, Clone)]
+pub struct CreateStorageDto {
+    pub provider_type: String,
+    pub name: String,
+    pub host: String,
+    pub creator_id: Id,
+    pub oauth_client_id: Option<String>,
+    pub oauth_client_secret: Option<String>,
+}
+
+/// DTO for updating a storage; the provider type cannot change
+#[derive(Debug, Clone, Default)]
+pub struct UpdateStorageDto {
+    pub name: Option<String>,
+    pub host: Option<String>,
+    pub oauth_client_id: Option<String>,
+    pub oauth_client_secret: Option<String>,
+}
+
+/// Storage repository
+pub struct StorageRepository {
+    db: DbExecutor,
+}
+
+impl StorageRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Find storages by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[Id]) -> RepositoryResult<Vec<StorageRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, StorageRow>(&format!(
+            "SELECT {} FROM storages s {} WHERE s.id = ANY($1)",
+            STORAGE_COLUMNS,
+            oauth_client_join()
+        ))
+        .bind(ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Check that the name and host are given and not used by another storage
+    async fn validate(&self, name: &str, host: &str, exclude_id: Option<Id>) -> RepositoryResult<()> {
+        if name.trim().is_empty() {
+            return Err(RepositoryError::invalid("name", "blank", "can't be blank"));
+        }
+        if host.is_empty() {
+            return Err(RepositoryError::invalid("host", "blank", "can't be blank"));
+        }
+
+        let (name_taken, host_taken) = sqlx::query_as::<_, (bool, bool)>(
+            r#"
+            SELECT COALESCE(BOOL_OR(LOWER(name) = LOWER($1)), FALSE),
+                   COALESCE(BOOL_OR(host = $2), FALSE)
+            FROM storages
+            WHERE id IS DISTINCT FROM $3
+            "#,
+        )
+        .bind(name.trim())
+        .bind(host)
+        .bind(exclude_id)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        if name_taken {
+            return Err(RepositoryError::invalid("name", "taken", "has already been taken"));
+        }
+        if host_taken {
+            return Err(RepositoryError::invalid("host", "taken", "has already been taken"));
+        }
+        Ok(())
+    }
+}
+
+fn oauth_client_join() -> String {
+    format!(
+        "LEFT JOIN oauth_clients oc ON oc.integration_type = '{}' AND oc.integration_id = s.id",
+        OAUTH_INTEGRATION_TYPE
+    )
+}
+
+/// Host without surrounding whitespace and trailing slashes, so that the
+/// same storage is not added twice
+pub fn normalize_host(host: &str) -> String {
+    host.trim().trim_end_matches('/').to_string()
+}
+
+/// Store the OAuth client of a storage, replacing a previous one
+async fn save_oauth_client(
+    tx: &mut DbTransaction,
+    storage_id: Id,
+    client_id: &str,
+    client_secret: Option<&str>,
+) -> RepositoryResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO oauth_clients (client_id, client_secret, integration_type, integration_id)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (integration_type, integration_id)
+        DO UPDATE SET client_id = EXCLUDED.client_id,
+                      client_secret = COALESCE(EXCLUDED.client_secret, oauth_clients.client_secret),
+                      updated_at = NOW()
+        "#,
+    )
+    .bind(client_id)
+    .bind(client_secret)
+    .bind(OAUTH_INTEGRATION_TYPE)
+    .bind(storage_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl Repository<StorageRow, CreateStorageDto, UpdateStorageDto> for StorageRepository {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<StorageRow>> {
+        let row = sqlx::query_as::<_, StorageRow>(&format!(
+            "SELECT {} FROM storages s {} WHERE s.id = $1",
+            STORAGE_COLUMNS,
+            oauth_client_join()
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<StorageRow>> {
+        let rows = sqlx::query_as::<_, StorageRow>(&format!(
+            "SELECT {} FROM storages s {} ORDER BY LOWER(s.name), s.id LIMIT $1 OFFSET $2",
+            STORAGE_COLUMNS,
+            oauth_client_join()
+        ))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM storages")
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn create(&self, dto: CreateStorageDto) -> RepositoryResult<StorageRow> {
+        if StorageType::from_provider_type(&dto.provider_type).is_none() {
+            return Err(RepositoryError::invalid("type", "inclusion", "is not set to one of the allowed values"));
+        }
+        let host = normalize_host(&dto.host);
+        self.validate(&dto.name, &host, None).await?;
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        let id = sqlx::query_scalar::<_, Id>(
+            r#"
+            INSERT INTO storages (provider_type, name, host, creator_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            RETURNING id
+            "#,
+        )
+        .bind(&dto.provider_type)
+        .bind(dto.name.trim())
+        .bind(&host)
+        .bind(dto.creator_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if let Some(client_id) = dto.oauth_client_id.as_deref() {
+            save_oauth_client(&mut tx, id, client_id, dto.oauth_client_secret.as_deref()).await?;
+        }
+        tx.commit().await?;
+
+        self.find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("Storage {} not found", id)))
+    }
+
+    async fn update(&self, id: Id, dto: UpdateStorageDto) -> RepositoryResult<StorageRow> {
+        let existing = self
+            .find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("Storage {} not found", id)))?;
+
+        let name = dto.name.unwrap_or(existing.name);
+        let host = dto.host.as_deref().map(normalize_host).unwrap_or(existing.host);
+        self.validate(&name, &host, Some(id)).await?;
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        sqlx::query("UPDATE storages SET name = $2, host = $3, updated_at = NOW() WHERE id = $1")
+            .bind(id)
+            .bind(name.trim())
+            .bind(&host)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(client_id) = dto.oauth_client_id.as_deref() {
+            save_oauth_client(&mut tx, id, client_id, dto.oauth_client_secret.as_deref()).await?;
+        }
+        tx.commit().await?;
+
+        self.find_by_id(id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound(format!("Storage {} not found", id)))
+    }
+
+    /// Delete a storage with its OAuth client; its file links are deleted
+    /// by the database
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        sqlx::query("DELETE FROM oauth_clients WHERE integration_type = $1 AND integration_id = $2")
+            .bind(OAUTH_INTEGRATION_TYPE)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM storages WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Storage {} not found", id)));
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM storages WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(exists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host(" https://cloud.example.com/ "), "https://cloud.example.com");
+        assert_eq!(normalize_host("https://cloud.example.com/nextcloud//"), "https://cloud.example.com/nextcloud");
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{TestDb, UserFixture};
+
+    fn nextcloud(name: &str, host: &str, creator_id: Id) -> CreateStorageDto {
+        CreateStorageDto {
+            provider_type: StorageType::Nextcloud.provider_type().to_string(),
+            name: name.to_string(),
+            host: host.to_string(),
+            creator_id,
+            oauth_client_id: None,
+            oauth_client_secret: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_storages_keep_their_oauth_client() {
+        let db = TestDb::connect().await;
+        let admin = db.insert_user(UserFixture::new("storage-admin").with_admin()).await;
+        let storages = db.storages();
+
+        let mut dto = nextcloud("Cloud", "https://cloud.example.com/", admin);
+        dto.oauth_client_id = Some("client".into());
+        dto.oauth_client_secret = Some("secret".into());
+        let storage = storages.create(dto).await.unwrap();
+        assert_eq!(storage.host, "https://cloud.example.com");
+        assert_eq!(storage.oauth_client_id.as_deref(), Some("client"));
+
+        // A new client id keeps the secret unless it is replaced too
+        let updated = storages
+            .update(storage.id, UpdateStorageDto {
+                name: Some("Team cloud".into()),
+                oauth_client_id: Some("rotated".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(updated.name, "Team cloud");
+        assert_eq!(updated.oauth_client_id.as_deref(), Some("rotated"));
+        assert_eq!(updated.oauth_client_secret.as_deref(), Some("secret"));
+
+        storages.delete(storage.id).await.unwrap();
+        assert!(!storages.exists(storage.id).await.unwrap());
+        let clients: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM oauth_clients WHERE integration_id = $1")
+            .bind(storage.id)
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(clients, 0);
+    }
+
+    #[tokio::test]
+    async fn test_storage_names_and_hosts_are_unique() {
+        let db = TestDb::connect().await;
+        let admin = db.insert_user(UserFixture::new("unique-storage-admin").with_admin()).await;
+        let storages = db.storages();
+        storages.create(nextcloud("Unique cloud", "https://unique.example.com", admin)).await.unwrap();
+
+        let same_name = storages.create(nextcloud("unique cloud", "https://other.example.com", admin)).await;
+        assert!(matches!(same_name, Err(RepositoryError::Validation(errors)) if errors[0].property == "name"));
+
+        let same_host = storages.create(nextcloud("Other", "https://unique.example.com/", admin)).await;
+        assert!(matches!(same_host, Err(RepositoryError::Validation(errors)) if errors[0].property == "host"));
+
+        let mut unknown = nextcloud("Drive", "https://drive.example.com", admin);
+        unknown.provider_type = "Storages::Dropbox".into();
+        assert!(matches!(storages.create(unknown).await, Err(RepositoryError::Validation(_))));
+    }
+}
@@ -0,0 +1,515 @@
+//! Database-backed test harness
+//!
+//! Enabled by the `pg-tests` feature. [`TestDb`] connects to the database
+//! at `DATABASE_URL`, applies the embedded schema and runs every test in a
+//! transaction that is rolled back when the harness is dropped, so tests
+//! neither see nor leave behind each other's rows.
+//!
+//! A statement failing in the database aborts the test transaction; tests
+//! expecting such an error assert it last.
+//!
+//! ```ignore
+//! let db = TestDb::connect().await;
+//! let author = db.insert_user(UserFixture::new("author")).await;
+//! let project = db.insert_project(ProjectFixture::new("demo")).await;
+//! let id = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+//! assert!(db.work_packages().exists(id).await.unwrap());
+//! ```
+
+use op_core::traits::Id;
+use sqlx::postgres::PgPoolOptions;
+use tokio::sync::OnceCell;
+
+use crate::custom_fields::CustomFieldRepository;
+use crate::executor::DbExecutor;
+use crate::migrations::MIGRATOR;
+use crate::file_links::FileLinkRepository;
+use crate::documents::DocumentRepository;
+use crate::forums::{ForumRepository, MessageRepository};
+use crate::journals::JournalRepository;
+use crate::groups::GroupRepository;
+use crate::work_package_summaries::SummaryRepository;
+use crate::work_package_views::WorkPackageViewRepository;
+use crate::attachment_uploads::AttachmentUploadRepository;
+use crate::projects::ProjectRepository;
+use crate::members::MemberRepository;
+use crate::query_subscriptions::QuerySubscriptionRepository;
+use crate::priorities::PriorityRepository;
+use crate::idempotency::IdempotencyKeyRepository;
+use crate::api_keys::ApiKeyRepository;
+use crate::notifications::NotificationRepository;
+use crate::revoked_access::RevokedAccessRepository;
+use crate::scheduled_jobs::ScheduledJobRepository;
+use crate::settings::SettingRepository;
+use crate::statuses::StatusRepository;
+use crate::storages::StorageRepository;
+use crate::time_entries::TimeEntryRepository;
+use crate::costs::CostRepository;
+use crate::types::TypeRepository;
+use crate::users::UserRepository;
+use crate::work_packages::WorkPackageRepository;
+
+/// Test database whose changes are rolled back when dropped
+pub struct TestDb {
+    executor: DbExecutor,
+    type_id: OnceCell<Id>,
+    status_id: OnceCell<Id>,
+}
+
+impl TestDb {
+    /// Connect to `DATABASE_URL`, migrate and begin the test transaction
+    pub async fn connect() -> Self {
+        let url = std::env::var("DATABASE_URL").expect("pg-tests need DATABASE_URL to be set");
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await
+            .expect("connect to the test database");
+        MIGRATOR.run(&pool).await.expect("migrate the test database");
+
+        Self {
+            executor: DbExecutor::begin(&pool).await.expect("begin the test transaction"),
+            type_id: OnceCell::new(),
+            status_id: OnceCell::new(),
+        }
+    }
+
+    /// Executor bound to the test transaction
+    pub fn executor(&self) -> DbExecutor {
+        self.executor.clone()
+    }
+
+    pub fn work_packages(&self) -> WorkPackageRepository {
+        WorkPackageRepository::with_executor(self.executor())
+    }
+
+    pub fn journals(&self) -> JournalRepository {
+        JournalRepository::with_executor(self.executor())
+    }
+
+    pub fn projects(&self) -> ProjectRepository {
+        ProjectRepository::with_executor(self.executor())
+    }
+
+    pub fn custom_fields(&self) -> CustomFieldRepository {
+        CustomFieldRepository::with_executor(self.executor())
+    }
+
+    pub fn summaries(&self) -> SummaryRepository {
+        SummaryRepository::with_executor(self.executor())
+    }
+
+    pub fn work_package_views(&self) -> WorkPackageViewRepository {
+        WorkPackageViewRepository::with_executor(self.executor())
+    }
+
+    pub fn attachment_uploads(&self) -> AttachmentUploadRepository {
+        AttachmentUploadRepository::with_executor(self.executor())
+    }
+
+    pub fn groups(&self) -> GroupRepository {
+        GroupRepository::with_executor(self.executor())
+    }
+
+    pub fn members(&self) -> MemberRepository {
+        MemberRepository::with_executor(self.executor())
+    }
+
+    pub fn query_subscriptions(&self) -> QuerySubscriptionRepository {
+        QuerySubscriptionRepository::with_executor(self.executor())
+    }
+
+    pub fn scheduled_jobs(&self) -> ScheduledJobRepository {
+        ScheduledJobRepository::with_executor(self.executor())
+    }
+
+    pub fn idempotency_keys(&self) -> IdempotencyKeyRepository {
+        IdempotencyKeyRepository::with_executor(self.executor())
+    }
+
+    pub fn api_keys(&self) -> ApiKeyRepository {
+        ApiKeyRepository::with_executor(self.executor())
+    }
+
+    pub fn time_entries(&self) -> TimeEntryRepository {
+        TimeEntryRepository::with_executor(self.executor())
+    }
+
+    pub fn statuses(&self) -> StatusRepository {
+        StatusRepository::with_executor(self.executor())
+    }
+
+    pub fn types(&self) -> TypeRepository {
+        TypeRepository::with_executor(self.executor())
+    }
+
+    pub fn priorities(&self) -> PriorityRepository {
+        PriorityRepository::with_executor(self.executor())
+    }
+
+    pub fn users(&self) -> UserRepository {
+        UserRepository::with_executor(self.executor())
+    }
+
+    pub fn storages(&self) -> StorageRepository {
+        StorageRepository::with_executor(self.executor())
+    }
+
+    pub fn file_links(&self) -> FileLinkRepository {
+        FileLinkRepository::with_executor(self.executor())
+    }
+
+    pub fn revoked_access(&self) -> RevokedAccessRepository {
+        RevokedAccessRepository::with_executor(self.executor())
+    }
+
+    pub fn costs(&self) -> CostRepository {
+        CostRepository::with_executor(self.executor())
+    }
+
+    pub fn forums(&self) -> ForumRepository {
+        ForumRepository::with_executor(self.executor())
+    }
+
+    pub fn messages(&self) -> MessageRepository {
+        MessageRepository::with_executor(self.executor())
+    }
+
+    pub fn documents(&self) -> DocumentRepository {
+        DocumentRepository::with_executor(self.executor())
+    }
+
+    pub fn settings(&self) -> SettingRepository {
+        SettingRepository::with_executor(self.executor())
+    }
+
+    pub fn notifications(&self) -> NotificationRepository {
+        NotificationRepository::with_executor(self.executor())
+    }
+
+    /// Skip foreign key checks for the rest of the test, like a database
+    /// without constraints; needs a superuser
+    pub async fn disable_foreign_keys(&self) {
+        sqlx::query("SET LOCAL session_replication_role = replica")
+            .execute(&mut *self.connection().await)
+            .await
+            .expect("disable foreign keys");
+    }
+
+    /// Insert a user, group or placeholder user
+    pub async fn insert_user(&self, user: UserFixture) -> Id {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO users (type, login, firstname, lastname, mail, admin, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+        )
+        .bind(user.principal_type)
+        .bind(&user.login)
+        .bind(&user.firstname)
+        .bind(&user.lastname)
+        .bind(&user.mail)
+        .bind(user.admin)
+        .bind(user.status)
+        .fetch_one(&mut *self.connection().await)
+        .await
+        .expect("insert user")
+    }
+
+    /// Add a user to a group
+    pub async fn insert_group_member(&self, group_id: Id, user_id: Id) -> Id {
+        sqlx::query_scalar("INSERT INTO group_users (group_id, user_id) VALUES ($1, $2) RETURNING id")
+            .bind(group_id)
+            .bind(user_id)
+            .fetch_one(&mut *self.connection().await)
+            .await
+            .expect("insert group member")
+    }
+
+    /// Insert a project
+    pub async fn insert_project(&self, project: ProjectFixture) -> Id {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO projects (name, identifier, public, parent_id, active, templated)
+            VALUES ($1, $2, $3, $4, TRUE, FALSE)
+            RETURNING id
+            "#,
+        )
+        .bind(&project.name)
+        .bind(&project.identifier)
+        .bind(project.public)
+        .bind(project.parent_id)
+        .fetch_one(&mut *self.connection().await)
+        .await
+        .expect("insert project")
+    }
+
+    /// Insert a work package, of a default type and status unless given
+    pub async fn insert_work_package(&self, work_package: WorkPackageFixture) -> Id {
+        let type_id = match work_package.type_id {
+            Some(id) => id,
+            None => self.default_type_id().await,
+        };
+        let status_id = match work_package.status_id {
+            Some(id) => id,
+            None => self.default_status_id().await,
+        };
+
+        let mut conn = self.connection().await;
+        let id = sqlx::query_scalar(
+            r#"
+            INSERT INTO work_packages (subject, project_id, type_id, status_id, author_id,
+                                       assigned_to_id, parent_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+        )
+        .bind(&work_package.subject)
+        .bind(work_package.project_id)
+        .bind(type_id)
+        .bind(status_id)
+        .bind(work_package.author_id)
+        .bind(work_package.assigned_to_id)
+        .bind(work_package.parent_id)
+        .fetch_one(&mut *conn)
+        .await
+        .expect("insert work package");
+        crate::work_package_summaries::count_work_packages(&mut conn, &[id], 1)
+            .await
+            .expect("count work package");
+        id
+    }
+
+    /// Insert a role granting the permissions
+    pub async fn insert_role(&self, name: &str, permissions: &[&str]) -> Id {
+        let mut conn = self.connection().await;
+        let role_id = sqlx::query_scalar("INSERT INTO roles (name) VALUES ($1) RETURNING id")
+            .bind(name)
+            .fetch_one(&mut *conn)
+            .await
+            .expect("insert role");
+
+        for permission in permissions {
+            sqlx::query("INSERT INTO role_permissions (role_id, permission) VALUES ($1, $2)")
+                .bind(role_id)
+                .bind(permission)
+                .execute(&mut *conn)
+                .await
+                .expect("insert role permission");
+        }
+        role_id
+    }
+
+    /// Insert a saved query of the user without filters
+    pub async fn insert_query(&self, user_id: Id, project_id: Option<Id>, name: &str) -> Id {
+        sqlx::query_scalar("INSERT INTO queries (user_id, project_id, name) VALUES ($1, $2, $3) RETURNING id")
+            .bind(user_id)
+            .bind(project_id)
+            .bind(name)
+            .fetch_one(&mut *self.connection().await)
+            .await
+            .expect("insert query")
+    }
+
+    /// Insert a work package custom field, in all projects when `is_for_all`
+    pub async fn insert_custom_field(&self, name: &str, is_for_all: bool) -> Id {
+        sqlx::query_scalar(
+            "INSERT INTO custom_fields (type, name, is_for_all) VALUES ('WorkPackageCustomField', $1, $2) RETURNING id",
+        )
+        .bind(name)
+        .bind(is_for_all)
+        .fetch_one(&mut *self.connection().await)
+        .await
+        .expect("insert custom field")
+    }
+
+    /// Insert a project custom field of the format, not activated in any
+    /// project
+    pub async fn insert_project_custom_field(&self, name: &str, field_format: &str, is_required: bool) -> Id {
+        sqlx::query_scalar(
+            "INSERT INTO custom_fields (type, name, field_format, is_required) \
+             VALUES ('ProjectCustomField', $1, $2, $3) RETURNING id",
+        )
+        .bind(name)
+        .bind(field_format)
+        .bind(is_required)
+        .fetch_one(&mut *self.connection().await)
+        .await
+        .expect("insert project custom field")
+    }
+
+    /// Activate a custom field in a project
+    pub async fn activate_custom_field(&self, custom_field_id: Id, project_id: Id) {
+        sqlx::query("INSERT INTO custom_fields_projects (custom_field_id, project_id) VALUES ($1, $2)")
+            .bind(custom_field_id)
+            .bind(project_id)
+            .execute(&mut *self.connection().await)
+            .await
+            .expect("activate custom field");
+    }
+
+    async fn default_type_id(&self) -> Id {
+        *self
+            .type_id
+            .get_or_init(|| async {
+                sqlx::query_scalar("INSERT INTO types (name, is_default) VALUES ('Task', TRUE) RETURNING id")
+                    .fetch_one(&mut *self.connection().await)
+                    .await
+                    .expect("insert type")
+            })
+            .await
+    }
+
+    async fn default_status_id(&self) -> Id {
+        *self
+            .status_id
+            .get_or_init(|| async {
+                sqlx::query_scalar("INSERT INTO statuses (name, is_default) VALUES ('New', TRUE) RETURNING id")
+                    .fetch_one(&mut *self.connection().await)
+                    .await
+                    .expect("insert status")
+            })
+            .await
+    }
+
+    async fn connection(&self) -> crate::executor::DbConnection {
+        self.executor.acquire().await.expect("acquire the test connection")
+    }
+}
+
+/// User to insert; active and not an administrator by default
+#[derive(Debug, Clone)]
+pub struct UserFixture {
+    pub principal_type: &'static str,
+    pub login: String,
+    pub firstname: String,
+    pub lastname: String,
+    pub mail: String,
+    pub admin: bool,
+    pub status: i32,
+}
+
+impl UserFixture {
+    pub fn new(login: impl Into<String>) -> Self {
+        let login = login.into();
+        Self {
+            principal_type: crate::users::principal_type::USER,
+            mail: format!("{}@example.com", login),
+            firstname: login.clone(),
+            lastname: "Tester".into(),
+            login,
+            admin: false,
+            status: crate::users::status::ACTIVE,
+        }
+    }
+
+    /// Group named `name`; groups keep their name in the last name
+    pub fn group(name: impl Into<String>) -> Self {
+        Self::principal(crate::users::principal_type::GROUP, name.into())
+    }
+
+    /// Placeholder user named `name`
+    pub fn placeholder(name: impl Into<String>) -> Self {
+        Self::principal(crate::users::principal_type::PLACEHOLDER_USER, name.into())
+    }
+
+    fn principal(principal_type: &'static str, name: String) -> Self {
+        Self {
+            principal_type,
+            login: String::new(),
+            firstname: String::new(),
+            lastname: name,
+            mail: String::new(),
+            admin: false,
+            status: crate::users::status::ACTIVE,
+        }
+    }
+
+    pub fn with_admin(mut self) -> Self {
+        self.admin = true;
+        self
+    }
+
+    pub fn with_status(mut self, status: i32) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+/// Project to insert; private and top-level by default
+#[derive(Debug, Clone)]
+pub struct ProjectFixture {
+    pub identifier: String,
+    pub name: String,
+    pub public: bool,
+    pub parent_id: Option<Id>,
+}
+
+impl ProjectFixture {
+    pub fn new(identifier: impl Into<String>) -> Self {
+        let identifier = identifier.into();
+        Self {
+            name: identifier.clone(),
+            identifier,
+            public: false,
+            parent_id: None,
+        }
+    }
+
+    pub fn with_public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    pub fn with_parent(mut self, parent_id: Id) -> Self {
+        self.parent_id = Some(parent_id);
+        self
+    }
+}
+
+/// Work package to insert
+#[derive(Debug, Clone)]
+pub struct WorkPackageFixture {
+    pub subject: String,
+    pub project_id: Id,
+    pub author_id: Id,
+    pub type_id: Option<Id>,
+    pub status_id: Option<Id>,
+    pub assigned_to_id: Option<Id>,
+    pub parent_id: Option<Id>,
+}
+
+impl WorkPackageFixture {
+    pub fn new(project_id: Id, author_id: Id) -> Self {
+        Self {
+            subject: "Work package".into(),
+            project_id,
+            author_id,
+            type_id: None,
+            status_id: None,
+            assigned_to_id: None,
+            parent_id: None,
+        }
+    }
+
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = subject.into();
+        self
+    }
+
+    pub fn with_status(mut self, status_id: Id) -> Self {
+        self.status_id = Some(status_id);
+        self
+    }
+
+    pub fn with_assignee(mut self, user_id: Id) -> Self {
+        self.assigned_to_id = Some(user_id);
+        self
+    }
+
+    pub fn with_parent(mut self, parent_id: Id) -> Self {
+        self.parent_id = Some(parent_id);
+        self
+    }
+}
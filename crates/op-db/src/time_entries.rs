@@ -7,9 +7,17 @@
 use async_trait::async_trait;
 use chrono::{Datelike, DateTime, NaiveDate, Utc};
 use op_core::traits::Id;
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder};
 
+use crate::executor::DbExecutor;
 use crate::repository::{Pagination, PaginatedResult, Repository, RepositoryError, RepositoryResult};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// Time logged on a work package that is gone stays logged on the project
+pub const TIME_ENTRY_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::new("time_entries", "work_package_id", "work_packages", OrphanAction::Nullify),
+];
 
 /// Time entry database entity
 #[derive(Debug, Clone, FromRow)]
@@ -56,14 +64,216 @@ pub struct UpdateTimeEntryDto {
     pub spent_on: Option<NaiveDate>,
 }
 
+/// What time entries are grouped by in a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeEntryGroupBy {
+    User,
+    Project,
+    WorkPackage,
+    Activity,
+    /// ISO week, starting on Monday
+    Week,
+    /// Calendar month
+    Month,
+}
+
+impl TimeEntryGroupBy {
+    pub const ALL: [Self; 6] = [
+        Self::User,
+        Self::Project,
+        Self::WorkPackage,
+        Self::Activity,
+        Self::Week,
+        Self::Month,
+    ];
+
+    /// Parse the API name of a grouping (`user`, `workPackage`, `month`, ...)
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|group| group.name() == name)
+    }
+
+    /// API name of the grouping
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Project => "project",
+            Self::WorkPackage => "workPackage",
+            Self::Activity => "activity",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    /// Column of the group key in [`TimeEntryAggregateRow`]
+    fn column(&self) -> &'static str {
+        match self {
+            Self::User => "user_id",
+            Self::Project => "project_id",
+            Self::WorkPackage => "work_package_id",
+            Self::Activity => "activity_id",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    /// Expression computing the group key of a time entry
+    fn sql(&self) -> &'static str {
+        match self {
+            Self::User => "te.user_id",
+            Self::Project => "te.project_id",
+            Self::WorkPackage => "te.work_package_id",
+            Self::Activity => "te.activity_id",
+            Self::Week => "date_trunc('week', te.spent_on)::date",
+            Self::Month => "date_trunc('month', te.spent_on)::date",
+        }
+    }
+
+    /// Key of the rows when not grouped by this
+    fn null_sql(&self) -> &'static str {
+        match self {
+            Self::Week | Self::Month => "NULL::date",
+            _ => "NULL::bigint",
+        }
+    }
+}
+
+/// Time entries to sum up, grouped by any combination of attributes and
+/// periods
+#[derive(Debug, Clone)]
+pub struct TimeEntryReport {
+    /// First day of the period, inclusive
+    pub from: NaiveDate,
+    /// Last day of the period, inclusive
+    pub to: NaiveDate,
+    pub group_by: Vec<TimeEntryGroupBy>,
+    /// Only entries of the project and its subprojects
+    pub project_id: Option<Id>,
+    /// Only entries of these users
+    pub user_ids: Option<Vec<Id>>,
+    /// Only entries the user may view: all entries of projects granting
+    /// `view_time_entries` and their own ones of projects granting
+    /// `view_own_time_entries`. Without it, as for administrators, every
+    /// entry is summed up.
+    pub visible_to: Option<Id>,
+}
+
+impl TimeEntryReport {
+    pub fn new(from: NaiveDate, to: NaiveDate, group_by: Vec<TimeEntryGroupBy>) -> Self {
+        Self {
+            from,
+            to,
+            group_by,
+            project_id: None,
+            user_ids: None,
+            visible_to: None,
+        }
+    }
+
+    /// Sum of hours per group of the matching entries
+    fn push_grouped<'a>(&'a self, sql: &mut QueryBuilder<'a, Postgres>) {
+        sql.push("SELECT ");
+        for group in TimeEntryGroupBy::ALL {
+            let key = if self.group_by.contains(&group) { group.sql() } else { group.null_sql() };
+            sql.push(format_args!("{} AS {}, ", key, group.column()));
+        }
+        sql.push("SUM(te.hours) AS hours FROM time_entries te WHERE te.spent_on >= ");
+        sql.push_bind(self.from);
+        sql.push(" AND te.spent_on <= ");
+        sql.push_bind(self.to);
+
+        if let Some(project_id) = self.project_id {
+            sql.push(
+                " AND te.project_id IN (WITH RECURSIVE subtree AS (SELECT id FROM projects WHERE id = ",
+            );
+            sql.push_bind(project_id);
+            sql.push(
+                " UNION ALL SELECT p.id FROM projects p JOIN subtree s ON p.parent_id = s.id) \
+                 SELECT id FROM subtree)",
+            );
+        }
+        if let Some(user_ids) = &self.user_ids {
+            sql.push(" AND te.user_id = ANY(");
+            sql.push_bind(user_ids.as_slice());
+            sql.push(")");
+        }
+        if let Some(user_id) = self.visible_to {
+            let granting = "SELECT m.project_id FROM members m \
+                            JOIN member_roles mr ON mr.member_id = m.id \
+                            JOIN role_permissions rp ON rp.role_id = mr.role_id \
+                            WHERE m.entity_type IS NULL AND m.user_id = ";
+            sql.push(" AND (te.project_id IN (");
+            sql.push(granting).push_bind(user_id);
+            sql.push(" AND rp.permission = 'view_time_entries') OR (te.user_id = ");
+            sql.push_bind(user_id);
+            sql.push(" AND te.project_id IN (");
+            sql.push(granting).push_bind(user_id);
+            sql.push(" AND rp.permission = 'view_own_time_entries')))");
+        }
+
+        sql.push(format_args!(" GROUP BY {}", self.group_by_sql()));
+    }
+
+    fn group_by_sql(&self) -> String {
+        self.group_by.iter().map(|group| group.sql()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Query of one page of groups
+    fn rows_query(&self, pagination: Pagination) -> QueryBuilder<'_, Postgres> {
+        let mut sql = QueryBuilder::new("");
+        self.push_grouped(&mut sql);
+        sql.push(format_args!(" ORDER BY {} LIMIT ", self.group_by_sql()));
+        sql.push_bind(pagination.limit);
+        sql.push(" OFFSET ");
+        sql.push_bind(pagination.offset);
+        sql
+    }
+
+    /// Query of the number of groups and the grand total of hours
+    fn totals_query(&self) -> QueryBuilder<'_, Postgres> {
+        let mut sql = QueryBuilder::new("SELECT COUNT(*), COALESCE(SUM(hours), 0) FROM (");
+        self.push_grouped(&mut sql);
+        sql.push(") report");
+        sql
+    }
+}
+
+/// Hours summed up for one group of a report. Keys the report is not
+/// grouped by are `None`, as is the work package of entries logged on the
+/// project.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct TimeEntryAggregateRow {
+    pub user_id: Option<i64>,
+    pub project_id: Option<i64>,
+    pub work_package_id: Option<i64>,
+    pub activity_id: Option<i64>,
+    /// Monday of the week
+    pub week: Option<NaiveDate>,
+    /// First day of the month
+    pub month: Option<NaiveDate>,
+    pub hours: f64,
+}
+
+/// A page of report groups with the hours of all groups
+#[derive(Debug, Clone)]
+pub struct TimeEntryAggregate {
+    pub groups: PaginatedResult<TimeEntryAggregateRow>,
+    pub total_hours: f64,
+}
+
 /// Time entry repository implementation
 pub struct TimeEntryRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl TimeEntryRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
     }
 
     /// Find time entries by project ID
@@ -86,14 +296,14 @@ impl TimeEntryRepository {
         .bind(project_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
             "SELECT COUNT(*) FROM time_entries WHERE project_id = $1",
         )
         .bind(project_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -119,14 +329,14 @@ impl TimeEntryRepository {
         .bind(user_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
             "SELECT COUNT(*) FROM time_entries WHERE user_id = $1",
         )
         .bind(user_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -152,14 +362,14 @@ impl TimeEntryRepository {
         .bind(work_package_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
             "SELECT COUNT(*) FROM time_entries WHERE work_package_id = $1",
         )
         .bind(work_package_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -190,7 +400,7 @@ impl TimeEntryRepository {
             .bind(uid)
             .bind(pagination.limit)
             .bind(pagination.offset)
-            .fetch_all(&self.pool)
+            .fetch_all(&mut *self.db.acquire().await?)
             .await?;
 
             let total = sqlx::query_scalar::<_, i64>(
@@ -199,7 +409,7 @@ impl TimeEntryRepository {
             .bind(from)
             .bind(to)
             .bind(uid)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
             (items, total)
@@ -219,7 +429,7 @@ impl TimeEntryRepository {
             .bind(to)
             .bind(pagination.limit)
             .bind(pagination.offset)
-            .fetch_all(&self.pool)
+            .fetch_all(&mut *self.db.acquire().await?)
             .await?;
 
             let total = sqlx::query_scalar::<_, i64>(
@@ -227,7 +437,7 @@ impl TimeEntryRepository {
             )
             .bind(from)
             .bind(to)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
             (items, total)
@@ -242,7 +452,7 @@ impl TimeEntryRepository {
             "SELECT SUM(hours) FROM time_entries WHERE work_package_id = $1",
         )
         .bind(work_package_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(total.unwrap_or(0.0))
@@ -254,7 +464,7 @@ impl TimeEntryRepository {
             "SELECT SUM(hours) FROM time_entries WHERE project_id = $1",
         )
         .bind(project_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(total.unwrap_or(0.0))
@@ -273,11 +483,39 @@ impl TimeEntryRepository {
         .bind(user_id)
         .bind(from)
         .bind(to)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(total.unwrap_or(0.0))
     }
+
+    /// Sum up the hours of the report's entries per group
+    pub async fn aggregate(
+        &self,
+        report: &TimeEntryReport,
+        pagination: Pagination,
+    ) -> RepositoryResult<TimeEntryAggregate> {
+        if report.group_by.is_empty() {
+            return Err(RepositoryError::invalid("group_by", "blank", "must name at least one attribute"));
+        }
+
+        let items = report
+            .rows_query(pagination)
+            .build_query_as::<TimeEntryAggregateRow>()
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+
+        let (total, total_hours) = report
+            .totals_query()
+            .build_query_as::<(i64, f64)>()
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(TimeEntryAggregate {
+            groups: PaginatedResult::new(items, total, pagination),
+            total_hours,
+        })
+    }
 }
 
 #[async_trait]
@@ -293,7 +531,7 @@ impl Repository<TimeEntryRow, CreateTimeEntryDto, UpdateTimeEntryDto> for TimeEn
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -312,7 +550,7 @@ impl Repository<TimeEntryRow, CreateTimeEntryDto, UpdateTimeEntryDto> for TimeEn
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -320,7 +558,7 @@ impl Repository<TimeEntryRow, CreateTimeEntryDto, UpdateTimeEntryDto> for TimeEn
 
     async fn count(&self) -> RepositoryResult<i64> {
         let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM time_entries")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(count)
@@ -357,7 +595,7 @@ impl Repository<TimeEntryRow, CreateTimeEntryDto, UpdateTimeEntryDto> for TimeEn
         .bind(tmonth)
         .bind(tweek)
         .bind(dto.logged_by_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -401,7 +639,7 @@ impl Repository<TimeEntryRow, CreateTimeEntryDto, UpdateTimeEntryDto> for TimeEn
         .bind(tmonth)
         .bind(tweek)
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?
         .ok_or_else(|| RepositoryError::NotFound(format!("Time entry with id {} not found", id)))?;
 
@@ -411,7 +649,7 @@ impl Repository<TimeEntryRow, CreateTimeEntryDto, UpdateTimeEntryDto> for TimeEn
     async fn delete(&self, id: Id) -> RepositoryResult<()> {
         let result = sqlx::query("DELETE FROM time_entries WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -428,9 +666,177 @@ impl Repository<TimeEntryRow, CreateTimeEntryDto, UpdateTimeEntryDto> for TimeEn
         let exists =
             sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM time_entries WHERE id = $1)")
                 .bind(id)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(exists)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_group_by_names() {
+        for group in TimeEntryGroupBy::ALL {
+            assert_eq!(TimeEntryGroupBy::parse(group.name()), Some(group));
+        }
+        assert_eq!(TimeEntryGroupBy::parse("workPackage"), Some(TimeEntryGroupBy::WorkPackage));
+        assert_eq!(TimeEntryGroupBy::parse("work_package"), None);
+    }
+
+    #[test]
+    fn test_report_binds_filters() {
+        let mut report = TimeEntryReport::new(
+            date(2024, 1, 1),
+            date(2024, 3, 31),
+            vec![TimeEntryGroupBy::User, TimeEntryGroupBy::Month],
+        );
+        report.project_id = Some(3);
+        report.user_ids = Some(vec![4, 5]);
+        report.visible_to = Some(4);
+
+        let rows = report.rows_query(Pagination { limit: 20, offset: 40 });
+        let sql = rows.sql();
+        assert!(sql.starts_with(
+            "SELECT te.user_id AS user_id, NULL::bigint AS project_id, NULL::bigint AS work_package_id, \
+             NULL::bigint AS activity_id, NULL::date AS week, date_trunc('month', te.spent_on)::date AS month, \
+             SUM(te.hours) AS hours FROM time_entries te WHERE te.spent_on >= $1 AND te.spent_on <= $2"
+        ));
+        assert!(sql.contains("FROM projects WHERE id = $3 UNION ALL"));
+        assert!(sql.contains("AND te.user_id = ANY($4)"));
+        assert!(sql.contains("m.user_id = $5 AND rp.permission = 'view_time_entries') OR (te.user_id = $6"));
+        assert!(sql.ends_with(
+            "GROUP BY te.user_id, date_trunc('month', te.spent_on)::date \
+             ORDER BY te.user_id, date_trunc('month', te.spent_on)::date LIMIT $8 OFFSET $9"
+        ));
+
+        let totals = report.totals_query();
+        assert!(totals.sql().starts_with("SELECT COUNT(*), COALESCE(SUM(hours), 0) FROM (SELECT "));
+        assert!(totals.sql().ends_with(") report"));
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture};
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn entry(project_id: Id, user_id: Id, spent_on: NaiveDate, hours: f64) -> CreateTimeEntryDto {
+        CreateTimeEntryDto {
+            project_id,
+            user_id,
+            work_package_id: None,
+            hours,
+            comments: None,
+            activity_id: 1,
+            spent_on,
+            logged_by_id: Some(user_id),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_buckets_by_month() {
+        let db = TestDb::connect().await;
+        let alice = db.insert_user(UserFixture::new("alice")).await;
+        let bob = db.insert_user(UserFixture::new("bob")).await;
+        let parent = db.insert_project(ProjectFixture::new("parent")).await;
+        let child = db.insert_project(ProjectFixture::new("child").with_parent(parent)).await;
+        let other = db.insert_project(ProjectFixture::new("other")).await;
+        let repo = db.time_entries();
+
+        repo.create(entry(parent, alice, date(2024, 1, 31), 2.0)).await.unwrap();
+        repo.create(entry(child, alice, date(2024, 2, 1), 3.0)).await.unwrap();
+        repo.create(entry(child, bob, date(2024, 2, 29), 1.5)).await.unwrap();
+        repo.create(entry(other, bob, date(2024, 2, 10), 8.0)).await.unwrap();
+
+        let mut report = TimeEntryReport::new(
+            date(2024, 1, 1),
+            date(2024, 2, 29),
+            vec![TimeEntryGroupBy::Month, TimeEntryGroupBy::User],
+        );
+        report.project_id = Some(parent);
+        let aggregate = repo.aggregate(&report, Pagination { limit: 2, offset: 0 }).await.unwrap();
+
+        let keys: Vec<_> = aggregate
+            .groups
+            .items
+            .iter()
+            .map(|row| (row.month, row.user_id, row.hours))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (Some(date(2024, 1, 1)), Some(alice), 2.0),
+                (Some(date(2024, 2, 1)), Some(alice), 3.0),
+            ]
+        );
+        assert!(aggregate.groups.items.iter().all(|row| row.project_id.is_none() && row.week.is_none()));
+        assert_eq!(aggregate.groups.total, 3);
+        assert_eq!(aggregate.total_hours, 6.5);
+
+        report.user_ids = Some(vec![bob]);
+        let aggregate = repo.aggregate(&report, Pagination { limit: 2, offset: 0 }).await.unwrap();
+        assert_eq!(aggregate.groups.total, 1);
+        assert_eq!(aggregate.total_hours, 1.5);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_empty_range() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("empty")).await;
+        let project = db.insert_project(ProjectFixture::new("empty")).await;
+        let repo = db.time_entries();
+        repo.create(entry(project, user, date(2024, 3, 1), 4.0)).await.unwrap();
+
+        let report = TimeEntryReport::new(date(2024, 4, 1), date(2024, 4, 30), vec![TimeEntryGroupBy::Project]);
+        let aggregate = repo.aggregate(&report, Pagination::default()).await.unwrap();
+        assert!(aggregate.groups.items.is_empty());
+        assert_eq!(aggregate.groups.total, 0);
+        assert_eq!(aggregate.total_hours, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_own_entries_only() {
+        let db = TestDb::connect().await;
+        let manager = db.insert_user(UserFixture::new("manager")).await;
+        let member = db.insert_user(UserFixture::new("member")).await;
+        let project = db.insert_project(ProjectFixture::new("visible")).await;
+        let viewer = db.insert_role("Time viewer", &["view_time_entries"]).await;
+        let own = db.insert_role("Own time viewer", &["view_own_time_entries"]).await;
+        for (user_id, role_id) in [(manager, viewer), (member, own)] {
+            db.members()
+                .create(crate::CreateMemberDto {
+                    user_id,
+                    project_id: Some(project),
+                    role_ids: vec![role_id],
+                    entity_type: None,
+                    entity_id: None,
+                })
+                .await
+                .unwrap();
+        }
+        let repo = db.time_entries();
+        repo.create(entry(project, manager, date(2024, 5, 6), 5.0)).await.unwrap();
+        repo.create(entry(project, member, date(2024, 5, 7), 2.0)).await.unwrap();
+
+        let mut report = TimeEntryReport::new(date(2024, 5, 1), date(2024, 5, 31), vec![TimeEntryGroupBy::Week]);
+        report.visible_to = Some(manager);
+        let aggregate = repo.aggregate(&report, Pagination::default()).await.unwrap();
+        assert_eq!(aggregate.groups.items[0].week, Some(date(2024, 5, 6)));
+        assert_eq!(aggregate.total_hours, 7.0);
+
+        report.visible_to = Some(member);
+        let aggregate = repo.aggregate(&report, Pagination::default()).await.unwrap();
+        assert_eq!(aggregate.total_hours, 2.0);
+    }
+}
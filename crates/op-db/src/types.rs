@@ -6,9 +6,12 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use op_core::traits::Id;
+use op_models::FormConfiguration;
 use sqlx::{FromRow, PgPool};
 
+use crate::executor::{DbExecutor, DbTransaction};
 use crate::repository::{Repository, RepositoryError, RepositoryResult};
+use crate::work_packages::{count_referencing, reassign_referencing, ReferenceColumn};
 
 /// Type database entity
 #[derive(Debug, Clone, FromRow)]
@@ -22,6 +25,8 @@ pub struct TypeRow {
     pub is_standard: bool,
     pub color_id: Option<i64>,
     pub description: Option<String>,
+    /// Stored form configuration, the default layout when `None`
+    pub form_configuration: Option<serde_json::Value>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -46,6 +51,13 @@ impl TypeRow {
     pub fn is_milestone(&self) -> bool {
         self.is_milestone
     }
+
+    /// Stored form configuration; one that no longer parses is treated as
+    /// not configured
+    pub fn form_configuration(&self) -> Option<FormConfiguration> {
+        let value = self.form_configuration.clone()?;
+        serde_json::from_value(value).ok()
+    }
 }
 
 /// DTO for creating a type
@@ -74,12 +86,39 @@ pub struct UpdateTypeDto {
 
 /// Type repository implementation
 pub struct TypeRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl TypeRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Find types by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[Id]) -> RepositoryResult<Vec<TypeRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, TypeRow>(
+            r#"
+            SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
+            FROM types
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
     }
 
     /// Find the standard (built-in) type
@@ -87,13 +126,13 @@ impl TypeRepository {
         let row = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             WHERE is_standard = true
             LIMIT 1
             "#,
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -104,13 +143,13 @@ impl TypeRepository {
         let rows = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             WHERE is_default = true
             ORDER BY position ASC
             "#,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -121,30 +160,53 @@ impl TypeRepository {
         let rows = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             WHERE is_milestone = true
             ORDER BY position ASC
             "#,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
     }
 
+    /// Check whether a type represents milestones
+    pub async fn is_milestone(&self, id: Id) -> RepositoryResult<bool> {
+        let is_milestone = sqlx::query_scalar::<_, bool>(
+            "SELECT is_milestone FROM types WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        is_milestone.ok_or_else(|| RepositoryError::NotFound(format!("Type with id {} not found", id)))
+    }
+
+    /// IDs of milestone types
+    pub async fn milestone_ids(&self) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            "SELECT id FROM types WHERE is_milestone = true ORDER BY position ASC",
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
     /// Find types shown in roadmap
     pub async fn find_in_roadmap(&self) -> RepositoryResult<Vec<TypeRow>> {
         let rows = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             WHERE is_in_roadmap = true
             ORDER BY position ASC
             "#,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -155,7 +217,7 @@ impl TypeRepository {
         let rows = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT t.id, t.name, t.position, t.is_default, t.is_in_roadmap, t.is_milestone,
-                   t.is_standard, t.color_id, t.description, t.created_at, t.updated_at
+                   t.is_standard, t.color_id, t.description, t.form_configuration, t.created_at, t.updated_at
             FROM types t
             INNER JOIN projects_types pt ON pt.type_id = t.id
             WHERE pt.project_id = $1
@@ -163,7 +225,7 @@ impl TypeRepository {
             "#,
         )
         .bind(project_id)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -174,13 +236,13 @@ impl TypeRepository {
         let row = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             WHERE LOWER(name) = LOWER($1)
             "#,
         )
         .bind(name)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -191,13 +253,13 @@ impl TypeRepository {
         let rows = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             WHERE is_standard = false
             ORDER BY position ASC
             "#,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -217,14 +279,14 @@ impl TypeRepository {
             .bind(name),
         };
 
-        let unique = query.fetch_one(&self.pool).await?;
+        let unique = query.fetch_one(&mut *self.db.acquire().await?).await?;
         Ok(unique)
     }
 
     /// Get max position for ordering
     async fn get_max_position(&self) -> RepositoryResult<i32> {
         let max_pos = sqlx::query_scalar::<_, Option<i32>>("SELECT MAX(position) FROM types")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?
             .unwrap_or(0);
 
@@ -242,7 +304,7 @@ impl TypeRepository {
         )
         .bind(project_id)
         .bind(type_id)
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -258,11 +320,104 @@ impl TypeRepository {
         )
         .bind(project_id)
         .bind(type_id)
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
     }
+
+    /// Ids of all work package custom fields, the ones form configurations
+    /// may reference
+    pub async fn custom_field_ids(&self) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, Id>(
+            "SELECT id FROM custom_fields WHERE type = 'WorkPackageCustomField' ORDER BY position, id",
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Store the form configuration of the type, `None` resetting it to the
+    /// default layout. Rejected when it references attributes or custom
+    /// fields that do not exist.
+    pub async fn set_form_configuration(
+        &self,
+        id: Id,
+        configuration: Option<&FormConfiguration>,
+    ) -> RepositoryResult<TypeRow> {
+        if let Some(configuration) = configuration {
+            let custom_field_ids = self.custom_field_ids().await?;
+            if let Err(e) = configuration.validate(&custom_field_ids) {
+                return Err(RepositoryError::invalid("attribute_groups", "invalid", e.to_string()));
+            }
+        }
+        let value = configuration.map(|configuration| {
+            serde_json::to_value(configuration).expect("form configuration serializes")
+        });
+
+        let row = sqlx::query_as::<_, TypeRow>(
+            r#"
+            UPDATE types SET form_configuration = $1, updated_at = NOW()
+            WHERE id = $2
+            RETURNING id, name, position, is_default, is_in_roadmap, is_milestone,
+                      is_standard, color_id, description, form_configuration, created_at, updated_at
+            "#,
+        )
+        .bind(value)
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?
+        .ok_or_else(|| RepositoryError::NotFound(format!("Type with id {} not found", id)))?;
+
+        Ok(row)
+    }
+
+    /// Number of work packages of the type
+    pub async fn work_package_count(&self, id: Id) -> RepositoryResult<i64> {
+        count_referencing(&mut *self.db.acquire().await?, ReferenceColumn::Type, id).await
+    }
+
+    /// Delete the type after moving its work packages to `reassign_to`,
+    /// all in one transaction. Each moved work package is journaled as a
+    /// system update by the user. Returns the number of work packages moved.
+    pub async fn delete_reassigning(&self, id: Id, reassign_to: Id, user_id: Id) -> RepositoryResult<u64> {
+        self.ensure_not_standard(id).await?;
+        if reassign_to == id || !self.exists(reassign_to).await? {
+            return Err(RepositoryError::invalid("reassign_to", "invalid", "must be another existing type"));
+        }
+
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        let moved = reassign_referencing(&mut tx, ReferenceColumn::Type, id, reassign_to, user_id).await?;
+
+        let result = sqlx::query("DELETE FROM types WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(format!("Type with id {} not found", id)));
+        }
+
+        tx.commit().await?;
+        Ok(moved)
+    }
+
+    async fn ensure_not_standard(&self, id: Id) -> RepositoryResult<()> {
+        let is_standard = sqlx::query_scalar::<_, bool>(
+            "SELECT is_standard FROM types WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        if is_standard == Some(true) {
+            return Err(RepositoryError::Conflict(
+                "Cannot delete the standard type".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -271,13 +426,13 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
         let row = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             WHERE id = $1
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -287,7 +442,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
         let rows = sqlx::query_as::<_, TypeRow>(
             r#"
             SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
-                   is_standard, color_id, description, created_at, updated_at
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
             FROM types
             ORDER BY position ASC
             LIMIT $1 OFFSET $2
@@ -295,7 +450,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -303,7 +458,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
 
     async fn count(&self) -> RepositoryResult<i64> {
         let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM types")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(count)
@@ -331,7 +486,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
                 $1, $2, $3, $4, $5, false, $6, $7, NOW(), NOW()
             )
             RETURNING id, name, position, is_default, is_in_roadmap, is_milestone,
-                      is_standard, color_id, description, created_at, updated_at
+                      is_standard, color_id, description, form_configuration, created_at, updated_at
             "#,
         )
         .bind(&dto.name)
@@ -341,7 +496,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
         .bind(dto.is_milestone)
         .bind(dto.color_id)
         .bind(&dto.description)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -370,7 +525,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
                 updated_at = NOW()
             WHERE id = $8
             RETURNING id, name, position, is_default, is_in_roadmap, is_milestone,
-                      is_standard, color_id, description, created_at, updated_at
+                      is_standard, color_id, description, form_configuration, created_at, updated_at
             "#,
         )
         .bind(&dto.name)
@@ -381,7 +536,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
         .bind(dto.color_id)
         .bind(&dto.description)
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?
         .ok_or_else(|| RepositoryError::NotFound(format!("Type with id {} not found", id)))?;
 
@@ -389,37 +544,20 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
     }
 
     async fn delete(&self, id: Id) -> RepositoryResult<()> {
-        // Check if this is the standard type
-        let is_standard = sqlx::query_scalar::<_, bool>(
-            "SELECT is_standard FROM types WHERE id = $1",
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
-
-        if is_standard == Some(true) {
-            return Err(RepositoryError::Conflict(
-                "Cannot delete the standard type".to_string(),
-            ));
-        }
-
-        // Check if any work packages use this type
-        let has_work_packages = sqlx::query_scalar::<_, bool>(
-            "SELECT EXISTS(SELECT 1 FROM work_packages WHERE type_id = $1)",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
-
-        if has_work_packages {
-            return Err(RepositoryError::Conflict(
-                "Cannot delete type: work packages are using this type".to_string(),
-            ));
+        self.ensure_not_standard(id).await?;
+
+        // Work packages of the type have to be moved first
+        let work_packages = self.work_package_count(id).await?;
+        if work_packages > 0 {
+            return Err(RepositoryError::Conflict(format!(
+                "Cannot delete type: {} work packages are using this type",
+                work_packages
+            )));
         }
 
         let result = sqlx::query("DELETE FROM types WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -436,7 +574,7 @@ impl Repository<TypeRow, CreateTypeDto, UpdateTypeDto> for TypeRepository {
         let exists =
             sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM types WHERE id = $1)")
                 .bind(id)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(exists)
@@ -459,6 +597,7 @@ mod tests {
             is_standard: false,
             color_id: None,
             description: None,
+            form_configuration: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
@@ -468,3 +607,67 @@ mod tests {
         assert!(!type_row.is_standard());
     }
 }
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use op_models::FormGroup;
+
+    use super::*;
+    use crate::testing::TestDb;
+
+    async fn create_type(db: &TestDb, name: &str) -> TypeRow {
+        db.types()
+            .create(CreateTypeDto {
+                name: name.into(),
+                position: None,
+                is_default: false,
+                is_in_roadmap: true,
+                is_milestone: false,
+                color_id: None,
+                description: None,
+            })
+            .await
+            .unwrap()
+    }
+
+    fn group(name: &str, attributes: &[&str]) -> FormGroup {
+        FormGroup::AttributeGroup {
+            name: name.into(),
+            attributes: attributes.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_form_configuration_round_trips() {
+        let db = TestDb::connect().await;
+        let repo = db.types();
+        let bug = create_type(&db, "Bug").await;
+        assert_eq!(bug.form_configuration(), None);
+
+        let custom_field = db.insert_custom_field("Severity", true).await;
+        let configuration = FormConfiguration::new(vec![
+            group("People", &["assignee"]),
+            group("Triage", &["priority", &format!("cf_{}", custom_field)]),
+        ]);
+        repo.set_form_configuration(bug.id, Some(&configuration)).await.unwrap();
+
+        let stored = repo.find_by_id(bug.id).await.unwrap().unwrap();
+        assert_eq!(stored.form_configuration(), Some(configuration));
+
+        let reset = repo.set_form_configuration(bug.id, None).await.unwrap();
+        assert_eq!(reset.form_configuration(), None);
+    }
+
+    #[tokio::test]
+    async fn test_form_configuration_rejects_missing_custom_fields() {
+        let db = TestDb::connect().await;
+        let bug = create_type(&db, "Bug").await;
+        let custom_field = db.insert_custom_field("Severity", false).await;
+
+        let configuration = FormConfiguration::new(vec![group("Triage", &[&format!("cf_{}", custom_field + 1)])]);
+        let error = db.types().set_form_configuration(bug.id, Some(&configuration)).await.unwrap_err();
+
+        assert!(matches!(error, RepositoryError::Validation(_)), "{:?}", error);
+        assert_eq!(db.types().find_by_id(bug.id).await.unwrap().unwrap().form_configuration(), None);
+    }
+}
@@ -2,17 +2,26 @@
 //!
 //! Database operations for users.
 
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use op_core::email::normalize_email;
 use op_core::traits::Id;
-use sqlx::{FromRow, PgPool};
+use serde::Serialize;
+use sqlx::{FromRow, PgConnection, PgPool};
 
+use crate::executor::{DbExecutor, DbTransaction};
 use crate::repository::{Pagination, PaginatedResult, Repository, RepositoryError, RepositoryResult};
 
 /// User database entity
 #[derive(Debug, Clone, FromRow)]
 pub struct UserRow {
     pub id: i64,
+    /// Kind of principal, see [`principal_type`]
+    #[sqlx(rename = "type")]
+    pub principal_type: String,
     pub login: String,
     pub firstname: String,
     pub lastname: String,
@@ -38,12 +47,51 @@ impl UserRow {
         self.status == 3
     }
 
-    /// Get full name
+    /// Check if the user was deleted and anonymized
+    pub fn is_deleted(&self) -> bool {
+        self.status == status::DELETED
+    }
+
+    /// Check if the principal is a group of users
+    pub fn is_group(&self) -> bool {
+        self.principal_type == principal_type::GROUP
+    }
+
+    /// Check if the principal is a placeholder user
+    pub fn is_placeholder(&self) -> bool {
+        self.principal_type == principal_type::PLACEHOLDER_USER
+    }
+
+    /// Get full name; groups and placeholder users keep theirs in `lastname`
     pub fn full_name(&self) -> String {
+        if self.is_group() || self.is_placeholder() {
+            return self.lastname.clone();
+        }
         format!("{} {}", self.firstname, self.lastname)
     }
 }
 
+/// Values of the `users.type` discriminator
+pub mod principal_type {
+    pub const USER: &str = "User";
+    pub const GROUP: &str = "Group";
+    pub const PLACEHOLDER_USER: &str = "PlaceholderUser";
+    pub const ANONYMOUS_USER: &str = "AnonymousUser";
+    pub const SYSTEM_USER: &str = "SystemUser";
+    pub const DELETED_USER: &str = "DeletedUser";
+
+    /// Principals that can be members, assignees or responsibles
+    pub const PRINCIPALS: &[&str] = &[USER, GROUP, PLACEHOLDER_USER];
+}
+
+/// Member of a group, with the group's id
+#[derive(Debug, Clone, FromRow)]
+struct GroupMemberRow {
+    group_id: i64,
+    #[sqlx(flatten)]
+    user: UserRow,
+}
+
 /// DTO for creating a user
 #[derive(Debug, Clone)]
 pub struct CreateUserDto {
@@ -78,47 +126,430 @@ pub mod status {
     pub const ACTIVE: i32 = 1;
     pub const LOCKED: i32 = 3;
     pub const INVITED: i32 = 4;
+    /// Deleted; the row is kept anonymized so history stays consistent
+    pub const DELETED: i32 = 5;
+}
+
+/// Statuses of users left out of principal listings
+fn excluded_statuses(include_locked: bool) -> Vec<i32> {
+    if include_locked {
+        vec![status::DELETED]
+    } else {
+        vec![status::LOCKED, status::DELETED]
+    }
+}
+
+/// Login of the placeholder user deleted users' content is attributed to
+pub const DELETED_USER_LOGIN: &str = "deleted_user";
+
+/// What happens to a row referencing a deleted user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanAction {
+    /// Attribute the row to the deleted user placeholder
+    Reassign,
+    /// Clear the reference
+    Nullify,
+    /// Remove the row
+    Delete,
+}
+
+/// A column referencing users
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserReference {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub action: OrphanAction,
+}
+
+/// Columns referencing users and how each is handled on deletion.
+///
+/// Authorship is kept by reassigning to the placeholder; assignments are
+/// cleared; watching, memberships and tokens have no meaning for a deleted
+/// user and are removed.
+pub const USER_REFERENCES: &[UserReference] = &[
+    UserReference { table: "work_packages", column: "author_id", action: OrphanAction::Reassign },
+    UserReference { table: "work_packages", column: "assigned_to_id", action: OrphanAction::Nullify },
+    UserReference { table: "work_packages", column: "responsible_id", action: OrphanAction::Nullify },
+    UserReference { table: "journals", column: "user_id", action: OrphanAction::Reassign },
+    UserReference { table: "time_entries", column: "user_id", action: OrphanAction::Reassign },
+    UserReference { table: "cost_entries", column: "user_id", action: OrphanAction::Reassign },
+    UserReference { table: "storages", column: "creator_id", action: OrphanAction::Reassign },
+    UserReference { table: "file_links", column: "creator_id", action: OrphanAction::Reassign },
+    UserReference { table: "watchers", column: "user_id", action: OrphanAction::Delete },
+    UserReference { table: "members", column: "user_id", action: OrphanAction::Delete },
+    UserReference { table: "group_users", column: "user_id", action: OrphanAction::Delete },
+    UserReference { table: "tokens", column: "user_id", action: OrphanAction::Delete },
+];
+
+impl UserReference {
+    /// Statement handling the references of user `$1`; reassignments bind
+    /// the placeholder as `$2`
+    fn orphan_sql(&self) -> String {
+        match self.action {
+            OrphanAction::Reassign => format!(
+                "UPDATE {t} SET {c} = $2 WHERE {c} = $1",
+                t = self.table,
+                c = self.column
+            ),
+            OrphanAction::Nullify => format!(
+                "UPDATE {t} SET {c} = NULL WHERE {c} = $1",
+                t = self.table,
+                c = self.column
+            ),
+            OrphanAction::Delete => format!("DELETE FROM {} WHERE {} = $1", self.table, self.column),
+        }
+    }
+
+    fn count_sql(&self) -> String {
+        format!("SELECT COUNT(*) FROM {} WHERE {} = $1", self.table, self.column)
+    }
+
+    /// `table.column`
+    pub fn name(&self) -> String {
+        format!("{}.{}", self.table, self.column)
+    }
+}
+
+/// Id of the placeholder deleted users' content is attributed to, created
+/// the first time it is needed
+pub(crate) async fn deleted_user_placeholder(conn: &mut PgConnection) -> RepositoryResult<Id> {
+    let existing = sqlx::query_scalar::<_, Id>("SELECT id FROM users WHERE type = 'DeletedUser' ORDER BY id LIMIT 1")
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some(placeholder_id) = existing {
+        return Ok(placeholder_id);
+    }
+
+    let placeholder_id = sqlx::query_scalar::<_, Id>(
+        r#"
+        INSERT INTO users (type, login, firstname, lastname, mail, admin, status,
+                           created_at, updated_at)
+        VALUES ('DeletedUser', $1, 'Deleted', 'user', '', false, $2, NOW(), NOW())
+        RETURNING id
+        "#,
+    )
+    .bind(DELETED_USER_LOGIN)
+    .bind(status::LOCKED)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(placeholder_id)
+}
+
+/// Rows changed per referencing column by a soft delete
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoftDeleteReport {
+    pub placeholder_id: Id,
+    pub changed: Vec<(UserReference, u64)>,
+}
+
+impl SoftDeleteReport {
+    /// Rows changed for a referencing column
+    pub fn changed(&self, table: &str, column: &str) -> u64 {
+        self.changed
+            .iter()
+            .find(|(r, _)| r.table == table && r.column == column)
+            .map(|(_, n)| *n)
+            .unwrap_or(0)
+    }
 }
 
 /// User repository implementation
 pub struct UserRepository {
-    pool: PgPool,
+    db: DbExecutor,
 }
 
 impl UserRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Find users by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[Id]) -> RepositoryResult<Vec<UserRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
+                   language, hashed_password, salt, created_at, updated_at, last_login_on
+            FROM users
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Find principals of the given types, all of users, groups and
+    /// placeholder users when none are given; deleted users are left out,
+    /// and locked users unless `include_locked`
+    pub async fn find_principals(
+        &self,
+        types: &[&str],
+        include_locked: bool,
+        pagination: Pagination,
+    ) -> RepositoryResult<PaginatedResult<UserRow>> {
+        let types = if types.is_empty() { principal_type::PRINCIPALS } else { types };
+        let excluded = excluded_statuses(include_locked);
+
+        let items = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
+                   language, hashed_password, salt, created_at, updated_at, last_login_on
+            FROM users
+            WHERE type = ANY($1) AND status <> ALL($2)
+            ORDER BY lower(lastname), lower(firstname), id
+            LIMIT $3 OFFSET $4
+            "#,
+        )
+        .bind(types)
+        .bind(&excluded)
+        .bind(pagination.limit)
+        .bind(pagination.offset)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        let total = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE type = ANY($1) AND status <> ALL($2)")
+            .bind(types)
+            .bind(&excluded)
+            .fetch_one(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(PaginatedResult::new(items, total, pagination))
+    }
+
+    /// Find the principals of the given types work packages of a project
+    /// can be assigned to: members whose roles grant `work_package_assigned`,
+    /// leaving out deleted users, and locked users unless `include_locked`
+    pub async fn find_assignable(
+        &self,
+        project_id: Id,
+        types: &[&str],
+        include_locked: bool,
+    ) -> RepositoryResult<Vec<UserRow>> {
+        let types = if types.is_empty() { principal_type::PRINCIPALS } else { types };
+
+        let rows = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT u.id, u.type, u.login, u.firstname, u.lastname, u.mail, u.admin, u.status,
+                   u.language, u.hashed_password, u.salt, u.created_at, u.updated_at, u.last_login_on
+            FROM users u
+            WHERE u.type = ANY($2)
+              AND u.status <> ALL($3)
+              AND EXISTS (
+                  SELECT 1
+                  FROM members m
+                  JOIN member_roles mr ON mr.member_id = m.id
+                  JOIN role_permissions rp ON rp.role_id = mr.role_id
+                  WHERE m.user_id = u.id
+                    AND m.project_id = $1
+                    AND m.entity_type IS NULL
+                    AND rp.permission = 'work_package_assigned'
+              )
+            ORDER BY lower(u.lastname), lower(u.firstname), u.id
+            "#,
+        )
+        .bind(project_id)
+        .bind(types)
+        .bind(excluded_statuses(include_locked))
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Find the members of groups, as pairs of group id and user
+    pub async fn find_group_members(&self, group_ids: &[Id]) -> RepositoryResult<Vec<(Id, UserRow)>> {
+        if group_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, GroupMemberRow>(
+            r#"
+            SELECT gu.group_id, u.id, u.type, u.login, u.firstname, u.lastname, u.mail, u.admin,
+                   u.status, u.language, u.hashed_password, u.salt, u.created_at, u.updated_at,
+                   u.last_login_on
+            FROM group_users gu
+            JOIN users u ON u.id = gu.user_id
+            WHERE gu.group_id = ANY($1)
+            ORDER BY gu.group_id, lower(u.lastname), lower(u.firstname), u.id
+            "#,
+        )
+        .bind(group_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.group_id, row.user)).collect())
+    }
+
+    /// Count the rows referencing a user, per referencing column
+    pub async fn count_references(&self, id: Id) -> RepositoryResult<Vec<(UserReference, i64)>> {
+        let mut counts = Vec::with_capacity(USER_REFERENCES.len());
+        for reference in USER_REFERENCES {
+            let count = sqlx::query_scalar::<_, i64>(&reference.count_sql())
+                .bind(id)
+                .fetch_one(&mut *self.db.acquire().await?)
+                .await?;
+            counts.push((*reference, count));
+        }
+        Ok(counts)
+    }
+
+    /// Soft delete a user: hand their content over to the deleted user
+    /// placeholder and anonymize the row instead of removing it.
+    ///
+    /// Runs in a single transaction, which is rolled back if any reference
+    /// to the user is left afterwards. Safe to run again after a failure.
+    pub async fn soft_delete(&self, id: Id) -> RepositoryResult<SoftDeleteReport> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if !exists {
+            return Err(RepositoryError::NotFound(format!("User with id {} not found", id)));
+        }
+
+        let placeholder_id = deleted_user_placeholder(&mut tx).await?;
+        if placeholder_id == id {
+            return Err(RepositoryError::validation("The deleted user placeholder cannot be deleted"));
+        }
+
+        // Role assignments hang off memberships, which are removed below
+        sqlx::query("DELETE FROM member_roles WHERE member_id IN (SELECT id FROM members WHERE user_id = $1)")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        let mut changed = Vec::with_capacity(USER_REFERENCES.len());
+        for reference in USER_REFERENCES {
+            let sql = reference.orphan_sql();
+            let mut query = sqlx::query(&sql).bind(id);
+            if reference.action == OrphanAction::Reassign {
+                query = query.bind(placeholder_id);
+            }
+            let result = query.execute(&mut *tx).await?;
+            changed.push((*reference, result.rows_affected()));
+        }
+
+        for reference in USER_REFERENCES {
+            let remaining = sqlx::query_scalar::<_, i64>(&reference.count_sql())
+                .bind(id)
+                .fetch_one(&mut *tx)
+                .await?;
+            if remaining > 0 {
+                return Err(RepositoryError::Conflict(format!(
+                    "{} rows in {} still reference user {}",
+                    remaining,
+                    reference.name(),
+                    id
+                )));
+            }
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET login = 'deleted_' || id || '_' || md5(random()::text),
+                firstname = 'Deleted', lastname = 'user', mail = '',
+                admin = false, status = $2, language = NULL,
+                hashed_password = NULL, salt = NULL, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status::DELETED)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(SoftDeleteReport { placeholder_id, changed })
     }
 
     /// Find a user by login
     pub async fn find_by_login(&self, login: &str) -> RepositoryResult<Option<UserRow>> {
         let row = sqlx::query_as::<_, UserRow>(
             r#"
-            SELECT id, login, firstname, lastname, mail, admin, status,
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
                    language, hashed_password, salt, created_at, updated_at, last_login_on
             FROM users
             WHERE login = $1
             "#,
         )
         .bind(login)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
     }
 
-    /// Find a user by email
+    /// Ids of the users with the logins, compared case-insensitively, as
+    /// `(lowercase login, id)` pairs. Groups and other principals without
+    /// a login never match.
+    pub async fn find_ids_by_logins(&self, logins: &[String]) -> RepositoryResult<Vec<(String, Id)>> {
+        if logins.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, (String, Id)>(
+            r#"
+            SELECT lower(login), id FROM users
+            WHERE type = $1 AND lower(login) = ANY(SELECT lower(l) FROM UNNEST($2::TEXT[]) AS l)
+            "#,
+        )
+        .bind(principal_type::USER)
+        .bind(logins)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Find a user by email, compared like [`Self::is_email_unique`] does
     pub async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<UserRow>> {
+        let email = normalize_email(email).unwrap_or_else(|_| email.trim().to_string());
         let row = sqlx::query_as::<_, UserRow>(
             r#"
-            SELECT id, login, firstname, lastname, mail, admin, status,
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
                    language, hashed_password, salt, created_at, updated_at, last_login_on
             FROM users
-            WHERE mail = $1
+            WHERE LOWER(mail) = LOWER($1)
+            ORDER BY id
+            LIMIT 1
             "#,
         )
         .bind(email)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Find the anonymous user
+    pub async fn find_anonymous(&self) -> RepositoryResult<Option<UserRow>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
+                   language, hashed_password, salt, created_at, updated_at, last_login_on
+            FROM users
+            WHERE type = 'AnonymousUser'
+            LIMIT 1
+            "#,
+        )
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -131,7 +562,7 @@ impl UserRepository {
     ) -> RepositoryResult<PaginatedResult<UserRow>> {
         let items = sqlx::query_as::<_, UserRow>(
             r#"
-            SELECT id, login, firstname, lastname, mail, admin, status,
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
                    language, hashed_password, salt, created_at, updated_at, last_login_on
             FROM users
             WHERE status = $1
@@ -142,13 +573,13 @@ impl UserRepository {
         .bind(status::ACTIVE)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total =
             sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE status = $1")
                 .bind(status::ACTIVE)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -158,7 +589,7 @@ impl UserRepository {
     pub async fn find_admins(&self) -> RepositoryResult<Vec<UserRow>> {
         let rows = sqlx::query_as::<_, UserRow>(
             r#"
-            SELECT id, login, firstname, lastname, mail, admin, status,
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
                    language, hashed_password, salt, created_at, updated_at, last_login_on
             FROM users
             WHERE admin = true AND status = $1
@@ -166,7 +597,7 @@ impl UserRepository {
             "#,
         )
         .bind(status::ACTIVE)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -176,7 +607,7 @@ impl UserRepository {
     pub async fn update_last_login(&self, id: Id) -> RepositoryResult<()> {
         sqlx::query("UPDATE users SET last_login_on = NOW(), updated_at = NOW() WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(())
@@ -195,7 +626,7 @@ impl UserRepository {
         .bind(hashed_password)
         .bind(salt)
         .bind(id)
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -206,7 +637,7 @@ impl UserRepository {
         sqlx::query("UPDATE users SET status = $1, updated_at = NOW() WHERE id = $2")
             .bind(status::LOCKED)
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(())
@@ -217,12 +648,62 @@ impl UserRepository {
         sqlx::query("UPDATE users SET status = $1, updated_at = NOW() WHERE id = $2")
             .bind(status::ACTIVE)
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(())
     }
 
+    /// Time zone the user chose in their preferences, e.g. "Europe/Berlin"
+    pub async fn find_time_zone(&self, id: Id) -> RepositoryResult<Option<String>> {
+        let time_zone = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT NULLIF(settings->>'time_zone', '') FROM user_preferences WHERE user_id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(time_zone.flatten())
+    }
+
+    /// Whether users chose to hide their email address, for those of
+    /// `ids` who made a choice in their preferences
+    pub async fn find_hide_mail(&self, ids: &[Id]) -> RepositoryResult<HashMap<Id, bool>> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query_as::<_, (Id, String)>(
+            "SELECT user_id, settings->>'hide_mail' FROM user_preferences \
+             WHERE user_id = ANY($1) AND settings->>'hide_mail' IS NOT NULL",
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        // Preferences written by Rails may hold the flag as a string
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, value)| match value.as_str() {
+                "true" | "1" => Some((id, true)),
+                "false" | "0" => Some((id, false)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Language the user chose, e.g. "de"
+    pub async fn find_language(&self, id: Id) -> RepositoryResult<Option<String>> {
+        let language = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT NULLIF(language, '') FROM users WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(language.flatten())
+    }
+
     /// Check if login is unique
     pub async fn is_login_unique(&self, login: &str, exclude_id: Option<Id>) -> RepositoryResult<bool> {
         let query = match exclude_id {
@@ -239,29 +720,33 @@ impl UserRepository {
             .bind(login),
         };
 
-        let unique = query.fetch_one(&self.pool).await?;
+        let unique = query.fetch_one(&mut *self.db.acquire().await?).await?;
         Ok(unique)
     }
 
     /// Check if email is unique
+    ///
+    /// Addresses are compared in their normalized form and regardless of
+    /// case, so `User@Foo.com` and `user@foo.com` collide.
     pub async fn is_email_unique(&self, email: &str, exclude_id: Option<Id>) -> RepositoryResult<bool> {
+        let email = normalize_email(email).unwrap_or_else(|_| email.trim().to_string());
         let query = match exclude_id {
             Some(id) => {
                 sqlx::query_scalar::<_, bool>(
-                    "SELECT NOT EXISTS(SELECT 1 FROM users WHERE mail = $1 AND id != $2)",
+                    "SELECT NOT EXISTS(SELECT 1 FROM users WHERE LOWER(mail) = LOWER($1) AND id != $2)",
                 )
                 .bind(email)
                 .bind(id)
             }
             None => {
                 sqlx::query_scalar::<_, bool>(
-                    "SELECT NOT EXISTS(SELECT 1 FROM users WHERE mail = $1)",
+                    "SELECT NOT EXISTS(SELECT 1 FROM users WHERE LOWER(mail) = LOWER($1))",
                 )
                 .bind(email)
             }
         };
 
-        let unique = query.fetch_one(&self.pool).await?;
+        let unique = query.fetch_one(&mut *self.db.acquire().await?).await?;
         Ok(unique)
     }
 }
@@ -271,14 +756,14 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
     async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<UserRow>> {
         let row = sqlx::query_as::<_, UserRow>(
             r#"
-            SELECT id, login, firstname, lastname, mail, admin, status,
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
                    language, hashed_password, salt, created_at, updated_at, last_login_on
             FROM users
             WHERE id = $1
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -287,7 +772,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
     async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<UserRow>> {
         let rows = sqlx::query_as::<_, UserRow>(
             r#"
-            SELECT id, login, firstname, lastname, mail, admin, status,
+            SELECT id, type, login, firstname, lastname, mail, admin, status,
                    language, hashed_password, salt, created_at, updated_at, last_login_on
             FROM users
             ORDER BY login ASC
@@ -296,7 +781,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
@@ -304,7 +789,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
 
     async fn count(&self) -> RepositoryResult<i64> {
         let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users")
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(count)
@@ -319,7 +804,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
             ) VALUES (
                 $1, $2, $3, $4, $5, $6, $7, $8, $9, NOW(), NOW()
             )
-            RETURNING id, login, firstname, lastname, mail, admin, status,
+            RETURNING id, type, login, firstname, lastname, mail, admin, status,
                       language, hashed_password, salt, created_at, updated_at, last_login_on
             "#,
         )
@@ -332,7 +817,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
         .bind(&dto.language)
         .bind(&dto.hashed_password)
         .bind(&dto.salt)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
@@ -353,7 +838,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
                 salt = COALESCE($9, salt),
                 updated_at = NOW()
             WHERE id = $10
-            RETURNING id, login, firstname, lastname, mail, admin, status,
+            RETURNING id, type, login, firstname, lastname, mail, admin, status,
                       language, hashed_password, salt, created_at, updated_at, last_login_on
             "#,
         )
@@ -367,7 +852,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
         .bind(&dto.hashed_password)
         .bind(&dto.salt)
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?
         .ok_or_else(|| RepositoryError::NotFound(format!("User with id {} not found", id)))?;
 
@@ -377,7 +862,7 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
     async fn delete(&self, id: Id) -> RepositoryResult<()> {
         let result = sqlx::query("DELETE FROM users WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -394,9 +879,346 @@ impl Repository<UserRow, CreateUserDto, UpdateUserDto> for UserRepository {
         let exists =
             sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)")
                 .bind(id)
-                .fetch_one(&self.pool)
+                .fetch_one(&mut *self.db.acquire().await?)
                 .await?;
 
         Ok(exists)
     }
 }
+
+/// Users as the API handlers need them, stored in the database or, in the
+/// embedded mode, in memory
+#[async_trait]
+pub trait UserStore: Repository<UserRow, CreateUserDto, UpdateUserDto> {
+    /// Find user by login
+    async fn find_by_login(&self, login: &str) -> RepositoryResult<Option<UserRow>>;
+}
+
+#[async_trait]
+impl UserStore for UserRepository {
+    async fn find_by_login(&self, login: &str) -> RepositoryResult<Option<UserRow>> {
+        UserRepository::find_by_login(self, login).await
+    }
+}
+
+/// Users of a single process
+#[derive(Default)]
+pub struct MemoryUserStore {
+    users: Mutex<BTreeMap<Id, UserRow>>,
+}
+
+impl MemoryUserStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Repository<UserRow, CreateUserDto, UpdateUserDto> for MemoryUserStore {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<UserRow>> {
+        Ok(self.users.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<UserRow>> {
+        let users = self.users.lock().unwrap();
+        Ok(users.values().skip(offset as usize).take(limit as usize).cloned().collect())
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        Ok(self.users.lock().unwrap().len() as i64)
+    }
+
+    async fn create(&self, dto: CreateUserDto) -> RepositoryResult<UserRow> {
+        let mut users = self.users.lock().unwrap();
+        if users.values().any(|user| user.login == dto.login) {
+            return Err(RepositoryError::Conflict(format!("Login {} is already taken", dto.login)));
+        }
+        let id = users.keys().next_back().copied().unwrap_or(0) + 1;
+        let now = Utc::now();
+        let row = UserRow {
+            id,
+            principal_type: principal_type::USER.to_string(),
+            login: dto.login,
+            firstname: dto.firstname,
+            lastname: dto.lastname,
+            mail: dto.mail,
+            admin: dto.admin,
+            status: dto.status,
+            language: dto.language,
+            hashed_password: dto.hashed_password,
+            salt: dto.salt,
+            created_at: now,
+            updated_at: now,
+            last_login_on: None,
+        };
+        users.insert(id, row.clone());
+        Ok(row)
+    }
+
+    async fn update(&self, id: Id, dto: UpdateUserDto) -> RepositoryResult<UserRow> {
+        let mut users = self.users.lock().unwrap();
+        let user = users
+            .get_mut(&id)
+            .ok_or_else(|| RepositoryError::NotFound(format!("User with id {} not found", id)))?;
+        if let Some(login) = dto.login {
+            user.login = login;
+        }
+        if let Some(firstname) = dto.firstname {
+            user.firstname = firstname;
+        }
+        if let Some(lastname) = dto.lastname {
+            user.lastname = lastname;
+        }
+        if let Some(mail) = dto.mail {
+            user.mail = mail;
+        }
+        user.admin = dto.admin.unwrap_or(user.admin);
+        user.status = dto.status.unwrap_or(user.status);
+        user.language = dto.language.or(user.language.take());
+        user.hashed_password = dto.hashed_password.or(user.hashed_password.take());
+        user.salt = dto.salt.or(user.salt.take());
+        user.updated_at = Utc::now();
+        Ok(user.clone())
+    }
+
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        self.users
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .map(|_| ())
+            .ok_or_else(|| RepositoryError::NotFound(format!("User with id {} not found", id)))
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        Ok(self.users.lock().unwrap().contains_key(&id))
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryUserStore {
+    async fn find_by_login(&self, login: &str) -> RepositoryResult<Option<UserRow>> {
+        let users = self.users.lock().unwrap();
+        Ok(users.values().find(|user| user.login == login).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_references_cover_content() {
+        let names: Vec<String> = USER_REFERENCES.iter().map(UserReference::name).collect();
+        for name in [
+            "work_packages.author_id",
+            "work_packages.assigned_to_id",
+            "journals.user_id",
+            "time_entries.user_id",
+            "watchers.user_id",
+            "members.user_id",
+            "tokens.user_id",
+        ] {
+            assert!(names.contains(&name.to_string()), "{} is not handled", name);
+        }
+    }
+
+    #[test]
+    fn test_orphan_sql() {
+        let author = UserReference { table: "work_packages", column: "author_id", action: OrphanAction::Reassign };
+        assert_eq!(author.orphan_sql(), "UPDATE work_packages SET author_id = $2 WHERE author_id = $1");
+
+        let assignee = UserReference { action: OrphanAction::Nullify, column: "assigned_to_id", ..author };
+        assert_eq!(
+            assignee.orphan_sql(),
+            "UPDATE work_packages SET assigned_to_id = NULL WHERE assigned_to_id = $1"
+        );
+
+        let watcher = UserReference { table: "watchers", column: "user_id", action: OrphanAction::Delete };
+        assert_eq!(watcher.orphan_sql(), "DELETE FROM watchers WHERE user_id = $1");
+        assert_eq!(watcher.count_sql(), "SELECT COUNT(*) FROM watchers WHERE user_id = $1");
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_finds_users_by_login() {
+        let store = MemoryUserStore::new();
+        let dto = CreateUserDto {
+            login: "admin".into(),
+            firstname: "Ada".into(),
+            lastname: "Admin".into(),
+            mail: "admin@example.com".into(),
+            admin: true,
+            status: status::ACTIVE,
+            language: None,
+            hashed_password: None,
+            salt: None,
+        };
+        let admin = store.create(dto.clone()).await.unwrap();
+        assert!(matches!(store.create(dto).await, Err(RepositoryError::Conflict(_))));
+        assert_eq!(store.find_by_login("admin").await.unwrap().map(|user| user.id), Some(admin.id));
+
+        let locked = store
+            .update(admin.id, UpdateUserDto { status: Some(status::LOCKED), ..Default::default() })
+            .await
+            .unwrap();
+        assert!(locked.is_locked() && locked.admin);
+        assert_eq!(locked.full_name(), "Ada Admin");
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::members::CreateMemberDto;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture};
+
+    #[tokio::test]
+    async fn test_time_zone_from_preferences() {
+        let db = TestDb::connect().await;
+        let alice = db.insert_user(UserFixture::new("tz-alice")).await;
+        let bob = db.insert_user(UserFixture::new("tz-bob")).await;
+        let carol = db.insert_user(UserFixture::new("tz-carol")).await;
+        sqlx::query(
+            "INSERT INTO user_preferences (user_id, settings) VALUES \
+             ($1, '{\"time_zone\": \"Australia/Brisbane\"}'), ($2, '{\"time_zone\": \"\"}')",
+        )
+        .bind(alice)
+        .bind(bob)
+        .execute(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+        let repo = db.users();
+
+        assert_eq!(repo.find_time_zone(alice).await.unwrap().as_deref(), Some("Australia/Brisbane"));
+        assert_eq!(repo.find_time_zone(bob).await.unwrap(), None);
+        assert_eq!(repo.find_time_zone(carol).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_hide_mail_from_preferences() {
+        let db = TestDb::connect().await;
+        let alice = db.insert_user(UserFixture::new("mail-alice")).await;
+        let bob = db.insert_user(UserFixture::new("mail-bob")).await;
+        let carol = db.insert_user(UserFixture::new("mail-carol")).await;
+        let dave = db.insert_user(UserFixture::new("mail-dave")).await;
+        sqlx::query(
+            "INSERT INTO user_preferences (user_id, settings) VALUES \
+             ($1, '{\"hide_mail\": true}'), ($2, '{\"hide_mail\": \"false\"}'), ($3, '{\"time_zone\": \"UTC\"}')",
+        )
+        .bind(alice)
+        .bind(bob)
+        .bind(carol)
+        .execute(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        let hidden = db.users().find_hide_mail(&[alice, bob, carol, dave]).await.unwrap();
+        assert_eq!(hidden, HashMap::from([(alice, true), (bob, false)]));
+    }
+
+    #[tokio::test]
+    async fn test_language() {
+        let db = TestDb::connect().await;
+        let alice = db.insert_user(UserFixture::new("lang-alice")).await;
+        let bob = db.insert_user(UserFixture::new("lang-bob")).await;
+        sqlx::query("UPDATE users SET language = CASE id WHEN $1 THEN 'de' ELSE '' END WHERE id IN ($1, $2)")
+            .bind(alice)
+            .bind(bob)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        let repo = db.users();
+
+        assert_eq!(repo.find_language(alice).await.unwrap().as_deref(), Some("de"));
+        assert_eq!(repo.find_language(bob).await.unwrap(), None);
+        assert_eq!(repo.find_language(0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_email_uniqueness_compares_normalized_addresses() {
+        let db = TestDb::connect().await;
+        let mut fixture = UserFixture::new("mail-owner");
+        fixture.mail = "User@Foo.com".to_string();
+        let owner = db.insert_user(fixture).await;
+        // Addresses are stored normalized, with the domain in punycode
+        let mut fixture = UserFixture::new("idn-owner");
+        fixture.mail = normalize_email("User@Bücher.example").unwrap();
+        db.insert_user(fixture).await;
+        let repo = db.users();
+
+        assert!(!repo.is_email_unique("user@foo.com", None).await.unwrap());
+        assert!(repo.is_email_unique("user@foo.com", Some(owner)).await.unwrap());
+        assert!(!repo.is_email_unique("user@bücher.example", None).await.unwrap());
+        assert!(!repo.is_email_unique(" USER@BÜCHER.EXAMPLE", None).await.unwrap());
+        assert!(repo.is_email_unique("other@bücher.example", None).await.unwrap());
+        assert_eq!(repo.find_by_email("user@foo.com").await.unwrap().map(|user| user.id), Some(owner));
+    }
+
+    #[tokio::test]
+    async fn test_principals_of_each_type() {
+        let db = TestDb::connect().await;
+        let user = db.insert_user(UserFixture::new("alice")).await;
+        let group = db.insert_user(UserFixture::group("Developers")).await;
+        let placeholder = db.insert_user(UserFixture::placeholder("Designer")).await;
+        db.insert_user(UserFixture::new("gone").with_status(status::DELETED)).await;
+        let repo = db.users();
+
+        let all = repo.find_principals(&[], false, Pagination::new(100, 0)).await.unwrap();
+        let ids: Vec<Id> = all.items.iter().map(|row| row.id).collect();
+        assert!(ids.contains(&user) && ids.contains(&group) && ids.contains(&placeholder));
+        assert!(all.items.iter().all(|row| row.status != status::DELETED));
+
+        let groups = repo
+            .find_principals(&[principal_type::GROUP], false, Pagination::new(100, 0))
+            .await
+            .unwrap();
+        assert!(groups.items.iter().all(UserRow::is_group));
+        let developers = groups.items.iter().find(|row| row.id == group).unwrap();
+        assert_eq!(developers.full_name(), "Developers");
+
+        let found = repo.find_by_id(placeholder).await.unwrap().unwrap();
+        assert!(found.is_placeholder());
+        assert_eq!(found.full_name(), "Designer");
+    }
+
+    #[tokio::test]
+    async fn test_assignable_principals_and_group_members() {
+        let db = TestDb::connect().await;
+        let assignee = db.insert_user(UserFixture::new("assignee")).await;
+        let watcher = db.insert_user(UserFixture::new("watcher")).await;
+        let locked = db.insert_user(UserFixture::new("locked").with_status(status::LOCKED)).await;
+        let group = db.insert_user(UserFixture::group("Developers")).await;
+        let project = db.insert_project(ProjectFixture::new("assignable")).await;
+        let assignable = db.insert_role("Assignable", &["work_package_assigned"]).await;
+        let viewer = db.insert_role("Viewer", &["view_work_packages"]).await;
+        for (user_id, role_id) in [(assignee, assignable), (watcher, viewer), (locked, assignable), (group, assignable)] {
+            db.members()
+                .create(CreateMemberDto {
+                    user_id,
+                    project_id: Some(project),
+                    role_ids: vec![role_id],
+                    entity_type: None,
+                    entity_id: None,
+                })
+                .await
+                .unwrap();
+        }
+        db.insert_group_member(group, assignee).await;
+        let repo = db.users();
+
+        let ids = |rows: Vec<UserRow>| rows.into_iter().map(|row| row.id).collect::<Vec<_>>();
+        let all = ids(repo.find_assignable(project, &[], false).await.unwrap());
+        assert_eq!(all.len(), 2);
+        assert!(all.contains(&assignee) && all.contains(&group));
+        let users = ids(repo.find_assignable(project, &[principal_type::USER], false).await.unwrap());
+        assert_eq!(users, vec![assignee]);
+        let with_locked = ids(repo.find_assignable(project, &[principal_type::USER], true).await.unwrap());
+        assert_eq!(with_locked.len(), 2);
+        assert!(with_locked.contains(&locked));
+
+        let members = repo.find_group_members(&[group]).await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].0, group);
+        assert_eq!(members[0].1.id, assignee);
+    }
+}
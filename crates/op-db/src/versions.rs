@@ -6,6 +6,8 @@ use async_trait::async_trait;
 use chrono::{DateTime, NaiveDate, Utc};
 use sqlx::{FromRow, PgPool};
 
+use op_core::error::PropertyError;
+
 use crate::{Pagination, PaginatedResult, Repository, RepositoryError};
 
 /// Version status constants
@@ -109,6 +111,27 @@ impl VersionRepository {
         Self { pool }
     }
 
+    /// Find versions by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[i64]) -> Result<Vec<VersionRow>, RepositoryError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, VersionRow>(
+            r#"
+            SELECT id, project_id, name, description, effective_date, start_date,
+                   status, sharing, wiki_page_title, created_at, updated_at
+            FROM versions
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
     /// Find all versions for a project
     pub async fn find_by_project(
         &self,
@@ -143,6 +166,7 @@ impl VersionRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -246,18 +270,48 @@ impl VersionRepository {
         Ok(count == 0)
     }
 
-    fn validate_dates(
+    /// Check the attributes of a version, reporting every invalid one
+    fn validate(
+        name: &str,
+        version_status: &str,
+        version_sharing: &str,
         start_date: Option<NaiveDate>,
         effective_date: Option<NaiveDate>,
     ) -> Result<(), RepositoryError> {
+        let mut errors = Vec::new();
+
+        if name.trim().is_empty() {
+            errors.push(PropertyError::new("name", "blank", "can't be blank"));
+        }
+        if !status::is_valid(version_status) {
+            errors.push(PropertyError::new(
+                "status",
+                "inclusion",
+                format!("must be one of: {}", status::all().join(", ")),
+            ));
+        }
+        if !sharing::is_valid(version_sharing) {
+            errors.push(PropertyError::new(
+                "sharing",
+                "inclusion",
+                format!("must be one of: {}", sharing::all().join(", ")),
+            ));
+        }
         if let (Some(start), Some(end)) = (start_date, effective_date) {
             if end < start {
-                return Err(RepositoryError::Validation(
-                    "Effective date must be greater than or equal to start date".to_string(),
+                errors.push(PropertyError::new(
+                    "effective_date",
+                    "greater_than_or_equal_to_start_date",
+                    "must be greater than or equal to start date",
                 ));
             }
         }
-        Ok(())
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(RepositoryError::Validation(errors))
+        }
     }
 }
 
@@ -321,31 +375,15 @@ impl Repository<VersionRow, CreateVersionDto, UpdateVersionDto> for VersionRepos
     }
 
     async fn create(&self, dto: CreateVersionDto) -> Result<VersionRow, RepositoryError> {
-        // Validate name
-        if dto.name.trim().is_empty() {
-            return Err(RepositoryError::Validation("Name can't be blank".to_string()));
-        }
-
-        // Validate status
         let version_status = dto.status.as_deref().unwrap_or(status::OPEN);
-        if !status::is_valid(version_status) {
-            return Err(RepositoryError::Validation(format!(
-                "Status must be one of: {}",
-                status::all().join(", ")
-            )));
-        }
-
-        // Validate sharing
         let version_sharing = dto.sharing.as_deref().unwrap_or(sharing::NONE);
-        if !sharing::is_valid(version_sharing) {
-            return Err(RepositoryError::Validation(format!(
-                "Sharing must be one of: {}",
-                sharing::all().join(", ")
-            )));
-        }
-
-        // Validate dates
-        Self::validate_dates(dto.start_date, dto.effective_date)?;
+        Self::validate(
+            &dto.name,
+            version_status,
+            version_sharing,
+            dto.start_date,
+            dto.effective_date,
+        )?;
 
         // Check name uniqueness
         if !self.is_name_unique(dto.project_id, &dto.name, None).await? {
@@ -396,29 +434,13 @@ impl Repository<VersionRow, CreateVersionDto, UpdateVersionDto> for VersionRepos
         let version_sharing = dto.sharing.unwrap_or(existing.sharing);
         let wiki_page_title = dto.wiki_page_title.or(existing.wiki_page_title);
 
-        // Validate name
-        if name.trim().is_empty() {
-            return Err(RepositoryError::Validation("Name can't be blank".to_string()));
-        }
-
-        // Validate status
-        if !status::is_valid(&version_status) {
-            return Err(RepositoryError::Validation(format!(
-                "Status must be one of: {}",
-                status::all().join(", ")
-            )));
-        }
-
-        // Validate sharing
-        if !sharing::is_valid(&version_sharing) {
-            return Err(RepositoryError::Validation(format!(
-                "Sharing must be one of: {}",
-                sharing::all().join(", ")
-            )));
-        }
-
-        // Validate dates
-        Self::validate_dates(start_date, effective_date)?;
+        Self::validate(
+            &name,
+            &version_status,
+            &version_sharing,
+            start_date,
+            effective_date,
+        )?;
 
         // Check name uniqueness
         if !self.is_name_unique(existing.project_id, &name, Some(id)).await? {
@@ -510,4 +532,35 @@ mod tests {
         assert!(sharing::is_valid("system"));
         assert!(!sharing::is_valid("unknown"));
     }
+
+    #[tokio::test]
+    async fn test_create_reports_every_invalid_attribute() {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        let repo = VersionRepository::new(pool);
+
+        let result = repo
+            .create(CreateVersionDto {
+                project_id: 1,
+                name: "  ".into(),
+                description: None,
+                effective_date: None,
+                start_date: None,
+                status: Some("archived".into()),
+                sharing: None,
+                wiki_page_title: None,
+            })
+            .await;
+
+        let Err(RepositoryError::Validation(errors)) = result else {
+            panic!("expected a validation error");
+        };
+        let properties: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|e| (e.property.as_str(), e.code.as_str()))
+            .collect();
+        assert_eq!(properties, vec![("name", "blank"), ("status", "inclusion")]);
+        assert_eq!(errors[0].full_message(), "Name can't be blank");
+    }
 }
@@ -7,6 +7,14 @@ use chrono::{DateTime, Utc};
 use sqlx::{FromRow, PgPool};
 
 use crate::{Pagination, PaginatedResult, Repository, RepositoryError};
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// Watchers of work packages that are gone; those of deleted users are
+/// covered by the user references
+pub const WATCHER_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::polymorphic("watchers", "watchable_id", "watchable_type", "WorkPackage", "work_packages", OrphanAction::Delete),
+];
 
 /// Watcher row from database
 #[derive(Debug, Clone, FromRow)]
@@ -90,6 +98,7 @@ impl WatcherRepository {
             total,
             limit: pagination.limit,
             offset: pagination.offset,
+            total_is_estimate: false,
         })
     }
 
@@ -102,6 +111,23 @@ impl WatcherRepository {
         self.find_by_watchable("WorkPackage", work_package_id, pagination).await
     }
 
+    /// Find the watchers of several work packages at once
+    pub async fn find_by_work_packages(&self, work_package_ids: &[i64]) -> Result<Vec<WatcherRow>, RepositoryError> {
+        let rows = sqlx::query_as::<_, WatcherRow>(
+            r#"
+            SELECT id, watchable_type, watchable_id, user_id
+            FROM watchers
+            WHERE watchable_type = 'WorkPackage' AND watchable_id = ANY($1)
+            ORDER BY watchable_id, id
+            "#,
+        )
+        .bind(work_package_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
     /// Find what a user is watching
     pub async fn find_by_user(
         &self,
@@ -160,6 +186,23 @@ impl WatcherRepository {
         Ok(count > 0)
     }
 
+    /// Count the watchers of an entity
+    pub async fn count_by_watchable(
+        &self,
+        watchable_type: &str,
+        watchable_id: i64,
+    ) -> Result<i64, RepositoryError> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM watchers WHERE watchable_type = $1 AND watchable_id = $2",
+        )
+        .bind(watchable_type)
+        .bind(watchable_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
     /// Find watcher by user and watchable
     pub async fn find_by_user_and_watchable(
         &self,
@@ -213,12 +256,11 @@ impl WatcherRepository {
         .await?;
 
         match user_status {
-            None => Err(RepositoryError::Validation(format!(
-                "User {} not found",
-                user_id
-            ))),
-            Some(status) if status == 3 || status == 4 => Err(RepositoryError::Validation(
-                "Locked or deleted users cannot watch entities".to_string(),
+            None => Err(RepositoryError::invalid("user", "does_not_exist", "does not exist")),
+            Some(status) if status == 3 || status == 4 => Err(RepositoryError::invalid(
+                "user",
+                "locked",
+                "is locked or deleted and cannot watch entities",
             )),
             _ => Ok(()),
         }
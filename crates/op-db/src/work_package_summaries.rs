@@ -0,0 +1,348 @@
+//! Work package summaries repository
+//!
+//! Table: work_package_summaries
+//!
+//! Counts of work packages by project, type, status and priority, so the
+//! overview widgets need not run work package queries. The writes of
+//! [`WorkPackageRepository`](crate::WorkPackageRepository) and of the
+//! cascades move work packages between the counts in their transactions;
+//! [`SummaryRepository::rebuild`] corrects drift, e.g. from writes of other
+//! applications to the same database. Work packages without a priority
+//! count under priority 0, those in the trash do not count.
+
+use op_core::traits::Id;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::repository::RepositoryResult;
+
+/// Attribute work package counts are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryDimension {
+    Type,
+    Status,
+    Priority,
+}
+
+impl SummaryDimension {
+    /// Dimension of an attribute name, e.g. `status`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "type" => Some(Self::Type),
+            "status" => Some(Self::Status),
+            "priority" => Some(Self::Priority),
+            _ => None,
+        }
+    }
+
+    fn column(self) -> &'static str {
+        match self {
+            Self::Type => "type_id",
+            Self::Status => "status_id",
+            Self::Priority => "priority_id",
+        }
+    }
+
+    /// Column of the dimension, or NULL when not grouped by it; work
+    /// packages without a priority have none
+    fn select(self, grouped: bool) -> String {
+        match (self, grouped) {
+            (_, false) => format!("NULL::BIGINT AS {}", self.column()),
+            (Self::Priority, true) => "NULLIF(priority_id, 0) AS priority_id".to_string(),
+            (_, true) => self.column().to_string(),
+        }
+    }
+}
+
+/// Number of work packages of a group; the ids of dimensions not grouped by
+/// are `None`
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct SummaryCount {
+    pub type_id: Option<Id>,
+    pub status_id: Option<Id>,
+    pub priority_id: Option<Id>,
+    pub count: i64,
+}
+
+/// Work package summaries repository
+pub struct SummaryRepository {
+    db: DbExecutor,
+}
+
+impl SummaryRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Counts of the work packages of a project grouped by the dimensions,
+    /// ordered by the ids of the groups; one total without dimensions
+    pub async fn counts_for_project(
+        &self,
+        project_id: Id,
+        dimensions: &[SummaryDimension],
+    ) -> RepositoryResult<Vec<SummaryCount>> {
+        let all = [SummaryDimension::Type, SummaryDimension::Status, SummaryDimension::Priority];
+        let select: Vec<String> = all.iter().map(|d| d.select(dimensions.contains(d))).collect();
+        let grouped: Vec<&str> = all
+            .iter()
+            .filter(|d| dimensions.contains(d))
+            .map(|d| d.column())
+            .collect();
+        let group_by = if grouped.is_empty() {
+            String::new()
+        } else {
+            format!("GROUP BY {0} ORDER BY {0}", grouped.join(", "))
+        };
+
+        let counts = sqlx::query_as::<_, SummaryCount>(&format!(
+            "SELECT {}, COALESCE(SUM(count), 0)::BIGINT AS count \
+             FROM work_package_summaries WHERE project_id = $1 AND count <> 0 {}",
+            select.join(", "),
+            group_by
+        ))
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// Recount the work packages and replace the summaries with the result;
+    /// returns the number of groups whose count was off
+    ///
+    /// The table is locked against the writes of work packages meanwhile, so
+    /// none of them is counted twice or lost.
+    pub async fn rebuild(&self) -> RepositoryResult<u64> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        sqlx::query("LOCK TABLE work_package_summaries IN EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+
+        let drifted = sqlx::query_scalar::<_, i64>(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM ({}) actual
+            FULL JOIN (SELECT * FROM work_package_summaries WHERE count <> 0) stored
+                USING (project_id, type_id, status_id, priority_id)
+            WHERE actual.count IS DISTINCT FROM stored.count
+            "#,
+            ACTUAL_COUNTS
+        ))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM work_package_summaries").execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "INSERT INTO work_package_summaries (project_id, type_id, status_id, priority_id, count) {}",
+            ACTUAL_COUNTS
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(drifted as u64)
+    }
+}
+
+/// Counts of the work packages as stored in the summaries
+const ACTUAL_COUNTS: &str = "SELECT project_id, type_id, status_id, COALESCE(priority_id, 0) AS priority_id, \
+    COUNT(*) AS count FROM work_packages WHERE deleted_at IS NULL GROUP BY project_id, type_id, status_id, COALESCE(priority_id, 0)";
+
+/// Add the work packages to the counts of their groups, e.g. after creating
+/// them, or with a negative `sign` take them away, e.g. before deleting
+/// them. Updates take the work packages away before and add them after.
+/// Work packages in the trash are not counted, so they are taken away
+/// before moving them there and added after restoring them.
+pub(crate) async fn count_work_packages(conn: &mut PgConnection, ids: &[Id], sign: i64) -> RepositoryResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO work_package_summaries AS s (project_id, type_id, status_id, priority_id, count)
+        SELECT project_id, type_id, status_id, COALESCE(priority_id, 0), COUNT(*) * $2
+        FROM work_packages
+        WHERE id = ANY($1) AND deleted_at IS NULL
+        GROUP BY project_id, type_id, status_id, COALESCE(priority_id, 0)
+        ON CONFLICT (project_id, type_id, status_id, priority_id)
+        DO UPDATE SET count = s.count + EXCLUDED.count
+        "#,
+    )
+    .bind(ids)
+    .bind(sign)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::repository::Repository;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+    use crate::work_packages::{CreateWorkPackageDto, DeleteCascade, TrashCascade, UpdateWorkPackageDto};
+    use crate::StatusRepository;
+
+    const ALL: [SummaryDimension; 3] = [SummaryDimension::Type, SummaryDimension::Status, SummaryDimension::Priority];
+
+    /// Counts of the work packages of the project outside the trash grouped
+    /// by every dimension
+    async fn actual(db: &TestDb, project_id: Id) -> Vec<SummaryCount> {
+        sqlx::query_as(
+            "SELECT type_id, status_id, priority_id, COUNT(*) AS count FROM work_packages \
+             WHERE project_id = $1 AND deleted_at IS NULL GROUP BY 1, 2, 3 ORDER BY 1, 2, 3",
+        )
+        .bind(project_id)
+        .fetch_all(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap()
+    }
+
+    async fn assert_consistent(db: &TestDb, project_id: Id, step: &str) {
+        let stored = db.summaries().counts_for_project(project_id, &ALL).await.unwrap();
+        assert_eq!(stored, actual(db, project_id).await, "after {}", step);
+    }
+
+    async fn insert_id(db: &TestDb, sql: &str) -> Id {
+        sqlx::query_scalar(sql)
+            .fetch_one(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn create_dto(project_id: Id, author_id: Id, type_id: Id, status_id: Id, priority_id: Option<Id>) -> CreateWorkPackageDto {
+        CreateWorkPackageDto {
+            subject: "Summarized".into(),
+            description: None,
+            project_id,
+            type_id,
+            status_id,
+            priority_id,
+            author_id,
+            assigned_to_id: None,
+            responsible_id: None,
+            start_date: None,
+            due_date: None,
+            estimated_hours: None,
+            done_ratio: 0,
+            parent_id: None,
+            version_id: None,
+            category_id: None,
+            duration: None,
+            ignore_non_working_days: None,
+            journal_cause: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_keep_the_summaries_consistent() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("summary-author")).await;
+        let project = db.insert_project(ProjectFixture::new("summaries")).await;
+        let first = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let template = db.work_packages().find_by_id(first).await.unwrap().unwrap();
+        let (task, new) = (template.type_id, template.status_id);
+        let closed = insert_id(&db, "INSERT INTO statuses (name, is_closed) VALUES ('Closed', TRUE) RETURNING id").await;
+        let bug = insert_id(&db, "INSERT INTO types (name) VALUES ('Bug') RETURNING id").await;
+        let high = insert_id(&db, "INSERT INTO enumerations (type, name) VALUES ('IssuePriority', 'High') RETURNING id").await;
+        let repo = db.work_packages();
+        assert_consistent(&db, project, "inserting").await;
+
+        let a = repo.create(create_dto(project, author, task, new, Some(high))).await.unwrap();
+        let b = repo.create(create_dto(project, author, bug, new, None)).await.unwrap();
+        let c = repo.create_journaled(create_dto(project, author, bug, new, Some(high))).await.unwrap();
+        assert_consistent(&db, project, "creating").await;
+
+        let update = UpdateWorkPackageDto { status_id: Some(closed), lock_version: 0, ..Default::default() };
+        repo.update(a.id, update).await.unwrap();
+        assert_consistent(&db, project, "updating").await;
+        repo.update_status(b.id, closed, 0).await.unwrap();
+        assert_consistent(&db, project, "updating the status").await;
+
+        // A failed update changes nothing
+        let stale = UpdateWorkPackageDto { type_id: Some(bug), lock_version: 0, ..Default::default() };
+        assert!(repo.update(a.id, stale).await.is_err());
+        assert_consistent(&db, project, "a conflict").await;
+
+        StatusRepository::with_executor(db.executor())
+            .delete_reassigning(closed, new, author)
+            .await
+            .unwrap();
+        assert_consistent(&db, project, "reassigning a status").await;
+
+        repo.delete(a.id).await.unwrap();
+        let mut deletion = repo.begin_deletion().await.unwrap();
+        deletion.delete_work_packages(&[b.id]).await.unwrap();
+        deletion.commit().await.unwrap();
+        assert_consistent(&db, project, "deleting").await;
+
+        let by_type = db.summaries().counts_for_project(project, &[SummaryDimension::Type]).await.unwrap();
+        assert_eq!(
+            by_type.iter().map(|g| (g.type_id, g.status_id, g.count)).collect::<Vec<_>>(),
+            vec![(Some(task), None, 1), (Some(bug), None, 1)]
+        );
+        let total = db.summaries().counts_for_project(project, &[]).await.unwrap();
+        assert_eq!(total[0].count, 2);
+        assert!(repo.exists(c.id).await.unwrap());
+
+        // Work packages in the trash do not count, and purging them does
+        // not uncount them twice
+        let mut trash = repo.begin_trash().await.unwrap();
+        trash.trash_work_packages(&[c.id], author).await.unwrap();
+        trash.commit().await.unwrap();
+        assert_consistent(&db, project, "trashing").await;
+        let total = db.summaries().counts_for_project(project, &[]).await.unwrap();
+        assert_eq!(total[0].count, 1);
+
+        let mut trash = repo.begin_trash().await.unwrap();
+        trash.restore_work_packages(&[c.id]).await.unwrap();
+        trash.commit().await.unwrap();
+        assert_consistent(&db, project, "restoring").await;
+
+        let mut trash = repo.begin_trash().await.unwrap();
+        trash.trash_work_packages(&[c.id], author).await.unwrap();
+        trash.commit().await.unwrap();
+        let mut deletion = repo.begin_deletion().await.unwrap();
+        deletion.delete_work_packages(&[c.id]).await.unwrap();
+        deletion.commit().await.unwrap();
+        assert_consistent(&db, project, "purging").await;
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_corrects_drift() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("drift-author")).await;
+        let project = db.insert_project(ProjectFixture::new("drift")).await;
+        for _ in 0..3 {
+            db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        }
+        let summaries = db.summaries();
+        assert_eq!(summaries.rebuild().await.unwrap(), 0);
+
+        // A corrupted count and a group without work packages
+        let mut conn = db.executor().acquire().await.unwrap();
+        sqlx::query("UPDATE work_package_summaries SET count = 7 WHERE project_id = $1")
+            .bind(project)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO work_package_summaries (project_id, type_id, status_id, priority_id, count) \
+             SELECT project_id, type_id + 1000, status_id, 0, 2 FROM work_package_summaries WHERE project_id = $1",
+        )
+        .bind(project)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        drop(conn);
+        assert_ne!(summaries.counts_for_project(project, &ALL).await.unwrap(), actual(&db, project).await);
+
+        assert_eq!(summaries.rebuild().await.unwrap(), 2);
+        assert_consistent(&db, project, "rebuilding").await;
+        assert_eq!(summaries.rebuild().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,176 @@
+//! Work package views repository
+//!
+//! Table: work_package_views
+//!
+//! When users last opened work packages. A work package has unseen changes
+//! for a user when it was updated, or journaled, after the user last viewed
+//! it, or when the user never viewed it. Views are recorded at most once per
+//! [`VIEW_THROTTLE`] for a user and work package, so that reloading a work
+//! package does not write on every request.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use op_core::traits::Id;
+use sqlx::PgPool;
+
+use crate::executor::DbExecutor;
+use crate::repository::RepositoryResult;
+
+/// Shortest interval between two recorded views of a work package by a user
+pub const VIEW_THROTTLE: Duration = Duration::minutes(1);
+
+/// Condition for work packages `wp` changed since the user last viewed them,
+/// comparing the view with the later of `updated_at` and the newest journal
+pub fn unseen_changes_sql(user_id: Id) -> String {
+    format!(
+        "NOT EXISTS (SELECT 1 FROM work_package_views wpv WHERE wpv.work_package_id = wp.id \
+         AND wpv.user_id = {} AND wpv.viewed_at >= GREATEST(wp.updated_at, (SELECT MAX(j.created_at) \
+         FROM journals j WHERE j.journable_type = 'WorkPackage' AND j.journable_id = wp.id)))",
+        user_id
+    )
+}
+
+/// Work package views repository
+pub struct WorkPackageViewRepository {
+    db: DbExecutor,
+}
+
+impl WorkPackageViewRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db }
+    }
+
+    /// Record that the user viewed the work package at `at`, unless a view
+    /// within [`VIEW_THROTTLE`] before was recorded; whether it was written
+    pub async fn record_view(&self, user_id: Id, work_package_id: Id, at: DateTime<Utc>) -> RepositoryResult<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO work_package_views (user_id, work_package_id, viewed_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, work_package_id) DO UPDATE SET viewed_at = EXCLUDED.viewed_at
+            WHERE work_package_views.viewed_at <= $4
+            "#,
+        )
+        .bind(user_id)
+        .bind(work_package_id)
+        .bind(at)
+        .bind(at - VIEW_THROTTLE)
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// When the user last viewed the work package
+    pub async fn viewed_at(&self, user_id: Id, work_package_id: Id) -> RepositoryResult<Option<DateTime<Utc>>> {
+        let viewed_at = sqlx::query_scalar(
+            "SELECT viewed_at FROM work_package_views WHERE user_id = $1 AND work_package_id = $2",
+        )
+        .bind(user_id)
+        .bind(work_package_id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(viewed_at)
+    }
+
+    /// The work packages among `work_package_ids` with changes the user has
+    /// not seen
+    pub async fn unseen(&self, user_id: Id, work_package_ids: &[Id]) -> RepositoryResult<HashSet<Id>> {
+        if work_package_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let ids: Vec<Id> = sqlx::query_scalar(&format!(
+            "SELECT wp.id FROM work_packages wp WHERE wp.id = ANY($1) AND {}",
+            unseen_changes_sql(user_id)
+        ))
+        .bind(work_package_ids)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(ids.into_iter().collect())
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    async fn set_updated_at(db: &TestDb, work_package: Id, updated_at: DateTime<Utc>) {
+        sqlx::query("UPDATE work_packages SET updated_at = $2 WHERE id = $1")
+            .bind(work_package)
+            .bind(updated_at)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_views_are_recorded_at_most_once_a_minute() {
+        let db = TestDb::connect().await;
+        let views = db.work_package_views();
+        let user = db.insert_user(UserFixture::new("viewer")).await;
+        let project = db.insert_project(ProjectFixture::new("viewed")).await;
+        let work_package = db.insert_work_package(WorkPackageFixture::new(project, user)).await;
+        let first = Utc::now();
+
+        assert!(views.record_view(user, work_package, first).await.unwrap());
+        assert!(!views.record_view(user, work_package, first + Duration::seconds(59)).await.unwrap());
+        assert_eq!(views.viewed_at(user, work_package).await.unwrap(), Some(first).map(truncate));
+
+        let later = first + VIEW_THROTTLE;
+        assert!(views.record_view(user, work_package, later).await.unwrap());
+        assert_eq!(views.viewed_at(user, work_package).await.unwrap(), Some(later).map(truncate));
+    }
+
+    #[tokio::test]
+    async fn test_unseen_changes_compare_updates_and_journals_with_the_view() {
+        let db = TestDb::connect().await;
+        let views = db.work_package_views();
+        let user = db.insert_user(UserFixture::new("reader")).await;
+        let project = db.insert_project(ProjectFixture::new("unseen")).await;
+        let mut ids = Vec::new();
+        for subject in ["Never viewed", "Seen", "Updated", "Journaled"] {
+            ids.push(db.insert_work_package(WorkPackageFixture::new(project, user).with_subject(subject)).await);
+        }
+        let viewed = Utc::now() - Duration::hours(1);
+        for id in &ids {
+            set_updated_at(&db, *id, viewed - Duration::hours(1)).await;
+        }
+        for id in &ids[1..] {
+            views.record_view(user, *id, viewed).await.unwrap();
+        }
+        set_updated_at(&db, ids[2], viewed + Duration::minutes(5)).await;
+        sqlx::query(
+            "INSERT INTO journals (journable_type, journable_id, user_id, version, data_type, data_id, created_at) \
+             VALUES ('WorkPackage', $1, $2, 1, 'Journal::WorkPackageJournal', 0, $3)",
+        )
+        .bind(ids[3])
+        .bind(user)
+        .bind(viewed + Duration::minutes(5))
+        .execute(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        let unseen = views.unseen(user, &ids).await.unwrap();
+        assert_eq!(unseen, HashSet::from([ids[0], ids[2], ids[3]]));
+
+        // Views are per user
+        let other = db.insert_user(UserFixture::new("other-reader")).await;
+        assert_eq!(views.unseen(other, &ids).await.unwrap().len(), 4);
+    }
+
+    /// Timestamps as stored, to the microsecond
+    fn truncate(at: DateTime<Utc>) -> DateTime<Utc> {
+        DateTime::from_timestamp_micros(at.timestamp_micros()).unwrap()
+    }
+}
@@ -3,11 +3,30 @@
 //! Database operations for work packages.
 
 use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
 use chrono::{DateTime, Utc};
+use op_core::metrics::{DomainMetrics, QueryTimer};
 use op_core::traits::Id;
+use op_queries::{Filter, FilterOperator, FilterValue, Query};
 use sqlx::{FromRow, PgPool, Row};
 
+use crate::executor::{DbExecutor, DbTransaction};
+use crate::query_executor::{
+    validate_filter, FilterError, FilterErrorKind, QueryValidationError, WorkPackageQueryExecutor,
+};
+use crate::work_package_summaries::count_work_packages;
 use crate::repository::{Pagination, PaginatedResult, Repository, RepositoryError, RepositoryResult};
+use crate::statuses::StatusRow;
+use crate::types::TypeRow;
+use crate::integrity::OrphanCheck;
+use crate::users::OrphanAction;
+
+/// Work packages whose parent is gone move to the top of the hierarchy
+pub const WORK_PACKAGE_ORPHANS: &[OrphanCheck] = &[
+    OrphanCheck::new("work_packages", "parent_id", "work_packages", OrphanAction::Nullify),
+];
 
 /// Work package database entity
 #[derive(Debug, Clone, FromRow)]
@@ -30,10 +49,95 @@ pub struct WorkPackageRow {
     pub version_id: Option<i64>,
     pub category_id: Option<i64>,
     pub lock_version: i32,
+    /// Working days from the start to the due date, both included
+    pub duration: Option<i32>,
+    /// Whether weekends and non-working days count towards the duration
+    pub ignore_non_working_days: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Work package referenced from formatted text, with the names shown
+/// next to the link
+#[derive(Debug, Clone, FromRow)]
+pub struct WorkPackageReferenceRow {
+    pub id: i64,
+    pub subject: String,
+    pub project_id: i64,
+    pub type_name: Option<String>,
+    pub status_name: Option<String>,
+    pub status_is_closed: Option<bool>,
+}
+
+/// Work package with the names and status color shown in condensed
+/// representations, e.g. next to notifications
+#[derive(Debug, Clone, FromRow)]
+pub struct CondensedWorkPackageRow {
+    pub id: Id,
+    pub subject: String,
+    pub project_id: Id,
+    pub project_name: Option<String>,
+    pub type_id: Id,
+    pub type_name: Option<String>,
+    pub status_id: Id,
+    pub status_name: Option<String>,
+    /// Hex code of the status color, e.g. `#1A67A3`
+    pub status_color: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Work package whose subject is similar to a text, with the trigram
+/// similarity of the two between 0 and 1
+#[derive(Debug, Clone, FromRow)]
+pub struct SimilarWorkPackageRow {
+    #[sqlx(flatten)]
+    pub work_package: CondensedWorkPackageRow,
+    pub similarity: f32,
+}
+
+/// Work package in the trash, with what decides whether it can be restored
+#[derive(Debug, Clone, FromRow)]
+pub struct TrashedWorkPackageRow {
+    pub id: Id,
+    pub subject: String,
+    pub project_id: Id,
+    pub parent_id: Option<Id>,
+    pub status_id: Id,
+    pub deleted_at: DateTime<Utc>,
+    /// User who moved the work package to the trash
+    pub deleted_by: Option<Id>,
+    /// Whether the project still exists and is not archived
+    pub project_active: bool,
+    /// Whether the status still exists
+    pub status_exists: bool,
+    /// Whether the parent is in the trash too
+    pub parent_trashed: bool,
+}
+
+/// Watcher of a work package together with the work package's project
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct WorkPackageWatcherRow {
+    pub work_package_id: Id,
+    pub project_id: Id,
+    pub user_id: Id,
+}
+
+/// Values a new work package of a type in a project defaults to, `None`
+/// where nothing is configured
+#[derive(Debug, Clone, Default, PartialEq, Eq, FromRow)]
+pub struct WorkPackageDefaultsRow {
+    /// Initial status of the type: the default status when the workflows
+    /// of the type start from it, else the first of the statuses they
+    /// start from
+    pub type_status_id: Option<Id>,
+    /// Status flagged as default
+    pub status_id: Option<Id>,
+    /// Priority flagged as default, if active
+    pub priority_id: Option<Id>,
+    /// Default version of the project, if still open
+    pub version_id: Option<Id>,
+}
+
 /// DTO for creating a work package
 #[derive(Debug, Clone)]
 pub struct CreateWorkPackageDto {
@@ -53,6 +157,44 @@ pub struct CreateWorkPackageDto {
     pub parent_id: Option<i64>,
     pub version_id: Option<i64>,
     pub category_id: Option<i64>,
+    pub duration: Option<i32>,
+    /// Defaults to false
+    pub ignore_non_working_days: Option<bool>,
+    /// Cause of the initial journal where one is written, when the author
+    /// did not set every attribute, e.g. `default_attribute_written`
+    pub journal_cause: Option<&'static str>,
+}
+
+impl CreateWorkPackageDto {
+    /// Row the work package would be created as, for dry runs. Nothing is
+    /// inserted, so the id is 0 and no id is drawn from the sequence.
+    pub fn preview(&self) -> WorkPackageRow {
+        let now = Utc::now();
+        WorkPackageRow {
+            id: 0,
+            subject: self.subject.clone(),
+            description: self.description.clone(),
+            project_id: self.project_id,
+            type_id: self.type_id,
+            status_id: self.status_id,
+            priority_id: self.priority_id,
+            author_id: self.author_id,
+            assigned_to_id: self.assigned_to_id,
+            responsible_id: self.responsible_id,
+            start_date: self.start_date,
+            due_date: self.due_date,
+            estimated_hours: self.estimated_hours,
+            done_ratio: self.done_ratio,
+            parent_id: self.parent_id,
+            version_id: self.version_id,
+            category_id: self.category_id,
+            lock_version: 0,
+            duration: self.duration,
+            ignore_non_working_days: self.ignore_non_working_days.unwrap_or(false),
+            created_at: now,
+            updated_at: now,
+        }
+    }
 }
 
 /// DTO for updating a work package
@@ -72,17 +214,36 @@ pub struct UpdateWorkPackageDto {
     pub parent_id: Option<i64>,
     pub version_id: Option<i64>,
     pub category_id: Option<i64>,
+    pub duration: Option<i32>,
+    pub ignore_non_working_days: Option<bool>,
     pub lock_version: i32,
 }
 
 /// Work package repository implementation
 pub struct WorkPackageRepository {
-    pool: PgPool,
+    db: DbExecutor,
+    metrics: Option<Arc<DomainMetrics>>,
 }
 
 impl WorkPackageRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_executor(pool.into())
+    }
+
+    /// Repository running its queries on the executor, e.g. a transaction
+    /// shared with other repositories
+    pub fn with_executor(db: DbExecutor) -> Self {
+        Self { db, metrics: None }
+    }
+
+    /// Record query durations into the given metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<DomainMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    fn timer(&self, method: &'static str) -> QueryTimer<'_> {
+        QueryTimer::start(self.metrics.as_deref(), "work_packages", method)
     }
 
     /// Find work packages by project ID
@@ -91,15 +252,16 @@ impl WorkPackageRepository {
         project_id: Id,
         pagination: Pagination,
     ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
+        let _timer = self.timer("find_by_project");
         let items = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             SELECT id, subject, description, project_id, type_id, status_id,
                    priority_id, author_id, assigned_to_id, responsible_id,
                    start_date, due_date, estimated_hours, done_ratio,
                    parent_id, version_id, category_id, lock_version,
-                   created_at, updated_at
+                   duration, ignore_non_working_days, created_at, updated_at
             FROM work_packages
-            WHERE project_id = $1
+            WHERE project_id = $1 AND deleted_at IS NULL
             ORDER BY id DESC
             LIMIT $2 OFFSET $3
             "#,
@@ -107,14 +269,14 @@ impl WorkPackageRepository {
         .bind(project_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM work_packages WHERE project_id = $1",
+            "SELECT COUNT(*) FROM work_packages WHERE project_id = $1 AND deleted_at IS NULL",
         )
         .bind(project_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -126,15 +288,16 @@ impl WorkPackageRepository {
         status_id: Id,
         pagination: Pagination,
     ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
+        let _timer = self.timer("find_by_status");
         let items = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             SELECT id, subject, description, project_id, type_id, status_id,
                    priority_id, author_id, assigned_to_id, responsible_id,
                    start_date, due_date, estimated_hours, done_ratio,
                    parent_id, version_id, category_id, lock_version,
-                   created_at, updated_at
+                   duration, ignore_non_working_days, created_at, updated_at
             FROM work_packages
-            WHERE status_id = $1
+            WHERE status_id = $1 AND deleted_at IS NULL
             ORDER BY id DESC
             LIMIT $2 OFFSET $3
             "#,
@@ -142,14 +305,14 @@ impl WorkPackageRepository {
         .bind(status_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM work_packages WHERE status_id = $1",
+            "SELECT COUNT(*) FROM work_packages WHERE status_id = $1 AND deleted_at IS NULL",
         )
         .bind(status_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -161,15 +324,16 @@ impl WorkPackageRepository {
         user_id: Id,
         pagination: Pagination,
     ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
+        let _timer = self.timer("find_by_assignee");
         let items = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             SELECT id, subject, description, project_id, type_id, status_id,
                    priority_id, author_id, assigned_to_id, responsible_id,
                    start_date, due_date, estimated_hours, done_ratio,
                    parent_id, version_id, category_id, lock_version,
-                   created_at, updated_at
+                   duration, ignore_non_working_days, created_at, updated_at
             FROM work_packages
-            WHERE assigned_to_id = $1
+            WHERE assigned_to_id = $1 AND deleted_at IS NULL
             ORDER BY id DESC
             LIMIT $2 OFFSET $3
             "#,
@@ -177,14 +341,14 @@ impl WorkPackageRepository {
         .bind(user_id)
         .bind(pagination.limit)
         .bind(pagination.offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         let total = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM work_packages WHERE assigned_to_id = $1",
+            "SELECT COUNT(*) FROM work_packages WHERE assigned_to_id = $1 AND deleted_at IS NULL",
         )
         .bind(user_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(PaginatedResult::new(items, total, pagination))
@@ -192,25 +356,212 @@ impl WorkPackageRepository {
 
     /// Find children of a work package
     pub async fn find_children(&self, parent_id: Id) -> RepositoryResult<Vec<WorkPackageRow>> {
+        let _timer = self.timer("find_children");
         let items = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             SELECT id, subject, description, project_id, type_id, status_id,
                    priority_id, author_id, assigned_to_id, responsible_id,
                    start_date, due_date, estimated_hours, done_ratio,
                    parent_id, version_id, category_id, lock_version,
-                   created_at, updated_at
+                   duration, ignore_non_working_days, created_at, updated_at
             FROM work_packages
-            WHERE parent_id = $1
+            WHERE parent_id = $1 AND deleted_at IS NULL
             ORDER BY id ASC
             "#,
         )
         .bind(parent_id)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(items)
+    }
+
+    /// Find work packages by ids in one query, for batch loading
+    pub async fn find_by_ids(&self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let _timer = self.timer("find_by_ids");
+        let items = sqlx::query_as::<_, WorkPackageRow>(
+            r#"
+            SELECT id, subject, description, project_id, type_id, status_id,
+                   priority_id, author_id, assigned_to_id, responsible_id,
+                   start_date, due_date, estimated_hours, done_ratio,
+                   parent_id, version_id, category_id, lock_version,
+                   duration, ignore_non_working_days, created_at, updated_at
+            FROM work_packages
+            WHERE id = ANY($1) AND deleted_at IS NULL
+            ORDER BY id ASC
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(items)
     }
 
+    /// Find the referenced work packages in one query. With `visible_to`,
+    /// only the work packages that user may view are returned.
+    pub async fn find_references(
+        &self,
+        ids: &[Id],
+        visible_to: Option<Id>,
+    ) -> RepositoryResult<Vec<WorkPackageReferenceRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let visibility = visible_to
+            .map(|user_id| format!("AND {}", crate::query_executor::visible_work_packages_sql(user_id)))
+            .unwrap_or_default();
+        let sql = format!(
+            r#"
+            SELECT wp.id, wp.subject, wp.project_id,
+                   t.name AS type_name, s.name AS status_name, s.is_closed AS status_is_closed
+            FROM work_packages wp
+            LEFT JOIN types t ON t.id = wp.type_id
+            LEFT JOIN statuses s ON s.id = wp.status_id
+            WHERE wp.id = ANY($1) AND wp.deleted_at IS NULL {}
+            ORDER BY wp.id ASC
+            "#,
+            visibility
+        );
+
+        let _timer = self.timer("find_references");
+        let items = sqlx::query_as::<_, WorkPackageReferenceRow>(&sql)
+            .bind(ids)
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(items)
+    }
+
+    /// Find the work packages in condensed form in one query. With
+    /// `visible_to`, only the work packages that user may view are returned.
+    pub async fn find_condensed(
+        &self,
+        ids: &[Id],
+        visible_to: Option<Id>,
+    ) -> RepositoryResult<Vec<CondensedWorkPackageRow>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let visibility = visible_to
+            .map(|user_id| format!("AND {}", crate::query_executor::visible_work_packages_sql(user_id)))
+            .unwrap_or_default();
+        let sql = format!(
+            r#"
+            SELECT wp.id, wp.subject, wp.project_id, p.name AS project_name,
+                   wp.type_id, t.name AS type_name,
+                   wp.status_id, s.name AS status_name, c.hexcode AS status_color,
+                   wp.updated_at
+            FROM work_packages wp
+            LEFT JOIN projects p ON p.id = wp.project_id
+            LEFT JOIN types t ON t.id = wp.type_id
+            LEFT JOIN statuses s ON s.id = wp.status_id
+            LEFT JOIN colors c ON c.id = s.color_id
+            WHERE wp.id = ANY($1) AND wp.deleted_at IS NULL {}
+            ORDER BY wp.id ASC
+            "#,
+            visibility
+        );
+
+        let _timer = self.timer("find_condensed");
+        let items = sqlx::query_as::<_, CondensedWorkPackageRow>(&sql)
+            .bind(ids)
+            .fetch_all(&mut *self.db.acquire().await?)
+            .await?;
+
+        Ok(items)
+    }
+
+    /// Work packages of the project whose subjects are at least `threshold`
+    /// similar to `text`, the most similar first, only those `visible_to`
+    /// may view when given. The subject trigram index keeps this fast
+    /// enough to run while the subject is typed.
+    pub async fn find_similar_subjects(
+        &self,
+        project_id: Id,
+        text: &str,
+        limit: i64,
+        threshold: f32,
+        visible_to: Option<Id>,
+    ) -> RepositoryResult<Vec<SimilarWorkPackageRow>> {
+        let text = text.trim();
+        if text.is_empty() || limit <= 0 {
+            return Ok(Vec::new());
+        }
+
+        let visibility = visible_to
+            .map(|user_id| format!("AND {}", crate::query_executor::visible_work_packages_sql(user_id)))
+            .unwrap_or_default();
+        let sql = format!(
+            r#"
+            SELECT wp.id, wp.subject, wp.project_id, p.name AS project_name,
+                   wp.type_id, t.name AS type_name,
+                   wp.status_id, s.name AS status_name, c.hexcode AS status_color,
+                   wp.updated_at, similarity(wp.subject, $2) AS similarity
+            FROM work_packages wp
+            LEFT JOIN projects p ON p.id = wp.project_id
+            LEFT JOIN types t ON t.id = wp.type_id
+            LEFT JOIN statuses s ON s.id = wp.status_id
+            LEFT JOIN colors c ON c.id = s.color_id
+            WHERE wp.project_id = $1 AND wp.deleted_at IS NULL AND wp.subject % $2 {}
+            ORDER BY similarity DESC, wp.id DESC
+            LIMIT $3
+            "#,
+            visibility
+        );
+
+        let _timer = self.timer("find_similar_subjects");
+        // The threshold of `%` is a setting, local to this transaction
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        sqlx::query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+            .bind(threshold.clamp(0.0, 1.0).to_string())
+            .execute(&mut *tx)
+            .await?;
+        let items = sqlx::query_as::<_, SimilarWorkPackageRow>(&sql)
+            .bind(project_id)
+            .bind(text)
+            .bind(limit)
+            .fetch_all(&mut *tx)
+            .await?;
+        tx.rollback().await?;
+
+        Ok(items)
+    }
+
+    /// Defaults of new work packages of the type in the project
+    pub async fn find_defaults(&self, project_id: Id, type_id: Id) -> RepositoryResult<WorkPackageDefaultsRow> {
+        let _timer = self.timer("find_defaults");
+        let row = sqlx::query_as::<_, WorkPackageDefaultsRow>(
+            r#"
+            SELECT
+                (SELECT s.id FROM statuses s
+                 WHERE EXISTS (SELECT 1 FROM workflows w WHERE w.type_id = $2 AND w.old_status_id = s.id)
+                 ORDER BY s.is_default DESC, s.position, s.id
+                 LIMIT 1) AS type_status_id,
+                (SELECT id FROM statuses WHERE is_default = true ORDER BY position, id LIMIT 1) AS status_id,
+                (SELECT id FROM enumerations
+                 WHERE type = 'IssuePriority' AND is_default = true AND active = true
+                 ORDER BY position, id
+                 LIMIT 1) AS priority_id,
+                (SELECT v.id FROM projects p
+                 JOIN versions v ON v.id = p.default_version_id
+                 WHERE p.id = $1 AND v.status = 'open') AS version_id
+            "#,
+        )
+        .bind(project_id)
+        .bind(type_id)
+        .fetch_one(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(row)
+    }
+
     /// Update the status of a work package
     pub async fn update_status(
         &self,
@@ -218,6 +569,9 @@ impl WorkPackageRepository {
         status_id: Id,
         lock_version: i32,
     ) -> RepositoryResult<WorkPackageRow> {
+        let _timer = self.timer("update_status");
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        count_work_packages(&mut tx, &[id], -1).await?;
         let row = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             UPDATE work_packages
@@ -227,75 +581,185 @@ impl WorkPackageRepository {
                       priority_id, author_id, assigned_to_id, responsible_id,
                       start_date, due_date, estimated_hours, done_ratio,
                       parent_id, version_id, category_id, lock_version,
-                      created_at, updated_at
+                      duration, ignore_non_working_days, created_at, updated_at
             "#,
         )
         .bind(status_id)
         .bind(id)
         .bind(lock_version)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *tx)
         .await?
         .ok_or_else(|| {
             RepositoryError::Conflict("Work package was modified by another user".to_string())
         })?;
+        count_work_packages(&mut tx, &[id], 1).await?;
+        tx.commit().await?;
+
+        Ok(row)
+    }
+
+    /// Move a work package to the dates its predecessors allow. Unlike an
+    /// update by a user, it does not check the lock version but increments
+    /// it, so that a user holding the former dates gets a conflict.
+    pub async fn reschedule(
+        &self,
+        id: Id,
+        start_date: Option<chrono::NaiveDate>,
+        due_date: Option<chrono::NaiveDate>,
+        duration: Option<i32>,
+    ) -> RepositoryResult<WorkPackageRow> {
+        let _timer = self.timer("reschedule");
+        let row = sqlx::query_as::<_, WorkPackageRow>(
+            r#"
+            UPDATE work_packages
+            SET start_date = $2, due_date = $3, duration = $4,
+                lock_version = lock_version + 1, updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, subject, description, project_id, type_id, status_id,
+                      priority_id, author_id, assigned_to_id, responsible_id,
+                      start_date, due_date, estimated_hours, done_ratio,
+                      parent_id, version_id, category_id, lock_version,
+                      duration, ignore_non_working_days, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(start_date)
+        .bind(due_date)
+        .bind(duration)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?
+        .ok_or_else(|| RepositoryError::NotFound(format!("Work package {} not found", id)))?;
+
+        Ok(row)
+    }
+
+    /// Find the work packages of a project with a start or due date, as
+    /// shown on a timeline, earliest first
+    pub async fn find_scheduled_in_project(&self, project_id: Id) -> RepositoryResult<Vec<WorkPackageRow>> {
+        let _timer = self.timer("find_scheduled_in_project");
+        let items = sqlx::query_as::<_, WorkPackageRow>(
+            r#"
+            SELECT id, subject, description, project_id, type_id, status_id,
+                   priority_id, author_id, assigned_to_id, responsible_id,
+                   start_date, due_date, estimated_hours, done_ratio,
+                   parent_id, version_id, category_id, lock_version,
+                   duration, ignore_non_working_days, created_at, updated_at
+            FROM work_packages
+            WHERE project_id = $1 AND deleted_at IS NULL AND (start_date IS NOT NULL OR due_date IS NOT NULL)
+            ORDER BY COALESCE(start_date, due_date), id
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(items)
+    }
+
+    /// Find a work package in the trash
+    pub async fn find_trashed(&self, id: Id) -> RepositoryResult<Option<TrashedWorkPackageRow>> {
+        let _timer = self.timer("find_trashed");
+        let row = sqlx::query_as::<_, TrashedWorkPackageRow>(&format!(
+            "{} WHERE wp.id = $1 AND wp.deleted_at IS NOT NULL",
+            TRASHED_WORK_PACKAGES
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?;
 
         Ok(row)
     }
 }
 
+/// Select of [`TrashedWorkPackageRow`]s from `work_packages wp`
+const TRASHED_WORK_PACKAGES: &str = r#"
+    SELECT wp.id, wp.subject, wp.project_id, wp.parent_id, wp.status_id, wp.deleted_at, wp.deleted_by,
+           COALESCE(p.active, FALSE) AS project_active,
+           st.id IS NOT NULL AS status_exists,
+           COALESCE(parent.deleted_at IS NOT NULL, FALSE) AS parent_trashed
+    FROM work_packages wp
+    LEFT JOIN projects p ON p.id = wp.project_id
+    LEFT JOIN statuses st ON st.id = wp.status_id
+    LEFT JOIN work_packages parent ON parent.id = wp.parent_id
+"#;
+
+/// Watchers of the work packages with their projects
+async fn find_watchers(conn: &mut sqlx::PgConnection, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>> {
+    let rows = sqlx::query_as::<_, WorkPackageWatcherRow>(
+        r#"
+        SELECT wp.id AS work_package_id, wp.project_id, w.user_id
+        FROM watchers w
+        JOIN work_packages wp ON wp.id = w.watchable_id
+        WHERE w.watchable_type = 'WorkPackage' AND w.watchable_id = ANY($1)
+        ORDER BY wp.id, w.user_id
+        "#,
+    )
+    .bind(ids)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows)
+}
+
 #[async_trait]
 impl Repository<WorkPackageRow, CreateWorkPackageDto, UpdateWorkPackageDto>
     for WorkPackageRepository
 {
     async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<WorkPackageRow>> {
+        let _timer = self.timer("find_by_id");
         let row = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             SELECT id, subject, description, project_id, type_id, status_id,
                    priority_id, author_id, assigned_to_id, responsible_id,
                    start_date, due_date, estimated_hours, done_ratio,
                    parent_id, version_id, category_id, lock_version,
-                   created_at, updated_at
+                   duration, ignore_non_working_days, created_at, updated_at
             FROM work_packages
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(row)
     }
 
     async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<WorkPackageRow>> {
+        let _timer = self.timer("find_all");
         let rows = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             SELECT id, subject, description, project_id, type_id, status_id,
                    priority_id, author_id, assigned_to_id, responsible_id,
                    start_date, due_date, estimated_hours, done_ratio,
                    parent_id, version_id, category_id, lock_version,
-                   created_at, updated_at
+                   duration, ignore_non_working_days, created_at, updated_at
             FROM work_packages
+            WHERE deleted_at IS NULL
             ORDER BY id DESC
             LIMIT $1 OFFSET $2
             "#,
         )
         .bind(limit)
         .bind(offset)
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(rows)
     }
 
     async fn count(&self) -> RepositoryResult<i64> {
-        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM work_packages")
-            .fetch_one(&self.pool)
+        let _timer = self.timer("count");
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM work_packages WHERE deleted_at IS NULL")
+            .fetch_one(&mut *self.db.acquire().await?)
             .await?;
 
         Ok(count)
     }
 
     async fn create(&self, dto: CreateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+        let _timer = self.timer("create");
+        let mut tx = DbTransaction::begin(&self.db).await?;
         let row = sqlx::query_as::<_, WorkPackageRow>(
             r#"
             INSERT INTO work_packages (
@@ -303,15 +767,16 @@ impl Repository<WorkPackageRow, CreateWorkPackageDto, UpdateWorkPackageDto>
                 priority_id, author_id, assigned_to_id, responsible_id,
                 start_date, due_date, estimated_hours, done_ratio,
                 parent_id, version_id, category_id, lock_version,
-                created_at, updated_at
+                duration, ignore_non_working_days, created_at, updated_at
             ) VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, 0, NOW(), NOW()
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, 0, $17,
+                COALESCE($18, false), NOW(), NOW()
             )
             RETURNING id, subject, description, project_id, type_id, status_id,
                       priority_id, author_id, assigned_to_id, responsible_id,
                       start_date, due_date, estimated_hours, done_ratio,
                       parent_id, version_id, category_id, lock_version,
-                      created_at, updated_at
+                      duration, ignore_non_working_days, created_at, updated_at
             "#,
         )
         .bind(&dto.subject)
@@ -330,70 +795,32 @@ impl Repository<WorkPackageRow, CreateWorkPackageDto, UpdateWorkPackageDto>
         .bind(dto.parent_id)
         .bind(dto.version_id)
         .bind(dto.category_id)
-        .fetch_one(&self.pool)
+        .bind(dto.duration)
+        .bind(dto.ignore_non_working_days)
+        .fetch_one(&mut *tx)
         .await?;
+        count_work_packages(&mut tx, &[row.id], 1).await?;
+        tx.commit().await?;
 
         Ok(row)
     }
 
     async fn update(&self, id: Id, dto: UpdateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
-        // Build dynamic update query
-        let row = sqlx::query_as::<_, WorkPackageRow>(
-            r#"
-            UPDATE work_packages SET
-                subject = COALESCE($1, subject),
-                description = COALESCE($2, description),
-                type_id = COALESCE($3, type_id),
-                status_id = COALESCE($4, status_id),
-                priority_id = COALESCE($5, priority_id),
-                assigned_to_id = $6,
-                responsible_id = $7,
-                start_date = $8,
-                due_date = $9,
-                estimated_hours = $10,
-                done_ratio = COALESCE($11, done_ratio),
-                parent_id = $12,
-                version_id = $13,
-                category_id = $14,
-                lock_version = lock_version + 1,
-                updated_at = NOW()
-            WHERE id = $15 AND lock_version = $16
-            RETURNING id, subject, description, project_id, type_id, status_id,
-                      priority_id, author_id, assigned_to_id, responsible_id,
-                      start_date, due_date, estimated_hours, done_ratio,
-                      parent_id, version_id, category_id, lock_version,
-                      created_at, updated_at
-            "#,
-        )
-        .bind(&dto.subject)
-        .bind(&dto.description)
-        .bind(dto.type_id)
-        .bind(dto.status_id)
-        .bind(dto.priority_id)
-        .bind(dto.assigned_to_id)
-        .bind(dto.responsible_id)
-        .bind(dto.start_date)
-        .bind(dto.due_date)
-        .bind(dto.estimated_hours)
-        .bind(dto.done_ratio)
-        .bind(dto.parent_id)
-        .bind(dto.version_id)
-        .bind(dto.category_id)
-        .bind(id)
-        .bind(dto.lock_version)
-        .fetch_optional(&self.pool)
-        .await?
-        .ok_or_else(|| {
-            RepositoryError::Conflict("Work package was modified by another user".to_string())
-        })?;
+        let _timer = self.timer("update");
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        let row = update_row(&mut tx, id, &dto).await?;
+        tx.commit().await?;
 
         Ok(row)
     }
 
     async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        let _timer = self.timer("delete");
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        count_work_packages(&mut tx, &[id], -1).await?;
         let result = sqlx::query("DELETE FROM work_packages WHERE id = $1")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
 
         if result.rows_affected() == 0 {
@@ -402,18 +829,1969 @@ impl Repository<WorkPackageRow, CreateWorkPackageDto, UpdateWorkPackageDto>
                 id
             )));
         }
+        tx.commit().await?;
 
         Ok(())
     }
 
     async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        let _timer = self.timer("exists");
         let exists = sqlx::query_scalar::<_, bool>(
-            "SELECT EXISTS(SELECT 1 FROM work_packages WHERE id = $1)",
+            "SELECT EXISTS(SELECT 1 FROM work_packages WHERE id = $1 AND deleted_at IS NULL)",
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(exists)
     }
 }
+
+/// Work packages as the API handlers need them, stored in the database or,
+/// in the embedded mode, in memory
+#[async_trait]
+pub trait WorkPackageStore: Repository<WorkPackageRow, CreateWorkPackageDto, UpdateWorkPackageDto> {
+    /// Work packages of the query's project, or of all projects, matching
+    /// its filters; `me` in the filters stands for the current user
+    async fn find_matching(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<WorkPackageRow>>;
+}
+
+#[async_trait]
+impl WorkPackageStore for WorkPackageRepository {
+    async fn find_matching(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
+        let result = WorkPackageQueryExecutor::with_executor(self.db.clone())
+            .execute(query, pagination, current_user_id)
+            .await?;
+
+        Ok(PaginatedResult {
+            items: result.items.into_iter().map(WorkPackageRow::from).collect(),
+            total: result.total,
+            limit: result.limit,
+            offset: result.offset,
+            total_is_estimate: result.total_is_estimate,
+        })
+    }
+}
+
+/// Work packages of a single process. There is no trash: deleted work
+/// packages are gone, and queries see neither subprojects nor the
+/// attributes of statuses, types and other associations.
+#[derive(Default)]
+pub struct MemoryWorkPackageStore {
+    work_packages: Mutex<BTreeMap<Id, WorkPackageRow>>,
+}
+
+impl MemoryWorkPackageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Repository<WorkPackageRow, CreateWorkPackageDto, UpdateWorkPackageDto> for MemoryWorkPackageStore {
+    async fn find_by_id(&self, id: Id) -> RepositoryResult<Option<WorkPackageRow>> {
+        Ok(self.work_packages.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn find_all(&self, limit: i64, offset: i64) -> RepositoryResult<Vec<WorkPackageRow>> {
+        let work_packages = self.work_packages.lock().unwrap();
+        Ok(work_packages.values().skip(offset as usize).take(limit as usize).cloned().collect())
+    }
+
+    async fn count(&self) -> RepositoryResult<i64> {
+        Ok(self.work_packages.lock().unwrap().len() as i64)
+    }
+
+    async fn create(&self, dto: CreateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+        let mut work_packages = self.work_packages.lock().unwrap();
+        let id = work_packages.keys().next_back().copied().unwrap_or(0) + 1;
+        let row = WorkPackageRow { id, ..dto.preview() };
+        work_packages.insert(id, row.clone());
+        Ok(row)
+    }
+
+    /// Like the database update, the attributes that can be cleared are
+    /// replaced even when unset
+    async fn update(&self, id: Id, dto: UpdateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+        let mut work_packages = self.work_packages.lock().unwrap();
+        let row = work_packages
+            .get_mut(&id)
+            .ok_or_else(|| RepositoryError::NotFound(format!("Work package with id {} not found", id)))?;
+        if row.lock_version != dto.lock_version {
+            return Err(RepositoryError::Conflict("Work package was modified by another user".to_string()));
+        }
+
+        if let Some(subject) = dto.subject {
+            row.subject = subject;
+        }
+        row.description = dto.description.or(row.description.take());
+        row.type_id = dto.type_id.unwrap_or(row.type_id);
+        row.status_id = dto.status_id.unwrap_or(row.status_id);
+        row.priority_id = dto.priority_id.or(row.priority_id);
+        row.assigned_to_id = dto.assigned_to_id;
+        row.responsible_id = dto.responsible_id;
+        row.start_date = dto.start_date;
+        row.due_date = dto.due_date;
+        row.estimated_hours = dto.estimated_hours;
+        row.done_ratio = dto.done_ratio.unwrap_or(row.done_ratio);
+        row.parent_id = dto.parent_id;
+        row.version_id = dto.version_id;
+        row.category_id = dto.category_id;
+        row.duration = dto.duration.or(row.duration);
+        row.ignore_non_working_days = dto.ignore_non_working_days.unwrap_or(row.ignore_non_working_days);
+        row.lock_version += 1;
+        row.updated_at = Utc::now();
+        Ok(row.clone())
+    }
+
+    async fn delete(&self, id: Id) -> RepositoryResult<()> {
+        self.work_packages
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .map(|_| ())
+            .ok_or_else(|| RepositoryError::NotFound(format!("Work package with id {} not found", id)))
+    }
+
+    async fn exists(&self, id: Id) -> RepositoryResult<bool> {
+        Ok(self.work_packages.lock().unwrap().contains_key(&id))
+    }
+}
+
+#[async_trait]
+impl WorkPackageStore for MemoryWorkPackageStore {
+    async fn find_matching(
+        &self,
+        query: &Query,
+        pagination: &Pagination,
+        current_user_id: Option<Id>,
+    ) -> RepositoryResult<PaginatedResult<WorkPackageRow>> {
+        let filters = query.filters.filters();
+        let errors: Vec<FilterError> = filters
+            .iter()
+            .filter_map(|filter| validate_filter(filter).and_then(|_| evaluable(filter)).err())
+            .collect();
+        if !errors.is_empty() {
+            return Err(QueryValidationError { errors }.into());
+        }
+
+        let work_packages = self.work_packages.lock().unwrap();
+        let matching: Vec<&WorkPackageRow> = work_packages
+            .values()
+            .filter(|row| query.project_id.is_none_or(|project_id| row.project_id == project_id))
+            .filter(|row| filters.iter().all(|filter| matches_filter(row, filter, current_user_id)))
+            .collect();
+        let items = matching
+            .iter()
+            .skip(pagination.offset as usize)
+            .take(pagination.limit as usize)
+            .map(|row| (*row).clone())
+            .collect();
+        Ok(PaginatedResult::new(items, matching.len() as i64, *pagination))
+    }
+}
+
+/// Id attributes the memory store filters by
+const MEMORY_ID_ATTRIBUTES: [&str; 11] = [
+    "id",
+    "project_id",
+    "type_id",
+    "status_id",
+    "priority_id",
+    "author_id",
+    "assigned_to_id",
+    "responsible_id",
+    "version_id",
+    "category_id",
+    "parent_id",
+];
+
+/// Value of one of the [`MEMORY_ID_ATTRIBUTES`]
+fn id_attribute(row: &WorkPackageRow, attribute: &str) -> Option<Id> {
+    match attribute {
+        "id" => Some(row.id),
+        "project_id" => Some(row.project_id),
+        "type_id" => Some(row.type_id),
+        "status_id" => Some(row.status_id),
+        "priority_id" => row.priority_id,
+        "author_id" => Some(row.author_id),
+        "assigned_to_id" => row.assigned_to_id,
+        "responsible_id" => row.responsible_id,
+        "version_id" => row.version_id,
+        "category_id" => row.category_id,
+        "parent_id" => row.parent_id,
+        _ => None,
+    }
+}
+
+/// Check that the memory store can evaluate a valid filter: one comparing
+/// ids or the text of the subject or description
+fn evaluable(filter: &Filter) -> Result<(), FilterError> {
+    let text = matches!(filter.attribute.as_str(), "subject" | "description");
+    if !text && !MEMORY_ID_ATTRIBUTES.contains(&filter.attribute.as_str()) {
+        return Err(FilterError::new(
+            filter,
+            FilterErrorKind::UnknownAttribute,
+            "cannot be filtered without a database",
+        ));
+    }
+    let supported = match filter.operator {
+        FilterOperator::Equals | FilterOperator::NotEquals | FilterOperator::IsNull | FilterOperator::IsNotNull => true,
+        FilterOperator::Contains
+        | FilterOperator::NotContains
+        | FilterOperator::StartsWith
+        | FilterOperator::EndsWith => text,
+        _ => false,
+    };
+    if !supported {
+        return Err(FilterError::new(
+            filter,
+            FilterErrorKind::UnsupportedOperator,
+            format!("does not support the operator '{}'", filter.operator.symbol()),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the row matches a filter the memory store can evaluate
+fn matches_filter(row: &WorkPackageRow, filter: &Filter, current_user_id: Option<Id>) -> bool {
+    let text = match filter.attribute.as_str() {
+        "subject" => Some(Some(row.subject.as_str())),
+        "description" => Some(row.description.as_deref()),
+        _ => None,
+    };
+    if let Some(text) = text {
+        return matches_text(text.filter(|text| !text.is_empty()), filter);
+    }
+
+    let value = id_attribute(row, &filter.attribute);
+    let ids: Vec<Id> = match &filter.values {
+        FilterValue::Id(id) => vec![*id],
+        FilterValue::Ids(ids) => ids.clone(),
+        FilterValue::Me => current_user_id.into_iter().collect(),
+        FilterValue::IdsAndMe(ids) => current_user_id.into_iter().chain(ids.iter().copied()).collect(),
+        _ => Vec::new(),
+    };
+    match filter.operator {
+        FilterOperator::Equals => value.is_some_and(|value| ids.contains(&value)),
+        FilterOperator::NotEquals => value.is_some_and(|value| !ids.contains(&value)),
+        FilterOperator::IsNull => value.is_none(),
+        FilterOperator::IsNotNull => value.is_some(),
+        _ => false,
+    }
+}
+
+/// Whether a text matches a filter, compared case-insensitively like the
+/// database does for contains, starts and ends with
+fn matches_text(text: Option<&str>, filter: &Filter) -> bool {
+    let values: Vec<String> = match &filter.values {
+        FilterValue::String(value) | FilterValue::Date(value) => vec![value.clone()],
+        FilterValue::Strings(values) => values.clone(),
+        FilterValue::Id(id) => vec![id.to_string()],
+        FilterValue::Ids(ids) => ids.iter().map(Id::to_string).collect(),
+        _ => Vec::new(),
+    };
+    let lowercase = text.map(str::to_lowercase);
+    let any = |matches: fn(&str, &str) -> bool| {
+        lowercase
+            .as_deref()
+            .is_some_and(|text| values.iter().any(|value| matches(text, &value.to_lowercase())))
+    };
+    match filter.operator {
+        FilterOperator::Equals => text.is_some_and(|text| values.iter().any(|value| value == text)),
+        FilterOperator::NotEquals => text.is_some_and(|text| values.iter().all(|value| value != text)),
+        FilterOperator::Contains => any(|text, value| text.contains(value)),
+        FilterOperator::NotContains => !any(|text, value| text.contains(value)),
+        FilterOperator::StartsWith => any(|text, value| text.starts_with(value)),
+        FilterOperator::EndsWith => any(|text, value| text.ends_with(value)),
+        FilterOperator::IsNull => text.is_none(),
+        FilterOperator::IsNotNull => text.is_some(),
+        _ => false,
+    }
+}
+
+/// Steps of deleting work packages together with their dependent records
+///
+/// Implementations run all steps in one unit of work: nothing is visible
+/// until `commit`, and `rollback` undoes every step taken so far.
+#[async_trait]
+pub trait DeleteCascade: Send {
+    /// Ids of all descendants of a work package, children first
+    async fn find_descendant_ids(&mut self, id: Id) -> RepositoryResult<Vec<Id>>;
+
+    /// Work packages moved to the trash before the time together with
+    /// their descendants in the trash, children first
+    async fn find_expired(&mut self, trashed_before: DateTime<Utc>) -> RepositoryResult<Vec<TrashedWorkPackageRow>>;
+
+    /// Watchers of the work packages, read before they are deleted
+    async fn find_watchers(&mut self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>>;
+
+    /// Delete the journals and their data rows, returning the journal count
+    async fn delete_journals(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    async fn delete_watchers(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    /// Delete the shares, revoking access of the users they were shared with
+    async fn delete_shares(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    /// Delete relations from or to any of the work packages
+    async fn delete_relations(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    /// Delete the links to files in storages; the files themselves stay
+    async fn delete_file_links(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    /// Ids of the attachments of the work packages. The attachments are
+    /// removed with their files after commit, as files are not transactional.
+    async fn attachment_ids(&mut self, ids: &[Id]) -> RepositoryResult<Vec<Id>>;
+
+    /// Delete the time entries along with the cost entries, returning the
+    /// time entry count
+    async fn delete_time_entries(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    /// Keep the time and cost entries, booked on the project only
+    async fn detach_time_entries(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    /// Delete notifications about the work packages
+    async fn delete_notifications(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    async fn delete_work_packages(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    async fn commit(self) -> RepositoryResult<()>
+    where
+        Self: Sized;
+
+    async fn rollback(self) -> RepositoryResult<()>
+    where
+        Self: Sized;
+}
+
+/// Delete cascade in a database transaction
+pub struct WorkPackageDeletion {
+    tx: DbTransaction,
+}
+
+impl WorkPackageRepository {
+    /// Start deleting work packages in a new transaction
+    pub async fn begin_deletion(&self) -> RepositoryResult<WorkPackageDeletion> {
+        Ok(WorkPackageDeletion {
+            tx: DbTransaction::begin(&self.db).await?,
+        })
+    }
+}
+
+impl WorkPackageDeletion {
+    async fn execute(&mut self, sql: &str, ids: &[Id]) -> RepositoryResult<u64> {
+        let result = sqlx::query(sql).bind(ids).execute(&mut *self.tx).await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl DeleteCascade for WorkPackageDeletion {
+    async fn find_descendant_ids(&mut self, id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, i64>(
+            r#"
+            WITH RECURSIVE descendants AS (
+                SELECT id, 1 AS depth FROM work_packages WHERE parent_id = $1
+                UNION ALL
+                SELECT wp.id, d.depth + 1
+                FROM work_packages wp
+                JOIN descendants d ON wp.parent_id = d.id
+            )
+            SELECT id FROM descendants ORDER BY depth DESC, id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(ids)
+    }
+
+    async fn find_expired(&mut self, trashed_before: DateTime<Utc>) -> RepositoryResult<Vec<TrashedWorkPackageRow>> {
+        // A work package below another expired one is reached through
+        // both; the deepest path puts it before its children
+        let rows = sqlx::query_as::<_, TrashedWorkPackageRow>(&format!(
+            r#"
+            WITH RECURSIVE expired AS (
+                SELECT id, 0 AS depth FROM work_packages WHERE deleted_at < $1
+                UNION ALL
+                SELECT wp.id, e.depth + 1
+                FROM work_packages wp
+                JOIN expired e ON wp.parent_id = e.id
+                WHERE wp.deleted_at IS NOT NULL
+            )
+            {}
+            JOIN (SELECT id, MAX(depth) AS depth FROM expired GROUP BY id) e ON e.id = wp.id
+            ORDER BY e.depth DESC, wp.id
+            "#,
+            TRASHED_WORK_PACKAGES
+        ))
+        .bind(trashed_before)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn find_watchers(&mut self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>> {
+        find_watchers(&mut self.tx, ids).await
+    }
+
+    async fn delete_journals(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute(
+            r#"
+            DELETE FROM work_package_journals
+            WHERE id IN (
+                SELECT data_id FROM journals
+                WHERE journable_type = 'WorkPackage' AND journable_id = ANY($1)
+                  AND data_type = 'Journal::WorkPackageJournal'
+            )
+            "#,
+            ids,
+        )
+        .await?;
+
+        self.execute(
+            "DELETE FROM journals WHERE journable_type = 'WorkPackage' AND journable_id = ANY($1)",
+            ids,
+        )
+        .await
+    }
+
+    async fn delete_watchers(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute(
+            "DELETE FROM watchers WHERE watchable_type = 'WorkPackage' AND watchable_id = ANY($1)",
+            ids,
+        )
+        .await
+    }
+
+    async fn delete_file_links(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute(
+            "DELETE FROM file_links WHERE container_type = 'WorkPackage' AND container_id = ANY($1)",
+            ids,
+        )
+        .await
+    }
+
+    async fn delete_shares(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute(
+            r#"
+            DELETE FROM member_roles
+            WHERE member_id IN (
+                SELECT id FROM members WHERE entity_type = 'WorkPackage' AND entity_id = ANY($1)
+            )
+            "#,
+            ids,
+        )
+        .await?;
+
+        self.execute(
+            "DELETE FROM members WHERE entity_type = 'WorkPackage' AND entity_id = ANY($1)",
+            ids,
+        )
+        .await
+    }
+
+    async fn delete_relations(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute(
+            "DELETE FROM relations WHERE from_id = ANY($1) OR to_id = ANY($1)",
+            ids,
+        )
+        .await
+    }
+
+    async fn attachment_ids(&mut self, ids: &[Id]) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM attachments WHERE container_type = 'WorkPackage' AND container_id = ANY($1) ORDER BY id",
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(ids)
+    }
+
+    async fn delete_time_entries(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute("DELETE FROM cost_entries WHERE work_package_id = ANY($1)", ids)
+            .await?;
+        self.execute("DELETE FROM time_entries WHERE work_package_id = ANY($1)", ids)
+            .await
+    }
+
+    async fn detach_time_entries(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute(
+            "UPDATE cost_entries SET work_package_id = NULL, updated_at = NOW() WHERE work_package_id = ANY($1)",
+            ids,
+        )
+        .await?;
+        self.execute(
+            "UPDATE time_entries SET work_package_id = NULL, updated_at = NOW() WHERE work_package_id = ANY($1)",
+            ids,
+        )
+        .await
+    }
+
+    async fn delete_notifications(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        self.execute(
+            "DELETE FROM notifications WHERE resource_type = 'WorkPackage' AND resource_id = ANY($1)",
+            ids,
+        )
+        .await
+    }
+
+    async fn delete_work_packages(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        count_work_packages(&mut self.tx, ids, -1).await?;
+        self.execute("DELETE FROM work_packages WHERE id = ANY($1)", ids)
+            .await
+    }
+
+    async fn commit(self) -> RepositoryResult<()> {
+        self.tx.commit().await
+    }
+
+    async fn rollback(self) -> RepositoryResult<()> {
+        self.tx.rollback().await
+    }
+}
+
+/// Steps of moving work packages to the trash and restoring them
+///
+/// Trashed work packages keep their rows and dependent records but are
+/// left out of the finders, queries and summaries until restored or
+/// purged through the [`DeleteCascade`]. Like it, implementations run all
+/// steps in one unit of work.
+#[async_trait]
+pub trait TrashCascade: Send {
+    /// Ids of the descendants of a work package not in the trash yet,
+    /// children first
+    async fn find_descendant_ids(&mut self, id: Id) -> RepositoryResult<Vec<Id>>;
+
+    /// Move the work packages to the trash as deleted by the user, taking
+    /// them out of the summaries
+    async fn trash_work_packages(&mut self, ids: &[Id], user_id: Id) -> RepositoryResult<u64>;
+
+    /// A work package in the trash followed by the descendants trashed
+    /// with it, parents first; empty if it is not in the trash
+    async fn find_trashed_subtree(&mut self, id: Id) -> RepositoryResult<Vec<TrashedWorkPackageRow>>;
+
+    /// Take the work packages out of the trash, counting them in the
+    /// summaries again
+    async fn restore_work_packages(&mut self, ids: &[Id]) -> RepositoryResult<u64>;
+
+    async fn find_watchers(&mut self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>>;
+
+    async fn commit(self) -> RepositoryResult<()>
+    where
+        Self: Sized;
+
+    async fn rollback(self) -> RepositoryResult<()>
+    where
+        Self: Sized;
+}
+
+/// Trash cascade in a database transaction
+pub struct WorkPackageTrash {
+    tx: DbTransaction,
+}
+
+impl WorkPackageRepository {
+    /// Start moving work packages to or out of the trash in a new transaction
+    pub async fn begin_trash(&self) -> RepositoryResult<WorkPackageTrash> {
+        Ok(WorkPackageTrash {
+            tx: DbTransaction::begin(&self.db).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl TrashCascade for WorkPackageTrash {
+    async fn find_descendant_ids(&mut self, id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, i64>(
+            r#"
+            WITH RECURSIVE descendants AS (
+                SELECT id, 1 AS depth FROM work_packages WHERE parent_id = $1 AND deleted_at IS NULL
+                UNION ALL
+                SELECT wp.id, d.depth + 1
+                FROM work_packages wp
+                JOIN descendants d ON wp.parent_id = d.id
+                WHERE wp.deleted_at IS NULL
+            )
+            SELECT id FROM descendants ORDER BY depth DESC, id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(ids)
+    }
+
+    async fn trash_work_packages(&mut self, ids: &[Id], user_id: Id) -> RepositoryResult<u64> {
+        count_work_packages(&mut self.tx, ids, -1).await?;
+        let result = sqlx::query(
+            "UPDATE work_packages SET deleted_at = NOW(), deleted_by = $2 WHERE id = ANY($1) AND deleted_at IS NULL",
+        )
+        .bind(ids)
+        .bind(user_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn find_trashed_subtree(&mut self, id: Id) -> RepositoryResult<Vec<TrashedWorkPackageRow>> {
+        // Descendants trashed before their ancestor were deleted on their
+        // own and stay in the trash
+        let rows = sqlx::query_as::<_, TrashedWorkPackageRow>(&format!(
+            r#"
+            WITH RECURSIVE subtree AS (
+                SELECT id, deleted_at, 0 AS depth FROM work_packages WHERE id = $1 AND deleted_at IS NOT NULL
+                UNION ALL
+                SELECT wp.id, wp.deleted_at, s.depth + 1
+                FROM work_packages wp
+                JOIN subtree s ON wp.parent_id = s.id
+                WHERE wp.deleted_at = s.deleted_at
+            )
+            {}
+            JOIN subtree s ON s.id = wp.id
+            ORDER BY s.depth, wp.id
+            "#,
+            TRASHED_WORK_PACKAGES
+        ))
+        .bind(id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn restore_work_packages(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+        let result = sqlx::query(
+            "UPDATE work_packages SET deleted_at = NULL, deleted_by = NULL WHERE id = ANY($1) AND deleted_at IS NOT NULL",
+        )
+        .bind(ids)
+        .execute(&mut *self.tx)
+        .await?;
+        count_work_packages(&mut self.tx, ids, 1).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn find_watchers(&mut self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>> {
+        find_watchers(&mut self.tx, ids).await
+    }
+
+    async fn commit(self) -> RepositoryResult<()> {
+        self.tx.commit().await
+    }
+
+    async fn rollback(self) -> RepositoryResult<()> {
+        self.tx.rollback().await
+    }
+}
+
+/// Steps of copying work packages together with their dependent records
+///
+/// Like [`DeleteCascade`], implementations run all steps in one unit of
+/// work. Attachment files are copied only once it is committed.
+#[async_trait]
+pub trait CopyCascade: Send {
+    /// Writes to the copies after the commit
+    type Committed: CommittedCopy;
+
+    /// The work package followed by its descendants, parents before children;
+    /// empty if it does not exist
+    async fn find_subtree(&mut self, id: Id) -> RepositoryResult<Vec<WorkPackageRow>>;
+
+    /// Whether the target project exists
+    async fn project_exists(&mut self, project_id: Id) -> RepositoryResult<bool>;
+
+    async fn find_types(&mut self, ids: &[Id]) -> RepositoryResult<Vec<TypeRow>>;
+
+    /// Types enabled in the project, by position
+    async fn enabled_types(&mut self, project_id: Id) -> RepositoryResult<Vec<TypeRow>>;
+
+    /// All statuses, by position
+    async fn statuses(&mut self) -> RepositoryResult<Vec<StatusRow>>;
+
+    /// Insert a work package along with its initial journal
+    async fn create_work_package(&mut self, dto: CreateWorkPackageDto) -> RepositoryResult<WorkPackageRow>;
+
+    async fn copy_custom_values(&mut self, from: Id, to: Id) -> RepositoryResult<u64>;
+
+    async fn copy_watchers(&mut self, from: Id, to: Id) -> RepositoryResult<u64>;
+
+    /// Copy the relations between the copied work packages, given as pairs
+    /// of source and copy ids. Relations to work packages outside the set
+    /// are not copied.
+    async fn copy_relations(&mut self, copies: &[(Id, Id)]) -> RepositoryResult<u64>;
+
+    /// Ids of the attachments of a work package
+    async fn attachment_ids(&mut self, id: Id) -> RepositoryResult<Vec<Id>>;
+
+    async fn commit(self) -> RepositoryResult<Self::Committed>
+    where
+        Self: Sized;
+
+    async fn rollback(self) -> RepositoryResult<()>
+    where
+        Self: Sized;
+}
+
+/// Changes to copies once their attachments are copied
+#[async_trait]
+pub trait CommittedCopy: Send {
+    /// Replace the description of a copy and of its initial journal, e.g.
+    /// to point inline images to the copied attachments
+    async fn update_description(&mut self, id: Id, description: &str) -> RepositoryResult<()>;
+}
+
+/// Copy cascade in a database transaction
+pub struct WorkPackageCopy {
+    db: DbExecutor,
+    tx: DbTransaction,
+}
+
+/// Committed copy, changed in transactions of their own
+pub struct CommittedWorkPackageCopy {
+    db: DbExecutor,
+}
+
+impl WorkPackageRepository {
+    /// Start copying work packages in a new transaction
+    pub async fn begin_copy(&self) -> RepositoryResult<WorkPackageCopy> {
+        Ok(WorkPackageCopy {
+            db: self.db.clone(),
+            tx: DbTransaction::begin(&self.db).await?,
+        })
+    }
+
+    /// Create a work package with its initial journal, so it has a history
+    /// comments can be added to
+    pub async fn create_journaled(&self, dto: CreateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        let row = insert_with_journal(&mut tx, dto).await?;
+        tx.commit().await?;
+        Ok(row)
+    }
+
+    /// Update a work package and journal the change by the user with the
+    /// notes, as a change made in the Rails app is
+    pub async fn update_journaled(
+        &self,
+        id: Id,
+        dto: UpdateWorkPackageDto,
+        user_id: Id,
+        notes: &str,
+    ) -> RepositoryResult<WorkPackageRow> {
+        let _timer = self.timer("update_journaled");
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        let row = update_row(&mut tx, id, &dto).await?;
+        journal_update(&mut tx, id, user_id, notes).await?;
+        tx.commit().await?;
+        Ok(row)
+    }
+}
+
+/// Update a work package if its lock version matches, moving it between
+/// the summary counts if the type, status or priority changes
+async fn update_row(tx: &mut DbTransaction, id: Id, dto: &UpdateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+    count_work_packages(tx, &[id], -1).await?;
+    let row = sqlx::query_as::<_, WorkPackageRow>(
+        r#"
+        UPDATE work_packages SET
+            subject = COALESCE($1, subject),
+            description = COALESCE($2, description),
+            type_id = COALESCE($3, type_id),
+            status_id = COALESCE($4, status_id),
+            priority_id = COALESCE($5, priority_id),
+            assigned_to_id = $6,
+            responsible_id = $7,
+            start_date = $8,
+            due_date = $9,
+            estimated_hours = $10,
+            done_ratio = COALESCE($11, done_ratio),
+            parent_id = $12,
+            version_id = $13,
+            category_id = $14,
+            duration = COALESCE($17, duration),
+            ignore_non_working_days = COALESCE($18, ignore_non_working_days),
+            lock_version = lock_version + 1,
+            updated_at = NOW()
+        WHERE id = $15 AND lock_version = $16
+        RETURNING id, subject, description, project_id, type_id, status_id,
+                  priority_id, author_id, assigned_to_id, responsible_id,
+                  start_date, due_date, estimated_hours, done_ratio,
+                  parent_id, version_id, category_id, lock_version,
+                  duration, ignore_non_working_days, created_at, updated_at
+        "#,
+    )
+    .bind(&dto.subject)
+    .bind(&dto.description)
+    .bind(dto.type_id)
+    .bind(dto.status_id)
+    .bind(dto.priority_id)
+    .bind(dto.assigned_to_id)
+    .bind(dto.responsible_id)
+    .bind(dto.start_date)
+    .bind(dto.due_date)
+    .bind(dto.estimated_hours)
+    .bind(dto.done_ratio)
+    .bind(dto.parent_id)
+    .bind(dto.version_id)
+    .bind(dto.category_id)
+    .bind(id)
+    .bind(dto.lock_version)
+    .bind(dto.duration)
+    .bind(dto.ignore_non_working_days)
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| {
+        RepositoryError::Conflict("Work package was modified by another user".to_string())
+    })?;
+    count_work_packages(tx, &[id], 1).await?;
+
+    Ok(row)
+}
+
+/// Journal the current state of a work package as a new version with the
+/// notes, written by the user
+async fn journal_update(tx: &mut DbTransaction, id: Id, user_id: Id, notes: &str) -> RepositoryResult<()> {
+    let data_id = sqlx::query_scalar::<_, i64>(
+        r#"
+        INSERT INTO work_package_journals (
+            type_id, project_id, subject, description, due_date, category_id, status_id,
+            assigned_to_id, priority_id, version_id, author_id, done_ratio, estimated_hours,
+            start_date, parent_id, responsible_id, duration, ignore_non_working_days
+        )
+        SELECT type_id, project_id, subject, description, due_date, category_id, status_id,
+               assigned_to_id, COALESCE(priority_id, 0), version_id, author_id, done_ratio,
+               estimated_hours, start_date, parent_id, responsible_id, duration, ignore_non_working_days
+        FROM work_packages
+        WHERE id = $1
+        RETURNING id
+        "#,
+    )
+    .bind(id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO journals (journable_type, journable_id, user_id, notes, version,
+                              data_type, data_id, cause, restricted, created_at, updated_at)
+        SELECT 'WorkPackage', $1, $2, $3,
+               COALESCE((SELECT MAX(version) FROM journals
+                         WHERE journable_type = 'WorkPackage' AND journable_id = $1), 0) + 1,
+               'Journal::WorkPackageJournal', $4, '{}', false, NOW(), NOW()
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(notes)
+    .bind(data_id)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Insert a work package along with its initial journal
+async fn insert_with_journal(tx: &mut DbTransaction, dto: CreateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+    let row = sqlx::query_as::<_, WorkPackageRow>(
+        r#"
+        INSERT INTO work_packages (
+            subject, description, project_id, type_id, status_id,
+            priority_id, author_id, assigned_to_id, responsible_id,
+            start_date, due_date, estimated_hours, done_ratio,
+            parent_id, version_id, category_id, lock_version,
+            duration, ignore_non_working_days, created_at, updated_at
+        ) VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, 0, $17,
+            COALESCE($18, false), NOW(), NOW()
+        )
+        RETURNING id, subject, description, project_id, type_id, status_id,
+                  priority_id, author_id, assigned_to_id, responsible_id,
+                  start_date, due_date, estimated_hours, done_ratio,
+                  parent_id, version_id, category_id, lock_version,
+                  duration, ignore_non_working_days, created_at, updated_at
+        "#,
+    )
+    .bind(&dto.subject)
+    .bind(&dto.description)
+    .bind(dto.project_id)
+    .bind(dto.type_id)
+    .bind(dto.status_id)
+    .bind(dto.priority_id)
+    .bind(dto.author_id)
+    .bind(dto.assigned_to_id)
+    .bind(dto.responsible_id)
+    .bind(dto.start_date)
+    .bind(dto.due_date)
+    .bind(dto.estimated_hours)
+    .bind(dto.done_ratio)
+    .bind(dto.parent_id)
+    .bind(dto.version_id)
+    .bind(dto.category_id)
+    .bind(dto.duration)
+    .bind(dto.ignore_non_working_days)
+    .fetch_one(&mut **tx)
+    .await?;
+    count_work_packages(tx, &[row.id], 1).await?;
+
+    let data_id = sqlx::query_scalar::<_, i64>(
+        r#"
+        INSERT INTO work_package_journals (
+            type_id, project_id, subject, description, due_date, category_id, status_id,
+            assigned_to_id, priority_id, version_id, author_id, done_ratio, estimated_hours,
+            start_date, parent_id, responsible_id, duration, ignore_non_working_days
+        )
+        SELECT type_id, project_id, subject, description, due_date, category_id, status_id,
+               assigned_to_id, COALESCE(priority_id, 0), version_id, author_id, done_ratio,
+               estimated_hours, start_date, parent_id, responsible_id, duration, ignore_non_working_days
+        FROM work_packages
+        WHERE id = $1
+        RETURNING id
+        "#,
+    )
+    .bind(row.id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO journals (journable_type, journable_id, user_id, notes, version,
+                              data_type, data_id, cause, restricted, created_at, updated_at)
+        VALUES ('WorkPackage', $1, $2, '', 1, 'Journal::WorkPackageJournal', $3, $4, false, NOW(), NOW())
+        "#,
+    )
+    .bind(row.id)
+    .bind(row.author_id)
+    .bind(data_id)
+    .bind(match dto.journal_cause {
+        Some(cause) => serde_json::json!({ "type": cause }),
+        None => serde_json::json!({}),
+    })
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(row)
+}
+
+#[async_trait]
+impl CopyCascade for WorkPackageCopy {
+    type Committed = CommittedWorkPackageCopy;
+
+    async fn find_subtree(&mut self, id: Id) -> RepositoryResult<Vec<WorkPackageRow>> {
+        let rows = sqlx::query_as::<_, WorkPackageRow>(
+            r#"
+            WITH RECURSIVE subtree AS (
+                SELECT id, 0 AS depth FROM work_packages WHERE id = $1 AND deleted_at IS NULL
+                UNION ALL
+                SELECT wp.id, s.depth + 1
+                FROM work_packages wp
+                JOIN subtree s ON wp.parent_id = s.id
+                WHERE wp.deleted_at IS NULL
+            )
+            SELECT wp.id, wp.subject, wp.description, wp.project_id, wp.type_id, wp.status_id,
+                   wp.priority_id, wp.author_id, wp.assigned_to_id, wp.responsible_id,
+                   wp.start_date, wp.due_date, wp.estimated_hours, wp.done_ratio,
+                   wp.parent_id, wp.version_id, wp.category_id, wp.lock_version,
+                   wp.duration, wp.ignore_non_working_days, wp.created_at, wp.updated_at
+            FROM work_packages wp
+            JOIN subtree s ON s.id = wp.id
+            ORDER BY s.depth, wp.id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn project_exists(&mut self, project_id: Id) -> RepositoryResult<bool> {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM projects WHERE id = $1)")
+            .bind(project_id)
+            .fetch_one(&mut *self.tx)
+            .await?;
+
+        Ok(exists)
+    }
+
+    async fn find_types(&mut self, ids: &[Id]) -> RepositoryResult<Vec<TypeRow>> {
+        let rows = sqlx::query_as::<_, TypeRow>(
+            r#"
+            SELECT id, name, position, is_default, is_in_roadmap, is_milestone,
+                   is_standard, color_id, description, form_configuration, created_at, updated_at
+            FROM types
+            WHERE id = ANY($1)
+            ORDER BY position ASC
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn enabled_types(&mut self, project_id: Id) -> RepositoryResult<Vec<TypeRow>> {
+        let rows = sqlx::query_as::<_, TypeRow>(
+            r#"
+            SELECT t.id, t.name, t.position, t.is_default, t.is_in_roadmap, t.is_milestone,
+                   t.is_standard, t.color_id, t.description, t.form_configuration, t.created_at, t.updated_at
+            FROM types t
+            INNER JOIN projects_types pt ON pt.type_id = t.id
+            WHERE pt.project_id = $1
+            ORDER BY t.position ASC
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn statuses(&mut self) -> RepositoryResult<Vec<StatusRow>> {
+        let rows = sqlx::query_as::<_, StatusRow>(
+            r#"
+            SELECT id, name, is_closed, is_default, is_readonly, position,
+                   default_done_ratio, color_id, created_at, updated_at
+            FROM statuses
+            ORDER BY position ASC
+            "#,
+        )
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn create_work_package(&mut self, dto: CreateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+        insert_with_journal(&mut self.tx, dto).await
+    }
+
+    async fn copy_custom_values(&mut self, from: Id, to: Id) -> RepositoryResult<u64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO custom_values (customized_type, customized_id, custom_field_id, value)
+            SELECT customized_type, $2, custom_field_id, value
+            FROM custom_values
+            WHERE customized_type = 'WorkPackage' AND customized_id = $1
+            ORDER BY id
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn copy_watchers(&mut self, from: Id, to: Id) -> RepositoryResult<u64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO watchers (watchable_type, watchable_id, user_id)
+            SELECT watchable_type, $2, user_id
+            FROM watchers
+            WHERE watchable_type = 'WorkPackage' AND watchable_id = $1
+            ORDER BY id
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn copy_relations(&mut self, copies: &[(Id, Id)]) -> RepositoryResult<u64> {
+        let (sources, targets): (Vec<Id>, Vec<Id>) = copies.iter().copied().unzip();
+        let result = sqlx::query(
+            r#"
+            WITH copies AS (
+                SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS c (source_id, copy_id)
+            )
+            INSERT INTO relations (from_id, to_id, relation_type, lag, description, created_at, updated_at)
+            SELECT f.copy_id, t.copy_id, r.relation_type, r.lag, r.description, NOW(), NOW()
+            FROM relations r
+            JOIN copies f ON f.source_id = r.from_id
+            JOIN copies t ON t.source_id = r.to_id
+            ORDER BY r.id
+            "#,
+        )
+        .bind(&sources)
+        .bind(&targets)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn attachment_ids(&mut self, id: Id) -> RepositoryResult<Vec<Id>> {
+        let ids = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM attachments WHERE container_type = 'WorkPackage' AND container_id = $1 ORDER BY id",
+        )
+        .bind(id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        Ok(ids)
+    }
+
+    async fn commit(self) -> RepositoryResult<CommittedWorkPackageCopy> {
+        self.tx.commit().await?;
+        Ok(CommittedWorkPackageCopy { db: self.db })
+    }
+
+    async fn rollback(self) -> RepositoryResult<()> {
+        self.tx.rollback().await
+    }
+}
+
+#[async_trait]
+impl CommittedCopy for CommittedWorkPackageCopy {
+    async fn update_description(&mut self, id: Id, description: &str) -> RepositoryResult<()> {
+        let mut tx = DbTransaction::begin(&self.db).await?;
+        sqlx::query("UPDATE work_packages SET description = $2 WHERE id = $1")
+            .bind(id)
+            .bind(description)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            UPDATE work_package_journals SET description = $2
+            WHERE id = (
+                SELECT data_id FROM journals
+                WHERE journable_type = 'WorkPackage' AND journable_id = $1 AND version = 1
+            )
+            "#,
+        )
+        .bind(id)
+        .bind(description)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+}
+
+/// Attribute of work packages referencing a lookup table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReferenceColumn {
+    Status,
+    Type,
+    Priority,
+}
+
+impl ReferenceColumn {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Status => "status_id",
+            Self::Type => "type_id",
+            Self::Priority => "priority_id",
+        }
+    }
+}
+
+/// Number of work packages referencing `id` in the column
+pub(crate) async fn count_referencing(
+    conn: &mut sqlx::PgConnection,
+    column: ReferenceColumn,
+    id: Id,
+) -> RepositoryResult<i64> {
+    let count = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT COUNT(*) FROM work_packages WHERE {} = $1",
+        column.name()
+    ))
+    .bind(id)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(count)
+}
+
+/// Move the work packages referencing `from` in the column to `to`
+///
+/// Every moved work package gets a journal with the cause system_update, as
+/// its history needs one per change; the journals and their data rows are
+/// inserted in the same statement as the update rather than one by one.
+/// Returns the number of work packages moved.
+pub(crate) async fn reassign_referencing(
+    conn: &mut sqlx::PgConnection,
+    column: ReferenceColumn,
+    from: Id,
+    to: Id,
+    user_id: Id,
+) -> RepositoryResult<u64> {
+    let moved = sqlx::query_scalar::<_, Id>(&format!(
+        "SELECT id FROM work_packages WHERE {} = $1",
+        column.name()
+    ))
+    .bind(from)
+    .fetch_all(&mut *conn)
+    .await?;
+    count_work_packages(conn, &moved, -1).await?;
+
+    // Data ids are drawn up front so each journal can reference its row
+    let sql = format!(
+        r#"
+        WITH moved AS (
+            UPDATE work_packages
+            SET {column} = $2, lock_version = lock_version + 1, updated_at = NOW()
+            WHERE {column} = $1
+            RETURNING *
+        ), snapshots AS (
+            SELECT moved.*, nextval(pg_get_serial_sequence('work_package_journals', 'id')) AS data_id
+            FROM moved
+        ), data AS (
+            INSERT INTO work_package_journals (
+                id, type_id, project_id, subject, description, due_date, category_id, status_id,
+                assigned_to_id, priority_id, version_id, author_id, done_ratio, estimated_hours,
+                start_date, parent_id, responsible_id, duration, ignore_non_working_days
+            )
+            SELECT data_id, type_id, project_id, subject, description, due_date, category_id, status_id,
+                   assigned_to_id, COALESCE(priority_id, 0), version_id, author_id, done_ratio,
+                   estimated_hours, start_date, parent_id, responsible_id, duration, ignore_non_working_days
+            FROM snapshots
+        )
+        INSERT INTO journals (journable_type, journable_id, user_id, notes, version,
+                              data_type, data_id, cause, restricted, created_at, updated_at)
+        SELECT 'WorkPackage', s.id, $3, '',
+               COALESCE((SELECT MAX(j.version) FROM journals j
+                         WHERE j.journable_type = 'WorkPackage' AND j.journable_id = s.id), 0) + 1,
+               'Journal::WorkPackageJournal', s.data_id, $4, false, NOW(), NOW()
+        FROM snapshots s
+        "#,
+        column = column.name()
+    );
+
+    let result = sqlx::query(&sql)
+        .bind(from)
+        .bind(to)
+        .bind(user_id)
+        .bind(serde_json::json!({ "type": crate::journals::cause_type::SYSTEM_UPDATE }))
+        .execute(&mut *conn)
+        .await?;
+    count_work_packages(conn, &moved, 1).await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dto(subject: &str, project_id: Id, assigned_to_id: Option<Id>) -> CreateWorkPackageDto {
+        CreateWorkPackageDto {
+            subject: subject.into(),
+            description: None,
+            project_id,
+            type_id: 1,
+            status_id: 1,
+            priority_id: None,
+            author_id: 1,
+            assigned_to_id,
+            responsible_id: None,
+            start_date: None,
+            due_date: None,
+            estimated_hours: None,
+            done_ratio: 0,
+            parent_id: None,
+            version_id: None,
+            category_id: None,
+            duration: None,
+            ignore_non_working_days: None,
+            journal_cause: None,
+        }
+    }
+
+    fn subjects(result: &PaginatedResult<WorkPackageRow>) -> Vec<&str> {
+        result.items.iter().map(|row| row.subject.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_evaluates_filters() {
+        let store = MemoryWorkPackageStore::new();
+        store.create(dto("Fix login bug", 1, Some(7))).await.unwrap();
+        store.create(dto("Write docs", 1, None)).await.unwrap();
+        store.create(dto("Login page design", 2, Some(8))).await.unwrap();
+        let page = Pagination::new(10, 0);
+
+        let query = Query::new("All")
+            .with_filter(Filter::new("subject", FilterOperator::Contains, FilterValue::String("LOGIN".into())));
+        let result = store.find_matching(&query, &page, None).await.unwrap();
+        assert_eq!(subjects(&result), vec!["Fix login bug", "Login page design"]);
+
+        let query = Query::for_project("Mine", 1)
+            .with_filter(Filter::new("assigned_to_id", FilterOperator::Equals, FilterValue::Me));
+        let result = store.find_matching(&query, &page, Some(7)).await.unwrap();
+        assert_eq!((subjects(&result), result.total), (vec!["Fix login bug"], 1));
+
+        let query = Query::new("Unassigned")
+            .with_filter(Filter::new("assigned_to_id", FilterOperator::IsNull, FilterValue::None));
+        let result = store.find_matching(&query, &Pagination::new(10, 0), None).await.unwrap();
+        assert_eq!(subjects(&result), vec!["Write docs"]);
+
+        let result = store.find_matching(&Query::new("Paged"), &Pagination::new(1, 1), None).await.unwrap();
+        assert_eq!((subjects(&result), result.total), (vec!["Write docs"], 3));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_rejects_filters_it_cannot_evaluate() {
+        let store = MemoryWorkPackageStore::new();
+        let query = Query::new("Late")
+            .with_filter(Filter::new("due_date", FilterOperator::LessThan, FilterValue::Date("2024-01-01".into())))
+            .with_filter(Filter::new("status_id", FilterOperator::Contains, FilterValue::Id(1)));
+
+        let Err(RepositoryError::InvalidQuery(e)) = store.find_matching(&query, &Pagination::default(), None).await else {
+            panic!("expected the filters to be rejected");
+        };
+        let attributes: Vec<&str> = e.errors.iter().map(|error| error.attribute.as_str()).collect();
+        assert_eq!(attributes, vec!["due_date", "status_id"]);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_checks_the_lock_version() {
+        let store = MemoryWorkPackageStore::new();
+        let row = store.create(dto("Draft", 1, Some(7))).await.unwrap();
+        let update = UpdateWorkPackageDto {
+            subject: Some("Final".into()),
+            lock_version: row.lock_version,
+            ..Default::default()
+        };
+
+        let updated = store.update(row.id, update.clone()).await.unwrap();
+        assert_eq!((updated.subject.as_str(), updated.lock_version), ("Final", 1));
+        // Cleared like in the database
+        assert_eq!(updated.assigned_to_id, None);
+        assert!(matches!(store.update(row.id, update).await, Err(RepositoryError::Conflict(_))));
+        assert!(matches!(
+            store.update(row.id + 1, UpdateWorkPackageDto::default()).await,
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+}
+
+#[cfg(all(test, feature = "pg-tests"))]
+mod pg_tests {
+    use super::*;
+    use crate::testing::{ProjectFixture, TestDb, UserFixture, WorkPackageFixture};
+
+    fn create_dto(project_id: Id, author_id: Id, type_id: Id, status_id: Id) -> CreateWorkPackageDto {
+        CreateWorkPackageDto {
+            subject: "Write tests".into(),
+            description: Some("Against a real database".into()),
+            project_id,
+            type_id,
+            status_id,
+            priority_id: None,
+            author_id,
+            assigned_to_id: Some(author_id),
+            responsible_id: None,
+            start_date: chrono::NaiveDate::from_ymd_opt(2026, 1, 5),
+            due_date: chrono::NaiveDate::from_ymd_opt(2026, 1, 9),
+            estimated_hours: Some(4.5),
+            done_ratio: 0,
+            parent_id: None,
+            version_id: None,
+            category_id: None,
+            duration: Some(5),
+            ignore_non_working_days: None,
+            journal_cause: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_find_update_delete() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-crud")).await;
+        let template = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let template = db.work_packages().find_by_id(template).await.unwrap().unwrap();
+        let repo = db.work_packages();
+
+        let created = repo
+            .create(create_dto(project, author, template.type_id, template.status_id))
+            .await
+            .unwrap();
+        assert_eq!(created.lock_version, 0);
+        assert_eq!(created.estimated_hours, Some(4.5));
+        assert_eq!((created.duration, created.ignore_non_working_days), (Some(5), false));
+        assert_eq!(repo.find_by_id(created.id).await.unwrap().unwrap().subject, "Write tests");
+
+        let updated = repo
+            .update(
+                created.id,
+                UpdateWorkPackageDto {
+                    subject: Some("Write more tests".into()),
+                    done_ratio: Some(50),
+                    lock_version: 0,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!((updated.subject.as_str(), updated.done_ratio, updated.lock_version), ("Write more tests", 50, 1));
+        // Update clears the nullable attributes not given
+        assert_eq!(updated.assigned_to_id, None);
+
+        // A stale lock version is a conflict
+        let stale = repo
+            .update(created.id, UpdateWorkPackageDto { lock_version: 0, ..Default::default() })
+            .await;
+        assert!(matches!(stale, Err(RepositoryError::Conflict(_))));
+
+        repo.delete(created.id).await.unwrap();
+        assert!(!repo.exists(created.id).await.unwrap());
+        assert!(matches!(repo.delete(created.id).await, Err(RepositoryError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_find_by_project_and_assignee_paginate() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let assignee = db.insert_user(UserFixture::new("assignee")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-pages")).await;
+        let other = db.insert_project(ProjectFixture::new("wp-other")).await;
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let fixture = WorkPackageFixture::new(project, author).with_subject(format!("WP {}", i));
+            ids.push(db.insert_work_package(fixture.with_assignee(assignee)).await);
+        }
+        db.insert_work_package(WorkPackageFixture::new(other, author)).await;
+        let repo = db.work_packages();
+
+        let page = repo
+            .find_by_project(project, Pagination { limit: 2, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(page.total, 3);
+        // Newest first
+        assert_eq!(page.items.iter().map(|wp| wp.id).collect::<Vec<_>>(), vec![ids[2], ids[1]]);
+
+        let assigned = repo
+            .find_by_assignee(assignee, Pagination { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(assigned.total, 3);
+    }
+
+    #[tokio::test]
+    async fn test_reschedule_moves_dates_and_bumps_lock_version() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-timeline")).await;
+        let undated = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let scheduled = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let repo = db.work_packages();
+        assert!(repo.find_scheduled_in_project(project).await.unwrap().is_empty());
+
+        let start = chrono::NaiveDate::from_ymd_opt(2024, 1, 9);
+        let due = chrono::NaiveDate::from_ymd_opt(2024, 1, 10);
+        let moved = repo.reschedule(scheduled, start, due, Some(2)).await.unwrap();
+        assert_eq!((moved.start_date, moved.due_date, moved.duration, moved.lock_version), (start, due, Some(2), 1));
+
+        let timeline = repo.find_scheduled_in_project(project).await.unwrap();
+        assert_eq!(timeline.iter().map(|wp| wp.id).collect::<Vec<_>>(), vec![scheduled]);
+        assert!(!timeline.iter().any(|wp| wp.id == undated));
+        assert!(matches!(repo.reschedule(-1, start, due, None).await, Err(RepositoryError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_update_journaled_adds_a_version_with_the_notes() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let editor = db.insert_user(UserFixture::new("editor")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-journaled")).await;
+        let template = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let template = db.work_packages().find_by_id(template).await.unwrap().unwrap();
+        let repo = db.work_packages();
+
+        let created = repo
+            .create_journaled(create_dto(project, author, template.type_id, template.status_id))
+            .await
+            .unwrap();
+        let updated = repo
+            .update_journaled(
+                created.id,
+                UpdateWorkPackageDto {
+                    done_ratio: Some(100),
+                    assigned_to_id: Some(editor),
+                    lock_version: 0,
+                    ..Default::default()
+                },
+                editor,
+                "Done",
+            )
+            .await
+            .unwrap();
+        assert_eq!((updated.done_ratio, updated.lock_version), (100, 1));
+
+        let stale = repo
+            .update_journaled(created.id, UpdateWorkPackageDto { lock_version: 0, ..Default::default() }, editor, "")
+            .await;
+        assert!(matches!(stale, Err(RepositoryError::Conflict(_))));
+
+        let journals = sqlx::query_as::<_, (i32, Id, String, i32)>(
+            r#"
+            SELECT j.version, j.user_id, COALESCE(j.notes, ''), d.done_ratio
+            FROM journals j
+            JOIN work_package_journals d ON d.id = j.data_id
+            WHERE j.journable_type = 'WorkPackage' AND j.journable_id = $1
+            ORDER BY j.version
+            "#,
+        )
+        .bind(created.id)
+        .fetch_all(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+        assert_eq!(journals, vec![(1, author, String::new(), 0), (2, editor, "Done".to_string(), 100)]);
+    }
+
+    #[tokio::test]
+    async fn test_deletion_cascade_commits_in_nested_transaction() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-delete")).await;
+        let parent = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let child = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_parent(parent))
+            .await;
+        let grandchild = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_parent(child))
+            .await;
+        sqlx::query("INSERT INTO watchers (watchable_type, watchable_id, user_id) VALUES ('WorkPackage', $1, $2)")
+            .bind(child)
+            .bind(author)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        let repo = db.work_packages();
+
+        let mut deletion = repo.begin_deletion().await.unwrap();
+        let descendants = deletion.find_descendant_ids(parent).await.unwrap();
+        assert_eq!(descendants, vec![grandchild, child]);
+        let mut ids = descendants;
+        ids.push(parent);
+        assert_eq!(deletion.delete_watchers(&ids).await.unwrap(), 1);
+        assert_eq!(deletion.delete_work_packages(&ids).await.unwrap(), 3);
+        deletion.commit().await.unwrap();
+        assert!(!repo.exists(parent).await.unwrap());
+
+        // A rolled back deletion leaves the work package
+        let survivor = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let mut deletion = repo.begin_deletion().await.unwrap();
+        deletion.delete_work_packages(&[survivor]).await.unwrap();
+        deletion.rollback().await.unwrap();
+        assert!(repo.exists(survivor).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_copy_cascade_copies_subtree_records() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let watcher = db.insert_user(UserFixture::new("watcher")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-copy")).await;
+        let parent = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let child = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_parent(parent))
+            .await;
+        let outside = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let mut conn = db.executor().acquire().await.unwrap();
+        sqlx::query("INSERT INTO watchers (watchable_type, watchable_id, user_id) VALUES ('WorkPackage', $1, $2)")
+            .bind(parent)
+            .bind(watcher)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        for (from, to) in [(parent, child), (child, outside)] {
+            sqlx::query("INSERT INTO relations (from_id, to_id, relation_type) VALUES ($1, $2, 'relates')")
+                .bind(from)
+                .bind(to)
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+        }
+        drop(conn);
+        let repo = db.work_packages();
+
+        let mut copy = repo.begin_copy().await.unwrap();
+        let subtree = copy.find_subtree(parent).await.unwrap();
+        assert_eq!(subtree.iter().map(|wp| wp.id).collect::<Vec<_>>(), vec![parent, child]);
+        assert!(copy.find_subtree(outside + 1000).await.unwrap().is_empty());
+
+        let source = &subtree[0];
+        let mut dto = create_dto(project, author, source.type_id, source.status_id);
+        dto.subject = "Copy".into();
+        let parent_copy = copy.create_work_package(dto.clone()).await.unwrap();
+        dto.parent_id = Some(parent_copy.id);
+        dto.journal_cause = Some(crate::journals::cause_type::DEFAULT_ATTRIBUTE_WRITTEN);
+        let child_copy = copy.create_work_package(dto).await.unwrap();
+
+        assert_eq!(copy.copy_watchers(parent, parent_copy.id).await.unwrap(), 1);
+        let copies = [(parent, parent_copy.id), (child, child_copy.id)];
+        // The relation to the work package outside the copied set stays behind
+        assert_eq!(copy.copy_relations(&copies).await.unwrap(), 1);
+        assert_eq!(copy.copy_custom_values(parent, parent_copy.id).await.unwrap(), 0);
+        let mut committed = copy.commit().await.unwrap();
+
+        // Descriptions are rewritten after the commit, in the initial journal too
+        let description = "![](/api/v3/attachments/2/content)";
+        committed.update_description(parent_copy.id, description).await.unwrap();
+        let copied = repo.find_by_id(parent_copy.id).await.unwrap().unwrap();
+        assert_eq!(copied.description.as_deref(), Some(description));
+        let journaled: Option<String> = sqlx::query_scalar(
+            "SELECT wpj.description FROM journals j JOIN work_package_journals wpj ON wpj.id = j.data_id \
+             WHERE j.journable_type = 'WorkPackage' AND j.journable_id = $1",
+        )
+        .bind(parent_copy.id)
+        .fetch_one(&mut *db.executor().acquire().await.unwrap())
+        .await
+        .unwrap();
+        assert_eq!(journaled.as_deref(), Some(description));
+
+        let journals = db
+            .journals()
+            .find_by_work_package(parent_copy.id, Pagination { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(journals.total, 1);
+        assert!(journals.items[0].is_initial());
+        assert_eq!(journals.items[0].cause_type(), None);
+        let journals = db
+            .journals()
+            .find_by_work_package(child_copy.id, Pagination { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(journals.items[0].cause_type(), Some(crate::journals::cause_type::DEFAULT_ATTRIBUTE_WRITTEN));
+        assert_eq!(repo.find_by_id(child_copy.id).await.unwrap().unwrap().parent_id, Some(parent_copy.id));
+    }
+
+    #[tokio::test]
+    async fn test_references_are_restricted_to_visible_work_packages() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let reader = db.insert_user(UserFixture::new("reader")).await;
+        let viewer = db.insert_role("Viewer", &["view_work_packages"]).await;
+        let shared = db.insert_project(ProjectFixture::new("refs-shared")).await;
+        let private = db.insert_project(ProjectFixture::new("refs-private")).await;
+        db.members()
+            .create(crate::members::CreateMemberDto {
+                user_id: reader,
+                project_id: Some(shared),
+                role_ids: vec![viewer],
+                entity_type: None,
+                entity_id: None,
+            })
+            .await
+            .unwrap();
+        let visible = db
+            .insert_work_package(WorkPackageFixture::new(shared, author).with_subject("Visible"))
+            .await;
+        let hidden = db
+            .insert_work_package(WorkPackageFixture::new(private, author).with_subject("Secret"))
+            .await;
+        let repo = db.work_packages();
+
+        let found = repo.find_references(&[visible, hidden, hidden + 1000], Some(reader)).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, visible);
+        assert_eq!(found[0].subject, "Visible");
+        assert_eq!(found[0].status_name.as_deref(), Some("New"));
+        assert_eq!(found[0].status_is_closed, Some(false));
+
+        let unrestricted = repo.find_references(&[visible, hidden], None).await.unwrap();
+        assert_eq!(unrestricted.iter().map(|r| r.id).collect::<Vec<_>>(), vec![visible, hidden]);
+    }
+
+    /// Move work packages to the trash as the user and commit
+    async fn trash(repo: &WorkPackageRepository, ids: &[Id], user_id: Id) {
+        let mut trash = repo.begin_trash().await.unwrap();
+        trash.trash_work_packages(ids, user_id).await.unwrap();
+        trash.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_trash_hides_work_packages_until_restored() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-trash")).await;
+        let parent = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let child = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_parent(parent))
+            .await;
+        let grandchild = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_parent(child))
+            .await;
+        let repo = db.work_packages();
+
+        // The grandchild went first, on its own
+        trash(&repo, &[grandchild], author).await;
+        sqlx::query("UPDATE work_packages SET deleted_at = NOW() - INTERVAL '1 day' WHERE id = $1")
+            .bind(grandchild)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        let mut subtree = repo.begin_trash().await.unwrap();
+        assert_eq!(subtree.find_descendant_ids(parent).await.unwrap(), vec![child]);
+        subtree.rollback().await.unwrap();
+        trash(&repo, &[child, parent], author).await;
+
+        assert!(repo.find_by_id(parent).await.unwrap().is_none());
+        assert!(!repo.exists(child).await.unwrap());
+        assert_eq!(repo.count().await.unwrap(), 0);
+        let trashed = repo.find_trashed(child).await.unwrap().unwrap();
+        assert_eq!(trashed.deleted_by, Some(author));
+        assert!(trashed.parent_trashed);
+        assert!(trashed.project_active && trashed.status_exists);
+        assert!(repo.find_trashed(project + 1000).await.unwrap().is_none());
+
+        // Restoring the parent brings back what was trashed with it only
+        let mut restore = repo.begin_trash().await.unwrap();
+        let subtree = restore.find_trashed_subtree(parent).await.unwrap();
+        assert_eq!(subtree.iter().map(|row| row.id).collect::<Vec<_>>(), vec![parent, child]);
+        restore.restore_work_packages(&[parent, child]).await.unwrap();
+        restore.commit().await.unwrap();
+
+        assert!(repo.exists(parent).await.unwrap());
+        assert_eq!(repo.find_children(parent).await.unwrap().len(), 1);
+        assert!(repo.find_by_id(grandchild).await.unwrap().is_none());
+        assert!(!repo.find_trashed(grandchild).await.unwrap().unwrap().parent_trashed);
+    }
+
+    #[tokio::test]
+    async fn test_purge_deletes_expired_trash_with_its_descendants() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("wp-purge")).await;
+        let parent = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let child = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_parent(parent))
+            .await;
+        let recent = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let kept = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let repo = db.work_packages();
+
+        // The parent went to the trash 40 days ago, the child only today
+        trash(&repo, &[parent], author).await;
+        sqlx::query("UPDATE work_packages SET deleted_at = NOW() - INTERVAL '40 days' WHERE id = $1")
+            .bind(parent)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+        trash(&repo, &[child, recent], author).await;
+        sqlx::query("INSERT INTO watchers (watchable_type, watchable_id, user_id) VALUES ('WorkPackage', $1, $2)")
+            .bind(child)
+            .bind(author)
+            .execute(&mut *db.executor().acquire().await.unwrap())
+            .await
+            .unwrap();
+
+        let mut deletion = repo.begin_deletion().await.unwrap();
+        let expired = deletion.find_expired(Utc::now() - chrono::Duration::days(30)).await.unwrap();
+        let ids: Vec<Id> = expired.iter().map(|row| row.id).collect();
+        assert_eq!(ids, vec![child, parent]);
+        let watchers = deletion.find_watchers(&ids).await.unwrap();
+        assert_eq!(
+            watchers,
+            vec![WorkPackageWatcherRow { work_package_id: child, project_id: project, user_id: author }]
+        );
+        deletion.delete_watchers(&ids).await.unwrap();
+        assert_eq!(deletion.delete_work_packages(&ids).await.unwrap(), 2);
+        deletion.commit().await.unwrap();
+
+        // With its parent purged the child is gone, so it cannot be restored
+        assert!(repo.find_trashed(child).await.unwrap().is_none());
+        let mut restore = repo.begin_trash().await.unwrap();
+        assert!(restore.find_trashed_subtree(child).await.unwrap().is_empty());
+        restore.rollback().await.unwrap();
+
+        assert!(repo.find_trashed(recent).await.unwrap().is_some());
+        assert!(repo.exists(kept).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_similar_subjects_rank_near_duplicates_first() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("help-desk")).await;
+        let other_project = db.insert_project(ProjectFixture::new("elsewhere")).await;
+        let mut ids = Vec::new();
+        for subject in [
+            "Printer on floor 3 is out of toner",
+            "Printer on the third floor out of toner",
+            "Printer jams on floor 2",
+            "Reset my VPN password",
+        ] {
+            ids.push(db.insert_work_package(WorkPackageFixture::new(project, author).with_subject(subject)).await);
+        }
+        let elsewhere = WorkPackageFixture::new(other_project, author)
+            .with_subject("Printer on floor 3 is out of toner");
+        db.insert_work_package(elsewhere).await;
+
+        let repo = db.work_packages();
+        let similar = repo
+            .find_similar_subjects(project, "printer floor 3 out of toner", 10, 0.5, None)
+            .await
+            .unwrap();
+        let found: Vec<Id> = similar.iter().map(|row| row.work_package.id).collect();
+        assert_eq!(found, vec![ids[0], ids[1]]);
+        assert!(similar[0].similarity > similar[1].similarity);
+        assert!(similar[1].similarity >= 0.5);
+        assert_eq!(similar[0].work_package.status_name.as_deref(), Some("New"));
+
+        // A lower threshold lets in the other printer issue, not the VPN one
+        let loose = repo
+            .find_similar_subjects(project, "printer floor 3 out of toner", 10, 0.3, None)
+            .await
+            .unwrap();
+        let found: Vec<Id> = loose.iter().map(|row| row.work_package.id).collect();
+        assert_eq!(found, vec![ids[0], ids[1], ids[2]]);
+
+        let limited = repo.find_similar_subjects(project, "printer floor 3 out of toner", 1, 0.3, None).await.unwrap();
+        assert_eq!(limited.len(), 1);
+        let unrelated = repo.find_similar_subjects(project, "Quarterly budget review", 10, 0.3, None).await.unwrap();
+        assert!(unrelated.is_empty());
+        assert!(repo.find_similar_subjects(project, "  ", 10, 0.3, None).await.unwrap().is_empty());
+
+        // The threshold does not outlive the query
+        let mut conn = db.executor().acquire().await.unwrap();
+        let threshold: f32 = sqlx::query_scalar("SELECT show_limit()").fetch_one(&mut *conn).await.unwrap();
+        assert_eq!(threshold, 0.3);
+    }
+
+    #[tokio::test]
+    async fn test_defaults_of_new_work_packages() {
+        let db = TestDb::connect().await;
+        let project = db.insert_project(ProjectFixture::new("defaults")).await;
+        let role = db.insert_role("Member", &[]).await;
+        let mut conn = db.executor().acquire().await.unwrap();
+        for table in ["statuses", "enumerations"] {
+            sqlx::query(&format!("UPDATE {} SET is_default = false", table))
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+        }
+        async fn insert(conn: &mut sqlx::PgConnection, sql: &str) -> Id {
+            sqlx::query_scalar(sql).fetch_one(conn).await.unwrap()
+        }
+        let new = insert(
+            &mut conn,
+            "INSERT INTO statuses (name, is_default, position) VALUES ('New', TRUE, 2) RETURNING id",
+        )
+        .await;
+        let triage = insert(&mut conn, "INSERT INTO statuses (name, position) VALUES ('Triage', 1) RETURNING id").await;
+        let done = insert(&mut conn, "INSERT INTO statuses (name, position) VALUES ('Done', 3) RETURNING id").await;
+        let bug = insert(&mut conn, "INSERT INTO types (name) VALUES ('Bug') RETURNING id").await;
+        let task = insert(&mut conn, "INSERT INTO types (name) VALUES ('Task') RETURNING id").await;
+        let normal = insert(
+            &mut conn,
+            "INSERT INTO enumerations (name, type, is_default, active) VALUES ('Normal', 'IssuePriority', TRUE, TRUE) \
+             RETURNING id",
+        )
+        .await;
+        for (type_id, old_status_id) in [(bug, done), (bug, triage), (task, triage), (task, new)] {
+            sqlx::query("INSERT INTO workflows (type_id, old_status_id, new_status_id, role_id) VALUES ($1, $2, $3, $4)")
+                .bind(type_id)
+                .bind(old_status_id)
+                .bind(done)
+                .bind(role)
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+        }
+        let version: Id =
+            sqlx::query_scalar("INSERT INTO versions (project_id, name, status) VALUES ($1, '1.0', 'open') RETURNING id")
+                .bind(project)
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+        drop(conn);
+
+        let repo = db.work_packages();
+        let bug_defaults = repo.find_defaults(project, bug).await.unwrap();
+        assert_eq!(
+            bug_defaults,
+            WorkPackageDefaultsRow {
+                type_status_id: Some(triage),
+                status_id: Some(new),
+                priority_id: Some(normal),
+                version_id: None,
+            }
+        );
+        // Workflows starting from the default status keep it
+        assert_eq!(repo.find_defaults(project, task).await.unwrap().type_status_id, Some(new));
+        assert_eq!(repo.find_defaults(project, task + 1000).await.unwrap().type_status_id, None);
+
+        let mut conn = db.executor().acquire().await.unwrap();
+        sqlx::query("UPDATE projects SET default_version_id = $1 WHERE id = $2")
+            .bind(version)
+            .bind(project)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        drop(conn);
+        assert_eq!(repo.find_defaults(project, bug).await.unwrap().version_id, Some(version));
+
+        // Closed versions take no new work packages
+        let mut conn = db.executor().acquire().await.unwrap();
+        sqlx::query("UPDATE versions SET status = 'closed' WHERE id = $1")
+            .bind(version)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        drop(conn);
+        assert_eq!(repo.find_defaults(project, bug).await.unwrap().version_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_condensed_work_packages_in_one_query() {
+        let db = TestDb::connect().await;
+        let author = db.insert_user(UserFixture::new("author")).await;
+        let project = db.insert_project(ProjectFixture::new("condensed")).await;
+        let first = db
+            .insert_work_package(WorkPackageFixture::new(project, author).with_subject("First"))
+            .await;
+        let second = db.insert_work_package(WorkPackageFixture::new(project, author)).await;
+        let mut conn = db.executor().acquire().await.unwrap();
+        let color: Id = sqlx::query_scalar("INSERT INTO colors (name, hexcode) VALUES ('Blue', '#1A67A3') RETURNING id")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE statuses SET color_id = $1 WHERE id = (SELECT status_id FROM work_packages WHERE id = $2)")
+            .bind(color)
+            .bind(first)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let metrics = Arc::new(DomainMetrics::new());
+        let repo = db.work_packages().with_metrics(metrics.clone());
+        let found = repo.find_condensed(&[second, first, second + 1000], None).await.unwrap();
+
+        assert_eq!(found.iter().map(|wp| wp.id).collect::<Vec<_>>(), vec![first, second]);
+        assert_eq!(found[0].subject, "First");
+        assert_eq!(found[0].project_name.as_deref(), Some("condensed"));
+        assert_eq!(found[0].status_name.as_deref(), Some("New"));
+        assert_eq!(found[0].status_color.as_deref(), Some("#1A67A3"));
+        assert!(found[0].type_name.is_some());
+        assert_eq!(metrics.query_histogram("work_packages", "find_condensed").unwrap().count(), 1);
+    }
+}
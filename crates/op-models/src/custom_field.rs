@@ -0,0 +1,164 @@
+//! Custom field model
+//!
+//! Mirrors: app/models/custom_field.rb, app/models/project_custom_field.rb
+//! Table: custom_fields
+//!
+//! Custom fields add attributes to work packages or projects. Their values
+//! are stored as text in `custom_values`, one row per field and customized
+//! record, and read according to the format of the field.
+
+use chrono::NaiveDate;
+use op_core::traits::Id;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// `custom_fields.type` of work package custom fields
+pub const WORK_PACKAGE_CUSTOM_FIELD: &str = "WorkPackageCustomField";
+
+/// `custom_fields.type` of project custom fields
+pub const PROJECT_CUSTOM_FIELD: &str = "ProjectCustomField";
+
+/// Custom field of projects or work packages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomField {
+    pub id: Id,
+    pub name: String,
+    /// `string`, `text`, `int`, `float`, `bool`, `date`, `list`, `user` or
+    /// `version`
+    pub field_format: String,
+    pub is_required: bool,
+    pub position: i32,
+    /// Section the field is shown in; project custom fields only
+    pub section: Option<CustomFieldSection>,
+}
+
+/// Named group of project custom fields, ordered by position
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomFieldSection {
+    pub id: Id,
+    pub name: String,
+    pub position: i32,
+}
+
+impl CustomField {
+    /// Name of the field in API v3 resources, e.g. `customField3`
+    pub fn attribute_name(&self) -> String {
+        attribute_name(self.id)
+    }
+
+    /// Schema type of the values, e.g. `Integer`
+    pub fn schema_type(&self) -> &'static str {
+        schema_type(&self.field_format)
+    }
+
+    /// Code and message of the error of a stored value that does not fit
+    /// the format; list, user and version values are not checked
+    pub fn format_error(&self, value: &str) -> Option<(&'static str, &'static str)> {
+        let valid = match self.field_format.as_str() {
+            "int" => value.trim().parse::<i64>().is_ok(),
+            "float" => value.trim().parse::<f64>().is_ok_and(f64::is_finite),
+            "bool" => parse_bool(value).is_some(),
+            "date" => NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+            _ => true,
+        };
+        match (valid, self.field_format.as_str()) {
+            (true, _) => None,
+            (false, "int") => Some(("not_an_integer", "must be an integer")),
+            (false, "float") => Some(("not_a_number", "is not a number")),
+            (false, "date") => Some(("not_a_date", "is not a valid date")),
+            (false, _) => Some(("invalid", "is invalid")),
+        }
+    }
+
+    /// Stored value as JSON of the schema type
+    pub fn typed_value(&self, value: &str) -> Value {
+        typed_value(&self.field_format, value)
+    }
+}
+
+/// Name of the custom field in API v3 resources, e.g. `customField3`
+pub fn attribute_name(id: Id) -> String {
+    format!("customField{}", id)
+}
+
+/// Id of the custom field an API v3 attribute like `customField3` names
+pub fn attribute_id(name: &str) -> Option<Id> {
+    name.strip_prefix("customField")?.parse().ok()
+}
+
+/// Schema type of the values of a field format
+pub fn schema_type(field_format: &str) -> &'static str {
+    match field_format {
+        "text" => "Formattable",
+        "int" => "Integer",
+        "float" => "Float",
+        "bool" => "Boolean",
+        "date" => "Date",
+        "list" => "CustomOption",
+        "user" => "User",
+        "version" => "Version",
+        _ => "String",
+    }
+}
+
+/// Stored value of a field format as JSON of its schema type; values not
+/// fitting the format are kept as strings
+pub fn typed_value(field_format: &str, value: &str) -> Value {
+    let typed = match field_format {
+        "int" => value.trim().parse::<i64>().ok().map(Value::from),
+        "float" => value.trim().parse::<f64>().ok().map(Value::from),
+        "bool" => parse_bool(value).map(Value::from),
+        _ => None,
+    };
+    typed.unwrap_or_else(|| json!(value))
+}
+
+/// Boolean of a stored value, `t` and `f` as written by OpenProject
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "t" | "1" | "true" => Some(true),
+        "f" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(field_format: &str) -> CustomField {
+        CustomField {
+            id: 3,
+            name: "Budget".into(),
+            field_format: field_format.into(),
+            is_required: false,
+            position: 1,
+            section: None,
+        }
+    }
+
+    #[test]
+    fn test_attribute_names() {
+        assert_eq!(field("int").attribute_name(), "customField3");
+        assert_eq!(attribute_id("customField3"), Some(3));
+        assert_eq!(attribute_id("customField"), None);
+        assert_eq!(attribute_id("name"), None);
+    }
+
+    #[test]
+    fn test_values_are_checked_and_typed_by_format() {
+        assert_eq!(field("int").format_error("12"), None);
+        assert_eq!(field("int").format_error("1.5").unwrap().0, "not_an_integer");
+        assert_eq!(field("float").format_error("NaN").unwrap().0, "not_a_number");
+        assert_eq!(field("date").format_error("2024-02-30").unwrap().0, "not_a_date");
+        assert_eq!(field("bool").format_error("yes").unwrap().0, "invalid");
+        assert_eq!(field("string").format_error("anything"), None);
+
+        assert_eq!(field("int").typed_value("12"), json!(12));
+        assert_eq!(field("float").typed_value("1.5"), json!(1.5));
+        assert_eq!(field("bool").typed_value("f"), json!(false));
+        assert_eq!(field("date").typed_value("2024-02-01"), json!("2024-02-01"));
+        assert_eq!(field("int").typed_value("legacy"), json!("legacy"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Document model
+//!
+//! Mirrors: modules/documents/app/models/document.rb
+//! Table: documents
+
+use chrono::{DateTime, Utc};
+use op_core::traits::{Entity, HalRepresentable, Id, Identifiable, ProjectScoped, Timestamped};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Document of a project
+///
+/// Its files are attachments with container type `Document`.
+///
+/// # Ruby equivalent
+/// ```ruby
+/// class Document < ApplicationRecord
+///   belongs_to :project
+///   belongs_to :category, class_name: "DocumentCategory"
+///   acts_as_attachable
+///   validates_presence_of :project, :title, :category
+/// end
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    pub id: Option<Id>,
+
+    pub project_id: Id,
+
+    pub category_id: Option<Id>,
+
+    #[validate(length(min = 1, max = 255))]
+    pub title: String,
+
+    pub description: Option<String>,
+
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Identifiable for Document {
+    fn id(&self) -> Option<Id> {
+        self.id
+    }
+}
+
+impl Timestamped for Document {
+    fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+}
+
+impl ProjectScoped for Document {
+    fn project_id(&self) -> Option<Id> {
+        Some(self.project_id)
+    }
+}
+
+impl Entity for Document {
+    const TABLE_NAME: &'static str = "documents";
+    const TYPE_NAME: &'static str = "Document";
+}
+
+impl HalRepresentable for Document {
+    fn hal_type(&self) -> &'static str {
+        "Document"
+    }
+
+    fn self_href(&self) -> String {
+        format!("/api/v3/documents/{}", self.id.unwrap_or(0))
+    }
+}
+
+/// Category of documents
+///
+/// Mirrors: modules/documents/app/models/document_category.rb
+/// Table: enumerations (with type = 'DocumentCategory')
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentCategory {
+    pub id: Option<Id>,
+
+    pub name: String,
+
+    #[serde(default)]
+    pub position: i32,
+
+    /// Category of new documents that name none
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+impl Identifiable for DocumentCategory {
+    fn id(&self) -> Option<Id> {
+        self.id
+    }
+}
+
+impl HalRepresentable for DocumentCategory {
+    fn hal_type(&self) -> &'static str {
+        "DocumentCategory"
+    }
+
+    fn self_href(&self) -> String {
+        format!("/api/v3/document_categories/{}", self.id.unwrap_or(0))
+    }
+}
+
+impl DocumentCategory {
+    /// Standard category names
+    pub const DOCUMENTATION: &'static str = "Documentation";
+    pub const SPECIFICATION: &'static str = "Specification";
+    pub const OTHER: &'static str = "Other";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_hrefs() {
+        let document = Document {
+            id: Some(4),
+            project_id: 1,
+            title: "Handbook".into(),
+            ..Default::default()
+        };
+        assert_eq!(document.self_href(), "/api/v3/documents/4");
+        assert_eq!(ProjectScoped::project_id(&document), Some(1));
+        assert!(document.validate().is_ok());
+    }
+}
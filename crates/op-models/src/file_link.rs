@@ -0,0 +1,116 @@
+//! File link model
+//!
+//! Mirrors: modules/storages/app/models/storages/file_link.rb
+//! Table: file_links
+
+use chrono::{DateTime, Utc};
+use op_core::traits::{Entity, HalRepresentable, Id, Identifiable, Timestamped};
+use serde::{Deserialize, Serialize};
+
+/// Link from a work package to a file in a storage
+///
+/// The origin fields keep what the storage reported about the file when it
+/// was linked; the file itself may have been renamed or deleted since.
+///
+/// # Ruby equivalent
+/// ```ruby
+/// class Storages::FileLink < ApplicationRecord
+///   belongs_to :storage
+///   belongs_to :container, polymorphic: true
+///   validates :origin_id, :origin_name, presence: true
+/// end
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FileLink {
+    pub id: Option<Id>,
+
+    pub storage_id: Id,
+
+    pub creator_id: Option<Id>,
+
+    /// Linked work package
+    pub container_id: Id,
+
+    pub container_type: String,
+
+    /// Id of the file in the storage
+    pub origin_id: String,
+
+    pub origin_name: String,
+
+    pub origin_mime_type: Option<String>,
+
+    pub origin_created_by_name: Option<String>,
+
+    pub origin_created_at: Option<DateTime<Utc>>,
+    pub origin_updated_at: Option<DateTime<Utc>>,
+
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Identifiable for FileLink {
+    fn id(&self) -> Option<Id> {
+        self.id
+    }
+}
+
+impl Timestamped for FileLink {
+    fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+}
+
+impl Entity for FileLink {
+    const TABLE_NAME: &'static str = "file_links";
+    const TYPE_NAME: &'static str = "FileLink";
+}
+
+impl HalRepresentable for FileLink {
+    fn hal_type(&self) -> &'static str {
+        "FileLink"
+    }
+
+    fn self_href(&self) -> String {
+        format!("/api/v3/file_links/{}", self.id.unwrap_or(0))
+    }
+}
+
+impl FileLink {
+    /// Container type of links on work packages
+    pub const WORK_PACKAGE: &'static str = "WorkPackage";
+
+    /// Link a file to a work package
+    pub fn for_work_package(
+        work_package_id: Id,
+        storage_id: Id,
+        origin_id: impl Into<String>,
+        origin_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            storage_id,
+            container_id: work_package_id,
+            container_type: Self::WORK_PACKAGE.to_string(),
+            origin_id: origin_id.into(),
+            origin_name: origin_name.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_link_for_work_package() {
+        let link = FileLink::for_work_package(17, 1, "5503", "logo.png");
+        assert_eq!(link.container_type, "WorkPackage");
+        assert_eq!(link.self_href(), "/api/v3/file_links/0");
+    }
+}
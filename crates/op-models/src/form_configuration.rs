@@ -0,0 +1,292 @@
+//! Form configuration of work package types
+//!
+//! Mirrors: app/models/type/attribute_groups.rb, app/models/type/attribute_group.rb,
+//! app/models/type/query_group.rb
+//!
+//! A type lays out the work package form in groups: attribute groups list
+//! attributes in order, query groups embed a table of the work package's
+//! children. Attributes are keyed like in OpenProject, e.g. `assignee`,
+//! `estimated_time` or `cf_3` for the custom field 3.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use op_core::traits::Id;
+use serde::{Deserialize, Serialize};
+
+/// Attributes of work packages a form group can reference, besides custom
+/// fields
+pub const FORM_ATTRIBUTES: &[&str] = &[
+    "id", "subject", "description", "type", "status", "project", "parent", "author",
+    "assignee", "responsible", "priority", "category", "version", "date", "start_date",
+    "due_date", "duration", "estimated_time", "remaining_time", "spent_time",
+    "percentage_done", "story_points", "budget", "created_at", "updated_at",
+];
+
+/// Groups of the work package form of a type, in display order
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FormConfiguration {
+    pub groups: Vec<FormGroup>,
+}
+
+/// Group of the work package form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "_type")]
+pub enum FormGroup {
+    /// Attributes shown together, in order
+    #[serde(rename = "WorkPackageFormAttributeGroup")]
+    AttributeGroup { name: String, attributes: Vec<String> },
+    /// Table of the children of the work package selected by a query
+    #[serde(rename = "WorkPackageFormQueryGroup")]
+    QueryGroup { name: String, query: QueryGroupProps },
+}
+
+/// Query of the embedded table of a query group
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryGroupProps {
+    /// Attributes shown as columns
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Filters in the API v3 syntax, applied on top of the children
+    #[serde(default)]
+    pub filters: Vec<serde_json::Value>,
+    /// Sort criteria as `[attribute, direction]` pairs
+    #[serde(default)]
+    pub sort_by: Vec<[String; 2]>,
+}
+
+/// Reason a form configuration is rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormConfigurationError {
+    /// A group without a name
+    BlankName,
+    /// An attribute that does not exist, e.g. a deleted custom field
+    UnknownAttribute(String),
+    /// An attribute placed in more than one group
+    DuplicateAttribute(String),
+}
+
+impl fmt::Display for FormConfigurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlankName => write!(f, "contains a group without a name."),
+            Self::UnknownAttribute(key) => write!(f, "contains the unknown attribute '{}'.", key),
+            Self::DuplicateAttribute(key) => write!(f, "contains the attribute '{}' more than once.", key),
+        }
+    }
+}
+
+impl FormGroup {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::AttributeGroup { name, .. } | Self::QueryGroup { name, .. } => name,
+        }
+    }
+
+    /// Attributes referenced by the group, the columns for a query group
+    pub fn attributes(&self) -> &[String] {
+        match self {
+            Self::AttributeGroup { attributes, .. } => attributes,
+            Self::QueryGroup { query, .. } => &query.columns,
+        }
+    }
+}
+
+impl FormConfiguration {
+    pub fn new(groups: Vec<FormGroup>) -> Self {
+        Self { groups }
+    }
+
+    /// Configuration of types that were never configured, with the custom
+    /// fields in a group of their own
+    pub fn default_for(custom_field_ids: &[Id]) -> Self {
+        let group = |name: &str, attributes: &[&str]| FormGroup::AttributeGroup {
+            name: name.into(),
+            attributes: attributes.iter().map(|key| key.to_string()).collect(),
+        };
+        let mut groups = vec![
+            group("People", &["assignee", "responsible"]),
+            group("Estimates and progress", &["estimated_time", "remaining_time", "spent_time", "percentage_done"]),
+            group("Details", &["category", "date", "priority", "version"]),
+        ];
+        if !custom_field_ids.is_empty() {
+            groups.push(FormGroup::AttributeGroup {
+                name: "Other".into(),
+                attributes: custom_field_ids.iter().map(|id| custom_field_key(*id)).collect(),
+            });
+        }
+        Self { groups }
+    }
+
+    /// Check the group names and that every attribute exists and is placed
+    /// once; query columns may repeat attributes of other groups
+    pub fn validate(&self, custom_field_ids: &[Id]) -> Result<(), FormConfigurationError> {
+        let mut placed = HashSet::new();
+        for group in &self.groups {
+            if group.name().trim().is_empty() {
+                return Err(FormConfigurationError::BlankName);
+            }
+            for key in group.attributes() {
+                if !is_known_attribute(key, custom_field_ids) {
+                    return Err(FormConfigurationError::UnknownAttribute(key.clone()));
+                }
+            }
+            if let FormGroup::AttributeGroup { attributes, .. } = group {
+                if let Some(key) = attributes.iter().find(|key| !placed.insert(key.as_str())) {
+                    return Err(FormConfigurationError::DuplicateAttribute(key.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Referenced attributes that do not exist, in order of appearance
+    pub fn unknown_attributes(&self, custom_field_ids: &[Id]) -> Vec<String> {
+        let mut unknown: Vec<String> = Vec::new();
+        for key in self.groups.iter().flat_map(FormGroup::attributes) {
+            if !is_known_attribute(key, custom_field_ids) && !unknown.contains(key) {
+                unknown.push(key.clone());
+            }
+        }
+        unknown
+    }
+
+    /// Configuration without the attributes that do not exist, dropping
+    /// attribute groups left empty
+    pub fn without_unknown(&self, custom_field_ids: &[Id]) -> Self {
+        let known = |keys: &[String]| -> Vec<String> {
+            keys.iter().filter(|key| is_known_attribute(key, custom_field_ids)).cloned().collect()
+        };
+        let groups = self
+            .groups
+            .iter()
+            .filter_map(|group| match group {
+                FormGroup::AttributeGroup { name, attributes } => {
+                    let attributes = known(attributes);
+                    (!attributes.is_empty()).then(|| FormGroup::AttributeGroup { name: name.clone(), attributes })
+                }
+                FormGroup::QueryGroup { name, query } => Some(FormGroup::QueryGroup {
+                    name: name.clone(),
+                    query: QueryGroupProps { columns: known(&query.columns), ..query.clone() },
+                }),
+            })
+            .collect();
+        Self { groups }
+    }
+}
+
+/// Key of a custom field in form configurations, e.g. `cf_3`
+pub fn custom_field_key(id: Id) -> String {
+    format!("cf_{}", id)
+}
+
+/// Id of the custom field a key like `cf_3` references
+pub fn custom_field_id(key: &str) -> Option<Id> {
+    key.strip_prefix("cf_")?.parse().ok()
+}
+
+/// Whether the key is a work package attribute or one of the custom fields
+pub fn is_known_attribute(key: &str, custom_field_ids: &[Id]) -> bool {
+    match custom_field_id(key) {
+        Some(id) => custom_field_ids.contains(&id),
+        None => FORM_ATTRIBUTES.contains(&key),
+    }
+}
+
+/// API v3 name of an attribute, e.g. `estimated_time` -> `estimatedTime`
+/// and `cf_3` -> `customField3`
+pub fn api_attribute_name(key: &str) -> String {
+    if let Some(id) = custom_field_id(key) {
+        return format!("customField{}", id);
+    }
+    let mut words = key.split('_');
+    let mut name = words.next().unwrap_or_default().to_string();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn attributes(name: &str, keys: &[&str]) -> FormGroup {
+        FormGroup::AttributeGroup {
+            name: name.into(),
+            attributes: keys.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_round_trips_through_json() {
+        let configuration = FormConfiguration::new(vec![
+            attributes("People", &["assignee", "cf_3"]),
+            FormGroup::QueryGroup {
+                name: "Children".into(),
+                query: QueryGroupProps {
+                    columns: vec!["subject".into(), "status".into()],
+                    filters: vec![json!({ "status": { "operator": "o", "values": [] } })],
+                    sort_by: vec![["id".into(), "asc".into()]],
+                },
+            },
+        ]);
+
+        let value = serde_json::to_value(&configuration).unwrap();
+        assert_eq!(value[0]["_type"], "WorkPackageFormAttributeGroup");
+        assert_eq!(value[1]["_type"], "WorkPackageFormQueryGroup");
+        assert_eq!(value[1]["query"]["sortBy"], json!([["id", "asc"]]));
+        assert_eq!(serde_json::from_value::<FormConfiguration>(value).unwrap(), configuration);
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_and_duplicate_attributes() {
+        let valid = FormConfiguration::new(vec![attributes("Details", &["priority", "cf_3"])]);
+        assert_eq!(valid.validate(&[3]), Ok(()));
+        assert_eq!(
+            valid.validate(&[4]),
+            Err(FormConfigurationError::UnknownAttribute("cf_3".into()))
+        );
+
+        let duplicate = FormConfiguration::new(vec![
+            attributes("People", &["assignee"]),
+            attributes("Details", &["assignee"]),
+        ]);
+        assert_eq!(
+            duplicate.validate(&[]),
+            Err(FormConfigurationError::DuplicateAttribute("assignee".into()))
+        );
+        assert_eq!(
+            FormConfiguration::new(vec![attributes(" ", &[])]).validate(&[]),
+            Err(FormConfigurationError::BlankName)
+        );
+    }
+
+    #[test]
+    fn test_unknown_attributes_are_filtered() {
+        let configuration = FormConfiguration::new(vec![
+            attributes("Details", &["priority", "cf_9", "bogus"]),
+            attributes("Other", &["cf_9"]),
+        ]);
+
+        assert_eq!(configuration.unknown_attributes(&[3]), vec!["cf_9", "bogus"]);
+        assert_eq!(
+            configuration.without_unknown(&[3]),
+            FormConfiguration::new(vec![attributes("Details", &["priority"])])
+        );
+    }
+
+    #[test]
+    fn test_api_attribute_names() {
+        assert_eq!(api_attribute_name("percentage_done"), "percentageDone");
+        assert_eq!(api_attribute_name("cf_12"), "customField12");
+        assert_eq!(api_attribute_name("assignee"), "assignee");
+    }
+}
@@ -13,10 +13,15 @@ pub mod project;
 pub mod work_package;
 pub mod status;
 pub mod type_def;
+pub mod form_configuration;
+pub mod custom_field;
 pub mod priority;
 pub mod version;
 pub mod member;
 pub mod role;
+pub mod storage;
+pub mod file_link;
+pub mod document;
 
 // Re-exports for convenience
 pub use user::model::{User, NewUser, UpdateUser};
@@ -24,7 +29,12 @@ pub use project::{Project, CreateProjectDto, UpdateProjectDto, ProjectStatusCode
 pub use work_package::model::WorkPackage;
 pub use status::Status;
 pub use type_def::Type;
+pub use form_configuration::{FormConfiguration, FormConfigurationError, FormGroup, QueryGroupProps};
+pub use custom_field::{CustomField, CustomFieldSection};
 pub use priority::Priority;
 pub use version::{Version, VersionStatus, VersionSharing, CreateVersionDto};
 pub use member::{Member, CreateMemberDto, UpdateMemberDto};
 pub use role::{Role, permissions};
+pub use storage::{Storage, StorageType};
+pub use file_link::FileLink;
+pub use document::{Document, DocumentCategory};
@@ -157,6 +157,7 @@ pub mod permissions {
     pub const COMMENT_ON_WORK_PACKAGES: &str = "comment_on_work_packages";
     pub const LOG_TIME: &str = "log_time";
     pub const VIEW_TIME_ENTRIES: &str = "view_time_entries";
+    pub const VIEW_OWN_TIME_ENTRIES: &str = "view_own_time_entries";
 
     // Project permissions
     pub const VIEW_PROJECT: &str = "view_project";
@@ -178,11 +179,20 @@ pub mod permissions {
     pub const ADD_MESSAGES: &str = "add_messages";
     pub const EDIT_MESSAGES: &str = "edit_messages";
     pub const DELETE_MESSAGES: &str = "delete_messages";
+    pub const MANAGE_FORUMS: &str = "manage_forums";
 
     // File permissions
     pub const VIEW_FILES: &str = "view_files";
     pub const MANAGE_FILES: &str = "manage_files";
 
+    // File link permissions
+    pub const VIEW_FILE_LINKS: &str = "view_file_links";
+    pub const MANAGE_FILE_LINKS: &str = "manage_file_links";
+
+    // Document permissions
+    pub const VIEW_DOCUMENTS: &str = "view_documents";
+    pub const MANAGE_DOCUMENTS: &str = "manage_documents";
+
     // Meeting permissions
     pub const VIEW_MEETINGS: &str = "view_meetings";
     pub const CREATE_MEETINGS: &str = "create_meetings";
@@ -192,6 +202,12 @@ pub mod permissions {
     // Budget permissions
     pub const VIEW_BUDGETS: &str = "view_budgets";
     pub const EDIT_BUDGETS: &str = "edit_budgets";
+
+    // Cost permissions
+    pub const LOG_COSTS: &str = "log_costs";
+    pub const VIEW_COST_ENTRIES: &str = "view_cost_entries";
+    pub const VIEW_OWN_COST_ENTRIES: &str = "view_own_cost_entries";
+    pub const VIEW_HOURLY_RATES: &str = "view_hourly_rates";
 }
 
 #[cfg(test)]
@@ -0,0 +1,162 @@
+//! Storage model
+//!
+//! Mirrors: modules/storages/app/models/storages/storage.rb
+//! Table: storages
+//!
+//! Storages are external file stores, e.g. a Nextcloud instance, whose
+//! files can be linked to work packages.
+
+use chrono::{DateTime, Utc};
+use op_core::traits::{Entity, HalRepresentable, Id, Identifiable, Timestamped};
+use serde::{Deserialize, Serialize};
+
+/// Kind of file store a storage connects to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum StorageType {
+    #[default]
+    Nextcloud,
+    OneDrive,
+}
+
+impl StorageType {
+    pub const ALL: [Self; 2] = [Self::Nextcloud, Self::OneDrive];
+
+    /// Type of a `storages.provider_type` value
+    pub fn from_provider_type(provider_type: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.provider_type() == provider_type)
+    }
+
+    /// Type of an API type URN, e.g. `urn:openproject-org:api:v3:storages:Nextcloud`
+    pub fn from_urn(urn: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.urn() == urn)
+    }
+
+    /// The `storages.provider_type` value
+    pub fn provider_type(self) -> &'static str {
+        match self {
+            Self::Nextcloud => "Storages::NextcloudStorage",
+            Self::OneDrive => "Storages::OneDriveStorage",
+        }
+    }
+
+    /// Display name
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nextcloud => "Nextcloud",
+            Self::OneDrive => "OneDrive",
+        }
+    }
+
+    /// URN identifying the type in the API
+    pub fn urn(self) -> String {
+        format!("urn:openproject-org:api:v3:storages:{}", self.name())
+    }
+}
+
+/// Storage entity
+///
+/// # Ruby equivalent
+/// ```ruby
+/// class Storages::Storage < ApplicationRecord
+///   has_many :file_links
+///   has_one :oauth_client, as: :integration
+///   validates :name, :host, presence: true, uniqueness: true
+/// end
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Storage {
+    pub id: Option<Id>,
+
+    pub storage_type: StorageType,
+
+    /// Unique name
+    pub name: String,
+
+    /// Base URL of the storage, without a trailing slash
+    pub host: String,
+
+    pub creator_id: Option<Id>,
+
+    /// Client OpenProject authenticates with at the storage
+    pub oauth_client_id: Option<String>,
+
+    #[serde(skip_serializing)]
+    pub oauth_client_secret: Option<String>,
+
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Identifiable for Storage {
+    fn id(&self) -> Option<Id> {
+        self.id
+    }
+}
+
+impl Timestamped for Storage {
+    fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+}
+
+impl Entity for Storage {
+    const TABLE_NAME: &'static str = "storages";
+    const TYPE_NAME: &'static str = "Storage";
+}
+
+impl HalRepresentable for Storage {
+    fn hal_type(&self) -> &'static str {
+        "Storage"
+    }
+
+    fn self_href(&self) -> String {
+        format!("/api/v3/storages/{}", self.id.unwrap_or(0))
+    }
+}
+
+impl Storage {
+    /// Create a new storage
+    pub fn new(storage_type: StorageType, name: impl Into<String>, host: impl Into<String>) -> Self {
+        Self {
+            storage_type,
+            name: name.into(),
+            host: host.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether OpenProject has a client to authenticate at the storage
+    pub fn has_oauth_client(&self) -> bool {
+        self.oauth_client_id.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_types() {
+        assert_eq!(
+            StorageType::from_provider_type("Storages::NextcloudStorage"),
+            Some(StorageType::Nextcloud)
+        );
+        assert_eq!(
+            StorageType::from_urn("urn:openproject-org:api:v3:storages:OneDrive"),
+            Some(StorageType::OneDrive)
+        );
+        assert_eq!(StorageType::from_provider_type("Storages::Dropbox"), None);
+    }
+
+    #[test]
+    fn test_storage_new() {
+        let storage = Storage::new(StorageType::Nextcloud, "Cloud", "https://cloud.example.com");
+        assert_eq!(storage.name, "Cloud");
+        assert!(!storage.has_oauth_client());
+    }
+}
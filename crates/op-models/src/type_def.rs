@@ -10,6 +10,8 @@ use op_core::traits::{Entity, Id, Identifiable, Timestamped, HalRepresentable};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+use crate::form_configuration::FormConfiguration;
+
 /// Work package type entity
 ///
 /// Types categorize work packages (Task, Bug, Feature, Epic, etc.)
@@ -54,9 +56,9 @@ pub struct Type {
     /// Description
     pub description: Option<String>,
 
-    /// Attribute groups configuration (JSON)
-    /// Defines which attributes are shown in which groups for this type
-    pub attribute_groups: Option<serde_json::Value>,
+    /// Form configuration: which attributes are shown in which groups for
+    /// this type, the default layout when `None`
+    pub attribute_groups: Option<FormConfiguration>,
 
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
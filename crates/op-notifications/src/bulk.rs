@@ -0,0 +1,255 @@
+//! Bulk Operation Summaries
+//!
+//! Bulk updates, project copies and imports touch many work packages at
+//! once. Notifying every watcher and assignee about each of them would send
+//! thousands of emails, so while such an operation runs, notifications are
+//! suppressed: the work package fan-out records what it would have notified
+//! in a [`SuppressionWindow`] instead. The window keeps one [`BulkSummary`]
+//! per actor, project and [`BulkOperation`].
+//!
+//! When the operation is done, the [`SummaryMailer`] sends each recipient of
+//! a summary a single email ("142 work packages updated in Project X by
+//! Alice") linking to the affected work packages.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+
+use crate::email::{EmailRenderer, EmailSender};
+use crate::service::{ServiceError, ServiceResult};
+
+/// Kind of bulk operation whose notifications are summarized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BulkOperation {
+    Update,
+    Copy,
+    Import,
+}
+
+impl BulkOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkOperation::Update => "update",
+            BulkOperation::Copy => "copy",
+            BulkOperation::Import => "import",
+        }
+    }
+}
+
+/// What one actor did to the work packages of a project in a bulk operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkSummary {
+    pub actor_id: Id,
+    pub project_id: Id,
+    pub operation: BulkOperation,
+    pub work_package_ids: BTreeSet<Id>,
+    /// Users who would have been notified about any of the work packages
+    pub recipient_ids: BTreeSet<Id>,
+}
+
+impl BulkSummary {
+    /// Number of work packages the operation touched
+    pub fn count(&self) -> usize {
+        self.work_package_ids.len()
+    }
+}
+
+/// Notifications held back while a bulk operation runs
+#[derive(Debug, Default)]
+pub struct SuppressionWindow {
+    summaries: Mutex<BTreeMap<(Id, Id, BulkOperation), BulkSummary>>,
+}
+
+impl SuppressionWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the notification of `recipients` about a work package instead
+    /// of sending it. Actors are not told about their own operations.
+    pub fn record(&self, operation: BulkOperation, actor_id: Id, project_id: Id, work_package_id: Id, recipients: &[Id]) {
+        let mut summaries = self.summaries.lock().expect("suppression window lock poisoned");
+        let summary = summaries
+            .entry((actor_id, project_id, operation))
+            .or_insert_with(|| BulkSummary {
+                actor_id,
+                project_id,
+                operation,
+                work_package_ids: BTreeSet::new(),
+                recipient_ids: BTreeSet::new(),
+            });
+        summary.work_package_ids.insert(work_package_id);
+        summary
+            .recipient_ids
+            .extend(recipients.iter().copied().filter(|&id| id != actor_id));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.summaries.lock().expect("suppression window lock poisoned").is_empty()
+    }
+
+    /// End the window, returning its summaries
+    pub fn close(&self) -> Vec<BulkSummary> {
+        std::mem::take(&mut *self.summaries.lock().expect("suppression window lock poisoned"))
+            .into_values()
+            .collect()
+    }
+}
+
+/// A user emailed a summary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryRecipient {
+    pub id: Id,
+    pub email: String,
+    pub name: Option<String>,
+    pub language: Option<String>,
+}
+
+/// Looks up the names and addresses a summary email needs
+#[async_trait]
+pub trait SummaryDirectory: Send + Sync {
+    /// Display name of a user
+    async fn user_name(&self, user_id: Id) -> ServiceResult<Option<String>>;
+
+    /// Name of a project
+    async fn project_name(&self, project_id: Id) -> ServiceResult<Option<String>>;
+
+    /// The users to email among the given ones; users who are not active or
+    /// have no address are left out
+    async fn recipients(&self, user_ids: &[Id]) -> ServiceResult<Vec<SummaryRecipient>>;
+}
+
+/// Sends the summaries of closed suppression windows
+#[derive(Clone)]
+pub struct SummaryMailer {
+    sender: Arc<dyn EmailSender>,
+    renderer: EmailRenderer,
+}
+
+impl SummaryMailer {
+    pub fn new(sender: Arc<dyn EmailSender>, renderer: EmailRenderer) -> Self {
+        Self { sender, renderer }
+    }
+
+    /// Email every recipient of each summary once, returning the number of
+    /// emails sent. A failed send is logged and does not stop the others.
+    pub async fn send(&self, summaries: &[BulkSummary], directory: &dyn SummaryDirectory) -> ServiceResult<usize> {
+        let mut sent = 0;
+        for summary in summaries {
+            let recipient_ids: Vec<Id> = summary.recipient_ids.iter().copied().collect();
+            if recipient_ids.is_empty() {
+                continue;
+            }
+
+            let actor = directory
+                .user_name(summary.actor_id)
+                .await?
+                .unwrap_or_else(|| format!("#{}", summary.actor_id));
+            let project = directory
+                .project_name(summary.project_id)
+                .await?
+                .unwrap_or_else(|| format!("#{}", summary.project_id));
+
+            for recipient in directory.recipients(&recipient_ids).await? {
+                let message = self
+                    .renderer
+                    .render_bulk_summary(
+                        summary,
+                        &actor,
+                        &project,
+                        &recipient.email,
+                        recipient.name.as_deref(),
+                        recipient.language.as_deref(),
+                    )
+                    .map_err(|e| ServiceError::DeliveryError(e.to_string()))?;
+
+                match self.sender.send(&message).await {
+                    Ok(_) => sent += 1,
+                    Err(e) => {
+                        tracing::warn!(user_id = recipient.id, error = %e, "Failed to send bulk summary email");
+                    }
+                }
+            }
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::{EmailAddress, MemoryEmailSender};
+
+    struct Directory;
+
+    #[async_trait]
+    impl SummaryDirectory for Directory {
+        async fn user_name(&self, user_id: Id) -> ServiceResult<Option<String>> {
+            Ok((user_id == 1).then(|| "Alice".to_string()))
+        }
+
+        async fn project_name(&self, _project_id: Id) -> ServiceResult<Option<String>> {
+            Ok(Some("Project X".into()))
+        }
+
+        async fn recipients(&self, user_ids: &[Id]) -> ServiceResult<Vec<SummaryRecipient>> {
+            Ok(user_ids
+                .iter()
+                .filter(|&&id| id != 4)
+                .map(|&id| SummaryRecipient {
+                    id,
+                    email: format!("user{}@example.com", id),
+                    name: None,
+                    language: (id == 3).then(|| "de".to_string()),
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_window_keeps_one_summary_per_actor_project_and_operation() {
+        let window = SuppressionWindow::new();
+        for work_package_id in 1..=142 {
+            window.record(BulkOperation::Update, 1, 7, work_package_id, &[1, 2, 3]);
+        }
+        window.record(BulkOperation::Update, 1, 7, 5, &[4]);
+        window.record(BulkOperation::Update, 1, 8, 200, &[2]);
+        window.record(BulkOperation::Copy, 1, 7, 300, &[2]);
+
+        let summaries = window.close();
+        assert!(window.is_empty());
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(summaries[0].count(), 142);
+        // The actor is not a recipient of their own operation
+        assert_eq!(summaries[0].recipient_ids, BTreeSet::from([2, 3, 4]));
+        assert_eq!((summaries[1].operation, summaries[1].count()), (BulkOperation::Copy, 1));
+        assert_eq!((summaries[2].project_id, summaries[2].count()), (8, 1));
+    }
+
+    #[tokio::test]
+    async fn test_mailer_sends_one_email_per_recipient_and_summary() {
+        let window = SuppressionWindow::new();
+        for work_package_id in 1..=142 {
+            window.record(BulkOperation::Update, 1, 7, work_package_id, &[2, 3, 4]);
+        }
+        let sender = Arc::new(MemoryEmailSender::new());
+        let mailer = SummaryMailer::new(
+            sender.clone(),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        );
+
+        let sent = mailer.send(&window.close(), &Directory).await.unwrap();
+        assert_eq!(sent, 2);
+
+        let messages = sender.sent_messages().await;
+        assert_eq!(messages[0].to[0].email, "user2@example.com");
+        assert_eq!(messages[0].subject, "[OpenProject] 142 work packages updated in Project X by Alice");
+        assert!(messages[0].text_body.contains("http://localhost/projects/7/work_packages?filters="));
+        assert_eq!(
+            messages[1].subject,
+            "[OpenProject] 142 Arbeitspakete in Project X von Alice aktualisiert"
+        );
+    }
+}
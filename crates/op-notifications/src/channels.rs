@@ -2,11 +2,20 @@
 //!
 //! Mirrors: app/services/notifications/create_service.rb
 
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
 use async_trait::async_trait;
+use futures::future::{join_all, FutureExt};
+use op_core::traits::Id;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::notification::Notification;
+use crate::email::{EmailAddress, EmailRenderer, EmailSender};
+use crate::jobs::{Job, JobQueue};
+use crate::notification::{EmailFrequency, Notification, NotificationReason, NotificationSettings};
+use crate::service::{resolve_project_email_settings, NotificationStore, ProjectEmailSettingsSource};
+use crate::throttle::EmailThrottle;
 
 /// Channel errors
 #[derive(Debug, Error)]
@@ -46,44 +55,95 @@ pub enum Channel {
 pub struct ChannelConfig {
     pub channel: Channel,
     pub enabled: bool,
+    /// Reasons this channel delivers for (empty = all reasons)
+    #[serde(default)]
+    pub reasons: Vec<NotificationReason>,
     pub settings: serde_json::Value,
 }
 
 impl ChannelConfig {
-    pub fn in_app() -> Self {
+    pub fn new(channel: Channel) -> Self {
         Self {
-            channel: Channel::InApp,
+            channel,
             enabled: true,
+            reasons: Vec::new(),
             settings: serde_json::json!({}),
         }
     }
 
+    pub fn in_app() -> Self {
+        Self::new(Channel::InApp)
+    }
+
     pub fn email() -> Self {
-        Self {
-            channel: Channel::Email,
-            enabled: true,
-            settings: serde_json::json!({}),
-        }
+        Self::new(Channel::Email)
     }
 
     pub fn webhook(url: impl Into<String>) -> Self {
         Self {
-            channel: Channel::Webhook,
-            enabled: true,
             settings: serde_json::json!({
                 "url": url.into(),
             }),
+            ..Self::new(Channel::Webhook)
+        }
+    }
+
+    /// Only deliver for the given reasons
+    pub fn for_reasons(mut self, reasons: Vec<NotificationReason>) -> Self {
+        self.reasons = reasons;
+        self
+    }
+
+    /// Whether the channel delivers notifications with this reason
+    pub fn applies_to(&self, reason: NotificationReason) -> bool {
+        self.enabled && (self.reasons.is_empty() || self.reasons.contains(&reason))
+    }
+}
+
+/// The user a notification is delivered to
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub user_id: Id,
+    pub settings: NotificationSettings,
+    /// Address for immediate emails; without one, emails are sent by a job
+    /// that looks the address up
+    pub email: Option<EmailAddress>,
+    pub language: Option<String>,
+}
+
+impl Recipient {
+    pub fn new(settings: NotificationSettings) -> Self {
+        Self {
+            user_id: settings.user_id,
+            settings,
+            email: None,
+            language: None,
         }
     }
+
+    pub fn with_email(mut self, email: EmailAddress) -> Self {
+        self.email = Some(email);
+        self
+    }
+
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
 }
 
 /// Delivery result for a single channel
+///
+/// A successful result without message or job id means the delivery was
+/// deferred, e.g. to the recipient's email digest.
 #[derive(Debug, Clone)]
 pub struct DeliveryResult {
     pub channel: Channel,
     pub success: bool,
     pub message_id: Option<String>,
     pub error: Option<String>,
+    /// Job completing the delivery later (queued send or retry)
+    pub job_id: Option<String>,
 }
 
 impl DeliveryResult {
@@ -93,6 +153,7 @@ impl DeliveryResult {
             success: true,
             message_id: Some(message_id.into()),
             error: None,
+            job_id: None,
         }
     }
 
@@ -102,8 +163,42 @@ impl DeliveryResult {
             success: false,
             message_id: None,
             error: Some(error.into()),
+            job_id: None,
+        }
+    }
+
+    /// Delivery handed to a background job
+    pub fn queued(channel: Channel, job_id: impl Into<String>) -> Self {
+        Self {
+            channel,
+            success: true,
+            message_id: None,
+            error: None,
+            job_id: Some(job_id.into()),
         }
     }
+
+    /// Delivery left for a later batch, such as a digest
+    pub fn deferred(channel: Channel) -> Self {
+        Self {
+            channel,
+            success: true,
+            message_id: None,
+            error: None,
+            job_id: None,
+        }
+    }
+
+    /// Whether the notification was delivered, not queued or deferred
+    pub fn is_sent(&self) -> bool {
+        self.success && self.message_id.is_some()
+    }
+
+    /// Record the job retrying a failed delivery
+    pub fn with_retry(mut self, job_id: impl Into<String>) -> Self {
+        self.job_id = Some(job_id.into());
+        self
+    }
 }
 
 /// Channel handler trait
@@ -115,48 +210,216 @@ pub trait ChannelHandler: Send + Sync {
     /// Check if the channel is available
     fn is_available(&self) -> bool;
 
-    /// Deliver a notification
-    async fn deliver(&self, notification: &Notification) -> ChannelResult<DeliveryResult>;
+    /// Whether the recipient wants notifications on this channel
+    fn accepts(&self, _recipient: &Recipient) -> bool {
+        true
+    }
+
+    /// Deliver a notification to its recipient
+    async fn deliver(
+        &self,
+        notification: &Notification,
+        recipient: &Recipient,
+    ) -> ChannelResult<DeliveryResult>;
 }
 
 /// In-app channel handler (stores in database)
-pub struct InAppChannel {
-    enabled: bool,
+///
+/// Notifications created by the service are already stored in one batch
+/// and only confirmed here; notifications without an id are written.
+pub struct InAppChannel<S: NotificationStore> {
+    store: Arc<S>,
 }
 
-impl Default for InAppChannel {
-    fn default() -> Self {
-        Self::new()
+impl<S: NotificationStore> InAppChannel<S> {
+    pub fn new(store: Arc<S>) -> Self {
+        Self { store }
     }
 }
 
-impl InAppChannel {
-    pub fn new() -> Self {
-        Self { enabled: true }
+#[async_trait]
+impl<S: NotificationStore> ChannelHandler for InAppChannel<S> {
+    fn channel(&self) -> Channel {
+        Channel::InApp
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    fn accepts(&self, recipient: &Recipient) -> bool {
+        recipient.settings.in_app_enabled
+    }
+
+    async fn deliver(
+        &self,
+        notification: &Notification,
+        _recipient: &Recipient,
+    ) -> ChannelResult<DeliveryResult> {
+        let id = match notification.id {
+            Some(id) => id,
+            None => self
+                .store
+                .create(&mut notification.clone())
+                .await
+                .map_err(|e| ChannelError::DeliveryFailed(e.to_string()))?,
+        };
+
+        Ok(DeliveryResult::success(Channel::InApp, id.to_string()))
+    }
+}
+
+/// Job type sending a notification email
+pub const SEND_EMAIL_JOB: &str = "send_notification_email";
+
+/// Queue of the email jobs
+pub const MAILERS_QUEUE: &str = "mailers";
+
+/// Delay before retrying a transiently failed email, in seconds
+pub const EMAIL_RETRY_DELAY_SECS: i64 = 60;
+
+/// Email channel handler
+///
+/// Sends immediately when the recipient's address is known and they chose
+/// immediate emails, queues a send job when the address must be looked up,
+/// and leaves daily and weekly recipients to the digest. Transient send
+/// failures are retried through the job queue. With a throttle, sends over
+/// a limit or during a pause are queued to run once they may.
+pub struct EmailChannel<E: EmailSender, Q: JobQueue> {
+    sender: Arc<E>,
+    job_queue: Arc<Q>,
+    renderer: EmailRenderer,
+    throttle: Option<Arc<EmailThrottle>>,
+    project_settings: Option<Arc<dyn ProjectEmailSettingsSource>>,
+}
+
+impl<E: EmailSender, Q: JobQueue> EmailChannel<E, Q> {
+    pub fn new(sender: Arc<E>, job_queue: Arc<Q>, renderer: EmailRenderer) -> Self {
+        Self {
+            sender,
+            job_queue,
+            renderer,
+            throttle: None,
+            project_settings: None,
+        }
+    }
+
+    /// Keep sends within the throttle's limits
+    pub fn with_throttle(mut self, throttle: Arc<EmailThrottle>) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Send emails from the sender of the notification's project, if it
+    /// overrides the instance one
+    pub fn with_project_settings(mut self, settings: Arc<dyn ProjectEmailSettingsSource>) -> Self {
+        self.project_settings = Some(settings);
+        self
+    }
+
+    async fn enqueue_send(
+        &self,
+        notification: &Notification,
+        delay_secs: Option<i64>,
+    ) -> ChannelResult<String> {
+        let mut job = Job::new(
+            SEND_EMAIL_JOB,
+            serde_json::json!({
+                "notification_id": notification.id,
+                "recipient_id": notification.recipient_id,
+            }),
+        )
+        .queue(MAILERS_QUEUE);
+        if let Some(delay) = delay_secs {
+            job = job.run_in(delay);
+        }
+
+        self.job_queue
+            .enqueue(job)
+            .await
+            .map_err(|e| ChannelError::DeliveryFailed(e.to_string()))
     }
 }
 
 #[async_trait]
-impl ChannelHandler for InAppChannel {
+impl<E: EmailSender, Q: JobQueue> ChannelHandler for EmailChannel<E, Q> {
     fn channel(&self) -> Channel {
-        Channel::InApp
+        Channel::Email
     }
 
     fn is_available(&self) -> bool {
-        self.enabled
+        self.sender.is_configured()
     }
 
-    async fn deliver(&self, notification: &Notification) -> ChannelResult<DeliveryResult> {
-        if !self.enabled {
-            return Err(ChannelError::Disabled);
+    fn accepts(&self, recipient: &Recipient) -> bool {
+        recipient.settings.should_email()
+    }
+
+    async fn deliver(
+        &self,
+        notification: &Notification,
+        recipient: &Recipient,
+    ) -> ChannelResult<DeliveryResult> {
+        if recipient.settings.email_frequency != EmailFrequency::Immediate
+            || !recipient
+                .settings
+                .email_reason_enabled(notification.reason, notification.project_id)
+        {
+            return Ok(DeliveryResult::deferred(Channel::Email));
         }
 
-        // In a real implementation, this would store to database
-        // For now, we just return success
-        Ok(DeliveryResult::success(
-            Channel::InApp,
-            notification.id.map(|id| id.to_string()).unwrap_or_else(|| "pending".to_string()),
-        ))
+        let Some(address) = &recipient.email else {
+            let job_id = self.enqueue_send(notification, None).await?;
+            return Ok(DeliveryResult::queued(Channel::Email, job_id));
+        };
+
+        // An invalid address fails the same on every attempt
+        let project = resolve_project_email_settings(self.project_settings.as_ref(), notification).await;
+        let message = self
+            .renderer
+            .render_for_project(
+                notification,
+                &address.email,
+                address.name.as_deref(),
+                recipient.language.as_deref(),
+                project.as_ref(),
+            )
+            .map_err(|e| ChannelError::DeliveryFailed(e.to_string()))?;
+
+        if let Some(throttle) = &self.throttle {
+            if let Err(deferral) = throttle.acquire(&address.email) {
+                tracing::debug!(
+                    notification_id = ?notification.id,
+                    reason = ?deferral.reason,
+                    retry_in_secs = deferral.retry_in_secs(),
+                    "Deferring notification email"
+                );
+                let job_id = self.enqueue_send(notification, Some(deferral.retry_in_secs())).await?;
+                return Ok(DeliveryResult::queued(Channel::Email, job_id));
+            }
+        }
+
+        let sent = self.sender.send(&message).await;
+        if let Some(throttle) = &self.throttle {
+            match &sent {
+                Ok(_) => throttle.record_success(),
+                Err(e) => {
+                    throttle.record_failure(e).await;
+                }
+            }
+        }
+
+        match sent {
+            Ok(message_id) => Ok(DeliveryResult::success(Channel::Email, message_id)),
+            Err(e) if e.is_transient() => {
+                let result = DeliveryResult::failure(Channel::Email, e.to_string());
+                match self.enqueue_send(notification, Some(EMAIL_RETRY_DELAY_SECS)).await {
+                    Ok(job_id) => Ok(result.with_retry(job_id)),
+                    Err(_) => Ok(result),
+                }
+            }
+            Err(e) => Err(ChannelError::DeliveryFailed(e.to_string())),
+        }
     }
 }
 
@@ -192,7 +455,11 @@ impl ChannelHandler for WebhookChannel {
         self.enabled && !self.url.is_empty()
     }
 
-    async fn deliver(&self, notification: &Notification) -> ChannelResult<DeliveryResult> {
+    async fn deliver(
+        &self,
+        notification: &Notification,
+        _recipient: &Recipient,
+    ) -> ChannelResult<DeliveryResult> {
         if !self.is_available() {
             return Err(ChannelError::Disabled);
         }
@@ -224,7 +491,7 @@ impl ChannelHandler for WebhookChannel {
 
 /// Multi-channel dispatcher
 pub struct ChannelDispatcher {
-    handlers: Vec<Box<dyn ChannelHandler>>,
+    handlers: Vec<(ChannelConfig, Box<dyn ChannelHandler>)>,
 }
 
 impl Default for ChannelDispatcher {
@@ -240,30 +507,66 @@ impl ChannelDispatcher {
         }
     }
 
-    /// Add a channel handler
+    /// Add a channel handler delivering for all reasons
     pub fn add_handler<H: ChannelHandler + 'static>(&mut self, handler: H) {
-        self.handlers.push(Box::new(handler));
+        let config = ChannelConfig::new(handler.channel());
+        self.add_channel(config, handler);
     }
 
-    /// Register the default channels
-    pub fn with_defaults(mut self) -> Self {
-        self.add_handler(InAppChannel::new());
-        self
+    /// Add a channel handler with its configuration
+    pub fn add_channel<H: ChannelHandler + 'static>(&mut self, config: ChannelConfig, handler: H) {
+        self.handlers.push((config, Box::new(handler)));
     }
 
-    /// Deliver a notification to all channels
-    pub async fn deliver_all(&self, notification: &Notification) -> Vec<DeliveryResult> {
-        let mut results = Vec::new();
+    /// Replace the handler of the handler's channel, keeping its
+    /// configuration, or add it if the channel has no handler yet
+    pub fn replace_handler<H: ChannelHandler + 'static>(&mut self, handler: H) {
+        match self.handlers.iter_mut().find(|(_, h)| h.channel() == handler.channel()) {
+            Some((_, existing)) => *existing = Box::new(handler),
+            None => self.add_handler(handler),
+        }
+    }
 
-        for handler in &self.handlers {
-            if handler.is_available() {
-                match handler.deliver(notification).await {
-                    Ok(result) => results.push(result),
-                    Err(e) => results.push(DeliveryResult::failure(handler.channel(), e.to_string())),
+    /// Deliver a notification to every channel enabled for its reason and
+    /// accepted by the recipient
+    ///
+    /// Channels are delivered to concurrently and independently: an error
+    /// or panic in one channel is recorded as its failed result and does not
+    /// affect the others. Results are in registration order.
+    pub async fn deliver_all(
+        &self,
+        notification: &Notification,
+        recipient: &Recipient,
+    ) -> Vec<DeliveryResult> {
+        let deliveries = self
+            .handlers
+            .iter()
+            .filter(|(config, handler)| {
+                config.applies_to(notification.reason)
+                    && handler.is_available()
+                    && handler.accepts(recipient)
+            })
+            .map(|(_, handler)| async move {
+                let channel = handler.channel();
+                match AssertUnwindSafe(handler.deliver(notification, recipient))
+                    .catch_unwind()
+                    .await
+                {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(e)) => DeliveryResult::failure(channel, e.to_string()),
+                    Err(_) => DeliveryResult::failure(channel, "channel panicked during delivery"),
                 }
-            }
+            });
+
+        let results = join_all(deliveries).await;
+        for result in results.iter().filter(|r| !r.success) {
+            tracing::warn!(
+                channel = ?result.channel,
+                notification_id = ?notification.id,
+                error = result.error.as_deref().unwrap_or_default(),
+                "Notification delivery failed"
+            );
         }
-
         results
     }
 
@@ -272,10 +575,11 @@ impl ChannelDispatcher {
         &self,
         channel: Channel,
         notification: &Notification,
+        recipient: &Recipient,
     ) -> ChannelResult<DeliveryResult> {
-        for handler in &self.handlers {
+        for (_, handler) in &self.handlers {
             if handler.channel() == channel {
-                return handler.deliver(notification).await;
+                return handler.deliver(notification, recipient).await;
             }
         }
 
@@ -286,38 +590,216 @@ impl ChannelDispatcher {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::notification::{NotificationReason, NotificationType};
+    use crate::email::{EmailError, EmailMessage, EmailResult, MemoryEmailSender, ProjectEmailSettings};
+    use crate::jobs::MemoryJobQueue;
+    use crate::notification::NotificationType;
+    use crate::service::{MemoryNotificationStore, MemoryProjectEmailSettings};
+
+    fn notification(reason: NotificationReason) -> Notification {
+        let mut notification =
+            Notification::work_package(1, NotificationType::WorkPackageUpdated, reason, 100);
+        notification.id = Some(10);
+        notification
+    }
+
+    fn recipient(frequency: EmailFrequency) -> Recipient {
+        let mut settings = NotificationSettings::for_user(1);
+        settings.email_frequency = frequency;
+        Recipient::new(settings).with_email(EmailAddress::new("alice@example.com"))
+    }
+
+    fn renderer() -> EmailRenderer {
+        EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com"))
+    }
+
+    /// Sender failing every message with the given error
+    struct FailingSender(fn() -> EmailError);
+
+    #[async_trait]
+    impl EmailSender for FailingSender {
+        async fn send(&self, _message: &EmailMessage) -> EmailResult<String> {
+            Err((self.0)())
+        }
+
+        fn is_configured(&self) -> bool {
+            true
+        }
+    }
+
+    /// Channel that panics while delivering
+    struct PanickingChannel;
+
+    #[async_trait]
+    impl ChannelHandler for PanickingChannel {
+        fn channel(&self) -> Channel {
+            Channel::Slack
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        async fn deliver(
+            &self,
+            _notification: &Notification,
+            _recipient: &Recipient,
+        ) -> ChannelResult<DeliveryResult> {
+            panic!("slack is down");
+        }
+    }
 
     #[tokio::test]
     async fn test_in_app_channel() {
-        let channel = InAppChannel::new();
-        let notification = Notification::work_package(
-            1,
-            NotificationType::WorkPackageCreated,
-            NotificationReason::Watched,
-            100,
-        );
-
-        let result = channel.deliver(&notification).await.unwrap();
+        let store = Arc::new(MemoryNotificationStore::new());
+        let channel = InAppChannel::new(store.clone());
+
+        // Already stored notifications are confirmed, new ones are written
+        let result = channel
+            .deliver(&notification(NotificationReason::Watched), &recipient(EmailFrequency::Immediate))
+            .await
+            .unwrap();
         assert!(result.success);
+        assert_eq!(result.message_id.as_deref(), Some("10"));
+        assert_eq!(store.unread_count(1).await.unwrap(), 0);
+
+        let mut unsaved = notification(NotificationReason::Watched);
+        unsaved.id = None;
+        let result = channel
+            .deliver(&unsaved, &recipient(EmailFrequency::Immediate))
+            .await
+            .unwrap();
         assert_eq!(result.channel, Channel::InApp);
+        assert_eq!(store.unread_count(1).await.unwrap(), 1);
     }
 
     #[tokio::test]
-    async fn test_channel_dispatcher() {
+    async fn test_email_channel_honors_frequency() {
+        let sender = Arc::new(MemoryEmailSender::new());
+        let queue = Arc::new(MemoryJobQueue::new());
+        let channel = EmailChannel::new(sender.clone(), queue.clone(), renderer());
+        let notification = notification(NotificationReason::Assigned);
+
+        let result = channel.deliver(&notification, &recipient(EmailFrequency::Immediate)).await.unwrap();
+        assert!(result.success && result.message_id.is_some());
+        assert_eq!(sender.sent_messages().await.len(), 1);
+
+        // Digest recipients are left to the digest
+        let result = channel.deliver(&notification, &recipient(EmailFrequency::Daily)).await.unwrap();
+        assert!(result.success && result.message_id.is_none() && result.job_id.is_none());
+        assert_eq!(sender.sent_messages().await.len(), 1);
+
+        // So are reasons the recipient wants no immediate emails for
+        let mut digest_only = recipient(EmailFrequency::Immediate);
+        digest_only.settings.email_reasons.assignee = false;
+        let result = channel.deliver(&notification, &digest_only).await.unwrap();
+        assert!(result.success && result.message_id.is_none() && result.job_id.is_none());
+        assert_eq!(sender.sent_messages().await.len(), 1);
+
+        // Without an address the send is queued
+        let mut unknown = recipient(EmailFrequency::Immediate);
+        unknown.email = None;
+        let result = channel.deliver(&notification, &unknown).await.unwrap();
+        let job = queue.dequeue(MAILERS_QUEUE).await.unwrap().unwrap();
+        assert_eq!(result.job_id.as_deref(), Some(job.id.as_str()));
+        assert_eq!(job.job_type, SEND_EMAIL_JOB);
+
+        let mut never = recipient(EmailFrequency::Never);
+        assert!(!channel.accepts(&never));
+        never.settings.email_frequency = EmailFrequency::Weekly;
+        assert!(channel.accepts(&never));
+    }
+
+    #[tokio::test]
+    async fn test_email_channel_defers_sends_over_the_limit() {
+        let sender = Arc::new(MemoryEmailSender::new());
+        let queue = Arc::new(MemoryJobQueue::new());
+        let throttle = Arc::new(EmailThrottle::new(op_core::config::EmailSendLimits {
+            per_recipient_per_hour: Some(1),
+            ..Default::default()
+        }));
+        let channel = EmailChannel::new(sender.clone(), queue.clone(), renderer()).with_throttle(throttle);
+        let notification = notification(NotificationReason::Assigned);
+
+        let result = channel.deliver(&notification, &recipient(EmailFrequency::Immediate)).await.unwrap();
+        assert!(result.message_id.is_some());
+
+        // The second email to the recipient waits for the window to slide
+        let result = channel.deliver(&notification, &recipient(EmailFrequency::Immediate)).await.unwrap();
+        assert!(result.success && result.message_id.is_none());
+        assert_eq!(sender.sent_messages().await.len(), 1);
+        assert!(queue.dequeue(MAILERS_QUEUE).await.unwrap().is_none());
+        let job = queue.get(result.job_id.as_deref().unwrap()).await.unwrap().unwrap();
+        assert_eq!(job.job_type, SEND_EMAIL_JOB);
+        assert!(job.run_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_isolates_failures() {
+        let queue = Arc::new(MemoryJobQueue::new());
         let mut dispatcher = ChannelDispatcher::new();
-        dispatcher.add_handler(InAppChannel::new());
+        dispatcher.add_handler(InAppChannel::new(Arc::new(MemoryNotificationStore::new())));
+        dispatcher.add_handler(EmailChannel::new(
+            Arc::new(FailingSender(|| EmailError::SmtpError("connection reset".into()))),
+            queue.clone(),
+            renderer(),
+        ));
+        dispatcher.add_handler(PanickingChannel);
+        dispatcher.add_handler(WebhookChannel::new("https://example.com/hook"));
+
+        let results = dispatcher
+            .deliver_all(&notification(NotificationReason::Watched), &recipient(EmailFrequency::Immediate))
+            .await;
+
+        let channels: Vec<Channel> = results.iter().map(|r| r.channel).collect();
+        assert_eq!(channels, vec![Channel::InApp, Channel::Email, Channel::Slack, Channel::Webhook]);
+        let succeeded: Vec<bool> = results.iter().map(|r| r.success).collect();
+        assert_eq!(succeeded, vec![true, false, false, true]);
+
+        // The transient email failure is retried through the job queue
+        let email = &results[1];
+        assert_eq!(email.error.as_deref(), Some("SMTP error: connection reset"));
+        let retry = queue.dequeue(MAILERS_QUEUE).await.unwrap();
+        assert!(retry.is_none(), "retry must not be ready before its delay");
+        let job_id = email.job_id.as_deref().unwrap();
+        assert!(queue.get(job_id).await.unwrap().unwrap().run_at.is_some());
+
+        assert_eq!(results[2].error.as_deref(), Some("channel panicked during delivery"));
+    }
 
-        let notification = Notification::work_package(
-            1,
-            NotificationType::WorkPackageUpdated,
-            NotificationReason::Assigned,
-            100,
+    #[tokio::test]
+    async fn test_permanent_email_failure_is_not_retried() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let channel = EmailChannel::new(
+            Arc::new(FailingSender(|| EmailError::InvalidRecipient("alice".into()))),
+            queue.clone(),
+            renderer(),
         );
+        let mut dispatcher = ChannelDispatcher::new();
+        dispatcher.add_handler(channel);
 
-        let results = dispatcher.deliver_all(&notification).await;
-        assert_eq!(results.len(), 1);
-        assert!(results[0].success);
+        let results = dispatcher
+            .deliver_all(&notification(NotificationReason::Watched), &recipient(EmailFrequency::Immediate))
+            .await;
+        assert!(!results[0].success);
+        assert!(results[0].job_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_channels_follow_reason_config() {
+        let mut dispatcher = ChannelDispatcher::new();
+        dispatcher.add_handler(InAppChannel::new(Arc::new(MemoryNotificationStore::new())));
+        dispatcher.add_channel(
+            ChannelConfig::webhook("https://example.com/hook").for_reasons(vec![NotificationReason::Mentioned]),
+            WebhookChannel::new("https://example.com/hook"),
+        );
+        let recipient = recipient(EmailFrequency::Immediate);
+
+        let watched = dispatcher.deliver_all(&notification(NotificationReason::Watched), &recipient).await;
+        assert_eq!(watched.len(), 1);
+
+        let mentioned = dispatcher.deliver_all(&notification(NotificationReason::Mentioned), &recipient).await;
+        assert_eq!(mentioned.len(), 2);
+        assert_eq!(mentioned[1].channel, Channel::Webhook);
     }
 
     #[test]
@@ -325,5 +807,82 @@ mod tests {
         let config = ChannelConfig::webhook("https://example.com/webhook");
         assert_eq!(config.channel, Channel::Webhook);
         assert!(config.enabled);
+        assert!(config.applies_to(NotificationReason::Watched));
+    }
+
+    /// Value of the message's header
+    fn header<'a>(message: &'a EmailMessage, name: &str) -> Option<&'a str> {
+        message.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn test_email_channel_threads_the_emails_of_a_work_package() {
+        let sender = Arc::new(MemoryEmailSender::new());
+        let queue = Arc::new(MemoryJobQueue::new());
+        let channel = EmailChannel::new(sender.clone(), queue, renderer());
+
+        let events = [
+            (NotificationType::WorkPackageCreated, 501),
+            (NotificationType::WorkPackageCommented, 502),
+            (NotificationType::WorkPackageUpdated, 503),
+        ];
+        for (notification_type, journal_id) in events {
+            let notification = Notification::work_package(1, notification_type, NotificationReason::Assigned, 100)
+                .with_project(7)
+                .with_journal(journal_id);
+            channel.deliver(&notification, &recipient(EmailFrequency::Immediate)).await.unwrap();
+        }
+
+        let messages = sender.sent_messages().await;
+        let thread = "<openproject.work_package-100@localhost>";
+        assert_eq!(header(&messages[0], "Message-ID"), Some(thread));
+        assert_eq!(header(&messages[0], "In-Reply-To"), None);
+        assert_eq!(
+            header(&messages[1], "Message-ID"),
+            Some("<openproject.work_package-100.journal-502@localhost>")
+        );
+        assert_eq!(
+            header(&messages[2], "Message-ID"),
+            Some("<openproject.work_package-100.journal-503@localhost>")
+        );
+        for message in &messages[1..] {
+            assert_eq!(header(message, "In-Reply-To"), Some(thread));
+            assert_eq!(header(message, "References"), Some(thread));
+        }
+        for message in &messages {
+            assert_eq!(header(message, "X-OpenProject-Type"), Some("WorkPackage"));
+            assert_eq!(header(message, "X-OpenProject-Project"), Some("7"));
+            assert_eq!(header(message, "X-OpenProject-Id"), Some("100"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_email_channel_sends_from_the_project_sender() {
+        let sender = Arc::new(MemoryEmailSender::new());
+        let queue = Arc::new(MemoryJobQueue::new());
+        let settings = Arc::new(MemoryProjectEmailSettings::new());
+        settings
+            .set(
+                7,
+                ProjectEmailSettings {
+                    from_name: Some("Apollo".into()),
+                    from_address: Some("apollo@example.com".into()),
+                    reply_to: Some("apollo-support@example.com".into()),
+                },
+            )
+            .await;
+        let channel = EmailChannel::new(sender.clone(), queue, renderer()).with_project_settings(settings);
+
+        for project_id in [7, 8] {
+            let notification = notification(NotificationReason::Assigned).with_project(project_id);
+            channel.deliver(&notification, &recipient(EmailFrequency::Immediate)).await.unwrap();
+        }
+
+        let messages = sender.sent_messages().await;
+        assert_eq!(messages[0].from.to_rfc5322(), "Apollo <apollo@example.com>");
+        assert_eq!(messages[0].reply_to.as_ref().unwrap().email, "apollo-support@example.com");
+        // Projects without settings send from the instance address
+        assert_eq!(messages[1].from.to_rfc5322(), "noreply@example.com");
+        assert!(messages[1].reply_to.is_none());
     }
 }
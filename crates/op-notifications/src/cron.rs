@@ -0,0 +1,312 @@
+//! Cron expressions for recurring jobs
+//!
+//! Supports the five standard fields (minute, hour, day of month, month,
+//! day of week) with an optional leading seconds field, `*`, lists, ranges,
+//! steps and three-letter month and weekday names. As in Vixie cron, an
+//! expression restricting both the day of month and the day of week matches
+//! days matching either.
+//!
+//! Next runs are computed in the wall-clock time of a timezone: a time
+//! skipped by a DST change runs at the first instant after the gap, and a
+//! time repeated by one runs once, at its first occurrence.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use thiserror::Error;
+
+/// Years searched for the next run before giving up, e.g. for `0 0 30 2 *`
+const SEARCH_YEARS: i64 = 5;
+
+/// Longest DST gap searched for its end
+const MAX_GAP_MINUTES: i64 = 3 * 60;
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Cron expression errors
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CronError {
+    #[error("Expected 5 or 6 fields, got {0}")]
+    FieldCount(usize),
+    #[error("Invalid {field} field '{value}'")]
+    InvalidField { field: &'static str, value: String },
+}
+
+/// Values allowed by one field, as a bit set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Whether the field was given as `*` or a step of it
+    any: bool,
+}
+
+impl Field {
+    fn parse(
+        name: &'static str,
+        spec: &str,
+        min: u32,
+        max: u32,
+        names: &[&str],
+    ) -> Result<Self, CronError> {
+        let invalid = || CronError::InvalidField { field: name, value: spec.to_string() };
+        let value = |s: &str| -> Result<u32, CronError> {
+            let lower = s.to_ascii_lowercase();
+            let parsed = match names.iter().position(|n| *n == lower) {
+                Some(index) => index as u32 + min,
+                None => s.parse().map_err(|_| invalid())?,
+            };
+            if parsed < min || parsed > max {
+                return Err(invalid());
+            }
+            Ok(parsed)
+        };
+
+        let mut bits = 0u64;
+        for part in spec.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => {
+                    let step: u32 = step.parse().map_err(|_| invalid())?;
+                    if step == 0 {
+                        return Err(invalid());
+                    }
+                    (range, Some(step))
+                }
+                None => (part, None),
+            };
+
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    None if step.is_some() => (value(range)?, max),
+                    None => {
+                        let single = value(range)?;
+                        (single, single)
+                    }
+                },
+            };
+            if start > end {
+                return Err(invalid());
+            }
+
+            for v in (start..=end).step_by(step.unwrap_or(1) as usize) {
+                bits |= 1 << v;
+            }
+        }
+
+        Ok(Self { bits, any: spec.starts_with('*') })
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+
+    fn values(&self, max: u32) -> impl Iterator<Item = u32> + '_ {
+        (0..=max).filter(move |v| self.contains(*v))
+    }
+}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    seconds: Field,
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+}
+
+impl CronSchedule {
+    /// Parse `[second] minute hour day-of-month month day-of-week`; without
+    /// the seconds field runs start at second zero
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let (seconds, rest) = match fields.len() {
+            5 => ("0", &fields[..]),
+            6 => (fields[0], &fields[1..]),
+            n => return Err(CronError::FieldCount(n)),
+        };
+
+        let mut days_of_week = Field::parse("day of week", rest[4], 0, 7, &WEEKDAYS)?;
+        // Sunday is both 0 and 7
+        if days_of_week.contains(7) {
+            days_of_week.bits = (days_of_week.bits & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            expression: fields.join(" "),
+            seconds: Field::parse("second", seconds, 0, 59, &[])?,
+            minutes: Field::parse("minute", rest[0], 0, 59, &[])?,
+            hours: Field::parse("hour", rest[1], 0, 23, &[])?,
+            days_of_month: Field::parse("day of month", rest[2], 1, 31, &[])?,
+            months: Field::parse("month", rest[3], 1, 12, &MONTHS)?,
+            days_of_week,
+        })
+    }
+
+    /// The expression as given, with normalized whitespace
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// First run strictly after `after`, in the wall-clock time of `tz`
+    pub fn next_after(&self, after: DateTime<Utc>, tz: Tz) -> Option<DateTime<Utc>> {
+        let local = after.with_timezone(&tz).naive_local();
+        let first_day = local.date();
+        let last_day = first_day + Duration::days(366 * SEARCH_YEARS);
+
+        for day in first_day.iter_days().take_while(|day| *day <= last_day) {
+            if !self.matches_day(day) {
+                continue;
+            }
+
+            for time in self.times() {
+                let candidate = day.and_time(time);
+                if candidate < local {
+                    continue;
+                }
+                match resolve(candidate, tz) {
+                    Some(run) if run > after => return Some(run),
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, day: NaiveDate) -> bool {
+        if !self.months.contains(day.month()) {
+            return false;
+        }
+
+        let day_of_month = self.days_of_month.contains(day.day());
+        let day_of_week = self.days_of_week.contains(day.weekday().num_days_from_sunday());
+        match (self.days_of_month.any, self.days_of_week.any) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+
+    /// Times of a matching day, in order
+    fn times(&self) -> impl Iterator<Item = NaiveTime> + '_ {
+        self.hours.values(23).flat_map(move |h| {
+            self.minutes.values(59).flat_map(move |m| {
+                self.seconds
+                    .values(59)
+                    .filter_map(move |s| NaiveTime::from_hms_opt(h, m, s))
+            })
+        })
+    }
+}
+
+/// Instant of a wall-clock time: the first of a repeated time and the end
+/// of the gap for a skipped one
+fn resolve(local: NaiveDateTime, tz: Tz) -> Option<DateTime<Utc>> {
+    if let Some(time) = tz.from_local_datetime(&local).earliest() {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    let minute = local.with_second(0)?;
+    (1..=MAX_GAP_MINUTES).find_map(|i| {
+        tz.from_local_datetime(&(minute + Duration::minutes(i)))
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+    })
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::{America::New_York, Europe::Berlin, UTC};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn next(expression: &str, after: &str, tz: Tz) -> DateTime<Utc> {
+        CronSchedule::parse(expression).unwrap().next_after(utc(after), tz).unwrap()
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        assert!(CronSchedule::parse("*/15 * * * *").is_ok());
+        assert!(CronSchedule::parse("30 0 9 * * mon-fri").is_ok());
+        assert!(CronSchedule::parse("0 0 1,15 jan,jul *").is_ok());
+        assert_eq!(CronSchedule::parse("* * * *"), Err(CronError::FieldCount(4)));
+        assert!(matches!(
+            CronSchedule::parse("60 * * * *"),
+            Err(CronError::InvalidField { field: "minute", .. })
+        ));
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("0 5-1 * * *").is_err());
+        assert!(CronSchedule::parse("0 0 * foo *").is_err());
+    }
+
+    #[test]
+    fn test_next_after_in_utc() {
+        assert_eq!(next("*/15 * * * *", "2024-05-01T10:07:30Z", UTC), utc("2024-05-01T10:15:00Z"));
+        assert_eq!(next("*/15 * * * *", "2024-05-01T10:15:00Z", UTC), utc("2024-05-01T10:30:00Z"));
+        assert_eq!(next("*/20 * * * * *", "2024-05-01T10:15:00Z", UTC), utc("2024-05-01T10:15:20Z"));
+        assert_eq!(next("0 9 * * *", "2024-12-31T09:00:00Z", UTC), utc("2025-01-01T09:00:00Z"));
+        assert_eq!(next("0 0 29 2 *", "2024-03-01T00:00:00Z", UTC), utc("2028-02-29T00:00:00Z"));
+        assert!(CronSchedule::parse("0 0 30 2 *").unwrap().next_after(utc("2024-01-01T00:00:00Z"), UTC).is_none());
+    }
+
+    #[test]
+    fn test_day_of_week() {
+        // 2024-05-04 is a Saturday
+        assert_eq!(next("0 8 * * mon-fri", "2024-05-04T00:00:00Z", UTC), utc("2024-05-06T08:00:00Z"));
+        assert_eq!(next("0 8 * * 7", "2024-05-04T00:00:00Z", UTC), utc("2024-05-05T08:00:00Z"));
+        // Day of month or day of week when both are restricted
+        assert_eq!(next("0 8 13 * fri", "2024-05-04T00:00:00Z", UTC), utc("2024-05-10T08:00:00Z"));
+        assert_eq!(next("0 8 6 * fri", "2024-05-04T00:00:00Z", UTC), utc("2024-05-06T08:00:00Z"));
+    }
+
+    #[test]
+    fn test_daily_run_keeps_local_time_across_dst() {
+        // Berlin switches to CEST on 2024-03-31 and back on 2024-10-27
+        assert_eq!(next("0 9 * * *", "2024-03-30T08:00:00Z", Berlin), utc("2024-03-31T07:00:00Z"));
+        assert_eq!(next("0 9 * * *", "2024-10-26T07:00:00Z", Berlin), utc("2024-10-27T08:00:00Z"));
+        assert_eq!(next("0 9 * * *", "2024-03-09T14:00:00Z", New_York), utc("2024-03-10T13:00:00Z"));
+    }
+
+    #[test]
+    fn test_time_skipped_by_dst_runs_after_the_gap() {
+        // 02:30 does not exist on 2024-03-31 in Berlin; clocks jump from 02:00 to 03:00
+        assert_eq!(next("30 2 * * *", "2024-03-31T00:00:00Z", Berlin), utc("2024-03-31T01:00:00Z"));
+        assert_eq!(next("30 2 * * *", "2024-03-31T01:00:00Z", Berlin), utc("2024-04-01T00:30:00Z"));
+
+        // Every run in the gap collapses into one at its end
+        assert_eq!(next("*/15 * * * *", "2024-03-31T00:50:00Z", Berlin), utc("2024-03-31T01:00:00Z"));
+        assert_eq!(next("*/15 * * * *", "2024-03-31T01:00:00Z", Berlin), utc("2024-03-31T01:15:00Z"));
+    }
+
+    #[test]
+    fn test_time_repeated_by_dst_runs_once() {
+        // 02:30 happens twice on 2024-10-27 in Berlin, first in CEST then in CET
+        assert_eq!(next("30 2 * * *", "2024-10-26T23:00:00Z", Berlin), utc("2024-10-27T00:30:00Z"));
+        assert_eq!(next("30 2 * * *", "2024-10-27T00:30:00Z", Berlin), utc("2024-10-28T01:30:00Z"));
+
+        // Hourly runs skip the repeated hour instead of running twice at 02:00
+        assert_eq!(next("0 * * * *", "2024-10-27T00:00:00Z", Berlin), utc("2024-10-27T02:00:00Z"));
+    }
+}
@@ -2,12 +2,21 @@
 //!
 //! Mirrors: app/mailers/*.rb
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use op_core::config::join_url_root;
+use op_core::email::normalize_email;
+use op_core::i18n::{escape_html, Args, I18n};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::notification::{Notification, NotificationType};
+use crate::bulk::BulkSummary;
+use crate::notification::{AttributeChange, Notification, NotificationSnapshot, NotificationType};
+
+/// Job type sending the digests of the recipients whose digest time has come
+pub const DIGEST_JOB: &str = "Notifications::ScheduleDigestMailsJob";
 
 /// Email errors
 #[derive(Debug, Error)]
@@ -24,6 +33,16 @@ pub enum EmailError {
     RateLimited,
 }
 
+impl EmailError {
+    /// Whether sending again later may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            EmailError::SendFailed(_) | EmailError::SmtpError(_) | EmailError::RateLimited
+        )
+    }
+}
+
 pub type EmailResult<T> = Result<T, EmailError>;
 
 /// Email message
@@ -83,14 +102,25 @@ impl EmailAddress {
 }
 
 impl EmailMessage {
-    /// Create a new email message
+    /// Create a new email message to the normalized recipient addresses
+    ///
+    /// Fails with [`EmailError::InvalidRecipient`] naming the first address
+    /// that is not valid, rather than leaving it to the mail server.
     pub fn new(
         from: EmailAddress,
         to: Vec<EmailAddress>,
         subject: impl Into<String>,
         text_body: impl Into<String>,
-    ) -> Self {
-        Self {
+    ) -> EmailResult<Self> {
+        let to = to
+            .into_iter()
+            .map(|address| match normalize_email(&address.email) {
+                Ok(email) => Ok(EmailAddress { email, ..address }),
+                Err(_) => Err(EmailError::InvalidRecipient(address.email)),
+            })
+            .collect::<EmailResult<Vec<_>>>()?;
+
+        Ok(Self {
             id: uuid::Uuid::new_v4().to_string(),
             from,
             to,
@@ -102,7 +132,7 @@ impl EmailMessage {
             html_body: None,
             headers: Vec::new(),
             created_at: Utc::now(),
-        }
+        })
     }
 
     /// Add HTML body
@@ -148,6 +178,11 @@ pub trait EmailSender: Send + Sync {
 
     /// Check if the sender is configured
     fn is_configured(&self) -> bool;
+
+    /// Short sender identifier used as a metrics label
+    fn sender_type(&self) -> &'static str {
+        "custom"
+    }
 }
 
 /// Console email sender (for development)
@@ -189,12 +224,74 @@ impl EmailSender for ConsoleEmailSender {
     fn is_configured(&self) -> bool {
         true
     }
+
+    fn sender_type(&self) -> &'static str {
+        "console"
+    }
+}
+
+/// A work package shared with a user, as told in the share email
+#[derive(Debug, Clone)]
+pub struct SharedWorkPackage {
+    pub work_package_id: i64,
+    pub project_id: Option<i64>,
+    pub subject: String,
+    /// Name of the user who shared the work package
+    pub actor_name: String,
+    /// Whether the recipient was invited and has yet to activate their account
+    pub invited: bool,
+}
+
+/// A work package listed in a query results email
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedWorkPackage {
+    pub id: i64,
+    pub subject: String,
+}
+
+/// How the results of a subscribed query changed since the last check
+#[derive(Debug, Clone, Default)]
+pub struct QueryResultChanges {
+    pub query_id: i64,
+    pub query_name: String,
+    pub project_id: Option<i64>,
+    /// Work packages that entered the results
+    pub added: Vec<ListedWorkPackage>,
+    /// Work packages that left the results
+    pub removed: Vec<ListedWorkPackage>,
+    /// Work packages in the results that were updated
+    pub changed: Vec<ListedWorkPackage>,
+}
+
+/// Sender overrides of a project's notification emails, kept under
+/// `email` in the project settings. Unset fields use the instance defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectEmailSettings {
+    /// Display name of the sender
+    pub from_name: Option<String>,
+    /// Sender address
+    pub from_address: Option<String>,
+    /// Address replies go to
+    pub reply_to: Option<String>,
+}
+
+impl ProjectEmailSettings {
+    /// The sender of the project's emails, given the instance sender
+    fn from(&self, default: &EmailAddress) -> EmailAddress {
+        EmailAddress {
+            email: self.from_address.clone().unwrap_or_else(|| default.email.clone()),
+            name: self.from_name.clone().or_else(|| default.name.clone()),
+        }
+    }
 }
 
 /// Email renderer for notifications
+#[derive(Clone)]
 pub struct EmailRenderer {
     base_url: String,
     from_address: EmailAddress,
+    app_title: String,
+    i18n: Arc<I18n>,
 }
 
 impl EmailRenderer {
@@ -202,19 +299,68 @@ impl EmailRenderer {
         Self {
             base_url: base_url.into(),
             from_address,
+            app_title: "OpenProject".to_string(),
+            i18n: I18n::shared(),
         }
     }
 
-    /// Render a notification as an email
+    /// Serve links under the relative URL root of the instance, e.g.
+    /// `/openproject`
+    pub fn with_url_root(mut self, root: &str) -> Self {
+        self.base_url = join_url_root(&self.base_url, root);
+        self
+    }
+
+    /// Use the given translation catalogs
+    pub fn with_i18n(mut self, i18n: Arc<I18n>) -> Self {
+        self.i18n = i18n;
+        self
+    }
+
+    /// Application title used in subjects and bodies
+    pub fn with_app_title(mut self, app_title: impl Into<String>) -> Self {
+        self.app_title = app_title.into();
+        self
+    }
+
+    /// Render a notification as an email in the instance default locale
     pub fn render_notification(
         &self,
         notification: &Notification,
         recipient_email: &str,
         recipient_name: Option<&str>,
-    ) -> EmailMessage {
-        let subject = self.render_subject(notification);
-        let text_body = self.render_text_body(notification);
-        let html_body = self.render_html_body(notification);
+    ) -> EmailResult<EmailMessage> {
+        self.render_localized(notification, recipient_email, recipient_name, None)
+    }
+
+    /// Render a notification as an email in the recipient's language
+    pub fn render_localized(
+        &self,
+        notification: &Notification,
+        recipient_email: &str,
+        recipient_name: Option<&str>,
+        recipient_language: Option<&str>,
+    ) -> EmailResult<EmailMessage> {
+        self.render_for_project(notification, recipient_email, recipient_name, recipient_language, None)
+    }
+
+    /// Render a notification as an email in the recipient's language, sent
+    /// from the project's sender if it overrides the instance one
+    ///
+    /// Emails about a work package form one thread: the email about its
+    /// creation starts it and the others reply to it.
+    pub fn render_for_project(
+        &self,
+        notification: &Notification,
+        recipient_email: &str,
+        recipient_name: Option<&str>,
+        recipient_language: Option<&str>,
+        project: Option<&ProjectEmailSettings>,
+    ) -> EmailResult<EmailMessage> {
+        let locale = self.i18n.resolve_locale(recipient_language);
+        let subject = self.render_subject(notification, &locale);
+        let text_body = self.render_text_body(notification, &locale);
+        let html_body = self.render_html_body(notification, &locale);
 
         let to = EmailAddress::new(recipient_email);
         let to = match recipient_name {
@@ -222,85 +368,451 @@ impl EmailRenderer {
             None => to,
         };
 
-        EmailMessage::new(
-            self.from_address.clone(),
-            vec![to],
-            subject,
-            text_body,
+        let from = match project {
+            Some(project) => project.from(&self.from_address),
+            None => self.from_address.clone(),
+        };
+        let mut message = EmailMessage::new(from, vec![to], subject, text_body)?
+            .with_html(html_body)
+            .with_openproject_headers(notification.project_id, notification.resource_id);
+        if let Some(reply_to) = project.and_then(|p| p.reply_to.as_ref()) {
+            message = message.reply_to(EmailAddress::new(reply_to));
+        }
+        if notification.notification_type.is_work_package() {
+            message = self.with_thread_headers(message, notification);
+        }
+        Ok(message)
+    }
+
+    /// Message-ID of the email starting the thread of a work package
+    pub fn thread_message_id(&self, work_package_id: i64) -> String {
+        format!("<openproject.work_package-{}@{}>", work_package_id, self.host())
+    }
+
+    /// Message-ID of the email about a journal of a work package
+    pub fn journal_message_id(&self, work_package_id: i64, journal_id: i64) -> String {
+        format!(
+            "<openproject.work_package-{}.journal-{}@{}>",
+            work_package_id,
+            journal_id,
+            self.host()
         )
-        .with_html(html_body)
-        .with_openproject_headers(notification.project_id, notification.resource_id)
     }
 
-    fn render_subject(&self, notification: &Notification) -> String {
-        match notification.notification_type {
-            NotificationType::WorkPackageCreated => {
-                format!("[OpenProject] Work Package #{} created", notification.resource_id)
-            }
-            NotificationType::WorkPackageUpdated => {
-                format!("[OpenProject] Work Package #{} updated", notification.resource_id)
-            }
-            NotificationType::WorkPackageCommented => {
-                format!("[OpenProject] New comment on Work Package #{}", notification.resource_id)
-            }
-            NotificationType::WorkPackageAssigned => {
-                format!("[OpenProject] Work Package #{} assigned to you", notification.resource_id)
-            }
-            NotificationType::WorkPackageMentioned => {
-                format!("[OpenProject] You were mentioned in Work Package #{}", notification.resource_id)
-            }
-            NotificationType::WorkPackageDueDateAlert => {
-                format!("[OpenProject] Work Package #{} is due soon", notification.resource_id)
-            }
-            NotificationType::WorkPackageOverdue => {
-                format!("[OpenProject] Work Package #{} is overdue", notification.resource_id)
+    /// Host name of the instance, identifying its Message-IDs
+    fn host(&self) -> &str {
+        let authority = self.base_url.split_once("://").map_or(self.base_url.as_str(), |(_, rest)| rest);
+        let authority = authority.split('/').next().unwrap_or_default();
+        let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+        match host.split_once(':') {
+            Some((host, _)) => host,
+            None => host,
+        }
+    }
+
+    /// Place the email in the thread of its work package, deterministically
+    /// so that every recipient's copy and every resend carries the same IDs
+    fn with_thread_headers(&self, message: EmailMessage, notification: &Notification) -> EmailMessage {
+        let thread = self.thread_message_id(notification.resource_id);
+        if notification.notification_type == NotificationType::WorkPackageCreated {
+            return message.header("Message-ID", thread);
+        }
+
+        let message_id = match notification.journal_id {
+            Some(journal_id) => self.journal_message_id(notification.resource_id, journal_id),
+            None => format!(
+                "<openproject.work_package-{}.{}@{}>",
+                notification.resource_id,
+                message.id,
+                self.host()
+            ),
+        };
+        message
+            .header("Message-ID", message_id)
+            .header("In-Reply-To", thread.clone())
+            .header("References", thread)
+    }
+
+    /// Render the email telling a user a work package was shared with them.
+    /// Invited users are asked to activate their account first.
+    pub fn render_share(
+        &self,
+        shared: &SharedWorkPackage,
+        recipient_email: &str,
+        recipient_name: Option<&str>,
+        recipient_language: Option<&str>,
+    ) -> EmailResult<EmailMessage> {
+        let i18n = &self.i18n;
+        let locale = self.i18n.resolve_locale(recipient_language);
+        let app = &self.app_title;
+        let actor = &shared.actor_name;
+
+        let subject = i18n.t(
+            &locale,
+            "email.share.subject",
+            &[("app", app), ("actor", actor), ("id", &shared.work_package_id)],
+        );
+        let intro = i18n.t(
+            &locale,
+            "email.share.body",
+            &[("actor", actor), ("subject", &shared.subject)],
+        );
+        let call_to_action = if shared.invited {
+            let url = format!("{}/account/activate", self.base_url);
+            i18n.t(&locale, "email.share.invitation", &[("app", app), ("url", &url)])
+        } else {
+            let url = format!("{}/work_packages/{}", self.base_url, shared.work_package_id);
+            i18n.t(&locale, "email.share.view", &[("url", &url)])
+        };
+
+        let text_body = format!("{}\n\n{}\n", intro, call_to_action);
+        let html_body = format!(
+            "<!DOCTYPE html>\n<html lang=\"{}\">\n<body>\n    <p>{}</p>\n    <p>{}</p>\n</body>\n</html>",
+            escape_html(&locale),
+            escape_html(&intro),
+            escape_html(&call_to_action),
+        );
+
+        let to = EmailAddress::new(recipient_email);
+        let to = match recipient_name {
+            Some(name) => to.with_name(name),
+            None => to,
+        };
+
+        Ok(EmailMessage::new(self.from_address.clone(), vec![to], subject, text_body)?
+            .with_html(html_body)
+            .with_openproject_headers(shared.project_id, shared.work_package_id))
+    }
+
+    /// Render the email telling a subscriber how the results of a query
+    /// changed. Empty sections are left out.
+    pub fn render_query_changes(
+        &self,
+        changes: &QueryResultChanges,
+        recipient_email: &str,
+        recipient_name: Option<&str>,
+        recipient_language: Option<&str>,
+    ) -> EmailResult<EmailMessage> {
+        let i18n = &self.i18n;
+        let locale = self.i18n.resolve_locale(recipient_language);
+        let query = &changes.query_name;
+
+        let subject = i18n.t(
+            &locale,
+            "email.query_subscription.subject",
+            &[("app", &self.app_title), ("query", query)],
+        );
+        let intro = i18n.t(&locale, "email.query_subscription.intro", &[("query", query)]);
+        let url = match changes.project_id {
+            Some(project_id) => format!(
+                "{}/projects/{}/work_packages?query_id={}",
+                self.base_url, project_id, changes.query_id
+            ),
+            None => format!("{}/work_packages?query_id={}", self.base_url, changes.query_id),
+        };
+        let view = i18n.t(&locale, "email.query_subscription.view", &[("url", &url)]);
+
+        let sections: Vec<(String, &[ListedWorkPackage])> = [
+            ("email.query_subscription.added", &changes.added),
+            ("email.query_subscription.removed", &changes.removed),
+            ("email.query_subscription.changed", &changes.changed),
+        ]
+        .into_iter()
+        .filter(|(_, work_packages)| !work_packages.is_empty())
+        .map(|(key, work_packages)| (i18n.t(&locale, key, &[]), work_packages.as_slice()))
+        .collect();
+
+        let mut text_body = format!("{}\n", intro);
+        let mut html_sections = String::new();
+        for (label, work_packages) in &sections {
+            text_body.push_str(&format!("\n{} ({}):\n", label, work_packages.len()));
+            html_sections.push_str(&format!(
+                "    <h3>{} ({})</h3>\n    <ul>\n",
+                escape_html(label),
+                work_packages.len()
+            ));
+            for work_package in work_packages.iter() {
+                text_body.push_str(&format!("- #{} {}\n", work_package.id, work_package.subject));
+                html_sections.push_str(&format!(
+                    "        <li><a href=\"{}/work_packages/{}\">#{}</a> {}</li>\n",
+                    escape_html(&self.base_url),
+                    work_package.id,
+                    work_package.id,
+                    escape_html(&work_package.subject),
+                ));
             }
-            NotificationType::MembershipAdded => {
-                "[OpenProject] You have been added to a project".to_string()
+            html_sections.push_str("    </ul>\n");
+        }
+        text_body.push_str(&format!("\n{}\n", view));
+
+        let html_body = format!(
+            "<!DOCTYPE html>\n<html lang=\"{}\">\n<body>\n    <p>{}</p>\n{}    <p>{}</p>\n</body>\n</html>",
+            escape_html(&locale),
+            escape_html(&intro),
+            html_sections,
+            escape_html(&view),
+        );
+
+        let to = EmailAddress::new(recipient_email);
+        let to = match recipient_name {
+            Some(name) => to.with_name(name),
+            None => to,
+        };
+
+        let message = EmailMessage::new(self.from_address.clone(), vec![to], subject, text_body)?
+            .with_html(html_body)
+            .header("X-OpenProject-Type", "Query")
+            .header("X-OpenProject-Id", changes.query_id.to_string());
+        Ok(match changes.project_id {
+            Some(project_id) => message.header("X-OpenProject-Project", project_id.to_string()),
+            None => message,
+        })
+    }
+
+    /// Render the email summarizing a bulk operation, with a link to its
+    /// work packages
+    pub fn render_bulk_summary(
+        &self,
+        summary: &BulkSummary,
+        actor_name: &str,
+        project_name: &str,
+        recipient_email: &str,
+        recipient_name: Option<&str>,
+        recipient_language: Option<&str>,
+    ) -> EmailResult<EmailMessage> {
+        let i18n = &self.i18n;
+        let locale = self.i18n.resolve_locale(recipient_language);
+
+        let text = i18n.t_plural(
+            &locale,
+            &format!("email.bulk.{}", summary.operation.as_str()),
+            summary.count() as u64,
+            &[("project", &project_name), ("actor", &actor_name)],
+        );
+        let subject = i18n.t(&locale, "email.bulk.subject", &[("app", &self.app_title), ("summary", &text)]);
+
+        let ids: Vec<String> = summary.work_package_ids.iter().map(|id| format!("\"{}\"", id)).collect();
+        let filters = format!("[{{\"id\":{{\"operator\":\"=\",\"values\":[{}]}}}}]", ids.join(","));
+        let url = format!(
+            "{}/projects/{}/work_packages?filters={}",
+            self.base_url,
+            summary.project_id,
+            encode_query_value(&filters)
+        );
+        let view = i18n.t(&locale, "email.bulk.view", &[("url", &url)]);
+
+        let text_body = format!("{}\n\n{}\n", text, view);
+        let html_body = format!(
+            "<!DOCTYPE html>\n<html lang=\"{}\">\n<body>\n    <p>{}</p>\n    <p>{}</p>\n</body>\n</html>",
+            escape_html(&locale),
+            escape_html(&text),
+            escape_html(&view),
+        );
+
+        let to = EmailAddress::new(recipient_email);
+        let to = match recipient_name {
+            Some(name) => to.with_name(name),
+            None => to,
+        };
+
+        Ok(EmailMessage::new(self.from_address.clone(), vec![to], subject, text_body)?
+            .with_html(html_body)
+            .header("X-OpenProject-Type", "BulkSummary")
+            .header("X-OpenProject-Project", summary.project_id.to_string()))
+    }
+
+    fn render_subject(&self, notification: &Notification, locale: &str) -> String {
+        let app = &self.app_title;
+        let id = notification.resource_id;
+
+        if let Some(snapshot) = self.work_package_snapshot(notification) {
+            let type_name = self.snapshot_type(snapshot, locale);
+            return match self.snapshot_summary(snapshot, locale) {
+                Some(summary) => self.i18n.t(
+                    locale,
+                    "email.snapshot.subject_with_summary",
+                    &[("app", app), ("type", &type_name), ("id", &id), ("subject", &snapshot.subject), ("summary", &summary)],
+                ),
+                None => self.i18n.t(
+                    locale,
+                    "email.snapshot.subject",
+                    &[("app", app), ("type", &type_name), ("id", &id), ("subject", &snapshot.subject)],
+                ),
+            };
+        }
+
+        match notification.notification_type {
+            NotificationType::WorkPackageCreated
+            | NotificationType::WorkPackageUpdated
+            | NotificationType::WorkPackageCommented
+            | NotificationType::WorkPackageAssigned
+            | NotificationType::WorkPackageMentioned
+            | NotificationType::WorkPackageDueDateAlert
+            | NotificationType::WorkPackageOverdue
+            | NotificationType::WorkPackageTrashed
+            | NotificationType::WorkPackagePurged
+            | NotificationType::MembershipAdded => {
+                let key = notification
+                    .notification_type
+                    .i18n_key()
+                    .replacen("notification.", "email.subject.", 1);
+                self.i18n.t(locale, &key, &[("app", app), ("id", &id)])
             }
-            _ => {
-                format!("[OpenProject] {} notification", notification.resource_type)
+            _ => self.i18n.t(
+                locale,
+                "email.subject.default",
+                &[("app", app), ("resource", &notification.resource_type)],
+            ),
+        }
+    }
+
+    /// Snapshot of a work package notification, if captured
+    fn work_package_snapshot<'n>(&self, notification: &'n Notification) -> Option<&'n NotificationSnapshot> {
+        notification
+            .snapshot
+            .as_ref()
+            .filter(|_| notification.notification_type.is_work_package())
+    }
+
+    fn snapshot_type(&self, snapshot: &NotificationSnapshot, locale: &str) -> String {
+        snapshot
+            .type_name
+            .clone()
+            .unwrap_or_else(|| self.i18n.t(locale, "email.snapshot.work_package", &[]))
+    }
+
+    fn snapshot_title(&self, notification: &Notification, snapshot: &NotificationSnapshot, locale: &str) -> String {
+        self.i18n.t(
+            locale,
+            "email.snapshot.title",
+            &[
+                ("type", &self.snapshot_type(snapshot, locale)),
+                ("id", &notification.resource_id),
+                ("subject", &snapshot.subject),
+            ],
+        )
+    }
+
+    /// What the journal did, for the subject: its first change, or the
+    /// comment when nothing else changed
+    fn snapshot_summary(&self, snapshot: &NotificationSnapshot, locale: &str) -> Option<String> {
+        match snapshot.changes.first() {
+            Some(change) => Some(self.describe_change(change, locale)),
+            None => snapshot
+                .comment_excerpt
+                .as_ref()
+                .map(|_| self.i18n.t(locale, "email.snapshot.commented", &[])),
+        }
+    }
+
+    fn describe_change(&self, change: &AttributeChange, locale: &str) -> String {
+        let attribute = self.i18n.attribute(locale, &change.attribute);
+        match (&change.old, &change.new) {
+            (Some(old), Some(new)) => self.i18n.t(
+                locale,
+                "email.snapshot.changed",
+                &[("attribute", &attribute), ("old", old), ("new", new)],
+            ),
+            (None, Some(new)) => self.i18n.t(locale, "email.snapshot.set", &[("attribute", &attribute), ("new", new)]),
+            (Some(old), None) => {
+                self.i18n.t(locale, "email.snapshot.deleted", &[("attribute", &attribute), ("old", old)])
             }
+            (None, None) => attribute,
         }
     }
 
-    fn render_text_body(&self, notification: &Notification) -> String {
+    fn type_label(&self, notification_type: NotificationType, locale: &str) -> String {
+        self.i18n.t(locale, notification_type.i18n_key(), &[])
+    }
+
+    fn details_url(&self, notification: &Notification) -> String {
+        format!("{}/work_packages/{}", self.base_url, notification.resource_id)
+    }
+
+    fn render_text_body(&self, notification: &Notification, locale: &str) -> String {
+        let i18n = &self.i18n;
+        let app = &self.app_title;
         let mut body = String::new();
 
-        body.push_str(&format!(
-            "You have a new notification in OpenProject.\n\n"
-        ));
+        body.push_str(&i18n.t(locale, "email.body.intro", &[("app", app)]));
+        body.push_str("\n\n");
 
-        body.push_str(&format!(
-            "Type: {:?}\n",
-            notification.notification_type
-        ));
+        let type_label = self.type_label(notification.notification_type, locale);
+        body.push_str(&i18n.t(locale, "email.body.type", &[("type", &type_label)]));
+        body.push('\n');
 
-        body.push_str(&format!(
-            "Resource: {} #{}\n",
-            notification.resource_type,
-            notification.resource_id
-        ));
+        match self.work_package_snapshot(notification) {
+            Some(snapshot) => {
+                body.push_str(&self.snapshot_title(notification, snapshot, locale));
+                body.push('\n');
+                if let Some(ref project) = snapshot.project_name {
+                    body.push_str(&i18n.t(locale, "email.snapshot.project", &[("project", project)]));
+                    body.push('\n');
+                }
+            }
+            None => {
+                body.push_str(&i18n.t(
+                    locale,
+                    "email.body.resource",
+                    &[("resource", &notification.resource_type), ("id", &notification.resource_id)],
+                ));
+                body.push('\n');
+            }
+        }
 
         if let Some(actor_id) = notification.actor_id {
-            body.push_str(&format!("By: User #{}\n", actor_id));
+            body.push_str(&i18n.t(locale, "email.body.actor", &[("actor", &actor_id)]));
+            body.push('\n');
         }
 
-        body.push_str(&format!(
-            "\nView details: {}/work_packages/{}\n",
-            self.base_url,
-            notification.resource_id
-        ));
+        if let Some(snapshot) = self.work_package_snapshot(notification) {
+            if !snapshot.changes.is_empty() {
+                body.push('\n');
+                body.push_str(&i18n.t(locale, "email.snapshot.changes", &[]));
+                body.push('\n');
+                for change in &snapshot.changes {
+                    body.push_str(&format!("- {}\n", self.describe_change(change, locale)));
+                }
+            }
+            if let Some(ref comment) = snapshot.comment_excerpt {
+                body.push('\n');
+                body.push_str(&i18n.t(locale, "email.snapshot.comment", &[]));
+                body.push('\n');
+                body.push_str(&format!("> {}\n", comment));
+            }
+        }
+
+        body.push('\n');
+        let url = self.details_url(notification);
+        body.push_str(&i18n.t(locale, "email.body.view_details", &[("url", &url)]));
+        body.push('\n');
 
-        body.push_str("\n---\nYou received this email because you are subscribed to notifications.\n");
+        body.push_str("\n---\n");
+        body.push_str(&i18n.t(locale, "email.body.footer", &[]));
+        body.push('\n');
 
         body
     }
 
-    fn render_html_body(&self, notification: &Notification) -> String {
+    fn render_html_body(&self, notification: &Notification, locale: &str) -> String {
+        let i18n = &self.i18n;
+        let app = &self.app_title;
+
+        let heading = escape_html(&i18n.t(locale, "email.body.heading", &[("app", app)]));
+        let button = escape_html(&i18n.t(locale, "email.body.view_button", &[("app", app)]));
+        let footer = escape_html(&i18n.t(locale, "email.body.footer", &[]));
+        let content = match self.work_package_snapshot(notification) {
+            Some(snapshot) => self.render_html_snapshot(notification, snapshot, locale),
+            None => format!(
+                "<p>{} #{}</p>",
+                escape_html(&notification.resource_type),
+                notification.resource_id
+            ),
+        };
+
         format!(
             r#"<!DOCTYPE html>
-<html>
+<html lang="{}">
 <head>
     <meta charset="utf-8">
     <style>
@@ -315,30 +827,58 @@ impl EmailRenderer {
 <body>
     <div class="container">
         <div class="header">
-            <h1>OpenProject Notification</h1>
+            <h1>{}</h1>
         </div>
         <div class="content">
-            <p>{} #{}</p>
-            <p><a class="button" href="{}/work_packages/{}">View in OpenProject</a></p>
+            {}
+            <p><a class="button" href="{}">{}</a></p>
         </div>
         <div class="footer">
-            <p>You received this email because you are subscribed to notifications.</p>
+            <p>{}</p>
         </div>
     </div>
 </body>
 </html>"#,
-            notification.resource_type,
-            notification.resource_id,
-            self.base_url,
-            notification.resource_id
+            escape_html(locale),
+            heading,
+            content,
+            escape_html(&self.details_url(notification)),
+            button,
+            footer
         )
     }
+
+    /// Title, project, changes and comment of a snapshot as HTML
+    fn render_html_snapshot(&self, notification: &Notification, snapshot: &NotificationSnapshot, locale: &str) -> String {
+        let mut html = format!(
+            "<p><strong>{}</strong></p>",
+            escape_html(&self.snapshot_title(notification, snapshot, locale))
+        );
+        if let Some(ref project) = snapshot.project_name {
+            html.push_str(&format!(
+                "<p>{}</p>",
+                escape_html(&self.i18n.t(locale, "email.snapshot.project", &[("project", project)]))
+            ));
+        }
+        if !snapshot.changes.is_empty() {
+            html.push_str("<ul>");
+            for change in &snapshot.changes {
+                html.push_str(&format!("<li>{}</li>", escape_html(&self.describe_change(change, locale))));
+            }
+            html.push_str("</ul>");
+        }
+        if let Some(ref comment) = snapshot.comment_excerpt {
+            html.push_str(&format!("<blockquote>{}</blockquote>", escape_html(comment)));
+        }
+        html
+    }
 }
 
 /// Email digest builder
 pub struct DigestBuilder {
     notifications: Vec<Notification>,
     renderer: EmailRenderer,
+    recipient_language: Option<String>,
 }
 
 impl DigestBuilder {
@@ -346,9 +886,16 @@ impl DigestBuilder {
         Self {
             notifications: Vec::new(),
             renderer,
+            recipient_language: None,
         }
     }
 
+    /// Render the digest in the recipient's language
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.recipient_language = Some(language.into());
+        self
+    }
+
     pub fn add(&mut self, notification: Notification) {
         self.notifications.push(notification);
     }
@@ -358,25 +905,35 @@ impl DigestBuilder {
         recipient_email: &str,
         recipient_name: Option<&str>,
         period: &str,
-    ) -> Option<EmailMessage> {
+    ) -> EmailResult<Option<EmailMessage>> {
         if self.notifications.is_empty() {
-            return None;
+            return Ok(None);
         }
 
-        let subject = format!("[OpenProject] Your {} digest ({} notifications)", period, self.notifications.len());
+        let i18n = &self.renderer.i18n;
+        let locale = i18n.resolve_locale(self.recipient_language.as_deref());
+        let app = &self.renderer.app_title;
+        let count = self.notifications.len() as u64;
 
-        let mut text_body = format!(
-            "Here's your {} OpenProject digest with {} notifications:\n\n",
-            period,
-            self.notifications.len()
-        );
+        let period = i18n
+            .lookup(&locale, &format!("email.digest.period.{}", period))
+            .unwrap_or(period);
+        let args: Args<'_> = &[("app", app), ("period", &period)];
+
+        let subject = i18n.t_plural(&locale, "email.digest.subject", count, args);
+
+        let mut text_body = i18n.t_plural(&locale, "email.digest.intro", count, args);
+        text_body.push_str("\n\n");
 
         for notification in &self.notifications {
+            let resource = match self.renderer.work_package_snapshot(notification) {
+                Some(snapshot) => self.renderer.snapshot_title(notification, snapshot, &locale),
+                None => format!("{} #{}", notification.resource_type, notification.resource_id),
+            };
             text_body.push_str(&format!(
-                "- {:?}: {} #{}\n",
-                notification.notification_type,
-                notification.resource_type,
-                notification.resource_id
+                "- {}: {}\n",
+                self.renderer.type_label(notification.notification_type, &locale),
+                resource
             ));
         }
 
@@ -386,12 +943,12 @@ impl DigestBuilder {
             None => to,
         };
 
-        Some(EmailMessage::new(
+        Ok(Some(EmailMessage::new(
             self.renderer.from_address.clone(),
             vec![to],
             subject,
             text_body,
-        ))
+        )?))
     }
 }
 
@@ -591,6 +1148,10 @@ impl EmailSender for MsGraphEmailSender {
             && !self.config.client_secret.is_empty()
             && !self.config.sender.is_empty()
     }
+
+    fn sender_type(&self) -> &'static str {
+        "ms_graph"
+    }
 }
 
 /// Memory-based email sender for testing
@@ -632,6 +1193,21 @@ impl EmailSender for MemoryEmailSender {
     fn is_configured(&self) -> bool {
         true
     }
+
+    fn sender_type(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Percent-encode a query parameter value
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -654,6 +1230,7 @@ mod tests {
         let to = vec![EmailAddress::new("user@example.com")];
 
         let message = EmailMessage::new(from, to, "Test Subject", "Test body")
+            .unwrap()
             .with_html("<p>Test body</p>")
             .header("X-Custom", "value");
 
@@ -662,6 +1239,21 @@ mod tests {
         assert_eq!(message.headers.len(), 1);
     }
 
+    #[test]
+    fn test_email_message_recipients() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let to = vec![EmailAddress::new(" User@Bücher.DE ").with_name("User")];
+        let message = EmailMessage::new(from.clone(), to, "Test", "Test body").unwrap();
+        assert_eq!(message.to[0].email, "User@xn--bcher-kva.de");
+        assert_eq!(message.to[0].name.as_deref(), Some("User"));
+
+        let to = vec![EmailAddress::new("user@example.com"), EmailAddress::new("user@@example")];
+        match EmailMessage::new(from, to, "Test", "Test body") {
+            Err(EmailError::InvalidRecipient(address)) => assert_eq!(address, "user@@example"),
+            other => panic!("expected an invalid recipient, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_email_renderer() {
         let from = EmailAddress::new("noreply@openproject.com").with_name("OpenProject");
@@ -674,20 +1266,216 @@ mod tests {
             100,
         );
 
-        let email = renderer.render_notification(&notification, "user@example.com", Some("Test User"));
+        let email = renderer.render_notification(&notification, "user@example.com", Some("Test User")).unwrap();
 
         assert!(email.subject.contains("100"));
         assert!(email.subject.contains("updated"));
         assert!(email.text_body.contains("https://openproject.example.com"));
     }
 
+    #[test]
+    fn test_email_links_carry_the_url_root() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let renderer = EmailRenderer::new("https://openproject.example.com/", from).with_url_root("/op/");
+
+        let notification = Notification::work_package(
+            1,
+            NotificationType::WorkPackageUpdated,
+            NotificationReason::Assigned,
+            100,
+        );
+
+        let email = renderer.render_notification(&notification, "user@example.com", None).unwrap();
+        assert!(email.text_body.contains("https://openproject.example.com/op/work_packages/100"));
+        assert!(!email.text_body.contains(".com//"));
+    }
+
+    #[test]
+    fn test_email_renderer_localized() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let renderer = EmailRenderer::new("https://openproject.example.com", from);
+
+        let notification = Notification::work_package(
+            1,
+            NotificationType::WorkPackageAssigned,
+            NotificationReason::Assigned,
+            7,
+        );
+
+        let de = renderer.render_localized(&notification, "user@example.com", None, Some("de")).unwrap();
+        assert_eq!(de.subject, "[OpenProject] Arbeitspaket #7 wurde Ihnen zugewiesen");
+        assert!(de.text_body.contains("Art: Arbeitspaket zugewiesen"));
+        assert!(de.html_body.unwrap().contains("In OpenProject anzeigen"));
+
+        let fr = renderer.render_localized(&notification, "user@example.com", None, Some("fr")).unwrap();
+        assert_eq!(fr.subject, "[OpenProject] Le lot de travaux n°7 vous a été assigné");
+
+        // Unknown languages fall back to the instance default
+        let other = renderer.render_localized(&notification, "user@example.com", None, Some("ja")).unwrap();
+        assert_eq!(other.subject, "[OpenProject] Work Package #7 assigned to you");
+    }
+
+    #[test]
+    fn test_html_body_escapes_values() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let renderer = EmailRenderer::new("https://op.example.com", from).with_app_title("<OP>");
+
+        let mut notification = Notification::work_package(
+            1,
+            NotificationType::WorkPackageCreated,
+            NotificationReason::Watched,
+            1,
+        );
+        notification.resource_type = "<script>".to_string();
+
+        let html = renderer.render_notification(&notification, "user@example.com", None).unwrap().html_body.unwrap();
+        assert!(html.contains("&lt;OP&gt; Notification"));
+        assert!(html.contains("&lt;script&gt; #1"));
+        assert!(!html.contains("<script>"));
+    }
+
+    fn snapshot_notification() -> Notification {
+        let snapshot = NotificationSnapshot::new("Crash on <save>")
+            .with_type("Bug")
+            .with_project("Demo")
+            .with_change("status", Some("New".into()), Some("In Progress".into()))
+            .with_change("assigned_to", None, Some("Ada".into()))
+            .with_comment("Reproduced on **main**");
+        Notification::work_package(1, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 123)
+            .with_actor(2)
+            .with_snapshot(9, snapshot)
+    }
+
+    #[test]
+    fn test_snapshot_renders_specific_email() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let renderer = EmailRenderer::new("https://op.example.com", from);
+        let notification = snapshot_notification();
+
+        let email = renderer.render_notification(&notification, "user@example.com", None).unwrap();
+        assert_eq!(
+            email.subject,
+            "[OpenProject] Bug #123: Crash on <save> — Status changed from New to In Progress"
+        );
+        assert!(email.text_body.contains("Bug #123: Crash on <save>\nProject: Demo\n"));
+        assert!(email.text_body.contains("- Status changed from New to In Progress\n- Assignee set to Ada\n"));
+        assert!(email.text_body.contains("> Reproduced on **main**"));
+        assert!(!email.text_body.contains("Resource:"));
+
+        let html = email.html_body.unwrap();
+        assert!(html.contains("<strong>Bug #123: Crash on &lt;save&gt;</strong>"));
+        assert!(html.contains("<li>Status changed from New to In Progress</li>"));
+
+        let de = renderer.render_localized(&notification, "user@example.com", None, Some("de")).unwrap();
+        assert_eq!(
+            de.subject,
+            "[OpenProject] Bug #123: Crash on <save> — Status geändert von New zu In Progress"
+        );
+
+        // A comment alone is summarized as such
+        let mut commented = notification.clone();
+        if let Some(ref mut snapshot) = commented.snapshot {
+            snapshot.changes.clear();
+            snapshot.type_name = None;
+        }
+        let email = renderer.render_notification(&commented, "user@example.com", None).unwrap();
+        assert_eq!(email.subject, "[OpenProject] Work Package #123: Crash on <save> — comment added");
+
+        let mut digest = DigestBuilder::new(renderer);
+        digest.add(notification);
+        assert!(digest
+            .build("user@example.com", None, "daily")
+            .unwrap()
+            .unwrap()
+            .text_body
+            .contains("- Work package updated: Bug #123: Crash on <save>\n"));
+    }
+
+    #[test]
+    fn test_share_email() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let renderer = EmailRenderer::new("https://op.example.com", from);
+        let mut shared = SharedWorkPackage {
+            work_package_id: 42,
+            project_id: Some(3),
+            subject: "Launch <plan>".to_string(),
+            actor_name: "Ada Admin".to_string(),
+            invited: false,
+        };
+
+        let email = renderer.render_share(&shared, "guest@example.com", Some("Guest"), None).unwrap();
+        assert_eq!(email.subject, "[OpenProject] Ada Admin shared Work Package #42 with you");
+        assert!(email.text_body.contains("Ada Admin shared the work package \"Launch <plan>\" with you."));
+        assert!(email.text_body.contains("https://op.example.com/work_packages/42"));
+        assert!(email.html_body.unwrap().contains("Launch &lt;plan&gt;"));
+        assert!(email.headers.contains(&("X-OpenProject-Id".to_string(), "42".to_string())));
+
+        shared.invited = true;
+        let invitation = renderer.render_share(&shared, "guest@example.com", None, Some("de")).unwrap();
+        assert_eq!(invitation.subject, "[OpenProject] Ada Admin hat Arbeitspaket #42 mit Ihnen geteilt");
+        assert!(invitation.text_body.contains("https://op.example.com/account/activate"));
+        assert!(!invitation.text_body.contains("/work_packages/42"));
+    }
+
+    #[test]
+    fn test_query_changes_email() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let renderer = EmailRenderer::new("https://op.example.com", from);
+        let changes = QueryResultChanges {
+            query_id: 9,
+            query_name: "Open <bugs>".to_string(),
+            project_id: Some(3),
+            added: vec![ListedWorkPackage { id: 12, subject: "Crash on save".to_string() }],
+            removed: vec![
+                ListedWorkPackage { id: 4, subject: "Typo".to_string() },
+                ListedWorkPackage { id: 5, subject: String::new() },
+            ],
+            changed: Vec::new(),
+        };
+
+        let email = renderer.render_query_changes(&changes, "user@example.com", None, None).unwrap();
+        assert_eq!(email.subject, "[OpenProject] Results of \"Open <bugs>\" changed");
+        assert!(email.text_body.contains("Added (1):\n- #12 Crash on save\n"));
+        assert!(email.text_body.contains("Removed (2):\n- #4 Typo\n- #5 \n"));
+        assert!(!email.text_body.contains("Changed"));
+        assert!(email.text_body.contains("https://op.example.com/projects/3/work_packages?query_id=9"));
+        let html = email.html_body.unwrap();
+        assert!(html.contains("Open &lt;bugs&gt;"));
+        assert!(html.contains("<a href=\"https://op.example.com/work_packages/12\">#12</a> Crash on save"));
+        assert!(email.headers.contains(&("X-OpenProject-Type".to_string(), "Query".to_string())));
+
+        let localized = renderer.render_query_changes(&changes, "user@example.com", None, Some("de")).unwrap();
+        assert_eq!(localized.subject, "[OpenProject] Ergebnisse von \"Open <bugs>\" geändert");
+        assert!(localized.text_body.contains("Entfallen (2):"));
+    }
+
+    #[test]
+    fn test_digest_pluralization() {
+        let from = EmailAddress::new("noreply@openproject.com");
+        let wp = |id| Notification::work_package(1, NotificationType::WorkPackageUpdated, NotificationReason::Watched, id);
+
+        let mut single = DigestBuilder::new(EmailRenderer::new("https://op.example.com", from.clone()));
+        single.add(wp(1));
+        let email = single.build("user@example.com", None, "daily").unwrap().unwrap();
+        assert_eq!(email.subject, "[OpenProject] Your daily digest (1 notification)");
+
+        let mut several =
+            DigestBuilder::new(EmailRenderer::new("https://op.example.com", from)).with_language("de");
+        for id in 1..=3 {
+            several.add(wp(id));
+        }
+        let email = several.build("user@example.com", None, "weekly").unwrap().unwrap();
+        assert_eq!(email.subject, "[OpenProject] Ihre wöchentliche Zusammenfassung (3 Benachrichtigungen)");
+        assert!(email.text_body.contains("- Arbeitspaket aktualisiert: WorkPackage #2"));
+    }
+
     #[tokio::test]
     async fn test_console_sender() {
         let sender = ConsoleEmailSender::new();
         let from = EmailAddress::new("test@example.com");
         let to = vec![EmailAddress::new("user@example.com")];
 
-        let message = EmailMessage::new(from, to, "Test", "Test body");
+        let message = EmailMessage::new(from, to, "Test", "Test body").unwrap();
         let result = sender.send(&message).await;
 
         assert!(result.is_ok());
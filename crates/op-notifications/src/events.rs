@@ -0,0 +1,123 @@
+//! Queued delivery of domain events
+//!
+//! Subscribers with [`Delivery::Queued`](op_core::events::Delivery) get their
+//! events through the job queue: publishing enqueues a job per subscriber
+//! and event, and the workers hand it to the subscriber of the bus it names.
+//! Failed deliveries are retried like other jobs.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use op_core::events::{DomainEvent, EventBus, EventError, EventQueue, EventResult};
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::{Job, JobError, JobHandler, JobQueue, JobResult};
+
+/// Job type delivering a domain event to a queued subscriber
+pub const DELIVER_DOMAIN_EVENT_JOB: &str = "DomainEvents::DeliveryJob";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DeliveryArgs {
+    subscriber: String,
+    event: DomainEvent,
+}
+
+/// Enqueues domain events as jobs
+pub struct JobEventQueue {
+    queue: Arc<dyn JobQueue>,
+}
+
+impl JobEventQueue {
+    pub fn new(queue: Arc<dyn JobQueue>) -> Self {
+        Self { queue }
+    }
+}
+
+#[async_trait]
+impl EventQueue for JobEventQueue {
+    async fn enqueue(&self, subscriber: &str, event: &DomainEvent) -> EventResult<()> {
+        let args = DeliveryArgs {
+            subscriber: subscriber.to_string(),
+            event: event.clone(),
+        };
+        let args = serde_json::to_value(args).map_err(|e| EventError::Queue(e.to_string()))?;
+        self.queue
+            .enqueue(Job::new(DELIVER_DOMAIN_EVENT_JOB, args))
+            .await
+            .map(|_| ())
+            .map_err(|e| EventError::Queue(e.to_string()))
+    }
+}
+
+/// Handler of [`DELIVER_DOMAIN_EVENT_JOB`], delivering to the subscribers
+/// of the bus
+pub struct DeliverDomainEventJob {
+    bus: EventBus,
+}
+
+impl DeliverDomainEventJob {
+    pub fn new(bus: EventBus) -> Self {
+        Self { bus }
+    }
+}
+
+#[async_trait]
+impl JobHandler for DeliverDomainEventJob {
+    async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+        let args: DeliveryArgs =
+            serde_json::from_value(args).map_err(|e| JobError::SerializationError(e.to_string()))?;
+        self.bus
+            .deliver(&args.subscriber, &args.event)
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobWorker;
+    use crate::MemoryJobQueue;
+    use op_core::events::{Delivery, EventRecorder, EventSubscriber};
+
+    /// Recorder handled by the workers
+    struct QueuedRecorder(Arc<EventRecorder>);
+
+    #[async_trait]
+    impl EventSubscriber for QueuedRecorder {
+        fn name(&self) -> &str {
+            "queued"
+        }
+
+        fn delivery(&self) -> Delivery {
+            Delivery::Queued
+        }
+
+        async fn handle(&self, event: &DomainEvent) -> EventResult<()> {
+            self.0.handle(event).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queued_subscribers_get_events_from_the_workers() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let recorder = Arc::new(EventRecorder::new());
+        let bus = EventBus::new()
+            .with_subscriber(Arc::new(QueuedRecorder(recorder.clone())))
+            .with_queue(Arc::new(JobEventQueue::new(queue.clone())));
+
+        let event = DomainEvent::CommentAdded {
+            work_package_id: 1,
+            journal_id: 2,
+            author_id: 3,
+        };
+        assert!(bus.publish(event.clone()).await.is_empty());
+        assert!(recorder.events().is_empty());
+        assert_eq!(queue.pending_count("default").await.unwrap(), 1);
+
+        let mut worker = JobWorker::new(queue.clone(), "default");
+        worker.register(DELIVER_DOMAIN_EVENT_JOB, DeliverDomainEventJob::new(bus));
+        assert!(worker.process_one().await.unwrap());
+        assert_eq!(recorder.events(), vec![event]);
+    }
+}
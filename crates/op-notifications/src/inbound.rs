@@ -0,0 +1,1058 @@
+//! Inbound Email
+//!
+//! Mirrors: app/models/mail_handler.rb
+//!
+//! Replies to notification emails become comments on the work package the
+//! notification was about. Raw RFC 822 messages arrive either through the
+//! inbound HTTP endpoint (e.g. SendGrid inbound parse) or from a polled
+//! mailbox via a [`POLL_INBOUND_MAIL_JOB`].
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::Engine;
+use op_core::traits::Id;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::jobs::{JobError, JobHandler, JobResult};
+
+/// Header of notification emails naming the work package
+pub const WORK_PACKAGE_ID_HEADER: &str = "X-OpenProject-Id";
+
+/// Header of notification emails naming the resource type
+pub const RESOURCE_TYPE_HEADER: &str = "X-OpenProject-Type";
+
+/// Job type polling the inbound mailbox
+pub const POLL_INBOUND_MAIL_JOB: &str = "PollInboundMailJob";
+
+/// Nesting limit for multipart messages
+const MAX_MIME_DEPTH: usize = 8;
+
+/// Inbound email errors
+#[derive(Debug, Error)]
+pub enum InboundError {
+    #[error("Malformed message: {0}")]
+    Malformed(String),
+    #[error("Message has no sender")]
+    MissingSender,
+    #[error("Message does not reference a work package")]
+    NoWorkPackage,
+    #[error("Unknown sender: {0}")]
+    UnknownSender(String),
+    #[error("Reply has no content")]
+    EmptyReply,
+    #[error("Comment rejected: {0}")]
+    Rejected(String),
+    #[error("Comment could not be created: {0}")]
+    CommentFailed(String),
+    #[error("Mailbox error: {0}")]
+    Mailbox(String),
+}
+
+impl InboundError {
+    /// Whether processing the message again may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::CommentFailed(_) | Self::Mailbox(_))
+    }
+}
+
+pub type InboundResult<T> = Result<T, InboundError>;
+
+/// What happens to replies from addresses that belong to no user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownSenderPolicy {
+    /// Drop the reply
+    #[default]
+    Reject,
+    /// Comment as the anonymous user
+    Anonymous,
+}
+
+/// Inbound email settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InboundConfig {
+    /// Lines starting the quoted original message; the reply ends there
+    pub reply_markers: Vec<String>,
+    /// Lines starting the sender's signature; the reply ends there
+    pub signature_markers: Vec<String>,
+    pub unknown_sender_policy: UnknownSenderPolicy,
+    /// Local part prefix of plus-addressed recipients, `wp` in `wp+123@example.com`
+    pub plus_address_prefix: String,
+    /// Attachments larger than this many bytes are left out of the comment
+    pub max_attachment_size: usize,
+}
+
+impl Default for InboundConfig {
+    fn default() -> Self {
+        Self {
+            reply_markers: vec![
+                "-- Reply above this line --".to_string(),
+                "-----Original Message-----".to_string(),
+            ],
+            signature_markers: vec!["-- ".to_string()],
+            unknown_sender_policy: UnknownSenderPolicy::default(),
+            plus_address_prefix: "wp".to_string(),
+            max_attachment_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl InboundConfig {
+    /// Reduce a reply to the text the sender wrote
+    ///
+    /// Cuts at the first reply or signature marker and at "On ... wrote:"
+    /// attribution lines, and drops `>` quoted lines.
+    pub fn strip_reply(&self, text: &str) -> String {
+        let mut lines = Vec::new();
+
+        for line in text.lines() {
+            let trimmed = line.trim();
+            let is_marker = self
+                .reply_markers
+                .iter()
+                .any(|m| !m.trim().is_empty() && trimmed.starts_with(m.trim()));
+            let is_signature = self
+                .signature_markers
+                .iter()
+                .any(|m| !m.trim().is_empty() && trimmed == m.trim());
+            let is_attribution = trimmed.starts_with("On ") && trimmed.ends_with("wrote:");
+
+            if is_marker || is_signature || is_attribution {
+                break;
+            }
+            if trimmed.starts_with('>') {
+                continue;
+            }
+            lines.push(line.trim_end());
+        }
+
+        lines.join("\n").trim().to_string()
+    }
+}
+
+/// A file attached to an inbound email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboundAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// An attachment left out of the comment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedAttachment {
+    pub filename: String,
+    pub reason: String,
+}
+
+/// A parsed inbound email
+///
+/// Parsing degrades gracefully: unreadable header lines, unterminated
+/// multiparts and undecodable parts are skipped instead of failing the
+/// whole message.
+#[derive(Debug, Clone, Default)]
+pub struct InboundEmail {
+    headers: Vec<(String, String)>,
+    /// Plain text body, or the text of the HTML body if there is none
+    pub text: String,
+    pub attachments: Vec<InboundAttachment>,
+    pub skipped_attachments: Vec<SkippedAttachment>,
+}
+
+impl InboundEmail {
+    /// Parse a raw RFC 822 message
+    pub fn parse(raw: &[u8], max_attachment_size: usize) -> InboundResult<Self> {
+        let raw = String::from_utf8_lossy(raw).replace("\r\n", "\n");
+        let (header_block, body) = split_entity(&raw);
+        let headers = parse_headers(header_block);
+
+        if headers.is_empty() {
+            return Err(InboundError::Malformed("no headers".to_string()));
+        }
+
+        let mut email = Self {
+            headers,
+            ..Default::default()
+        };
+        let mut html = None;
+        let headers = email.headers.clone();
+        email.parse_entity(&headers, body, 0, max_attachment_size, &mut html);
+
+        if email.text.trim().is_empty() {
+            if let Some(html) = html {
+                email.text = html_to_text(&html);
+            }
+        }
+
+        Ok(email)
+    }
+
+    /// First value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header_value(&self.headers, name)
+    }
+
+    /// Address of the sender
+    pub fn sender(&self) -> Option<String> {
+        self.header("From")
+            .and_then(|from| split_addresses(from).into_iter().next())
+    }
+
+    /// Addresses the message was delivered to
+    pub fn recipients(&self) -> Vec<String> {
+        ["Delivered-To", "To", "Cc"]
+            .iter()
+            .flat_map(|name| {
+                self.headers
+                    .iter()
+                    .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+                    .flat_map(|(_, v)| split_addresses(v))
+            })
+            .collect()
+    }
+
+    /// The work package replied to, from the OpenProject header or a
+    /// plus-addressed recipient
+    pub fn work_package_id(&self, plus_address_prefix: &str) -> Option<Id> {
+        let is_work_package = self
+            .header(RESOURCE_TYPE_HEADER)
+            .is_none_or(|t| t.trim().eq_ignore_ascii_case("WorkPackage"));
+        let from_header = self
+            .header(WORK_PACKAGE_ID_HEADER)
+            .filter(|_| is_work_package)
+            .and_then(|v| v.trim().trim_matches(|c| c == '<' || c == '>').parse().ok());
+
+        from_header.or_else(|| {
+            self.recipients().iter().find_map(|address| {
+                let local = address.split('@').next()?;
+                let (prefix, tag) = local.split_once('+')?;
+                if !prefix.eq_ignore_ascii_case(plus_address_prefix) {
+                    return None;
+                }
+                tag.parse().ok()
+            })
+        })
+    }
+
+    fn parse_entity(
+        &mut self,
+        headers: &[(String, String)],
+        body: &str,
+        depth: usize,
+        max_attachment_size: usize,
+        html: &mut Option<String>,
+    ) {
+        let (mime_type, params) = header_value(headers, "Content-Type")
+            .map(parse_parameterized)
+            .unwrap_or_else(|| ("text/plain".to_string(), Vec::new()));
+
+        if mime_type.starts_with("multipart/") && depth < MAX_MIME_DEPTH {
+            if let Some(boundary) = param(&params, "boundary") {
+                let parts = split_multipart(body, boundary);
+                if !parts.is_empty() {
+                    for part in parts {
+                        let (part_headers, part_body) = split_entity(part);
+                        let part_headers = parse_headers(part_headers);
+                        self.parse_entity(&part_headers, part_body, depth + 1, max_attachment_size, html);
+                    }
+                    return;
+                }
+            }
+            // Without a usable boundary the body is all we have
+            self.add_text(body);
+            return;
+        }
+
+        let (disposition, disposition_params) = header_value(headers, "Content-Disposition")
+            .map(parse_parameterized)
+            .unwrap_or_default();
+        let filename = param(&disposition_params, "filename").or_else(|| param(&params, "name"));
+        let encoding = header_value(headers, "Content-Transfer-Encoding")
+            .map(|e| e.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        let charset = param(&params, "charset").unwrap_or("utf-8");
+
+        let is_attachment = disposition == "attachment" || filename.is_some();
+        let decoded = decode_body(body, &encoding);
+
+        if is_attachment {
+            let filename = filename.unwrap_or("attachment").to_string();
+            match decoded {
+                None => self.skip(filename, "could not be decoded"),
+                Some(data) if data.len() > max_attachment_size => self.skip(
+                    filename,
+                    &format!("exceeds the maximum size of {} bytes", max_attachment_size),
+                ),
+                Some(data) => self.attachments.push(InboundAttachment {
+                    filename,
+                    content_type: mime_type,
+                    data,
+                }),
+            }
+            return;
+        }
+
+        let text = decoded
+            .map(|data| decode_charset(&data, charset))
+            .unwrap_or_else(|| body.to_string());
+
+        match mime_type.as_str() {
+            "text/html" => {
+                html.get_or_insert(text);
+            }
+            t if t.starts_with("text/") => self.add_text(&text),
+            _ => {}
+        }
+    }
+
+    fn add_text(&mut self, text: &str) {
+        if self.text.trim().is_empty() {
+            self.text = text.to_string();
+        }
+    }
+
+    fn skip(&mut self, filename: String, reason: &str) {
+        warn!(filename = %filename, reason, "Skipping inbound email attachment");
+        self.skipped_attachments.push(SkippedAttachment {
+            filename,
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// Split an entity into its header block and body
+fn split_entity(raw: &str) -> (&str, &str) {
+    if let Some(body) = raw.strip_prefix('\n') {
+        return ("", body);
+    }
+    match raw.find("\n\n") {
+        Some(pos) => (&raw[..pos], &raw[pos + 2..]),
+        None => (raw, ""),
+    }
+}
+
+/// Parse header lines, unfolding continuations and skipping unreadable lines
+fn parse_headers(block: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    for line in block.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+            if !name.is_empty() && !name.contains(' ') {
+                headers.push((name.to_string(), value.trim().to_string()));
+            }
+        }
+    }
+
+    headers
+}
+
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Parse `type/subtype; key=value; ...` into the lowercased value and its parameters
+fn parse_parameterized(value: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = value.split(';');
+    let main = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let params = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().trim_matches('"').to_string()))
+        .collect();
+
+    (main, params)
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+        .filter(|v| !v.is_empty())
+}
+
+/// Parts of a multipart body; an unterminated last part is kept
+fn split_multipart<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == delimiter || trimmed == format!("{}--", delimiter) {
+            // The line break before a delimiter belongs to the delimiter
+            if let Some(s) = start {
+                let end = if offset > s && body[..offset].ends_with('\n') { offset - 1 } else { offset };
+                parts.push(&body[s..end]);
+            }
+            if trimmed.ends_with("--") && trimmed != delimiter {
+                return parts;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+
+    if let Some(s) = start {
+        parts.push(&body[s..]);
+    }
+    parts
+}
+
+/// Decode a body by its transfer encoding; `None` if it is not valid
+fn decode_body(body: &str, encoding: &str) -> Option<Vec<u8>> {
+    match encoding {
+        "base64" => {
+            let compact: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+            base64::engine::general_purpose::STANDARD.decode(compact).ok()
+        }
+        "quoted-printable" => Some(decode_quoted_printable(body)),
+        _ => Some(body.as_bytes().to_vec()),
+    }
+}
+
+fn decode_quoted_printable(body: &str) -> Vec<u8> {
+    let bytes = body.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'=' {
+            // Soft line break
+            if bytes.get(i + 1) == Some(&b'\n') {
+                i += 2;
+                continue;
+            }
+            let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    out
+}
+
+fn decode_charset(data: &[u8], charset: &str) -> String {
+    match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "latin1" | "windows-1252" => data.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+/// Plain text of an HTML body, for messages without a text part
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut tag = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag.trim_start_matches('/').split_whitespace().next().unwrap_or("");
+                if matches!(name.to_ascii_lowercase().as_str(), "br" | "br/" | "p" | "div" | "li") {
+                    text.push('\n');
+                }
+            }
+            c if in_tag => tag.push(c),
+            c => text.push(c),
+        }
+    }
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// Addresses of an address list header, e.g. `"Doe, Jane" <jane@example.com>, bob@example.com`
+fn split_addresses(value: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in value.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ',' if !quoted => entries.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    entries.push(current);
+
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let address = match (entry.rfind('<'), entry.rfind('>')) {
+                (Some(start), Some(end)) if start < end => &entry[start + 1..end],
+                _ => entry.as_str(),
+            };
+            let address = address.trim().to_ascii_lowercase();
+            address.contains('@').then_some(address)
+        })
+        .collect()
+}
+
+/// Comment to create from a reply
+#[derive(Debug, Clone)]
+pub struct InboundComment {
+    pub work_package_id: Id,
+    pub user_id: Id,
+    pub notes: String,
+    pub attachments: Vec<InboundAttachment>,
+}
+
+/// Looks up the users replies come from
+#[async_trait]
+pub trait SenderDirectory: Send + Sync {
+    /// The user with the given email address
+    async fn find_user_id(&self, email: &str) -> InboundResult<Option<Id>>;
+
+    /// The anonymous user, for [`UnknownSenderPolicy::Anonymous`]
+    async fn anonymous_user_id(&self) -> InboundResult<Option<Id>>;
+}
+
+/// Creates comments on work packages
+///
+/// Implementations check the user may comment on the work package and
+/// return [`InboundError::Rejected`] if not.
+#[async_trait]
+pub trait CommentSink: Send + Sync {
+    /// Create the comment, returning the ID of its journal
+    async fn create_comment(&self, comment: InboundComment) -> InboundResult<Id>;
+}
+
+/// Outcome of processing a reply
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessedReply {
+    pub work_package_id: Id,
+    pub user_id: Id,
+    pub journal_id: Id,
+    pub attachments: usize,
+    pub skipped_attachments: Vec<SkippedAttachment>,
+}
+
+/// Turns replies to notification emails into work package comments
+pub struct InboundMailProcessor<D: SenderDirectory, C: CommentSink> {
+    directory: D,
+    comments: C,
+    config: InboundConfig,
+}
+
+impl<D: SenderDirectory, C: CommentSink> InboundMailProcessor<D, C> {
+    pub fn new(directory: D, comments: C, config: InboundConfig) -> Self {
+        Self {
+            directory,
+            comments,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &InboundConfig {
+        &self.config
+    }
+
+    /// Process a raw RFC 822 message
+    pub async fn process(&self, raw: &[u8]) -> InboundResult<ProcessedReply> {
+        let email = InboundEmail::parse(raw, self.config.max_attachment_size)?;
+
+        let sender = email.sender().ok_or(InboundError::MissingSender)?;
+        let work_package_id = email
+            .work_package_id(&self.config.plus_address_prefix)
+            .ok_or(InboundError::NoWorkPackage)?;
+
+        let user_id = match self.directory.find_user_id(&sender).await? {
+            Some(id) => id,
+            None => match self.config.unknown_sender_policy {
+                UnknownSenderPolicy::Reject => return Err(InboundError::UnknownSender(sender)),
+                UnknownSenderPolicy::Anonymous => self
+                    .directory
+                    .anonymous_user_id()
+                    .await?
+                    .ok_or(InboundError::UnknownSender(sender))?,
+            },
+        };
+
+        let notes = self.config.strip_reply(&email.text);
+        if notes.is_empty() && email.attachments.is_empty() {
+            return Err(InboundError::EmptyReply);
+        }
+
+        let attachments = email.attachments.len();
+        let journal_id = self
+            .comments
+            .create_comment(InboundComment {
+                work_package_id,
+                user_id,
+                notes,
+                attachments: email.attachments,
+            })
+            .await?;
+
+        info!(work_package_id, user_id, journal_id, "Created comment from email reply");
+
+        Ok(ProcessedReply {
+            work_package_id,
+            user_id,
+            journal_id,
+            attachments,
+            skipped_attachments: email.skipped_attachments,
+        })
+    }
+}
+
+/// A message in a mailbox
+#[derive(Debug, Clone)]
+pub struct MailboxMessage {
+    pub uid: u32,
+    pub raw: Vec<u8>,
+}
+
+/// A mailbox replies are delivered to, e.g. an IMAP inbox
+#[async_trait]
+pub trait Mailbox: Send + Sync {
+    /// Messages not marked as seen yet
+    async fn fetch_unseen(&self) -> InboundResult<Vec<MailboxMessage>>;
+
+    /// Mark a message so it is not fetched again
+    async fn mark_seen(&self, uid: u32) -> InboundResult<()>;
+}
+
+/// In-memory mailbox (for testing)
+#[derive(Default)]
+pub struct MemoryMailbox {
+    messages: RwLock<Vec<MailboxMessage>>,
+    seen: RwLock<HashSet<u32>>,
+}
+
+impl MemoryMailbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deliver a message, returning its UID
+    pub async fn deliver(&self, raw: impl Into<Vec<u8>>) -> u32 {
+        let mut messages = self.messages.write().await;
+        let uid = messages.len() as u32 + 1;
+        messages.push(MailboxMessage { uid, raw: raw.into() });
+        uid
+    }
+
+    pub async fn is_seen(&self, uid: u32) -> bool {
+        self.seen.read().await.contains(&uid)
+    }
+}
+
+#[async_trait]
+impl Mailbox for MemoryMailbox {
+    async fn fetch_unseen(&self) -> InboundResult<Vec<MailboxMessage>> {
+        let seen = self.seen.read().await;
+        let messages = self.messages.read().await;
+        Ok(messages
+            .iter()
+            .filter(|m| !seen.contains(&m.uid))
+            .cloned()
+            .collect())
+    }
+
+    async fn mark_seen(&self, uid: u32) -> InboundResult<()> {
+        self.seen.write().await.insert(uid);
+        Ok(())
+    }
+}
+
+/// Job processing the unseen messages of a mailbox
+///
+/// Processed and permanently rejected messages are marked as seen;
+/// messages failing transiently stay unseen for the next poll, and the
+/// job fails so the failure is visible.
+pub struct PollInboundMailJob<M: Mailbox, D: SenderDirectory, C: CommentSink> {
+    mailbox: Arc<M>,
+    processor: Arc<InboundMailProcessor<D, C>>,
+}
+
+impl<M: Mailbox, D: SenderDirectory, C: CommentSink> PollInboundMailJob<M, D, C> {
+    pub fn new(mailbox: Arc<M>, processor: Arc<InboundMailProcessor<D, C>>) -> Self {
+        Self { mailbox, processor }
+    }
+}
+
+#[async_trait]
+impl<M: Mailbox, D: SenderDirectory, C: CommentSink> JobHandler for PollInboundMailJob<M, D, C> {
+    async fn handle(&self, _args: serde_json::Value) -> JobResult<()> {
+        let messages = self
+            .mailbox
+            .fetch_unseen()
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))?;
+
+        let mut transient_failures = 0;
+        for message in messages {
+            match self.processor.process(&message.raw).await {
+                Ok(_) => {}
+                Err(e) if e.is_transient() => {
+                    warn!(uid = message.uid, error = %e, "Inbound email will be retried");
+                    transient_failures += 1;
+                    continue;
+                }
+                Err(e) => warn!(uid = message.uid, error = %e, "Inbound email rejected"),
+            }
+
+            self.mailbox
+                .mark_seen(message.uid)
+                .await
+                .map_err(|e| JobError::Failed(e.to_string()))?;
+        }
+
+        if transient_failures > 0 {
+            return Err(JobError::Failed(format!(
+                "{} inbound emails could not be processed",
+                transient_failures
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    struct FakeDirectory {
+        users: HashMap<String, Id>,
+        anonymous: Option<Id>,
+    }
+
+    impl FakeDirectory {
+        fn new() -> Self {
+            let mut users = HashMap::new();
+            users.insert("jane@example.com".to_string(), 7);
+            Self {
+                users,
+                anonymous: Some(2),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SenderDirectory for FakeDirectory {
+        async fn find_user_id(&self, email: &str) -> InboundResult<Option<Id>> {
+            Ok(self.users.get(email).copied())
+        }
+
+        async fn anonymous_user_id(&self) -> InboundResult<Option<Id>> {
+            Ok(self.anonymous)
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeComments {
+        created: Mutex<Vec<InboundComment>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl CommentSink for Arc<FakeComments> {
+        async fn create_comment(&self, comment: InboundComment) -> InboundResult<Id> {
+            if self.fail {
+                return Err(InboundError::CommentFailed("database unavailable".to_string()));
+            }
+            let mut created = self.created.lock().unwrap();
+            created.push(comment);
+            Ok(created.len() as Id)
+        }
+    }
+
+    fn create_processor(
+        config: InboundConfig,
+    ) -> (InboundMailProcessor<FakeDirectory, Arc<FakeComments>>, Arc<FakeComments>) {
+        let comments = Arc::new(FakeComments::default());
+        (
+            InboundMailProcessor::new(FakeDirectory::new(), comments.clone(), config),
+            comments,
+        )
+    }
+
+    const PLAIN_REPLY: &str = "From: Jane Doe <Jane@Example.com>\r\n\
+        To: OpenProject <wp+42@openproject.example.com>\r\n\
+        Subject: Re: [Project] Bug #42\r\n\
+        \r\n\
+        Fixed in the latest build.\r\n\
+        \r\n\
+        On Mon, 1 Jan 2024, OpenProject wrote:\r\n\
+        > Bug #42 was updated\r\n";
+
+    #[test]
+    fn test_work_package_from_header() {
+        let raw = "From: jane@example.com\nX-OpenProject-Type: WorkPackage\nX-OpenProject-Id: 123\n\nThanks";
+        let email = InboundEmail::parse(raw.as_bytes(), 1024).unwrap();
+        assert_eq!(email.work_package_id("wp"), Some(123));
+
+        let raw = "From: jane@example.com\nX-OpenProject-Type: News\nX-OpenProject-Id: 123\n\nThanks";
+        let email = InboundEmail::parse(raw.as_bytes(), 1024).unwrap();
+        assert_eq!(email.work_package_id("wp"), None);
+    }
+
+    #[test]
+    fn test_work_package_from_plus_address() {
+        let email = InboundEmail::parse(PLAIN_REPLY.as_bytes(), 1024).unwrap();
+        assert_eq!(email.sender().as_deref(), Some("jane@example.com"));
+        assert_eq!(email.work_package_id("wp"), Some(42));
+        assert_eq!(email.work_package_id("issue"), None);
+
+        let raw = "From: jane@example.com\nTo: \"Replies, OpenProject\" <noreply@example.com>, WP+9@example.com\n\nHi";
+        let email = InboundEmail::parse(raw.as_bytes(), 1024).unwrap();
+        assert_eq!(email.work_package_id("wp"), Some(9));
+    }
+
+    #[test]
+    fn test_strip_reply() {
+        let config = InboundConfig::default();
+        let text = "Looks good.\nShip it.\n\n-- \nJane\nCEO";
+        assert_eq!(config.strip_reply(text), "Looks good.\nShip it.");
+
+        let text = "Done\n-- Reply above this line --\nOld notification";
+        assert_eq!(config.strip_reply(text), "Done");
+
+        let text = "Agreed\n> quoted line\nthanks";
+        assert_eq!(config.strip_reply(text), "Agreed\nthanks");
+    }
+
+    #[test]
+    fn test_strip_reply_with_configured_markers() {
+        let config = InboundConfig {
+            reply_markers: vec!["### Antwort oberhalb ###".to_string()],
+            signature_markers: vec!["Regards,".to_string()],
+            ..Default::default()
+        };
+
+        let text = "Erledigt\n### Antwort oberhalb ###\nalt";
+        assert_eq!(config.strip_reply(text), "Erledigt");
+        let text = "Done\nRegards,\nJane";
+        assert_eq!(config.strip_reply(text), "Done");
+    }
+
+    #[test]
+    fn test_parse_multipart_with_attachments() {
+        let raw = "From: jane@example.com\n\
+            Content-Type: multipart/mixed; boundary=\"outer\"\n\
+            \n\
+            --outer\n\
+            Content-Type: multipart/alternative; boundary=inner\n\
+            \n\
+            --inner\n\
+            Content-Type: text/plain; charset=utf-8\n\
+            Content-Transfer-Encoding: quoted-printable\n\
+            \n\
+            Gr=C3=BC=C3=9Fe, see the =\n\
+            log\n\
+            --inner\n\
+            Content-Type: text/html\n\
+            \n\
+            <p>ignored</p>\n\
+            --inner--\n\
+            --outer\n\
+            Content-Type: text/plain; name=\"log.txt\"\n\
+            Content-Disposition: attachment; filename=\"log.txt\"\n\
+            Content-Transfer-Encoding: base64\n\
+            \n\
+            ZXJyb3Igb24g\n\
+            bGluZSAx\n\
+            --outer--\n";
+
+        let email = InboundEmail::parse(raw.as_bytes(), 1024).unwrap();
+        assert_eq!(email.text.trim(), "Grüße, see the log");
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].filename, "log.txt");
+        assert_eq!(email.attachments[0].data, b"error on line 1");
+        assert!(email.skipped_attachments.is_empty());
+    }
+
+    #[test]
+    fn test_html_only_message() {
+        let raw = "From: jane@example.com\nContent-Type: text/html\n\n<div>Done &amp; dusted<br>Jane</div>";
+        let email = InboundEmail::parse(raw.as_bytes(), 1024).unwrap();
+        assert_eq!(email.text.trim(), "Done & dusted\nJane");
+    }
+
+    #[test]
+    fn test_malformed_mime_degrades() {
+        // No closing boundary, a broken header line and an undecodable attachment
+        let raw = "From: jane@example.com\n\
+            this line is not a header\n\
+            Content-Type: multipart/mixed; boundary=b\n\
+            \n\
+            --b\n\
+            \n\
+            Still readable\n\
+            --b\n\
+            Content-Disposition: attachment; filename=broken.bin\n\
+            Content-Transfer-Encoding: base64\n\
+            \n\
+            !!! not base64 !!!\n";
+
+        let email = InboundEmail::parse(raw.as_bytes(), 1024).unwrap();
+        assert_eq!(email.text.trim(), "Still readable");
+        assert!(email.attachments.is_empty());
+        assert_eq!(email.skipped_attachments[0].filename, "broken.bin");
+
+        // A multipart without a boundary is read as text
+        let raw = "From: jane@example.com\nContent-Type: multipart/mixed\n\nplain after all";
+        let email = InboundEmail::parse(raw.as_bytes(), 1024).unwrap();
+        assert_eq!(email.text, "plain after all");
+
+        assert!(matches!(
+            InboundEmail::parse(b"", 1024),
+            Err(InboundError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_process_creates_comment() {
+        let (processor, comments) = create_processor(InboundConfig::default());
+
+        let reply = processor.process(PLAIN_REPLY.as_bytes()).await.unwrap();
+        assert_eq!(reply.work_package_id, 42);
+        assert_eq!(reply.user_id, 7);
+        assert_eq!(reply.journal_id, 1);
+
+        let created = comments.created.lock().unwrap();
+        assert_eq!(created[0].notes, "Fixed in the latest build.");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_attachment_is_skipped() {
+        let (processor, comments) = create_processor(InboundConfig {
+            max_attachment_size: 4,
+            ..Default::default()
+        });
+        let raw = "From: jane@example.com\n\
+            To: wp+42@example.com\n\
+            Content-Type: multipart/mixed; boundary=b\n\
+            \n\
+            --b\n\
+            Content-Type: text/plain\n\
+            \n\
+            Screenshot attached\n\
+            --b\n\
+            Content-Type: image/png\n\
+            Content-Disposition: attachment; filename=screen.png\n\
+            \n\
+            far too large\n\
+            --b\n\
+            Content-Disposition: attachment; filename=ok.txt\n\
+            \n\
+            tiny\n\
+            --b--\n";
+
+        let reply = processor.process(raw.as_bytes()).await.unwrap();
+        assert_eq!(reply.attachments, 1);
+        assert_eq!(reply.skipped_attachments.len(), 1);
+        assert_eq!(reply.skipped_attachments[0].filename, "screen.png");
+
+        let created = comments.created.lock().unwrap();
+        assert_eq!(created[0].attachments[0].filename, "ok.txt");
+        assert_eq!(created[0].notes, "Screenshot attached");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_sender_policy() {
+        let raw = "From: stranger@example.com\nTo: wp+42@example.com\n\nHello";
+
+        let (processor, _) = create_processor(InboundConfig::default());
+        assert!(matches!(
+            processor.process(raw.as_bytes()).await,
+            Err(InboundError::UnknownSender(_))
+        ));
+
+        let (processor, _) = create_processor(InboundConfig {
+            unknown_sender_policy: UnknownSenderPolicy::Anonymous,
+            ..Default::default()
+        });
+        assert_eq!(processor.process(raw.as_bytes()).await.unwrap().user_id, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_replies_without_target_or_content() {
+        let (processor, _) = create_processor(InboundConfig::default());
+
+        let raw = "From: jane@example.com\nTo: support@example.com\n\nHello";
+        assert!(matches!(
+            processor.process(raw.as_bytes()).await,
+            Err(InboundError::NoWorkPackage)
+        ));
+
+        let raw = "From: jane@example.com\nTo: wp+42@example.com\n\n> only quoted text";
+        assert!(matches!(
+            processor.process(raw.as_bytes()).await,
+            Err(InboundError::EmptyReply)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_poll_job_marks_processed_messages() {
+        let mailbox = Arc::new(MemoryMailbox::new());
+        let processed = mailbox.deliver(PLAIN_REPLY).await;
+        let rejected = mailbox.deliver("From: stranger@example.com\nTo: wp+1@example.com\n\nHi").await;
+
+        let (processor, comments) = create_processor(InboundConfig::default());
+        let job = PollInboundMailJob::new(mailbox.clone(), Arc::new(processor));
+
+        job.handle(serde_json::json!({})).await.unwrap();
+        assert!(mailbox.is_seen(processed).await);
+        assert!(mailbox.is_seen(rejected).await);
+        assert_eq!(comments.created.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_poll_job_keeps_transient_failures_unseen() {
+        let mailbox = Arc::new(MemoryMailbox::new());
+        let uid = mailbox.deliver(PLAIN_REPLY).await;
+
+        let comments = Arc::new(FakeComments {
+            fail: true,
+            ..Default::default()
+        });
+        let processor = InboundMailProcessor::new(FakeDirectory::new(), comments, InboundConfig::default());
+        let job = PollInboundMailJob::new(mailbox.clone(), Arc::new(processor));
+
+        assert!(job.handle(serde_json::json!({})).await.is_err());
+        assert!(!mailbox.is_seen(uid).await);
+    }
+}
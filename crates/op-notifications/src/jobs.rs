@@ -8,9 +8,12 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use op_core::metrics::DomainMetrics;
+use op_core::request_id::{self, REQUEST_ID_METADATA_KEY};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::RwLock;
+use tracing::Instrument;
 
 /// Job errors
 #[derive(Debug, Error)]
@@ -86,11 +89,26 @@ pub struct Job {
     pub started_at: Option<DateTime<Utc>>,
     /// When the job completed/failed
     pub finished_at: Option<DateTime<Utc>>,
+    /// Correlation metadata (e.g. the originating request ID)
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// Summary the handler reported on completion (e.g. counts of what it
+    /// changed)
+    #[serde(default)]
+    pub result: Option<serde_json::Value>,
 }
 
 impl Job {
     /// Create a new job
+    ///
+    /// When created while handling an API request, the request ID is
+    /// recorded in the job metadata.
     pub fn new(job_type: impl Into<String>, args: serde_json::Value) -> Self {
+        let mut metadata = HashMap::new();
+        if let Some(id) = request_id::current() {
+            metadata.insert(REQUEST_ID_METADATA_KEY.to_string(), id);
+        }
+
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             job_type: job_type.into(),
@@ -105,9 +123,22 @@ impl Job {
             created_at: Utc::now(),
             started_at: None,
             finished_at: None,
+            metadata,
+            result: None,
         }
     }
 
+    /// Add a metadata entry
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// ID of the API request that enqueued this job
+    pub fn request_id(&self) -> Option<&str> {
+        self.metadata.get(REQUEST_ID_METADATA_KEY).map(String::as_str)
+    }
+
     /// Set the queue
     pub fn queue(mut self, queue: impl Into<String>) -> Self {
         self.queue = queue.into();
@@ -360,12 +391,20 @@ pub struct JobWorker<Q: JobQueue> {
     queue: Arc<Q>,
     queue_name: String,
     handlers: HashMap<String, Box<dyn JobHandler>>,
+    metrics: Option<Arc<DomainMetrics>>,
+    pauses: Vec<tokio::sync::watch::Receiver<bool>>,
 }
 
 /// Handler for a specific job type
 #[async_trait]
 pub trait JobHandler: Send + Sync {
     async fn handle(&self, args: serde_json::Value) -> JobResult<()>;
+
+    /// Handle the job, returning the summary recorded as the job result.
+    /// Handlers reporting no summary only implement [`handle`](Self::handle).
+    async fn perform(&self, args: serde_json::Value) -> JobResult<Option<serde_json::Value>> {
+        self.handle(args).await.map(|()| None)
+    }
 }
 
 impl<Q: JobQueue> JobWorker<Q> {
@@ -374,9 +413,26 @@ impl<Q: JobQueue> JobWorker<Q> {
             queue,
             queue_name: queue_name.into(),
             handlers: HashMap::new(),
+            metrics: None,
+            pauses: Vec::new(),
         }
     }
 
+    /// Record processed, failed and retried jobs in the given metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<DomainMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Stop dequeuing jobs in [`run`](Self::run) while the flag is set,
+    /// e.g. while the database is unhealthy; with several flags the worker
+    /// pauses while any of them is set. The job running when a flag is set
+    /// still finishes.
+    pub fn with_pause(mut self, pause: tokio::sync::watch::Receiver<bool>) -> Self {
+        self.pauses.push(pause);
+        self
+    }
+
     /// Register a handler for a job type
     pub fn register<H: JobHandler + 'static>(&mut self, job_type: impl Into<String>, handler: H) {
         self.handlers.insert(job_type.into(), Box::new(handler));
@@ -394,14 +450,23 @@ impl<Q: JobQueue> JobWorker<Q> {
             None => {
                 let mut failed_job = job;
                 failed_job.mark_failed(format!("Unknown job type: {}", failed_job.job_type));
+                self.record_outcome(&failed_job);
                 self.queue.update(&failed_job).await?;
                 return Ok(true);
             }
         };
 
+        let span = tracing::info_span!(
+            "job",
+            job_id = %job.id,
+            job_type = %job.job_type,
+            request_id = job.request_id().unwrap_or("-"),
+        );
+
         let mut job = job;
-        match handler.handle(job.args.clone()).await {
-            Ok(()) => {
+        match handler.perform(job.args.clone()).instrument(span).await {
+            Ok(result) => {
+                job.result = result;
                 job.mark_completed();
             }
             Err(e) => {
@@ -409,10 +474,26 @@ impl<Q: JobQueue> JobWorker<Q> {
             }
         }
 
+        self.record_outcome(&job);
         self.queue.update(&job).await?;
         Ok(true)
     }
 
+    fn record_outcome(&self, job: &Job) {
+        let Some(metrics) = &self.metrics else {
+            return;
+        };
+
+        match job.status {
+            JobStatus::Completed => metrics.record_job_processed(),
+            JobStatus::Retrying => {
+                metrics.record_job_failed();
+                metrics.record_job_retried();
+            }
+            _ => metrics.record_job_failed(),
+        }
+    }
+
     /// Run the worker loop
     pub async fn run(&self, shutdown: tokio::sync::watch::Receiver<bool>) {
         loop {
@@ -420,6 +501,11 @@ impl<Q: JobQueue> JobWorker<Q> {
                 break;
             }
 
+            if self.pauses.iter().any(|pause| *pause.borrow()) {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                continue;
+            }
+
             match self.process_one().await {
                 Ok(true) => {
                     // Processed a job, continue immediately
@@ -531,4 +617,119 @@ mod tests {
         let count = queue.pending_count("default").await.unwrap();
         assert_eq!(count, 2);
     }
+
+    #[tokio::test]
+    async fn test_job_records_request_id() {
+        let job = request_id::scope("req-42".into(), async {
+            Job::new("test", serde_json::json!({}))
+        })
+        .await;
+        assert_eq!(job.request_id(), Some("req-42"));
+
+        let job = Job::new("test", serde_json::json!({}));
+        assert_eq!(job.request_id(), None);
+
+        let job = Job::new("test", serde_json::json!({})).with_metadata("request_id", "abc");
+        let json = serde_json::to_value(job).unwrap();
+        assert_eq!(json["metadata"]["request_id"], "abc");
+    }
+
+    struct NoopHandler;
+
+    #[async_trait]
+    impl JobHandler for NoopHandler {
+        async fn handle(&self, _args: serde_json::Value) -> JobResult<()> {
+            Ok(())
+        }
+    }
+
+    struct CountingHandler;
+
+    #[async_trait]
+    impl JobHandler for CountingHandler {
+        async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+            self.perform(args).await.map(|_| ())
+        }
+
+        async fn perform(&self, args: serde_json::Value) -> JobResult<Option<serde_json::Value>> {
+            Ok(Some(serde_json::json!({ "counted": args["items"].as_array().map_or(0, Vec::len) })))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_records_job_result() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let counted = queue
+            .enqueue(Job::new("count", serde_json::json!({ "items": [1, 2, 3] })))
+            .await
+            .unwrap();
+        let noop = queue.enqueue(Job::new("noop", serde_json::json!({}))).await.unwrap();
+
+        let mut worker = JobWorker::new(queue.clone(), "default");
+        worker.register("count", CountingHandler);
+        worker.register("noop", NoopHandler);
+        while worker.process_one().await.unwrap() {}
+
+        let job = queue.get(&counted).await.unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.result, Some(serde_json::json!({ "counted": 3 })));
+        assert_eq!(queue.get(&noop).await.unwrap().unwrap().result, None);
+    }
+
+    #[tokio::test]
+    async fn test_worker_pauses_while_flagged() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        queue.enqueue(Job::new("noop", serde_json::json!({}))).await.unwrap();
+
+        let (pause, paused) = tokio::sync::watch::channel(true);
+        let (shutdown, shutdown_rx) = tokio::sync::watch::channel(false);
+        let mut worker = JobWorker::new(queue.clone(), "default").with_pause(paused);
+        worker.register("noop", NoopHandler);
+        let running = tokio::spawn(async move { worker.run(shutdown_rx).await });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        assert_eq!(queue.pending_count("default").await.unwrap(), 1);
+
+        pause.send(false).unwrap();
+        tokio::time::timeout(tokio::time::Duration::from_secs(5), async {
+            while queue.pending_count("default").await.unwrap() > 0 {
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        shutdown.send(true).unwrap();
+        running.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_worker_pauses_while_any_flag_is_set() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        queue.enqueue(Job::new("noop", serde_json::json!({}))).await.unwrap();
+
+        let (_healthy, database) = tokio::sync::watch::channel(false);
+        let (maintenance, in_maintenance) = tokio::sync::watch::channel(true);
+        let (shutdown, shutdown_rx) = tokio::sync::watch::channel(false);
+        let mut worker = JobWorker::new(queue.clone(), "default")
+            .with_pause(database)
+            .with_pause(in_maintenance);
+        worker.register("noop", NoopHandler);
+        let running = tokio::spawn(async move { worker.run(shutdown_rx).await });
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        assert_eq!(queue.pending_count("default").await.unwrap(), 1);
+
+        maintenance.send(false).unwrap();
+        tokio::time::timeout(tokio::time::Duration::from_secs(5), async {
+            while queue.pending_count("default").await.unwrap() > 0 {
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        shutdown.send(true).unwrap();
+        running.await.unwrap();
+    }
 }
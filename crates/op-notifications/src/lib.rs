@@ -5,19 +5,53 @@
 //! ## Features
 //!
 //! - Background job queue with retry support
+//! - Recurring jobs on cron schedules
 //! - In-app notifications (bell icon)
 //! - Email notifications
 //! - Digest emails (daily/weekly)
 //! - Mention notifications
+//! - Notification event streams for connected clients
+//! - Outbound email limits and circuit breaker
+//! - Summaries instead of per-item notifications during bulk operations
+//! - Queued delivery of domain events
 
 pub mod jobs;
+pub mod cron;
+pub mod scheduler;
 pub mod notification;
 pub mod channels;
 pub mod email;
 pub mod service;
+pub mod inbound;
+pub mod stream;
+pub mod throttle;
+pub mod bulk;
+pub mod events;
 
 pub use jobs::{Job, JobQueue, JobStatus, JobError, MemoryJobQueue};
-pub use notification::{Notification, NotificationType, NotificationReason};
-pub use channels::{Channel, ChannelConfig};
-pub use email::{EmailMessage, EmailRenderer};
-pub use service::{NotificationService, NotificationEvent};
+pub use cron::{CronError, CronSchedule};
+pub use scheduler::{MemoryScheduleStore, MisfirePolicy, ScheduleStore, ScheduledJob, Scheduler};
+pub use notification::{
+    AttributeChange, DateAlerts, EmailFrequency, Notification, NotificationGroup, NotificationReason, NotificationSettings,
+    NotificationSnapshot, NotificationType, ProjectNotificationSettings, ReasonSettings,
+};
+pub use channels::{
+    Channel, ChannelConfig, ChannelDispatcher, ChannelHandler, DeliveryResult, EmailChannel, InAppChannel,
+    Recipient, WebhookChannel,
+};
+pub use email::{
+    EmailMessage, EmailRenderer, ListedWorkPackage, ProjectEmailSettings, QueryResultChanges, SharedWorkPackage,
+};
+pub use service::{
+    JournalSnapshots, MemoryNotificationStore, MemoryProjectEmailSettings, NotificationEvent, NotificationService,
+    NotificationStore, ProjectEmailSettingsSource,
+};
+pub use inbound::{
+    CommentSink, InboundAttachment, InboundComment, InboundConfig, InboundEmail, InboundError,
+    InboundMailProcessor, Mailbox, MailboxMessage, MemoryMailbox, PollInboundMailJob, ProcessedReply,
+    SenderDirectory, SkippedAttachment, UnknownSenderPolicy,
+};
+pub use stream::{NotificationStreams, Received, StreamEvent, StreamEventKind, Subscription};
+pub use bulk::{BulkOperation, BulkSummary, SummaryDirectory, SummaryMailer, SummaryRecipient, SuppressionWindow};
+pub use events::{DeliverDomainEventJob, JobEventQueue, DELIVER_DOMAIN_EVENT_JOB};
+pub use throttle::{AdminDirectory, Deferral, DeferralReason, EmailThrottle, ThrottleStatus};
@@ -6,6 +6,12 @@ use chrono::{DateTime, Utc};
 use op_core::traits::Id;
 use serde::{Deserialize, Serialize};
 
+/// Job type creating the start and due date alerts of the day
+pub const DATE_ALERTS_JOB: &str = "Notifications::ScheduleDateAlertsNotificationsJob";
+
+/// Characters of a comment kept in a notification snapshot
+pub const EXCERPT_MAX_CHARS: usize = 280;
+
 /// Notification type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -24,6 +30,10 @@ pub enum NotificationType {
     WorkPackageDueDateAlert,
     /// Work package overdue
     WorkPackageOverdue,
+    /// Work package moved to the trash
+    WorkPackageTrashed,
+    /// Work package deleted permanently from the trash
+    WorkPackagePurged,
     /// Project created
     ProjectCreated,
     /// Membership added
@@ -44,6 +54,10 @@ pub enum NotificationType {
     MeetingInvitation,
     /// Reminder
     Reminder,
+    /// A subscribed query could not be checked anymore
+    QuerySubscriptionSuspended,
+    /// Outbound email paused after repeated delivery failures
+    EmailDeliveryPaused,
 }
 
 impl NotificationType {
@@ -57,6 +71,8 @@ impl NotificationType {
             Self::WorkPackageMentioned => "notification.work_package.mentioned",
             Self::WorkPackageDueDateAlert => "notification.work_package.due_date_alert",
             Self::WorkPackageOverdue => "notification.work_package.overdue",
+            Self::WorkPackageTrashed => "notification.work_package.trashed",
+            Self::WorkPackagePurged => "notification.work_package.purged",
             Self::ProjectCreated => "notification.project.created",
             Self::MembershipAdded => "notification.membership.added",
             Self::MembershipUpdated => "notification.membership.updated",
@@ -67,6 +83,8 @@ impl NotificationType {
             Self::FileUploaded => "notification.file.uploaded",
             Self::MeetingInvitation => "notification.meeting.invitation",
             Self::Reminder => "notification.reminder",
+            Self::QuerySubscriptionSuspended => "notification.query.subscription_suspended",
+            Self::EmailDeliveryPaused => "notification.email.delivery_paused",
         }
     }
 
@@ -81,6 +99,8 @@ impl NotificationType {
                 | Self::WorkPackageMentioned
                 | Self::WorkPackageDueDateAlert
                 | Self::WorkPackageOverdue
+                | Self::WorkPackageTrashed
+                | Self::WorkPackagePurged
         )
     }
 }
@@ -130,10 +150,20 @@ pub struct Notification {
     pub project_id: Option<Id>,
     /// Journal ID (for change notifications)
     pub journal_id: Option<Id>,
+    /// What the journal changed, captured when the notification was created.
+    /// Notifications stored before snapshots existed have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<NotificationSnapshot>,
     /// Has been read
     pub read_at: Option<DateTime<Utc>>,
     /// Has been emailed
     pub mail_sent_at: Option<DateTime<Utc>>,
+    /// Has been included in a digest, or claimed by a digest being sent
+    #[serde(default)]
+    pub mail_reminder_sent: bool,
+    /// Has been emailed immediately
+    #[serde(default)]
+    pub mail_alert_sent: bool,
     /// Created timestamp
     pub created_at: DateTime<Utc>,
     /// Updated timestamp
@@ -160,8 +190,11 @@ impl Notification {
             resource_id,
             project_id: None,
             journal_id: None,
+            snapshot: None,
             read_at: None,
             mail_sent_at: None,
+            mail_reminder_sent: false,
+            mail_alert_sent: false,
             created_at: now,
             updated_at: now,
         }
@@ -201,6 +234,13 @@ impl Notification {
         self
     }
 
+    /// Set the journal together with what it changed
+    pub fn with_snapshot(mut self, journal_id: Id, snapshot: NotificationSnapshot) -> Self {
+        self.journal_id = Some(journal_id);
+        self.snapshot = Some(snapshot);
+        self
+    }
+
     /// Check if the notification is unread
     pub fn is_unread(&self) -> bool {
         self.read_at.is_none()
@@ -217,13 +257,165 @@ impl Notification {
         self.updated_at = Utc::now();
     }
 
-    /// Mark as mail sent
+    /// Check if the notification is still to be included in a digest
+    pub fn is_digest_pending(&self) -> bool {
+        !self.mail_reminder_sent && !self.mail_alert_sent
+    }
+
+    /// Mark as mail sent, immediately rather than in a digest
     pub fn mark_mail_sent(&mut self) {
         self.mail_sent_at = Some(Utc::now());
+        self.mail_alert_sent = true;
         self.updated_at = Utc::now();
     }
 }
 
+/// Denormalized details of the journal a notification is about, so that
+/// emails and the notification center render them without looking anything up
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationSnapshot {
+    /// Subject of the work package
+    pub subject: String,
+    /// Name of the work package's type, e.g. "Bug"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    /// Name of the project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    /// Start of the journal's comment, at most [`EXCERPT_MAX_CHARS`] characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment_excerpt: Option<String>,
+    /// Attributes the journal changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<AttributeChange>,
+}
+
+impl NotificationSnapshot {
+    pub fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the type name
+    pub fn with_type(mut self, type_name: impl Into<String>) -> Self {
+        self.type_name = Some(type_name.into());
+        self
+    }
+
+    /// Set the project name
+    pub fn with_project(mut self, project_name: impl Into<String>) -> Self {
+        self.project_name = Some(project_name.into());
+        self
+    }
+
+    /// Keep an excerpt of the comment; blank comments are left out
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment_excerpt = excerpt(comment, EXCERPT_MAX_CHARS);
+        self
+    }
+
+    /// Add a changed attribute with its displayed old and new values
+    pub fn with_change(mut self, attribute: impl Into<String>, old: Option<String>, new: Option<String>) -> Self {
+        self.changes.push(AttributeChange {
+            attribute: attribute.into(),
+            old,
+            new,
+        });
+        self
+    }
+
+    /// Title of the resource, e.g. "Bug #123: Crash on save". Without a
+    /// type name, `fallback_type` is used.
+    pub fn title(&self, resource_id: Id, fallback_type: &str) -> String {
+        format!(
+            "{} #{}: {}",
+            self.type_name.as_deref().unwrap_or(fallback_type),
+            resource_id,
+            self.subject
+        )
+    }
+}
+
+/// An attribute changed by a journal, with its values as displayed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttributeChange {
+    /// Attribute name, e.g. "status"
+    pub attribute: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+/// Whitespace-collapsed start of `text`, cut at `max_chars` characters with
+/// an ellipsis. `None` for blank text.
+pub fn excerpt(text: &str, max_chars: usize) -> Option<String> {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_empty() {
+        return None;
+    }
+    if collapsed.chars().count() <= max_chars {
+        return Some(collapsed);
+    }
+
+    let cut: String = collapsed.chars().take(max_chars.saturating_sub(1)).collect();
+    Some(format!("{}…", cut.trim_end()))
+}
+
+/// Notifications of one user about the same resource, aggregated for the
+/// notification center
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationGroup {
+    /// Related resource type
+    pub resource_type: String,
+    /// Related resource ID
+    pub resource_id: Id,
+    /// Most recent notification of the group
+    pub newest: Notification,
+    /// Number of notifications in the group
+    pub count: usize,
+    /// Distinct reasons, most recent first
+    pub reasons: Vec<NotificationReason>,
+}
+
+impl NotificationGroup {
+    /// Group a user's notifications by resource. Groups are ordered by
+    /// their newest notification, most recent first.
+    pub fn group(notifications: impl IntoIterator<Item = Notification>) -> Vec<Self> {
+        let mut notifications: Vec<Notification> = notifications.into_iter().collect();
+        notifications.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+
+        let mut groups: Vec<Self> = Vec::new();
+        for notification in notifications {
+            match groups.iter_mut().find(|g| g.contains(&notification)) {
+                Some(group) => {
+                    group.count += 1;
+                    if !group.reasons.contains(&notification.reason) {
+                        group.reasons.push(notification.reason);
+                    }
+                }
+                None => groups.push(Self {
+                    resource_type: notification.resource_type.clone(),
+                    resource_id: notification.resource_id,
+                    reasons: vec![notification.reason],
+                    count: 1,
+                    newest: notification,
+                }),
+            }
+        }
+
+        groups
+    }
+
+    /// Check if the notification is about this group's resource
+    pub fn contains(&self, notification: &Notification) -> bool {
+        notification.resource_type == self.resource_type
+            && notification.resource_id == self.resource_id
+    }
+}
+
 /// Notification settings for a user
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationSettings {
@@ -240,6 +432,18 @@ pub struct NotificationSettings {
     pub enabled_reasons: Vec<NotificationReason>,
     /// Specific projects to watch (None = all projects)
     pub watched_projects: Option<Vec<Id>>,
+    /// In-app notifications by reason
+    #[serde(default)]
+    pub in_app_reasons: ReasonSettings,
+    /// Immediate emails by reason; other notifications wait for the digest
+    #[serde(default = "ReasonSettings::all_enabled")]
+    pub email_reasons: ReasonSettings,
+    /// Date alerts of work packages
+    #[serde(default)]
+    pub date_alerts: DateAlerts,
+    /// Settings overriding the ones above in single projects
+    #[serde(default)]
+    pub project_overrides: Vec<ProjectNotificationSettings>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -259,10 +463,14 @@ impl Default for NotificationSettings {
             email_enabled: true,
             email_frequency: EmailFrequency::Immediate,
             enabled_types: vec![
+                NotificationType::WorkPackageUpdated,
                 NotificationType::WorkPackageAssigned,
                 NotificationType::WorkPackageMentioned,
                 NotificationType::WorkPackageCommented,
+                NotificationType::WorkPackageTrashed,
+                NotificationType::WorkPackagePurged,
                 NotificationType::MembershipAdded,
+                NotificationType::MessagePosted,
             ],
             enabled_reasons: vec![
                 NotificationReason::Assigned,
@@ -270,6 +478,10 @@ impl Default for NotificationSettings {
                 NotificationReason::Watched,
             ],
             watched_projects: None,
+            in_app_reasons: ReasonSettings::default(),
+            email_reasons: ReasonSettings::all_enabled(),
+            date_alerts: DateAlerts::default(),
+            project_overrides: Vec::new(),
         }
     }
 }
@@ -300,7 +512,7 @@ impl NotificationSettings {
         }
 
         // Check reason
-        if !self.enabled_reasons.contains(&reason) {
+        if !self.in_app_reason_enabled(reason, project_id) {
             return false;
         }
 
@@ -316,10 +528,175 @@ impl NotificationSettings {
         true
     }
 
+    /// Check if notifications of the type are wanted in the project for
+    /// being a member of it. Subscribing to the type is all it takes; the
+    /// reasons of the settings matrix do not apply.
+    pub fn subscribed_to(&self, notification_type: NotificationType, project_id: Id) -> bool {
+        self.in_app_enabled
+            && self.enabled_types.contains(&notification_type)
+            && match &self.watched_projects {
+                Some(watched) => watched.contains(&project_id),
+                None => true,
+            }
+    }
+
     /// Check if email should be sent
     pub fn should_email(&self) -> bool {
         self.email_enabled && self.email_frequency != EmailFrequency::Never
     }
+
+    /// Override of the project's settings, if any
+    pub fn project_override(&self, project_id: Option<Id>) -> Option<&ProjectNotificationSettings> {
+        let project_id = project_id?;
+        self.project_overrides.iter().find(|o| o.project_id == project_id)
+    }
+
+    /// Whether in-app notifications are wanted for the reason. Reasons
+    /// outside the settings matrix are looked up in `enabled_reasons`.
+    pub fn in_app_reason_enabled(&self, reason: NotificationReason, project_id: Option<Id>) -> bool {
+        let reasons = self
+            .project_override(project_id)
+            .map_or(&self.in_app_reasons, |o| &o.in_app);
+        reasons
+            .get(reason)
+            .unwrap_or_else(|| self.enabled_reasons.contains(&reason))
+    }
+
+    /// Whether a notification with the reason is emailed right away rather
+    /// than with the digest
+    pub fn email_reason_enabled(&self, reason: NotificationReason, project_id: Option<Id>) -> bool {
+        let reasons = self
+            .project_override(project_id)
+            .map_or(&self.email_reasons, |o| &o.email);
+        reasons.get(reason).unwrap_or(true)
+    }
+
+    /// Date alerts for work packages of the project
+    pub fn date_alerts_for(&self, project_id: Option<Id>) -> DateAlerts {
+        self.project_override(project_id)
+            .map_or(self.date_alerts, |o| o.date_alerts)
+    }
+}
+
+/// Reasons a user turns notifications on or off for, per channel and
+/// project, on the notification settings page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReasonSettings {
+    pub involved: bool,
+    pub watched: bool,
+    pub mentioned: bool,
+    pub assignee: bool,
+    pub responsible: bool,
+}
+
+impl Default for ReasonSettings {
+    /// The reasons enabled by default in `enabled_reasons`
+    fn default() -> Self {
+        Self {
+            involved: false,
+            watched: true,
+            mentioned: true,
+            assignee: true,
+            responsible: false,
+        }
+    }
+}
+
+impl ReasonSettings {
+    /// Keys of the reasons in the API
+    pub const KEYS: [&'static str; 5] = ["involved", "watched", "mentioned", "assignee", "responsible"];
+
+    pub fn all_enabled() -> Self {
+        Self {
+            involved: true,
+            watched: true,
+            mentioned: true,
+            assignee: true,
+            responsible: true,
+        }
+    }
+
+    /// Whether the reason is enabled, `None` for reasons outside the matrix
+    pub fn get(&self, reason: NotificationReason) -> Option<bool> {
+        match reason {
+            NotificationReason::Involved => Some(self.involved),
+            NotificationReason::Watched => Some(self.watched),
+            NotificationReason::Mentioned => Some(self.mentioned),
+            NotificationReason::Assigned => Some(self.assignee),
+            NotificationReason::Responsible => Some(self.responsible),
+            _ => None,
+        }
+    }
+
+    /// Flag of the reason with the key, `None` for unknown keys
+    pub fn flag_mut(&mut self, key: &str) -> Option<&mut bool> {
+        match key {
+            "involved" => Some(&mut self.involved),
+            "watched" => Some(&mut self.watched),
+            "mentioned" => Some(&mut self.mentioned),
+            "assignee" => Some(&mut self.assignee),
+            "responsible" => Some(&mut self.responsible),
+            _ => None,
+        }
+    }
+
+    /// Flags keyed as in [`Self::KEYS`]
+    pub fn flags(&self) -> [(&'static str, bool); 5] {
+        [
+            ("involved", self.involved),
+            ("watched", self.watched),
+            ("mentioned", self.mentioned),
+            ("assignee", self.assignee),
+            ("responsible", self.responsible),
+        ]
+    }
+}
+
+/// Days before a work package starts or is due that an alert is sent, and
+/// days between reminders of overdue work packages. `None` turns an alert off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DateAlerts {
+    pub start_date: Option<u32>,
+    pub due_date: Option<u32>,
+    pub overdue: Option<u32>,
+}
+
+impl Default for DateAlerts {
+    fn default() -> Self {
+        Self {
+            start_date: Some(1),
+            due_date: Some(1),
+            overdue: None,
+        }
+    }
+}
+
+impl DateAlerts {
+    /// Lead times offered for start and due date alerts
+    pub const LEAD_DAYS: [u32; 4] = [0, 1, 3, 7];
+    /// Intervals offered for overdue reminders
+    pub const OVERDUE_DAYS: [u32; 3] = [1, 3, 7];
+}
+
+/// Notification settings of a user in one project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectNotificationSettings {
+    pub project_id: Id,
+    pub in_app: ReasonSettings,
+    pub email: ReasonSettings,
+    pub date_alerts: DateAlerts,
+}
+
+impl ProjectNotificationSettings {
+    /// Override starting from the user's global settings
+    pub fn inherit(project_id: Id, settings: &NotificationSettings) -> Self {
+        Self {
+            project_id,
+            in_app: settings.in_app_reasons,
+            email: settings.email_reasons,
+            date_alerts: settings.date_alerts,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -357,6 +734,56 @@ mod tests {
         assert!(!notification.is_unread());
     }
 
+    #[test]
+    fn test_snapshot_round_trip() {
+        let snapshot = NotificationSnapshot::new("Crash on save")
+            .with_type("Bug")
+            .with_project("Demo")
+            .with_comment("  Fixed in\n\nthe  next build ")
+            .with_change("status", Some("New".into()), Some("In Progress".into()));
+        let notification = Notification::work_package(
+            1,
+            NotificationType::WorkPackageUpdated,
+            NotificationReason::Watched,
+            123,
+        )
+        .with_snapshot(9, snapshot.clone());
+
+        assert_eq!(notification.journal_id, Some(9));
+        assert_eq!(snapshot.comment_excerpt.as_deref(), Some("Fixed in the next build"));
+        assert_eq!(snapshot.title(123, "Work Package"), "Bug #123: Crash on save");
+
+        let json = serde_json::to_value(&notification).unwrap();
+        assert_eq!(json["snapshot"]["changes"][0]["old"], "New");
+        let restored: Notification = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.snapshot, Some(snapshot));
+
+        // Notifications stored before snapshots existed
+        let mut old = serde_json::to_value(Notification::work_package(
+            1,
+            NotificationType::WorkPackageUpdated,
+            NotificationReason::Watched,
+            123,
+        ))
+        .unwrap();
+        assert!(old.get("snapshot").is_none());
+        old.as_object_mut().unwrap().remove("snapshot");
+        assert_eq!(serde_json::from_value::<Notification>(old).unwrap().snapshot, None);
+    }
+
+    #[test]
+    fn test_excerpt_truncates_long_comments() {
+        assert_eq!(excerpt(" \n ", 10), None);
+        assert_eq!(excerpt("short", 10).as_deref(), Some("short"));
+        assert_eq!(excerpt("exactly 10", 10).as_deref(), Some("exactly 10"));
+
+        let long = "Grüße ".repeat(1000);
+        let cut = NotificationSnapshot::new("Long").with_comment(&long).comment_excerpt.unwrap();
+        assert_eq!(cut.chars().count(), EXCERPT_MAX_CHARS);
+        assert!(cut.ends_with("Grü…"));
+        assert!(long.starts_with(cut.trim_end_matches('…')));
+    }
+
     #[test]
     fn test_notification_settings() {
         let settings = NotificationSettings::for_user(1);
@@ -374,6 +801,22 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_subscribed_to() {
+        let mut settings = NotificationSettings::for_user(1);
+        assert!(!settings.subscribed_to(NotificationType::DocumentAdded, 1));
+
+        settings.enabled_types.push(NotificationType::DocumentAdded);
+        assert!(settings.subscribed_to(NotificationType::DocumentAdded, 1));
+
+        settings.watched_projects = Some(vec![2]);
+        assert!(!settings.subscribed_to(NotificationType::DocumentAdded, 1));
+        assert!(settings.subscribed_to(NotificationType::DocumentAdded, 2));
+
+        settings.in_app_enabled = false;
+        assert!(!settings.subscribed_to(NotificationType::DocumentAdded, 2));
+    }
+
     #[test]
     fn test_project_filter() {
         let mut settings = NotificationSettings::for_user(1);
@@ -391,4 +834,39 @@ mod tests {
             Some(99),
         ));
     }
+
+    #[test]
+    fn test_project_override() {
+        let mut settings = NotificationSettings::for_user(1);
+        let mut quiet = ProjectNotificationSettings::inherit(5, &settings);
+        quiet.in_app.watched = false;
+        quiet.email.mentioned = false;
+        settings.project_overrides.push(quiet);
+
+        let watched = |project_id| {
+            settings.should_notify(NotificationType::WorkPackageUpdated, NotificationReason::Watched, project_id)
+        };
+        assert!(watched(Some(1)));
+        assert!(watched(None));
+        assert!(!watched(Some(5)));
+
+        assert!(settings.email_reason_enabled(NotificationReason::Mentioned, Some(1)));
+        assert!(!settings.email_reason_enabled(NotificationReason::Mentioned, Some(5)));
+        // Reasons outside the matrix are not affected
+        assert!(!settings.in_app_reason_enabled(NotificationReason::System, Some(5)));
+        assert_eq!(settings.date_alerts_for(Some(5)), DateAlerts::default());
+    }
+
+    #[test]
+    fn test_settings_stored_before_the_matrix() {
+        let mut json = serde_json::to_value(NotificationSettings::for_user(1)).unwrap();
+        for key in ["in_app_reasons", "email_reasons", "date_alerts", "project_overrides"] {
+            json.as_object_mut().unwrap().remove(key);
+        }
+
+        let settings: NotificationSettings = serde_json::from_value(json).unwrap();
+        assert_eq!(settings.in_app_reasons, ReasonSettings::default());
+        assert_eq!(settings.email_reasons, ReasonSettings::all_enabled());
+        assert!(settings.project_overrides.is_empty());
+    }
 }
@@ -0,0 +1,336 @@
+//! Recurring jobs
+//!
+//! Mirrors: config/initializers/good_job.rb (cron)
+//!
+//! The [`Scheduler`] enqueues jobs registered with a cron expression onto
+//! the [`JobQueue`] when they are due; it never runs them itself. The last
+//! run of each job is kept in a [`ScheduleStore`] and advanced with a
+//! compare-and-set, so neither a restarted scheduler nor a second instance
+//! sharing the store enqueues a run twice, and runs that passed while no
+//! scheduler was running are noticed. Whether those are made up for is the
+//! job's [`MisfirePolicy`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::cron::CronSchedule;
+use crate::jobs::{Job, JobQueue, JobResult};
+
+/// Metadata key of enqueued jobs holding the schedule name
+pub const SCHEDULED_JOB_METADATA_KEY: &str = "scheduled_job";
+
+/// Metadata key of enqueued jobs holding the run they were enqueued for
+pub const SCHEDULED_AT_METADATA_KEY: &str = "scheduled_at";
+
+/// Runs enqueued later than this after their time count as missed
+pub const DEFAULT_MISFIRE_GRACE: Duration = Duration::minutes(1);
+
+/// What happens to runs missed while no scheduler was running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MisfirePolicy {
+    /// Enqueue a single run for all of them
+    #[default]
+    RunOnce,
+    /// Drop them and wait for the next run
+    Skip,
+}
+
+/// A job enqueued on a cron schedule
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+    /// Key of the last run marker; the job type unless set
+    pub name: String,
+    pub schedule: CronSchedule,
+    pub job_type: String,
+    pub args: serde_json::Value,
+    pub queue: String,
+    pub misfire: MisfirePolicy,
+}
+
+impl ScheduledJob {
+    pub fn new(job_type: impl Into<String>, schedule: CronSchedule) -> Self {
+        let job_type = job_type.into();
+        Self {
+            name: job_type.clone(),
+            schedule,
+            job_type,
+            args: serde_json::json!({}),
+            queue: "default".to_string(),
+            misfire: MisfirePolicy::default(),
+        }
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn with_args(mut self, args: serde_json::Value) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_queue(mut self, queue: impl Into<String>) -> Self {
+        self.queue = queue.into();
+        self
+    }
+
+    pub fn with_misfire(mut self, misfire: MisfirePolicy) -> Self {
+        self.misfire = misfire;
+        self
+    }
+}
+
+/// Last run markers of scheduled jobs
+#[async_trait]
+pub trait ScheduleStore: Send + Sync {
+    /// When the job was last considered, if ever
+    async fn last_run(&self, name: &str) -> JobResult<Option<DateTime<Utc>>>;
+
+    /// Move the marker from `from` to `to`; false when it no longer is
+    /// `from` because another scheduler moved it first
+    async fn advance(&self, name: &str, from: Option<DateTime<Utc>>, to: DateTime<Utc>) -> JobResult<bool>;
+}
+
+/// In-memory schedule store for development/testing
+#[derive(Default)]
+pub struct MemoryScheduleStore {
+    runs: RwLock<HashMap<String, DateTime<Utc>>>,
+}
+
+impl MemoryScheduleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ScheduleStore for MemoryScheduleStore {
+    async fn last_run(&self, name: &str) -> JobResult<Option<DateTime<Utc>>> {
+        Ok(self.runs.read().await.get(name).copied())
+    }
+
+    async fn advance(&self, name: &str, from: Option<DateTime<Utc>>, to: DateTime<Utc>) -> JobResult<bool> {
+        let mut runs = self.runs.write().await;
+        if runs.get(name).copied() != from {
+            return Ok(false);
+        }
+        runs.insert(name.to_string(), to);
+        Ok(true)
+    }
+}
+
+/// Enqueues scheduled jobs when they are due
+pub struct Scheduler {
+    queue: Arc<dyn JobQueue>,
+    store: Arc<dyn ScheduleStore>,
+    timezone: Tz,
+    misfire_grace: Duration,
+    jobs: Vec<ScheduledJob>,
+}
+
+impl Scheduler {
+    /// Scheduler reading cron expressions in the wall-clock time of `timezone`
+    pub fn new(queue: Arc<dyn JobQueue>, store: Arc<dyn ScheduleStore>, timezone: Tz) -> Self {
+        Self {
+            queue,
+            store,
+            timezone,
+            misfire_grace: DEFAULT_MISFIRE_GRACE,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// How late a run may be enqueued before it counts as missed
+    pub fn with_misfire_grace(mut self, grace: Duration) -> Self {
+        self.misfire_grace = grace;
+        self
+    }
+
+    pub fn register(&mut self, job: ScheduledJob) {
+        self.jobs.push(job);
+    }
+
+    pub fn jobs(&self) -> &[ScheduledJob] {
+        &self.jobs
+    }
+
+    /// Enqueue the jobs due at `now`, returning the ids of the enqueued jobs
+    ///
+    /// A job seen for the first time is only due at its next run, so
+    /// registering one does not run it right away.
+    pub async fn tick(&self, now: DateTime<Utc>) -> JobResult<Vec<String>> {
+        let mut enqueued = Vec::new();
+
+        for job in &self.jobs {
+            let last_run = self.store.last_run(&job.name).await?;
+            let due = match last_run {
+                Some(last_run) => match job.schedule.next_after(last_run, self.timezone) {
+                    Some(due) if due <= now => Some(due),
+                    _ => continue,
+                },
+                None => None,
+            };
+
+            // Another scheduler got here first
+            if !self.store.advance(&job.name, last_run, now).await? {
+                continue;
+            }
+            let Some(due) = due else {
+                continue;
+            };
+
+            let missed = now - due > self.misfire_grace;
+            if missed && job.misfire == MisfirePolicy::Skip {
+                warn!(job = %job.name, scheduled_at = %due, "Skipping missed scheduled run");
+                continue;
+            }
+            if missed {
+                info!(job = %job.name, scheduled_at = %due, "Running missed scheduled job once");
+            }
+
+            let id = self
+                .queue
+                .enqueue(
+                    Job::new(job.job_type.clone(), job.args.clone())
+                        .queue(job.queue.clone())
+                        .with_metadata(SCHEDULED_JOB_METADATA_KEY, job.name.clone())
+                        .with_metadata(SCHEDULED_AT_METADATA_KEY, due.to_rfc3339()),
+                )
+                .await?;
+            enqueued.push(id);
+        }
+
+        Ok(enqueued)
+    }
+
+    /// Run the scheduler loop
+    pub async fn run(&self, shutdown: tokio::sync::watch::Receiver<bool>) {
+        loop {
+            if *shutdown.borrow() {
+                break;
+            }
+
+            if let Err(e) = self.tick(Utc::now()).await {
+                tracing::error!("Scheduler error: {}", e);
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{JobStatus, MemoryJobQueue};
+    use chrono_tz::Europe::Berlin;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn hourly(misfire: MisfirePolicy) -> ScheduledJob {
+        ScheduledJob::new("Test::HourlyJob", CronSchedule::parse("0 * * * *").unwrap()).with_misfire(misfire)
+    }
+
+    fn scheduler(queue: Arc<MemoryJobQueue>, store: Arc<MemoryScheduleStore>, job: ScheduledJob) -> Scheduler {
+        let mut scheduler = Scheduler::new(queue, store, Berlin);
+        scheduler.register(job);
+        scheduler
+    }
+
+    async fn pending(queue: &MemoryJobQueue) -> Vec<Job> {
+        queue.list("default", Some(JobStatus::Pending)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enqueues_due_jobs_once() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let store = Arc::new(MemoryScheduleStore::new());
+        let scheduler = scheduler(queue.clone(), store, hourly(MisfirePolicy::RunOnce));
+
+        // Registering does not run the job
+        assert!(scheduler.tick(utc("2024-05-01T10:10:00Z")).await.unwrap().is_empty());
+        assert!(scheduler.tick(utc("2024-05-01T10:59:59Z")).await.unwrap().is_empty());
+
+        let ids = scheduler.tick(utc("2024-05-01T11:00:01Z")).await.unwrap();
+        assert_eq!(ids.len(), 1);
+        assert!(scheduler.tick(utc("2024-05-01T11:00:02Z")).await.unwrap().is_empty());
+
+        let jobs = pending(&queue).await;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].job_type, "Test::HourlyJob");
+        assert_eq!(jobs[0].metadata[SCHEDULED_JOB_METADATA_KEY], "Test::HourlyJob");
+        assert_eq!(jobs[0].metadata[SCHEDULED_AT_METADATA_KEY], "2024-05-01T11:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_schedulers_sharing_a_store_enqueue_once() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let store = Arc::new(MemoryScheduleStore::new());
+        let first = scheduler(queue.clone(), store.clone(), hourly(MisfirePolicy::RunOnce));
+        let second = scheduler(queue.clone(), store, hourly(MisfirePolicy::RunOnce));
+
+        first.tick(utc("2024-05-01T10:10:00Z")).await.unwrap();
+        let now = utc("2024-05-01T11:00:00Z");
+        let enqueued = first.tick(now).await.unwrap().len() + second.tick(now).await.unwrap().len();
+
+        assert_eq!(enqueued, 1);
+        assert_eq!(pending(&queue).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_missed_runs_are_run_once_after_restart() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let store = Arc::new(MemoryScheduleStore::new());
+        scheduler(queue.clone(), store.clone(), hourly(MisfirePolicy::RunOnce))
+            .tick(utc("2024-05-01T10:10:00Z"))
+            .await
+            .unwrap();
+
+        // Down from 10:10 to 13:30, missing three runs
+        let restarted = scheduler(queue.clone(), store, hourly(MisfirePolicy::RunOnce));
+        assert_eq!(restarted.tick(utc("2024-05-01T13:30:00Z")).await.unwrap().len(), 1);
+        assert!(restarted.tick(utc("2024-05-01T13:59:00Z")).await.unwrap().is_empty());
+        assert_eq!(restarted.tick(utc("2024-05-01T14:00:00Z")).await.unwrap().len(), 1);
+
+        let jobs = pending(&queue).await;
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs
+            .iter()
+            .any(|job| job.metadata[SCHEDULED_AT_METADATA_KEY] == "2024-05-01T11:00:00+00:00"));
+    }
+
+    #[tokio::test]
+    async fn test_missed_runs_are_skipped_after_restart() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let store = Arc::new(MemoryScheduleStore::new());
+        scheduler(queue.clone(), store.clone(), hourly(MisfirePolicy::Skip))
+            .tick(utc("2024-05-01T10:10:00Z"))
+            .await
+            .unwrap();
+
+        let restarted = scheduler(queue.clone(), store, hourly(MisfirePolicy::Skip));
+        assert!(restarted.tick(utc("2024-05-01T13:30:00Z")).await.unwrap().is_empty());
+        assert_eq!(restarted.tick(utc("2024-05-01T14:00:10Z")).await.unwrap().len(), 1);
+        assert_eq!(pending(&queue).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_within_grace_is_not_missed() {
+        let queue = Arc::new(MemoryJobQueue::new());
+        let store = Arc::new(MemoryScheduleStore::new());
+        let scheduler = scheduler(queue.clone(), store, hourly(MisfirePolicy::Skip));
+
+        scheduler.tick(utc("2024-05-01T10:10:00Z")).await.unwrap();
+        assert_eq!(scheduler.tick(utc("2024-05-01T11:00:45Z")).await.unwrap().len(), 1);
+    }
+}
@@ -6,15 +6,24 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use op_core::metrics::DomainMetrics;
 use op_core::traits::Id;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
 
-use crate::channels::{Channel, ChannelDispatcher, DeliveryResult};
-use crate::email::{EmailRenderer, EmailSender};
-use crate::jobs::{Job, JobQueue};
+use crate::bulk::{BulkOperation, SummaryMailer, SuppressionWindow};
+use crate::channels::{
+    Channel, ChannelConfig, ChannelDispatcher, ChannelHandler, DeliveryResult, EmailChannel, InAppChannel,
+    Recipient,
+};
+use crate::email::{DigestBuilder, EmailRenderer, EmailSender, ProjectEmailSettings};
+use crate::jobs::JobQueue;
+use crate::stream::{NotificationStreams, StreamEventKind};
+use crate::throttle::EmailThrottle;
 use crate::notification::{
-    EmailFrequency, Notification, NotificationReason, NotificationSettings, NotificationType,
+    EmailFrequency, Notification, NotificationGroup, NotificationReason, NotificationSettings,
+    NotificationSnapshot, NotificationType,
 };
 
 /// Service errors
@@ -28,6 +37,8 @@ pub enum ServiceError {
     DeliveryError(String),
     #[error("Job queue error: {0}")]
     JobError(String),
+    #[error("Delivery deferred for {0} seconds")]
+    Deferred(i64),
 }
 
 pub type ServiceResult<T> = Result<T, ServiceError>;
@@ -46,6 +57,11 @@ pub trait NotificationStore: Send + Sync {
     /// Create a notification
     async fn create(&self, notification: &mut Notification) -> ServiceResult<Id>;
 
+    /// Create notifications in one operation, assigning their ids. Database
+    /// stores use a single multi-row `INSERT ... RETURNING id`. The returned
+    /// ids are in the order of the input.
+    async fn create_many(&self, notifications: &mut [Notification]) -> ServiceResult<Vec<Id>>;
+
     /// Get a notification by ID
     async fn get(&self, id: Id) -> ServiceResult<Option<Notification>>;
 
@@ -57,27 +73,138 @@ pub trait NotificationStore: Send + Sync {
         limit: usize,
     ) -> ServiceResult<Vec<Notification>>;
 
+    /// Get notifications for a user grouped by resource, newest group first
+    async fn get_for_user_grouped(
+        &self,
+        user_id: Id,
+        unread_only: bool,
+    ) -> ServiceResult<Vec<NotificationGroup>>;
+
+    /// Get a user's notifications about one resource, newest first
+    async fn get_group(
+        &self,
+        user_id: Id,
+        resource_type: &str,
+        resource_id: Id,
+        unread_only: bool,
+    ) -> ServiceResult<Vec<Notification>>;
+
+    /// Mark all of a user's notifications about one resource as read in a
+    /// single operation
+    async fn mark_group_read(
+        &self,
+        user_id: Id,
+        resource_type: &str,
+        resource_id: Id,
+    ) -> ServiceResult<usize>;
+
     /// Get user's notification settings
     async fn get_settings(&self, user_id: Id) -> ServiceResult<NotificationSettings>;
 
+    /// Replace a user's notification settings, project overrides included,
+    /// in one write so readers never see part of an update
+    async fn update_settings(&self, settings: &NotificationSettings) -> ServiceResult<()>;
+
     /// Update a notification
     async fn update(&self, notification: &Notification) -> ServiceResult<()>;
 
     /// Delete a notification
     async fn delete(&self, id: Id) -> ServiceResult<()>;
 
-    /// Mark all as read for a user
+    /// Mark notifications as read in a single update, returning the number
+    /// of notifications that were unread
+    async fn mark_read_many(&self, ids: &[Id]) -> ServiceResult<usize>;
+
+    /// Mark all as read for a user in a single update, returning the number
+    /// of notifications that were unread
     async fn mark_all_read(&self, user_id: Id) -> ServiceResult<usize>;
 
     /// Get unread count for a user
     async fn unread_count(&self, user_id: Id) -> ServiceResult<usize>;
 
-    /// Get pending email notifications for digest
+    /// Get pending email notifications for digest: those neither included
+    /// in a digest nor emailed immediately
     async fn get_pending_digest(
         &self,
         user_id: Id,
         frequency: EmailFrequency,
     ) -> ServiceResult<Vec<Notification>>;
+
+    /// Claim the pending digest notifications of a user, marking them as
+    /// included in a digest in the same operation, so that no other digest
+    /// run, on this or another worker, claims them as well. Database stores
+    /// claim in one `UPDATE ... RETURNING` skipping locked rows.
+    async fn claim_digest(
+        &self,
+        user_id: Id,
+        frequency: EmailFrequency,
+    ) -> ServiceResult<Vec<Notification>>;
+
+    /// Release claimed notifications whose digest could not be sent, for
+    /// the next run to claim again, returning the number released
+    async fn release_digest(&self, ids: &[Id]) -> ServiceResult<usize>;
+
+    /// Mark notifications as emailed immediately, leaving them out of
+    /// digests
+    async fn mark_alert_sent(&self, ids: &[Id]) -> ServiceResult<()>;
+}
+
+/// Source of the snapshots stored with notifications about a journal
+#[async_trait]
+pub trait JournalSnapshots: Send + Sync {
+    /// Snapshot of the journal and its work package, `None` if the journal
+    /// does not exist
+    async fn snapshot(&self, journal_id: Id) -> ServiceResult<Option<NotificationSnapshot>>;
+}
+
+/// Source of the email settings of projects
+#[async_trait]
+pub trait ProjectEmailSettingsSource: Send + Sync {
+    /// The project's email settings, `None` if it keeps the instance
+    /// defaults
+    async fn email_settings(&self, project_id: Id) -> ServiceResult<Option<ProjectEmailSettings>>;
+}
+
+/// In-memory project email settings for development/testing
+#[derive(Default)]
+pub struct MemoryProjectEmailSettings {
+    settings: RwLock<std::collections::HashMap<Id, ProjectEmailSettings>>,
+}
+
+impl MemoryProjectEmailSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the email settings of a project
+    pub async fn set(&self, project_id: Id, settings: ProjectEmailSettings) {
+        self.settings.write().await.insert(project_id, settings);
+    }
+}
+
+#[async_trait]
+impl ProjectEmailSettingsSource for MemoryProjectEmailSettings {
+    async fn email_settings(&self, project_id: Id) -> ServiceResult<Option<ProjectEmailSettings>> {
+        Ok(self.settings.read().await.get(&project_id).cloned())
+    }
+}
+
+/// Email settings of the notification's project. Failing to read them
+/// does not fail the email, which is then sent from the instance sender.
+pub(crate) async fn resolve_project_email_settings(
+    source: Option<&Arc<dyn ProjectEmailSettingsSource>>,
+    notification: &Notification,
+) -> Option<ProjectEmailSettings> {
+    let source = source?;
+    let project_id = notification.project_id?;
+
+    match source.email_settings(project_id).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            tracing::warn!(project_id, error = %e, "Failed to read project email settings");
+            None
+        }
+    }
 }
 
 /// In-memory notification store for development/testing
@@ -115,6 +242,20 @@ impl NotificationStore for MemoryNotificationStore {
         Ok(id)
     }
 
+    async fn create_many(&self, notifications: &mut [Notification]) -> ServiceResult<Vec<Id>> {
+        let mut stored = self.notifications.write().await;
+        let mut ids = Vec::with_capacity(notifications.len());
+
+        for notification in notifications.iter_mut() {
+            let id = self.next_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            notification.id = Some(id);
+            stored.push(notification.clone());
+            ids.push(id);
+        }
+
+        Ok(ids)
+    }
+
     async fn get(&self, id: Id) -> ServiceResult<Option<Notification>> {
         let notifications = self.notifications.read().await;
         Ok(notifications.iter().find(|n| n.id == Some(id)).cloned())
@@ -136,6 +277,64 @@ impl NotificationStore for MemoryNotificationStore {
             .collect())
     }
 
+    async fn get_for_user_grouped(
+        &self,
+        user_id: Id,
+        unread_only: bool,
+    ) -> ServiceResult<Vec<NotificationGroup>> {
+        let notifications = self.notifications.read().await;
+        Ok(NotificationGroup::group(
+            notifications
+                .iter()
+                .filter(|n| n.recipient_id == user_id)
+                .filter(|n| !unread_only || n.is_unread())
+                .cloned(),
+        ))
+    }
+
+    async fn get_group(
+        &self,
+        user_id: Id,
+        resource_type: &str,
+        resource_id: Id,
+        unread_only: bool,
+    ) -> ServiceResult<Vec<Notification>> {
+        let notifications = self.notifications.read().await;
+        let mut group: Vec<Notification> = notifications
+            .iter()
+            .filter(|n| n.recipient_id == user_id)
+            .filter(|n| n.resource_type == resource_type && n.resource_id == resource_id)
+            .filter(|n| !unread_only || n.is_unread())
+            .cloned()
+            .collect();
+        group.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        Ok(group)
+    }
+
+    async fn mark_group_read(
+        &self,
+        user_id: Id,
+        resource_type: &str,
+        resource_id: Id,
+    ) -> ServiceResult<usize> {
+        // The write lock is held for the whole group
+        let mut notifications = self.notifications.write().await;
+        let mut count = 0;
+
+        for notification in notifications.iter_mut() {
+            if notification.recipient_id == user_id
+                && notification.resource_type == resource_type
+                && notification.resource_id == resource_id
+                && notification.is_unread()
+            {
+                notification.mark_read();
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
     async fn get_settings(&self, user_id: Id) -> ServiceResult<NotificationSettings> {
         let settings = self.settings.read().await;
         Ok(settings
@@ -144,6 +343,12 @@ impl NotificationStore for MemoryNotificationStore {
             .unwrap_or_else(|| NotificationSettings::for_user(user_id)))
     }
 
+    async fn update_settings(&self, settings: &NotificationSettings) -> ServiceResult<()> {
+        let mut stored = self.settings.write().await;
+        stored.insert(settings.user_id, settings.clone());
+        Ok(())
+    }
+
     async fn update(&self, notification: &Notification) -> ServiceResult<()> {
         let mut notifications = self.notifications.write().await;
         if let Some(pos) = notifications.iter().position(|n| n.id == notification.id) {
@@ -158,6 +363,20 @@ impl NotificationStore for MemoryNotificationStore {
         Ok(())
     }
 
+    async fn mark_read_many(&self, ids: &[Id]) -> ServiceResult<usize> {
+        let mut notifications = self.notifications.write().await;
+        let mut count = 0;
+
+        for notification in notifications.iter_mut() {
+            if notification.id.is_some_and(|id| ids.contains(&id)) && notification.is_unread() {
+                notification.mark_read();
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
     async fn mark_all_read(&self, user_id: Id) -> ServiceResult<usize> {
         let mut notifications = self.notifications.write().await;
         let mut count = 0;
@@ -188,12 +407,63 @@ impl NotificationStore for MemoryNotificationStore {
         let notifications = self.notifications.read().await;
         Ok(notifications
             .iter()
-            .filter(|n| n.recipient_id == user_id && !n.is_mail_sent())
+            .filter(|n| n.recipient_id == user_id && n.is_digest_pending())
             .cloned()
             .collect())
     }
+
+    async fn claim_digest(
+        &self,
+        user_id: Id,
+        _frequency: EmailFrequency,
+    ) -> ServiceResult<Vec<Notification>> {
+        let mut notifications = self.notifications.write().await;
+        let mut claimed = Vec::new();
+
+        for notification in notifications.iter_mut() {
+            if notification.recipient_id == user_id && notification.is_digest_pending() {
+                notification.mail_reminder_sent = true;
+                notification.updated_at = Utc::now();
+                claimed.push(notification.clone());
+            }
+        }
+
+        Ok(claimed)
+    }
+
+    async fn release_digest(&self, ids: &[Id]) -> ServiceResult<usize> {
+        let mut notifications = self.notifications.write().await;
+        let mut count = 0;
+
+        for notification in notifications.iter_mut() {
+            if notification.id.is_some_and(|id| ids.contains(&id)) && notification.mail_reminder_sent {
+                notification.mail_reminder_sent = false;
+                notification.updated_at = Utc::now();
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    async fn mark_alert_sent(&self, ids: &[Id]) -> ServiceResult<()> {
+        let mut notifications = self.notifications.write().await;
+        for notification in notifications.iter_mut() {
+            if notification.id.is_some_and(|id| ids.contains(&id)) {
+                notification.mail_alert_sent = true;
+                notification.updated_at = Utc::now();
+            }
+        }
+        Ok(())
+    }
 }
 
+/// Maximum number of notifications delivered to channels concurrently
+pub const DELIVERY_CONCURRENCY: usize = 16;
+
+/// Seconds after which emails held back by a deferral are retried
+pub const EMAIL_DEFERRAL_SECS: i64 = 60;
+
 /// Notification service
 pub struct NotificationService<S: NotificationStore, Q: JobQueue, E: EmailSender> {
     store: Arc<S>,
@@ -201,6 +471,12 @@ pub struct NotificationService<S: NotificationStore, Q: JobQueue, E: EmailSender
     email_sender: Arc<E>,
     dispatcher: ChannelDispatcher,
     email_renderer: EmailRenderer,
+    metrics: Option<Arc<DomainMetrics>>,
+    streams: Option<Arc<NotificationStreams>>,
+    email_throttle: Option<Arc<EmailThrottle>>,
+    email_deferral: Option<watch::Receiver<bool>>,
+    snapshots: Option<Arc<dyn JournalSnapshots>>,
+    project_email_settings: Option<Arc<dyn ProjectEmailSettingsSource>>,
 }
 
 impl<S: NotificationStore, Q: JobQueue, E: EmailSender> NotificationService<S, Q, E> {
@@ -209,16 +485,172 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> NotificationService<S, Q
         job_queue: Arc<Q>,
         email_sender: Arc<E>,
         email_renderer: EmailRenderer,
-    ) -> Self {
+    ) -> Self
+    where
+        S: 'static,
+        Q: 'static,
+        E: 'static,
+    {
+        let mut dispatcher = ChannelDispatcher::new();
+        dispatcher.add_handler(InAppChannel::new(store.clone()));
+        dispatcher.add_handler(EmailChannel::new(
+            email_sender.clone(),
+            job_queue.clone(),
+            email_renderer.clone(),
+        ));
+
         Self {
             store,
             job_queue,
             email_sender,
-            dispatcher: ChannelDispatcher::new().with_defaults(),
+            dispatcher,
             email_renderer,
+            metrics: None,
+            streams: None,
+            email_throttle: None,
+            email_deferral: None,
+            snapshots: None,
+            project_email_settings: None,
         }
     }
 
+    /// Mailer sending the summaries of bulk operations through this
+    /// service's sender and renderer
+    pub fn summary_mailer(&self) -> SummaryMailer
+    where
+        E: 'static,
+    {
+        SummaryMailer::new(self.email_sender.clone(), self.email_renderer.clone())
+    }
+
+    /// Queue receiving the email send and retry jobs, for the worker
+    /// running them
+    pub fn job_queue(&self) -> &Arc<Q> {
+        &self.job_queue
+    }
+
+    /// Deliver notifications on an additional channel, e.g. a webhook
+    pub fn with_channel<H: ChannelHandler + 'static>(mut self, config: ChannelConfig, handler: H) -> Self {
+        self.dispatcher.add_channel(config, handler);
+        self
+    }
+
+    /// Record created notifications and sent emails in the given metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<DomainMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Publish created and read notifications to the recipients' event streams
+    pub fn with_streams(mut self, streams: Arc<NotificationStreams>) -> Self {
+        self.streams = Some(streams);
+        self
+    }
+
+    /// Keep emails sent by the email channel and [`send_email`](Self::send_email)
+    /// within the throttle's limits
+    pub fn with_email_throttle(mut self, throttle: Arc<EmailThrottle>) -> Self
+    where
+        Q: 'static,
+        E: 'static,
+    {
+        self.email_throttle = Some(throttle);
+        self.dispatcher.replace_handler(self.email_channel());
+        self
+    }
+
+    /// Send emails about a project's notifications from the project's
+    /// sender, if it overrides the instance one
+    pub fn with_project_email_settings(mut self, settings: Arc<dyn ProjectEmailSettingsSource>) -> Self
+    where
+        Q: 'static,
+        E: 'static,
+    {
+        self.project_email_settings = Some(settings);
+        self.dispatcher.replace_handler(self.email_channel());
+        self
+    }
+
+    /// Email channel sending through this service's sender, throttle and
+    /// project settings
+    fn email_channel(&self) -> EmailChannel<E, Q> {
+        let mut channel =
+            EmailChannel::new(self.email_sender.clone(), self.job_queue.clone(), self.email_renderer.clone());
+        if let Some(ref throttle) = self.email_throttle {
+            channel = channel.with_throttle(throttle.clone());
+        }
+        if let Some(ref settings) = self.project_email_settings {
+            channel = channel.with_project_settings(settings.clone());
+        }
+        channel
+    }
+
+    /// Defer [`send_email`](Self::send_email) while the flag is set, e.g.
+    /// while the email health check is degraded
+    pub fn with_email_deferral(mut self, deferral: watch::Receiver<bool>) -> Self {
+        self.email_deferral = Some(deferral);
+        self
+    }
+
+    /// Capture a snapshot of the journal of notifications that have none,
+    /// so that they render without further lookups
+    pub fn with_snapshots(mut self, snapshots: Arc<dyn JournalSnapshots>) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    /// Snapshot for a template about a journal. Failing to capture one
+    /// does not fail the notification, which then renders generically.
+    async fn capture_snapshot(&self, template: &Notification) -> Option<NotificationSnapshot> {
+        let snapshots = self.snapshots.as_ref()?;
+        let journal_id = template.journal_id?;
+
+        match snapshots.snapshot(journal_id).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::warn!(journal_id, error = %e, "Failed to capture notification snapshot");
+                None
+            }
+        }
+    }
+
+    /// Publish an event with the user's new unread count. Failing to read
+    /// the count does not fail the change that is published.
+    async fn publish(&self, user_id: Id, kind: StreamEventKind, notification_ids: Vec<Id>) {
+        let Some(ref streams) = self.streams else {
+            return;
+        };
+        if let Err(e) = streams
+            .publish_from(self.store.as_ref(), user_id, kind, notification_ids)
+            .await
+        {
+            tracing::warn!(user_id, error = %e, "Failed to publish notification event");
+        }
+    }
+
+    /// Publish read events for notifications, grouped by recipient
+    async fn publish_read(&self, notification_ids: &[Id]) -> ServiceResult<()> {
+        if self.streams.is_none() {
+            return Ok(());
+        }
+
+        let mut by_recipient: Vec<(Id, Vec<Id>)> = Vec::new();
+        for &id in notification_ids {
+            let Some(notification) = self.store.get(id).await? else {
+                continue;
+            };
+            match by_recipient.iter_mut().find(|(user_id, _)| *user_id == notification.recipient_id) {
+                Some((_, ids)) => ids.push(id),
+                None => by_recipient.push((notification.recipient_id, vec![id])),
+            }
+        }
+
+        for (user_id, ids) in by_recipient {
+            self.publish(user_id, StreamEventKind::Read, ids).await;
+        }
+        Ok(())
+    }
+
     /// Create and send a notification
     pub async fn notify(
         &self,
@@ -230,16 +662,7 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> NotificationService<S, Q
         actor_id: Option<Id>,
         project_id: Option<Id>,
     ) -> ServiceResult<NotificationEvent> {
-        // Get user settings
-        let settings = self.store.get_settings(recipient_id).await?;
-
-        // Check if notification should be sent
-        if !settings.should_notify(notification_type, reason, project_id) {
-            return Err(ServiceError::DeliveryError("User has disabled this notification type".into()));
-        }
-
-        // Create notification
-        let mut notification = Notification::new(
+        let mut template = Notification::new(
             recipient_id,
             notification_type,
             reason,
@@ -248,48 +671,175 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> NotificationService<S, Q
         );
 
         if let Some(aid) = actor_id {
-            notification = notification.with_actor(aid);
+            template = template.with_actor(aid);
         }
 
         if let Some(pid) = project_id {
-            notification = notification.with_project(pid);
+            template = template.with_project(pid);
         }
 
-        // Store notification
-        let id = self.store.create(&mut notification).await?;
-        notification.id = Some(id);
+        self.notify_many(&[recipient_id], &template)
+            .await?
+            .pop()
+            .ok_or_else(|| ServiceError::DeliveryError("User has disabled this notification type".into()))
+    }
 
-        // Deliver to channels
-        let delivery_results = self.dispatcher.deliver_all(&notification).await;
+    /// Create and send a notification to many recipients
+    ///
+    /// The recipients are filtered by their settings first, then all
+    /// notifications are stored with a single `create_many`. Each
+    /// notification is then dispatched to its channels, at most
+    /// [`DELIVERY_CONCURRENCY`] notifications at a time, and the per-channel
+    /// results are recorded in the event. Events are returned in recipient order; recipients who disabled
+    /// the notification are left out. The template's recipient is ignored.
+    ///
+    /// A template about a journal without a snapshot gets one captured once
+    /// for all recipients.
+    pub async fn notify_many(
+        &self,
+        recipient_ids: &[Id],
+        template: &Notification,
+    ) -> ServiceResult<Vec<NotificationEvent>> {
+        let mut notifications = Vec::with_capacity(recipient_ids.len());
+        let mut recipients = Vec::with_capacity(recipient_ids.len());
+
+        for &recipient_id in recipient_ids {
+            let settings = self.store.get_settings(recipient_id).await?;
+            if !settings.should_notify(template.notification_type, template.reason, template.project_id) {
+                continue;
+            }
+
+            let mut notification = template.clone();
+            notification.recipient_id = recipient_id;
+            notifications.push(notification);
+            recipients.push(Recipient::new(settings));
+        }
+
+        if notifications.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if template.snapshot.is_none() {
+            if let Some(snapshot) = self.capture_snapshot(template).await {
+                for notification in &mut notifications {
+                    notification.snapshot = Some(snapshot.clone());
+                }
+            }
+        }
+
+        // Store notifications
+        self.store.create_many(&mut notifications).await?;
+
+        if let Some(ref metrics) = self.metrics {
+            for _ in &notifications {
+                metrics.record_notification_created();
+            }
+        }
 
-        // Queue email if needed
-        if settings.should_email() && settings.email_frequency == EmailFrequency::Immediate {
-            self.queue_email(&notification).await?;
+        for notification in &notifications {
+            self.publish(
+                notification.recipient_id,
+                StreamEventKind::Created,
+                notification.id.into_iter().collect(),
+            )
+            .await;
         }
 
-        Ok(NotificationEvent {
-            notification,
-            delivery_results,
-            timestamp: Utc::now(),
-        })
+        // Deliver to channels
+        let delivery_results: Vec<Vec<DeliveryResult>> = stream::iter(notifications.iter().zip(&recipients))
+            .map(|(notification, recipient)| self.dispatcher.deliver_all(notification, recipient))
+            .buffered(DELIVERY_CONCURRENCY)
+            .collect()
+            .await;
+
+        // Notifications emailed right away are left out of digests
+        let alerted: Vec<Id> = notifications
+            .iter()
+            .zip(&delivery_results)
+            .filter(|(_, results)| results.iter().any(|r| r.channel == Channel::Email && r.is_sent()))
+            .filter_map(|(notification, _)| notification.id)
+            .collect();
+        if !alerted.is_empty() {
+            self.store.mark_alert_sent(&alerted).await?;
+        }
+
+        let timestamp = Utc::now();
+        Ok(notifications
+            .into_iter()
+            .zip(delivery_results)
+            .map(|(notification, delivery_results)| NotificationEvent {
+                notification,
+                delivery_results,
+                timestamp,
+            })
+            .collect())
     }
 
-    /// Queue an email for delivery
-    async fn queue_email(&self, notification: &Notification) -> ServiceResult<()> {
-        let job = Job::new(
-            "send_notification_email",
-            serde_json::json!({
-                "notification_id": notification.id,
-            }),
+    /// Render and send the email for a notification in the recipient's
+    /// language, marking it as mailed
+    ///
+    /// Fails with [`ServiceError::Deferred`] when the email throttle or the
+    /// email deferral holds the send back; the caller retries after the
+    /// given delay.
+    pub async fn send_email(
+        &self,
+        notification_id: Id,
+        recipient_email: &str,
+        recipient_name: Option<&str>,
+        recipient_language: Option<&str>,
+    ) -> ServiceResult<String> {
+        let mut notification = self
+            .store
+            .get(notification_id)
+            .await?
+            .ok_or(ServiceError::NotFound(notification_id))?;
+
+        let project = resolve_project_email_settings(self.project_email_settings.as_ref(), &notification).await;
+        let message = self.email_renderer.render_for_project(
+            &notification,
+            recipient_email,
+            recipient_name,
+            recipient_language,
+            project.as_ref(),
         )
-        .queue("mailers");
+        .map_err(|e| ServiceError::DeliveryError(e.to_string()))?;
 
-        self.job_queue
-            .enqueue(job)
-            .await
-            .map_err(|e| ServiceError::JobError(e.to_string()))?;
+        if self.email_deferral.as_ref().is_some_and(|deferral| *deferral.borrow()) {
+            return Err(ServiceError::Deferred(EMAIL_DEFERRAL_SECS));
+        }
 
-        Ok(())
+        if let Some(ref throttle) = self.email_throttle {
+            throttle
+                .acquire(recipient_email)
+                .map_err(|deferral| ServiceError::Deferred(deferral.retry_in_secs()))?;
+        }
+
+        let sender_type = self.email_sender.sender_type();
+        let message_id = match self.email_sender.send(&message).await {
+            Ok(id) => {
+                if let Some(ref metrics) = self.metrics {
+                    metrics.record_email_sent(sender_type);
+                }
+                if let Some(ref throttle) = self.email_throttle {
+                    throttle.record_success();
+                }
+                id
+            }
+            Err(e) => {
+                if let Some(ref metrics) = self.metrics {
+                    metrics.record_email_failed(sender_type);
+                }
+                if let Some(ref throttle) = self.email_throttle {
+                    throttle.record_failure(&e).await;
+                }
+                return Err(ServiceError::DeliveryError(e.to_string()));
+            }
+        };
+
+        notification.mark_mail_sent();
+        self.store.update(&notification).await?;
+
+        Ok(message_id)
     }
 
     /// Get notifications for a user
@@ -302,6 +852,56 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> NotificationService<S, Q
         self.store.get_for_user(user_id, unread_only, limit).await
     }
 
+    /// Get notifications grouped by resource for the notification center
+    pub async fn get_notification_groups(
+        &self,
+        user_id: Id,
+        unread_only: bool,
+    ) -> ServiceResult<Vec<NotificationGroup>> {
+        self.store.get_for_user_grouped(user_id, unread_only).await
+    }
+
+    /// Get the notifications of one group
+    pub async fn get_group_details(
+        &self,
+        user_id: Id,
+        resource_type: &str,
+        resource_id: Id,
+        unread_only: bool,
+    ) -> ServiceResult<Vec<Notification>> {
+        self.store
+            .get_group(user_id, resource_type, resource_id, unread_only)
+            .await
+    }
+
+    /// Mark all notifications of a group as read
+    pub async fn mark_group_read(
+        &self,
+        user_id: Id,
+        resource_type: &str,
+        resource_id: Id,
+    ) -> ServiceResult<usize> {
+        let unread_ids: Vec<Id> = if self.streams.is_some() {
+            self.store
+                .get_group(user_id, resource_type, resource_id, true)
+                .await?
+                .into_iter()
+                .filter_map(|n| n.id)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let marked = self
+            .store
+            .mark_group_read(user_id, resource_type, resource_id)
+            .await?;
+        if marked > 0 {
+            self.publish(user_id, StreamEventKind::Read, unread_ids).await;
+        }
+        Ok(marked)
+    }
+
     /// Get unread count
     pub async fn unread_count(&self, user_id: Id) -> ServiceResult<usize> {
         self.store.unread_count(user_id).await
@@ -316,17 +916,101 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> NotificationService<S, Q
             .ok_or(ServiceError::NotFound(notification_id))?;
 
         notification.mark_read();
-        self.store.update(&notification).await
+        self.store.update(&notification).await?;
+        self.publish(notification.recipient_id, StreamEventKind::Read, vec![notification_id])
+            .await;
+        Ok(())
+    }
+
+    /// Mark notifications as read
+    pub async fn mark_read_many(&self, notification_ids: &[Id]) -> ServiceResult<usize> {
+        let marked = self.store.mark_read_many(notification_ids).await?;
+        if marked > 0 {
+            self.publish_read(notification_ids).await?;
+        }
+        Ok(marked)
     }
 
     /// Mark all notifications as read
     pub async fn mark_all_read(&self, user_id: Id) -> ServiceResult<usize> {
-        self.store.mark_all_read(user_id).await
+        let marked = self.store.mark_all_read(user_id).await?;
+        self.publish(user_id, StreamEventKind::AllRead, Vec::new()).await;
+        Ok(marked)
     }
 
     /// Delete a notification
     pub async fn delete(&self, notification_id: Id) -> ServiceResult<()> {
-        self.store.delete(notification_id).await
+        let recipient_id = match self.streams {
+            Some(_) => self.store.get(notification_id).await?.map(|n| n.recipient_id),
+            None => None,
+        };
+
+        self.store.delete(notification_id).await?;
+        if let Some(user_id) = recipient_id {
+            self.publish(user_id, StreamEventKind::Count, Vec::new()).await;
+        }
+        Ok(())
+    }
+
+    /// Send a user's digest of the notifications pending for it, returning
+    /// the number of notifications in the digest
+    ///
+    /// The notifications are claimed before the digest is sent, so a
+    /// digest run that crashes or runs on several workers never sends them
+    /// twice. If the digest cannot be sent they are released again for the
+    /// next run.
+    pub async fn send_digest(
+        &self,
+        user_id: Id,
+        frequency: EmailFrequency,
+        recipient_email: &str,
+        recipient_name: Option<&str>,
+        recipient_language: Option<&str>,
+    ) -> ServiceResult<usize> {
+        let period = match frequency {
+            EmailFrequency::Daily => "daily",
+            EmailFrequency::Weekly => "weekly",
+            EmailFrequency::Immediate | EmailFrequency::Never => return Ok(0),
+        };
+
+        let claimed = self.store.claim_digest(user_id, frequency).await?;
+        if claimed.is_empty() {
+            return Ok(0);
+        }
+        let ids: Vec<Id> = claimed.iter().filter_map(|n| n.id).collect();
+        let count = claimed.len();
+
+        let mut digest = DigestBuilder::new(self.email_renderer.clone());
+        if let Some(language) = recipient_language {
+            digest = digest.with_language(language);
+        }
+        for notification in claimed {
+            digest.add(notification);
+        }
+
+        let sender_type = self.email_sender.sender_type();
+        let sent = match digest.build(recipient_email, recipient_name, period) {
+            Ok(Some(message)) => self.email_sender.send(&message).await.map(|_| ()),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        match sent {
+            Ok(()) => {
+                if let Some(ref metrics) = self.metrics {
+                    metrics.record_email_sent(sender_type);
+                }
+                Ok(count)
+            }
+            Err(e) => {
+                if let Some(ref metrics) = self.metrics {
+                    metrics.record_email_failed(sender_type);
+                }
+                // Leaving them claimed would drop them from every digest
+                self.store.release_digest(&ids).await?;
+                Err(ServiceError::DeliveryError(e.to_string()))
+            }
+        }
     }
 
     /// Send daily digest emails
@@ -345,11 +1029,19 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> NotificationService<S, Q
 /// Work package notification helper
 pub struct WorkPackageNotifier<S: NotificationStore, Q: JobQueue, E: EmailSender> {
     service: Arc<NotificationService<S, Q, E>>,
+    suppression: Option<(Arc<SuppressionWindow>, BulkOperation)>,
 }
 
 impl<S: NotificationStore, Q: JobQueue, E: EmailSender> WorkPackageNotifier<S, Q, E> {
     pub fn new(service: Arc<NotificationService<S, Q, E>>) -> Self {
-        Self { service }
+        Self { service, suppression: None }
+    }
+
+    /// Record notifications in the window of a bulk operation instead of
+    /// sending them; no events are returned for recorded notifications
+    pub fn suppressing(mut self, window: Arc<SuppressionWindow>, operation: BulkOperation) -> Self {
+        self.suppression = Some((window, operation));
+        self
     }
 
     /// Notify about work package creation
@@ -359,28 +1051,37 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> WorkPackageNotifier<S, Q
         project_id: Id,
         author_id: Id,
         watchers: Vec<Id>,
-    ) -> Vec<ServiceResult<NotificationEvent>> {
-        let mut results = Vec::new();
-
-        for watcher_id in watchers {
-            if watcher_id != author_id {
-                let result = self
-                    .service
-                    .notify(
-                        watcher_id,
-                        NotificationType::WorkPackageCreated,
-                        NotificationReason::Watched,
-                        "WorkPackage",
-                        work_package_id,
-                        Some(author_id),
-                        Some(project_id),
-                    )
-                    .await;
-                results.push(result);
-            }
-        }
+    ) -> ServiceResult<Vec<NotificationEvent>> {
+        self.fan_out(
+            NotificationType::WorkPackageCreated,
+            NotificationReason::Watched,
+            work_package_id,
+            project_id,
+            author_id,
+            &watchers,
+        )
+        .await
+    }
 
-        results
+    /// Notify the watchers of a work package about an update. Watchers are
+    /// read at the time of the update, so users who stopped watching receive
+    /// no further notifications while keeping the ones they already have.
+    pub async fn on_updated(
+        &self,
+        work_package_id: Id,
+        project_id: Id,
+        actor_id: Id,
+        watchers: Vec<Id>,
+    ) -> ServiceResult<Vec<NotificationEvent>> {
+        self.fan_out(
+            NotificationType::WorkPackageUpdated,
+            NotificationReason::Watched,
+            work_package_id,
+            project_id,
+            actor_id,
+            &watchers,
+        )
+        .await
     }
 
     /// Notify about work package assignment
@@ -390,7 +1091,12 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> WorkPackageNotifier<S, Q
         project_id: Id,
         actor_id: Id,
         assignee_id: Id,
-    ) -> ServiceResult<NotificationEvent> {
+    ) -> ServiceResult<Option<NotificationEvent>> {
+        if let Some((ref window, operation)) = self.suppression {
+            window.record(operation, actor_id, project_id, work_package_id, &[assignee_id]);
+            return Ok(None);
+        }
+
         self.service
             .notify(
                 assignee_id,
@@ -402,6 +1108,7 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> WorkPackageNotifier<S, Q
                 Some(project_id),
             )
             .await
+            .map(Some)
     }
 
     /// Notify about mentions in a work package
@@ -411,41 +1118,240 @@ impl<S: NotificationStore, Q: JobQueue, E: EmailSender> WorkPackageNotifier<S, Q
         project_id: Id,
         actor_id: Id,
         mentioned_user_ids: Vec<Id>,
-    ) -> Vec<ServiceResult<NotificationEvent>> {
-        let mut results = Vec::new();
-
-        for user_id in mentioned_user_ids {
-            if user_id != actor_id {
-                let result = self
-                    .service
-                    .notify(
-                        user_id,
-                        NotificationType::WorkPackageMentioned,
-                        NotificationReason::Mentioned,
-                        "WorkPackage",
-                        work_package_id,
-                        Some(actor_id),
-                        Some(project_id),
-                    )
-                    .await;
-                results.push(result);
-            }
+    ) -> ServiceResult<Vec<NotificationEvent>> {
+        self.fan_out(
+            NotificationType::WorkPackageMentioned,
+            NotificationReason::Mentioned,
+            work_package_id,
+            project_id,
+            actor_id,
+            &mentioned_user_ids,
+        )
+        .await
+    }
+
+    /// Notify the watchers of a work package about it being moved to the
+    /// trash
+    pub async fn on_trashed(
+        &self,
+        work_package_id: Id,
+        project_id: Id,
+        actor_id: Id,
+        watchers: Vec<Id>,
+    ) -> ServiceResult<Vec<NotificationEvent>> {
+        self.fan_out(
+            NotificationType::WorkPackageTrashed,
+            NotificationReason::Watched,
+            work_package_id,
+            project_id,
+            actor_id,
+            &watchers,
+        )
+        .await
+    }
+
+    /// Notify the former watchers of a work package about it being deleted
+    /// permanently. The actor is whoever moved it to the trash.
+    pub async fn on_purged(
+        &self,
+        work_package_id: Id,
+        project_id: Id,
+        actor_id: Id,
+        watchers: Vec<Id>,
+    ) -> ServiceResult<Vec<NotificationEvent>> {
+        self.fan_out(
+            NotificationType::WorkPackagePurged,
+            NotificationReason::Watched,
+            work_package_id,
+            project_id,
+            actor_id,
+            &watchers,
+        )
+        .await
+    }
+
+    /// Notify all recipients but the actor with one batch
+    async fn fan_out(
+        &self,
+        notification_type: NotificationType,
+        reason: NotificationReason,
+        work_package_id: Id,
+        project_id: Id,
+        actor_id: Id,
+        recipients: &[Id],
+    ) -> ServiceResult<Vec<NotificationEvent>> {
+        if let Some((ref window, operation)) = self.suppression {
+            window.record(operation, actor_id, project_id, work_package_id, recipients);
+            return Ok(Vec::new());
         }
 
-        results
+        let recipients: Vec<Id> = recipients
+            .iter()
+            .copied()
+            .filter(|&id| id != actor_id)
+            .collect();
+        let template = Notification::work_package(actor_id, notification_type, reason, work_package_id)
+            .with_actor(actor_id)
+            .with_project(project_id);
+
+        self.service.notify_many(&recipients, &template).await
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::email::ConsoleEmailSender;
+    use crate::email::{ConsoleEmailSender, EmailAddress};
+    use crate::channels::Channel;
     use crate::jobs::MemoryJobQueue;
+    use crate::notification::ProjectNotificationSettings;
 
     fn create_test_store() -> Arc<MemoryNotificationStore> {
         Arc::new(MemoryNotificationStore::new())
     }
 
+    // Store contract tests, generic so every `NotificationStore`
+    // implementation runs the same assertions
+
+    async fn create_at(
+        store: &impl NotificationStore,
+        recipient_id: Id,
+        reason: NotificationReason,
+        work_package_id: Id,
+        minutes_ago: i64,
+    ) {
+        let mut notification = Notification::work_package(
+            recipient_id,
+            NotificationType::WorkPackageUpdated,
+            reason,
+            work_package_id,
+        );
+        notification.created_at = Utc::now() - chrono::Duration::minutes(minutes_ago);
+        store.create(&mut notification).await.unwrap();
+    }
+
+    async fn seed_groups(store: &impl NotificationStore) {
+        create_at(store, 1, NotificationReason::Watched, 100, 30).await;
+        create_at(store, 1, NotificationReason::Mentioned, 100, 20).await;
+        create_at(store, 1, NotificationReason::Watched, 100, 10).await;
+        create_at(store, 1, NotificationReason::Assigned, 200, 15).await;
+        create_at(store, 2, NotificationReason::Watched, 100, 5).await;
+    }
+
+    async fn assert_groups_by_resource(store: &impl NotificationStore) {
+        seed_groups(store).await;
+
+        let groups = store.get_for_user_grouped(1, false).await.unwrap();
+        assert_eq!(groups.len(), 2);
+
+        assert_eq!(groups[0].resource_id, 100);
+        assert_eq!(groups[0].count, 3);
+        assert_eq!(
+            groups[0].reasons,
+            vec![NotificationReason::Watched, NotificationReason::Mentioned]
+        );
+        assert_eq!(groups[0].newest.reason, NotificationReason::Watched);
+        assert_eq!(groups[0].newest.recipient_id, 1);
+
+        assert_eq!(groups[1].resource_id, 200);
+        assert_eq!(groups[1].count, 1);
+
+        let details = store.get_group(1, "WorkPackage", 100, false).await.unwrap();
+        assert_eq!(details.len(), 3);
+        assert!(details.windows(2).all(|w| w[0].created_at >= w[1].created_at));
+    }
+
+    async fn assert_group_marked_read(store: &impl NotificationStore) {
+        seed_groups(store).await;
+
+        let marked = store.mark_group_read(1, "WorkPackage", 100).await.unwrap();
+        assert_eq!(marked, 3);
+        assert_eq!(store.unread_count(1).await.unwrap(), 1);
+        // Other recipients' notifications about the resource stay unread
+        assert_eq!(store.unread_count(2).await.unwrap(), 1);
+
+        let groups = store.get_for_user_grouped(1, true).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].resource_id, 200);
+
+        let groups = store.get_for_user_grouped(1, false).await.unwrap();
+        assert_eq!(groups.len(), 2);
+    }
+
+    async fn assert_create_many_keeps_input_order(store: &impl NotificationStore) {
+        let mut notifications: Vec<Notification> = [5, 3, 9, 1]
+            .into_iter()
+            .map(|recipient| {
+                Notification::work_package(recipient, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 42)
+            })
+            .collect();
+
+        let ids = store.create_many(&mut notifications).await.unwrap();
+        assert_eq!(ids.len(), 4);
+        for (id, notification) in ids.iter().zip(&notifications) {
+            assert_eq!(notification.id, Some(*id));
+            let stored = store.get(*id).await.unwrap().unwrap();
+            assert_eq!(stored.recipient_id, notification.recipient_id);
+        }
+
+        assert!(store.create_many(&mut []).await.unwrap().is_empty());
+    }
+
+    async fn assert_read_many_counts_unread(store: &impl NotificationStore) {
+        let mut notifications: Vec<Notification> = (0..3)
+            .map(|_| Notification::work_package(1, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 42))
+            .collect();
+        let ids = store.create_many(&mut notifications).await.unwrap();
+
+        assert_eq!(store.mark_read_many(&ids[..2]).await.unwrap(), 2);
+        // Already read notifications are not counted again
+        assert_eq!(store.mark_read_many(&ids).await.unwrap(), 1);
+        assert_eq!(store.mark_all_read(1).await.unwrap(), 0);
+        assert_eq!(store.unread_count(1).await.unwrap(), 0);
+    }
+
+    async fn assert_digest_claimed_once(store: &impl NotificationStore) {
+        let template = Notification::work_package(1, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 42);
+        let mut notifications = vec![template; 3];
+        let ids = store.create_many(&mut notifications).await.unwrap();
+        store.mark_alert_sent(&ids[2..]).await.unwrap();
+
+        let claimed = store.claim_digest(1, EmailFrequency::Daily).await.unwrap();
+        assert_eq!(claimed.iter().map(|n| n.id.unwrap()).collect::<Vec<_>>(), ids[..2]);
+        assert!(claimed.iter().all(|n| n.mail_reminder_sent));
+        // A second run, e.g. on another worker, finds nothing left
+        assert!(store.claim_digest(1, EmailFrequency::Daily).await.unwrap().is_empty());
+        assert!(store.get_pending_digest(1, EmailFrequency::Daily).await.unwrap().is_empty());
+
+        assert_eq!(store.release_digest(&ids).await.unwrap(), 2);
+        assert_eq!(store.get_pending_digest(1, EmailFrequency::Daily).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_claim_digest() {
+        assert_digest_claimed_once(&MemoryNotificationStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_create_many() {
+        assert_create_many_keeps_input_order(&MemoryNotificationStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_mark_read_many() {
+        assert_read_many_counts_unread(&MemoryNotificationStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_grouped() {
+        assert_groups_by_resource(create_test_store().as_ref()).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_mark_group_read() {
+        assert_group_marked_read(create_test_store().as_ref()).await;
+    }
+
     #[tokio::test]
     async fn test_memory_store_create() {
         let store = create_test_store();
@@ -508,8 +1414,335 @@ mod tests {
     async fn test_memory_store_settings() {
         let store = create_test_store();
 
-        let settings = store.get_settings(1).await.unwrap();
+        let mut settings = store.get_settings(1).await.unwrap();
         assert_eq!(settings.user_id, 1);
         assert!(settings.in_app_enabled);
+
+        settings.email_frequency = EmailFrequency::Daily;
+        settings.in_app_reasons.responsible = true;
+        let mut project = ProjectNotificationSettings::inherit(3, &settings);
+        project.date_alerts.overdue = Some(7);
+        settings.project_overrides.push(project.clone());
+        store.update_settings(&settings).await.unwrap();
+
+        let stored = store.get_settings(1).await.unwrap();
+        assert_eq!(stored.email_frequency, EmailFrequency::Daily);
+        assert!(stored.in_app_reasons.responsible);
+        assert_eq!(stored.project_overrides, vec![project]);
+        // Other users keep the defaults
+        assert!(store.get_settings(2).await.unwrap().project_overrides.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_work_package_update_notifies_current_watchers() {
+        let store = create_test_store();
+        let service = Arc::new(NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        ));
+        let notifier = WorkPackageNotifier::new(service);
+
+        // The actor is not notified about their own update
+        let events = notifier.on_updated(42, 1, 2, vec![1, 2]).await.unwrap();
+        assert_eq!(events.len(), 1);
+        let notifications = store.get_for_user(1, false, 10).await.unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].reason, NotificationReason::Watched);
+
+        // After unwatching, later updates do not notify but the existing
+        // notification is kept
+        let events = notifier.on_updated(42, 1, 2, vec![2]).await.unwrap();
+        assert!(events.is_empty());
+        assert_eq!(store.get_for_user(1, false, 10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_trash_and_purge_notify_distinctly() {
+        let store = create_test_store();
+        let service = Arc::new(NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        ));
+        let notifier = WorkPackageNotifier::new(service);
+
+        notifier.on_trashed(42, 1, 2, vec![1, 2]).await.unwrap();
+        notifier.on_purged(42, 1, 2, vec![1, 2]).await.unwrap();
+
+        let mut types: Vec<_> = store
+            .get_for_user(1, false, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|n| n.notification_type)
+            .collect();
+        types.sort_by_key(|t| t.i18n_key());
+        assert_eq!(
+            types,
+            vec![NotificationType::WorkPackagePurged, NotificationType::WorkPackageTrashed]
+        );
+        assert!(store.get_for_user(2, false, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_suppressing_notifier_records_instead_of_notifying() {
+        let store = create_test_store();
+        let service = Arc::new(NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        ));
+        let window = Arc::new(SuppressionWindow::new());
+        let notifier = WorkPackageNotifier::new(service).suppressing(window.clone(), BulkOperation::Import);
+
+        for work_package_id in 1..=50 {
+            assert!(notifier.on_created(work_package_id, 1, 2, vec![1, 3]).await.unwrap().is_empty());
+        }
+        assert!(notifier.on_assigned(51, 1, 2, 4).await.unwrap().is_none());
+
+        assert_eq!(store.unread_count(1).await.unwrap(), 0);
+        let summaries = window.close();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!((summaries[0].operation, summaries[0].count()), (BulkOperation::Import, 51));
+        assert_eq!(summaries[0].recipient_ids.iter().copied().collect::<Vec<_>>(), vec![1, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_notify_many_batches_recipients_in_order() {
+        let store = create_test_store();
+        let mut settings = NotificationSettings::for_user(7);
+        settings.in_app_enabled = false;
+        store.settings.write().await.insert(7, settings);
+
+        let service = NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        );
+        let template = Notification::work_package(0, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 42)
+            .with_project(1);
+
+        let recipients: Vec<Id> = (1..=40).rev().collect();
+        let events = service.notify_many(&recipients, &template).await.unwrap();
+
+        // Recipient 7 disabled in-app notifications
+        let notified: Vec<Id> = events.iter().map(|e| e.notification.recipient_id).collect();
+        let expected: Vec<Id> = recipients.iter().copied().filter(|&id| id != 7).collect();
+        assert_eq!(notified, expected);
+
+        let ids: Vec<Id> = events.iter().map(|e| e.notification.id.unwrap()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Delivered in-app, with the email queued for the address lookup
+        for event in &events {
+            let results = &event.delivery_results;
+            assert_eq!(results.len(), 2);
+            assert_eq!((results[0].channel, results[0].success), (Channel::InApp, true));
+            assert_eq!(results[1].channel, Channel::Email);
+            assert!(results[1].success && results[1].job_id.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_is_captured_once_for_all_recipients() {
+        struct CountingSnapshots(std::sync::atomic::AtomicUsize);
+
+        #[async_trait]
+        impl JournalSnapshots for CountingSnapshots {
+            async fn snapshot(&self, journal_id: Id) -> ServiceResult<Option<NotificationSnapshot>> {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok((journal_id == 9).then(|| NotificationSnapshot::new("Crash on save")))
+            }
+        }
+
+        let store = create_test_store();
+        let snapshots = Arc::new(CountingSnapshots(Default::default()));
+        let service = NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        )
+        .with_snapshots(snapshots.clone());
+        let template = Notification::work_package(0, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 42)
+            .with_journal(9);
+
+        let events = service.notify_many(&[1, 2, 3], &template).await.unwrap();
+        assert_eq!(snapshots.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+        for event in &events {
+            let stored = store.get(event.notification.id.unwrap()).await.unwrap().unwrap();
+            assert_eq!(stored.snapshot.unwrap().subject, "Crash on save");
+        }
+
+        // Missing journals and notifications without one render generically
+        let missing = service.notify_many(&[1], &template.clone().with_journal(10)).await.unwrap();
+        assert_eq!(missing[0].notification.snapshot, None);
+        let plain = Notification::work_package(0, NotificationType::WorkPackageUpdated, NotificationReason::Watched, 42);
+        service.notify_many(&[1], &plain).await.unwrap();
+        assert_eq!(snapshots.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_published_to_streams() {
+        use crate::stream::{NotificationStreams, Received, StreamEventKind};
+
+        let store = create_test_store();
+        let streams = Arc::new(NotificationStreams::new());
+        let service = NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        )
+        .with_streams(streams.clone());
+        let mut subscription = streams.subscribe(1, None);
+
+        let mut received = Vec::new();
+        let event = service
+            .notify(1, NotificationType::WorkPackageAssigned, NotificationReason::Assigned, "WorkPackage", 42, Some(2), None)
+            .await
+            .unwrap();
+        let id = event.notification.id.unwrap();
+        service.notify(1, NotificationType::WorkPackageUpdated, NotificationReason::Watched, "WorkPackage", 43, Some(2), None)
+            .await
+            .unwrap();
+        service.mark_read(id).await.unwrap();
+        service.mark_all_read(1).await.unwrap();
+        // Other users' changes are not published to the subscription
+        service.notify(2, NotificationType::WorkPackageAssigned, NotificationReason::Assigned, "WorkPackage", 42, Some(1), None)
+            .await
+            .unwrap();
+
+        for _ in 0..4 {
+            match subscription.recv().await {
+                Some(Received::Event(event)) => received.push((event.kind, event.unread_count)),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(
+            received,
+            vec![
+                (StreamEventKind::Created, 1),
+                (StreamEventKind::Created, 2),
+                (StreamEventKind::Read, 1),
+                (StreamEventKind::AllRead, 0),
+            ]
+        );
+        assert!(!streams.has_stream(2));
+    }
+
+    #[tokio::test]
+    async fn test_send_email_is_deferred_by_the_throttle() {
+        use crate::throttle::EmailThrottle;
+        use op_core::config::EmailSendLimits;
+
+        let store = create_test_store();
+        let throttle = Arc::new(EmailThrottle::new(EmailSendLimits {
+            per_hour: Some(1),
+            ..Default::default()
+        }));
+        let service = NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        )
+        .with_email_throttle(throttle.clone());
+
+        let mut first = Notification::work_package(1, NotificationType::WorkPackageAssigned, NotificationReason::Assigned, 42);
+        let mut second = first.clone();
+        store.create(&mut first).await.unwrap();
+        store.create(&mut second).await.unwrap();
+
+        service.send_email(first.id.unwrap(), "user@example.com", None, None).await.unwrap();
+        let result = service.send_email(second.id.unwrap(), "user@example.com", None, None).await;
+        assert!(matches!(result, Err(ServiceError::Deferred(secs)) if secs > 3500));
+        assert_eq!(throttle.status().sent_last_hour, 1);
+    }
+
+    /// Sender failing every message, as a digest run does when it crashes
+    /// between claiming and sending
+    struct FailingSender;
+
+    #[async_trait]
+    impl EmailSender for FailingSender {
+        async fn send(&self, _message: &crate::email::EmailMessage) -> crate::email::EmailResult<String> {
+            Err(crate::email::EmailError::SmtpError("connection reset".into()))
+        }
+
+        fn is_configured(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_digest_is_sent_again_exactly_once() {
+        use crate::email::MemoryEmailSender;
+
+        let store = create_test_store();
+        let renderer = EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com"));
+        let failing = NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(FailingSender),
+            renderer.clone(),
+        );
+        let sender = Arc::new(MemoryEmailSender::new());
+        let retrying =
+            NotificationService::new(store.clone(), Arc::new(MemoryJobQueue::new()), sender.clone(), renderer);
+
+        for work_package_id in [42, 43] {
+            let mut notification = Notification::work_package(
+                1,
+                NotificationType::WorkPackageUpdated,
+                NotificationReason::Watched,
+                work_package_id,
+            );
+            store.create(&mut notification).await.unwrap();
+        }
+
+        let result = failing.send_digest(1, EmailFrequency::Daily, "user@example.com", None, None).await;
+        assert!(matches!(result, Err(ServiceError::DeliveryError(_))));
+        assert_eq!(store.get_pending_digest(1, EmailFrequency::Daily).await.unwrap().len(), 2);
+
+        let sent = retrying.send_digest(1, EmailFrequency::Daily, "user@example.com", None, None).await;
+        assert_eq!(sent.unwrap(), 2);
+        let again = retrying.send_digest(1, EmailFrequency::Daily, "user@example.com", None, None).await;
+        assert_eq!(again.unwrap(), 0);
+
+        let messages = sender.sent_messages().await;
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].subject.contains("(2 notifications)"), "{}", messages[0].subject);
+    }
+
+    #[tokio::test]
+    async fn test_send_email_is_deferred_while_flagged() {
+        let store = create_test_store();
+        let (deferral, receiver) = watch::channel(true);
+        let service = NotificationService::new(
+            store.clone(),
+            Arc::new(MemoryJobQueue::new()),
+            Arc::new(ConsoleEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        )
+        .with_email_deferral(receiver);
+
+        let mut notification = Notification::work_package(1, NotificationType::WorkPackageAssigned, NotificationReason::Assigned, 42);
+        store.create(&mut notification).await.unwrap();
+        let id = notification.id.unwrap();
+
+        let result = service.send_email(id, "user@example.com", None, None).await;
+        assert!(matches!(result, Err(ServiceError::Deferred(EMAIL_DEFERRAL_SECS))));
+        assert!(!store.get(id).await.unwrap().unwrap().is_mail_sent());
+
+        deferral.send(false).unwrap();
+        service.send_email(id, "user@example.com", None, None).await.unwrap();
+        // Emailed immediately, so left out of digests
+        assert!(store.get_pending_digest(1, EmailFrequency::Daily).await.unwrap().is_empty());
     }
 }
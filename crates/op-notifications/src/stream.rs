@@ -0,0 +1,342 @@
+//! Notification Event Streams
+//!
+//! Pushes notification changes to connected clients instead of having them
+//! poll the unread count.
+//!
+//! Every user with an open stream has a broadcast channel. Events carry a
+//! sequence id and the latest events are kept in a bounded buffer, so a
+//! client reconnecting with the id of the last event it saw receives the
+//! events it missed. Publishing never waits for subscribers: a client that
+//! stops reading falls behind, loses the oldest events and is told to
+//! resynchronize.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use op_core::traits::Id;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::service::{NotificationStore, ServiceResult};
+
+/// Events buffered per subscriber before a slow subscriber lags
+pub const CHANNEL_CAPACITY: usize = 64;
+
+/// Events kept per user for replay after a reconnect
+pub const REPLAY_CAPACITY: usize = 100;
+
+/// How long the events of a user without subscribers are kept
+pub const IDLE_RETENTION: Duration = Duration::from_secs(300);
+
+/// What changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamEventKind {
+    /// Notifications were created
+    Created,
+    /// Notifications were read
+    Read,
+    /// All notifications were read
+    AllRead,
+    /// The unread count changed, or the client has to resynchronize
+    Count,
+}
+
+/// Event sent to a user's subscribers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamEvent {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: StreamEventKind,
+    pub notification_ids: Vec<Id>,
+    pub unread_count: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// What a subscriber received
+#[derive(Debug, Clone, PartialEq)]
+pub enum Received {
+    Event(StreamEvent),
+    /// The subscriber did not keep up and this many events were dropped
+    Lagged(u64),
+}
+
+struct UserStream {
+    sender: broadcast::Sender<StreamEvent>,
+    history: VecDeque<StreamEvent>,
+    /// Events up to this id may be missing from the history, either
+    /// because they were evicted or the stream did not exist yet
+    complete_after: u64,
+    idle_since: Option<Instant>,
+}
+
+/// Registry of per-user notification event streams
+pub struct NotificationStreams {
+    users: Mutex<HashMap<Id, UserStream>>,
+    sequence: AtomicU64,
+    retention: Duration,
+}
+
+impl Default for NotificationStreams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NotificationStreams {
+    pub fn new() -> Self {
+        Self {
+            users: Mutex::new(HashMap::new()),
+            sequence: AtomicU64::new(0),
+            retention: IDLE_RETENTION,
+        }
+    }
+
+    /// Keep the events of users without subscribers for this long
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Subscribe to a user's events. With the id of the last event the
+    /// client saw, the events published since are returned as missed.
+    pub fn subscribe(self: &Arc<Self>, user_id: Id, last_event_id: Option<u64>) -> Subscription {
+        let mut users = self.users.lock().unwrap();
+        self.prune(&mut users);
+
+        let sequence = self.sequence.load(Ordering::SeqCst);
+        let stream = users.entry(user_id).or_insert_with(|| UserStream {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            history: VecDeque::new(),
+            complete_after: sequence,
+            idle_since: None,
+        });
+        stream.idle_since = None;
+
+        let (missed, gap) = match last_event_id {
+            Some(last) => (
+                stream.history.iter().filter(|e| e.id > last).cloned().collect(),
+                last < stream.complete_after,
+            ),
+            None => (Vec::new(), false),
+        };
+
+        Subscription {
+            user_id,
+            receiver: Some(stream.sender.subscribe()),
+            streams: self.clone(),
+            missed,
+            gap,
+        }
+    }
+
+    /// Publish an event to the user's subscribers. Nothing is published
+    /// for users without a stream.
+    pub fn publish(
+        &self,
+        user_id: Id,
+        kind: StreamEventKind,
+        notification_ids: Vec<Id>,
+        unread_count: usize,
+    ) -> Option<StreamEvent> {
+        let mut users = self.users.lock().unwrap();
+        self.prune(&mut users);
+        let stream = users.get_mut(&user_id)?;
+
+        let event = StreamEvent {
+            id: self.sequence.fetch_add(1, Ordering::SeqCst) + 1,
+            kind,
+            notification_ids,
+            unread_count,
+            timestamp: Utc::now(),
+        };
+
+        stream.history.push_back(event.clone());
+        if stream.history.len() > REPLAY_CAPACITY {
+            if let Some(evicted) = stream.history.pop_front() {
+                stream.complete_after = evicted.id;
+            }
+        }
+
+        // Fails only when every subscriber is gone; the event stays buffered
+        let _ = stream.sender.send(event.clone());
+        Some(event)
+    }
+
+    /// Publish an event with the user's unread count read from the store.
+    /// The store is not queried for users without a stream.
+    pub async fn publish_from<S: NotificationStore + ?Sized>(
+        &self,
+        store: &S,
+        user_id: Id,
+        kind: StreamEventKind,
+        notification_ids: Vec<Id>,
+    ) -> ServiceResult<Option<StreamEvent>> {
+        if !self.has_stream(user_id) {
+            return Ok(None);
+        }
+        let unread_count = store.unread_count(user_id).await?;
+        Ok(self.publish(user_id, kind, notification_ids, unread_count))
+    }
+
+    /// Count event resynchronizing a client, carrying the id of the latest
+    /// event so a reconnect replays only newer events
+    pub fn count_event(&self, unread_count: usize) -> StreamEvent {
+        StreamEvent {
+            id: self.sequence.load(Ordering::SeqCst),
+            kind: StreamEventKind::Count,
+            notification_ids: Vec::new(),
+            unread_count,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Whether events are currently kept for the user
+    pub fn has_stream(&self, user_id: Id) -> bool {
+        let mut users = self.users.lock().unwrap();
+        self.prune(&mut users);
+        users.contains_key(&user_id)
+    }
+
+    /// Number of open subscriptions of the user
+    pub fn subscriber_count(&self, user_id: Id) -> usize {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map_or(0, |stream| stream.sender.receiver_count())
+    }
+
+    fn prune(&self, users: &mut HashMap<Id, UserStream>) {
+        let retention = self.retention;
+        users.retain(|_, stream| stream.idle_since.is_none_or(|since| since.elapsed() < retention));
+    }
+
+    fn unsubscribed(&self, user_id: Id) {
+        let mut users = self.users.lock().unwrap();
+        if let Some(stream) = users.get_mut(&user_id) {
+            if stream.sender.receiver_count() == 0 {
+                stream.idle_since = Some(Instant::now());
+            }
+        }
+        self.prune(&mut users);
+    }
+}
+
+/// A client's subscription to a user's events, released when dropped
+pub struct Subscription {
+    user_id: Id,
+    receiver: Option<broadcast::Receiver<StreamEvent>>,
+    streams: Arc<NotificationStreams>,
+    /// Events published since the last event the client saw
+    pub missed: Vec<StreamEvent>,
+    /// Whether events older than the replay buffer were missed as well, so
+    /// the client has to resynchronize
+    pub gap: bool,
+}
+
+impl Subscription {
+    /// Wait for the next event; `None` once the registry is gone
+    pub async fn recv(&mut self) -> Option<Received> {
+        let receiver = self.receiver.as_mut()?;
+        match receiver.recv().await {
+            Ok(event) => Some(Received::Event(event)),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => Some(Received::Lagged(skipped)),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        drop(self.receiver.take());
+        self.streams.unsubscribed(self.user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(received: Option<Received>) -> StreamEvent {
+        match received {
+            Some(Received::Event(event)) => event,
+            other => panic!("expected an event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_of_a_user_receive_its_events() {
+        let streams = Arc::new(NotificationStreams::new());
+        let mut first = streams.subscribe(1, None);
+        let mut second = streams.subscribe(1, None);
+        let mut other = streams.subscribe(2, None);
+        assert_eq!(streams.subscriber_count(1), 2);
+
+        let published = streams.publish(1, StreamEventKind::Created, vec![10], 1).unwrap();
+        assert_eq!(event(first.recv().await), published);
+        assert_eq!(event(second.recv().await), published);
+
+        streams.publish(2, StreamEventKind::AllRead, vec![], 0);
+        assert_eq!(event(other.recv().await).kind, StreamEventKind::AllRead);
+        assert!(streams.publish(3, StreamEventKind::Created, vec![11], 1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_replays_missed_events() {
+        let streams = Arc::new(NotificationStreams::new());
+        let subscription = streams.subscribe(1, None);
+        let seen = streams.publish(1, StreamEventKind::Created, vec![10], 1).unwrap();
+        drop(subscription);
+        assert_eq!(streams.subscriber_count(1), 0);
+
+        streams.publish(1, StreamEventKind::Created, vec![11], 2);
+        streams.publish(1, StreamEventKind::Read, vec![10], 1);
+
+        let resumed = streams.subscribe(1, Some(seen.id));
+        assert!(!resumed.gap);
+        let missed: Vec<_> = resumed.missed.iter().map(|e| e.notification_ids.clone()).collect();
+        assert_eq!(missed, vec![vec![11], vec![10]]);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_evicted_events_reports_gap() {
+        let streams = Arc::new(NotificationStreams::new());
+        let _subscription = streams.subscribe(1, None);
+        let first = streams.publish(1, StreamEventKind::Created, vec![1], 1).unwrap();
+        for id in 0..REPLAY_CAPACITY as Id {
+            streams.publish(1, StreamEventKind::Created, vec![id], 1);
+        }
+
+        let resumed = streams.subscribe(1, Some(first.id - 1));
+        assert!(resumed.gap);
+        assert_eq!(resumed.missed.len(), REPLAY_CAPACITY);
+        assert!(!streams.subscribe(1, Some(first.id)).gap);
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_lags_without_blocking_publishers() {
+        let streams = Arc::new(NotificationStreams::new());
+        let mut slow = streams.subscribe(1, None);
+        for id in 0..(CHANNEL_CAPACITY + 5) as Id {
+            streams.publish(1, StreamEventKind::Created, vec![id], 1);
+        }
+
+        assert_eq!(slow.recv().await, Some(Received::Lagged(5)));
+        assert_eq!(event(slow.recv().await).notification_ids, vec![5]);
+    }
+
+    #[tokio::test]
+    async fn test_idle_streams_are_removed() {
+        let streams = Arc::new(NotificationStreams::new().with_retention(Duration::ZERO));
+        let subscription = streams.subscribe(1, None);
+        assert!(streams.has_stream(1));
+
+        drop(subscription);
+        assert!(!streams.has_stream(1));
+    }
+}
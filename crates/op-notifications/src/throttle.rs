@@ -0,0 +1,377 @@
+//! Outbound Email Throttling
+//!
+//! Keeps outbound email within the hourly budgets of the instance and of
+//! each recipient, and stops sending for a while when the mail server keeps
+//! failing. Emails over a limit are deferred to the job queue, never
+//! dropped.
+//!
+//! Counters are sliding one-hour windows of send timestamps kept in memory,
+//! so checking a send takes no database query. Time comes from a [`Clock`];
+//! tests move a [`op_core::clock::ManualClock`] forward to empty the
+//! windows or end a cooldown.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use op_core::clock::{Clock, SystemClock};
+use op_core::config::EmailSendLimits;
+use op_core::traits::Id;
+use serde::Serialize;
+
+use crate::email::EmailError;
+use crate::notification::{Notification, NotificationReason, NotificationType};
+use crate::service::{NotificationStore, ServiceResult};
+
+/// Why a send was deferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeferralReason {
+    /// The instance's hourly budget is used up
+    HourlyLimit,
+    /// The recipient's hourly cap is reached
+    RecipientLimit,
+    /// Sends are paused after repeated delivery failures
+    CircuitOpen,
+}
+
+/// A send that has to wait
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deferral {
+    pub reason: DeferralReason,
+    /// When the send may be attempted again
+    pub retry_in: Duration,
+}
+
+impl Deferral {
+    /// Delay for the deferred send job, at least one second
+    pub fn retry_in_secs(&self) -> i64 {
+        self.retry_in.num_seconds().max(1)
+    }
+}
+
+/// Snapshot of the throttle for health checks
+#[derive(Debug, Clone, Serialize)]
+pub struct ThrottleStatus {
+    pub sent_last_hour: usize,
+    pub hourly_limit: Option<u32>,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paused_until: Option<DateTime<Utc>>,
+}
+
+impl ThrottleStatus {
+    pub fn is_paused(&self) -> bool {
+        self.paused_until.is_some()
+    }
+}
+
+/// Looks up the users told when sending is paused
+#[async_trait]
+pub trait AdminDirectory: Send + Sync {
+    /// Ids of the active administrators
+    async fn admin_ids(&self) -> ServiceResult<Vec<Id>>;
+}
+
+struct PauseAlerts {
+    notifications: Arc<dyn NotificationStore>,
+    admins: Arc<dyn AdminDirectory>,
+}
+
+#[derive(Default)]
+struct ThrottleState {
+    sent: VecDeque<DateTime<Utc>>,
+    sent_to: HashMap<String, VecDeque<DateTime<Utc>>>,
+    consecutive_failures: u32,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+impl ThrottleState {
+    /// Forget sends older than the window
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::hours(1);
+        prune_window(&mut self.sent, cutoff);
+        self.sent_to.retain(|_, sent| {
+            prune_window(sent, cutoff);
+            !sent.is_empty()
+        });
+        if self.paused_until.is_some_and(|until| until <= now) {
+            self.paused_until = None;
+        }
+    }
+}
+
+fn prune_window(window: &mut VecDeque<DateTime<Utc>>, cutoff: DateTime<Utc>) {
+    while window.front().is_some_and(|&sent| sent <= cutoff) {
+        window.pop_front();
+    }
+}
+
+/// Time until the oldest send leaves a full window
+fn window_retry(window: &VecDeque<DateTime<Utc>>, limit: u32, now: DateTime<Utc>) -> Option<Duration> {
+    if window.len() < limit as usize {
+        return None;
+    }
+    window.front().map(|&oldest| oldest + Duration::hours(1) - now)
+}
+
+/// Send budgets and circuit breaker for outbound email
+///
+/// Call [`acquire`](Self::acquire) before each send and report the outcome
+/// with [`record_success`](Self::record_success) or
+/// [`record_failure`](Self::record_failure). After
+/// `failure_threshold` consecutive SMTP or send failures, every send is
+/// deferred for `cooldown_seconds`; administrators are notified once per
+/// pause.
+pub struct EmailThrottle {
+    limits: EmailSendLimits,
+    clock: Arc<dyn Clock>,
+    state: Mutex<ThrottleState>,
+    alerts: Option<PauseAlerts>,
+}
+
+impl EmailThrottle {
+    pub fn new(limits: EmailSendLimits) -> Self {
+        Self {
+            limits,
+            clock: Arc::new(SystemClock),
+            state: Mutex::new(ThrottleState::default()),
+            alerts: None,
+        }
+    }
+
+    /// Take the time from the clock instead of the system time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Notify the administrators when sending is paused
+    pub fn with_admin_alerts(
+        mut self,
+        notifications: Arc<dyn NotificationStore>,
+        admins: Arc<dyn AdminDirectory>,
+    ) -> Self {
+        self.alerts = Some(PauseAlerts { notifications, admins });
+        self
+    }
+
+    pub fn limits(&self) -> &EmailSendLimits {
+        &self.limits
+    }
+
+    /// Count a send to the recipient, or tell how long it has to wait
+    pub fn acquire(&self, recipient: &str) -> Result<(), Deferral> {
+        let now = self.clock.now();
+        let recipient = recipient.trim().to_lowercase();
+        let mut state = self.state.lock().unwrap();
+        state.prune(now);
+
+        if let Some(until) = state.paused_until {
+            return Err(Deferral {
+                reason: DeferralReason::CircuitOpen,
+                retry_in: until - now,
+            });
+        }
+        if let Some(retry_in) = self
+            .limits
+            .per_hour
+            .and_then(|limit| window_retry(&state.sent, limit, now))
+        {
+            return Err(Deferral {
+                reason: DeferralReason::HourlyLimit,
+                retry_in,
+            });
+        }
+        if let Some(retry_in) = self.limits.per_recipient_per_hour.and_then(|limit| {
+            state
+                .sent_to
+                .get(&recipient)
+                .and_then(|sent| window_retry(sent, limit, now))
+        }) {
+            return Err(Deferral {
+                reason: DeferralReason::RecipientLimit,
+                retry_in,
+            });
+        }
+
+        state.sent.push_back(now);
+        state.sent_to.entry(recipient).or_default().push_back(now);
+        Ok(())
+    }
+
+    /// A send succeeded
+    pub fn record_success(&self) {
+        self.state.lock().unwrap().consecutive_failures = 0;
+    }
+
+    /// A send failed. SMTP and send failures count towards pausing; other
+    /// errors, such as an invalid recipient, say nothing about the mail
+    /// server. Returns whether this failure paused sending.
+    pub async fn record_failure(&self, error: &EmailError) -> bool {
+        if !matches!(error, EmailError::SmtpError(_) | EmailError::SendFailed(_)) {
+            return false;
+        }
+
+        let now = self.clock.now();
+        let paused_until = {
+            let mut state = self.state.lock().unwrap();
+            state.consecutive_failures += 1;
+            let threshold = self.limits.failure_threshold;
+            if threshold == 0 || state.consecutive_failures < threshold || state.paused_until.is_some() {
+                return false;
+            }
+            let until = now + Duration::seconds(self.limits.cooldown_seconds as i64);
+            state.paused_until = Some(until);
+            state.consecutive_failures = 0;
+            until
+        };
+
+        tracing::error!(
+            error = %error,
+            threshold = self.limits.failure_threshold,
+            %paused_until,
+            "Pausing outbound email after repeated delivery failures"
+        );
+        self.alert_admins().await;
+        true
+    }
+
+    /// Current counters and pause
+    pub fn status(&self) -> ThrottleStatus {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        state.prune(now);
+        ThrottleStatus {
+            sent_last_hour: state.sent.len(),
+            hourly_limit: self.limits.per_hour,
+            consecutive_failures: state.consecutive_failures,
+            paused_until: state.paused_until,
+        }
+    }
+
+    /// Notify the administrators, logging rather than failing on errors
+    async fn alert_admins(&self) {
+        let Some(alerts) = &self.alerts else {
+            return;
+        };
+        let admin_ids = match alerts.admins.admin_ids().await {
+            Ok(ids) => ids,
+            Err(e) => {
+                tracing::warn!(error = %e, "Administrators not notified of paused email");
+                return;
+            }
+        };
+
+        let mut notifications: Vec<Notification> = admin_ids
+            .into_iter()
+            .map(|admin_id| {
+                Notification::new(
+                    admin_id,
+                    NotificationType::EmailDeliveryPaused,
+                    NotificationReason::System,
+                    "Email",
+                    0,
+                )
+            })
+            .collect();
+        if notifications.is_empty() {
+            return;
+        }
+        if let Err(e) = alerts.notifications.create_many(&mut notifications).await {
+            tracing::warn!(error = %e, "Administrators not notified of paused email");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::MemoryNotificationStore;
+    use op_core::clock::ManualClock;
+
+    struct Admins(Vec<Id>);
+
+    #[async_trait]
+    impl AdminDirectory for Admins {
+        async fn admin_ids(&self) -> ServiceResult<Vec<Id>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn limits() -> EmailSendLimits {
+        EmailSendLimits {
+            per_hour: Some(3),
+            per_recipient_per_hour: Some(2),
+            failure_threshold: 2,
+            cooldown_seconds: 600,
+        }
+    }
+
+    fn throttle(clock: &Arc<ManualClock>) -> EmailThrottle {
+        EmailThrottle::new(limits()).with_clock(clock.clone())
+    }
+
+    #[test]
+    fn test_limits_defer_until_the_window_slides() {
+        let clock = Arc::new(ManualClock::default());
+        let throttle = throttle(&clock);
+
+        assert!(throttle.acquire("a@example.com").is_ok());
+        clock.advance(Duration::minutes(10));
+        assert!(throttle.acquire("A@example.com").is_ok());
+
+        let deferral = throttle.acquire("a@example.com").unwrap_err();
+        assert_eq!(deferral.reason, DeferralReason::RecipientLimit);
+        assert_eq!(deferral.retry_in, Duration::minutes(50));
+
+        assert!(throttle.acquire("b@example.com").is_ok());
+        let deferral = throttle.acquire("c@example.com").unwrap_err();
+        assert_eq!(deferral.reason, DeferralReason::HourlyLimit);
+        assert_eq!(throttle.status().sent_last_hour, 3);
+
+        // The first send leaves the window, freeing one send
+        clock.advance(Duration::minutes(50));
+        assert!(throttle.acquire("c@example.com").is_ok());
+        assert_eq!(
+            throttle.acquire("d@example.com").unwrap_err().reason,
+            DeferralReason::HourlyLimit
+        );
+    }
+
+    #[test]
+    fn test_unlimited_by_default() {
+        let throttle = EmailThrottle::new(EmailSendLimits::default());
+        for _ in 0..100 {
+            assert!(throttle.acquire("a@example.com").is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_after_consecutive_failures() {
+        let clock = Arc::new(ManualClock::default());
+        let store = Arc::new(MemoryNotificationStore::new());
+        let throttle = throttle(&clock).with_admin_alerts(store.clone(), Arc::new(Admins(vec![1, 2])));
+
+        assert!(!throttle.record_failure(&EmailError::SmtpError("down".into())).await);
+        throttle.record_success();
+        assert!(!throttle.record_failure(&EmailError::SmtpError("down".into())).await);
+        assert!(!throttle.record_failure(&EmailError::InvalidRecipient("x".into())).await);
+        assert!(throttle.record_failure(&EmailError::SendFailed("down".into())).await);
+        assert!(throttle.status().is_paused());
+
+        let deferral = throttle.acquire("a@example.com").unwrap_err();
+        assert_eq!(deferral.reason, DeferralReason::CircuitOpen);
+        assert_eq!(deferral.retry_in_secs(), 600);
+
+        let alerts = store.get_for_user(1, true, 10).await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].notification_type, NotificationType::EmailDeliveryPaused);
+        assert_eq!(store.get_for_user(2, true, 10).await.unwrap().len(), 1);
+
+        clock.advance(Duration::seconds(600));
+        assert!(!throttle.status().is_paused());
+        assert!(throttle.acquire("a@example.com").is_ok());
+    }
+}
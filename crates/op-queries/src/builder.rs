@@ -5,9 +5,10 @@
 use op_core::traits::Id;
 
 use crate::columns::{Column, ColumnSet};
-use crate::filters::{Filter, FilterOperator, FilterSet, FilterValue};
-use crate::query::{DisplayRepresentation, GroupBy, Query, QueryVisibility};
+use crate::filters::{attributes, Filter, FilterOperator, FilterSet, FilterValue};
+use crate::query::{DateGranularity, DisplayRepresentation, GroupBy, Query, QueryVisibility};
 use crate::sorts::{SortCriterion, SortDirection, SortOrder};
+use crate::timestamps::Timestamps;
 
 /// Builder for constructing queries fluently
 #[derive(Debug, Default)]
@@ -25,6 +26,7 @@ pub struct QueryBuilder {
     include_subprojects: bool,
     show_hierarchies: bool,
     show_sums: bool,
+    timestamps: Timestamps,
 }
 
 impl QueryBuilder {
@@ -128,10 +130,9 @@ impl QueryBuilder {
     pub fn open(mut self) -> Self {
         self.filters.add(Filter::new(
             "status_id",
-            FilterOperator::IsNotNull,
+            FilterOperator::Open,
             FilterValue::None,
         ));
-        // In real implementation, this would filter by status.is_closed = false
         self
     }
 
@@ -139,10 +140,9 @@ impl QueryBuilder {
     pub fn closed(mut self) -> Self {
         self.filters.add(Filter::new(
             "status_id",
-            FilterOperator::IsNull,
+            FilterOperator::Closed,
             FilterValue::None,
         ));
-        // In real implementation, this would filter by status.is_closed = true
         self
     }
 
@@ -258,6 +258,12 @@ impl QueryBuilder {
         self
     }
 
+    /// Filter to work packages visible in a timeline window (inclusive)
+    pub fn date_window(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.filters.add(Filter::date_intersects(from, to));
+        self
+    }
+
     /// Filter overdue (due date in the past)
     pub fn overdue(mut self) -> Self {
         self.filters.add(Filter::new(
@@ -268,7 +274,7 @@ impl QueryBuilder {
         self
     }
 
-    /// Filter by parent work package
+    /// Filter for the direct children of a work package
     pub fn parent(mut self, parent_id: Id) -> Self {
         self.filters.add(Filter::equals(
             "parent_id",
@@ -277,6 +283,15 @@ impl QueryBuilder {
         self
     }
 
+    /// Filter for a work package and all its descendants
+    pub fn in_subtree_of(mut self, work_package_id: Id) -> Self {
+        self.filters.add(Filter::equals(
+            attributes::SUBTREE_OF,
+            FilterValue::Id(work_package_id),
+        ));
+        self
+    }
+
     /// Filter for root work packages (no parent)
     pub fn roots_only(mut self) -> Self {
         self.filters.add(Filter::is_null("parent_id"));
@@ -409,6 +424,36 @@ impl QueryBuilder {
         self
     }
 
+    /// Group a date attribute by day
+    pub fn group_by_day(mut self, attribute: impl Into<String>) -> Self {
+        self.group_by = GroupBy::by_period(attribute, DateGranularity::Day);
+        self
+    }
+
+    /// Group a date attribute by ISO week
+    pub fn group_by_week(mut self, attribute: impl Into<String>) -> Self {
+        self.group_by = GroupBy::by_period(attribute, DateGranularity::Week);
+        self
+    }
+
+    /// Group a date attribute by month
+    pub fn group_by_month(mut self, attribute: impl Into<String>) -> Self {
+        self.group_by = GroupBy::by_period(attribute, DateGranularity::Month);
+        self
+    }
+
+    /// Group a date attribute by quarter
+    pub fn group_by_quarter(mut self, attribute: impl Into<String>) -> Self {
+        self.group_by = GroupBy::by_period(attribute, DateGranularity::Quarter);
+        self
+    }
+
+    /// Group a date attribute by year
+    pub fn group_by_year(mut self, attribute: impl Into<String>) -> Self {
+        self.group_by = GroupBy::by_period(attribute, DateGranularity::Year);
+        self
+    }
+
     /// No grouping
     pub fn ungrouped(mut self) -> Self {
         self.group_by = GroupBy::none();
@@ -447,6 +492,12 @@ impl QueryBuilder {
         self
     }
 
+    /// Compare results against the given timestamps
+    pub fn timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
     /// Build the query
     pub fn build(self) -> Query {
         Query {
@@ -467,6 +518,7 @@ impl QueryBuilder {
             include_subprojects: self.include_subprojects,
             show_hierarchies: self.show_hierarchies,
             show_sums: self.show_sums,
+            timestamps: self.timestamps,
         }
     }
 }
@@ -592,6 +644,33 @@ mod tests {
         assert_eq!(query.filters.len(), 3);
     }
 
+    #[test]
+    fn test_hierarchy_filters() {
+        let query = QueryBuilder::new().parent(3).in_subtree_of(7).build();
+
+        let children = query.filters.filters_for(attributes::PARENT_ID);
+        assert_eq!(children[0].values, FilterValue::Id(3));
+        let subtree = query.filters.filters_for(attributes::SUBTREE_OF);
+        assert_eq!(subtree[0].operator, FilterOperator::Equals);
+        assert_eq!(subtree[0].values, FilterValue::Id(7));
+    }
+
+    #[test]
+    fn test_open_and_closed_filter_by_status_state() {
+        let query = presets::my_work_packages();
+        let status = query.filters.filters_for("status_id");
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].operator, FilterOperator::Open);
+        assert!(query
+            .filters
+            .filters()
+            .iter()
+            .all(|f| f.operator != FilterOperator::IsNotNull));
+
+        let query = QueryBuilder::new().closed().build();
+        assert_eq!(query.filters.filters_for("status_id")[0].operator, FilterOperator::Closed);
+    }
+
     #[test]
     fn test_builder_with_sorts() {
         let query = QueryBuilder::new()
@@ -613,6 +692,10 @@ mod tests {
 
         assert!(query.is_grouped());
         assert_eq!(query.group_by.attribute, Some("status".to_string()));
+
+        let query = QueryBuilder::new().group_by_month("due_date").build();
+        assert_eq!(query.group_by.attribute.as_deref(), Some("due_date"));
+        assert_eq!(query.group_by.granularity, Some(DateGranularity::Month));
     }
 
     #[test]
@@ -637,6 +720,16 @@ mod tests {
         assert!(query.show_timeline);
     }
 
+    #[test]
+    fn test_builder_date_window() {
+        let query = QueryBuilder::new()
+            .gantt_view()
+            .date_window("2024-01-01", "2024-03-31")
+            .build();
+
+        assert!(query.filters.has_filter_for(crate::filters::attributes::DATES_INTERVAL));
+    }
+
     #[test]
     fn test_preset_my_work_packages() {
         let query = presets::my_work_packages();
@@ -174,12 +174,14 @@ pub mod standard {
         Column::property("start_date")
             .with_caption("Start date")
             .with_sortable(true)
+            .with_groupable(true)
     }
 
     pub fn due_date() -> Column {
         Column::property("due_date")
             .with_caption("Finish date")
             .with_sortable(true)
+            .with_groupable(true)
     }
 
     pub fn estimated_hours() -> Column {
@@ -210,12 +212,21 @@ pub mod standard {
         Column::property("created_at")
             .with_caption("Created on")
             .with_sortable(true)
+            .with_groupable(true)
     }
 
     pub fn updated_at() -> Column {
         Column::property("updated_at")
             .with_caption("Updated on")
             .with_sortable(true)
+            .with_groupable(true)
+    }
+
+    /// Creation of the newest journal
+    pub fn latest_activity_at() -> Column {
+        Column::computed("latest_activity_at")
+            .with_caption("Latest activity at")
+            .with_sortable(true)
     }
 
     pub fn version() -> Column {
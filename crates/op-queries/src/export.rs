@@ -0,0 +1,716 @@
+//! Query Export and Import
+//!
+//! Serializes a saved query's configuration (filters, sorts, columns,
+//! grouping and display settings, but not its results) to a versioned JSON
+//! document that can be imported into another project or instance.
+//!
+//! Project-scoped references are not portable by ID: status, type and
+//! version filter values are exported by name and resolved by name on
+//! import. References that cannot be resolved, and filters on custom fields
+//! missing from the target project, are dropped and reported as warnings
+//! rather than failing the import.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use op_core::traits::Id;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::columns::{Column, ColumnSet};
+use crate::filters::{attributes, Filter, FilterOperator, FilterSet, FilterValue};
+use crate::query::{DisplayRepresentation, GroupBy, Query};
+use crate::registry;
+use crate::sorts::{SortCriterion, SortDirection, SortOrder};
+use crate::timestamps::Timestamps;
+
+/// Version of the document format written by [`QueryDocument::export`]
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Errors rejecting a document as a whole
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ImportError {
+    #[error("is malformed: {0}")]
+    Malformed(String),
+    #[error("version {0} is not supported")]
+    UnsupportedVersion(u32),
+    #[error("can't be blank")]
+    BlankName,
+    #[error("operator '{1}' of filter '{0}' is invalid")]
+    InvalidOperator(String, String),
+    #[error("direction '{1}' of sort '{0}' is invalid")]
+    InvalidSortDirection(String, String),
+    #[error("representation '{0}' is invalid")]
+    InvalidDisplay(String),
+    #[error("timestamps are invalid: {0}")]
+    InvalidTimestamps(String),
+    #[error("grouping '{0}' is invalid")]
+    InvalidGroupBy(String),
+}
+
+impl ImportError {
+    /// Document property the error refers to
+    pub fn property(&self) -> &'static str {
+        match self {
+            Self::Malformed(_) => "document",
+            Self::UnsupportedVersion(_) => "schemaVersion",
+            Self::BlankName => "name",
+            Self::InvalidOperator(..) => "filters",
+            Self::InvalidSortDirection(..) => "sortBy",
+            Self::InvalidDisplay(_) | Self::InvalidTimestamps(_) => "display",
+            Self::InvalidGroupBy(_) => "groupBy",
+        }
+    }
+}
+
+/// Problems an import recovered from by dropping part of the query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportWarning {
+    /// A referenced status, type or version does not exist in the target
+    UnresolvedReference { attribute: String, name: String },
+    /// A filter on a custom field the target project does not have
+    MissingCustomFieldFilter { attribute: String },
+    /// A column showing a custom field the target project does not have
+    MissingCustomFieldColumn { attribute: String },
+    /// A filter stored by the Ruby application that could not be read
+    UnreadableFilter { attribute: String },
+    /// A sort criterion stored by the Ruby application that could not be read
+    UnreadableSort { attribute: String },
+    /// A setting stored by the Ruby application that could not be read at all
+    UnreadableSetting { setting: String },
+}
+
+impl fmt::Display for ImportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnresolvedReference { attribute, name } => {
+                write!(f, "Filter '{}': '{}' could not be found and was removed", attribute, name)
+            }
+            Self::MissingCustomFieldFilter { attribute } => {
+                write!(f, "Filter '{}': custom field is not available and the filter was removed", attribute)
+            }
+            Self::MissingCustomFieldColumn { attribute } => {
+                write!(f, "Column '{}': custom field is not available and the column was removed", attribute)
+            }
+            Self::UnreadableFilter { attribute } => {
+                write!(f, "Filter '{}': stored in a format that could not be read and was removed", attribute)
+            }
+            Self::UnreadableSort { attribute } => {
+                write!(f, "Sort '{}': stored in a format that could not be read and was removed", attribute)
+            }
+            Self::UnreadableSetting { setting } => {
+                write!(f, "Stored {} could not be read and were reset", setting)
+            }
+        }
+    }
+}
+
+/// Kinds of project-scoped records referenced by filter values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    Status,
+    Type,
+    Version,
+}
+
+impl ReferenceKind {
+    /// Kind referenced by a filter attribute
+    pub fn for_attribute(attribute: &str) -> Option<Self> {
+        match attribute {
+            attributes::STATUS_ID => Some(Self::Status),
+            attributes::TYPE_ID => Some(Self::Type),
+            attributes::VERSION_ID => Some(Self::Version),
+            _ => None,
+        }
+    }
+}
+
+/// Names of the records available in a project, used to translate
+/// references between IDs and names
+#[derive(Debug, Clone, Default)]
+pub struct References {
+    names: HashMap<ReferenceKind, HashMap<Id, String>>,
+    custom_fields: HashSet<Id>,
+}
+
+impl References {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a referenceable record
+    pub fn add(&mut self, kind: ReferenceKind, id: Id, name: impl Into<String>) -> &mut Self {
+        self.names.entry(kind).or_default().insert(id, name.into());
+        self
+    }
+
+    /// Register a referenceable record (builder pattern)
+    pub fn with(mut self, kind: ReferenceKind, id: Id, name: impl Into<String>) -> Self {
+        self.add(kind, id, name);
+        self
+    }
+
+    /// Register a custom field available in the project
+    pub fn add_custom_field(&mut self, id: Id) -> &mut Self {
+        self.custom_fields.insert(id);
+        self
+    }
+
+    /// Register a custom field available in the project (builder pattern)
+    pub fn with_custom_field(mut self, id: Id) -> Self {
+        self.add_custom_field(id);
+        self
+    }
+
+    /// Name of a referenced record
+    pub fn name(&self, kind: ReferenceKind, id: Id) -> Option<&str> {
+        self.names.get(&kind)?.get(&id).map(String::as_str)
+    }
+
+    /// ID of the record with the given name
+    pub fn id(&self, kind: ReferenceKind, name: &str) -> Option<Id> {
+        self.names
+            .get(&kind)?
+            .iter()
+            .find(|(_, n)| n.as_str() == name)
+            .map(|(id, _)| *id)
+    }
+
+    /// Check if a custom field is available
+    pub fn has_custom_field(&self, id: Id) -> bool {
+        self.custom_fields.contains(&id)
+    }
+}
+
+/// A portable query configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryDocument {
+    pub schema_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub filters: Vec<FilterEntry>,
+    #[serde(default)]
+    pub sort_by: Vec<SortEntry>,
+    #[serde(default)]
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub group_by: Option<String>,
+    #[serde(default)]
+    pub display: DisplaySettings,
+}
+
+/// A filter with its operator and values in their string form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterEntry {
+    pub attribute: String,
+    pub operator: String,
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+/// A sort criterion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortEntry {
+    pub attribute: String,
+    pub direction: String,
+}
+
+/// Display settings of a query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplaySettings {
+    pub representation: String,
+    pub show_sums: bool,
+    pub show_hierarchies: bool,
+    pub include_subprojects: bool,
+    pub timeline_visible: bool,
+    #[serde(default)]
+    pub timestamps: Option<String>,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        let query = Query::new("");
+        Self {
+            representation: query.display.as_str().into(),
+            show_sums: query.show_sums,
+            show_hierarchies: query.show_hierarchies,
+            include_subprojects: query.include_subprojects,
+            timeline_visible: query.show_timeline,
+            timestamps: None,
+        }
+    }
+}
+
+/// Result of a successful import
+#[derive(Debug, Clone)]
+pub struct ImportedQuery {
+    pub query: Query,
+    pub warnings: Vec<ImportWarning>,
+}
+
+impl QueryDocument {
+    /// Export a query, replacing referenced IDs with names. IDs without a
+    /// known name are kept as they are and will not resolve on import.
+    pub fn export(query: &Query, references: &References) -> Self {
+        let filters = query
+            .filters
+            .filters()
+            .iter()
+            .map(|filter| {
+                let mut entry = FilterEntry::from_filter(filter);
+                if let Some(kind) = ReferenceKind::for_attribute(&filter.attribute) {
+                    for value in entry.values.iter_mut() {
+                        let name = value
+                            .parse()
+                            .ok()
+                            .and_then(|id| references.name(kind, id));
+                        if let Some(name) = name {
+                            *value = name.to_string();
+                        }
+                    }
+                }
+                entry
+            })
+            .collect();
+
+        Self {
+            schema_version: SCHEMA_VERSION,
+            name: query.name.clone(),
+            filters,
+            sort_by: query.sorts.criteria().iter().map(SortEntry::from_criterion).collect(),
+            columns: query.columns.names().into_iter().map(String::from).collect(),
+            group_by: query.group_by.to_param(),
+            display: DisplaySettings {
+                representation: query.display.as_str().into(),
+                show_sums: query.show_sums,
+                show_hierarchies: query.show_hierarchies,
+                include_subprojects: query.include_subprojects,
+                timeline_visible: query.show_timeline,
+                timestamps: query.timestamps.is_historic().then(|| query.timestamps.to_string()),
+            },
+        }
+    }
+
+    /// Parse and validate a document
+    pub fn parse(json: &str) -> Result<Self, ImportError> {
+        let document: Self =
+            serde_json::from_str(json).map_err(|e| ImportError::Malformed(e.to_string()))?;
+        document.validate()?;
+        Ok(document)
+    }
+
+    /// Validate the parts of the document that can't be recovered from
+    pub fn validate(&self) -> Result<(), ImportError> {
+        if self.schema_version == 0 || self.schema_version > SCHEMA_VERSION {
+            return Err(ImportError::UnsupportedVersion(self.schema_version));
+        }
+        if self.name.trim().is_empty() {
+            return Err(ImportError::BlankName);
+        }
+        for filter in &self.filters {
+            if FilterOperator::from_str(&filter.operator).is_none() {
+                return Err(ImportError::InvalidOperator(
+                    filter.attribute.clone(),
+                    filter.operator.clone(),
+                ));
+            }
+        }
+        for sort in &self.sort_by {
+            if SortDirection::from_str(&sort.direction).is_none() {
+                return Err(ImportError::InvalidSortDirection(
+                    sort.attribute.clone(),
+                    sort.direction.clone(),
+                ));
+            }
+        }
+        if let Some(group_by) = self.group_by.as_deref().filter(|g| GroupBy::parse(g).is_none()) {
+            return Err(ImportError::InvalidGroupBy(group_by.to_string()));
+        }
+        if DisplayRepresentation::from_str(&self.display.representation).is_none() {
+            return Err(ImportError::InvalidDisplay(self.display.representation.clone()));
+        }
+        if let Some(timestamps) = &self.display.timestamps {
+            Timestamps::parse(timestamps).map_err(|e| ImportError::InvalidTimestamps(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Build the query for the target project, resolving references by name
+    pub fn import(mut self, project_id: Option<Id>, references: &References) -> Result<ImportedQuery, ImportError> {
+        self.validate()?;
+
+        let mut warnings = Vec::new();
+        let mut filters = Vec::with_capacity(self.filters.len());
+
+        for mut entry in self.filters {
+            if let Some(cf_id) = custom_field_id(&entry.attribute) {
+                if !references.has_custom_field(cf_id) {
+                    warnings.push(ImportWarning::MissingCustomFieldFilter { attribute: entry.attribute });
+                    continue;
+                }
+            }
+
+            if let Some(kind) = ReferenceKind::for_attribute(&entry.attribute) {
+                let mut resolved = Vec::with_capacity(entry.values.len());
+                for name in entry.values {
+                    match references.id(kind, &name) {
+                        Some(id) => resolved.push(id.to_string()),
+                        None => warnings.push(ImportWarning::UnresolvedReference {
+                            attribute: entry.attribute.clone(),
+                            name,
+                        }),
+                    }
+                }
+                // A filter left without values would match everything
+                if resolved.is_empty() {
+                    continue;
+                }
+                entry.values = resolved;
+            }
+
+            filters.push(entry);
+        }
+        self.filters = filters;
+
+        self.columns.retain(|name| match custom_field_id(name) {
+            Some(cf_id) if !references.has_custom_field(cf_id) => {
+                warnings.push(ImportWarning::MissingCustomFieldColumn { attribute: name.clone() });
+                false
+            }
+            _ => true,
+        });
+
+        let query = self.into_query(project_id)?;
+        Ok(ImportedQuery { query, warnings })
+    }
+
+    /// Build the query as the document describes it, without translating
+    /// references
+    pub fn into_query(self, project_id: Option<Id>) -> Result<Query, ImportError> {
+        let mut filters = FilterSet::new();
+        for entry in &self.filters {
+            filters.add(entry.to_filter()?);
+        }
+
+        let mut columns = ColumnSet::new();
+        for name in self.columns {
+            match custom_field_id(&name) {
+                Some(cf_id) => columns.add(Column::custom_field(cf_id)),
+                None => columns.add(Column::property(name)),
+            };
+        }
+
+        let mut sorts = SortOrder::new();
+        for entry in &self.sort_by {
+            sorts.add(entry.to_criterion()?);
+        }
+
+        let display = DisplayRepresentation::from_str(&self.display.representation)
+            .ok_or_else(|| ImportError::InvalidDisplay(self.display.representation.clone()))?;
+        let group_by = match self.group_by {
+            Some(group_by) => GroupBy::parse(&group_by).ok_or(ImportError::InvalidGroupBy(group_by))?,
+            None => GroupBy::none(),
+        };
+
+        let mut query = Query::new(self.name)
+            .with_filters(filters)
+            .with_sorts(sorts)
+            .with_columns(columns)
+            .with_display(display)
+            .with_group_by(group_by);
+        query.project_id = project_id;
+        query.show_sums = self.display.show_sums;
+        query.show_hierarchies = self.display.show_hierarchies;
+        query.include_subprojects = self.display.include_subprojects;
+        query.show_timeline = self.display.timeline_visible;
+        if let Some(timestamps) = self.display.timestamps {
+            query.timestamps = Timestamps::parse(&timestamps)
+                .map_err(|e| ImportError::InvalidTimestamps(e.to_string()))?;
+        }
+
+        Ok(query)
+    }
+}
+
+impl FilterEntry {
+    /// String form of a filter
+    pub fn from_filter(filter: &Filter) -> Self {
+        let values = match &filter.values {
+            FilterValue::Id(_)
+            | FilterValue::Ids(_)
+            | FilterValue::String(_)
+            | FilterValue::Strings(_)
+            | FilterValue::Me
+            | FilterValue::IdsAndMe(_) => filter.values.as_strings(),
+            FilterValue::Bool(b) => vec![if *b { "t" } else { "f" }.to_string()],
+            FilterValue::Date(date) => vec![date.clone()],
+            FilterValue::DateRange { from, to } => vec![from.clone(), to.clone()],
+            FilterValue::Number(n) => vec![n.to_string()],
+            FilterValue::None => vec![],
+        };
+
+        Self {
+            attribute: filter.attribute.clone(),
+            operator: filter.operator.to_string(),
+            values,
+        }
+    }
+
+    /// Parse the filter back
+    pub fn to_filter(&self) -> Result<Filter, ImportError> {
+        let operator = FilterOperator::from_str(&self.operator)
+            .ok_or_else(|| ImportError::InvalidOperator(self.attribute.clone(), self.operator.clone()))?;
+
+        let values = match (&operator, self.values.as_slice()) {
+            (_, []) => FilterValue::None,
+            (FilterOperator::Between | FilterOperator::DateIntersects, [from, to]) => FilterValue::DateRange {
+                from: from.clone(),
+                to: to.clone(),
+            },
+            (_, values) => {
+                let values: Vec<&str> = values.iter().map(|v| strip_principal_path(v)).collect();
+                let me = values.contains(&"me");
+                let ids = values.iter().filter(|v| **v != "me").map(|v| v.parse());
+                match ids.collect::<Result<Vec<Id>, _>>() {
+                    Ok(ids) if me && ids.is_empty() => FilterValue::Me,
+                    Ok(ids) if me => FilterValue::IdsAndMe(ids),
+                    Ok(ids) => FilterValue::from_ids(ids),
+                    Err(_) => FilterValue::from_strings(self.values.clone()),
+                }
+            }
+        };
+
+        let attribute = attributes::from_api_name(&self.attribute);
+        if registry::find(attribute).is_some_and(|definition| !definition.allows(&operator)) {
+            return Err(ImportError::InvalidOperator(self.attribute.clone(), self.operator.clone()));
+        }
+
+        Ok(Filter::new(attribute, operator, values))
+    }
+}
+
+impl SortEntry {
+    /// String form of a sort criterion
+    pub fn from_criterion(criterion: &SortCriterion) -> Self {
+        Self {
+            attribute: criterion.attribute.clone(),
+            direction: criterion.direction.as_str().into(),
+        }
+    }
+
+    /// Parse the sort criterion back
+    pub fn to_criterion(&self) -> Result<SortCriterion, ImportError> {
+        let direction = SortDirection::from_str(&self.direction).ok_or_else(|| {
+            ImportError::InvalidSortDirection(self.attribute.clone(), self.direction.clone())
+        })?;
+        Ok(SortCriterion::new(self.attribute.clone(), direction))
+    }
+}
+
+/// Paths of principal hrefs, which filter values may use instead of ids
+const PRINCIPAL_PATHS: [&str; 3] = ["/api/v3/users/", "/api/v3/groups/", "/api/v3/placeholder_users/"];
+
+/// Value of a principal href, so that `/api/v3/groups/5` reads as `5` and
+/// `/api/v3/users/me` as `me`; other values are returned as they are
+fn strip_principal_path(value: &str) -> &str {
+    PRINCIPAL_PATHS
+        .iter()
+        .find_map(|path| value.strip_prefix(path))
+        .unwrap_or(value)
+}
+
+/// Custom field ID of a `cf_<id>` attribute
+fn custom_field_id(attribute: &str) -> Option<Id> {
+    attribute.strip_prefix("cf_")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QueryBuilder;
+
+    fn source_references() -> References {
+        References::new()
+            .with(ReferenceKind::Status, 1, "New")
+            .with(ReferenceKind::Status, 2, "In progress")
+            .with(ReferenceKind::Type, 3, "Bug")
+            .with(ReferenceKind::Version, 9, "1.0")
+            .with_custom_field(5)
+    }
+
+    fn source_query() -> Query {
+        let mut query = QueryBuilder::new()
+            .name("Open bugs")
+            .project(1)
+            .status(vec![1, 2])
+            .type_ids(vec![3])
+            .sort_by_priority()
+            .group_by_status()
+            .build();
+        query.filters.add(Filter::equals("cf_5", FilterValue::String("high".into())));
+        query.filters.add(Filter::equals(attributes::VERSION_ID, FilterValue::Id(9)));
+        query
+    }
+
+    #[test]
+    fn test_export_replaces_ids_with_names() {
+        let document = QueryDocument::export(&source_query(), &source_references());
+
+        assert_eq!(document.schema_version, SCHEMA_VERSION);
+        assert_eq!(document.name, "Open bugs");
+        let status = document.filters.iter().find(|f| f.attribute == "status_id").unwrap();
+        assert_eq!(status.values, vec!["New", "In progress"]);
+        assert_eq!(document.group_by.as_deref(), Some("status"));
+
+        let json = serde_json::to_string(&document).unwrap();
+        assert!(json.contains("\"schemaVersion\":1"));
+        assert_eq!(QueryDocument::parse(&json).unwrap(), document);
+    }
+
+    #[test]
+    fn test_import_resolves_names() {
+        let document = QueryDocument::export(&source_query(), &source_references());
+        let target = References::new()
+            .with(ReferenceKind::Status, 11, "New")
+            .with(ReferenceKind::Status, 12, "In progress")
+            .with(ReferenceKind::Type, 13, "Bug")
+            .with(ReferenceKind::Version, 19, "1.0")
+            .with_custom_field(5);
+
+        let imported = document.import(Some(2), &target).unwrap();
+        assert!(imported.warnings.is_empty());
+        assert_eq!(imported.query.project_id, Some(2));
+        assert_eq!(
+            imported.query.filters.filters_for("status_id")[0].values,
+            FilterValue::Ids(vec![11, 12])
+        );
+        assert_eq!(imported.query.filters.filters_for("type_id")[0].values, FilterValue::Id(13));
+        assert!(imported.query.is_grouped());
+    }
+
+    #[test]
+    fn test_import_reports_unresolvable_references() {
+        let document = QueryDocument::export(&source_query(), &source_references());
+        let target = References::new()
+            .with(ReferenceKind::Status, 11, "New")
+            .with(ReferenceKind::Type, 13, "Bug");
+
+        let imported = document.import(Some(2), &target).unwrap();
+        let filters = &imported.query.filters;
+        assert_eq!(filters.filters_for("status_id")[0].values, FilterValue::Id(11));
+        assert!(!filters.has_filter_for("version_id"));
+        assert!(!filters.has_filter_for("cf_5"));
+
+        assert_eq!(
+            imported.warnings,
+            vec![
+                ImportWarning::UnresolvedReference {
+                    attribute: "status_id".into(),
+                    name: "In progress".into()
+                },
+                ImportWarning::MissingCustomFieldFilter { attribute: "cf_5".into() },
+                ImportWarning::UnresolvedReference {
+                    attribute: "version_id".into(),
+                    name: "1.0".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_values_accept_me_and_principal_hrefs() {
+        let values = |values: &[&str]| {
+            FilterEntry {
+                attribute: attributes::ASSIGNED_TO_ID.into(),
+                operator: "=".into(),
+                values: values.iter().map(|v| v.to_string()).collect(),
+            }
+            .to_filter()
+            .unwrap()
+            .values
+        };
+
+        assert_eq!(values(&["me"]), FilterValue::Me);
+        assert_eq!(values(&["/api/v3/users/me"]), FilterValue::Me);
+        assert_eq!(values(&["me", "5", "/api/v3/groups/7"]), FilterValue::IdsAndMe(vec![5, 7]));
+        assert_eq!(
+            values(&["/api/v3/users/3", "/api/v3/groups/7", "/api/v3/placeholder_users/9"]),
+            FilterValue::Ids(vec![3, 7, 9])
+        );
+        assert_eq!(values(&["me", "high"]), FilterValue::Strings(vec!["me".into(), "high".into()]));
+
+        // Mixed lists survive the round trip
+        let filter = Filter::equals(attributes::ASSIGNED_TO_ID, FilterValue::IdsAndMe(vec![5]));
+        assert_eq!(FilterEntry::from_filter(&filter).values, vec!["me", "5"]);
+        assert_eq!(FilterEntry::from_filter(&filter).to_filter().unwrap().values, filter.values);
+    }
+
+    #[test]
+    fn test_filters_reject_operators_they_lack() {
+        let entry = |attribute: &str, operator: &str| FilterEntry {
+            attribute: attribute.into(),
+            operator: operator.into(),
+            values: vec!["4".into()],
+        };
+
+        assert!(matches!(
+            entry(attributes::STATUS_ID, "~").to_filter(),
+            Err(ImportError::InvalidOperator(attribute, operator)) if attribute == "status_id" && operator == "~"
+        ));
+        assert!(entry("parent", "<>d").to_filter().is_err());
+        assert!(entry(attributes::DUE_DATE, "<t+").to_filter().is_err(), "needs its days");
+        assert!(entry(attributes::DUE_DATE, "<t+4").to_filter().is_ok());
+        // Filters outside the registry, like those of other collections, keep every operator
+        assert!(entry("principal", "~").to_filter().is_ok());
+    }
+
+    #[test]
+    fn test_hierarchy_filters_accept_api_names() {
+        let filter = |attribute: &str| {
+            FilterEntry {
+                attribute: attribute.into(),
+                operator: "=".into(),
+                values: vec!["4".into()],
+            }
+            .to_filter()
+            .unwrap()
+        };
+
+        assert_eq!(filter("parent").attribute, attributes::PARENT_ID);
+        let subtree = filter("subtreeOf");
+        assert_eq!(subtree.attribute, attributes::SUBTREE_OF);
+        assert_eq!(subtree.values, FilterValue::Id(4));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_documents() {
+        assert!(matches!(QueryDocument::parse("{"), Err(ImportError::Malformed(_))));
+
+        let future = r#"{"schemaVersion": 2, "name": "Q"}"#;
+        assert_eq!(QueryDocument::parse(future), Err(ImportError::UnsupportedVersion(2)));
+
+        let operator = r#"{"schemaVersion": 1, "name": "Q",
+            "filters": [{"attribute": "status_id", "operator": "??", "values": []}]}"#;
+        assert!(matches!(QueryDocument::parse(operator), Err(ImportError::InvalidOperator(..))));
+
+        let grouping = r#"{"schemaVersion": 1, "name": "Q", "groupBy": "status:month"}"#;
+        assert_eq!(QueryDocument::parse(grouping), Err(ImportError::InvalidGroupBy("status:month".into())));
+
+        let minimal = QueryDocument::parse(r#"{"schemaVersion": 1, "name": "Q"}"#).unwrap();
+        assert_eq!(minimal.display, DisplaySettings::default());
+    }
+
+    #[test]
+    fn test_date_grouping_round_trip() {
+        let query = QueryBuilder::new().name("By week").group_by_week("created_at").build();
+        let document = QueryDocument::export(&query, &References::new());
+        assert_eq!(document.group_by.as_deref(), Some("created_at:week"));
+
+        let json = serde_json::to_string(&document).unwrap();
+        let imported = QueryDocument::parse(&json).unwrap().into_query(None).unwrap();
+        assert_eq!(imported.group_by.attribute.as_deref(), Some("created_at"));
+        assert_eq!(imported.group_by.granularity, Some(crate::DateGranularity::Week));
+    }
+}
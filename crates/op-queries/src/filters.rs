@@ -33,6 +33,8 @@ pub enum FilterOperator {
     LessThanOrEqual,
     /// Between two values (<>d)
     Between,
+    /// Start/due date span intersects a date window (&&)
+    DateIntersects,
     /// Is null/empty (*)
     IsNull,
     /// Is not null/empty (!*)
@@ -55,6 +57,10 @@ pub enum FilterOperator {
     MoreThanDaysFromNow(i32),
     /// Current user (me)
     CurrentUser,
+    /// Status is open (o)
+    Open,
+    /// Status is closed (c)
+    Closed,
 }
 
 impl FilterOperator {
@@ -72,8 +78,11 @@ impl FilterOperator {
             "<" => Some(Self::LessThan),
             "<=" => Some(Self::LessThanOrEqual),
             "<>d" => Some(Self::Between),
-            "*" | "o" => Some(Self::IsNull),
-            "!*" | "c" => Some(Self::IsNotNull),
+            "&&" => Some(Self::DateIntersects),
+            "*" => Some(Self::IsNull),
+            "!*" => Some(Self::IsNotNull),
+            "o" => Some(Self::Open),
+            "c" => Some(Self::Closed),
             "t" => Some(Self::Today),
             "w" => Some(Self::ThisWeek),
             s if s.starts_with("t-") => s[2..].parse().ok().map(Self::DaysAgo),
@@ -100,6 +109,7 @@ impl FilterOperator {
             Self::LessThan => "<".to_string(),
             Self::LessThanOrEqual => "<=".to_string(),
             Self::Between => "<>d".to_string(),
+            Self::DateIntersects => "&&".to_string(),
             Self::IsNull => "*".to_string(),
             Self::IsNotNull => "!*".to_string(),
             Self::Today => "t".to_string(),
@@ -111,6 +121,69 @@ impl FilterOperator {
             Self::LessThanDaysFromNow(n) => format!("<t+{}", n),
             Self::MoreThanDaysFromNow(n) => format!(">t+{}", n),
             Self::CurrentUser => "=".to_string(), // Special handling needed
+            Self::Open => "o".to_string(),
+            Self::Closed => "c".to_string(),
+        }
+    }
+
+    /// Symbol of the operator without its number of days, as in the
+    /// operator hrefs of API v3, e.g. `t-` for `t-5`
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Equals | Self::CurrentUser => "=",
+            Self::NotEquals => "!",
+            Self::Contains => "~",
+            Self::NotContains => "!~",
+            Self::StartsWith => "**",
+            Self::EndsWith => "*~",
+            Self::GreaterThan => ">",
+            Self::GreaterThanOrEqual => ">=",
+            Self::LessThan => "<",
+            Self::LessThanOrEqual => "<=",
+            Self::Between => "<>d",
+            Self::DateIntersects => "&&",
+            Self::IsNull => "*",
+            Self::IsNotNull => "!*",
+            Self::Today => "t",
+            Self::ThisWeek => "w",
+            Self::DaysAgo(_) => "t-",
+            Self::DaysFromNow(_) => "t+",
+            Self::LessThanDaysAgo(_) => "<t-",
+            Self::MoreThanDaysAgo(_) => ">t-",
+            Self::LessThanDaysFromNow(_) => "<t+",
+            Self::MoreThanDaysFromNow(_) => ">t+",
+            Self::Open => "o",
+            Self::Closed => "c",
+        }
+    }
+
+    /// Name of the operator shown in filter forms
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Equals | Self::CurrentUser => "is",
+            Self::NotEquals => "is not",
+            Self::Contains => "contains",
+            Self::NotContains => "doesn't contain",
+            Self::StartsWith => "starts with",
+            Self::EndsWith => "ends with",
+            Self::GreaterThan => ">",
+            Self::GreaterThanOrEqual => ">=",
+            Self::LessThan => "<",
+            Self::LessThanOrEqual => "<=",
+            Self::Between => "between",
+            Self::DateIntersects => "intersects",
+            Self::IsNull => "none",
+            Self::IsNotNull => "all",
+            Self::Today => "today",
+            Self::ThisWeek => "this week",
+            Self::DaysAgo(_) => "days ago",
+            Self::DaysFromNow(_) => "in",
+            Self::LessThanDaysAgo(_) => "less than days ago",
+            Self::MoreThanDaysAgo(_) => "more than days ago",
+            Self::LessThanDaysFromNow(_) => "in less than",
+            Self::MoreThanDaysFromNow(_) => "in more than",
+            Self::Open => "open",
+            Self::Closed => "closed",
         }
     }
 
@@ -123,6 +196,8 @@ impl FilterOperator {
                 | Self::Today
                 | Self::ThisWeek
                 | Self::CurrentUser
+                | Self::Open
+                | Self::Closed
         )
     }
 }
@@ -148,6 +223,8 @@ pub enum FilterValue {
     Number(f64),
     /// Special "me" value for current user
     Me,
+    /// The current user together with literal IDs, e.g. `["me", "5"]`
+    IdsAndMe(Vec<Id>),
     /// No value (for null checks)
     None,
 }
@@ -171,11 +248,11 @@ impl FilterValue {
         }
     }
 
-    /// Get as list of IDs
+    /// Get as list of IDs, without the current user
     pub fn as_ids(&self) -> Vec<Id> {
         match self {
             Self::Id(id) => vec![*id],
-            Self::Ids(ids) => ids.clone(),
+            Self::Ids(ids) | Self::IdsAndMe(ids) => ids.clone(),
             _ => vec![],
         }
     }
@@ -187,6 +264,10 @@ impl FilterValue {
             Self::Strings(ss) => ss.clone(),
             Self::Id(id) => vec![id.to_string()],
             Self::Ids(ids) => ids.iter().map(|id| id.to_string()).collect(),
+            Self::Me => vec!["me".to_string()],
+            Self::IdsAndMe(ids) => std::iter::once("me".to_string())
+                .chain(ids.iter().map(|id| id.to_string()))
+                .collect(),
             _ => vec![],
         }
     }
@@ -242,6 +323,18 @@ impl Filter {
         Self::new(attribute, FilterOperator::IsNotNull, FilterValue::None)
     }
 
+    /// Create a filter matching work packages whose dates intersect a window
+    pub fn date_intersects(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self::new(
+            attributes::DATES_INTERVAL,
+            FilterOperator::DateIntersects,
+            FilterValue::DateRange {
+                from: from.into(),
+                to: to.into(),
+            },
+        )
+    }
+
     /// Check if this filter is valid
     pub fn is_valid(&self) -> bool {
         if self.attribute.is_empty() {
@@ -270,16 +363,45 @@ pub mod attributes {
     pub const DESCRIPTION: &str = "description";
     pub const START_DATE: &str = "start_date";
     pub const DUE_DATE: &str = "due_date";
+    pub const DATES_INTERVAL: &str = "dates_interval";
     pub const ESTIMATED_HOURS: &str = "estimated_hours";
     pub const DONE_RATIO: &str = "done_ratio";
     pub const CREATED_AT: &str = "created_at";
     pub const UPDATED_AT: &str = "updated_at";
+    /// Direct children of the work packages
     pub const PARENT_ID: &str = "parent_id";
+    /// The work packages and their descendants at any depth
+    pub const SUBTREE_OF: &str = "subtree_of";
     pub const SUBPROJECT_ID: &str = "subproject_id";
     pub const WATCHER_ID: &str = "watcher_id";
+    /// Journal notes containing text
+    pub const COMMENT: &str = "comment";
+    pub const ATTACHMENT_FILE_NAME: &str = "attachment_file_name";
+    /// Extracted text of attachments
+    pub const ATTACHMENT_CONTENT: &str = "attachment_content";
     pub const RESPONSIBLE_ID: &str = "responsible_id";
+    /// Work packages assigned to members of the groups
+    pub const MEMBER_OF_GROUP: &str = "member_of_group";
+    /// Work packages with a journal by one of the users
+    pub const UPDATED_BY: &str = "updated_by";
+    /// Creation of the newest journal of a work package
+    pub const LATEST_ACTIVITY_AT: &str = "latest_activity_at";
+    /// Work packages changed since the current user last viewed them
+    pub const UNSEEN_CHANGES: &str = "unseen_changes";
+    /// Work packages in the trash, left out of queries without the filter
+    pub const TRASHED: &str = "trashed";
     pub const MANUAL_SORT: &str = "manual_sort";
     pub const ID: &str = "id";
+
+    /// Attribute of a filter name used by API v3 clients, which filter by
+    /// `parent` and `subtreeOf`; other names are attributes already
+    pub fn from_api_name(name: &str) -> &str {
+        match name {
+            "parent" => PARENT_ID,
+            "subtreeOf" => SUBTREE_OF,
+            name => name,
+        }
+    }
 }
 
 /// Filter set - a collection of filters with AND semantics
@@ -380,6 +502,11 @@ mod tests {
             FilterOperator::from_str(">t+10"),
             Some(FilterOperator::MoreThanDaysFromNow(10))
         );
+        assert_eq!(FilterOperator::from_str("o"), Some(FilterOperator::Open));
+        assert_eq!(FilterOperator::from_str("c"), Some(FilterOperator::Closed));
+        assert_eq!(FilterOperator::Closed.to_string(), "c");
+        assert_eq!(FilterOperator::LessThanDaysAgo(3).symbol(), "<t-");
+        assert_eq!(FilterOperator::from_str(FilterOperator::IsNotNull.symbol()), Some(FilterOperator::IsNotNull));
     }
 
     #[test]
@@ -406,6 +533,19 @@ mod tests {
         assert!(filter.is_valid());
     }
 
+    #[test]
+    fn test_filter_date_intersects() {
+        assert_eq!(
+            FilterOperator::from_str("&&"),
+            Some(FilterOperator::DateIntersects)
+        );
+        assert_eq!(FilterOperator::DateIntersects.to_string(), "&&");
+
+        let filter = Filter::date_intersects("2024-01-01", "2024-03-31");
+        assert_eq!(filter.attribute, attributes::DATES_INTERVAL);
+        assert!(filter.is_valid());
+    }
+
     #[test]
     fn test_filter_set() {
         let filters = FilterSet::new()
@@ -428,6 +568,13 @@ mod tests {
         assert_eq!(multiple.as_ids(), vec![1, 2, 3]);
     }
 
+    #[test]
+    fn test_hierarchy_filter_api_names() {
+        assert_eq!(attributes::from_api_name("parent"), attributes::PARENT_ID);
+        assert_eq!(attributes::from_api_name("subtreeOf"), attributes::SUBTREE_OF);
+        assert_eq!(attributes::from_api_name("status_id"), attributes::STATUS_ID);
+    }
+
     #[test]
     fn test_filter_operator_requires_values() {
         assert!(FilterOperator::Equals.requires_values());
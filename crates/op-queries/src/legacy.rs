@@ -0,0 +1,336 @@
+//! Ruby-serialized Query Settings
+//!
+//! Queries saved by the Ruby application store their filters, columns and
+//! sort criteria as YAML written by Psych instead of the JSON this crate
+//! writes. Filters are a hash of attribute => operator and values, or a
+//! list of serialized filter objects; columns a list of symbols
+//! (`:subject`); sort criteria a list of `[attribute, direction]` pairs.
+//! Keys may be symbols or strings, and hashes may be tagged as
+//! `HashWithIndifferentAccess`.
+//!
+//! The settings are read into the entries of a
+//! [`QueryDocument`](crate::export::QueryDocument). Filters and sort
+//! criteria that can't be read are left out and reported as
+//! [`ImportWarning`]s rather than failing the whole query.
+
+use yaml_rust2::yaml::Hash;
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::export::{FilterEntry, ImportWarning, SortEntry};
+use crate::sorts::SortDirection;
+
+/// Setting names used in warnings
+pub const FILTERS: &str = "filters";
+pub const COLUMNS: &str = "columns";
+pub const SORT_CRITERIA: &str = "sort criteria";
+
+/// Operators whose number of days Ruby stores as the value
+const RELATIVE_DAYS_OPERATORS: [&str; 6] = ["t-", "t+", "<t-", ">t-", "<t+", ">t+"];
+
+/// Check if a stored setting is a YAML document written by Ruby rather
+/// than JSON
+pub fn is_ruby_yaml(stored: &str) -> bool {
+    stored.trim_start().starts_with("---")
+}
+
+/// Read filters, leaving out those that can't be read
+pub fn parse_filters(yaml: &str) -> (Vec<FilterEntry>, Vec<ImportWarning>) {
+    let mut filters = Vec::new();
+    let mut warnings = Vec::new();
+
+    let document = load(yaml);
+    let conditions: Vec<(Option<String>, &Yaml)> = match &document {
+        Some(Yaml::Null) => Vec::new(),
+        Some(Yaml::Hash(hash)) => hash.iter().map(|(attribute, condition)| (name(attribute), condition)).collect(),
+        Some(Yaml::Array(items)) => items
+            .iter()
+            .flat_map(|item| match item {
+                // A serialized filter object naming its attribute
+                Yaml::Hash(object) if get(object, "name").is_some() => {
+                    vec![(get(object, "name").and_then(name), item)]
+                }
+                Yaml::Hash(hash) => hash.iter().map(|(attribute, condition)| (name(attribute), condition)).collect(),
+                _ => vec![(None, item)],
+            })
+            .collect(),
+        _ => {
+            warnings.push(ImportWarning::UnreadableSetting { setting: FILTERS.into() });
+            return (filters, warnings);
+        }
+    };
+
+    for (attribute, condition) in conditions {
+        match attribute.as_deref().and_then(|attribute| filter_entry(attribute, condition)) {
+            Some(entry) => filters.push(entry),
+            None => warnings.push(ImportWarning::UnreadableFilter {
+                attribute: attribute.unwrap_or_default(),
+            }),
+        }
+    }
+
+    (filters, warnings)
+}
+
+/// Read column names; without readable columns the query shows the
+/// default ones
+pub fn parse_columns(yaml: &str) -> (Vec<String>, Vec<ImportWarning>) {
+    match load(yaml) {
+        Some(Yaml::Null) => (Vec::new(), Vec::new()),
+        Some(Yaml::Array(items)) => {
+            let columns: Vec<String> = items.iter().filter_map(name).collect();
+            let warnings = if columns.len() < items.len() {
+                vec![ImportWarning::UnreadableSetting { setting: COLUMNS.into() }]
+            } else {
+                Vec::new()
+            };
+            (columns, warnings)
+        }
+        _ => (Vec::new(), vec![ImportWarning::UnreadableSetting { setting: COLUMNS.into() }]),
+    }
+}
+
+/// Read sort criteria, leaving out those that can't be read
+pub fn parse_sort_criteria(yaml: &str) -> (Vec<SortEntry>, Vec<ImportWarning>) {
+    let items = match load(yaml) {
+        Some(Yaml::Null) => return (Vec::new(), Vec::new()),
+        Some(Yaml::Array(items)) => items,
+        _ => return (Vec::new(), vec![ImportWarning::UnreadableSetting { setting: SORT_CRITERIA.into() }]),
+    };
+
+    let mut sorts = Vec::new();
+    let mut warnings = Vec::new();
+    for item in &items {
+        let (attribute, direction) = match item {
+            Yaml::Array(pair) => (pair.first().and_then(name), pair.get(1).map(name)),
+            item => (name(item), None),
+        };
+        let direction = match direction {
+            None => Some("asc".to_string()),
+            Some(direction) => direction.filter(|d| SortDirection::from_str(d).is_some()),
+        };
+        match (attribute, direction) {
+            (Some(attribute), Some(direction)) => sorts.push(SortEntry { attribute, direction }),
+            (attribute, _) => warnings.push(ImportWarning::UnreadableSort {
+                attribute: attribute.unwrap_or_default(),
+            }),
+        }
+    }
+
+    (sorts, warnings)
+}
+
+/// The filter on `attribute` described by a hash with an operator and
+/// values, if it is one this crate supports
+fn filter_entry(attribute: &str, condition: &Yaml) -> Option<FilterEntry> {
+    let Yaml::Hash(condition) = condition else {
+        return None;
+    };
+    let operator = get(condition, "operator").and_then(value)?;
+    let values = match get(condition, "values") {
+        None | Some(Yaml::Null) => Vec::new(),
+        Some(Yaml::Array(items)) => {
+            let values: Vec<Option<String>> =
+                items.iter().filter(|item| !item.is_null()).map(value).collect();
+            values.into_iter().collect::<Option<_>>()?
+        }
+        Some(scalar) => vec![value(scalar)?],
+    };
+
+    // Ruby keeps the days of relative date operators among the values
+    let (operator, values) = match values.as_slice() {
+        [days] if RELATIVE_DAYS_OPERATORS.contains(&operator.as_str()) && days.parse::<i32>().is_ok() => {
+            (format!("{}{}", operator, days), Vec::new())
+        }
+        _ => (operator, values),
+    };
+
+    let entry = FilterEntry {
+        attribute: attribute.to_string(),
+        operator,
+        values,
+    };
+    entry.to_filter().ok()?;
+    Some(entry)
+}
+
+fn load(yaml: &str) -> Option<Yaml> {
+    YamlLoader::load_from_str(yaml).ok()?.into_iter().next()
+}
+
+/// Value of a key that Ruby may have written as a symbol or a string
+fn get<'a>(hash: &'a Hash, key: &str) -> Option<&'a Yaml> {
+    hash.iter().find(|(k, _)| name(k).as_deref() == Some(key)).map(|(_, v)| v)
+}
+
+/// A name written as a symbol (`:status_id`) or a string
+fn name(yaml: &Yaml) -> Option<String> {
+    match yaml {
+        Yaml::String(s) => Some(s.strip_prefix(':').unwrap_or(s).to_string()),
+        _ => None,
+    }
+}
+
+/// A scalar value in the string form filters use
+fn value(yaml: &Yaml) -> Option<String> {
+    match yaml {
+        Yaml::String(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Real(r) => Some(r.clone()),
+        Yaml::Boolean(b) => Some(if *b { "t" } else { "f" }.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Filters as stored by OpenProject 7, with indifferent access hashes
+    const INDIFFERENT_FILTERS: &str = r#"--- !ruby/hash:ActiveSupport::HashWithIndifferentAccess
+status_id: !ruby/hash:ActiveSupport::HashWithIndifferentAccess
+  operator: o
+  values:
+  - ''
+assigned_to_id: !ruby/hash:ActiveSupport::HashWithIndifferentAccess
+  operator: "="
+  values:
+  - me
+  - '12'
+"#;
+
+    /// Filters as stored by later versions, serialized filter objects with
+    /// symbol names
+    const OBJECT_FILTERS: &str = r#"---
+- !ruby/object:Queries::WorkPackages::Filter::StatusFilter
+  name: :status_id
+  operator: "!"
+  values:
+  - '3'
+  - '7'
+  context:
+- !ruby/object:Queries::WorkPackages::Filter::DueDateFilter
+  name: :due_date
+  operator: "<t+"
+  values:
+  - '2'
+- !ruby/object:Queries::WorkPackages::Filter::SubjectFilter
+  name: :subject
+  operator: "~"
+  values:
+  -
+  - Release
+"#;
+
+    /// Filters with symbol keys and exotic operators
+    const SYMBOL_FILTERS: &str = r#"---
+- :type_id:
+    :operator: "="
+    :values:
+    - '1'
+- :cf_7:
+    :operator: "=n"
+    :values:
+    - '4'
+- :watcher_id:
+    :operator: "="
+    :values: !ruby/object:Object {}
+- :priority_id: open
+"#;
+
+    const COLUMN_NAMES: &str = r#"---
+- :id
+- :subject
+- :type
+- :status
+- :assigned_to
+- :cf_5
+"#;
+
+    const SORT_CRITERIA_PAIRS: &str = r#"---
+- - id
+  - desc
+- - :subject
+  - asc
+- - parent
+- - start_date
+  - sideways
+"#;
+
+    fn entry(attribute: &str, operator: &str, values: &[&str]) -> FilterEntry {
+        FilterEntry {
+            attribute: attribute.into(),
+            operator: operator.into(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_detects_ruby_yaml() {
+        assert!(is_ruby_yaml(INDIFFERENT_FILTERS));
+        assert!(is_ruby_yaml("--- []\n"));
+        assert!(!is_ruby_yaml(r#"[{"status_id":{"operator":"o","values":[]}}]"#));
+    }
+
+    #[test]
+    fn test_parses_indifferent_access_hashes() {
+        let (filters, warnings) = parse_filters(INDIFFERENT_FILTERS);
+        assert_eq!(
+            filters,
+            vec![entry("status_id", "o", &[""]), entry("assigned_to_id", "=", &["me", "12"])]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parses_serialized_filter_objects_skipping_nil_values() {
+        let (filters, warnings) = parse_filters(OBJECT_FILTERS);
+        assert_eq!(
+            filters,
+            vec![
+                entry("status_id", "!", &["3", "7"]),
+                entry("due_date", "<t+2", &[]),
+                entry("subject", "~", &["Release"]),
+            ]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_unreadable_filters_are_left_out_with_warnings() {
+        let (filters, warnings) = parse_filters(SYMBOL_FILTERS);
+        assert_eq!(filters, vec![entry("type_id", "=", &["1"])]);
+        assert_eq!(
+            warnings,
+            ["cf_7", "watcher_id", "priority_id"]
+                .map(|attribute| ImportWarning::UnreadableFilter { attribute: attribute.into() })
+                .to_vec()
+        );
+
+        let (filters, warnings) = parse_filters("--- !ruby/object:Query 42\n");
+        assert!(filters.is_empty());
+        assert_eq!(warnings, vec![ImportWarning::UnreadableSetting { setting: FILTERS.into() }]);
+        assert_eq!(parse_filters("--- \n"), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn test_parses_column_symbols() {
+        let (columns, warnings) = parse_columns(COLUMN_NAMES);
+        assert_eq!(columns, vec!["id", "subject", "type", "status", "assigned_to", "cf_5"]);
+        assert!(warnings.is_empty());
+
+        let (columns, warnings) = parse_columns("---\n- :subject\n- - nested\n");
+        assert_eq!(columns, vec!["subject"]);
+        assert_eq!(warnings, vec![ImportWarning::UnreadableSetting { setting: COLUMNS.into() }]);
+    }
+
+    #[test]
+    fn test_parses_sort_criteria_pairs() {
+        let (sorts, warnings) = parse_sort_criteria(SORT_CRITERIA_PAIRS);
+        let sort = |attribute: &str, direction: &str| SortEntry {
+            attribute: attribute.into(),
+            direction: direction.into(),
+        };
+        assert_eq!(sorts, vec![sort("id", "desc"), sort("subject", "asc"), sort("parent", "asc")]);
+        assert_eq!(warnings, vec![ImportWarning::UnreadableSort { attribute: "start_date".into() }]);
+    }
+}
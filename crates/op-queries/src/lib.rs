@@ -8,10 +8,14 @@
 //! ## Structure
 //!
 //! - `filters` - Filter types and operators for querying work packages
+//! - `registry` - The filters clients can use, with their operators and values
 //! - `sorts` - Sort orders and directions
 //! - `columns` - Column configuration for display
 //! - `query` - The Query model for saved views
 //! - `builder` - Fluent API for constructing queries
+//! - `timestamps` - Points in time for baseline comparison
+//! - `export` - Portable JSON documents for sharing queries
+//! - `legacy` - Settings of queries saved by the Ruby application as YAML
 //!
 //! ## Example
 //!
@@ -36,14 +40,21 @@
 //! ```
 
 pub mod filters;
+pub mod registry;
 pub mod sorts;
 pub mod columns;
 pub mod query;
 pub mod builder;
+pub mod timestamps;
+pub mod export;
+pub mod legacy;
 
 // Re-exports for convenience
 pub use filters::{Filter, FilterOperator, FilterSet, FilterValue};
+pub use registry::{FilterDefinition, FilterKind, ValueSchema, ValueType};
 pub use sorts::{SortCriterion, SortDirection, SortOrder};
 pub use columns::{Column, ColumnSet, ColumnType};
-pub use query::{DisplayRepresentation, GroupBy, Query, QueryVisibility};
+pub use query::{DateGranularity, DisplayRepresentation, GroupBy, Query, QueryVisibility};
 pub use builder::{QueryBuilder, presets};
+pub use timestamps::{Timestamp, TimestampError, Timestamps};
+pub use export::{ImportError, ImportWarning, ImportedQuery, QueryDocument, References, ReferenceKind};
@@ -5,11 +5,13 @@
 //! A Query is a saved configuration for filtering, sorting, and displaying
 //! work packages. Queries can be saved as personal or shared views.
 
+use chrono::{Datelike, NaiveDate, Weekday};
 use op_core::traits::Id;
 
 use crate::columns::ColumnSet;
-use crate::filters::{Filter, FilterSet};
+use crate::filters::{attributes, Filter, FilterOperator, FilterSet, FilterValue};
 use crate::sorts::SortOrder;
+use crate::timestamps::Timestamps;
 
 /// Query visibility settings
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -82,6 +84,93 @@ pub enum TimelineZoomLevel {
     Auto,
 }
 
+/// Period work packages grouped by a date are gathered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DateGranularity {
+    Day,
+    /// ISO 8601 week, starting on Monday
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl DateGranularity {
+    /// Parse from string
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "day" => Some(Self::Day),
+            "week" => Some(Self::Week),
+            "month" => Some(Self::Month),
+            "quarter" => Some(Self::Quarter),
+            "year" => Some(Self::Year),
+            _ => None,
+        }
+    }
+
+    /// Convert to string, also the unit of `date_trunc`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Quarter => "quarter",
+            Self::Year => "year",
+        }
+    }
+
+    /// Key of the period containing the date, as an ISO 8601 string:
+    /// `2024-03-15`, `2024-W11`, `2024-03`, `2024-Q1` or `2024`. Weeks
+    /// belong to the year of their Thursday, so 2024-12-30 is in `2025-W01`.
+    pub fn period_key(&self, date: NaiveDate) -> String {
+        match self {
+            Self::Day => date.format("%Y-%m-%d").to_string(),
+            Self::Week => {
+                let week = date.iso_week();
+                format!("{:04}-W{:02}", week.year(), week.week())
+            }
+            Self::Month => date.format("%Y-%m").to_string(),
+            Self::Quarter => format!("{:04}-Q{}", date.year(), date.month0() / 3 + 1),
+            Self::Year => format!("{:04}", date.year()),
+        }
+    }
+
+    /// Human readable label of a period key: "March 15, 2024", "CW 11",
+    /// "March 2024", "Q1 2024" or "2024"; `None` for a key not of this
+    /// granularity
+    pub fn label(&self, key: &str) -> Option<String> {
+        match self {
+            Self::Day => {
+                let date = NaiveDate::parse_from_str(key, "%Y-%m-%d").ok()?;
+                Some(date.format("%B %-d, %Y").to_string())
+            }
+            Self::Week => {
+                let (year, week) = key.split_once("-W")?;
+                let week: u32 = week.parse().ok()?;
+                NaiveDate::from_isoywd_opt(year.parse().ok()?, week, Weekday::Mon)?;
+                Some(format!("CW {}", week))
+            }
+            Self::Month => {
+                let date = NaiveDate::parse_from_str(&format!("{}-01", key), "%Y-%m-%d").ok()?;
+                Some(date.format("%B %Y").to_string())
+            }
+            Self::Quarter => {
+                let (year, quarter) = key.split_once("-Q")?;
+                let year: i32 = year.parse().ok()?;
+                let quarter: u32 = quarter.parse().ok().filter(|q| (1..=4).contains(q))?;
+                Some(format!("Q{} {}", quarter, year))
+            }
+            Self::Year => {
+                let year: i32 = key.parse().ok().filter(|_| key.len() == 4)?;
+                Some(year.to_string())
+            }
+        }
+    }
+}
+
+/// Attributes holding a date or timestamp, which can be grouped by period
+pub const DATE_GROUP_ATTRIBUTES: [&str; 4] = ["start_date", "due_date", "created_at", "updated_at"];
+
 /// Grouping configuration
 #[derive(Debug, Clone, Default)]
 pub struct GroupBy {
@@ -89,6 +178,9 @@ pub struct GroupBy {
     pub attribute: Option<String>,
     /// Collapse groups by default
     pub collapsed: bool,
+    /// Period of the groups of a date attribute; without one, every
+    /// distinct date forms a group
+    pub granularity: Option<DateGranularity>,
 }
 
 impl GroupBy {
@@ -97,6 +189,7 @@ impl GroupBy {
         Self {
             attribute: Some(attribute.into()),
             collapsed: false,
+            granularity: None,
         }
     }
 
@@ -105,9 +198,43 @@ impl GroupBy {
         Self {
             attribute: Some(attribute.into()),
             collapsed: true,
+            granularity: None,
         }
     }
 
+    /// Group a date attribute by period
+    pub fn by_period(attribute: impl Into<String>, granularity: DateGranularity) -> Self {
+        Self {
+            attribute: Some(attribute.into()),
+            collapsed: false,
+            granularity: Some(granularity),
+        }
+    }
+
+    /// Parse the string form of a grouping, the attribute optionally
+    /// followed by a granularity, e.g. `status` or `due_date:month`. A
+    /// granularity is only valid for [`DATE_GROUP_ATTRIBUTES`].
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.split_once(':') {
+            None => (!value.is_empty()).then(|| Self::by(value)),
+            Some((attribute, granularity)) => {
+                let granularity = DateGranularity::parse(granularity)?;
+                DATE_GROUP_ATTRIBUTES
+                    .contains(&attribute)
+                    .then(|| Self::by_period(attribute, granularity))
+            }
+        }
+    }
+
+    /// String form of the grouping, read by [`Self::parse`]
+    pub fn to_param(&self) -> Option<String> {
+        let attribute = self.attribute.as_deref()?;
+        Some(match self.granularity {
+            Some(granularity) => format!("{}:{}", attribute, granularity.as_str()),
+            None => attribute.to_string(),
+        })
+    }
+
     /// No grouping
     pub fn none() -> Self {
         Self::default()
@@ -181,6 +308,8 @@ pub struct Query {
     pub show_hierarchies: bool,
     /// Show sums row
     pub show_sums: bool,
+    /// Points in time to evaluate the query at (baseline comparison)
+    pub timestamps: Timestamps,
 }
 
 impl Query {
@@ -204,6 +333,7 @@ impl Query {
             include_subprojects: true,
             show_hierarchies: true,
             show_sums: false,
+            timestamps: Timestamps::default(),
         }
     }
 
@@ -317,6 +447,12 @@ impl Query {
         self
     }
 
+    /// Set the timestamps to evaluate the query at
+    pub fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
     /// Check if this query is saved
     pub fn is_saved(&self) -> bool {
         self.id.is_some()
@@ -346,6 +482,33 @@ impl Query {
     pub fn is_grouped(&self) -> bool {
         self.group_by.is_grouped()
     }
+
+    /// Filters to execute, restricted to the query's project
+    ///
+    /// `project_ids` are the projects in scope: the query's project, plus
+    /// its descendants when subprojects are included. Explicit `project_id`
+    /// equality filters narrow the scope instead of being added next to
+    /// it, so projects outside the scope stay excluded. Global queries
+    /// return their filters unchanged.
+    pub fn scoped_filters(&self, project_ids: &[Id]) -> FilterSet {
+        if self.project_id.is_none() {
+            return self.filters.clone();
+        }
+
+        let mut scope = project_ids.to_vec();
+        let mut filters = FilterSet::new();
+        for filter in self.filters.filters() {
+            if filter.attribute == attributes::PROJECT_ID && filter.operator == FilterOperator::Equals {
+                let selected = filter.values.as_ids();
+                scope.retain(|id| selected.contains(id));
+            } else {
+                filters.add(filter.clone());
+            }
+        }
+
+        filters.add(Filter::equals(attributes::PROJECT_ID, FilterValue::from_ids(scope)));
+        filters
+    }
 }
 
 impl Default for Query {
@@ -368,6 +531,44 @@ mod tests {
         assert!(query.is_global());
     }
 
+    #[test]
+    fn test_scoped_filters() {
+        let project_ids = |filters: &FilterSet| -> Vec<Vec<Id>> {
+            filters
+                .filters_for(attributes::PROJECT_ID)
+                .iter()
+                .map(|filter| filter.values.as_ids())
+                .collect()
+        };
+
+        // Global queries are not scoped
+        let global = Query::new("Global").with_filter(Filter::equals("status_id", FilterValue::Id(1)));
+        assert_eq!(global.scoped_filters(&[1, 2]).len(), 1);
+
+        // Project queries get the scope as a filter
+        let query = Query::for_project("Scoped", 5).with_filter(Filter::equals("status_id", FilterValue::Id(1)));
+        let filters = query.scoped_filters(&[5, 6, 7]);
+        assert!(filters.has_filter_for("status_id"));
+        assert_eq!(project_ids(&filters), vec![vec![5, 6, 7]]);
+
+        // An explicit project filter narrows the scope
+        let query = Query::for_project("Narrowed", 5)
+            .with_filter(Filter::equals(attributes::PROJECT_ID, FilterValue::Ids(vec![6, 9])));
+        assert_eq!(project_ids(&query.scoped_filters(&[5, 6, 7])), vec![vec![6]]);
+
+        // Selecting only projects outside the scope matches nothing
+        let query = Query::for_project("Outside", 5)
+            .with_filter(Filter::equals(attributes::PROJECT_ID, FilterValue::Id(9)));
+        assert_eq!(project_ids(&query.scoped_filters(&[5])), vec![Vec::<Id>::new()]);
+
+        // Other operators apply on top of the scope
+        let query = Query::for_project("Excluding", 5)
+            .with_filter(Filter::not_equals(attributes::PROJECT_ID, FilterValue::Id(6)));
+        let filters = query.scoped_filters(&[5, 6]);
+        assert_eq!(filters.len(), 2);
+        assert_eq!(project_ids(&filters), vec![vec![6], vec![5, 6]]);
+    }
+
     #[test]
     fn test_query_for_project() {
         let query = Query::for_project("Project Query", 42);
@@ -447,4 +648,57 @@ mod tests {
         let none = GroupBy::none();
         assert!(!none.is_grouped());
     }
+
+    #[test]
+    fn test_group_by_param_round_trip() {
+        let monthly = GroupBy::parse("due_date:month").unwrap();
+        assert_eq!(monthly.attribute.as_deref(), Some("due_date"));
+        assert_eq!(monthly.granularity, Some(DateGranularity::Month));
+        assert_eq!(monthly.to_param().as_deref(), Some("due_date:month"));
+
+        let status = GroupBy::parse("status").unwrap();
+        assert_eq!(status.granularity, None);
+        assert_eq!(status.to_param().as_deref(), Some("status"));
+
+        assert!(GroupBy::parse("due_date:fortnight").is_none());
+        assert!(GroupBy::parse("status:month").is_none());
+        assert!(GroupBy::parse("").is_none());
+        assert_eq!(GroupBy::none().to_param(), None);
+    }
+
+    #[test]
+    fn test_iso_weeks_at_year_boundaries() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let week = DateGranularity::Week;
+
+        // Monday 2024-12-30 starts the first week of 2025
+        assert_eq!(week.period_key(date(2024, 12, 29)), "2024-W52");
+        assert_eq!(week.period_key(date(2024, 12, 30)), "2025-W01");
+        // 2021-01-03 is a Sunday in the last week of 2020, which has 53
+        assert_eq!(week.period_key(date(2021, 1, 3)), "2020-W53");
+        assert_eq!(week.period_key(date(2021, 1, 4)), "2021-W01");
+
+        assert_eq!(week.label("2020-W53").as_deref(), Some("CW 53"));
+        assert_eq!(week.label("2025-W01").as_deref(), Some("CW 1"));
+        // 2021 has no 53rd week
+        assert_eq!(week.label("2021-W53"), None);
+    }
+
+    #[test]
+    fn test_month_groups_across_a_leap_day() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let month = DateGranularity::Month;
+
+        assert_eq!(month.period_key(date(2024, 2, 28)), "2024-02");
+        assert_eq!(month.period_key(date(2024, 2, 29)), "2024-02");
+        assert_eq!(month.period_key(date(2024, 3, 1)), "2024-03");
+        assert_eq!(month.label("2024-02").as_deref(), Some("February 2024"));
+        assert_eq!(month.label("2024-03").as_deref(), Some("March 2024"));
+
+        assert_eq!(DateGranularity::Day.label("2024-02-29").as_deref(), Some("February 29, 2024"));
+        assert_eq!(DateGranularity::Day.label("2023-02-29"), None);
+        assert_eq!(DateGranularity::Quarter.period_key(date(2024, 2, 29)), "2024-Q1");
+        assert_eq!(DateGranularity::Quarter.label("2024-Q1").as_deref(), Some("Q1 2024"));
+        assert_eq!(DateGranularity::Year.label("2024").as_deref(), Some("2024"));
+    }
 }
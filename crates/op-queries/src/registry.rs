@@ -0,0 +1,316 @@
+//! Filter Registry
+//!
+//! Mirrors: app/models/queries/work_packages/filter/*.rb
+//!
+//! The work package filters with the operators they support and the values
+//! each operator takes. Parsing a filter rejects operators its definition
+//! lacks, and the filter instance schemas of API v3 describe the same
+//! definitions, so both always agree.
+
+use op_core::traits::Id;
+
+use crate::filters::{attributes, FilterOperator};
+
+/// Resource or literal a filter value refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Status,
+    Type,
+    Priority,
+    Project,
+    User,
+    Group,
+    Version,
+    Category,
+    WorkPackage,
+    CustomOption,
+    String,
+    Integer,
+    Float,
+    Date,
+    Boolean,
+}
+
+impl ValueType {
+    /// Name of the type in API v3 schemas
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Status => "Status",
+            Self::Type => "Type",
+            Self::Priority => "Priority",
+            Self::Project => "Project",
+            Self::User => "User",
+            Self::Group => "Group",
+            Self::Version => "Version",
+            Self::Category => "Category",
+            Self::WorkPackage => "WorkPackage",
+            Self::CustomOption => "CustomOption",
+            Self::String => "String",
+            Self::Integer => "Integer",
+            Self::Float => "Float",
+            Self::Date => "Date",
+            Self::Boolean => "Boolean",
+        }
+    }
+
+    /// Collection the values are chosen from, for resources listed
+    /// independently of a project
+    pub fn allowed_values_href(&self) -> Option<&'static str> {
+        match self {
+            Self::Status => Some("/api/v3/statuses"),
+            Self::Type => Some("/api/v3/types"),
+            Self::Priority => Some("/api/v3/priorities"),
+            Self::Project => Some("/api/v3/projects"),
+            Self::User => Some("/api/v3/principals"),
+            Self::Group => Some("/api/v3/groups"),
+            Self::Version => Some("/api/v3/versions"),
+            _ => None,
+        }
+    }
+}
+
+/// Values an operator takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueSchema {
+    pub value_type: ValueType,
+    /// Exact number of values, or `None` for a list
+    pub count: Option<usize>,
+}
+
+impl ValueSchema {
+    fn list(value_type: ValueType) -> Self {
+        Self { value_type, count: None }
+    }
+
+    fn exactly(count: usize, value_type: ValueType) -> Self {
+        Self { value_type, count: Some(count) }
+    }
+
+    /// Type in API v3 schemas, e.g. `[]Status` or `[1]Integer`
+    pub fn type_name(&self) -> String {
+        match self.count {
+            Some(count) => format!("[{}]{}", count, self.value_type.name()),
+            None => format!("[]{}", self.value_type.name()),
+        }
+    }
+}
+
+/// How a filter compares, which determines its operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    /// One of the values
+    List(ValueType),
+    /// One of the values, any or none
+    ListOptional(ValueType),
+    /// One of the statuses, or all open or closed ones
+    Status,
+    /// Text matched anywhere, at the start or at the end
+    Text,
+    /// Text matched anywhere
+    Search,
+    Integer,
+    Float,
+    Date,
+    /// Date that may be unset
+    DateOptional,
+    /// Start and due date intersecting a window
+    DateInterval,
+    Boolean,
+}
+
+const RELATIVE_DATE_OPERATORS: [&str; 9] = ["<t+", ">t+", "t+", "t", "w", ">t-", "<t-", "t-", "<>d"];
+
+impl FilterKind {
+    /// Symbols of the supported operators, see [`FilterOperator::symbol`]
+    pub fn operators(&self) -> Vec<&'static str> {
+        match self {
+            Self::List(_) | Self::Boolean => vec!["=", "!"],
+            Self::ListOptional(_) => vec!["=", "!", "!*", "*"],
+            Self::Status => vec!["o", "=", "c", "!", "!*"],
+            Self::Text => vec!["~", "!~", "**", "*~"],
+            Self::Search => vec!["~", "!~"],
+            Self::Integer | Self::Float => vec!["=", "!", ">=", "<=", "!*", "*"],
+            Self::Date => RELATIVE_DATE_OPERATORS.to_vec(),
+            Self::DateOptional => RELATIVE_DATE_OPERATORS.iter().chain(&["!*", "*"]).copied().collect(),
+            Self::DateInterval => vec!["&&"],
+        }
+    }
+
+    /// Values the operator takes, `None` for operators without values
+    pub fn values(&self, operator: &str) -> Option<ValueSchema> {
+        match operator {
+            "o" | "c" | "t" | "w" | "*" | "!*" => return None,
+            "<t+" | ">t+" | "t+" | ">t-" | "<t-" | "t-" => return Some(ValueSchema::exactly(1, ValueType::Integer)),
+            "<>d" | "&&" => return Some(ValueSchema::exactly(2, ValueType::Date)),
+            _ => {}
+        }
+        Some(match self {
+            Self::List(value_type) | Self::ListOptional(value_type) => ValueSchema::list(*value_type),
+            Self::Status => ValueSchema::list(ValueType::Status),
+            Self::Text | Self::Search => ValueSchema::exactly(1, ValueType::String),
+            Self::Integer => ValueSchema::exactly(1, ValueType::Integer),
+            Self::Float => ValueSchema::exactly(1, ValueType::Float),
+            Self::Date | Self::DateOptional | Self::DateInterval => ValueSchema::exactly(1, ValueType::Date),
+            Self::Boolean => ValueSchema::exactly(1, ValueType::Boolean),
+        })
+    }
+
+    /// Kind of the filter on a custom field of the given format
+    pub fn for_custom_field(field_format: &str) -> Self {
+        match field_format {
+            "list" => Self::ListOptional(ValueType::CustomOption),
+            "user" => Self::ListOptional(ValueType::User),
+            "version" => Self::ListOptional(ValueType::Version),
+            "int" => Self::Integer,
+            "float" => Self::Float,
+            "date" => Self::DateOptional,
+            "bool" => Self::Boolean,
+            _ => Self::Search,
+        }
+    }
+}
+
+/// A filter clients can use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterDefinition {
+    /// Name in API v3, e.g. `assignee`
+    pub name: String,
+    /// Attribute of the parsed filter, e.g. `assigned_to_id`
+    pub attribute: String,
+    pub title: String,
+    pub kind: FilterKind,
+}
+
+impl FilterDefinition {
+    fn new(name: &str, attribute: &str, title: &str, kind: FilterKind) -> Self {
+        Self {
+            name: name.into(),
+            attribute: attribute.into(),
+            title: title.into(),
+            kind,
+        }
+    }
+
+    /// Filter on a work package custom field
+    pub fn custom_field(id: Id, name: &str, field_format: &str) -> Self {
+        Self::new(
+            &format!("customField{}", id),
+            &format!("cf_{}", id),
+            name,
+            FilterKind::for_custom_field(field_format),
+        )
+    }
+
+    /// Whether the filter supports the operator
+    pub fn allows(&self, operator: &FilterOperator) -> bool {
+        self.kind.operators().contains(&operator.symbol())
+    }
+}
+
+/// Built-in filters as API name, attribute, title and kind
+const FILTERS: &[(&str, &str, &str, FilterKind)] = &[
+    ("status", attributes::STATUS_ID, "Status", FilterKind::Status),
+    ("type", attributes::TYPE_ID, "Type", FilterKind::List(ValueType::Type)),
+    ("priority", attributes::PRIORITY_ID, "Priority", FilterKind::List(ValueType::Priority)),
+    ("project", attributes::PROJECT_ID, "Project", FilterKind::List(ValueType::Project)),
+    ("assignee", attributes::ASSIGNED_TO_ID, "Assignee", FilterKind::ListOptional(ValueType::User)),
+    ("memberOfGroup", attributes::MEMBER_OF_GROUP, "Assignee's group", FilterKind::ListOptional(ValueType::Group)),
+    ("responsible", attributes::RESPONSIBLE_ID, "Accountable", FilterKind::ListOptional(ValueType::User)),
+    ("author", attributes::AUTHOR_ID, "Author", FilterKind::List(ValueType::User)),
+    ("watcher", attributes::WATCHER_ID, "Watcher", FilterKind::ListOptional(ValueType::User)),
+    ("version", attributes::VERSION_ID, "Version", FilterKind::ListOptional(ValueType::Version)),
+    ("category", attributes::CATEGORY_ID, "Category", FilterKind::ListOptional(ValueType::Category)),
+    ("id", attributes::ID, "ID", FilterKind::List(ValueType::WorkPackage)),
+    ("parent", attributes::PARENT_ID, "Parent", FilterKind::List(ValueType::WorkPackage)),
+    ("subtreeOf", attributes::SUBTREE_OF, "Subtree of", FilterKind::List(ValueType::WorkPackage)),
+    ("subject", attributes::SUBJECT, "Subject", FilterKind::Text),
+    ("description", attributes::DESCRIPTION, "Description", FilterKind::Text),
+    ("comment", attributes::COMMENT, "Comment", FilterKind::Search),
+    ("attachmentFileName", attributes::ATTACHMENT_FILE_NAME, "Attachment file name", FilterKind::Search),
+    ("attachmentContent", attributes::ATTACHMENT_CONTENT, "Attachment content", FilterKind::Search),
+    ("startDate", attributes::START_DATE, "Start date", FilterKind::DateOptional),
+    ("dueDate", attributes::DUE_DATE, "Finish date", FilterKind::DateOptional),
+    ("datesInterval", attributes::DATES_INTERVAL, "Dates interval", FilterKind::DateInterval),
+    ("createdAt", attributes::CREATED_AT, "Created on", FilterKind::Date),
+    ("updatedAt", attributes::UPDATED_AT, "Updated on", FilterKind::Date),
+    ("updatedBy", attributes::UPDATED_BY, "Updated by", FilterKind::List(ValueType::User)),
+    ("latestActivityAt", attributes::LATEST_ACTIVITY_AT, "Latest activity at", FilterKind::Date),
+    ("unseenChanges", attributes::UNSEEN_CHANGES, "Unseen changes", FilterKind::Boolean),
+    ("trashed", attributes::TRASHED, "Trashed", FilterKind::Boolean),
+    ("estimatedTime", attributes::ESTIMATED_HOURS, "Work", FilterKind::Float),
+    ("percentageDone", attributes::DONE_RATIO, "% Complete", FilterKind::Integer),
+];
+
+/// The built-in filters
+pub fn filters() -> Vec<FilterDefinition> {
+    FILTERS
+        .iter()
+        .map(|(name, attribute, title, kind)| FilterDefinition::new(name, attribute, title, *kind))
+        .collect()
+}
+
+/// Built-in filter of a parsed filter's attribute
+pub fn find(attribute: &str) -> Option<FilterDefinition> {
+    filters().into_iter().find(|definition| definition.attribute == attribute)
+}
+
+/// Built-in filter by its API v3 name
+pub fn find_by_name(name: &str) -> Option<FilterDefinition> {
+    filters().into_iter().find(|definition| definition.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_operator_parses() {
+        for definition in filters() {
+            for symbol in definition.kind.operators() {
+                let operator = match symbol {
+                    "t-" | "t+" | "<t-" | ">t-" | "<t+" | ">t+" => format!("{}1", symbol),
+                    symbol => symbol.to_string(),
+                };
+                let parsed = FilterOperator::from_str(&operator).unwrap();
+                assert_eq!(parsed.symbol(), symbol);
+                assert!(definition.allows(&parsed), "{} {}", definition.name, symbol);
+            }
+        }
+    }
+
+    #[test]
+    fn test_values_depend_on_the_operator() {
+        let due_date = find_by_name("dueDate").unwrap();
+        assert_eq!(due_date.attribute, attributes::DUE_DATE);
+        assert_eq!(due_date.kind.values("t-").unwrap().type_name(), "[1]Integer");
+        assert_eq!(due_date.kind.values("<>d").unwrap().type_name(), "[2]Date");
+        assert_eq!(due_date.kind.values("!*"), None);
+
+        let status = find(attributes::STATUS_ID).unwrap();
+        assert_eq!(status.kind.values("=").unwrap().type_name(), "[]Status");
+        assert_eq!(status.kind.values("o"), None);
+        assert!(!status.allows(&FilterOperator::Contains));
+
+        let group = find(attributes::MEMBER_OF_GROUP).unwrap();
+        assert_eq!(group.name, "memberOfGroup");
+        assert_eq!(group.kind.values("=").unwrap().value_type.allowed_values_href(), Some("/api/v3/groups"));
+
+        let updated_by = find_by_name("updatedBy").unwrap();
+        assert_eq!(updated_by.attribute, attributes::UPDATED_BY);
+        assert_eq!(updated_by.kind.values("=").unwrap().type_name(), "[]User");
+        assert_eq!(find(attributes::LATEST_ACTIVITY_AT).unwrap().kind.values("<>d").unwrap().type_name(), "[2]Date");
+        assert_eq!(find_by_name("unseenChanges").unwrap().kind.operators(), vec!["=", "!"]);
+    }
+
+    #[test]
+    fn test_custom_field_filters() {
+        let list = FilterDefinition::custom_field(5, "Severity", "list");
+        assert_eq!(list.name, "customField5");
+        assert_eq!(list.attribute, "cf_5");
+        assert_eq!(list.kind.values("=").unwrap().type_name(), "[]CustomOption");
+
+        let text = FilterDefinition::custom_field(6, "Notes", "text");
+        assert_eq!(text.kind.operators(), vec!["~", "!~"]);
+        assert_eq!(FilterDefinition::custom_field(7, "Due", "date").kind, FilterKind::DateOptional);
+    }
+}
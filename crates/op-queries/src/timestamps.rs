@@ -0,0 +1,298 @@
+//! Query Timestamps
+//!
+//! Mirrors: app/models/queries/timestamps and Timestamp (lib/timestamp.rb)
+//!
+//! A query can be evaluated at several points in time for baseline
+//! comparison. Timestamps are given as a comma-separated list of:
+//!
+//! - ISO 8601 durations relative to now (`PT0S`, `P-1D`, `-P1W`)
+//! - absolute ISO 8601 datetimes (`2024-01-01T10:00:00Z`)
+//! - relative dates at a time of day (`oneWeekAgo@12:00+00:00`)
+
+use std::fmt;
+
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use thiserror::Error;
+
+/// Timestamp parsing errors
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TimestampError {
+    #[error("Timestamps must not be empty")]
+    Empty,
+
+    #[error("'{0}' is not a valid timestamp")]
+    Invalid(String),
+}
+
+/// Named relative dates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeDate {
+    OneDayAgo,
+    LastWorkingDay,
+    OneWeekAgo,
+    OneMonthAgo,
+}
+
+impl RelativeDate {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "oneDayAgo" => Some(Self::OneDayAgo),
+            "lastWorkingDay" => Some(Self::LastWorkingDay),
+            "oneWeekAgo" => Some(Self::OneWeekAgo),
+            "oneMonthAgo" => Some(Self::OneMonthAgo),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::OneDayAgo => "oneDayAgo",
+            Self::LastWorkingDay => "lastWorkingDay",
+            Self::OneWeekAgo => "oneWeekAgo",
+            Self::OneMonthAgo => "oneMonthAgo",
+        }
+    }
+
+    /// Date relative to `today`; working days are Monday to Friday
+    pub fn date_from(&self, today: NaiveDate) -> NaiveDate {
+        match self {
+            Self::OneDayAgo => today - Duration::days(1),
+            Self::OneWeekAgo => today - Duration::days(7),
+            Self::OneMonthAgo => today.checked_sub_months(Months::new(1)).unwrap_or(today),
+            Self::LastWorkingDay => {
+                let mut date = today - Duration::days(1);
+                while matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+                    date -= Duration::days(1);
+                }
+                date
+            }
+        }
+    }
+}
+
+/// A single point in time the query is evaluated at
+#[derive(Debug, Clone, PartialEq)]
+pub enum Timestamp {
+    /// Offset from now, as given in ISO 8601 (`PT0S` is now)
+    Duration { iso: String, offset: Duration },
+    /// Fixed point in time
+    Absolute(DateTime<Utc>),
+    /// Named date at a time of day in a fixed offset
+    Relative {
+        date: RelativeDate,
+        time: NaiveTime,
+        offset: FixedOffset,
+    },
+}
+
+impl Timestamp {
+    /// The current time
+    pub fn now() -> Self {
+        Self::Duration {
+            iso: "PT0S".to_string(),
+            offset: Duration::zero(),
+        }
+    }
+
+    /// Parse a single timestamp
+    pub fn parse(input: &str) -> Result<Self, TimestampError> {
+        let input = input.trim();
+        let invalid = || TimestampError::Invalid(input.to_string());
+
+        if input.starts_with('P') || input.starts_with("-P") {
+            let negative = input.contains('-');
+            let hours = op_core::duration::parse_iso8601_duration(&input.replace('-', ""))
+                .map_err(|_| invalid())?;
+            let seconds = (hours * 3600.0).round() as i64;
+            return Ok(Self::Duration {
+                iso: input.to_string(),
+                offset: Duration::seconds(if negative { -seconds } else { seconds }),
+            });
+        }
+
+        if let Some((name, time)) = input.split_once('@') {
+            let date = RelativeDate::parse(name).ok_or_else(invalid)?;
+            // "12:00+00:00" - time of day followed by a UTC offset
+            let split = time.rfind(['+', '-']).ok_or_else(invalid)?;
+            let (time, offset) = time.split_at(split);
+            let time = NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| invalid())?;
+            let offset = DateTime::parse_from_rfc3339(&format!("2000-01-01T00:00:00{}", offset))
+                .map_err(|_| invalid())?
+                .timezone();
+            return Ok(Self::Relative { date, time, offset });
+        }
+
+        DateTime::parse_from_rfc3339(input)
+            .map(|dt| Self::Absolute(dt.with_timezone(&Utc)))
+            .map_err(|_| invalid())
+    }
+
+    /// Whether this timestamp always refers to the current time
+    pub fn is_now(&self) -> bool {
+        matches!(self, Self::Duration { offset, .. } if offset.is_zero())
+    }
+
+    /// Resolve to a point in time relative to `now`
+    pub fn resolve(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::Duration { offset, .. } => now + *offset,
+            Self::Absolute(at) => *at,
+            Self::Relative { date, time, offset } => {
+                let today = now.with_timezone(offset).date_naive();
+                let local = date.date_from(today).and_time(*time);
+                offset
+                    .from_local_datetime(&local)
+                    .single()
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or(now)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duration { iso, .. } => write!(f, "{}", iso),
+            Self::Absolute(at) => write!(f, "{}", at.to_rfc3339()),
+            Self::Relative { date, time, offset } => {
+                write!(f, "{}@{}{}", date.as_str(), time.format("%H:%M"), offset)
+            }
+        }
+    }
+}
+
+/// Timestamps a query is evaluated at; the first is the baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timestamps(Vec<Timestamp>);
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Self(vec![Timestamp::now()])
+    }
+}
+
+impl Timestamps {
+    pub fn new(timestamps: Vec<Timestamp>) -> Self {
+        if timestamps.is_empty() {
+            Self::default()
+        } else {
+            Self(timestamps)
+        }
+    }
+
+    /// Parse a comma-separated list as stored in `queries.timestamps`
+    pub fn parse(input: &str) -> Result<Self, TimestampError> {
+        let parts: Vec<&str> = input.split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+        if parts.is_empty() {
+            return Err(TimestampError::Empty);
+        }
+        parts.into_iter().map(Timestamp::parse).collect::<Result<Vec<_>, _>>().map(Self)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Timestamp> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The earliest-listed timestamp results are compared against
+    pub fn baseline(&self) -> &Timestamp {
+        &self.0[0]
+    }
+
+    /// Whether any timestamp refers to the past, requiring journal lookups
+    pub fn is_historic(&self) -> bool {
+        self.0.iter().any(|t| !t.is_now())
+    }
+
+    /// Timestamps other than the current time
+    pub fn historic(&self) -> impl Iterator<Item = &Timestamp> {
+        self.0.iter().filter(|t| !t.is_now())
+    }
+}
+
+impl fmt::Display for Timestamps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter().map(|t| t.to_string()).collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        // Wednesday
+        Utc.with_ymd_and_hms(2024, 5, 15, 9, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_durations() {
+        let ts = Timestamp::parse("PT0S").unwrap();
+        assert!(ts.is_now());
+        assert_eq!(ts.resolve(now()), now());
+
+        let ts = Timestamp::parse("P-1D").unwrap();
+        assert!(!ts.is_now());
+        assert_eq!(ts.resolve(now()), now() - Duration::days(1));
+
+        let ts = Timestamp::parse("-P1W").unwrap();
+        assert_eq!(ts.resolve(now()), now() - Duration::days(7));
+        assert_eq!(ts.to_string(), "-P1W");
+    }
+
+    #[test]
+    fn test_parse_absolute() {
+        let ts = Timestamp::parse("2024-01-01T10:00:00+02:00").unwrap();
+        assert_eq!(ts.resolve(now()), Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        let ts = Timestamp::parse("oneWeekAgo@12:00+00:00").unwrap();
+        assert_eq!(ts.resolve(now()), Utc.with_ymd_and_hms(2024, 5, 8, 12, 0, 0).unwrap());
+        assert_eq!(ts.to_string(), "oneWeekAgo@12:00+00:00");
+
+        let ts = Timestamp::parse("oneDayAgo@08:00+02:00").unwrap();
+        assert_eq!(ts.resolve(now()), Utc.with_ymd_and_hms(2024, 5, 14, 6, 0, 0).unwrap());
+
+        let ts = Timestamp::parse("oneMonthAgo@00:00-05:00").unwrap();
+        assert_eq!(ts.resolve(now()), Utc.with_ymd_and_hms(2024, 4, 15, 5, 0, 0).unwrap());
+
+        // Monday → previous Friday
+        let monday = Utc.with_ymd_and_hms(2024, 5, 13, 9, 0, 0).unwrap();
+        let ts = Timestamp::parse("lastWorkingDay@17:00+00:00").unwrap();
+        assert_eq!(ts.resolve(monday), Utc.with_ymd_and_hms(2024, 5, 10, 17, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for invalid in ["", "yesterday", "P1Y", "someDayAgo@12:00+00:00", "oneDayAgo@25:00+00:00", "oneDayAgo@12:00"] {
+            assert!(Timestamp::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!(Timestamps::parse(" , "), Err(TimestampError::Empty));
+    }
+
+    #[test]
+    fn test_timestamps_list() {
+        let ts = Timestamps::parse("oneWeekAgo@12:00+00:00, PT0S").unwrap();
+        assert_eq!(ts.len(), 2);
+        assert!(ts.is_historic());
+        assert_eq!(ts.baseline().to_string(), "oneWeekAgo@12:00+00:00");
+        assert_eq!(ts.historic().count(), 1);
+        assert_eq!(ts.to_string(), "oneWeekAgo@12:00+00:00,PT0S");
+
+        assert!(!Timestamps::default().is_historic());
+    }
+}
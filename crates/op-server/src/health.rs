@@ -1,17 +1,27 @@
 //! Health Check System
 //!
-//! Provides comprehensive health checks for all system components.
+//! Provides comprehensive health checks for all system components, and
+//! tracks their status over time: transitions are logged once a check has
+//! reported the new status several times in a row, and other components
+//! can subscribe to them.
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use async_trait::async_trait;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::Json;
+use op_db::{IntegrityOptions, IntegrityReport, IntegrityRepository};
+use op_notifications::EmailThrottle;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use tokio::sync::RwLock;
-use tracing::debug;
+use tokio::sync::{watch, RwLock};
+use tracing::{debug, info, warn};
+
+/// Interval of the integrity audits run for the health check
+pub const INTEGRITY_AUDIT_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 /// Health check status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -26,6 +36,25 @@ impl HealthStatus {
     pub fn is_healthy(&self) -> bool {
         matches!(self, Self::Healthy | Self::Degraded)
     }
+
+    /// The worse of two statuses
+    fn worst(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Unhealthy, _) | (_, Self::Unhealthy) => Self::Unhealthy,
+            (Self::Degraded, _) | (_, Self::Degraded) => Self::Degraded,
+            _ => Self::Healthy,
+        }
+    }
+}
+
+impl std::fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Healthy => "healthy",
+            Self::Degraded => "degraded",
+            Self::Unhealthy => "unhealthy",
+        })
+    }
 }
 
 /// Individual component health
@@ -67,6 +96,10 @@ pub struct HealthConfig {
     pub check_timeout: Duration,
     /// Cache duration for health results
     pub cache_duration: Duration,
+    /// Interval of the checks run by [`HealthChecker::monitor`]
+    pub check_interval: Duration,
+    /// Consecutive results with a new status before a transition is reported
+    pub transition_threshold: u32,
 }
 
 impl Default for HealthConfig {
@@ -74,10 +107,74 @@ impl Default for HealthConfig {
         Self {
             check_timeout: Duration::from_secs(5),
             cache_duration: Duration::from_secs(10),
+            check_interval: Duration::from_secs(15),
+            transition_threshold: 3,
         }
     }
 }
 
+/// Additional component check run along with the built-in ones
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    async fn check(&self) -> ComponentHealth;
+}
+
+/// Reported change of a check's status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthTransition {
+    pub check: String,
+    pub from: HealthStatus,
+    pub to: HealthStatus,
+    /// Message of the result that completed the transition
+    pub message: Option<String>,
+}
+
+/// Debounced status of one check
+#[derive(Debug)]
+struct CheckState {
+    reported: HealthStatus,
+    /// Status differing from the reported one, with the number of
+    /// consecutive results that had it
+    pending: Option<(HealthStatus, u32)>,
+}
+
+impl CheckState {
+    fn new() -> Self {
+        Self {
+            reported: HealthStatus::Healthy,
+            pending: None,
+        }
+    }
+
+    /// Record a result; returns the previous status once `threshold`
+    /// consecutive results agree on a new one
+    fn observe(&mut self, status: HealthStatus, threshold: u32) -> Option<HealthStatus> {
+        if status == self.reported {
+            self.pending = None;
+            return None;
+        }
+
+        let count = match self.pending {
+            Some((pending, count)) if pending == status => count + 1,
+            _ => 1,
+        };
+        if count < threshold.max(1) {
+            self.pending = Some((status, count));
+            return None;
+        }
+
+        self.pending = None;
+        Some(std::mem::replace(&mut self.reported, status))
+    }
+}
+
+/// Flag following the reported status of one check
+struct Subscriber {
+    check: String,
+    condition: Box<dyn Fn(HealthStatus) -> bool + Send + Sync>,
+    flag: watch::Sender<bool>,
+}
+
 /// Cached health result
 struct CachedHealth {
     report: HealthReport,
@@ -91,6 +188,9 @@ pub struct HealthChecker {
     cache: RwLock<Option<CachedHealth>>,
     // Database pool for health checks
     pool: Option<PgPool>,
+    checks: Vec<Arc<dyn HealthCheck>>,
+    states: Mutex<HashMap<String, CheckState>>,
+    subscribers: Mutex<Vec<Subscriber>>,
 }
 
 impl HealthChecker {
@@ -100,6 +200,9 @@ impl HealthChecker {
             start_time: Instant::now(),
             cache: RwLock::new(None),
             pool: None,
+            checks: Vec::new(),
+            states: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(Vec::new()),
         }
     }
 
@@ -113,6 +216,54 @@ impl HealthChecker {
         self
     }
 
+    /// Report outbound email as degraded while sending is paused
+    pub fn with_email_throttle(self, throttle: Arc<EmailThrottle>) -> Self {
+        self.with_check(Arc::new(EmailCheck { throttle }))
+    }
+
+    /// Report the orphaned rows found by an integrity audit, rerun once the
+    /// last one is older than `interval`
+    pub fn with_integrity_audit(self, pool: PgPool, interval: Duration) -> Self {
+        self.with_check(Arc::new(IntegrityCheck {
+            repository: IntegrityRepository::new(pool),
+            interval,
+            last: tokio::sync::Mutex::new(None),
+        }))
+    }
+
+    /// Run an additional check
+    pub fn with_check(mut self, check: Arc<dyn HealthCheck>) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// Flag set while the reported status of `check` meets `condition`
+    ///
+    /// The flag changes with the debounced transitions, e.g.
+    /// `subscribe("database", |status| status == HealthStatus::Unhealthy)`
+    /// to pause work needing the database.
+    pub fn subscribe(
+        &self,
+        check: impl Into<String>,
+        condition: impl Fn(HealthStatus) -> bool + Send + Sync + 'static,
+    ) -> watch::Receiver<bool> {
+        let check = check.into();
+        let reported = self
+            .states
+            .lock()
+            .unwrap()
+            .get(&check)
+            .map_or(HealthStatus::Healthy, |state| state.reported);
+
+        let (flag, receiver) = watch::channel(condition(reported));
+        self.subscribers.lock().unwrap().push(Subscriber {
+            check,
+            condition: Box::new(condition),
+            flag,
+        });
+        receiver
+    }
+
     /// Get cached health or perform checks
     pub async fn check(&self) -> HealthReport {
         // Check cache first
@@ -126,8 +277,13 @@ impl HealthChecker {
             }
         }
 
-        // Perform checks
+        self.refresh().await
+    }
+
+    /// Perform the checks, bypassing the cache
+    pub async fn refresh(&self) -> HealthReport {
         let report = self.perform_checks().await;
+        self.track(&report.components);
 
         // Update cache
         {
@@ -141,6 +297,65 @@ impl HealthChecker {
         report
     }
 
+    /// Run the checks every `check_interval` so transitions are noticed
+    /// without health requests
+    pub async fn monitor(self: Arc<Self>, mut shutdown: watch::Receiver<bool>) {
+        let mut interval = tokio::time::interval(self.config.check_interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.refresh().await;
+                }
+                _ = shutdown.changed() => break,
+            }
+        }
+    }
+
+    /// Record the results of the checks, reporting debounced transitions
+    fn track(&self, components: &[ComponentHealth]) -> Vec<HealthTransition> {
+        let transitions: Vec<HealthTransition> = {
+            let mut states = self.states.lock().unwrap();
+            components
+                .iter()
+                .filter_map(|component| {
+                    let from = states
+                        .entry(component.name.clone())
+                        .or_insert_with(CheckState::new)
+                        .observe(component.status, self.config.transition_threshold)?;
+                    Some(HealthTransition {
+                        check: component.name.clone(),
+                        from,
+                        to: component.status,
+                        message: component.message.clone(),
+                    })
+                })
+                .collect()
+        };
+
+        for transition in &transitions {
+            let message = transition.message.as_deref().unwrap_or("");
+            if transition.to == HealthStatus::Healthy {
+                info!(check = %transition.check, from = %transition.from, to = %transition.to, "Health check recovered");
+            } else {
+                warn!(
+                    check = %transition.check,
+                    from = %transition.from,
+                    to = %transition.to,
+                    error = message,
+                    "Health check status changed"
+                );
+            }
+
+            for subscriber in self.subscribers.lock().unwrap().iter() {
+                if subscriber.check == transition.check {
+                    subscriber.flag.send_replace((subscriber.condition)(transition.to));
+                }
+            }
+        }
+
+        transitions
+    }
+
     async fn perform_checks(&self) -> HealthReport {
         let mut components = Vec::new();
         let mut overall_status = HealthStatus::Healthy;
@@ -172,6 +387,13 @@ impl HealthChecker {
         }
         components.push(disk_health);
 
+        // Additional checks, e.g. outbound email
+        for check in &self.checks {
+            let health = check.check().await;
+            overall_status = overall_status.worst(health.status);
+            components.push(health);
+        }
+
         HealthReport {
             status: overall_status,
             version: env!("CARGO_PKG_VERSION").to_string(),
@@ -263,11 +485,105 @@ impl HealthChecker {
     }
 }
 
+/// Outbound email, degraded while the throttle pauses sending
+struct EmailCheck {
+    throttle: Arc<EmailThrottle>,
+}
+
+#[async_trait]
+impl HealthCheck for EmailCheck {
+    async fn check(&self) -> ComponentHealth {
+        let start = Instant::now();
+        let status = self.throttle.status();
+
+        let (health, message) = match status.paused_until {
+            Some(until) => (
+                HealthStatus::Degraded,
+                format!("Sending paused after repeated failures until {}", until.to_rfc3339()),
+            ),
+            None => (HealthStatus::Healthy, "Sending".to_string()),
+        };
+
+        ComponentHealth {
+            name: "email".to_string(),
+            status: health,
+            message: Some(message),
+            response_time_ms: start.elapsed().as_millis() as u64,
+            details: serde_json::to_value(&status).ok(),
+        }
+    }
+}
+
+/// Orphaned rows found by the last integrity audit; orphans do not impair
+/// serving, so only a failing audit degrades the check
+struct IntegrityCheck {
+    repository: IntegrityRepository,
+    interval: Duration,
+    last: tokio::sync::Mutex<Option<(Instant, Result<IntegrityReport, String>)>>,
+}
+
+#[async_trait]
+impl HealthCheck for IntegrityCheck {
+    async fn check(&self) -> ComponentHealth {
+        let start = Instant::now();
+        let mut last = self.last.lock().await;
+        if last.as_ref().is_none_or(|(audited_at, _)| audited_at.elapsed() >= self.interval) {
+            let options = IntegrityOptions {
+                sample_size: 0,
+                ..Default::default()
+            };
+            let result = self.repository.audit(options).await.map_err(|e| e.to_string());
+            *last = Some((Instant::now(), result));
+        }
+        let (_, result) = last.as_ref().expect("audited above");
+
+        let (status, message, details) = integrity_health(result);
+        ComponentHealth {
+            name: "integrity".to_string(),
+            status,
+            message: Some(message),
+            response_time_ms: start.elapsed().as_millis() as u64,
+            details,
+        }
+    }
+}
+
+/// Status, message and details of an integrity audit's result, with the
+/// orphan count as a gauge and the counts of the checks finding any
+fn integrity_health(result: &Result<IntegrityReport, String>) -> (HealthStatus, String, Option<serde_json::Value>) {
+    match result {
+        Ok(report) => {
+            let checks: serde_json::Map<String, serde_json::Value> = report
+                .checks
+                .iter()
+                .filter(|count| count.orphans > 0)
+                .map(|count| (count.check.clone(), count.orphans.into()))
+                .collect();
+            let failed: Vec<&str> = report.errors().map(|count| count.check.as_str()).collect();
+            let status = if failed.is_empty() { HealthStatus::Healthy } else { HealthStatus::Degraded };
+            (
+                status,
+                format!("{} orphaned rows", report.orphans),
+                Some(serde_json::json!({
+                    "orphans": report.orphans,
+                    "checks": checks,
+                    "failed_checks": failed,
+                })),
+            )
+        }
+        Err(e) => (HealthStatus::Degraded, format!("Integrity audit failed: {}", e), None),
+    }
+}
+
 /// Application state containing health checker and database pool
 pub struct AppState {
     pub health: Arc<HealthChecker>,
     pub config: op_core::config::AppConfig,
+    /// Problems found validating the configuration at startup
+    pub config_warnings: Vec<op_core::config::Warning>,
     pub db: Option<PgPool>,
+    /// Maintenance mode blocking writes while active
+    pub maintenance: Arc<op_api::MaintenanceMode>,
 }
 
 /// Simple liveness check (Kubernetes)
@@ -282,11 +598,20 @@ pub async fn readiness(State(state): State<Arc<AppState>>) -> (StatusCode, Json<
     (status, Json(report))
 }
 
+/// Health report along with the configuration warnings
+#[derive(Debug, Serialize)]
+pub struct FullHealthReport {
+    #[serde(flatten)]
+    pub report: HealthReport,
+    pub config_warnings: Vec<op_core::config::Warning>,
+}
+
 /// Full health check
-pub async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthReport>) {
+pub async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<FullHealthReport>) {
     let report = state.health.check().await;
     let status = report.http_status();
-    (status, Json(report))
+    let config_warnings = state.config_warnings.clone();
+    (status, Json(FullHealthReport { report, config_warnings }))
 }
 
 /// OpenProject-style health check (simple OK response)
@@ -307,6 +632,107 @@ mod tests {
         assert!(!report.components.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_paused_email_degrades_health() {
+        use op_core::config::EmailSendLimits;
+        use op_notifications::email::EmailError;
+
+        let throttle = Arc::new(EmailThrottle::new(EmailSendLimits {
+            failure_threshold: 1,
+            ..Default::default()
+        }));
+        let checker = HealthChecker::new(HealthConfig {
+            cache_duration: Duration::ZERO,
+            ..Default::default()
+        })
+        .with_email_throttle(throttle.clone());
+
+        let report = checker.check().await;
+        assert_eq!(report.status, HealthStatus::Healthy);
+
+        throttle.record_failure(&EmailError::SmtpError("connection refused".into())).await;
+        let report = checker.check().await;
+        assert_eq!(report.status, HealthStatus::Degraded);
+        let email = report.components.iter().find(|c| c.name == "email").unwrap();
+        assert_eq!(email.status, HealthStatus::Degraded);
+    }
+
+    /// Check reporting the given statuses in turn
+    struct FlappingCheck {
+        statuses: Mutex<std::collections::VecDeque<HealthStatus>>,
+    }
+
+    impl FlappingCheck {
+        fn new(statuses: &[HealthStatus]) -> Self {
+            Self {
+                statuses: Mutex::new(statuses.iter().copied().collect()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for FlappingCheck {
+        async fn check(&self) -> ComponentHealth {
+            let status = self.statuses.lock().unwrap().pop_front().unwrap_or(HealthStatus::Healthy);
+            ComponentHealth {
+                name: "flapping".to_string(),
+                status,
+                message: Some(format!("Reported {}", status)),
+                response_time_ms: 0,
+                details: None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_transitions_are_debounced() {
+        use HealthStatus::*;
+
+        let mut state = CheckState::new();
+        let transitions: Vec<(HealthStatus, HealthStatus)> = [Unhealthy, Healthy, Unhealthy, Unhealthy, Unhealthy, Degraded, Healthy, Healthy]
+            .into_iter()
+            .filter_map(|status| state.observe(status, 2).map(|from| (from, status)))
+            .collect();
+
+        assert_eq!(transitions, vec![(Healthy, Unhealthy), (Unhealthy, Healthy)]);
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_follow_debounced_transitions() {
+        use HealthStatus::*;
+
+        let sequence = [Degraded, Healthy, Degraded, Degraded, Unhealthy, Unhealthy, Healthy, Healthy];
+        let checker = HealthChecker::new(HealthConfig {
+            cache_duration: Duration::ZERO,
+            transition_threshold: 2,
+            ..Default::default()
+        })
+        .with_check(Arc::new(FlappingCheck::new(&sequence)));
+        let impaired = checker.subscribe("flapping", |status| status != Healthy);
+        let down = checker.subscribe("flapping", |status| status == Unhealthy);
+
+        let mut flags = Vec::new();
+        for status in sequence {
+            let report = checker.refresh().await;
+            assert_eq!(report.status, status);
+            flags.push((*impaired.borrow(), *down.borrow()));
+        }
+
+        assert_eq!(
+            flags,
+            vec![
+                (false, false),
+                (false, false),
+                (false, false),
+                (true, false),
+                (true, false),
+                (true, true),
+                (true, true),
+                (false, false),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_health_cache() {
         let checker = HealthChecker::new(HealthConfig {
@@ -321,6 +747,42 @@ mod tests {
         assert_eq!(report1.timestamp, report2.timestamp);
     }
 
+    #[test]
+    fn test_integrity_health_reports_the_orphan_count() {
+        use op_db::{OrphanAction, OrphanCount};
+
+        let count = |check: &str, orphans: i64, error: Option<&str>| OrphanCount {
+            check: check.into(),
+            references: "work_packages",
+            action: OrphanAction::Delete,
+            orphans,
+            sample_ids: Vec::new(),
+            repaired: 0,
+            error: error.map(str::to_string),
+        };
+        let mut report = IntegrityReport {
+            dry_run: true,
+            orphans: 3,
+            repaired: 0,
+            checks: vec![count("journals.journable_id[WorkPackage]", 3, None), count("relations.from_id", 0, None)],
+        };
+
+        let (status, message, details) = integrity_health(&Ok(report.clone()));
+        assert_eq!(status, HealthStatus::Healthy);
+        assert_eq!(message, "3 orphaned rows");
+        let details = details.unwrap();
+        assert_eq!(details["orphans"], 3);
+        assert_eq!(details["checks"], serde_json::json!({ "journals.journable_id[WorkPackage]": 3 }));
+
+        report.checks.push(count("watchers.watchable_id[WorkPackage]", 0, Some("relation does not exist")));
+        let (status, _, details) = integrity_health(&Ok(report));
+        assert_eq!(status, HealthStatus::Degraded);
+        assert_eq!(details.unwrap()["failed_checks"], serde_json::json!(["watchers.watchable_id[WorkPackage]"]));
+
+        let (status, _, _) = integrity_health(&Err("connection refused".into()));
+        assert_eq!(status, HealthStatus::Degraded);
+    }
+
     #[test]
     fn test_health_status_http() {
         let healthy = HealthReport {
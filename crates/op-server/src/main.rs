@@ -1,6 +1,11 @@
 //! OpenProject RS Server
 //!
 //! Production-ready HTTP server for OpenProject Rust implementation.
+//!
+//! `op-server --seed demo` prepares the schema, seeds the basic and demo
+//! data and exits instead of serving. `op-server --audit-integrity` prints
+//! a JSON report of the rows referencing missing rows and exits; with
+//! `--repair` the orphans are repaired as well.
 
 use std::sync::Arc;
 
@@ -12,19 +17,31 @@ use axum::{
 use tower::ServiceBuilder;
 use tower_http::{
     compression::CompressionLayer,
-    cors::{Any, CorsLayer},
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer},
     trace::TraceLayer,
 };
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use op_core::config::AppConfig;
-use op_db::{Database, DatabaseConfig};
+use op_api::maintenance::{MaintenanceMode, MAINTENANCE_REFRESH_INTERVAL};
+use op_attachments::{AttachmentConfig, AttachmentService, LocalStorage, PgAttachmentStore, PgUploadSessionStore};
+use op_core::config::{fatal_report, AppConfig, CorsConfig, SchemaMode};
+use op_db::{Database, DatabaseConfig, IntegrityOptions, IntegrityRepository};
+use op_notifications::jobs::JobWorker;
+use op_notifications::{MemoryJobQueue, Scheduler};
+use op_services::scheduled_jobs::{
+    default_schedules, CleanupOrphanAttachmentsJob, PgScheduleStore, PurgeWorkPackageTrashJob,
+    RebuildWorkPackageSummariesJob, CLEANUP_ORPHAN_ATTACHMENTS_JOB, PURGE_WORK_PACKAGE_TRASH_JOB,
+    REBUILD_WORK_PACKAGE_SUMMARIES_JOB,
+};
+use op_services::seeds::DemoSeeder;
+use sqlx::PgPool;
+use tokio::sync::watch;
 
 mod health;
 mod metrics;
 
-use health::{AppState, HealthChecker, HealthConfig};
+use health::{AppState, HealthChecker, HealthConfig, HealthStatus, INTEGRITY_AUDIT_INTERVAL};
 use metrics::Metrics;
 
 #[tokio::main]
@@ -34,10 +51,18 @@ async fn main() -> anyhow::Result<()> {
 
     // Load configuration
     dotenvy::dotenv().ok();
-    let config = AppConfig::from_env().unwrap_or_else(|e| {
-        tracing::warn!("Failed to load config from env: {}, using defaults", e);
-        AppConfig::default()
-    });
+    let config = AppConfig::from_env()?;
+    let config_warnings = match config.validate() {
+        Ok(warnings) => warnings,
+        Err(errors) => {
+            let report = fatal_report(&errors);
+            tracing::error!("{}", report);
+            anyhow::bail!(report);
+        }
+    };
+    for warning in &config_warnings {
+        tracing::warn!(key = %warning.key, "Configuration: {}", warning);
+    }
 
     info!(
         version = env!("CARGO_PKG_VERSION"),
@@ -58,18 +83,60 @@ async fn main() -> anyhow::Result<()> {
             None
         }
     };
+    if let Some(ref db) = db {
+        prepare_schema(db, config.database.schema).await?;
+    }
+
+    if let Some(data) = seed_argument(std::env::args()) {
+        anyhow::ensure!(data == "demo", "Unknown seed data {:?}, expected \"demo\"", data);
+        let db = db.ok_or_else(|| anyhow::anyhow!("Seeding needs a database"))?;
+        let report = DemoSeeder::new(db.pool().clone()).call().await?;
+        info!(%report, "Seeded demo data");
+        return Ok(());
+    }
+
+    if let Some(options) = integrity_arguments(std::env::args()) {
+        let db = db.ok_or_else(|| anyhow::anyhow!("The integrity audit needs a database"))?;
+        let report = IntegrityRepository::new(db.pool().clone()).audit(options).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
 
     // Initialize components
     let metrics = Arc::new(Metrics::new());
-    let mut health_checker = HealthChecker::new(HealthConfig::default());
+    let email_throttle = Arc::new(op_notifications::EmailThrottle::new(config.email.send_limits.clone()));
+    let mut health_checker = HealthChecker::new(HealthConfig::default()).with_email_throttle(email_throttle);
+    if let Some(ref db) = db {
+        health_checker = health_checker
+            .with_pool(db.pool().clone())
+            .with_integrity_audit(db.pool().clone(), INTEGRITY_AUDIT_INTERVAL);
+    }
+
+    let health_checker = Arc::new(health_checker);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(health_checker.clone().monitor(shutdown_rx.clone()));
+
+    // Maintenance mode, following toggles of other instances
+    let maintenance = Arc::new(match db {
+        Some(ref db) => MaintenanceMode::with_pool(db.pool().clone()),
+        None => MaintenanceMode::new(),
+    });
+    if let Err(e) = maintenance.reload().await {
+        tracing::warn!("Failed to load the maintenance mode: {}", e);
+    }
+    tokio::spawn(maintenance.clone().monitor(MAINTENANCE_REFRESH_INTERVAL, shutdown_rx.clone()));
+
+    // Recurring jobs
     if let Some(ref db) = db {
-        health_checker = health_checker.with_pool(db.pool().clone());
+        spawn_scheduled_jobs(&config, db.pool().clone(), &health_checker, &maintenance, shutdown_rx);
     }
 
     let app_state = Arc::new(AppState {
-        health: Arc::new(health_checker),
+        health: health_checker,
         config: config.clone(),
+        config_warnings,
         db: db.map(|d| d.pool().clone()),
+        maintenance,
     });
 
     // Build router
@@ -83,6 +150,7 @@ async fn main() -> anyhow::Result<()> {
     axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
         .await?;
+    shutdown_tx.send(true).ok();
 
     info!("Server shutdown complete");
     Ok(())
@@ -106,6 +174,106 @@ fn init_tracing() {
         .init();
 }
 
+/// Value of the `--seed` argument, e.g. `demo` for `--seed demo` or
+/// `--seed=demo`
+fn seed_argument(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            return Some(args.next().unwrap_or_default());
+        }
+        if let Some(value) = arg.strip_prefix("--seed=") {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// Options of `--audit-integrity`, repairing with `--repair`
+fn integrity_arguments(args: impl IntoIterator<Item = String>) -> Option<IntegrityOptions> {
+    let args: Vec<String> = args.into_iter().collect();
+    args.iter().any(|arg| arg == "--audit-integrity").then(|| IntegrityOptions {
+        repair: args.iter().any(|arg| arg == "--repair"),
+        ..Default::default()
+    })
+}
+
+/// Migrate the schema, or report how a Rails-managed one differs from
+/// what op-rs expects
+async fn prepare_schema(db: &Database, mode: SchemaMode) -> anyhow::Result<()> {
+    match mode {
+        SchemaMode::Migrate => db.migrate().await?,
+        SchemaMode::Check => {
+            let report = db.check_schema().await?;
+            if report.is_compatible() {
+                info!("Database schema is compatible");
+            } else {
+                for mismatch in &report.mismatches {
+                    tracing::error!(%mismatch, "Database schema mismatch");
+                }
+                tracing::error!(
+                    "{} database schema mismatches; the features using them will fail. \
+                     Set DATABASE_SCHEMA_MODE=migrate to let op-rs manage the schema.",
+                    report.mismatches.len()
+                );
+            }
+        }
+        SchemaMode::Skip => {}
+    }
+    Ok(())
+}
+
+/// Start the scheduler enqueuing the recurring jobs and the worker running
+/// the ones handled in-process; the worker pauses while the database is
+/// unhealthy or the instance is in maintenance
+fn spawn_scheduled_jobs(
+    config: &AppConfig,
+    pool: PgPool,
+    health: &HealthChecker,
+    maintenance: &MaintenanceMode,
+    shutdown: watch::Receiver<bool>,
+) {
+    let timezone = config.instance.timezone.parse::<chrono_tz::Tz>().unwrap_or_else(|_| {
+        tracing::warn!(timezone = %config.instance.timezone, "Unknown instance timezone, scheduling in UTC");
+        chrono_tz::UTC
+    });
+
+    let queue = Arc::new(MemoryJobQueue::new());
+    let mut scheduler = Scheduler::new(queue.clone(), Arc::new(PgScheduleStore::new(pool.clone())), timezone);
+    // Mails are sent by the workers handling them, so only the jobs this
+    // process runs are scheduled here
+    for job in default_schedules()
+        .into_iter()
+        .filter(|job| {
+            [CLEANUP_ORPHAN_ATTACHMENTS_JOB, REBUILD_WORK_PACKAGE_SUMMARIES_JOB, PURGE_WORK_PACKAGE_TRASH_JOB]
+                .contains(&job.job_type.as_str())
+        })
+    {
+        scheduler.register(job);
+    }
+
+    let summaries = RebuildWorkPackageSummariesJob::new(pool.clone());
+    let attachments = AttachmentService::new(
+        Arc::new(PgAttachmentStore::new(pool.clone())),
+        Arc::new(LocalStorage::new(&config.storage.local_path, "/attachments")),
+        AttachmentConfig::default(),
+    )
+    .with_upload_sessions(Arc::new(PgUploadSessionStore::new(pool.clone())));
+    let attachments = Arc::new(attachments);
+    let trash = PurgeWorkPackageTrashJob::new(pool, attachments.clone(), config.instance.work_package_trash_retention_days);
+    let mut worker = JobWorker::new(queue, "default")
+        .with_pause(health.subscribe("database", |status| status == HealthStatus::Unhealthy))
+        .with_pause(maintenance.paused());
+    worker.register(CLEANUP_ORPHAN_ATTACHMENTS_JOB, CleanupOrphanAttachmentsJob::new(attachments));
+    worker.register(REBUILD_WORK_PACKAGE_SUMMARIES_JOB, summaries);
+    worker.register(PURGE_WORK_PACKAGE_TRASH_JOB, trash);
+
+    info!(%timezone, jobs = scheduler.jobs().len(), "Starting job scheduler");
+    let worker_shutdown = shutdown.clone();
+    tokio::spawn(async move { scheduler.run(shutdown).await });
+    tokio::spawn(async move { worker.run(worker_shutdown).await });
+}
+
 /// Build the application router
 fn build_router(state: Arc<AppState>, metrics: Arc<Metrics>) -> Router {
     // Health check routes (no auth required)
@@ -129,26 +297,65 @@ fn build_router(state: Arc<AppState>, metrics: Arc<Metrics>) -> Router {
         .route("/configuration", get(api_configuration))
         .route("/users/me", get(api_current_user));
 
-    // Main router
-    Router::new()
+    // Main router, under the relative URL root if one is configured
+    let root = op_api::UrlRoot::new(&state.config.server.url_root());
+    let app = Router::new()
         .merge(health_routes)
         .merge(metrics_routes)
         .nest("/api/v3", api_routes)
+        // Links are prefixed before the response is compressed
+        .layer(middleware::from_fn_with_state(root.clone(), op_api::url_root_middleware))
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
                 .layer(CompressionLayer::new())
-                .layer(
-                    CorsLayer::new()
-                        .allow_origin(Any)
-                        .allow_methods(Any)
-                        .allow_headers(Any),
-                ),
+                .layer(cors_layer(&state.config.server.cors)),
         )
+        .layer(middleware::from_fn_with_state(
+            state.maintenance.clone(),
+            op_api::maintenance_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            op_api::QueryBudget::default().with_metrics(metrics.domain.clone()),
+            op_api::query_budget_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             metrics,
             metrics::metrics_middleware,
         ))
+        .layer(middleware::from_fn(op_api::locale_middleware))
+        .layer(middleware::from_fn(op_api::version_header_middleware))
+        .layer(middleware::from_fn(op_api::request_id_middleware));
+    if root.is_empty() {
+        app
+    } else {
+        Router::new().nest(root.as_str(), app)
+    }
+}
+
+/// CORS as configured; credentials are only allowed along with a list of
+/// origins, which the configuration validation enforces
+fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    if config.allows_any_origin() {
+        return CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any);
+    }
+
+    let origins: Vec<_> = config
+        .allowed_origins
+        .iter()
+        .filter_map(|origin| match origin.parse() {
+            Ok(origin) => Some(origin),
+            Err(_) => {
+                tracing::warn!(%origin, "Ignoring invalid CORS origin");
+                None
+            }
+        })
+        .collect();
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(config.allow_credentials)
 }
 
 /// Graceful shutdown signal handler
@@ -202,9 +409,10 @@ async fn api_root() -> Json<serde_json::Value> {
 }
 
 /// API configuration endpoint
-async fn api_configuration() -> Json<serde_json::Value> {
+async fn api_configuration(locale: op_api::RequestLocale) -> Json<serde_json::Value> {
     Json(serde_json::json!({
         "_type": "Configuration",
+        "locale": locale.as_str(),
         "maximumAttachmentFileSize": 256 * 1024 * 1024,
         "perPageOptions": [20, 100],
         "dateFormat": "%Y-%m-%d",
@@ -253,19 +461,76 @@ mod tests {
     use tower::ServiceExt;
 
     fn test_app() -> Router {
-        let metrics = Arc::new(Metrics::new());
+        test_app_with_metrics(Arc::new(Metrics::new()))
+    }
+
+    fn test_app_with_metrics(metrics: Arc<Metrics>) -> Router {
+        test_app_with(metrics, Arc::new(MaintenanceMode::new()))
+    }
+
+    fn test_app_with(metrics: Arc<Metrics>, maintenance: Arc<MaintenanceMode>) -> Router {
         let health_checker = Arc::new(HealthChecker::new(HealthConfig::default()));
         let config = AppConfig::default();
 
         let state = Arc::new(AppState {
             health: health_checker,
             config,
+            config_warnings: Vec::new(),
             db: None,
+            maintenance,
         });
 
         build_router(state, metrics)
     }
 
+    #[tokio::test]
+    async fn test_routes_are_served_under_the_url_root() {
+        let mut config = AppConfig::default();
+        config.server.rails_relative_url_root = Some("/op".into());
+        let state = Arc::new(AppState {
+            health: Arc::new(HealthChecker::new(HealthConfig::default())),
+            config,
+            config_warnings: Vec::new(),
+            db: None,
+            maintenance: Arc::new(MaintenanceMode::new()),
+        });
+        let app = build_router(state, Arc::new(Metrics::new()));
+
+        assert_eq!(status_of(&app, "GET", "/api/v3").await, StatusCode::NOT_FOUND);
+        let request = Request::builder().uri("/op/api/v3").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["_links"]["self"]["href"], "/op/api/v3");
+        assert_eq!(json["_links"]["workPackages"]["href"], "/op/api/v3/work_packages");
+    }
+
+    async fn status_of(app: &Router, method: &str, uri: &str) -> StatusCode {
+        let request = Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[test]
+    fn test_seed_argument() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(seed_argument(args(&["op-server", "--seed", "demo"])), Some("demo".into()));
+        assert_eq!(seed_argument(args(&["op-server", "--seed=demo"])), Some("demo".into()));
+        assert_eq!(seed_argument(args(&["op-server", "--seed"])), Some(String::new()));
+        assert_eq!(seed_argument(args(&["op-server"])), None);
+    }
+
+    #[test]
+    fn test_integrity_arguments() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(integrity_arguments(args(&["op-server"])), None);
+        let options = integrity_arguments(args(&["op-server", "--audit-integrity"])).unwrap();
+        assert!(!options.repair);
+        let options = integrity_arguments(args(&["op-server", "--audit-integrity", "--repair"])).unwrap();
+        assert!(options.repair);
+        assert_eq!(integrity_arguments(args(&["op-server", "--repair"])), None);
+    }
+
     #[tokio::test]
     async fn test_health_endpoint() {
         let app = test_app();
@@ -283,6 +548,61 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_full_health_lists_config_warnings() {
+        let mut config = AppConfig::default();
+        config.server.development = true;
+        let state = Arc::new(AppState {
+            health: Arc::new(HealthChecker::new(HealthConfig::default())),
+            config_warnings: config.validate().unwrap(),
+            config,
+            db: None,
+            maintenance: Arc::new(MaintenanceMode::new()),
+        });
+        let app = build_router(state, Arc::new(Metrics::new()));
+
+        let request = Request::builder().uri("/health/full").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["config_warnings"][0]["key"], "SECRET_KEY_BASE");
+        assert!(json["components"].is_array());
+    }
+
+    #[tokio::test]
+    async fn test_cors_credentials_for_listed_origins() {
+        let mut config = AppConfig::default();
+        config.server.cors = CorsConfig {
+            allowed_origins: vec!["https://op.example.com".into()],
+            allow_credentials: true,
+        };
+        let state = Arc::new(AppState {
+            health: Arc::new(HealthChecker::new(HealthConfig::default())),
+            config,
+            config_warnings: Vec::new(),
+            db: None,
+            maintenance: Arc::new(MaintenanceMode::new()),
+        });
+        let app = build_router(state, Arc::new(Metrics::new()));
+
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method("OPTIONS")
+                .uri("/api/v3")
+                .header("origin", origin)
+                .header("access-control-request-method", "GET")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(preflight("https://op.example.com")).await.unwrap();
+        let headers = response.headers();
+        assert_eq!(headers["access-control-allow-origin"], "https://op.example.com");
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+
+        let response = app.oneshot(preflight("https://elsewhere.example.com")).await.unwrap();
+        assert!(response.headers().get("access-control-allow-origin").is_none());
+    }
+
     #[tokio::test]
     async fn test_api_root() {
         let app = test_app();
@@ -300,6 +620,44 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_configuration_reports_negotiated_locale() {
+        let response = test_app()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v3/configuration")
+                    .header("Accept-Language", "de-CH, fr;q=0.8")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()["content-language"], "de");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["locale"], "de");
+        assert_eq!(body["_type"], "Configuration");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_round_trips() {
+        let app = test_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/health")
+                    .header("X-Request-ID", "trace-me")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()["x-request-id"], "trace-me");
+    }
+
     #[tokio::test]
     async fn test_metrics_endpoint() {
         let app = test_app();
@@ -316,4 +674,183 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_maintenance_blocks_writes_only() {
+        let maintenance = Arc::new(MaintenanceMode::new());
+        let app = test_app_with(Arc::new(Metrics::new()), maintenance.clone());
+        maintenance
+            .set(op_api::MaintenanceState::enabled(Some("Moving storage".into()), None))
+            .await
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(Request::builder().method("PATCH").uri("/api/v3/users/me").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "300");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["errorIdentifier"], "urn:openproject-org:api:v3:errors:ServiceUnavailable");
+        assert!(body["message"].as_str().unwrap().ends_with("Moving storage"));
+
+        assert_eq!(status_of(&app, "GET", "/api/v3/users/me").await, StatusCode::OK);
+        assert_eq!(status_of(&app, "GET", "/health").await, StatusCode::OK);
+
+        // Without maintenance the write reaches the router again
+        maintenance.set(op_api::MaintenanceState::default()).await.unwrap();
+        assert_eq!(status_of(&app, "PATCH", "/api/v3/users/me").await, StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_worker_resumes_after_maintenance() {
+        use op_notifications::jobs::JobWorker;
+        use op_notifications::{Job, JobQueue, MemoryJobQueue};
+
+        let maintenance = MaintenanceMode::new();
+        maintenance.set(op_api::MaintenanceState::enabled(None, None)).await.unwrap();
+
+        let queue = Arc::new(MemoryJobQueue::new());
+        queue.enqueue(Job::new("noop", serde_json::json!({}))).await.unwrap();
+        let mut worker = JobWorker::new(queue.clone(), "default").with_pause(maintenance.paused());
+        worker.register("noop", NoopHandler);
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let running = tokio::spawn(async move { worker.run(shutdown_rx).await });
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(queue.pending_count("default").await.unwrap(), 1);
+
+        maintenance.set(op_api::MaintenanceState::default()).await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while queue.pending_count("default").await.unwrap() > 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        shutdown.send(true).unwrap();
+        running.await.unwrap();
+    }
+
+    struct AdminUser;
+
+    impl op_contracts::base::UserContext for AdminUser {
+        fn id(&self) -> op_core::traits::Id {
+            1
+        }
+
+        fn is_admin(&self) -> bool {
+            true
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, _permission: &str, _project_id: op_core::traits::Id) -> bool {
+            true
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            true
+        }
+    }
+
+    struct NoopHandler;
+
+    #[async_trait::async_trait]
+    impl op_notifications::jobs::JobHandler for NoopHandler {
+        async fn handle(&self, _args: serde_json::Value) -> op_notifications::jobs::JobResult<()> {
+            Ok(())
+        }
+    }
+
+    struct FailingHandler;
+
+    #[async_trait::async_trait]
+    impl op_notifications::jobs::JobHandler for FailingHandler {
+        async fn handle(&self, _args: serde_json::Value) -> op_notifications::jobs::JobResult<()> {
+            Err(op_notifications::JobError::Failed("boom".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_reports_domain_counters() {
+        use op_notifications::email::{EmailAddress, MemoryEmailSender};
+        use op_notifications::jobs::JobWorker;
+        use op_notifications::service::MemoryNotificationStore;
+        use op_notifications::{
+            EmailRenderer, Job, JobQueue, MemoryJobQueue, NotificationReason, NotificationService,
+            NotificationType,
+        };
+        use op_services::work_packages::{CreateWorkPackageService, WorkPackageParams};
+
+        let metrics = Arc::new(Metrics::new());
+        let domain = metrics.domain.clone();
+
+        // Work packages
+        let user = AdminUser;
+        let result = CreateWorkPackageService::new(&user)
+            .with_metrics(&domain)
+            .call(WorkPackageParams::new().with_subject("Metered").with_project_id(1));
+        assert!(result.is_success());
+
+        // Notifications and emails
+        let queue = Arc::new(MemoryJobQueue::new());
+        let service = NotificationService::new(
+            Arc::new(MemoryNotificationStore::new()),
+            queue.clone(),
+            Arc::new(MemoryEmailSender::new()),
+            EmailRenderer::new("http://localhost", EmailAddress::new("noreply@example.com")),
+        )
+        .with_metrics(domain.clone());
+
+        let event = service
+            .notify(
+                2,
+                NotificationType::WorkPackageAssigned,
+                NotificationReason::Assigned,
+                "WorkPackage",
+                1000,
+                Some(1),
+                Some(1),
+            )
+            .await
+            .unwrap();
+        service
+            .send_email(event.notification.id.unwrap(), "user@example.com", None, None)
+            .await
+            .unwrap();
+
+        // Jobs
+        queue.enqueue(Job::new("failing", serde_json::json!({}))).await.unwrap();
+        let mut worker = JobWorker::new(queue.clone(), "default").with_metrics(domain.clone());
+        worker.register("failing", FailingHandler);
+        assert!(worker.process_one().await.unwrap());
+
+        let response = test_app_with_metrics(metrics)
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains("work_packages_created_total 1"));
+        assert!(body.contains("notifications_created_total 1"));
+        assert!(body.contains("emails_sent_total{sender=\"memory\"} 1"));
+        assert!(body.contains("domain_jobs_failed_total 1"));
+        assert!(body.contains("domain_jobs_retried_total 1"));
+    }
 }
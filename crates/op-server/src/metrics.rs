@@ -10,6 +10,7 @@ use axum::extract::State;
 use axum::http::StatusCode;
 use axum::middleware::Next;
 use axum::response::Response;
+use op_core::metrics::DomainMetrics;
 use tracing::{debug, info_span, Instrument};
 
 /// Metrics collector
@@ -33,6 +34,8 @@ pub struct Metrics {
     /// Background jobs
     pub jobs_processed: AtomicU64,
     pub jobs_failed: AtomicU64,
+    /// Domain events and repository timings, shared with services and repositories
+    pub domain: Arc<DomainMetrics>,
     /// Start time for uptime calculation
     start_time: Instant,
 }
@@ -58,6 +61,7 @@ impl Metrics {
             cache_misses: AtomicU64::new(0),
             jobs_processed: AtomicU64::new(0),
             jobs_failed: AtomicU64::new(0),
+            domain: Arc::new(DomainMetrics::new()),
             start_time: Instant::now(),
         }
     }
@@ -212,6 +216,9 @@ impl Metrics {
         output.push_str("# TYPE uptime_seconds gauge\n");
         output.push_str(&format!("uptime_seconds {}\n", self.uptime_seconds()));
 
+        // Domain metrics
+        output.push_str(&self.domain.export_prometheus());
+
         output
     }
 
@@ -238,6 +245,7 @@ impl Metrics {
                 "processed": self.jobs_processed.load(Ordering::Relaxed),
                 "failed": self.jobs_failed.load(Ordering::Relaxed),
             },
+            "domain": self.domain.export_json(),
             "uptime_seconds": self.uptime_seconds(),
         })
     }
@@ -22,6 +22,11 @@ pub trait Callable<Params, Output> {
 pub struct ServiceContext<'a, U: UserContext> {
     pub user: &'a U,
     pub send_notifications: bool,
+    /// Validate without persisting, journaling or notifying
+    pub dry_run: bool,
+    /// Summarize the notifications of a bulk operation or import instead
+    /// of notifying about each work package
+    pub suppress_notifications: bool,
 }
 
 impl<'a, U: UserContext> ServiceContext<'a, U> {
@@ -29,6 +34,8 @@ impl<'a, U: UserContext> ServiceContext<'a, U> {
         Self {
             user,
             send_notifications: true,
+            dry_run: false,
+            suppress_notifications: false,
         }
     }
 
@@ -36,8 +43,38 @@ impl<'a, U: UserContext> ServiceContext<'a, U> {
         Self {
             user,
             send_notifications: false,
+            dry_run: false,
+            suppress_notifications: false,
         }
     }
+
+    /// Context of a dry run, which never notifies
+    pub fn dry_run(user: &'a U) -> Self {
+        Self {
+            user,
+            send_notifications: false,
+            dry_run: true,
+            suppress_notifications: false,
+        }
+    }
+
+    /// Context of a bulk operation, a project copy or an import, whose
+    /// notifications are summarized once it is done. Single API requests
+    /// never run in it.
+    pub fn bulk(user: &'a U) -> Self {
+        Self {
+            user,
+            send_notifications: true,
+            dry_run: false,
+            suppress_notifications: true,
+        }
+    }
+
+    /// Whether each work package changed in this context notifies its
+    /// watchers and assignees on its own
+    pub fn notifies_individually(&self) -> bool {
+        self.send_notifications && !self.dry_run && !self.suppress_notifications
+    }
 }
 
 /// Base contracted service that validates through contracts
@@ -112,6 +149,22 @@ pub trait CreateService<T>: WriteService<T> {
 
         ServiceResult::success(entity)
     }
+
+    /// Set attributes and validate as `execute` does, returning the entity
+    /// that would be created without persisting it
+    fn dry_run(&self, params: &Self::Params) -> ServiceResult<T> {
+        let mut entity = self.new_instance();
+
+        if let Err(errors) = self.set_attributes(&mut entity, params) {
+            return ServiceResult::failure(errors);
+        }
+
+        if let Err(errors) = self.validate(&entity) {
+            return ServiceResult::failure(errors);
+        }
+
+        ServiceResult::success(entity)
+    }
 }
 
 /// Trait for update services
@@ -138,6 +191,25 @@ pub trait UpdateService<T>: WriteService<T> {
 
         ServiceResult::success(entity.clone())
     }
+
+    /// Set attributes and validate as `execute` does on a copy of the
+    /// entity, returning the copy without persisting it
+    fn dry_run(&self, entity: &T, params: &Self::Params) -> ServiceResult<T>
+    where
+        T: Clone,
+    {
+        let mut entity = entity.clone();
+
+        if let Err(errors) = self.set_attributes(&mut entity, params) {
+            return ServiceResult::failure(errors);
+        }
+
+        if let Err(errors) = self.validate(&entity) {
+            return ServiceResult::failure(errors);
+        }
+
+        ServiceResult::success(entity)
+    }
 }
 
 /// Trait for delete services
@@ -231,6 +303,22 @@ pub trait AsyncCreateService<T: Send + Sync>: AsyncWriteService<T> {
 
         ServiceResult::success(entity)
     }
+
+    /// Set attributes and validate as `execute` does, returning the entity
+    /// that would be created without persisting it
+    fn dry_run(&self, params: &Self::Params) -> ServiceResult<T> {
+        let mut entity = self.new_instance();
+
+        if let Err(errors) = self.set_attributes(&mut entity, params) {
+            return ServiceResult::failure(errors);
+        }
+
+        if let Err(errors) = self.validate(&entity) {
+            return ServiceResult::failure(errors);
+        }
+
+        ServiceResult::success(entity)
+    }
 }
 
 #[async_trait]
@@ -257,6 +345,25 @@ pub trait AsyncUpdateService<T: Send + Sync>: AsyncWriteService<T> {
 
         ServiceResult::success(entity.clone())
     }
+
+    /// Set attributes and validate as `execute` does on a copy of the
+    /// entity, returning the copy without persisting it
+    fn dry_run(&self, entity: &T, params: &Self::Params) -> ServiceResult<T>
+    where
+        T: Clone,
+    {
+        let mut entity = entity.clone();
+
+        if let Err(errors) = self.set_attributes(&mut entity, params) {
+            return ServiceResult::failure(errors);
+        }
+
+        if let Err(errors) = self.validate(&entity) {
+            return ServiceResult::failure(errors);
+        }
+
+        ServiceResult::success(entity)
+    }
 }
 
 #[async_trait]
@@ -350,6 +457,50 @@ mod tests {
         assert_eq!(entity.id, Some(1));
     }
 
+    // Mock update service, setting and validating as the create service
+    struct MockUpdateService;
+
+    impl WriteService<MockEntity> for MockUpdateService {
+        type Params = MockParams;
+
+        fn set_attributes(&self, entity: &mut MockEntity, params: &Self::Params) -> Result<(), ValidationErrors> {
+            MockCreateService.set_attributes(entity, params)
+        }
+
+        fn validate(&self, entity: &MockEntity) -> Result<(), ValidationErrors> {
+            MockCreateService.validate(entity)
+        }
+
+        fn persist(&self, entity: &mut MockEntity) -> Result<(), ValidationErrors> {
+            entity.name.push_str(" (saved)");
+            Ok(())
+        }
+    }
+
+    impl UpdateService<MockEntity> for MockUpdateService {}
+
+    #[test]
+    fn test_dry_run_validates_without_persisting() {
+        let service = MockCreateService;
+        let params = MockParams {
+            name: "Test".to_string(),
+        };
+
+        let created = service.dry_run(&params);
+        assert_eq!(created.result().unwrap().id, None);
+
+        let existing = MockEntity {
+            id: Some(5),
+            name: "Old".to_string(),
+        };
+        let updated = MockUpdateService.dry_run(&existing, &params);
+        assert_eq!(updated.result().unwrap().name, "Test");
+        assert_eq!(existing.name, "Old");
+
+        let invalid = service.dry_run(&MockParams { name: String::new() });
+        assert!(invalid.errors().has_error("name"));
+    }
+
     #[test]
     fn test_create_service_validation_failure() {
         let service = MockCreateService;
@@ -0,0 +1,255 @@
+//! Work package cost services
+//!
+//! Mirrors:
+//! - modules/costs/app/services/costs/query_*
+//! - modules/costs/lib/costs/patches/work_package_patch.rb
+//!
+//! A work package costs the time logged on it and on its descendants,
+//! priced with the users' hourly rates (labor costs), plus the units of
+//! cost types booked on them, priced with the unit rates (material costs).
+//! Entries with overridden costs keep those. The totals are cached on the
+//! work package and refreshed by a [`REFRESH_COSTS_JOB`] whenever entries
+//! or rates change.
+
+mod rates;
+mod refresh;
+
+use op_core::traits::Id;
+use op_db::{CostEntryRow, CostRepository, RepositoryResult, TimeEntryRow, WorkPackageCostsRow};
+use serde::Serialize;
+
+pub use rates::RateTable;
+pub use refresh::{RefreshCostsArgs, RefreshCostsJob, REFRESH_COSTS_JOB};
+
+/// Costs of a work package and its descendants
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct WorkPackageCosts {
+    pub labor_costs: f64,
+    pub material_costs: f64,
+    pub overall_costs: f64,
+}
+
+impl WorkPackageCosts {
+    fn new(labor_costs: f64, material_costs: f64) -> Self {
+        let labor_costs = round_to_cents(labor_costs);
+        let material_costs = round_to_cents(material_costs);
+        Self {
+            labor_costs,
+            material_costs,
+            overall_costs: round_to_cents(labor_costs + material_costs),
+        }
+    }
+}
+
+impl From<WorkPackageCostsRow> for WorkPackageCosts {
+    fn from(row: WorkPackageCostsRow) -> Self {
+        Self {
+            labor_costs: row.labor_costs,
+            material_costs: row.material_costs,
+            overall_costs: row.overall_costs,
+        }
+    }
+}
+
+impl From<WorkPackageCosts> for WorkPackageCostsRow {
+    fn from(costs: WorkPackageCosts) -> Self {
+        Self {
+            labor_costs: costs.labor_costs,
+            material_costs: costs.material_costs,
+            overall_costs: costs.overall_costs,
+        }
+    }
+}
+
+/// Whose entries a user sees the costs of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostViewer {
+    /// Users allowed to view the cost entries of the project see all costs
+    All,
+    /// Everyone else sees what their own entries cost
+    Own(Id),
+}
+
+/// Cost of a time entry: its overridden costs, else its hours priced with
+/// the user's hourly rate on the day; time without a rate costs nothing
+pub fn labor_costs(entry: &TimeEntryRow, rates: &RateTable) -> f64 {
+    entry.overridden_costs.unwrap_or_else(|| {
+        rates
+            .hourly_rate(entry.user_id, entry.project_id, entry.spent_on)
+            .map_or(0.0, |rate| entry.hours * rate.rate)
+    })
+}
+
+/// Cost of a cost entry: its overridden costs, else its units priced with
+/// the unit rate of the cost type on the day
+pub fn material_costs(entry: &CostEntryRow, rates: &RateTable) -> f64 {
+    entry.overridden_costs.unwrap_or_else(|| {
+        rates
+            .cost_rate(entry.cost_type_id, entry.spent_on)
+            .map_or(0.0, |rate| entry.units * rate.rate)
+    })
+}
+
+/// Total costs of the entries
+pub fn sum_costs(time_entries: &[TimeEntryRow], cost_entries: &[CostEntryRow], rates: &RateTable) -> WorkPackageCosts {
+    WorkPackageCosts::new(
+        time_entries.iter().map(|entry| labor_costs(entry, rates)).sum(),
+        cost_entries.iter().map(|entry| material_costs(entry, rates)).sum(),
+    )
+}
+
+fn round_to_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Service computing and caching the costs of work packages
+pub struct CostService<'a> {
+    repository: &'a CostRepository,
+}
+
+impl<'a> CostService<'a> {
+    pub fn new(repository: &'a CostRepository) -> Self {
+        Self { repository }
+    }
+
+    /// Costs of the work package and its descendants, of all entries or of
+    /// those of one user only
+    pub async fn compute(&self, work_package_id: Id, only_user: Option<Id>) -> RepositoryResult<WorkPackageCosts> {
+        let ids = self.repository.subtree_ids(work_package_id).await?;
+        let mut time_entries = self.repository.time_entries(&ids).await?;
+        let mut cost_entries = self.repository.cost_entries(&ids).await?;
+        if let Some(user_id) = only_user {
+            time_entries.retain(|entry| entry.user_id == user_id);
+            cost_entries.retain(|entry| entry.user_id == user_id);
+        }
+
+        let mut user_ids: Vec<Id> = time_entries.iter().map(|entry| entry.user_id).collect();
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        let mut cost_type_ids: Vec<Id> = cost_entries.iter().map(|entry| entry.cost_type_id).collect();
+        cost_type_ids.sort_unstable();
+        cost_type_ids.dedup();
+
+        let mut rates = self.repository.hourly_rates(&user_ids).await?;
+        rates.extend(self.repository.cost_rates(&cost_type_ids).await?);
+
+        Ok(sum_costs(&time_entries, &cost_entries, &RateTable::new(rates)))
+    }
+
+    /// Recompute and cache the costs of the work packages and of all their
+    /// ancestors, returning how many were refreshed
+    pub async fn refresh(&self, work_package_ids: &[Id]) -> RepositoryResult<usize> {
+        let ids = self.repository.with_ancestor_ids(work_package_ids).await?;
+        for &id in &ids {
+            let costs = self.compute(id, None).await?;
+            self.repository.save_work_package_costs(id, costs.into()).await?;
+        }
+
+        Ok(ids.len())
+    }
+
+    /// Costs as the viewer may see them. All costs are read from the cache,
+    /// computed if the work package was never refreshed; a viewer's own
+    /// contribution is always computed.
+    pub async fn visible_costs(&self, work_package_id: Id, viewer: CostViewer) -> RepositoryResult<WorkPackageCosts> {
+        match viewer {
+            CostViewer::All => {
+                let cached = self.repository.find_work_package_costs(&[work_package_id]).await?;
+                match cached.get(&work_package_id) {
+                    Some(costs) => Ok((*costs).into()),
+                    None => self.compute(work_package_id, None).await,
+                }
+            }
+            CostViewer::Own(user_id) => self.compute(work_package_id, Some(user_id)).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+    use op_db::{rate_type, RateRow};
+
+    fn day() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 4).unwrap()
+    }
+
+    fn time_entry(user_id: Id, hours: f64, overridden_costs: Option<f64>) -> TimeEntryRow {
+        TimeEntryRow {
+            id: 1,
+            project_id: 10,
+            user_id,
+            work_package_id: Some(100),
+            hours,
+            comments: None,
+            activity_id: 1,
+            spent_on: day(),
+            tyear: 2024,
+            tmonth: 3,
+            tweek: 10,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            overridden_costs,
+            costs: None,
+            rate_id: None,
+            logged_by_id: None,
+        }
+    }
+
+    fn cost_entry(user_id: Id, units: f64) -> CostEntryRow {
+        CostEntryRow {
+            id: 1,
+            user_id,
+            project_id: 10,
+            work_package_id: Some(100),
+            cost_type_id: 20,
+            units,
+            spent_on: day(),
+            comments: None,
+            overridden_costs: None,
+            costs: None,
+            rate_id: None,
+            logged_by_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn rates() -> RateTable {
+        let rate = |id, rate_type: &str, rate, user_id, cost_type_id| RateRow {
+            id,
+            rate_type: rate_type.to_string(),
+            rate,
+            valid_from: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            user_id,
+            project_id: None,
+            cost_type_id,
+        };
+        RateTable::new(vec![
+            rate(1, rate_type::DEFAULT_HOURLY, 60.0, Some(1), None),
+            rate(2, rate_type::COST, 12.5, None, Some(20)),
+        ])
+    }
+
+    #[test]
+    fn test_sum_costs_prices_hours_and_units() {
+        let costs = sum_costs(
+            &[time_entry(1, 2.5, None), time_entry(1, 1.0, Some(100.0)), time_entry(2, 3.0, None)],
+            &[cost_entry(1, 4.0)],
+            &rates(),
+        );
+        // User 2 has no rate, their time costs nothing
+        assert_eq!(costs.labor_costs, 250.0);
+        assert_eq!(costs.material_costs, 50.0);
+        assert_eq!(costs.overall_costs, 300.0);
+    }
+
+    #[test]
+    fn test_costs_are_rounded_to_cents() {
+        let costs = sum_costs(&[time_entry(1, 1.0 / 3.0, None)], &[], &rates());
+        assert_eq!(costs.labor_costs, 20.0);
+        let costs = sum_costs(&[], &[cost_entry(1, 0.333)], &rates());
+        assert_eq!(costs.material_costs, 4.16);
+    }
+}
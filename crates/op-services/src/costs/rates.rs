@@ -0,0 +1,234 @@
+//! Rate resolution
+//!
+//! Mirrors: modules/costs/app/models/hourly_rate.rb, default_hourly_rate.rb
+//! and cost_rate.rb
+//!
+//! Rates apply from their `valid_from` day until a later rate of the same
+//! kind does. A user's hourly rate in a project takes precedence over their
+//! default rate, regardless of which of the two is newer.
+
+use chrono::NaiveDate;
+use op_core::traits::Id;
+use op_db::{rate_type, RateRow};
+
+/// The rates entries are priced with
+#[derive(Debug, Clone, Default)]
+pub struct RateTable {
+    rates: Vec<RateRow>,
+}
+
+impl RateTable {
+    pub fn new(rates: Vec<RateRow>) -> Self {
+        Self { rates }
+    }
+
+    /// Hourly rate of a user working in a project on a day: the user's
+    /// rate in the project, else their default rate
+    pub fn hourly_rate(&self, user_id: Id, project_id: Id, on: NaiveDate) -> Option<&RateRow> {
+        self.latest(on, |rate| {
+            rate.rate_type == rate_type::HOURLY && rate.user_id == Some(user_id) && rate.project_id == Some(project_id)
+        })
+        .or_else(|| {
+            self.latest(on, |rate| {
+                rate.rate_type == rate_type::DEFAULT_HOURLY && rate.user_id == Some(user_id)
+            })
+        })
+    }
+
+    /// Price of a unit of a cost type on a day
+    pub fn cost_rate(&self, cost_type_id: Id, on: NaiveDate) -> Option<&RateRow> {
+        self.latest(on, |rate| {
+            rate.rate_type == rate_type::COST && rate.cost_type_id == Some(cost_type_id)
+        })
+    }
+
+    /// The matching rate valid from the latest day not after `on`; of rates
+    /// valid from the same day, the last one created
+    fn latest(&self, on: NaiveDate, matches: impl Fn(&RateRow) -> bool) -> Option<&RateRow> {
+        self.rates
+            .iter()
+            .filter(|rate| rate.valid_from <= on && matches(rate))
+            .max_by_key(|rate| (rate.valid_from, rate.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: Id = 1;
+    const OTHER_USER: Id = 2;
+    const PROJECT: Id = 10;
+    const OTHER_PROJECT: Id = 11;
+    const CONCRETE: Id = 20;
+    const STEEL: Id = 21;
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn rate(id: Id, rate_type: &str, rate: f64, valid_from: NaiveDate) -> RateRow {
+        RateRow {
+            id,
+            rate_type: rate_type.to_string(),
+            rate,
+            valid_from,
+            user_id: None,
+            project_id: None,
+            cost_type_id: None,
+        }
+    }
+
+    fn project_rate(id: Id, user_id: Id, project_id: Id, value: f64, valid_from: NaiveDate) -> RateRow {
+        RateRow {
+            user_id: Some(user_id),
+            project_id: Some(project_id),
+            ..rate(id, rate_type::HOURLY, value, valid_from)
+        }
+    }
+
+    fn default_rate(id: Id, user_id: Id, value: f64, valid_from: NaiveDate) -> RateRow {
+        RateRow {
+            user_id: Some(user_id),
+            ..rate(id, rate_type::DEFAULT_HOURLY, value, valid_from)
+        }
+    }
+
+    fn unit_rate(id: Id, cost_type_id: Id, value: f64, valid_from: NaiveDate) -> RateRow {
+        RateRow {
+            cost_type_id: Some(cost_type_id),
+            ..rate(id, rate_type::COST, value, valid_from)
+        }
+    }
+
+    fn hourly(table: &RateTable, user_id: Id, project_id: Id, on: NaiveDate) -> Option<f64> {
+        table.hourly_rate(user_id, project_id, on).map(|rate| rate.rate)
+    }
+
+    #[test]
+    fn test_no_rates() {
+        let table = RateTable::default();
+        assert_eq!(hourly(&table, USER, PROJECT, day(1, 1)), None);
+        assert!(table.cost_rate(CONCRETE, day(1, 1)).is_none());
+    }
+
+    #[test]
+    fn test_project_rate_applies_in_its_project() {
+        let table = RateTable::new(vec![project_rate(1, USER, PROJECT, 80.0, day(1, 1))]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(2, 1)), Some(80.0));
+        assert_eq!(hourly(&table, USER, OTHER_PROJECT, day(2, 1)), None);
+        assert_eq!(hourly(&table, OTHER_USER, PROJECT, day(2, 1)), None);
+    }
+
+    #[test]
+    fn test_default_rate_applies_in_every_project() {
+        let table = RateTable::new(vec![default_rate(1, USER, 60.0, day(1, 1))]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(2, 1)), Some(60.0));
+        assert_eq!(hourly(&table, USER, OTHER_PROJECT, day(2, 1)), Some(60.0));
+        assert_eq!(hourly(&table, OTHER_USER, PROJECT, day(2, 1)), None);
+    }
+
+    #[test]
+    fn test_rates_apply_from_their_first_day() {
+        let table = RateTable::new(vec![default_rate(1, USER, 60.0, day(3, 1))]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(2, 29)), None);
+        assert_eq!(hourly(&table, USER, PROJECT, day(3, 1)), Some(60.0));
+        assert_eq!(hourly(&table, USER, PROJECT, day(12, 31)), Some(60.0));
+    }
+
+    #[test]
+    fn test_later_rate_wins() {
+        let table = RateTable::new(vec![
+            // Created out of order on purpose
+            default_rate(1, USER, 70.0, day(6, 1)),
+            default_rate(2, USER, 50.0, day(1, 1)),
+            default_rate(3, USER, 60.0, day(3, 1)),
+        ]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(2, 1)), Some(50.0));
+        assert_eq!(hourly(&table, USER, PROJECT, day(3, 1)), Some(60.0));
+        assert_eq!(hourly(&table, USER, PROJECT, day(5, 31)), Some(60.0));
+        assert_eq!(hourly(&table, USER, PROJECT, day(6, 1)), Some(70.0));
+    }
+
+    #[test]
+    fn test_last_created_rate_wins_on_the_same_day() {
+        let table = RateTable::new(vec![
+            project_rate(4, USER, PROJECT, 90.0, day(1, 1)),
+            project_rate(2, USER, PROJECT, 85.0, day(1, 1)),
+        ]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(1, 1)), Some(90.0));
+    }
+
+    #[test]
+    fn test_project_rate_overrides_default_rate() {
+        let table = RateTable::new(vec![
+            default_rate(1, USER, 60.0, day(1, 1)),
+            project_rate(2, USER, PROJECT, 80.0, day(1, 1)),
+        ]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(2, 1)), Some(80.0));
+        assert_eq!(hourly(&table, USER, OTHER_PROJECT, day(2, 1)), Some(60.0));
+    }
+
+    #[test]
+    fn test_older_project_rate_overrides_newer_default_rate() {
+        let table = RateTable::new(vec![
+            project_rate(1, USER, PROJECT, 80.0, day(1, 1)),
+            default_rate(2, USER, 100.0, day(6, 1)),
+        ]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(7, 1)), Some(80.0));
+        assert_eq!(hourly(&table, USER, OTHER_PROJECT, day(7, 1)), Some(100.0));
+    }
+
+    #[test]
+    fn test_default_rate_applies_before_project_rate_starts() {
+        let table = RateTable::new(vec![
+            default_rate(1, USER, 60.0, day(1, 1)),
+            project_rate(2, USER, PROJECT, 80.0, day(6, 1)),
+        ]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(5, 31)), Some(60.0));
+        assert_eq!(hourly(&table, USER, PROJECT, day(6, 1)), Some(80.0));
+    }
+
+    #[test]
+    fn test_later_project_rate_wins_over_earlier_project_rate_and_default() {
+        let table = RateTable::new(vec![
+            default_rate(1, USER, 60.0, day(1, 1)),
+            project_rate(2, USER, PROJECT, 80.0, day(2, 1)),
+            default_rate(3, USER, 65.0, day(4, 1)),
+            project_rate(4, USER, PROJECT, 95.0, day(5, 1)),
+        ]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(1, 15)), Some(60.0));
+        assert_eq!(hourly(&table, USER, PROJECT, day(4, 15)), Some(80.0));
+        assert_eq!(hourly(&table, USER, PROJECT, day(5, 15)), Some(95.0));
+        assert_eq!(hourly(&table, USER, OTHER_PROJECT, day(4, 15)), Some(65.0));
+    }
+
+    #[test]
+    fn test_unit_rates_do_not_price_hours() {
+        let table = RateTable::new(vec![unit_rate(1, CONCRETE, 120.0, day(1, 1))]);
+        assert_eq!(hourly(&table, USER, PROJECT, day(2, 1)), None);
+    }
+
+    #[test]
+    fn test_hourly_rates_do_not_price_units() {
+        let table = RateTable::new(vec![
+            default_rate(1, USER, 60.0, day(1, 1)),
+            project_rate(2, USER, PROJECT, 80.0, day(1, 1)),
+        ]);
+        assert!(table.cost_rate(CONCRETE, day(2, 1)).is_none());
+    }
+
+    #[test]
+    fn test_cost_rate_of_the_cost_type_on_the_day() {
+        let table = RateTable::new(vec![
+            unit_rate(1, CONCRETE, 100.0, day(1, 1)),
+            unit_rate(2, CONCRETE, 120.0, day(7, 1)),
+            unit_rate(3, STEEL, 900.0, day(1, 1)),
+        ]);
+        let price = |cost_type_id, on| table.cost_rate(cost_type_id, on).map(|rate| rate.rate);
+        assert_eq!(price(CONCRETE, day(6, 30)), Some(100.0));
+        assert_eq!(price(CONCRETE, day(7, 1)), Some(120.0));
+        assert_eq!(price(STEEL, day(7, 1)), Some(900.0));
+        assert_eq!(price(CONCRETE, day(1, 1) - chrono::Duration::days(1)), None);
+    }
+}
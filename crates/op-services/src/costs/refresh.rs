@@ -0,0 +1,100 @@
+//! Refresh Costs Job
+//!
+//! Mirrors: modules/costs/app/workers/costs/update_work_package_costs_job.rb
+//!
+//! Recomputes the cached costs of work packages after time entries, cost
+//! entries or rates changed. A changed rate affects every work package
+//! priced with it, so those are looked up when the job runs.
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+use op_db::CostRepository;
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use op_notifications::Job;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::CostService;
+
+/// Job type of cost refresh jobs
+pub const REFRESH_COSTS_JOB: &str = "Costs::RefreshWorkPackageCostsJob";
+
+/// Arguments of a [`REFRESH_COSTS_JOB`]: what changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "changed", rename_all = "snake_case")]
+pub enum RefreshCostsArgs {
+    /// Entries on these work packages
+    WorkPackages { ids: Vec<Id> },
+    /// The hourly rates of a user
+    HourlyRates { user_id: Id },
+    /// The unit rates of a cost type
+    CostRates { cost_type_id: Id },
+}
+
+impl RefreshCostsArgs {
+    /// Refresh the work packages, leaving out repeated ids
+    pub fn work_packages(ids: impl IntoIterator<Item = Id>) -> Self {
+        let mut ids: Vec<Id> = ids.into_iter().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        Self::WorkPackages { ids }
+    }
+
+    /// Build the job; refreshing is idempotent, so failed attempts are retried
+    pub fn into_job(self) -> Job {
+        Job::new(REFRESH_COSTS_JOB, serde_json::json!(self))
+    }
+}
+
+/// Job handler refreshing work package costs in the background
+pub struct RefreshCostsJob {
+    pool: PgPool,
+}
+
+impl RefreshCostsJob {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl JobHandler for RefreshCostsJob {
+    async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+        let args: RefreshCostsArgs =
+            serde_json::from_value(args).map_err(|e| JobError::SerializationError(e.to_string()))?;
+
+        let repository = CostRepository::new(self.pool.clone());
+        let ids = match args {
+            RefreshCostsArgs::WorkPackages { ids } => Ok(ids),
+            RefreshCostsArgs::HourlyRates { user_id } => repository.work_packages_of_user(user_id).await,
+            RefreshCostsArgs::CostRates { cost_type_id } => repository.work_packages_of_cost_type(cost_type_id).await,
+        }
+        .map_err(|e| JobError::Failed(e.to_string()))?;
+
+        let refreshed = CostService::new(&repository)
+            .refresh(&ids)
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))?;
+
+        tracing::info!(refreshed, "Refreshed work package costs");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_args_round_trip() {
+        let job = RefreshCostsArgs::work_packages([3, 1, 3]).into_job();
+        assert_eq!(job.job_type, REFRESH_COSTS_JOB);
+        assert!(job.can_retry());
+        assert_eq!(job.args, serde_json::json!({ "changed": "work_packages", "ids": [1, 3] }));
+
+        let parsed: RefreshCostsArgs =
+            serde_json::from_value(serde_json::json!({ "changed": "hourly_rates", "user_id": 5 })).unwrap();
+        assert_eq!(parsed, RefreshCostsArgs::HourlyRates { user_id: 5 });
+    }
+}
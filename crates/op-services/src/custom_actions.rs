@@ -0,0 +1,551 @@
+//! Custom actions
+//!
+//! Mirrors:
+//! - app/models/custom_action.rb
+//! - app/models/custom_actions/conditions/*
+//! - app/services/custom_actions/update_work_package_service.rb
+//!
+//! A custom action is offered on the work packages its conditions match:
+//! each condition lists the statuses, types, projects or roles it accepts,
+//! and an empty list accepts any. Executing it applies its changes through
+//! [`UpdateWorkPackageService`], so the update contract decides whether the
+//! user may make them. An action whose conditions no longer match, or that
+//! was offered on a work package changed since, is refused as a conflict.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use op_contracts::base::UserContext;
+use op_core::error::ValidationErrors;
+use op_core::metrics::DomainMetrics;
+use op_core::traits::Id;
+use op_db::CustomActionRow;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::work_packages::{UpdateWorkPackageService, WorkPackageEntity, WorkPackageParams};
+
+/// Conditions a work package and user must meet for an action to be offered
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CustomActionConditions {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub status_ids: Vec<Id>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub type_ids: Vec<Id>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub project_ids: Vec<Id>,
+    /// Roles of which the user needs one in the project
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub role_ids: Vec<Id>,
+}
+
+impl CustomActionConditions {
+    /// Whether every condition accepts the work package and user
+    pub fn fulfilled_by(&self, context: &CustomActionContext) -> bool {
+        let accepts = |ids: &[Id], id: Id| ids.is_empty() || ids.contains(&id);
+
+        accepts(&self.status_ids, context.status_id)
+            && accepts(&self.type_ids, context.type_id)
+            && accepts(&self.project_ids, context.project_id)
+            && (self.role_ids.is_empty() || self.role_ids.iter().any(|id| context.role_ids.contains(id)))
+    }
+}
+
+/// What the conditions of actions are evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct CustomActionContext {
+    pub status_id: Id,
+    pub type_id: Id,
+    pub project_id: Id,
+    /// Roles of the user in the project of the work package
+    pub role_ids: Vec<Id>,
+}
+
+impl CustomActionContext {
+    pub fn new(work_package: &WorkPackageEntity, role_ids: Vec<Id>) -> Self {
+        Self {
+            status_id: work_package.status_id,
+            type_id: work_package.type_id,
+            project_id: work_package.project_id,
+            role_ids,
+        }
+    }
+}
+
+/// User an action assigns, written as `"me"` for the user executing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "serde_json::Value", into = "serde_json::Value")]
+pub enum ActionPrincipal {
+    CurrentUser,
+    User(Id),
+}
+
+impl ActionPrincipal {
+    fn resolve(self, user_id: Id) -> Id {
+        match self {
+            ActionPrincipal::CurrentUser => user_id,
+            ActionPrincipal::User(id) => id,
+        }
+    }
+}
+
+impl TryFrom<serde_json::Value> for ActionPrincipal {
+    type Error = String;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match &value {
+            serde_json::Value::String(s) if s == "me" => Ok(ActionPrincipal::CurrentUser),
+            serde_json::Value::Number(n) => n
+                .as_i64()
+                .map(ActionPrincipal::User)
+                .ok_or_else(|| format!("{} is not a user id", n)),
+            _ => Err(format!("expected a user id or \"me\", got {}", value)),
+        }
+    }
+}
+
+impl From<ActionPrincipal> for serde_json::Value {
+    fn from(principal: ActionPrincipal) -> Self {
+        match principal {
+            ActionPrincipal::CurrentUser => serde_json::Value::from("me"),
+            ActionPrincipal::User(id) => serde_json::Value::from(id),
+        }
+    }
+}
+
+/// One change of an action, e.g. `{"status": 5}` or `{"assignedTo": "me"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CustomActionChange {
+    Status(Id),
+    Type(Id),
+    Priority(Id),
+    AssignedTo(ActionPrincipal),
+    Responsible(ActionPrincipal),
+    DoneRatio(i32),
+    /// Notes of the journal recording the change
+    Comment(String),
+}
+
+impl CustomActionChange {
+    /// Attribute the change sets, named as in errors
+    pub fn attribute(&self) -> &'static str {
+        match self {
+            CustomActionChange::Status(_) => "status",
+            CustomActionChange::Type(_) => "type",
+            CustomActionChange::Priority(_) => "priority",
+            CustomActionChange::AssignedTo(_) => "assignee",
+            CustomActionChange::Responsible(_) => "responsible",
+            CustomActionChange::DoneRatio(_) => "percentageDone",
+            CustomActionChange::Comment(_) => "comment",
+        }
+    }
+}
+
+/// Check the changes an administrator saves for an action
+pub fn validate_changes(changes: &[CustomActionChange]) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if changes.is_empty() {
+        errors.add("actions", "can't be empty");
+    }
+
+    let mut attributes = HashSet::new();
+    for change in changes {
+        if !attributes.insert(change.attribute()) {
+            errors.add("actions", format!("change the {} more than once", change.attribute()));
+        }
+        match change {
+            CustomActionChange::DoneRatio(ratio) if !(0..=100).contains(ratio) => {
+                errors.add("actions", "set the percentage done outside of 0 to 100");
+            }
+            CustomActionChange::Comment(notes) if notes.trim().is_empty() => {
+                errors.add("actions", "add a blank comment");
+            }
+            _ => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// A custom action with its conditions and changes read from the JSON
+#[derive(Debug, Clone)]
+pub struct CustomAction {
+    pub id: Id,
+    pub name: String,
+    pub description: Option<String>,
+    pub position: i32,
+    pub conditions: CustomActionConditions,
+    pub changes: Vec<CustomActionChange>,
+}
+
+impl CustomAction {
+    pub fn from_row(row: &CustomActionRow) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            id: row.id,
+            name: row.name.clone(),
+            description: row.description.clone(),
+            position: row.position,
+            conditions: serde_json::from_value(row.conditions.clone())?,
+            changes: serde_json::from_value(row.actions.clone())?,
+        })
+    }
+
+    /// Whether the action is offered in the context
+    pub fn applicable_to(&self, context: &CustomActionContext) -> bool {
+        self.conditions.fulfilled_by(context)
+    }
+
+    /// Params of the update applying the changes, for the executing user
+    pub fn params(&self, user_id: Id) -> WorkPackageParams {
+        let mut params = WorkPackageParams::new().send_notifications(true);
+        for change in &self.changes {
+            match *change {
+                CustomActionChange::Status(id) => params.status_id = Some(id),
+                CustomActionChange::Type(id) => params.type_id = Some(id),
+                CustomActionChange::Priority(id) => params.priority_id = Some(id),
+                CustomActionChange::AssignedTo(principal) => params.assigned_to_id = Some(principal.resolve(user_id)),
+                CustomActionChange::Responsible(principal) => params.responsible_id = Some(principal.resolve(user_id)),
+                CustomActionChange::DoneRatio(ratio) => params.done_ratio = Some(ratio),
+                CustomActionChange::Comment(_) => {}
+            }
+        }
+        params
+    }
+
+    /// Notes the change is journaled with, empty without a comment
+    pub fn notes(&self) -> &str {
+        self.changes
+            .iter()
+            .find_map(|change| match change {
+                CustomActionChange::Comment(notes) => Some(notes.as_str()),
+                _ => None,
+            })
+            .unwrap_or("")
+    }
+}
+
+/// Actions offered in the context, in their order. Rows whose JSON cannot
+/// be read are left out.
+pub fn applicable_actions(rows: &[CustomActionRow], context: &CustomActionContext) -> Vec<CustomAction> {
+    rows.iter()
+        .filter_map(|row| match CustomAction::from_row(row) {
+            Ok(action) => Some(action),
+            Err(e) => {
+                warn!(custom_action_id = row.id, error = %e, "Skipping unreadable custom action");
+                None
+            }
+        })
+        .filter(|action| action.applicable_to(context))
+        .collect()
+}
+
+/// Why a custom action was not applied
+#[derive(Debug, Clone)]
+pub enum CustomActionError {
+    /// The work package changed since the action was offered, or the
+    /// action no longer applies to it
+    Conflict(String),
+    /// The update contract refused the changes
+    Invalid(ValidationErrors),
+}
+
+impl fmt::Display for CustomActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomActionError::Conflict(reason) => write!(f, "{}", reason),
+            CustomActionError::Invalid(errors) => write!(f, "{}", errors.full_messages().join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for CustomActionError {}
+
+/// Work package with the changes of an action applied, to be saved with
+/// the notes of its comment
+#[derive(Debug, Clone)]
+pub struct AppliedCustomAction {
+    pub work_package: WorkPackageEntity,
+    pub notes: String,
+}
+
+/// Service applying a custom action to a work package
+///
+/// # Example
+/// ```ignore
+/// let applied = ExecuteCustomActionService::new(&user, &action)
+///     .call(work_package, role_ids, lock_version)?;
+/// repo.update_journaled(id, dto, user.id(), &applied.notes).await?;
+/// ```
+pub struct ExecuteCustomActionService<'a, U: UserContext> {
+    user: &'a U,
+    action: &'a CustomAction,
+    metrics: Option<&'a DomainMetrics>,
+}
+
+impl<'a, U: UserContext> ExecuteCustomActionService<'a, U> {
+    pub fn new(user: &'a U, action: &'a CustomAction) -> Self {
+        Self {
+            user,
+            action,
+            metrics: None,
+        }
+    }
+
+    /// Record successful updates in the given metrics collector
+    pub fn with_metrics(mut self, metrics: &'a DomainMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Apply the action to the work package in its current state. The
+    /// lock version is the one the action was offered at; `role_ids` are
+    /// the roles of the user in the project.
+    pub fn call(
+        self,
+        work_package: WorkPackageEntity,
+        role_ids: Vec<Id>,
+        lock_version: i32,
+    ) -> Result<AppliedCustomAction, CustomActionError> {
+        if work_package.lock_version != lock_version {
+            return Err(CustomActionError::Conflict(
+                "Work package was modified by another user".to_string(),
+            ));
+        }
+        let context = CustomActionContext::new(&work_package, role_ids);
+        if !self.action.applicable_to(&context) {
+            return Err(CustomActionError::Conflict(format!(
+                "The custom action {} is not applicable to the work package",
+                self.action.name
+            )));
+        }
+
+        let mut service = UpdateWorkPackageService::new(self.user);
+        if let Some(metrics) = self.metrics {
+            service = service.with_metrics(metrics);
+        }
+        let result = service.call(work_package, self.action.params(self.user.id()));
+        if result.is_failure() {
+            return Err(CustomActionError::Invalid(result.errors().clone()));
+        }
+
+        Ok(AppliedCustomAction {
+            work_package: result.unwrap(),
+            notes: self.action.notes().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_contracts::work_packages::permissions::EDIT_WORK_PACKAGES;
+
+    const CLOSED: Id = 5;
+
+    struct Member {
+        id: Id,
+        permissions: Vec<&'static str>,
+    }
+
+    impl UserContext for Member {
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn is_admin(&self) -> bool {
+            false
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, permission: &str, _project_id: Id) -> bool {
+            self.permissions.contains(&permission)
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            false
+        }
+    }
+
+    fn editor() -> Member {
+        Member {
+            id: 7,
+            permissions: vec![EDIT_WORK_PACKAGES],
+        }
+    }
+
+    fn context(status_id: Id, role_ids: Vec<Id>) -> CustomActionContext {
+        CustomActionContext {
+            status_id,
+            type_id: 1,
+            project_id: 1,
+            role_ids,
+        }
+    }
+
+    fn work_package(status_id: Id, lock_version: i32) -> WorkPackageEntity {
+        let mut work_package = WorkPackageEntity::new(1, 1, 1);
+        work_package.id = Some(100);
+        work_package.subject = "Broken login".into();
+        work_package.status_id = status_id;
+        work_package.lock_version = lock_version;
+        work_package
+    }
+
+    /// Closes open work packages for developers, assigning the user
+    fn close_action() -> CustomAction {
+        CustomAction {
+            id: 1,
+            name: "Close".into(),
+            description: None,
+            position: 1,
+            conditions: CustomActionConditions {
+                status_ids: vec![1],
+                role_ids: vec![3],
+                ..Default::default()
+            },
+            changes: vec![
+                CustomActionChange::Status(CLOSED),
+                CustomActionChange::AssignedTo(ActionPrincipal::CurrentUser),
+                CustomActionChange::DoneRatio(100),
+                CustomActionChange::Comment("Closed".into()),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_empty_conditions_accept_any_work_package() {
+        let conditions = CustomActionConditions::default();
+        assert!(conditions.fulfilled_by(&context(1, Vec::new())));
+        assert!(conditions.fulfilled_by(&context(CLOSED, vec![3])));
+    }
+
+    #[test]
+    fn test_conditions_need_each_dimension_to_match() {
+        let conditions = CustomActionConditions {
+            status_ids: vec![1, 2],
+            type_ids: vec![1],
+            project_ids: vec![1],
+            role_ids: vec![3, 4],
+        };
+        assert!(conditions.fulfilled_by(&context(2, vec![4])));
+        assert!(!conditions.fulfilled_by(&context(CLOSED, vec![4])));
+        assert!(!conditions.fulfilled_by(&context(1, vec![9])));
+        assert!(!conditions.fulfilled_by(&context(1, Vec::new())));
+        assert!(!conditions.fulfilled_by(&CustomActionContext {
+            type_id: 2,
+            ..context(1, vec![3])
+        }));
+        assert!(!conditions.fulfilled_by(&CustomActionContext {
+            project_id: 2,
+            ..context(1, vec![3])
+        }));
+    }
+
+    #[test]
+    fn test_applicable_actions_skip_unreadable_rows() {
+        let row = |id: Id, conditions: serde_json::Value, actions: serde_json::Value| CustomActionRow {
+            id,
+            name: format!("Action {}", id),
+            description: None,
+            position: id as i32,
+            conditions,
+            actions,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let rows = vec![
+            row(1, serde_json::json!({ "statusIds": [1] }), serde_json::json!([{ "status": 5 }])),
+            row(2, serde_json::json!({ "statusIds": [2] }), serde_json::json!([{ "status": 5 }])),
+            row(3, serde_json::json!({}), serde_json::json!([{ "unknown": 1 }])),
+            row(4, serde_json::json!({}), serde_json::json!([{ "assignedTo": "me" }])),
+        ];
+
+        let ids: Vec<Id> = applicable_actions(&rows, &context(1, Vec::new()))
+            .iter()
+            .map(|action| action.id)
+            .collect();
+        assert_eq!(ids, vec![1, 4]);
+    }
+
+    #[test]
+    fn test_changes_read_and_write_me_for_the_current_user() {
+        let changes: Vec<CustomActionChange> =
+            serde_json::from_value(serde_json::json!([{ "assignedTo": "me" }, { "responsible": 4 }])).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                CustomActionChange::AssignedTo(ActionPrincipal::CurrentUser),
+                CustomActionChange::Responsible(ActionPrincipal::User(4)),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&changes).unwrap(),
+            serde_json::json!([{ "assignedTo": "me" }, { "responsible": 4 }])
+        );
+        assert!(serde_json::from_value::<CustomActionChange>(serde_json::json!({ "assignedTo": "you" })).is_err());
+    }
+
+    #[test]
+    fn test_validate_changes() {
+        assert!(validate_changes(&close_action().changes).is_ok());
+        assert!(validate_changes(&[]).unwrap_err().has_error("actions"));
+        assert!(validate_changes(&[CustomActionChange::DoneRatio(120)]).is_err());
+        assert!(validate_changes(&[CustomActionChange::Comment(" ".into())]).is_err());
+        assert!(validate_changes(&[CustomActionChange::Status(1), CustomActionChange::Status(2)]).is_err());
+    }
+
+    #[test]
+    fn test_execute_applies_the_changes_for_the_user() {
+        let action = close_action();
+        let applied = ExecuteCustomActionService::new(&editor(), &action)
+            .call(work_package(1, 3), vec![3], 3)
+            .unwrap();
+
+        assert_eq!(applied.work_package.status_id, CLOSED);
+        assert_eq!(applied.work_package.assigned_to_id, Some(7));
+        assert_eq!(applied.work_package.done_ratio, 100);
+        assert_eq!(applied.work_package.subject, "Broken login");
+        assert_eq!(applied.notes, "Closed");
+    }
+
+    #[test]
+    fn test_execute_conflicts_when_the_work_package_changed_concurrently() {
+        // Offered at lock version 3 while open; closed by someone else since
+        let action = close_action();
+        let result = ExecuteCustomActionService::new(&editor(), &action).call(work_package(CLOSED, 4), vec![3], 3);
+        assert!(matches!(result, Err(CustomActionError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_execute_conflicts_when_the_action_no_longer_applies() {
+        let action = close_action();
+        let result = ExecuteCustomActionService::new(&editor(), &action).call(work_package(CLOSED, 4), vec![3], 4);
+        assert!(matches!(result, Err(CustomActionError::Conflict(_))));
+
+        let result = ExecuteCustomActionService::new(&editor(), &action).call(work_package(1, 4), Vec::new(), 4);
+        assert!(matches!(result, Err(CustomActionError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_execute_runs_the_update_contract() {
+        let action = close_action();
+        let watcher = Member {
+            id: 8,
+            permissions: Vec::new(),
+        };
+        let result = ExecuteCustomActionService::new(&watcher, &action).call(work_package(1, 3), vec![3], 3);
+        match result {
+            Err(CustomActionError::Invalid(errors)) => assert!(errors.has_error("base")),
+            other => panic!("expected the contract to refuse, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,531 @@
+//! Document services
+//!
+//! Mirrors:
+//! - modules/documents/app/controllers/documents_controller.rb
+//! - modules/documents/app/services/documents/*
+//! - modules/documents/app/models/document.rb (notify on create)
+//!
+//! Documents are created, changed and deleted with `manage_documents`.
+//! Members of the project who may view documents and subscribed to
+//! `document_added` notifications hear about new ones. Files of a document
+//! are attachments; they are removed with it.
+
+use async_trait::async_trait;
+use op_attachments::{AttachmentService, AttachmentStore, Storage};
+use op_contracts::base::UserContext;
+use op_contracts::documents::permissions::MANAGE_DOCUMENTS;
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+use op_db::{
+    journable_type, CreateDocumentDto, DocumentRepository, DocumentRow, Repository, RepositoryError,
+    RepositoryResult, UpdateDocumentDto,
+};
+use op_notifications::{
+    Notification, NotificationReason, NotificationStore, NotificationStreams, NotificationType, StreamEventKind,
+};
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::permissions::{PermissionService, PermissionSource};
+use crate::result::ServiceResult;
+
+/// Attributes of a new document
+#[derive(Debug, Clone, Default)]
+pub struct DocumentParams {
+    /// Category of the document, the default category when `None`
+    pub category_id: Option<Id>,
+    pub title: String,
+    pub description: Option<String>,
+}
+
+/// Changes to a document; `None` keeps the current value
+#[derive(Debug, Clone, Default)]
+pub struct UpdateDocumentParams {
+    pub category_id: Option<Id>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Storage of documents
+#[async_trait]
+pub trait DocumentStore: Send + Sync {
+    async fn find_document(&self, id: Id) -> RepositoryResult<Option<DocumentRow>>;
+
+    async fn create_document(&self, dto: CreateDocumentDto) -> RepositoryResult<DocumentRow>;
+
+    async fn update_document(&self, id: Id, dto: UpdateDocumentDto) -> RepositoryResult<DocumentRow>;
+
+    /// Delete a document, returning the ids of its attachments
+    async fn delete_document(&self, id: Id) -> RepositoryResult<Vec<Id>>;
+
+    /// Members of the project allowed to view its documents
+    async fn project_members(&self, project_id: Id) -> RepositoryResult<Vec<Id>>;
+}
+
+/// Service for creating, changing and deleting documents
+pub struct DocumentService<'a, U: UserContext, S: DocumentStore, P: PermissionSource> {
+    user: &'a U,
+    store: &'a S,
+    permissions: &'a PermissionService<P>,
+    notifications: Option<&'a dyn NotificationStore>,
+    streams: Option<&'a NotificationStreams>,
+}
+
+impl<'a, U: UserContext, S: DocumentStore, P: PermissionSource> DocumentService<'a, U, S, P> {
+    pub fn new(user: &'a U, store: &'a S, permissions: &'a PermissionService<P>) -> Self {
+        Self {
+            user,
+            store,
+            permissions,
+            notifications: None,
+            streams: None,
+        }
+    }
+
+    /// Notify project members about new documents in `notifications`
+    pub fn with_notifications(mut self, notifications: &'a dyn NotificationStore) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+
+    /// Tell connected clients of the members about their new notifications
+    pub fn with_streams(mut self, streams: &'a NotificationStreams) -> Self {
+        self.streams = Some(streams);
+        self
+    }
+
+    /// Create a document in the project
+    pub async fn create(&self, project_id: Id, params: DocumentParams) -> ServiceResult<DocumentRow> {
+        into_result(self.try_create(project_id, params).await)
+    }
+
+    /// Change a document
+    pub async fn update(&self, document_id: Id, params: UpdateDocumentParams) -> ServiceResult<DocumentRow> {
+        into_result(self.try_update(document_id, params).await)
+    }
+
+    /// Delete a document. Attachment files are removed through
+    /// `attachments` once the document is gone.
+    pub async fn delete<St: AttachmentStore, Fs: Storage>(
+        &self,
+        document_id: Id,
+        attachments: Option<&AttachmentService<St, Fs>>,
+    ) -> ServiceResult<DocumentRow> {
+        into_result(self.try_delete(document_id, attachments).await)
+    }
+
+    async fn try_create(&self, project_id: Id, params: DocumentParams) -> Result<DocumentRow, ValidationErrors> {
+        self.authorize(project_id, "You are not authorized to add documents").await?;
+
+        let document = self
+            .store
+            .create_document(CreateDocumentDto {
+                project_id,
+                category_id: params.category_id,
+                title: params.title,
+                description: params.description,
+            })
+            .await
+            .map_err(repository_error)?;
+
+        self.notify_members(&document).await;
+        Ok(document)
+    }
+
+    async fn try_update(&self, document_id: Id, params: UpdateDocumentParams) -> Result<DocumentRow, ValidationErrors> {
+        let document = self.find_document(document_id).await?;
+        self.authorize(document.project_id, "You are not authorized to edit this document")
+            .await?;
+
+        let dto = UpdateDocumentDto {
+            category_id: params.category_id,
+            title: params.title,
+            description: params.description,
+        };
+        self.store
+            .update_document(document.id, dto)
+            .await
+            .map_err(repository_error)
+    }
+
+    async fn try_delete<St: AttachmentStore, Fs: Storage>(
+        &self,
+        document_id: Id,
+        attachments: Option<&AttachmentService<St, Fs>>,
+    ) -> Result<DocumentRow, ValidationErrors> {
+        let document = self.find_document(document_id).await?;
+        self.authorize(document.project_id, "You are not authorized to delete this document")
+            .await?;
+
+        let attachment_ids = self.store.delete_document(document.id).await.map_err(repository_error)?;
+
+        // Files cannot be rolled back, so they go only after the document
+        if let Some(attachments) = attachments {
+            for attachment_id in attachment_ids {
+                if let Err(e) = attachments.delete(attachment_id).await {
+                    warn!(attachment_id, error = %e, "Failed to remove attachment");
+                }
+            }
+        }
+
+        Ok(document)
+    }
+
+    async fn find_document(&self, id: Id) -> Result<DocumentRow, ValidationErrors> {
+        self.store
+            .find_document(id)
+            .await
+            .map_err(base_error)?
+            .ok_or_else(|| base_error("Document not found"))
+    }
+
+    async fn authorize(&self, project_id: Id, message: &str) -> Result<(), ValidationErrors> {
+        let allowed = self
+            .permissions
+            .allowed_in_project(self.user, MANAGE_DOCUMENTS, project_id)
+            .await
+            .map_err(base_error)?;
+        if allowed {
+            Ok(())
+        } else {
+            Err(base_error(message))
+        }
+    }
+
+    /// Notify the project's members who subscribed to new documents. The
+    /// document stands even if notifying fails.
+    async fn notify_members(&self, document: &DocumentRow) {
+        let Some(store) = self.notifications else {
+            return;
+        };
+
+        let members = match self.store.project_members(document.project_id).await {
+            Ok(members) => members,
+            Err(e) => {
+                warn!(project_id = document.project_id, error = %e, "Failed to find project members");
+                return;
+            }
+        };
+
+        let mut notifications = Vec::new();
+        for recipient_id in members.into_iter().filter(|&id| id != self.user.id()) {
+            match store.get_settings(recipient_id).await {
+                Ok(settings) if settings.subscribed_to(NotificationType::DocumentAdded, document.project_id) => {
+                    notifications.push(
+                        Notification::new(
+                            recipient_id,
+                            NotificationType::DocumentAdded,
+                            NotificationReason::ProjectMember,
+                            journable_type::DOCUMENT,
+                            document.id,
+                        )
+                        .with_actor(self.user.id())
+                        .with_project(document.project_id),
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => warn!(recipient_id, error = %e, "Failed to load notification settings"),
+            }
+        }
+        if notifications.is_empty() {
+            return;
+        }
+
+        if let Err(e) = store.create_many(&mut notifications).await {
+            warn!(document_id = document.id, error = %e, "Failed to notify project members");
+            return;
+        }
+
+        if let Some(streams) = self.streams {
+            for notification in &notifications {
+                let ids = notification.id.into_iter().collect();
+                if let Err(e) = streams
+                    .publish_from(store, notification.recipient_id, StreamEventKind::Created, ids)
+                    .await
+                {
+                    warn!(recipient_id = notification.recipient_id, error = %e, "Failed to publish notification");
+                }
+            }
+        }
+    }
+}
+
+fn into_result<T>(result: Result<T, ValidationErrors>) -> ServiceResult<T> {
+    match result {
+        Ok(value) => ServiceResult::success(value),
+        Err(errors) => ServiceResult::failure(errors),
+    }
+}
+
+fn base_error(message: impl ToString) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    errors.add_base(message.to_string());
+    errors
+}
+
+/// Validation errors of the store keep their properties
+fn repository_error(error: RepositoryError) -> ValidationErrors {
+    match error {
+        RepositoryError::Validation(errors) => errors.into(),
+        error => base_error(error),
+    }
+}
+
+/// Documents stored in the database
+pub struct PgDocumentStore {
+    documents: DocumentRepository,
+}
+
+impl PgDocumentStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            documents: DocumentRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl DocumentStore for PgDocumentStore {
+    async fn find_document(&self, id: Id) -> RepositoryResult<Option<DocumentRow>> {
+        self.documents.find_by_id(id).await
+    }
+
+    async fn create_document(&self, dto: CreateDocumentDto) -> RepositoryResult<DocumentRow> {
+        self.documents.create(dto).await
+    }
+
+    async fn update_document(&self, id: Id, dto: UpdateDocumentDto) -> RepositoryResult<DocumentRow> {
+        self.documents.update(id, dto).await
+    }
+
+    async fn delete_document(&self, id: Id) -> RepositoryResult<Vec<Id>> {
+        self.documents.delete_with_attachments(id).await
+    }
+
+    async fn project_members(&self, project_id: Id) -> RepositoryResult<Vec<Id>> {
+        self.documents.member_ids(project_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use op_attachments::{AttachmentConfig, ContainerType, CreateAttachmentParams, MemoryAttachmentStore, MemoryStorage};
+    use op_contracts::documents::permissions::VIEW_DOCUMENTS;
+    use op_notifications::MemoryNotificationStore;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Permissions by (user, project)
+    #[derive(Default)]
+    struct MemoryPermissions {
+        projects: HashMap<(Id, Id), Vec<String>>,
+    }
+
+    #[async_trait]
+    impl PermissionSource for MemoryPermissions {
+        async fn project_permissions(&self, user_id: Id, project_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(self.projects.get(&(user_id, project_id)).cloned().unwrap_or_default())
+        }
+
+        async fn work_package_permissions(&self, _user_id: Id, _work_package_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Documents of project 1 with its members
+    struct MemoryDocumentStore {
+        documents: Mutex<Vec<DocumentRow>>,
+        members: Vec<Id>,
+        /// Attachment ids by document
+        attachments: Mutex<HashMap<Id, Vec<Id>>>,
+    }
+
+    impl MemoryDocumentStore {
+        fn new(members: Vec<Id>) -> Self {
+            Self {
+                documents: Mutex::new(Vec::new()),
+                members,
+                attachments: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl DocumentStore for MemoryDocumentStore {
+        async fn find_document(&self, id: Id) -> RepositoryResult<Option<DocumentRow>> {
+            Ok(self.documents.lock().unwrap().iter().find(|d| d.id == id).cloned())
+        }
+
+        async fn create_document(&self, dto: CreateDocumentDto) -> RepositoryResult<DocumentRow> {
+            if dto.title.trim().is_empty() {
+                return Err(RepositoryError::invalid("title", "blank", "can't be blank"));
+            }
+
+            let mut documents = self.documents.lock().unwrap();
+            let now = Utc::now();
+            let document = DocumentRow {
+                id: documents.len() as Id + 1,
+                project_id: dto.project_id,
+                category_id: dto.category_id,
+                category_name: None,
+                category_position: None,
+                title: dto.title,
+                description: dto.description,
+                created_at: now,
+                updated_at: now,
+            };
+            documents.push(document.clone());
+            Ok(document)
+        }
+
+        async fn update_document(&self, id: Id, dto: UpdateDocumentDto) -> RepositoryResult<DocumentRow> {
+            let mut documents = self.documents.lock().unwrap();
+            let document = documents.iter_mut().find(|d| d.id == id).unwrap();
+            if let Some(title) = dto.title {
+                document.title = title;
+            }
+            if let Some(category_id) = dto.category_id {
+                document.category_id = Some(category_id);
+            }
+            Ok(document.clone())
+        }
+
+        async fn delete_document(&self, id: Id) -> RepositoryResult<Vec<Id>> {
+            self.documents.lock().unwrap().retain(|d| d.id != id);
+            Ok(self.attachments.lock().unwrap().remove(&id).unwrap_or_default())
+        }
+
+        async fn project_members(&self, _project_id: Id) -> RepositoryResult<Vec<Id>> {
+            Ok(self.members.clone())
+        }
+    }
+
+    struct Member {
+        id: Id,
+    }
+
+    impl UserContext for Member {
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn is_admin(&self) -> bool {
+            false
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+            false
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            false
+        }
+    }
+
+    /// In project 1, user 1 manages documents, users 2 and 3 read them
+    fn permissions() -> PermissionService<MemoryPermissions> {
+        let mut source = MemoryPermissions::default();
+        source
+            .projects
+            .insert((1, 1), vec![VIEW_DOCUMENTS.to_string(), MANAGE_DOCUMENTS.to_string()]);
+        for reader in [2, 3] {
+            source.projects.insert((reader, 1), vec![VIEW_DOCUMENTS.to_string()]);
+        }
+        PermissionService::new(source)
+    }
+
+    fn document(title: &str) -> DocumentParams {
+        DocumentParams {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    type NoAttachments = AttachmentService<MemoryAttachmentStore, MemoryStorage>;
+
+    #[tokio::test]
+    async fn test_new_documents_notify_subscribed_members() {
+        let store = MemoryDocumentStore::new(vec![1, 2, 3]);
+        let permissions = permissions();
+        let notifications = MemoryNotificationStore::new();
+        let mut subscribed = notifications.get_settings(2).await.unwrap();
+        subscribed.enabled_types.push(NotificationType::DocumentAdded);
+        notifications.update_settings(&subscribed).await.unwrap();
+
+        let service = DocumentService::new(&Member { id: 1 }, &store, &permissions).with_notifications(&notifications);
+        let created = service.create(1, document("Handbook")).await.unwrap();
+
+        // Only members who subscribed to new documents hear about it
+        let received = notifications.get_for_user(2, false, 10).await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!((received[0].resource_type.as_str(), received[0].resource_id), ("Document", created.id));
+        assert_eq!(
+            (received[0].notification_type, received[0].actor_id, received[0].project_id),
+            (NotificationType::DocumentAdded, Some(1), Some(1))
+        );
+        assert!(notifications.get_for_user(1, false, 10).await.unwrap().is_empty());
+        assert!(notifications.get_for_user(3, false, 10).await.unwrap().is_empty());
+
+        // Changes notify nobody
+        let retitled = UpdateDocumentParams {
+            title: Some("User handbook".into()),
+            ..Default::default()
+        };
+        assert_eq!(service.update(created.id, retitled).await.unwrap().title, "User handbook");
+        assert_eq!(notifications.get_for_user(2, false, 10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_managing_documents_needs_permission() {
+        let store = MemoryDocumentStore::new(Vec::new());
+        let permissions = permissions();
+        let manager = DocumentService::new(&Member { id: 1 }, &store, &permissions);
+        let reader = DocumentService::new(&Member { id: 2 }, &store, &permissions);
+
+        let result = reader.create(1, document("Minutes")).await;
+        assert!(result.full_messages().iter().any(|m| m.contains("not authorized")));
+        assert!(manager.create(1, document(" ")).await.errors().has_error("title"));
+
+        let created = manager.create(1, document("Minutes")).await.unwrap();
+        assert!(reader.update(created.id, UpdateDocumentParams::default()).await.is_failure());
+        assert!(reader.delete(created.id, None::<&NoAttachments>).await.is_failure());
+        assert_eq!(store.documents.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_a_document_removes_its_attachments() {
+        let store = MemoryDocumentStore::new(Vec::new());
+        let permissions = permissions();
+        let attachments = AttachmentService::new(
+            Arc::new(MemoryAttachmentStore::new()),
+            Arc::new(MemoryStorage::new()),
+            AttachmentConfig::default(),
+        );
+        let manager = DocumentService::new(&Member { id: 1 }, &store, &permissions);
+
+        let created = manager.create(1, document("Specification")).await.unwrap();
+        let file_id = attachments
+            .create(
+                CreateAttachmentParams::new("spec.pdf").container(ContainerType::Document, created.id),
+                "pdf".into(),
+                1,
+            )
+            .await
+            .unwrap()
+            .attachment
+            .id
+            .unwrap();
+        store.attachments.lock().unwrap().insert(created.id, vec![file_id]);
+
+        let deleted = manager.delete(created.id, Some(&attachments)).await.unwrap();
+        assert_eq!(deleted.id, created.id);
+        assert!(store.find_document(created.id).await.unwrap().is_none());
+        assert!(attachments.get(file_id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,719 @@
+//! Forum message services
+//!
+//! Mirrors:
+//! - app/services/messages/create_service.rb
+//! - app/services/messages/update_service.rb
+//! - app/controllers/messages_controller.rb#destroy
+//! - app/workers/notifications/workflow_job.rb (forum watchers)
+//!
+//! Topics are messages without a parent; replies belong to a topic. Topic
+//! and forum counters are kept by the store in the same transaction as the
+//! message itself, which is also where replies to locked topics are
+//! rejected. Watchers of a forum are notified about its new topics.
+
+use async_trait::async_trait;
+use op_attachments::{AttachmentService, AttachmentStore, Storage};
+use op_contracts::base::UserContext;
+use op_contracts::forums::permissions::{
+    ADD_MESSAGES, DELETE_MESSAGES, DELETE_OWN_MESSAGES, EDIT_MESSAGES, EDIT_OWN_MESSAGES, MANAGE_FORUMS,
+};
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+use op_db::{
+    journable_type, CreateMessageDto, ForumRepository, ForumRow, MessageRepository, MessageRow, Repository,
+    RepositoryError, RepositoryResult, UpdateMessageDto,
+};
+use op_notifications::{
+    Notification, NotificationReason, NotificationStore, NotificationStreams, NotificationType, StreamEventKind,
+};
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::permissions::{PermissionService, PermissionSource};
+use crate::result::ServiceResult;
+
+/// Subject and content of a new topic or reply
+#[derive(Debug, Clone, Default)]
+pub struct MessageParams {
+    pub subject: String,
+    pub content: Option<String>,
+    /// Lock the new topic; needs `manage_forums`
+    pub locked: bool,
+    /// Pin the new topic on top of the forum; needs `manage_forums`
+    pub sticky: bool,
+}
+
+/// Changes to a message; `None` keeps the current value
+#[derive(Debug, Clone, Default)]
+pub struct UpdateMessageParams {
+    pub subject: Option<String>,
+    pub content: Option<String>,
+    pub locked: Option<bool>,
+    pub sticky: Option<bool>,
+}
+
+/// Storage of forums and their messages
+#[async_trait]
+pub trait ForumStore: Send + Sync {
+    async fn find_forum(&self, id: Id) -> RepositoryResult<Option<ForumRow>>;
+
+    async fn find_message(&self, id: Id) -> RepositoryResult<Option<MessageRow>>;
+
+    /// Create a topic or reply, updating the counters of topic and forum
+    async fn create_message(&self, dto: CreateMessageDto) -> RepositoryResult<MessageRow>;
+
+    /// Update a message, journaling the change as made by `user_id`
+    async fn update_message(&self, id: Id, user_id: Id, dto: UpdateMessageDto) -> RepositoryResult<MessageRow>;
+
+    /// Delete a message with its replies, returning the ids of their attachments
+    async fn delete_message(&self, id: Id) -> RepositoryResult<Vec<Id>>;
+
+    /// Watchers of the forum allowed to view its messages
+    async fn forum_watchers(&self, forum_id: Id) -> RepositoryResult<Vec<Id>>;
+}
+
+/// Service for posting, editing and deleting forum messages
+///
+/// Posting needs `add_messages` in the forum's project. Authors edit and
+/// delete their own messages with the `*_own_messages` permissions, others'
+/// with `edit_messages` and `delete_messages`. Locking and pinning topics
+/// needs `manage_forums`.
+pub struct MessageService<'a, U: UserContext, S: ForumStore, P: PermissionSource> {
+    user: &'a U,
+    store: &'a S,
+    permissions: &'a PermissionService<P>,
+    notifications: Option<&'a dyn NotificationStore>,
+    streams: Option<&'a NotificationStreams>,
+}
+
+impl<'a, U: UserContext, S: ForumStore, P: PermissionSource> MessageService<'a, U, S, P> {
+    pub fn new(user: &'a U, store: &'a S, permissions: &'a PermissionService<P>) -> Self {
+        Self {
+            user,
+            store,
+            permissions,
+            notifications: None,
+            streams: None,
+        }
+    }
+
+    /// Notify forum watchers about new topics in `notifications`
+    pub fn with_notifications(mut self, notifications: &'a dyn NotificationStore) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+
+    /// Tell connected clients of the watchers about their new notifications
+    pub fn with_streams(mut self, streams: &'a NotificationStreams) -> Self {
+        self.streams = Some(streams);
+        self
+    }
+
+    /// Start a topic in the forum
+    pub async fn create_topic(&self, forum_id: Id, params: MessageParams) -> ServiceResult<MessageRow> {
+        into_result(self.try_create_topic(forum_id, params).await)
+    }
+
+    /// Reply to a topic; locked topics take no replies
+    pub async fn reply(&self, topic_id: Id, params: MessageParams) -> ServiceResult<MessageRow> {
+        into_result(self.try_reply(topic_id, params).await)
+    }
+
+    /// Edit a message
+    pub async fn update(&self, message_id: Id, params: UpdateMessageParams) -> ServiceResult<MessageRow> {
+        into_result(self.try_update(message_id, params).await)
+    }
+
+    /// Delete a message; deleting a topic deletes its replies. Attachment
+    /// files are removed through `attachments` once the messages are gone.
+    pub async fn delete<St: AttachmentStore, Fs: Storage>(
+        &self,
+        message_id: Id,
+        attachments: Option<&AttachmentService<St, Fs>>,
+    ) -> ServiceResult<MessageRow> {
+        into_result(self.try_delete(message_id, attachments).await)
+    }
+
+    async fn try_create_topic(&self, forum_id: Id, params: MessageParams) -> Result<MessageRow, ValidationErrors> {
+        let forum = self
+            .store
+            .find_forum(forum_id)
+            .await
+            .map_err(base_error)?
+            .ok_or_else(|| base_error("Forum not found"))?;
+        self.authorize(ADD_MESSAGES, forum.project_id, "You are not authorized to post in this forum")
+            .await?;
+        if (params.locked || params.sticky) && !self.allowed(MANAGE_FORUMS, forum.project_id).await? {
+            return Err(base_error("You are not authorized to lock or pin topics"));
+        }
+
+        let topic = self
+            .store
+            .create_message(CreateMessageDto {
+                forum_id: forum.id,
+                parent_id: None,
+                subject: params.subject,
+                content: params.content,
+                author_id: self.user.id(),
+                locked: params.locked,
+                sticky: params.sticky,
+            })
+            .await
+            .map_err(repository_error)?;
+
+        self.notify_watchers(&forum, &topic).await;
+        Ok(topic)
+    }
+
+    async fn try_reply(&self, topic_id: Id, params: MessageParams) -> Result<MessageRow, ValidationErrors> {
+        let (topic, forum) = self.find_message(topic_id).await?;
+        self.authorize(ADD_MESSAGES, forum.project_id, "You are not authorized to post in this forum")
+            .await?;
+
+        let subject = if params.subject.trim().is_empty() {
+            format!("RE: {}", topic.subject)
+        } else {
+            params.subject
+        };
+
+        self.store
+            .create_message(CreateMessageDto {
+                forum_id: forum.id,
+                parent_id: Some(topic.id),
+                subject,
+                content: params.content,
+                author_id: self.user.id(),
+                locked: false,
+                sticky: false,
+            })
+            .await
+            .map_err(repository_error)
+    }
+
+    async fn try_update(&self, message_id: Id, params: UpdateMessageParams) -> Result<MessageRow, ValidationErrors> {
+        let (message, forum) = self.find_message(message_id).await?;
+        if !self.may_change(&message, forum.project_id, EDIT_MESSAGES, EDIT_OWN_MESSAGES).await? {
+            return Err(base_error("You are not authorized to edit this message"));
+        }
+        if (params.locked.is_some() || params.sticky.is_some())
+            && !self.allowed(MANAGE_FORUMS, forum.project_id).await?
+        {
+            return Err(base_error("You are not authorized to lock or pin topics"));
+        }
+
+        let dto = UpdateMessageDto {
+            subject: params.subject,
+            content: params.content,
+            locked: params.locked,
+            sticky: params.sticky,
+        };
+        self.store
+            .update_message(message.id, self.user.id(), dto)
+            .await
+            .map_err(repository_error)
+    }
+
+    async fn try_delete<St: AttachmentStore, Fs: Storage>(
+        &self,
+        message_id: Id,
+        attachments: Option<&AttachmentService<St, Fs>>,
+    ) -> Result<MessageRow, ValidationErrors> {
+        let (message, forum) = self.find_message(message_id).await?;
+        if !self.may_change(&message, forum.project_id, DELETE_MESSAGES, DELETE_OWN_MESSAGES).await? {
+            return Err(base_error("You are not authorized to delete this message"));
+        }
+
+        let attachment_ids = self.store.delete_message(message.id).await.map_err(repository_error)?;
+
+        // Files cannot be rolled back, so they go only after the messages
+        if let Some(attachments) = attachments {
+            for attachment_id in attachment_ids {
+                if let Err(e) = attachments.delete(attachment_id).await {
+                    warn!(attachment_id, error = %e, "Failed to remove attachment");
+                }
+            }
+        }
+
+        Ok(message)
+    }
+
+    async fn find_message(&self, id: Id) -> Result<(MessageRow, ForumRow), ValidationErrors> {
+        let message = self
+            .store
+            .find_message(id)
+            .await
+            .map_err(base_error)?
+            .ok_or_else(|| base_error("Message not found"))?;
+        let forum = self
+            .store
+            .find_forum(message.forum_id)
+            .await
+            .map_err(base_error)?
+            .ok_or_else(|| base_error("Forum not found"))?;
+        Ok((message, forum))
+    }
+
+    async fn allowed(&self, permission: &str, project_id: Id) -> Result<bool, ValidationErrors> {
+        self.permissions
+            .allowed_in_project(self.user, permission, project_id)
+            .await
+            .map_err(base_error)
+    }
+
+    async fn authorize(&self, permission: &str, project_id: Id, message: &str) -> Result<(), ValidationErrors> {
+        if self.allowed(permission, project_id).await? {
+            Ok(())
+        } else {
+            Err(base_error(message))
+        }
+    }
+
+    /// Whether the user may change any message, or this one as its author
+    async fn may_change(
+        &self,
+        message: &MessageRow,
+        project_id: Id,
+        any: &str,
+        own: &str,
+    ) -> Result<bool, ValidationErrors> {
+        if self.allowed(any, project_id).await? {
+            return Ok(true);
+        }
+        Ok(message.author_id == Some(self.user.id()) && self.allowed(own, project_id).await?)
+    }
+
+    /// Notify the forum's watchers about a new topic. The topic stands even
+    /// if notifying fails.
+    async fn notify_watchers(&self, forum: &ForumRow, topic: &MessageRow) {
+        let Some(store) = self.notifications else {
+            return;
+        };
+
+        let watchers = match self.store.forum_watchers(forum.id).await {
+            Ok(watchers) => watchers,
+            Err(e) => {
+                warn!(forum_id = forum.id, error = %e, "Failed to find forum watchers");
+                return;
+            }
+        };
+
+        let mut notifications = Vec::new();
+        for recipient_id in watchers.into_iter().filter(|&id| id != self.user.id()) {
+            match store.get_settings(recipient_id).await {
+                Ok(settings)
+                    if settings.should_notify(
+                        NotificationType::MessagePosted,
+                        NotificationReason::Watched,
+                        Some(forum.project_id),
+                    ) =>
+                {
+                    notifications.push(
+                        Notification::new(
+                            recipient_id,
+                            NotificationType::MessagePosted,
+                            NotificationReason::Watched,
+                            journable_type::MESSAGE,
+                            topic.id,
+                        )
+                        .with_actor(self.user.id())
+                        .with_project(forum.project_id),
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => warn!(recipient_id, error = %e, "Failed to load notification settings"),
+            }
+        }
+        if notifications.is_empty() {
+            return;
+        }
+
+        if let Err(e) = store.create_many(&mut notifications).await {
+            warn!(message_id = topic.id, error = %e, "Failed to notify forum watchers");
+            return;
+        }
+
+        if let Some(streams) = self.streams {
+            for notification in &notifications {
+                let ids = notification.id.into_iter().collect();
+                if let Err(e) = streams
+                    .publish_from(store, notification.recipient_id, StreamEventKind::Created, ids)
+                    .await
+                {
+                    warn!(recipient_id = notification.recipient_id, error = %e, "Failed to publish notification");
+                }
+            }
+        }
+    }
+}
+
+fn into_result<T>(result: Result<T, ValidationErrors>) -> ServiceResult<T> {
+    match result {
+        Ok(value) => ServiceResult::success(value),
+        Err(errors) => ServiceResult::failure(errors),
+    }
+}
+
+fn base_error(message: impl ToString) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    errors.add_base(message.to_string());
+    errors
+}
+
+/// Validation errors of the store keep their properties
+fn repository_error(error: RepositoryError) -> ValidationErrors {
+    match error {
+        RepositoryError::Validation(errors) => errors.into(),
+        error => base_error(error),
+    }
+}
+
+/// Forums and messages stored in the database
+pub struct PgForumStore {
+    forums: ForumRepository,
+    messages: MessageRepository,
+}
+
+impl PgForumStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            forums: ForumRepository::new(pool.clone()),
+            messages: MessageRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl ForumStore for PgForumStore {
+    async fn find_forum(&self, id: Id) -> RepositoryResult<Option<ForumRow>> {
+        self.forums.find_by_id(id).await
+    }
+
+    async fn find_message(&self, id: Id) -> RepositoryResult<Option<MessageRow>> {
+        self.messages.find_by_id(id).await
+    }
+
+    async fn create_message(&self, dto: CreateMessageDto) -> RepositoryResult<MessageRow> {
+        self.messages.create(dto).await
+    }
+
+    async fn update_message(&self, id: Id, user_id: Id, dto: UpdateMessageDto) -> RepositoryResult<MessageRow> {
+        self.messages.update(id, user_id, dto).await
+    }
+
+    async fn delete_message(&self, id: Id) -> RepositoryResult<Vec<Id>> {
+        self.messages.delete(id).await
+    }
+
+    async fn forum_watchers(&self, forum_id: Id) -> RepositoryResult<Vec<Id>> {
+        self.forums.watcher_ids(forum_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use op_attachments::{AttachmentConfig, ContainerType, CreateAttachmentParams, MemoryAttachmentStore, MemoryStorage};
+    use op_contracts::forums::permissions::VIEW_MESSAGES;
+    use op_notifications::MemoryNotificationStore;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Permissions by (user, project)
+    #[derive(Default)]
+    struct MemoryPermissions {
+        projects: HashMap<(Id, Id), Vec<String>>,
+    }
+
+    #[async_trait]
+    impl PermissionSource for MemoryPermissions {
+        async fn project_permissions(&self, user_id: Id, project_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(self.projects.get(&(user_id, project_id)).cloned().unwrap_or_default())
+        }
+
+        async fn work_package_permissions(&self, _user_id: Id, _work_package_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Forum 1 of project 1 with its messages and watchers
+    struct MemoryForumStore {
+        forum: Mutex<ForumRow>,
+        messages: Mutex<Vec<MessageRow>>,
+        watchers: Vec<Id>,
+        /// Attachment ids by message
+        attachments: Mutex<HashMap<Id, Vec<Id>>>,
+    }
+
+    impl MemoryForumStore {
+        fn new(watchers: Vec<Id>) -> Self {
+            Self {
+                forum: Mutex::new(ForumRow {
+                    id: 1,
+                    project_id: 1,
+                    name: "General".into(),
+                    description: None,
+                    position: 1,
+                    topics_count: 0,
+                    messages_count: 0,
+                    last_message_id: None,
+                }),
+                messages: Mutex::new(Vec::new()),
+                watchers,
+                attachments: Mutex::new(HashMap::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ForumStore for MemoryForumStore {
+        async fn find_forum(&self, id: Id) -> RepositoryResult<Option<ForumRow>> {
+            let forum = self.forum.lock().unwrap();
+            Ok((forum.id == id).then(|| forum.clone()))
+        }
+
+        async fn find_message(&self, id: Id) -> RepositoryResult<Option<MessageRow>> {
+            Ok(self.messages.lock().unwrap().iter().find(|m| m.id == id).cloned())
+        }
+
+        async fn create_message(&self, dto: CreateMessageDto) -> RepositoryResult<MessageRow> {
+            let mut messages = self.messages.lock().unwrap();
+            if let Some(parent_id) = dto.parent_id {
+                let parent = messages.iter().find(|m| m.id == parent_id).unwrap();
+                if parent.locked {
+                    return Err(RepositoryError::invalid("parent", "locked", "The topic is locked."));
+                }
+            }
+
+            let now = Utc::now();
+            let message = MessageRow {
+                id: messages.len() as Id + 1,
+                forum_id: dto.forum_id,
+                parent_id: dto.parent_id,
+                subject: dto.subject,
+                content: dto.content,
+                author_id: Some(dto.author_id),
+                replies_count: 0,
+                last_reply_id: None,
+                last_reply_at: None,
+                locked: dto.locked,
+                sticky: i32::from(dto.sticky),
+                sticked_on: dto.sticky.then_some(now),
+                created_at: now,
+                updated_at: now,
+            };
+            if let Some(parent) = messages.iter_mut().find(|m| Some(m.id) == dto.parent_id) {
+                parent.replies_count += 1;
+                parent.last_reply_id = Some(message.id);
+            }
+            messages.push(message.clone());
+            Ok(message)
+        }
+
+        async fn update_message(&self, id: Id, _user_id: Id, dto: UpdateMessageDto) -> RepositoryResult<MessageRow> {
+            let mut messages = self.messages.lock().unwrap();
+            let message = messages.iter_mut().find(|m| m.id == id).unwrap();
+            if let Some(subject) = dto.subject {
+                message.subject = subject;
+            }
+            if let Some(locked) = dto.locked {
+                message.locked = locked;
+            }
+            Ok(message.clone())
+        }
+
+        async fn delete_message(&self, id: Id) -> RepositoryResult<Vec<Id>> {
+            let mut messages = self.messages.lock().unwrap();
+            let ids: Vec<Id> = messages
+                .iter()
+                .filter(|m| m.id == id || m.parent_id == Some(id))
+                .map(|m| m.id)
+                .collect();
+            messages.retain(|m| !ids.contains(&m.id));
+
+            let mut attachments = self.attachments.lock().unwrap();
+            Ok(ids.iter().flat_map(|id| attachments.remove(id).unwrap_or_default()).collect())
+        }
+
+        async fn forum_watchers(&self, _forum_id: Id) -> RepositoryResult<Vec<Id>> {
+            Ok(self.watchers.clone())
+        }
+    }
+
+    struct Poster {
+        id: Id,
+    }
+
+    impl UserContext for Poster {
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn is_admin(&self) -> bool {
+            false
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+            false
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            false
+        }
+    }
+
+    /// In project 1, user 1 moderates the forums, users 2 and 3 post and
+    /// edit their own messages, user 4 only reads
+    fn permissions() -> PermissionService<MemoryPermissions> {
+        let mut source = MemoryPermissions::default();
+        let grant = |permissions: &[&str]| permissions.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        source.projects.insert(
+            (1, 1),
+            grant(&[VIEW_MESSAGES, ADD_MESSAGES, EDIT_MESSAGES, DELETE_MESSAGES, MANAGE_FORUMS]),
+        );
+        for poster in [2, 3] {
+            source.projects.insert(
+                (poster, 1),
+                grant(&[VIEW_MESSAGES, ADD_MESSAGES, EDIT_OWN_MESSAGES, DELETE_OWN_MESSAGES]),
+            );
+        }
+        source.projects.insert((4, 1), grant(&[VIEW_MESSAGES]));
+        PermissionService::new(source)
+    }
+
+    fn post(subject: &str) -> MessageParams {
+        MessageParams {
+            subject: subject.into(),
+            content: Some("Hello".into()),
+            ..Default::default()
+        }
+    }
+
+    type NoAttachments = AttachmentService<MemoryAttachmentStore, MemoryStorage>;
+
+    #[tokio::test]
+    async fn test_new_topics_notify_forum_watchers() {
+        let store = MemoryForumStore::new(vec![2, 3, 4]);
+        let permissions = permissions();
+        let notifications = MemoryNotificationStore::new();
+        let mut muted = notifications.get_settings(4).await.unwrap();
+        muted.enabled_types.retain(|t| *t != NotificationType::MessagePosted);
+        notifications.update_settings(&muted).await.unwrap();
+
+        let service = MessageService::new(&Poster { id: 2 }, &store, &permissions).with_notifications(&notifications);
+        let topic = service.create_topic(1, post("Release party")).await.unwrap();
+        assert_eq!((topic.author_id, topic.parent_id), (Some(2), None));
+
+        // The author and watchers who muted forum messages are left out
+        let received = notifications.get_for_user(3, false, 10).await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!((received[0].resource_type.as_str(), received[0].resource_id), ("Message", topic.id));
+        assert_eq!((received[0].reason, received[0].actor_id), (NotificationReason::Watched, Some(2)));
+        assert!(notifications.get_for_user(2, false, 10).await.unwrap().is_empty());
+        assert!(notifications.get_for_user(4, false, 10).await.unwrap().is_empty());
+
+        // Replies do not notify forum watchers
+        service.reply(topic.id, post("")).await.unwrap();
+        assert_eq!(notifications.get_for_user(3, false, 10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_locked_topics_take_no_replies() {
+        let store = MemoryForumStore::new(Vec::new());
+        let permissions = permissions();
+        let moderator = MessageService::new(&Poster { id: 1 }, &store, &permissions);
+        let poster = MessageService::new(&Poster { id: 2 }, &store, &permissions);
+
+        let topic = poster.create_topic(1, post("Questions")).await.unwrap();
+        let reply = poster.reply(topic.id, post("")).await.unwrap();
+        assert_eq!(reply.subject, "RE: Questions");
+
+        // Only moderators lock topics
+        let lock = UpdateMessageParams {
+            locked: Some(true),
+            ..Default::default()
+        };
+        assert!(poster.update(topic.id, lock.clone()).await.is_failure());
+        assert!(moderator.update(topic.id, lock).await.unwrap().locked);
+
+        let result = poster.reply(topic.id, post("Too late")).await;
+        assert!(result.errors().has_error("parent"));
+        assert_eq!(store.find_message(topic.id).await.unwrap().unwrap().replies_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_posting_needs_permission() {
+        let store = MemoryForumStore::new(Vec::new());
+        let permissions = permissions();
+
+        let reader = MessageService::new(&Poster { id: 4 }, &store, &permissions);
+        let result = reader.create_topic(1, post("Hi")).await;
+        assert!(result.full_messages().iter().any(|m| m.contains("not authorized")));
+
+        let poster = MessageService::new(&Poster { id: 2 }, &store, &permissions);
+        let pinned = MessageParams {
+            sticky: true,
+            ..post("Read me first")
+        };
+        assert!(poster.create_topic(1, pinned).await.is_failure());
+        assert!(store.messages.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_authors_change_their_own_messages() {
+        let store = MemoryForumStore::new(Vec::new());
+        let permissions = permissions();
+        let author = MessageService::new(&Poster { id: 2 }, &store, &permissions);
+        let other = MessageService::new(&Poster { id: 3 }, &store, &permissions);
+
+        let topic = author.create_topic(1, post("Draft")).await.unwrap();
+        let edit = UpdateMessageParams {
+            subject: Some("Final".into()),
+            ..Default::default()
+        };
+        assert!(other.update(topic.id, edit.clone()).await.is_failure());
+        assert_eq!(author.update(topic.id, edit).await.unwrap().subject, "Final");
+
+        assert!(other.delete(topic.id, None::<&NoAttachments>).await.is_failure());
+        assert!(author.delete(topic.id, None::<&NoAttachments>).await.is_success());
+        assert!(store.messages.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deleting_a_topic_removes_reply_attachments() {
+        let store = MemoryForumStore::new(Vec::new());
+        let permissions = permissions();
+        let attachments = AttachmentService::new(
+            Arc::new(MemoryAttachmentStore::new()),
+            Arc::new(MemoryStorage::new()),
+            AttachmentConfig::default(),
+        );
+        let poster = MessageService::new(&Poster { id: 2 }, &store, &permissions);
+        let moderator = MessageService::new(&Poster { id: 1 }, &store, &permissions);
+
+        let topic = poster.create_topic(1, post("Screenshots")).await.unwrap();
+        let reply = moderator.reply(topic.id, post("")).await.unwrap();
+        let file_id = attachments
+            .create(
+                CreateAttachmentParams::new("screen.png").container(ContainerType::Message, reply.id),
+                "png".into(),
+                1,
+            )
+            .await
+            .unwrap()
+            .attachment
+            .id
+            .unwrap();
+        store.attachments.lock().unwrap().insert(reply.id, vec![file_id]);
+
+        // The author of the topic deletes it with the moderator's reply
+        let deleted = poster.delete(topic.id, Some(&attachments)).await.unwrap();
+        assert_eq!(deleted.id, topic.id);
+        assert!(store.find_message(reply.id).await.unwrap().is_none());
+        assert!(attachments.get(file_id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,852 @@
+//! LDAP group synchronization
+//!
+//! Mirrors: modules/ldap_groups/app/services/ldap_groups/synchronize_groups_service.rb,
+//! modules/ldap_groups/app/workers/ldap_groups/synchronization_job.rb
+//!
+//! The [`LDAP_GROUP_SYNC_JOB`] mirrors the groups of every LDAP source with
+//! group synchronization configured as local groups. Groups are matched by
+//! an identifier that survives renames, `entryUUID` by default, so renaming
+//! or moving a group in LDAP renames the local group. The members of each
+//! group are reconciled with the users whose login the membership attribute
+//! names, either through the users' DNs or directly as logins; members that
+//! match no local user are listed in the report. Users added to or removed
+//! from a group gain or lose the roles of the group's project memberships.
+//!
+//! Groups that disappeared from LDAP lose their synchronized members and
+//! stop being synchronized, but the local group is kept along with its
+//! memberships.
+//!
+//! Searches and member lists are fetched in pages, so large groups are
+//! never held in memory as a whole beyond the ids of their members. The job
+//! accepts `{"dry_run": true}` to report what would change without writing,
+//! and `{"source": "<name>"}` to synchronize one source only.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use op_core::config::{LdapConfig, LdapGroupSyncConfig};
+use op_core::traits::Id;
+use op_db::{GroupRepository, RepositoryError, RepositoryResult, SynchronizedGroupRow, UserRepository};
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+/// Job type synchronizing the groups of the LDAP sources
+pub const LDAP_GROUP_SYNC_JOB: &str = "LdapGroups::SynchronizationJob";
+
+/// Failure talking to an LDAP server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapError(pub String);
+
+impl fmt::Display for LdapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LDAP error: {}", self.0)
+    }
+}
+
+pub type LdapResult<T> = Result<T, LdapError>;
+
+/// Entry of an LDAP directory; attribute names are compared
+/// case-insensitively like LDAP does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapEntry {
+    pub dn: String,
+    attributes: HashMap<String, Vec<String>>,
+}
+
+impl LdapEntry {
+    pub fn new(dn: impl Into<String>) -> Self {
+        Self {
+            dn: dn.into(),
+            attributes: HashMap::new(),
+        }
+    }
+
+    pub fn with(mut self, attribute: &str, values: &[&str]) -> Self {
+        self.attributes
+            .entry(attribute.to_lowercase())
+            .or_default()
+            .extend(values.iter().map(|value| value.to_string()));
+        self
+    }
+
+    pub fn values(&self, attribute: &str) -> &[String] {
+        self.attributes.get(&attribute.to_lowercase()).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn first(&self, attribute: &str) -> Option<&str> {
+        self.values(attribute).first().map(String::as_str)
+    }
+
+    /// Entry with only the requested attributes, like a search returns it
+    fn select(&self, attributes: &[&str]) -> Self {
+        let mut selected = Self::new(self.dn.clone());
+        for attribute in attributes {
+            if let Some(values) = self.attributes.get(&attribute.to_lowercase()) {
+                selected.attributes.insert(attribute.to_lowercase(), values.clone());
+            }
+        }
+        selected
+    }
+}
+
+/// Page of search results
+#[derive(Debug, Clone, Default)]
+pub struct SearchPage {
+    pub entries: Vec<LdapEntry>,
+    /// Continues the search with the next page, `None` on the last page
+    pub cookie: Option<String>,
+}
+
+/// Read access to an LDAP directory
+#[async_trait]
+pub trait LdapDirectory: Send + Sync {
+    /// Page of at most `page_size` entries at or below `base_dn` matching
+    /// the filter, with the requested attributes. `cookie` continues a
+    /// previous search (simple paged results).
+    async fn search(
+        &self,
+        base_dn: &str,
+        filter: &str,
+        attributes: &[&str],
+        page_size: u32,
+        cookie: Option<&str>,
+    ) -> LdapResult<SearchPage>;
+
+    /// At most `count` values of a multi-valued attribute starting at
+    /// `start` (ranged retrieval); empty past the last value
+    async fn attribute_values(&self, dn: &str, attribute: &str, start: usize, count: usize) -> LdapResult<Vec<String>>;
+
+    /// The entry with the DN, with the requested attributes
+    async fn entry(&self, dn: &str, attributes: &[&str]) -> LdapResult<Option<LdapEntry>>;
+}
+
+/// In-process directory, e.g. for tests. Supports filters made of
+/// equality and presence assertions combined with `&`, `|` and `!`.
+#[derive(Default)]
+pub struct MemoryDirectory {
+    entries: RwLock<Vec<LdapEntry>>,
+}
+
+impl MemoryDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the entry, replacing one with the same DN
+    pub fn insert(&self, entry: LdapEntry) {
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|existing| !existing.dn.eq_ignore_ascii_case(&entry.dn));
+        entries.push(entry);
+    }
+
+    pub fn remove(&self, dn: &str) {
+        self.entries.write().unwrap().retain(|entry| !entry.dn.eq_ignore_ascii_case(dn));
+    }
+}
+
+#[async_trait]
+impl LdapDirectory for MemoryDirectory {
+    async fn search(
+        &self,
+        base_dn: &str,
+        filter: &str,
+        attributes: &[&str],
+        page_size: u32,
+        cookie: Option<&str>,
+    ) -> LdapResult<SearchPage> {
+        let offset = match cookie {
+            Some(cookie) => cookie.parse::<usize>().map_err(|_| LdapError("invalid paging cookie".into()))?,
+            None => 0,
+        };
+        let base_dn = base_dn.to_lowercase();
+        let entries = self.entries.read().unwrap();
+        let matching: Vec<&LdapEntry> = entries
+            .iter()
+            .filter(|entry| entry.dn.to_lowercase().ends_with(&base_dn))
+            .filter(|entry| filter_matches(filter, entry))
+            .collect();
+
+        let end = (offset + page_size.max(1) as usize).min(matching.len());
+        Ok(SearchPage {
+            entries: matching[offset.min(end)..end].iter().map(|entry| entry.select(attributes)).collect(),
+            cookie: (end < matching.len()).then(|| end.to_string()),
+        })
+    }
+
+    async fn attribute_values(&self, dn: &str, attribute: &str, start: usize, count: usize) -> LdapResult<Vec<String>> {
+        let entries = self.entries.read().unwrap();
+        let entry = entries
+            .iter()
+            .find(|entry| entry.dn.eq_ignore_ascii_case(dn))
+            .ok_or_else(|| LdapError(format!("no such object: {}", dn)))?;
+        Ok(entry.values(attribute).iter().skip(start).take(count).cloned().collect())
+    }
+
+    async fn entry(&self, dn: &str, attributes: &[&str]) -> LdapResult<Option<LdapEntry>> {
+        let entries = self.entries.read().unwrap();
+        Ok(entries
+            .iter()
+            .find(|entry| entry.dn.eq_ignore_ascii_case(dn))
+            .map(|entry| entry.select(attributes)))
+    }
+}
+
+/// Whether the entry matches an LDAP filter like `(&(objectClass=group)(cn=*))`
+fn filter_matches(filter: &str, entry: &LdapEntry) -> bool {
+    let filter = filter.trim();
+    let inner = filter
+        .strip_prefix('(')
+        .and_then(|rest| rest.strip_suffix(')'))
+        .unwrap_or(filter);
+
+    match inner.chars().next() {
+        Some('&') => filter_components(&inner[1..]).iter().all(|component| filter_matches(component, entry)),
+        Some('|') => filter_components(&inner[1..]).iter().any(|component| filter_matches(component, entry)),
+        Some('!') => !filter_matches(&inner[1..], entry),
+        _ => match inner.split_once('=') {
+            Some((attribute, "*")) => !entry.values(attribute).is_empty(),
+            Some((attribute, value)) => entry.values(attribute).iter().any(|v| v.eq_ignore_ascii_case(value)),
+            None => false,
+        },
+    }
+}
+
+/// Parenthesized components of `&` and `|` filters
+fn filter_components(filters: &str) -> Vec<&str> {
+    let mut components = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in filters.char_indices() {
+        match c {
+            '(' => {
+                if depth == 0 {
+                    start = i;
+                }
+                depth += 1;
+            }
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    components.push(&filters[start..=i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    components
+}
+
+/// Member value that matches no local user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnresolvableMember {
+    /// Name of the group
+    pub group: String,
+    /// Value of the membership attribute, a DN or a login
+    pub member: String,
+}
+
+/// Changes made, or in a dry run that would be made, for an LDAP source
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct GroupSyncReport {
+    pub source: String,
+    pub dry_run: bool,
+    pub groups_created: usize,
+    /// Groups renamed or moved in LDAP
+    pub groups_updated: usize,
+    /// Groups no longer in LDAP
+    pub groups_removed: usize,
+    pub members_added: usize,
+    pub members_removed: usize,
+    pub unresolvable_members: Vec<UnresolvableMember>,
+}
+
+/// Storage of synchronized groups and their members
+#[async_trait]
+pub trait GroupSyncStore: Send + Sync {
+    async fn synchronized_groups(&self, source: &str) -> RepositoryResult<Vec<SynchronizedGroupRow>>;
+
+    async fn create_group(&self, source: &str, entry_id: &str, dn: &str, name: &str) -> RepositoryResult<SynchronizedGroupRow>;
+
+    async fn update_group(&self, id: Id, dn: &str, name: &str) -> RepositoryResult<()>;
+
+    /// Stop synchronizing the group, keeping the local group
+    async fn delete_group(&self, id: Id) -> RepositoryResult<()>;
+
+    async fn member_ids(&self, group_id: Id) -> RepositoryResult<Vec<Id>>;
+
+    /// Ids of the users by lowercase login
+    async fn user_ids_by_login(&self, logins: &[String]) -> RepositoryResult<HashMap<String, Id>>;
+
+    async fn add_members(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<()>;
+
+    async fn remove_members(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<()>;
+}
+
+/// Synchronized groups stored in `ldap_groups_synchronized_groups`
+pub struct PgGroupSyncStore {
+    groups: GroupRepository,
+    users: UserRepository,
+}
+
+impl PgGroupSyncStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            groups: GroupRepository::new(pool.clone()),
+            users: UserRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl GroupSyncStore for PgGroupSyncStore {
+    async fn synchronized_groups(&self, source: &str) -> RepositoryResult<Vec<SynchronizedGroupRow>> {
+        self.groups.synchronized_groups(source).await
+    }
+
+    async fn create_group(&self, source: &str, entry_id: &str, dn: &str, name: &str) -> RepositoryResult<SynchronizedGroupRow> {
+        self.groups.create_synchronized(source, entry_id, dn, name).await
+    }
+
+    async fn update_group(&self, id: Id, dn: &str, name: &str) -> RepositoryResult<()> {
+        self.groups.update_synchronized(id, dn, name).await
+    }
+
+    async fn delete_group(&self, id: Id) -> RepositoryResult<()> {
+        self.groups.delete_synchronized(id).await
+    }
+
+    async fn member_ids(&self, group_id: Id) -> RepositoryResult<Vec<Id>> {
+        self.groups.member_ids(group_id).await
+    }
+
+    async fn user_ids_by_login(&self, logins: &[String]) -> RepositoryResult<HashMap<String, Id>> {
+        Ok(self.users.find_ids_by_logins(logins).await?.into_iter().collect())
+    }
+
+    async fn add_members(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<()> {
+        self.groups.add_users(group_id, user_ids).await.map(|_| ())
+    }
+
+    async fn remove_members(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<()> {
+        self.groups.remove_users(group_id, user_ids).await.map(|_| ())
+    }
+}
+
+/// Arguments of the job
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct SyncArgs {
+    dry_run: bool,
+    source: Option<String>,
+}
+
+/// Synchronizes the groups of the LDAP sources with group synchronization
+/// configured
+pub struct LdapGroupSyncJob<S: GroupSyncStore> {
+    store: Arc<S>,
+    sources: Vec<(LdapConfig, Arc<dyn LdapDirectory>)>,
+}
+
+impl<S: GroupSyncStore> LdapGroupSyncJob<S> {
+    pub fn new(store: Arc<S>) -> Self {
+        Self {
+            store,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Synchronize the groups of the source, read from the directory
+    pub fn with_source(mut self, config: LdapConfig, directory: Arc<dyn LdapDirectory>) -> Self {
+        self.sources.push((config, directory));
+        self
+    }
+
+    /// Synchronize the groups of the source; in a dry run nothing is
+    /// written
+    pub async fn synchronize(
+        &self,
+        config: &LdapConfig,
+        directory: &dyn LdapDirectory,
+        dry_run: bool,
+    ) -> JobResult<GroupSyncReport> {
+        let mut report = GroupSyncReport {
+            source: config.name.clone(),
+            dry_run,
+            ..Default::default()
+        };
+        let Some(sync) = &config.group_sync else {
+            return Ok(report);
+        };
+        let base_dn = if sync.base_dn.is_empty() { &config.base_dn } else { &sync.base_dn };
+
+        let mut synchronized: HashMap<String, SynchronizedGroupRow> = self
+            .store
+            .synchronized_groups(&config.name)
+            .await
+            .map_err(failed)?
+            .into_iter()
+            .map(|group| (group.entry_id.clone(), group))
+            .collect();
+
+        let attributes = [sync.id_attribute.as_str(), sync.name_attribute.as_str()];
+        let mut cookie: Option<String> = None;
+        loop {
+            let page = directory
+                .search(base_dn, &sync.filter, &attributes, sync.page_size, cookie.as_deref())
+                .await
+                .map_err(ldap_failed)?;
+            for entry in &page.entries {
+                let entry_id = entry.first(&sync.id_attribute).unwrap_or(&entry.dn);
+                let existing = synchronized.remove(entry_id);
+                self.synchronize_group(config, sync, directory, entry, entry_id, existing, &mut report)
+                    .await?;
+            }
+            match page.cookie {
+                Some(next) => cookie = Some(next),
+                None => break,
+            }
+        }
+
+        // Whatever was not found anymore was deleted in LDAP
+        for group in synchronized.into_values() {
+            let member_ids = self.store.member_ids(group.group_id).await.map_err(failed)?;
+            report.groups_removed += 1;
+            report.members_removed += member_ids.len();
+            if !dry_run {
+                self.store.remove_members(group.group_id, &member_ids).await.map_err(failed)?;
+                self.store.delete_group(group.id).await.map_err(failed)?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn synchronize_group(
+        &self,
+        config: &LdapConfig,
+        sync: &LdapGroupSyncConfig,
+        directory: &dyn LdapDirectory,
+        entry: &LdapEntry,
+        entry_id: &str,
+        existing: Option<SynchronizedGroupRow>,
+        report: &mut GroupSyncReport,
+    ) -> JobResult<()> {
+        let name = entry
+            .first(&sync.name_attribute)
+            .map(str::to_string)
+            .unwrap_or_else(|| rdn(&entry.dn).map(|(_, value)| value.to_string()).unwrap_or_default());
+
+        let group_id = match existing {
+            Some(group) => {
+                if group.name != name || group.dn != entry.dn {
+                    report.groups_updated += 1;
+                    if !report.dry_run {
+                        self.store.update_group(group.id, &entry.dn, &name).await.map_err(failed)?;
+                    }
+                }
+                Some(group.group_id)
+            }
+            None => {
+                report.groups_created += 1;
+                if report.dry_run {
+                    None
+                } else {
+                    let group = self
+                        .store
+                        .create_group(&config.name, entry_id, &entry.dn, &name)
+                        .await
+                        .map_err(failed)?;
+                    Some(group.group_id)
+                }
+            }
+        };
+
+        let wanted = self.resolve_members(config, sync, directory, entry, &name, report).await?;
+        let current: HashSet<Id> = match group_id {
+            Some(group_id) => self.store.member_ids(group_id).await.map_err(failed)?.into_iter().collect(),
+            None => HashSet::new(),
+        };
+
+        let mut added: Vec<Id> = wanted.difference(&current).copied().collect();
+        let mut removed: Vec<Id> = current.difference(&wanted).copied().collect();
+        added.sort_unstable();
+        removed.sort_unstable();
+        report.members_added += added.len();
+        report.members_removed += removed.len();
+
+        if let (Some(group_id), false) = (group_id, report.dry_run) {
+            self.store.add_members(group_id, &added).await.map_err(failed)?;
+            self.store.remove_members(group_id, &removed).await.map_err(failed)?;
+        }
+        Ok(())
+    }
+
+    /// Ids of the local users the group's membership attribute names,
+    /// reading the values one page at a time
+    async fn resolve_members(
+        &self,
+        config: &LdapConfig,
+        sync: &LdapGroupSyncConfig,
+        directory: &dyn LdapDirectory,
+        entry: &LdapEntry,
+        group_name: &str,
+        report: &mut GroupSyncReport,
+    ) -> JobResult<HashSet<Id>> {
+        let page_size = sync.page_size.max(1) as usize;
+        let unresolvable = |member: &str, report: &mut GroupSyncReport| {
+            report.unresolvable_members.push(UnresolvableMember {
+                group: group_name.to_string(),
+                member: member.to_string(),
+            });
+        };
+
+        let mut wanted = HashSet::new();
+        let mut start = 0;
+        loop {
+            let values = directory
+                .attribute_values(&entry.dn, &sync.membership_attribute, start, page_size)
+                .await
+                .map_err(ldap_failed)?;
+            start += values.len();
+
+            let mut logins = Vec::with_capacity(values.len());
+            for value in &values {
+                match login_of(config, directory, value).await.map_err(ldap_failed)? {
+                    Some(login) => logins.push((value, login.to_lowercase())),
+                    None => unresolvable(value, report),
+                }
+            }
+            let lookup: Vec<String> = logins.iter().map(|(_, login)| login.clone()).collect();
+            let ids = self.store.user_ids_by_login(&lookup).await.map_err(failed)?;
+            for (value, login) in logins {
+                match ids.get(&login) {
+                    Some(id) => {
+                        wanted.insert(*id);
+                    }
+                    None => unresolvable(value, report),
+                }
+            }
+
+            if values.len() < page_size {
+                break;
+            }
+        }
+        Ok(wanted)
+    }
+}
+
+/// Login named by a value of the membership attribute: the login itself,
+/// or the DN of the user. DNs whose first component is the login
+/// attribute are resolved without asking the directory.
+async fn login_of(config: &LdapConfig, directory: &dyn LdapDirectory, value: &str) -> LdapResult<Option<String>> {
+    let login_attribute = &config.attribute_mapping.login;
+    let Some((attribute, rdn_value)) = rdn(value) else {
+        return Ok(Some(value.to_string()));
+    };
+    if attribute.eq_ignore_ascii_case(login_attribute) && !rdn_value.contains('\\') {
+        return Ok(Some(rdn_value.to_string()));
+    }
+    let entry = directory.entry(value, &[login_attribute]).await?;
+    Ok(entry.and_then(|entry| entry.first(login_attribute).map(str::to_string)))
+}
+
+/// Attribute and value of the first component of a DN, `None` for values
+/// that are no DN
+fn rdn(dn: &str) -> Option<(&str, &str)> {
+    let first = dn.split(',').next()?;
+    let (attribute, value) = first.split_once('=')?;
+    Some((attribute.trim(), value.trim()))
+}
+
+fn failed(e: RepositoryError) -> JobError {
+    JobError::Failed(e.to_string())
+}
+
+fn ldap_failed(e: LdapError) -> JobError {
+    JobError::Failed(e.to_string())
+}
+
+#[async_trait]
+impl<S: GroupSyncStore + 'static> JobHandler for LdapGroupSyncJob<S> {
+    async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+        let args: SyncArgs = serde_json::from_value(args).unwrap_or_default();
+
+        let mut failures = 0;
+        for (config, directory) in &self.sources {
+            if config.group_sync.is_none() || args.source.as_ref().is_some_and(|source| *source != config.name) {
+                continue;
+            }
+            match self.synchronize(config, directory.as_ref(), args.dry_run).await {
+                Ok(report) => info!(
+                    source = %report.source,
+                    dry_run = report.dry_run,
+                    groups_created = report.groups_created,
+                    groups_updated = report.groups_updated,
+                    groups_removed = report.groups_removed,
+                    members_added = report.members_added,
+                    members_removed = report.members_removed,
+                    unresolvable_members = report.unresolvable_members.len(),
+                    "Synchronized LDAP groups"
+                ),
+                Err(e) => {
+                    warn!(source = %config.name, error = %e, "LDAP groups will be synchronized again");
+                    failures += 1;
+                }
+            }
+        }
+
+        if failures > 0 {
+            return Err(JobError::Failed(format!("{} LDAP sources could not be synchronized", failures)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use op_core::config::LdapAttributeMapping;
+    use std::collections::BTreeSet;
+    use std::sync::Mutex;
+
+    const GROUPS: &str = "ou=groups,dc=example,dc=com";
+
+    fn config(page_size: u32) -> LdapConfig {
+        LdapConfig {
+            name: "corp".into(),
+            host: "ldap.example.com".into(),
+            port: 389,
+            base_dn: "dc=example,dc=com".into(),
+            bind_dn: None,
+            bind_password: None,
+            filter: None,
+            attribute_mapping: LdapAttributeMapping {
+                login: "uid".into(),
+                firstname: "givenName".into(),
+                lastname: "sn".into(),
+                mail: "mail".into(),
+                admin: None,
+            },
+            group_sync: Some(LdapGroupSyncConfig {
+                base_dn: GROUPS.into(),
+                page_size,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn group(uuid: &str, name: &str, members: &[&str]) -> LdapEntry {
+        LdapEntry::new(format!("cn={},{}", name, GROUPS))
+            .with("objectClass", &["groupOfNames"])
+            .with("entryUUID", &[uuid])
+            .with("cn", &[name])
+            .with("member", members)
+    }
+
+    fn user_dn(login: &str) -> String {
+        format!("uid={},ou=people,dc=example,dc=com", login)
+    }
+
+    #[derive(Default)]
+    struct MemoryStore {
+        groups: Mutex<Vec<SynchronizedGroupRow>>,
+        members: Mutex<HashMap<Id, BTreeSet<Id>>>,
+        users: HashMap<String, Id>,
+    }
+
+    impl MemoryStore {
+        fn with_users(logins: &[(&str, Id)]) -> Self {
+            Self {
+                users: logins.iter().map(|(login, id)| (login.to_string(), *id)).collect(),
+                ..Default::default()
+            }
+        }
+
+        fn group_named(&self, name: &str) -> Option<SynchronizedGroupRow> {
+            self.groups.lock().unwrap().iter().find(|group| group.name == name).cloned()
+        }
+
+        fn members_of(&self, group_id: Id) -> Vec<Id> {
+            self.members.lock().unwrap().get(&group_id).map(|m| m.iter().copied().collect()).unwrap_or_default()
+        }
+    }
+
+    #[async_trait]
+    impl GroupSyncStore for MemoryStore {
+        async fn synchronized_groups(&self, source: &str) -> RepositoryResult<Vec<SynchronizedGroupRow>> {
+            Ok(self.groups.lock().unwrap().iter().filter(|g| g.ldap_source == source).cloned().collect())
+        }
+
+        async fn create_group(&self, source: &str, entry_id: &str, dn: &str, name: &str) -> RepositoryResult<SynchronizedGroupRow> {
+            let mut groups = self.groups.lock().unwrap();
+            let id = groups.len() as Id + 1;
+            let group = SynchronizedGroupRow {
+                id,
+                group_id: 100 + id,
+                ldap_source: source.into(),
+                entry_id: entry_id.into(),
+                dn: dn.into(),
+                name: name.into(),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            };
+            groups.push(group.clone());
+            Ok(group)
+        }
+
+        async fn update_group(&self, id: Id, dn: &str, name: &str) -> RepositoryResult<()> {
+            let mut groups = self.groups.lock().unwrap();
+            let group = groups.iter_mut().find(|g| g.id == id).unwrap();
+            group.dn = dn.into();
+            group.name = name.into();
+            Ok(())
+        }
+
+        async fn delete_group(&self, id: Id) -> RepositoryResult<()> {
+            self.groups.lock().unwrap().retain(|g| g.id != id);
+            Ok(())
+        }
+
+        async fn member_ids(&self, group_id: Id) -> RepositoryResult<Vec<Id>> {
+            Ok(self.members_of(group_id))
+        }
+
+        async fn user_ids_by_login(&self, logins: &[String]) -> RepositoryResult<HashMap<String, Id>> {
+            Ok(logins
+                .iter()
+                .filter_map(|login| self.users.get(login).map(|id| (login.clone(), *id)))
+                .collect())
+        }
+
+        async fn add_members(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<()> {
+            self.members.lock().unwrap().entry(group_id).or_default().extend(user_ids);
+            Ok(())
+        }
+
+        async fn remove_members(&self, group_id: Id, user_ids: &[Id]) -> RepositoryResult<()> {
+            if let Some(members) = self.members.lock().unwrap().get_mut(&group_id) {
+                members.retain(|id| !user_ids.contains(id));
+            }
+            Ok(())
+        }
+    }
+
+    fn setup(page_size: u32) -> (Arc<MemoryStore>, Arc<MemoryDirectory>, LdapGroupSyncJob<MemoryStore>) {
+        let store = Arc::new(MemoryStore::with_users(&[("alice", 1), ("bob", 2), ("carol", 3)]));
+        let directory = Arc::new(MemoryDirectory::new());
+        // Carol's DN is not named by her login, so it is looked up
+        directory.insert(LdapEntry::new("cn=Carol Smith,ou=people,dc=example,dc=com").with("uid", &["carol"]));
+        let job = LdapGroupSyncJob::new(store.clone()).with_source(config(page_size), directory.clone());
+        (store, directory, job)
+    }
+
+    #[tokio::test]
+    async fn test_creates_groups_and_adds_members() {
+        let (store, directory, job) = setup(2);
+        directory.insert(group(
+            "1",
+            "developers",
+            &[&user_dn("alice"), &user_dn("bob"), "cn=Carol Smith,ou=people,dc=example,dc=com", &user_dn("mallory")],
+        ));
+        directory.insert(group("2", "designers", &[&user_dn("bob")]));
+        directory.insert(group("3", "admins", &[&user_dn("alice")]));
+        // Not a group of names, and outside of the group base
+        directory.insert(LdapEntry::new(format!("cn=printers,{}", GROUPS)).with("cn", &["printers"]));
+        directory.insert(
+            LdapEntry::new("cn=staff,ou=other,dc=example,dc=com").with("objectClass", &["groupOfNames"]),
+        );
+
+        job.handle(serde_json::json!({})).await.unwrap();
+
+        let developers = store.group_named("developers").unwrap();
+        assert_eq!(developers.entry_id, "1");
+        assert_eq!(store.members_of(developers.group_id), vec![1, 2, 3]);
+        assert_eq!(store.members_of(store.group_named("designers").unwrap().group_id), vec![2]);
+        assert_eq!(store.groups.lock().unwrap().len(), 3);
+
+        let report = job.synchronize(&config(2), directory.as_ref(), false).await.unwrap();
+        assert_eq!((report.groups_created, report.groups_updated, report.members_added), (0, 0, 0));
+        assert_eq!(
+            report.unresolvable_members,
+            vec![UnresolvableMember { group: "developers".into(), member: user_dn("mallory") }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_removes_members_and_deleted_groups() {
+        let (store, directory, job) = setup(500);
+        directory.insert(group("1", "developers", &[&user_dn("alice"), &user_dn("bob")]));
+        directory.insert(group("2", "designers", &[&user_dn("bob")]));
+        job.handle(serde_json::json!({})).await.unwrap();
+        let designers = store.group_named("designers").unwrap();
+
+        directory.insert(group("1", "developers", &[&user_dn("alice")]));
+        directory.remove(&designers.dn);
+        let report = job.synchronize(&config(500), directory.as_ref(), false).await.unwrap();
+
+        assert_eq!((report.groups_removed, report.members_removed), (1, 2));
+        assert_eq!(store.members_of(store.group_named("developers").unwrap().group_id), vec![1]);
+        assert!(store.members_of(designers.group_id).is_empty());
+        assert!(store.group_named("designers").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_renamed_groups_keep_their_local_group() {
+        let (store, directory, job) = setup(500);
+        directory.insert(group("1", "devs", &[&user_dn("alice")]));
+        job.handle(serde_json::json!({})).await.unwrap();
+        let devs = store.group_named("devs").unwrap();
+
+        directory.remove(&devs.dn);
+        directory.insert(group("1", "developers", &[&user_dn("alice")]));
+        let report = job.synchronize(&config(500), directory.as_ref(), false).await.unwrap();
+
+        assert_eq!((report.groups_created, report.groups_updated, report.groups_removed), (0, 1, 0));
+        let developers = store.group_named("developers").unwrap();
+        assert_eq!(developers.group_id, devs.group_id);
+        assert_eq!(developers.dn, format!("cn=developers,{}", GROUPS));
+        assert_eq!(store.members_of(developers.group_id), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reports_without_writing() {
+        let (store, directory, job) = setup(500);
+        directory.insert(group("1", "developers", &[&user_dn("alice"), "bob", "nobody"]));
+
+        job.handle(serde_json::json!({ "dry_run": true })).await.unwrap();
+        assert!(store.groups.lock().unwrap().is_empty());
+
+        let report = job.synchronize(&config(500), directory.as_ref(), true).await.unwrap();
+        assert_eq!((report.groups_created, report.members_added), (1, 2));
+        assert_eq!(report.unresolvable_members.len(), 1);
+        assert!(store.groups.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_directory_filters_and_pages() {
+        let directory = MemoryDirectory::new();
+        for name in ["a", "b", "c"] {
+            directory.insert(group(name, name, &[]));
+        }
+        directory.insert(LdapEntry::new(format!("cn=d,{}", GROUPS)).with("objectClass", &["posixGroup"]));
+
+        let first = directory.search(GROUPS, "(objectClass=groupOfNames)", &["cn"], 2, None).await.unwrap();
+        assert_eq!(first.entries.len(), 2);
+        let second = directory
+            .search(GROUPS, "(objectClass=groupOfNames)", &["cn"], 2, first.cookie.as_deref())
+            .await
+            .unwrap();
+        assert_eq!(second.entries.len(), 1);
+        assert_eq!(second.cookie, None);
+        assert!(second.entries[0].first("entryUUID").is_none());
+
+        let filter = "(&(|(objectClass=groupOfNames)(objectClass=posixGroup))(!(cn=a)))";
+        let page = directory.search(GROUPS, filter, &[], 10, None).await.unwrap();
+        assert_eq!(page.entries.len(), 3);
+    }
+}
@@ -11,6 +11,19 @@
 //! - `result` - ServiceResult type for operation outcomes
 //! - `base` - Base service traits (Callable, WriteService, etc.)
 //! - `work_packages` - Work package CRUD services
+//! - `permissions` - Permission resolution from memberships and shares
+//! - `shares` - Sharing work packages with users outside the project
+//! - `revoked_access` - Removing watchers and notifications users can no longer see
+//! - `query_subscriptions` - Emailing subscribers when saved query results change
+//! - `scheduled_jobs` - Schedules and handlers of the built-in recurring jobs
+//! - `ldap_groups` - Mirroring the groups of LDAP sources as local groups
+//! - `costs` - Labor and material costs of work packages, priced with rates
+//! - `storages` - Providers of the external file stores files are linked from
+//! - `forums` - Posting, editing and deleting forum topics and replies
+//! - `documents` - Project documents, their files and notifications about new ones
+//! - `custom_actions` - Buttons applying predefined changes to the work packages they match
+//! - `reporting` - Status counts of work packages over time for cumulative flow charts
+//! - `seeds` - Basic data and reproducible demo projects for new instances
 //!
 //! ## Example
 //!
@@ -35,6 +48,19 @@ pub mod base;
 pub mod work_packages;
 pub mod projects;
 pub mod users;
+pub mod permissions;
+pub mod shares;
+pub mod revoked_access;
+pub mod query_subscriptions;
+pub mod scheduled_jobs;
+pub mod ldap_groups;
+pub mod costs;
+pub mod storages;
+pub mod forums;
+pub mod documents;
+pub mod custom_actions;
+pub mod reporting;
+pub mod seeds;
 
 // Re-exports
 pub use result::ServiceResult;
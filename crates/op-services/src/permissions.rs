@@ -0,0 +1,233 @@
+//! Permission resolution
+//!
+//! Mirrors: app/services/authorization/user_permissible_service.rb
+//!
+//! Resolves permissions from memberships on every check, so membership and
+//! share changes apply to the next request. Work package permissions come
+//! from project memberships and from shares, entity-scoped memberships of
+//! the work package itself. Anonymous users hold the permissions of the
+//! built-in Anonymous role in public projects.
+
+use async_trait::async_trait;
+use op_contracts::base::UserContext;
+use op_contracts::work_packages::permissions::VIEW_WORK_PACKAGES;
+use op_core::traits::Id;
+use op_db::{member_entity_type, MemberRepository, RepositoryResult};
+
+/// Source of the permissions granted through memberships
+#[async_trait]
+pub trait PermissionSource: Send + Sync {
+    /// Permissions granted in the project by project memberships
+    async fn project_permissions(&self, user_id: Id, project_id: Id) -> RepositoryResult<Vec<String>>;
+
+    /// Permissions granted on the work package by shares
+    async fn work_package_permissions(&self, user_id: Id, work_package_id: Id) -> RepositoryResult<Vec<String>>;
+
+    /// Permissions of anonymous users in the project, those of the
+    /// Anonymous role if the project is public; none by default
+    async fn anonymous_permissions(&self, _project_id: Id) -> RepositoryResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+#[async_trait]
+impl PermissionSource for MemberRepository {
+    async fn project_permissions(&self, user_id: Id, project_id: Id) -> RepositoryResult<Vec<String>> {
+        self.permissions_in_project(user_id, project_id).await
+    }
+
+    async fn work_package_permissions(&self, user_id: Id, work_package_id: Id) -> RepositoryResult<Vec<String>> {
+        self.permissions_on_entity(user_id, member_entity_type::WORK_PACKAGE, work_package_id)
+            .await
+    }
+
+    async fn anonymous_permissions(&self, project_id: Id) -> RepositoryResult<Vec<String>> {
+        self.anonymous_permissions_in_project(project_id).await
+    }
+}
+
+/// Service answering whether a user holds a permission
+///
+/// Administrators and permissions already held by the user context are
+/// allowed without a lookup; anonymous users have no memberships, only the
+/// Anonymous role in public projects.
+pub struct PermissionService<P: PermissionSource> {
+    source: P,
+}
+
+impl<P: PermissionSource> PermissionService<P> {
+    pub fn new(source: P) -> Self {
+        Self { source }
+    }
+
+    /// Whether the user holds the permission in the project
+    pub async fn allowed_in_project<U: UserContext>(
+        &self,
+        user: &U,
+        permission: &str,
+        project_id: Id,
+    ) -> RepositoryResult<bool> {
+        if user.is_admin() || user.allowed_in_project(permission, project_id) {
+            return Ok(true);
+        }
+        let granted = if user.is_anonymous() {
+            self.source.anonymous_permissions(project_id).await?
+        } else {
+            self.source.project_permissions(user.id(), project_id).await?
+        };
+        Ok(granted.iter().any(|p| p == permission))
+    }
+
+    /// Whether the user holds the permission on the work package, through
+    /// its project or a share of the work package
+    pub async fn allowed_on_work_package<U: UserContext>(
+        &self,
+        user: &U,
+        permission: &str,
+        work_package_id: Id,
+        project_id: Id,
+    ) -> RepositoryResult<bool> {
+        if user.allowed_for_work_package(permission, work_package_id)
+            || self.allowed_in_project(user, permission, project_id).await?
+        {
+            return Ok(true);
+        }
+        if user.is_anonymous() {
+            return Ok(false);
+        }
+
+        let granted = self.source.work_package_permissions(user.id(), work_package_id).await?;
+        Ok(granted.iter().any(|p| p == permission))
+    }
+
+    /// Whether the user may view the work package
+    pub async fn work_package_visible<U: UserContext>(
+        &self,
+        user: &U,
+        work_package_id: Id,
+        project_id: Id,
+    ) -> RepositoryResult<bool> {
+        self.allowed_on_work_package(user, VIEW_WORK_PACKAGES, work_package_id, project_id)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Memberships keyed by (user, project) and shares by (user, work package)
+    #[derive(Default)]
+    struct MemoryPermissions {
+        projects: Mutex<HashMap<(Id, Id), Vec<String>>>,
+        work_packages: Mutex<HashMap<(Id, Id), Vec<String>>>,
+        /// Permissions of the Anonymous role in public projects
+        public_projects: Mutex<HashMap<Id, Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl PermissionSource for MemoryPermissions {
+        async fn project_permissions(&self, user_id: Id, project_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(self.projects.lock().unwrap().get(&(user_id, project_id)).cloned().unwrap_or_default())
+        }
+
+        async fn work_package_permissions(&self, user_id: Id, work_package_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(self
+                .work_packages
+                .lock()
+                .unwrap()
+                .get(&(user_id, work_package_id))
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        async fn anonymous_permissions(&self, project_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(self.public_projects.lock().unwrap().get(&project_id).cloned().unwrap_or_default())
+        }
+    }
+
+    struct TestUser {
+        id: Id,
+        admin: bool,
+    }
+
+    impl UserContext for TestUser {
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn is_admin(&self) -> bool {
+            self.admin
+        }
+
+        fn is_anonymous(&self) -> bool {
+            self.id == 0
+        }
+
+        fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+            false
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            false
+        }
+    }
+
+    fn user(id: Id) -> TestUser {
+        TestUser { id, admin: false }
+    }
+
+    #[tokio::test]
+    async fn test_project_membership_grants_work_packages_in_project() {
+        let source = MemoryPermissions::default();
+        source.projects.lock().unwrap().insert((5, 1), vec![VIEW_WORK_PACKAGES.to_string()]);
+        let service = PermissionService::new(source);
+
+        assert!(service.work_package_visible(&user(5), 10, 1).await.unwrap());
+        assert!(!service.work_package_visible(&user(5), 20, 2).await.unwrap());
+        assert!(!service.allowed_in_project(&user(5), "edit_work_packages", 1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_share_grants_only_the_shared_work_package() {
+        let source = MemoryPermissions::default();
+        source.work_packages.lock().unwrap().insert((5, 10), vec![VIEW_WORK_PACKAGES.to_string()]);
+        let service = PermissionService::new(source);
+
+        assert!(service.work_package_visible(&user(5), 10, 1).await.unwrap());
+        assert!(!service.work_package_visible(&user(5), 11, 1).await.unwrap());
+        assert!(!service.allowed_in_project(&user(5), VIEW_WORK_PACKAGES, 1).await.unwrap());
+        assert!(!service.work_package_visible(&user(6), 10, 1).await.unwrap());
+
+        // Revoking the share takes effect on the next check
+        service.source.work_packages.lock().unwrap().clear();
+        assert!(!service.work_package_visible(&user(5), 10, 1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_admin_and_anonymous() {
+        let source = MemoryPermissions::default();
+        source.work_packages.lock().unwrap().insert((0, 10), vec![VIEW_WORK_PACKAGES.to_string()]);
+        let service = PermissionService::new(source);
+
+        let admin = TestUser { id: 1, admin: true };
+        assert!(service.work_package_visible(&admin, 10, 1).await.unwrap());
+        assert!(!service.work_package_visible(&user(0), 10, 1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_role_applies_in_public_projects() {
+        let source = MemoryPermissions::default();
+        source.public_projects.lock().unwrap().insert(1, vec![VIEW_WORK_PACKAGES.to_string()]);
+        // Memberships of a user with the anonymous id grant nothing
+        source.projects.lock().unwrap().insert((0, 2), vec![VIEW_WORK_PACKAGES.to_string()]);
+        let service = PermissionService::new(source);
+
+        assert!(service.work_package_visible(&user(0), 10, 1).await.unwrap());
+        assert!(!service.allowed_in_project(&user(0), "edit_work_packages", 1).await.unwrap());
+        assert!(!service.work_package_visible(&user(0), 20, 2).await.unwrap());
+        assert!(!service.work_package_visible(&user(5), 10, 1).await.unwrap());
+    }
+}
@@ -0,0 +1,254 @@
+//! Copy Service for Projects
+//!
+//! Mirrors: app/services/projects/copy_service.rb
+
+use op_contracts::base::UserContext;
+use op_contracts::projects::permissions;
+use op_contracts::projects::ProjectBaseContract;
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+use op_db::{CopyDependency, CreateProjectDto, ProjectRepository, ProjectRow, Repository};
+use serde::{Deserialize, Serialize};
+
+use crate::base::ServiceContext;
+use crate::result::ServiceResult;
+
+/// Attributes of the new project and the data to copy into it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopyProjectParams {
+    pub name: String,
+    pub identifier: String,
+    pub description: Option<String>,
+    pub public: Option<bool>,
+    pub parent_id: Option<Id>,
+    pub dependencies: Vec<CopyDependency>,
+}
+
+impl CopyProjectParams {
+    pub fn new(name: impl Into<String>, identifier: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            identifier: identifier.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_public(mut self, public: bool) -> Self {
+        self.public = Some(public);
+        self
+    }
+
+    pub fn with_parent_id(mut self, parent_id: Id) -> Self {
+        self.parent_id = Some(parent_id);
+        self
+    }
+
+    pub fn with_dependencies(mut self, dependencies: Vec<CopyDependency>) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+}
+
+/// Service for copying a project along with selected dependencies
+///
+/// # Example
+/// ```ignore
+/// let service = CopyProjectService::new(&user, &repository);
+/// let params = CopyProjectParams::new("Copy", "copy")
+///     .with_dependencies(CopyDependency::ALL.to_vec());
+/// let result = service.call(source_id, params).await;
+/// ```
+pub struct CopyProjectService<'a, U: UserContext> {
+    user: &'a U,
+    repository: &'a ProjectRepository,
+}
+
+impl<'a, U: UserContext> CopyProjectService<'a, U> {
+    pub fn new(user: &'a U, repository: &'a ProjectRepository) -> Self {
+        Self { user, repository }
+    }
+
+    /// Context the copy runs in; what is copied along with the project is
+    /// summarized rather than notified item by item
+    pub fn context(&self) -> ServiceContext<'a, U> {
+        ServiceContext::bulk(self.user)
+    }
+
+    /// Execute the copy operation
+    pub async fn call(self, source_id: Id, params: CopyProjectParams) -> ServiceResult<ProjectRow> {
+        let source = match self.repository.find_by_id(source_id).await {
+            Ok(Some(source)) => source,
+            Ok(None) => return ServiceResult::failure_with_base_error("Project not found"),
+            Err(e) => return ServiceResult::failure_with_base_error(e.to_string()),
+        };
+
+        if let Err(errors) = self.validate(&source, &params) {
+            return ServiceResult::failure(errors);
+        }
+
+        self.copy(&source, params).await
+    }
+
+    /// Validate the user may copy `source` with the given attributes
+    pub fn validate(&self, source: &ProjectRow, params: &CopyProjectParams) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        if !self.user.is_admin() && !self.user.allowed_in_project(permissions::COPY_PROJECTS, source.id) {
+            errors.add_base("You are not authorized to copy this project");
+        }
+
+        validate_attributes(self.user, params, &mut errors);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Persist the copy; callers have validated permissions and attributes
+    pub(crate) async fn copy(&self, source: &ProjectRow, params: CopyProjectParams) -> ServiceResult<ProjectRow> {
+        match self.repository.is_identifier_unique(&params.identifier, None).await {
+            Ok(true) => {}
+            Ok(false) => return ServiceResult::failure_with_error("identifier", "has already been taken"),
+            Err(e) => return ServiceResult::failure_with_base_error(e.to_string()),
+        }
+
+        let dto = CreateProjectDto {
+            name: params.name,
+            description: params.description.or_else(|| source.description.clone()),
+            identifier: params.identifier,
+            public: params.public.unwrap_or(source.public),
+            parent_id: params.parent_id,
+            active: true,
+            templated: false,
+        };
+
+        match self.repository.copy(source.id, dto, &params.dependencies).await {
+            Ok(project) => ServiceResult::success(project),
+            Err(e) => ServiceResult::failure_with_base_error(e.to_string()),
+        }
+    }
+}
+
+/// Validate the attributes of the project to be created
+pub(crate) fn validate_attributes<U: UserContext>(
+    user: &U,
+    params: &CopyProjectParams,
+    errors: &mut ValidationErrors,
+) {
+    let contract = ProjectBaseContract::new(user);
+    contract.validate_name(&params.name, errors);
+    contract.validate_identifier(&params.identifier, errors);
+    contract.validate_parent(params.parent_id, errors);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashSet;
+
+    pub(crate) struct MockUser {
+        pub admin: bool,
+        pub project_permissions: HashSet<String>,
+        pub global_permissions: HashSet<String>,
+    }
+
+    impl UserContext for MockUser {
+        fn id(&self) -> Id {
+            2
+        }
+
+        fn is_admin(&self) -> bool {
+            self.admin
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, permission: &str, _project_id: Id) -> bool {
+            self.project_permissions.contains(permission)
+        }
+
+        fn allowed_globally(&self, permission: &str) -> bool {
+            self.global_permissions.contains(permission)
+        }
+    }
+
+    pub(crate) fn user(project_permissions: &[&str], global_permissions: &[&str]) -> MockUser {
+        MockUser {
+            admin: false,
+            project_permissions: project_permissions.iter().map(|p| p.to_string()).collect(),
+            global_permissions: global_permissions.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    pub(crate) fn project(templated: bool) -> ProjectRow {
+        ProjectRow {
+            id: 7,
+            name: "Source".into(),
+            description: None,
+            identifier: "source".into(),
+            public: false,
+            parent_id: None,
+            lft: 1,
+            rgt: 2,
+            active: true,
+            templated,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub(crate) fn repository() -> ProjectRepository {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/openproject_test")
+            .unwrap();
+        ProjectRepository::new(pool)
+    }
+
+    #[tokio::test]
+    async fn test_copy_requires_permission() {
+        let repository = repository();
+        let params = CopyProjectParams::new("Copy", "copy");
+
+        let allowed = user(&[permissions::COPY_PROJECTS], &[]);
+        let service = CopyProjectService::new(&allowed, &repository);
+        assert!(service.validate(&project(false), &params).is_ok());
+
+        let denied = user(&[], &[]);
+        let service = CopyProjectService::new(&denied, &repository);
+        let errors = service.validate(&project(false), &params).unwrap_err();
+        assert!(!errors.base_errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_copy_validates_attributes() {
+        let repository = repository();
+        let allowed = user(&[permissions::COPY_PROJECTS], &[]);
+        let service = CopyProjectService::new(&allowed, &repository);
+
+        let errors = service
+            .validate(&project(false), &CopyProjectParams::new("", "Not Valid"))
+            .unwrap_err();
+        assert!(errors.has_error("name"));
+        assert!(errors.has_error("identifier"));
+    }
+
+    #[tokio::test]
+    async fn test_copy_suppresses_notifications() {
+        let repository = repository();
+        let allowed = user(&[permissions::COPY_PROJECTS], &[]);
+        let context = CopyProjectService::new(&allowed, &repository).context();
+        assert!(context.suppress_notifications);
+        assert!(!context.notifies_individually());
+        assert!(ServiceContext::new(&allowed).notifies_individually());
+    }
+}
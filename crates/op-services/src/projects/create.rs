@@ -22,6 +22,7 @@ use super::ProjectParams;
 pub struct CreateProjectService<'a, U: UserContext> {
     user: &'a U,
     send_notifications: bool,
+    dry_run: bool,
 }
 
 impl<'a, U: UserContext> CreateProjectService<'a, U> {
@@ -29,6 +30,7 @@ impl<'a, U: UserContext> CreateProjectService<'a, U> {
         Self {
             user,
             send_notifications: true,
+            dry_run: false,
         }
     }
 
@@ -36,9 +38,17 @@ impl<'a, U: UserContext> CreateProjectService<'a, U> {
         Self {
             user,
             send_notifications: false,
+            dry_run: false,
         }
     }
 
+    /// Only validate; the result holds the project that would be created,
+    /// which has no id as nothing is saved or announced
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
     /// Execute the create operation
     pub fn call(self, params: ProjectParams) -> ServiceResult<ProjectEntity> {
         // Create new project with defaults
@@ -48,7 +58,7 @@ impl<'a, U: UserContext> CreateProjectService<'a, U> {
         let set_attrs_service = SetAttributesService::new(self.user, project);
         let result = set_attrs_service.call(&params);
 
-        if result.is_failure() {
+        if result.is_failure() || self.dry_run {
             return result;
         }
 
@@ -232,4 +242,19 @@ mod tests {
         let result = service.call(params);
         assert!(result.is_success());
     }
+
+    #[test]
+    fn test_dry_run_assigns_no_id() {
+        let user = create_admin_user();
+
+        let params = ProjectParams::new()
+            .with_name("Draft")
+            .with_identifier("draft");
+        let result = CreateProjectService::new(&user).dry_run().call(params);
+        assert_eq!(result.result().unwrap().identifier, "draft");
+        assert_eq!(result.result().unwrap().id, None);
+
+        let invalid = CreateProjectService::new(&user).dry_run().call(ProjectParams::new().with_identifier("draft"));
+        assert!(invalid.is_failure());
+    }
 }
@@ -0,0 +1,44 @@
+//! Project identifiers
+//!
+//! Generating free identifiers and checking requested ones against the
+//! stored projects.
+
+use std::collections::HashSet;
+
+use op_contracts::base::UserContext;
+use op_contracts::projects::{identifier_from_name, unique_identifier, ProjectBaseContract};
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+use op_db::{ProjectStore, RepositoryResult};
+
+/// Free identifier for a new project named `name`
+///
+/// Derived from the name and suffixed with `-2`, `-3`, ... while another
+/// project uses it.
+pub async fn generate_identifier(repository: &dyn ProjectStore, name: &str) -> RepositoryResult<String> {
+    let base = identifier_from_name(name);
+    let taken: HashSet<String> = repository.identifiers_with_prefix(&base).await?.into_iter().collect();
+
+    Ok(unique_identifier(&base, |candidate| taken.contains(candidate)))
+}
+
+/// Errors of `identifier` for the project `project_id`, `None` for a new one
+///
+/// Uniqueness is only looked up for well-formed identifiers.
+pub async fn identifier_errors<U: UserContext>(
+    user: &U,
+    repository: &dyn ProjectStore,
+    identifier: &str,
+    project_id: Option<Id>,
+) -> RepositoryResult<ValidationErrors> {
+    let contract = ProjectBaseContract::new(user);
+    let mut errors = ValidationErrors::new();
+
+    contract.validate_identifier(identifier, &mut errors);
+    if errors.is_empty() {
+        let taken = !repository.is_identifier_unique(identifier, project_id).await?;
+        contract.validate_identifier_available(taken, &mut errors);
+    }
+
+    Ok(errors)
+}
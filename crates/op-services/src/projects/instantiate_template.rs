@@ -0,0 +1,203 @@
+//! Instantiate Template Service for Projects
+//!
+//! Mirrors: app/services/projects/instantiate_template/instantiate_template_service.rb
+//! and app/workers/projects/copy_job.rb
+//!
+//! Creating a project from a template copies the template's dependencies
+//! and runs in the background; the API enqueues an
+//! [`INSTANTIATE_TEMPLATE_JOB`] and clients poll the job status.
+
+use async_trait::async_trait;
+use op_contracts::base::UserContext;
+use op_contracts::projects::permissions;
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+use op_db::{ProjectRepository, ProjectRow, Repository};
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use op_notifications::Job;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::result::ServiceResult;
+use super::copy::{validate_attributes, CopyProjectParams, CopyProjectService};
+
+/// Job type of template instantiation jobs
+pub const INSTANTIATE_TEMPLATE_JOB: &str = "Projects::InstantiateTemplateJob";
+
+/// Job metadata key holding the ID of the user who enqueued the job
+pub const JOB_USER_METADATA_KEY: &str = "user_id";
+
+/// Service for creating a project from a template
+pub struct InstantiateTemplateService<'a, U: UserContext> {
+    user: &'a U,
+    repository: &'a ProjectRepository,
+}
+
+impl<'a, U: UserContext> InstantiateTemplateService<'a, U> {
+    pub fn new(user: &'a U, repository: &'a ProjectRepository) -> Self {
+        Self { user, repository }
+    }
+
+    /// Execute the instantiation
+    pub async fn call(self, template_id: Id, params: CopyProjectParams) -> ServiceResult<ProjectRow> {
+        let template = match self.repository.find_by_id(template_id).await {
+            Ok(Some(template)) => template,
+            Ok(None) => return ServiceResult::failure_with_base_error("Template not found"),
+            Err(e) => return ServiceResult::failure_with_base_error(e.to_string()),
+        };
+
+        if let Err(errors) = self.validate(&template, &params) {
+            return ServiceResult::failure(errors);
+        }
+
+        CopyProjectService::new(self.user, self.repository)
+            .copy(&template, params)
+            .await
+    }
+
+    /// Validate the user may create a project from `template`
+    pub fn validate(&self, template: &ProjectRow, params: &CopyProjectParams) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        if !template.templated {
+            errors.add("template", "is not a project template");
+        }
+
+        if !self.user.is_admin() && !self.user.allowed_globally(permissions::ADD_PROJECT) {
+            errors.add_base("You are not authorized to create projects");
+        }
+
+        validate_attributes(self.user, params, &mut errors);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Arguments of an [`INSTANTIATE_TEMPLATE_JOB`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstantiateTemplateArgs {
+    pub template_id: Id,
+    pub params: CopyProjectParams,
+}
+
+impl InstantiateTemplateArgs {
+    /// Build the job, recording the enqueuing user. Jobs are not retried:
+    /// a failed copy is rolled back and reported to the polling client.
+    pub fn into_job(self, user_id: Id) -> Job {
+        Job::new(INSTANTIATE_TEMPLATE_JOB, serde_json::json!(self))
+            .with_metadata(JOB_USER_METADATA_KEY, user_id.to_string())
+            .max_retries(0)
+    }
+}
+
+/// Job handler instantiating templates in the background
+pub struct InstantiateTemplateJob {
+    pool: PgPool,
+}
+
+impl InstantiateTemplateJob {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl JobHandler for InstantiateTemplateJob {
+    async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+        let args: InstantiateTemplateArgs = serde_json::from_value(args)
+            .map_err(|e| JobError::SerializationError(e.to_string()))?;
+
+        let repository = ProjectRepository::new(self.pool.clone());
+        let result = InstantiateTemplateService::new(&JobContext, &repository)
+            .call(args.template_id, args.params)
+            .await;
+
+        if result.is_failure() {
+            return Err(JobError::Failed(result.full_messages().join(", ")));
+        }
+
+        Ok(())
+    }
+}
+
+/// Context jobs run in. Permissions are checked by the API before the job
+/// is enqueued.
+struct JobContext;
+
+impl UserContext for JobContext {
+    fn id(&self) -> Id {
+        0
+    }
+
+    fn is_admin(&self) -> bool {
+        true
+    }
+
+    fn is_anonymous(&self) -> bool {
+        false
+    }
+
+    fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+        true
+    }
+
+    fn allowed_globally(&self, _permission: &str) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::copy::tests::{project, repository, user};
+    use op_db::CopyDependency;
+
+    #[tokio::test]
+    async fn test_instantiate_requires_template() {
+        let repository = repository();
+        let allowed = user(&[], &[permissions::ADD_PROJECT]);
+        let service = InstantiateTemplateService::new(&allowed, &repository);
+        let params = CopyProjectParams::new("From template", "from-template");
+
+        assert!(service.validate(&project(true), &params).is_ok());
+
+        let errors = service.validate(&project(false), &params).unwrap_err();
+        assert!(errors.has_error("template"));
+    }
+
+    #[tokio::test]
+    async fn test_instantiate_requires_add_project() {
+        let repository = repository();
+        // Copying permission on the template is not enough
+        let denied = user(&[permissions::COPY_PROJECTS], &[]);
+        let service = InstantiateTemplateService::new(&denied, &repository);
+
+        let errors = service
+            .validate(&project(true), &CopyProjectParams::new("From template", "from-template"))
+            .unwrap_err();
+        assert!(!errors.base_errors.is_empty());
+    }
+
+    #[test]
+    fn test_job_args_round_trip() {
+        let args = InstantiateTemplateArgs {
+            template_id: 7,
+            params: CopyProjectParams::new("From template", "from-template")
+                .with_dependencies(vec![CopyDependency::Members, CopyDependency::Versions]),
+        };
+
+        let job = args.into_job(2);
+        assert_eq!(job.job_type, INSTANTIATE_TEMPLATE_JOB);
+        assert_eq!(job.metadata.get(JOB_USER_METADATA_KEY).map(String::as_str), Some("2"));
+        assert!(!job.can_retry());
+
+        let parsed: InstantiateTemplateArgs = serde_json::from_value(job.args).unwrap();
+        assert_eq!(parsed.template_id, 7);
+        assert_eq!(parsed.params.identifier, "from-template");
+        assert_eq!(parsed.params.dependencies, vec![CopyDependency::Members, CopyDependency::Versions]);
+    }
+}
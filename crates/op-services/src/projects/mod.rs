@@ -5,16 +5,27 @@
 //! - app/services/projects/update_service.rb
 //! - app/services/projects/delete_service.rb
 //! - app/services/projects/set_attributes_service.rb
+//! - app/services/projects/copy_service.rb
+//! - app/services/projects/instantiate_template/instantiate_template_service.rb
 
 mod create;
 mod update;
 mod delete;
 mod set_attributes;
+mod copy;
+mod identifier;
+mod instantiate_template;
 
 pub use create::CreateProjectService;
 pub use update::UpdateProjectService;
 pub use delete::DeleteProjectService;
 pub use set_attributes::{ProjectEntity, SetAttributesService};
+pub use copy::{CopyProjectParams, CopyProjectService};
+pub use identifier::{generate_identifier, identifier_errors};
+pub use instantiate_template::{
+    InstantiateTemplateArgs, InstantiateTemplateJob, InstantiateTemplateService,
+    INSTANTIATE_TEMPLATE_JOB, JOB_USER_METADATA_KEY,
+};
 
 /// Project service params
 #[derive(Debug, Clone, Default)]
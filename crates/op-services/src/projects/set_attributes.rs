@@ -3,7 +3,7 @@
 //! Mirrors: app/services/projects/set_attributes_service.rb
 
 use op_contracts::base::UserContext;
-use op_contracts::projects::{CreateProjectContract, ProjectData};
+use op_contracts::projects::{identifier_from_name, CreateProjectContract, ProjectData, UpdateProjectContract};
 use op_core::error::ValidationErrors;
 use op_core::traits::Id;
 
@@ -75,11 +75,13 @@ impl ProjectData for ProjectEntity {
 pub struct SetAttributesService<'a, U: UserContext> {
     user: &'a U,
     model: ProjectEntity,
+    original_identifier: String,
 }
 
 impl<'a, U: UserContext> SetAttributesService<'a, U> {
     pub fn new(user: &'a U, model: ProjectEntity) -> Self {
-        Self { user, model }
+        let original_identifier = model.identifier.clone();
+        Self { user, model, original_identifier }
     }
 
     /// Set attributes from params and validate
@@ -116,13 +118,26 @@ impl<'a, U: UserContext> SetAttributesService<'a, U> {
         if let Some(parent_id) = params.parent_id {
             self.model.parent_id = Some(parent_id);
         }
+
+        // New projects without an identifier get one derived from their name
+        if self.model.is_new() && self.model.identifier.is_empty() && !self.model.name.trim().is_empty() {
+            self.model.identifier = identifier_from_name(&self.model.name);
+        }
     }
 
     fn validate(&self) -> Result<(), ValidationErrors> {
         use op_contracts::base::Contract;
 
-        let contract = CreateProjectContract::new(self.user);
-        contract.validate(&self.model)
+        match self.model.id {
+            None => CreateProjectContract::new(self.user).validate(&self.model),
+            Some(id) => {
+                let mut contract = UpdateProjectContract::new(self.user, id);
+                if self.model.identifier != self.original_identifier {
+                    contract.mark_changed("identifier");
+                }
+                contract.validate(&self.model)
+            }
+        }
     }
 }
 
@@ -186,6 +201,16 @@ mod tests {
         assert_eq!(project.description, Some("A test project".to_string()));
     }
 
+    #[test]
+    fn test_identifier_generated_from_name() {
+        let user = create_admin_user();
+        let service = SetAttributesService::new(&user, ProjectEntity::new());
+
+        let result = service.call(&ProjectParams::new().with_name("Zürich Straßenbau"));
+        assert!(result.is_success());
+        assert_eq!(result.result().unwrap().identifier, "zurich-strassenbau");
+    }
+
     #[test]
     fn test_set_attributes_validation_fails() {
         let user = create_admin_user();
@@ -21,6 +21,7 @@ use super::ProjectParams;
 pub struct UpdateProjectService<'a, U: UserContext> {
     user: &'a U,
     send_notifications: bool,
+    dry_run: bool,
 }
 
 impl<'a, U: UserContext> UpdateProjectService<'a, U> {
@@ -28,6 +29,7 @@ impl<'a, U: UserContext> UpdateProjectService<'a, U> {
         Self {
             user,
             send_notifications: true,
+            dry_run: false,
         }
     }
 
@@ -35,9 +37,17 @@ impl<'a, U: UserContext> UpdateProjectService<'a, U> {
         Self {
             user,
             send_notifications: false,
+            dry_run: false,
         }
     }
 
+    /// Only validate; the result holds the project as it would be saved,
+    /// without journaling or notifying the change
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
     /// Execute the update operation
     pub fn call(
         self,
@@ -58,7 +68,7 @@ impl<'a, U: UserContext> UpdateProjectService<'a, U> {
         let set_attrs_service = SetAttributesService::new(self.user, project);
         let result = set_attrs_service.call(&params);
 
-        if result.is_failure() {
+        if result.is_failure() || self.dry_run {
             return result;
         }
 
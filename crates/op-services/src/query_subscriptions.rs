@@ -0,0 +1,785 @@
+//! Saved query subscriptions
+//!
+//! A user subscribed to a saved query is emailed when work packages enter
+//! or leave its results, or are updated while in them. The
+//! [`QUERY_SUBSCRIPTION_JOB`] is meant to run periodically, e.g. hourly:
+//! each run checks the subscriptions due at their frequency, compares the
+//! result ids with the snapshot taken at the previous check and stores the
+//! new snapshot. The first check of a subscription only takes the snapshot.
+//!
+//! A query that cannot be run anymore, e.g. because it or its project was
+//! deleted, suspends the subscription and notifies the subscriber instead
+//! of failing the run for everybody.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use op_core::clock::{parse_time_zone, Tz};
+use op_core::traits::Id;
+use op_db::{
+    subscription_frequency, Pagination, ProjectRepository, QueryRepository, QuerySubscriptionRepository,
+    QuerySubscriptionRow, Repository, RepositoryError, RepositoryResult, UserRepository,
+    WorkPackageQueryExecutor, WorkPackageRepository,
+};
+use op_notifications::email::{EmailSender, ListedWorkPackage, QueryResultChanges};
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use op_notifications::{EmailRenderer, Notification, NotificationReason, NotificationStore, NotificationType};
+use op_queries::{Filter, FilterValue};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::warn;
+
+/// Job type checking the subscriptions that are due
+pub const QUERY_SUBSCRIPTION_JOB: &str = "Queries::SubscriptionJob";
+
+/// Most results tracked per subscription
+pub const SNAPSHOT_LIMIT: i64 = 1000;
+
+/// A subscription counts as due this much before its interval has passed,
+/// so a job run starting slightly early does not skip it
+pub const DUE_TOLERANCE: Duration = Duration::minutes(5);
+
+/// How often a subscribed query is checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionFrequency {
+    Hourly,
+    #[default]
+    Daily,
+    Weekly,
+}
+
+impl SubscriptionFrequency {
+    pub const ALL: [SubscriptionFrequency; 3] = [
+        SubscriptionFrequency::Hourly,
+        SubscriptionFrequency::Daily,
+        SubscriptionFrequency::Weekly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionFrequency::Hourly => subscription_frequency::HOURLY,
+            SubscriptionFrequency::Daily => subscription_frequency::DAILY,
+            SubscriptionFrequency::Weekly => subscription_frequency::WEEKLY,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|frequency| frequency.as_str() == name)
+    }
+
+    /// Time between two checks
+    pub fn interval(&self) -> Duration {
+        match self {
+            SubscriptionFrequency::Hourly => Duration::hours(1),
+            SubscriptionFrequency::Daily => Duration::days(1),
+            SubscriptionFrequency::Weekly => Duration::weeks(1),
+        }
+    }
+}
+
+/// A user's subscription to a saved query
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuerySubscription {
+    pub id: Id,
+    pub query_id: Id,
+    pub user_id: Id,
+    pub frequency: SubscriptionFrequency,
+    /// Result ids at the last check; `None` until the first check
+    pub snapshot: Option<Vec<Id>>,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
+    pub suspended_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl QuerySubscription {
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_at.is_some()
+    }
+
+    /// Whether the subscription is to be checked at `now`
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        !self.is_suspended()
+            && self
+                .checked_at
+                .is_none_or(|checked_at| now - checked_at >= self.frequency.interval() - DUE_TOLERANCE)
+    }
+}
+
+impl From<QuerySubscriptionRow> for QuerySubscription {
+    fn from(row: QuerySubscriptionRow) -> Self {
+        Self {
+            id: row.id,
+            query_id: row.query_id,
+            user_id: row.user_id,
+            frequency: SubscriptionFrequency::parse(&row.frequency).unwrap_or_default(),
+            snapshot: row.snapshot,
+            checked_at: row.checked_at,
+            suspended_at: row.suspended_at,
+            suspended_reason: row.suspended_reason,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// A work package in the results of a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultWorkPackage {
+    pub id: Id,
+    pub subject: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Results of a subscribed query, as seen by the subscriber
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResults {
+    pub query_name: String,
+    pub project_id: Option<Id>,
+    pub work_packages: Vec<ResultWorkPackage>,
+}
+
+/// Subscriber to email
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionRecipient {
+    pub mail: String,
+    pub name: Option<String>,
+    pub language: Option<String>,
+}
+
+/// How the results changed between two checks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResultDiff {
+    pub added: Vec<Id>,
+    pub removed: Vec<Id>,
+    /// Work packages in both results updated since the previous check
+    pub changed: Vec<Id>,
+}
+
+impl ResultDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the results with the snapshot of the previous check, taken at
+/// `since`
+pub fn diff_results(previous: &[Id], current: &[ResultWorkPackage], since: DateTime<Utc>) -> ResultDiff {
+    let before: HashSet<Id> = previous.iter().copied().collect();
+    let now: HashSet<Id> = current.iter().map(|wp| wp.id).collect();
+
+    let (kept, added): (Vec<_>, Vec<_>) = current.iter().partition(|wp| before.contains(&wp.id));
+    ResultDiff {
+        added: added.into_iter().map(|wp| wp.id).collect(),
+        removed: previous.iter().copied().filter(|id| !now.contains(id)).collect(),
+        changed: kept
+            .into_iter()
+            .filter(|wp| wp.updated_at > since)
+            .map(|wp| wp.id)
+            .collect(),
+    }
+}
+
+/// Whether a failure may go away by itself, so the subscription is checked
+/// again instead of being suspended
+fn is_transient(error: &RepositoryError) -> bool {
+    matches!(
+        error,
+        RepositoryError::Database(
+            sqlx::Error::Io(_)
+                | sqlx::Error::Tls(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed
+        )
+    )
+}
+
+/// Storage of subscriptions and the queries they run
+#[async_trait]
+pub trait QuerySubscriptionStore: Send + Sync {
+    /// Subscriptions that are not suspended
+    async fn active_subscriptions(&self) -> RepositoryResult<Vec<QuerySubscription>>;
+
+    /// Run the subscribed query as the subscriber, returning at most
+    /// [`SNAPSHOT_LIMIT`] results
+    async fn run_query(&self, subscription: &QuerySubscription) -> RepositoryResult<QueryResults>;
+
+    /// Work packages by id, e.g. those that left the results. Deleted work
+    /// packages are missing.
+    async fn find_work_packages(&self, ids: &[Id]) -> RepositoryResult<Vec<ListedWorkPackage>>;
+
+    async fn find_recipient(&self, user_id: Id) -> RepositoryResult<Option<SubscriptionRecipient>>;
+
+    async fn save_snapshot(&self, id: Id, snapshot: &[Id], checked_at: DateTime<Utc>) -> RepositoryResult<()>;
+
+    async fn suspend(&self, id: Id, reason: &str) -> RepositoryResult<()>;
+}
+
+/// Subscriptions stored in `query_subscriptions`, running the saved queries
+/// with the work package query executor
+pub struct PgQuerySubscriptionStore {
+    pool: PgPool,
+    subscriptions: QuerySubscriptionRepository,
+    time_zone: Tz,
+}
+
+impl PgQuerySubscriptionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            subscriptions: QuerySubscriptionRepository::new(pool.clone()),
+            pool,
+            time_zone: Tz::UTC,
+        }
+    }
+
+    /// Time zone of the instance, evaluating relative date filters of
+    /// subscribers without one in their preferences
+    pub fn with_time_zone(mut self, time_zone: Tz) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+}
+
+#[async_trait]
+impl QuerySubscriptionStore for PgQuerySubscriptionStore {
+    async fn active_subscriptions(&self) -> RepositoryResult<Vec<QuerySubscription>> {
+        Ok(self
+            .subscriptions
+            .find_active()
+            .await?
+            .into_iter()
+            .map(QuerySubscription::from)
+            .collect())
+    }
+
+    async fn run_query(&self, subscription: &QuerySubscription) -> RepositoryResult<QueryResults> {
+        let not_found = |what: &str, id: Id| RepositoryError::NotFound(format!("{} {} not found", what, id));
+
+        let row = QueryRepository::new(self.pool.clone())
+            .find_by_id(subscription.query_id)
+            .await?
+            .ok_or_else(|| not_found("Query", subscription.query_id))?;
+        let users = UserRepository::new(self.pool.clone());
+        let user = users
+            .find_by_id(subscription.user_id)
+            .await?
+            .filter(|user| user.is_active())
+            .ok_or_else(|| not_found("User", subscription.user_id))?;
+        let time_zone = users
+            .find_time_zone(user.id)
+            .await?
+            .as_deref()
+            .and_then(parse_time_zone)
+            .unwrap_or(self.time_zone);
+
+        let mut query = row.to_query()?;
+        if let Some(project_id) = row.project_id {
+            let project = ProjectRepository::new(self.pool.clone())
+                .find_by_id(project_id)
+                .await?
+                .ok_or_else(|| not_found("Project", project_id))?;
+            if !project.active {
+                return Err(RepositoryError::validation(format!("Project {} is archived", project_id)));
+            }
+            if !query.filters.has_filter_for("project_id") && !query.filters.has_filter_for("project") {
+                query.filters.add(Filter::equals("project_id", FilterValue::from_ids(vec![project_id])));
+            }
+        }
+
+        let mut executor = WorkPackageQueryExecutor::new(&self.pool).in_time_zone(time_zone);
+        if !user.admin {
+            executor = executor.visible_to(user.id);
+        }
+        let results = executor
+            .execute(&query, &Pagination::new(SNAPSHOT_LIMIT, 0), Some(user.id))
+            .await?;
+
+        Ok(QueryResults {
+            query_name: row.name,
+            project_id: row.project_id,
+            work_packages: results
+                .items
+                .into_iter()
+                .map(|wp| ResultWorkPackage {
+                    id: wp.id,
+                    subject: wp.subject,
+                    updated_at: wp.updated_at,
+                })
+                .collect(),
+        })
+    }
+
+    async fn find_work_packages(&self, ids: &[Id]) -> RepositoryResult<Vec<ListedWorkPackage>> {
+        Ok(WorkPackageRepository::new(self.pool.clone())
+            .find_by_ids(ids)
+            .await?
+            .into_iter()
+            .map(|wp| ListedWorkPackage {
+                id: wp.id,
+                subject: wp.subject,
+            })
+            .collect())
+    }
+
+    async fn find_recipient(&self, user_id: Id) -> RepositoryResult<Option<SubscriptionRecipient>> {
+        Ok(UserRepository::new(self.pool.clone())
+            .find_by_id(user_id)
+            .await?
+            .filter(|user| user.is_active())
+            .map(|user| {
+                let name = user.full_name().trim().to_string();
+                SubscriptionRecipient {
+                    mail: user.mail,
+                    name: Some(name).filter(|n| !n.is_empty()),
+                    language: user.language,
+                }
+            }))
+    }
+
+    async fn save_snapshot(&self, id: Id, snapshot: &[Id], checked_at: DateTime<Utc>) -> RepositoryResult<()> {
+        self.subscriptions.update_snapshot(id, snapshot, checked_at).await
+    }
+
+    async fn suspend(&self, id: Id, reason: &str) -> RepositoryResult<()> {
+        self.subscriptions.suspend(id, reason).await
+    }
+}
+
+/// Job handler checking the subscriptions that are due and emailing the
+/// subscribers of queries whose results changed
+///
+/// Subscriptions failing transiently, e.g. while the database is
+/// unreachable, keep their snapshot and are checked again by the next run;
+/// the job fails so the failure is visible.
+pub struct QuerySubscriptionJob<S: QuerySubscriptionStore, E: EmailSender> {
+    store: Arc<S>,
+    sender: Arc<E>,
+    renderer: EmailRenderer,
+    notifications: Arc<dyn NotificationStore>,
+}
+
+impl<S: QuerySubscriptionStore, E: EmailSender> QuerySubscriptionJob<S, E> {
+    pub fn new(
+        store: Arc<S>,
+        sender: Arc<E>,
+        renderer: EmailRenderer,
+        notifications: Arc<dyn NotificationStore>,
+    ) -> Self {
+        Self {
+            store,
+            sender,
+            renderer,
+            notifications,
+        }
+    }
+
+    async fn check(&self, subscription: &QuerySubscription, now: DateTime<Utc>) -> JobResult<()> {
+        let results = match self.store.run_query(subscription).await {
+            Ok(results) => results,
+            Err(e) if is_transient(&e) => return Err(failed(e)),
+            Err(e) => return self.suspend(subscription, &e.to_string()).await,
+        };
+
+        if let Some(previous) = &subscription.snapshot {
+            let since = subscription.checked_at.unwrap_or(subscription.created_at);
+            let diff = diff_results(previous, &results.work_packages, since);
+            if !diff.is_empty() {
+                let Some(recipient) = self.store.find_recipient(subscription.user_id).await.map_err(failed)? else {
+                    return self.suspend(subscription, "The subscriber cannot be emailed").await;
+                };
+                let changes = self.describe(subscription, &results, diff).await?;
+                let message = match self.renderer.render_query_changes(
+                    &changes,
+                    &recipient.mail,
+                    recipient.name.as_deref(),
+                    recipient.language.as_deref(),
+                ) {
+                    Ok(message) => message,
+                    Err(e) => return self.suspend(subscription, &e.to_string()).await,
+                };
+                self.sender
+                    .send(&message)
+                    .await
+                    .map_err(|e| JobError::Failed(e.to_string()))?;
+            }
+        }
+
+        let snapshot: Vec<Id> = results.work_packages.iter().map(|wp| wp.id).collect();
+        self.store
+            .save_snapshot(subscription.id, &snapshot, now)
+            .await
+            .map_err(failed)
+    }
+
+    async fn describe(
+        &self,
+        subscription: &QuerySubscription,
+        results: &QueryResults,
+        diff: ResultDiff,
+    ) -> JobResult<QueryResultChanges> {
+        let subjects: HashMap<Id, &str> = results
+            .work_packages
+            .iter()
+            .map(|wp| (wp.id, wp.subject.as_str()))
+            .collect();
+        let listed = |ids: Vec<Id>| -> Vec<ListedWorkPackage> {
+            ids.into_iter()
+                .map(|id| ListedWorkPackage {
+                    id,
+                    subject: subjects.get(&id).copied().unwrap_or_default().to_string(),
+                })
+                .collect()
+        };
+
+        // Removed work packages are no longer in the results; deleted ones
+        // are listed by id only
+        let found: HashMap<Id, String> = self
+            .store
+            .find_work_packages(&diff.removed)
+            .await
+            .map_err(failed)?
+            .into_iter()
+            .map(|wp| (wp.id, wp.subject))
+            .collect();
+        let removed = diff
+            .removed
+            .into_iter()
+            .map(|id| ListedWorkPackage {
+                id,
+                subject: found.get(&id).cloned().unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(QueryResultChanges {
+            query_id: subscription.query_id,
+            query_name: results.query_name.clone(),
+            project_id: results.project_id,
+            added: listed(diff.added),
+            removed,
+            changed: listed(diff.changed),
+        })
+    }
+
+    /// Suspend the subscription and tell the subscriber why
+    async fn suspend(&self, subscription: &QuerySubscription, reason: &str) -> JobResult<()> {
+        warn!(
+            subscription_id = subscription.id,
+            query_id = subscription.query_id,
+            reason,
+            "Suspending query subscription"
+        );
+        self.store
+            .suspend(subscription.id, reason)
+            .await
+            .map_err(failed)?;
+
+        let mut notification = Notification::new(
+            subscription.user_id,
+            NotificationType::QuerySubscriptionSuspended,
+            NotificationReason::Subscribed,
+            "Query",
+            subscription.query_id,
+        );
+        if let Err(e) = self.notifications.create(&mut notification).await {
+            warn!(subscription_id = subscription.id, error = %e, "Suspension notification not created");
+        }
+        Ok(())
+    }
+}
+
+fn failed(e: RepositoryError) -> JobError {
+    JobError::Failed(e.to_string())
+}
+
+#[async_trait]
+impl<S: QuerySubscriptionStore, E: EmailSender> JobHandler for QuerySubscriptionJob<S, E> {
+    async fn handle(&self, _args: serde_json::Value) -> JobResult<()> {
+        let now = Utc::now();
+        let subscriptions = self.store.active_subscriptions().await.map_err(failed)?;
+
+        let mut failures = 0;
+        for subscription in subscriptions.iter().filter(|s| s.is_due(now)) {
+            if let Err(e) = self.check(subscription, now).await {
+                warn!(subscription_id = subscription.id, error = %e, "Query subscription will be checked again");
+                failures += 1;
+            }
+        }
+
+        if failures > 0 {
+            return Err(JobError::Failed(format!(
+                "{} query subscriptions could not be checked",
+                failures
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_notifications::email::{EmailAddress, MemoryEmailSender};
+    use op_notifications::MemoryNotificationStore;
+    use std::sync::Mutex;
+
+    fn result(id: Id, subject: &str, updated_at: DateTime<Utc>) -> ResultWorkPackage {
+        ResultWorkPackage {
+            id,
+            subject: subject.to_string(),
+            updated_at,
+        }
+    }
+
+    fn subscription(id: Id, query_id: Id) -> QuerySubscription {
+        QuerySubscription {
+            id,
+            query_id,
+            user_id: 7,
+            frequency: SubscriptionFrequency::Daily,
+            snapshot: None,
+            checked_at: None,
+            suspended_at: None,
+            suspended_reason: None,
+            created_at: Utc::now() - Duration::days(3),
+        }
+    }
+
+    /// Query results by query id; a missing query fails like a deleted one
+    #[derive(Default)]
+    struct MemoryStore {
+        subscriptions: Mutex<Vec<QuerySubscription>>,
+        results: Mutex<HashMap<Id, Vec<ResultWorkPackage>>>,
+        /// Filters of queries, validated as the executor does
+        filters: Mutex<HashMap<Id, Filter>>,
+        unreachable: Mutex<bool>,
+    }
+
+    impl MemoryStore {
+        fn subscription(&self, id: Id) -> QuerySubscription {
+            self.subscriptions.lock().unwrap().iter().find(|s| s.id == id).cloned().unwrap()
+        }
+
+        fn set_results(&self, query_id: Id, results: Vec<ResultWorkPackage>) {
+            self.results.lock().unwrap().insert(query_id, results);
+        }
+
+        /// Pretend the previous check happened a day ago
+        fn age(&self) {
+            for s in self.subscriptions.lock().unwrap().iter_mut() {
+                s.checked_at = s.checked_at.map(|at| at - Duration::days(1));
+            }
+        }
+    }
+
+    #[async_trait]
+    impl QuerySubscriptionStore for MemoryStore {
+        async fn active_subscriptions(&self) -> RepositoryResult<Vec<QuerySubscription>> {
+            if *self.unreachable.lock().unwrap() {
+                return Err(RepositoryError::Database(sqlx::Error::PoolTimedOut));
+            }
+            Ok(self
+                .subscriptions
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|s| !s.is_suspended())
+                .cloned()
+                .collect())
+        }
+
+        async fn run_query(&self, subscription: &QuerySubscription) -> RepositoryResult<QueryResults> {
+            if let Some(filter) = self.filters.lock().unwrap().get(&subscription.query_id) {
+                if let Err(error) = op_db::validate_filter(filter) {
+                    return Err(op_db::QueryValidationError { errors: vec![error] }.into());
+                }
+            }
+            let work_packages = self
+                .results
+                .lock()
+                .unwrap()
+                .get(&subscription.query_id)
+                .cloned()
+                .ok_or_else(|| RepositoryError::NotFound(format!("Query {} not found", subscription.query_id)))?;
+            Ok(QueryResults {
+                query_name: "Open bugs".to_string(),
+                project_id: Some(3),
+                work_packages,
+            })
+        }
+
+        async fn find_work_packages(&self, ids: &[Id]) -> RepositoryResult<Vec<ListedWorkPackage>> {
+            Ok(ids
+                .iter()
+                .filter(|id| **id != 99)
+                .map(|id| ListedWorkPackage {
+                    id: *id,
+                    subject: format!("Closed #{}", id),
+                })
+                .collect())
+        }
+
+        async fn find_recipient(&self, _user_id: Id) -> RepositoryResult<Option<SubscriptionRecipient>> {
+            Ok(Some(SubscriptionRecipient {
+                mail: "jane@example.com".to_string(),
+                name: Some("Jane".to_string()),
+                language: None,
+            }))
+        }
+
+        async fn save_snapshot(&self, id: Id, snapshot: &[Id], checked_at: DateTime<Utc>) -> RepositoryResult<()> {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            let subscription = subscriptions.iter_mut().find(|s| s.id == id).unwrap();
+            subscription.snapshot = Some(snapshot.to_vec());
+            subscription.checked_at = Some(checked_at);
+            Ok(())
+        }
+
+        async fn suspend(&self, id: Id, reason: &str) -> RepositoryResult<()> {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            let subscription = subscriptions.iter_mut().find(|s| s.id == id).unwrap();
+            subscription.suspended_at = Some(Utc::now());
+            subscription.suspended_reason = Some(reason.to_string());
+            Ok(())
+        }
+    }
+
+    fn job(
+        store: Arc<MemoryStore>,
+    ) -> (QuerySubscriptionJob<MemoryStore, MemoryEmailSender>, Arc<MemoryEmailSender>, Arc<MemoryNotificationStore>) {
+        let sender = Arc::new(MemoryEmailSender::new());
+        let notifications = Arc::new(MemoryNotificationStore::new());
+        let renderer = EmailRenderer::new("https://op.example.com", EmailAddress::new("noreply@example.com"));
+        let job = QuerySubscriptionJob::new(store, sender.clone(), renderer, notifications.clone());
+        (job, sender, notifications)
+    }
+
+    #[test]
+    fn test_diff_results() {
+        let since = Utc::now() - Duration::hours(1);
+        let stale = since - Duration::hours(1);
+        let current = vec![
+            result(1, "Unchanged", stale),
+            result(2, "Updated", Utc::now()),
+            result(5, "New", stale),
+        ];
+
+        let diff = diff_results(&[1, 2, 3, 4], &current, since);
+        assert_eq!(diff.added, vec![5]);
+        assert_eq!(diff.removed, vec![3, 4]);
+        assert_eq!(diff.changed, vec![2]);
+        assert!(diff_results(&[1], &[result(1, "Unchanged", stale)], since).is_empty());
+    }
+
+    #[test]
+    fn test_due_at_frequency() {
+        let now = Utc::now();
+        let mut s = subscription(1, 1);
+        assert!(s.is_due(now));
+
+        s.checked_at = Some(now - Duration::hours(23) - Duration::minutes(57));
+        assert!(s.is_due(now));
+        s.checked_at = Some(now - Duration::hours(2));
+        assert!(!s.is_due(now));
+
+        s.frequency = SubscriptionFrequency::Hourly;
+        assert!(s.is_due(now));
+        s.suspended_at = Some(now);
+        assert!(!s.is_due(now));
+
+        assert_eq!(SubscriptionFrequency::parse("weekly"), Some(SubscriptionFrequency::Weekly));
+        assert_eq!(SubscriptionFrequency::parse("monthly"), None);
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_emailed_after_the_first_check() {
+        let store = Arc::new(MemoryStore::default());
+        store.subscriptions.lock().unwrap().push(subscription(1, 10));
+        let old = Utc::now() - Duration::days(2);
+        store.set_results(10, vec![result(1, "Crash", old), result(2, "Typo", old), result(99, "Gone", old)]);
+        let (job, sender, _) = job(store.clone());
+
+        job.handle(serde_json::json!({})).await.unwrap();
+        assert_eq!(store.subscription(1).snapshot, Some(vec![1, 2, 99]));
+        assert!(sender.sent_messages().await.is_empty());
+
+        // Not due again until a day has passed
+        store.set_results(10, vec![result(1, "Crash", Utc::now()), result(3, "Slow", old)]);
+        job.handle(serde_json::json!({})).await.unwrap();
+        assert!(sender.sent_messages().await.is_empty());
+
+        store.age();
+        job.handle(serde_json::json!({})).await.unwrap();
+        let sent = sender.sent_messages().await;
+        assert_eq!(sent.len(), 1);
+        let body = &sent[0].text_body;
+        assert!(body.contains("Added (1):\n- #3 Slow\n"));
+        assert!(body.contains("Removed (2):\n- #2 Closed #2\n- #99 \n"));
+        assert!(body.contains("Changed (1):\n- #1 Crash\n"));
+        assert_eq!(store.subscription(1).snapshot, Some(vec![1, 3]));
+
+        store.set_results(10, vec![result(1, "Crash", old), result(3, "Slow", old)]);
+        store.age();
+        job.handle(serde_json::json!({})).await.unwrap();
+        assert_eq!(sender.sent_messages().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failing_query_suspends_only_its_subscription() {
+        let store = Arc::new(MemoryStore::default());
+        store.subscriptions.lock().unwrap().push(subscription(1, 10));
+        store.subscriptions.lock().unwrap().push(subscription(2, 20));
+        store.set_results(20, vec![result(1, "Crash", Utc::now())]);
+        let (job, _, notifications) = job(store.clone());
+
+        job.handle(serde_json::json!({})).await.unwrap();
+
+        let suspended = store.subscription(1);
+        assert!(suspended.is_suspended());
+        assert_eq!(suspended.suspended_reason.as_deref(), Some("Entity not found: Query 10 not found"));
+        assert_eq!(store.subscription(2).snapshot, Some(vec![1]));
+
+        let notified = notifications.get_for_user(7, true, 10).await.unwrap();
+        assert_eq!(notified.len(), 1);
+        assert_eq!(notified[0].notification_type, NotificationType::QuerySubscriptionSuspended);
+        assert_eq!((notified[0].resource_type.as_str(), notified[0].resource_id), ("Query", 10));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_filter_suspends_the_subscription() {
+        let store = Arc::new(MemoryStore::default());
+        store.subscriptions.lock().unwrap().push(subscription(1, 10));
+        store.set_results(10, vec![result(1, "Everything", Utc::now())]);
+        store
+            .filters
+            .lock()
+            .unwrap()
+            .insert(10, Filter::equals("asigned_to_id", FilterValue::Me));
+        let (job, _, _) = job(store.clone());
+
+        job.handle(serde_json::json!({})).await.unwrap();
+
+        let suspended = store.subscription(1);
+        assert_eq!(
+            suspended.suspended_reason.as_deref(),
+            Some("Invalid filters: asigned_to_id is not a known filter")
+        );
+        assert_eq!(suspended.snapshot, None);
+    }
+
+    #[tokio::test]
+    async fn test_transient_failure_fails_the_run() {
+        let store = Arc::new(MemoryStore::default());
+        store.subscriptions.lock().unwrap().push(subscription(1, 10));
+        *store.unreachable.lock().unwrap() = true;
+        let (job, _, _) = job(store.clone());
+
+        assert!(job.handle(serde_json::json!({})).await.is_err());
+        assert!(!store.subscription(1).is_suspended());
+        assert!(is_transient(&RepositoryError::Database(sqlx::Error::PoolTimedOut)));
+        assert!(!is_transient(&RepositoryError::validation("Project 3 is archived")));
+    }
+}
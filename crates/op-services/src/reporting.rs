@@ -0,0 +1,569 @@
+//! Reporting
+//!
+//! Status counts of a project's work packages over time, for cumulative
+//! flow diagrams and throughput charts.
+//!
+//! The history is reconstructed from the work package journals: each
+//! journal holds the status and project of its work package until the
+//! next one, the last until the work package went to the trash. Work
+//! packages therefore count from their creation on, in the project they
+//! were in at the time, and no longer once deleted. Series are kept per
+//! status id, so a status renamed since shows under its current name.
+//!
+//! Days end at midnight UTC. The history of a day that is over no longer
+//! changes, so its counts are memoized per project and day in a
+//! [`StatusHistoryCache`]; only purging work packages from the trash or
+//! restoring them rewrites it.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use op_core::clock::{start_of_week, Clock, SystemClock};
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+use op_db::{JournalRepository, RepositoryResult, StatusTransitionRow};
+
+/// Days a report may span at most
+pub const MAX_REPORT_DAYS: i64 = 3 * 366;
+
+/// Days kept at most by a [`StatusHistoryCache`]
+const MAX_CACHED_DAYS: usize = 100_000;
+
+/// Length of the buckets of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Day,
+    /// Weeks starting on Monday
+    Week,
+    Month,
+    Quarter,
+}
+
+impl Granularity {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "day" => Some(Self::Day),
+            "week" => Some(Self::Week),
+            "month" => Some(Self::Month),
+            "quarter" => Some(Self::Quarter),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Quarter => "quarter",
+        }
+    }
+
+    /// First day of the bucket containing `day`
+    fn start_of(self, day: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => day,
+            Self::Week => start_of_week(day),
+            Self::Month => day.with_day(1).unwrap(),
+            Self::Quarter => NaiveDate::from_ymd_opt(day.year(), (day.month0() / 3) * 3 + 1, 1).unwrap(),
+        }
+    }
+
+    /// First day of the bucket following the one starting at `start`
+    fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => start + Duration::days(1),
+            Self::Week => start + Duration::days(7),
+            Self::Month => start + Months::new(1),
+            Self::Quarter => start + Months::new(3),
+        }
+    }
+}
+
+/// Days of a report, split into buckets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub granularity: Granularity,
+}
+
+impl ReportRange {
+    /// Range of the days from `from` to `to`, both included, ending today
+    /// at the latest
+    pub fn new(
+        from: NaiveDate,
+        to: NaiveDate,
+        granularity: Granularity,
+        today: NaiveDate,
+    ) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let to = to.min(today);
+        if from > today {
+            errors.add("from", "can't be in the future");
+        } else if from > to {
+            errors.add("to", "must be on or after from");
+        } else if (to - from).num_days() + 1 > MAX_REPORT_DAYS {
+            errors.add("to", format!("is too far from from (at most {} days)", MAX_REPORT_DAYS));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(Self { from, to, granularity })
+    }
+
+    /// Buckets covering the range; the first and last are cut off at its
+    /// ends
+    pub fn buckets(&self) -> Vec<Bucket> {
+        let mut buckets = Vec::new();
+        let mut start = self.from;
+        while start <= self.to {
+            let next = self.granularity.next(self.granularity.start_of(start));
+            let end = (next - Duration::days(1)).min(self.to);
+            buckets.push(Bucket { start, end });
+            start = next;
+        }
+        buckets
+    }
+
+    fn days(&self) -> impl Iterator<Item = NaiveDate> {
+        self.from.iter_days().take_while({
+            let to = self.to;
+            move |day| *day <= to
+        })
+    }
+}
+
+/// Days of a report counted together, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bucket {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// Work packages of a project per status at the end of a day, and those
+/// closed that day
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DayStatus {
+    pub counts: BTreeMap<Id, i64>,
+    pub closed: i64,
+}
+
+/// Status of a work package for a span of time
+#[derive(Debug, Clone)]
+struct StatusInterval {
+    project_id: Id,
+    status_id: Id,
+    from: DateTime<Utc>,
+    /// `None` while the work package still has the status
+    until: Option<DateTime<Utc>>,
+    /// Whether the work package was closed by entering the status
+    closes: bool,
+}
+
+/// Statuses of work packages over time, reconstructed from their journals
+#[derive(Debug, Clone, Default)]
+pub struct StatusHistory {
+    intervals: Vec<StatusInterval>,
+}
+
+impl StatusHistory {
+    /// History of the journals, ordered by work package and version
+    pub fn new(transitions: &[StatusTransitionRow], closed_status_ids: &HashSet<Id>) -> Self {
+        let mut intervals: Vec<StatusInterval> = Vec::new();
+        for (index, row) in transitions.iter().enumerate() {
+            let previous = index
+                .checked_sub(1)
+                .map(|i| &transitions[i])
+                .filter(|previous| previous.work_package_id == row.work_package_id);
+            let next = transitions
+                .get(index + 1)
+                .filter(|next| next.work_package_id == row.work_package_id);
+
+            // Comments journal the state again without changing it
+            if let Some(previous) = previous {
+                if (previous.project_id, previous.status_id) == (row.project_id, row.status_id) {
+                    if let Some(last) = intervals.last_mut() {
+                        last.until = next.map(|next| next.created_at).or(row.deleted_at);
+                    }
+                    continue;
+                }
+            }
+
+            let closed = closed_status_ids.contains(&row.status_id);
+            let was_closed = previous.is_some_and(|previous| closed_status_ids.contains(&previous.status_id));
+            intervals.push(StatusInterval {
+                project_id: row.project_id,
+                status_id: row.status_id,
+                from: row.created_at,
+                until: next.map(|next| next.created_at).or(row.deleted_at),
+                closes: closed && previous.is_some() && !was_closed,
+            });
+        }
+        Self { intervals }
+    }
+
+    /// Statuses of the project's work packages as the day ended
+    pub fn day(&self, project_id: Id, day: NaiveDate) -> DayStatus {
+        let start = day.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let end = start + Duration::days(1);
+
+        let mut status = DayStatus::default();
+        for interval in self.intervals.iter().filter(|interval| interval.project_id == project_id) {
+            if interval.from < end && interval.until.is_none_or(|until| until >= end) {
+                *status.counts.entry(interval.status_id).or_default() += 1;
+            }
+            if interval.closes && interval.from >= start && interval.from < end {
+                status.closed += 1;
+            }
+        }
+        status
+    }
+}
+
+/// Memoized statuses of the days that are over, per project
+#[derive(Debug, Default)]
+pub struct StatusHistoryCache {
+    days: Mutex<HashMap<(Id, NaiveDate), DayStatus>>,
+}
+
+impl StatusHistoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, project_id: Id, day: NaiveDate) -> Option<DayStatus> {
+        self.days.lock().unwrap().get(&(project_id, day)).cloned()
+    }
+
+    fn put(&self, project_id: Id, day: NaiveDate, status: DayStatus) {
+        let mut days = self.days.lock().unwrap();
+        if days.len() >= MAX_CACHED_DAYS {
+            days.clear();
+        }
+        days.insert((project_id, day), status);
+    }
+
+    /// Number of memoized days
+    pub fn len(&self) -> usize {
+        self.days.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Source of the journaled statuses of work packages
+#[async_trait]
+pub trait StatusHistorySource: Send + Sync {
+    /// Journals of the work packages that were in the project at some
+    /// point, created before `before`, ordered by work package and version
+    async fn status_transitions(
+        &self,
+        project_id: Id,
+        before: DateTime<Utc>,
+    ) -> RepositoryResult<Vec<StatusTransitionRow>>;
+}
+
+#[async_trait]
+impl StatusHistorySource for JournalRepository {
+    async fn status_transitions(
+        &self,
+        project_id: Id,
+        before: DateTime<Utc>,
+    ) -> RepositoryResult<Vec<StatusTransitionRow>> {
+        self.find_status_transitions(project_id, before).await
+    }
+}
+
+/// Status counts of a bucket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowBucket {
+    pub bucket: Bucket,
+    /// Work packages per status as the bucket ended
+    pub counts: BTreeMap<Id, i64>,
+    /// Work packages closed during the bucket
+    pub closed: i64,
+}
+
+/// Cumulative flow of a project's work packages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CumulativeFlow {
+    pub buckets: Vec<FlowBucket>,
+}
+
+impl CumulativeFlow {
+    /// Statuses counted in any bucket
+    pub fn status_ids(&self) -> BTreeSet<Id> {
+        self.buckets.iter().flat_map(|bucket| bucket.counts.keys().copied()).collect()
+    }
+
+    /// Work packages with the status at the end of each bucket
+    pub fn series(&self, status_id: Id) -> Vec<i64> {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.counts.get(&status_id).copied().unwrap_or(0))
+            .collect()
+    }
+}
+
+/// Service reconstructing status counts over time
+pub struct ReportingService<S: StatusHistorySource> {
+    source: S,
+    cache: Arc<StatusHistoryCache>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<S: StatusHistorySource> ReportingService<S> {
+    pub fn new(source: S, cache: Arc<StatusHistoryCache>) -> Self {
+        Self {
+            source,
+            cache,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Take the current time from the clock, e.g. a fixed one in tests
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Today in UTC, the last day a report may cover
+    pub fn today(&self) -> NaiveDate {
+        self.clock.now().date_naive()
+    }
+
+    /// Status counts of the project's work packages at the end of each
+    /// bucket of the range, and the work packages closed during it. The
+    /// journals are only read if a day of the range is not memoized yet.
+    pub async fn cumulative_flow(
+        &self,
+        project_id: Id,
+        range: &ReportRange,
+        closed_status_ids: &HashSet<Id>,
+    ) -> RepositoryResult<CumulativeFlow> {
+        let today = self.today();
+        let mut days: BTreeMap<NaiveDate, DayStatus> = range
+            .days()
+            .filter(|day| *day < today)
+            .filter_map(|day| self.cache.get(project_id, day).map(|status| (day, status)))
+            .collect();
+
+        if range.days().any(|day| !days.contains_key(&day)) {
+            let before = (range.to + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap().and_utc();
+            let transitions = self.source.status_transitions(project_id, before).await?;
+            let history = StatusHistory::new(&transitions, closed_status_ids);
+            for day in range.days().filter(|day| !days.contains_key(day)).collect::<Vec<_>>() {
+                let status = history.day(project_id, day);
+                if day < today {
+                    self.cache.put(project_id, day, status.clone());
+                }
+                days.insert(day, status);
+            }
+        }
+
+        let buckets = range
+            .buckets()
+            .into_iter()
+            .map(|bucket| FlowBucket {
+                bucket,
+                counts: days[&bucket.end].counts.clone(),
+                closed: days.range(bucket.start..=bucket.end).map(|(_, status)| status.closed).sum(),
+            })
+            .collect();
+        Ok(CumulativeFlow { buckets })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_core::clock::ManualClock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const PROJECT: Id = 1;
+    const OTHER_PROJECT: Id = 2;
+    const NEW: Id = 1;
+    const IN_PROGRESS: Id = 2;
+    const CLOSED: Id = 3;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        format!("{}Z", s).parse().unwrap()
+    }
+
+    fn journal(work_package_id: Id, project_id: Id, status_id: Id, created_at: &str) -> StatusTransitionRow {
+        StatusTransitionRow {
+            work_package_id,
+            project_id,
+            status_id,
+            created_at: at(created_at),
+            deleted_at: None,
+        }
+    }
+
+    /// Journals of four work packages in March 2024:
+    /// - 1 created on the 1st, in progress on the 3rd, commented on the
+    ///   4th, closed on the 5th
+    /// - 2 created on the 2nd, deleted on the 4th
+    /// - 3 created on the 4th in the other project, moved in on the 6th
+    /// - 4 created on the 6th, after the range
+    fn history() -> Vec<StatusTransitionRow> {
+        let mut deleted = journal(2, PROJECT, NEW, "2024-03-02T09:00:00");
+        deleted.deleted_at = Some(at("2024-03-04T12:00:00"));
+        vec![
+            journal(1, PROJECT, NEW, "2024-03-01T10:00:00"),
+            journal(1, PROJECT, IN_PROGRESS, "2024-03-03T08:00:00"),
+            journal(1, PROJECT, IN_PROGRESS, "2024-03-04T08:00:00"),
+            journal(1, PROJECT, CLOSED, "2024-03-05T23:59:00"),
+            deleted,
+            journal(3, OTHER_PROJECT, IN_PROGRESS, "2024-03-04T10:00:00"),
+            journal(3, PROJECT, IN_PROGRESS, "2024-03-06T10:00:00"),
+            journal(4, PROJECT, NEW, "2024-03-07T10:00:00"),
+        ]
+    }
+
+    fn closed() -> HashSet<Id> {
+        HashSet::from([CLOSED])
+    }
+
+    /// Journals of a fixed history, counting the reads
+    #[derive(Default)]
+    struct FixtureSource {
+        reads: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl StatusHistorySource for FixtureSource {
+        async fn status_transitions(
+            &self,
+            _project_id: Id,
+            before: DateTime<Utc>,
+        ) -> RepositoryResult<Vec<StatusTransitionRow>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            Ok(history().into_iter().filter(|row| row.created_at < before).collect())
+        }
+    }
+
+    fn service(today: &str) -> ReportingService<FixtureSource> {
+        let now = date(today).and_hms_opt(12, 0, 0).unwrap().and_utc();
+        ReportingService::new(FixtureSource::default(), Arc::new(StatusHistoryCache::new()))
+            .with_clock(Arc::new(ManualClock::new(now)))
+    }
+
+    fn range(from: &str, to: &str, granularity: Granularity) -> ReportRange {
+        ReportRange::new(date(from), date(to), granularity, date("2024-12-31")).unwrap()
+    }
+
+    #[test]
+    fn test_reconstructs_the_statuses_of_each_day() {
+        let history = StatusHistory::new(&history(), &closed());
+        let counts = |day: &str| history.day(PROJECT, date(day)).counts.into_iter().collect::<Vec<_>>();
+
+        assert_eq!(counts("2024-02-29"), vec![]);
+        assert_eq!(counts("2024-03-01"), vec![(NEW, 1)]);
+        assert_eq!(counts("2024-03-02"), vec![(NEW, 2)]);
+        assert_eq!(counts("2024-03-03"), vec![(NEW, 1), (IN_PROGRESS, 1)]);
+        // Deleted during the day, and not counted in the other project
+        assert_eq!(counts("2024-03-04"), vec![(IN_PROGRESS, 1)]);
+        assert_eq!(counts("2024-03-05"), vec![(CLOSED, 1)]);
+        assert_eq!(counts("2024-03-06"), vec![(IN_PROGRESS, 1), (CLOSED, 1)]);
+        assert_eq!(history.day(OTHER_PROJECT, date("2024-03-05")).counts, BTreeMap::from([(IN_PROGRESS, 1)]));
+
+        // Closing counts once, though the comment journaled the status again
+        let closed: Vec<i64> = (1..=7)
+            .map(|day| history.day(PROJECT, date(&format!("2024-03-0{}", day))).closed)
+            .collect();
+        assert_eq!(closed, vec![0, 0, 0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_buckets_are_cut_off_at_the_range() {
+        let starts = |range: ReportRange| {
+            range
+                .buckets()
+                .into_iter()
+                .map(|bucket| (bucket.start.to_string(), bucket.end.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            starts(range("2024-03-06", "2024-03-19", Granularity::Week)),
+            vec![
+                ("2024-03-06".into(), "2024-03-10".into()),
+                ("2024-03-11".into(), "2024-03-17".into()),
+                ("2024-03-18".into(), "2024-03-19".into()),
+            ]
+        );
+        assert_eq!(
+            starts(range("2024-01-15", "2024-03-10", Granularity::Month)),
+            vec![
+                ("2024-01-15".into(), "2024-01-31".into()),
+                ("2024-02-01".into(), "2024-02-29".into()),
+                ("2024-03-01".into(), "2024-03-10".into()),
+            ]
+        );
+        assert_eq!(
+            starts(range("2024-02-10", "2024-07-01", Granularity::Quarter)),
+            vec![
+                ("2024-02-10".into(), "2024-03-31".into()),
+                ("2024-04-01".into(), "2024-06-30".into()),
+                ("2024-07-01".into(), "2024-07-01".into()),
+            ]
+        );
+        assert_eq!(range("2024-03-01", "2024-03-03", Granularity::Day).buckets().len(), 3);
+    }
+
+    #[test]
+    fn test_ranges_are_guarded() {
+        let today = date("2024-03-10");
+        let range = ReportRange::new(date("2024-03-01"), date("2024-04-30"), Granularity::Day, today).unwrap();
+        assert_eq!(range.to, today);
+
+        assert!(ReportRange::new(date("2024-03-11"), date("2024-03-12"), Granularity::Day, today).is_err());
+        assert!(ReportRange::new(date("2024-03-05"), date("2024-03-04"), Granularity::Day, today).is_err());
+        let long = ReportRange::new(date("2020-01-01"), today, Granularity::Month, today);
+        assert!(long.unwrap_err().has_error("to"));
+    }
+
+    #[tokio::test]
+    async fn test_cumulative_flow_per_bucket() {
+        let service = service("2024-03-20");
+        let flow = service
+            .cumulative_flow(PROJECT, &range("2024-03-01", "2024-03-06", Granularity::Week), &closed())
+            .await
+            .unwrap();
+
+        assert_eq!(flow.buckets.len(), 2);
+        assert_eq!(flow.buckets[0].bucket.end, date("2024-03-03"));
+        assert_eq!(flow.status_ids(), BTreeSet::from([NEW, IN_PROGRESS, CLOSED]));
+        assert_eq!(flow.series(NEW), vec![1, 0]);
+        assert_eq!(flow.series(IN_PROGRESS), vec![1, 1]);
+        assert_eq!(flow.series(CLOSED), vec![0, 1]);
+        assert_eq!(flow.buckets.iter().map(|bucket| bucket.closed).collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_days_that_are_over_are_memoized() {
+        let service = service("2024-03-06");
+        let march = range("2024-03-01", "2024-03-06", Granularity::Day);
+        let first = service.cumulative_flow(PROJECT, &march, &closed()).await.unwrap();
+        assert_eq!(service.source.reads.load(Ordering::SeqCst), 1);
+        assert_eq!(service.cache.len(), 5);
+
+        // Days before today come from the cache, today is read again
+        let past = range("2024-03-02", "2024-03-05", Granularity::Day);
+        service.cumulative_flow(PROJECT, &past, &closed()).await.unwrap();
+        assert_eq!(service.source.reads.load(Ordering::SeqCst), 1);
+        let again = service.cumulative_flow(PROJECT, &march, &closed()).await.unwrap();
+        assert_eq!(service.source.reads.load(Ordering::SeqCst), 2);
+        assert_eq!(again, first);
+    }
+}
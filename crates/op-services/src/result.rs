@@ -2,7 +2,7 @@
 //!
 //! Mirrors: app/services/service_result.rb
 
-use op_core::error::ValidationErrors;
+use op_core::error::{PropertyError, ValidationErrors};
 use std::fmt;
 
 /// Represents the result of a service call
@@ -68,6 +68,12 @@ impl<T> ServiceResult<T> {
         Self::failure(errors)
     }
 
+    /// Create a failed service result from property-coded errors, e.g. those
+    /// of a `RepositoryError::Validation`
+    pub fn failure_with_property_errors(errors: Vec<PropertyError>) -> Self {
+        Self::failure(errors.into())
+    }
+
     /// Create a failed service result with a message
     pub fn failure_with_message(errors: ValidationErrors, message: impl Into<String>) -> Self {
         Self {
@@ -164,6 +170,14 @@ impl<T> ServiceResult<T> {
         errors
     }
 
+    /// Property errors of this call and its dependent calls
+    pub fn property_errors(&self) -> Vec<PropertyError> {
+        self.all_errors()
+            .into_iter()
+            .flat_map(ValidationErrors::property_errors)
+            .collect()
+    }
+
     /// Full error messages
     pub fn full_messages(&self) -> Vec<String> {
         self.errors.full_messages()
@@ -311,6 +325,19 @@ mod tests {
         assert!(result.errors().has_error("field"));
     }
 
+    #[test]
+    fn test_property_errors_include_dependent_results() {
+        let mut result: ServiceResult<i32> = ServiceResult::failure_with_property_errors(vec![
+            PropertyError::new("name", "blank", "can't be blank"),
+        ]);
+        result.add_dependent(ServiceResult::failure_with_base_error("Parent is archived"));
+
+        let errors = result.property_errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0], PropertyError::new("name", "blank", "can't be blank"));
+        assert_eq!(errors[1], PropertyError::base("Parent is archived"));
+    }
+
     #[test]
     fn test_map_success() {
         let result = ServiceResult::success(42);
@@ -0,0 +1,179 @@
+//! Cleanup Revoked Access Job
+//!
+//! Mirrors: app/services/members/cleanup_service.rb
+//!
+//! Deleting a membership, taking `view_work_packages` away from its roles or
+//! making a project private leaves users watching and notified about work
+//! packages they can no longer see. This job removes those watchers and
+//! notifications, batch by batch over the work packages of the project.
+//!
+//! Access is checked when the job runs, so running it again, or after the
+//! user got access back, removes nothing that is still visible. Removed
+//! watchers and notifications are not restored when a user regains access;
+//! they have to watch the work packages again.
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+use op_db::{RepositoryResult, RevokedAccessRepository};
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use op_notifications::Job;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::projects::JOB_USER_METADATA_KEY;
+
+/// Job type of revoked access cleanup jobs
+pub const CLEANUP_REVOKED_ACCESS_JOB: &str = "Members::CleanupRevokedAccessJob";
+
+/// Work packages visited per batch
+pub const CLEANUP_BATCH_SIZE: i64 = 1000;
+
+/// Arguments of a [`CLEANUP_REVOKED_ACCESS_JOB`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupRevokedAccessArgs {
+    pub project_id: Id,
+    /// Users who may have lost access; `None` for everyone watching or
+    /// notified about work packages of the project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_ids: Option<Vec<Id>>,
+}
+
+impl CleanupRevokedAccessArgs {
+    /// Clean up after the users' access to the project changed
+    pub fn users(project_id: Id, user_ids: impl IntoIterator<Item = Id>) -> Self {
+        Self {
+            project_id,
+            user_ids: Some(user_ids.into_iter().collect()),
+        }
+    }
+
+    /// Clean up after the project became private
+    pub fn project(project_id: Id) -> Self {
+        Self {
+            project_id,
+            user_ids: None,
+        }
+    }
+
+    /// Build the job, recording the admin whose change revoked the access.
+    /// The cleanup is idempotent, so failed attempts are retried.
+    pub fn into_job(self, changed_by: Id) -> Job {
+        Job::new(CLEANUP_REVOKED_ACCESS_JOB, serde_json::json!(self))
+            .with_metadata(JOB_USER_METADATA_KEY, changed_by.to_string())
+    }
+}
+
+/// What a cleanup removed, recorded as the job result
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokedAccessCleanup {
+    /// Users who cannot view the work packages of the project
+    pub users: Vec<Id>,
+    pub watchers_removed: u64,
+    pub notifications_removed: u64,
+}
+
+/// Remove the watchers and notifications of the users who cannot view the
+/// work packages of the project
+pub async fn cleanup_revoked_access(
+    repository: &RevokedAccessRepository,
+    args: &CleanupRevokedAccessArgs,
+    batch_size: i64,
+) -> RepositoryResult<RevokedAccessCleanup> {
+    let candidates = match &args.user_ids {
+        Some(ids) => ids.clone(),
+        None => repository.subscribed_users(args.project_id).await?,
+    };
+    let users = repository.users_without_access(args.project_id, &candidates).await?;
+    let mut cleanup = RevokedAccessCleanup {
+        users,
+        ..Default::default()
+    };
+    if cleanup.users.is_empty() {
+        return Ok(cleanup);
+    }
+
+    let mut after_id = 0;
+    loop {
+        let batch = repository
+            .remove_batch(args.project_id, &cleanup.users, after_id, batch_size)
+            .await?;
+        cleanup.watchers_removed += batch.watchers_removed;
+        cleanup.notifications_removed += batch.notifications_removed;
+        match batch.last_work_package_id {
+            Some(last_id) => after_id = last_id,
+            None => return Ok(cleanup),
+        }
+    }
+}
+
+/// Job handler cleaning up after revoked access in the background
+pub struct CleanupRevokedAccessJob {
+    pool: PgPool,
+}
+
+impl CleanupRevokedAccessJob {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl JobHandler for CleanupRevokedAccessJob {
+    async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+        self.perform(args).await.map(|_| ())
+    }
+
+    async fn perform(&self, args: serde_json::Value) -> JobResult<Option<serde_json::Value>> {
+        let args: CleanupRevokedAccessArgs =
+            serde_json::from_value(args).map_err(|e| JobError::SerializationError(e.to_string()))?;
+
+        let repository = RevokedAccessRepository::new(self.pool.clone());
+        let cleanup = cleanup_revoked_access(&repository, &args, CLEANUP_BATCH_SIZE)
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))?;
+
+        tracing::info!(
+            project_id = args.project_id,
+            users = cleanup.users.len(),
+            watchers_removed = cleanup.watchers_removed,
+            notifications_removed = cleanup.notifications_removed,
+            "Cleaned up after revoked access"
+        );
+
+        serde_json::to_value(cleanup)
+            .map(Some)
+            .map_err(|e| JobError::SerializationError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_args_round_trip() {
+        let job = CleanupRevokedAccessArgs::users(10, [5]).into_job(1);
+        assert_eq!(job.job_type, CLEANUP_REVOKED_ACCESS_JOB);
+        assert_eq!(job.metadata.get(JOB_USER_METADATA_KEY).map(String::as_str), Some("1"));
+        assert!(job.can_retry());
+        assert_eq!(job.args, serde_json::json!({ "project_id": 10, "user_ids": [5] }));
+
+        let job = CleanupRevokedAccessArgs::project(10).into_job(1);
+        let parsed: CleanupRevokedAccessArgs = serde_json::from_value(job.args).unwrap();
+        assert_eq!(parsed, CleanupRevokedAccessArgs::project(10));
+    }
+
+    #[test]
+    fn test_cleanup_serializes_counts() {
+        let cleanup = RevokedAccessCleanup {
+            users: vec![5],
+            watchers_removed: 3,
+            notifications_removed: 7,
+        };
+        assert_eq!(
+            serde_json::to_value(cleanup).unwrap(),
+            serde_json::json!({ "users": [5], "watchersRemoved": 3, "notificationsRemoved": 7 })
+        );
+    }
+}
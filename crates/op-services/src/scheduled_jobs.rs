@@ -0,0 +1,240 @@
+//! Recurring jobs of the instance
+//!
+//! The schedules of the built-in recurring jobs, the database-backed store
+//! the scheduler keeps its last run markers in, the job removing uploads
+//! that were never attached to a container, the job recounting the work
+//! package summaries, and the job emptying the work package trash.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use op_attachments::{AttachmentService, AttachmentStore, Storage};
+use op_db::{ScheduledJobRepository, SummaryRepository, WorkPackageRepository};
+use op_notifications::email::DIGEST_JOB;
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use op_notifications::notification::DATE_ALERTS_JOB;
+use op_notifications::{CronSchedule, MisfirePolicy, NotificationStore, ScheduleStore, ScheduledJob};
+use sqlx::PgPool;
+
+use crate::ldap_groups::LDAP_GROUP_SYNC_JOB;
+use crate::query_subscriptions::QUERY_SUBSCRIPTION_JOB;
+use crate::work_packages::PurgeWorkPackagesService;
+
+/// Job type removing attachments left without a container
+pub const CLEANUP_ORPHAN_ATTACHMENTS_JOB: &str = "Attachments::CleanupUncontaineredJob";
+
+/// Job type recounting the work package summaries
+pub const REBUILD_WORK_PACKAGE_SUMMARIES_JOB: &str = "WorkPackages::RebuildSummariesJob";
+
+/// Job type deleting work packages whose time in the trash is up
+pub const PURGE_WORK_PACKAGE_TRASH_JOB: &str = "WorkPackages::PurgeTrashJob";
+
+/// Schedules of the built-in recurring jobs
+///
+/// Digests and date alerts go out at times the recipients choose, so the
+/// jobs look for due recipients every quarter of an hour. A missed orphan
+/// cleanup, LDAP group synchronization, summary rebuild or trash purge is
+/// left to the next one.
+pub fn default_schedules() -> Vec<ScheduledJob> {
+    let cron = |expression: &str| CronSchedule::parse(expression).expect("built-in schedules are valid");
+
+    vec![
+        ScheduledJob::new(DIGEST_JOB, cron("*/15 * * * *")),
+        ScheduledJob::new(DATE_ALERTS_JOB, cron("*/15 * * * *")),
+        ScheduledJob::new(QUERY_SUBSCRIPTION_JOB, cron("0 * * * *")),
+        ScheduledJob::new(CLEANUP_ORPHAN_ATTACHMENTS_JOB, cron("30 3 * * *")).with_misfire(MisfirePolicy::Skip),
+        ScheduledJob::new(LDAP_GROUP_SYNC_JOB, cron("0 */4 * * *")).with_misfire(MisfirePolicy::Skip),
+        ScheduledJob::new(REBUILD_WORK_PACKAGE_SUMMARIES_JOB, cron("45 2 * * *")).with_misfire(MisfirePolicy::Skip),
+        ScheduledJob::new(PURGE_WORK_PACKAGE_TRASH_JOB, cron("15 3 * * *")).with_misfire(MisfirePolicy::Skip),
+    ]
+}
+
+/// Schedule store keeping the markers in the `scheduled_jobs` table
+pub struct PgScheduleStore {
+    jobs: ScheduledJobRepository,
+}
+
+impl PgScheduleStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            jobs: ScheduledJobRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl ScheduleStore for PgScheduleStore {
+    async fn last_run(&self, name: &str) -> JobResult<Option<DateTime<Utc>>> {
+        self.jobs
+            .last_run_at(name)
+            .await
+            .map_err(|e| JobError::QueueError(e.to_string()))
+    }
+
+    async fn advance(&self, name: &str, from: Option<DateTime<Utc>>, to: DateTime<Utc>) -> JobResult<bool> {
+        self.jobs
+            .advance(name, from, to)
+            .await
+            .map_err(|e| JobError::QueueError(e.to_string()))
+    }
+}
+
+/// Removes attachments older than the configured age that were never
+/// attached to a container
+pub struct CleanupOrphanAttachmentsJob<St: AttachmentStore, S: Storage> {
+    attachments: Arc<AttachmentService<St, S>>,
+}
+
+impl<St: AttachmentStore, S: Storage> CleanupOrphanAttachmentsJob<St, S> {
+    pub fn new(attachments: Arc<AttachmentService<St, S>>) -> Self {
+        Self { attachments }
+    }
+}
+
+#[async_trait]
+impl<St: AttachmentStore + 'static, S: Storage + 'static> JobHandler for CleanupOrphanAttachmentsJob<St, S> {
+    async fn handle(&self, _args: serde_json::Value) -> JobResult<()> {
+        self.attachments
+            .cleanup_orphans()
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Recounts the work package summaries, correcting counts that drifted
+/// from the work packages
+pub struct RebuildWorkPackageSummariesJob {
+    summaries: SummaryRepository,
+}
+
+impl RebuildWorkPackageSummariesJob {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            summaries: SummaryRepository::new(pool),
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for RebuildWorkPackageSummariesJob {
+    async fn handle(&self, _args: serde_json::Value) -> JobResult<()> {
+        let drifted = self
+            .summaries
+            .rebuild()
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))?;
+        if drifted > 0 {
+            tracing::warn!(groups = drifted, "Corrected drifted work package summaries");
+        }
+        Ok(())
+    }
+}
+
+/// Deletes work packages that have been in the trash for longer than the
+/// retention, with everything depending on them
+pub struct PurgeWorkPackageTrashJob<St: AttachmentStore, S: Storage> {
+    work_packages: WorkPackageRepository,
+    attachments: Arc<AttachmentService<St, S>>,
+    retention: Duration,
+    notifications: Option<Arc<dyn NotificationStore>>,
+}
+
+impl<St: AttachmentStore, S: Storage> PurgeWorkPackageTrashJob<St, S> {
+    pub fn new(pool: PgPool, attachments: Arc<AttachmentService<St, S>>, retention_days: u32) -> Self {
+        Self {
+            work_packages: WorkPackageRepository::new(pool),
+            attachments,
+            retention: Duration::days(retention_days.into()),
+            notifications: None,
+        }
+    }
+
+    /// Notify the former watchers of the deleted work packages
+    pub fn with_notifications(mut self, notifications: Arc<dyn NotificationStore>) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+}
+
+#[async_trait]
+impl<St: AttachmentStore + 'static, S: Storage + 'static> JobHandler for PurgeWorkPackageTrashJob<St, S> {
+    async fn handle(&self, _args: serde_json::Value) -> JobResult<()> {
+        let deletion = self
+            .work_packages
+            .begin_deletion()
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))?;
+        let mut service = PurgeWorkPackagesService::new();
+        if let Some(notifications) = &self.notifications {
+            service = service.with_notifications(notifications.as_ref());
+        }
+
+        let result = service
+            .call(Utc::now() - self.retention, deletion, &self.attachments)
+            .await;
+        if result.is_failure() {
+            return Err(JobError::Failed(result.errors().full_messages().join(", ")));
+        }
+        let purged = result.unwrap().work_package_ids.len();
+        if purged > 0 {
+            tracing::info!(work_packages = purged, "Purged work packages from the trash");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_attachments::{AttachmentConfig, CreateAttachmentParams, ContainerType, MemoryAttachmentStore, MemoryStorage};
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    #[test]
+    fn test_default_schedules() {
+        let schedules = default_schedules();
+        let names: HashSet<&str> = schedules.iter().map(|job| job.name.as_str()).collect();
+
+        assert_eq!(names.len(), schedules.len());
+        assert!(names.contains(CLEANUP_ORPHAN_ATTACHMENTS_JOB));
+        assert!(names.contains(DIGEST_JOB));
+        assert!(names.contains(DATE_ALERTS_JOB));
+        assert!(names.contains(QUERY_SUBSCRIPTION_JOB));
+        assert!(names.contains(LDAP_GROUP_SYNC_JOB));
+        assert!(names.contains(REBUILD_WORK_PACKAGE_SUMMARIES_JOB));
+        assert!(names.contains(PURGE_WORK_PACKAGE_TRASH_JOB));
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_removes_only_orphans() {
+        let config = AttachmentConfig {
+            cleanup_orphans_after: Duration::ZERO,
+            ..Default::default()
+        };
+        let store = Arc::new(MemoryAttachmentStore::new());
+        let attachments = Arc::new(AttachmentService::new(store, Arc::new(MemoryStorage::new()), config));
+
+        let orphan = attachments
+            .create(CreateAttachmentParams::new("orphan.txt"), "orphan".into(), 1)
+            .await
+            .unwrap();
+        let attached = attachments
+            .create(
+                CreateAttachmentParams::new("notes.txt").container(ContainerType::WorkPackage, 1),
+                "notes".into(),
+                1,
+            )
+            .await
+            .unwrap();
+
+        CleanupOrphanAttachmentsJob::new(attachments.clone())
+            .handle(serde_json::json!({}))
+            .await
+            .unwrap();
+
+        assert!(attachments.get(orphan.attachment.id.unwrap()).await.unwrap().is_none());
+        assert!(attachments.get(attached.attachment.id.unwrap()).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,162 @@
+//! Basic data every instance needs
+//!
+//! Mirrors: app/seeders/basic_data/*.rb
+//!
+//! OpenProject's default colors, statuses, types, priorities, time entry
+//! activities, document categories and project roles. Records are looked up by name, so existing
+//! ones are kept as they are.
+
+use op_contracts::documents::permissions as documents;
+use op_contracts::forums::permissions as forums;
+use op_contracts::work_packages::permissions as work_packages;
+use op_models::permissions::*;
+
+/// Colors by name and hex code
+pub const COLORS: &[(&str, &str)] = &[
+    ("Blue (dark)", "#175A8E"),
+    ("Blue", "#1A67A3"),
+    ("Blue (light)", "#00B0F0"),
+    ("Green (light)", "#35C53F"),
+    ("Green (dark)", "#339933"),
+    ("Yellow", "#FFFF00"),
+    ("Orange", "#FFCC00"),
+    ("Red", "#FF3300"),
+    ("Magenta", "#E20074"),
+    ("White", "#FFFFFF"),
+    ("Grey (light)", "#F8F8F8"),
+    ("Grey", "#EAEAEA"),
+    ("Grey (dark)", "#878787"),
+    ("Black", "#000000"),
+];
+
+pub struct StatusSeed {
+    pub name: &'static str,
+    pub color: &'static str,
+    pub is_closed: bool,
+    pub is_default: bool,
+    pub default_done_ratio: i32,
+}
+
+const fn status(name: &'static str, color: &'static str, default_done_ratio: i32) -> StatusSeed {
+    StatusSeed {
+        name,
+        color,
+        is_closed: false,
+        is_default: false,
+        default_done_ratio,
+    }
+}
+
+pub const STATUSES: &[StatusSeed] = &[
+    StatusSeed {
+        is_default: true,
+        ..status("New", "Blue (light)", 0)
+    },
+    status("In specification", "Yellow", 10),
+    status("Specified", "Green (light)", 20),
+    status("Confirmed", "Green (dark)", 20),
+    status("To be scheduled", "Orange", 20),
+    status("Scheduled", "Blue (dark)", 30),
+    status("In progress", "Blue", 50),
+    status("Developed", "Green (dark)", 80),
+    status("In testing", "Orange", 80),
+    status("Tested", "Green (light)", 90),
+    status("Test failed", "Red", 70),
+    StatusSeed {
+        is_closed: true,
+        ..status("Closed", "Grey (dark)", 100)
+    },
+    status("On hold", "Magenta", 0),
+    StatusSeed {
+        is_closed: true,
+        ..status("Rejected", "Black", 0)
+    },
+];
+
+pub struct TypeSeed {
+    pub name: &'static str,
+    pub color: &'static str,
+    pub is_default: bool,
+    pub is_in_roadmap: bool,
+    pub is_milestone: bool,
+}
+
+pub const TYPES: &[TypeSeed] = &[
+    TypeSeed { name: "Task", color: "Blue", is_default: true, is_in_roadmap: true, is_milestone: false },
+    TypeSeed { name: "Milestone", color: "Green (light)", is_default: true, is_in_roadmap: false, is_milestone: true },
+    TypeSeed { name: "Phase", color: "Blue (dark)", is_default: true, is_in_roadmap: false, is_milestone: false },
+    TypeSeed { name: "Feature", color: "Blue (light)", is_default: false, is_in_roadmap: true, is_milestone: false },
+    TypeSeed { name: "Epic", color: "Magenta", is_default: false, is_in_roadmap: true, is_milestone: false },
+    TypeSeed { name: "User story", color: "Grey (dark)", is_default: false, is_in_roadmap: true, is_milestone: false },
+    TypeSeed { name: "Bug", color: "Red", is_default: false, is_in_roadmap: true, is_milestone: false },
+];
+
+/// Priorities by name and color; "Normal" is the default
+pub const PRIORITIES: &[(&str, &str)] = &[
+    ("Low", "Grey"),
+    ("Normal", "Blue (light)"),
+    ("High", "Orange"),
+    ("Immediate", "Red"),
+];
+
+pub const DEFAULT_PRIORITY: &str = "Normal";
+
+/// Time entry activities; "Development" is the default
+pub const ACTIVITIES: &[&str] = &["Management", "Specification", "Development", "Testing", "Support", "Other"];
+
+pub const DEFAULT_ACTIVITY: &str = "Development";
+
+/// Document categories; "Documentation" is the default
+pub const DOCUMENT_CATEGORIES: &[&str] = &["Documentation", "Specification", "Other"];
+
+pub const DEFAULT_DOCUMENT_CATEGORY: &str = "Documentation";
+
+/// Project roles and their permissions
+pub const ROLES: &[(&str, &[&str])] = &[
+    (PROJECT_ADMIN, &[
+        VIEW_PROJECT, EDIT_PROJECT, SELECT_PROJECT_MODULES, MANAGE_MEMBERS, MANAGE_VERSIONS,
+        MANAGE_CATEGORIES, MANAGE_PROJECT_ACTIVITIES,
+        VIEW_WORK_PACKAGES, ADD_WORK_PACKAGES, EDIT_WORK_PACKAGES, DELETE_WORK_PACKAGES,
+        MOVE_WORK_PACKAGES, COPY_WORK_PACKAGES, MANAGE_WORK_PACKAGE_RELATIONS,
+        work_packages::MANAGE_SUBTASKS, work_packages::ADD_WORK_PACKAGE_NOTES, ASSIGN_VERSIONS,
+        LOG_TIME, VIEW_TIME_ENTRIES,
+        forums::VIEW_MESSAGES, forums::ADD_MESSAGES, forums::EDIT_MESSAGES, forums::DELETE_MESSAGES,
+        forums::MANAGE_FORUMS,
+        VIEW_FILE_LINKS, MANAGE_FILE_LINKS,
+        documents::VIEW_DOCUMENTS, documents::MANAGE_DOCUMENTS,
+    ]),
+    (MEMBER, &[
+        VIEW_PROJECT, VIEW_WORK_PACKAGES, ADD_WORK_PACKAGES, EDIT_WORK_PACKAGES,
+        COPY_WORK_PACKAGES, MANAGE_WORK_PACKAGE_RELATIONS, work_packages::MANAGE_SUBTASKS,
+        work_packages::ADD_WORK_PACKAGE_NOTES, ASSIGN_VERSIONS, LOG_TIME, VIEW_TIME_ENTRIES,
+        forums::VIEW_MESSAGES, forums::ADD_MESSAGES, forums::EDIT_OWN_MESSAGES,
+        VIEW_FILE_LINKS, documents::VIEW_DOCUMENTS,
+    ]),
+    (READER, &[
+        VIEW_PROJECT, VIEW_WORK_PACKAGES, work_packages::ADD_WORK_PACKAGE_NOTES,
+        VIEW_OWN_TIME_ENTRIES, forums::VIEW_MESSAGES, VIEW_FILE_LINKS, documents::VIEW_DOCUMENTS,
+    ]),
+];
+
+const PROJECT_ADMIN: &str = op_models::Role::PROJECT_ADMIN;
+const MEMBER: &str = op_models::Role::MEMBER;
+const READER: &str = op_models::Role::READER;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_referenced_colors_exist() {
+        let colors: Vec<&str> = COLORS.iter().map(|(name, _)| *name).collect();
+        let referenced = STATUSES
+            .iter()
+            .map(|s| s.color)
+            .chain(TYPES.iter().map(|t| t.color))
+            .chain(PRIORITIES.iter().map(|(_, color)| *color));
+        for color in referenced {
+            assert!(colors.contains(&color), "unknown color {}", color);
+        }
+        assert_eq!(STATUSES.iter().filter(|s| s.is_default).count(), 1);
+    }
+}
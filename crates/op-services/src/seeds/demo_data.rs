@@ -0,0 +1,381 @@
+//! Demo data of evaluation and demo instances
+//!
+//! Mirrors: app/seeders/demo_data/*.rb
+//!
+//! The demo projects are described here; [`DemoPlan::generate`] rolls the
+//! attributes the descriptions leave open, like statuses, assignees, dates
+//! and logged time, with a [`SeedRng`]. The same seed and start date always
+//! give the same plan.
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use super::rng::SeedRng;
+
+/// Login of the demo administrator; `admin` itself is a reserved login
+pub const ADMIN_LOGIN: &str = "demo_admin";
+
+pub struct DemoUser {
+    pub login: &'static str,
+    pub firstname: &'static str,
+    pub lastname: &'static str,
+    pub admin: bool,
+}
+
+pub const USERS: &[DemoUser] = &[
+    DemoUser { login: ADMIN_LOGIN, firstname: "OpenProject", lastname: "Admin", admin: true },
+    DemoUser { login: "alice", firstname: "Alice", lastname: "Hansen", admin: false },
+    DemoUser { login: "bob", firstname: "Bob", lastname: "Meyer", admin: false },
+    DemoUser { login: "carla", firstname: "Carla", lastname: "Rossi", admin: false },
+    DemoUser { login: "devi", firstname: "Devi", lastname: "Patel", admin: false },
+    DemoUser { login: "emil", firstname: "Emil", lastname: "Novak", admin: false },
+];
+
+pub struct DemoProject {
+    pub identifier: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub public: bool,
+    pub parent: Option<&'static str>,
+    /// Logins and role names
+    pub members: &'static [(&'static str, &'static str)],
+    /// Names and due dates, in weeks after the start date
+    pub versions: &'static [(&'static str, i64)],
+    pub work_packages: &'static [WorkPackageSeed],
+}
+
+/// Work package of a demo project; parents and versions refer to earlier
+/// entries by index
+pub struct WorkPackageSeed {
+    pub subject: &'static str,
+    pub type_name: &'static str,
+    pub parent: Option<usize>,
+    pub version: Option<usize>,
+    pub description: Option<&'static str>,
+}
+
+const fn wp(subject: &'static str, type_name: &'static str, parent: Option<usize>) -> WorkPackageSeed {
+    WorkPackageSeed {
+        subject,
+        type_name,
+        parent,
+        version: None,
+        description: None,
+    }
+}
+
+const fn sprint(subject: &'static str, type_name: &'static str, parent: usize, version: usize) -> WorkPackageSeed {
+    WorkPackageSeed {
+        subject,
+        type_name,
+        parent: Some(parent),
+        version: Some(version),
+        description: None,
+    }
+}
+
+pub const PROJECTS: &[DemoProject] = &[
+    DemoProject {
+        identifier: "demo-project",
+        name: "Demo project",
+        description: "This is a short summary of the goals of this demo project.",
+        public: true,
+        parent: None,
+        members: &[
+            (ADMIN_LOGIN, "Project admin"),
+            ("alice", "Project admin"),
+            ("bob", "Member"),
+            ("carla", "Member"),
+            ("devi", "Reader"),
+        ],
+        versions: &[("Release 1.0", 8)],
+        work_packages: &[
+            WorkPackageSeed {
+                description: Some("Kick-off with the whole team to agree on scope and timeline."),
+                ..wp("Start of project", "Milestone", None)
+            },
+            wp("Project planning", "Phase", None),
+            wp("Gather requirements", "Task", Some(1)),
+            wp("Create project plan", "Task", Some(1)),
+            wp("Set up the team", "Task", Some(1)),
+            wp("Planning completed", "Milestone", None),
+            wp("Development", "Phase", None),
+            WorkPackageSeed {
+                version: Some(0),
+                description: Some("Sign-up, login and password reset for the new portal."),
+                ..wp("User accounts", "Feature", Some(6))
+            },
+            WorkPackageSeed { version: Some(0), ..wp("Dashboard", "Feature", Some(6)) },
+            WorkPackageSeed { version: Some(0), ..wp("Search", "Feature", Some(6)) },
+            WorkPackageSeed { version: Some(0), ..wp("Login fails for names with umlauts", "Bug", Some(6)) },
+            wp("Testing", "Phase", None),
+            wp("Write test plan", "Task", Some(11)),
+            wp("Run acceptance tests", "Task", Some(11)),
+            wp("Go live", "Milestone", None),
+            wp("Prepare the launch event", "Task", None),
+        ],
+    },
+    DemoProject {
+        identifier: "scrum-project",
+        name: "Scrum project",
+        description: "A project of the demo project's team, planned in sprints.",
+        public: false,
+        parent: Some("demo-project"),
+        members: &[
+            (ADMIN_LOGIN, "Project admin"),
+            ("alice", "Member"),
+            ("bob", "Project admin"),
+            ("devi", "Member"),
+            ("emil", "Member"),
+        ],
+        versions: &[("Sprint 1", 2), ("Sprint 2", 4), ("Bug backlog", 12)],
+        work_packages: &[
+            WorkPackageSeed {
+                description: Some("Everything customers need to order from the shop."),
+                ..wp("Online shop", "Epic", None)
+            },
+            sprint("As a customer I can browse products", "User story", 0, 0),
+            sprint("As a customer I can put products into a cart", "User story", 0, 0),
+            sprint("As a customer I can pay by credit card", "User story", 0, 1),
+            sprint("As a customer I get an order confirmation", "User story", 0, 1),
+            wp("Customer care", "Epic", None),
+            sprint("As an agent I can look up orders", "User story", 5, 1),
+            sprint("As an agent I can refund an order", "User story", 5, 1),
+            sprint("Design the product page", "Task", 1, 0),
+            sprint("Implement the cart service", "Task", 2, 0),
+            sprint("Connect the payment provider", "Task", 3, 1),
+            WorkPackageSeed { version: Some(2), ..wp("Cart is emptied after login", "Bug", None) },
+            WorkPackageSeed { version: Some(2), ..wp("Prices are rounded wrongly", "Bug", None) },
+            WorkPackageSeed { version: Some(2), ..wp("Confirmation mail is sent twice", "Bug", None) },
+        ],
+    },
+];
+
+const COMMENTS: &[&str] = &[
+    "I had a first look, this should be straightforward.",
+    "Can we discuss this in the next meeting?",
+    "Waiting for feedback from the customer.",
+    "Done on my side, please review.",
+    "I added some notes to the description.",
+    "This depends on the other work package being finished first.",
+];
+
+const TIME_ENTRY_COMMENTS: &[&str] = &["Analysis", "Implementation", "Review", "Meeting", "Documentation"];
+
+const OPEN_STATUSES: &[&str] = &["New", "In specification", "Confirmed", "In progress", "On hold"];
+
+const DONE_STATUSES: &[&str] = &["Closed", "Tested"];
+
+const PRIORITIES: &[&str] = &["Low", "Normal", "Normal", "Normal", "High", "Immediate"];
+
+/// Everything the demo seeder creates
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemoPlan {
+    pub work_packages: Vec<PlannedWorkPackage>,
+    pub relations: Vec<PlannedRelation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedWorkPackage {
+    pub project: &'static str,
+    pub subject: &'static str,
+    pub description: Option<&'static str>,
+    pub type_name: &'static str,
+    /// Index of the parent in the plan
+    pub parent: Option<usize>,
+    pub version: Option<&'static str>,
+    pub status: &'static str,
+    pub priority: &'static str,
+    pub author: &'static str,
+    pub assignee: Option<&'static str>,
+    pub start_date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub estimated_hours: Option<f64>,
+    pub done_ratio: i32,
+    /// Authors and texts
+    pub comments: Vec<(&'static str, &'static str)>,
+    pub time_entries: Vec<PlannedTimeEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedTimeEntry {
+    pub user: &'static str,
+    pub activity: &'static str,
+    pub hours: f64,
+    pub spent_on: NaiveDate,
+    pub comment: &'static str,
+}
+
+/// Relation between work packages, by their index in the plan
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedRelation {
+    pub from: usize,
+    pub to: usize,
+    pub relation_type: &'static str,
+}
+
+impl DemoPlan {
+    /// Plan of the demo projects' work packages, starting at `start_date`
+    pub fn generate(seed: u64, start_date: NaiveDate) -> Self {
+        let mut rng = SeedRng::new(seed);
+        let mut work_packages: Vec<PlannedWorkPackage> = Vec::new();
+        let mut relations = Vec::new();
+
+        for project in PROJECTS {
+            let offset = work_packages.len();
+            let assignees: Vec<&'static str> = project
+                .members
+                .iter()
+                .filter(|(_, role)| *role != "Reader")
+                .map(|(login, _)| *login)
+                .collect();
+
+            // Work packages follow one another; parents grow to span their children
+            let mut next_start = start_date;
+            for seed in project.work_packages {
+                let start = working_day(match seed.parent {
+                    Some(parent) => work_packages[offset + parent].start_date + Duration::days(rng.between(0, 3)),
+                    None => next_start,
+                });
+                let (due, estimated_hours) = if seed.type_name == "Milestone" {
+                    (start, None)
+                } else {
+                    let days = rng.between(2, 10);
+                    (working_day(start + Duration::days(days)), Some(rng.between(1, 8) as f64 * 2.0))
+                };
+                next_start = next_start.max(due + Duration::days(1));
+                let mut ancestor = seed.parent.map(|parent| offset + parent);
+                while let Some(index) = ancestor {
+                    let parent = &mut work_packages[index];
+                    parent.due_date = parent.due_date.max(due);
+                    ancestor = parent.parent;
+                }
+
+                let done = due < start_date + Duration::days(14) && rng.chance(70);
+                let status = if done { rng.pick(DONE_STATUSES) } else { rng.pick(OPEN_STATUSES) };
+                let done_ratio = if done { 100 } else { rng.between(0, 8) as i32 * 10 };
+                let author = rng.pick(&assignees);
+                let assignee = if rng.chance(85) { Some(rng.pick(&assignees)) } else { None };
+
+                let comments = (0..rng.between(0, 2))
+                    .map(|_| (rng.pick(&assignees), rng.pick(COMMENTS)))
+                    .collect();
+
+                let mut time_entries = Vec::new();
+                if let (Some(user), Some(_)) = (assignee, estimated_hours) {
+                    for _ in 0..rng.between(0, 3) {
+                        time_entries.push(PlannedTimeEntry {
+                            user,
+                            activity: rng.pick(super::basic_data::ACTIVITIES),
+                            hours: rng.between(1, 8) as f64 / 2.0,
+                            spent_on: start + Duration::days(rng.between(0, (due - start).num_days())),
+                            comment: rng.pick(TIME_ENTRY_COMMENTS),
+                        });
+                    }
+                }
+
+                work_packages.push(PlannedWorkPackage {
+                    project: project.identifier,
+                    subject: seed.subject,
+                    description: seed.description,
+                    type_name: seed.type_name,
+                    parent: seed.parent.map(|parent| offset + parent),
+                    version: seed.version.map(|version| project.versions[version].0),
+                    status,
+                    priority: rng.pick(PRIORITIES),
+                    author,
+                    assignee,
+                    start_date: start,
+                    due_date: due,
+                    estimated_hours,
+                    done_ratio,
+                    comments,
+                    time_entries,
+                });
+            }
+
+            // Top-level work packages follow their predecessor
+            let top_level: Vec<usize> = (offset..work_packages.len())
+                .filter(|&i| work_packages[i].parent.is_none())
+                .collect();
+            for pair in top_level.windows(2) {
+                relations.push(PlannedRelation { from: pair[1], to: pair[0], relation_type: "follows" });
+            }
+
+            // And a few related work packages
+            let count = (work_packages.len() - offset) as u64;
+            for _ in 0..3 {
+                let from = offset + rng.below(count) as usize;
+                let to = offset + rng.below(count) as usize;
+                let planned = |a: usize, b: usize| relations.iter().any(|r: &PlannedRelation| {
+                    (r.from == a && r.to == b) || (r.from == b && r.to == a)
+                });
+                let related = work_packages[from].parent == Some(to) || work_packages[to].parent == Some(from);
+                if from != to && !related && !planned(from, to) {
+                    relations.push(PlannedRelation { from, to, relation_type: "relates" });
+                }
+            }
+        }
+
+        Self { work_packages, relations }
+    }
+}
+
+/// The date, or the Monday after it if it falls on a weekend; work packages
+/// must not start or end on non-working days
+fn working_day(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date + Duration::days(2),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 8).unwrap()
+    }
+
+    #[test]
+    fn test_plans_are_reproducible() {
+        let plan = DemoPlan::generate(1, start());
+        assert_eq!(plan, DemoPlan::generate(1, start()));
+        assert_ne!(plan, DemoPlan::generate(2, start()));
+
+        let later = DemoPlan::generate(1, start() + Duration::days(7));
+        assert_eq!(later.work_packages[0].start_date, start() + Duration::days(7));
+    }
+
+    #[test]
+    fn test_plan_is_consistent() {
+        let plan = DemoPlan::generate(1, start());
+        assert_eq!(plan.work_packages.len(), 30);
+
+        for (i, wp) in plan.work_packages.iter().enumerate() {
+            assert!(wp.due_date >= wp.start_date, "{}", wp.subject);
+            for date in [wp.start_date, wp.due_date] {
+                assert!(!matches!(date.weekday(), Weekday::Sat | Weekday::Sun), "{}", wp.subject);
+            }
+            if let Some(parent) = wp.parent {
+                assert!(parent < i, "parents come first");
+                assert_eq!(plan.work_packages[parent].project, wp.project);
+            }
+            for entry in &wp.time_entries {
+                assert!(entry.spent_on >= wp.start_date && entry.spent_on <= wp.due_date);
+            }
+        }
+        assert_eq!(plan.work_packages[0].type_name, "Milestone");
+        assert_eq!(plan.work_packages[0].estimated_hours, None);
+
+        for relation in &plan.relations {
+            let (from, to) = (&plan.work_packages[relation.from], &plan.work_packages[relation.to]);
+            assert_eq!(from.project, to.project);
+            assert_ne!(relation.from, relation.to);
+        }
+        assert!(plan.relations.iter().any(|r| r.relation_type == "follows"));
+        assert!(plan.work_packages.iter().any(|wp| !wp.comments.is_empty()));
+        assert!(plan.work_packages.iter().any(|wp| !wp.time_entries.is_empty()));
+    }
+}
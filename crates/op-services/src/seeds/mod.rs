@@ -0,0 +1,762 @@
+//! Seed data for development and demo instances
+//!
+//! Mirrors: app/seeders/root_seeder.rb
+//!
+//! [`DemoSeeder`] fills a database with OpenProject's basic data and two
+//! demo projects: users, memberships, versions, work packages with comments,
+//! logged time and relations, a saved query and a board. Everything is
+//! generated from a fixed seed, so two instances seeded alike look alike.
+//!
+//! Seeding twice creates nothing new: records are looked up by name, login,
+//! identifier or subject first. Users, projects and work packages pass their
+//! create services' validation before being saved.
+
+pub mod basic_data;
+pub mod demo_data;
+pub mod rng;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{Duration, NaiveDate};
+use op_contracts::base::UserContext;
+use op_core::traits::Id;
+use op_db::{
+    journable_type, ActivityRepository, BoardRepository, ColorRepository, CreateActivityDto, CreateBoardDto,
+    CreateMemberDto, CreatePriorityDto, CreateProjectDto, CreateQueryDto, CreateRelationDto, CreateRoleDto,
+    CreateStatusDto, CreateTimeEntryDto, CreateTypeDto, CreateUserDto, CreateVersionDto, CreateWorkPackageDto,
+    DocumentRepository, JournalRepository, MemberRepository, Pagination, PriorityRepository, ProjectRepository,
+    QueryRepository, RelationRepository, Repository, RepositoryError, RoleRepository, StatusRepository,
+    TimeEntryRepository, TypeRepository, UserRepository, VersionRepository, WorkPackageRepository,
+};
+use op_queries::builder::QueryBuilder;
+use sqlx::PgPool;
+
+use crate::projects::{CreateProjectService, ProjectParams};
+use crate::result::ServiceResult;
+use crate::users::{CreateUserService, UserParams};
+use crate::work_packages::{CreateWorkPackageService, WorkPackageParams};
+use basic_data::{
+    ACTIVITIES, COLORS, DEFAULT_ACTIVITY, DEFAULT_DOCUMENT_CATEGORY, DEFAULT_PRIORITY, DOCUMENT_CATEGORIES, PRIORITIES,
+    ROLES, STATUSES, TYPES,
+};
+use demo_data::{DemoPlan, ADMIN_LOGIN, PROJECTS, USERS};
+
+/// Seed of the demo data unless another is given
+pub const DEFAULT_SEED: u64 = 1;
+
+/// Project holding the saved query, and the one holding the board
+const QUERY_PROJECT: &str = "demo-project";
+const BOARD_PROJECT: &str = "scrum-project";
+const QUERY_NAME: &str = "Project plan";
+const BOARD_NAME: &str = "Kanban board";
+const BOARD_COLUMNS: &[&str] = &["New", "In progress", "Tested", "Closed"];
+
+/// Enough to see every record of a demo project at once
+const ALL: Pagination = Pagination { limit: 1000, offset: 0 };
+
+/// How the demo data is generated
+#[derive(Debug, Clone)]
+pub struct SeedOptions {
+    pub seed: u64,
+    /// First day of the demo projects
+    pub start_date: NaiveDate,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            seed: DEFAULT_SEED,
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 8).expect("valid date"),
+        }
+    }
+}
+
+/// Records created by a seeder run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub users: usize,
+    pub projects: usize,
+    pub versions: usize,
+    pub work_packages: usize,
+    pub comments: usize,
+    pub time_entries: usize,
+    pub relations: usize,
+    pub queries: usize,
+    pub boards: usize,
+}
+
+impl SeedReport {
+    /// Whether everything existed already
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} users, {} projects, {} versions, {} work packages, {} comments, {} time entries, \
+             {} relations, {} queries, {} boards",
+            self.users,
+            self.projects,
+            self.versions,
+            self.work_packages,
+            self.comments,
+            self.time_entries,
+            self.relations,
+            self.queries,
+            self.boards
+        )
+    }
+}
+
+/// Error aborting a seeder run
+#[derive(Debug)]
+pub enum SeedError {
+    Repository(RepositoryError),
+    /// A record failed its service's validation
+    Invalid { record: String, messages: Vec<String> },
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Repository(e) => write!(f, "{}", e),
+            Self::Invalid { record, messages } => write!(f, "{} is invalid: {}", record, messages.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for SeedError {}
+
+impl From<RepositoryError> for SeedError {
+    fn from(e: RepositoryError) -> Self {
+        Self::Repository(e)
+    }
+}
+
+pub type SeedResult<T> = Result<T, SeedError>;
+
+/// Ids of seeded records by name
+type Ids = HashMap<&'static str, Id>;
+
+/// Seeds the basic and demo data
+pub struct DemoSeeder {
+    pool: PgPool,
+    options: SeedOptions,
+}
+
+impl DemoSeeder {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            options: SeedOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: SeedOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Seed whatever is missing, reporting what was created
+    pub async fn call(&self) -> SeedResult<SeedReport> {
+        let mut report = SeedReport::default();
+
+        let colors = self.seed_colors().await?;
+        let statuses = self.seed_statuses(&colors).await?;
+        let types = self.seed_types(&colors).await?;
+        let priorities = self.seed_priorities(&colors).await?;
+        let activities = self.seed_activities().await?;
+        self.seed_document_categories().await?;
+        let roles = self.seed_roles().await?;
+
+        let users = self.seed_users(&mut report).await?;
+        let admin = users[ADMIN_LOGIN];
+        let projects = self.seed_projects(admin, &types, &users, &roles, &mut report).await?;
+
+        let plan = DemoPlan::generate(self.options.seed, self.options.start_date);
+        let refs = References {
+            statuses: &statuses,
+            types: &types,
+            priorities: &priorities,
+            activities: &activities,
+            users: &users,
+            projects: &projects,
+        };
+        let work_packages = self.seed_work_packages(&plan, &refs, &mut report).await?;
+        self.seed_relations(&plan, &work_packages, &mut report).await?;
+        self.seed_query(admin, projects.ids[QUERY_PROJECT], &mut report).await?;
+        self.seed_board(admin, projects.ids[BOARD_PROJECT], &statuses, &mut report).await?;
+
+        Ok(report)
+    }
+
+    async fn seed_colors(&self) -> SeedResult<Ids> {
+        let repo = ColorRepository::new(self.pool.clone());
+        let mut ids = Ids::new();
+        for (name, hexcode) in COLORS {
+            let color = match repo.find_by_name(name).await? {
+                Some(color) => color,
+                None => repo.create(name, hexcode).await?,
+            };
+            ids.insert(*name, color.id);
+        }
+        Ok(ids)
+    }
+
+    async fn seed_statuses(&self, colors: &Ids) -> SeedResult<Ids> {
+        let repo = StatusRepository::new(self.pool.clone());
+        let mut ids = Ids::new();
+        for (position, status) in (1..).zip(STATUSES) {
+            let row = match repo.find_by_name(status.name).await? {
+                Some(row) => row,
+                None => {
+                    repo.create(CreateStatusDto {
+                        name: status.name.into(),
+                        is_closed: status.is_closed,
+                        is_default: status.is_default,
+                        is_readonly: false,
+                        position: Some(position),
+                        default_done_ratio: status.default_done_ratio,
+                        color_id: colors.get(status.color).copied(),
+                    })
+                    .await?
+                }
+            };
+            ids.insert(status.name, row.id);
+        }
+        Ok(ids)
+    }
+
+    async fn seed_types(&self, colors: &Ids) -> SeedResult<Ids> {
+        let repo = TypeRepository::new(self.pool.clone());
+        let mut ids = Ids::new();
+        for (position, seed) in (1..).zip(TYPES) {
+            let row = match repo.find_by_name(seed.name).await? {
+                Some(row) => row,
+                None => {
+                    repo.create(CreateTypeDto {
+                        name: seed.name.into(),
+                        position: Some(position),
+                        is_default: seed.is_default,
+                        is_in_roadmap: seed.is_in_roadmap,
+                        is_milestone: seed.is_milestone,
+                        color_id: colors.get(seed.color).copied(),
+                        description: None,
+                    })
+                    .await?
+                }
+            };
+            ids.insert(seed.name, row.id);
+        }
+        Ok(ids)
+    }
+
+    async fn seed_priorities(&self, colors: &Ids) -> SeedResult<Ids> {
+        let repo = PriorityRepository::new(self.pool.clone());
+        let mut ids = Ids::new();
+        for (position, (name, color)) in (1..).zip(PRIORITIES) {
+            let row = match repo.find_by_name(name).await? {
+                Some(row) => row,
+                None => {
+                    repo.create(CreatePriorityDto {
+                        name: (*name).into(),
+                        position: Some(position),
+                        is_default: *name == DEFAULT_PRIORITY,
+                        active: true,
+                        color_id: colors.get(color).copied(),
+                        project_id: None,
+                    })
+                    .await?
+                }
+            };
+            ids.insert(*name, row.id);
+        }
+        Ok(ids)
+    }
+
+    async fn seed_activities(&self) -> SeedResult<Ids> {
+        let repo = ActivityRepository::new(self.pool.clone());
+        let existing = repo.find_shared().await?;
+        let mut ids = Ids::new();
+        for (position, name) in (1..).zip(ACTIVITIES) {
+            let id = match existing.iter().find(|activity| activity.name.eq_ignore_ascii_case(name)) {
+                Some(activity) => activity.id,
+                None => {
+                    let activity = repo
+                        .create(CreateActivityDto {
+                            name: (*name).into(),
+                            position: Some(position),
+                            is_default: Some(*name == DEFAULT_ACTIVITY),
+                            active: Some(true),
+                            project_id: None,
+                            parent_id: None,
+                        })
+                        .await?;
+                    activity.id
+                }
+            };
+            ids.insert(*name, id);
+        }
+        Ok(ids)
+    }
+
+    async fn seed_document_categories(&self) -> SeedResult<()> {
+        let repo = DocumentRepository::new(self.pool.clone());
+        let existing = repo.categories().await?;
+        for name in DOCUMENT_CATEGORIES {
+            if !existing.iter().any(|category| category.name.eq_ignore_ascii_case(name)) {
+                repo.create_category(name, *name == DEFAULT_DOCUMENT_CATEGORY).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Roles found by name keep their permissions
+    async fn seed_roles(&self) -> SeedResult<Ids> {
+        let repo = RoleRepository::new(self.pool.clone());
+        let mut ids = Ids::new();
+        for (name, permissions) in ROLES {
+            let role = match repo.find_by_name(name).await? {
+                Some(role) => role,
+                None => {
+                    let role = repo
+                        .create(CreateRoleDto {
+                            name: (*name).into(),
+                            position: None,
+                            role_type: "ProjectRole".into(),
+                        })
+                        .await?;
+                    let permissions: Vec<String> = permissions.iter().map(|p| p.to_string()).collect();
+                    repo.set_permissions(role.id, &permissions).await?;
+                    role
+                }
+            };
+            ids.insert(*name, role.id);
+        }
+        Ok(ids)
+    }
+
+    async fn seed_users(&self, report: &mut SeedReport) -> SeedResult<Ids> {
+        let repo = UserRepository::new(self.pool.clone());
+        let mut ids = Ids::new();
+        for user in USERS {
+            if let Some(row) = repo.find_by_login(user.login).await? {
+                ids.insert(user.login, row.id);
+                continue;
+            }
+
+            let mail = format!("{}@example.com", user.login);
+            let params = UserParams {
+                login: Some(user.login.into()),
+                firstname: Some(user.firstname.into()),
+                lastname: Some(user.lastname.into()),
+                mail: Some(mail.clone()),
+                admin: Some(user.admin),
+                language: Some("en".into()),
+                ..UserParams::default()
+            };
+            validated(
+                format!("User {}", user.login),
+                CreateUserService::without_notifications(&SeedContext(0)).call(params),
+            )?;
+
+            let row = repo
+                .create(CreateUserDto {
+                    login: user.login.into(),
+                    firstname: user.firstname.into(),
+                    lastname: user.lastname.into(),
+                    mail,
+                    admin: user.admin,
+                    status: op_db::user_status::ACTIVE,
+                    language: Some("en".into()),
+                    hashed_password: None,
+                    salt: None,
+                })
+                .await?;
+            ids.insert(user.login, row.id);
+            report.users += 1;
+        }
+        Ok(ids)
+    }
+
+    /// Projects with their types, members and versions; the versions' ids
+    /// are keyed by project identifier and name, e.g. `scrum-project/Sprint 1`
+    async fn seed_projects(
+        &self,
+        admin: Id,
+        types: &Ids,
+        users: &Ids,
+        roles: &Ids,
+        report: &mut SeedReport,
+    ) -> SeedResult<SeededProjects> {
+        let repo = ProjectRepository::new(self.pool.clone());
+        let type_repo = TypeRepository::new(self.pool.clone());
+        let members = MemberRepository::new(self.pool.clone());
+        let versions = VersionRepository::new(self.pool.clone());
+        let mut seeded = SeededProjects::default();
+
+        for project in PROJECTS {
+            let parent_id = project.parent.map(|parent| seeded.ids[parent]);
+            let id = match repo.find_by_identifier(project.identifier).await? {
+                Some(row) => row.id,
+                None => {
+                    let params = ProjectParams {
+                        name: Some(project.name.into()),
+                        identifier: Some(project.identifier.into()),
+                        description: Some(project.description.into()),
+                        public: Some(project.public),
+                        active: Some(true),
+                        parent_id,
+                        send_notifications: false,
+                    };
+                    validated(
+                        format!("Project {}", project.identifier),
+                        CreateProjectService::without_notifications(&SeedContext(admin))
+                            .dry_run()
+                            .call(params),
+                    )?;
+
+                    let row = repo
+                        .create(CreateProjectDto {
+                            name: project.name.into(),
+                            description: Some(project.description.into()),
+                            identifier: project.identifier.into(),
+                            public: project.public,
+                            parent_id,
+                            active: true,
+                            templated: false,
+                        })
+                        .await?;
+                    report.projects += 1;
+                    row.id
+                }
+            };
+            seeded.ids.insert(project.identifier, id);
+
+            for type_id in types.values() {
+                type_repo.enable_for_project(*type_id, id).await?;
+            }
+
+            for (login, role) in project.members {
+                let user_id = users[login];
+                if members.find_by_project_and_user(id, user_id).await?.is_none() {
+                    members
+                        .create(CreateMemberDto {
+                            user_id,
+                            project_id: Some(id),
+                            role_ids: vec![roles[role]],
+                            entity_type: None,
+                            entity_id: None,
+                        })
+                        .await?;
+                }
+            }
+
+            let existing = versions.find_by_project(id, ALL).await?.items;
+            for (name, weeks) in project.versions {
+                let version_id = match existing.iter().find(|version| version.name == *name) {
+                    Some(version) => version.id,
+                    None => {
+                        let version = versions
+                            .create(CreateVersionDto {
+                                project_id: id,
+                                name: (*name).into(),
+                                description: None,
+                                effective_date: Some(self.options.start_date + Duration::weeks(*weeks)),
+                                start_date: None,
+                                status: Some("open".into()),
+                                sharing: Some("none".into()),
+                                wiki_page_title: None,
+                            })
+                            .await?;
+                        report.versions += 1;
+                        version.id
+                    }
+                };
+                seeded.versions.insert(format!("{}/{}", project.identifier, name), version_id);
+            }
+        }
+        Ok(seeded)
+    }
+
+    /// Work packages of the plan, found by subject within their project;
+    /// returns their ids in plan order. Comments and logged time are added
+    /// to new work packages only.
+    async fn seed_work_packages(
+        &self,
+        plan: &DemoPlan,
+        refs: &References<'_>,
+        report: &mut SeedReport,
+    ) -> SeedResult<Vec<Id>> {
+        let repo = WorkPackageRepository::new(self.pool.clone());
+        let journals = JournalRepository::new(self.pool.clone());
+        let time_entries = TimeEntryRepository::new(self.pool.clone());
+
+        let mut existing: HashMap<&str, HashMap<String, Id>> = HashMap::new();
+        for identifier in refs.projects.ids.keys() {
+            let rows = repo.find_by_project(refs.projects.ids[identifier], ALL).await?.items;
+            existing.insert(identifier, rows.into_iter().map(|row| (row.subject, row.id)).collect());
+        }
+
+        let mut ids: Vec<Id> = Vec::with_capacity(plan.work_packages.len());
+        for planned in &plan.work_packages {
+            if let Some(id) = existing[planned.project].get(planned.subject) {
+                ids.push(*id);
+                continue;
+            }
+
+            let project_id = refs.projects.ids[planned.project];
+            let author_id = refs.users[planned.author];
+            let params = WorkPackageParams {
+                subject: Some(planned.subject.into()),
+                description: planned.description.map(String::from),
+                project_id: Some(project_id),
+                type_id: Some(refs.types[planned.type_name]),
+                status_id: Some(refs.statuses[planned.status]),
+                priority_id: Some(refs.priorities[planned.priority]),
+                assigned_to_id: planned.assignee.map(|login| refs.users[login]),
+                start_date: Some(planned.start_date),
+                due_date: Some(planned.due_date),
+                estimated_hours: planned.estimated_hours,
+                done_ratio: Some(planned.done_ratio),
+                parent_id: planned.parent.map(|parent| ids[parent]),
+                version_id: planned
+                    .version
+                    .map(|name| refs.projects.versions[&format!("{}/{}", planned.project, name)]),
+                send_notifications: false,
+                ..WorkPackageParams::default()
+            };
+            let entity = validated(
+                format!("Work package {}", planned.subject),
+                CreateWorkPackageService::without_notifications(&SeedContext(author_id))
+                    .dry_run()
+                    .call(params),
+            )?;
+
+            let row = repo
+                .create_journaled(CreateWorkPackageDto {
+                    subject: entity.subject,
+                    description: entity.description,
+                    project_id,
+                    type_id: entity.type_id,
+                    status_id: entity.status_id,
+                    priority_id: Some(entity.priority_id),
+                    author_id,
+                    assigned_to_id: entity.assigned_to_id,
+                    responsible_id: None,
+                    start_date: entity.start_date,
+                    due_date: entity.due_date,
+                    estimated_hours: entity.estimated_hours,
+                    done_ratio: entity.done_ratio,
+                    parent_id: entity.parent_id,
+                    version_id: entity.version_id,
+                    category_id: None,
+                    duration: entity.duration,
+                    ignore_non_working_days: Some(entity.ignore_non_working_days),
+                    journal_cause: None,
+                })
+                .await?;
+            report.work_packages += 1;
+
+            for (login, notes) in &planned.comments {
+                journals
+                    .create_comment(journable_type::WORK_PACKAGE, row.id, refs.users[login], notes)
+                    .await?;
+                report.comments += 1;
+            }
+
+            for entry in &planned.time_entries {
+                let user_id = refs.users[entry.user];
+                time_entries
+                    .create(CreateTimeEntryDto {
+                        project_id,
+                        user_id,
+                        work_package_id: Some(row.id),
+                        hours: entry.hours,
+                        comments: Some(entry.comment.into()),
+                        activity_id: refs.activities[entry.activity],
+                        spent_on: entry.spent_on,
+                        logged_by_id: Some(user_id),
+                    })
+                    .await?;
+                report.time_entries += 1;
+            }
+
+            ids.push(row.id);
+        }
+        Ok(ids)
+    }
+
+    async fn seed_relations(&self, plan: &DemoPlan, ids: &[Id], report: &mut SeedReport) -> SeedResult<()> {
+        let repo = RelationRepository::new(self.pool.clone());
+        for relation in &plan.relations {
+            let (from_id, to_id) = (ids[relation.from], ids[relation.to]);
+            if repo.relation_exists(from_id, to_id).await? {
+                continue;
+            }
+            repo.create(CreateRelationDto {
+                from_id,
+                to_id,
+                relation_type: relation.relation_type.into(),
+                lag: None,
+                description: None,
+            })
+            .await?;
+            report.relations += 1;
+        }
+        Ok(())
+    }
+
+    /// Public Gantt chart of the project's work packages
+    async fn seed_query(&self, admin: Id, project_id: Id, report: &mut SeedReport) -> SeedResult<()> {
+        let repo = QueryRepository::new(self.pool.clone());
+        let existing = repo.find_by_project(project_id, ALL).await?.items;
+        if existing.iter().any(|query| query.name == QUERY_NAME) {
+            return Ok(());
+        }
+
+        let query = QueryBuilder::new()
+            .name(QUERY_NAME)
+            .project(project_id)
+            .user(admin)
+            .public()
+            .gantt_view()
+            .sort_by_asc("start_date")
+            .build();
+        repo.create(CreateQueryDto::from_query(&query, admin)).await?;
+        report.queries += 1;
+        Ok(())
+    }
+
+    /// Status board with a column query per status
+    async fn seed_board(&self, admin: Id, project_id: Id, statuses: &Ids, report: &mut SeedReport) -> SeedResult<()> {
+        let boards = BoardRepository::new(self.pool.clone());
+        if boards.find_by_project(project_id).await?.iter().any(|board| board.name == BOARD_NAME) {
+            return Ok(());
+        }
+
+        let queries = QueryRepository::new(self.pool.clone());
+        let mut query_ids = Vec::with_capacity(BOARD_COLUMNS.len());
+        for status in BOARD_COLUMNS {
+            let query = QueryBuilder::new()
+                .name(*status)
+                .project(project_id)
+                .user(admin)
+                .board_view()
+                .status(vec![statuses[status]])
+                .build();
+            query_ids.push(queries.create(CreateQueryDto::from_query(&query, admin)).await?.id);
+            report.queries += 1;
+        }
+
+        boards
+            .create(CreateBoardDto {
+                project_id,
+                user_id: Some(admin),
+                name: BOARD_NAME.into(),
+                options: serde_json::json!({ "type": "action", "attribute": "status", "highlightingMode": "priority" }),
+                query_ids,
+            })
+            .await?;
+        report.boards += 1;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SeededProjects {
+    ids: Ids,
+    versions: HashMap<String, Id>,
+}
+
+/// Seeded records work packages refer to
+struct References<'a> {
+    statuses: &'a Ids,
+    types: &'a Ids,
+    priorities: &'a Ids,
+    activities: &'a Ids,
+    users: &'a Ids,
+    projects: &'a SeededProjects,
+}
+
+fn validated<T>(record: String, result: ServiceResult<T>) -> SeedResult<T> {
+    if result.is_failure() {
+        return Err(SeedError::Invalid {
+            record,
+            messages: result.full_messages(),
+        });
+    }
+    Ok(result.unwrap())
+}
+
+/// The seeder acts as an administrator, in the name of the record's author
+struct SeedContext(Id);
+
+impl UserContext for SeedContext {
+    fn id(&self) -> Id {
+        self.0
+    }
+
+    fn is_admin(&self) -> bool {
+        true
+    }
+
+    fn is_anonymous(&self) -> bool {
+        false
+    }
+
+    fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+        true
+    }
+
+    fn allowed_globally(&self, _permission: &str) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_names_exist() {
+        let statuses: Vec<&str> = STATUSES.iter().map(|s| s.name).collect();
+        for status in BOARD_COLUMNS {
+            assert!(statuses.contains(status));
+        }
+
+        let plan = DemoPlan::generate(DEFAULT_SEED, SeedOptions::default().start_date);
+        let types: Vec<&str> = TYPES.iter().map(|t| t.name).collect();
+        let logins: Vec<&str> = USERS.iter().map(|u| u.login).collect();
+        let priorities: Vec<&str> = PRIORITIES.iter().map(|(name, _)| *name).collect();
+        for wp in &plan.work_packages {
+            assert!(types.contains(&wp.type_name), "{}", wp.type_name);
+            assert!(statuses.contains(&wp.status), "{}", wp.status);
+            assert!(priorities.contains(&wp.priority), "{}", wp.priority);
+            assert!(logins.contains(&wp.author));
+        }
+
+        let roles: Vec<&str> = ROLES.iter().map(|(name, _)| *name).collect();
+        for project in PROJECTS {
+            for (login, role) in project.members {
+                assert!(logins.contains(login) && roles.contains(role));
+            }
+        }
+    }
+
+    #[test]
+    fn test_report_lists_created_records() {
+        let report = SeedReport {
+            users: 6,
+            work_packages: 30,
+            ..SeedReport::default()
+        };
+        assert!(!report.is_empty());
+        assert!(report.to_string().starts_with("6 users, 0 projects"));
+        assert!(SeedReport::default().is_empty());
+    }
+}
@@ -0,0 +1,857 @@
+//! Work package share services
+//!
+//! Mirrors:
+//! - app/services/shares/create_service.rb
+//! - app/services/shares/update_service.rb
+//! - app/services/shares/delete_service.rb
+//! - app/workers/mails/shared_work_package_job.rb
+//!
+//! A share is an entity-scoped membership (entity_type = 'WorkPackage')
+//! holding one of the builtin share roles. It grants access to that work
+//! package only, without making the user a project member. Sharing with an
+//! email address no user has yet invites a new user.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use op_contracts::base::UserContext;
+use op_contracts::work_packages::permissions::{SHARE_WORK_PACKAGES, VIEW_SHARED_WORK_PACKAGES};
+use op_core::email::normalize_email;
+use op_core::error::ValidationErrors;
+use op_core::traits::Id;
+use op_db::{
+    member_entity_type, role_builtin, user_status, CreateMemberDto, CreateUserDto, MemberRepository,
+    MemberWithRoles, Repository, RepositoryResult, RoleRepository, UpdateMemberDto, UserRepository,
+    UserRow, WorkPackageRepository,
+};
+use op_notifications::channels::MAILERS_QUEUE;
+use op_notifications::email::{EmailSender, SharedWorkPackage};
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use op_notifications::{EmailRenderer, Job, JobQueue};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::permissions::{PermissionService, PermissionSource};
+use crate::result::ServiceResult;
+
+/// Job type sending the email telling a user a work package was shared with them
+pub const SHARE_MAIL_JOB: &str = "Mails::SharedWorkPackageJob";
+
+/// Role a work package is shared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareRole {
+    View,
+    Comment,
+    Edit,
+}
+
+impl ShareRole {
+    pub const ALL: [ShareRole; 3] = [ShareRole::View, ShareRole::Comment, ShareRole::Edit];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareRole::View => "view",
+            ShareRole::Comment => "comment",
+            ShareRole::Edit => "edit",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| role.as_str() == name)
+    }
+
+    /// Builtin role granting the share's permissions
+    pub fn builtin(&self) -> i32 {
+        match self {
+            ShareRole::View => role_builtin::WORK_PACKAGE_VIEWER,
+            ShareRole::Comment => role_builtin::WORK_PACKAGE_COMMENTER,
+            ShareRole::Edit => role_builtin::WORK_PACKAGE_EDITOR,
+        }
+    }
+}
+
+/// A work package shared with a user
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Share {
+    pub id: Id,
+    pub work_package_id: Id,
+    pub project_id: Option<Id>,
+    pub user_id: Id,
+    pub role: ShareRole,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// User a work package can be shared with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareRecipient {
+    pub id: Id,
+    /// Invited users have yet to activate their account
+    pub invited: bool,
+}
+
+/// Who to share a work package with
+#[derive(Debug, Clone)]
+pub enum ShareWith {
+    User(Id),
+    /// An existing user's email address, or the address to invite
+    Email(String),
+}
+
+/// Share service params
+#[derive(Debug, Clone)]
+pub struct ShareParams {
+    pub recipient: ShareWith,
+    pub role: ShareRole,
+}
+
+/// Storage of shares and the users they are for
+#[async_trait]
+pub trait ShareStore: Send + Sync {
+    /// Find a user that can be shared with; deleted users cannot
+    async fn find_user(&self, id: Id) -> RepositoryResult<Option<ShareRecipient>>;
+
+    async fn find_user_by_email(&self, email: &str) -> RepositoryResult<Option<ShareRecipient>>;
+
+    /// Create an invited user for the email address
+    async fn invite_user(&self, email: &str) -> RepositoryResult<ShareRecipient>;
+
+    /// Id of the role granting the share role, if the role is set up
+    async fn role_id(&self, role: ShareRole) -> RepositoryResult<Option<Id>>;
+
+    /// Shares of a work package, oldest first
+    async fn find_shares(&self, work_package_id: Id) -> RepositoryResult<Vec<Share>>;
+
+    async fn find_share(&self, id: Id) -> RepositoryResult<Option<Share>>;
+
+    async fn create_share(
+        &self,
+        work_package_id: Id,
+        project_id: Id,
+        user_id: Id,
+        role: ShareRole,
+        role_id: Id,
+    ) -> RepositoryResult<Share>;
+
+    async fn update_share(&self, id: Id, role: ShareRole, role_id: Id) -> RepositoryResult<Share>;
+
+    async fn delete_share(&self, id: Id) -> RepositoryResult<()>;
+}
+
+/// Service for sharing a work package with users
+///
+/// Sharing requires the `share_work_packages` permission in the work
+/// package's project; a share itself never allows sharing further.
+/// Recipients are emailed through the job queue given with `with_jobs`.
+pub struct ShareService<'a, U: UserContext, S: ShareStore, P: PermissionSource> {
+    user: &'a U,
+    store: &'a S,
+    permissions: &'a PermissionService<P>,
+    jobs: Option<&'a dyn JobQueue>,
+}
+
+impl<'a, U: UserContext, S: ShareStore, P: PermissionSource> ShareService<'a, U, S, P> {
+    pub fn new(user: &'a U, store: &'a S, permissions: &'a PermissionService<P>) -> Self {
+        Self {
+            user,
+            store,
+            permissions,
+            jobs: None,
+        }
+    }
+
+    /// Queue share emails on `jobs`
+    pub fn with_jobs(mut self, jobs: &'a dyn JobQueue) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Shares of the work package
+    pub async fn list(&self, work_package_id: Id, project_id: Id) -> ServiceResult<Vec<Share>> {
+        into_result(self.try_list(work_package_id, project_id).await)
+    }
+
+    /// Share the work package with a user, inviting them when needed
+    pub async fn create(&self, work_package_id: Id, project_id: Id, params: ShareParams) -> ServiceResult<Share> {
+        into_result(self.try_create(work_package_id, project_id, params).await)
+    }
+
+    /// Change the role of a share
+    pub async fn update(&self, work_package_id: Id, project_id: Id, share_id: Id, role: ShareRole) -> ServiceResult<Share> {
+        into_result(self.try_update(work_package_id, project_id, share_id, role).await)
+    }
+
+    /// Remove a share, revoking the user's access
+    pub async fn delete(&self, work_package_id: Id, project_id: Id, share_id: Id) -> ServiceResult<Share> {
+        into_result(self.try_delete(work_package_id, project_id, share_id).await)
+    }
+
+    async fn try_list(&self, work_package_id: Id, project_id: Id) -> Result<Vec<Share>, ValidationErrors> {
+        let allowed = self.allowed(VIEW_SHARED_WORK_PACKAGES, project_id).await?
+            || self.allowed(SHARE_WORK_PACKAGES, project_id).await?;
+        if !allowed {
+            return Err(base_error("You are not authorized to view the shares of this work package"));
+        }
+
+        self.store.find_shares(work_package_id).await.map_err(base_error)
+    }
+
+    async fn try_create(&self, work_package_id: Id, project_id: Id, mut params: ShareParams) -> Result<Share, ValidationErrors> {
+        self.authorize(project_id).await?;
+        let role_id = self.role_id(params.role).await?;
+
+        // Invitations are looked up and created by the normalized address
+        if let ShareWith::Email(email) = &mut params.recipient {
+            *email = normalize_email(email).map_err(|_| field_error("email", "is not a valid email address"))?;
+        }
+
+        let existing = match &params.recipient {
+            ShareWith::User(id) => {
+                let user = self.store.find_user(*id).await.map_err(base_error)?;
+                Some(user.ok_or_else(|| field_error("user", "does not exist"))?)
+            }
+            ShareWith::Email(email) => self.store.find_user_by_email(email).await.map_err(base_error)?,
+        };
+
+        if let Some(user) = &existing {
+            let shares = self.store.find_shares(work_package_id).await.map_err(base_error)?;
+            if shares.iter().any(|share| share.user_id == user.id) {
+                return Err(field_error("user", "already has access to this work package"));
+            }
+        }
+
+        let recipient = match (existing, &params.recipient) {
+            (Some(user), _) => user,
+            (None, ShareWith::Email(email)) => self.store.invite_user(email).await.map_err(base_error)?,
+            (None, ShareWith::User(_)) => unreachable!("unknown users are rejected above"),
+        };
+
+        let share = self
+            .store
+            .create_share(work_package_id, project_id, recipient.id, params.role, role_id)
+            .await
+            .map_err(base_error)?;
+
+        self.notify(&share, recipient.invited).await;
+        Ok(share)
+    }
+
+    async fn try_update(&self, work_package_id: Id, project_id: Id, share_id: Id, role: ShareRole) -> Result<Share, ValidationErrors> {
+        self.authorize(project_id).await?;
+        let share = self.find_share(work_package_id, share_id).await?;
+        if share.role == role {
+            return Ok(share);
+        }
+
+        let role_id = self.role_id(role).await?;
+        self.store.update_share(share.id, role, role_id).await.map_err(base_error)
+    }
+
+    async fn try_delete(&self, work_package_id: Id, project_id: Id, share_id: Id) -> Result<Share, ValidationErrors> {
+        self.authorize(project_id).await?;
+        let share = self.find_share(work_package_id, share_id).await?;
+
+        self.store.delete_share(share.id).await.map_err(base_error)?;
+        Ok(share)
+    }
+
+    async fn allowed(&self, permission: &str, project_id: Id) -> Result<bool, ValidationErrors> {
+        self.permissions
+            .allowed_in_project(self.user, permission, project_id)
+            .await
+            .map_err(base_error)
+    }
+
+    async fn authorize(&self, project_id: Id) -> Result<(), ValidationErrors> {
+        if self.allowed(SHARE_WORK_PACKAGES, project_id).await? {
+            Ok(())
+        } else {
+            Err(base_error("You are not authorized to share this work package"))
+        }
+    }
+
+    async fn role_id(&self, role: ShareRole) -> Result<Id, ValidationErrors> {
+        self.store
+            .role_id(role)
+            .await
+            .map_err(base_error)?
+            .ok_or_else(|| base_error(format!("The {} share role is not set up", role.as_str())))
+    }
+
+    async fn find_share(&self, work_package_id: Id, share_id: Id) -> Result<Share, ValidationErrors> {
+        self.store
+            .find_share(share_id)
+            .await
+            .map_err(base_error)?
+            .filter(|share| share.work_package_id == work_package_id)
+            .ok_or_else(|| base_error("Share not found"))
+    }
+
+    /// Queue the share email. The share stands even if queueing fails.
+    async fn notify(&self, share: &Share, invited: bool) {
+        let Some(jobs) = self.jobs else {
+            return;
+        };
+
+        let args = ShareMailArgs {
+            share_id: share.id,
+            work_package_id: share.work_package_id,
+            user_id: share.user_id,
+            actor_id: self.user.id(),
+            invited,
+        };
+        if let Err(e) = jobs.enqueue(args.into_job()).await {
+            warn!(share_id = share.id, error = %e, "Failed to queue share email");
+        }
+    }
+}
+
+fn into_result<T>(result: Result<T, ValidationErrors>) -> ServiceResult<T> {
+    match result {
+        Ok(value) => ServiceResult::success(value),
+        Err(errors) => ServiceResult::failure(errors),
+    }
+}
+
+fn base_error(message: impl ToString) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    errors.add_base(message.to_string());
+    errors
+}
+
+fn field_error(field: &str, message: &str) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    errors.add(field, message);
+    errors
+}
+
+/// Arguments of a [`SHARE_MAIL_JOB`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShareMailArgs {
+    pub share_id: Id,
+    pub work_package_id: Id,
+    pub user_id: Id,
+    /// User who shared the work package
+    pub actor_id: Id,
+    pub invited: bool,
+}
+
+impl ShareMailArgs {
+    pub fn into_job(self) -> Job {
+        Job::new(SHARE_MAIL_JOB, serde_json::json!(self)).queue(MAILERS_QUEUE)
+    }
+}
+
+/// Shares stored as entity-scoped memberships
+pub struct PgShareStore {
+    members: MemberRepository,
+    roles: RoleRepository,
+    users: UserRepository,
+}
+
+impl PgShareStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            members: MemberRepository::new(pool.clone()),
+            roles: RoleRepository::new(pool.clone()),
+            users: UserRepository::new(pool),
+        }
+    }
+
+    /// Share roles by the ids of the roles granting them
+    async fn share_roles(&self) -> RepositoryResult<Vec<(Id, ShareRole)>> {
+        let mut roles = Vec::new();
+        for role in ShareRole::ALL {
+            if let Some(row) = self.roles.find_by_builtin(role.builtin()).await? {
+                roles.push((row.id, role));
+            }
+        }
+        Ok(roles)
+    }
+
+    fn to_share(member: MemberWithRoles, roles: &[(Id, ShareRole)]) -> Option<Share> {
+        let role = roles
+            .iter()
+            .find(|(id, _)| member.has_role(*id))
+            .map(|(_, role)| *role)?;
+        let member = member.member;
+
+        Some(Share {
+            id: member.id,
+            work_package_id: member.entity_id?,
+            project_id: member.project_id,
+            user_id: member.user_id,
+            role,
+            created_at: member.created_at,
+            updated_at: member.updated_at,
+        })
+    }
+}
+
+fn to_recipient(user: UserRow) -> Option<ShareRecipient> {
+    (!user.is_deleted()).then_some(ShareRecipient {
+        id: user.id,
+        invited: user.status == user_status::INVITED,
+    })
+}
+
+#[async_trait]
+impl ShareStore for PgShareStore {
+    async fn find_user(&self, id: Id) -> RepositoryResult<Option<ShareRecipient>> {
+        Ok(self.users.find_by_id(id).await?.and_then(to_recipient))
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> RepositoryResult<Option<ShareRecipient>> {
+        Ok(self.users.find_by_email(email).await?.and_then(to_recipient))
+    }
+
+    async fn invite_user(&self, email: &str) -> RepositoryResult<ShareRecipient> {
+        let firstname = email.split('@').next().unwrap_or(email).to_string();
+        let user = self
+            .users
+            .create(CreateUserDto {
+                login: email.to_string(),
+                firstname,
+                lastname: String::new(),
+                mail: email.to_string(),
+                admin: false,
+                status: user_status::INVITED,
+                language: None,
+                hashed_password: None,
+                salt: None,
+            })
+            .await?;
+
+        Ok(ShareRecipient {
+            id: user.id,
+            invited: true,
+        })
+    }
+
+    async fn role_id(&self, role: ShareRole) -> RepositoryResult<Option<Id>> {
+        Ok(self.roles.find_by_builtin(role.builtin()).await?.map(|row| row.id))
+    }
+
+    async fn find_shares(&self, work_package_id: Id) -> RepositoryResult<Vec<Share>> {
+        let roles = self.share_roles().await?;
+        let members = self
+            .members
+            .find_by_entity(member_entity_type::WORK_PACKAGE, work_package_id)
+            .await?;
+
+        Ok(members
+            .into_iter()
+            .filter_map(|member| Self::to_share(member, &roles))
+            .collect())
+    }
+
+    async fn find_share(&self, id: Id) -> RepositoryResult<Option<Share>> {
+        let Some(member) = self.members.find_by_id_with_roles(id).await? else {
+            return Ok(None);
+        };
+        if member.member.entity_type.as_deref() != Some(member_entity_type::WORK_PACKAGE) {
+            return Ok(None);
+        }
+
+        Ok(Self::to_share(member, &self.share_roles().await?))
+    }
+
+    async fn create_share(
+        &self,
+        work_package_id: Id,
+        project_id: Id,
+        user_id: Id,
+        role: ShareRole,
+        role_id: Id,
+    ) -> RepositoryResult<Share> {
+        let member = self
+            .members
+            .create(CreateMemberDto {
+                user_id,
+                project_id: Some(project_id),
+                role_ids: vec![role_id],
+                entity_type: Some(member_entity_type::WORK_PACKAGE.to_string()),
+                entity_id: Some(work_package_id),
+            })
+            .await?;
+
+        Ok(Share {
+            id: member.id,
+            work_package_id,
+            project_id: member.project_id,
+            user_id,
+            role,
+            created_at: member.created_at,
+            updated_at: member.updated_at,
+        })
+    }
+
+    async fn update_share(&self, id: Id, role: ShareRole, role_id: Id) -> RepositoryResult<Share> {
+        let member = self
+            .members
+            .update(
+                id,
+                UpdateMemberDto {
+                    role_ids: Some(vec![role_id]),
+                },
+            )
+            .await?;
+
+        Ok(Share {
+            id: member.id,
+            work_package_id: member.entity_id.unwrap_or_default(),
+            project_id: member.project_id,
+            user_id: member.user_id,
+            role,
+            created_at: member.created_at,
+            updated_at: member.updated_at,
+        })
+    }
+
+    async fn delete_share(&self, id: Id) -> RepositoryResult<()> {
+        self.members.delete(id).await
+    }
+}
+
+/// Job handler emailing users about work packages shared with them
+pub struct ShareMailJob<E: EmailSender> {
+    pool: PgPool,
+    sender: Arc<E>,
+    renderer: EmailRenderer,
+}
+
+impl<E: EmailSender> ShareMailJob<E> {
+    pub fn new(pool: PgPool, sender: Arc<E>, renderer: EmailRenderer) -> Self {
+        Self { pool, sender, renderer }
+    }
+}
+
+#[async_trait]
+impl<E: EmailSender> JobHandler for ShareMailJob<E> {
+    async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+        let args: ShareMailArgs = serde_json::from_value(args)
+            .map_err(|e| JobError::SerializationError(e.to_string()))?;
+        let failed = |e: op_db::RepositoryError| JobError::Failed(e.to_string());
+
+        // Nothing to tell once the share was removed again
+        if PgShareStore::new(self.pool.clone())
+            .find_share(args.share_id)
+            .await
+            .map_err(failed)?
+            .is_none()
+        {
+            return Ok(());
+        }
+
+        let users = UserRepository::new(self.pool.clone());
+        let recipient = users
+            .find_by_id(args.user_id)
+            .await
+            .map_err(failed)?
+            .ok_or_else(|| JobError::Failed(format!("user {} not found", args.user_id)))?;
+        let actor_name = users
+            .find_by_id(args.actor_id)
+            .await
+            .map_err(failed)?
+            .map(|actor| actor.full_name())
+            .unwrap_or_default();
+        let work_package = WorkPackageRepository::new(self.pool.clone())
+            .find_by_id(args.work_package_id)
+            .await
+            .map_err(failed)?
+            .ok_or_else(|| JobError::Failed(format!("work package {} not found", args.work_package_id)))?;
+
+        let shared = SharedWorkPackage {
+            work_package_id: work_package.id,
+            project_id: Some(work_package.project_id),
+            subject: work_package.subject,
+            actor_name,
+            invited: args.invited,
+        };
+        let name = recipient.full_name();
+        let message = self.renderer.render_share(
+            &shared,
+            &recipient.mail,
+            Some(name.trim()).filter(|n| !n.is_empty()),
+            recipient.language.as_deref(),
+        )
+        .map_err(|e| JobError::Failed(e.to_string()))?;
+
+        self.sender
+            .send(&message)
+            .await
+            .map(|_| ())
+            .map_err(|e| JobError::Failed(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use op_notifications::MemoryJobQueue;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Permissions by (user, project) and by (user, work package)
+    #[derive(Default)]
+    struct MemoryPermissions {
+        projects: HashMap<(Id, Id), Vec<String>>,
+        work_packages: HashMap<(Id, Id), Vec<String>>,
+    }
+
+    #[async_trait]
+    impl PermissionSource for MemoryPermissions {
+        async fn project_permissions(&self, user_id: Id, project_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(self.projects.get(&(user_id, project_id)).cloned().unwrap_or_default())
+        }
+
+        async fn work_package_permissions(&self, user_id: Id, work_package_id: Id) -> RepositoryResult<Vec<String>> {
+            Ok(self.work_packages.get(&(user_id, work_package_id)).cloned().unwrap_or_default())
+        }
+    }
+
+    #[derive(Default)]
+    struct MemoryShareStore {
+        users: Mutex<Vec<(ShareRecipient, String)>>,
+        shares: Mutex<Vec<Share>>,
+    }
+
+    impl MemoryShareStore {
+        fn with_user(self, id: Id, email: &str) -> Self {
+            let recipient = ShareRecipient { id, invited: false };
+            self.users.lock().unwrap().push((recipient, email.to_string()));
+            self
+        }
+    }
+
+    #[async_trait]
+    impl ShareStore for MemoryShareStore {
+        async fn find_user(&self, id: Id) -> RepositoryResult<Option<ShareRecipient>> {
+            Ok(self.users.lock().unwrap().iter().find(|(u, _)| u.id == id).map(|(u, _)| u.clone()))
+        }
+
+        async fn find_user_by_email(&self, email: &str) -> RepositoryResult<Option<ShareRecipient>> {
+            Ok(self.users.lock().unwrap().iter().find(|(_, m)| m == email).map(|(u, _)| u.clone()))
+        }
+
+        async fn invite_user(&self, email: &str) -> RepositoryResult<ShareRecipient> {
+            let mut users = self.users.lock().unwrap();
+            let recipient = ShareRecipient {
+                id: 100 + users.len() as Id,
+                invited: true,
+            };
+            users.push((recipient.clone(), email.to_string()));
+            Ok(recipient)
+        }
+
+        async fn role_id(&self, role: ShareRole) -> RepositoryResult<Option<Id>> {
+            Ok(Some(role.builtin() as Id))
+        }
+
+        async fn find_shares(&self, work_package_id: Id) -> RepositoryResult<Vec<Share>> {
+            Ok(self
+                .shares
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|s| s.work_package_id == work_package_id)
+                .cloned()
+                .collect())
+        }
+
+        async fn find_share(&self, id: Id) -> RepositoryResult<Option<Share>> {
+            Ok(self.shares.lock().unwrap().iter().find(|s| s.id == id).cloned())
+        }
+
+        async fn create_share(
+            &self,
+            work_package_id: Id,
+            project_id: Id,
+            user_id: Id,
+            role: ShareRole,
+            _role_id: Id,
+        ) -> RepositoryResult<Share> {
+            let mut shares = self.shares.lock().unwrap();
+            let share = Share {
+                id: shares.len() as Id + 1,
+                work_package_id,
+                project_id: Some(project_id),
+                user_id,
+                role,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            };
+            shares.push(share.clone());
+            Ok(share)
+        }
+
+        async fn update_share(&self, id: Id, role: ShareRole, _role_id: Id) -> RepositoryResult<Share> {
+            let mut shares = self.shares.lock().unwrap();
+            let share = shares.iter_mut().find(|s| s.id == id).unwrap();
+            share.role = role;
+            Ok(share.clone())
+        }
+
+        async fn delete_share(&self, id: Id) -> RepositoryResult<()> {
+            self.shares.lock().unwrap().retain(|s| s.id != id);
+            Ok(())
+        }
+    }
+
+    struct Sharer {
+        id: Id,
+    }
+
+    impl UserContext for Sharer {
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn is_admin(&self) -> bool {
+            false
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+            false
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            false
+        }
+    }
+
+    /// User 1 may share in project 1, user 5 holds a share of work package 10
+    fn permissions() -> PermissionService<MemoryPermissions> {
+        let mut source = MemoryPermissions::default();
+        source.projects.insert((1, 1), vec![SHARE_WORK_PACKAGES.to_string()]);
+        source.work_packages.insert((5, 10), vec![SHARE_WORK_PACKAGES.to_string()]);
+        PermissionService::new(source)
+    }
+
+    fn share_with(recipient: ShareWith, role: ShareRole) -> ShareParams {
+        ShareParams { recipient, role }
+    }
+
+    #[test]
+    fn test_share_role_names() {
+        assert_eq!(ShareRole::parse("comment"), Some(ShareRole::Comment));
+        assert_eq!(ShareRole::parse("admin"), None);
+        assert_eq!(ShareRole::Edit.builtin(), role_builtin::WORK_PACKAGE_EDITOR);
+        assert_eq!(serde_json::json!(ShareRole::View), "view");
+    }
+
+    #[tokio::test]
+    async fn test_share_with_existing_user() {
+        let store = MemoryShareStore::default().with_user(5, "guest@example.com");
+        let permissions = permissions();
+        let jobs = MemoryJobQueue::new();
+        let service = ShareService::new(&Sharer { id: 1 }, &store, &permissions).with_jobs(&jobs);
+
+        let share = service
+            .create(10, 1, share_with(ShareWith::Email("guest@example.com".into()), ShareRole::Comment))
+            .await
+            .unwrap();
+        assert_eq!((share.work_package_id, share.user_id, share.role), (10, 5, ShareRole::Comment));
+
+        let job = jobs.dequeue(MAILERS_QUEUE).await.unwrap().unwrap();
+        assert_eq!(job.job_type, SHARE_MAIL_JOB);
+        let args: ShareMailArgs = serde_json::from_value(job.args).unwrap();
+        assert_eq!((args.user_id, args.actor_id, args.invited), (5, 1, false));
+
+        // The same user cannot be shared with twice
+        let result = service
+            .create(10, 1, share_with(ShareWith::User(5), ShareRole::Edit))
+            .await;
+        assert!(result.errors().has_error("user"));
+    }
+
+    #[tokio::test]
+    async fn test_share_with_unknown_email_invites() {
+        let store = MemoryShareStore::default();
+        let permissions = permissions();
+        let jobs = MemoryJobQueue::new();
+        let service = ShareService::new(&Sharer { id: 1 }, &store, &permissions).with_jobs(&jobs);
+
+        let share = service
+            .create(10, 1, share_with(ShareWith::Email(" new@example.com ".into()), ShareRole::View))
+            .await
+            .unwrap();
+
+        let invited = store.find_user_by_email("new@example.com").await.unwrap().unwrap();
+        assert!(invited.invited);
+        assert_eq!(share.user_id, invited.id);
+
+        let job = jobs.dequeue(MAILERS_QUEUE).await.unwrap().unwrap();
+        let args: ShareMailArgs = serde_json::from_value(job.args).unwrap();
+        assert!(args.invited);
+
+        // Invitations are made for the normalized address
+        service
+            .create(11, 1, share_with(ShareWith::Email("Guest@Bücher.DE".into()), ShareRole::View))
+            .await
+            .unwrap();
+        assert!(store.find_user_by_email("Guest@xn--bcher-kva.de").await.unwrap().is_some());
+
+        for address in ["not an address", "guest@localhost", "guest@exa_mple.com"] {
+            let result = service
+                .create(10, 1, share_with(ShareWith::Email(address.into()), ShareRole::View))
+                .await;
+            assert!(result.errors().has_error("email"), "{}", address);
+        }
+        let result = service
+            .create(10, 1, share_with(ShareWith::User(99), ShareRole::View))
+            .await;
+        assert!(result.errors().has_error("user"));
+    }
+
+    #[tokio::test]
+    async fn test_sharing_requires_permission_in_project() {
+        let store = MemoryShareStore::default().with_user(5, "guest@example.com");
+        let permissions = permissions();
+
+        // Project 2 grants user 1 nothing
+        let service = ShareService::new(&Sharer { id: 1 }, &store, &permissions);
+        let result = service
+            .create(20, 2, share_with(ShareWith::User(5), ShareRole::View))
+            .await;
+        assert!(result.is_failure());
+        assert!(result.full_messages().iter().any(|m| m.contains("not authorized")));
+
+        // Holding a share does not allow sharing further
+        let service = ShareService::new(&Sharer { id: 5 }, &store, &permissions);
+        let result = service
+            .create(10, 1, share_with(ShareWith::Email("other@example.com".into()), ShareRole::View))
+            .await;
+        assert!(result.is_failure());
+        assert!(store.shares.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_share() {
+        let store = MemoryShareStore::default().with_user(5, "guest@example.com");
+        let permissions = permissions();
+        let service = ShareService::new(&Sharer { id: 1 }, &store, &permissions);
+
+        let share = service
+            .create(10, 1, share_with(ShareWith::User(5), ShareRole::View))
+            .await
+            .unwrap();
+
+        let updated = service.update(10, 1, share.id, ShareRole::Edit).await.unwrap();
+        assert_eq!(updated.role, ShareRole::Edit);
+
+        // Shares are only reachable through their own work package
+        assert!(service.delete(11, 1, share.id).await.is_failure());
+
+        let removed = service.delete(10, 1, share.id).await.unwrap();
+        assert_eq!(removed.id, share.id);
+        assert!(service.list(10, 1).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! Storage providers
+//!
+//! Mirrors:
+//! - modules/storages/app/common/storages/peripherals/registry.rb
+//! - modules/storages/app/services/storages/file_link_sync_service.rb
+//!
+//! A provider talks to the file store behind a storage: it checks that the
+//! store can be reached and tells whether the file of a link can still be
+//! opened. Each storage type brings its own provider.
+
+mod nextcloud;
+
+use std::fmt;
+
+use async_trait::async_trait;
+use op_db::{FileLinkRow, StorageRow};
+use op_models::StorageType;
+
+pub use nextcloud::NextcloudProvider;
+
+/// Whether the current user can open the file of a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    ViewAllowed,
+    ViewNotAllowed,
+    /// The file was deleted from the storage
+    NotFound,
+    /// The storage could not be asked
+    Error,
+}
+
+impl LinkStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::ViewAllowed => "ViewAllowed",
+            LinkStatus::ViewNotAllowed => "ViewNotAllowed",
+            LinkStatus::NotFound => "NotFound",
+            LinkStatus::Error => "Error",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            LinkStatus::ViewAllowed => "View allowed",
+            LinkStatus::ViewNotAllowed => "View not allowed",
+            LinkStatus::NotFound => "Not found",
+            LinkStatus::Error => "Error",
+        }
+    }
+
+    /// URN of the status in the API
+    pub fn urn(&self) -> String {
+        match self {
+            LinkStatus::ViewAllowed | LinkStatus::ViewNotAllowed => {
+                format!("urn:openproject-org:api:v3:file-links:permission:{}", self.as_str())
+            }
+            LinkStatus::NotFound | LinkStatus::Error => {
+                format!("urn:openproject-org:api:v3:file-links:{}", self.as_str())
+            }
+        }
+    }
+}
+
+/// Why a storage cannot be reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionError {
+    /// The host is not an http(s) URL
+    InvalidHost(String),
+    /// Nothing answered at the host
+    Unreachable(String),
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::InvalidHost(host) => write!(f, "{} is not an http(s) URL", host),
+            ConnectionError::Unreachable(reason) => write!(f, "is not reachable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
+
+/// Access to the file store behind a storage
+#[async_trait]
+pub trait StorageProvider: Send + Sync {
+    fn storage_type(&self) -> StorageType;
+
+    /// Check that the storage answers at its host
+    async fn validate_connection(&self) -> Result<(), ConnectionError>;
+
+    /// Whether the file of a link can be opened
+    async fn probe_origin(&self, link: &FileLinkRow) -> LinkStatus;
+
+    /// URL opening the linked file in the storage
+    fn origin_href(&self, link: &FileLinkRow) -> String;
+}
+
+/// Provider of a storage type at a host; `None` for types without one yet
+pub fn provider(storage_type: StorageType, host: &str) -> Option<Box<dyn StorageProvider>> {
+    match storage_type {
+        StorageType::Nextcloud => Some(Box::new(NextcloudProvider::new(host))),
+        StorageType::OneDrive => None,
+    }
+}
+
+/// Provider of a stored storage
+pub fn provider_for(storage: &StorageRow) -> Option<Box<dyn StorageProvider>> {
+    provider(storage.storage_type()?, &storage.host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_status_urns() {
+        assert_eq!(
+            LinkStatus::ViewAllowed.urn(),
+            "urn:openproject-org:api:v3:file-links:permission:ViewAllowed"
+        );
+        assert_eq!(LinkStatus::NotFound.urn(), "urn:openproject-org:api:v3:file-links:NotFound");
+        assert_eq!(LinkStatus::ViewNotAllowed.title(), "View not allowed");
+    }
+}
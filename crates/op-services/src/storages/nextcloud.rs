@@ -0,0 +1,121 @@
+//! Nextcloud storage provider
+//!
+//! Mirrors: modules/storages/app/common/storages/peripherals/storage_interaction/nextcloud/
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use op_db::FileLinkRow;
+use op_models::StorageType;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use super::{ConnectionError, LinkStatus, StorageProvider};
+
+/// How long to wait for the storage to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Provider of Nextcloud storages
+///
+/// Until users can authorize OpenProject at the storage there is no token
+/// to ask Nextcloud for a file's permissions with, so links of a reachable
+/// storage are reported as viewable.
+#[derive(Debug, Clone)]
+pub struct NextcloudProvider {
+    host: String,
+    connect_timeout: Duration,
+}
+
+impl NextcloudProvider {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.trim_end_matches('/').to_string(),
+            connect_timeout: CONNECT_TIMEOUT,
+        }
+    }
+
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Host name and port to connect to
+    fn address(&self) -> Result<(String, u16), ConnectionError> {
+        let invalid = || ConnectionError::InvalidHost(self.host.clone());
+        let (rest, default_port) = if let Some(rest) = self.host.strip_prefix("https://") {
+            (rest, 443)
+        } else if let Some(rest) = self.host.strip_prefix("http://") {
+            (rest, 80)
+        } else {
+            return Err(invalid());
+        };
+
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        let authority = authority.rsplit('@').next().unwrap_or_default();
+        let (name, port) = match authority.rsplit_once(':') {
+            Some((name, port)) if !port.contains(']') => (name, port.parse().map_err(|_| invalid())?),
+            _ => (authority, default_port),
+        };
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok((name.to_string(), port))
+    }
+}
+
+#[async_trait]
+impl StorageProvider for NextcloudProvider {
+    fn storage_type(&self) -> StorageType {
+        StorageType::Nextcloud
+    }
+
+    async fn validate_connection(&self) -> Result<(), ConnectionError> {
+        let (name, port) = self.address()?;
+        match timeout(self.connect_timeout, TcpStream::connect((name.as_str(), port))).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(err)) => Err(ConnectionError::Unreachable(err.to_string())),
+            Err(_) => Err(ConnectionError::Unreachable("connection timed out".to_string())),
+        }
+    }
+
+    async fn probe_origin(&self, _link: &FileLinkRow) -> LinkStatus {
+        match self.validate_connection().await {
+            Ok(()) => LinkStatus::ViewAllowed,
+            Err(_) => LinkStatus::Error,
+        }
+    }
+
+    fn origin_href(&self, link: &FileLinkRow) -> String {
+        format!("{}/index.php/f/{}", self.host, link.origin_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_address_from_host() {
+        let address = |host: &str| NextcloudProvider::new(host).address();
+        assert_eq!(address("https://cloud.example.com/").unwrap(), ("cloud.example.com".to_string(), 443));
+        assert_eq!(address("http://cloud.example.com:8080/nc").unwrap(), ("cloud.example.com".to_string(), 8080));
+        assert_eq!(address("https://[::1]:8443").unwrap(), ("::1".to_string(), 8443));
+        assert!(matches!(address("ftp://cloud.example.com"), Err(ConnectionError::InvalidHost(_))));
+        assert!(matches!(address("https://cloud.example.com:port"), Err(ConnectionError::InvalidHost(_))));
+    }
+
+    #[tokio::test]
+    async fn test_validate_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let provider = NextcloudProvider::new(&format!("http://127.0.0.1:{}", port));
+        assert_eq!(provider.validate_connection().await, Ok(()));
+
+        drop(listener);
+        let unreachable = provider.validate_connection().await;
+        assert!(matches!(unreachable, Err(ConnectionError::Unreachable(_))));
+    }
+}
@@ -50,16 +50,8 @@ impl<'a, U: UserContext> DeleteUserService<'a, U> {
             return ServiceResult::failure(errors);
         }
 
-        // In a real implementation, this would:
-        // 1. Anonymize user data (GDPR compliance)
-        // 2. Reassign or delete work packages
-        // 3. Remove project memberships
-        // 4. Delete user preferences
-        // 5. Clean up notifications
-        // 6. Remove API tokens
-        // 7. Delete OAuth grants
-        // 8. Archive or delete journals
-        // 9. Handle time entries
+        // Anonymizing the user and reassigning their content happens in the
+        // background, see ReassignOrphanedContentJob
 
         let service_result = ServiceResult::success(user_entity.clone());
 
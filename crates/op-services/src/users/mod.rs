@@ -5,16 +5,21 @@
 //! - app/services/users/update_service.rb
 //! - app/services/users/delete_service.rb
 //! - app/services/users/set_attributes_service.rb
+//! - app/workers/principals/delete_job.rb
 
 mod create;
 mod update;
 mod delete;
 mod set_attributes;
+mod reassign_orphaned_content;
 
 pub use create::CreateUserService;
 pub use update::UpdateUserService;
 pub use delete::DeleteUserService;
 pub use set_attributes::{SetAttributesService, UserEntity};
+pub use reassign_orphaned_content::{
+    ReassignOrphanedContentArgs, ReassignOrphanedContentJob, REASSIGN_ORPHANED_CONTENT_JOB,
+};
 
 /// Parameters for user operations
 #[derive(Debug, Clone, Default)]
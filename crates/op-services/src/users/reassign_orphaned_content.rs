@@ -0,0 +1,89 @@
+//! Reassign Orphaned Content Job for Users
+//!
+//! Mirrors: app/workers/principals/delete_job.rb
+//!
+//! Deleting a user keeps the history they authored: their work packages,
+//! journals and time entries are attributed to the "Deleted user"
+//! placeholder, and the user row is anonymized rather than removed. As this
+//! touches many tables it runs in the background.
+
+use async_trait::async_trait;
+use op_core::traits::Id;
+use op_db::UserRepository;
+use op_notifications::jobs::{JobError, JobHandler, JobResult};
+use op_notifications::Job;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::projects::JOB_USER_METADATA_KEY;
+
+/// Job type of user deletion jobs
+pub const REASSIGN_ORPHANED_CONTENT_JOB: &str = "Users::ReassignOrphanedContentJob";
+
+/// Arguments of a [`REASSIGN_ORPHANED_CONTENT_JOB`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReassignOrphanedContentArgs {
+    pub user_id: Id,
+}
+
+impl ReassignOrphanedContentArgs {
+    pub fn new(user_id: Id) -> Self {
+        Self { user_id }
+    }
+
+    /// Build the job, recording the admin who deleted the user. The
+    /// reassignment is transactional, so failed attempts can be retried.
+    pub fn into_job(self, deleted_by: Id) -> Job {
+        Job::new(REASSIGN_ORPHANED_CONTENT_JOB, serde_json::json!(self))
+            .with_metadata(JOB_USER_METADATA_KEY, deleted_by.to_string())
+    }
+}
+
+/// Job handler soft deleting users in the background
+pub struct ReassignOrphanedContentJob {
+    pool: PgPool,
+}
+
+impl ReassignOrphanedContentJob {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl JobHandler for ReassignOrphanedContentJob {
+    async fn handle(&self, args: serde_json::Value) -> JobResult<()> {
+        let args: ReassignOrphanedContentArgs = serde_json::from_value(args)
+            .map_err(|e| JobError::SerializationError(e.to_string()))?;
+
+        let report = UserRepository::new(self.pool.clone())
+            .soft_delete(args.user_id)
+            .await
+            .map_err(|e| JobError::Failed(e.to_string()))?;
+
+        tracing::info!(
+            user_id = args.user_id,
+            placeholder_id = report.placeholder_id,
+            "Reassigned content of deleted user"
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_args_round_trip() {
+        let job = ReassignOrphanedContentArgs::new(100).into_job(1);
+
+        assert_eq!(job.job_type, REASSIGN_ORPHANED_CONTENT_JOB);
+        assert_eq!(job.metadata.get(JOB_USER_METADATA_KEY).map(String::as_str), Some("1"));
+        assert!(job.can_retry());
+
+        let parsed: ReassignOrphanedContentArgs = serde_json::from_value(job.args).unwrap();
+        assert_eq!(parsed.user_id, 100);
+    }
+}
@@ -0,0 +1,929 @@
+//! Copy Service for Work Packages
+//!
+//! Mirrors: app/services/work_packages/copy_service.rb
+
+use op_attachments::{rewrite_attachment_references, AttachmentService, AttachmentStore, ContainerType, Storage};
+use op_contracts::base::UserContext;
+use op_contracts::work_packages::permissions;
+use op_core::metrics::DomainMetrics;
+use op_core::traits::Id;
+use op_db::work_packages::WorkPackageRow;
+use op_db::{CommittedCopy, CopyCascade, CreateWorkPackageDto, RepositoryError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::result::ServiceResult;
+use super::create::CreateWorkPackageService;
+use super::set_attributes::Scheduling;
+use super::working_days::WorkingDays;
+use super::WorkPackageParams;
+
+/// Marker put in front of the subject of a copy unless another is given
+pub const DEFAULT_SUBJECT_PREFIX: &str = "Copy of ";
+
+/// Target of a copy and the data to copy along
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopyWorkPackageParams {
+    /// Project of the copy, the project of the source when unset
+    pub project_id: Option<Id>,
+    /// Put in front of the subject of the copied work package; its
+    /// descendants keep their subjects
+    pub subject_prefix: String,
+    pub copy_attachments: bool,
+    pub copy_watchers: bool,
+    /// Copy the descendants too, with the relations between them
+    pub copy_children: bool,
+}
+
+impl Default for CopyWorkPackageParams {
+    fn default() -> Self {
+        Self {
+            project_id: None,
+            subject_prefix: DEFAULT_SUBJECT_PREFIX.to_string(),
+            copy_attachments: true,
+            copy_watchers: false,
+            copy_children: false,
+        }
+    }
+}
+
+impl CopyWorkPackageParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_project_id(mut self, project_id: Id) -> Self {
+        self.project_id = Some(project_id);
+        self
+    }
+
+    pub fn with_subject_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.subject_prefix = prefix.into();
+        self
+    }
+
+    pub fn with_attachments(mut self, copy: bool) -> Self {
+        self.copy_attachments = copy;
+        self
+    }
+
+    pub fn with_watchers(mut self, copy: bool) -> Self {
+        self.copy_watchers = copy;
+        self
+    }
+
+    pub fn with_children(mut self, copy: bool) -> Self {
+        self.copy_children = copy;
+        self
+    }
+}
+
+/// Type or status of a copy replaced as the target project lacks it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Substitution {
+    /// The copied work package
+    pub work_package_id: Id,
+    /// `type` or `status`
+    pub attribute: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+/// The copy of a work package and what was copied with it
+#[derive(Debug, Clone)]
+pub struct CopiedWorkPackage {
+    pub work_package: WorkPackageRow,
+    /// Ids of the source and copy of every copied work package, parents first
+    pub copies: Vec<(Id, Id)>,
+    pub substitutions: Vec<Substitution>,
+    pub custom_values: u64,
+    pub watchers: u64,
+    pub relations: u64,
+    pub attachments: u64,
+}
+
+/// Records copied along with the work packages in the unit of work
+struct CopiedDependents {
+    custom_values: u64,
+    watchers: u64,
+    relations: u64,
+    /// Attachments to copy, with the id of the copy they go to
+    attachment_ids: Vec<(Id, Id)>,
+}
+
+/// Service for copying a work package, optionally with its descendants
+///
+/// Every copy passes through [`CreateWorkPackageService`], so it is checked
+/// against the contract of the target project, and is persisted with its
+/// initial journal in one unit of work. Types and statuses missing in the
+/// target are replaced by the ones of the same name, else by the defaults,
+/// and reported as substitutions. Attachment files are copied only once
+/// the unit of work is committed; inline references to them in the
+/// descriptions of the copies are then pointed to the copied attachments.
+///
+/// # Example
+/// ```ignore
+/// let copy = work_packages.begin_copy().await?;
+/// let service = CopyWorkPackageService::new(&user);
+/// let params = CopyWorkPackageParams::new().with_project_id(2).with_children(true);
+/// let result = service.call(source_id, params, copy, &attachments).await;
+/// ```
+pub struct CopyWorkPackageService<'a, U: UserContext> {
+    user: &'a U,
+    metrics: Option<&'a DomainMetrics>,
+    working_days: WorkingDays,
+}
+
+impl<'a, U: UserContext> CopyWorkPackageService<'a, U> {
+    pub fn new(user: &'a U) -> Self {
+        Self {
+            user,
+            metrics: None,
+            working_days: WorkingDays::default(),
+        }
+    }
+
+    /// Schedule the copies by the given calendar
+    pub fn with_working_days(mut self, working_days: WorkingDays) -> Self {
+        self.working_days = working_days;
+        self
+    }
+
+    /// Record successful operations in the given metrics collector
+    pub fn with_metrics(mut self, metrics: &'a DomainMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Execute the copy operation
+    ///
+    /// Any failure before the commit rolls back the whole unit of work.
+    pub async fn call<C, St, S>(
+        self,
+        source_id: Id,
+        params: CopyWorkPackageParams,
+        mut copy: C,
+        attachments: &AttachmentService<St, S>,
+    ) -> ServiceResult<CopiedWorkPackage>
+    where
+        C: CopyCascade,
+        St: AttachmentStore,
+        S: Storage,
+    {
+        let mut subtree = match copy.find_subtree(source_id).await {
+            Ok(subtree) => subtree,
+            Err(e) => return Self::abort(copy, e).await,
+        };
+
+        let source_project_id = match subtree.first() {
+            Some(source) => source.project_id,
+            None => return Self::reject(copy, ServiceResult::failure_with_base_error("Work package not found")).await,
+        };
+
+        if !self.user.is_admin() && !self.user.allowed_in_project(permissions::VIEW_WORK_PACKAGES, source_project_id) {
+            let result = ServiceResult::failure_with_base_error("You are not authorized to copy this work package");
+            return Self::reject(copy, result).await;
+        }
+
+        if !params.copy_children {
+            subtree.truncate(1);
+        }
+
+        let project_id = params.project_id.unwrap_or(source_project_id);
+        match copy.project_exists(project_id).await {
+            Ok(true) => {}
+            Ok(false) => return Self::reject(copy, ServiceResult::failure_with_error("project", "does not exist")).await,
+            Err(e) => return Self::abort(copy, e).await,
+        }
+
+        let (types, statuses, milestone_type_ids) = match Self::mappings(&mut copy, &subtree, project_id).await {
+            Ok(mappings) => mappings,
+            Err(e) => return Self::abort(copy, e).await,
+        };
+
+        let scheduling = Scheduling::new(self.working_days.clone()).with_milestone_types(milestone_type_ids);
+
+        let mut copied: Vec<(Id, WorkPackageRow)> = Vec::with_capacity(subtree.len());
+        let mut substitutions = Vec::new();
+        for (index, source) in subtree.iter().enumerate() {
+            let Some((type_id, type_substitution)) = types.resolve(source.type_id) else {
+                let result = ServiceResult::failure_with_error("type", "is not enabled in the target project");
+                return Self::reject(copy, result).await;
+            };
+            let Some((status_id, status_substitution)) = statuses.resolve(source.status_id) else {
+                return Self::reject(copy, ServiceResult::failure_with_error("status", "does not exist")).await;
+            };
+            for (attribute, substitution) in [("type", type_substitution), ("status", status_substitution)] {
+                if let Some((from, to)) = substitution {
+                    substitutions.push(Substitution { work_package_id: source.id, attribute, from, to });
+                }
+            }
+
+            let same_project = project_id == source.project_id;
+            let parent_id = if index == 0 {
+                source.parent_id.filter(|_| same_project)
+            } else {
+                copied
+                    .iter()
+                    .find(|(source_id, _)| Some(*source_id) == source.parent_id)
+                    .map(|(_, row)| row.id)
+            };
+            let subject = if index == 0 {
+                format!("{}{}", params.subject_prefix, source.subject)
+            } else {
+                source.subject.clone()
+            };
+            let attributes = WorkPackageParams {
+                subject: Some(subject),
+                description: source.description.clone(),
+                project_id: Some(project_id),
+                type_id: Some(type_id),
+                status_id: Some(status_id),
+                priority_id: source.priority_id,
+                assigned_to_id: source.assigned_to_id,
+                responsible_id: source.responsible_id,
+                start_date: source.start_date,
+                due_date: source.due_date,
+                // Derived from the dates where both are set
+                duration: None,
+                ignore_non_working_days: Some(source.ignore_non_working_days),
+                estimated_hours: source.estimated_hours,
+                done_ratio: Some(source.done_ratio),
+                parent_id,
+                // Versions and categories belong to the source project
+                version_id: source.version_id.filter(|_| same_project),
+                category_id: source.category_id.filter(|_| same_project),
+                nulled_attributes: Vec::new(),
+                send_notifications: false,
+            };
+
+            let created = CreateWorkPackageService::without_notifications(self.user)
+                .with_scheduling(scheduling.clone())
+                .call(attributes);
+            if created.is_failure() {
+                return Self::reject(copy, ServiceResult::failure(created.errors().clone())).await;
+            }
+            let entity = created.unwrap();
+            let journal_cause = entity.journal_cause();
+
+            let dto = CreateWorkPackageDto {
+                subject: entity.subject,
+                description: entity.description,
+                project_id: entity.project_id,
+                type_id: entity.type_id,
+                status_id: entity.status_id,
+                priority_id: Some(entity.priority_id),
+                author_id: entity.author_id,
+                assigned_to_id: entity.assigned_to_id,
+                responsible_id: entity.responsible_id,
+                start_date: entity.start_date,
+                due_date: entity.due_date,
+                estimated_hours: entity.estimated_hours,
+                done_ratio: entity.done_ratio,
+                parent_id: entity.parent_id,
+                version_id: entity.version_id,
+                category_id: entity.category_id,
+                duration: entity.duration.or(source.duration),
+                ignore_non_working_days: Some(entity.ignore_non_working_days),
+                journal_cause,
+            };
+            match copy.create_work_package(dto).await {
+                Ok(row) => copied.push((source.id, row)),
+                Err(e) => return Self::abort(copy, e).await,
+            }
+        }
+
+        let copies: Vec<(Id, Id)> = copied.iter().map(|(source_id, row)| (*source_id, row.id)).collect();
+        let dependents = match Self::dependents(&mut copy, &params, &copies).await {
+            Ok(dependents) => dependents,
+            Err(e) => return Self::abort(copy, e).await,
+        };
+
+        let mut committed = match copy.commit().await {
+            Ok(committed) => committed,
+            Err(e) => return ServiceResult::failure_with_base_error(format!("Could not copy the work package: {}", e)),
+        };
+
+        // Files cannot be rolled back, so they are copied only after the commit
+        let mut copied_attachments = 0;
+        let mut attachment_copies: HashMap<Id, HashMap<Id, Id>> = HashMap::new();
+        for (copy_id, attachment_id) in dependents.attachment_ids {
+            match attachments
+                .copy_to(attachment_id, ContainerType::WorkPackage, copy_id, self.user.id())
+                .await
+            {
+                Ok(copied) => {
+                    copied_attachments += 1;
+                    if let Some(id) = copied.attachment.id {
+                        attachment_copies.entry(copy_id).or_default().insert(attachment_id, id);
+                    }
+                }
+                Err(e) => warn!(attachment_id, copy_id, error = %e, "Failed to copy attachment"),
+            }
+        }
+        Self::rewrite_references(&mut committed, &mut copied, &attachment_copies).await;
+
+        if let Some(metrics) = self.metrics {
+            for _ in &copies {
+                metrics.record_work_package_created();
+            }
+        }
+
+        let (_, work_package) = copied.swap_remove(0);
+        ServiceResult::success(CopiedWorkPackage {
+            work_package,
+            copies,
+            substitutions,
+            custom_values: dependents.custom_values,
+            watchers: dependents.watchers,
+            relations: dependents.relations,
+            attachments: copied_attachments,
+        })
+    }
+
+    /// Point inline images in the descriptions of the copies to their copied
+    /// attachments. A copy whose description fails to update keeps pointing
+    /// to the attachments of its source.
+    async fn rewrite_references<K: CommittedCopy>(
+        committed: &mut K,
+        copied: &mut [(Id, WorkPackageRow)],
+        attachment_copies: &HashMap<Id, HashMap<Id, Id>>,
+    ) {
+        for (_, row) in copied.iter_mut() {
+            let Some(copies) = attachment_copies.get(&row.id) else { continue };
+            let Some(description) = row.description.as_deref() else { continue };
+            let Some(rewritten) = rewrite_attachment_references(description, copies) else { continue };
+            match committed.update_description(row.id, &rewritten).await {
+                Ok(()) => row.description = Some(rewritten),
+                Err(e) => warn!(copy_id = row.id, error = %e, "Failed to rewrite attachment references"),
+            }
+        }
+    }
+
+    /// Mappings of the types and statuses of the copied work packages, and
+    /// the milestone types of the target project
+    async fn mappings<C: CopyCascade>(
+        copy: &mut C,
+        subtree: &[WorkPackageRow],
+        project_id: Id,
+    ) -> Result<(NameMapping, NameMapping, Vec<Id>), RepositoryError> {
+        let mut type_ids: Vec<Id> = subtree.iter().map(|wp| wp.type_id).collect();
+        type_ids.sort_unstable();
+        type_ids.dedup();
+
+        let sources = copy.find_types(&type_ids).await?;
+        let enabled = copy.enabled_types(project_id).await?;
+        let milestone_type_ids = enabled.iter().filter(|t| t.is_milestone).map(|t| t.id).collect();
+        let types = NameMapping::new(
+            sources.into_iter().map(|t| (t.id, t.name)).collect(),
+            enabled.into_iter().map(|t| (t.id, t.name, t.is_default)).collect(),
+        );
+
+        let statuses = copy.statuses().await?;
+        let statuses = NameMapping::new(
+            statuses.iter().map(|s| (s.id, s.name.clone())).collect(),
+            statuses.into_iter().map(|s| (s.id, s.name, s.is_default)).collect(),
+        );
+
+        Ok((types, statuses, milestone_type_ids))
+    }
+
+    /// Copy the custom values, watchers and relations, and collect the
+    /// attachments to copy once committed
+    async fn dependents<C: CopyCascade>(
+        copy: &mut C,
+        params: &CopyWorkPackageParams,
+        copies: &[(Id, Id)],
+    ) -> Result<CopiedDependents, RepositoryError> {
+        let mut custom_values = 0;
+        let mut watchers = 0;
+        let mut attachment_ids = Vec::new();
+        for &(source_id, copy_id) in copies {
+            custom_values += copy.copy_custom_values(source_id, copy_id).await?;
+            if params.copy_watchers {
+                watchers += copy.copy_watchers(source_id, copy_id).await?;
+            }
+            if params.copy_attachments {
+                let ids = copy.attachment_ids(source_id).await?;
+                attachment_ids.extend(ids.into_iter().map(|id| (copy_id, id)));
+            }
+        }
+
+        let relations = if copies.len() > 1 {
+            copy.copy_relations(copies).await?
+        } else {
+            0
+        };
+
+        Ok(CopiedDependents {
+            custom_values,
+            watchers,
+            relations,
+            attachment_ids,
+        })
+    }
+
+    async fn reject<C: CopyCascade>(
+        copy: C,
+        result: ServiceResult<CopiedWorkPackage>,
+    ) -> ServiceResult<CopiedWorkPackage> {
+        if let Err(e) = copy.rollback().await {
+            warn!(error = %e, "Failed to roll back work package copy");
+        }
+        result
+    }
+
+    async fn abort<C: CopyCascade>(copy: C, error: RepositoryError) -> ServiceResult<CopiedWorkPackage> {
+        let result = ServiceResult::failure_with_base_error(format!("Could not copy the work package: {}", error));
+        Self::reject(copy, result).await
+    }
+}
+
+/// Picks the type or status of a copy among the ones available in the
+/// target project
+struct NameMapping {
+    /// Names of the types or statuses of the source work packages
+    sources: HashMap<Id, String>,
+    /// Id, name and default flag of the available ones, by position
+    available: Vec<(Id, String, bool)>,
+}
+
+impl NameMapping {
+    fn new(sources: Vec<(Id, String)>, available: Vec<(Id, String, bool)>) -> Self {
+        Self {
+            sources: sources.into_iter().collect(),
+            available,
+        }
+    }
+
+    /// The id to use instead of `id`: the same if available, else the one of
+    /// the same name, else the default. Returns the names replaced and
+    /// replacing it if the name changes, and `None` if nothing is available.
+    fn resolve(&self, id: Id) -> Option<(Id, Option<(String, String)>)> {
+        if self.available.iter().any(|(available_id, _, _)| *available_id == id) {
+            return Some((id, None));
+        }
+
+        let name = self.sources.get(&id).map(String::as_str).unwrap_or_default();
+        if let Some((same_name, _, _)) = self
+            .available
+            .iter()
+            .find(|(_, available_name, _)| available_name.eq_ignore_ascii_case(name))
+        {
+            return Some((*same_name, None));
+        }
+
+        let (default_id, default_name, _) = self
+            .available
+            .iter()
+            .find(|(_, _, is_default)| *is_default)
+            .or_else(|| self.available.first())?;
+        Some((*default_id, Some((name.to_string(), default_name.clone()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use op_attachments::{AttachmentConfig, CreateAttachmentParams, MemoryAttachmentStore, MemoryStorage};
+    use op_db::{RepositoryResult, StatusRow, TypeRow};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    struct MockUser {
+        id: Id,
+        admin: bool,
+        project_permissions: HashMap<Id, HashSet<String>>,
+    }
+
+    impl UserContext for MockUser {
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn is_admin(&self) -> bool {
+            self.admin
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, permission: &str, project_id: Id) -> bool {
+            self.project_permissions
+                .get(&project_id)
+                .map(|perms| perms.contains(permission))
+                .unwrap_or(false)
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            false
+        }
+    }
+
+    /// Outcome of a fake copy, shared with the test
+    #[derive(Default)]
+    struct CopyLog {
+        created: Vec<CreateWorkPackageDto>,
+        watchers: Vec<(Id, Id)>,
+        relations: Vec<(Id, Id)>,
+        committed: bool,
+        rolled_back: bool,
+        descriptions: Vec<(Id, String)>,
+    }
+
+    /// In-memory cascade with one custom value per copied work package
+    struct FakeCopy {
+        subtree: Vec<WorkPackageRow>,
+        types: Vec<TypeRow>,
+        enabled_types: Vec<TypeRow>,
+        statuses: Vec<StatusRow>,
+        attachment_ids: HashMap<Id, Vec<Id>>,
+        log: Arc<Mutex<CopyLog>>,
+    }
+
+    impl FakeCopy {
+        fn new(subtree: Vec<WorkPackageRow>) -> (Self, Arc<Mutex<CopyLog>>) {
+            let log = Arc::new(Mutex::new(CopyLog::default()));
+            let copy = Self {
+                subtree,
+                types: vec![type_row(1, "Task", true), type_row(2, "Bug", false)],
+                enabled_types: vec![type_row(1, "Task", true), type_row(2, "Bug", false)],
+                statuses: vec![status_row(1, "New", true), status_row(2, "In progress", false)],
+                attachment_ids: HashMap::new(),
+                log: log.clone(),
+            };
+            (copy, log)
+        }
+    }
+
+    #[async_trait]
+    impl CopyCascade for FakeCopy {
+        type Committed = FakeCommitted;
+
+        async fn find_subtree(&mut self, _id: Id) -> RepositoryResult<Vec<WorkPackageRow>> {
+            Ok(self.subtree.clone())
+        }
+
+        async fn project_exists(&mut self, project_id: Id) -> RepositoryResult<bool> {
+            Ok(project_id < 10)
+        }
+
+        async fn find_types(&mut self, ids: &[Id]) -> RepositoryResult<Vec<TypeRow>> {
+            Ok(self.types.iter().filter(|t| ids.contains(&t.id)).cloned().collect())
+        }
+
+        async fn enabled_types(&mut self, _project_id: Id) -> RepositoryResult<Vec<TypeRow>> {
+            Ok(self.enabled_types.clone())
+        }
+
+        async fn statuses(&mut self) -> RepositoryResult<Vec<StatusRow>> {
+            Ok(self.statuses.clone())
+        }
+
+        async fn create_work_package(&mut self, dto: CreateWorkPackageDto) -> RepositoryResult<WorkPackageRow> {
+            let mut log = self.log.lock().unwrap();
+            let mut row = work_package(200 + log.created.len() as Id, dto.project_id, dto.parent_id);
+            row.subject = dto.subject.clone();
+            row.type_id = dto.type_id;
+            row.status_id = dto.status_id;
+            row.description = dto.description.clone();
+            log.created.push(dto);
+            Ok(row)
+        }
+
+        async fn copy_custom_values(&mut self, _from: Id, _to: Id) -> RepositoryResult<u64> {
+            Ok(1)
+        }
+
+        async fn copy_watchers(&mut self, from: Id, to: Id) -> RepositoryResult<u64> {
+            self.log.lock().unwrap().watchers.push((from, to));
+            Ok(1)
+        }
+
+        async fn copy_relations(&mut self, copies: &[(Id, Id)]) -> RepositoryResult<u64> {
+            self.log.lock().unwrap().relations = copies.to_vec();
+            Ok(1)
+        }
+
+        async fn attachment_ids(&mut self, id: Id) -> RepositoryResult<Vec<Id>> {
+            Ok(self.attachment_ids.get(&id).cloned().unwrap_or_default())
+        }
+
+        async fn commit(self) -> RepositoryResult<FakeCommitted> {
+            self.log.lock().unwrap().committed = true;
+            Ok(FakeCommitted { log: self.log })
+        }
+
+        async fn rollback(self) -> RepositoryResult<()> {
+            self.log.lock().unwrap().rolled_back = true;
+            Ok(())
+        }
+    }
+
+    struct FakeCommitted {
+        log: Arc<Mutex<CopyLog>>,
+    }
+
+    #[async_trait]
+    impl CommittedCopy for FakeCommitted {
+        async fn update_description(&mut self, id: Id, description: &str) -> RepositoryResult<()> {
+            self.log.lock().unwrap().descriptions.push((id, description.to_string()));
+            Ok(())
+        }
+    }
+
+    fn work_package(id: Id, project_id: Id, parent_id: Option<Id>) -> WorkPackageRow {
+        WorkPackageRow {
+            id,
+            subject: format!("Work package {}", id),
+            description: Some("Steps to reproduce".into()),
+            project_id,
+            type_id: 1,
+            status_id: 2,
+            priority_id: Some(3),
+            author_id: 5,
+            assigned_to_id: Some(6),
+            responsible_id: None,
+            start_date: None,
+            due_date: None,
+            estimated_hours: Some(2.0),
+            done_ratio: 40,
+            parent_id,
+            version_id: Some(8),
+            category_id: None,
+            lock_version: 3,
+            duration: None,
+            ignore_non_working_days: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn type_row(id: Id, name: &str, is_default: bool) -> TypeRow {
+        TypeRow {
+            id,
+            name: name.into(),
+            position: id as i32,
+            is_default,
+            is_in_roadmap: true,
+            is_milestone: false,
+            is_standard: false,
+            color_id: None,
+            description: None,
+            form_configuration: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn status_row(id: Id, name: &str, is_default: bool) -> StatusRow {
+        StatusRow {
+            id,
+            name: name.into(),
+            is_closed: false,
+            is_default,
+            is_readonly: false,
+            position: id as i32,
+            default_done_ratio: 0,
+            color_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    type TestAttachments = AttachmentService<MemoryAttachmentStore, MemoryStorage>;
+
+    fn create_attachment_service() -> TestAttachments {
+        AttachmentService::new(
+            Arc::new(MemoryAttachmentStore::new()),
+            Arc::new(MemoryStorage::new()),
+            AttachmentConfig::default(),
+        )
+    }
+
+    fn create_user(projects: &[Id], permissions: &[&str]) -> MockUser {
+        let permissions: HashSet<String> = permissions.iter().map(|p| p.to_string()).collect();
+        MockUser {
+            id: 2,
+            admin: false,
+            project_permissions: projects.iter().map(|id| (*id, permissions.clone())).collect(),
+        }
+    }
+
+    fn create_copying_user() -> MockUser {
+        create_user(&[1, 2], &["view_work_packages", "add_work_packages"])
+    }
+
+    #[tokio::test]
+    async fn test_copy_single_work_package() {
+        let user = create_copying_user();
+        let attachments = create_attachment_service();
+        let params = CreateAttachmentParams::new("trace.log").container(ContainerType::WorkPackage, 100);
+        let attachment_id = attachments.create(params, "trace".into(), 5).await.unwrap().attachment.id.unwrap();
+
+        let (mut copy, log) = FakeCopy::new(vec![work_package(100, 1, Some(99)), work_package(101, 1, Some(100))]);
+        copy.attachment_ids.insert(100, vec![attachment_id]);
+        let metrics = DomainMetrics::new();
+        let service = CopyWorkPackageService::new(&user).with_metrics(&metrics);
+
+        let result = service.call(100, CopyWorkPackageParams::new(), copy, &attachments).await;
+        let copied = result.result().unwrap();
+
+        assert_eq!(copied.work_package.subject, "Copy of Work package 100");
+        // Children are copied only on request
+        assert_eq!(copied.copies, vec![(100, 200)]);
+        assert!(copied.substitutions.is_empty());
+        assert_eq!((copied.custom_values, copied.watchers, copied.relations, copied.attachments), (1, 0, 0, 1));
+        let copies = attachments.get_for_container(ContainerType::WorkPackage, 200).await.unwrap();
+        assert_eq!(copies.len(), 1);
+        assert_eq!(metrics.work_packages_created.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        let log = log.lock().unwrap();
+        assert!(log.committed);
+        let created = &log.created[0];
+        // Same project: parent and version stay, the copying user is the author
+        assert_eq!((created.parent_id, created.version_id), (Some(99), Some(8)));
+        assert_eq!((created.priority_id, created.assigned_to_id, created.done_ratio), (Some(3), Some(6), 40));
+        assert_eq!(created.author_id, user.id);
+    }
+
+    #[tokio::test]
+    async fn test_copy_points_inline_images_to_copied_attachments() {
+        let user = create_copying_user();
+        let attachments = create_attachment_service();
+        let mut attachment_ids = Vec::new();
+        for (container_id, filename) in [(100, "screenshot.png"), (101, "child.png"), (300, "other.png")] {
+            let params = CreateAttachmentParams::new(filename).container(ContainerType::WorkPackage, container_id);
+            attachment_ids.push(attachments.create(params, "png".into(), 5).await.unwrap().attachment.id.unwrap());
+        }
+        let [own, child, other] = attachment_ids[..] else { unreachable!() };
+
+        let mut source = work_package(100, 1, None);
+        source.description = Some(format!(
+            "![](/api/v3/attachments/{own}/content)\n`/api/v3/attachments/{own}/content`\n\
+             ![](/api/v3/attachments/{own}/content) ![](/api/v3/attachments/{other}/content)"
+        ));
+        let mut source_child = work_package(101, 1, Some(100));
+        source_child.description = Some(format!("![](/api/v3/attachments/{child}/content)"));
+        let (mut copy, log) = FakeCopy::new(vec![source, source_child]);
+        copy.attachment_ids.insert(100, vec![own]);
+        copy.attachment_ids.insert(101, vec![child]);
+
+        let params = CopyWorkPackageParams::new().with_children(true);
+        let result = CopyWorkPackageService::new(&user).call(100, params, copy, &attachments).await;
+        let copied = result.result().unwrap();
+
+        let own_copy = attachments.get_for_container(ContainerType::WorkPackage, 200).await.unwrap()[0].id.unwrap();
+        let child_copy = attachments.get_for_container(ContainerType::WorkPackage, 201).await.unwrap()[0].id.unwrap();
+        // Code and attachments of other containers are left alone
+        let expected = format!(
+            "![](/api/v3/attachments/{own_copy}/content)\n`/api/v3/attachments/{own}/content`\n\
+             ![](/api/v3/attachments/{own_copy}/content) ![](/api/v3/attachments/{other}/content)"
+        );
+        assert_eq!(copied.work_package.description.as_deref(), Some(expected.as_str()));
+        assert_eq!(
+            log.lock().unwrap().descriptions,
+            vec![(200, expected.clone()), (201, format!("![](/api/v3/attachments/{child_copy}/content)"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_derives_duration_from_dates() {
+        let user = create_copying_user();
+        let mut source = work_package(100, 1, None);
+        // Friday to Monday
+        source.start_date = chrono::NaiveDate::from_ymd_opt(2024, 1, 5);
+        source.due_date = chrono::NaiveDate::from_ymd_opt(2024, 1, 8);
+        let (copy, log) = FakeCopy::new(vec![source]);
+
+        let result = CopyWorkPackageService::new(&user)
+            .call(100, CopyWorkPackageParams::new(), copy, &create_attachment_service())
+            .await;
+        assert!(result.is_success());
+
+        let log = log.lock().unwrap();
+        let created = &log.created[0];
+        assert_eq!(created.duration, Some(2));
+        assert_eq!(created.journal_cause, Some(op_db::cause_type::DEFAULT_ATTRIBUTE_WRITTEN));
+    }
+
+    #[tokio::test]
+    async fn test_copy_subtree_remaps_parents_and_relations() {
+        let user = create_copying_user();
+        let subtree = vec![work_package(100, 1, None), work_package(101, 1, Some(100)), work_package(102, 1, Some(101))];
+        let (copy, log) = FakeCopy::new(subtree);
+        let params = CopyWorkPackageParams::new()
+            .with_project_id(2)
+            .with_subject_prefix("[Copy] ")
+            .with_children(true)
+            .with_watchers(true);
+
+        let result = CopyWorkPackageService::new(&user)
+            .call(100, params, copy, &create_attachment_service())
+            .await;
+        let copied = result.result().unwrap();
+        assert_eq!(copied.copies, vec![(100, 200), (101, 201), (102, 202)]);
+        assert_eq!((copied.watchers, copied.relations), (3, 1));
+
+        let log = log.lock().unwrap();
+        let subjects: Vec<&str> = log.created.iter().map(|dto| dto.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["[Copy] Work package 100", "Work package 101", "Work package 102"]);
+        let parents: Vec<Option<Id>> = log.created.iter().map(|dto| dto.parent_id).collect();
+        assert_eq!(parents, vec![None, Some(200), Some(201)]);
+        // Versions of the source project do not carry over to another project
+        assert!(log.created.iter().all(|dto| dto.project_id == 2 && dto.version_id.is_none()));
+        assert_eq!(log.relations, copied.copies);
+    }
+
+    #[tokio::test]
+    async fn test_types_map_by_name_and_fall_back_to_default() {
+        let user = create_copying_user();
+        let mut source = work_package(100, 1, None);
+        source.type_id = 2;
+        let mut child = work_package(101, 1, Some(100));
+        child.type_id = 3;
+        let (mut copy, log) = FakeCopy::new(vec![source, child]);
+        copy.types.push(type_row(3, "Epic", false));
+        copy.enabled_types = vec![type_row(7, "Feature", false), type_row(8, "Task", true), type_row(9, "bug", false)];
+
+        let params = CopyWorkPackageParams::new().with_project_id(2).with_children(true);
+        let result = CopyWorkPackageService::new(&user)
+            .call(100, params, copy, &create_attachment_service())
+            .await;
+        let copied = result.result().unwrap();
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.created.iter().map(|dto| dto.type_id).collect::<Vec<_>>(), vec![9, 8]);
+        assert_eq!(
+            copied.substitutions,
+            vec![Substitution {
+                work_package_id: 101,
+                attribute: "type",
+                from: "Epic".into(),
+                to: "Task".into(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_target_project_without_types_rejects_copy() {
+        let user = create_copying_user();
+        let (mut copy, log) = FakeCopy::new(vec![work_package(100, 1, None)]);
+        copy.enabled_types.clear();
+
+        let result = CopyWorkPackageService::new(&user)
+            .call(100, CopyWorkPackageParams::new().with_project_id(2), copy, &create_attachment_service())
+            .await;
+        assert!(result.errors().has_error("type"));
+
+        let log = log.lock().unwrap();
+        assert!(log.created.is_empty());
+        assert!(log.rolled_back);
+    }
+
+    #[tokio::test]
+    async fn test_contract_of_target_project_applies() {
+        // May add work packages in the source project only
+        let user = create_user(&[1], &["view_work_packages", "add_work_packages"]);
+        let (copy, log) = FakeCopy::new(vec![work_package(100, 1, None)]);
+
+        let result = CopyWorkPackageService::new(&user)
+            .call(100, CopyWorkPackageParams::new().with_project_id(2), copy, &create_attachment_service())
+            .await;
+        assert!(result.errors().has_error("base"));
+
+        let log = log.lock().unwrap();
+        assert!(log.created.is_empty());
+        assert!(log.rolled_back);
+        assert!(!log.committed);
+    }
+
+    #[tokio::test]
+    async fn test_copy_of_missing_or_invisible_work_package_fails() {
+        let user = create_copying_user();
+        let (copy, _log) = FakeCopy::new(vec![]);
+        let result = CopyWorkPackageService::new(&user)
+            .call(100, CopyWorkPackageParams::new(), copy, &create_attachment_service())
+            .await;
+        assert!(result.is_failure());
+
+        let stranger = create_user(&[2], &["view_work_packages", "add_work_packages"]);
+        let (copy, log) = FakeCopy::new(vec![work_package(100, 1, None)]);
+        let result = CopyWorkPackageService::new(&stranger)
+            .call(100, CopyWorkPackageParams::new().with_project_id(2), copy, &create_attachment_service())
+            .await;
+        assert!(result.is_failure());
+        assert!(log.lock().unwrap().created.is_empty());
+
+        let (copy, _log) = FakeCopy::new(vec![work_package(100, 1, None)]);
+        let result = CopyWorkPackageService::new(&user)
+            .call(100, CopyWorkPackageParams::new().with_project_id(42), copy, &create_attachment_service())
+            .await;
+        assert!(result.errors().has_error("project"));
+    }
+}
@@ -3,10 +3,12 @@
 //! Mirrors: app/services/work_packages/create_service.rb
 
 use op_contracts::base::UserContext;
+use op_core::metrics::DomainMetrics;
 use op_core::traits::Id;
 
 use crate::result::ServiceResult;
-use super::set_attributes::{SetAttributesService, WorkPackageEntity};
+use super::defaults::WorkPackageDefaults;
+use super::set_attributes::{Scheduling, SetAttributesService, WorkPackageEntity};
 use super::WorkPackageParams;
 
 /// Service for creating work packages
@@ -23,6 +25,10 @@ use super::WorkPackageParams;
 pub struct CreateWorkPackageService<'a, U: UserContext> {
     user: &'a U,
     send_notifications: bool,
+    metrics: Option<&'a DomainMetrics>,
+    scheduling: Scheduling,
+    defaults: WorkPackageDefaults,
+    dry_run: bool,
 }
 
 impl<'a, U: UserContext> CreateWorkPackageService<'a, U> {
@@ -30,6 +36,10 @@ impl<'a, U: UserContext> CreateWorkPackageService<'a, U> {
         Self {
             user,
             send_notifications: true,
+            metrics: None,
+            scheduling: Scheduling::default(),
+            defaults: WorkPackageDefaults::default(),
+            dry_run: false,
         }
     }
 
@@ -37,9 +47,39 @@ impl<'a, U: UserContext> CreateWorkPackageService<'a, U> {
         Self {
             user,
             send_notifications: false,
+            metrics: None,
+            scheduling: Scheduling::default(),
+            defaults: WorkPackageDefaults::default(),
+            dry_run: false,
         }
     }
 
+    /// Record successful operations in the given metrics collector
+    pub fn with_metrics(mut self, metrics: &'a DomainMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Schedule by the given calendar and milestone types
+    pub fn with_scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Fill the attributes not given from the defaults of the type and
+    /// project
+    pub fn with_defaults(mut self, defaults: WorkPackageDefaults) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Validate without persisting, journaling, notifying or recording
+    /// metrics; the result holds the would-be work package, without an id
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
     /// Execute the create operation
     pub fn call(self, params: WorkPackageParams) -> ServiceResult<WorkPackageEntity> {
         // Create new work package with defaults
@@ -48,10 +88,12 @@ impl<'a, U: UserContext> CreateWorkPackageService<'a, U> {
         let work_package = WorkPackageEntity::new(project_id, type_id, self.user.id());
 
         // Set attributes and validate
-        let set_attrs_service = SetAttributesService::new(self.user, work_package);
+        let set_attrs_service = SetAttributesService::new(self.user, work_package)
+            .with_scheduling(self.scheduling.clone())
+            .with_defaults(self.defaults.clone());
         let result = set_attrs_service.call(&params);
 
-        if result.is_failure() {
+        if result.is_failure() || self.dry_run {
             return result;
         }
 
@@ -78,6 +120,10 @@ impl<'a, U: UserContext> CreateWorkPackageService<'a, U> {
         // Add user as watcher (would be implemented with Watchers service)
         // self.set_user_as_watcher(&work_package);
 
+        if let Some(metrics) = self.metrics {
+            metrics.record_work_package_created();
+        }
+
         service_result
     }
 
@@ -223,4 +269,47 @@ mod tests {
         let result = service.call(params);
         assert!(result.is_success());
     }
+
+    #[test]
+    fn test_create_with_defaults() {
+        let user = create_user_with_permission();
+        let defaults = WorkPackageDefaults {
+            type_status_id: Some(4),
+            priority_id: Some(8),
+            ..Default::default()
+        }
+        .assigning_to_author(true);
+
+        let params = WorkPackageParams::new()
+            .with_subject("Defaulted")
+            .with_project_id(1)
+            .with_priority_id(9);
+        let wp = CreateWorkPackageService::new(&user).with_defaults(defaults).call(params).unwrap();
+        assert_eq!((wp.status_id, wp.priority_id, wp.assigned_to_id), (4, 9, Some(user.id)));
+        assert_eq!(wp.derived_attributes, vec!["status_id", "assigned_to_id"]);
+    }
+
+    #[test]
+    fn test_dry_run_leaves_no_trace() {
+        let user = create_admin_user();
+        let metrics = DomainMetrics::new();
+
+        let params = WorkPackageParams::new()
+            .with_subject("Validated only")
+            .with_project_id(1)
+            .with_type_id(1);
+        let result = CreateWorkPackageService::new(&user).with_metrics(&metrics).dry_run().call(params);
+        assert!(result.is_success());
+        let wp = result.result().unwrap();
+        assert_eq!(wp.subject, "Validated only");
+        assert_eq!(wp.id, None);
+        assert_eq!(metrics.work_packages_created.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+        let invalid = CreateWorkPackageService::new(&user)
+            .with_metrics(&metrics)
+            .dry_run()
+            .call(WorkPackageParams::new().with_project_id(1));
+        assert!(invalid.errors().has_error("subject"));
+        assert_eq!(metrics.work_packages_created.load(std::sync::atomic::Ordering::Relaxed), 0);
+    }
 }
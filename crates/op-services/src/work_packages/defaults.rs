@@ -0,0 +1,224 @@
+//! Defaults of new work packages
+//!
+//! Mirrors: the set_default_attributes part of
+//! app/services/work_packages/set_attributes_service.rb
+//!
+//! The attributes a new work package is created without are filled in, each
+//! from the first source configured:
+//!
+//! 1. status: the initial status of the type's workflows, then the default
+//!    status
+//! 2. priority: the default priority
+//! 3. version: the default version of the project
+//! 4. assignee: the author, when the instance assigns new work packages to
+//!    their authors
+//!
+//! Values given by the author are never replaced, and neither are the
+//! version and the assignee when they are given as null.
+
+use op_core::traits::Id;
+use op_db::WorkPackageDefaultsRow;
+
+use super::set_attributes::WorkPackageEntity;
+use super::WorkPackageParams;
+
+/// Values new work packages of a type in a project default to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkPackageDefaults {
+    /// Status the workflows of the type start from
+    pub type_status_id: Option<Id>,
+    pub status_id: Option<Id>,
+    pub priority_id: Option<Id>,
+    /// Default version of the project
+    pub version_id: Option<Id>,
+    /// Whether new work packages are assigned to their authors
+    pub assign_to_author: bool,
+}
+
+impl WorkPackageDefaults {
+    pub fn assigning_to_author(mut self, assign: bool) -> Self {
+        self.assign_to_author = assign;
+        self
+    }
+}
+
+impl From<WorkPackageDefaultsRow> for WorkPackageDefaults {
+    fn from(row: WorkPackageDefaultsRow) -> Self {
+        Self {
+            type_status_id: row.type_status_id,
+            status_id: row.status_id,
+            priority_id: row.priority_id,
+            version_id: row.version_id,
+            assign_to_author: false,
+        }
+    }
+}
+
+/// Fills in the attributes of a new work package not given in the params
+pub struct DefaultsResolver<'a> {
+    defaults: &'a WorkPackageDefaults,
+}
+
+impl<'a> DefaultsResolver<'a> {
+    pub fn new(defaults: &'a WorkPackageDefaults) -> Self {
+        Self { defaults }
+    }
+
+    /// Set the defaults on the model and return the attributes defaulted
+    pub fn apply(&self, model: &mut WorkPackageEntity, params: &WorkPackageParams) -> Vec<&'static str> {
+        let mut defaulted = Vec::new();
+
+        if params.status_id.is_none() {
+            if let Some(status_id) = self.defaults.type_status_id.or(self.defaults.status_id) {
+                model.status_id = status_id;
+                defaulted.push("status_id");
+            }
+        }
+        if params.priority_id.is_none() {
+            if let Some(priority_id) = self.defaults.priority_id {
+                model.priority_id = priority_id;
+                defaulted.push("priority_id");
+            }
+        }
+        if params.version_id.is_none() && !params.is_null("version_id") {
+            if let Some(version_id) = self.defaults.version_id {
+                model.version_id = Some(version_id);
+                defaulted.push("version_id");
+            }
+        }
+        if params.assigned_to_id.is_none() && !params.is_null("assigned_to_id") && self.defaults.assign_to_author {
+            model.assigned_to_id = Some(model.author_id);
+            defaulted.push("assigned_to_id");
+        }
+
+        defaulted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::work_packages::SetAttributesService;
+    use op_contracts::base::UserContext;
+
+    struct Admin;
+
+    impl UserContext for Admin {
+        fn id(&self) -> Id {
+            7
+        }
+
+        fn is_admin(&self) -> bool {
+            true
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+            true
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            true
+        }
+    }
+
+    /// The new work package created from the params, with the attributes
+    /// defaulted
+    fn resolve(defaults: &WorkPackageDefaults, params: &WorkPackageParams) -> (WorkPackageEntity, Vec<&'static str>) {
+        let params = params.clone().with_subject("Defaulted");
+        let model = SetAttributesService::new(&Admin, WorkPackageEntity::new(1, 1, Admin.id()))
+            .with_defaults(defaults.clone())
+            .call(&params)
+            .unwrap();
+        let defaulted = model.derived_attributes.clone();
+        (model, defaulted)
+    }
+
+    #[test]
+    fn test_status_of_the_type_before_the_default_status() {
+        let mut defaults = WorkPackageDefaults {
+            status_id: Some(3),
+            ..Default::default()
+        };
+        let (model, defaulted) = resolve(&defaults, &WorkPackageParams::new());
+        assert_eq!((model.status_id, defaulted), (3, vec!["status_id"]));
+
+        defaults.type_status_id = Some(5);
+        let (model, _) = resolve(&defaults, &WorkPackageParams::new());
+        assert_eq!(model.status_id, 5);
+
+        let (model, defaulted) = resolve(&defaults, &WorkPackageParams::new().with_status_id(4));
+        assert_eq!(model.status_id, 4);
+        assert!(defaulted.is_empty());
+    }
+
+    #[test]
+    fn test_default_priority() {
+        let defaults = WorkPackageDefaults {
+            priority_id: Some(8),
+            ..Default::default()
+        };
+        let (model, defaulted) = resolve(&defaults, &WorkPackageParams::new());
+        assert_eq!((model.priority_id, defaulted), (8, vec!["priority_id"]));
+
+        let (model, defaulted) = resolve(&defaults, &WorkPackageParams::new().with_priority_id(9));
+        assert_eq!(model.priority_id, 9);
+        assert!(defaulted.is_empty());
+    }
+
+    #[test]
+    fn test_default_version_of_the_project() {
+        let defaults = WorkPackageDefaults {
+            version_id: Some(2),
+            ..Default::default()
+        };
+        let (model, defaulted) = resolve(&defaults, &WorkPackageParams::new());
+        assert_eq!((model.version_id, defaulted), (Some(2), vec!["version_id"]));
+
+        let (model, _) = resolve(&defaults, &WorkPackageParams::new().with_version_id(6));
+        assert_eq!(model.version_id, Some(6));
+
+        // Creating without a version on purpose
+        let (model, defaulted) = resolve(&defaults, &WorkPackageParams::new().with_null("version_id"));
+        assert_eq!(model.version_id, None);
+        assert!(defaulted.is_empty());
+    }
+
+    #[test]
+    fn test_assigned_to_the_author_when_configured() {
+        let (model, defaulted) = resolve(&WorkPackageDefaults::default(), &WorkPackageParams::new());
+        assert_eq!(model.assigned_to_id, None);
+        assert!(defaulted.is_empty());
+
+        let defaults = WorkPackageDefaults::default().assigning_to_author(true);
+        let (model, defaulted) = resolve(&defaults, &WorkPackageParams::new());
+        assert_eq!((model.assigned_to_id, defaulted), (Some(7), vec!["assigned_to_id"]));
+
+        let (model, _) = resolve(&defaults, &WorkPackageParams::new().with_assigned_to_id(4));
+        assert_eq!(model.assigned_to_id, Some(4));
+        let (model, _) = resolve(&defaults, &WorkPackageParams::new().with_null("assigned_to_id"));
+        assert_eq!(model.assigned_to_id, None);
+    }
+
+    #[test]
+    fn test_given_values_take_precedence_over_every_source() {
+        let defaults = WorkPackageDefaults {
+            type_status_id: Some(5),
+            status_id: Some(3),
+            priority_id: Some(8),
+            version_id: Some(2),
+            assign_to_author: true,
+        };
+        let params = WorkPackageParams::new().with_priority_id(9).with_null("version_id");
+
+        let (model, defaulted) = resolve(&defaults, &params);
+        assert_eq!(
+            (model.status_id, model.priority_id, model.version_id, model.assigned_to_id),
+            (5, 9, None, Some(7))
+        );
+        assert_eq!(defaulted, vec!["status_id", "assigned_to_id"]);
+    }
+}
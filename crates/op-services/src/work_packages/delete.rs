@@ -1,43 +1,70 @@
 //! Delete Service for Work Packages
 //!
 //! Mirrors: app/services/work_packages/delete_service.rb
+//!
+//! Deleting moves the work package and its subtree to the trash. They are
+//! deleted permanently by `PurgeWorkPackagesService` once the retention
+//! has passed, and can be restored until then. Once the work packages are
+//! in the trash, a `WorkPackageDeleted` event is published for each.
 
 use op_contracts::base::{Contract, UserContext};
 use op_contracts::work_packages::{DeleteWorkPackageContract, DeleteWorkPackageData};
+use op_core::events::{DomainEvent, EventBus};
+use op_core::metrics::DomainMetrics;
 use op_core::traits::Id;
+use op_db::{RepositoryError, RepositoryResult, TrashCascade, WorkPackageWatcherRow};
+use op_notifications::{Notification, NotificationReason, NotificationStore, NotificationType};
+use tracing::warn;
 
 use crate::result::ServiceResult;
 use super::set_attributes::WorkPackageEntity;
 
 /// Service for deleting work packages
 ///
+/// Moves the work package with all its descendants to the trash in one
+/// unit of work and publishes their deletion once it committed.
+///
 /// # Example
 /// ```ignore
-/// let service = DeleteWorkPackageService::new(&user);
-/// let result = service.call(work_package);
+/// let trash = work_packages.begin_trash().await?;
+/// let service = DeleteWorkPackageService::new(&user).with_events(&bus);
+/// let result = service.call(&work_package, trash).await;
 /// ```
 pub struct DeleteWorkPackageService<'a, U: UserContext> {
     user: &'a U,
-    send_notifications: bool,
+    events: Option<&'a EventBus>,
+    metrics: Option<&'a DomainMetrics>,
 }
 
 impl<'a, U: UserContext> DeleteWorkPackageService<'a, U> {
     pub fn new(user: &'a U) -> Self {
         Self {
             user,
-            send_notifications: true,
+            events: None,
+            metrics: None,
         }
     }
 
-    pub fn without_notifications(user: &'a U) -> Self {
-        Self {
-            user,
-            send_notifications: false,
-        }
+    /// Publish the deletion of the trashed work packages on the bus, e.g.
+    /// to notify their watchers
+    pub fn with_events(mut self, events: &'a EventBus) -> Self {
+        self.events = Some(events);
+        self
     }
 
-    /// Execute the delete operation
-    pub fn call(self, work_package: &WorkPackageEntity) -> ServiceResult<()> {
+    /// Record successful operations in the given metrics collector
+    pub fn with_metrics(mut self, metrics: &'a DomainMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Execute the delete operation, returning the ids of the trashed work
+    /// packages, descendants first
+    pub async fn call<T: TrashCascade>(
+        self,
+        work_package: &WorkPackageEntity,
+        mut trash: T,
+    ) -> ServiceResult<Vec<Id>> {
         // Ensure work package exists (has an ID)
         let work_package_id = match work_package.id {
             Some(id) => id,
@@ -48,47 +75,146 @@ impl<'a, U: UserContext> DeleteWorkPackageService<'a, U> {
             }
         };
 
+        let descendant_ids = match trash.find_descendant_ids(work_package_id).await {
+            Ok(ids) => ids,
+            Err(e) => return abort(trash, e).await,
+        };
+
         // Validate delete permission through contract
         let contract =
             DeleteWorkPackageContract::new(self.user, work_package.project_id, work_package_id);
         let delete_data = DeleteWorkPackageData {
             id: work_package_id,
             project_id: work_package.project_id,
+            descendant_count: descendant_ids.len(),
         };
         if let Err(errors) = contract.validate(&delete_data) {
+            let _ = trash.rollback().await;
             return ServiceResult::failure(errors);
         }
 
-        // In a real implementation, this would:
-        // 1. Handle children (delete or orphan them)
-        // 2. Delete attachments
-        // 3. Delete journals
-        // 4. Delete time entries (or re-assign them)
-        // 5. Update ancestors
-        // 6. Delete the work package
-        // 7. Send notifications
+        let mut ids = descendant_ids;
+        ids.push(work_package_id);
+
+        let watchers = match self.trash(&mut trash, &ids).await {
+            Ok(watchers) => watchers,
+            Err(e) => return abort(trash, e).await,
+        };
+
+        if let Err(e) = trash.commit().await {
+            return ServiceResult::failure_with_base_error(format!(
+                "Could not delete the work package: {}",
+                e
+            ));
+        }
+
+        if let Some(bus) = self.events {
+            bus.publish_all(deleted_events(work_package.project_id, self.user.id(), &ids, &watchers)).await;
+        }
+
+        if let Some(metrics) = self.metrics {
+            for _ in &ids {
+                metrics.record_work_package_deleted();
+            }
+        }
+
+        ServiceResult::success(ids)
+    }
+
+    async fn trash<T: TrashCascade>(&self, trash: &mut T, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>> {
+        let watchers = trash.find_watchers(ids).await?;
+        trash.trash_work_packages(ids, self.user.id()).await?;
+        Ok(watchers)
+    }
+}
 
-        let service_result = ServiceResult::success(());
+/// A deletion event per trashed work package. Work packages nobody watches
+/// are recorded in the project of the deleted one.
+fn deleted_events(
+    project_id: Id,
+    actor_id: Id,
+    ids: &[Id],
+    watchers: &[WorkPackageWatcherRow],
+) -> Vec<DomainEvent> {
+    ids.iter()
+        .map(|&work_package_id| {
+            let watching: Vec<&WorkPackageWatcherRow> =
+                watchers.iter().filter(|watcher| watcher.work_package_id == work_package_id).collect();
+            DomainEvent::WorkPackageDeleted {
+                work_package_id,
+                project_id: watching.first().map_or(project_id, |watcher| watcher.project_id),
+                actor_id,
+                watcher_ids: watching.iter().map(|watcher| watcher.user_id).collect(),
+            }
+        })
+        .collect()
+}
 
-        // Handle children (would be recursive delete or orphan)
-        // self.handle_children(work_package, &mut service_result);
+async fn abort<T: TrashCascade>(trash: T, error: RepositoryError) -> ServiceResult<Vec<Id>> {
+    if let Err(e) = trash.rollback().await {
+        warn!(error = %e, "Failed to roll back work package deletion");
+    }
 
-        // Delete the work package (would call database)
-        // self.do_delete(work_package)?;
+    ServiceResult::failure_with_base_error(format!(
+        "Could not delete the work package: {}",
+        error
+    ))
+}
 
-        // Send notifications
-        if self.send_notifications {
-            // Would send delete notifications here
+/// Notify the watchers of work packages about them leaving, each on behalf
+/// of the actor recorded for the work package. Watchers are not notified
+/// about their own actions, and failures do not undo the change.
+pub(super) async fn notify_watchers(
+    store: &dyn NotificationStore,
+    notification_type: NotificationType,
+    watchers: &[WorkPackageWatcherRow],
+    actor_of: impl Fn(Id) -> Option<Id>,
+) {
+    let mut notifications = Vec::new();
+    for watcher in watchers {
+        let actor_id = actor_of(watcher.work_package_id);
+        if actor_id == Some(watcher.user_id) {
+            continue;
         }
 
-        service_result
+        match store.get_settings(watcher.user_id).await {
+            Ok(settings)
+                if settings.should_notify(notification_type, NotificationReason::Watched, Some(watcher.project_id)) =>
+            {
+                let mut notification = Notification::work_package(
+                    watcher.user_id,
+                    notification_type,
+                    NotificationReason::Watched,
+                    watcher.work_package_id,
+                )
+                .with_project(watcher.project_id);
+                if let Some(actor_id) = actor_id {
+                    notification = notification.with_actor(actor_id);
+                }
+                notifications.push(notification);
+            }
+            Ok(_) => {}
+            Err(e) => warn!(recipient_id = watcher.user_id, error = %e, "Failed to load notification settings"),
+        }
+    }
+    if notifications.is_empty() {
+        return;
+    }
+
+    if let Err(e) = store.create_many(&mut notifications).await {
+        warn!(error = %e, "Failed to notify watchers of deleted work packages");
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use async_trait::async_trait;
+    use op_db::TrashedWorkPackageRow;
+    use op_notifications::MemoryNotificationStore;
+    use super::super::events::WatcherNotifications;
     use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
 
     struct MockUser {
         id: Id,
@@ -121,6 +247,77 @@ mod tests {
         }
     }
 
+    /// Outcome of a fake trash, shared with the test
+    #[derive(Default)]
+    struct TrashLog {
+        trashed: Vec<(Id, Id)>,
+        committed: bool,
+        rolled_back: bool,
+    }
+
+    /// In-memory trash where user 5 watches every work package
+    struct FakeTrash {
+        descendants: Vec<Id>,
+        fail: bool,
+        log: Arc<Mutex<TrashLog>>,
+    }
+
+    impl FakeTrash {
+        fn new(descendants: Vec<Id>) -> (Self, Arc<Mutex<TrashLog>>) {
+            let log = Arc::new(Mutex::new(TrashLog::default()));
+            let trash = Self {
+                descendants,
+                fail: false,
+                log: log.clone(),
+            };
+            (trash, log)
+        }
+    }
+
+    #[async_trait]
+    impl TrashCascade for FakeTrash {
+        async fn find_descendant_ids(&mut self, _id: Id) -> RepositoryResult<Vec<Id>> {
+            Ok(self.descendants.clone())
+        }
+
+        async fn trash_work_packages(&mut self, ids: &[Id], user_id: Id) -> RepositoryResult<u64> {
+            if self.fail {
+                return Err(RepositoryError::Conflict("trash failed".into()));
+            }
+            self.log.lock().unwrap().trashed.extend(ids.iter().map(|&id| (id, user_id)));
+            Ok(ids.len() as u64)
+        }
+
+        async fn find_trashed_subtree(&mut self, _id: Id) -> RepositoryResult<Vec<TrashedWorkPackageRow>> {
+            Ok(Vec::new())
+        }
+
+        async fn restore_work_packages(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            Ok(ids.len() as u64)
+        }
+
+        async fn find_watchers(&mut self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>> {
+            Ok(ids
+                .iter()
+                .map(|&work_package_id| WorkPackageWatcherRow {
+                    work_package_id,
+                    project_id: 1,
+                    user_id: 5,
+                })
+                .collect())
+        }
+
+        async fn commit(self) -> RepositoryResult<()> {
+            self.log.lock().unwrap().committed = true;
+            Ok(())
+        }
+
+        async fn rollback(self) -> RepositoryResult<()> {
+            self.log.lock().unwrap().rolled_back = true;
+            Ok(())
+        }
+    }
+
     fn create_admin_user() -> MockUser {
         MockUser {
             id: 1,
@@ -129,19 +326,21 @@ mod tests {
         }
     }
 
-    fn create_user_with_delete_permission() -> MockUser {
-        let mut permissions = std::collections::HashMap::new();
-        let mut project_perms = HashSet::new();
-        project_perms.insert("delete_work_packages".to_string());
-        permissions.insert(1, project_perms);
+    fn create_user_with_permissions(permissions: &[&str]) -> MockUser {
+        let mut project_permissions = std::collections::HashMap::new();
+        project_permissions.insert(1, permissions.iter().map(|p| p.to_string()).collect());
 
         MockUser {
             id: 2,
             admin: false,
-            project_permissions: permissions,
+            project_permissions,
         }
     }
 
+    fn create_user_with_delete_permission() -> MockUser {
+        create_user_with_permissions(&["delete_work_packages"])
+    }
+
     fn create_existing_work_package() -> WorkPackageEntity {
         let mut wp = WorkPackageEntity::new(1, 1, 1);
         wp.id = Some(100);
@@ -149,47 +348,132 @@ mod tests {
         wp
     }
 
-    #[test]
-    fn test_delete_as_admin() {
+    #[tokio::test]
+    async fn test_delete_as_admin() {
         let user = create_admin_user();
         let work_package = create_existing_work_package();
+        let (trash, log) = FakeTrash::new(vec![]);
         let service = DeleteWorkPackageService::new(&user);
 
-        let result = service.call(&work_package);
+        let result = service.call(&work_package, trash).await;
         assert!(result.is_success());
+        assert!(log.lock().unwrap().committed);
     }
 
-    #[test]
-    fn test_delete_with_permission() {
+    #[tokio::test]
+    async fn test_delete_with_permission() {
         let user = create_user_with_delete_permission();
         let work_package = create_existing_work_package();
+        let (trash, _log) = FakeTrash::new(vec![]);
         let service = DeleteWorkPackageService::new(&user);
 
-        let result = service.call(&work_package);
+        let result = service.call(&work_package, trash).await;
         assert!(result.is_success());
     }
 
-    #[test]
-    fn test_cannot_delete_non_existent() {
+    #[tokio::test]
+    async fn test_cannot_delete_non_existent() {
         let user = create_admin_user();
         let work_package = WorkPackageEntity::new(1, 1, 1); // No ID
+        let (trash, _log) = FakeTrash::new(vec![]);
         let service = DeleteWorkPackageService::new(&user);
 
-        let result = service.call(&work_package);
+        let result = service.call(&work_package, trash).await;
         assert!(result.is_failure());
     }
 
-    #[test]
-    fn test_delete_without_permission() {
+    #[tokio::test]
+    async fn test_delete_without_permission() {
         let user = MockUser {
             id: 3,
             admin: false,
             project_permissions: std::collections::HashMap::new(),
         };
         let work_package = create_existing_work_package();
+        let (trash, log) = FakeTrash::new(vec![]);
         let service = DeleteWorkPackageService::new(&user);
 
-        let result = service.call(&work_package);
+        let result = service.call(&work_package, trash).await;
         assert!(result.is_failure());
+
+        let log = log.lock().unwrap();
+        assert!(log.trashed.is_empty());
+        assert!(log.rolled_back);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_descendants_requires_manage_subtasks() {
+        let user = create_user_with_delete_permission();
+        let work_package = create_existing_work_package();
+        let (trash, log) = FakeTrash::new(vec![102, 101]);
+        let service = DeleteWorkPackageService::new(&user);
+
+        let result = service.call(&work_package, trash).await;
+        assert!(result.is_failure());
+        assert!(!log.lock().unwrap().committed);
+
+        let user = create_user_with_permissions(&["delete_work_packages", "manage_subtasks"]);
+        let (trash, _log) = FakeTrash::new(vec![102, 101]);
+        let service = DeleteWorkPackageService::new(&user);
+
+        let result = service.call(&work_package, trash).await;
+        assert!(result.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_delete_trashes_subtree_and_notifies_watchers() {
+        let user = create_admin_user();
+        let work_package = create_existing_work_package();
+        let (trash, log) = FakeTrash::new(vec![102, 101]);
+        let store = Arc::new(MemoryNotificationStore::new());
+        let (bus, recorder) = EventBus::recording();
+        let bus = bus.with_subscriber(Arc::new(WatcherNotifications::new(store.clone())));
+        let metrics = DomainMetrics::new();
+        let service = DeleteWorkPackageService::new(&user)
+            .with_events(&bus)
+            .with_metrics(&metrics);
+
+        let result = service.call(&work_package, trash).await;
+        assert_eq!(result.result().unwrap(), &vec![102, 101, 100]);
+        assert_eq!(log.lock().unwrap().trashed, vec![(102, 1), (101, 1), (100, 1)]);
+        assert_eq!(metrics.work_packages_deleted.load(std::sync::atomic::Ordering::Relaxed), 3);
+
+        let notifications = store.get_for_user(5, false, 10).await.unwrap();
+        assert_eq!(notifications.len(), 3);
+        assert!(notifications
+            .iter()
+            .all(|n| n.notification_type == NotificationType::WorkPackageTrashed && n.actor_id == Some(1)));
+        assert_eq!(
+            recorder.events()[2],
+            DomainEvent::WorkPackageDeleted {
+                work_package_id: 100,
+                project_id: 1,
+                actor_id: 1,
+                watcher_ids: vec![5],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failure_rolls_back_without_notifying() {
+        let user = create_admin_user();
+        let work_package = create_existing_work_package();
+        let (mut trash, log) = FakeTrash::new(vec![101]);
+        trash.fail = true;
+        let store = Arc::new(MemoryNotificationStore::new());
+        let (bus, recorder) = EventBus::recording();
+        let bus = bus.with_subscriber(Arc::new(WatcherNotifications::new(store.clone())));
+        let service = DeleteWorkPackageService::new(&user).with_events(&bus);
+
+        let result = service.call(&work_package, trash).await;
+        assert!(result.is_failure());
+
+        {
+            let log = log.lock().unwrap();
+            assert!(log.rolled_back);
+            assert!(!log.committed);
+        }
+        assert!(store.get_for_user(5, false, 10).await.unwrap().is_empty());
+        assert!(recorder.events().is_empty());
     }
 }
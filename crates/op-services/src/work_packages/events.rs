@@ -0,0 +1,66 @@
+//! Subscribers of work package events
+//!
+//! Mirrors: app/workers/notifications/workflow_job.rb
+//!
+//! Watchers are notified about changes to the work packages they watch by
+//! reacting to the domain events published after the changes committed,
+//! rather than by the services making the changes.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use op_core::events::{DomainEvent, EventResult, EventSubscriber};
+use op_db::WorkPackageWatcherRow;
+use op_notifications::{NotificationStore, NotificationType};
+
+use super::delete::notify_watchers;
+
+/// Name of the [`WatcherNotifications`] subscriber
+pub const WATCHER_NOTIFICATIONS_SUBSCRIBER: &str = "watcher_notifications";
+
+/// Notifies the watchers of trashed work packages, except the user who
+/// trashed them
+pub struct WatcherNotifications {
+    store: Arc<dyn NotificationStore>,
+}
+
+impl WatcherNotifications {
+    pub fn new(store: Arc<dyn NotificationStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for WatcherNotifications {
+    fn name(&self) -> &str {
+        WATCHER_NOTIFICATIONS_SUBSCRIBER
+    }
+
+    fn handles(&self, event: &DomainEvent) -> bool {
+        matches!(event, DomainEvent::WorkPackageDeleted { .. })
+    }
+
+    async fn handle(&self, event: &DomainEvent) -> EventResult<()> {
+        let DomainEvent::WorkPackageDeleted {
+            work_package_id,
+            project_id,
+            actor_id,
+            watcher_ids,
+        } = event
+        else {
+            return Ok(());
+        };
+
+        let watchers: Vec<WorkPackageWatcherRow> = watcher_ids
+            .iter()
+            .map(|&user_id| WorkPackageWatcherRow {
+                work_package_id: *work_package_id,
+                project_id: *project_id,
+                user_id,
+            })
+            .collect();
+        let actor_id = *actor_id;
+        notify_watchers(self.store.as_ref(), NotificationType::WorkPackageTrashed, &watchers, |_| Some(actor_id)).await;
+        Ok(())
+    }
+}
@@ -4,17 +4,36 @@
 //! - app/services/work_packages/create_service.rb
 //! - app/services/work_packages/update_service.rb
 //! - app/services/work_packages/delete_service.rb
+//! - app/services/work_packages/copy_service.rb
 //! - app/services/work_packages/set_attributes_service.rb
+//! - app/services/work_packages/set_schedule_service.rb
+//! - app/models/work_packages/shared/working_days.rb
 
 mod create;
 mod update;
 mod delete;
+mod purge;
+mod restore;
+mod copy;
 mod set_attributes;
+mod defaults;
+mod reschedule;
+mod working_days;
+mod events;
 
 pub use create::CreateWorkPackageService;
 pub use update::UpdateWorkPackageService;
 pub use delete::DeleteWorkPackageService;
-pub use set_attributes::SetAttributesService;
+pub use purge::{DeletionSummary, PurgeWorkPackagesService, TimeEntryPolicy};
+pub use restore::RestoreWorkPackageService;
+pub use copy::{
+    CopiedWorkPackage, CopyWorkPackageParams, CopyWorkPackageService, Substitution, DEFAULT_SUBJECT_PREFIX,
+};
+pub use reschedule::{Dependency, RescheduleService, ScheduledDates};
+pub use set_attributes::{Scheduling, SetAttributesService, WorkPackageEntity};
+pub use defaults::{DefaultsResolver, WorkPackageDefaults};
+pub use working_days::WorkingDays;
+pub use events::{WatcherNotifications, WATCHER_NOTIFICATIONS_SUBSCRIBER};
 
 /// Work package service params
 #[derive(Debug, Clone, Default)]
@@ -29,11 +48,16 @@ pub struct WorkPackageParams {
     pub responsible_id: Option<i64>,
     pub start_date: Option<chrono::NaiveDate>,
     pub due_date: Option<chrono::NaiveDate>,
+    /// Working days from the start to the due date, both included
+    pub duration: Option<i32>,
+    pub ignore_non_working_days: Option<bool>,
     pub estimated_hours: Option<f64>,
     pub done_ratio: Option<i32>,
     pub parent_id: Option<i64>,
     pub version_id: Option<i64>,
     pub category_id: Option<i64>,
+    /// Nullable attributes given as null, e.g. `version_id`
+    pub nulled_attributes: Vec<&'static str>,
     pub send_notifications: bool,
 }
 
@@ -83,6 +107,26 @@ impl WorkPackageParams {
         self
     }
 
+    pub fn with_start_date(mut self, start_date: chrono::NaiveDate) -> Self {
+        self.start_date = Some(start_date);
+        self
+    }
+
+    pub fn with_due_date(mut self, due_date: chrono::NaiveDate) -> Self {
+        self.due_date = Some(due_date);
+        self
+    }
+
+    pub fn with_duration(mut self, duration: i32) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn with_ignore_non_working_days(mut self, ignore: bool) -> Self {
+        self.ignore_non_working_days = Some(ignore);
+        self
+    }
+
     pub fn with_estimated_hours(mut self, hours: f64) -> Self {
         self.estimated_hours = Some(hours);
         self
@@ -98,6 +142,22 @@ impl WorkPackageParams {
         self
     }
 
+    pub fn with_version_id(mut self, version_id: i64) -> Self {
+        self.version_id = Some(version_id);
+        self
+    }
+
+    /// Give the attribute as null, e.g. to create a work package without
+    /// the version it would default to
+    pub fn with_null(mut self, attribute: &'static str) -> Self {
+        self.nulled_attributes.push(attribute);
+        self
+    }
+
+    pub fn is_null(&self, attribute: &str) -> bool {
+        self.nulled_attributes.contains(&attribute)
+    }
+
     pub fn send_notifications(mut self, send: bool) -> Self {
         self.send_notifications = send;
         self
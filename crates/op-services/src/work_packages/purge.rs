@@ -0,0 +1,459 @@
+//! Purge Service for Work Packages
+//!
+//! Deletes work packages permanently once they have been in the trash
+//! for longer than the retention.
+
+use chrono::{DateTime, Utc};
+use op_attachments::{AttachmentService, AttachmentStore, Storage};
+use op_core::traits::Id;
+use op_db::{DeleteCascade, RepositoryError, RepositoryResult, TrashedWorkPackageRow, WorkPackageWatcherRow};
+use op_notifications::{NotificationStore, NotificationType};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::result::ServiceResult;
+use super::delete::notify_watchers;
+
+/// What happens to time booked on deleted work packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeEntryPolicy {
+    /// Delete the time entries with the work packages
+    #[default]
+    Delete,
+    /// Keep the time entries, booked on the project only
+    ReassignToProject,
+}
+
+/// Records removed or changed by a purge
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeletionSummary {
+    /// Deleted work packages, descendants first
+    pub work_package_ids: Vec<Id>,
+    pub journals: u64,
+    pub watchers: u64,
+    pub shares: u64,
+    pub relations: u64,
+    pub file_links: u64,
+    pub attachments: u64,
+    pub time_entries_deleted: u64,
+    pub time_entries_reassigned: u64,
+    pub notifications: u64,
+}
+
+/// Service for deleting work packages from the trash permanently
+///
+/// Deletes the expired work packages, together with the descendants in
+/// the trash, and their dependent records in one unit of work. Attachment
+/// files are removed only once the unit of work is committed.
+///
+/// # Example
+/// ```ignore
+/// let deletion = work_packages.begin_deletion().await?;
+/// let service = PurgeWorkPackagesService::new().with_notifications(&store);
+/// let result = service.call(Utc::now() - retention, deletion, &attachments).await;
+/// ```
+#[derive(Default)]
+pub struct PurgeWorkPackagesService<'a> {
+    time_entry_policy: TimeEntryPolicy,
+    notifications: Option<&'a dyn NotificationStore>,
+}
+
+impl<'a> PurgeWorkPackagesService<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Choose what happens to time booked on the deleted work packages
+    pub fn with_time_entry_policy(mut self, policy: TimeEntryPolicy) -> Self {
+        self.time_entry_policy = policy;
+        self
+    }
+
+    /// Notify the former watchers of the deleted work packages in
+    /// `notifications`
+    pub fn with_notifications(mut self, notifications: &'a dyn NotificationStore) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+
+    /// Delete the work packages moved to the trash before `trashed_before`
+    ///
+    /// Any failure inside the cascade rolls back the whole unit of work.
+    pub async fn call<D, St, S>(
+        self,
+        trashed_before: DateTime<Utc>,
+        mut deletion: D,
+        attachments: &AttachmentService<St, S>,
+    ) -> ServiceResult<DeletionSummary>
+    where
+        D: DeleteCascade,
+        St: AttachmentStore,
+        S: Storage,
+    {
+        let (expired, watchers) = match Self::find_expired(&mut deletion, trashed_before).await {
+            Ok(found) => found,
+            Err(e) => return Self::abort(deletion, e).await,
+        };
+        let ids: Vec<Id> = expired.iter().map(|row| row.id).collect();
+
+        let (mut summary, attachment_ids) = match self.cascade(&mut deletion, ids).await {
+            Ok(cascaded) => cascaded,
+            Err(e) => return Self::abort(deletion, e).await,
+        };
+
+        if let Err(e) = deletion.commit().await {
+            return ServiceResult::failure_with_base_error(format!(
+                "Could not delete the work packages: {}",
+                e
+            ));
+        }
+
+        // Files cannot be rolled back, so they go only after the commit
+        for attachment_id in attachment_ids {
+            match attachments.delete(attachment_id).await {
+                Ok(()) => summary.attachments += 1,
+                Err(e) => warn!(attachment_id, error = %e, "Failed to remove attachment"),
+            }
+        }
+
+        if let Some(store) = self.notifications {
+            let deleted_by: HashMap<Id, Option<Id>> = expired.iter().map(|row| (row.id, row.deleted_by)).collect();
+            notify_watchers(store, NotificationType::WorkPackagePurged, &watchers, |id| {
+                deleted_by.get(&id).copied().flatten()
+            })
+            .await;
+        }
+
+        ServiceResult::success(summary)
+    }
+
+    /// The expired work packages and their watchers, read before the
+    /// watchers are deleted with them
+    async fn find_expired<D: DeleteCascade>(
+        deletion: &mut D,
+        trashed_before: DateTime<Utc>,
+    ) -> RepositoryResult<(Vec<TrashedWorkPackageRow>, Vec<WorkPackageWatcherRow>)> {
+        let expired = deletion.find_expired(trashed_before).await?;
+        let ids: Vec<Id> = expired.iter().map(|row| row.id).collect();
+        let watchers = deletion.find_watchers(&ids).await?;
+        Ok((expired, watchers))
+    }
+
+    /// Remove the work packages and everything depending on them
+    async fn cascade<D: DeleteCascade>(
+        &self,
+        deletion: &mut D,
+        ids: Vec<Id>,
+    ) -> RepositoryResult<(DeletionSummary, Vec<Id>)> {
+        if ids.is_empty() {
+            return Ok((DeletionSummary::default(), Vec::new()));
+        }
+
+        let mut summary = DeletionSummary {
+            journals: deletion.delete_journals(&ids).await?,
+            watchers: deletion.delete_watchers(&ids).await?,
+            shares: deletion.delete_shares(&ids).await?,
+            relations: deletion.delete_relations(&ids).await?,
+            file_links: deletion.delete_file_links(&ids).await?,
+            ..Default::default()
+        };
+
+        let attachment_ids = deletion.attachment_ids(&ids).await?;
+
+        match self.time_entry_policy {
+            TimeEntryPolicy::Delete => {
+                summary.time_entries_deleted = deletion.delete_time_entries(&ids).await?;
+            }
+            TimeEntryPolicy::ReassignToProject => {
+                summary.time_entries_reassigned = deletion.detach_time_entries(&ids).await?;
+            }
+        }
+
+        summary.notifications = deletion.delete_notifications(&ids).await?;
+        deletion.delete_work_packages(&ids).await?;
+        summary.work_package_ids = ids;
+
+        Ok((summary, attachment_ids))
+    }
+
+    async fn abort<D: DeleteCascade>(
+        deletion: D,
+        error: RepositoryError,
+    ) -> ServiceResult<DeletionSummary> {
+        if let Err(e) = deletion.rollback().await {
+            warn!(error = %e, "Failed to roll back work package purge");
+        }
+
+        ServiceResult::failure_with_base_error(format!(
+            "Could not delete the work packages: {}",
+            error
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use op_attachments::{
+        AttachmentConfig, CreateAttachmentParams, ContainerType, MemoryAttachmentStore,
+        MemoryStorage,
+    };
+    use op_notifications::MemoryNotificationStore;
+    use std::sync::{Arc, Mutex};
+
+    /// Outcome of a fake deletion, shared with the test
+    #[derive(Default)]
+    struct DeletionLog {
+        steps: Vec<&'static str>,
+        committed: bool,
+        rolled_back: bool,
+    }
+
+    /// In-memory cascade over a trash, counting one dependent record per
+    /// work package; user 5 watches every work package
+    struct FakeDeletion {
+        trash: Vec<TrashedWorkPackageRow>,
+        attachment_ids: Vec<Id>,
+        fail_at: Option<&'static str>,
+        log: Arc<Mutex<DeletionLog>>,
+    }
+
+    impl FakeDeletion {
+        fn new(trash: Vec<TrashedWorkPackageRow>) -> (Self, Arc<Mutex<DeletionLog>>) {
+            let log = Arc::new(Mutex::new(DeletionLog::default()));
+            let deletion = Self {
+                trash,
+                attachment_ids: Vec::new(),
+                fail_at: None,
+                log: log.clone(),
+            };
+            (deletion, log)
+        }
+
+        fn failing_at(mut self, step: &'static str) -> Self {
+            self.fail_at = Some(step);
+            self
+        }
+
+        fn step(&self, name: &'static str, ids: &[Id]) -> RepositoryResult<u64> {
+            if self.fail_at == Some(name) {
+                return Err(RepositoryError::Conflict(format!("{} failed", name)));
+            }
+            self.log.lock().unwrap().steps.push(name);
+            Ok(ids.len() as u64)
+        }
+    }
+
+    #[async_trait]
+    impl DeleteCascade for FakeDeletion {
+        async fn find_descendant_ids(&mut self, _id: Id) -> RepositoryResult<Vec<Id>> {
+            Ok(Vec::new())
+        }
+
+        async fn find_expired(&mut self, trashed_before: DateTime<Utc>) -> RepositoryResult<Vec<TrashedWorkPackageRow>> {
+            Ok(self
+                .trash
+                .iter()
+                .filter(|row| row.deleted_at < trashed_before)
+                .cloned()
+                .collect())
+        }
+
+        async fn find_watchers(&mut self, ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>> {
+            Ok(ids
+                .iter()
+                .map(|&work_package_id| WorkPackageWatcherRow {
+                    work_package_id,
+                    project_id: 1,
+                    user_id: 5,
+                })
+                .collect())
+        }
+
+        async fn delete_journals(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("journals", ids)
+        }
+
+        async fn delete_watchers(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("watchers", ids)
+        }
+
+        async fn delete_shares(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("shares", ids)
+        }
+
+        async fn delete_relations(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("relations", ids)
+        }
+
+        async fn delete_file_links(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("file_links", ids)
+        }
+
+        async fn attachment_ids(&mut self, ids: &[Id]) -> RepositoryResult<Vec<Id>> {
+            self.step("attachments", ids)?;
+            Ok(self.attachment_ids.clone())
+        }
+
+        async fn delete_time_entries(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("time_entries", ids)
+        }
+
+        async fn detach_time_entries(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("detach_time_entries", ids)
+        }
+
+        async fn delete_notifications(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("notifications", ids)
+        }
+
+        async fn delete_work_packages(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.step("work_packages", ids)
+        }
+
+        async fn commit(self) -> RepositoryResult<()> {
+            self.log.lock().unwrap().committed = true;
+            Ok(())
+        }
+
+        async fn rollback(self) -> RepositoryResult<()> {
+            self.log.lock().unwrap().rolled_back = true;
+            Ok(())
+        }
+    }
+
+    type TestAttachments = AttachmentService<MemoryAttachmentStore, MemoryStorage>;
+
+    fn create_attachment_service() -> TestAttachments {
+        AttachmentService::new(
+            Arc::new(MemoryAttachmentStore::new()),
+            Arc::new(MemoryStorage::new()),
+            AttachmentConfig::default(),
+        )
+    }
+
+    async fn attach(service: &TestAttachments, work_package_id: Id) -> Id {
+        let params = CreateAttachmentParams::new("notes.txt")
+            .container(ContainerType::WorkPackage, work_package_id);
+        let created = service.create(params, "notes".into(), 1).await.unwrap();
+        created.attachment.id.unwrap()
+    }
+
+    fn trashed(id: Id, days_ago: i64) -> TrashedWorkPackageRow {
+        TrashedWorkPackageRow {
+            id,
+            subject: format!("Work package {}", id),
+            project_id: 1,
+            parent_id: None,
+            status_id: 1,
+            deleted_at: Utc::now() - Duration::days(days_ago),
+            deleted_by: Some(1),
+            project_active: true,
+            status_exists: true,
+            parent_trashed: false,
+        }
+    }
+
+    fn retention_cutoff() -> DateTime<Utc> {
+        Utc::now() - Duration::days(30)
+    }
+
+    #[tokio::test]
+    async fn test_purge_deletes_only_expired_trash() {
+        let attachments = create_attachment_service();
+        let attachment_id = attach(&attachments, 101).await;
+        let store = MemoryNotificationStore::new();
+
+        let (mut deletion, log) = FakeDeletion::new(vec![trashed(102, 40), trashed(101, 40), trashed(100, 3)]);
+        deletion.attachment_ids = vec![attachment_id];
+        let service = PurgeWorkPackagesService::new().with_notifications(&store);
+
+        let result = service.call(retention_cutoff(), deletion, &attachments).await;
+        let summary = result.result().unwrap();
+
+        assert_eq!(summary.work_package_ids, vec![102, 101]);
+        assert_eq!(summary.journals, 2);
+        assert_eq!(summary.watchers, 2);
+        assert_eq!(summary.shares, 2);
+        assert_eq!(summary.relations, 2);
+        assert_eq!(summary.attachments, 1);
+        assert_eq!(summary.time_entries_deleted, 2);
+        assert_eq!(summary.time_entries_reassigned, 0);
+        assert_eq!(summary.notifications, 2);
+        assert!(attachments.get(attachment_id).await.unwrap().is_none());
+        assert!(log.lock().unwrap().committed);
+
+        // Watchers hear about the purge, on behalf of who trashed them
+        let notifications = store.get_for_user(5, false, 10).await.unwrap();
+        assert_eq!(notifications.len(), 2);
+        assert!(notifications
+            .iter()
+            .all(|n| n.notification_type == NotificationType::WorkPackagePurged && n.actor_id == Some(1)));
+    }
+
+    #[tokio::test]
+    async fn test_purge_without_expired_trash_deletes_nothing() {
+        let (deletion, log) = FakeDeletion::new(vec![trashed(100, 3)]);
+        let service = PurgeWorkPackagesService::new();
+
+        let result = service
+            .call(retention_cutoff(), deletion, &create_attachment_service())
+            .await;
+        assert!(result.result().unwrap().work_package_ids.is_empty());
+
+        let log = log.lock().unwrap();
+        assert!(log.steps.is_empty());
+        assert!(log.committed);
+    }
+
+    #[tokio::test]
+    async fn test_reassign_time_entries_to_project() {
+        let (deletion, log) = FakeDeletion::new(vec![trashed(100, 40)]);
+        let service = PurgeWorkPackagesService::new().with_time_entry_policy(TimeEntryPolicy::ReassignToProject);
+
+        let result = service
+            .call(retention_cutoff(), deletion, &create_attachment_service())
+            .await;
+        let summary = result.result().unwrap();
+
+        assert_eq!(summary.time_entries_deleted, 0);
+        assert_eq!(summary.time_entries_reassigned, 1);
+        let log = log.lock().unwrap();
+        assert!(log.steps.contains(&"detach_time_entries"));
+        assert!(!log.steps.contains(&"time_entries"));
+    }
+
+    #[tokio::test]
+    async fn test_failure_mid_cascade_rolls_back() {
+        let attachments = create_attachment_service();
+        let attachment_id = attach(&attachments, 100).await;
+        let store = MemoryNotificationStore::new();
+
+        let (mut deletion, log) = FakeDeletion::new(vec![trashed(101, 40), trashed(100, 40)]);
+        deletion.attachment_ids = vec![attachment_id];
+        let deletion = deletion.failing_at("time_entries");
+        let service = PurgeWorkPackagesService::new().with_notifications(&store);
+
+        let result = service.call(retention_cutoff(), deletion, &attachments).await;
+        assert!(result.is_failure());
+        assert!(result
+            .errors()
+            .full_messages()
+            .iter()
+            .any(|m| m.contains("time_entries failed")));
+
+        {
+            let log = log.lock().unwrap();
+            assert!(log.rolled_back);
+            assert!(!log.committed);
+            assert_eq!(log.steps, vec!["journals", "watchers", "shares", "relations", "file_links", "attachments"]);
+        }
+
+        // Nothing outside the unit of work was touched
+        assert!(attachments.get(attachment_id).await.unwrap().is_some());
+        assert!(store.get_for_user(5, false, 10).await.unwrap().is_empty());
+    }
+}
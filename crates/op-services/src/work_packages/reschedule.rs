@@ -0,0 +1,356 @@
+//! Rescheduling of successors
+//!
+//! Mirrors: app/services/work_packages/set_schedule_service.rb
+//!
+//! A work package following another starts once the predecessor finished
+//! and the lag of the relation passed. The lag counts the working days of
+//! the successor's calendar, or all days when the successor ignores
+//! non-working days. A successor starting earlier is moved forward keeping
+//! its duration, and its own successors in turn; one starting later keeps
+//! its dates.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{Duration, NaiveDate};
+use op_core::traits::Id;
+use op_db::work_packages::WorkPackageRow;
+use op_db::{relation_type, RelationRepository, RelationRow, RepositoryResult, WorkPackageRepository};
+
+use super::working_days::WorkingDays;
+
+/// Dates of a work package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledDates {
+    pub id: Id,
+    pub start_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub duration: Option<i32>,
+    pub ignore_non_working_days: bool,
+}
+
+impl From<&WorkPackageRow> for ScheduledDates {
+    fn from(row: &WorkPackageRow) -> Self {
+        Self {
+            id: row.id,
+            start_date: row.start_date,
+            due_date: row.due_date,
+            duration: row.duration,
+            ignore_non_working_days: row.ignore_non_working_days,
+        }
+    }
+}
+
+/// A work package following a predecessor by `lag` working days
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dependency {
+    pub predecessor_id: Id,
+    pub successor_id: Id,
+    pub lag: i32,
+}
+
+impl Dependency {
+    /// The dependency of a precedes or follows relation
+    pub fn of(row: &RelationRow) -> Option<Self> {
+        let (predecessor_id, successor_id) = match row.relation_type.as_str() {
+            relation_type::PRECEDES => (row.from_id, row.to_id),
+            relation_type::FOLLOWS => (row.to_id, row.from_id),
+            _ => return None,
+        };
+        Some(Self {
+            predecessor_id,
+            successor_id,
+            lag: row.lag.unwrap_or(0).max(0),
+        })
+    }
+}
+
+/// Service moving successors to the dates their predecessors allow
+///
+/// # Example
+/// ```ignore
+/// let service = RescheduleService::new();
+/// let moved = service.call(&work_packages, &relations, &[predecessor_id]).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RescheduleService {
+    working_days: WorkingDays,
+    all_days: WorkingDays,
+}
+
+impl Default for RescheduleService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RescheduleService {
+    pub fn new() -> Self {
+        Self {
+            working_days: WorkingDays::default(),
+            all_days: WorkingDays::all_days(),
+        }
+    }
+
+    /// Count lags and durations by the given calendar instead of Monday to
+    /// Friday
+    pub fn with_working_days(mut self, working_days: WorkingDays) -> Self {
+        self.working_days = working_days;
+        self
+    }
+
+    fn calendar(&self, work_package: &ScheduledDates) -> &WorkingDays {
+        if work_package.ignore_non_working_days {
+            &self.all_days
+        } else {
+            &self.working_days
+        }
+    }
+
+    /// Soonest start of `successor` following `predecessor` by `lag`; `None`
+    /// while the predecessor has no dates
+    pub fn soonest_start(&self, predecessor: &ScheduledDates, lag: i32, successor: &ScheduledDates) -> Option<NaiveDate> {
+        let calendar = self.calendar(successor);
+        let mut day = predecessor.due_date.or(predecessor.start_date)?;
+        // The lag's working days pass first, then the successor starts on
+        // the next working day
+        for _ in 0..=lag.max(0) {
+            day += Duration::days(1);
+            while !calendar.is_working_day(day) {
+                day += Duration::days(1);
+            }
+        }
+        Some(day)
+    }
+
+    /// New dates of the work packages moved because the dates of `changed`
+    /// or their relations changed. `work_packages` holds the successors of
+    /// `changed`, transitively, and all of their predecessors.
+    pub fn plan(
+        &self,
+        changed: &[Id],
+        work_packages: &[ScheduledDates],
+        dependencies: &[Dependency],
+    ) -> Vec<ScheduledDates> {
+        let mut schedule: HashMap<Id, ScheduledDates> =
+            work_packages.iter().map(|wp| (wp.id, wp.clone())).collect();
+        let dependencies: Vec<&Dependency> = dependencies
+            .iter()
+            .filter(|dep| schedule.contains_key(&dep.predecessor_id) && schedule.contains_key(&dep.successor_id))
+            .collect();
+
+        // Only the changed work packages and what follows them move
+        let mut affected: HashSet<Id> = changed.iter().copied().collect();
+        let mut frontier: Vec<Id> = changed.to_vec();
+        while let Some(id) = frontier.pop() {
+            for dep in dependencies.iter().filter(|dep| dep.predecessor_id == id) {
+                if affected.insert(dep.successor_id) {
+                    frontier.push(dep.successor_id);
+                }
+            }
+        }
+        let successors: BTreeSet<Id> = dependencies
+            .iter()
+            .map(|dep| dep.successor_id)
+            .filter(|id| affected.contains(id))
+            .collect();
+
+        // Without cycles the longest chain has fewer relations than there
+        // are work packages, so more passes could only go round a cycle
+        let mut moved = BTreeSet::new();
+        for _ in 0..schedule.len() {
+            let mut moved_any = false;
+            for successor_id in &successors {
+                let successor = &schedule[successor_id];
+                let soonest = dependencies
+                    .iter()
+                    .filter(|dep| dep.successor_id == *successor_id)
+                    .filter_map(|dep| self.soonest_start(&schedule[&dep.predecessor_id], dep.lag, successor))
+                    .max();
+                let Some(soonest) = soonest else {
+                    continue;
+                };
+                if successor.start_date.or(successor.due_date).is_none_or(|start| start >= soonest) {
+                    continue;
+                }
+
+                let rescheduled = self.move_to(successor, soonest);
+                schedule.insert(*successor_id, rescheduled);
+                moved.insert(*successor_id);
+                moved_any = true;
+            }
+            if !moved_any {
+                break;
+            }
+        }
+
+        moved.into_iter().map(|id| schedule[&id].clone()).collect()
+    }
+
+    /// The work package starting on `start`, keeping its duration
+    fn move_to(&self, work_package: &ScheduledDates, start: NaiveDate) -> ScheduledDates {
+        let calendar = self.calendar(work_package);
+        let duration = work_package.duration.or_else(|| match (work_package.start_date, work_package.due_date) {
+            (Some(start), Some(due)) => calendar.duration(start, due),
+            _ => None,
+        });
+
+        ScheduledDates {
+            start_date: work_package.start_date.map(|_| start),
+            due_date: work_package.due_date.map(|_| calendar.due_date(start, duration.unwrap_or(1))),
+            ..work_package.clone()
+        }
+    }
+
+    /// Reschedule the successors of the changed work packages, transitively,
+    /// and save the moved ones
+    pub async fn call(
+        &self,
+        work_packages: &WorkPackageRepository,
+        relations: &RelationRepository,
+        changed: &[Id],
+    ) -> RepositoryResult<Vec<WorkPackageRow>> {
+        // Successors are followed further, predecessors only loaded
+        let mut expanded: HashSet<Id> = changed.iter().copied().collect();
+        let mut loaded = expanded.clone();
+        let mut dependencies = HashMap::new();
+        let mut frontier = changed.to_vec();
+        while !frontier.is_empty() {
+            let rows = relations.find_precedes_touching(&frontier).await?;
+            let current: HashSet<Id> = frontier.drain(..).collect();
+            for row in &rows {
+                let Some(dep) = Dependency::of(row) else {
+                    continue;
+                };
+                if current.contains(&dep.predecessor_id) && expanded.insert(dep.successor_id) {
+                    frontier.push(dep.successor_id);
+                }
+                loaded.extend([dep.predecessor_id, dep.successor_id]);
+                dependencies.insert(row.id, dep);
+            }
+        }
+        // The other predecessors of the successors bound their start too
+        let successors: Vec<Id> = expanded.iter().copied().collect();
+        for row in relations.find_precedes_touching(&successors).await? {
+            if let Some(dep) = Dependency::of(&row).filter(|dep| expanded.contains(&dep.successor_id)) {
+                loaded.insert(dep.predecessor_id);
+                dependencies.insert(row.id, dep);
+            }
+        }
+
+        let ids: Vec<Id> = loaded.into_iter().collect();
+        let schedule: Vec<ScheduledDates> = work_packages
+            .find_by_ids(&ids)
+            .await?
+            .iter()
+            .map(ScheduledDates::from)
+            .collect();
+        let dependencies: Vec<Dependency> = dependencies.into_values().collect();
+
+        let mut moved = Vec::new();
+        for dates in self.plan(changed, &schedule, &dependencies) {
+            moved.push(
+                work_packages
+                    .reschedule(dates.id, dates.start_date, dates.due_date, dates.duration)
+                    .await?,
+            );
+        }
+
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        // 2024-01-01 is a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn scheduled(id: Id, start: u32, due: u32, duration: i32) -> ScheduledDates {
+        ScheduledDates {
+            id,
+            start_date: Some(date(start)),
+            due_date: Some(date(due)),
+            duration: Some(duration),
+            ignore_non_working_days: false,
+        }
+    }
+
+    fn follows(predecessor_id: Id, successor_id: Id, lag: i32) -> Dependency {
+        Dependency { predecessor_id, successor_id, lag }
+    }
+
+    #[test]
+    fn test_lag_counts_working_days() {
+        let service = RescheduleService::new();
+        // Due on Friday, two working days of lag on Monday and Tuesday
+        let predecessor = scheduled(1, 1, 5, 5);
+        let successor = scheduled(2, 2, 2, 1);
+        assert_eq!(service.soonest_start(&predecessor, 0, &successor), Some(date(8)));
+        assert_eq!(service.soonest_start(&predecessor, 2, &successor), Some(date(10)));
+
+        // Ignoring non-working days, the weekend counts as lag
+        let successor = ScheduledDates { ignore_non_working_days: true, ..successor };
+        assert_eq!(service.soonest_start(&predecessor, 2, &successor), Some(date(8)));
+
+        let holiday = RescheduleService::new().with_working_days(WorkingDays::default().with_non_working_date(date(9)));
+        assert_eq!(holiday.soonest_start(&predecessor, 2, &scheduled(2, 2, 2, 1)), Some(date(11)));
+    }
+
+    #[test]
+    fn test_chain_with_lag_over_a_weekend() {
+        let service = RescheduleService::new();
+        // 1 is due Thursday; 2 follows with a lag of 2 and lasts 2 days; 3
+        // follows 2 directly
+        let work_packages = [scheduled(1, 1, 4, 4), scheduled(2, 2, 3, 2), scheduled(3, 4, 4, 1)];
+        let dependencies = [follows(1, 2, 2), follows(2, 3, 0)];
+
+        let moved = service.plan(&[1], &work_packages, &dependencies);
+
+        // Friday and Monday are the lag, 2 runs Tuesday to Wednesday and 3
+        // starts on Thursday
+        assert_eq!(moved, vec![scheduled(2, 9, 10, 2), scheduled(3, 11, 11, 1)]);
+    }
+
+    #[test]
+    fn test_editing_lag_reschedules_successor() {
+        let service = RescheduleService::new();
+        // 2 starts on Monday right after 1
+        let work_packages = [scheduled(1, 1, 5, 5), scheduled(2, 8, 9, 2)];
+        assert!(service.plan(&[2], &work_packages, &[follows(1, 2, 0)]).is_empty());
+
+        let moved = service.plan(&[2], &work_packages, &[follows(1, 2, 3)]);
+        assert_eq!(moved, vec![scheduled(2, 11, 12, 2)]);
+    }
+
+    #[test]
+    fn test_successors_starting_later_and_unrelated_keep_their_dates() {
+        let service = RescheduleService::new();
+        let work_packages = [scheduled(1, 1, 2, 2), scheduled(2, 15, 16, 2), scheduled(3, 1, 1, 1)];
+        let dependencies = [follows(1, 2, 1), follows(1, 3, 0)];
+
+        // 3 is not affected by a change of 2
+        assert!(service.plan(&[2], &work_packages, &dependencies).is_empty());
+        assert_eq!(service.plan(&[1], &work_packages, &dependencies), vec![scheduled(3, 3, 3, 1)]);
+    }
+
+    #[test]
+    fn test_latest_predecessor_bounds_the_start() {
+        let service = RescheduleService::new();
+        let work_packages = [scheduled(1, 1, 2, 2), scheduled(2, 1, 9, 7), scheduled(3, 1, 1, 1)];
+        let dependencies = [follows(1, 3, 0), follows(2, 3, 0)];
+
+        assert_eq!(service.plan(&[1], &work_packages, &dependencies), vec![scheduled(3, 10, 10, 1)]);
+    }
+
+    #[test]
+    fn test_cycles_terminate() {
+        let service = RescheduleService::new();
+        let work_packages = [scheduled(1, 1, 1, 1), scheduled(2, 1, 1, 1)];
+        let dependencies = [follows(1, 2, 0), follows(2, 1, 0)];
+
+        assert_eq!(service.plan(&[1], &work_packages, &dependencies).len(), 2);
+    }
+}
@@ -0,0 +1,232 @@
+//! Restore Service for Work Packages
+//!
+//! Takes work packages out of the trash again.
+
+use op_contracts::base::{Contract, UserContext};
+use op_contracts::work_packages::{RestoreWorkPackageContract, RestoreWorkPackageData};
+use op_core::traits::Id;
+use op_db::{RepositoryError, TrashCascade};
+use tracing::warn;
+
+use crate::result::ServiceResult;
+
+/// Service for restoring work packages from the trash
+///
+/// Restores the work package together with the descendants that were
+/// trashed with it. Descendants trashed on their own before stay in the
+/// trash. Fails if the project is archived, a status is gone or the parent
+/// is still in the trash.
+///
+/// # Example
+/// ```ignore
+/// let trash = work_packages.begin_trash().await?;
+/// let result = RestoreWorkPackageService::new(&user).call(id, trash).await;
+/// ```
+pub struct RestoreWorkPackageService<'a, U: UserContext> {
+    user: &'a U,
+}
+
+impl<'a, U: UserContext> RestoreWorkPackageService<'a, U> {
+    pub fn new(user: &'a U) -> Self {
+        Self { user }
+    }
+
+    /// Execute the restore operation, returning the ids of the restored
+    /// work packages, parents first
+    pub async fn call<T: TrashCascade>(self, work_package_id: Id, mut trash: T) -> ServiceResult<Vec<Id>> {
+        let subtree = match trash.find_trashed_subtree(work_package_id).await {
+            Ok(subtree) => subtree,
+            Err(e) => return abort(trash, e).await,
+        };
+        let Some(root) = subtree.first() else {
+            let _ = trash.rollback().await;
+            return ServiceResult::failure_with_base_error("The work package is not in the trash");
+        };
+
+        let contract = RestoreWorkPackageContract::new(self.user, root.project_id);
+        let restore_data = RestoreWorkPackageData {
+            id: root.id,
+            project_id: root.project_id,
+            descendant_count: subtree.len() - 1,
+            project_active: subtree.iter().all(|row| row.project_active),
+            statuses_exist: subtree.iter().all(|row| row.status_exists),
+            parent_trashed: root.parent_trashed,
+        };
+        if let Err(errors) = contract.validate(&restore_data) {
+            let _ = trash.rollback().await;
+            return ServiceResult::failure(errors);
+        }
+
+        let ids: Vec<Id> = subtree.iter().map(|row| row.id).collect();
+        if let Err(e) = trash.restore_work_packages(&ids).await {
+            return abort(trash, e).await;
+        }
+
+        if let Err(e) = trash.commit().await {
+            return ServiceResult::failure_with_base_error(format!(
+                "Could not restore the work package: {}",
+                e
+            ));
+        }
+
+        ServiceResult::success(ids)
+    }
+}
+
+async fn abort<T: TrashCascade>(trash: T, error: RepositoryError) -> ServiceResult<Vec<Id>> {
+    if let Err(e) = trash.rollback().await {
+        warn!(error = %e, "Failed to roll back work package restore");
+    }
+
+    ServiceResult::failure_with_base_error(format!(
+        "Could not restore the work package: {}",
+        error
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use op_db::{RepositoryResult, TrashedWorkPackageRow, WorkPackageWatcherRow};
+    use std::sync::{Arc, Mutex};
+
+    struct AdminUser;
+
+    impl UserContext for AdminUser {
+        fn id(&self) -> Id {
+            1
+        }
+
+        fn is_admin(&self) -> bool {
+            true
+        }
+
+        fn is_anonymous(&self) -> bool {
+            false
+        }
+
+        fn allowed_in_project(&self, _permission: &str, _project_id: Id) -> bool {
+            true
+        }
+
+        fn allowed_globally(&self, _permission: &str) -> bool {
+            true
+        }
+    }
+
+    #[derive(Default)]
+    struct RestoreLog {
+        restored: Vec<Id>,
+        committed: bool,
+        rolled_back: bool,
+    }
+
+    struct FakeTrash {
+        subtree: Vec<TrashedWorkPackageRow>,
+        log: Arc<Mutex<RestoreLog>>,
+    }
+
+    impl FakeTrash {
+        fn new(subtree: Vec<TrashedWorkPackageRow>) -> (Self, Arc<Mutex<RestoreLog>>) {
+            let log = Arc::new(Mutex::new(RestoreLog::default()));
+            (Self { subtree, log: log.clone() }, log)
+        }
+    }
+
+    #[async_trait]
+    impl TrashCascade for FakeTrash {
+        async fn find_descendant_ids(&mut self, _id: Id) -> RepositoryResult<Vec<Id>> {
+            Ok(Vec::new())
+        }
+
+        async fn trash_work_packages(&mut self, ids: &[Id], _user_id: Id) -> RepositoryResult<u64> {
+            Ok(ids.len() as u64)
+        }
+
+        async fn find_trashed_subtree(&mut self, _id: Id) -> RepositoryResult<Vec<TrashedWorkPackageRow>> {
+            Ok(self.subtree.clone())
+        }
+
+        async fn restore_work_packages(&mut self, ids: &[Id]) -> RepositoryResult<u64> {
+            self.log.lock().unwrap().restored.extend_from_slice(ids);
+            Ok(ids.len() as u64)
+        }
+
+        async fn find_watchers(&mut self, _ids: &[Id]) -> RepositoryResult<Vec<WorkPackageWatcherRow>> {
+            Ok(Vec::new())
+        }
+
+        async fn commit(self) -> RepositoryResult<()> {
+            self.log.lock().unwrap().committed = true;
+            Ok(())
+        }
+
+        async fn rollback(self) -> RepositoryResult<()> {
+            self.log.lock().unwrap().rolled_back = true;
+            Ok(())
+        }
+    }
+
+    fn trashed(id: Id, parent_id: Option<Id>) -> TrashedWorkPackageRow {
+        TrashedWorkPackageRow {
+            id,
+            subject: format!("Work package {}", id),
+            project_id: 1,
+            parent_id,
+            status_id: 1,
+            deleted_at: Utc::now(),
+            deleted_by: Some(1),
+            project_active: true,
+            status_exists: true,
+            parent_trashed: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_subtree() {
+        let (trash, log) = FakeTrash::new(vec![trashed(100, None), trashed(101, Some(100))]);
+
+        let result = RestoreWorkPackageService::new(&AdminUser).call(100, trash).await;
+        assert_eq!(result.result().unwrap(), &vec![100, 101]);
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.restored, vec![100, 101]);
+        assert!(log.committed);
+    }
+
+    #[tokio::test]
+    async fn test_restore_fails_outside_the_trash() {
+        let (trash, log) = FakeTrash::new(Vec::new());
+
+        let result = RestoreWorkPackageService::new(&AdminUser).call(100, trash).await;
+        assert!(result.is_failure());
+        assert!(log.lock().unwrap().rolled_back);
+    }
+
+    #[tokio::test]
+    async fn test_restore_fails_while_parent_is_trashed() {
+        let mut child = trashed(101, Some(100));
+        child.parent_trashed = true;
+        let (trash, log) = FakeTrash::new(vec![child]);
+
+        let result = RestoreWorkPackageService::new(&AdminUser).call(101, trash).await;
+        assert!(result.errors().has_error("parent"));
+
+        let log = log.lock().unwrap();
+        assert!(log.restored.is_empty());
+        assert!(log.rolled_back);
+    }
+
+    #[tokio::test]
+    async fn test_restore_fails_when_a_status_is_gone() {
+        let mut child = trashed(101, Some(100));
+        child.status_exists = false;
+        let (trash, log) = FakeTrash::new(vec![trashed(100, None), child]);
+
+        let result = RestoreWorkPackageService::new(&AdminUser).call(100, trash).await;
+        assert!(result.errors().has_error("status"));
+        assert!(log.lock().unwrap().restored.is_empty());
+    }
+}
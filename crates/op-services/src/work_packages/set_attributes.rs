@@ -1,13 +1,23 @@
 //! Set Attributes Service for Work Packages
 //!
 //! Mirrors: app/services/work_packages/set_attributes_service.rb
+//!
+//! Besides assigning the params, the service keeps the start date, the due
+//! date and the duration consistent: of the three, the one not sent is
+//! derived from the others on the working days calendar. New work packages
+//! get the defaults of their type and project for the attributes not sent.
+
+use std::collections::HashSet;
 
 use op_contracts::base::UserContext;
-use op_contracts::work_packages::{CreateWorkPackageContract, WorkPackageData};
+use op_contracts::work_packages::{CreateWorkPackageContract, UpdateWorkPackageContract, WorkPackageData};
 use op_core::error::ValidationErrors;
 use op_core::traits::Id;
+use op_db::cause_type;
 
 use crate::result::ServiceResult;
+use super::defaults::{DefaultsResolver, WorkPackageDefaults};
+use super::working_days::WorkingDays;
 use super::WorkPackageParams;
 
 /// Work package entity for service operations
@@ -25,12 +35,18 @@ pub struct WorkPackageEntity {
     pub responsible_id: Option<Id>,
     pub start_date: Option<chrono::NaiveDate>,
     pub due_date: Option<chrono::NaiveDate>,
+    /// Working days from the start to the due date, both included
+    pub duration: Option<i32>,
+    /// Whether weekends and non-working days count towards the duration
+    pub ignore_non_working_days: bool,
     pub estimated_hours: Option<f64>,
     pub done_ratio: i32,
     pub parent_id: Option<Id>,
     pub version_id: Option<Id>,
     pub category_id: Option<Id>,
     pub lock_version: i32,
+    /// Attributes the service derived rather than took from the params
+    pub derived_attributes: Vec<&'static str>,
 }
 
 impl WorkPackageEntity {
@@ -48,18 +64,27 @@ impl WorkPackageEntity {
             responsible_id: None,
             start_date: None,
             due_date: None,
+            duration: None,
+            ignore_non_working_days: false,
             estimated_hours: None,
             done_ratio: 0,
             parent_id: None,
             version_id: None,
             category_id: None,
             lock_version: 0,
+            derived_attributes: Vec::new(),
         }
     }
 
     pub fn is_new(&self) -> bool {
         self.id.is_none()
     }
+
+    /// Cause of the journal recording the changes: values filled in by the
+    /// service are journaled as default attributes written
+    pub fn journal_cause(&self) -> Option<&'static str> {
+        (!self.derived_attributes.is_empty()).then_some(cause_type::DEFAULT_ATTRIBUTE_WRITTEN)
+    }
 }
 
 /// Implement WorkPackageData trait for WorkPackageEntity
@@ -115,28 +140,101 @@ impl WorkPackageData for WorkPackageEntity {
     fn lock_version(&self) -> i32 {
         self.lock_version
     }
+
+    fn start_date(&self) -> Option<chrono::NaiveDate> {
+        self.start_date
+    }
+
+    fn due_date(&self) -> Option<chrono::NaiveDate> {
+        self.due_date
+    }
+
+    fn duration(&self) -> Option<i32> {
+        self.duration
+    }
+}
+
+/// Calendar and types the dates of work packages are scheduled by
+#[derive(Debug, Clone, Default)]
+pub struct Scheduling {
+    pub working_days: WorkingDays,
+    /// Types whose work packages are milestones, lasting a single day
+    pub milestone_type_ids: HashSet<Id>,
+}
+
+impl Scheduling {
+    pub fn new(working_days: WorkingDays) -> Self {
+        Self {
+            working_days,
+            milestone_type_ids: HashSet::new(),
+        }
+    }
+
+    pub fn with_milestone_types(mut self, type_ids: impl IntoIterator<Item = Id>) -> Self {
+        self.milestone_type_ids.extend(type_ids);
+        self
+    }
+
+    pub fn is_milestone(&self, type_id: Id) -> bool {
+        self.milestone_type_ids.contains(&type_id)
+    }
 }
 
 /// Service for setting attributes on a work package
 pub struct SetAttributesService<'a, U: UserContext> {
     user: &'a U,
     model: WorkPackageEntity,
+    scheduling: Scheduling,
+    defaults: WorkPackageDefaults,
 }
 
 impl<'a, U: UserContext> SetAttributesService<'a, U> {
     pub fn new(user: &'a U, model: WorkPackageEntity) -> Self {
-        Self { user, model }
+        Self {
+            user,
+            model,
+            scheduling: Scheduling::default(),
+            defaults: WorkPackageDefaults::default(),
+        }
+    }
+
+    /// Schedule by the given calendar and milestone types instead of
+    /// Monday to Friday without milestones
+    pub fn with_scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Fill the attributes of new work packages not sent from the given
+    /// defaults; without, only the sent attributes are set
+    pub fn with_defaults(mut self, defaults: WorkPackageDefaults) -> Self {
+        self.defaults = defaults;
+        self
     }
 
     /// Set attributes from params and validate
     pub fn call(mut self, params: &WorkPackageParams) -> ServiceResult<WorkPackageEntity> {
+        let ignore_changed = params
+            .ignore_non_working_days
+            .is_some_and(|ignore| ignore != self.model.ignore_non_working_days);
+
         // Set attributes from params
         self.set_attributes(params);
+        if self.model.is_new() {
+            let defaulted = DefaultsResolver::new(&self.defaults).apply(&mut self.model, params);
+            self.model.derived_attributes.extend(defaulted);
+        }
+
+        // Derive the schedule attributes not sent
+        let mut errors = ValidationErrors::new();
+        self.update_schedule(params, ignore_changed, &mut errors);
 
         // Run contract validation
-        let validation_result = self.validate_create();
+        if let Err(contract_errors) = self.validate_contract() {
+            errors.merge(contract_errors);
+        }
 
-        if let Err(errors) = validation_result {
+        if !errors.is_empty() {
             return ServiceResult::failure(errors);
         }
 
@@ -174,6 +272,12 @@ impl<'a, U: UserContext> SetAttributesService<'a, U> {
         if let Some(due_date) = params.due_date {
             self.model.due_date = Some(due_date);
         }
+        if let Some(duration) = params.duration {
+            self.model.duration = Some(duration);
+        }
+        if let Some(ignore) = params.ignore_non_working_days {
+            self.model.ignore_non_working_days = ignore;
+        }
         if let Some(estimated_hours) = params.estimated_hours {
             self.model.estimated_hours = Some(estimated_hours);
         }
@@ -189,13 +293,112 @@ impl<'a, U: UserContext> SetAttributesService<'a, U> {
         if let Some(category_id) = params.category_id {
             self.model.category_id = Some(category_id);
         }
+        for attribute in &params.nulled_attributes {
+            match *attribute {
+                "assigned_to_id" => self.model.assigned_to_id = None,
+                "responsible_id" => self.model.responsible_id = None,
+                "version_id" => self.model.version_id = None,
+                "category_id" => self.model.category_id = None,
+                "parent_id" => self.model.parent_id = None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Keep start date, due date and duration consistent
+    ///
+    /// Sent values win over stored ones: with two of the three sent, the
+    /// third is derived from them, and a sent duration moves the due date,
+    /// or the start date when only the due date is known. Changing whether
+    /// non-working days are ignored moves the due date to keep the
+    /// duration. Milestones last a single day.
+    fn update_schedule(&mut self, params: &WorkPackageParams, ignore_changed: bool, errors: &mut ValidationErrors) {
+        let calendar = if self.model.ignore_non_working_days {
+            WorkingDays::all_days()
+        } else {
+            self.scheduling.working_days.clone()
+        };
+        let before = (self.model.start_date, self.model.due_date, self.model.duration);
+
+        for (attribute, date) in [("start_date", params.start_date), ("due_date", params.due_date)] {
+            if date.is_some_and(|date| !calendar.is_working_day(date)) {
+                errors.add(attribute, "can't be a non-working day");
+            }
+        }
+        // Non-positive durations are reported by the contract
+        if !errors.is_empty() || params.duration.is_some_and(|duration| duration < 1) {
+            return;
+        }
+
+        if self.scheduling.is_milestone(self.model.type_id) {
+            if let (Some(start), Some(due)) = (params.start_date, params.due_date) {
+                if start != due {
+                    errors.add("due_date", "must be the same as the start date for milestones");
+                    return;
+                }
+            }
+            if params.duration.is_some_and(|duration| duration != 1) {
+                errors.add("duration", "must be 1 day for milestones");
+                return;
+            }
+            let date = if params.start_date.is_none() && params.due_date.is_some() {
+                self.model.due_date
+            } else {
+                self.model.start_date.or(self.model.due_date)
+            };
+            self.model.start_date = date;
+            self.model.due_date = date;
+            self.model.duration = Some(1);
+        } else if let (Some(start), Some(due)) = (params.start_date, params.due_date) {
+            let duration = calendar.duration(start, due);
+            match params.duration {
+                Some(sent) if duration != Some(sent) => {
+                    errors.add("duration", "does not match the working days between the start and finish dates");
+                    return;
+                }
+                _ => self.model.duration = duration,
+            }
+        } else if let Some(duration) = params
+            .duration
+            .or_else(|| self.model.duration.filter(|_| ignore_changed))
+        {
+            match (self.model.start_date, self.model.due_date) {
+                (Some(start), _) if params.due_date.is_none() => {
+                    self.model.due_date = Some(calendar.due_date(start, duration));
+                }
+                (_, Some(due)) => self.model.start_date = Some(calendar.start_date(due, duration)),
+                _ => {}
+            }
+        } else if params.start_date.is_some() || params.due_date.is_some() {
+            match (self.model.start_date, self.model.due_date, self.model.duration) {
+                (Some(start), Some(due), _) => self.model.duration = calendar.duration(start, due),
+                (Some(start), None, Some(duration)) => self.model.due_date = Some(calendar.due_date(start, duration)),
+                (None, Some(due), Some(duration)) => self.model.start_date = Some(calendar.start_date(due, duration)),
+                _ => {}
+            }
+        }
+
+        let after = (self.model.start_date, self.model.due_date, self.model.duration);
+        for (attribute, changed, sent) in [
+            ("start_date", before.0 != after.0, params.start_date.is_some()),
+            ("due_date", before.1 != after.1, params.due_date.is_some()),
+            ("duration", before.2 != after.2, params.duration.is_some()),
+        ] {
+            if changed && !sent {
+                self.model.derived_attributes.push(attribute);
+            }
+        }
     }
 
-    fn validate_create(&self) -> Result<(), ValidationErrors> {
+    /// Validate against the update contract for saved work packages, the
+    /// create contract for new ones
+    fn validate_contract(&self) -> Result<(), ValidationErrors> {
         use op_contracts::base::Contract;
 
-        let contract = CreateWorkPackageContract::new(self.user, self.model.project_id);
-        contract.validate(&self.model)
+        match self.model.id {
+            Some(id) => UpdateWorkPackageContract::new(self.user, self.model.project_id, id).validate(&self.model),
+            None => CreateWorkPackageContract::new(self.user, self.model.project_id).validate(&self.model),
+        }
     }
 }
 
@@ -273,4 +476,168 @@ mod tests {
         assert!(result.is_failure());
         assert!(result.errors().has_error("subject"));
     }
+
+    #[test]
+    fn test_defaults_fill_new_work_packages_only() {
+        let user = create_admin_user();
+        let defaults = WorkPackageDefaults {
+            status_id: Some(3),
+            version_id: Some(2),
+            ..Default::default()
+        };
+        let params = WorkPackageParams::new().with_subject("Defaulted");
+
+        let wp = SetAttributesService::new(&user, WorkPackageEntity::new(1, 1, user.id))
+            .with_defaults(defaults.clone())
+            .call(&params)
+            .unwrap();
+        assert_eq!((wp.status_id, wp.version_id), (3, Some(2)));
+        assert_eq!(wp.derived_attributes, vec!["status_id", "version_id"]);
+        assert_eq!(wp.journal_cause(), Some(cause_type::DEFAULT_ATTRIBUTE_WRITTEN));
+
+        let mut saved = WorkPackageEntity::new(1, 1, user.id);
+        saved.id = Some(10);
+        let wp = SetAttributesService::new(&user, saved).with_defaults(defaults).call(&params).unwrap();
+        assert_eq!((wp.status_id, wp.version_id), (1, None));
+        assert_eq!(wp.journal_cause(), None);
+    }
+
+    #[test]
+    fn test_null_params_clear_attributes() {
+        let user = create_admin_user();
+        let mut saved = WorkPackageEntity::new(1, 1, user.id);
+        saved.subject = "Planned".into();
+        saved.version_id = Some(2);
+        saved.assigned_to_id = Some(4);
+
+        let params = WorkPackageParams::new().with_null("version_id");
+        let wp = SetAttributesService::new(&user, saved).call(&params).unwrap();
+        assert_eq!((wp.version_id, wp.assigned_to_id), (None, Some(4)));
+    }
+
+    fn date(day: u32) -> chrono::NaiveDate {
+        // 2024-01-01 is a Monday
+        chrono::NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn scheduled(start: Option<u32>, due: Option<u32>, duration: Option<i32>) -> WorkPackageEntity {
+        let mut entity = WorkPackageEntity::new(1, 1, 1);
+        entity.subject = "Plan".into();
+        entity.start_date = start.map(date);
+        entity.due_date = due.map(date);
+        entity.duration = duration;
+        entity
+    }
+
+    fn schedule_params(start: Option<u32>, due: Option<u32>, duration: Option<i32>) -> WorkPackageParams {
+        WorkPackageParams {
+            start_date: start.map(date),
+            due_date: due.map(date),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_schedule_derives_missing_attribute() {
+        let user = create_admin_user();
+        let new = scheduled(None, None, None);
+        let planned = scheduled(Some(4), Some(8), Some(3));
+        // (stored, sent start/due/duration, expected start/due/duration, derived)
+        let cases = vec![
+            // Friday to Monday spans a weekend
+            (&new, schedule_params(Some(5), Some(8), None), (5, 8, 2), vec!["duration"]),
+            (&new, schedule_params(Some(4), None, Some(3)), (4, 8, 3), vec!["due_date"]),
+            (&new, schedule_params(None, Some(9), Some(3)), (5, 9, 3), vec!["start_date"]),
+            (&new, schedule_params(Some(5), Some(8), Some(2)), (5, 8, 2), vec![]),
+            (&new, schedule_params(Some(1), Some(12), None), (1, 12, 10), vec!["duration"]),
+            // Sent durations move the due date
+            (&planned, schedule_params(None, None, Some(5)), (4, 10, 5), vec!["due_date"]),
+            (&planned, schedule_params(Some(2), None, None), (2, 8, 5), vec!["duration"]),
+            (&planned, schedule_params(None, Some(12), None), (4, 12, 7), vec!["duration"]),
+            (&planned, schedule_params(None, Some(12), Some(2)), (11, 12, 2), vec!["start_date"]),
+        ];
+        for (stored, params, (start, due, duration), derived) in cases {
+            let result = SetAttributesService::new(&user, stored.clone()).call(&params);
+            let wp = result.result().unwrap_or_else(|| panic!("{:?} fails", params));
+            assert_eq!(
+                (wp.start_date, wp.due_date, wp.duration),
+                (Some(date(start)), Some(date(due)), Some(duration)),
+                "{:?}",
+                params
+            );
+            assert_eq!(wp.derived_attributes, derived, "{:?}", params);
+        }
+    }
+
+    #[test]
+    fn test_schedule_ignoring_non_working_days() {
+        let user = create_admin_user();
+
+        let params = schedule_params(Some(5), None, Some(3)).with_ignore_non_working_days(true);
+        let result = SetAttributesService::new(&user, scheduled(None, None, None)).call(&params);
+        let wp = result.result().unwrap();
+        assert_eq!(wp.due_date, Some(date(7)));
+
+        // Changing the flag keeps the duration and moves the due date
+        let params = WorkPackageParams::new().with_ignore_non_working_days(true);
+        let result = SetAttributesService::new(&user, scheduled(Some(4), Some(8), Some(3))).call(&params);
+        let wp = result.result().unwrap();
+        assert_eq!((wp.due_date, wp.duration), (Some(date(6)), Some(3)));
+        assert_eq!(wp.journal_cause(), Some(cause_type::DEFAULT_ATTRIBUTE_WRITTEN));
+
+        let mut stored = scheduled(Some(4), Some(6), Some(3));
+        stored.ignore_non_working_days = true;
+        let params = WorkPackageParams::new().with_ignore_non_working_days(false);
+        let wp = SetAttributesService::new(&user, stored).call(&params).unwrap();
+        assert_eq!(wp.due_date, Some(date(8)));
+    }
+
+    #[test]
+    fn test_schedule_errors() {
+        let user = create_admin_user();
+        // (sent start/due/duration, attribute in error)
+        let cases = [
+            (schedule_params(Some(5), Some(8), Some(4)), "duration"),
+            (schedule_params(Some(6), None, Some(2)), "start_date"),
+            (schedule_params(Some(4), Some(7), None), "due_date"),
+            (schedule_params(Some(8), Some(5), None), "due_date"),
+            (schedule_params(Some(5), None, Some(0)), "duration"),
+        ];
+        for (params, attribute) in cases {
+            let result = SetAttributesService::new(&user, scheduled(None, None, None)).call(&params);
+            assert!(result.errors().has_error(attribute), "{:?}", params);
+        }
+    }
+
+    #[test]
+    fn test_milestones_last_one_day() {
+        let user = create_admin_user();
+        let scheduling = Scheduling::default().with_milestone_types([1]);
+
+        let params = WorkPackageParams::new().with_start_date(date(5));
+        let result = SetAttributesService::new(&user, scheduled(None, None, None))
+            .with_scheduling(scheduling.clone())
+            .call(&params);
+        let wp = result.result().unwrap();
+        assert_eq!((wp.start_date, wp.due_date, wp.duration), (Some(date(5)), Some(date(5)), Some(1)));
+        assert_eq!(wp.derived_attributes, vec!["due_date", "duration"]);
+
+        let params = WorkPackageParams::new().with_due_date(date(9));
+        let result = SetAttributesService::new(&user, scheduled(Some(5), Some(5), Some(1)))
+            .with_scheduling(scheduling.clone())
+            .call(&params);
+        let wp = result.result().unwrap();
+        assert_eq!((wp.start_date, wp.due_date), (Some(date(9)), Some(date(9))));
+
+        for (params, attribute) in [
+            (schedule_params(Some(5), Some(8), None), "due_date"),
+            (schedule_params(Some(5), None, Some(2)), "duration"),
+        ] {
+            let result = SetAttributesService::new(&user, scheduled(None, None, None))
+                .with_scheduling(scheduling.clone())
+                .call(&params);
+            assert!(result.errors().has_error(attribute), "{:?}", params);
+        }
+    }
 }
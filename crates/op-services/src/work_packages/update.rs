@@ -3,10 +3,11 @@
 //! Mirrors: app/services/work_packages/update_service.rb
 
 use op_contracts::base::UserContext;
+use op_core::metrics::DomainMetrics;
 use op_core::traits::Id;
 
 use crate::result::ServiceResult;
-use super::set_attributes::{SetAttributesService, WorkPackageEntity};
+use super::set_attributes::{Scheduling, SetAttributesService, WorkPackageEntity};
 use super::WorkPackageParams;
 
 /// Service for updating work packages
@@ -21,6 +22,9 @@ use super::WorkPackageParams;
 pub struct UpdateWorkPackageService<'a, U: UserContext> {
     user: &'a U,
     send_notifications: bool,
+    metrics: Option<&'a DomainMetrics>,
+    scheduling: Scheduling,
+    dry_run: bool,
 }
 
 impl<'a, U: UserContext> UpdateWorkPackageService<'a, U> {
@@ -28,6 +32,9 @@ impl<'a, U: UserContext> UpdateWorkPackageService<'a, U> {
         Self {
             user,
             send_notifications: true,
+            metrics: None,
+            scheduling: Scheduling::default(),
+            dry_run: false,
         }
     }
 
@@ -35,9 +42,31 @@ impl<'a, U: UserContext> UpdateWorkPackageService<'a, U> {
         Self {
             user,
             send_notifications: false,
+            metrics: None,
+            scheduling: Scheduling::default(),
+            dry_run: false,
         }
     }
 
+    /// Record successful operations in the given metrics collector
+    pub fn with_metrics(mut self, metrics: &'a DomainMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Schedule by the given calendar and milestone types
+    pub fn with_scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Only validate; the result holds the work package as it would be
+    /// saved, and neither journals, notifications nor metrics are recorded
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
     /// Execute the update operation
     pub fn call(
         self,
@@ -55,10 +84,10 @@ impl<'a, U: UserContext> UpdateWorkPackageService<'a, U> {
         let original_parent_id = work_package.parent_id;
 
         // Set attributes and validate
-        let set_attrs_service = SetAttributesService::new(self.user, work_package);
+        let set_attrs_service = SetAttributesService::new(self.user, work_package).with_scheduling(self.scheduling);
         let result = set_attrs_service.call(&params);
 
-        if result.is_failure() {
+        if result.is_failure() || self.dry_run {
             return result;
         }
 
@@ -93,6 +122,10 @@ impl<'a, U: UserContext> UpdateWorkPackageService<'a, U> {
             // Would send update notifications here
         }
 
+        if let Some(metrics) = self.metrics {
+            metrics.record_work_package_updated();
+        }
+
         service_result
     }
 }
@@ -213,4 +246,17 @@ mod tests {
         let result = service.call(work_package, params);
         assert!(result.is_failure());
     }
+
+    #[test]
+    fn test_dry_run_records_nothing() {
+        let user = create_admin_user();
+        let metrics = DomainMetrics::new();
+
+        let result = UpdateWorkPackageService::new(&user)
+            .with_metrics(&metrics)
+            .dry_run()
+            .call(create_existing_work_package(), WorkPackageParams::new().with_subject("Previewed"));
+        assert_eq!(result.result().unwrap().subject, "Previewed");
+        assert_eq!(metrics.work_packages_updated.load(std::sync::atomic::Ordering::Relaxed), 0);
+    }
 }
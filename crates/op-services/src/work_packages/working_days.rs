@@ -0,0 +1,162 @@
+//! Working days calendar
+//!
+//! Mirrors: app/models/work_packages/shared/working_days.rb
+//!
+//! Durations count working days with both the start and the due date
+//! included, so a work package starting and ending on the same working day
+//! lasts one day.
+
+use std::collections::BTreeSet;
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+/// Weekdays and dates work is done on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkingDays {
+    /// Monday first
+    weekdays: [bool; 7],
+    non_working_dates: BTreeSet<NaiveDate>,
+}
+
+impl Default for WorkingDays {
+    /// Monday to Friday
+    fn default() -> Self {
+        Self::new([true, true, true, true, true, false, false])
+    }
+}
+
+impl WorkingDays {
+    /// Calendar of the given weekdays, Monday first; without any working
+    /// weekday every day is a working day
+    pub fn new(weekdays: [bool; 7]) -> Self {
+        let weekdays = if weekdays.contains(&true) { weekdays } else { [true; 7] };
+        Self {
+            weekdays,
+            non_working_dates: BTreeSet::new(),
+        }
+    }
+
+    /// Every day is a working day, as for work packages ignoring
+    /// non-working days
+    pub fn all_days() -> Self {
+        Self::new([true; 7])
+    }
+
+    /// Exclude a date, e.g. a public holiday
+    pub fn with_non_working_date(mut self, date: NaiveDate) -> Self {
+        self.non_working_dates.insert(date);
+        self
+    }
+
+    pub fn is_working_day(&self, date: NaiveDate) -> bool {
+        self.weekdays[date.weekday().num_days_from_monday() as usize] && !self.non_working_dates.contains(&date)
+    }
+
+    /// Working days from `start` to `due`, both included; `None` if `due`
+    /// is before `start`
+    pub fn duration(&self, start: NaiveDate, due: NaiveDate) -> Option<i32> {
+        if due < start {
+            return None;
+        }
+        let days = start.iter_days().take_while(|day| *day <= due);
+        Some(days.filter(|day| self.is_working_day(*day)).count() as i32)
+    }
+
+    /// Due date of a work package lasting `duration` working days from
+    /// `start`; a start on a non-working day counts from the next working day
+    pub fn due_date(&self, start: NaiveDate, duration: i32) -> NaiveDate {
+        let mut due = start;
+        let mut remaining = duration.max(1);
+        loop {
+            if self.is_working_day(due) {
+                remaining -= 1;
+                if remaining == 0 {
+                    return due;
+                }
+            }
+            due += Duration::days(1);
+        }
+    }
+
+    /// Start date of a work package lasting `duration` working days up to
+    /// `due`; a due date on a non-working day counts from the working day
+    /// before
+    pub fn start_date(&self, due: NaiveDate, duration: i32) -> NaiveDate {
+        let mut start = due;
+        let mut remaining = duration.max(1);
+        loop {
+            if self.is_working_day(start) {
+                remaining -= 1;
+                if remaining == 0 {
+                    return start;
+                }
+            }
+            start -= Duration::days(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        // 2024-01-01 is a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    #[test]
+    fn test_weekends_are_not_working_days() {
+        let calendar = WorkingDays::default();
+        assert!(calendar.is_working_day(date(5)));
+        assert!(!calendar.is_working_day(date(6)));
+        assert!(!calendar.is_working_day(date(7)));
+        assert!(WorkingDays::all_days().is_working_day(date(6)));
+    }
+
+    #[test]
+    fn test_durations() {
+        let holiday = WorkingDays::default().with_non_working_date(date(10));
+        // (calendar, start, due, duration)
+        let cases = [
+            (WorkingDays::default(), date(1), date(1), Some(1)),
+            (WorkingDays::default(), date(1), date(5), Some(5)),
+            (WorkingDays::default(), date(5), date(8), Some(2)),
+            (WorkingDays::default(), date(4), date(16), Some(9)),
+            (WorkingDays::default(), date(6), date(7), Some(0)),
+            (WorkingDays::default(), date(8), date(5), None),
+            (WorkingDays::all_days(), date(5), date(8), Some(4)),
+            (holiday, date(8), date(12), Some(4)),
+        ];
+        for (calendar, start, due, duration) in cases {
+            assert_eq!(calendar.duration(start, due), duration, "{} to {}", start, due);
+        }
+    }
+
+    #[test]
+    fn test_dates_from_durations() {
+        // (calendar, start, duration, due)
+        let cases = [
+            (WorkingDays::default(), date(1), 1, date(1)),
+            (WorkingDays::default(), date(4), 3, date(8)),
+            (WorkingDays::default(), date(5), 6, date(12)),
+            (WorkingDays::default(), date(6), 1, date(8)),
+            (WorkingDays::default(), date(6), 2, date(9)),
+            (WorkingDays::all_days(), date(5), 3, date(7)),
+            (WorkingDays::default().with_non_working_date(date(8)), date(5), 2, date(9)),
+        ];
+        for (calendar, start, duration, due) in cases {
+            assert_eq!(calendar.due_date(start, duration), due, "{} + {}", start, duration);
+            if calendar.is_working_day(start) {
+                assert_eq!(calendar.start_date(due, duration), start, "{} - {}", due, duration);
+            }
+        }
+        // A due date on a weekend counts back from Friday
+        assert_eq!(WorkingDays::default().start_date(date(7), 2), date(4));
+    }
+
+    #[test]
+    fn test_calendar_without_working_weekdays_works_every_day() {
+        assert_eq!(WorkingDays::new([false; 7]), WorkingDays::all_days());
+    }
+}